@@ -64,8 +64,8 @@ const getDebuggingCommands = (os: string | null, dataDir: string) => {
 
   const logPath =
     os === "windows"
-      ? `${dataDir}\\screenpipe.${new Date().toISOString().split("T")[0]}.log`
-      : `${dataDir}/screenpipe.${new Date().toISOString().split("T")[0]}.log`;
+      ? `${dataDir}\\logs\\screenpipe.${new Date().toISOString().split("T")[0]}.log`
+      : `${dataDir}/logs/screenpipe.${new Date().toISOString().split("T")[0]}.log`;
 
   const dbPath =
     os === "windows" ? `${dataDir}\\db.sqlite` : `${dataDir}/db.sqlite`;
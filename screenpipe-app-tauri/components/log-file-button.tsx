@@ -30,12 +30,13 @@ export const LogFileButton = ({
 
   const getLogFilePath = async () => {
     const dataDir = await getDataDir();
-    const logFileName = isAppLog ? "screenpipe-app" : "screenpipe";
+    // the server writes its logs in logs/, the app in the data dir itself
+    const logFileName = isAppLog ? "screenpipe-app" : "logs/screenpipe";
     const os = platform();
     if (os === "macos" || os === "linux") {
       return `${dataDir}/${logFileName}.${new Date().toISOString().split("T")[0]}.log`
     }
-    return `${dataDir}\\${logFileName}.${new Date().toISOString().split("T")[0]}.log`
+    return `${dataDir}\\${logFileName.replace("/", "\\")}.${new Date().toISOString().split("T")[0]}.log`
   };
 
   const handleOpenLogFile = async () => {
@@ -1,6 +1,7 @@
 use crate::get_data_dir;
 use serde::Serialize;
 use serde_json::Value;
+use tauri::Emitter;
 use tauri::Manager;
 use tracing::{info, error};
 
@@ -108,6 +109,15 @@ pub fn show_main_window(app_handle: &tauri::AppHandle<tauri::Wry>, overlay: bool
     }
 }
 
+/// Shows the main window and hands it a `screenpipe://` link, which it resolves to a
+/// timeline position through the server's `/deep-links/resolve`.
+pub fn open_deep_link(app_handle: &tauri::AppHandle<tauri::Wry>, url: &str) {
+    info!("opening deep link {}", url);
+    show_main_window(app_handle, false);
+    if let Err(e) = app_handle.emit("deep-link", url) {
+        error!("failed to send deep link to the window: {}", e);
+    }
+}
 
 #[tauri::command]
 pub fn show_meetings(app_handle: tauri::AppHandle<tauri::Wry>) {
@@ -20,6 +20,7 @@ use tauri::{
 };
 use tauri_plugin_autostart::MacosLauncher;
 use tauri_plugin_autostart::ManagerExt;
+use tauri_plugin_deep_link::DeepLinkExt;
 #[allow(unused_imports)]
 use tauri_plugin_shell::process::CommandEvent;
 use tauri_plugin_store::StoreBuilder;
@@ -61,7 +62,7 @@ async fn get_pipe_port(pipe_id: &str) -> anyhow::Result<u16> {
     // Fetch pipe config from API
     let client = reqwest::Client::new();
     let response = client
-        .get(format!("http://localhost:3030/pipes/info/{}", pipe_id))
+        .get(format!("http://localhost:3030/v1/pipes/info/{}", pipe_id))
         .send()
         .await?
         .json::<Value>()
@@ -153,7 +154,12 @@ async fn main() {
             MacosLauncher::LaunchAgent,
             None,
         ))
-        .plugin(tauri_plugin_single_instance::init(|app, _args, _cwd| {
+        .plugin(tauri_plugin_single_instance::init(|app, args, _cwd| {
+            // On windows and linux a deep link starts another instance with the url
+            if let Some(url) = args.iter().find(|arg| arg.starts_with("screenpipe://")) {
+                commands::open_deep_link(app, url);
+                return;
+            }
             let windows = app.webview_windows();
             windows
                 .values()
@@ -162,6 +168,7 @@ async fn main() {
                 .set_focus()
                 .expect("Can't focus window!");
         }))
+        .plugin(tauri_plugin_deep_link::init())
         .plugin(tauri_plugin_updater::Builder::new().build())
         .plugin(tauri_plugin_global_shortcut::Builder::new().build())
         .manage(sidecar_state)
@@ -239,6 +246,18 @@ async fn main() {
 
             info!("Local data directory: {}", base_dir.display());
 
+            // screenpipe:// links, from `copy?what=link` of the server
+            #[cfg(any(windows, target_os = "linux"))]
+            if let Err(e) = app.deep_link().register_all() {
+                error!("failed to register the screenpipe:// scheme: {}", e);
+            }
+            let deep_link_handle = app_handle.clone();
+            app.deep_link().on_open_url(move |event| {
+                for url in event.urls() {
+                    commands::open_deep_link(&deep_link_handle, url.as_str());
+                }
+            });
+
             // PostHog analytics setup
             let posthog_api_key = "phc_Bt8GoTBPgkCpDrbaIZzJIEYt0CrJjhBiuLaBck1clce".to_string();
             let interval_hours = 1;
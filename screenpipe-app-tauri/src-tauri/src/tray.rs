@@ -13,7 +13,7 @@ pub async fn update_tray_menu(
     // Fetch enabled pipes from API
     let client = reqwest::Client::new();
     let response = client
-        .get("http://localhost:3030/pipes/list")
+        .get("http://localhost:3030/v1/pipes/list")
         .send()
         .await?
         .json::<Value>()
@@ -122,6 +122,10 @@ async fn download_model(model_type: PyannoteModel) -> Result<()> {
 }
 
 fn get_cache_dir() -> Result<PathBuf> {
+    // Set by the server when a custom models directory is configured
+    if let Some(models_dir) = std::env::var_os("SCREENPIPE_MODELS_DIR") {
+        return Ok(PathBuf::from(models_dir));
+    }
     let proj_dirs = dirs::cache_dir().ok_or_else(|| anyhow::anyhow!("failed to get cache dir"))?;
     Ok(proj_dirs.join("screenpipe").join("models"))
 }
@@ -25,13 +25,15 @@ use std::{
     path::PathBuf,
     sync::Arc,
     sync::Mutex as StdMutex,
-    time::{SystemTime, UNIX_EPOCH},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 use tokio::sync::Mutex;
 
 use hound::{WavSpec, WavWriter};
 use regex::Regex;
 use reqwest::Client;
+use screenpipe_core::latency::{latency_tracker, LatencyStamps, PipelineKind};
+use screenpipe_core::models::ModelSlot;
 use screenpipe_core::Language;
 use serde_json::Value;
 use std::io::Cursor;
@@ -445,6 +447,8 @@ pub struct AudioInput {
     pub sample_rate: u32,
     pub channels: u16,
     pub device: Arc<AudioDevice>,
+    /// Captured when the chunk was complete, processed once transcribed
+    pub latency: LatencyStamps,
 }
 
 #[derive(Debug, Clone)]
@@ -500,7 +504,12 @@ pub async fn create_whisper_channel(
     crossbeam::channel::Receiver<TranscriptionResult>,
     Arc<AtomicBool>, // Shutdown flag
 )> {
-    let mut whisper_model = WhisperModel::new(&audio_transcription_engine)?;
+    // Unloaded when idle or on request, see `screenpipe_core::models`
+    let whisper_engine = audio_transcription_engine.clone();
+    let whisper_model = ModelSlot::new(audio_transcription_engine.to_string(), move || {
+        WhisperModel::new(&whisper_engine)
+    });
+    whisper_model.load()?;
     let (input_sender, input_receiver): (
         crossbeam::channel::Sender<AudioInput>,
         crossbeam::channel::Receiver<AudioInput>,
@@ -529,6 +538,10 @@ pub async fn create_whisper_channel(
     )?));
 
     let embedding_manager = EmbeddingManager::new(usize::MAX);
+    // Loaded the first time capture is degraded for latency
+    let small_model = ModelSlot::new(AudioTranscriptionEngine::WhisperTiny.to_string(), || {
+        WhisperModel::new(&AudioTranscriptionEngine::WhisperTiny)
+    });
 
     tokio::spawn(async move {
         loop {
@@ -590,20 +603,38 @@ pub async fn create_whisper_channel(
                                 }
                             };
 
+                            // Over the latency budget, transcribe with the tiny model until back under
+                            let degraded = is_large_whisper(&audio_transcription_engine)
+                                && latency_tracker().degradation(PipelineKind::Audio).small_model();
+                            let degraded = degraded && match small_model.load() {
+                                Ok(()) => true,
+                                Err(e) => {
+                                    error!("failed to load the tiny whisper model: {:?}", e);
+                                    false
+                                }
+                            };
+
                             while let Some(segment) = segments.recv().await {
+                                let (stt_slot, stt_engine) = if degraded {
+                                    (&small_model, Arc::new(AudioTranscriptionEngine::WhisperTiny))
+                                } else {
+                                    (&whisper_model, audio_transcription_engine.clone())
+                                };
                                 let path = path.clone();
                                 let transcription_result = if cfg!(target_os = "macos") {
                                     let timestamp = timestamp + segment.start.round() as u64;
                                     #[cfg(target_os = "macos")]
                                     {
                                         autoreleasepool(|| {
-                                            match stt_sync(&segment.samples, segment.sample_rate, &audio.device.to_string(), &mut whisper_model, audio_transcription_engine.clone(), deepgram_api_key.clone(), languages.clone()) {
+                                            let (transcription, waited) = stt_with_slot(stt_slot, &segment.samples, segment.sample_rate, &audio.device.to_string(), stt_engine.clone(), deepgram_api_key.clone(), languages.clone());
+                                            match transcription {
                                                 Ok(transcription) => TranscriptionResult {
                                                     input: AudioInput {
                                                         data: Arc::new(segment.samples),
                                                         sample_rate: segment.sample_rate,
                                                         channels: 1,
                                                         device: audio.device.clone(),
+                                                        latency: audio.latency.excluding(waited).processed(),
                                                     },
                                                     transcription: Some(transcription),
                                                     path,
@@ -621,6 +652,7 @@ pub async fn create_whisper_channel(
                                                             sample_rate: segment.sample_rate,
                                                             channels: 1,
                                                             device: audio.device.clone(),
+                                                            latency: audio.latency.excluding(waited).processed(),
                                                         },
                                                         transcription: None,
                                                         path,
@@ -639,13 +671,15 @@ pub async fn create_whisper_channel(
                                         unreachable!("This code should not be reached on non-macOS platforms")
                                     }
                                 } else {
-                                    match stt_sync(&segment.samples, segment.sample_rate, &audio.device.to_string(), &mut whisper_model, audio_transcription_engine.clone(), deepgram_api_key.clone(), languages.clone()) {
+                                    let (transcription, waited) = stt_with_slot(stt_slot, &segment.samples, segment.sample_rate, &audio.device.to_string(), stt_engine.clone(), deepgram_api_key.clone(), languages.clone());
+                                    match transcription {
                                         Ok(transcription) => TranscriptionResult {
                                             input: AudioInput {
                                                 data: Arc::new(segment.samples),
                                                 sample_rate: segment.sample_rate,
                                                 channels: 1,
                                                 device: audio.device.clone(),
+                                                latency: audio.latency.excluding(waited).processed(),
                                             },
                                             transcription: Some(transcription),
                                             path,
@@ -663,6 +697,7 @@ pub async fn create_whisper_channel(
                                                     sample_rate: segment.sample_rate,
                                                     channels: 1,
                                                     device: audio.device.clone(),
+                                                    latency: audio.latency.excluding(waited).processed(),
                                                 },
                                                 transcription: None,
                                                 path,
@@ -697,6 +732,43 @@ pub async fn create_whisper_channel(
     Ok((input_sender, output_receiver, shutdown_flag))
 }
 
+/// Transcribes with the model of `slot`, loaded again first if it was unloaded. Also
+/// returns the time spent loading, which isn't transcription latency.
+fn stt_with_slot(
+    slot: &ModelSlot<WhisperModel>,
+    audio: &[f32],
+    sample_rate: u32,
+    device: &str,
+    audio_transcription_engine: Arc<AudioTranscriptionEngine>,
+    deepgram_api_key: Option<String>,
+    languages: Vec<Language>,
+) -> (Result<String>, Duration) {
+    let transcribed = slot.with(|whisper_model| {
+        stt_sync(
+            audio,
+            sample_rate,
+            device,
+            whisper_model,
+            audio_transcription_engine,
+            deepgram_api_key,
+            languages,
+        )
+    });
+    match transcribed {
+        Ok((transcription, load)) => (transcription, load.unwrap_or_default()),
+        Err(e) => (Err(e), Duration::ZERO),
+    }
+}
+
+fn is_large_whisper(engine: &AudioTranscriptionEngine) -> bool {
+    matches!(
+        engine,
+        AudioTranscriptionEngine::WhisperDistilLargeV3
+            | AudioTranscriptionEngine::WhisperLargeV3Turbo
+            | AudioTranscriptionEngine::WhisperLargeV3
+    )
+}
+
 pub fn longest_common_word_substring(s1: &str, s2: &str) -> Option<(usize, usize)> {
     let s1 = s1.to_lowercase();
     let s2 = s2.to_lowercase();
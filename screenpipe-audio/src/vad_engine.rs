@@ -190,6 +190,9 @@ impl SileroVad {
     }
 
     fn get_cache_dir() -> anyhow::Result<PathBuf> {
+        if let Some(models_dir) = std::env::var_os("SCREENPIPE_MODELS_DIR") {
+            return Ok(PathBuf::from(models_dir).join("vad"));
+        }
         let proj_dirs =
             dirs::cache_dir().ok_or_else(|| anyhow::anyhow!("failed to get cache dir"))?;
         Ok(proj_dirs.join("screenpipe").join("vad"))
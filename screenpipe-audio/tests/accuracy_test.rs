@@ -5,6 +5,7 @@ use screenpipe_audio::stt::{prepare_segments, stt};
 use screenpipe_audio::vad_engine::{SileroVad, VadEngine};
 use screenpipe_audio::whisper::WhisperModel;
 use screenpipe_audio::{AudioInput, AudioTranscriptionEngine};
+use screenpipe_core::latency::LatencyStamps;
 use screenpipe_core::Language;
 use std::path::PathBuf;
 use std::sync::Arc;
@@ -89,6 +90,7 @@ async fn test_transcription_accuracy() {
                 sample_rate: 44100, // hardcoded based on test data sample rate
                 channels: 1,
                 device: Arc::new(screenpipe_audio::default_input_device().unwrap()),
+                latency: LatencyStamps::now(),
             };
 
             let mut segments = prepare_segments(
@@ -13,6 +13,7 @@ mod tests {
         AudioTranscriptionEngine,
     };
     use screenpipe_audio::{parse_audio_device, record_and_transcribe};
+    use screenpipe_core::latency::LatencyStamps;
     use screenpipe_core::Language;
     use std::path::PathBuf;
     use std::process::Command;
@@ -308,6 +309,7 @@ mod tests {
             sample_rate: 44100, // hardcoded based on test data sample rate
             channels: 1,
             device: Arc::new(screenpipe_audio::default_input_device().unwrap()),
+            latency: LatencyStamps::now(),
         };
 
 
@@ -400,6 +402,7 @@ mod tests {
             sample_rate: 16000, // Adjust this based on your test audio
             channels: 1,
             device: Arc::new(default_output_device().unwrap()),
+            latency: LatencyStamps::now(),
         };
 
         let project_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
@@ -1,7 +1,7 @@
 use std::path::Path;
 use anyhow::Result;
 use dirs::home_dir;
-use screenpipe_core::{download_pipe, run_pipe};
+use screenpipe_core::pipe_manager::{PipeManager, RuntimeConfig};
 use tracing::info;
 use tracing_subscriber::{fmt, prelude::*, EnvFilter};
 
@@ -17,6 +17,7 @@ async fn main() -> Result<()> {
 
     // Set up the path to the screenpipe directory
     let screenpipe_dir = home_dir().unwrap().join(".screenpipe");
+    let manager = PipeManager::new(screenpipe_dir, RuntimeConfig::default())?;
 
     // The name of the pipe (folder name in examples)
     let pipe_url = "screenpipe-core/examples/simple-pipe-api-pipe";
@@ -24,10 +25,10 @@ async fn main() -> Result<()> {
     info!("Starting the simple pipe API pipe...");
 
     // download the pipe
-    download_pipe(pipe_url, screenpipe_dir.clone()).await?;
+    manager.download_pipe(pipe_url).await?;
 
     // Run the pipe
-    match run_pipe(pipe_name, screenpipe_dir).await {
+    match manager.run_pipe(pipe_name).await {
         Ok(_) => println!("Pipe execution completed successfully."),
         Err(e) => eprintln!("Error executing pipe: {}", e),
     }
@@ -0,0 +1,251 @@
+//! Timestamps for captured items that never go backwards.
+//!
+//! Every frame and audio chunk is stamped by the process wide [`capture_clock`] with
+//! its wall clock time and a monotonic counter anchored at process start. The wall
+//! clock can jump: ntp steps it, users change it by hand. When it moves further or less
+//! than the monotonic clock between two stamps, by more than
+//! [`ClockConfig::jump_threshold`], the jump is recorded as a [`ClockAdjustment`].
+//!
+//! With [`TimestampSource::Monotonic`] stamps keep following the monotonic clock and
+//! only drift towards the jumped wall clock by [`ClockConfig::max_slew`] of the elapsed
+//! time, so stamped wall times never go backwards and re-anchor on the system clock
+//! once it has settled. Queries keep using the wall times; ordering within a process
+//! comes from the monotonic counter.
+//!
+//! The monotonic clock stops while the machine sleeps, so after a wake reported by
+//! [`crate::power`] the clock trusts the wall clock again and jumps forward at once.
+
+use crate::power::power_state;
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use tracing::{info, warn};
+
+/// Disagreement between the wall and monotonic clocks that counts as a jump.
+pub const DEFAULT_JUMP_THRESHOLD: Duration = Duration::from_secs(2);
+/// Stamps move towards a jumped wall clock by at most a tenth of the elapsed time.
+pub const DEFAULT_MAX_SLEW: f64 = 0.1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TimestampSource {
+    /// The system wall clock as is, stamps go backwards when it is set back
+    System,
+    /// Wall clock time corrected from the monotonic clock after a jump
+    #[default]
+    Monotonic,
+}
+
+impl TimestampSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TimestampSource::System => "system",
+            TimestampSource::Monotonic => "monotonic",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClockConfig {
+    pub source: TimestampSource,
+    pub jump_threshold: Duration,
+    /// Fraction of the elapsed time stamps may be moved by while re-anchoring, below 1
+    /// so they never go backwards
+    pub max_slew: f64,
+}
+
+impl Default for ClockConfig {
+    fn default() -> Self {
+        Self {
+            source: TimestampSource::default(),
+            jump_threshold: DEFAULT_JUMP_THRESHOLD,
+            max_slew: DEFAULT_MAX_SLEW,
+        }
+    }
+}
+
+/// When an item was captured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Stamp {
+    pub wall: DateTime<Utc>,
+    /// Nanoseconds since the clock was created, strictly increasing
+    pub monotonic_ns: u64,
+}
+
+/// A wall clock jump noticed while stamping.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClockAdjustment {
+    /// Stamped time of the first item after the jump
+    pub detected_at: DateTime<Utc>,
+    /// What the system clock read at that point
+    pub system_time: DateTime<Utc>,
+    pub monotonic_ns: u64,
+    /// How far the wall clock moved beyond the monotonic clock, negative when set back
+    pub jump_ms: i64,
+    pub source: TimestampSource,
+}
+
+struct ClockState {
+    config: ClockConfig,
+    last_mono: Instant,
+    last_monotonic_ns: u64,
+    last_system: DateTime<Utc>,
+    last_wall: DateTime<Utc>,
+}
+
+type SystemTime = Box<dyn Fn() -> DateTime<Utc> + Send + Sync>;
+
+pub struct Clock {
+    anchor: Instant,
+    system_time: SystemTime,
+    state: Mutex<ClockState>,
+    power_epoch: AtomicU64,
+    events: broadcast::Sender<ClockAdjustment>,
+}
+
+static CAPTURE_CLOCK: Lazy<Arc<Clock>> = Lazy::new(|| Arc::new(Clock::default()));
+
+/// Process wide clock stamping captured items.
+pub fn capture_clock() -> Arc<Clock> {
+    CAPTURE_CLOCK.clone()
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::new(ClockConfig::default())
+    }
+}
+
+impl Clock {
+    pub fn new(config: ClockConfig) -> Self {
+        Self::with_system_time(config, Utc::now)
+    }
+
+    /// A clock reading the wall time from `system_time` instead of the system clock.
+    pub fn with_system_time(
+        config: ClockConfig,
+        system_time: impl Fn() -> DateTime<Utc> + Send + Sync + 'static,
+    ) -> Self {
+        let clock = Self::starting_at(config, system_time(), Instant::now());
+        Self {
+            system_time: Box::new(system_time),
+            ..clock
+        }
+    }
+
+    /// A clock whose monotonic counter starts at `mono`, for driving it with
+    /// [`Clock::stamp_at`].
+    pub fn starting_at(config: ClockConfig, wall: DateTime<Utc>, mono: Instant) -> Self {
+        let (events, _) = broadcast::channel(16);
+        Self {
+            anchor: mono,
+            system_time: Box::new(Utc::now),
+            state: Mutex::new(ClockState {
+                config,
+                last_mono: mono,
+                last_monotonic_ns: 0,
+                last_system: wall,
+                last_wall: wall,
+            }),
+            power_epoch: AtomicU64::new(power_state().epoch()),
+            events,
+        }
+    }
+
+    pub fn config(&self) -> ClockConfig {
+        self.state.lock().unwrap().config
+    }
+
+    pub fn set_config(&self, config: ClockConfig) {
+        info!(
+            "stamping captured items with the {} clock",
+            config.source.as_str()
+        );
+        self.state.lock().unwrap().config = config;
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<ClockAdjustment> {
+        self.events.subscribe()
+    }
+
+    /// Stamps an item captured now.
+    pub fn now(&self) -> Stamp {
+        let epoch = power_state().epoch();
+        if self.power_epoch.swap(epoch, Ordering::SeqCst) != epoch {
+            self.resync_at((self.system_time)(), Instant::now());
+        }
+        self.stamp_at((self.system_time)(), Instant::now())
+    }
+
+    /// Stamps an item given both clocks' readings. Readings must come in order.
+    pub fn stamp_at(&self, wall: DateTime<Utc>, mono: Instant) -> Stamp {
+        let mut state = self.state.lock().unwrap();
+        let mono = mono.max(state.last_mono);
+        let elapsed = mono - state.last_mono;
+        let elapsed_wall = chrono::Duration::from_std(elapsed).unwrap_or_default();
+        let expected = state.last_wall + elapsed_wall;
+        let disagreement = (wall - state.last_system) - elapsed_wall;
+
+        let stamped = match state.config.source {
+            TimestampSource::System => wall,
+            TimestampSource::Monotonic => {
+                let max_correction = chrono::Duration::nanoseconds(
+                    (elapsed.as_nanos() as f64 * state.config.max_slew.clamp(0.0, 0.99)) as i64,
+                );
+                let correction = (wall - expected).clamp(-max_correction, max_correction);
+                (expected + correction).max(state.last_wall)
+            }
+        };
+        let monotonic_ns =
+            ((mono - self.anchor).as_nanos() as u64).max(state.last_monotonic_ns + 1);
+
+        state.last_mono = mono;
+        state.last_monotonic_ns = monotonic_ns;
+        state.last_system = wall;
+        state.last_wall = stamped;
+        let ClockConfig {
+            source,
+            jump_threshold,
+            ..
+        } = state.config;
+        drop(state);
+
+        if disagreement
+            .abs()
+            .to_std()
+            .is_ok_and(|d| d > jump_threshold)
+        {
+            let adjustment = ClockAdjustment {
+                detected_at: stamped,
+                system_time: wall,
+                monotonic_ns,
+                jump_ms: disagreement.num_milliseconds(),
+                source,
+            };
+            warn!(
+                "wall clock jumped by {}ms, stamping from the {} clock",
+                adjustment.jump_ms,
+                source.as_str()
+            );
+            let _ = self.events.send(adjustment);
+        }
+
+        Stamp {
+            wall: stamped,
+            monotonic_ns,
+        }
+    }
+
+    /// Re-anchors on the wall clock after a sleep, when the monotonic clock may have
+    /// stopped. Only ever moves stamps forward.
+    pub fn resync_at(&self, wall: DateTime<Utc>, mono: Instant) {
+        let mut state = self.state.lock().unwrap();
+        let mono = mono.max(state.last_mono);
+        let elapsed = chrono::Duration::from_std(mono - state.last_mono).unwrap_or_default();
+        state.last_wall = (state.last_wall + elapsed).max(wall);
+        state.last_system = wall;
+        state.last_mono = mono;
+    }
+}
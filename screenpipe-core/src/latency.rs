@@ -0,0 +1,477 @@
+//! End-to-end capture latency: how long a frame or audio chunk takes from capture
+//! until it is searchable.
+//!
+//! Every item carries its own [`LatencyStamps`] through the pipeline, stamped when it
+//! is captured, when OCR or transcription is done and when it is committed to the
+//! database. Once committed the stamps are recorded in the process wide
+//! [`latency_tracker`], which keeps rolling percentiles per stage, checks them against
+//! the configured [`LatencyBudget`] and, if asked to, degrades capture while the budget
+//! keeps being blown.
+
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, Once};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use tracing::{info, warn};
+
+/// Items kept per pipeline for the rolling percentiles.
+pub const LATENCY_WINDOW: usize = 512;
+/// How often the budget monitor compares the recent items to the budget.
+pub const BUDGET_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+/// Consecutive checks over budget before alerting and degrading.
+pub const DEFAULT_SUSTAINED_CHECKS: u32 = 3;
+/// Deepest degradation level, capture runs at a quarter of the configured fps.
+pub const MAX_DEGRADATION_LEVEL: u8 = 2;
+
+/// When an item was captured, processed (OCR or transcription) and committed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LatencyStamps {
+    pub captured_at: Instant,
+    pub processed_at: Option<Instant>,
+    pub committed_at: Option<Instant>,
+}
+
+impl LatencyStamps {
+    pub fn captured(at: Instant) -> Self {
+        Self {
+            captured_at: at,
+            processed_at: None,
+            committed_at: None,
+        }
+    }
+
+    pub fn now() -> Self {
+        Self::captured(Instant::now())
+    }
+
+    pub fn mark_processed(&mut self) {
+        self.processed_at = Some(Instant::now());
+    }
+
+    /// Copy of these stamps marked processed now, for results built from their input.
+    pub fn processed(mut self) -> Self {
+        self.mark_processed();
+        self
+    }
+
+    /// Copy of these stamps leaving out `waited` spent before processing, e.g. loading
+    /// a model unloaded while idle, which isn't capture falling behind.
+    pub fn excluding(mut self, waited: Duration) -> Self {
+        self.captured_at += waited;
+        self
+    }
+
+    pub fn mark_committed(&mut self) {
+        self.committed_at = Some(Instant::now());
+    }
+
+    /// Every stamp set so far follows the previous one, and none was skipped.
+    pub fn is_monotonic(&self) -> bool {
+        match (self.processed_at, self.committed_at) {
+            (None, None) => true,
+            (Some(processed), None) => processed >= self.captured_at,
+            (Some(processed), Some(committed)) => {
+                processed >= self.captured_at && committed >= processed
+            }
+            (None, Some(_)) => false,
+        }
+    }
+
+    pub fn stage(&self, stage: LatencyStage) -> Option<Duration> {
+        match stage {
+            LatencyStage::Processing => Some(self.processed_at? - self.captured_at),
+            LatencyStage::Commit => Some(self.committed_at? - self.processed_at?),
+            LatencyStage::EndToEnd => Some(self.committed_at? - self.captured_at),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LatencyStage {
+    /// Capture until OCR or transcription finished
+    Processing,
+    /// OCR or transcription until the rows are committed
+    Commit,
+    /// Capture until searchable
+    EndToEnd,
+}
+
+impl LatencyStage {
+    pub const ALL: [LatencyStage; 3] = [
+        LatencyStage::Processing,
+        LatencyStage::Commit,
+        LatencyStage::EndToEnd,
+    ];
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PipelineKind {
+    Vision,
+    Audio,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LatencyBudget {
+    /// Capture to searchable, compared with the p95 of the items since the last check
+    pub end_to_end: Duration,
+    /// Lower the fps and switch to a smaller whisper model while over budget
+    pub auto_degrade: bool,
+    pub sustained_checks: u32,
+}
+
+impl LatencyBudget {
+    pub fn new(end_to_end: Duration) -> Self {
+        Self {
+            end_to_end,
+            auto_degrade: false,
+            sustained_checks: DEFAULT_SUSTAINED_CHECKS,
+        }
+    }
+}
+
+/// How far capture is turned down to get back under budget.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Degradation {
+    pub level: u8,
+}
+
+impl Degradation {
+    pub fn is_active(&self) -> bool {
+        self.level > 0
+    }
+
+    /// Every level halves the capture fps.
+    pub fn interval_scale(&self) -> u32 {
+        1 << self.level
+    }
+
+    /// Transcribe with the tiny whisper model instead of the configured one.
+    pub fn small_model(&self) -> bool {
+        self.level > 0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum LatencyEvent {
+    BudgetExceeded {
+        pipeline: PipelineKind,
+        p95_ms: u64,
+        budget_ms: u64,
+    },
+    Degraded {
+        pipeline: PipelineKind,
+        level: u8,
+    },
+    Recovered {
+        pipeline: PipelineKind,
+        level: u8,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct Percentiles {
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub p99_ms: f64,
+}
+
+impl Percentiles {
+    /// Nearest rank percentiles, `None` without samples.
+    pub fn of(samples: &[Duration]) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        let mut sorted = samples.to_vec();
+        sorted.sort();
+        let rank = |p: f64| {
+            let index = ((p * sorted.len() as f64).ceil() as usize).clamp(1, sorted.len()) - 1;
+            sorted[index].as_secs_f64() * 1000.0
+        };
+        Some(Self {
+            p50_ms: rank(0.50),
+            p95_ms: rank(0.95),
+            p99_ms: rank(0.99),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PipelineLatency {
+    pub samples: usize,
+    pub stages: BTreeMap<LatencyStage, Percentiles>,
+    /// p95 capture to searchable over the rolling window
+    pub searchable_within_ms: Option<f64>,
+    pub over_budget_checks: u32,
+    pub degradation: Degradation,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct LatencySnapshot {
+    pub pipelines: BTreeMap<PipelineKind, PipelineLatency>,
+    pub budget_ms: Option<u64>,
+    pub auto_degrade: bool,
+    /// Items whose stamps went backwards or skipped a stage, they are not recorded
+    pub non_monotonic: u64,
+}
+
+impl LatencySnapshot {
+    /// Slowest pipeline's p95 capture to searchable.
+    pub fn searchable_within(&self) -> Option<Duration> {
+        self.pipelines
+            .values()
+            .filter_map(|p| p.searchable_within_ms)
+            .reduce(f64::max)
+            .map(|ms| Duration::from_secs_f64(ms / 1000.0))
+    }
+
+    pub fn over_budget(&self) -> bool {
+        let Some(budget) = self.budget_ms else {
+            return false;
+        };
+        self.pipelines
+            .values()
+            .any(|p| p.searchable_within_ms.is_some_and(|ms| ms > budget as f64))
+    }
+}
+
+#[derive(Default)]
+struct PipelineWindow {
+    stamps: VecDeque<LatencyStamps>,
+    /// End to end of the items committed since the last budget check
+    since_check: Vec<Duration>,
+    over_budget_checks: u32,
+    under_budget_checks: u32,
+    degradation: Degradation,
+}
+
+impl PipelineWindow {
+    fn stage(&self, stage: LatencyStage) -> Vec<Duration> {
+        self.stamps.iter().filter_map(|s| s.stage(stage)).collect()
+    }
+}
+
+#[derive(Default)]
+struct TrackerState {
+    pipelines: BTreeMap<PipelineKind, PipelineWindow>,
+    budget: Option<LatencyBudget>,
+}
+
+pub struct LatencyTracker {
+    state: Mutex<TrackerState>,
+    non_monotonic: AtomicU64,
+    events: broadcast::Sender<LatencyEvent>,
+}
+
+static LATENCY_TRACKER: Lazy<LatencyTracker> = Lazy::new(LatencyTracker::new);
+
+/// Process wide tracker the capture pipelines record into.
+pub fn latency_tracker() -> &'static LatencyTracker {
+    &LATENCY_TRACKER
+}
+
+impl Default for LatencyTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LatencyTracker {
+    pub fn new() -> Self {
+        let (events, _) = broadcast::channel(16);
+        Self {
+            state: Mutex::new(TrackerState::default()),
+            non_monotonic: AtomicU64::new(0),
+            events,
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<LatencyEvent> {
+        self.events.subscribe()
+    }
+
+    pub fn set_budget(&self, budget: Option<LatencyBudget>) {
+        let mut state = self.state.lock().unwrap();
+        state.budget = budget;
+        if !budget.is_some_and(|b| b.auto_degrade) {
+            for window in state.pipelines.values_mut() {
+                window.degradation = Degradation::default();
+            }
+        }
+    }
+
+    pub fn budget(&self) -> Option<LatencyBudget> {
+        self.state.lock().unwrap().budget
+    }
+
+    /// Records a committed item. Stamps that are not monotonic are counted and dropped,
+    /// they would only skew the percentiles.
+    pub fn record(&self, pipeline: PipelineKind, stamps: &LatencyStamps) {
+        let end_to_end = match stamps.stage(LatencyStage::EndToEnd) {
+            Some(end_to_end) if stamps.is_monotonic() => end_to_end,
+            _ => {
+                self.non_monotonic.fetch_add(1, Ordering::Relaxed);
+                warn!(
+                    "dropping {:?} latency stamps out of order: {:?}",
+                    pipeline, stamps
+                );
+                return;
+            }
+        };
+        let mut state = self.state.lock().unwrap();
+        let window = state.pipelines.entry(pipeline).or_default();
+        if window.stamps.len() == LATENCY_WINDOW {
+            window.stamps.pop_front();
+        }
+        window.stamps.push_back(*stamps);
+        window.since_check.push(end_to_end);
+    }
+
+    pub fn degradation(&self, pipeline: PipelineKind) -> Degradation {
+        let state = self.state.lock().unwrap();
+        state
+            .pipelines
+            .get(&pipeline)
+            .map(|w| w.degradation)
+            .unwrap_or_default()
+    }
+
+    /// `interval` stretched by the current vision degradation.
+    pub fn capture_interval(&self, interval: Duration) -> Duration {
+        interval * self.degradation(PipelineKind::Vision).interval_scale()
+    }
+
+    pub fn snapshot(&self) -> LatencySnapshot {
+        let state = self.state.lock().unwrap();
+        let pipelines = state
+            .pipelines
+            .iter()
+            .map(|(kind, window)| {
+                let stages = LatencyStage::ALL
+                    .iter()
+                    .filter_map(|stage| Some((*stage, Percentiles::of(&window.stage(*stage))?)))
+                    .collect::<BTreeMap<_, _>>();
+                let searchable_within_ms = stages.get(&LatencyStage::EndToEnd).map(|p| p.p95_ms);
+                let latency = PipelineLatency {
+                    samples: window.stamps.len(),
+                    stages,
+                    searchable_within_ms,
+                    over_budget_checks: window.over_budget_checks,
+                    degradation: window.degradation,
+                };
+                (*kind, latency)
+            })
+            .collect();
+        LatencySnapshot {
+            pipelines,
+            budget_ms: state.budget.map(|b| b.end_to_end.as_millis() as u64),
+            auto_degrade: state.budget.is_some_and(|b| b.auto_degrade),
+            non_monotonic: self.non_monotonic.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Compares the p95 of the items committed since the previous check with the
+    /// budget. After `sustained_checks` checks in a row over budget it alerts and, with
+    /// auto degrade, turns capture down one level. As many checks in a row under half
+    /// the budget turn it back up. Pipelines that committed nothing are left alone.
+    pub fn check_budget(&self) -> Vec<LatencyEvent> {
+        let mut state = self.state.lock().unwrap();
+        let Some(budget) = state.budget else {
+            return Vec::new();
+        };
+        let mut events = Vec::new();
+        for (kind, window) in state.pipelines.iter_mut() {
+            let recent = std::mem::take(&mut window.since_check);
+            let Some(p95) = Percentiles::of(&recent).map(|p| p.p95_ms) else {
+                continue;
+            };
+            let budget_ms = budget.end_to_end.as_secs_f64() * 1000.0;
+
+            if p95 > budget_ms {
+                window.under_budget_checks = 0;
+                window.over_budget_checks += 1;
+                if window.over_budget_checks < budget.sustained_checks {
+                    continue;
+                }
+                window.over_budget_checks = 0;
+                events.push(LatencyEvent::BudgetExceeded {
+                    pipeline: *kind,
+                    p95_ms: p95 as u64,
+                    budget_ms: budget_ms as u64,
+                });
+                if budget.auto_degrade && window.degradation.level < MAX_DEGRADATION_LEVEL {
+                    window.degradation.level += 1;
+                    events.push(LatencyEvent::Degraded {
+                        pipeline: *kind,
+                        level: window.degradation.level,
+                    });
+                }
+            } else {
+                window.over_budget_checks = 0;
+                if p95 > budget_ms / 2.0 || !window.degradation.is_active() {
+                    window.under_budget_checks = 0;
+                    continue;
+                }
+                window.under_budget_checks += 1;
+                if window.under_budget_checks >= budget.sustained_checks {
+                    window.under_budget_checks = 0;
+                    window.degradation.level -= 1;
+                    events.push(LatencyEvent::Recovered {
+                        pipeline: *kind,
+                        level: window.degradation.level,
+                    });
+                }
+            }
+        }
+        drop(state);
+
+        for event in &events {
+            match event {
+                LatencyEvent::BudgetExceeded {
+                    pipeline,
+                    p95_ms,
+                    budget_ms,
+                } => warn!(
+                    "{:?} items take {}ms to become searchable (p95), over the {}ms budget",
+                    pipeline, p95_ms, budget_ms
+                ),
+                LatencyEvent::Degraded { pipeline, level } => {
+                    warn!("degrading {:?} capture to level {}", pipeline, level)
+                }
+                LatencyEvent::Recovered { pipeline, level } => {
+                    info!("{:?} latency back under budget, level {}", pipeline, level)
+                }
+            }
+            let _ = self.events.send(event.clone());
+        }
+        events
+    }
+
+    /// Forgets every recorded item and degradation, keeping the budget.
+    pub fn reset(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.pipelines.clear();
+        self.non_monotonic.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Checks the tracker against its budget every [`BUDGET_CHECK_INTERVAL`]. Must be
+/// called from within a tokio runtime; later calls do nothing.
+pub fn start_latency_monitor() {
+    static STARTED: Once = Once::new();
+    STARTED.call_once(|| {
+        tokio::spawn(async {
+            let mut interval = tokio::time::interval(BUDGET_CHECK_INTERVAL);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                latency_tracker().check_budget();
+            }
+        });
+    });
+}
@@ -24,6 +24,54 @@ pub use llama::*;
 pub mod pipes;
 #[cfg(feature = "pipes")]
 pub use pipes::*;
+#[cfg(feature = "pipes")]
+pub mod pipe_archive;
+#[cfg(feature = "pipes")]
+pub mod pipe_bitbucket;
+#[cfg(feature = "pipes")]
+pub mod pipe_bundle;
+#[cfg(feature = "pipes")]
+pub mod pipe_commands;
+#[cfg(feature = "pipes")]
+pub mod pipe_config;
+#[cfg(feature = "pipes")]
+pub mod pipe_config_schema;
+#[cfg(feature = "pipes")]
+pub mod pipe_deno;
+#[cfg(feature = "pipes")]
+pub mod pipe_diff;
+#[cfg(feature = "pipes")]
+pub mod pipe_git;
+#[cfg(feature = "pipes")]
+pub mod pipe_gitlab;
+#[cfg(feature = "pipes")]
+pub mod pipe_github;
+#[cfg(feature = "pipes")]
+pub use pipe_github::*;
+#[cfg(feature = "pipes")]
+pub mod pipe_ignore;
+#[cfg(feature = "pipes")]
+pub mod pipe_ipc;
+#[cfg(feature = "pipes")]
+pub mod pipe_link;
+#[cfg(feature = "pipes")]
+pub mod pipe_lock;
+#[cfg(feature = "pipes")]
+pub use pipe_lock::*;
+#[cfg(feature = "pipes")]
+pub mod pipe_manager;
+#[cfg(feature = "pipes")]
+pub mod pipe_manifest;
+#[cfg(feature = "pipes")]
+pub mod pipe_metadata;
+#[cfg(feature = "pipes")]
+pub mod pipe_npm;
+#[cfg(feature = "pipes")]
+pub mod pipe_registry;
+#[cfg(feature = "pipes")]
+pub mod pipe_sandbox;
+#[cfg(feature = "pipes")]
+pub mod pipe_stats;
 mod language;
 #[cfg(feature = "security")]
 pub mod pii_removal;
@@ -38,4 +86,14 @@ pub use pii_removal::*;
 pub mod network;
 pub use network::*;
 
+pub mod power;
+
+pub mod latency;
+
+pub mod models;
+
+pub mod clock;
+
+pub mod window_layout;
+
 pub use language::{Language, TESSERACT_LANGUAGES};
@@ -0,0 +1,293 @@
+//! Models kept in memory for transcription, and when they are let go.
+//!
+//! A model lives in a [`ModelSlot`], which loads it the first time it is used and
+//! again the first time after it was unloaded. Slots register themselves in the process
+//! wide [`model_registry`], through which the server reports what is resident and
+//! unloads models: all of them on request, or those unused for the configured idle
+//! period with [`run_idle_unloader`].
+//!
+//! Inference holds the slot's lock for as long as it runs, so an unload waits for it
+//! to finish, and an idle check never drops a model in use.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, TryLockError, Weak};
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info};
+
+/// Most often the idle unloader looks for idle models.
+pub const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// What is known about one model, in health and `GET /models/metrics`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelStatus {
+    pub name: String,
+    pub resident: bool,
+    /// Being loaded, a use is waiting for it
+    pub loading: bool,
+    /// Inference is running on it
+    pub in_use: bool,
+    pub loaded_at: Option<DateTime<Utc>>,
+    pub last_used: Option<DateTime<Utc>>,
+    pub loads: u32,
+    pub unloads: u32,
+    pub last_load_ms: Option<f64>,
+    pub avg_load_ms: Option<f64>,
+}
+
+/// A model the registry can report on and unload.
+pub trait ResidentModel: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn status(&self) -> ModelStatus;
+
+    /// Drops the model, once the inference using it is done. Returns whether it was
+    /// resident.
+    fn unload(&self) -> bool;
+
+    /// Drops the model if it wasn't used for `idle`. A model in use isn't idle.
+    fn unload_if_idle(&self, idle: Duration) -> bool;
+}
+
+type Loader<T> = Box<dyn Fn() -> Result<T> + Send + Sync>;
+
+#[derive(Debug, Default)]
+struct SlotStats {
+    loaded_at: Option<DateTime<Utc>>,
+    last_used: Option<DateTime<Utc>>,
+    last_used_at: Option<Instant>,
+    loads: u32,
+    unloads: u32,
+    last_load: Option<Duration>,
+    total_load: Duration,
+}
+
+/// A model loaded on demand, see the module docs.
+pub struct ModelSlot<T> {
+    name: String,
+    loader: Loader<T>,
+    model: Mutex<Option<T>>,
+    loading: AtomicBool,
+    in_use: AtomicBool,
+    // Apart from the model so its status can be read during inference
+    stats: Mutex<SlotStats>,
+}
+
+impl<T: Send + 'static> ModelSlot<T> {
+    /// An empty slot, registered in [`model_registry`]. Nothing is loaded until the
+    /// model is first used or [`ModelSlot::load`] is called.
+    pub fn new(
+        name: impl Into<String>,
+        loader: impl Fn() -> Result<T> + Send + Sync + 'static,
+    ) -> Arc<Self> {
+        let slot = Arc::new(Self {
+            name: name.into(),
+            loader: Box::new(loader),
+            model: Mutex::new(None),
+            loading: AtomicBool::new(false),
+            in_use: AtomicBool::new(false),
+            stats: Mutex::new(SlotStats::default()),
+        });
+        let resident: Arc<dyn ResidentModel> = slot.clone();
+        model_registry().register(&resident);
+        slot
+    }
+
+    /// Loads the model now if it isn't resident, to fail early on a missing model.
+    pub fn load(&self) -> Result<()> {
+        let mut model = self.model.lock().unwrap();
+        self.ensure_loaded(&mut model)?;
+        Ok(())
+    }
+
+    /// Runs `f` on the model, loading it first if needed. Returns the time spent
+    /// loading along with the result, so it can be told apart from inference.
+    pub fn with<R>(&self, f: impl FnOnce(&mut T) -> R) -> Result<(R, Option<Duration>)> {
+        let mut model = self.model.lock().unwrap();
+        let load = self.ensure_loaded(&mut model)?;
+        let model = model.as_mut().unwrap();
+        self.in_use.store(true, Ordering::SeqCst);
+        let result = f(model);
+        self.in_use.store(false, Ordering::SeqCst);
+
+        let mut stats = self.stats.lock().unwrap();
+        stats.last_used = Some(Utc::now());
+        stats.last_used_at = Some(Instant::now());
+        Ok((result, load))
+    }
+
+    /// Loads the model if it isn't resident, returning how long that took.
+    fn ensure_loaded(&self, model: &mut Option<T>) -> Result<Option<Duration>> {
+        if model.is_some() {
+            return Ok(None);
+        }
+        self.loading.store(true, Ordering::SeqCst);
+        let started = Instant::now();
+        let loaded = (self.loader)();
+        self.loading.store(false, Ordering::SeqCst);
+        let loaded = loaded.map_err(|e| {
+            error!("failed to load model {}: {}", self.name, e);
+            e
+        })?;
+        let took = started.elapsed();
+        info!("model {} loaded in {:.1}s", self.name, took.as_secs_f64());
+
+        let mut stats = self.stats.lock().unwrap();
+        stats.loaded_at = Some(Utc::now());
+        // Idle from the load until first used
+        stats.last_used_at = Some(Instant::now());
+        stats.loads += 1;
+        stats.last_load = Some(took);
+        stats.total_load += took;
+        *model = Some(loaded);
+        Ok(Some(took))
+    }
+
+    fn drop_model(&self, model: &mut Option<T>) -> bool {
+        if model.take().is_none() {
+            return false;
+        }
+        let mut stats = self.stats.lock().unwrap();
+        stats.loaded_at = None;
+        stats.unloads += 1;
+        info!("model {} unloaded", self.name);
+        true
+    }
+}
+
+impl<T: Send + 'static> ResidentModel for ModelSlot<T> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn status(&self) -> ModelStatus {
+        // Not the model lock, held during inference
+        let resident = match self.model.try_lock() {
+            Ok(model) => model.is_some(),
+            Err(TryLockError::WouldBlock) => !self.loading.load(Ordering::SeqCst),
+            Err(TryLockError::Poisoned(model)) => model.into_inner().is_some(),
+        };
+        let stats = self.stats.lock().unwrap();
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        ModelStatus {
+            name: self.name.clone(),
+            resident,
+            loading: self.loading.load(Ordering::SeqCst),
+            in_use: self.in_use.load(Ordering::SeqCst),
+            loaded_at: stats.loaded_at,
+            last_used: stats.last_used,
+            loads: stats.loads,
+            unloads: stats.unloads,
+            last_load_ms: stats.last_load.map(ms),
+            avg_load_ms: (stats.loads > 0).then(|| ms(stats.total_load) / stats.loads as f64),
+        }
+    }
+
+    fn unload(&self) -> bool {
+        let mut model = self.model.lock().unwrap();
+        self.drop_model(&mut model)
+    }
+
+    fn unload_if_idle(&self, idle: Duration) -> bool {
+        let mut model = match self.model.try_lock() {
+            Ok(model) => model,
+            // In use or loading
+            Err(TryLockError::WouldBlock) => return false,
+            Err(TryLockError::Poisoned(model)) => model.into_inner(),
+        };
+        let idle_for = self
+            .stats
+            .lock()
+            .unwrap()
+            .last_used_at
+            .map(|at| at.elapsed());
+        if idle_for.is_some_and(|idle_for| idle_for >= idle) {
+            debug!("model {} idle for {:?}", self.name, idle_for);
+            self.drop_model(&mut model)
+        } else {
+            false
+        }
+    }
+}
+
+/// Every live [`ModelSlot`] of the process.
+#[derive(Default)]
+pub struct ModelRegistry {
+    models: Mutex<Vec<Weak<dyn ResidentModel>>>,
+    idle_timeout: Mutex<Option<Duration>>,
+}
+
+static MODEL_REGISTRY: Lazy<ModelRegistry> = Lazy::new(ModelRegistry::default);
+
+/// Process wide registry the transcription pipeline registers its models in.
+pub fn model_registry() -> &'static ModelRegistry {
+    &MODEL_REGISTRY
+}
+
+impl ModelRegistry {
+    pub fn register(&self, model: &Arc<dyn ResidentModel>) {
+        let mut models = self.models.lock().unwrap();
+        models.retain(|m| m.strong_count() > 0);
+        models.push(Arc::downgrade(model));
+    }
+
+    fn live(&self) -> Vec<Arc<dyn ResidentModel>> {
+        self.models
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(Weak::upgrade)
+            .collect()
+    }
+
+    pub fn statuses(&self) -> Vec<ModelStatus> {
+        self.live().iter().map(|m| m.status()).collect()
+    }
+
+    /// Unloads every model, waiting for running inference. Returns the names of the
+    /// models that were resident. Blocks, call it off the async runtime.
+    pub fn unload_all(&self) -> Vec<String> {
+        self.live()
+            .iter()
+            .filter(|m| m.unload())
+            .map(|m| m.name().to_string())
+            .collect()
+    }
+
+    /// Unloads the models unused for `idle`. Returns their names.
+    pub fn unload_idle(&self, idle: Duration) -> Vec<String> {
+        self.live()
+            .iter()
+            .filter(|m| m.unload_if_idle(idle))
+            .map(|m| m.name().to_string())
+            .collect()
+    }
+
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        *self.idle_timeout.lock().unwrap()
+    }
+}
+
+/// Unloads the models of [`model_registry`] unused for `idle`, until the process
+/// exits. Must be called from within a tokio runtime.
+pub async fn run_idle_unloader(idle: Duration) {
+    *model_registry().idle_timeout.lock().unwrap() = Some(idle);
+    let period = (idle / 4).clamp(Duration::from_secs(1), IDLE_CHECK_INTERVAL);
+    let mut interval = tokio::time::interval(period);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        let unloaded =
+            tokio::task::spawn_blocking(move || model_registry().unload_idle(idle)).await;
+        match unloaded {
+            Ok(names) if !names.is_empty() => {
+                info!("unloaded idle models: {}", names.join(", "))
+            }
+            Ok(_) => {}
+            Err(e) => error!("idle model check failed: {}", e),
+        }
+    }
+}
@@ -18,6 +18,13 @@ pub fn remove_pii(text: &str) -> String {
     sanitized
 }
 
+/// Whether `text` has pii, or the placeholders [`remove_pii`] put in its place.
+pub fn contains_pii(text: &str) -> bool {
+    PII_PATTERNS
+        .iter()
+        .any(|(pattern, placeholder)| text.contains(placeholder) || pattern.is_match(text))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -29,4 +36,11 @@ mod tests {
         let expected = "My card is [CREDIT_CARD] and SSN is [SSN]. Email: [EMAIL]";
         assert_eq!(remove_pii(input), expected);
     }
+
+    #[test]
+    fn test_contains_pii() {
+        assert!(contains_pii("reach me at test@example.com"));
+        assert!(contains_pii("reach me at [EMAIL]"));
+        assert!(!contains_pii("nothing to see here"));
+    }
 }
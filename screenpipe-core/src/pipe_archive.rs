@@ -0,0 +1,306 @@
+//! Pipes published as an archive: a url whose path ends in `.zip`, `.tar.gz` or `.tgz`,
+//! such as a github release asset,
+//! `https://github.com/<owner>/<repo>/releases/download/<tag>/<pipe>.tar.gz`, or a local
+//! file with one of those extensions. The pipe is installed under the name of the archive,
+//! `<pipe>`.
+//!
+//! The archive is downloaded to a temp file and extracted into the pipe folder, a local one
+//! is extracted where it is. When all of its entries are in one folder, the usual wrapping
+//! of release archives, that folder is left out, and what is left has to have a pipe.json,
+//! pipe.ts or pipe.js. Entries with `..` or an absolute path fail the whole extraction,
+//! links and hidden files are skipped.
+
+use anyhow::Result;
+use reqwest::Client;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+use tempfile::NamedTempFile;
+use tokio::io::AsyncWriteExt;
+use tracing::debug;
+use url::Url;
+
+use crate::pipes::{is_hidden_file, sanitize_pipe_name};
+
+/// Size an archive may have, downloaded and once extracted.
+pub const MAX_ARCHIVE_SIZE: u64 = 256 * 1024 * 1024;
+
+/// Files an archive has one of, once its root folder is left out.
+const PIPE_FILES: [&str; 3] = ["pipe.json", "pipe.ts", "pipe.js"];
+
+/// Format of a pipe archive, told by the extension of its url or path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveKind {
+    Zip,
+    TarGz,
+}
+
+impl ArchiveKind {
+    const EXTENSIONS: [(&'static str, ArchiveKind); 3] = [
+        (".zip", ArchiveKind::Zip),
+        (".tar.gz", ArchiveKind::TarGz),
+        (".tgz", ArchiveKind::TarGz),
+    ];
+
+    /// `None` for paths and urls of anything but an archive.
+    pub fn from_source(source: &str) -> Option<ArchiveKind> {
+        Some(archive_name(source)?.1)
+    }
+}
+
+/// Last segment of the url or path of an archive without its extension, and the archive
+/// kind.
+fn archive_name(source: &str) -> Option<(String, ArchiveKind)> {
+    let file_name = match Url::parse(source) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => {
+            url.path_segments()?.next_back()?.to_string()
+        }
+        Ok(_) => return None,
+        Err(_) => Path::new(source).file_name()?.to_str()?.to_string(),
+    };
+    let lowercase = file_name.to_lowercase();
+    ArchiveKind::EXTENSIONS
+        .iter()
+        .find(|(extension, _)| lowercase.ends_with(extension))
+        .map(|(extension, kind)| {
+            let stem = &file_name[..file_name.len() - extension.len()];
+            (stem.to_string(), *kind)
+        })
+        .filter(|(stem, _)| !stem.is_empty())
+}
+
+/// Id a pipe downloaded from the archive at `source` is installed under.
+pub fn archive_pipe_id(source: &str) -> Option<String> {
+    archive_name(source).map(|(stem, _)| sanitize_pipe_name(&stem))
+}
+
+/// Downloads the archive at `source`, or takes the local one, and extracts it into
+/// `dest_dir`. Fails when it has no pipe.json, pipe.ts or pipe.js.
+pub async fn download_archive(client: &Client, source: &str, dest_dir: &Path) -> Result<()> {
+    let kind = ArchiveKind::from_source(source)
+        .ok_or_else(|| anyhow::anyhow!("{} isn't a zip or tar.gz archive", source))?;
+    let downloaded = match Url::parse(source) {
+        Ok(_) => Some(fetch_archive(client, source).await?),
+        Err(_) => None,
+    };
+    let archive = match &downloaded {
+        Some(file) => file.path().to_path_buf(),
+        None if Path::new(source).is_file() => PathBuf::from(source),
+        None => anyhow::bail!("{} isn't a file", source),
+    };
+    let extracted = dest_dir.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        extract_archive(kind, &archive, &extracted, MAX_ARCHIVE_SIZE)
+    })
+    .await??;
+
+    if !PIPE_FILES.iter().any(|file| dest_dir.join(file).is_file()) {
+        anyhow::bail!(
+            "{} has no pipe.json, pipe.ts or pipe.js at its root",
+            source
+        );
+    }
+    Ok(())
+}
+
+/// Downloads the archive at `url` to a temp file, failing once it is over
+/// [`MAX_ARCHIVE_SIZE`].
+pub(crate) async fn fetch_archive(client: &Client, url: &str) -> Result<NamedTempFile> {
+    let mut response = client
+        .get(url)
+        .header("User-Agent", "screenpipe")
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map_err(|e| anyhow::anyhow!("failed to download the pipe archive: {}", e))?;
+
+    let archive = NamedTempFile::new()?;
+    let mut file = tokio::fs::File::create(archive.path()).await?;
+    let mut size = 0;
+    while let Some(chunk) = response.chunk().await? {
+        size += chunk.len() as u64;
+        if size > MAX_ARCHIVE_SIZE {
+            anyhow::bail!("the pipe archive is over {}", megabytes(MAX_ARCHIVE_SIZE));
+        }
+        file.write_all(&chunk).await?;
+    }
+    file.flush().await?;
+    debug!("downloaded a {} byte archive from {}", size, url);
+    Ok(archive)
+}
+
+/// Extracts `archive` into `dest_dir`, failing once more than `max_size` bytes would be
+/// written. Nothing is written when an entry escapes `dest_dir`.
+pub fn extract_archive(
+    kind: ArchiveKind,
+    archive: &Path,
+    dest_dir: &Path,
+    max_size: u64,
+) -> Result<()> {
+    match kind {
+        ArchiveKind::Zip => extract_zip(archive, dest_dir, max_size),
+        ArchiveKind::TarGz => extract_tar_gz(archive, dest_dir, max_size),
+    }
+}
+
+fn extract_zip(archive: &Path, dest_dir: &Path, max_size: u64) -> Result<()> {
+    let mut zip = zip::ZipArchive::new(File::open(archive)?)?;
+    let mut paths = Vec::with_capacity(zip.len());
+    for i in 0..zip.len() {
+        let entry = zip.by_index_raw(i)?;
+        paths.push((entry_path(entry.name())?, entry.size()));
+    }
+    let mut extractor = Extractor::new(dest_dir, &paths, max_size)?;
+
+    for (i, (path, _)) in paths.iter().enumerate() {
+        let mut entry = zip.by_index(i)?;
+        if entry.is_dir() {
+            extractor.dir(path)?;
+        } else if entry.is_file() {
+            extractor.file(path, &mut entry)?;
+        } else {
+            debug!("skipping link {:?} of the pipe archive", path);
+        }
+    }
+    extractor.finish()
+}
+
+fn extract_tar_gz(archive: &Path, dest_dir: &Path, max_size: u64) -> Result<()> {
+    let open = || -> Result<tar::Archive<flate2::read::GzDecoder<File>>> {
+        Ok(tar::Archive::new(flate2::read::GzDecoder::new(File::open(
+            archive,
+        )?)))
+    };
+    let mut paths = Vec::new();
+    for entry in open()?.entries()? {
+        let entry = entry?;
+        let name = entry.path()?.to_string_lossy().into_owned();
+        paths.push((entry_path(&name)?, entry.size()));
+    }
+    let mut extractor = Extractor::new(dest_dir, &paths, max_size)?;
+
+    for (entry, (path, _)) in open()?.entries()?.zip(&paths) {
+        let mut entry = entry?;
+        let kind = entry.header().entry_type();
+        if kind.is_dir() {
+            extractor.dir(path)?;
+        } else if kind.is_file() {
+            extractor.file(path, &mut entry)?;
+        } else {
+            debug!("skipping {:?} entry {:?} of the pipe archive", kind, path);
+        }
+    }
+    extractor.finish()
+}
+
+/// Path of an entry relative to the archive root, an error when it would escape it.
+fn entry_path(name: &str) -> Result<PathBuf> {
+    let mut path = PathBuf::new();
+    for component in Path::new(name).components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                anyhow::bail!("pipe archive entry {} is outside of the archive", name)
+            }
+        }
+    }
+    Ok(path)
+}
+
+struct Extractor<'a> {
+    dest_dir: &'a Path,
+    /// Folder all entries are in, left out of their paths
+    root: Option<PathBuf>,
+    max_size: u64,
+    written: u64,
+    files: usize,
+}
+
+impl<'a> Extractor<'a> {
+    /// Checks the sizes the archive lists before any entry is extracted.
+    fn new(dest_dir: &'a Path, paths: &[(PathBuf, u64)], max_size: u64) -> Result<Self> {
+        let listed = paths.iter().map(|(_, size)| size).sum::<u64>();
+        if listed > max_size {
+            anyhow::bail!("the pipe archive is over {} extracted", megabytes(max_size));
+        }
+        Ok(Extractor {
+            dest_dir,
+            root: common_root(paths.iter().map(|(path, _)| path.as_path())),
+            max_size,
+            written: 0,
+            files: 0,
+        })
+    }
+
+    /// Where the entry at `path` goes, `None` for the root folder and hidden files.
+    fn dest(&self, path: &Path) -> Option<PathBuf> {
+        let path = match &self.root {
+            Some(root) => path.strip_prefix(root).ok()?,
+            None => path,
+        };
+        if path.as_os_str().is_empty() || path.iter().any(is_hidden_file) {
+            return None;
+        }
+        Some(self.dest_dir.join(path))
+    }
+
+    fn dir(&mut self, path: &Path) -> Result<()> {
+        if let Some(dest) = self.dest(path) {
+            fs::create_dir_all(dest)?;
+        }
+        Ok(())
+    }
+
+    fn file(&mut self, path: &Path, reader: &mut impl Read) -> Result<()> {
+        let Some(dest) = self.dest(path) else {
+            debug!("skipping {:?} of the pipe archive", path);
+            return Ok(());
+        };
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // Listed sizes can't be trusted, what is written is counted
+        let left = self.max_size - self.written;
+        let written = io::copy(&mut reader.take(left + 1), &mut File::create(&dest)?)?;
+        if written > left {
+            anyhow::bail!(
+                "the pipe archive is over {} extracted",
+                megabytes(self.max_size)
+            );
+        }
+        self.written += written;
+        self.files += 1;
+        Ok(())
+    }
+
+    fn finish(self) -> Result<()> {
+        if self.files == 0 {
+            anyhow::bail!("the pipe archive has no files");
+        }
+        Ok(())
+    }
+}
+
+/// The folder every path is in, when there is one.
+fn common_root<'p>(paths: impl Iterator<Item = &'p Path>) -> Option<PathBuf> {
+    let mut root = None;
+    let mut nested = false;
+    for path in paths {
+        let mut components = path.components();
+        let first = components.next()?;
+        nested |= components.next().is_some();
+        match root {
+            None => root = Some(first),
+            Some(root) if root != first => return None,
+            Some(_) => {}
+        }
+    }
+    // A single file isn't a folder
+    root.filter(|_| nested)
+        .map(|root| PathBuf::from(root.as_os_str()))
+}
+
+fn megabytes(bytes: u64) -> String {
+    format!("{} MB", bytes / (1024 * 1024))
+}
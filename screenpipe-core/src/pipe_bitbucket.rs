@@ -0,0 +1,439 @@
+//! Pipes from bitbucket cloud: a repo, `https://bitbucket.org/<workspace>/<repo>`, or a
+//! ref and a folder in it, `https://bitbucket.org/<workspace>/<repo>/src/<ref>[/<folder>]`.
+//!
+//! A source is downloaded like one from gitlab: its ref is resolved to a commit, the
+//! folder is listed with the src api, one folder and a page of 100 entries at a time,
+//! and each file is fetched from the src api at that commit. The api has no blob shas,
+//! files are known by the commit they were downloaded at.
+
+use anyhow::Result;
+use reqwest::header::{HeaderMap, HeaderValue};
+use reqwest::Client;
+use serde::Deserialize;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tracing::debug;
+use url::Url;
+
+use crate::pipe_github::{is_commit_sha, tree_at_ref, GithubCommit, MAX_GITHUB_DEPTH};
+use crate::pipe_lock::PipeFiles;
+use crate::pipes::{
+    download_listed_files, is_hidden_file, sanitize_pipe_name, with_retries, DownloadProgress,
+    HttpOptions, InstalledFiles, ListedFile, ServerError,
+};
+
+/// Environment variable with the bitbucket access token pipes in private repos are
+/// downloaded with.
+pub const BITBUCKET_TOKEN_ENV: &str = "BITBUCKET_TOKEN";
+
+/// Host of bitbucket pipe urls.
+pub const BITBUCKET_HOST: &str = "bitbucket.org";
+
+/// Bitbucket cloud api, on a host of its own.
+pub const BITBUCKET_API: &str = "https://api.bitbucket.org";
+
+/// Entries of the src api per page, its maximum.
+const BITBUCKET_PAGE_SIZE: usize = 100;
+
+/// A pipe source on bitbucket.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BitbucketSource {
+    pub workspace: String,
+    pub repo: String,
+    /// Path segments after `src`, the ref then the folder
+    pub tree: Vec<String>,
+}
+
+/// An entry of a src api listing.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct BitbucketEntry {
+    /// Path in the repo
+    pub path: String,
+    /// `commit_file` or `commit_directory`
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// `link` for a symlink, `executable`, ...
+    #[serde(default)]
+    pub attributes: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct BitbucketPage {
+    values: Vec<BitbucketEntry>,
+    next: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct BitbucketRepo {
+    mainbranch: Option<BitbucketBranch>,
+}
+
+#[derive(Deserialize)]
+struct BitbucketBranch {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct BitbucketCommit {
+    hash: String,
+}
+
+/// Client for bitbucket requests, sending `token`, or the one in [`BITBUCKET_TOKEN_ENV`],
+/// as a bearer token. Its header is marked sensitive, debug output leaves it out.
+pub fn bitbucket_client(token: Option<&str>) -> Result<Client> {
+    bitbucket_client_with(&HttpOptions::default(), token)
+}
+
+/// [`bitbucket_client`] with the timeout and proxy of `http`.
+pub fn bitbucket_client_with(http: &HttpOptions, token: Option<&str>) -> Result<Client> {
+    let token = match token {
+        Some(token) => Some(token.to_string()),
+        None => std::env::var(BITBUCKET_TOKEN_ENV).ok(),
+    };
+    let mut headers = HeaderMap::new();
+    if let Some(token) = token.as_deref().map(str::trim).filter(|t| !t.is_empty()) {
+        let mut value = HeaderValue::from_str(&format!("Bearer {}", token))
+            .map_err(|_| anyhow::anyhow!("the bitbucket token has invalid characters"))?;
+        value.set_sensitive(true);
+        headers.insert(reqwest::header::AUTHORIZATION, value);
+    }
+    Ok(http.client_builder()?.default_headers(headers).build()?)
+}
+
+/// `client`, sending no token, for bitbucket requests without a [`BITBUCKET_TOKEN_ENV`],
+/// else a client sending it.
+pub(crate) fn bitbucket_client_for(client: &Client, http: &HttpOptions) -> Result<Client> {
+    if std::env::var_os(BITBUCKET_TOKEN_ENV).is_none() {
+        return Ok(client.clone());
+    }
+    bitbucket_client_with(http, None)
+}
+
+#[derive(Deserialize)]
+struct BitbucketError {
+    error: BitbucketErrorDetail,
+}
+
+#[derive(Deserialize)]
+struct BitbucketErrorDetail {
+    message: String,
+}
+
+/// Error of a bitbucket api response, with the `error.message` of its body when it has
+/// one.
+pub fn bitbucket_error(status: u16, body: &str) -> anyhow::Error {
+    match serde_json::from_str::<BitbucketError>(body) {
+        Ok(BitbucketError { error }) => {
+            anyhow::anyhow!("bitbucket api error ({}): {}", status, error.message)
+        }
+        Err(_) => anyhow::anyhow!("bitbucket api returned status {}", status),
+    }
+}
+
+async fn bitbucket_get(client: &Client, url: Url) -> Result<Option<reqwest::Response>> {
+    // Urls are left out of errors like for github
+    let response = client
+        .get(url)
+        .header("User-Agent", "screenpipe")
+        .send()
+        .await
+        .map_err(reqwest::Error::without_url)?;
+    match response.status().as_u16() {
+        200..=299 => Ok(Some(response)),
+        404 => Ok(None),
+        status => {
+            let body = response.text().await.unwrap_or_default();
+            let e = bitbucket_error(status, &body);
+            if status >= 500 {
+                return Err(ServerError(e).into());
+            }
+            Err(e)
+        }
+    }
+}
+
+impl BitbucketSource {
+    /// `None` for local paths and urls that aren't a bitbucket repo or src folder.
+    pub fn parse(source: &str) -> Option<Self> {
+        let url = Url::parse(source).ok()?;
+        let host = url.host_str()?;
+        if host != BITBUCKET_HOST && host != "www.bitbucket.org" {
+            return None;
+        }
+        let segments: Vec<&str> = url.path_segments()?.filter(|s| !s.is_empty()).collect();
+        let (workspace, repo, tree) = match segments.as_slice() {
+            [workspace, repo] => (workspace, repo, &[][..]),
+            [workspace, repo, "src", tree @ ..] if !tree.is_empty() => (workspace, repo, tree),
+            _ => return None,
+        };
+        Some(Self {
+            workspace: workspace.to_string(),
+            repo: repo.strip_suffix(".git").unwrap_or(repo).to_string(),
+            tree: tree.iter().map(|s| s.to_string()).collect(),
+        })
+    }
+
+    /// Id the pipe is installed under, as for a github source: the name of its folder,
+    /// or of the repo for a repo root or a ref without a folder.
+    pub fn pipe_id(&self) -> String {
+        match self.tree.as_slice() {
+            [_, .., folder] => sanitize_pipe_name(folder),
+            _ => sanitize_pipe_name(&self.repo),
+        }
+    }
+
+    /// Url of the repo on the `api` followed by `segments`, each encoded as one path
+    /// segment.
+    fn api_url(&self, api: &str, segments: &[&str]) -> Result<Url> {
+        let mut url = Url::parse(api)?;
+        url.path_segments_mut()
+            .map_err(|_| anyhow::anyhow!("invalid bitbucket api url: {}", api))?
+            .pop_if_empty()
+            .extend(["2.0", "repositories", &self.workspace, &self.repo])
+            .extend(segments);
+        Ok(url)
+    }
+
+    /// Src api url of `path` at `commit`.
+    fn src_url(&self, api: &str, commit: &GithubCommit, path: &str) -> Result<Url> {
+        let mut url = self.api_url(api, &["src", &commit.sha])?;
+        url.path_segments_mut()
+            .map_err(|_| anyhow::anyhow!("invalid bitbucket api url: {}", api))?
+            .extend(path.split('/').filter(|part| !part.is_empty()));
+        Ok(url)
+    }
+
+    fn not_found(&self) -> anyhow::Error {
+        anyhow::anyhow!(
+            "bitbucket repo {}/{} not found or token missing, set {} to download pipes from \
+             a private repo",
+            self.workspace,
+            self.repo,
+            BITBUCKET_TOKEN_ENV
+        )
+    }
+
+    /// The source at `git_ref`, a branch, tag or commit, with the folder of its url.
+    pub async fn at_ref(&self, client: &Client, api: &str, git_ref: &str) -> Result<Self> {
+        let folder = if self.tree.is_empty() {
+            String::new()
+        } else {
+            self.commit(client, api).await?.path
+        };
+        Ok(BitbucketSource {
+            tree: tree_at_ref(git_ref, &folder),
+            ..self.clone()
+        })
+    }
+
+    /// The ref and folder of the source, the main branch for a repo root, and the commit
+    /// the ref points to now. A ref with slashes is the shortest that exists.
+    pub async fn commit(&self, client: &Client, api: &str) -> Result<GithubCommit> {
+        if self.tree.is_empty() {
+            let repo: BitbucketRepo = bitbucket_get(client, self.api_url(api, &[])?)
+                .await?
+                .ok_or_else(|| self.not_found())?
+                .json()
+                .await?;
+            let git_ref = repo.mainbranch.map(|branch| branch.name).ok_or_else(|| {
+                anyhow::anyhow!("bitbucket repo {}/{} is empty", self.workspace, self.repo)
+            })?;
+            let sha = self
+                .find_commit(client, api, &git_ref)
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("{} has no branch {}", self.repo, git_ref))?;
+            return Ok(GithubCommit {
+                git_ref,
+                path: String::new(),
+                sha,
+            });
+        }
+        for i in 1..=self.tree.len() {
+            let git_ref = self.tree[..i].join("/");
+            if let Some(sha) = self.find_commit(client, api, &git_ref).await? {
+                return Ok(GithubCommit {
+                    git_ref,
+                    path: self.tree[i..].join("/"),
+                    sha,
+                });
+            }
+            // A commit has no slashes
+            if is_commit_sha(&git_ref) {
+                break;
+            }
+        }
+        if bitbucket_get(client, self.api_url(api, &[])?)
+            .await?
+            .is_none()
+        {
+            return Err(self.not_found());
+        }
+        anyhow::bail!(
+            "no branch, tag or commit of {}/{} matches {}",
+            self.workspace,
+            self.repo,
+            self.tree.join("/")
+        )
+    }
+
+    /// Sha of the commit `git_ref` points to, `None` when it isn't a ref.
+    async fn find_commit(
+        &self,
+        client: &Client,
+        api: &str,
+        git_ref: &str,
+    ) -> Result<Option<String>> {
+        let url = self.api_url(api, &["commit", git_ref])?;
+        let Some(response) = bitbucket_get(client, url).await? else {
+            return Ok(None);
+        };
+        let commit: BitbucketCommit = response.json().await?;
+        if !is_commit_sha(&commit.hash) {
+            anyhow::bail!("unexpected commit sha for {} at {}", self.repo, git_ref);
+        }
+        Ok(Some(commit.hash))
+    }
+
+    /// The files below the folder of `commit`, listing its folders one by one and
+    /// following the pages of each listing. Hidden files and symlinks are left out.
+    pub(crate) async fn list(
+        &self,
+        client: &Client,
+        api: &str,
+        commit: &GithubCommit,
+    ) -> Result<Vec<ListedFile>> {
+        let prefix = if commit.path.is_empty() {
+            String::new()
+        } else {
+            format!("{}/", commit.path)
+        };
+        let mut listed = Vec::new();
+        let mut folders = vec![commit.path.clone()];
+        while let Some(folder) = folders.pop() {
+            let mut url = self.src_url(api, commit, &folder)?;
+            // The src api lists a folder when its url ends with a slash
+            url.path_segments_mut()
+                .map_err(|_| anyhow::anyhow!("invalid bitbucket api url: {}", api))?
+                .push("");
+            url.query_pairs_mut()
+                .append_pair("pagelen", &BITBUCKET_PAGE_SIZE.to_string());
+            let mut next = Some(url);
+            while let Some(url) = next.take() {
+                let page: BitbucketPage = bitbucket_get(client, url)
+                    .await?
+                    .ok_or_else(|| match folder.as_str() {
+                        "" => self.not_found(),
+                        path => anyhow::anyhow!("{} isn't in the repo", path),
+                    })?
+                    .json()
+                    .await?;
+                for entry in page.values {
+                    let Some(rel) = entry.path.strip_prefix(&prefix) else {
+                        continue;
+                    };
+                    if rel
+                        .split('/')
+                        .any(|part| is_hidden_file(std::ffi::OsStr::new(part)))
+                    {
+                        debug!("skipping hidden file: {}", entry.path);
+                        continue;
+                    }
+                    // Paths come from the api, none may write outside of dest_dir
+                    if rel.contains('\\') || rel.split('/').any(|part| part == "..") {
+                        debug!("skipping entry with an invalid name: {}", entry.path);
+                        continue;
+                    }
+                    match entry.kind.as_str() {
+                        "commit_directory" if rel.split('/').count() > MAX_GITHUB_DEPTH => {
+                            anyhow::bail!(
+                                "{} is nested deeper than {} folders",
+                                rel,
+                                MAX_GITHUB_DEPTH
+                            )
+                        }
+                        "commit_directory" => folders.push(entry.path),
+                        // The src api answers with the target of a symlink, not its content
+                        "commit_file" if entry.attributes.iter().any(|a| a == "link") => {
+                            debug!("skipping symlink: {}", entry.path)
+                        }
+                        "commit_file" => listed.push(ListedFile {
+                            rel: rel.to_string(),
+                            path: entry.path,
+                            sha: commit.sha.clone(),
+                        }),
+                        kind => debug!("skipping {}: {}", entry.path, kind),
+                    }
+                }
+                next = match page.next {
+                    // Pages are only followed on the api the token is sent to
+                    Some(url) if url.starts_with(api) => Some(Url::parse(&url)?),
+                    Some(url) => anyhow::bail!("unexpected next page of a listing: {}", url),
+                    None => None,
+                };
+            }
+        }
+        if listed.is_empty() {
+            anyhow::bail!(
+                "{}/{} has no files at {}",
+                self.workspace,
+                self.repo,
+                commit.sha
+            );
+        }
+        Ok(listed)
+    }
+
+    /// Downloads the folder of `commit` into `dest_dir`, copying the files `installed`
+    /// has at the same commit and fetching `concurrency` files at once, each request made
+    /// up to `attempts` times. Returns the files written, each reported to `progress`.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn download(
+        &self,
+        client: &Client,
+        api: &str,
+        commit: &GithubCommit,
+        dest_dir: &Path,
+        installed: Arc<InstalledFiles>,
+        concurrency: usize,
+        attempts: u32,
+        progress: Option<&mpsc::Sender<DownloadProgress>>,
+    ) -> Result<PipeFiles> {
+        let listed = with_retries(attempts, "list", "", || self.list(client, api, commit)).await?;
+        download_listed_files(
+            listed,
+            dest_dir,
+            &installed,
+            concurrency,
+            attempts,
+            progress,
+            |file| async move {
+                self.file(client, api, commit, &file.path)
+                    .await
+                    .and_then(|content| {
+                        content.ok_or_else(|| anyhow::anyhow!("bitbucket api returned status 404"))
+                    })
+            },
+        )
+        .await
+    }
+
+    /// Content of the file at `path` in the repo at `commit`, `None` when it has none.
+    pub async fn file(
+        &self,
+        client: &Client,
+        api: &str,
+        commit: &GithubCommit,
+        path: &str,
+    ) -> Result<Option<Vec<u8>>> {
+        let url = self.src_url(api, commit, path)?;
+        let Some(response) = bitbucket_get(client, url).await? else {
+            return Ok(None);
+        };
+        let content = response
+            .bytes()
+            .await
+            .map_err(reqwest::Error::without_url)?;
+        Ok(Some(content.to_vec()))
+    }
+}
@@ -0,0 +1,300 @@
+//! Bundles: several pipes of one repo installed together. A `bundle.json` at the root of
+//! the source lists them, by name and path below it:
+//!
+//! ```json
+//! [
+//!   { "name": "notes", "path": "pipes/notes" },
+//!   { "name": "digest", "path": "pipes/digest" }
+//! ]
+//! ```
+//!
+//! A bundle installs from github, at the ref of its url, or from a local folder. Each
+//! pipe downloads as it would on its own, under the id of its folder. If one fails, the
+//! ones installed before it are removed and the copies they replaced put back.
+
+use anyhow::{Context, Result};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::path::{Component, Path, PathBuf};
+use tracing::{info, warn};
+
+use crate::pipe_github::{
+    expand_github_shorthand, fetch_raw_github_file, github_client_for, with_stored_token,
+    GithubSource, GITHUB_API, GITHUB_RAW,
+};
+use crate::pipe_link::{is_linked_pipe, remove_pipe_dir};
+use crate::pipe_lock::PipeLock;
+use crate::pipe_manager::{PipeManager, RuntimeConfig};
+use crate::pipes::{
+    install_pipe, pipe_id_from_source, sibling_dir, DownloadOptions, OverwritePolicy,
+};
+
+/// The list of pipes of a bundle, at the root of its source.
+pub const PIPE_BUNDLE_FILE: &str = "bundle.json";
+
+/// The content of a [`PIPE_BUNDLE_FILE`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct PipeBundle {
+    pub pipes: Vec<BundleEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BundleEntry {
+    pub name: String,
+    /// Folder of the pipe below the root of the bundle, `/` separated
+    pub path: String,
+}
+
+impl PipeBundle {
+    /// Fails on a bundle listing no pipe, or a pipe without a name or with a path that
+    /// isn't a folder below the root of the bundle.
+    pub fn parse(content: &[u8]) -> Result<Self> {
+        let bundle: PipeBundle = serde_json::from_slice(content)
+            .map_err(|e| anyhow::anyhow!("{}: {}", PIPE_BUNDLE_FILE, e))?;
+        if bundle.pipes.is_empty() {
+            anyhow::bail!("{} lists no pipe", PIPE_BUNDLE_FILE);
+        }
+        for entry in &bundle.pipes {
+            if entry.name.trim().is_empty() {
+                anyhow::bail!("{}: a pipe has no name", PIPE_BUNDLE_FILE);
+            }
+            let path = Path::new(&entry.path);
+            let below_root = path
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)));
+            if entry.path.trim().is_empty() || entry.path.contains('\\') || !below_root {
+                anyhow::bail!(
+                    "{}: path '{}' of {} isn't a folder of the bundle",
+                    PIPE_BUNDLE_FILE,
+                    entry.path,
+                    entry.name
+                );
+            }
+        }
+        Ok(bundle)
+    }
+}
+
+/// The pipes of the bundle at `source`, each with the source it downloads from. Fails
+/// when `source` has no [`PIPE_BUNDLE_FILE`] or two of its pipes would install under
+/// the same id.
+pub async fn bundle_sources(
+    client: &Client,
+    source: &str,
+    api: &str,
+    raw: &str,
+) -> Result<Vec<(BundleEntry, String)>> {
+    let expanded = expand_github_shorthand(source);
+    let source = expanded.as_deref().unwrap_or(source);
+    let sources: Vec<(BundleEntry, String)> = if let Some(github) = GithubSource::parse(source) {
+        let commit = github.commit(client, api).await?;
+        let content = fetch_raw_github_file(client, &github, &commit, raw, PIPE_BUNDLE_FILE)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("{} has no {}", source, PIPE_BUNDLE_FILE))?;
+        PipeBundle::parse(&content)?
+            .pipes
+            .into_iter()
+            .map(|entry| {
+                let folder: Vec<&str> = [commit.path.as_str(), entry.path.as_str()]
+                    .into_iter()
+                    .flat_map(|path| path.split('/'))
+                    .filter(|part| !part.is_empty())
+                    .collect();
+                let url = format!(
+                    "https://github.com/{}/{}/tree/{}/{}",
+                    github.owner,
+                    github.repo,
+                    commit.git_ref,
+                    folder.join("/")
+                );
+                (entry, url)
+            })
+            .collect()
+    } else if Path::new(source).is_dir() {
+        let root = Path::new(source);
+        let content = match tokio::fs::read(root.join(PIPE_BUNDLE_FILE)).await {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                anyhow::bail!("{} has no {}", source, PIPE_BUNDLE_FILE)
+            }
+            Err(e) => return Err(e.into()),
+        };
+        PipeBundle::parse(&content)?
+            .pipes
+            .into_iter()
+            .map(|entry| {
+                let path = root.join(&entry.path).to_string_lossy().into_owned();
+                (entry, path)
+            })
+            .collect()
+    } else {
+        anyhow::bail!(
+            "{} isn't a github repo or a local folder, bundles install from one of those",
+            source
+        );
+    };
+
+    let mut ids = Vec::new();
+    for (entry, source) in &sources {
+        let id = pipe_id_from_source(source)
+            .ok_or_else(|| anyhow::anyhow!("invalid pipe source: {}", source))?;
+        if ids.contains(&id) {
+            anyhow::bail!(
+                "{}: {} installs as pipe {}, as another pipe of the bundle does",
+                PIPE_BUNDLE_FILE,
+                entry.name,
+                id
+            );
+        }
+        ids.push(id);
+    }
+    Ok(sources)
+}
+
+/// Installs every pipe of the bundle at `source`, or none of them. Returns their folders,
+/// in the order of its [`PIPE_BUNDLE_FILE`].
+#[deprecated(note = "use PipeManager::download_pipe_bundle")]
+pub async fn download_pipe_bundle(source: &str, screenpipe_dir: PathBuf) -> Result<Vec<PathBuf>> {
+    PipeManager::new(screenpipe_dir, RuntimeConfig::default())?
+        .download_pipe_bundle(source)
+        .await
+}
+
+/// [`download_pipe_bundle`], each pipe downloaded with `options`.
+#[deprecated(note = "use PipeManager::download_pipe_bundle_with")]
+pub async fn download_pipe_bundle_with(
+    source: &str,
+    screenpipe_dir: PathBuf,
+    options: DownloadOptions,
+) -> Result<Vec<PathBuf>> {
+    PipeManager::with_http(screenpipe_dir, &options.http)?
+        .download_pipe_bundle_with(source, options)
+        .await
+}
+
+/// Installs the bundle at `source` to `screenpipe_dir`, each pipe downloaded with `options`
+/// and `client`.
+pub(crate) async fn install_pipe_bundle(
+    source: &str,
+    screenpipe_dir: PathBuf,
+    options: DownloadOptions,
+    client: &Client,
+) -> Result<Vec<PathBuf>> {
+    let options = with_stored_token(options, &screenpipe_dir).await?;
+    let github = github_client_for(client, &options)?;
+    let sources = bundle_sources(&github, source, GITHUB_API, GITHUB_RAW).await?;
+    info!(
+        "installing {} pipes of the bundle {}",
+        sources.len(),
+        source
+    );
+
+    // Each pipe a download replaces, set aside until the whole bundle is installed
+    let mut installs: Vec<(PathBuf, Option<PathBuf>)> = Vec::new();
+    let mut installed = Vec::new();
+    let mut failed = None;
+    for (entry, entry_source) in &sources {
+        match install_entry(
+            entry_source,
+            &screenpipe_dir,
+            &options,
+            client,
+            &mut installs,
+        )
+        .await
+        {
+            Ok(dir) => installed.push(dir),
+            Err(e) => {
+                failed = Some(e.context(format!("pipe {} of the bundle", entry.name)));
+                break;
+            }
+        }
+    }
+
+    if let Some(e) = failed {
+        for (pipe_dir, backup) in installs.into_iter().rev() {
+            if let Err(e) = put_back(&pipe_dir, backup.as_deref()).await {
+                warn!("failed to undo the install of {:?}: {}", pipe_dir, e);
+            }
+        }
+        return Err(e);
+    }
+    for backup in installs.into_iter().filter_map(|(_, backup)| backup) {
+        if let Err(e) = remove_pipe_dir(&backup).await {
+            warn!("failed to remove the previous copy {:?}: {}", backup, e);
+        }
+    }
+    Ok(installed)
+}
+
+/// Installs the pipe of the bundle at `source`, recording in `installs` what undoes it.
+/// Only a download overwriting the installed pipe sets it aside, one keeping it with
+/// [`OverwritePolicy::Skip`] or failing on it leaves it in place.
+async fn install_entry(
+    source: &str,
+    screenpipe_dir: &Path,
+    options: &DownloadOptions,
+    client: &Client,
+    installs: &mut Vec<(PathBuf, Option<PathBuf>)>,
+) -> Result<PathBuf> {
+    if options.overwrite != OverwritePolicy::Overwrite {
+        return install_pipe(
+            source,
+            screenpipe_dir.to_path_buf(),
+            options.clone(),
+            client,
+        )
+        .await;
+    }
+    let id = pipe_id_from_source(source).unwrap_or_default();
+    let pipe_dir = screenpipe_dir.join("pipes").join(&id);
+    let backup = set_aside(&pipe_dir, &id).await?;
+    installs.push((pipe_dir.clone(), backup));
+    let dir = install_pipe(
+        source,
+        screenpipe_dir.to_path_buf(),
+        options.clone(),
+        client,
+    )
+    .await?;
+    if dir != pipe_dir {
+        // Renamed past a pipe of another source, which stays where it was
+        let (pipe_dir, backup) = installs.pop().unwrap_or_default();
+        put_back(&pipe_dir, backup.as_deref()).await?;
+        installs.push((dir.clone(), None));
+    }
+    Ok(dir)
+}
+
+/// Moves the pipe installed in `pipe_dir` aside, leaving its pipe.json for the download
+/// to keep its settings from, and its lock to tell where it is from. `None` when none is
+/// installed.
+async fn set_aside(pipe_dir: &Path, id: &str) -> Result<Option<PathBuf>> {
+    if !pipe_dir.exists() && !is_linked_pipe(pipe_dir) {
+        return Ok(None);
+    }
+    let backup = sibling_dir(pipe_dir, "bundle", id);
+    tokio::fs::rename(pipe_dir, &backup)
+        .await
+        .with_context(|| format!("failed to move {:?} aside", pipe_dir))?;
+    tokio::fs::create_dir_all(pipe_dir).await?;
+    if backup.join("pipe.json").exists() {
+        tokio::fs::copy(backup.join("pipe.json"), pipe_dir.join("pipe.json")).await?;
+    }
+    if let Some(lock) = PipeLock::load(&backup)? {
+        lock.without_files().save(pipe_dir).await?;
+    }
+    Ok(Some(backup))
+}
+
+/// Removes what was installed in `pipe_dir` and puts `backup` back.
+async fn put_back(pipe_dir: &Path, backup: Option<&Path>) -> Result<()> {
+    if pipe_dir.exists() || is_linked_pipe(pipe_dir) {
+        remove_pipe_dir(pipe_dir).await?;
+    }
+    if let Some(backup) = backup {
+        tokio::fs::rename(backup, pipe_dir).await?;
+    }
+    Ok(())
+}
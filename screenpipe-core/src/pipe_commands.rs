@@ -0,0 +1,89 @@
+//! Commands a pipe prints to talk to screenpipe, after github actions' workflow commands:
+//! a line of its stdout `::screenpipe-<command> <params>::<value>` is run rather than
+//! logged. `set-output name=<name>::<value>` sets an output of the pipe, see
+//! [`PipeOutput`], `warning::<message>` and `error::<message>` are logged at that level.
+//!
+//! As in github actions `%`, `\r` and `\n` are escaped in values as `%25`, `%0D` and
+//! `%0A`, and `:` and `,` in params as `%3A` and `%2C` too. A line with an unknown
+//! command, or a `set-output` without a name, is logged as any other.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tracing::{debug, error, warn};
+
+/// What a line of a pipe's stdout starts with to be a command.
+pub const COMMAND_PREFIX: &str = "::screenpipe-";
+
+/// A command of a pipe.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutputCommand {
+    SetOutput { name: String, value: String },
+    Warning(String),
+    Error(String),
+}
+
+/// The command `line` is, `None` for a line to log.
+pub fn parse_output_command(line: &str) -> Option<OutputCommand> {
+    let command = line.trim_end_matches('\r').strip_prefix(COMMAND_PREFIX)?;
+    let (command, value) = command.split_once("::")?;
+    let (command, params) = command.split_once(' ').unwrap_or((command, ""));
+    let value = unescape(value, false);
+    match command {
+        "set-output" => {
+            let name = params
+                .split(',')
+                .filter_map(|param| param.trim().split_once('='))
+                .find(|(key, _)| *key == "name")
+                .map(|(_, name)| unescape(name, true))
+                .filter(|name| !name.is_empty())?;
+            Some(OutputCommand::SetOutput { name, value })
+        }
+        "warning" => Some(OutputCommand::Warning(value)),
+        "error" => Some(OutputCommand::Error(value)),
+        _ => None,
+    }
+}
+
+/// `%25` last, `%250A` is a literal `%0A`.
+fn unescape(escaped: &str, param: bool) -> String {
+    let mut unescaped = escaped.replace("%0D", "\r").replace("%0A", "\n");
+    if param {
+        unescaped = unescaped.replace("%3A", ":").replace("%2C", ",");
+    }
+    unescaped.replace("%25", "%")
+}
+
+/// The outputs a pipe sets, by name, the last value of each. A clone shares them, keep
+/// one of those given in [`crate::PipeRunOptions`] to read them once the pipe exited.
+#[derive(Debug, Clone, Default)]
+pub struct PipeOutput {
+    outputs: Arc<Mutex<HashMap<String, String>>>,
+}
+
+impl PipeOutput {
+    pub fn get(&self, name: &str) -> Option<String> {
+        self.outputs.lock().unwrap().get(name).cloned()
+    }
+
+    pub fn outputs(&self) -> HashMap<String, String> {
+        self.outputs.lock().unwrap().clone()
+    }
+
+    fn set(&self, name: String, value: String) {
+        self.outputs.lock().unwrap().insert(name, value);
+    }
+}
+
+/// Runs `command` of `pipe`, an output is only logged without `output`.
+pub(crate) fn run_output_command(pipe: &str, command: OutputCommand, output: Option<&PipeOutput>) {
+    match command {
+        OutputCommand::SetOutput { name, value } => {
+            debug!("[{}] set output {}", pipe, name);
+            if let Some(output) = output {
+                output.set(name, value);
+            }
+        }
+        OutputCommand::Warning(message) => warn!("[{}] {}", pipe, message),
+        OutputCommand::Error(message) => error!("[{}] {}", pipe, message),
+    }
+}
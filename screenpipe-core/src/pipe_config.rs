@@ -0,0 +1,249 @@
+//! The one way pipe config files are read: a pipe's `pipe.json`, and the `package.json`
+//! read before it is installed and run.
+//!
+//! Files are hand edited and come from anywhere, so before parsing one its size is
+//! checked against [`MAX_CONFIG_BYTES`], and a leading utf-8 BOM, as some windows
+//! editors write, is skipped. A file that doesn't parse fails with its path, line and
+//! column. In lenient mode, see [`set_lenient`], trailing commas in objects and arrays
+//! are accepted too.
+
+use serde_json::Value;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::io::AsyncReadExt;
+
+/// Largest config file read, pipe.json files are a few kilobytes.
+pub const MAX_CONFIG_BYTES: u64 = 1024 * 1024;
+
+const BOM: &[u8] = b"\xEF\xBB\xBF";
+
+static LENIENT: AtomicBool = AtomicBool::new(false);
+
+/// Accept trailing commas in every config file read from now on.
+pub fn set_lenient(lenient: bool) {
+    LENIENT.store(lenient, Ordering::Relaxed);
+}
+
+pub fn lenient() -> bool {
+    LENIENT.load(Ordering::Relaxed)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoadOptions {
+    pub max_bytes: u64,
+    /// Accept trailing commas
+    pub lenient: bool,
+}
+
+impl Default for LoadOptions {
+    /// [`MAX_CONFIG_BYTES`], lenient as set with [`set_lenient`].
+    fn default() -> Self {
+        Self {
+            max_bytes: MAX_CONFIG_BYTES,
+            lenient: lenient(),
+        }
+    }
+}
+
+/// Why a config file couldn't be loaded. Lines and columns start at 1, columns count
+/// bytes.
+#[derive(Debug)]
+pub enum ConfigError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    TooLarge {
+        path: PathBuf,
+        size: u64,
+        limit: u64,
+    },
+    NotUtf8 {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+    },
+    Syntax {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+}
+
+impl ConfigError {
+    pub fn path(&self) -> &Path {
+        match self {
+            ConfigError::Io { path, .. }
+            | ConfigError::TooLarge { path, .. }
+            | ConfigError::NotUtf8 { path, .. }
+            | ConfigError::Syntax { path, .. } => path,
+        }
+    }
+
+    /// The file doesn't exist, which most callers treat as an empty config.
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, ConfigError::Io { source, .. } if source.kind() == io::ErrorKind::NotFound)
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            ConfigError::TooLarge { path, size, limit } => write!(
+                f,
+                "{}: file is {} bytes, config files are limited to {} bytes",
+                path.display(),
+                size,
+                limit
+            ),
+            ConfigError::NotUtf8 { path, line, column } => write!(
+                f,
+                "{}: not valid utf-8 at line {}, column {}",
+                path.display(),
+                line,
+                column
+            ),
+            ConfigError::Syntax {
+                path,
+                line,
+                column,
+                message,
+            } => write!(
+                f,
+                "{}: invalid json at line {}, column {}: {}",
+                path.display(),
+                line,
+                column,
+                message
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Reads and parses the config file at `path` with the default [`LoadOptions`].
+pub async fn load_config(path: &Path) -> Result<Value, ConfigError> {
+    load_config_with(path, LoadOptions::default()).await
+}
+
+pub async fn load_config_with(path: &Path, options: LoadOptions) -> Result<Value, ConfigError> {
+    let io_error = |source| ConfigError::Io {
+        path: path.to_path_buf(),
+        source,
+    };
+    let too_large = |size| ConfigError::TooLarge {
+        path: path.to_path_buf(),
+        size,
+        limit: options.max_bytes,
+    };
+
+    let file = tokio::fs::File::open(path).await.map_err(io_error)?;
+    let size = file.metadata().await.map_err(io_error)?.len();
+    if size > options.max_bytes {
+        return Err(too_large(size));
+    }
+    // The file may grow between the check and the read
+    let mut content = Vec::with_capacity(size as usize);
+    file.take(options.max_bytes + 1)
+        .read_to_end(&mut content)
+        .await
+        .map_err(io_error)?;
+    if content.len() as u64 > options.max_bytes {
+        return Err(too_large(content.len() as u64));
+    }
+    parse_config(&content, path, options.lenient)
+}
+
+/// Parses the content of a config file, `path` is only used in errors.
+pub fn parse_config(content: &[u8], path: &Path, lenient: bool) -> Result<Value, ConfigError> {
+    let content = content.strip_prefix(BOM).unwrap_or(content);
+    if let Err(e) = std::str::from_utf8(content) {
+        let (line, column) = position_of(content, e.valid_up_to());
+        return Err(ConfigError::NotUtf8 {
+            path: path.to_path_buf(),
+            line,
+            column,
+        });
+    }
+
+    let stripped;
+    let content = if lenient {
+        stripped = strip_trailing_commas(content);
+        &stripped
+    } else {
+        content
+    };
+    serde_json::from_slice(content).map_err(|e| {
+        // serde_json ends its messages with the position, given separately here
+        let message = e.to_string();
+        let suffix = format!(" at line {} column {}", e.line(), e.column());
+        ConfigError::Syntax {
+            path: path.to_path_buf(),
+            line: e.line(),
+            column: e.column(),
+            message: message
+                .strip_suffix(&suffix)
+                .unwrap_or(&message)
+                .to_string(),
+        }
+    })
+}
+
+/// Line and column of the byte at `index`.
+fn position_of(content: &[u8], index: usize) -> (usize, usize) {
+    let before = &content[..index];
+    let line = before.iter().filter(|&&b| b == b'\n').count() + 1;
+    let line_start = before
+        .iter()
+        .rposition(|&b| b == b'\n')
+        .map_or(0, |i| i + 1);
+    (line, index - line_start + 1)
+}
+
+/// Blanks the commas that close an object or array, outside of strings, e.g. in
+/// `[1, 2,]` but not in `[1,,]`. Blanked rather than removed so errors further on keep
+/// their columns.
+fn strip_trailing_commas(content: &[u8]) -> Vec<u8> {
+    let mut out = content.to_vec();
+    let mut in_string = false;
+    let mut escaped = false;
+    // Last byte outside of whitespace
+    let mut previous = None;
+    for (i, &byte) in content.iter().enumerate() {
+        if in_string {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match byte {
+            b'"' => in_string = true,
+            b',' if !matches!(previous, Some(b',') | Some(b'[') | Some(b'{') | None) => {
+                let next = content[i + 1..].iter().find(|b| !b.is_ascii_whitespace());
+                if matches!(next, Some(b'}') | Some(b']')) {
+                    out[i] = b' ';
+                }
+            }
+            _ => {}
+        }
+        if !byte.is_ascii_whitespace() {
+            previous = Some(byte);
+        }
+    }
+    out
+}
@@ -0,0 +1,110 @@
+//! Json schema of a pipe's settings, for the app to render a settings form with. A pipe
+//! may ship its own `config_schema.json`, else one is generated from the `fields` of its
+//! pipe.json and written next to it when the pipe is downloaded:
+//!
+//! ```json
+//! { "name": "interval", "type": "number", "default": 5, "description": "minutes" }
+//! ```
+//!
+//! becomes the property `interval` of an object schema, `{"type": "number", "default": 5,
+//! "description": "minutes"}`. Kinds json has no type for, `time`, `path`, `window`,
+//! `app` and `contentType`, are strings with the kind in `x-screenpipe-type`, so the
+//! form can show a picker. A field neither optional nor with a default is required.
+
+use anyhow::Result;
+use serde_json::{json, Map, Value};
+use std::path::Path;
+
+use crate::pipe_config::load_config;
+use crate::pipe_manifest::{type_name, validate_pipe_manifest, ManifestField};
+
+/// The schema of a pipe's settings, in its folder.
+pub const PIPE_CONFIG_SCHEMA_FILE: &str = "config_schema.json";
+
+/// Kinds of field the app has a picker for, a string in the settings.
+const PICKER_KINDS: [&str; 5] = ["time", "path", "window", "app", "contentType"];
+
+/// The schema of the settings `fields` describe.
+pub fn config_schema(fields: &[ManifestField]) -> Value {
+    let mut properties = Map::new();
+    let mut required = Vec::new();
+    for field in fields {
+        properties.insert(field.name.clone(), field_schema(field));
+        if !field.optional && field.default.is_none() {
+            required.push(json!(field.name));
+        }
+    }
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "type": "object",
+        "properties": properties,
+        "required": required,
+        "additionalProperties": false,
+    })
+}
+
+fn field_schema(field: &ManifestField) -> Value {
+    let mut schema = Map::new();
+    let kind = field
+        .kind
+        .as_deref()
+        .map(str::trim)
+        .filter(|k| !k.is_empty());
+    let json_type = match kind {
+        Some(kind) if PICKER_KINDS.contains(&kind) => {
+            schema.insert("x-screenpipe-type".to_string(), json!(kind));
+            Some("string")
+        }
+        Some(kind @ ("string" | "number" | "integer" | "boolean" | "array" | "object")) => {
+            Some(kind)
+        }
+        // A kind of its own, the default tells what the value is
+        _ => match field.default.as_ref().map(type_name) {
+            Some("null") | None => None,
+            Some(name) => Some(name),
+        },
+    };
+    if let Some(json_type) = json_type {
+        schema.insert("type".to_string(), json!(json_type));
+    }
+    if let Some(description) = &field.description {
+        schema.insert("description".to_string(), json!(description));
+    }
+    if let Some(default) = &field.default {
+        schema.insert("default".to_string(), default.clone());
+    }
+    Value::Object(schema)
+}
+
+/// The schema of the settings of the pipe in `pipe_dir`, its [`PIPE_CONFIG_SCHEMA_FILE`],
+/// or the one its pipe.json `fields` give. Fails when the file isn't a json object.
+pub async fn get_pipe_config_schema(pipe_dir: &Path) -> Result<Value> {
+    let path = pipe_dir.join(PIPE_CONFIG_SCHEMA_FILE);
+    match load_config(&path).await {
+        Ok(schema) if schema.is_object() => Ok(schema),
+        Ok(other) => anyhow::bail!(
+            "{}: expected object, found {}",
+            path.display(),
+            type_name(&other)
+        ),
+        Err(e) if e.is_not_found() => {
+            let manifest = validate_pipe_manifest(pipe_dir).await?;
+            Ok(config_schema(&manifest.fields))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Writes the schema of `fields` to the [`PIPE_CONFIG_SCHEMA_FILE`] of `pipe_dir`, unless
+/// the pipe ships one or has no fields.
+pub(crate) async fn write_pipe_config_schema(
+    pipe_dir: &Path,
+    fields: &[ManifestField],
+) -> Result<()> {
+    let path = pipe_dir.join(PIPE_CONFIG_SCHEMA_FILE);
+    if fields.is_empty() || path.exists() {
+        return Ok(());
+    }
+    tokio::fs::write(path, serde_json::to_vec_pretty(&config_schema(fields))?).await?;
+    Ok(())
+}
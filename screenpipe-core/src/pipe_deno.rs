@@ -0,0 +1,169 @@
+//! What a pipe running with deno may do. Deno starts it with the permissions it names in
+//! the `permissions` of its pipe.json, `"permissions": ["net", "env"]` passes
+//! `--allow-net --allow-env`, and `--no-prompt` so anything else fails rather than
+//! waiting on a prompt nobody sees. Scopes such as `read:ocr` are the user's to grant,
+//! only the bare names of [`DenoPermission`] are deno's.
+//!
+//! `policy.json` in the screenpipe dir lists the permissions pipes may ask for,
+//! [`DEFAULT_DENO_ALLOWLIST`] without one. A pipe asking for one that isn't listed
+//! doesn't run.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+use crate::pipe_config::load_config;
+use crate::pipe_manifest::{validate_pipe_manifest, PipeManifest};
+use crate::pipes::PipeError;
+
+/// The policy pipes run under, in the screenpipe dir.
+pub const PIPE_POLICY_FILE: &str = "policy.json";
+
+/// Permissions pipes may ask for when there is no [`PIPE_POLICY_FILE`].
+pub const DEFAULT_DENO_ALLOWLIST: [DenoPermission; 4] = [
+    DenoPermission::Read,
+    DenoPermission::Write,
+    DenoPermission::Net,
+    DenoPermission::Env,
+];
+
+/// A permission deno grants with an `--allow-` flag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DenoPermission {
+    Read,
+    Write,
+    Net,
+    Env,
+    Run,
+    Ffi,
+    Sys,
+    /// Every other one
+    All,
+}
+
+impl DenoPermission {
+    const ALL: [DenoPermission; 8] = [
+        DenoPermission::Read,
+        DenoPermission::Write,
+        DenoPermission::Net,
+        DenoPermission::Env,
+        DenoPermission::Run,
+        DenoPermission::Ffi,
+        DenoPermission::Sys,
+        DenoPermission::All,
+    ];
+
+    /// The permission `name` is, e.g. `net`. `None` for scopes like `read:ocr`.
+    pub fn parse(name: &str) -> Option<DenoPermission> {
+        Self::ALL
+            .into_iter()
+            .find(|permission| permission.to_string() == name)
+    }
+}
+
+impl std::fmt::Display for DenoPermission {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            DenoPermission::Read => "read",
+            DenoPermission::Write => "write",
+            DenoPermission::Net => "net",
+            DenoPermission::Env => "env",
+            DenoPermission::Run => "run",
+            DenoPermission::Ffi => "ffi",
+            DenoPermission::Sys => "sys",
+            DenoPermission::All => "all",
+        })
+    }
+}
+
+/// The deno permissions in the `permissions` of `manifest`, sorted.
+pub fn parse_permissions(manifest: &PipeManifest) -> Vec<DenoPermission> {
+    let mut permissions: Vec<DenoPermission> = manifest
+        .permissions
+        .iter()
+        .filter_map(|name| DenoPermission::parse(name.trim()))
+        .collect();
+    permissions.sort();
+    permissions.dedup();
+    permissions
+}
+
+pub fn permission_to_flag(p: DenoPermission) -> &'static str {
+    match p {
+        DenoPermission::Read => "--allow-read",
+        DenoPermission::Write => "--allow-write",
+        DenoPermission::Net => "--allow-net",
+        DenoPermission::Env => "--allow-env",
+        DenoPermission::Run => "--allow-run",
+        DenoPermission::Ffi => "--allow-ffi",
+        DenoPermission::Sys => "--allow-sys",
+        DenoPermission::All => "--allow-all",
+    }
+}
+
+/// The [`PIPE_POLICY_FILE`], `{"allowed_deno_permissions": ["read", "net", ...]}`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PipePolicy {
+    #[serde(default = "default_allowlist")]
+    pub allowed_deno_permissions: Vec<DenoPermission>,
+}
+
+impl Default for PipePolicy {
+    fn default() -> Self {
+        Self {
+            allowed_deno_permissions: default_allowlist(),
+        }
+    }
+}
+
+fn default_allowlist() -> Vec<DenoPermission> {
+    DEFAULT_DENO_ALLOWLIST.to_vec()
+}
+
+pub fn pipe_policy_path(screenpipe_dir: &Path) -> PathBuf {
+    screenpipe_dir.join(PIPE_POLICY_FILE)
+}
+
+/// The policy in `screenpipe_dir`, the default one when it has none. One that can't be
+/// read fails, pipes don't run under a policy other than the one set.
+pub async fn load_pipe_policy(screenpipe_dir: &Path) -> Result<PipePolicy> {
+    let path = pipe_policy_path(screenpipe_dir);
+    let policy = match load_config(&path).await {
+        Ok(policy) => policy,
+        Err(e) if e.is_not_found() => return Ok(PipePolicy::default()),
+        Err(e) => return Err(e.into()),
+    };
+    PipePolicy::deserialize(&policy).map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))
+}
+
+/// The deno permissions `pipe` runs with. Fails with [`PipeError::NotAllowed`] when it
+/// asks for one the policy doesn't allow.
+pub async fn pipe_deno_permissions(
+    pipe: &str,
+    pipe_dir: &Path,
+    screenpipe_dir: &Path,
+) -> Result<Vec<DenoPermission>> {
+    let permissions = parse_permissions(&validate_pipe_manifest(pipe_dir).await?);
+    if permissions.contains(&DenoPermission::All) {
+        warn!(
+            "pipe {} asks for every deno permission, --allow-all, name the ones it needs instead",
+            pipe
+        );
+    }
+    let policy = load_pipe_policy(screenpipe_dir).await?;
+    let denied: Vec<String> = permissions
+        .iter()
+        .filter(|permission| !policy.allowed_deno_permissions.contains(permission))
+        .map(ToString::to_string)
+        .collect();
+    if !denied.is_empty() {
+        return Err(PipeError::NotAllowed {
+            pipe: pipe.to_string(),
+            permissions: denied,
+        }
+        .into());
+    }
+    Ok(permissions)
+}
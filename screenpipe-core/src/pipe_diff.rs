@@ -0,0 +1,198 @@
+//! What changed between an installed pipe and the source it was installed from, before
+//! updating it. The source is downloaded into a temporary folder and both are compared
+//! file by file, a file changed in both gets a unified diff.
+//!
+//! Hidden files, what [`DEFAULT_PIPE_IGNORE`](crate::pipe_ignore::DEFAULT_PIPE_IGNORE)
+//! leaves out, `node_modules` installed by the pipe's first run among them, and the
+//! files screenpipe writes into the pipe folder, its lock, integrity record, generated
+//! settings schema and run stats, aren't compared.
+
+use anyhow::Result;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use similar::TextDiff;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+use crate::pipe_config_schema::PIPE_CONFIG_SCHEMA_FILE;
+use crate::pipe_github::{download_github_source, with_stored_token, GithubSource};
+use crate::pipe_ignore::PipeIgnore;
+use crate::pipe_link::is_linked_pipe;
+use crate::pipe_lock::{downloaded_pipe, PIPE_LOCK_FILE};
+use crate::pipe_manager::{PipeManager, RuntimeConfig};
+use crate::pipe_stats::{PIPE_LATEST_STATS_FILE, PIPE_STATS_FILE};
+use crate::pipes::{
+    install_pipe, installed_pipe_dir, installed_source, is_hidden_file, pipe_id_from_source,
+    DownloadOptions,
+};
+
+/// Files screenpipe writes into a pipe folder, left out of the diff.
+const WRITTEN_FILES: [&str; 4] = [
+    PIPE_LOCK_FILE,
+    PIPE_CONFIG_SCHEMA_FILE,
+    PIPE_STATS_FILE,
+    PIPE_LATEST_STATS_FILE,
+];
+
+/// Changes from an installed pipe to its source, by path below the pipe root with `/`
+/// separators, sorted.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PipeDiff {
+    /// Files only the source has
+    pub added: Vec<String>,
+    /// Files only the installed pipe has
+    pub removed: Vec<String>,
+    /// Files both have, with different content
+    pub modified: Vec<(String, FileDiff)>,
+}
+
+impl PipeDiff {
+    /// Whether the installed pipe has the files of its source.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+}
+
+/// How a file changed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileDiff {
+    /// Unified diff from the installed file, `a/<path>`, to the source's, `b/<path>`.
+    /// Empty for a binary file
+    pub unified: String,
+    /// Either file isn't utf-8 text
+    pub binary: bool,
+}
+
+/// What changed between the installed pipe `pipe` and its source. Fails for pipes copied
+/// from a local path without a source, and for linked pipes.
+#[deprecated(note = "use PipeManager::diff_pipe")]
+pub async fn diff_pipe(pipe: &str, screenpipe_dir: &Path, client: &Client) -> Result<PipeDiff> {
+    PipeManager::new(screenpipe_dir.to_path_buf(), RuntimeConfig::default())?
+        .diff_pipe_using(pipe, client)
+        .await
+}
+
+/// [`diff_pipe`], with github at `api` and `raw`.
+pub async fn diff_pipe_with(
+    pipe: &str,
+    screenpipe_dir: &Path,
+    client: &Client,
+    api: &str,
+    raw: &str,
+) -> Result<PipeDiff> {
+    let pipe_dir = installed_pipe_dir(pipe, screenpipe_dir)?;
+    if is_linked_pipe(&pipe_dir) {
+        anyhow::bail!("pipe {} is linked, it is the folder it links to", pipe);
+    }
+    let source = installed_source(&pipe_dir)
+        .await
+        .ok_or_else(|| anyhow::anyhow!("pipe {} has no source to diff with", pipe))?;
+    // At the ref it was downloaded at, which may not be the one of its url
+    let git_ref = downloaded_pipe(&pipe_dir)
+        .await
+        .filter(|downloaded| downloaded.source == source)
+        .map(|downloaded| downloaded.commit.git_ref);
+
+    let remote = tempfile::TempDir::new()?;
+    let remote_dir = if let Some(mut github) = GithubSource::parse(&source) {
+        if let Some(git_ref) = &git_ref {
+            github = github.at_ref(client, api, git_ref).await?;
+        }
+        let commit = github.commit(client, api).await?;
+        debug!("diffing pipe {} with {} at {}", pipe, source, commit.sha);
+        download_github_source(client, &github, &commit, remote.path(), api, raw).await?;
+        remote.path().to_path_buf()
+    } else {
+        debug!("diffing pipe {} with {}", pipe, source);
+        let options = DownloadOptions {
+            git_ref,
+            ..Default::default()
+        };
+        let options = with_stored_token(options, screenpipe_dir).await?;
+        let client = options.http.client()?;
+        install_pipe(&source, remote.path().to_path_buf(), options, &client).await?;
+        let id = pipe_id_from_source(&source)
+            .ok_or_else(|| anyhow::anyhow!("invalid pipe source: {}", source))?;
+        remote.path().join("pipes").join(id)
+    };
+
+    tokio::task::spawn_blocking(move || diff_dirs(&pipe_dir, &remote_dir)).await?
+}
+
+/// Changes from the pipe files in `installed` to those in `remote`.
+pub fn diff_dirs(installed: &Path, remote: &Path) -> Result<PipeDiff> {
+    let ignore = PipeIgnore::parse(None)?;
+    let mut installed_files = BTreeMap::new();
+    pipe_files(installed, installed, &ignore, &mut installed_files)?;
+    let mut remote_files = BTreeMap::new();
+    pipe_files(remote, remote, &ignore, &mut remote_files)?;
+
+    let mut diff = PipeDiff::default();
+    for (path, remote_path) in &remote_files {
+        let Some(installed_path) = installed_files.get(path) else {
+            diff.added.push(path.clone());
+            continue;
+        };
+        let old = fs::read(installed_path)?;
+        let new = fs::read(remote_path)?;
+        if old == new {
+            continue;
+        }
+        let file_diff = match (std::str::from_utf8(&old), std::str::from_utf8(&new)) {
+            (Ok(old), Ok(new)) => FileDiff {
+                unified: TextDiff::from_lines(old, new)
+                    .unified_diff()
+                    .header(&format!("a/{}", path), &format!("b/{}", path))
+                    .to_string(),
+                binary: false,
+            },
+            _ => FileDiff {
+                unified: String::new(),
+                binary: true,
+            },
+        };
+        diff.modified.push((path.clone(), file_diff));
+    }
+    diff.removed = installed_files
+        .into_keys()
+        .filter(|path| !remote_files.contains_key(path))
+        .collect();
+    Ok(diff)
+}
+
+/// The files under `dir` compared, by path relative to `root`.
+fn pipe_files(
+    root: &Path,
+    dir: &Path,
+    ignore: &PipeIgnore,
+    files: &mut BTreeMap<String, PathBuf>,
+) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let path = entry.path();
+        if is_hidden_file(&entry.file_name())
+            || file_type.is_symlink()
+            || (dir == root && WRITTEN_FILES.iter().any(|name| entry.file_name() == *name))
+        {
+            continue;
+        }
+        let relative: Vec<_> = path
+            .strip_prefix(root)?
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect();
+        let relative = relative.join("/");
+        if ignore.is_ignored(&relative, file_type.is_dir()) {
+            continue;
+        }
+        if file_type.is_dir() {
+            pipe_files(root, &path, ignore, files)?;
+        } else {
+            files.insert(relative, path);
+        }
+    }
+    Ok(())
+}
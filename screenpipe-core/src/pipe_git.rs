@@ -0,0 +1,178 @@
+//! Pipes from any other git host, gitea, codeberg or a server of your own, cloned with
+//! the system `git`. A source is the url git clones, `https://codeberg.org/acme/pipes.git`,
+//! `ssh://git@host/acme/pipes.git` or `git@host:acme/pipes.git`, followed by
+//! `#<ref>` to clone a branch, tag or commit other than the default branch and
+//! `:<folder>` for a pipe in a folder of the repo, e.g.
+//! `git@host:acme/pipes.git#main:pipes/notes`.
+//!
+//! The clone is shallow, and its `.git` folder is left out of the installed pipe.
+//! Symlinks are checked out as plain files, a repo can't link to files outside of it.
+
+use anyhow::Result;
+use std::path::Path;
+use std::process::Stdio;
+use tokio::process::Command;
+use tracing::debug;
+use url::Url;
+
+use crate::pipe_github::is_commit_sha;
+use crate::pipes::{copy_dir_all, find_git_path, sanitize_pipe_name, SymlinkPolicy};
+
+/// Url schemes git clones from, besides `user@host:path` ssh urls.
+const GIT_SCHEMES: [&str; 5] = ["https", "http", "ssh", "git", "file"];
+
+/// A pipe source cloned with git.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitSource {
+    /// Url git clones, e.g. `git@codeberg.org:acme/pipes.git`
+    pub url: String,
+    /// Branch, tag or commit sha, the default branch when `None`
+    pub branch: Option<String>,
+    /// Folder of the pipe in the repo, empty for the repo root
+    pub folder: String,
+}
+
+impl GitSource {
+    /// `None` for local paths and urls git doesn't clone. Github and gitlab urls are
+    /// taken too, their own downloads are tried first.
+    pub fn parse(source: &str) -> Option<Self> {
+        let (url, fragment) = match source.split_once('#') {
+            Some((url, fragment)) => (url, Some(fragment)),
+            None => (source, None),
+        };
+        let is_url = Url::parse(url).is_ok_and(|url| {
+            GIT_SCHEMES.contains(&url.scheme()) && !url.path().trim_matches('/').is_empty()
+        });
+        if !is_url && !is_scp_like(url) {
+            return None;
+        }
+
+        let (branch, folder) = match fragment.map(|f| f.split_once(':').unwrap_or((f, ""))) {
+            Some((branch, folder)) => (Some(branch).filter(|b| !b.is_empty()), folder),
+            None => (None, ""),
+        };
+        let folder = folder.trim_matches('/');
+        if folder.split('/').any(|part| part == ".." || part == ".")
+            || branch.is_some_and(|branch| branch.starts_with('-'))
+        {
+            return None;
+        }
+        Some(GitSource {
+            url: url.to_string(),
+            branch: branch.map(str::to_string),
+            folder: folder.to_string(),
+        })
+    }
+
+    /// The name of the pipe's folder, or of the repo for a pipe at its root.
+    pub fn pipe_id(&self) -> String {
+        let name = match self
+            .folder
+            .rsplit('/')
+            .next()
+            .filter(|name| !name.is_empty())
+        {
+            Some(folder) => folder,
+            None => {
+                let path = self.url.trim_end_matches('/');
+                let repo = path.rsplit(['/', ':']).next().unwrap_or(path);
+                repo.strip_suffix(".git").unwrap_or(repo)
+            }
+        };
+        sanitize_pipe_name(name)
+    }
+
+    /// Clones the repo with `--depth 1` and copies the pipe's folder into `dest_dir`, its
+    /// symlinks as `symlinks` says. Returns the commit checked out.
+    pub async fn download(&self, dest_dir: &Path, symlinks: SymlinkPolicy) -> Result<String> {
+        let git = find_git_path().ok_or_else(|| {
+            anyhow::anyhow!(
+                "git not found, install git or use a github url to download {}",
+                self.url
+            )
+        })?;
+        let checkout = tempfile::tempdir()?;
+        let dir = checkout.path();
+        debug!("cloning {} into {:?}", self.url, dir);
+
+        match self.branch.as_deref() {
+            // `clone --branch` only takes branches and tags, a commit is fetched on its own
+            Some(sha) if is_commit_sha(sha) => {
+                self.git(&git, dir, &["init", "--quiet"]).await?;
+                self.git(
+                    &git,
+                    dir,
+                    &["fetch", "--depth", "1", "--quiet", "--", &self.url, sha],
+                )
+                .await?;
+                self.git(&git, dir, &["checkout", "--quiet", "FETCH_HEAD"])
+                    .await?;
+            }
+            Some(branch) => {
+                self.git(
+                    &git,
+                    dir,
+                    &[
+                        "clone", "--depth", "1", "--quiet", "--branch", branch, "--", &self.url,
+                        ".",
+                    ],
+                )
+                .await?;
+            }
+            None => {
+                self.git(
+                    &git,
+                    dir,
+                    &["clone", "--depth", "1", "--quiet", "--", &self.url, "."],
+                )
+                .await?;
+            }
+        }
+        let sha = self.git(&git, dir, &["rev-parse", "HEAD"]).await?;
+
+        let folder = dir.join(&self.folder);
+        if !folder.is_dir() {
+            anyhow::bail!("{} has no folder {}", self.url, self.folder);
+        }
+        copy_dir_all(folder, dest_dir.to_path_buf(), symlinks).await?;
+        Ok(sha)
+    }
+
+    /// Runs `git` in `dir`, the checkout being made, and returns what it printed.
+    async fn git(&self, git: &Path, dir: &Path, args: &[&str]) -> Result<String> {
+        let output = Command::new(git)
+            .args(["-c", "core.symlinks=false"])
+            .args(args)
+            .current_dir(dir)
+            // Fail rather than wait for credentials nobody types
+            .env("GIT_TERMINAL_PROMPT", "0")
+            .stdin(Stdio::null())
+            .kill_on_drop(true)
+            .output()
+            .await?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "git clone of {} failed: {}",
+                self.url,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+}
+
+/// `user@host:path`, the short form of an ssh url.
+fn is_scp_like(url: &str) -> bool {
+    let Some((user_host, path)) = url.split_once(':') else {
+        return false;
+    };
+    let Some((user, host)) = user_host.split_once('@') else {
+        return false;
+    };
+    let is_name = |s: &str| {
+        !s.is_empty()
+            && s.chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+    };
+    is_name(user) && is_name(host) && !path.is_empty()
+}
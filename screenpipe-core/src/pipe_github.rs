@@ -0,0 +1,1089 @@
+//! Pipes from github: the token requests are made with, the rate limit they wait out,
+//! and the download of a source, `https://github.com/<owner>/<repo>[/tree/<ref>[/<folder>]]`
+//! or its `owner/repo` shorthand, at the commit its ref resolves to.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+use url::Url;
+
+use crate::pipe_ignore::{PipeIgnore, PIPE_IGNORE_FILE};
+use crate::pipe_lock::{PipeFile, PipeFiles};
+use crate::pipes::{
+    download_error, download_listed_files, is_hidden_file, pipe_id_from_source, sanitize_pipe_name,
+    with_retries, DownloadOptions, DownloadProgress, HttpOptions, InstalledFiles, ListedFile,
+    ServerError, DEFAULT_DOWNLOAD_ATTEMPTS, DEFAULT_DOWNLOAD_CONCURRENCY,
+};
+
+/// An entry of a github contents api response.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct GithubContentItem {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: GithubContentType,
+    #[serde(default)]
+    pub size: u64,
+    pub sha: String,
+    /// Contents api url of the entry
+    pub url: String,
+    #[serde(default)]
+    pub git_url: Option<String>,
+    /// Null for submodules and for files over the contents api size limit
+    #[serde(default)]
+    pub download_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GithubContentType {
+    File,
+    Dir,
+    Symlink,
+    Submodule,
+    #[serde(other)]
+    Unknown,
+}
+
+/// How an entry of a contents api listing is downloaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GithubFetch {
+    /// A file, from its `download_url`
+    Download(String),
+    /// A file too large for the contents api, from the git blobs api
+    Blob(String),
+    /// A directory, listed at this contents api url
+    Listing(String),
+    /// A symlink, the contents api url answers with its target when that is a file
+    Resolve(String),
+    Skip(&'static str),
+}
+
+impl GithubContentItem {
+    pub fn fetch(&self) -> GithubFetch {
+        match self.kind {
+            GithubContentType::File => match (&self.download_url, &self.git_url) {
+                (Some(download_url), _) => GithubFetch::Download(download_url.clone()),
+                (None, Some(git_url)) if git_url.contains("/git/blobs/") => {
+                    GithubFetch::Blob(git_url.clone())
+                }
+                // Listings report submodules as files, their git url is a tree
+                (None, Some(git_url)) if git_url.contains("/git/trees/") => {
+                    GithubFetch::Skip("submodule")
+                }
+                (None, _) => GithubFetch::Skip("no download url"),
+            },
+            GithubContentType::Dir => GithubFetch::Listing(self.url.clone()),
+            GithubContentType::Symlink => GithubFetch::Resolve(self.url.clone()),
+            GithubContentType::Submodule => GithubFetch::Skip("submodule"),
+            GithubContentType::Unknown => GithubFetch::Skip("unknown type"),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum GithubContents {
+    Listing(Vec<GithubContentItem>),
+    Item(GithubContentItem),
+    Error { message: String },
+}
+
+/// The entries of a contents api response: a directory listing, or a single entry
+/// when the path is a file or a symlink. Api error objects become errors carrying
+/// their message.
+pub fn parse_github_contents(status: u16, body: &str) -> Result<Vec<GithubContentItem>> {
+    let ok = (200..300).contains(&status);
+    match serde_json::from_str::<GithubContents>(body) {
+        Ok(GithubContents::Error { message }) => {
+            anyhow::bail!("github api error ({}): {}", status, message)
+        }
+        Ok(_) if !ok => anyhow::bail!("github api returned status {}", status),
+        Ok(GithubContents::Listing(items)) => Ok(items),
+        Ok(GithubContents::Item(item)) => Ok(vec![item]),
+        Err(e) if ok => anyhow::bail!("unexpected response from github api: {}", e),
+        Err(_) => anyhow::bail!("github api returned status {}", status),
+    }
+}
+
+/// Environment variable with the github token pipes in private repos are downloaded
+/// with.
+pub const GITHUB_TOKEN_ENV: &str = "GITHUB_TOKEN";
+
+/// File in the screenpipe dir with the github token set with [`store_github_token`].
+pub const GITHUB_TOKEN_FILE: &str = ".github_token";
+
+/// Stores `token` for the downloads of pipes in private repos, readable by the user
+/// only. It is used when [`DownloadOptions::token`] and [`GITHUB_TOKEN_ENV`] aren't
+/// set.
+pub async fn store_github_token(token: &str, screenpipe_dir: &Path) -> Result<()> {
+    let token = token.trim();
+    if token.is_empty() {
+        anyhow::bail!("the github token is empty");
+    }
+    if HeaderValue::from_str(token).is_err() {
+        anyhow::bail!("the github token has invalid characters");
+    }
+    tokio::fs::create_dir_all(screenpipe_dir).await?;
+    let path = screenpipe_dir.join(GITHUB_TOKEN_FILE);
+    let mut file = tokio::fs::OpenOptions::new();
+    file.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    file.mode(0o600);
+    file.open(&path).await?.write_all(token.as_bytes()).await?;
+    // The file may have been there with other permissions
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        tokio::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).await?;
+    }
+    Ok(())
+}
+
+/// The github token set with [`store_github_token`], `None` when there is none.
+pub async fn load_github_token(screenpipe_dir: &Path) -> Result<Option<String>> {
+    match tokio::fs::read_to_string(screenpipe_dir.join(GITHUB_TOKEN_FILE)).await {
+        Ok(token) => Ok(Some(token.trim().to_string()).filter(|t| !t.is_empty())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// `options` with the stored github token when they have none and
+/// [`GITHUB_TOKEN_ENV`] isn't set either.
+pub(crate) async fn with_stored_token(
+    mut options: DownloadOptions,
+    screenpipe_dir: &Path,
+) -> Result<DownloadOptions> {
+    if options.token.is_none() && std::env::var_os(GITHUB_TOKEN_ENV).is_none() {
+        options.token = load_github_token(screenpipe_dir).await?;
+    }
+    Ok(options)
+}
+
+/// Client for github requests, sending `token`, or the one in [`GITHUB_TOKEN_ENV`],
+/// as a bearer token. Its header is marked sensitive, debug output leaves it out.
+pub fn github_client(token: Option<&str>) -> Result<Client> {
+    github_client_with(&HttpOptions::default(), token)
+}
+
+/// [`github_client`] with the timeout and proxy of `http`.
+pub fn github_client_with(http: &HttpOptions, token: Option<&str>) -> Result<Client> {
+    let token = match token {
+        Some(token) => Some(token.to_string()),
+        None => std::env::var(GITHUB_TOKEN_ENV).ok(),
+    };
+    let mut headers = HeaderMap::new();
+    if let Some(token) = token.as_deref().map(str::trim).filter(|t| !t.is_empty()) {
+        let mut value = HeaderValue::from_str(&format!("Bearer {}", token))
+            .map_err(|_| anyhow::anyhow!("the github token has invalid characters"))?;
+        value.set_sensitive(true);
+        headers.insert(AUTHORIZATION, value);
+    }
+    Ok(http.client_builder()?.default_headers(headers).build()?)
+}
+
+/// `client`, sending no token, for the github requests of a download without one, else
+/// a client sending the token of `options` or the one in [`GITHUB_TOKEN_ENV`].
+pub(crate) fn github_client_for(client: &Client, options: &DownloadOptions) -> Result<Client> {
+    if options.token.is_none() && std::env::var_os(GITHUB_TOKEN_ENV).is_none() {
+        return Ok(client.clone());
+    }
+    github_client_with(&options.http, options.token.as_deref())
+}
+
+/// Attempts of a github request refused for the rate limit, the first one included.
+pub const GITHUB_RATE_LIMIT_ATTEMPTS: u32 = 3;
+
+/// A rate limit resetting later than this fails the request rather than being waited
+/// out, the hourly limit of requests without a token resets in up to an hour.
+pub const MAX_RATE_LIMIT_WAIT: Duration = Duration::from_secs(60);
+
+tokio::task_local! {
+    // The client can't carry it, the github requests of a download read it from here
+    static RATE_LIMIT_WAIT: Duration;
+}
+
+/// Runs `f` with the github rate limit wait of `http`, for the requests it makes. The
+/// downloads and updates of pipes do so with the [`DownloadOptions::http`] they're given.
+pub async fn with_rate_limit_wait<F: Future>(http: &HttpOptions, f: F) -> F::Output {
+    let wait = http.max_rate_limit_wait.unwrap_or(MAX_RATE_LIMIT_WAIT);
+    RATE_LIMIT_WAIT.scope(wait, f).await
+}
+
+/// Wait before retrying a rate limit that doesn't say when it resets, doubled for
+/// each attempt after.
+const RATE_LIMIT_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Gets `url` from the github api, waiting for a rate limit to reset and retrying
+/// until `max_attempts` requests were made. The wait is the `retry-after` or
+/// `x-ratelimit-reset` of the response, or doubles from one attempt to the next when
+/// it has neither. Fails with [`GithubRateLimited`] once the attempts are used up or
+/// the limit resets later than [`HttpOptions::max_rate_limit_wait`].
+pub async fn request_with_backoff(
+    client: &Client,
+    url: &str,
+    max_attempts: u32,
+) -> Result<reqwest::Response> {
+    github_get_with(client, url, "application/vnd.github+json", max_attempts).await
+}
+
+async fn github_get(client: &Client, url: &str, accept: &str) -> Result<reqwest::Response> {
+    github_get_with(client, url, accept, GITHUB_RATE_LIMIT_ATTEMPTS).await
+}
+
+async fn github_get_with(
+    client: &Client,
+    url: &str,
+    accept: &str,
+    max_attempts: u32,
+) -> Result<reqwest::Response> {
+    let mut attempt = 1;
+    loop {
+        // Urls are left out of errors, download urls of private repos carry a token
+        let response = client
+            .get(url)
+            .header("Accept", accept)
+            .header("User-Agent", "screenpipe")
+            .send()
+            .await
+            .map_err(reqwest::Error::without_url)?;
+        let status = response.status().as_u16();
+        if response.status().is_success() {
+            return Ok(response);
+        }
+        let Some(limited) = GithubRateLimited::from_headers(status, response.headers()) else {
+            let body = response.text().await.unwrap_or_default();
+            let e = github_error(status, &body);
+            if status >= 500 {
+                return Err(ServerError(e).into());
+            }
+            return Err(e);
+        };
+        let wait = match limited.reset_at {
+            Some(reset_at) => (reset_at - Utc::now()).to_std().unwrap_or_default(),
+            None => RATE_LIMIT_BACKOFF * 2u32.saturating_pow(attempt - 1),
+        };
+        let max_wait = RATE_LIMIT_WAIT
+            .try_with(|wait| *wait)
+            .unwrap_or(MAX_RATE_LIMIT_WAIT);
+        if attempt >= max_attempts || wait > max_wait {
+            return Err(limited.into());
+        }
+        warn!(
+            "github api rate limit exceeded, retrying in {:.1}s (attempt {} of {})",
+            wait.as_secs_f32(),
+            attempt + 1,
+            max_attempts
+        );
+        tokio::time::sleep(wait).await;
+        attempt += 1;
+    }
+}
+
+/// A github request refused for the rate limit, 60 requests an hour without a token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GithubRateLimited {
+    /// When github takes requests again
+    pub reset_at: Option<DateTime<Utc>>,
+    /// Source of the pipe that was being downloaded
+    pub source: Option<String>,
+}
+
+impl GithubRateLimited {
+    /// `None` unless a 403 or 429 response has the headers github sends when a
+    /// limit is hit, `x-ratelimit-remaining: 0` or `retry-after`.
+    pub fn from_headers(status: u16, headers: &HeaderMap) -> Option<Self> {
+        let header = |name: &str| headers.get(name)?.to_str().ok()?.trim().parse::<i64>().ok();
+        let exhausted = header("x-ratelimit-remaining") == Some(0);
+        let retry_after = header("retry-after");
+        let limited = match status {
+            403 => exhausted || retry_after.is_some(),
+            429 => true,
+            _ => false,
+        };
+        if !limited {
+            return None;
+        }
+        let reset_at = match (retry_after, header("x-ratelimit-reset")) {
+            (Some(seconds), _) => Some(Utc::now() + chrono::Duration::seconds(seconds)),
+            (None, Some(reset)) if exhausted => DateTime::from_timestamp(reset, 0),
+            _ => None,
+        };
+        Some(Self {
+            reset_at,
+            source: None,
+        })
+    }
+}
+
+impl std::fmt::Display for GithubRateLimited {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "github api rate limit exceeded")?;
+        if let Some(source) = &self.source {
+            write!(f, " downloading {}", source)?;
+        }
+        if let Some(reset_at) = self.reset_at {
+            let seconds = (reset_at - Utc::now()).num_seconds().max(0);
+            write!(
+                f,
+                ", it resets at {} (in {} min)",
+                reset_at.format("%H:%M:%S UTC"),
+                (seconds + 59) / 60
+            )?;
+        }
+        write!(f, ", set {} for a higher limit", GITHUB_TOKEN_ENV)
+    }
+}
+
+impl std::error::Error for GithubRateLimited {}
+
+/// `e`, naming the pipe's `source` when it is a rate limit, so batch installs tell
+/// which pipe hit it.
+pub(crate) fn rate_limited_source(e: anyhow::Error, source: &str) -> anyhow::Error {
+    match e.downcast::<GithubRateLimited>() {
+        Ok(limited) => GithubRateLimited {
+            source: Some(source.to_string()),
+            ..limited
+        }
+        .into(),
+        Err(e) => e,
+    }
+}
+
+/// Error of a failed github request, the api's message when the body has one.
+fn github_error(status: u16, body: &str) -> anyhow::Error {
+    parse_github_contents(status, body)
+        .err()
+        .unwrap_or_else(|| anyhow::anyhow!("github returned status {}", status))
+}
+
+async fn fetch_github_contents(client: &Client, api_url: &str) -> Result<Vec<GithubContentItem>> {
+    let response = github_get(client, api_url, "application/vnd.github.v3+json").await?;
+    let status = response.status().as_u16();
+    let body = response.text().await?;
+    parse_github_contents(status, &body)
+}
+
+/// The bytes of a file entry, `None` when it can't be downloaded.
+async fn fetch_github_file(client: &Client, fetch: &GithubFetch) -> Result<Option<Vec<u8>>> {
+    let response = match fetch {
+        GithubFetch::Download(url) => github_get(client, url, "*/*").await?,
+        GithubFetch::Blob(url) => {
+            github_get(client, url, "application/vnd.github.raw+json").await?
+        }
+        _ => return Ok(None),
+    };
+    let bytes = response
+        .bytes()
+        .await
+        .map_err(reqwest::Error::without_url)?;
+    Ok(Some(bytes.to_vec()))
+}
+
+/// The file `name` of the folder of `commit`, `None` when it has none.
+pub(crate) async fn fetch_raw_github_file(
+    client: &Client,
+    github: &GithubSource,
+    commit: &GithubCommit,
+    raw: &str,
+    name: &str,
+) -> Result<Option<Vec<u8>>> {
+    let mut url = Url::parse(raw)?;
+    url.path_segments_mut()
+        .map_err(|_| anyhow::anyhow!("invalid raw url: {}", raw))?
+        .pop_if_empty()
+        .extend([&github.owner, &github.repo, &commit.sha])
+        .extend(commit.path.split('/').filter(|part| !part.is_empty()))
+        .push(name);
+    let response = client
+        .get(url)
+        .header("User-Agent", "screenpipe")
+        .send()
+        .await
+        .map_err(reqwest::Error::without_url)?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    let status = response.status().as_u16();
+    if !response.status().is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(download_error(
+            github_error(status, &body),
+            "fetch",
+            name,
+            1,
+        ));
+    }
+    let content = response
+        .bytes()
+        .await
+        .map_err(reqwest::Error::without_url)?;
+    Ok(Some(content.to_vec()))
+}
+
+/// Folders below the pipe root a github download descends into.
+pub const MAX_GITHUB_DEPTH: usize = 10;
+
+/// Downloads a github contents api listing into `dest_dir`, descending into
+/// subdirectories so nested folders keep their layout.
+pub async fn download_github_listing(api_url: &str, dest_dir: &Path) -> Result<()> {
+    download_github_contents(
+        github_client(None)?,
+        api_url.to_string(),
+        dest_dir.to_path_buf(),
+        String::new(),
+        Arc::default(),
+        DEFAULT_DOWNLOAD_ATTEMPTS,
+    )
+    .await?;
+    Ok(())
+}
+
+/// Downloads one listing, `rel` is its path below the pipe root, empty for the root.
+/// Each request is made up to `attempts` times. Returns the files written.
+fn download_github_contents(
+    client: Client,
+    api_url: String,
+    dest_dir: PathBuf,
+    rel: String,
+    installed: Arc<InstalledFiles>,
+    attempts: u32,
+) -> Pin<Box<dyn Future<Output = anyhow::Result<PipeFiles>> + Send>> {
+    Box::pin(async move {
+        let mut files = PipeFiles::default();
+        let items = with_retries(attempts, "list", &rel, || {
+            fetch_github_contents(&client, &api_url)
+        })
+        .await?;
+        for item in items {
+            if is_hidden_file(std::ffi::OsStr::new(&item.name)) {
+                debug!("skipping hidden file: {}", item.name);
+                continue;
+            }
+            // Names come from the api, none may write outside of dest_dir
+            if item.name.contains(['/', '\\']) {
+                debug!("skipping entry with an invalid name: {}", item.name);
+                continue;
+            }
+            let path = dest_dir.join(&item.name);
+            let item_rel = if rel.is_empty() {
+                item.name.clone()
+            } else {
+                format!("{}/{}", rel, item.name)
+            };
+
+            let (fetch, sha) = match item.fetch() {
+                GithubFetch::Listing(url) => {
+                    if item_rel.split('/').count() > MAX_GITHUB_DEPTH {
+                        anyhow::bail!(
+                            "{} is nested deeper than {} folders",
+                            item_rel,
+                            MAX_GITHUB_DEPTH
+                        );
+                    }
+                    tokio::fs::create_dir_all(&path).await?;
+                    let listed = download_github_contents(
+                        client.clone(),
+                        url,
+                        path.clone(),
+                        item_rel,
+                        installed.clone(),
+                        attempts,
+                    )
+                    .await?;
+                    files.files.extend(listed.files);
+                    debug!("downloaded directory: {:?}", path);
+                    continue;
+                }
+                GithubFetch::Resolve(url) => {
+                    let target = with_retries(attempts, "resolve", &item_rel, || {
+                        fetch_github_contents(&client, &url)
+                    })
+                    .await?;
+                    match target.as_slice() {
+                        [target] if target.kind == GithubContentType::File => {
+                            (target.fetch(), target.sha.clone())
+                        }
+                        _ => (
+                            GithubFetch::Skip("symlink to a directory or outside the repo"),
+                            String::new(),
+                        ),
+                    }
+                }
+                fetch => (fetch, item.sha.clone()),
+            };
+            if !matches!(fetch, GithubFetch::Skip(_)) {
+                if let Some(file) = installed.copy(&item_rel, &sha, &path).await {
+                    debug!("kept unchanged file: {:?}", path);
+                    files.files.insert(item_rel, file);
+                    continue;
+                }
+            }
+            match with_retries(attempts, "download", &item_rel, || {
+                fetch_github_file(&client, &fetch)
+            })
+            .await?
+            {
+                Some(content) => {
+                    tokio::fs::write(&path, &content).await?;
+                    debug!("downloaded file: {:?}", path);
+                    files
+                        .files
+                        .insert(item_rel, PipeFile::read(&path, &sha).await?);
+                }
+                None => debug!("skipping {}: {:?}", item.name, fetch),
+            }
+        }
+
+        Ok(files)
+    })
+}
+
+/// An entry of a git trees api response.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct GithubTreeEntry {
+    /// Path in the repo
+    pub path: String,
+    pub mode: String,
+    /// `blob`, `tree`, or `commit` for a submodule
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub sha: String,
+}
+
+/// A recursive git trees api response, `truncated` when github left entries out.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct GithubGitTree {
+    pub tree: Vec<GithubTreeEntry>,
+    #[serde(default)]
+    pub truncated: bool,
+}
+
+/// Mode of a symlink in a git tree, its blob is the target.
+pub(crate) const GIT_SYMLINK_MODE: &str = "120000";
+
+/// The files of `folder` in a git tree, their path in the repo and below the folder.
+/// `None` when the tree can't tell: it is truncated, or has symlinks, which only the
+/// contents api follows.
+pub fn github_tree_files(
+    tree: &GithubGitTree,
+    folder: &str,
+) -> Result<Option<Vec<(String, String)>>> {
+    if tree.truncated {
+        return Ok(None);
+    }
+    let prefix = if folder.is_empty() {
+        String::new()
+    } else {
+        format!("{}/", folder)
+    };
+    let mut found = folder.is_empty();
+    let mut files = Vec::new();
+    for entry in &tree.tree {
+        let rel = if entry.path == folder {
+            found = true;
+            if entry.kind != "blob" {
+                continue;
+            }
+            // The source is a path to a file
+            folder.rsplit('/').next().unwrap_or(folder)
+        } else {
+            match entry.path.strip_prefix(&prefix) {
+                Some(rel) => rel,
+                None => continue,
+            }
+        };
+        if rel
+            .split('/')
+            .any(|part| is_hidden_file(std::ffi::OsStr::new(part)))
+        {
+            debug!("skipping hidden file: {}", entry.path);
+            continue;
+        }
+        // Paths come from the api, none may write outside of dest_dir
+        if rel.contains('\\') {
+            debug!("skipping entry with an invalid name: {}", entry.path);
+            continue;
+        }
+        match entry.kind.as_str() {
+            "tree" if rel.split('/').count() > MAX_GITHUB_DEPTH => {
+                anyhow::bail!("{} is nested deeper than {} folders", rel, MAX_GITHUB_DEPTH)
+            }
+            "blob" if entry.mode == GIT_SYMLINK_MODE => return Ok(None),
+            "blob" => files.push((entry.path.clone(), rel.to_string())),
+            kind => debug!("skipping {}: {}", entry.path, kind),
+        }
+    }
+    if !found {
+        anyhow::bail!("{} isn't in the repo", folder);
+    }
+    Ok(Some(files))
+}
+
+/// Downloads the folder of `commit` into `dest_dir`, listing it with one git trees api
+/// request and fetching the files from `raw`, so installs don't run into the rate
+/// limit of the api. Falls back to contents api listings when the tree can't tell the
+/// files. Returns the files written.
+pub async fn download_github_source(
+    client: &Client,
+    source: &GithubSource,
+    commit: &GithubCommit,
+    dest_dir: &Path,
+    api: &str,
+    raw: &str,
+) -> Result<PipeFiles> {
+    download_github_source_with(
+        client,
+        source,
+        commit,
+        dest_dir,
+        api,
+        raw,
+        Arc::default(),
+        DEFAULT_DOWNLOAD_CONCURRENCY,
+        DEFAULT_DOWNLOAD_ATTEMPTS,
+        None,
+    )
+    .await
+}
+
+/// [`download_github_source`], copying the files `installed` has at the listed sha and
+/// fetching `concurrency` files at once, each request made up to `attempts` times.
+/// Each file written is reported to `progress`, except for contents api listings.
+#[allow(clippy::too_many_arguments)]
+pub async fn download_github_source_with(
+    client: &Client,
+    source: &GithubSource,
+    commit: &GithubCommit,
+    dest_dir: &Path,
+    api: &str,
+    raw: &str,
+    installed: Arc<InstalledFiles>,
+    concurrency: usize,
+    attempts: u32,
+    progress: Option<&mpsc::Sender<DownloadProgress>>,
+) -> Result<PipeFiles> {
+    let url = format!(
+        "{}/repos/{}/{}/git/trees/{}?recursive=1",
+        api, source.owner, source.repo, commit.sha
+    );
+    let tree: GithubGitTree = with_retries(attempts, "list", "", || async {
+        let response = github_get(client, &url, "application/vnd.github+json").await?;
+        Ok(response.json().await?)
+    })
+    .await?;
+    let listed = match github_tree_files(&tree, &commit.path)? {
+        Some(files) => files,
+        None => {
+            info!(
+                "listing {}/{} with the contents api, its tree is truncated or has symlinks",
+                source.owner, source.repo
+            );
+            let at_commit = GithubTree {
+                git_ref: commit.sha.clone(),
+                path: commit.path.clone(),
+            };
+            return download_github_contents(
+                client.clone(),
+                source.contents_url(api, &at_commit),
+                dest_dir.to_path_buf(),
+                String::new(),
+                installed,
+                attempts,
+            )
+            .await;
+        }
+    };
+    let shas: HashMap<&str, &str> = tree
+        .tree
+        .iter()
+        .map(|entry| (entry.path.as_str(), entry.sha.as_str()))
+        .collect();
+    let raw_url = Url::parse(raw)?;
+    let raw_file_url = |path: &str| -> Result<Url> {
+        let mut url = raw_url.clone();
+        url.path_segments_mut()
+            .map_err(|_| anyhow::anyhow!("invalid raw url: {}", raw))?
+            .pop_if_empty()
+            .extend([&source.owner, &source.repo, &commit.sha])
+            .extend(path.split('/'));
+        Ok(url)
+    };
+
+    // The pipe's ignore file, hidden, isn't listed
+    let ignore_path = match commit.path.as_str() {
+        "" => PIPE_IGNORE_FILE.to_string(),
+        folder => format!("{}/{}", folder, PIPE_IGNORE_FILE),
+    };
+    let ignore = if shas.contains_key(ignore_path.as_str()) {
+        let url = raw_file_url(&ignore_path)?;
+        let content = with_retries(attempts, "fetch", &ignore_path, || async {
+            let response = github_get(client, url.as_str(), "*/*").await?;
+            Ok(response.text().await.map_err(reqwest::Error::without_url)?)
+        })
+        .await?;
+        PipeIgnore::parse(Some(&content))?
+    } else {
+        PipeIgnore::parse(None)?
+    };
+    let listed = listed
+        .into_iter()
+        .filter(|(path, rel)| {
+            let ignored = ignore.is_ignored(rel, false);
+            if ignored {
+                debug!("skipping {}, in {}", path, PIPE_IGNORE_FILE);
+            }
+            !ignored
+        })
+        .map(|(path, rel)| ListedFile {
+            sha: shas
+                .get(path.as_str())
+                .copied()
+                .unwrap_or_default()
+                .to_string(),
+            path,
+            rel,
+        })
+        .collect();
+    download_listed_files(
+        listed,
+        dest_dir,
+        &installed,
+        concurrency,
+        attempts,
+        progress,
+        |file| {
+            let url = raw_file_url(&file.path);
+            async move {
+                let url = url?;
+                let response = github_get(client, url.as_str(), "*/*").await?;
+                Ok(response
+                    .bytes()
+                    .await
+                    .map_err(reqwest::Error::without_url)?
+                    .to_vec())
+            }
+        },
+    )
+    .await
+}
+
+pub(crate) const GITHUB_API: &str = "https://api.github.com";
+pub(crate) const GITHUB_RAW: &str = "https://raw.githubusercontent.com";
+
+/// A pipe source on github: a repo, `https://github.com/<owner>/<repo>`, or a ref and
+/// a folder in it, `https://github.com/<owner>/<repo>/tree/<ref>[/<folder>]`. The ref
+/// may be a commit sha. `blob` urls are taken for `tree` ones.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GithubSource {
+    pub owner: String,
+    pub repo: String,
+    /// Path segments after `tree`, the ref then the folder. Where a ref with slashes,
+    /// e.g. `feature/foo`, ends only github knows, see [`GithubSource::resolve`]
+    pub tree: Vec<String>,
+}
+
+/// Ref and folder of a [`GithubSource`] once resolved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GithubTree {
+    pub git_ref: String,
+    /// Empty for the root of the repo
+    pub path: String,
+}
+
+/// The ref and folder a github source was resolved to, and the commit the ref points
+/// to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GithubCommit {
+    pub git_ref: String,
+    pub path: String,
+    pub sha: String,
+}
+
+/// A full commit sha, 40 hex chars, told apart from branch and tag names by its shape.
+pub fn is_commit_sha(git_ref: &str) -> bool {
+    git_ref.len() == 40 && git_ref.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Tree of a source at `git_ref` rather than the ref of its url, for the same `folder`.
+pub(crate) fn tree_at_ref(git_ref: &str, folder: &str) -> Vec<String> {
+    git_ref
+        .split('/')
+        .chain(folder.split('/'))
+        .filter(|segment| !segment.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[derive(Deserialize)]
+struct GithubRepo {
+    default_branch: String,
+}
+
+/// The github url of `owner/repo[/folder][@ref]`: `owner/repo` is the repo at its
+/// default branch, `owner/repo@dev` at `dev`, `owner/repo/pipes/notes` the folder at
+/// the default branch, `HEAD`. `None` for urls, for paths that exist, which stay
+/// local, and for anything else, which is taken for a local path.
+pub fn expand_github_shorthand(source: &str) -> Option<String> {
+    if Url::parse(source).is_ok() || Path::new(source).exists() {
+        return None;
+    }
+    let (path, git_ref) = match source.split_once('@') {
+        Some((path, git_ref)) => (path, Some(git_ref)),
+        None => (source, None),
+    };
+    let segments: Vec<&str> = path.split('/').collect();
+    let is_segment = |segment: &&str| {
+        !segment.is_empty()
+            && !segment.chars().all(|c| c == '.')
+            && segment
+                .chars()
+                .all(|c| c.is_alphanumeric() || matches!(c, '_' | '.' | '-'))
+    };
+    if segments.len() < 2 || !segments.iter().all(is_segment) {
+        return None;
+    }
+    if git_ref.is_some_and(|git_ref| git_ref.is_empty() || git_ref.contains(char::is_whitespace)) {
+        return None;
+    }
+    let repo = format!("https://github.com/{}/{}", segments[0], segments[1]);
+    let folder = &segments[2..];
+    Some(match (git_ref, folder.is_empty()) {
+        (None, true) => repo,
+        (Some(git_ref), true) => format!("{}/tree/{}", repo, git_ref),
+        (git_ref, false) => format!(
+            "{}/tree/{}/{}",
+            repo,
+            git_ref.unwrap_or("HEAD"),
+            folder.join("/")
+        ),
+    })
+}
+
+impl GithubSource {
+    /// `None` for local paths and urls that aren't a github repo or tree.
+    pub fn parse(source: &str) -> Option<Self> {
+        let url = Url::parse(source).ok()?;
+        if url.host_str() != Some("github.com") {
+            return None;
+        }
+        let segments: Vec<&str> = url.path_segments()?.filter(|s| !s.is_empty()).collect();
+        let (owner, repo, tree) = match segments.as_slice() {
+            [owner, repo] => (owner, repo, &[][..]),
+            [owner, repo, "tree", tree @ ..] if !tree.is_empty() => (owner, repo, tree),
+            // A file or folder at a commit, as linked from github
+            [owner, repo, "blob", tree @ ..] if tree.first().is_some_and(|r| is_commit_sha(r)) => {
+                (owner, repo, tree)
+            }
+            _ => return None,
+        };
+        Some(Self {
+            owner: owner.to_string(),
+            repo: repo.strip_suffix(".git").unwrap_or(repo).to_string(),
+            tree: tree.iter().map(|s| s.to_string()).collect(),
+        })
+    }
+
+    /// The source at `git_ref`, a branch, tag or commit, with the folder of its url.
+    pub async fn at_ref(&self, client: &Client, api: &str, git_ref: &str) -> Result<GithubSource> {
+        let folder = if self.tree.is_empty() {
+            String::new()
+        } else {
+            self.locate(client, api).await?.0.path
+        };
+        Ok(GithubSource {
+            tree: tree_at_ref(git_ref, &folder),
+            ..self.clone()
+        })
+    }
+
+    /// The ways `tree` splits into a ref and a folder, the shortest ref first.
+    pub fn ref_candidates(&self) -> Vec<GithubTree> {
+        (1..=self.tree.len())
+            .map(|i| GithubTree {
+                git_ref: self.tree[..i].join("/"),
+                path: self.tree[i..].join("/"),
+            })
+            .collect()
+    }
+
+    /// Id the pipe is installed under, the name of its folder, or of the repo for a
+    /// repo root or a ref without a folder. A ref with slashes and no folder is taken
+    /// for a folder.
+    pub fn pipe_id(&self) -> String {
+        match self.tree.as_slice() {
+            [_, .., folder] => sanitize_pipe_name(folder),
+            _ => sanitize_pipe_name(&self.repo),
+        }
+    }
+
+    /// Resolves the ref and folder, asking `api` for the default branch of a repo
+    /// root and, when the ref may have slashes, for the shortest ref that exists.
+    pub async fn resolve(&self, client: &Client, api: &str) -> Result<GithubTree> {
+        Ok(self.locate(client, api).await?.0)
+    }
+
+    /// [`GithubSource::resolve`], and the sha github answered the ref it found with.
+    async fn locate(&self, client: &Client, api: &str) -> Result<(GithubTree, Option<String>)> {
+        let repo_url = format!("{}/repos/{}/{}", api, self.owner, self.repo);
+        let mut candidates = self.ref_candidates();
+        if candidates.is_empty() {
+            let repo: GithubRepo = github_get(client, &repo_url, "application/vnd.github+json")
+                .await?
+                .json()
+                .await?;
+            let tree = GithubTree {
+                git_ref: repo.default_branch,
+                path: String::new(),
+            };
+            return Ok((tree, None));
+        }
+        // A commit has no slashes, the rest is the folder
+        if candidates.len() == 1 || is_commit_sha(&self.tree[0]) {
+            return Ok((candidates.remove(0), None));
+        }
+        for candidate in candidates {
+            let url = format!("{}/commits/{}", repo_url, candidate.git_ref);
+            let response = client
+                .get(&url)
+                .header("Accept", "application/vnd.github.sha")
+                .header("User-Agent", "screenpipe")
+                .send()
+                .await?;
+            let status = response.status().as_u16();
+            match status {
+                200..=299 => {
+                    let sha = response.text().await?.trim().to_string();
+                    return Ok((candidate, Some(sha)));
+                }
+                // Not a ref
+                404 | 422 => continue,
+                _ => {
+                    let headers = response.headers();
+                    if let Some(limited) = GithubRateLimited::from_headers(status, headers) {
+                        return Err(limited.into());
+                    }
+                    let body = response.text().await.unwrap_or_default();
+                    return Err(github_error(status, &body));
+                }
+            }
+        }
+        anyhow::bail!(
+            "no branch, tag or commit of {}/{} matches {}",
+            self.owner,
+            self.repo,
+            self.tree.join("/")
+        )
+    }
+
+    /// The resolved ref and folder, and the commit the ref points to now.
+    pub async fn commit(&self, client: &Client, api: &str) -> Result<GithubCommit> {
+        let result = self.find_commit(client, api).await;
+        // Github answers 404 for private repos it isn't shown a token for
+        let failed = matches!(&result, Err(e) if !e.is::<GithubRateLimited>());
+        if failed && self.is_missing(client, api).await {
+            anyhow::bail!(
+                "github repo {}/{} not found or token missing, set {} to download pipes \
+                 from a private repo",
+                self.owner,
+                self.repo,
+                GITHUB_TOKEN_ENV
+            );
+        }
+        result
+    }
+
+    async fn is_missing(&self, client: &Client, api: &str) -> bool {
+        let url = format!("{}/repos/{}/{}", api, self.owner, self.repo);
+        let response = client
+            .get(&url)
+            .header("Accept", "application/vnd.github+json")
+            .header("User-Agent", "screenpipe")
+            .send()
+            .await;
+        matches!(response, Ok(response) if response.status() == reqwest::StatusCode::NOT_FOUND)
+    }
+
+    async fn find_commit(&self, client: &Client, api: &str) -> Result<GithubCommit> {
+        let (tree, probed) = self.locate(client, api).await?;
+        let sha = if is_commit_sha(&tree.git_ref) {
+            tree.git_ref.to_lowercase()
+        } else if let Some(sha) = probed {
+            sha
+        } else {
+            let url = format!(
+                "{}/repos/{}/{}/commits/{}",
+                api, self.owner, self.repo, tree.git_ref
+            );
+            let response = github_get(client, &url, "application/vnd.github.sha").await?;
+            response.text().await?.trim().to_string()
+        };
+        if !is_commit_sha(&sha) {
+            anyhow::bail!(
+                "unexpected commit sha for {}/{} at {}: {}",
+                self.owner,
+                self.repo,
+                tree.git_ref,
+                sha
+            );
+        }
+        Ok(GithubCommit {
+            git_ref: tree.git_ref,
+            path: tree.path,
+            sha,
+        })
+    }
+
+    /// Contents api url of the folder of `tree`.
+    pub fn contents_url(&self, api: &str, tree: &GithubTree) -> String {
+        format!(
+            "{}/repos/{}/{}/contents/{}?ref={}",
+            api, self.owner, self.repo, tree.path, tree.git_ref
+        )
+    }
+}
+
+/// `source` with its ref `git_ref`, or the default branch of a repo root, replaced by
+/// the commit `sha`. `None` for local paths and other urls, when the tree of `source`
+/// doesn't start with `git_ref`, and when the pinned source would be installed under
+/// another id.
+pub fn pin_github_source(source: &str, git_ref: &str, sha: &str) -> Option<String> {
+    let github = GithubSource::parse(source)?;
+    let folder = if github.tree.is_empty() {
+        &[][..]
+    } else {
+        let ref_len = git_ref.split('/').count();
+        if github.tree.get(..ref_len)?.join("/") != git_ref {
+            return None;
+        }
+        &github.tree[ref_len..]
+    };
+    let mut segments = vec![github.owner.as_str(), github.repo.as_str(), "tree", sha];
+    segments.extend(folder.iter().map(String::as_str));
+    let mut url = Url::parse(source).ok()?;
+    url.set_path(&segments.join("/"));
+    let pinned = url.to_string();
+    (pipe_id_from_source(&pinned) == pipe_id_from_source(source)).then_some(pinned)
+}
+
+/// Ref of a github source, the default branch for a repo root, and the commit it
+/// points to now. `None` for local paths.
+pub async fn resolve_github_source(source: &str) -> Result<Option<GithubCommit>> {
+    let Some(github) = GithubSource::parse(source) else {
+        return Ok(None);
+    };
+    let client = github_client(None)?;
+    let commit = github.commit(&client, GITHUB_API).await;
+    commit.map(Some).map_err(|e| rate_limited_source(e, source))
+}
@@ -0,0 +1,426 @@
+//! Pipes from gitlab: a project, `https://gitlab.com/<group>/<repo>`, or a ref and a
+//! folder in it, `https://gitlab.com/<group>/<repo>/-/tree/<ref>[/<folder>]`. Groups may
+//! be nested. A self-hosted gitlab is told by the `/-/tree/` of its urls, its project
+//! roots by its host being listed in [`GITLAB_HOSTS_ENV`].
+//!
+//! A source is downloaded like one from github: its ref is resolved to a commit, the
+//! folder is listed with the repository tree api, a page of 100 entries at a time, and
+//! each file is fetched from the raw files api at that commit.
+
+use anyhow::Result;
+use reqwest::header::{HeaderMap, HeaderValue};
+use reqwest::Client;
+use serde::Deserialize;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tracing::debug;
+use url::Url;
+
+use crate::pipe_github::{
+    is_commit_sha, tree_at_ref, GithubCommit, GIT_SYMLINK_MODE, MAX_GITHUB_DEPTH,
+};
+use crate::pipe_lock::PipeFiles;
+use crate::pipes::{
+    download_listed_files, is_hidden_file, sanitize_pipe_name, with_retries, DownloadProgress,
+    HttpOptions, InstalledFiles, ListedFile, ServerError,
+};
+
+/// Environment variable with the gitlab token pipes in private projects are downloaded
+/// with.
+pub const GITLAB_TOKEN_ENV: &str = "GITLAB_TOKEN";
+
+/// Environment variable listing self-hosted gitlab hosts, comma separated, e.g.
+/// `git.example.com,gitlab.internal`.
+pub const GITLAB_HOSTS_ENV: &str = "SCREENPIPE_GITLAB_HOSTS";
+
+/// Entries of the repository tree api per page, its maximum.
+const GITLAB_PAGE_SIZE: usize = 100;
+
+/// A pipe source on gitlab.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitlabSource {
+    /// Scheme and host the api is at, e.g. `https://gitlab.com`
+    pub base_url: String,
+    /// Path of the project, groups included, e.g. `acme/tools/pipes`
+    pub project: String,
+    /// Path segments after `-/tree`, the ref then the folder
+    pub tree: Vec<String>,
+}
+
+/// An entry of a repository tree api response.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct GitlabTreeEntry {
+    /// Blob sha of a file
+    pub id: String,
+    /// Path in the project
+    pub path: String,
+    /// `blob`, `tree`, or `commit` for a submodule
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub mode: String,
+}
+
+#[derive(Deserialize)]
+struct GitlabProject {
+    default_branch: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct GitlabCommit {
+    id: String,
+}
+
+fn is_gitlab_host(host: &str) -> bool {
+    if host == "gitlab.com" {
+        return true;
+    }
+    std::env::var(GITLAB_HOSTS_ENV).is_ok_and(|hosts| {
+        hosts
+            .split(',')
+            .map(str::trim)
+            .any(|listed| !listed.is_empty() && listed.eq_ignore_ascii_case(host))
+    })
+}
+
+/// Client for gitlab requests, sending `token`, or the one in [`GITLAB_TOKEN_ENV`], as
+/// a private token. Its header is marked sensitive, debug output leaves it out.
+pub fn gitlab_client(token: Option<&str>) -> Result<Client> {
+    gitlab_client_with(&HttpOptions::default(), token)
+}
+
+/// [`gitlab_client`] with the timeout and proxy of `http`.
+pub fn gitlab_client_with(http: &HttpOptions, token: Option<&str>) -> Result<Client> {
+    let token = match token {
+        Some(token) => Some(token.to_string()),
+        None => std::env::var(GITLAB_TOKEN_ENV).ok(),
+    };
+    let mut headers = HeaderMap::new();
+    if let Some(token) = token.as_deref().map(str::trim).filter(|t| !t.is_empty()) {
+        let mut value = HeaderValue::from_str(token)
+            .map_err(|_| anyhow::anyhow!("the gitlab token has invalid characters"))?;
+        value.set_sensitive(true);
+        headers.insert("PRIVATE-TOKEN", value);
+    }
+    Ok(http.client_builder()?.default_headers(headers).build()?)
+}
+
+/// `client`, sending no token, for gitlab requests without a [`GITLAB_TOKEN_ENV`], else a
+/// client sending it.
+pub(crate) fn gitlab_client_for(client: &Client, http: &HttpOptions) -> Result<Client> {
+    if std::env::var_os(GITLAB_TOKEN_ENV).is_none() {
+        return Ok(client.clone());
+    }
+    gitlab_client_with(http, None)
+}
+
+#[derive(Deserialize)]
+struct GitlabError {
+    #[serde(alias = "error")]
+    message: serde_json::Value,
+}
+
+/// Error of a gitlab api response, with the `message` of its body when it has one.
+pub fn gitlab_error(status: u16, body: &str) -> anyhow::Error {
+    match serde_json::from_str::<GitlabError>(body) {
+        // Validation errors have an object of messages by field
+        Ok(GitlabError { message }) => {
+            let message = message
+                .as_str()
+                .map_or_else(|| message.to_string(), str::to_string);
+            anyhow::anyhow!("gitlab api error ({}): {}", status, message)
+        }
+        Err(_) => anyhow::anyhow!("gitlab api returned status {}", status),
+    }
+}
+
+async fn gitlab_get(client: &Client, url: Url) -> Result<Option<reqwest::Response>> {
+    // Urls are left out of errors like for github
+    let response = client
+        .get(url)
+        .header("User-Agent", "screenpipe")
+        .send()
+        .await
+        .map_err(reqwest::Error::without_url)?;
+    match response.status().as_u16() {
+        200..=299 => Ok(Some(response)),
+        404 => Ok(None),
+        status => {
+            let body = response.text().await.unwrap_or_default();
+            let e = gitlab_error(status, &body);
+            if status >= 500 {
+                return Err(ServerError(e).into());
+            }
+            Err(e)
+        }
+    }
+}
+
+impl GitlabSource {
+    /// `None` for local paths and urls that aren't a gitlab project or tree.
+    pub fn parse(source: &str) -> Option<Self> {
+        let url = Url::parse(source).ok()?;
+        let host = url.host_str()?;
+        let segments: Vec<&str> = url.path_segments()?.filter(|s| !s.is_empty()).collect();
+        let (project, tree) = match segments.iter().position(|s| *s == "-") {
+            Some(dash) => match &segments[dash + 1..] {
+                ["tree", tree @ ..] if !tree.is_empty() => (&segments[..dash], tree),
+                // A file or folder at a commit, as linked from gitlab
+                ["blob", tree @ ..] if tree.first().is_some_and(|r| is_commit_sha(r)) => {
+                    (&segments[..dash], tree)
+                }
+                _ => return None,
+            },
+            None if is_gitlab_host(host) => (&segments[..], &[][..]),
+            None => return None,
+        };
+        if project.len() < 2 {
+            return None;
+        }
+        let mut base_url = format!("{}://{}", url.scheme(), host);
+        if let Some(port) = url.port() {
+            base_url.push_str(&format!(":{}", port));
+        }
+        let project = project.join("/");
+        Some(Self {
+            base_url,
+            project: project.strip_suffix(".git").unwrap_or(&project).to_string(),
+            tree: tree.iter().map(|s| s.to_string()).collect(),
+        })
+    }
+
+    /// Id the pipe is installed under, as for a github source: the name of its folder,
+    /// or of the repo for a project root or a ref without a folder.
+    pub fn pipe_id(&self) -> String {
+        match self.tree.as_slice() {
+            [_, .., folder] => sanitize_pipe_name(folder),
+            _ => sanitize_pipe_name(self.project.rsplit('/').next().unwrap_or(&self.project)),
+        }
+    }
+
+    /// Api url of the project followed by `segments`, each encoded as one path segment.
+    fn api_url(&self, segments: &[&str]) -> Result<Url> {
+        let mut url = Url::parse(&self.base_url)?;
+        url.path_segments_mut()
+            .map_err(|_| anyhow::anyhow!("invalid gitlab url: {}", self.base_url))?
+            .pop_if_empty()
+            .extend(["api", "v4", "projects", &self.project])
+            .extend(segments);
+        Ok(url)
+    }
+
+    fn not_found(&self) -> anyhow::Error {
+        anyhow::anyhow!(
+            "gitlab project {} not found or token missing, set {} to download pipes from a \
+             private project",
+            self.project,
+            GITLAB_TOKEN_ENV
+        )
+    }
+
+    /// The source at `git_ref`, a branch, tag or commit, with the folder of its url.
+    pub async fn at_ref(&self, client: &Client, git_ref: &str) -> Result<GitlabSource> {
+        let folder = if self.tree.is_empty() {
+            String::new()
+        } else {
+            self.commit(client).await?.path
+        };
+        Ok(GitlabSource {
+            tree: tree_at_ref(git_ref, &folder),
+            ..self.clone()
+        })
+    }
+
+    /// The ref and folder of the source, the default branch for a project root, and the
+    /// commit the ref points to now. A ref with slashes is the shortest that exists.
+    pub async fn commit(&self, client: &Client) -> Result<GithubCommit> {
+        if self.tree.is_empty() {
+            let project: GitlabProject = gitlab_get(client, self.api_url(&[])?)
+                .await?
+                .ok_or_else(|| self.not_found())?
+                .json()
+                .await?;
+            let git_ref = project
+                .default_branch
+                .ok_or_else(|| anyhow::anyhow!("gitlab project {} is empty", self.project))?;
+            let sha = self
+                .find_commit(client, &git_ref)
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("{} has no branch {}", self.project, git_ref))?;
+            return Ok(GithubCommit {
+                git_ref,
+                path: String::new(),
+                sha,
+            });
+        }
+        for i in 1..=self.tree.len() {
+            let git_ref = self.tree[..i].join("/");
+            if let Some(sha) = self.find_commit(client, &git_ref).await? {
+                return Ok(GithubCommit {
+                    git_ref,
+                    path: self.tree[i..].join("/"),
+                    sha,
+                });
+            }
+            // A commit has no slashes
+            if is_commit_sha(&git_ref) {
+                break;
+            }
+        }
+        if gitlab_get(client, self.api_url(&[])?).await?.is_none() {
+            return Err(self.not_found());
+        }
+        anyhow::bail!(
+            "no branch, tag or commit of {} matches {}",
+            self.project,
+            self.tree.join("/")
+        )
+    }
+
+    /// Sha of the commit `git_ref` points to, `None` when it isn't a ref.
+    async fn find_commit(&self, client: &Client, git_ref: &str) -> Result<Option<String>> {
+        let url = self.api_url(&["repository", "commits", git_ref])?;
+        let Some(response) = gitlab_get(client, url).await? else {
+            return Ok(None);
+        };
+        let commit: GitlabCommit = response.json().await?;
+        if !is_commit_sha(&commit.id) {
+            anyhow::bail!("unexpected commit sha for {} at {}", self.project, git_ref);
+        }
+        Ok(Some(commit.id))
+    }
+
+    /// Every entry below the folder of `commit`, following the pages of the listing.
+    pub async fn tree(
+        &self,
+        client: &Client,
+        commit: &GithubCommit,
+    ) -> Result<Vec<GitlabTreeEntry>> {
+        let mut entries = Vec::new();
+        let mut page = "1".to_string();
+        loop {
+            let mut url = self.api_url(&["repository", "tree"])?;
+            url.query_pairs_mut()
+                .append_pair("ref", &commit.sha)
+                .append_pair("recursive", "true")
+                .append_pair("per_page", &GITLAB_PAGE_SIZE.to_string())
+                .append_pair("page", &page);
+            if !commit.path.is_empty() {
+                url.query_pairs_mut().append_pair("path", &commit.path);
+            }
+            let response =
+                gitlab_get(client, url)
+                    .await?
+                    .ok_or_else(|| match commit.path.as_str() {
+                        "" => self.not_found(),
+                        path => anyhow::anyhow!("{} isn't in the project", path),
+                    })?;
+            let next_page = response
+                .headers()
+                .get("x-next-page")
+                .and_then(|next| next.to_str().ok())
+                .map(str::trim)
+                .filter(|next| !next.is_empty())
+                .map(str::to_string);
+            entries.extend(response.json::<Vec<GitlabTreeEntry>>().await?);
+            match next_page {
+                Some(next) if next != page => page = next,
+                _ => break,
+            }
+        }
+        if entries.is_empty() {
+            anyhow::bail!("{} has no files at {}", self.project, commit.sha);
+        }
+        Ok(entries)
+    }
+
+    /// Downloads the folder of `commit` into `dest_dir`, copying the files `installed`
+    /// has at the listed sha and fetching `concurrency` files at once, each request made up
+    /// to `attempts` times. Returns the files written, each reported to `progress`.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn download(
+        &self,
+        client: &Client,
+        commit: &GithubCommit,
+        dest_dir: &Path,
+        installed: Arc<InstalledFiles>,
+        concurrency: usize,
+        attempts: u32,
+        progress: Option<&mpsc::Sender<DownloadProgress>>,
+    ) -> Result<PipeFiles> {
+        let prefix = if commit.path.is_empty() {
+            String::new()
+        } else {
+            format!("{}/", commit.path)
+        };
+        let mut listed = Vec::new();
+        let tree = with_retries(attempts, "list", "", || self.tree(client, commit)).await?;
+        for entry in tree {
+            let Some(rel) = entry.path.strip_prefix(&prefix) else {
+                continue;
+            };
+            if rel
+                .split('/')
+                .any(|part| is_hidden_file(std::ffi::OsStr::new(part)))
+            {
+                debug!("skipping hidden file: {}", entry.path);
+                continue;
+            }
+            // Paths come from the api, none may write outside of dest_dir
+            if rel.contains('\\') || rel.split('/').any(|part| part == "..") {
+                debug!("skipping entry with an invalid name: {}", entry.path);
+                continue;
+            }
+            match entry.kind.as_str() {
+                "tree" if rel.split('/').count() > MAX_GITHUB_DEPTH => {
+                    anyhow::bail!("{} is nested deeper than {} folders", rel, MAX_GITHUB_DEPTH)
+                }
+                // The raw api answers with the target of a symlink, not its content
+                "blob" if entry.mode == GIT_SYMLINK_MODE => {
+                    debug!("skipping symlink: {}", entry.path)
+                }
+                "blob" => listed.push(ListedFile {
+                    rel: rel.to_string(),
+                    path: entry.path,
+                    sha: entry.id,
+                }),
+                kind => debug!("skipping {}: {}", entry.path, kind),
+            }
+        }
+        download_listed_files(
+            listed,
+            dest_dir,
+            &installed,
+            concurrency,
+            attempts,
+            progress,
+            |file| async move {
+                self.file(client, commit, &file.path)
+                    .await
+                    .and_then(|content| {
+                        content.ok_or_else(|| anyhow::anyhow!("gitlab api returned status 404"))
+                    })
+            },
+        )
+        .await
+    }
+
+    /// Content of the file at `path` in the project at `commit`, `None` when it has none.
+    pub async fn file(
+        &self,
+        client: &Client,
+        commit: &GithubCommit,
+        path: &str,
+    ) -> Result<Option<Vec<u8>>> {
+        let mut url = self.api_url(&["repository", "files", path, "raw"])?;
+        url.query_pairs_mut().append_pair("ref", &commit.sha);
+        let Some(response) = gitlab_get(client, url).await? else {
+            return Ok(None);
+        };
+        let content = response
+            .bytes()
+            .await
+            .map_err(reqwest::Error::without_url)?;
+        Ok(Some(content.to_vec()))
+    }
+}
@@ -0,0 +1,60 @@
+//! `.screenpipeignore`, at the root of a pipe's source: files left out when it is
+//! installed, in gitignore syntax. `tests/` leaves out every `tests` folder,
+//! `/tests/` the one at the root, and `!important.log` brings back a file an earlier
+//! pattern left out. [`DEFAULT_PIPE_IGNORE`] comes first, a pipe's own patterns may
+//! re-include what it leaves out.
+//!
+//! It applies to a pipe copied from a local path or a git clone, and to one downloaded
+//! from github. Hidden files, the ignore file among them, are never installed.
+
+use anyhow::Result;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::path::Path;
+
+/// The ignore file of a pipe, at the root of its source.
+pub const PIPE_IGNORE_FILE: &str = ".screenpipeignore";
+
+/// Patterns every pipe is installed without.
+pub const DEFAULT_PIPE_IGNORE: [&str; 4] = ["node_modules", "target", "dist-cache", "*.log"];
+
+/// The files a pipe is installed without.
+#[derive(Debug, Clone)]
+pub struct PipeIgnore {
+    matcher: Gitignore,
+}
+
+impl PipeIgnore {
+    /// [`DEFAULT_PIPE_IGNORE`] followed by the lines of `content`, a pipe's ignore file.
+    pub fn parse(content: Option<&str>) -> Result<Self> {
+        // Paths are matched relative to the pipe root
+        let mut builder = GitignoreBuilder::new("");
+        for line in DEFAULT_PIPE_IGNORE {
+            builder.add_line(None, line)?;
+        }
+        for line in content.unwrap_or_default().lines() {
+            builder
+                .add_line(None, line)
+                .map_err(|e| anyhow::anyhow!("{}: {}", PIPE_IGNORE_FILE, e))?;
+        }
+        Ok(PipeIgnore {
+            matcher: builder.build()?,
+        })
+    }
+
+    /// The ignore file of the pipe source in `dir`, the defaults when it has none.
+    pub async fn load(dir: &Path) -> Result<Self> {
+        match tokio::fs::read_to_string(dir.join(PIPE_IGNORE_FILE)).await {
+            Ok(content) => Self::parse(Some(&content)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Self::parse(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Whether `path`, below the pipe root with `/` separators, is left out, itself or
+    /// a folder it is in.
+    pub fn is_ignored(&self, path: &str, is_dir: bool) -> bool {
+        self.matcher
+            .matched_path_or_any_parents(path, is_dir)
+            .is_ignore()
+    }
+}
@@ -0,0 +1,223 @@
+//! A channel pipes talk back to screenpipe on, next to what they print: a unix socket,
+//! `pipes/<pipe>.sock`, or a named pipe, `\\.\pipe\screenpipe-<pipe>`, on windows. A
+//! pipe finds its path in `SCREENPIPE_IPC_PATH` and writes one json message per line,
+//! e.g. `{"type": "metric", "key": "notes_written", "value": 3}`.
+//!
+//! Each message is logged under the pipe's name and passed on to the sender the pipe
+//! was started with, see [`crate::PipeRunOptions`]. Lines that aren't a message are
+//! logged and skipped, the connection stays open.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::sync::mpsc;
+use tokio::task::{JoinHandle, JoinSet};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn, Level};
+
+use crate::pipes::log_pipe_line;
+
+/// Environment variable with the path of the pipe's [`PipeIpcServer`].
+pub const IPC_PATH_ENV: &str = "SCREENPIPE_IPC_PATH";
+
+/// Time connections get to pass on their last messages once the pipe exited.
+const IPC_FLUSH_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A message of a pipe, `type` names its kind.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum IpcMessage {
+    /// Logged like a line the pipe prints, at `level` or info
+    Log {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        level: Option<String>,
+        message: String,
+    },
+    Metric {
+        key: String,
+        value: f64,
+    },
+    Notification {
+        title: String,
+        body: String,
+    },
+}
+
+/// A message and the pipe that sent it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PipeIpcEvent {
+    pub pipe: String,
+    pub message: IpcMessage,
+}
+
+/// Where the [`PipeIpcServer`] of `pipe` listens.
+pub fn ipc_path(pipe: &str, screenpipe_dir: &Path) -> PathBuf {
+    #[cfg(unix)]
+    {
+        screenpipe_dir.join("pipes").join(format!("{}.sock", pipe))
+    }
+    #[cfg(windows)]
+    {
+        let _ = screenpipe_dir;
+        PathBuf::from(format!(r"\\.\pipe\screenpipe-{}", pipe))
+    }
+}
+
+/// Listens for the messages of a pipe, at [`ipc_path`].
+pub struct PipeIpcServer {
+    pipe: String,
+    path: PathBuf,
+    events: Option<mpsc::Sender<PipeIpcEvent>>,
+    #[cfg(unix)]
+    listener: tokio::net::UnixListener,
+    #[cfg(windows)]
+    server: tokio::net::windows::named_pipe::NamedPipeServer,
+}
+
+impl PipeIpcServer {
+    /// Listens for `pipe`, whose messages are sent to `events` once [`Self::spawn`]ed. A
+    /// socket left behind by a run that didn't exit cleanly is replaced.
+    pub async fn bind(
+        pipe: &str,
+        screenpipe_dir: &Path,
+        events: Option<mpsc::Sender<PipeIpcEvent>>,
+    ) -> Result<Self> {
+        let path = ipc_path(pipe, screenpipe_dir);
+        #[cfg(unix)]
+        let listener = {
+            match tokio::fs::remove_file(&path).await {
+                Ok(()) => debug!("replacing the stale ipc socket {:?}", path),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e.into()),
+            }
+            tokio::net::UnixListener::bind(&path)
+                .map_err(|e| anyhow::anyhow!("failed to listen on {:?}: {}", path, e))?
+        };
+        #[cfg(windows)]
+        let server = tokio::net::windows::named_pipe::ServerOptions::new()
+            .first_pipe_instance(true)
+            .create(&path)
+            .map_err(|e| anyhow::anyhow!("failed to listen on {:?}: {}", path, e))?;
+        Ok(Self {
+            pipe: pipe.to_string(),
+            path,
+            events,
+            #[cfg(unix)]
+            listener,
+            #[cfg(windows)]
+            server,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reads the messages of each connection until `done` is cancelled, when the pipe
+    /// exited, then stops listening.
+    pub fn spawn(self, done: CancellationToken) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut server = self;
+            let mut connections = JoinSet::new();
+            loop {
+                tokio::select! {
+                    _ = done.cancelled() => break,
+                    accepted = server.accept() => match accepted {
+                        Ok(reader) => {
+                            connections.spawn(read_messages(
+                                server.pipe.clone(),
+                                reader,
+                                server.events.clone(),
+                            ));
+                        }
+                        Err(e) => {
+                            warn!("ipc of pipe {} stopped: {}", server.pipe, e);
+                            break;
+                        }
+                    },
+                }
+            }
+            let flushed = async { while connections.join_next().await.is_some() {} };
+            if tokio::time::timeout(IPC_FLUSH_TIMEOUT, flushed)
+                .await
+                .is_err()
+            {
+                debug!("closing the open ipc connections of pipe {}", server.pipe);
+            }
+        })
+    }
+
+    #[cfg(unix)]
+    async fn accept(&mut self) -> std::io::Result<tokio::net::UnixStream> {
+        Ok(self.listener.accept().await?.0)
+    }
+
+    /// The connected instance, a new one takes the next client.
+    #[cfg(windows)]
+    async fn accept(
+        &mut self,
+    ) -> std::io::Result<tokio::net::windows::named_pipe::NamedPipeServer> {
+        use tokio::net::windows::named_pipe::ServerOptions;
+        self.server.connect().await?;
+        let next = ServerOptions::new().create(&self.path)?;
+        Ok(std::mem::replace(&mut self.server, next))
+    }
+}
+
+#[cfg(unix)]
+impl Drop for PipeIpcServer {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+async fn read_messages<R: AsyncRead + Unpin>(
+    pipe: String,
+    reader: R,
+    events: Option<mpsc::Sender<PipeIpcEvent>>,
+) {
+    let mut lines = BufReader::new(reader).lines();
+    loop {
+        let line = match lines.next_line().await {
+            Ok(Some(line)) => line,
+            Ok(None) => return,
+            Err(e) => {
+                warn!("failed to read the ipc of pipe {}: {}", pipe, e);
+                return;
+            }
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let message = match serde_json::from_str::<IpcMessage>(&line) {
+            Ok(message) => message,
+            Err(e) => {
+                warn!("[{}] skipping invalid ipc message: {}", pipe, e);
+                continue;
+            }
+        };
+        match &message {
+            IpcMessage::Log { level, message } => log_pipe_line(
+                &pipe,
+                &json!({ "level": level, "message": message }).to_string(),
+                Level::INFO,
+            ),
+            IpcMessage::Metric { key, value } => info!("[{}] metric {} = {}", pipe, key, value),
+            IpcMessage::Notification { title, body } => {
+                info!("[{}] notification: {}: {}", pipe, title, body)
+            }
+        }
+        if let Some(events) = &events {
+            // Nobody listening doesn't stop the pipe
+            let _ = events
+                .send(PipeIpcEvent {
+                    pipe: pipe.clone(),
+                    message,
+                })
+                .await;
+        }
+    }
+}
@@ -0,0 +1,111 @@
+//! Pipes linked to a folder being developed: `pipes/<pipe>` is a symlink to it, a
+//! junction on windows, so each run uses its files as they are, with no download after
+//! an edit. Runs start in the folder itself, `PIPE_DIR` and `PIPE_FILE` name its real
+//! path so relative imports and `node_modules` resolve there.
+//!
+//! A linked pipe isn't updated, and deleting it removes the link, never the folder.
+
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+use crate::pipe_manager::{PipeManager, RuntimeConfig};
+use crate::pipe_manifest::validate_pipe_manifest;
+use crate::pipes::pipe_id_from_source;
+
+/// Links the folder at `source` as a pipe, under the id [`crate::download_pipe`] would
+/// install it with. A link to another folder is replaced, an installed copy isn't.
+#[deprecated(note = "use PipeManager::link_pipe")]
+pub async fn link_pipe(source: &str, screenpipe_dir: PathBuf) -> Result<PathBuf> {
+    PipeManager::new(screenpipe_dir, RuntimeConfig::default())?
+        .link_pipe(source)
+        .await
+}
+
+/// Links the folder at `source` as a pipe of `screenpipe_dir`.
+pub(crate) async fn create_pipe_link(source: &str, screenpipe_dir: PathBuf) -> Result<PathBuf> {
+    let source_path = Path::new(source);
+    if !source_path.is_dir() {
+        anyhow::bail!("Invalid local source path");
+    }
+    validate_pipe_manifest(source_path).await?;
+    // Relative to where the link is, a relative target would point elsewhere
+    let target = std::path::absolute(source_path)?;
+    let pipe_name = pipe_id_from_source(source)
+        .ok_or_else(|| anyhow::anyhow!("invalid pipe source: {}", source))?;
+    let dest_dir = screenpipe_dir.join("pipes").join(&pipe_name);
+
+    if is_linked_pipe(&dest_dir) {
+        remove_pipe_dir(&dest_dir).await?;
+    } else if dest_dir.exists() {
+        anyhow::bail!(
+            "pipe {} is installed already at {:?}, delete it to link {}",
+            pipe_name,
+            dest_dir,
+            source
+        );
+    }
+    tokio::fs::create_dir_all(screenpipe_dir.join("pipes")).await?;
+    link_dir(&target, &dest_dir).await?;
+    info!("pipe {} linked to {:?}", pipe_name, target);
+    Ok(dest_dir)
+}
+
+#[cfg(unix)]
+async fn link_dir(target: &Path, link: &Path) -> Result<()> {
+    tokio::fs::symlink(target, link).await?;
+    Ok(())
+}
+
+/// A junction, unlike a symlink it doesn't take developer mode or an admin.
+#[cfg(windows)]
+async fn link_dir(target: &Path, link: &Path) -> Result<()> {
+    let output = tokio::process::Command::new("cmd")
+        .args(["/C", "mklink", "/J"])
+        .arg(link)
+        .arg(target)
+        .output()
+        .await?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "failed to link {:?} to {:?}: {}",
+            link,
+            target,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// Whether the pipe in `pipe_dir` was linked with [`link_pipe`].
+pub fn is_linked_pipe(pipe_dir: &Path) -> bool {
+    std::fs::symlink_metadata(pipe_dir).is_ok_and(|metadata| metadata.file_type().is_symlink())
+}
+
+/// The folder a pipe in `pipe_dir` runs in, the one it is linked to, if it is.
+pub fn resolve_pipe_dir(pipe_dir: PathBuf) -> PathBuf {
+    if !is_linked_pipe(&pipe_dir) {
+        return pipe_dir;
+    }
+    match std::fs::read_link(&pipe_dir) {
+        Ok(target) => match pipe_dir.parent() {
+            Some(pipes_dir) => pipes_dir.join(target),
+            None => target,
+        },
+        Err(_) => pipe_dir,
+    }
+}
+
+/// Removes an installed pipe, only the link of a linked one.
+pub async fn remove_pipe_dir(pipe_dir: &Path) -> Result<()> {
+    if !is_linked_pipe(pipe_dir) {
+        tokio::fs::remove_dir_all(pipe_dir).await?;
+        return Ok(());
+    }
+    // A junction is removed as a folder, a symlink as a file
+    #[cfg(windows)]
+    tokio::fs::remove_dir(pipe_dir).await?;
+    #[cfg(not(windows))]
+    tokio::fs::remove_file(pipe_dir).await?;
+    Ok(())
+}
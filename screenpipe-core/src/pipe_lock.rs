@@ -0,0 +1,389 @@
+//! What a pipe was downloaded as: its [`PIPE_LOCK_FILE`], with the source, commit and
+//! sha256 of each file, the blob shas of [`PIPE_FILES_FILE`], and the `checksums.json` a
+//! source may check its files against.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+use tracing::warn;
+
+use crate::pipe_config::load_config;
+use crate::pipe_github::GithubCommit;
+use crate::pipe_metadata::{PipeMetadata, PipeSourceKind};
+use crate::pipes::{is_hidden_file, DownloadOptions};
+
+/// `pipe.lock`, written next to each downloaded pipe: where its files are from, when
+/// and by which screenpipe they were installed, and the sha256 of each as downloaded.
+pub const PIPE_LOCK_FILE: &str = "pipe.lock";
+
+/// The content of a pipe's [`PIPE_LOCK_FILE`]. One written by an earlier screenpipe
+/// may have nothing but the source and commit.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PipeLock {
+    /// Url or path the files are from, the source the registry lists for a pipe
+    /// installed by name
+    pub source: String,
+    pub kind: Option<PipeSourceKind>,
+    /// Commit of a github, gitlab, bitbucket or git pipe, version of an npm one
+    pub resolved_ref: Option<String>,
+    pub installed_at: Option<DateTime<Utc>>,
+    /// Version of screenpipe-core that installed it
+    pub installer_version: Option<String>,
+    /// Ref and commit of a github, gitlab or bitbucket pipe, `None` for other sources
+    #[serde(flatten)]
+    pub commit: Option<GithubCommit>,
+    #[serde(flatten)]
+    pub integrity: Option<PipeIntegrity>,
+}
+
+impl PipeLock {
+    /// A pipe installed now from `source`.
+    pub(crate) fn new(source: &str, kind: PipeSourceKind, resolved_ref: Option<String>) -> Self {
+        Self {
+            source: source.to_string(),
+            kind: Some(kind),
+            resolved_ref,
+            installed_at: Some(Utc::now()),
+            installer_version: Some(env!("CARGO_PKG_VERSION").to_string()),
+            commit: None,
+            integrity: None,
+        }
+    }
+
+    /// The lock without the commit and hashes of the files, for a folder left with the
+    /// pipe's settings to tell where it is from while its files are set aside.
+    pub fn without_files(self) -> Self {
+        Self {
+            commit: None,
+            integrity: None,
+            ..self
+        }
+    }
+
+    /// Where the pipe is from, `None` for a lock written before it was recorded.
+    pub fn metadata(&self) -> Option<PipeMetadata> {
+        Some(PipeMetadata {
+            source: self.source.clone(),
+            resolved_ref: self.resolved_ref.clone(),
+            installed_at: self.installed_at?,
+            installer_version: self.installer_version.clone()?,
+            kind: self.kind?,
+        })
+    }
+
+    /// The lock of `pipe_dir`, `None` for a pipe downloaded before there was one.
+    pub fn load(pipe_dir: &Path) -> Result<Option<PipeLock>> {
+        match fs::read(pipe_dir.join(PIPE_LOCK_FILE)) {
+            Ok(content) => serde_json::from_slice(&content)
+                .map(Some)
+                .map_err(|e| anyhow::anyhow!("invalid {}: {}", PIPE_LOCK_FILE, e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub async fn save(&self, pipe_dir: &Path) -> Result<()> {
+        tokio::fs::write(
+            pipe_dir.join(PIPE_LOCK_FILE),
+            serde_json::to_vec_pretty(self)?,
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+/// The source and commit of a pipe from github, gitlab or bitbucket, as its
+/// [`PIPE_LOCK_FILE`] has them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DownloadedPipe {
+    pub source: String,
+    #[serde(flatten)]
+    pub commit: GithubCommit,
+}
+
+/// `.pipe_files.json`, next to a pipe downloaded from github: the blob sha of each of
+/// its files. Hidden, so it is left out of copies and checksums of the pipe.
+pub const PIPE_FILES_FILE: &str = ".pipe_files.json";
+
+/// The content of a pipe's [`PIPE_FILES_FILE`], by path below the pipe root.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PipeFiles {
+    pub files: BTreeMap<String, PipeFile>,
+}
+
+/// A downloaded file, with the size and modification time it was written with so a
+/// file edited since isn't taken for the blob.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PipeFile {
+    pub sha: String,
+    pub size: u64,
+    /// Seconds since the unix epoch
+    pub modified: u64,
+}
+
+impl PipeFile {
+    pub(crate) async fn read(path: &Path, sha: &str) -> Result<PipeFile> {
+        let metadata = tokio::fs::metadata(path).await?;
+        Ok(PipeFile {
+            sha: sha.to_string(),
+            size: metadata.len(),
+            modified: metadata.modified()?.duration_since(UNIX_EPOCH)?.as_secs(),
+        })
+    }
+}
+
+impl PipeFiles {
+    /// The files recorded in `pipe_dir`, none when its record is missing or corrupt.
+    pub async fn load(pipe_dir: &Path) -> PipeFiles {
+        let Ok(content) = tokio::fs::read(pipe_dir.join(PIPE_FILES_FILE)).await else {
+            return PipeFiles::default();
+        };
+        match serde_json::from_slice(&content) {
+            Ok(files) => files,
+            Err(e) => {
+                warn!("ignoring {} of {:?}: {}", PIPE_FILES_FILE, pipe_dir, e);
+                PipeFiles::default()
+            }
+        }
+    }
+
+    pub async fn save(&self, pipe_dir: &Path) -> Result<()> {
+        tokio::fs::write(
+            pipe_dir.join(PIPE_FILES_FILE),
+            serde_json::to_vec_pretty(self)?,
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+/// `checksums.json`, in the source of a pipe: the sha256 of each of its files, by path
+/// below the pipe root. Hidden files, this one and [`PIPE_LOCK_FILE`] have none.
+pub const PIPE_CHECKSUMS_FILE: &str = "checksums.json";
+
+/// Checks the files of a pipe downloaded to `pipe_dir` against `checksums`, sha256 hex
+/// by path below the pipe root. Fails on a file whose hash differs, one `checksums`
+/// doesn't list and one it lists that wasn't downloaded.
+pub fn verify_pipe_checksums(pipe_dir: &Path, checksums: &HashMap<String, String>) -> Result<()> {
+    let mut files = Vec::new();
+    checksummed_files(pipe_dir, pipe_dir, &mut files)?;
+    files.sort();
+
+    for relative in &files {
+        let expected = checksums
+            .get(relative)
+            .ok_or_else(|| anyhow::anyhow!("{} has no checksum", relative))?;
+        let sha256 = sha256_file(&pipe_dir.join(relative))?;
+        if !sha256.eq_ignore_ascii_case(expected.trim()) {
+            anyhow::bail!(
+                "{} doesn't match its checksum, sha256 {} instead of {}",
+                relative,
+                sha256,
+                expected
+            );
+        }
+    }
+    let mut missing = checksums
+        .keys()
+        .filter(|path| !files.contains(path))
+        .collect::<Vec<_>>();
+    missing.sort();
+    if let Some(path) = missing.first() {
+        anyhow::bail!("{} has a checksum but wasn't downloaded", path);
+    }
+    Ok(())
+}
+
+fn sha256_file(path: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Paths of the files under `dir` with a checksum, relative to `root` with `/`
+/// separators.
+fn checksummed_files(root: &Path, dir: &Path, files: &mut Vec<String>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if is_hidden_file(&entry.file_name())
+            || (dir == root
+                && [PIPE_CHECKSUMS_FILE, PIPE_LOCK_FILE]
+                    .iter()
+                    .any(|name| entry.file_name() == *name))
+        {
+            continue;
+        }
+        let file_type = entry.file_type()?;
+        // A symlink copied as a link has no content of its own
+        if file_type.is_symlink() {
+            continue;
+        }
+        if file_type.is_dir() {
+            checksummed_files(root, &path, files)?;
+        } else {
+            let relative = path.strip_prefix(root)?;
+            let parts: Vec<_> = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect();
+            files.push(parts.join("/"));
+        }
+    }
+    Ok(())
+}
+
+/// Checksums of `options`, else those of the source's [`PIPE_CHECKSUMS_FILE`].
+pub(crate) async fn pipe_checksums(
+    pipe_dir: &Path,
+    options: &DownloadOptions,
+) -> Result<Option<HashMap<String, String>>> {
+    if let Some(checksums) = &options.checksums {
+        return Ok(Some(checksums.clone()));
+    }
+    match tokio::fs::read(pipe_dir.join(PIPE_CHECKSUMS_FILE)).await {
+        Ok(content) => serde_json::from_slice(&content)
+            .map(Some)
+            .map_err(|e| anyhow::anyhow!("invalid {}: {}", PIPE_CHECKSUMS_FILE, e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// The sha256 of each file a pipe was downloaded with, in its [`PIPE_LOCK_FILE`]: the
+/// files [`verify_pipe_checksums`] checks but pipe.json, which screenpipe writes the
+/// pipe's settings into.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PipeIntegrity {
+    /// `version` of the pipe.json the files were downloaded with
+    pub version: Option<String>,
+    /// Sha256 hex by path below the pipe root
+    pub files: BTreeMap<String, String>,
+}
+
+impl PipeIntegrity {
+    /// Hashes the files of the pipe in `pipe_dir`.
+    pub fn compute(pipe_dir: &Path) -> Result<PipeIntegrity> {
+        let mut paths = Vec::new();
+        checksummed_files(pipe_dir, pipe_dir, &mut paths)?;
+        let mut files = BTreeMap::new();
+        for relative in paths.into_iter().filter(|path| path != "pipe.json") {
+            files.insert(relative.clone(), sha256_file(&pipe_dir.join(&relative))?);
+        }
+        let version = fs::read(pipe_dir.join("pipe.json"))
+            .ok()
+            .and_then(|content| serde_json::from_slice::<Value>(&content).ok())
+            .and_then(|config| config.get("version")?.as_str().map(str::to_string));
+        Ok(PipeIntegrity { version, files })
+    }
+
+    /// The record of `pipe_dir`, `None` for a pipe downloaded before there was one.
+    pub fn load(pipe_dir: &Path) -> Result<Option<PipeIntegrity>> {
+        Ok(PipeLock::load(pipe_dir)?.and_then(|lock| lock.integrity))
+    }
+
+    /// Fails when a file both records have changed in `update` while the version of
+    /// the pipe didn't. Files added or removed by the update are expected.
+    pub fn check_update(&self, update: &PipeIntegrity) -> Result<()> {
+        if update.version.is_some() && update.version != self.version {
+            return Ok(());
+        }
+        let changed = self.files.iter().find(|(path, sha256)| {
+            update
+                .files
+                .get(*path)
+                .is_some_and(|updated| updated != *sha256)
+        });
+        match (changed, &update.version) {
+            (Some((path, _)), Some(version)) => anyhow::bail!(
+                "{} changed but the pipe is still at version {}",
+                path,
+                version
+            ),
+            (Some((path, _)), None) => {
+                anyhow::bail!("{} changed and the pipe has no version", path)
+            }
+            (None, _) => Ok(()),
+        }
+    }
+}
+
+/// State of a file of [`IntegrityReport`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileIntegrity {
+    /// As it was downloaded
+    Unchanged,
+    Modified,
+    Missing,
+}
+
+/// The files of a pipe's [`PipeIntegrity`] and whether each is still the one
+/// downloaded, by path below the pipe root. Files added since, a `node_modules` among
+/// them, aren't in it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct IntegrityReport {
+    pub files: BTreeMap<String, FileIntegrity>,
+}
+
+impl IntegrityReport {
+    /// Whether every file is the one downloaded.
+    pub fn is_intact(&self) -> bool {
+        self.files
+            .values()
+            .all(|status| *status == FileIntegrity::Unchanged)
+    }
+}
+
+/// Checks the files of the pipe in `pipe_dir` against the hashes in its
+/// [`PIPE_LOCK_FILE`]. Fails when it has none.
+#[deprecated(note = "use PipeManager::verify_pipe_integrity")]
+pub fn verify_pipe_integrity(pipe_dir: &Path) -> Result<IntegrityReport> {
+    check_pipe_integrity(pipe_dir)
+}
+
+/// [`verify_pipe_integrity`] of the pipe in `pipe_dir`.
+pub(crate) fn check_pipe_integrity(pipe_dir: &Path) -> Result<IntegrityReport> {
+    let integrity = PipeIntegrity::load(pipe_dir)?.ok_or_else(|| {
+        anyhow::anyhow!(
+            "{:?} has no file hashes in a {}, download it again to record its files",
+            pipe_dir,
+            PIPE_LOCK_FILE
+        )
+    })?;
+    let mut report = IntegrityReport::default();
+    for (relative, expected) in &integrity.files {
+        let path = pipe_dir.join(relative);
+        let status = if !path.is_file() {
+            FileIntegrity::Missing
+        } else if sha256_file(&path)?.eq_ignore_ascii_case(expected) {
+            FileIntegrity::Unchanged
+        } else {
+            FileIntegrity::Modified
+        };
+        report.files.insert(relative.clone(), status);
+    }
+    Ok(report)
+}
+
+/// The commit a pipe was downloaded at, `None` for pipes from other sources than
+/// github, gitlab and bitbucket.
+pub async fn downloaded_pipe(pipe_dir: &Path) -> Option<DownloadedPipe> {
+    let lock = load_config(&pipe_dir.join(PIPE_LOCK_FILE)).await.ok()?;
+    match serde_json::from_value::<PipeLock>(lock) {
+        Ok(lock) => Some(DownloadedPipe {
+            source: lock.source,
+            commit: lock.commit?,
+        }),
+        Err(e) => {
+            warn!("ignoring {} of {:?}: {}", PIPE_LOCK_FILE, pipe_dir, e);
+            None
+        }
+    }
+}
@@ -0,0 +1,325 @@
+//! [`PipeManager`]: the pipes of one screenpipe folder, with the http client, github token
+//! and run options set once for every operation on them. The free functions it replaces,
+//! [`crate::run_pipe`], [`crate::download_pipe`] and the like, are deprecated and build a
+//! manager with the default settings for each call.
+
+use anyhow::Result;
+use reqwest::Client;
+use std::path::{Path, PathBuf};
+use tokio::process::Child;
+use tokio::sync::mpsc;
+
+use crate::pipe_bundle::install_pipe_bundle;
+use crate::pipe_diff::{diff_pipe_with, PipeDiff};
+use crate::pipe_github::{
+    expand_github_shorthand, github_client_for, with_rate_limit_wait, with_stored_token,
+    GITHUB_API, GITHUB_RAW,
+};
+use crate::pipe_link::create_pipe_link;
+use crate::pipe_lock::{check_pipe_integrity, IntegrityReport};
+use crate::pipe_registry::{PipeRegistry, RegistryEntry, RegistryOptions};
+use crate::pipe_stats::{clear_pipe_stats, load_pipe_stats, PipeStats};
+use crate::pipes::{
+    deno_path, download_with_progress, fetch_pipe_update, install_pipe, install_pipe_update,
+    installed_pipe_dir, installed_pipes, installed_source, mark_pipe_disabled, run_watched_pipe,
+    start_pipe, start_pipe_once, unmark_pipe_disabled, update_installed_pipe,
+    update_installed_pipes, DownloadOptions, DownloadProgress, HttpOptions, InstalledPipe,
+    PipeRunOptions, PipeSource, PipeUpdate, PipeUpdateInfo, UpdateResult,
+};
+
+/// What a [`PipeManager`] downloads and runs pipes with.
+#[derive(Debug, Clone, Default)]
+pub struct RuntimeConfig {
+    /// Timeout and proxy of every request
+    pub http: HttpOptions,
+    /// Github token of pipes in private repos, the stored one or [`crate::GITHUB_TOKEN_ENV`]
+    /// when `None`
+    pub github_token: Option<String>,
+    /// What [`PipeManager::run_pipe`] starts a pipe with
+    pub run: PipeRunOptions,
+}
+
+#[derive(Debug, Clone)]
+pub struct PipeManager {
+    screenpipe_dir: PathBuf,
+    /// Built from [`RuntimeConfig::http`]. Without a token, as it is sent to any host
+    client: Client,
+    runtime_config: RuntimeConfig,
+}
+
+impl PipeManager {
+    /// Fails when the proxy of `config` isn't a valid url.
+    pub fn new(screenpipe_dir: PathBuf, config: RuntimeConfig) -> Result<Self> {
+        Ok(Self {
+            client: config.http.client()?,
+            screenpipe_dir,
+            runtime_config: config,
+        })
+    }
+
+    /// A manager with the default settings but for `http`, for the deprecated free
+    /// functions taking options.
+    pub(crate) fn with_http(screenpipe_dir: PathBuf, http: &HttpOptions) -> Result<Self> {
+        let config = RuntimeConfig {
+            http: http.clone(),
+            ..Default::default()
+        };
+        Self::new(screenpipe_dir, config)
+    }
+
+    /// A manager with the default settings but for `client`, for the deprecated free
+    /// functions taking one.
+    pub(crate) fn with_client(screenpipe_dir: PathBuf, client: Client) -> Self {
+        Self {
+            screenpipe_dir,
+            client,
+            runtime_config: RuntimeConfig::default(),
+        }
+    }
+
+    pub fn screenpipe_dir(&self) -> &Path {
+        &self.screenpipe_dir
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    pub fn runtime_config(&self) -> &RuntimeConfig {
+        &self.runtime_config
+    }
+
+    /// `options` with the token of the manager unless they have one, and its http settings
+    /// unless they set their own.
+    fn download_options(&self, options: DownloadOptions) -> DownloadOptions {
+        let http = if options.http == HttpOptions::default() {
+            self.runtime_config.http.clone()
+        } else {
+            options.http
+        };
+        DownloadOptions {
+            token: options
+                .token
+                .or_else(|| self.runtime_config.github_token.clone()),
+            http,
+            ..options
+        }
+    }
+
+    /// [`Self::client`], unless `options` set http settings of their own.
+    fn download_client(&self, options: &DownloadOptions) -> Result<Client> {
+        if options.http == self.runtime_config.http {
+            return Ok(self.client.clone());
+        }
+        options.http.client()
+    }
+
+    /// The client the source of the pipe in `pipe_dir` is fetched with: with the github
+    /// token of a download for a pipe from github, [`Self::client`] for other hosts, which
+    /// mustn't get it.
+    async fn source_client(&self, pipe_dir: &Path) -> Result<Client> {
+        let from_github = installed_source(pipe_dir).await.is_some_and(|source| {
+            let source = expand_github_shorthand(&source).unwrap_or(source);
+            matches!(PipeSource::parse(&source), Some(PipeSource::GitHub(_)))
+        });
+        if !from_github {
+            return Ok(self.client.clone());
+        }
+        let options = self.download_options(DownloadOptions::default());
+        let options = with_stored_token(options, &self.screenpipe_dir).await?;
+        github_client_for(&self.client, &options)
+    }
+
+    pub async fn download_pipe(&self, source: &str) -> Result<PathBuf> {
+        self.download_pipe_with(source, DownloadOptions::default())
+            .await
+    }
+
+    pub async fn download_pipe_with(
+        &self,
+        source: &str,
+        options: DownloadOptions,
+    ) -> Result<PathBuf> {
+        let options = self.download_options(options);
+        let client = self.download_client(&options)?;
+        install_pipe(source, self.screenpipe_dir.clone(), options, &client).await
+    }
+
+    /// [`Self::download_pipe_with`], sending the progress of the download to `tx`, see
+    /// [`crate::download_pipe_with_progress`].
+    pub async fn download_pipe_with_progress(
+        &self,
+        source: &str,
+        options: DownloadOptions,
+        tx: mpsc::Sender<DownloadProgress>,
+    ) -> Result<PathBuf> {
+        let options = self.download_options(options);
+        let client = self.download_client(&options)?;
+        download_with_progress(source, self.screenpipe_dir.clone(), options, &client, tx).await
+    }
+
+    pub async fn download_pipe_bundle(&self, source: &str) -> Result<Vec<PathBuf>> {
+        self.download_pipe_bundle_with(source, DownloadOptions::default())
+            .await
+    }
+
+    pub async fn download_pipe_bundle_with(
+        &self,
+        source: &str,
+        options: DownloadOptions,
+    ) -> Result<Vec<PathBuf>> {
+        let options = self.download_options(options);
+        let client = self.download_client(&options)?;
+        install_pipe_bundle(source, self.screenpipe_dir.clone(), options, &client).await
+    }
+
+    /// Links the folder at `source` as a pipe, see [`crate::pipe_link`].
+    pub async fn link_pipe(&self, source: &str) -> Result<PathBuf> {
+        create_pipe_link(source, self.screenpipe_dir.clone()).await
+    }
+
+    /// Starts `pipe` with the run options of the manager.
+    pub async fn run_pipe(&self, pipe: &str) -> Result<Child> {
+        self.run_pipe_with(pipe, self.runtime_config.run.clone())
+            .await
+    }
+
+    pub async fn run_pipe_with(&self, pipe: &str, options: PipeRunOptions) -> Result<Child> {
+        start_pipe(pipe, self.screenpipe_dir.clone(), options).await
+    }
+
+    /// Runs `pipe` once to handle `event`, see [`crate::run_pipe_once`].
+    pub async fn run_pipe_once(&self, pipe: &str, event: &str) -> Result<Child> {
+        self.run_pipe_once_with(pipe, event, self.runtime_config.run.clone())
+            .await
+    }
+
+    pub async fn run_pipe_once_with(
+        &self,
+        pipe: &str,
+        event: &str,
+        options: PipeRunOptions,
+    ) -> Result<Child> {
+        start_pipe_once(pipe, self.screenpipe_dir.clone(), event, options).await
+    }
+
+    /// Runs `pipe` and restarts it whenever a file in its folder changes, see
+    /// [`crate::watch_pipe`].
+    pub async fn watch_pipe(&self, pipe: &str) -> Result<()> {
+        self.watch_pipe_with(pipe, self.runtime_config.run.clone())
+            .await
+    }
+
+    pub async fn watch_pipe_with(&self, pipe: &str, options: PipeRunOptions) -> Result<()> {
+        run_watched_pipe(pipe, self.screenpipe_dir.clone(), options).await
+    }
+
+    pub async fn list_pipes(&self) -> Result<Vec<InstalledPipe>> {
+        installed_pipes(&self.screenpipe_dir).await
+    }
+
+    pub async fn enable_pipe(&self, pipe: &str) -> Result<()> {
+        unmark_pipe_disabled(pipe, &self.screenpipe_dir).await
+    }
+
+    pub async fn disable_pipe(&self, pipe: &str) -> Result<()> {
+        mark_pipe_disabled(pipe, &self.screenpipe_dir).await
+    }
+
+    pub async fn update_pipe(&self, pipe: &str) -> Result<UpdateResult> {
+        self.update_pipe_with(pipe, DownloadOptions::default())
+            .await
+    }
+
+    pub async fn update_pipe_with(
+        &self,
+        pipe: &str,
+        options: DownloadOptions,
+    ) -> Result<UpdateResult> {
+        let options = self.download_options(options);
+        let client = self.download_client(&options)?;
+        update_installed_pipe(pipe, self.screenpipe_dir.clone(), options, &client).await
+    }
+
+    pub async fn update_all_pipes(&self) -> Result<Vec<PipeUpdate>> {
+        self.update_all_pipes_with(DownloadOptions::default()).await
+    }
+
+    pub async fn update_all_pipes_with(&self, options: DownloadOptions) -> Result<Vec<PipeUpdate>> {
+        let options = self.download_options(options);
+        let client = self.download_client(&options)?;
+        update_installed_pipes(self.screenpipe_dir.clone(), options, &client).await
+    }
+
+    /// The newer version of `pipe` its source has, `None` when it is up to date.
+    pub async fn check_pipe_update(&self, pipe: &str) -> Result<Option<PipeUpdateInfo>> {
+        let pipe_dir = installed_pipe_dir(pipe, &self.screenpipe_dir)?;
+        let client = self.source_client(&pipe_dir).await?;
+        let check = fetch_pipe_update(&pipe_dir, &client, GITHUB_API, GITHUB_RAW);
+        with_rate_limit_wait(&self.runtime_config.http, check).await
+    }
+
+    /// Downloads `pipe` again when its source has a newer version, see
+    /// [`Self::check_pipe_update`]. [`Self::update_pipe`] goes by commit instead.
+    pub async fn update_pipe_version(&self, pipe: &str) -> Result<()> {
+        let update = self.check_pipe_update(pipe).await?;
+        let pipe_dir = installed_pipe_dir(pipe, &self.screenpipe_dir)?;
+        let options = self.download_options(DownloadOptions::default());
+        install_pipe_update(
+            &pipe_dir,
+            &self.screenpipe_dir,
+            update,
+            options,
+            &self.client,
+        )
+        .await
+    }
+
+    pub async fn diff_pipe(&self, pipe: &str) -> Result<PipeDiff> {
+        let pipe_dir = installed_pipe_dir(pipe, &self.screenpipe_dir)?;
+        let client = self.source_client(&pipe_dir).await?;
+        let diff = self.diff_pipe_using(pipe, &client);
+        with_rate_limit_wait(&self.runtime_config.http, diff).await
+    }
+
+    /// [`Self::diff_pipe`], fetching the source with `client`.
+    pub(crate) async fn diff_pipe_using(&self, pipe: &str, client: &Client) -> Result<PipeDiff> {
+        diff_pipe_with(pipe, &self.screenpipe_dir, client, GITHUB_API, GITHUB_RAW).await
+    }
+
+    /// Checks the files of `pipe` against the hashes its download recorded.
+    pub fn verify_pipe_integrity(&self, pipe: &str) -> Result<IntegrityReport> {
+        check_pipe_integrity(&installed_pipe_dir(pipe, &self.screenpipe_dir)?)
+    }
+
+    /// The recorded runs of `pipe`, oldest first.
+    pub async fn pipe_stats(&self, pipe: &str) -> Result<Vec<PipeStats>> {
+        load_pipe_stats(pipe, &self.screenpipe_dir).await
+    }
+
+    /// Forgets the recorded runs of `pipe`.
+    pub async fn reset_pipe_stats(&self, pipe: &str) -> Result<()> {
+        clear_pipe_stats(pipe, &self.screenpipe_dir).await
+    }
+
+    /// The pipes of the default registry `query` matches.
+    pub async fn search_registry(&self, query: &str) -> Result<Vec<RegistryEntry>> {
+        self.search_registry_with(query, &RegistryOptions::default())
+            .await
+    }
+
+    /// The pipes of the registry of `options` `query` matches.
+    pub async fn search_registry_with(
+        &self,
+        query: &str,
+        options: &RegistryOptions,
+    ) -> Result<Vec<RegistryEntry>> {
+        let registry = PipeRegistry::load(&self.client, &self.screenpipe_dir, options).await?;
+        Ok(registry.search(query).into_iter().cloned().collect())
+    }
+
+    /// Deno, which runs deno pipes, `None` when it isn't installed.
+    pub fn find_deno(&self) -> Option<PathBuf> {
+        deno_path()
+    }
+}
@@ -0,0 +1,441 @@
+//! Checks of `pipe.json` against the manifest schema, served on
+//! `GET /pipes/manifest-schema` for editors.
+//!
+//! The manifest is read leniently where it is used, so a misspelled key or a value of
+//! the wrong type would otherwise be ignored without a word. Pipes are checked when
+//! they are downloaded and before every run: values of the wrong type fail the pipe,
+//! unknown keys are warnings with the nearest known key, errors in strict mode.
+//!
+//! The checks follow the schema itself, for the keywords it uses: `type`, `enum`,
+//! `required`, `properties`, `additionalProperties`, `items`, `minimum`, `maximum`,
+//! `minLength` and `format: cron`. A download also needs the pipe to have a name and
+//! its version, if any, to be semver, see [`validate_pipe_manifest`].
+
+use crate::pipe_config::{self, load_config, parse_config, ConfigError};
+use crate::pipes::PipeRuntime;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+/// Bumped, along with the `$id` of the schema, when a manifest valid before may not be.
+pub const MANIFEST_SCHEMA_VERSION: u32 = 1;
+
+const MANIFEST_SCHEMA: &str = include_str!("pipe_manifest.schema.json");
+
+/// Furthest an unknown key may be from a known one for it to be suggested.
+const MAX_SUGGESTION_DISTANCE: usize = 2;
+
+pub fn manifest_schema() -> &'static Value {
+    static SCHEMA: OnceLock<Value> = OnceLock::new();
+    SCHEMA.get_or_init(|| {
+        serde_json::from_str(MANIFEST_SCHEMA).expect("the manifest schema is valid json")
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    /// Logged, the pipe still runs
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ManifestIssue {
+    /// Json path of the value, e.g. `$.crons[0].schedule`
+    pub path: String,
+    pub message: String,
+    pub severity: Severity,
+    /// Known key an unknown one is likely a typo of
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suggestion: Option<String>,
+}
+
+impl ManifestIssue {
+    fn error(path: &str, message: String) -> Self {
+        Self {
+            path: path.to_string(),
+            message,
+            severity: Severity::Error,
+            suggestion: None,
+        }
+    }
+
+    fn warning(path: &str, message: String) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::error(path, message)
+        }
+    }
+}
+
+impl fmt::Display for ManifestIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)?;
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, ", did you mean '{}'?", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+/// Everything wrong with `manifest`. In `strict` mode warnings are errors too.
+pub fn validate_manifest(manifest: &Value, strict: bool) -> Vec<ManifestIssue> {
+    let mut issues = Vec::new();
+    check(manifest, manifest_schema(), "$", &mut issues);
+
+    let is_nextjs = manifest.get("is_nextjs").and_then(Value::as_bool) == Some(true);
+    if manifest.get("crons").is_some_and(Value::is_array) && !is_nextjs {
+        issues.push(ManifestIssue::warning(
+            "$.crons",
+            "crons only run for next.js pipes, this pipe isn't one".to_string(),
+        ));
+    }
+
+    if strict {
+        for issue in &mut issues {
+            issue.severity = Severity::Error;
+        }
+    }
+    issues
+}
+
+/// Parses and validates the content of a `pipe.json`.
+pub fn validate_manifest_str(content: &str, strict: bool) -> Vec<ManifestIssue> {
+    let parsed = parse_config(
+        content.as_bytes(),
+        Path::new("pipe.json"),
+        pipe_config::lenient(),
+    );
+    match parsed {
+        Ok(manifest) => validate_manifest(&manifest, strict),
+        Err(e) => vec![ManifestIssue::error("$", e.to_string())],
+    }
+}
+
+/// Loads the `pipe.json` at `path` like every other reader of it does, then validates
+/// it. A file too large or that doesn't parse is an issue, failing to read it an error.
+pub async fn validate_manifest_file(
+    path: &Path,
+    strict: bool,
+) -> Result<Vec<ManifestIssue>, ConfigError> {
+    match load_config(path).await {
+        Ok(manifest) => Ok(validate_manifest(&manifest, strict)),
+        Err(e @ ConfigError::Io { .. }) => Err(e),
+        Err(e) => Ok(vec![ManifestIssue::error("$", e.to_string())]),
+    }
+}
+
+/// A pipe's manifest once checked, its pipe.json with `name`, `version` and
+/// `description` taken from its package.json when pipe.json leaves them out, as most
+/// pipes do.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PipeManifest {
+    pub name: String,
+    /// Semver, e.g. `1.2.0`
+    pub version: Option<String>,
+    pub author: Option<String>,
+    pub description: Option<String>,
+    pub enabled: bool,
+    pub is_nextjs: bool,
+    pub runtime: Option<PipeRuntime>,
+    pub port: Option<u16>,
+    pub permissions: Vec<String>,
+    /// `None` when the pipe declares none
+    pub hosts: Option<Vec<String>>,
+    /// Paths below the pipe root made executable when it is installed
+    pub executables: Vec<String>,
+    pub events: Vec<String>,
+    pub crons: Vec<ManifestCron>,
+    pub fields: Vec<ManifestField>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestCron {
+    pub path: String,
+    pub schedule: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestField {
+    pub name: String,
+    #[serde(default, rename = "type")]
+    pub kind: Option<String>,
+    #[serde(default)]
+    pub default: Option<Value>,
+    #[serde(default)]
+    pub value: Option<Value>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub optional: bool,
+}
+
+/// Reads and checks the manifest of the pipe in `pipe_dir`, as a download does before
+/// the pipe replaces an installed copy. Fails with every schema error of its pipe.json,
+/// when it has neither a pipe.json nor a package.json, when neither gives it a name, or
+/// when its version isn't semver. Schema warnings are left to [`validate_manifest`].
+pub async fn validate_pipe_manifest(pipe_dir: &Path) -> anyhow::Result<PipeManifest> {
+    let pipe_json_path = pipe_dir.join("pipe.json");
+    let package_json_path = pipe_dir.join("package.json");
+    let pipe_json = read_manifest_file(&pipe_json_path).await?;
+    let package_json = read_manifest_file(&package_json_path).await?;
+    if pipe_json.is_none() && package_json.is_none() {
+        anyhow::bail!(
+            "{}: pipe has no pipe.json or package.json",
+            pipe_dir.display()
+        );
+    }
+
+    let mut manifest = match &pipe_json {
+        Some(pipe_json) => {
+            let errors: Vec<String> = validate_manifest(pipe_json, false)
+                .iter()
+                .filter(|issue| issue.severity == Severity::Error)
+                .map(ToString::to_string)
+                .collect();
+            if !errors.is_empty() {
+                anyhow::bail!("{}: {}", pipe_json_path.display(), errors.join("; "));
+            }
+            PipeManifest::deserialize(pipe_json)
+                .map_err(|e| anyhow::anyhow!("{}: {}", pipe_json_path.display(), e))?
+        }
+        None => PipeManifest::default(),
+    };
+
+    if let Some(package_json) = &package_json {
+        let package_field = |key: &str| match package_json.get(key) {
+            None | Some(Value::Null) => Ok(None),
+            Some(Value::String(value)) => Ok(Some(value.clone())),
+            Some(other) => Err(anyhow::anyhow!(
+                "{}: $.{}: expected string, found {}",
+                package_json_path.display(),
+                key,
+                type_name(other)
+            )),
+        };
+        if manifest.name.is_empty() {
+            manifest.name = package_field("name")?.unwrap_or_default();
+        }
+        if manifest.version.is_none() {
+            manifest.version = package_field("version")?;
+        }
+        if manifest.description.is_none() {
+            manifest.description = package_field("description")?;
+        }
+    }
+
+    if manifest.name.trim().is_empty() {
+        anyhow::bail!(
+            "{}: pipe has no name, set 'name' in its pipe.json or package.json",
+            pipe_dir.display()
+        );
+    }
+    if let Some(version) = &manifest.version {
+        if let Err(e) = semver::Version::parse(version) {
+            anyhow::bail!(
+                "{}: version '{}' isn't semver, e.g. 1.0.0: {}",
+                pipe_dir.display(),
+                version,
+                e
+            );
+        }
+    }
+    Ok(manifest)
+}
+
+/// A manifest file parsed, `None` when the pipe has none.
+async fn read_manifest_file(path: &Path) -> anyhow::Result<Option<Value>> {
+    match load_config(path).await {
+        Ok(config) => Ok(Some(config)),
+        Err(e) if e.is_not_found() => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn check(value: &Value, schema: &Value, path: &str, issues: &mut Vec<ManifestIssue>) {
+    if let Some(expected) = schema.get("type") {
+        let types: Vec<&str> = match expected {
+            Value::Array(types) => types.iter().filter_map(Value::as_str).collect(),
+            expected => expected.as_str().into_iter().collect(),
+        };
+        if !types.iter().any(|t| has_type(value, t)) {
+            issues.push(ManifestIssue::error(
+                path,
+                format!(
+                    "expected {}, found {}",
+                    types.join(" or "),
+                    type_name(value)
+                ),
+            ));
+            return;
+        }
+    }
+
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+        if !allowed.contains(value) {
+            let allowed: Vec<String> = allowed.iter().map(Value::to_string).collect();
+            issues.push(ManifestIssue::error(
+                path,
+                format!("expected one of {}, found {}", allowed.join(", "), value),
+            ));
+        }
+    }
+
+    match value {
+        Value::Number(n) => {
+            let n = n.as_f64().unwrap_or_default();
+            let minimum = schema.get("minimum").and_then(Value::as_f64);
+            let maximum = schema.get("maximum").and_then(Value::as_f64);
+            if minimum.is_some_and(|min| n < min) || maximum.is_some_and(|max| n > max) {
+                issues.push(ManifestIssue::error(
+                    path,
+                    format!(
+                        "expected a number from {} to {}, found {}",
+                        minimum.map_or("any".to_string(), |min| min.to_string()),
+                        maximum.map_or("any".to_string(), |max| max.to_string()),
+                        n
+                    ),
+                ));
+            }
+        }
+        Value::String(s) => {
+            let min_length = schema.get("minLength").and_then(Value::as_u64);
+            if min_length.is_some_and(|min| (s.chars().count() as u64) < min) {
+                issues.push(ManifestIssue::error(
+                    path,
+                    "expected a non-empty string".to_string(),
+                ));
+            }
+            if schema.get("format").and_then(Value::as_str) == Some("cron") {
+                if let Err(e) = cron::Schedule::from_str(s) {
+                    issues.push(ManifestIssue::error(
+                        path,
+                        format!(
+                            "expected a cron expression with seconds, e.g. '0 */5 * * * *': {}",
+                            e
+                        ),
+                    ));
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    check(item, item_schema, &format!("{}[{}]", path, i), issues);
+                }
+            }
+        }
+        Value::Object(object) => {
+            let properties = schema.get("properties").and_then(Value::as_object);
+            for key in schema
+                .get("required")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str)
+            {
+                if !object.contains_key(key) {
+                    let expected = properties
+                        .and_then(|p| p.get(key))
+                        .and_then(|p| p.get("type"))
+                        .and_then(Value::as_str)
+                        .unwrap_or("value");
+                    issues.push(ManifestIssue::error(
+                        path,
+                        format!("missing required key '{}', expected {}", key, expected),
+                    ));
+                }
+            }
+            let closed = schema.get("additionalProperties") == Some(&Value::Bool(false));
+            for (key, value) in object {
+                let key_path = child_path(path, key);
+                match properties.and_then(|p| p.get(key)) {
+                    Some(property) => check(value, property, &key_path, issues),
+                    None if closed => {
+                        let known = properties.into_iter().flat_map(|p| p.keys());
+                        issues.push(ManifestIssue {
+                            suggestion: nearest_key(key, known),
+                            ..ManifestIssue::warning(&key_path, "unknown key".to_string())
+                        });
+                    }
+                    None => {}
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn has_type(value: &Value, expected: &str) -> bool {
+    match expected {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        _ => true,
+    }
+}
+
+pub(crate) fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_f64() => "number",
+        Value::Number(_) => "integer",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn child_path(path: &str, key: &str) -> String {
+    let plain = !key.is_empty()
+        && !key.starts_with(|c: char| c.is_ascii_digit())
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if plain {
+        format!("{}.{}", path, key)
+    } else {
+        format!("{}[{}]", path, Value::String(key.to_string()))
+    }
+}
+
+/// The known key `key` is likely a typo of: the same but for case, `-` or `_`, or
+/// within a few edits of it.
+fn nearest_key<'a>(key: &str, known: impl Iterator<Item = &'a String>) -> Option<String> {
+    let normalize = |k: &str| k.to_lowercase().replace(['-', '_'], "");
+    let normalized = normalize(key);
+    known
+        .filter(|k| !k.starts_with('$'))
+        .map(|k| {
+            let distance = edit_distance(&normalized, &normalize(k));
+            (distance, k)
+        })
+        .filter(|(distance, k)| {
+            *distance <= MAX_SUGGESTION_DISTANCE && *distance < k.chars().count()
+        })
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, k)| k.clone())
+}
+
+/// Levenshtein distance, in chars.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
@@ -0,0 +1,57 @@
+//! Where each pipe [`download_pipe`] installs is from, at which commit or version, when
+//! and by which screenpipe, as the [`PIPE_LOCK_FILE`] it writes into the pipe folder
+//! records it. Each download of the pipe writes it anew.
+//!
+//! [`download_pipe`]: crate::download_pipe
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::pipe_lock::{PipeLock, PIPE_LOCK_FILE};
+
+/// What a pipe was installed from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PipeSourceKind {
+    Github,
+    Gitlab,
+    Bitbucket,
+    /// A repo cloned with git
+    Git,
+    Npm,
+    /// A `.zip` or `.tar.gz` url or file
+    Archive,
+    /// A local folder
+    Local,
+}
+
+/// What a pipe's [`PIPE_LOCK_FILE`] says about where it is from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PipeMetadata {
+    /// Url or path the files are from, the source the registry lists for a pipe installed
+    /// by name
+    pub source: String,
+    /// Commit of a github, gitlab, bitbucket or git pipe, version of an npm one. `None`
+    /// for an archive or a local folder
+    pub resolved_ref: Option<String>,
+    pub installed_at: DateTime<Utc>,
+    /// Version of screenpipe-core that installed it
+    pub installer_version: String,
+    pub kind: PipeSourceKind,
+}
+
+/// The metadata of the pipe in `pipe_dir`. Fails for a pipe installed before it was
+/// recorded, and for a linked one.
+pub fn read_pipe_metadata(pipe_dir: &Path) -> Result<PipeMetadata> {
+    let lock = PipeLock::load(pipe_dir)?
+        .ok_or_else(|| anyhow::anyhow!("{:?} has no {}", pipe_dir, PIPE_LOCK_FILE))?;
+    lock.metadata().ok_or_else(|| {
+        anyhow::anyhow!(
+            "the {} of {:?} doesn't say where the pipe is from, download it again",
+            PIPE_LOCK_FILE,
+            pipe_dir
+        )
+    })
+}
@@ -0,0 +1,240 @@
+//! Pipes published to npm: `npm:<package>[@<version>]`, e.g. `npm:@scope/my-pipe@1.2.3`.
+//! The version may also be a range, `npm:my-pipe@^1.2`, which picks the highest version
+//! published in it, or a dist-tag such as `next`. Without one the `latest` tag is used.
+//!
+//! The version is resolved with the registry's json api and its tarball checked against
+//! the `dist.integrity` the registry lists before it is extracted, without the `package`
+//! folder npm wraps it in. A package is installed under its name with the scope folded
+//! in, `@scope/my-pipe` as `scope-my-pipe`.
+
+use anyhow::Result;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use reqwest::{Client, StatusCode};
+use serde::Deserialize;
+use sha2::{Digest, Sha256, Sha512};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use tracing::debug;
+
+use crate::pipe_archive::{extract_archive, fetch_archive, ArchiveKind, MAX_ARCHIVE_SIZE};
+use crate::pipes::sanitize_pipe_name;
+
+/// Prefix of a pipe source on npm.
+pub const NPM_PREFIX: &str = "npm:";
+
+pub const NPM_REGISTRY: &str = "https://registry.npmjs.org";
+
+/// Environment variable with a registry to use instead of [`NPM_REGISTRY`].
+pub const NPM_REGISTRY_ENV: &str = "SCREENPIPE_NPM_REGISTRY";
+
+/// A pipe source on npm.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NpmSource {
+    /// Name of the package, its scope included, e.g. `@scope/my-pipe`
+    pub package: String,
+    /// A version, a range or a dist-tag, `None` for `latest`
+    pub version: Option<String>,
+}
+
+/// A published version of a package.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NpmVersion {
+    pub version: String,
+    pub tarball: String,
+    /// Subresource integrity of the tarball, e.g. `sha512-<base64>`
+    pub integrity: Option<String>,
+}
+
+/// The abbreviated metadata of a package.
+#[derive(Debug, Deserialize)]
+struct NpmPackument {
+    #[serde(rename = "dist-tags", default)]
+    dist_tags: HashMap<String, String>,
+    #[serde(default)]
+    versions: HashMap<String, NpmManifest>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NpmManifest {
+    dist: NpmDist,
+}
+
+#[derive(Debug, Deserialize)]
+struct NpmDist {
+    tarball: String,
+    integrity: Option<String>,
+}
+
+impl NpmSource {
+    /// `None` for sources that don't start with [`NPM_PREFIX`] or name no package.
+    pub fn parse(source: &str) -> Option<Self> {
+        let spec = source.strip_prefix(NPM_PREFIX)?.trim();
+        // The `@` of a scope isn't the one before the version
+        let (package, version) = match spec.char_indices().skip(1).find(|(_, c)| *c == '@') {
+            Some((i, _)) => (&spec[..i], Some(spec[i + 1..].trim())),
+            None => (spec, None),
+        };
+        if !is_package_name(package) {
+            return None;
+        }
+        Some(NpmSource {
+            package: package.to_string(),
+            version: version.filter(|v| !v.is_empty()).map(str::to_string),
+        })
+    }
+
+    /// Id the pipe is installed under.
+    pub fn pipe_id(&self) -> String {
+        sanitize_pipe_name(self.package.trim_start_matches('@'))
+    }
+
+    /// The version of the package the source is at, on `registry`.
+    pub async fn resolve(&self, client: &Client, registry: &str) -> Result<NpmVersion> {
+        // A scoped name is one segment, its `/` encoded
+        let url = format!(
+            "{}/{}",
+            registry.trim_end_matches('/'),
+            self.package.replacen('/', "%2f", 1)
+        );
+        let response = client
+            .get(&url)
+            .header("User-Agent", "screenpipe")
+            .header("Accept", "application/vnd.npm.install-v1+json")
+            .send()
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to fetch {} from npm: {}", self.package, e))?;
+        match response.status() {
+            StatusCode::NOT_FOUND => anyhow::bail!("npm package {} not found", self.package),
+            status if !status.is_success() => anyhow::bail!(
+                "failed to fetch {} from npm: status {}",
+                self.package,
+                status
+            ),
+            _ => {}
+        }
+        let mut packument: NpmPackument = response.json().await?;
+
+        let version = self.pick_version(&packument)?;
+        let manifest = packument
+            .versions
+            .remove(&version)
+            .ok_or_else(|| anyhow::anyhow!("{}@{} isn't published", self.package, version))?;
+        Ok(NpmVersion {
+            version,
+            tarball: manifest.dist.tarball,
+            integrity: manifest.dist.integrity,
+        })
+    }
+
+    fn pick_version(&self, packument: &NpmPackument) -> Result<String> {
+        let spec = self.version.as_deref().unwrap_or("latest");
+        if let Some(version) = packument.dist_tags.get(spec) {
+            return Ok(version.clone());
+        }
+        if packument.versions.contains_key(spec) {
+            return Ok(spec.to_string());
+        }
+        // npm separates the comparators of a range with spaces, semver with commas
+        let range = semver::VersionReq::parse(spec)
+            .or_else(|_| {
+                semver::VersionReq::parse(&spec.split_whitespace().collect::<Vec<_>>().join(", "))
+            })
+            .map_err(|_| {
+                anyhow::anyhow!("{} isn't a version, range or tag of {}", spec, self.package)
+            })?;
+        packument
+            .versions
+            .keys()
+            .filter_map(|v| semver::Version::parse(v).ok())
+            .filter(|v| range.matches(v))
+            .max()
+            .map(|v| v.to_string())
+            .ok_or_else(|| anyhow::anyhow!("no version of {} matches {}", self.package, spec))
+    }
+
+    /// Downloads the package from `registry` and extracts it into `dest_dir`.
+    pub async fn download(
+        &self,
+        client: &Client,
+        registry: &str,
+        dest_dir: &Path,
+    ) -> Result<NpmVersion> {
+        let version = self.resolve(client, registry).await?;
+        debug!("downloading {}@{}", self.package, version.version);
+        let tarball = fetch_archive(client, &version.tarball).await?;
+
+        let checked = version.clone();
+        let package = self.package.clone();
+        let dest_dir = dest_dir.to_path_buf();
+        tokio::task::spawn_blocking(move || {
+            verify_integrity(tarball.path(), checked.integrity.as_deref()).map_err(|e| {
+                anyhow::anyhow!("tarball of {}@{}: {}", package, checked.version, e)
+            })?;
+            extract_archive(
+                ArchiveKind::TarGz,
+                tarball.path(),
+                &dest_dir,
+                MAX_ARCHIVE_SIZE,
+            )
+        })
+        .await??;
+        Ok(version)
+    }
+}
+
+/// The registry set in [`NPM_REGISTRY_ENV`], or [`NPM_REGISTRY`].
+pub fn npm_registry() -> String {
+    std::env::var(NPM_REGISTRY_ENV)
+        .ok()
+        .filter(|registry| !registry.trim().is_empty())
+        .unwrap_or_else(|| NPM_REGISTRY.to_string())
+}
+
+fn is_package_name(name: &str) -> bool {
+    let (scope, name) = match name.strip_prefix('@') {
+        Some(scoped) => match scoped.split_once('/') {
+            Some((scope, name)) => (Some(scope), name),
+            None => return false,
+        },
+        None => (None, name),
+    };
+    let valid = |part: &str| {
+        !part.is_empty()
+            && !part.starts_with(['.', '_'])
+            && part
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_' | '~'))
+    };
+    scope.is_none_or(valid) && valid(name)
+}
+
+/// Checks the file at `path` against the strongest sha256 or sha512 hash of the
+/// `integrity`, failing when it has neither.
+fn verify_integrity(path: &Path, integrity: Option<&str>) -> Result<()> {
+    let hashes = integrity.unwrap_or_default().split_whitespace();
+    let strongest = hashes
+        .filter_map(|hash| hash.split_once('-'))
+        .filter(|(alg, _)| matches!(*alg, "sha512" | "sha256"))
+        .max_by_key(|(alg, _)| *alg == "sha512");
+    let Some((alg, expected)) = strongest else {
+        anyhow::bail!("it has no sha512 or sha256 integrity to check it with");
+    };
+    let mut file = File::open(path)?;
+    let actual = if alg == "sha512" {
+        let mut hasher = Sha512::new();
+        io::copy(&mut file, &mut hasher)?;
+        STANDARD.encode(hasher.finalize())
+    } else {
+        let mut hasher = Sha256::new();
+        io::copy(&mut file, &mut hasher)?;
+        STANDARD.encode(hasher.finalize())
+    };
+    // Some registries leave out the padding
+    if actual.trim_end_matches('=') != expected.trim_end_matches('=') {
+        anyhow::bail!("it doesn't match its {} integrity", alg);
+    }
+    Ok(())
+}
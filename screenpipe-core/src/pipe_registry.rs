@@ -0,0 +1,279 @@
+//! The index of pipes that can be installed by name rather than by url: a json file at
+//! [`DEFAULT_REGISTRY_URL`] listing each pipe's name, description, author, version and
+//! source, the url [`crate::download_pipe`] takes.
+//!
+//! The index is cached in `cache/registry.json` of the screenpipe dir and fetched again
+//! once older than [`RegistryOptions::ttl`]. When that fetch fails the stale cache is
+//! used, so search and installing by name keep working offline.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::{debug, warn};
+
+use crate::pipe_manager::PipeManager;
+
+pub const DEFAULT_REGISTRY_URL: &str = "https://screenpipe.dev/pipes/index.json";
+
+/// Environment variable with the url of an index used in place of
+/// [`DEFAULT_REGISTRY_URL`].
+pub const REGISTRY_URL_ENV: &str = "SCREENPIPE_PIPE_REGISTRY";
+
+/// How long a cached index is used before it is fetched again.
+pub const DEFAULT_REGISTRY_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Name of the cache in the cache dir.
+pub const REGISTRY_CACHE_FILE: &str = "registry.json";
+
+/// The cache in the pipes dir of earlier versions, moved to the cache dir once the index
+/// is loaded.
+const LEGACY_REGISTRY_CACHE_FILE: &str = "registry_cache.json";
+
+/// A pipe listed in the index.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PipeEntry {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub author: Option<String>,
+    #[serde(default)]
+    pub version: Option<String>,
+    /// Url the pipe is downloaded from
+    pub source: String,
+}
+
+pub type RegistryEntry = PipeEntry;
+
+/// The index, `{"pipes": [...]}`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PipeRegistry {
+    pub pipes: Vec<PipeEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegistryOptions {
+    /// Where the index is fetched from, [`REGISTRY_URL_ENV`] or [`DEFAULT_REGISTRY_URL`]
+    /// by default
+    pub url: String,
+    /// Age after which the cached index is fetched again
+    pub ttl: Duration,
+}
+
+impl Default for RegistryOptions {
+    fn default() -> Self {
+        Self {
+            url: std::env::var(REGISTRY_URL_ENV)
+                .ok()
+                .filter(|url| !url.trim().is_empty())
+                .unwrap_or_else(|| DEFAULT_REGISTRY_URL.to_string()),
+            ttl: DEFAULT_REGISTRY_TTL,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct RegistryCache {
+    url: String,
+    fetched_at: DateTime<Utc>,
+    #[serde(flatten)]
+    registry: PipeRegistry,
+}
+
+impl PipeRegistry {
+    /// Fetches the index at [`DEFAULT_REGISTRY_URL`].
+    pub async fn fetch(client: &Client) -> Result<PipeRegistry> {
+        Self::fetch_from(client, DEFAULT_REGISTRY_URL).await
+    }
+
+    pub async fn fetch_from(client: &Client, url: &str) -> Result<PipeRegistry> {
+        let fetch = async {
+            client
+                .get(url)
+                .header("User-Agent", "screenpipe")
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<PipeRegistry>()
+                .await
+        };
+        fetch
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to fetch the pipe registry: {}", e))
+    }
+
+    /// The index of `options.url`, from the cache in `screenpipe_dir` while it is younger
+    /// than `options.ttl`.
+    pub async fn load(
+        client: &Client,
+        screenpipe_dir: &Path,
+        options: &RegistryOptions,
+    ) -> Result<PipeRegistry> {
+        let cache_path = registry_cache_path(screenpipe_dir);
+        migrate_legacy_cache(screenpipe_dir, &cache_path).await;
+        let cache = read_cache(&cache_path)
+            .await
+            .filter(|cache| cache.url == options.url);
+        if let Some(cache) = &cache {
+            let age = (Utc::now() - cache.fetched_at).to_std().unwrap_or_default();
+            if age < options.ttl {
+                debug!("using the pipe registry cached {}s ago", age.as_secs());
+                return Ok(cache.registry.clone());
+            }
+        }
+
+        match Self::fetch_from(client, &options.url).await {
+            Ok(registry) => {
+                let cache = RegistryCache {
+                    url: options.url.clone(),
+                    fetched_at: Utc::now(),
+                    registry,
+                };
+                if let Err(e) = write_cache(&cache_path, &cache).await {
+                    warn!("failed to cache the pipe registry: {}", e);
+                }
+                Ok(cache.registry)
+            }
+            Err(e) => match cache {
+                Some(cache) => {
+                    warn!("{}, using the one cached at {}", e, cache.fetched_at);
+                    Ok(cache.registry)
+                }
+                None => Err(e),
+            },
+        }
+    }
+
+    /// The pipe listed as `name`, in any case.
+    pub fn find(&self, name: &str) -> Option<&PipeEntry> {
+        self.pipes
+            .iter()
+            .find(|entry| entry.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Pipes whose name or description has every word of `query`, best matches first: a
+    /// name starting with a word, then a name having it, then a description. A word also
+    /// matches a name having its letters in order, e.g. `obsd` in `obsidian`. An empty
+    /// query lists every pipe.
+    pub fn search(&self, query: &str) -> Vec<&PipeEntry> {
+        let words: Vec<String> = normalize(query)
+            .split_whitespace()
+            .map(str::to_string)
+            .collect();
+        let mut matches: Vec<(u32, &PipeEntry)> = self
+            .pipes
+            .iter()
+            .filter_map(|entry| Some((score(entry, &words)?, entry)))
+            .collect();
+        matches.sort_by(|(a, a_entry), (b, b_entry)| {
+            b.cmp(a).then_with(|| a_entry.name.cmp(&b_entry.name))
+        });
+        matches.into_iter().map(|(_, entry)| entry).collect()
+    }
+}
+
+/// [`PipeRegistry::search`] of the index of [`RegistryOptions::default`], fetched with
+/// `client`.
+#[deprecated(note = "use PipeManager::search_registry")]
+pub async fn search_registry(
+    client: &Client,
+    query: &str,
+    screenpipe_dir: &Path,
+) -> Result<Vec<RegistryEntry>> {
+    PipeManager::with_client(screenpipe_dir.to_path_buf(), client.clone())
+        .search_registry(query)
+        .await
+}
+
+/// Whether `source` can be the name of a pipe in the index: one word of letters, digits,
+/// `-`, `_` and `.`, which isn't a url.
+pub fn is_registry_name(source: &str) -> bool {
+    !source.is_empty()
+        && !source.starts_with('.')
+        && source
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+pub fn registry_cache_path(screenpipe_dir: &Path) -> PathBuf {
+    screenpipe_dir.join("cache").join(REGISTRY_CACHE_FILE)
+}
+
+/// Moves `pipes/registry_cache.json` to `cache_path`, or removes it when there is a cache
+/// there already. Failing leaves it, the index is fetched again.
+async fn migrate_legacy_cache(screenpipe_dir: &Path, cache_path: &Path) {
+    let legacy = screenpipe_dir
+        .join("pipes")
+        .join(LEGACY_REGISTRY_CACHE_FILE);
+    if !tokio::fs::try_exists(&legacy).await.unwrap_or(false) {
+        return;
+    }
+    let migrated = async {
+        if tokio::fs::try_exists(cache_path).await? {
+            return tokio::fs::remove_file(&legacy).await;
+        }
+        if let Some(parent) = cache_path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::rename(&legacy, cache_path).await
+    };
+    match migrated.await {
+        Ok(()) => debug!(
+            "moved the pipe registry cache {:?} to {:?}",
+            legacy, cache_path
+        ),
+        Err(e) => warn!("failed to move the pipe registry cache {:?}: {}", legacy, e),
+    }
+}
+
+async fn read_cache(path: &Path) -> Option<RegistryCache> {
+    let content = tokio::fs::read(path).await.ok()?;
+    match serde_json::from_slice(&content) {
+        Ok(cache) => Some(cache),
+        Err(e) => {
+            warn!("ignoring the pipe registry cache {:?}: {}", path, e);
+            None
+        }
+    }
+}
+
+async fn write_cache(path: &Path, cache: &RegistryCache) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    tokio::fs::write(path, serde_json::to_vec_pretty(cache)?).await?;
+    Ok(())
+}
+
+/// Lowercase, `-` and `_` as spaces, so `time logs` matches `pipe-obsidian-time-logs`.
+fn normalize(text: &str) -> String {
+    text.to_lowercase().replace(['-', '_'], " ")
+}
+
+fn is_subsequence(needle: &str, haystack: &str) -> bool {
+    let mut haystack = haystack.chars();
+    needle.chars().all(|c| haystack.any(|h| h == c))
+}
+
+fn score(entry: &PipeEntry, words: &[String]) -> Option<u32> {
+    let name = normalize(&entry.name);
+    let description = normalize(&entry.description);
+    let parts: Vec<&str> = name.split_whitespace().collect();
+    words.iter().try_fold(0, |total, word| {
+        let score = if parts.iter().any(|part| part.starts_with(word.as_str())) {
+            3
+        } else if name.contains(word.as_str()) {
+            2
+        } else if description.contains(word.as_str()) {
+            1
+        } else if is_subsequence(word, &name) {
+            0
+        } else {
+            return None;
+        };
+        Some(total + score)
+    })
+}
@@ -0,0 +1,211 @@
+// Entrypoint of `screenpipe pipe repl`, run by bun with the environment a pipe gets.
+// Evaluates what is typed with `sp` (api helpers) and `sdk` (@screenpipe/js, when the
+// pipe has it installed) in scope.
+import { createInterface } from "node:readline";
+import { createRequire } from "node:module";
+import { inspect } from "node:util";
+
+const serverUrl = process.env.SCREENPIPE_SERVER_URL || "http://localhost:3030";
+const apiBase = `${serverUrl}/v1`;
+const notificationUrl = "http://localhost:11435";
+// Unset when the pipe runs without a permission set
+const permissions =
+  process.env.SCREENPIPE_PERMISSIONS === undefined
+    ? null
+    : process.env.SCREENPIPE_PERMISSIONS.split(",").filter(Boolean);
+
+async function request(method, path, body) {
+  const response = await fetch(`${apiBase}${path}`, {
+    method,
+    headers: body === undefined ? {} : { "Content-Type": "application/json" },
+    body: body === undefined ? undefined : JSON.stringify(body),
+  });
+  const text = await response.text();
+  let data;
+  try {
+    data = JSON.parse(text);
+  } catch {
+    data = text;
+  }
+  if (!response.ok) {
+    const error = new Error(`${method} ${path} failed with status ${response.status}`);
+    error.status = response.status;
+    error.problem = data;
+    throw error;
+  }
+  return data;
+}
+
+// camelCase keys become the snake_case query parameters the api reads
+function query(params = {}) {
+  const search = new URLSearchParams();
+  for (const [key, value] of Object.entries(params)) {
+    if (value === undefined || value === null || value === "") continue;
+    const name = key.replace(/[A-Z]/g, (c) => `_${c.toLowerCase()}`);
+    if (Array.isArray(value)) search.append(name, value.join(","));
+    else if (value instanceof Date) search.append(name, value.toISOString());
+    else search.append(name, String(value));
+  }
+  const encoded = search.toString();
+  return encoded ? `?${encoded}` : "";
+}
+
+const sp = {
+  serverUrl,
+  permissions,
+  get: (path) => request("GET", path),
+  post: (path, body) => request("POST", path, body),
+  health: () => request("GET", "/health"),
+  /** `GET /search`, e.g. `sp.search({ q: "invoice", contentType: "ocr", limit: 5 })` */
+  search: (params) => request("GET", `/search${query(params)}`),
+  /** Everything captured between `start` (default an hour ago) and `end`, oldest first */
+  async timeline({ start, end = new Date(), limit = 100, ...params } = {}) {
+    const result = await sp.search({
+      contentType: "all",
+      startTime: start ?? new Date(Date.now() - 60 * 60 * 1000),
+      endTime: end,
+      limit,
+      ...params,
+    });
+    return result.data.sort((a, b) =>
+      String(a.content.timestamp).localeCompare(String(b.content.timestamp))
+    );
+  },
+  /** Desktop notification through the app, `{ title, body }` */
+  async notify(options) {
+    const response = await fetch(`${notificationUrl}/notify`, {
+      method: "POST",
+      headers: { "Content-Type": "application/json" },
+      body: JSON.stringify(typeof options === "string" ? { title: "screenpipe", body: options } : options),
+    });
+    return response.ok;
+  },
+  help() {
+    console.log(
+      [
+        "sp.search(params)         GET /search, camelCase params",
+        "sp.timeline({ start, end }) captured content in a time range, oldest first",
+        "sp.notify({ title, body })  desktop notification",
+        "sp.get(path), sp.post(path, body), sp.health()",
+        "sp.permissions            scopes granted to this session (null: no permission set)",
+        "sdk                       @screenpipe/js, when installed in the pipe",
+        "_                         result of the last line",
+        ".exit or ctrl+d           leave",
+      ].join("\n")
+    );
+  },
+};
+globalThis.sp = sp;
+
+try {
+  // Resolved from the pipe's directory, where its dependencies are installed
+  const require = createRequire(`${process.cwd()}/`);
+  globalThis.sdk = await import(require.resolve("@screenpipe/js"));
+} catch {
+  globalThis.sdk = undefined;
+}
+
+const AsyncFunction = (async () => {}).constructor;
+
+function compile(source) {
+  // Top level declarations outlive the line they are typed on
+  const hoisted = source
+    .replace(/^(\s*)(?:const|let|var)\s+([A-Za-z_$][\w$]*)\s*=/gm, "$1globalThis.$2 =")
+    .replace(
+      /^(\s*)((?:async\s+)?function\b\s*\*?\s*|class\s+)([A-Za-z_$][\w$]*)/gm,
+      "$1globalThis.$3 = $2$3"
+    );
+  try {
+    return new AsyncFunction(`return (${hoisted}\n);`);
+  } catch {
+    return new AsyncFunction(hoisted);
+  }
+}
+
+// Open brackets, strings or comments mean the input goes on on the next line
+function isIncomplete(source) {
+  let depth = 0;
+  let quote = null;
+  for (let i = 0; i < source.length; i++) {
+    const c = source[i];
+    if (quote) {
+      if (c === "\\") i++;
+      else if (c === quote) quote = null;
+      else if (c === "\n" && quote !== "`") quote = null;
+    } else if (c === "/" && source[i + 1] === "/") {
+      i = source.indexOf("\n", i);
+      if (i === -1) break;
+    } else if (c === "/" && source[i + 1] === "*") {
+      i = source.indexOf("*/", i + 2);
+      if (i === -1) return true;
+      i++;
+    } else if (c === '"' || c === "'" || c === "`") quote = c;
+    else if ("([{".includes(c)) depth++;
+    else if (")]}".includes(c)) depth--;
+  }
+  return depth > 0 || quote === "`";
+}
+
+const colors = Boolean(process.stdout.isTTY);
+const rl = createInterface({ input: process.stdin, output: process.stdout, prompt: "sp> " });
+let buffer = "";
+
+rl.on("SIGINT", () => {
+  if (buffer) {
+    buffer = "";
+    process.stdout.write("\n");
+  } else {
+    process.stdout.write("\n(type .exit or press ctrl+d to leave)\n");
+  }
+  rl.setPrompt("sp> ");
+  rl.prompt();
+});
+
+const id = process.env.PIPE_ID;
+console.log(`screenpipe pipe repl${id ? ` (${id})` : ""}, api at ${serverUrl}, sp.help() for helpers`);
+rl.prompt();
+
+for await (const line of rl) {
+  if (!buffer && line.trim() === ".exit") break;
+  buffer = buffer ? `${buffer}\n${line}` : line;
+  if (!buffer.trim()) {
+    buffer = "";
+    rl.prompt();
+    continue;
+  }
+
+  if (isIncomplete(buffer)) {
+    rl.setPrompt("... ");
+    rl.prompt();
+    continue;
+  }
+
+  let run;
+  try {
+    run = compile(buffer);
+  } catch (error) {
+    console.error(String(error));
+    buffer = "";
+    rl.setPrompt("sp> ");
+    rl.prompt();
+    continue;
+  }
+  buffer = "";
+  rl.setPrompt("sp> ");
+
+  try {
+    const value = await run();
+    if (value !== undefined) {
+      globalThis._ = value;
+      console.log(inspect(value, { colors, depth: 4 }));
+    }
+  } catch (error) {
+    console.error(error instanceof Error && error.problem !== undefined
+      ? `${error.message}\n${inspect(error.problem, { colors, depth: 4 })}`
+      : error);
+  }
+  rl.prompt();
+}
+
+rl.close();
+process.exit(0);
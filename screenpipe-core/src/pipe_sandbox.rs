@@ -0,0 +1,332 @@
+//! Seccomp filter for the process of a deno pipe, on linux. Deno's permission flags decide
+//! what the pipe's code may reach, the filter what the deno process may ask the kernel for:
+//! a syscall the policy doesn't list kills it with `SIGSYS`, so a hole in the runtime
+//! doesn't hand the pipe `ptrace`, `mount`, kernel modules or namespaces.
+//!
+//! The filter is compiled before the process is forked and installed between fork and
+//! exec, where nothing may allocate. Elsewhere than linux the policy is left unapplied.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+
+/// Syscalls a pipe's deno process may make.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SandboxPolicy {
+    /// What [`Self::Standard`] allows, without starting processes, serving sockets or
+    /// signalling other processes. Threads can still be started
+    Strict,
+    /// What deno needs to run a script: files, memory, threads, timers, signals, outgoing
+    /// and local sockets, and starting subprocesses for `Deno.Command`
+    Standard,
+    /// No filter
+    #[default]
+    None,
+}
+
+impl SandboxPolicy {
+    /// The one of `self` and `other` allowing less.
+    pub fn stricter(self, other: SandboxPolicy) -> SandboxPolicy {
+        match (self, other) {
+            (SandboxPolicy::Strict, _) | (_, SandboxPolicy::Strict) => SandboxPolicy::Strict,
+            (SandboxPolicy::Standard, _) | (_, SandboxPolicy::Standard) => SandboxPolicy::Standard,
+            _ => SandboxPolicy::None,
+        }
+    }
+}
+
+/// Has the process `command` starts run under `policy`, on linux.
+pub fn sandbox_command(command: &mut Command, policy: SandboxPolicy) -> Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        if policy == SandboxPolicy::None {
+            return Ok(());
+        }
+        let programs = linux::programs(policy)?;
+        // Only async-signal-safe calls between fork and exec, the filters are built
+        unsafe {
+            command.pre_exec(move || {
+                for program in &programs {
+                    if seccompiler::apply_filter(program).is_err() {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                }
+                Ok(())
+            });
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = command;
+        if policy != SandboxPolicy::None {
+            tracing::debug!("{:?} sandbox left unapplied, seccomp is linux only", policy);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::SandboxPolicy;
+    use anyhow::Result;
+    use seccompiler::{
+        BpfProgram, SeccompAction, SeccompCmpArgLen, SeccompCmpOp, SeccompCondition, SeccompFilter,
+        SeccompRule, TargetArch,
+    };
+    use std::collections::BTreeMap;
+
+    /// Allowed under both policies.
+    const COMMON: &[libc::c_long] = &[
+        // Files
+        libc::SYS_read,
+        libc::SYS_write,
+        libc::SYS_readv,
+        libc::SYS_writev,
+        libc::SYS_pread64,
+        libc::SYS_pwrite64,
+        libc::SYS_preadv,
+        libc::SYS_pwritev,
+        libc::SYS_openat,
+        libc::SYS_close,
+        libc::SYS_close_range,
+        libc::SYS_lseek,
+        libc::SYS_fstat,
+        libc::SYS_newfstatat,
+        libc::SYS_statx,
+        libc::SYS_statfs,
+        libc::SYS_fstatfs,
+        libc::SYS_getdents64,
+        libc::SYS_readlinkat,
+        libc::SYS_faccessat,
+        libc::SYS_faccessat2,
+        libc::SYS_fcntl,
+        libc::SYS_ioctl,
+        libc::SYS_dup,
+        libc::SYS_dup3,
+        libc::SYS_pipe2,
+        libc::SYS_mkdirat,
+        libc::SYS_unlinkat,
+        libc::SYS_renameat2,
+        libc::SYS_linkat,
+        libc::SYS_symlinkat,
+        libc::SYS_fchmod,
+        libc::SYS_fchmodat,
+        libc::SYS_fchown,
+        libc::SYS_fchownat,
+        libc::SYS_utimensat,
+        libc::SYS_ftruncate,
+        libc::SYS_fallocate,
+        libc::SYS_fsync,
+        libc::SYS_fdatasync,
+        libc::SYS_fadvise64,
+        libc::SYS_flock,
+        libc::SYS_getcwd,
+        libc::SYS_chdir,
+        libc::SYS_fchdir,
+        libc::SYS_umask,
+        libc::SYS_copy_file_range,
+        libc::SYS_sendfile,
+        libc::SYS_memfd_create,
+        libc::SYS_inotify_init1,
+        libc::SYS_inotify_add_watch,
+        libc::SYS_inotify_rm_watch,
+        // Memory
+        libc::SYS_mmap,
+        libc::SYS_munmap,
+        libc::SYS_mprotect,
+        libc::SYS_mremap,
+        libc::SYS_madvise,
+        libc::SYS_msync,
+        libc::SYS_mincore,
+        libc::SYS_mlock,
+        libc::SYS_munlock,
+        libc::SYS_brk,
+        libc::SYS_membarrier,
+        libc::SYS_pkey_alloc,
+        libc::SYS_pkey_free,
+        libc::SYS_pkey_mprotect,
+        // Threads and the process itself
+        libc::SYS_execve,
+        libc::SYS_exit,
+        libc::SYS_exit_group,
+        libc::SYS_futex,
+        libc::SYS_set_tid_address,
+        libc::SYS_set_robust_list,
+        libc::SYS_get_robust_list,
+        libc::SYS_rseq,
+        libc::SYS_sched_yield,
+        libc::SYS_sched_getaffinity,
+        libc::SYS_getcpu,
+        libc::SYS_sched_getparam,
+        libc::SYS_sched_getscheduler,
+        libc::SYS_getpriority,
+        libc::SYS_setpriority,
+        libc::SYS_prctl,
+        libc::SYS_getpid,
+        libc::SYS_getppid,
+        libc::SYS_gettid,
+        libc::SYS_getpgid,
+        libc::SYS_getsid,
+        libc::SYS_getuid,
+        libc::SYS_geteuid,
+        libc::SYS_getgid,
+        libc::SYS_getegid,
+        libc::SYS_getgroups,
+        libc::SYS_getresuid,
+        libc::SYS_getresgid,
+        libc::SYS_capget,
+        libc::SYS_uname,
+        libc::SYS_sysinfo,
+        libc::SYS_getrusage,
+        // The pipe's resource limits are set once the filter is in
+        libc::SYS_prlimit64,
+        libc::SYS_tgkill,
+        libc::SYS_tkill,
+        // Signals
+        libc::SYS_rt_sigaction,
+        libc::SYS_rt_sigprocmask,
+        libc::SYS_rt_sigreturn,
+        libc::SYS_rt_sigsuspend,
+        libc::SYS_rt_sigtimedwait,
+        libc::SYS_sigaltstack,
+        libc::SYS_restart_syscall,
+        // Time
+        libc::SYS_clock_gettime,
+        libc::SYS_clock_getres,
+        libc::SYS_clock_nanosleep,
+        libc::SYS_nanosleep,
+        libc::SYS_gettimeofday,
+        libc::SYS_timerfd_create,
+        libc::SYS_timerfd_settime,
+        libc::SYS_timerfd_gettime,
+        libc::SYS_getrandom,
+        // Event loop
+        libc::SYS_epoll_create1,
+        libc::SYS_epoll_ctl,
+        libc::SYS_epoll_pwait,
+        libc::SYS_epoll_pwait2,
+        libc::SYS_eventfd2,
+        libc::SYS_ppoll,
+        libc::SYS_pselect6,
+        // Outgoing sockets
+        libc::SYS_socket,
+        libc::SYS_socketpair,
+        libc::SYS_connect,
+        libc::SYS_getsockname,
+        libc::SYS_getpeername,
+        libc::SYS_setsockopt,
+        libc::SYS_getsockopt,
+        libc::SYS_sendto,
+        libc::SYS_recvfrom,
+        libc::SYS_sendmsg,
+        libc::SYS_recvmsg,
+        libc::SYS_sendmmsg,
+        libc::SYS_recvmmsg,
+        libc::SYS_shutdown,
+    ];
+
+    /// The legacy syscalls x86_64 still has, aarch64 only has their newer forms.
+    #[cfg(target_arch = "x86_64")]
+    const COMMON_X86_64: &[libc::c_long] = &[
+        libc::SYS_open,
+        libc::SYS_creat,
+        libc::SYS_stat,
+        libc::SYS_lstat,
+        libc::SYS_access,
+        libc::SYS_readlink,
+        libc::SYS_mkdir,
+        libc::SYS_rmdir,
+        libc::SYS_unlink,
+        libc::SYS_rename,
+        libc::SYS_renameat,
+        libc::SYS_link,
+        libc::SYS_symlink,
+        libc::SYS_chmod,
+        libc::SYS_chown,
+        libc::SYS_lchown,
+        libc::SYS_getdents,
+        libc::SYS_pipe,
+        libc::SYS_dup2,
+        libc::SYS_poll,
+        libc::SYS_select,
+        libc::SYS_epoll_create,
+        libc::SYS_epoll_wait,
+        libc::SYS_eventfd,
+        libc::SYS_inotify_init,
+        libc::SYS_arch_prctl,
+        libc::SYS_getpgrp,
+        libc::SYS_getrlimit,
+        libc::SYS_setrlimit,
+        libc::SYS_alarm,
+        libc::SYS_time,
+    ];
+    #[cfg(not(target_arch = "x86_64"))]
+    const COMMON_X86_64: &[libc::c_long] = &[];
+
+    /// Allowed under [`SandboxPolicy::Standard`] only.
+    const STANDARD: &[libc::c_long] = &[
+        libc::SYS_clone,
+        libc::SYS_clone3,
+        libc::SYS_wait4,
+        libc::SYS_waitid,
+        libc::SYS_kill,
+        libc::SYS_pidfd_open,
+        libc::SYS_pidfd_send_signal,
+        libc::SYS_setpgid,
+        libc::SYS_setsid,
+        libc::SYS_bind,
+        libc::SYS_listen,
+        libc::SYS_accept4,
+    ];
+
+    #[cfg(target_arch = "x86_64")]
+    const STANDARD_X86_64: &[libc::c_long] = &[libc::SYS_fork, libc::SYS_vfork, libc::SYS_accept];
+    #[cfg(not(target_arch = "x86_64"))]
+    const STANDARD_X86_64: &[libc::c_long] = &[];
+
+    /// The filters of `policy`, installed in order.
+    pub(super) fn programs(policy: SandboxPolicy) -> Result<Vec<BpfProgram>> {
+        let arch = TargetArch::try_from(std::env::consts::ARCH)?;
+        let mut allowed: BTreeMap<i64, Vec<SeccompRule>> = COMMON
+            .iter()
+            .chain(COMMON_X86_64)
+            .map(|&syscall| (syscall, Vec::new()))
+            .collect();
+        let mut programs = Vec::new();
+        if policy == SandboxPolicy::Strict {
+            // Threads, not processes
+            let thread = SeccompCondition::new(
+                0,
+                SeccompCmpArgLen::Qword,
+                SeccompCmpOp::MaskedEq(libc::CLONE_THREAD as u64),
+                libc::CLONE_THREAD as u64,
+            )?;
+            allowed.insert(libc::SYS_clone, vec![SeccompRule::new(vec![thread])?]);
+            // Its flags are behind a pointer a filter can't read, libc falls back to clone
+            // on ENOSYS. First, as installing the next filter is a syscall this one allows
+            let clone3 = SeccompFilter::new(
+                [(libc::SYS_clone3, Vec::new())].into_iter().collect(),
+                SeccompAction::Allow,
+                SeccompAction::Errno(libc::ENOSYS as u32),
+                arch,
+            )?;
+            programs.push(BpfProgram::try_from(clone3)?);
+        } else {
+            allowed.extend(
+                STANDARD
+                    .iter()
+                    .chain(STANDARD_X86_64)
+                    .map(|&syscall| (syscall, Vec::new())),
+            );
+        }
+        let filter = SeccompFilter::new(
+            allowed,
+            SeccompAction::KillProcess,
+            SeccompAction::Allow,
+            arch,
+        )?;
+        programs.push(BpfProgram::try_from(filter)?);
+        Ok(programs)
+    }
+}
@@ -0,0 +1,158 @@
+//! How long each run of a pipe took and how it ended. A run is appended to
+//! `stats.json` in the pipe's folder, one json object per line, and `latest.json` holds
+//! the last one. Both are kept when the pipe is updated.
+//!
+//! Runs are recorded by whoever waits for the pipe to exit: start a [`PipeRun`] right
+//! before [`crate::run_pipe`] and [`PipeRun::finish`] it with the status
+//! [`crate::wait_pipe`] returned, `None` when it timed out.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::process::ExitStatus;
+use std::time::{Instant, SystemTime};
+use tokio::io::AsyncWriteExt;
+use tracing::warn;
+
+use crate::pipe_manager::{PipeManager, RuntimeConfig};
+use crate::pipes::installed_pipe_dir;
+
+/// Run history of a pipe, one [`PipeStats`] per line.
+pub const PIPE_STATS_FILE: &str = "stats.json";
+
+/// The last [`PipeStats`] of a pipe.
+pub const PIPE_LATEST_STATS_FILE: &str = "latest.json";
+
+/// A run of a pipe.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PipeStats {
+    /// Time from its start until it exited
+    pub wall_time_ms: u64,
+    /// `None` when it was killed, by a signal or once its timeout passed
+    pub exit_code: Option<i32>,
+    /// Runs before this one since its stats were reset
+    pub restart_count: u32,
+    /// When the run started
+    pub last_run_at: SystemTime,
+}
+
+/// A run of a pipe being timed.
+#[derive(Debug)]
+pub struct PipeRun {
+    pipe: String,
+    started_at: SystemTime,
+    started: Instant,
+}
+
+impl PipeRun {
+    pub fn start(pipe: &str) -> Self {
+        PipeRun {
+            pipe: pipe.to_string(),
+            started_at: SystemTime::now(),
+            started: Instant::now(),
+        }
+    }
+
+    /// Records the run, `status` is `None` if the pipe was killed. Failing to write the
+    /// stats is logged, it doesn't fail the run.
+    pub async fn finish(self, status: Option<ExitStatus>, screenpipe_dir: &Path) {
+        let pipe_dir = screenpipe_dir.join("pipes").join(&self.pipe);
+        let restart_count = match latest_stats(&pipe_dir).await {
+            Some(latest) => latest.restart_count + 1,
+            None => 0,
+        };
+        let stats = PipeStats {
+            wall_time_ms: self.started.elapsed().as_millis() as u64,
+            exit_code: status.and_then(|status| status.code()),
+            restart_count,
+            last_run_at: self.started_at,
+        };
+        if let Err(e) = record_pipe_stats(&pipe_dir, &stats).await {
+            warn!("failed to record the run of pipe {}: {}", self.pipe, e);
+        }
+    }
+}
+
+async fn record_pipe_stats(pipe_dir: &Path, stats: &PipeStats) -> Result<()> {
+    let mut line = serde_json::to_string(stats)?;
+    line.push('\n');
+    let mut history = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(pipe_dir.join(PIPE_STATS_FILE))
+        .await?;
+    history.write_all(line.as_bytes()).await?;
+    tokio::fs::write(
+        pipe_dir.join(PIPE_LATEST_STATS_FILE),
+        serde_json::to_string_pretty(stats)?,
+    )
+    .await?;
+    Ok(())
+}
+
+async fn latest_stats(pipe_dir: &Path) -> Option<PipeStats> {
+    let latest = tokio::fs::read(pipe_dir.join(PIPE_LATEST_STATS_FILE))
+        .await
+        .ok()?;
+    serde_json::from_slice(&latest).ok()
+}
+
+/// The recorded runs of `pipe`, oldest first. Lines that aren't a run are skipped.
+#[deprecated(note = "use PipeManager::pipe_stats")]
+pub async fn read_pipe_stats(pipe: &str, screenpipe_dir: &Path) -> Result<Vec<PipeStats>> {
+    PipeManager::new(screenpipe_dir.to_path_buf(), RuntimeConfig::default())?
+        .pipe_stats(pipe)
+        .await
+}
+
+/// The recorded runs of the pipe `pipe` of `screenpipe_dir`.
+pub(crate) async fn load_pipe_stats(pipe: &str, screenpipe_dir: &Path) -> Result<Vec<PipeStats>> {
+    let pipe_dir = installed_pipe_dir(pipe, screenpipe_dir)?;
+    let history = match tokio::fs::read_to_string(pipe_dir.join(PIPE_STATS_FILE)).await {
+        Ok(history) => history,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(history
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| match serde_json::from_str(line) {
+            Ok(stats) => Some(stats),
+            Err(e) => {
+                warn!("skipping a run of pipe {}: {}", pipe, e);
+                None
+            }
+        })
+        .collect())
+}
+
+/// Forgets the recorded runs of `pipe`, the next one has a `restart_count` of 0.
+#[deprecated(note = "use PipeManager::reset_pipe_stats")]
+pub async fn reset_pipe_stats(pipe: &str, screenpipe_dir: &Path) -> Result<()> {
+    PipeManager::new(screenpipe_dir.to_path_buf(), RuntimeConfig::default())?
+        .reset_pipe_stats(pipe)
+        .await
+}
+
+/// Removes the recorded runs of the pipe `pipe` of `screenpipe_dir`.
+pub(crate) async fn clear_pipe_stats(pipe: &str, screenpipe_dir: &Path) -> Result<()> {
+    let pipe_dir = installed_pipe_dir(pipe, screenpipe_dir)?;
+    for file in [PIPE_STATS_FILE, PIPE_LATEST_STATS_FILE] {
+        match tokio::fs::remove_file(pipe_dir.join(file)).await {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(())
+}
+
+/// Copies the stats of the pipe in `pipe_dir` to `new_dir`, a download replacing it.
+pub(crate) async fn keep_pipe_stats(pipe_dir: &Path, new_dir: &Path) -> Result<()> {
+    for file in [PIPE_STATS_FILE, PIPE_LATEST_STATS_FILE] {
+        if pipe_dir.join(file).exists() {
+            tokio::fs::copy(pipe_dir.join(file), new_dir.join(file)).await?;
+        }
+    }
+    Ok(())
+}
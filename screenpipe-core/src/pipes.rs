@@ -2,21 +2,30 @@
 #[cfg(feature = "pipes")]
 mod pipes {
     use regex::Regex;
+    use std::collections::HashMap;
     use std::future::Future;
     use std::path::PathBuf;
     use std::pin::Pin;
-    use std::time::{SystemTime, UNIX_EPOCH};
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
     use tokio::process::Command;
+    use tokio::sync::mpsc;
+    use tokio::task::JoinHandle;
+    use tokio_util::sync::CancellationToken;
+    use tokio_util::task::TaskTracker;
 
     use reqwest::Client;
+    use serde::{Deserialize, Serialize};
     use serde_json::Value;
 
     use anyhow::Result;
+    use chrono::{DateTime, Utc};
+    use notify::{RecommendedWatcher, RecursiveMode, Watcher};
     use reqwest;
     use std::fs;
     use std::path::Path;
     use tokio::io::{AsyncBufReadExt, BufReader};
-    use tracing::{debug, error, info};
+    use tracing::{debug, error, info, warn, Level};
     use url::Url;
     use which::which;
 
@@ -26,9 +35,41 @@ mod pipes {
     use tokio::io::AsyncWriteExt;
 
     use crate::pick_unused_port;
+    use crate::pipe_archive::{archive_pipe_id, download_archive, ArchiveKind};
+    use crate::pipe_bitbucket::{
+        bitbucket_client_for, BitbucketSource, BITBUCKET_API, BITBUCKET_HOST,
+    };
+    use crate::pipe_bundle::PIPE_BUNDLE_FILE;
+    use crate::pipe_commands::{parse_output_command, run_output_command, PipeOutput};
+    use crate::pipe_config::load_config;
+    use crate::pipe_config_schema::write_pipe_config_schema;
+    use crate::pipe_deno::{permission_to_flag, pipe_deno_permissions, DenoPermission};
+    use crate::pipe_git::GitSource;
+    use crate::pipe_github::{
+        download_github_source_with, expand_github_shorthand, fetch_raw_github_file,
+        github_client_for, is_commit_sha, rate_limited_source, with_rate_limit_wait,
+        with_stored_token, GithubCommit, GithubRateLimited, GithubSource, GITHUB_API, GITHUB_RAW,
+    };
+    use crate::pipe_gitlab::{gitlab_client_for, GitlabSource};
+    use crate::pipe_ignore::{PipeIgnore, PIPE_IGNORE_FILE};
+    use crate::pipe_ipc::{PipeIpcEvent, PipeIpcServer, IPC_PATH_ENV};
+    use crate::pipe_link::{is_linked_pipe, resolve_pipe_dir};
+    use crate::pipe_lock::{
+        downloaded_pipe, pipe_checksums, verify_pipe_checksums, PipeFile, PipeFiles, PipeIntegrity,
+        PipeLock, PIPE_LOCK_FILE,
+    };
+    use crate::pipe_manager::{PipeManager, RuntimeConfig};
+    use crate::pipe_manifest::validate_pipe_manifest;
+    use crate::pipe_metadata::{read_pipe_metadata, PipeSourceKind};
+    use crate::pipe_npm::{npm_registry, NpmSource};
+    use crate::pipe_registry::{is_registry_name, PipeRegistry, RegistryOptions};
+    use crate::pipe_sandbox::{sandbox_command, SandboxPolicy};
+    use crate::pipe_stats::{keep_pipe_stats, PipeRun};
+    use crate::power::{power_state, PowerEvent, SubsystemOutcome};
     use once_cell::sync::Lazy;
 
     // Add near other imports
+    use futures::StreamExt;
     use rand::distributions::Alphanumeric;
     use rand::{thread_rng, Rng};
     use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
@@ -44,7 +85,7 @@ mod pipes {
     }
 
     // Update this function near the top of the file
-    fn sanitize_pipe_name(name: &str) -> String {
+    pub(crate) fn sanitize_pipe_name(name: &str) -> String {
         let re = Regex::new(r"[^a-zA-Z0-9_-]").unwrap();
         let sanitized = re.replace_all(name, "-").to_string();
 
@@ -56,41 +97,502 @@ mod pipes {
             .to_string()
     }
 
-    pub async fn run_pipe(pipe: &str, screenpipe_dir: PathBuf) -> Result<tokio::process::Child> {
-        let bun_path = find_bun_path().ok_or_else(|| anyhow::anyhow!("bun not found"))?;
-        let pipe_dir = screenpipe_dir.join("pipes").join(pipe);
-        let pipe_json_path = pipe_dir.join("pipe.json");
+    /// Id a pipe downloaded from `source` is installed under.
+    pub fn pipe_id_from_source(source: &str) -> Option<String> {
+        let source = source.trim_matches('"');
+        let expanded = expand_github_shorthand(source);
+        let source = expanded.as_deref().unwrap_or(source);
+        if let Some(id) = archive_pipe_id(source) {
+            return Some(id);
+        }
+        if let Some(npm) = NpmSource::parse(source) {
+            return Some(npm.pipe_id());
+        }
+        if let Some(github) = GithubSource::parse(source) {
+            return Some(github.pipe_id());
+        }
+        if let Some(gitlab) = GitlabSource::parse(source) {
+            return Some(gitlab.pipe_id());
+        }
+        if let Some(bitbucket) = BitbucketSource::parse(source) {
+            return Some(bitbucket.pipe_id());
+        }
+        if let Some(git) = GitSource::parse(source) {
+            return Some(git.pipe_id());
+        }
+        let name = Path::new(source).file_name()?.to_str()?;
+        Some(sanitize_pipe_name(name))
+    }
 
-        // Check if pipe is still enabled
-        if pipe_json_path.exists() {
-            let pipe_json = tokio::fs::read_to_string(&pipe_json_path).await?;
-            let pipe_config: Value = serde_json::from_str(&pipe_json)?;
+    /// How long a pipe may run, the `timeout_secs` of its pipe.json. `None` when it has
+    /// none, the pipe runs until it exits.
+    pub async fn pipe_timeout(pipe: &str, screenpipe_dir: &Path) -> Option<Duration> {
+        let pipe_json_path = screenpipe_dir.join("pipes").join(pipe).join("pipe.json");
+        let pipe_config = load_config(&pipe_json_path).await.ok()?;
+        pipe_config
+            .get("timeout_secs")
+            .and_then(Value::as_u64)
+            .filter(|secs| *secs > 0)
+            .map(Duration::from_secs)
+    }
 
-            if !pipe_config
-                .get("enabled")
-                .and_then(Value::as_bool)
-                .unwrap_or(false)
-            {
-                debug!("pipe {} is disabled, stopping", pipe);
-                anyhow::bail!("pipe is disabled");
+    /// Permission scopes a pipe asks for through the `permissions` array of its pipe.json.
+    pub async fn requested_permissions(pipe: &str, screenpipe_dir: &Path) -> Vec<String> {
+        let pipe_json_path = screenpipe_dir.join("pipes").join(pipe).join("pipe.json");
+        let pipe_config = match load_config(&pipe_json_path).await {
+            Ok(pipe_config) => pipe_config,
+            Err(e) => {
+                if !e.is_not_found() {
+                    warn!("ignoring the permissions pipe {} requests: {}", pipe, e);
+                }
+                return Vec::new();
+            }
+        };
+
+        let mut scopes: Vec<String> = pipe_config
+            .get("permissions")
+            .and_then(Value::as_array)
+            .map(|scopes| {
+                scopes
+                    .iter()
+                    .filter_map(Value::as_str)
+                    // Granted by deno rather than the user
+                    .filter(|scope| DenoPermission::parse(scope.trim()).is_none())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+        scopes.sort();
+        scopes.dedup();
+        scopes
+    }
+
+    /// Hosts a pipe talks to, declared in the `hosts` array of its pipe.json, e.g.
+    /// `api.openai.com` or `*.github.com`. `None` when it declares none.
+    pub async fn requested_hosts(pipe: &str, screenpipe_dir: &Path) -> Option<Vec<String>> {
+        let pipe_json_path = screenpipe_dir.join("pipes").join(pipe).join("pipe.json");
+        let pipe_config = load_config(&pipe_json_path).await.ok()?;
+
+        let hosts = pipe_config.get("hosts")?.as_array()?;
+        Some(
+            hosts
+                .iter()
+                .filter_map(Value::as_str)
+                .map(|host| host.trim().to_lowercase())
+                .filter(|host| !host.is_empty())
+                .collect(),
+        )
+    }
+
+    /// Environment the pipe process receives for its granted permission scopes.
+    pub fn permission_env(granted: &[String]) -> (String, String) {
+        ("SCREENPIPE_PERMISSIONS".to_string(), granted.join(","))
+    }
+
+    /// Memory a pipe's process may use unless its [`PipeRunOptions`] or pipe.json say
+    /// otherwise.
+    pub const DEFAULT_PIPE_MEMORY_LIMIT: u64 = 512 * 1024 * 1024;
+
+    /// The `memory_limit_mb` of a pipe.json in bytes, `Some(None)` for 0, no limit. `None`
+    /// when it sets none.
+    fn manifest_memory_limit(pipe_config: &Value) -> Option<Option<u64>> {
+        let mb = pipe_config.get("memory_limit_mb")?.as_u64()?;
+        Some((mb > 0).then(|| mb * 1024 * 1024))
+    }
+
+    /// The pipe.json `sandbox` of a pipe, `None` when it has none.
+    fn manifest_sandbox(pipe_config: &Value) -> Option<SandboxPolicy> {
+        serde_json::from_value(pipe_config.get("sandbox")?.clone()).ok()
+    }
+
+    /// `options` with the memory limit and sandbox of the pipe in `pipe_dir`. Its pipe.json
+    /// memory limit is used when it sets one, a next.js pipe's dev server, which routinely
+    /// uses more than a pipe should, is only limited by it. Its pipe.json sandbox is used
+    /// when stricter than the one of `options`, a pipe can't loosen it.
+    async fn with_pipe_limits(pipe_dir: &Path, options: &PipeRunOptions) -> PipeRunOptions {
+        let pipe_config = load_config(&pipe_dir.join("pipe.json")).await.ok();
+        let own = pipe_config.as_ref().and_then(manifest_memory_limit);
+        let sandbox = match pipe_config.as_ref().and_then(manifest_sandbox) {
+            Some(own) => own.stricter(options.sandbox),
+            None => options.sandbox,
+        };
+        let is_nextjs = pipe_config.is_some_and(|config| config["is_nextjs"] == json!(true));
+        let memory_limit = match own {
+            Some(own) => own,
+            None if is_nextjs => None,
+            None => options.memory_limit,
+        };
+        PipeRunOptions {
+            memory_limit,
+            sandbox,
+            ..options.clone()
+        }
+    }
+
+    /// How long a pipe has to exit once asked to, before it is killed.
+    pub const DEFAULT_PIPE_SHUTDOWN_GRACE: Duration = Duration::from_secs(5);
+
+    /// Time the log tasks of a killed pipe get to pass on what it printed last.
+    const PIPE_LOG_FLUSH_TIMEOUT: Duration = Duration::from_secs(1);
+
+    /// Cancelled when screenpipe shuts down. Pipes started with it are sent SIGTERM then,
+    /// TerminateProcess on windows, and killed once `grace` passes.
+    #[derive(Debug, Clone)]
+    pub struct ShutdownToken {
+        token: CancellationToken,
+        tasks: TaskTracker,
+        grace: Duration,
+    }
+
+    impl Default for ShutdownToken {
+        fn default() -> Self {
+            Self::with_grace(DEFAULT_PIPE_SHUTDOWN_GRACE)
+        }
+    }
+
+    impl ShutdownToken {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        pub fn with_grace(grace: Duration) -> Self {
+            Self {
+                token: CancellationToken::new(),
+                tasks: TaskTracker::new(),
+                grace,
+            }
+        }
+
+        pub fn grace(&self) -> Duration {
+            self.grace
+        }
+
+        pub fn cancel(&self) {
+            self.token.cancel();
+        }
+
+        pub fn is_cancelled(&self) -> bool {
+            self.token.is_cancelled()
+        }
+
+        pub async fn cancelled(&self) {
+            self.token.cancelled().await
+        }
+
+        /// A token cancelled with this one, or on its own to stop only the pipes started
+        /// with it. [`Self::shutdown`] of this one waits for those too.
+        pub fn child(&self) -> ShutdownToken {
+            Self {
+                token: self.token.child_token(),
+                tasks: self.tasks.clone(),
+                grace: self.grace,
+            }
+        }
+
+        /// Cancels, then waits for the pipes started with the token to be stopped.
+        pub async fn shutdown(&self) {
+            self.cancel();
+            self.tasks.close();
+            self.tasks.wait().await;
+        }
+    }
+
+    /// How a pipe is started.
+    #[derive(Debug, Clone)]
+    pub struct PipeRunOptions {
+        /// Scopes the pipe is restricted to, `None` runs it without a permission set
+        pub granted: Option<Vec<String>>,
+        /// Added to its environment, e.g. the network proxy it goes through
+        pub extra_env: Vec<(String, String)>,
+        /// Bytes of memory the process may use, `None` for no limit. On linux this caps
+        /// its data segment and private mappings, `RLIMIT_DATA`, as the address space
+        /// bun reserves up front is far larger than what it uses. `RLIMIT_RSS` on macos,
+        /// a job object on windows. A pipe.json `memory_limit_mb` takes its place, and
+        /// next.js pipes aren't limited without one
+        pub memory_limit: Option<u64>,
+        /// CPU time the process may use before it is killed, `None` for no limit
+        pub cpu_time_limit: Option<std::time::Duration>,
+        /// Stops the process when cancelled, `None` leaves it to whoever holds its child
+        pub shutdown: Option<ShutdownToken>,
+        /// Gets the messages the pipe sends on its [`PipeIpcServer`], they are only logged
+        /// when `None`
+        pub ipc: Option<mpsc::Sender<PipeIpcEvent>>,
+        /// Gets the outputs the pipe sets with `::screenpipe-set-output`, see
+        /// [`crate::pipe_commands`], they are only logged when `None`
+        pub output: Option<PipeOutput>,
+        /// Syscalls a deno pipe may make, enforced with seccomp on linux. Left unapplied
+        /// to other runtimes and elsewhere. A stricter pipe.json `sandbox` takes its place
+        pub sandbox: SandboxPolicy,
+    }
+
+    impl Default for PipeRunOptions {
+        fn default() -> Self {
+            Self {
+                granted: None,
+                extra_env: Vec::new(),
+                memory_limit: Some(DEFAULT_PIPE_MEMORY_LIMIT),
+                cpu_time_limit: None,
+                shutdown: None,
+                ipc: None,
+                output: None,
+                sandbox: SandboxPolicy::None,
+            }
+        }
+    }
+
+    /// Caps the resources of the process `command` starts, as set in `options`. On
+    /// windows they are applied once the process runs.
+    pub fn limit_command(command: &mut Command, options: &PipeRunOptions) {
+        #[cfg(unix)]
+        {
+            let memory = options.memory_limit;
+            let cpu = options.cpu_time_limit.map(|limit| limit.as_secs().max(1));
+            // Only async-signal-safe calls between fork and exec
+            unsafe {
+                command.pre_exec(move || {
+                    let memory_resource = if cfg!(target_os = "macos") {
+                        libc::RLIMIT_RSS
+                    } else {
+                        libc::RLIMIT_DATA
+                    };
+                    for (resource, value) in [(memory_resource, memory), (libc::RLIMIT_CPU, cpu)] {
+                        let Some(value) = value else { continue };
+                        let mut limit = libc::rlimit {
+                            rlim_cur: 0,
+                            rlim_max: 0,
+                        };
+                        if libc::getrlimit(resource, &mut limit) != 0 {
+                            return Err(std::io::Error::last_os_error());
+                        }
+                        // Below the hard limit, which the pipe can't raise
+                        let value = (value as libc::rlim_t).min(limit.rlim_max);
+                        limit.rlim_cur = value;
+                        limit.rlim_max = value;
+                        if libc::setrlimit(resource, &limit) != 0 {
+                            return Err(std::io::Error::last_os_error());
+                        }
+                    }
+                    Ok(())
+                });
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = (command, options);
+    }
+
+    /// Puts a started pipe in a job object limiting it as set in `options`.
+    #[cfg(windows)]
+    fn limit_process(child: &tokio::process::Child, options: &PipeRunOptions) -> Result<()> {
+        use windows::core::PCWSTR;
+        use windows::Win32::Foundation::{CloseHandle, HANDLE};
+        use windows::Win32::System::JobObjects::{
+            AssignProcessToJobObject, CreateJobObjectW, JobObjectExtendedLimitInformation,
+            SetInformationJobObject, JOBOBJECT_EXTENDED_LIMIT_INFORMATION,
+            JOB_OBJECT_LIMIT_PROCESS_MEMORY, JOB_OBJECT_LIMIT_PROCESS_TIME,
+        };
+
+        let mut info = JOBOBJECT_EXTENDED_LIMIT_INFORMATION::default();
+        if let Some(bytes) = options.memory_limit {
+            info.BasicLimitInformation.LimitFlags |= JOB_OBJECT_LIMIT_PROCESS_MEMORY;
+            info.ProcessMemoryLimit = bytes as usize;
+        }
+        if let Some(limit) = options.cpu_time_limit {
+            info.BasicLimitInformation.LimitFlags |= JOB_OBJECT_LIMIT_PROCESS_TIME;
+            // In 100ns ticks
+            info.BasicLimitInformation.PerProcessUserTimeLimit = (limit.as_nanos() / 100) as i64;
+        }
+        let handle = match child.raw_handle() {
+            Some(handle) if info.BasicLimitInformation.LimitFlags.0 != 0 => handle,
+            _ => return Ok(()),
+        };
+        unsafe {
+            let job = CreateJobObjectW(None, PCWSTR::null())?;
+            let limited = SetInformationJobObject(
+                job,
+                JobObjectExtendedLimitInformation,
+                &info as *const _ as *const std::ffi::c_void,
+                std::mem::size_of_val(&info) as u32,
+            )
+            .and_then(|_| AssignProcessToJobObject(job, HANDLE(handle)));
+            // The job lives on as long as the pipe is in it
+            let _ = CloseHandle(job);
+            limited?;
+        }
+        Ok(())
+    }
+
+    /// Spawns `command` with the resource limits of `options`.
+    fn spawn_limited(
+        pipe: &str,
+        command: &mut Command,
+        options: &PipeRunOptions,
+    ) -> Result<tokio::process::Child> {
+        limit_command(command, options);
+        let child = command.spawn()?;
+        #[cfg(windows)]
+        if let Err(e) = limit_process(&child, options) {
+            warn!("failed to limit the resources of pipe {}: {}", pipe, e);
+        }
+        #[cfg(not(windows))]
+        let _ = pipe;
+        Ok(child)
+    }
+
+    /// What runs a pipe's main file.
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum PipeRuntime {
+        #[default]
+        Bun,
+        Node,
+        Deno,
+    }
+
+    impl std::fmt::Display for PipeRuntime {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str(match self {
+                PipeRuntime::Bun => "bun",
+                PipeRuntime::Node => "node",
+                PipeRuntime::Deno => "deno",
+            })
+        }
+    }
+
+    impl PipeRuntime {
+        /// Path of the runtime's executable, `None` when it isn't installed.
+        pub fn executable(self) -> Option<PathBuf> {
+            match self {
+                PipeRuntime::Bun => find_bun_path(),
+                PipeRuntime::Node => find_node_path(),
+                PipeRuntime::Deno => deno_path(),
+            }
+        }
+
+        /// Arguments running `main_module`. Node strips the types of a typescript file,
+        /// deno runs it with `deno_permissions` and fails rather than prompt for others.
+        pub fn run_args(
+            self,
+            main_module: &Path,
+            deno_permissions: &[DenoPermission],
+        ) -> Vec<std::ffi::OsString> {
+            let typescript = main_module
+                .extension()
+                .is_some_and(|extension| extension == "ts" || extension == "mts");
+            let flags: &[&str] = match self {
+                PipeRuntime::Bun => &["run"],
+                PipeRuntime::Node if typescript => &["--experimental-strip-types"],
+                PipeRuntime::Node => &[],
+                PipeRuntime::Deno => &["run", "--no-prompt"],
+            };
+            let mut args: Vec<std::ffi::OsString> = flags.iter().map(Into::into).collect();
+            if self == PipeRuntime::Deno {
+                args.extend(
+                    deno_permissions
+                        .iter()
+                        .map(|permission| permission_to_flag(*permission).into()),
+                );
             }
+            args.push(main_module.into());
+            args
         }
+    }
+
+    /// The runtime a pipe names in its pipe.json `runtime`, else deno for a pipe with a
+    /// deno.json and no package.json, else bun.
+    pub async fn detect_pipe_runtime(pipe_dir: &Path) -> PipeRuntime {
+        if let Ok(config) = load_config(&pipe_dir.join("pipe.json")).await {
+            if let Some(runtime) = config.get("runtime").filter(|runtime| !runtime.is_null()) {
+                match PipeRuntime::deserialize(runtime) {
+                    Ok(runtime) => return runtime,
+                    Err(e) => warn!("ignoring the runtime of pipe {:?}: {}", pipe_dir, e),
+                }
+            }
+        }
+        let has = |file: &str| pipe_dir.join(file).exists();
+        if (has("deno.json") || has("deno.jsonc")) && !has("package.json") {
+            PipeRuntime::Deno
+        } else {
+            PipeRuntime::Bun
+        }
+    }
+
+    /// The runtime of a pipe and its executable.
+    async fn pipe_runtime(pipe: &str, pipe_dir: &Path) -> Result<(PipeRuntime, PathBuf)> {
+        let runtime = detect_pipe_runtime(pipe_dir).await;
+        let path = runtime
+            .executable()
+            .ok_or_else(|| anyhow::anyhow!("{} not found, pipe {} runs with it", runtime, pipe))?;
+        Ok((runtime, path))
+    }
+
+    #[deprecated(note = "use PipeManager::run_pipe")]
+    pub async fn run_pipe(pipe: &str, screenpipe_dir: PathBuf) -> Result<tokio::process::Child> {
+        PipeManager::new(screenpipe_dir, RuntimeConfig::default())?
+            .run_pipe(pipe)
+            .await
+    }
+
+    /// Starts a pipe restricted to `granted` scopes, `None` runs it without a permission set.
+    /// `extra_env` is added to its environment, e.g. the network proxy it goes through.
+    #[deprecated(note = "use PipeManager::run_pipe_with")]
+    pub async fn run_pipe_with_permissions(
+        pipe: &str,
+        screenpipe_dir: PathBuf,
+        granted: Option<Vec<String>>,
+        extra_env: Vec<(String, String)>,
+    ) -> Result<tokio::process::Child> {
+        let options = PipeRunOptions {
+            granted,
+            extra_env,
+            ..Default::default()
+        };
+        PipeManager::new(screenpipe_dir, RuntimeConfig::default())?
+            .run_pipe_with(pipe, options)
+            .await
+    }
+
+    /// Starts a pipe as set in `options`.
+    #[deprecated(note = "use PipeManager::run_pipe_with")]
+    pub async fn run_pipe_with(
+        pipe: &str,
+        screenpipe_dir: PathBuf,
+        options: PipeRunOptions,
+    ) -> Result<tokio::process::Child> {
+        PipeManager::new(screenpipe_dir, RuntimeConfig::default())?
+            .run_pipe_with(pipe, options)
+            .await
+    }
+
+    /// Starts the pipe `pipe` of `screenpipe_dir` as set in `options`.
+    pub(crate) async fn start_pipe(
+        pipe: &str,
+        screenpipe_dir: PathBuf,
+        options: PipeRunOptions,
+    ) -> Result<tokio::process::Child> {
+        let pipe_dir = resolve_pipe_dir(screenpipe_dir.join("pipes").join(pipe));
+        let pipe_json_path = pipe_dir.join("pipe.json");
+
+        ensure_enabled(pipe, &pipe_json_path).await?;
+        let options = with_pipe_limits(&pipe_dir, &options).await;
 
         // Prepare environment variables
-        let mut env_vars = std::env::vars().collect::<Vec<(String, String)>>();
-        env_vars.push((
-            "SCREENPIPE_DIR".to_string(),
-            screenpipe_dir.to_str().unwrap().to_string(),
-        ));
-        env_vars.push(("PIPE_ID".to_string(), pipe.to_string()));
-        env_vars.push((
-            "PIPE_DIR".to_string(),
-            pipe_dir.to_str().unwrap().to_string(),
-        ));
+        let mut env_vars = pipe_env(pipe, &screenpipe_dir, &pipe_dir, options.granted.as_deref());
+        env_vars.extend(options.extra_env.iter().cloned());
+
+        // Listening before the pipe starts, it may send a message right away
+        let ipc = match PipeIpcServer::bind(pipe, &screenpipe_dir, options.ipc.clone()).await {
+            Ok(ipc) => {
+                env_vars.push((IPC_PATH_ENV.to_string(), ipc.path().display().to_string()));
+                Some(ipc)
+            }
+            Err(e) => {
+                warn!("pipe {} runs without ipc: {}", pipe, e);
+                None
+            }
+        };
 
         if pipe_json_path.exists() {
-            let pipe_json = tokio::fs::read_to_string(&pipe_json_path).await?;
-            let pipe_config: Value = serde_json::from_str(&pipe_json)?;
+            let pipe_config = load_config(&pipe_json_path).await?;
 
             if pipe_config["is_nextjs"] == json!(true) {
                 // Handle cron jobs if they exist
@@ -139,6 +641,7 @@ mod pipes {
                 }
 
                 // Install dependencies using bun
+                let bun_path = find_bun_path().ok_or_else(|| anyhow::anyhow!("bun not found"))?;
                 info!("installing dependencies for next.js pipe");
                 let install_output = Command::new(&bun_path)
                     .arg("install")
@@ -166,7 +669,8 @@ mod pipes {
                 env_vars.push(("PORT".to_string(), port.to_string()));
 
                 // Run the Next.js project with bun
-                let mut child = Command::new(&bun_path)
+                let mut command = Command::new(&bun_path);
+                command
                     .arg("run")
                     .arg("dev")
                     .arg("--port")
@@ -174,19 +678,25 @@ mod pipes {
                     .current_dir(&pipe_dir)
                     .envs(env_vars)
                     .stdout(std::process::Stdio::piped())
-                    .stderr(std::process::Stdio::piped())
-                    .spawn()?;
+                    .stderr(std::process::Stdio::piped());
+                let mut child = spawn_limited(pipe, &mut command, &options)?;
 
                 // Stream logs
-                stream_logs(pipe, &mut child).await?;
+                let logs = stream_logs(pipe, &mut child, ipc, options.output.clone()).await?;
+                stop_on_shutdown(pipe, &child, logs, &options);
 
                 return Ok(child);
             }
         }
 
-        // If it's not a Next.js project, run the pipe as before
+        // If it's not a Next.js project, run the pipe with its runtime
         let main_module = find_pipe_file(&pipe_dir)?;
-        info!("executing pipe: {:?}", main_module);
+        let (runtime, runtime_path) = pipe_runtime(pipe, &pipe_dir).await?;
+        let deno_permissions = match runtime {
+            PipeRuntime::Deno => pipe_deno_permissions(pipe, &pipe_dir, &screenpipe_dir).await?,
+            _ => Vec::new(),
+        };
+        info!("executing pipe with {}: {:?}", runtime, main_module);
 
         // Add PIPE_FILE to environment variables for non-Next.js pipes
         env_vars.push((
@@ -194,162 +704,1975 @@ mod pipes {
             main_module.to_str().unwrap().to_string(),
         ));
 
-        let mut child = Command::new(&bun_path)
-            .arg("run")
-            .arg(&main_module)
+        let mut command = Command::new(&runtime_path);
+        command
+            .args(runtime.run_args(&main_module, &deno_permissions))
             .envs(env_vars)
             .stdout(std::process::Stdio::piped())
-            .stderr(std::process::Stdio::piped())
-            .spawn()?;
+            .stderr(std::process::Stdio::piped());
+        if runtime == PipeRuntime::Deno {
+            sandbox_command(&mut command, options.sandbox)?;
+        } else if options.sandbox != SandboxPolicy::None {
+            debug!(
+                "pipe {} runs with {}, only deno pipes are sandboxed",
+                pipe, runtime
+            );
+        }
+        let mut child = spawn_limited(pipe, &mut command, &options)?;
 
         // Stream logs - don't block the main thread
-        stream_logs(pipe, &mut child).await?;
+        let logs = stream_logs(pipe, &mut child, ipc, options.output.clone()).await?;
+        stop_on_shutdown(pipe, &child, logs, &options);
 
         Ok(child)
     }
 
-    async fn stream_logs(pipe: &str, child: &mut tokio::process::Child) -> Result<()> {
-        let stdout = child.stdout.take().expect("failed to get stdout");
-        let stderr = child.stderr.take().expect("failed to get stderr");
-
-        let pipe_clone = pipe.to_string();
+    /// Waits for `pipe` to exit. Once `timeout` passes it is killed, SIGKILL on unix, and
+    /// [`PipeError::Timeout`] is returned. `None` waits with no deadline, the pipe.json
+    /// `timeout_secs` of the pipe is its [`pipe_timeout`].
+    pub async fn wait_pipe(
+        pipe: &str,
+        child: &mut tokio::process::Child,
+        timeout: Option<Duration>,
+    ) -> Result<std::process::ExitStatus> {
+        let Some(timeout) = timeout else {
+            return Ok(child.wait().await?);
+        };
+        match tokio::time::timeout(timeout, child.wait()).await {
+            Ok(status) => Ok(status?),
+            Err(_) => {
+                child.kill().await?;
+                Err(PipeError::Timeout {
+                    pipe_name: pipe.to_string(),
+                    elapsed: timeout,
+                }
+                .into())
+            }
+        }
+    }
 
-        // Spawn tasks to handle stdout and stderr
-        let _stdout_handle = tokio::spawn(async move {
-            let reader = BufReader::new(stdout);
-            let mut lines = reader.lines();
-            while let Ok(Some(line)) = lines.next_line().await {
-                info!("[{}] {}", pipe_clone, line);
+    /// Stops `child` once `options.shutdown` is cancelled: SIGTERM, or TerminateProcess on
+    /// windows, then SIGKILL when its output isn't closed after the grace period. `logs`
+    /// are awaited so what it prints while exiting is logged.
+    fn stop_on_shutdown(
+        pipe: &str,
+        child: &tokio::process::Child,
+        logs: Vec<JoinHandle<()>>,
+        options: &PipeRunOptions,
+    ) {
+        let (Some(shutdown), Some(pid)) = (options.shutdown.clone(), child.id()) else {
+            return;
+        };
+        let pipe = pipe.to_string();
+        shutdown.tasks.clone().spawn(async move {
+            let logs = async move {
+                for log in logs {
+                    let _ = log.await;
+                }
+            };
+            tokio::pin!(logs);
+            tokio::select! {
+                // The pipe exited on its own
+                _ = &mut logs => return,
+                _ = shutdown.cancelled() => {}
+            }
+            info!("stopping pipe {} with pid {}", pipe, pid);
+            terminate_process(pid);
+            if tokio::time::timeout(shutdown.grace(), &mut logs)
+                .await
+                .is_err()
+            {
+                warn!(
+                    "pipe {} didn't exit within {}s, killing it",
+                    pipe,
+                    shutdown.grace().as_secs_f32()
+                );
+                kill_process(pid);
+                let _ = tokio::time::timeout(PIPE_LOG_FLUSH_TIMEOUT, &mut logs).await;
             }
         });
+    }
 
-        let pipe_clone = pipe.to_string();
+    /// Changes to a watched pipe within this long of each other restart it once, so a
+    /// formatter or a checkout touching many files doesn't restart it for each.
+    pub const PIPE_WATCH_DEBOUNCE: Duration = Duration::from_millis(300);
+
+    /// Runs `pipe` and restarts it whenever a file in its folder changes, a quick loop
+    /// for developing one. Runs until the watcher fails.
+    #[deprecated(note = "use PipeManager::watch_pipe")]
+    pub async fn watch_pipe(pipe: &str, screenpipe_dir: PathBuf) -> Result<()> {
+        PipeManager::new(screenpipe_dir, RuntimeConfig::default())?
+            .watch_pipe(pipe)
+            .await
+    }
 
-        let _stderr_handle = tokio::spawn(async move {
-            let reader = BufReader::new(stderr);
-            let mut lines = reader.lines();
-            while let Ok(Some(line)) = lines.next_line().await {
-                // List of patterns that should be treated as info logs
-                let info_patterns = [
-                    "Download",
-                    "Task dev ",
-                    "$ next dev",
-                    "ready started server",
-                    "Local:",
-                    "Webpack is configured",
-                    "See instructions",
-                    "https://nextjs.org",
-                ];
+    /// [`watch_pipe`], starting the pipe with `options`. Returns once `options.shutdown`
+    /// is cancelled and the pipe is stopped.
+    #[deprecated(note = "use PipeManager::watch_pipe_with")]
+    pub async fn watch_pipe_with(
+        pipe: &str,
+        screenpipe_dir: PathBuf,
+        options: PipeRunOptions,
+    ) -> Result<()> {
+        PipeManager::new(screenpipe_dir, RuntimeConfig::default())?
+            .watch_pipe_with(pipe, options)
+            .await
+    }
 
-                if info_patterns.iter().any(|pattern| line.contains(pattern)) {
-                    info!("[{}] {}", pipe_clone, line);
-                } else {
-                    error!("[{}] {}", pipe_clone, line);
+    /// Runs the pipe `pipe` of `screenpipe_dir` with `options`, restarting it on each
+    /// change, until `options.shutdown` is cancelled.
+    pub(crate) async fn run_watched_pipe(
+        pipe: &str,
+        screenpipe_dir: PathBuf,
+        options: PipeRunOptions,
+    ) -> Result<()> {
+        let pipe_dir = screenpipe_dir.join("pipes").join(pipe);
+        // Events name the real path, e.g. /private/var rather than /var on macos
+        let pipe_dir = tokio::fs::canonicalize(&pipe_dir).await?;
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut watcher = RecommendedWatcher::new(
+            move |event: notify::Result<notify::Event>| {
+                let _ = tx.send(event);
+            },
+            notify::Config::default(),
+        )?;
+        watcher.watch(&pipe_dir, RecursiveMode::Recursive)?;
+        info!("watching pipe {} in {:?}", pipe, pipe_dir);
+
+        let shutdown = options.shutdown.clone().unwrap_or_default();
+        loop {
+            // Each run is stopped on its own, the pipe in its last state
+            let run = shutdown.child();
+            let run_options = PipeRunOptions {
+                shutdown: Some(run.clone()),
+                ..options.clone()
+            };
+            let pipe_run = PipeRun::start(pipe);
+            let mut child = match start_pipe(pipe, screenpipe_dir.clone(), run_options).await {
+                Ok(child) => Some(child),
+                Err(e) => {
+                    error!("failed to start pipe {}, waiting for a change: {}", pipe, e);
+                    None
                 }
-            }
-        });
+            };
+            // What starting the pipe writes, e.g. the port of a next.js pipe, isn't a change
+            let settled = tokio::time::Instant::now() + PIPE_WATCH_DEBOUNCE;
+            while let Ok(Some(_)) = tokio::time::timeout_at(settled, rx.recv()).await {}
 
-        info!("pipe execution completed successfully");
-        Ok(())
+            let changed = tokio::select! {
+                changed = next_pipe_change(&pipe_dir, &mut rx) => Some(changed),
+                _ = shutdown.cancelled() => None,
+            };
+            run.cancel();
+            if let Some(child) = &mut child {
+                let status = child.wait().await.ok();
+                pipe_run.finish(status, &screenpipe_dir).await;
+            }
+            match changed {
+                Some(Ok(path)) => info!("{} changed, restarting pipe {}", path.display(), pipe),
+                Some(Err(e)) => return Err(e),
+                None => return Ok(()),
+            }
+        }
     }
 
-    // Add this helper function for retrying installations
-    async fn retry_install(bun_path: &Path, dest_dir: &Path, max_retries: u32) -> Result<()> {
-        let mut attempt = 0;
-        let mut last_error = None;
+    /// The first file changed in `pipe_dir` once its events settle for
+    /// [`PIPE_WATCH_DEBOUNCE`]. Hidden files and `node_modules` aren't watched, the
+    /// pipe and its runtime write there.
+    async fn next_pipe_change(
+        pipe_dir: &Path,
+        rx: &mut tokio::sync::mpsc::UnboundedReceiver<notify::Result<notify::Event>>,
+    ) -> Result<PathBuf> {
+        loop {
+            let event = match rx.recv().await {
+                Some(Ok(event)) => event,
+                Some(Err(e)) => {
+                    warn!("failed to watch {:?}: {}", pipe_dir, e);
+                    continue;
+                }
+                None => anyhow::bail!("the watcher of {:?} stopped", pipe_dir),
+            };
+            if matches!(
+                event.kind,
+                notify::EventKind::Access(_) | notify::EventKind::Other
+            ) {
+                continue;
+            }
+            let changed = event.paths.into_iter().find(|path| {
+                path.strip_prefix(pipe_dir).is_ok_and(|rel| {
+                    !rel.iter()
+                        .any(|part| is_hidden_file(part) || part == "node_modules")
+                })
+            });
+            let Some(changed) = changed else {
+                continue;
+            };
+            while let Ok(Some(_)) = tokio::time::timeout(PIPE_WATCH_DEBOUNCE, rx.recv()).await {}
+            return Ok(changed);
+        }
+    }
 
-        while attempt < max_retries {
-            let mut install_child = Command::new(bun_path)
-                .arg("i")
-                .current_dir(dest_dir)
-                .stdout(std::process::Stdio::piped())
-                .stderr(std::process::Stdio::piped())
-                .spawn()?;
+    /// Asks the process to exit.
+    fn terminate_process(pid: u32) {
+        #[cfg(unix)]
+        {
+            use nix::sys::signal::{kill, Signal};
+            use nix::unistd::Pid;
+            let _ = kill(Pid::from_raw(pid as i32), Signal::SIGTERM);
+        }
+        // Windows has no signal a console process can be asked to exit with
+        #[cfg(windows)]
+        kill_process(pid);
+    }
 
-            // Stream logs for npm install
-            if let Ok(()) = stream_logs("bun install", &mut install_child).await {
-                let status = install_child.wait().await?;
-                if status.success() {
-                    return Ok(());
+    fn kill_process(pid: u32) {
+        #[cfg(unix)]
+        {
+            use nix::sys::signal::{kill, Signal};
+            use nix::unistd::Pid;
+            let _ = kill(Pid::from_raw(pid as i32), Signal::SIGKILL);
+        }
+        #[cfg(windows)]
+        {
+            use windows::Win32::Foundation::CloseHandle;
+            use windows::Win32::System::Threading::{
+                OpenProcess, TerminateProcess, PROCESS_TERMINATE,
+            };
+            unsafe {
+                if let Ok(process) = OpenProcess(PROCESS_TERMINATE, false, pid) {
+                    let _ = TerminateProcess(process, 1);
+                    let _ = CloseHandle(process);
                 }
             }
-
-            attempt += 1;
-            let delay = std::time::Duration::from_secs(2u64.pow(attempt)); // exponential backoff
-            error!(
-                "install attempt {} failed, retrying in {} seconds",
-                attempt,
-                delay.as_secs()
-            );
-            tokio::time::sleep(delay).await;
-            last_error = Some(anyhow::anyhow!(
-                "installation failed after {} attempts",
-                attempt
-            ));
         }
+    }
 
-        Err(last_error.unwrap_or_else(|| anyhow::anyhow!("installation failed")))
+    /// Runs the pipe's main file once to handle `event`, e.g. a job it scheduled. The
+    /// event is passed in `PIPE_EVENT` and written to the pipe's stdin. It starts as set
+    /// in `options`, but for its `ipc`. Its deadline is left to [`wait_pipe`].
+    #[deprecated(note = "use PipeManager::run_pipe_once_with")]
+    pub async fn run_pipe_once(
+        pipe: &str,
+        screenpipe_dir: PathBuf,
+        event: &str,
+        options: PipeRunOptions,
+    ) -> Result<tokio::process::Child> {
+        PipeManager::new(screenpipe_dir, RuntimeConfig::default())?
+            .run_pipe_once_with(pipe, event, options)
+            .await
     }
 
-    pub async fn download_pipe(source: &str, screenpipe_dir: PathBuf) -> anyhow::Result<PathBuf> {
-        info!("Processing pipe from source: {}", source);
+    /// Starts the pipe `pipe` of `screenpipe_dir` once to handle `event`.
+    pub(crate) async fn start_pipe_once(
+        pipe: &str,
+        screenpipe_dir: PathBuf,
+        event: &str,
+        options: PipeRunOptions,
+    ) -> Result<tokio::process::Child> {
+        let pipe_dir = resolve_pipe_dir(screenpipe_dir.join("pipes").join(pipe));
+        ensure_enabled(pipe, &pipe_dir.join("pipe.json")).await?;
+        let options = with_pipe_limits(&pipe_dir, &options).await;
+
+        let main_module = find_pipe_file(&pipe_dir)?;
+        let (runtime, runtime_path) = pipe_runtime(pipe, &pipe_dir).await?;
+        let deno_permissions = match runtime {
+            PipeRuntime::Deno => pipe_deno_permissions(pipe, &pipe_dir, &screenpipe_dir).await?,
+            _ => Vec::new(),
+        };
+        let mut env_vars = pipe_env(pipe, &screenpipe_dir, &pipe_dir, options.granted.as_deref());
+        env_vars.extend(options.extra_env.iter().cloned());
+        env_vars.push((
+            "PIPE_FILE".to_string(),
+            main_module.to_str().unwrap().to_string(),
+        ));
+        env_vars.push(("PIPE_EVENT".to_string(), event.to_string()));
+
+        info!("running pipe {} with {} for an event", pipe, runtime);
+        let mut command = Command::new(&runtime_path);
+        command
+            .args(runtime.run_args(&main_module, &deno_permissions))
+            .envs(env_vars)
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped());
+        if runtime == PipeRuntime::Deno {
+            sandbox_command(&mut command, options.sandbox)?;
+        }
+        let mut child = spawn_limited(pipe, &mut command, &options)?;
+
+        // Written in the background, a pipe that never reads stdin can't block us.
+        // Closed once written, so reading stdin to the end gets the event alone
+        if let Some(mut stdin) = child.stdin.take() {
+            let event = event.to_string();
+            tokio::spawn(async move {
+                let _ = stdin.write_all(event.as_bytes()).await;
+            });
+        }
+        let logs = stream_logs(pipe, &mut child, None, options.output.clone()).await?;
+        stop_on_shutdown(pipe, &child, logs, &options);
+
+        Ok(child)
+    }
+
+    /// Script `screenpipe pipe repl` runs, see pipe_repl.mjs.
+    const PIPE_REPL_ENTRYPOINT: &str = include_str!("pipe_repl.mjs");
+
+    /// Id of a repl session that isn't started for an installed pipe.
+    pub const PIPE_REPL_ID: &str = "repl";
+
+    /// A `screenpipe pipe repl` session: an interactive bun process evaluating code
+    /// against the api, with the environment and permission set a pipe runs with.
+    ///
+    /// The entrypoint lives in a scratch directory that is removed when the session is
+    /// dropped, whether the repl exited or not.
+    pub struct PipeReplSession {
+        dir: tempfile::TempDir,
+        pipe_dir: PathBuf,
+        env: Vec<(String, String)>,
+    }
+
+    impl PipeReplSession {
+        /// Prepares a session running as the installed `pipe`, or as a scratch pipe when
+        /// `None`, restricted to `granted` scopes and talking to the server at `server_url`.
+        pub async fn new(
+            pipe: Option<&str>,
+            screenpipe_dir: &Path,
+            server_url: &str,
+            granted: Option<Vec<String>>,
+        ) -> Result<Self> {
+            let dir = tempfile::Builder::new()
+                .prefix("screenpipe-repl-")
+                .tempdir()?;
+            tokio::fs::write(dir.path().join("repl.mjs"), PIPE_REPL_ENTRYPOINT).await?;
+
+            let (pipe, pipe_dir) = match pipe {
+                Some(pipe) => {
+                    let pipe_dir = resolve_pipe_dir(screenpipe_dir.join("pipes").join(pipe));
+                    if !pipe_dir.is_dir() {
+                        anyhow::bail!("pipe {} is not installed", pipe);
+                    }
+                    (pipe, pipe_dir)
+                }
+                None => (PIPE_REPL_ID, dir.path().to_path_buf()),
+            };
+            let mut env = pipe_env(pipe, screenpipe_dir, &pipe_dir, granted.as_deref());
+            env.push((
+                "SCREENPIPE_SERVER_URL".to_string(),
+                server_url.trim_end_matches('/').to_string(),
+            ));
+
+            Ok(Self { dir, pipe_dir, env })
+        }
+
+        pub fn entrypoint(&self) -> PathBuf {
+            self.dir.path().join("repl.mjs")
+        }
+
+        /// Directory the repl runs in, where `@screenpipe/js` is resolved from.
+        pub fn pipe_dir(&self) -> &Path {
+            &self.pipe_dir
+        }
+
+        pub fn env(&self) -> &[(String, String)] {
+            &self.env
+        }
+
+        /// Runs the repl on this terminal until the user leaves it, then removes the
+        /// session's files.
+        pub async fn run(self) -> Result<std::process::ExitStatus> {
+            let bun_path = find_bun_path().ok_or_else(|| anyhow::anyhow!("bun not found"))?;
+            let mut child = Command::new(&bun_path)
+                .arg("run")
+                .arg(self.entrypoint())
+                .current_dir(&self.pipe_dir)
+                .envs(self.env.iter().cloned())
+                .kill_on_drop(true)
+                .spawn()?;
+
+            // Ctrl+c reaches the repl too, which clears its line. It must not end this
+            // process before the session is cleaned up
+            loop {
+                tokio::select! {
+                    status = child.wait() => return Ok(status?),
+                    _ = tokio::signal::ctrl_c() => continue,
+                }
+            }
+        }
+    }
+
+    /// `.disabled`, in the folder of a pipe stopped with [`disable_pipe`]. Its pipe.json
+    /// and files are kept as they are.
+    pub const PIPE_DISABLED_FILE: &str = ".disabled";
+
+    /// Why a pipe didn't start, in the error of [`run_pipe`] and the like, or didn't exit, in
+    /// the error of [`wait_pipe`], for callers that tell it apart with `downcast_ref`.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum PipeError {
+        /// Disabled with [`disable_pipe`] or in its pipe.json
+        Disabled(String),
+        /// Asks for deno permissions the policy doesn't allow, see
+        /// [`crate::pipe_deno::pipe_deno_permissions`]
+        NotAllowed {
+            pipe: String,
+            permissions: Vec<String>,
+        },
+        /// Killed once it ran for `elapsed`, its timeout
+        Timeout {
+            pipe_name: String,
+            elapsed: Duration,
+        },
+    }
+
+    impl std::fmt::Display for PipeError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                PipeError::Disabled(pipe) => write!(f, "pipe {} is disabled", pipe),
+                PipeError::NotAllowed { pipe, permissions } => write!(
+                    f,
+                    "pipe {} asks for deno permissions the policy doesn't allow: {}",
+                    pipe,
+                    permissions.join(", ")
+                ),
+                PipeError::Timeout { pipe_name, elapsed } => write!(
+                    f,
+                    "pipe {} didn't exit within {}s, killed",
+                    pipe_name,
+                    elapsed.as_secs()
+                ),
+            }
+        }
+    }
+
+    impl std::error::Error for PipeError {}
+
+    /// Stops `pipe` from running until [`enable_pipe`], without touching its pipe.json.
+    #[deprecated(note = "use PipeManager::disable_pipe")]
+    pub async fn disable_pipe(pipe: &str, screenpipe_dir: &Path) -> Result<()> {
+        PipeManager::new(screenpipe_dir.to_path_buf(), RuntimeConfig::default())?
+            .disable_pipe(pipe)
+            .await
+    }
+
+    /// Writes the [`PIPE_DISABLED_FILE`] of the pipe `pipe` of `screenpipe_dir`.
+    pub(crate) async fn mark_pipe_disabled(pipe: &str, screenpipe_dir: &Path) -> Result<()> {
+        let pipe_dir = installed_pipe_dir(pipe, screenpipe_dir)?;
+        tokio::fs::write(pipe_dir.join(PIPE_DISABLED_FILE), b"").await?;
+        info!("pipe {} disabled", pipe);
+        Ok(())
+    }
+
+    /// Lets a pipe stopped with [`disable_pipe`] run again. A pipe its pipe.json has
+    /// disabled stays so.
+    #[deprecated(note = "use PipeManager::enable_pipe")]
+    pub async fn enable_pipe(pipe: &str, screenpipe_dir: &Path) -> Result<()> {
+        PipeManager::new(screenpipe_dir.to_path_buf(), RuntimeConfig::default())?
+            .enable_pipe(pipe)
+            .await
+    }
+
+    /// Removes the [`PIPE_DISABLED_FILE`] of the pipe `pipe` of `screenpipe_dir`.
+    pub(crate) async fn unmark_pipe_disabled(pipe: &str, screenpipe_dir: &Path) -> Result<()> {
+        let pipe_dir = installed_pipe_dir(pipe, screenpipe_dir)?;
+        match tokio::fs::remove_file(pipe_dir.join(PIPE_DISABLED_FILE)).await {
+            Ok(()) => info!("pipe {} enabled", pipe),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+        Ok(())
+    }
+
+    /// Whether `pipe_dir` has the [`PIPE_DISABLED_FILE`] of [`disable_pipe`].
+    pub fn is_pipe_disabled(pipe_dir: &Path) -> bool {
+        pipe_dir.join(PIPE_DISABLED_FILE).exists()
+    }
+
+    pub(crate) fn installed_pipe_dir(pipe: &str, screenpipe_dir: &Path) -> Result<PathBuf> {
+        let pipe_dir = screenpipe_dir.join("pipes").join(pipe);
+        if pipe.is_empty() || pipe.starts_with('.') || !pipe_dir.is_dir() {
+            anyhow::bail!("pipe {} isn't installed", pipe);
+        }
+        Ok(pipe_dir)
+    }
+
+    /// Fails with [`PipeError::Disabled`] if the pipe was disabled with [`disable_pipe`]
+    /// or its pipe.json has it disabled.
+    async fn ensure_enabled(pipe: &str, pipe_json_path: &Path) -> Result<()> {
+        if pipe_json_path.parent().is_some_and(is_pipe_disabled) {
+            debug!("pipe {} is disabled, stopping", pipe);
+            return Err(PipeError::Disabled(pipe.to_string()).into());
+        }
+        if pipe_json_path.exists() {
+            let pipe_config = load_config(pipe_json_path).await?;
+
+            if !pipe_config
+                .get("enabled")
+                .and_then(Value::as_bool)
+                .unwrap_or(false)
+            {
+                debug!("pipe {} is disabled, stopping", pipe);
+                return Err(PipeError::Disabled(pipe.to_string()).into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Environment every pipe process starts with.
+    fn pipe_env(
+        pipe: &str,
+        screenpipe_dir: &Path,
+        pipe_dir: &Path,
+        granted: Option<&[String]>,
+    ) -> Vec<(String, String)> {
+        let mut env_vars = std::env::vars().collect::<Vec<(String, String)>>();
+        env_vars.push((
+            "SCREENPIPE_DIR".to_string(),
+            screenpipe_dir.to_str().unwrap().to_string(),
+        ));
+        env_vars.push(("PIPE_ID".to_string(), pipe.to_string()));
+        env_vars.push((
+            "PIPE_DIR".to_string(),
+            pipe_dir.to_str().unwrap().to_string(),
+        ));
+        if let Some(granted) = granted {
+            env_vars.push(permission_env(granted));
+        }
+        env_vars.extend(pipe_dotenv(pipe_dir));
+        env_vars
+    }
+
+    /// Variables screenpipe sets for every pipe, a pipe's `.env` can't change them.
+    pub const RESERVED_PIPE_ENV: [&str; 4] = ["SCREENPIPE_DIR", "PIPE_ID", "PIPE_FILE", "PIPE_DIR"];
+
+    /// Entries of the `.env` file in `pipe_dir` that aren't set in screenpipe's own
+    /// environment, which wins, nor [`RESERVED_PIPE_ENV`].
+    pub fn pipe_dotenv(pipe_dir: &Path) -> Vec<(String, String)> {
+        let path = pipe_dir.join(".env");
+        let entries = match dotenvy::from_path_iter(&path) {
+            Ok(entries) => entries,
+            Err(e) if e.not_found() => return Vec::new(),
+            Err(e) => {
+                warn!("ignoring {:?}: {}", path, e);
+                return Vec::new();
+            }
+        };
+
+        let mut env_vars = Vec::new();
+        for entry in entries {
+            let (key, value) = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    warn!("ignoring the rest of {:?}: {}", path, e);
+                    break;
+                }
+            };
+            if RESERVED_PIPE_ENV.contains(&key.as_str()) {
+                warn!("{:?} can't set {}, screenpipe sets it", path, key);
+            } else if std::env::var_os(&key).is_none() {
+                env_vars.push((key, value));
+            }
+        }
+        env_vars
+    }
+
+    /// A line a pipe printed, see [`parse_pipe_log_line`].
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum PipeLogLine {
+        /// A json object with a `msg` or `message`. `level` is `None` when it has none or
+        /// an unknown one, `fields` are its other keys
+        Structured {
+            level: Option<Level>,
+            message: String,
+            fields: serde_json::Map<String, Value>,
+        },
+        Raw(String),
+    }
+
+    /// Parses a line of newline-delimited json logs, e.g.
+    /// `{"level":"warn","msg":"quota exceeded"}`. Levels are names, `warning`, `fatal`
+    /// and `critical` included, or pino's numbers.
+    pub fn parse_pipe_log_line(line: &str) -> PipeLogLine {
+        let raw = || PipeLogLine::Raw(line.to_string());
+        if !line.trim_start().starts_with('{') {
+            return raw();
+        }
+        let Ok(Value::Object(mut fields)) = serde_json::from_str::<Value>(line) else {
+            return raw();
+        };
+        let Some(message) = fields.remove("msg").or_else(|| fields.remove("message")) else {
+            return raw();
+        };
+        let message = match message {
+            Value::String(message) => message,
+            message => message.to_string(),
+        };
+        let level = fields.remove("level").and_then(|level| match level {
+            Value::String(name) => match name.to_lowercase().as_str() {
+                "trace" => Some(Level::TRACE),
+                "debug" => Some(Level::DEBUG),
+                "info" => Some(Level::INFO),
+                "warn" | "warning" => Some(Level::WARN),
+                "error" | "fatal" | "critical" => Some(Level::ERROR),
+                _ => None,
+            },
+            Value::Number(number) => match number.as_u64()? {
+                0..=10 => Some(Level::TRACE),
+                11..=20 => Some(Level::DEBUG),
+                21..=30 => Some(Level::INFO),
+                31..=40 => Some(Level::WARN),
+                _ => Some(Level::ERROR),
+            },
+            _ => None,
+        });
+        PipeLogLine::Structured {
+            level,
+            message,
+            fields,
+        }
+    }
+
+    /// Logs a line of `pipe` at its own level, `default` for lines without one.
+    pub(crate) fn log_pipe_line(pipe: &str, line: &str, default: Level) {
+        let (level, text) = match parse_pipe_log_line(line) {
+            PipeLogLine::Structured {
+                level,
+                message,
+                fields,
+            } => {
+                let fields: String = fields
+                    .iter()
+                    .map(|(key, value)| match value.as_str() {
+                        Some(text) => format!(" {}={}", key, text),
+                        None => format!(" {}={}", key, value),
+                    })
+                    .collect();
+                (level.unwrap_or(default), format!("{}{}", message, fields))
+            }
+            PipeLogLine::Raw(line) => (default, line),
+        };
+        match level {
+            Level::TRACE => tracing::trace!("[{}] {}", pipe, text),
+            Level::DEBUG => debug!("[{}] {}", pipe, text),
+            Level::INFO => info!("[{}] {}", pipe, text),
+            Level::WARN => warn!("[{}] {}", pipe, text),
+            _ => error!("[{}] {}", pipe, text),
+        }
+    }
+
+    /// Runs of each pipe started in this process whose output is still open.
+    static RUNNING_PIPES: Lazy<std::sync::Mutex<HashMap<String, usize>>> =
+        Lazy::new(Default::default);
+
+    /// Whether a run of `pipe` started in this process, by [`run_pipe`] and the like,
+    /// hasn't exited yet.
+    pub fn is_pipe_running(pipe: &str) -> bool {
+        RUNNING_PIPES.lock().unwrap().contains_key(pipe)
+    }
+
+    /// Counts a run of a pipe in [`RUNNING_PIPES`] until dropped.
+    struct RunningPipe(String);
+
+    impl RunningPipe {
+        fn start(pipe: &str) -> Self {
+            *RUNNING_PIPES
+                .lock()
+                .unwrap()
+                .entry(pipe.to_string())
+                .or_default() += 1;
+            RunningPipe(pipe.to_string())
+        }
+    }
+
+    impl Drop for RunningPipe {
+        fn drop(&mut self) {
+            let mut running = RUNNING_PIPES.lock().unwrap();
+            if let Some(runs) = running.get_mut(&self.0) {
+                *runs -= 1;
+                if *runs == 0 {
+                    running.remove(&self.0);
+                }
+            }
+        }
+    }
+
+    /// Logs what `child` prints, in tasks that end once its output is closed. Until then
+    /// the pipe counts as running, see [`is_pipe_running`], and `ipc` takes its messages.
+    /// The commands in its stdout are run, `output` takes the outputs they set.
+    async fn stream_logs(
+        pipe: &str,
+        child: &mut tokio::process::Child,
+        ipc: Option<PipeIpcServer>,
+        output: Option<PipeOutput>,
+    ) -> Result<Vec<JoinHandle<()>>> {
+        let stdout = child.stdout.take().expect("failed to get stdout");
+        let stderr = child.stderr.take().expect("failed to get stderr");
+
+        let pipe_clone = pipe.to_string();
+        let running = RunningPipe::start(pipe);
+        let exited = CancellationToken::new();
+        let ipc_handle = ipc.map(|ipc| ipc.spawn(exited.clone()));
+
+        // Spawn tasks to handle stdout and stderr
+        let stdout_handle = tokio::spawn(async move {
+            let _running = running;
+            let _exited = exited.drop_guard();
+            let reader = BufReader::new(stdout);
+            let mut lines = reader.lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                match parse_output_command(&line) {
+                    Some(command) => run_output_command(&pipe_clone, command, output.as_ref()),
+                    None => log_pipe_line(&pipe_clone, &line, Level::INFO),
+                }
+            }
+        });
+
+        let pipe_clone = pipe.to_string();
+
+        let stderr_handle = tokio::spawn(async move {
+            let reader = BufReader::new(stderr);
+            let mut lines = reader.lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                // List of patterns that should be treated as info logs
+                let info_patterns = [
+                    "Download",
+                    "Task dev ",
+                    "$ next dev",
+                    "ready started server",
+                    "Local:",
+                    "Webpack is configured",
+                    "See instructions",
+                    "https://nextjs.org",
+                ];
+
+                if info_patterns.iter().any(|pattern| line.contains(pattern)) {
+                    log_pipe_line(&pipe_clone, &line, Level::INFO);
+                } else {
+                    log_pipe_line(&pipe_clone, &line, Level::ERROR);
+                }
+            }
+        });
+
+        info!("pipe execution completed successfully");
+        Ok([Some(stdout_handle), Some(stderr_handle), ipc_handle]
+            .into_iter()
+            .flatten()
+            .collect())
+    }
+
+    // Add this helper function for retrying installations
+    async fn retry_install(bun_path: &Path, dest_dir: &Path, max_retries: u32) -> Result<()> {
+        let mut attempt = 0;
+        let mut last_error = None;
+
+        while attempt < max_retries {
+            let mut install_child = Command::new(bun_path)
+                .arg("i")
+                .current_dir(dest_dir)
+                .stdout(std::process::Stdio::piped())
+                .stderr(std::process::Stdio::piped())
+                .spawn()?;
+
+            // Stream logs for npm install
+            if stream_logs("bun install", &mut install_child, None, None)
+                .await
+                .is_ok()
+            {
+                let status = install_child.wait().await?;
+                if status.success() {
+                    return Ok(());
+                }
+            }
+
+            attempt += 1;
+            let delay = std::time::Duration::from_secs(2u64.pow(attempt)); // exponential backoff
+            error!(
+                "install attempt {} failed, retrying in {} seconds",
+                attempt,
+                delay.as_secs()
+            );
+            tokio::time::sleep(delay).await;
+            last_error = Some(anyhow::anyhow!(
+                "installation failed after {} attempts",
+                attempt
+            ));
+        }
+
+        Err(last_error.unwrap_or_else(|| anyhow::anyhow!("installation failed")))
+    }
+
+    /// Files of an installed pipe a github download copies rather than fetches, those
+    /// github lists at the sha they were downloaded at and that weren't changed since.
+    #[derive(Debug, Clone, Default)]
+    pub struct InstalledFiles {
+        pub dir: PathBuf,
+        pub files: PipeFiles,
+    }
+
+    impl InstalledFiles {
+        pub async fn load(pipe_dir: &Path) -> InstalledFiles {
+            InstalledFiles {
+                dir: pipe_dir.to_path_buf(),
+                files: PipeFiles::load(pipe_dir).await,
+            }
+        }
+
+        /// Copies `rel` to `dest` when the installed file is the blob `sha`.
+        pub(crate) async fn copy(&self, rel: &str, sha: &str, dest: &Path) -> Option<PipeFile> {
+            let recorded = self.files.files.get(rel).filter(|file| file.sha == sha)?;
+            let path = self.dir.join(rel);
+            if PipeFile::read(&path, sha).await.ok().as_ref() != Some(recorded) {
+                debug!("{} changed since it was downloaded", rel);
+                return None;
+            }
+            tokio::fs::copy(&path, dest).await.ok()?;
+            PipeFile::read(dest, sha).await.ok()
+        }
+    }
+
+    /// Files a github or gitlab download fetches at once unless its [`DownloadOptions`]
+    /// say otherwise.
+    pub const DEFAULT_DOWNLOAD_CONCURRENCY: usize = 8;
+
+    /// Requests a github, gitlab or bitbucket download makes for each file, and for its
+    /// listing, unless its [`DownloadOptions`] say otherwise.
+    pub const DEFAULT_DOWNLOAD_ATTEMPTS: u32 = 3;
+
+    /// Wait before retrying a failed request, doubled for each attempt after and up to
+    /// half of it added at random so concurrent downloads don't retry at once.
+    const DOWNLOAD_RETRY_BACKOFF: Duration = Duration::from_millis(500);
+
+    /// A 5xx answer of a host, retried like a dropped connection.
+    #[derive(Debug)]
+    pub(crate) struct ServerError(pub(crate) anyhow::Error);
+
+    impl std::fmt::Display for ServerError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            self.0.fmt(f)
+        }
+    }
+
+    impl std::error::Error for ServerError {}
+
+    /// Whether a retry may not hit `e`: a timeout, a connection that failed or dropped,
+    /// or a [`ServerError`]. 404s, rate limits and other errors fail the same again.
+    fn is_transient(e: &anyhow::Error) -> bool {
+        e.chain().any(|cause| {
+            if cause.is::<ServerError>() {
+                return true;
+            }
+            cause.downcast_ref::<reqwest::Error>().is_some_and(|e| {
+                e.is_timeout()
+                    || e.is_connect()
+                    || e.is_request()
+                    || e.is_body()
+                    || e.status().is_some_and(|status| status.is_server_error())
+            })
+        })
+    }
+
+    /// Runs `request`, the `action` of `rel`, again while it fails with a transient error
+    /// until `attempts` runs were made, see [`is_transient`]. The error names `rel` and
+    /// the attempts made, rate limits are passed on as they are.
+    pub(crate) async fn with_retries<T, F, Fut>(
+        attempts: u32,
+        action: &str,
+        rel: &str,
+        mut request: F,
+    ) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut attempt = 1;
+        loop {
+            let e = match request().await {
+                Ok(value) => return Ok(value),
+                Err(e) => e,
+            };
+            if attempt >= attempts || !is_transient(&e) {
+                return Err(download_error(e, action, rel, attempt));
+            }
+            let backoff = DOWNLOAD_RETRY_BACKOFF * 2u32.saturating_pow(attempt - 1);
+            let jitter = backoff.mul_f64(thread_rng().gen_range(0.0..0.5));
+            warn!(
+                "failed to {} {}, retrying in {:.1}s (attempt {} of {}): {}",
+                action,
+                if rel.is_empty() { "the pipe" } else { rel },
+                (backoff + jitter).as_secs_f32(),
+                attempt + 1,
+                attempts,
+                e
+            );
+            tokio::time::sleep(backoff + jitter).await;
+            attempt += 1;
+        }
+    }
+
+    /// `e` with the path below the pipe root that failed, empty for the pipe root, and the
+    /// attempts made when there were more than one. Rate limits are passed on as they are.
+    pub(crate) fn download_error(
+        e: anyhow::Error,
+        action: &str,
+        rel: &str,
+        attempts: u32,
+    ) -> anyhow::Error {
+        if e.is::<GithubRateLimited>() || (rel.is_empty() && attempts == 1) {
+            return e;
+        }
+        let target = if rel.is_empty() { "the pipe" } else { rel };
+        match attempts {
+            1 => anyhow::anyhow!("failed to {} {}: {}", action, target, e),
+            attempts => anyhow::anyhow!(
+                "failed to {} {} after {} attempts: {}",
+                action,
+                target,
+                attempts,
+                e
+            ),
+        }
+    }
+
+    /// A file of a listing: its path in the repo, below the pipe root, and its blob sha.
+    #[derive(Debug, Clone)]
+    pub(crate) struct ListedFile {
+        pub path: String,
+        pub rel: String,
+        pub sha: String,
+    }
+
+    /// Writes `listed` into `dest_dir`, fetching `concurrency` files at once with `fetch`
+    /// and copying those `installed` has. A fetch is made up to `attempts` times, see
+    /// [`with_retries`]. Folders are all created first. The first file that fails stops
+    /// the others, what was written is left to the caller to remove. Each file written is
+    /// reported to `progress`.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) async fn download_listed_files<F, Fut>(
+        listed: Vec<ListedFile>,
+        dest_dir: &Path,
+        installed: &InstalledFiles,
+        concurrency: usize,
+        attempts: u32,
+        progress: Option<&mpsc::Sender<DownloadProgress>>,
+        fetch: F,
+    ) -> Result<PipeFiles>
+    where
+        F: Fn(ListedFile) -> Fut,
+        Fut: Future<Output = Result<Vec<u8>>>,
+    {
+        let folders: std::collections::BTreeSet<PathBuf> = listed
+            .iter()
+            .filter_map(|file| dest_dir.join(&file.rel).parent().map(Path::to_path_buf))
+            .collect();
+        for folder in folders {
+            tokio::fs::create_dir_all(folder).await?;
+        }
+
+        let total_files = listed.len();
+        let mut downloads = futures::stream::iter(listed)
+            .map(|file| async {
+                let dest = dest_dir.join(&file.rel);
+                if let Some(kept) = installed.copy(&file.rel, &file.sha, &dest).await {
+                    debug!("kept unchanged file: {:?}", dest);
+                    return Ok((file.rel, kept));
+                }
+                let content =
+                    with_retries(attempts, "download", &file.rel, || fetch(file.clone())).await?;
+                tokio::fs::write(&dest, &content).await?;
+                debug!("downloaded file: {:?}", dest);
+                anyhow::Ok((file.rel, PipeFile::read(&dest, &file.sha).await?))
+            })
+            .buffer_unordered(concurrency.max(1));
+        let mut files = PipeFiles::default();
+        let mut bytes_downloaded = 0;
+        while let Some(downloaded) = downloads.next().await {
+            let (rel, file) = downloaded?;
+            bytes_downloaded += file.size;
+            if let Some(progress) = progress {
+                // A receiver that went away doesn't stop the download
+                let _ = progress
+                    .send(DownloadProgress::File {
+                        total_files,
+                        files_done: files.files.len() + 1,
+                        file: rel.clone(),
+                        bytes_downloaded,
+                    })
+                    .await;
+            }
+            files.files.insert(rel, file);
+        }
+        Ok(files)
+    }
+
+    /// How far a [`download_pipe_with_progress`] is. The last event is `Completed` or
+    /// `Failed`, the channel closes after it.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum DownloadProgress {
+        /// A file was written. Only github, gitlab and bitbucket pipes are downloaded file
+        /// by file, an archive, a clone or a local copy reports nothing before it completes
+        File {
+            /// Files of the pipe, all listed before the first is fetched
+            total_files: usize,
+            files_done: usize,
+            /// Path of the file below the pipe root
+            file: String,
+            /// Size of the files written so far, kept ones included
+            bytes_downloaded: u64,
+        },
+        /// The pipe is installed at `pipe_dir`
+        Completed { pipe_dir: PathBuf },
+        /// The download failed or its task was aborted, the installed copy is left as it was
+        Failed { error: String },
+    }
+
+    /// Reports a `Failed` download when dropped before it completes, the task running it
+    /// was aborted.
+    struct AbortedDownload(Option<mpsc::Sender<DownloadProgress>>);
+
+    impl Drop for AbortedDownload {
+        fn drop(&mut self) {
+            if let Some(progress) = self.0.take() {
+                let _ = progress.try_send(DownloadProgress::Failed {
+                    error: "the download was aborted".to_string(),
+                });
+            }
+        }
+    }
+
+    /// Folder a pipe is downloaded into, `pipes/.tmp-<pipe>-<random>`, hidden from the
+    /// pipe list. Removed when dropped, on a failed or aborted download, unless it was
+    /// installed.
+    struct PipeDownloadDir {
+        path: PathBuf,
+    }
+
+    /// Attempts at a rename or removal in the pipes folder. On windows a running pipe
+    /// keeps its folder locked for a moment after it is stopped.
+    #[cfg(windows)]
+    const PIPE_DIR_ATTEMPTS: u32 = 5;
+    #[cfg(not(windows))]
+    const PIPE_DIR_ATTEMPTS: u32 = 1;
+
+    impl PipeDownloadDir {
+        async fn create(dest_dir: &Path, pipe_name: &str) -> Result<PipeDownloadDir> {
+            let path = sibling_dir(dest_dir, "tmp", pipe_name);
+            tokio::fs::create_dir_all(&path).await?;
+            Ok(PipeDownloadDir { path })
+        }
+
+        /// Moves the download to `dest_dir`, replacing the installed copy. The installed
+        /// copy is moved aside first and put back when the download can't take its place.
+        async fn install(mut self, dest_dir: &Path) -> Result<()> {
+            let pipe_name = dest_dir
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let old_dir = sibling_dir(dest_dir, "old", &pipe_name);
+            if dest_dir.exists()
+                && retry_pipe_dir(|| tokio::fs::rename(dest_dir, &old_dir))
+                    .await
+                    .is_err()
+            {
+                // Windows refuses to move a folder a process has files open in
+                retry_pipe_dir(|| tokio::fs::remove_dir_all(dest_dir)).await?;
+            }
+            if let Err(e) = retry_pipe_dir(|| tokio::fs::rename(&self.path, dest_dir)).await {
+                if old_dir.exists() {
+                    let _ = tokio::fs::rename(&old_dir, dest_dir).await;
+                }
+                return Err(e.into());
+            }
+            self.path = PathBuf::new();
+            if old_dir.exists() {
+                if let Err(e) = retry_pipe_dir(|| tokio::fs::remove_dir_all(&old_dir)).await {
+                    warn!("failed to remove the previous copy {:?}: {}", old_dir, e);
+                }
+            }
+            Ok(())
+        }
+    }
+
+    impl Drop for PipeDownloadDir {
+        fn drop(&mut self) {
+            if !self.path.as_os_str().is_empty() && self.path.exists() {
+                if let Err(e) = fs::remove_dir_all(&self.path) {
+                    warn!("failed to remove the download {:?}: {}", self.path, e);
+                }
+            }
+        }
+    }
+
+    /// Makes the files `executables` lists executable, for whoever may read them. A copy
+    /// of a local folder or a clone keeps the mode of its files, a download writes them
+    /// with the default one. Nothing changes on windows. Fails on a path that isn't below
+    /// `pipe_dir`.
+    async fn set_executables(pipe_dir: &Path, executables: &[String]) -> Result<()> {
+        for executable in executables {
+            let below_root = Path::new(executable)
+                .components()
+                .all(|component| matches!(component, std::path::Component::Normal(_)));
+            if !below_root || executable.contains('\\') {
+                anyhow::bail!(
+                    "pipe.json: $.executables: '{}' isn't a path below the pipe root",
+                    executable
+                );
+            }
+            let path = pipe_dir.join(executable);
+            // Not a link, what it points to may not be the pipe's
+            let is_file = tokio::fs::symlink_metadata(&path)
+                .await
+                .is_ok_and(|metadata| metadata.is_file());
+            if !is_file {
+                warn!(
+                    "{} of the pipe's executables isn't one of its files",
+                    executable
+                );
+                continue;
+            }
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                let mut permissions = tokio::fs::metadata(&path).await?.permissions();
+                let mode = permissions.mode();
+                // Executable for whoever may read it, as `chmod +x` does
+                permissions.set_mode(mode | ((mode & 0o444) >> 2));
+                tokio::fs::set_permissions(&path, permissions).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// `.<kind>-<pipe>-<random>` next to `dest_dir`.
+    pub(crate) fn sibling_dir(dest_dir: &Path, kind: &str, pipe_name: &str) -> PathBuf {
+        let suffix: String = thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(8)
+            .map(char::from)
+            .collect();
+        dest_dir.with_file_name(format!(".{}-{}-{}", kind, pipe_name, suffix))
+    }
+
+    /// Runs `op` until it succeeds, [`PIPE_DIR_ATTEMPTS`] times at most.
+    async fn retry_pipe_dir<F, Fut>(mut op: F) -> std::io::Result<()>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = std::io::Result<()>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match op().await {
+                Err(e)
+                    if attempt < PIPE_DIR_ATTEMPTS && e.kind() != std::io::ErrorKind::NotFound =>
+                {
+                    debug!("retrying after {}", e);
+                    tokio::time::sleep(Duration::from_millis(100 << attempt)).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Where a pipe is downloaded from, a github, gitlab or bitbucket folder at a ref, or
+    /// a folder on disk. Archives, npm packages and the repos of other git hosts have
+    /// their own sources, see [`download_pipe`].
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum PipeSource {
+        GitHub(GithubSource),
+        GitLab(GitlabSource),
+        Bitbucket(BitbucketSource),
+        Local(PathBuf),
+    }
+
+    impl PipeSource {
+        /// `None` for a url of another host, or of github or bitbucket that isn't a repo
+        /// or a folder in one. Anything that isn't a url is a local path.
+        pub fn parse(source: &str) -> Option<Self> {
+            let Ok(url) = Url::parse(source) else {
+                return Some(Self::Local(PathBuf::from(source)));
+            };
+            match url.host_str() {
+                Some("github.com") => GithubSource::parse(source).map(Self::GitHub),
+                Some(BITBUCKET_HOST) => BitbucketSource::parse(source).map(Self::Bitbucket),
+                _ => GitlabSource::parse(source).map(Self::GitLab),
+            }
+        }
+
+        pub fn kind(&self) -> PipeSourceKind {
+            match self {
+                Self::GitHub(_) => PipeSourceKind::Github,
+                Self::GitLab(_) => PipeSourceKind::Gitlab,
+                Self::Bitbucket(_) => PipeSourceKind::Bitbucket,
+                Self::Local(_) => PipeSourceKind::Local,
+            }
+        }
+    }
+
+    /// The [`PipeSource`] of a url `source`, `None` for the url of a git repo of another
+    /// host, which is cloned.
+    fn parse_remote(source: &str, url: &Url, is_git: bool) -> Result<Option<PipeSource>> {
+        match PipeSource::parse(source) {
+            Some(host) => Ok(Some(host)),
+            None if url.host_str() == Some("github.com") => {
+                anyhow::bail!("Invalid GitHub URL format")
+            }
+            None if url.host_str() == Some(BITBUCKET_HOST) => {
+                anyhow::bail!("Invalid Bitbucket URL format")
+            }
+            None if is_git => Ok(None),
+            None => anyhow::bail!("Unsupported URL format"),
+        }
+    }
+
+    /// The client for the host of `host` and the commit its ref, or `options.git_ref`,
+    /// points to now, `host` is moved to that ref. `None` for a local path. The client is
+    /// `client` unless the host has a token.
+    async fn resolve_remote(
+        host: &mut PipeSource,
+        source: &str,
+        options: &DownloadOptions,
+        client: &Client,
+    ) -> Result<Option<(reqwest::Client, GithubCommit)>> {
+        match host {
+            PipeSource::GitHub(github) => {
+                let client = github_client_for(client, options)?;
+                if let Some(git_ref) = &options.git_ref {
+                    *github = github
+                        .at_ref(&client, GITHUB_API, git_ref)
+                        .await
+                        .map_err(|e| rate_limited_source(e, source))?;
+                }
+                let commit = github
+                    .commit(&client, GITHUB_API)
+                    .await
+                    .map_err(|e| rate_limited_source(e, source))?;
+                Ok(Some((client, commit)))
+            }
+            PipeSource::Bitbucket(bitbucket) => {
+                let client = bitbucket_client_for(client, &options.http)?;
+                if let Some(git_ref) = &options.git_ref {
+                    *bitbucket = bitbucket.at_ref(&client, BITBUCKET_API, git_ref).await?;
+                }
+                let commit = bitbucket.commit(&client, BITBUCKET_API).await?;
+                Ok(Some((client, commit)))
+            }
+            PipeSource::GitLab(gitlab) => {
+                let client = gitlab_client_for(client, &options.http)?;
+                if let Some(git_ref) = &options.git_ref {
+                    *gitlab = gitlab.at_ref(&client, git_ref).await?;
+                }
+                let commit = gitlab.commit(&client).await?;
+                Ok(Some((client, commit)))
+            }
+            PipeSource::Local(_) => Ok(None),
+        }
+    }
+
+    /// What [`update_pipe`] did to a pipe.
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case", tag = "status")]
+    pub enum UpdateResult {
+        /// Its source still points to the commit it was downloaded at, or it is pinned to
+        /// a commit
+        UpToDate,
+        /// Downloaded again, commit `from` replaced by `to`
+        Updated { from: String, to: String },
+        /// Copied from a local path or an archive, there is nothing to check
+        SourceUnknown,
+        /// Behind its source but running, it is left as it is
+        Busy,
+        /// Linked to a folder with [`crate::pipe_link::link_pipe`], it runs the files there
+        Linked,
+    }
+
+    /// Downloads `pipe` again when the ref it was downloaded from, recorded in its
+    /// [`PIPE_LOCK_FILE`], points to another commit now. Its settings and whether it is
+    /// disabled are kept, as with [`download_pipe`].
+    #[deprecated(note = "use PipeManager::update_pipe")]
+    pub async fn update_pipe(pipe: &str, screenpipe_dir: PathBuf) -> Result<UpdateResult> {
+        PipeManager::new(screenpipe_dir, RuntimeConfig::default())?
+            .update_pipe(pipe)
+            .await
+    }
+
+    /// [`update_pipe`], downloading with the token and http settings of `options`. Its ref
+    /// and conflict policy are the update's.
+    #[deprecated(note = "use PipeManager::update_pipe_with")]
+    pub async fn update_pipe_with(
+        pipe: &str,
+        screenpipe_dir: PathBuf,
+        options: DownloadOptions,
+    ) -> Result<UpdateResult> {
+        PipeManager::with_http(screenpipe_dir, &options.http)?
+            .update_pipe_with(pipe, options)
+            .await
+    }
+
+    /// Updates the pipe `pipe` of `screenpipe_dir`, downloading with `options` and
+    /// `client`.
+    pub(crate) async fn update_installed_pipe(
+        pipe: &str,
+        screenpipe_dir: PathBuf,
+        options: DownloadOptions,
+        client: &Client,
+    ) -> Result<UpdateResult> {
+        let pipe_dir = installed_pipe_dir(pipe, &screenpipe_dir)?;
+        if is_linked_pipe(&pipe_dir) {
+            debug!("pipe {} is linked, not updating it", pipe);
+            return Ok(UpdateResult::Linked);
+        }
+        let Some(installed) = downloaded_pipe(&pipe_dir).await else {
+            return Ok(UpdateResult::SourceUnknown);
+        };
+        if is_commit_sha(&installed.commit.git_ref) {
+            return Ok(UpdateResult::UpToDate);
+        }
+
+        let options = DownloadOptions {
+            git_ref: Some(installed.commit.git_ref.clone()),
+            on_conflict: ConflictPolicy::Replace,
+            ..options
+        };
+        let options = with_stored_token(options, &screenpipe_dir).await?;
+        let Some(mut host) = PipeSource::parse(&installed.source) else {
+            return Ok(UpdateResult::SourceUnknown);
+        };
+        let resolved = resolve_remote(&mut host, &installed.source, &options, client);
+        let Some((_, commit)) = with_rate_limit_wait(&options.http, resolved).await? else {
+            return Ok(UpdateResult::SourceUnknown);
+        };
+        if commit.sha == installed.commit.sha {
+            debug!("pipe {} is at {} already", pipe, commit.sha);
+            return Ok(UpdateResult::UpToDate);
+        }
+        if is_pipe_running(pipe) {
+            info!(
+                "pipe {} is running, not updating it to {}",
+                pipe, commit.sha
+            );
+            return Ok(UpdateResult::Busy);
+        }
+
+        info!(
+            "updating pipe {} from {} to {}",
+            pipe, installed.commit.sha, commit.sha
+        );
+        install_pipe(&installed.source, screenpipe_dir, options, client).await?;
+        // The ref may have moved again since it was resolved
+        let to = match downloaded_pipe(&pipe_dir).await {
+            Some(downloaded) => downloaded.commit.sha,
+            None => commit.sha,
+        };
+        Ok(UpdateResult::Updated {
+            from: installed.commit.sha,
+            to,
+        })
+    }
+
+    /// What [`update_all_pipes`] did to one pipe.
+    #[derive(Debug)]
+    pub struct PipeUpdate {
+        pub id: String,
+        pub result: Result<UpdateResult>,
+    }
+
+    /// [`update_pipe`] for each installed pipe, by name. A pipe failing to update doesn't
+    /// stop the others.
+    #[deprecated(note = "use PipeManager::update_all_pipes")]
+    pub async fn update_all_pipes(screenpipe_dir: PathBuf) -> Result<Vec<PipeUpdate>> {
+        PipeManager::new(screenpipe_dir, RuntimeConfig::default())?
+            .update_all_pipes()
+            .await
+    }
+
+    /// [`update_all_pipes`] with [`update_pipe_with`].
+    #[deprecated(note = "use PipeManager::update_all_pipes_with")]
+    pub async fn update_all_pipes_with(
+        screenpipe_dir: PathBuf,
+        options: DownloadOptions,
+    ) -> Result<Vec<PipeUpdate>> {
+        PipeManager::with_http(screenpipe_dir, &options.http)?
+            .update_all_pipes_with(options)
+            .await
+    }
+
+    /// Updates each pipe of `screenpipe_dir`, downloading with `options` and `client`.
+    pub(crate) async fn update_installed_pipes(
+        screenpipe_dir: PathBuf,
+        options: DownloadOptions,
+        client: &Client,
+    ) -> Result<Vec<PipeUpdate>> {
+        let mut updates = Vec::new();
+        for pipe in installed_pipes(&screenpipe_dir).await? {
+            let result =
+                update_installed_pipe(&pipe.id, screenpipe_dir.clone(), options.clone(), client)
+                    .await;
+            if let Err(e) = &result {
+                warn!("failed to update pipe {}: {}", pipe.id, e);
+            }
+            updates.push(PipeUpdate {
+                id: pipe.id,
+                result,
+            });
+        }
+        Ok(updates)
+    }
+
+    /// What [`download_pipe_with`] does when the pipe is installed already.
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum OverwritePolicy {
+        /// Keep the installed copy, nothing is downloaded and its path is returned
+        Skip,
+        /// Replace the installed copy once the download succeeded, the settings of its
+        /// pipe.json are kept
+        #[default]
+        Overwrite,
+        /// Fail, the installed copy is left as it is
+        ErrorIfExists,
+    }
+
+    /// What [`download_pipe_with`] does when the pipe installed under the name of the
+    /// download is from another source, or from one it has no record of, see
+    /// [`crate::pipe_metadata`]. A pipe from the same source is replaced.
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum ConflictPolicy {
+        /// Fail, the installed pipe is left as it is
+        #[default]
+        Error,
+        /// Install it under its name followed by the github owner, gitlab group or
+        /// bitbucket workspace of its source, `notes-bob`, else by a number, `notes-2`
+        Rename,
+        /// Replace the installed pipe, as updates of it do
+        Replace,
+    }
+
+    /// What copying a pipe from a local path or a git clone does with its symlinks.
+    /// Broken ones are skipped whatever the policy.
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum SymlinkPolicy {
+        /// Leave them out of the installed pipe
+        #[default]
+        Skip,
+        /// Copy what they point to, a folder linking back to one it is in is skipped
+        Follow,
+        /// Copy them as links, to the same target
+        CopyAsLink,
+    }
+
+    /// How [`download_pipe_with`] downloads a pipe.
+    #[derive(Clone, Default, PartialEq, Eq)]
+    pub struct DownloadOptions {
+        /// Keep the installed copy of a github pipe when its [`PIPE_LOCK_FILE`] has the
+        /// commit its source points to now
+        pub locked: bool,
+        /// Github token for pipes in private repos, [`GITHUB_TOKEN_ENV`] or the one set
+        /// with [`store_github_token`] when `None`
+        pub token: Option<String>,
+        /// Fetch every file of a github pipe, none is copied from the installed one
+        pub force: bool,
+        /// Branch, tag or commit to download the pipe at, in place of the ref of its url.
+        /// Its commit is recorded in the [`PIPE_LOCK_FILE`] of a github or gitlab pipe
+        pub git_ref: Option<String>,
+        /// Sha256 of each file, by path below the pipe root, see [`verify_pipe_checksums`].
+        /// The source's [`PIPE_CHECKSUMS_FILE`] when `None`, nothing is checked without one
+        pub checksums: Option<HashMap<String, String>>,
+        /// Files of a github or gitlab pipe fetched at once,
+        /// [`DEFAULT_DOWNLOAD_CONCURRENCY`] when `None`
+        pub concurrency: Option<usize>,
+        /// Requests made for each file of a github, gitlab or bitbucket pipe, and for its
+        /// listing, before a timeout, dropped connection or 5xx fails the download,
+        /// [`DEFAULT_DOWNLOAD_ATTEMPTS`] when `None`
+        pub max_attempts: Option<u32>,
+        /// Fail when a file of the installed pipe changed in the download but its version
+        /// didn't, see [`PipeIntegrity::check_update`]
+        pub verify_integrity: bool,
+        /// What happens to a pipe installed already, [`OverwritePolicy::Overwrite`] by
+        /// default
+        pub overwrite: OverwritePolicy,
+        /// Index a source that is the name of a pipe is looked up in,
+        /// [`RegistryOptions::default`] when `None`
+        pub registry: Option<RegistryOptions>,
+        /// What becomes of the symlinks of a pipe copied from a local path or a git clone
+        pub symlinks: SymlinkPolicy,
+        /// What happens when the pipe installed under the same name is from another source,
+        /// for [`OverwritePolicy::Overwrite`]
+        pub on_conflict: ConflictPolicy,
+        /// Install a pipe [`run_pipe`] can't start, for one started otherwise, see
+        /// [`PipeValidationError`]
+        pub skip_validation: bool,
+        /// Timeout and proxy of every request of the download
+        pub http: HttpOptions,
+    }
+
+    /// Settings of the http clients pipes are downloaded with.
+    #[derive(Debug, Clone, Default, PartialEq, Eq)]
+    pub struct HttpOptions {
+        /// Time each request may take, from connecting to reading the whole body. No
+        /// limit when `None`
+        pub timeout: Option<Duration>,
+        /// Url of the proxy every request goes through, the `HTTPS_PROXY` and `HTTP_PROXY`
+        /// of the environment when `None`
+        pub proxy: Option<String>,
+        /// Longest wait for a github rate limit to reset before a request fails,
+        /// [`MAX_RATE_LIMIT_WAIT`] when `None`. `Some(Duration::ZERO)` fails it right away
+        pub max_rate_limit_wait: Option<Duration>,
+    }
+
+    impl HttpOptions {
+        /// A client builder with these settings, for a client of its own headers.
+        pub fn client_builder(&self) -> Result<reqwest::ClientBuilder> {
+            let mut builder = Client::builder();
+            if let Some(timeout) = self.timeout {
+                builder = builder.timeout(timeout);
+            }
+            if let Some(proxy) = &self.proxy {
+                let proxy = reqwest::Proxy::all(proxy)
+                    .map_err(|e| anyhow::anyhow!("invalid proxy {}: {}", proxy, e))?;
+                builder = builder.proxy(proxy);
+            }
+            Ok(builder)
+        }
+
+        pub fn client(&self) -> Result<Client> {
+            Ok(self.client_builder()?.build()?)
+        }
+    }
+
+    // Not derived, the token stays out of logs
+    impl std::fmt::Debug for DownloadOptions {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.debug_struct("DownloadOptions")
+                .field("locked", &self.locked)
+                .field("force", &self.force)
+                .field("git_ref", &self.git_ref)
+                .field("checksums", &self.checksums.as_ref().map(HashMap::len))
+                .field("concurrency", &self.concurrency)
+                .field("max_attempts", &self.max_attempts)
+                .field("verify_integrity", &self.verify_integrity)
+                .field("overwrite", &self.overwrite)
+                .field("registry", &self.registry)
+                .field("symlinks", &self.symlinks)
+                .field("on_conflict", &self.on_conflict)
+                .field("skip_validation", &self.skip_validation)
+                .field("http", &self.http)
+                .field("token", &self.token.as_ref().map(|_| "<redacted>"))
+                .finish()
+        }
+    }
+
+    /// A downloaded pipe [`run_pipe`] can't start, in the error of [`download_pipe`] and the
+    /// like. It isn't installed, a copy installed before is kept.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct PipeValidationError {
+        pub pipe: String,
+        /// What it is missing
+        pub reason: String,
+    }
+
+    impl std::fmt::Display for PipeValidationError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(
+                f,
+                "pipe {} can't run: {}, install it with skip_validation if it runs otherwise",
+                self.pipe, self.reason
+            )
+        }
+    }
+
+    impl std::error::Error for PipeValidationError {}
+
+    #[deprecated(note = "use PipeManager::download_pipe")]
+    pub async fn download_pipe(source: &str, screenpipe_dir: PathBuf) -> anyhow::Result<PathBuf> {
+        PipeManager::new(screenpipe_dir, RuntimeConfig::default())?
+            .download_pipe(source)
+            .await
+    }
+
+    /// [`download_pipe`], sending the [`DownloadProgress`] of each file written to `tx`
+    /// and a `Completed` or `Failed` event once done, so a ui can close its progress bar.
+    /// The download goes on when the receiver is dropped.
+    ///
+    /// ```ignore
+    /// let (tx, mut rx) = tokio::sync::mpsc::channel(64);
+    /// let download = tokio::spawn(download_pipe_with_progress(url, screenpipe_dir, tx));
+    /// let bar = indicatif::ProgressBar::new(0);
+    /// while let Some(progress) = rx.recv().await {
+    ///     match progress {
+    ///         DownloadProgress::File { total_files, files_done, file, .. } => {
+    ///             bar.set_length(total_files as u64);
+    ///             bar.set_position(files_done as u64);
+    ///             bar.set_message(file);
+    ///         }
+    ///         DownloadProgress::Completed { .. } => bar.finish_with_message("installed"),
+    ///         DownloadProgress::Failed { error } => bar.abandon_with_message(error),
+    ///     }
+    /// }
+    /// let pipe_dir = download.await??;
+    /// ```
+    #[deprecated(note = "use PipeManager::download_pipe_with_progress")]
+    pub async fn download_pipe_with_progress(
+        source: &str,
+        screenpipe_dir: PathBuf,
+        tx: mpsc::Sender<DownloadProgress>,
+    ) -> anyhow::Result<PathBuf> {
+        PipeManager::new(screenpipe_dir, RuntimeConfig::default())?
+            .download_pipe_with_progress(source, DownloadOptions::default(), tx)
+            .await
+    }
+
+    /// [`download_pipe_with_progress`] with `options` and `client`.
+    pub(crate) async fn download_with_progress(
+        source: &str,
+        screenpipe_dir: PathBuf,
+        options: DownloadOptions,
+        client: &Client,
+        tx: mpsc::Sender<DownloadProgress>,
+    ) -> anyhow::Result<PathBuf> {
+        let mut aborted = AbortedDownload(Some(tx.clone()));
+        let downloaded =
+            download_pipe_reporting(source, screenpipe_dir, options, client, Some(&tx)).await;
+        let done = match &downloaded {
+            Ok(pipe_dir) => DownloadProgress::Completed {
+                pipe_dir: pipe_dir.clone(),
+            },
+            Err(e) => DownloadProgress::Failed {
+                error: e.to_string(),
+            },
+        };
+        let _ = tx.send(done).await;
+        aborted.0 = None;
+        downloaded
+    }
+
+    #[deprecated(note = "use PipeManager::download_pipe_with")]
+    pub async fn download_pipe_with(
+        source: &str,
+        screenpipe_dir: PathBuf,
+        options: DownloadOptions,
+    ) -> anyhow::Result<PathBuf> {
+        PipeManager::with_http(screenpipe_dir, &options.http)?
+            .download_pipe_with(source, options)
+            .await
+    }
+
+    /// Downloads the pipe at `source` to `screenpipe_dir` with `options`. Requests sending
+    /// no token go through `client`.
+    pub(crate) async fn install_pipe(
+        source: &str,
+        screenpipe_dir: PathBuf,
+        options: DownloadOptions,
+        client: &Client,
+    ) -> anyhow::Result<PathBuf> {
+        download_pipe_reporting(source, screenpipe_dir, options, client, None).await
+    }
+
+    async fn download_pipe_reporting(
+        source: &str,
+        screenpipe_dir: PathBuf,
+        options: DownloadOptions,
+        client: &Client,
+        progress: Option<&mpsc::Sender<DownloadProgress>>,
+    ) -> anyhow::Result<PathBuf> {
+        info!("Processing pipe from source: {}", source);
+        let options = with_stored_token(options, &screenpipe_dir).await?;
+
+        let mut pipe_name = pipe_id_from_source(source)
+            .ok_or_else(|| anyhow::anyhow!("invalid pipe source: {}", source))?;
+        let mut dest_dir = screenpipe_dir.join("pipes").join(&pipe_name);
+
+        debug!("Destination directory: {:?}", dest_dir);
+
+        if dest_dir.exists() {
+            match options.overwrite {
+                OverwritePolicy::Skip => {
+                    info!(
+                        "pipe {} is installed already, reusing {:?}",
+                        pipe_name, dest_dir
+                    );
+                    return Ok(dest_dir);
+                }
+                OverwritePolicy::ErrorIfExists => {
+                    anyhow::bail!("pipe {} is installed already at {:?}", pipe_name, dest_dir)
+                }
+                OverwritePolicy::Overwrite => {}
+            }
+        }
+
+        // A pipe of the registry is installed under its name, from the source it lists
+        let listed = registry_source(source, &screenpipe_dir, &options, client).await?;
+        let source = listed.as_deref().unwrap_or(source);
+        let expanded = expand_github_shorthand(source);
+        let source = expanded.as_deref().unwrap_or(source);
+
+        // A github, gitlab or bitbucket source is resolved to a commit first, the files are
+        // downloaded from it
+        let archive = ArchiveKind::from_source(source);
+        let npm = NpmSource::parse(source);
+        let mut git = GitSource::parse(source);
+        let mut host = match Url::parse(source) {
+            _ if archive.is_some() || npm.is_some() => None,
+            Ok(url) => parse_remote(source, &url, git.is_some())?,
+            // Scp-like git urls aren't urls either
+            Err(_) if git.is_some() => None,
+            Err(_) => PipeSource::parse(source),
+        };
+        let remote = match &mut host {
+            Some(host) => {
+                let resolved = resolve_remote(host, source, &options, client);
+                with_rate_limit_wait(&options.http, resolved).await?
+            }
+            None => None,
+        };
+        match (&options.git_ref, &mut git) {
+            (Some(git_ref), Some(git)) if remote.is_none() && archive.is_none() => {
+                git.branch = Some(git_ref.clone())
+            }
+            (Some(_), _) if remote.is_none() => anyhow::bail!(
+                "{} has no refs, a ref is only given for pipes from github, gitlab, bitbucket or git",
+                source
+            ),
+            _ => {}
+        }
 
-        let pipe_name =
-            sanitize_pipe_name(Path::new(source).file_name().unwrap().to_str().unwrap());
-        let dest_dir = screenpipe_dir.join("pipes").join(&pipe_name);
+        // A pipe of another source installed under the same name is kept, see `on_conflict`
+        if dest_dir.exists() && options.overwrite == OverwritePolicy::Overwrite {
+            pipe_name =
+                resolve_name_conflict(&pipe_name, source, &screenpipe_dir, options.on_conflict)?;
+            dest_dir = screenpipe_dir.join("pipes").join(&pipe_name);
+        }
 
-        debug!("Destination directory: {:?}", dest_dir);
+        // A pipe pinned to a commit is downloaded once, one on a branch with `locked`
+        let pinned = matches!(&remote, Some((_, commit)) if is_commit_sha(&commit.git_ref));
+        let keep_installed = options.locked || (pinned && !options.force);
+        if let (true, Some((_, commit))) = (keep_installed, &remote) {
+            let installed = downloaded_pipe(&dest_dir).await;
+            if installed.is_some_and(|i| i.source == source && i.commit.sha == commit.sha) {
+                info!(
+                    "pipe {} is at {} already, keeping the installed copy",
+                    pipe_name, commit.sha
+                );
+                return Ok(dest_dir);
+            }
+        }
 
         // Save existing pipe.json content before downloading
         let pipe_json_path = dest_dir.join("pipe.json");
         let existing_config = if pipe_json_path.exists() {
             debug!("Existing pipe.json found");
-            let content = tokio::fs::read_to_string(&pipe_json_path).await?;
-            Some(serde_json::from_str::<Value>(&content)?)
+            Some(load_config(&pipe_json_path).await?)
         } else {
             debug!("No existing pipe.json found");
             None
         };
 
-        // Create temp directory for download
-        let temp_dir = dest_dir.with_extension("_temp");
-        tokio::fs::create_dir_all(&temp_dir).await?;
+        // Everything is downloaded next to the installed copy first, which is only
+        // replaced once the download is complete and checked
+        let download_dir = PipeDownloadDir::create(&dest_dir, &pipe_name).await?;
+        let temp_dir = download_dir.path.clone();
 
         // Download to temp directory first
-        let download_result = if let Ok(parsed_url) = Url::parse(source) {
-            debug!("Source is a URL: {}", parsed_url);
-            if parsed_url.host_str() == Some("github.com") {
-                download_github_folder(&parsed_url, &temp_dir).await
-            } else {
-                anyhow::bail!("Unsupported URL format");
-            }
+        let concurrency = options.concurrency.unwrap_or(DEFAULT_DOWNLOAD_CONCURRENCY);
+        let attempts = options.max_attempts.unwrap_or(DEFAULT_DOWNLOAD_ATTEMPTS);
+        let installed = if options.force || remote.is_none() {
+            InstalledFiles::default()
         } else {
-            debug!("Source is a local path");
-            let source_path = Path::new(source);
-            if !source_path.exists() || !source_path.is_dir() {
-                anyhow::bail!("Invalid local source path");
+            InstalledFiles::load(&dest_dir).await
+        };
+        let installed = Arc::new(installed);
+        let download_result = match (&host, &remote) {
+            (Some(PipeSource::GitHub(github)), Some((client, commit))) => {
+                info!(
+                    "downloading {}/{} at {} ({})",
+                    github.owner, github.repo, commit.git_ref, commit.sha
+                );
+                let download = download_github_source_with(
+                    client,
+                    github,
+                    commit,
+                    &temp_dir,
+                    GITHUB_API,
+                    GITHUB_RAW,
+                    installed,
+                    concurrency,
+                    attempts,
+                    progress,
+                );
+                with_rate_limit_wait(&options.http, download)
+                    .await
+                    .map(|files| {
+                        let sha = Some(commit.sha.clone());
+                        (PipeSourceKind::Github, sha, Some(files))
+                    })
+            }
+            (Some(PipeSource::GitLab(gitlab)), Some((client, commit))) => {
+                info!(
+                    "downloading {} at {} ({})",
+                    gitlab.project, commit.git_ref, commit.sha
+                );
+                gitlab
+                    .download(
+                        client,
+                        commit,
+                        &temp_dir,
+                        installed,
+                        concurrency,
+                        attempts,
+                        progress,
+                    )
+                    .await
+                    .map(|files| {
+                        let sha = Some(commit.sha.clone());
+                        (PipeSourceKind::Gitlab, sha, Some(files))
+                    })
+            }
+            (Some(PipeSource::Bitbucket(bitbucket)), Some((client, commit))) => {
+                info!(
+                    "downloading {}/{} at {} ({})",
+                    bitbucket.workspace, bitbucket.repo, commit.git_ref, commit.sha
+                );
+                bitbucket
+                    .download(
+                        client,
+                        BITBUCKET_API,
+                        commit,
+                        &temp_dir,
+                        installed,
+                        concurrency,
+                        attempts,
+                        progress,
+                    )
+                    .await
+                    .map(|files| {
+                        let sha = Some(commit.sha.clone());
+                        (PipeSourceKind::Bitbucket, sha, Some(files))
+                    })
+            }
+            (Some(PipeSource::Local(source_path)), _) => {
+                debug!("Source is a local path");
+                if !source_path.exists() || !source_path.is_dir() {
+                    anyhow::bail!("Invalid local source path");
+                }
+                copy_dir_all(source_path, &temp_dir, options.symlinks)
+                    .await
+                    .map(|()| (PipeSourceKind::Local, None, None))
             }
-            copy_dir_all(source_path, &temp_dir).await
+            _ if archive.is_some() => {
+                info!("downloading the pipe archive {}", source);
+                download_archive(client, source, &temp_dir)
+                    .await
+                    .map(|()| (PipeSourceKind::Archive, None, None))
+            }
+            _ => match (&npm, &git) {
+                (Some(npm), _) => {
+                    info!("downloading {} from npm", npm.package);
+                    npm.download(client, &npm_registry(), &temp_dir)
+                        .await
+                        .map(|version| {
+                            info!("downloaded {}@{}", npm.package, version.version);
+                            (PipeSourceKind::Npm, Some(version.version), None)
+                        })
+                }
+                (None, Some(git)) => {
+                    info!("cloning {} with git", git.url);
+                    git.download(&temp_dir, options.symlinks)
+                        .await
+                        .map(|sha| (PipeSourceKind::Git, Some(sha), None))
+                }
+                (None, None) => anyhow::bail!("Unsupported URL format"),
+            },
         };
 
-        // remove temp dir if download failed
-        if let Err(e) = download_result {
-            tokio::fs::remove_dir_all(&temp_dir).await?;
-            error!("Failed to download pipe: {}", e);
+        let (kind, resolved_ref, files) = match download_result {
+            Ok(downloaded) => downloaded,
+            Err(e) => {
+                error!("Failed to download pipe: {}", e);
+                return Err(rate_limited_source(e, source));
+            }
+        };
+        // What a host listed, the next download copies the files that didn't change
+        if let Some(files) = files {
+            files.save(&temp_dir).await?;
         }
 
-        // If download successful, move temp dir to final location
-        if dest_dir.exists() {
-            tokio::fs::remove_dir_all(&dest_dir).await?;
+        // Nor does one with a file that isn't the one published
+        let verified = async {
+            if let Some(checksums) = pipe_checksums(&temp_dir, &options).await? {
+                let dir = temp_dir.clone();
+                tokio::task::spawn_blocking(move || verify_pipe_checksums(&dir, &checksums))
+                    .await??;
+            }
+            anyhow::Ok(())
         }
-        tokio::fs::rename(&temp_dir, &dest_dir).await?;
+        .await;
+        if let Err(e) = verified {
+            error!("pipe checksums don't match: {}", e);
+            return Err(e);
+        }
+
+        // A pipe with a broken manifest doesn't replace the installed copy
+        let manifest = match validate_pipe_manifest(&temp_dir).await {
+            Ok(manifest) => manifest,
+            Err(_) if temp_dir.join(PIPE_BUNDLE_FILE).exists() => anyhow::bail!(
+                "{} is a bundle of pipes, install the ones its {} lists with download_pipe_bundle",
+                source,
+                PIPE_BUNDLE_FILE
+            ),
+            Err(e) => {
+                error!("invalid pipe manifest: {}", e);
+                return Err(e);
+            }
+        };
+
+        // Nor does one that wouldn't start
+        if !options.skip_validation {
+            if let Err(e) = validate_downloaded_pipe(&pipe_name, &temp_dir).await {
+                error!("{}", e);
+                return Err(e.into());
+            }
+        }
+
+        // The files as downloaded, before the installed pipe.json is merged in
+        let integrity = {
+            let (temp_dir, dest_dir) = (temp_dir.clone(), dest_dir.clone());
+            let verify_integrity = options.verify_integrity;
+            tokio::task::spawn_blocking(move || {
+                let integrity = PipeIntegrity::compute(&temp_dir)?;
+                if verify_integrity {
+                    if let Some(installed) = PipeIntegrity::load(&dest_dir)? {
+                        installed.check_update(&integrity)?;
+                    }
+                }
+                anyhow::Ok(integrity)
+            })
+            .await?
+        };
+        let integrity = match integrity {
+            Ok(integrity) => integrity,
+            Err(e) => {
+                error!("pipe integrity check failed: {}", e);
+                return Err(e);
+            }
+        };
+
+        // Generated, it isn't one of the files downloaded
+        write_pipe_config_schema(&temp_dir, &manifest.fields).await?;
+        set_executables(&temp_dir, &manifest.executables).await?;
+
+        // In place of the lock a copy of a downloaded pipe has, it isn't at that commit
+        PipeLock {
+            commit: remote.as_ref().map(|(_, commit)| commit.clone()),
+            integrity: Some(integrity),
+            ..PipeLock::new(source, kind, resolved_ref)
+        }
+        .save(&temp_dir)
+        .await?;
 
         // Restore or merge pipe.json if needed
         if let Some(ref existing_config) = existing_config {
-            let new_config_path = dest_dir.join("pipe.json");
+            let new_config_path = temp_dir.join("pipe.json");
             if new_config_path.exists() {
-                let content = tokio::fs::read_to_string(&new_config_path).await?;
-                let new_json: Value = serde_json::from_str(&content)?;
+                let new_json = load_config(&new_config_path).await?;
 
                 // Create merged config
                 let mut merged_config = new_json.clone(); // Start with new schema
@@ -358,9 +2681,10 @@ mod pipes {
                 if let (Some(existing_obj), Some(new_obj)) =
                     (existing_config.as_object(), merged_config.as_object_mut())
                 {
-                    // Copy over non-fields properties from existing config
+                    // Copy over non-fields properties from existing config, the version
+                    // is the downloaded one's
                     for (key, value) in existing_obj {
-                        if key != "fields" {
+                        if key != "fields" && key != "version" {
                             new_obj.insert(key.clone(), value.clone());
                         }
                     }
@@ -399,44 +2723,549 @@ mod pipes {
             }
         }
 
-        // After downloading/copying the pipe, check if it's a Next.js project
-        let package_json_path = dest_dir.join("package.json");
+        // A pipe whose dependencies fail to install doesn't replace the installed copy
+        let package_json_path = temp_dir.join("package.json");
         if package_json_path.exists() {
-            let package_json = tokio::fs::read_to_string(&package_json_path).await?;
-            let package_data: Value = serde_json::from_str(&package_json)?;
+            let package_data = load_config(&package_json_path).await?;
 
             let bun_path = find_bun_path().ok_or_else(|| anyhow::anyhow!("bun not found"))?;
 
             // Make bun install mandatory for all package.json pipes with retries
-            retry_install(&bun_path, &dest_dir, 3).await?;
+            retry_install(&bun_path, &temp_dir, 3).await?;
 
             if package_data["dependencies"].get("next").is_some() {
                 info!("Detected Next.js project, setting up for production");
                 // Update pipe.json to indicate it's a Next.js project
-                let mut pipe_config = if let Some(existing_json) = &existing_config {
-                    existing_json.clone()
-                } else if pipe_json_path.exists() {
-                    let pipe_json = tokio::fs::read_to_string(&pipe_json_path).await?;
-                    serde_json::from_str(&pipe_json)?
+                let new_config_path = temp_dir.join("pipe.json");
+                let mut pipe_config = if new_config_path.exists() {
+                    load_config(&new_config_path).await?
                 } else {
                     json!({})
                 };
 
                 pipe_config["is_nextjs"] = json!(true);
                 let updated_pipe_json = serde_json::to_string_pretty(&pipe_config)?;
-                let mut file = File::create(&pipe_json_path).await?;
+                let mut file = File::create(&new_config_path).await?;
                 file.write_all(updated_pipe_json.as_bytes()).await?;
             }
         }
 
+        // A disabled pipe stays so once updated
+        if is_pipe_disabled(&dest_dir) {
+            tokio::fs::write(temp_dir.join(PIPE_DISABLED_FILE), b"").await?;
+        }
+        // Nor are its runs forgotten
+        keep_pipe_stats(&dest_dir, &temp_dir).await?;
+        download_dir.install(&dest_dir).await?;
+
         info!("pipe copied successfully to: {:?}", dest_dir);
         Ok(dest_dir)
     }
 
-    async fn copy_dir_all(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> anyhow::Result<()> {
+    /// Fails when [`run_pipe`] wouldn't find what to start in `pipe_dir`, a pipe.ts or
+    /// pipe.js, or a next.js app. Its manifest is checked already, its runtime with it, and
+    /// a deno pipe without a deno.json runs with deno's defaults.
+    async fn validate_downloaded_pipe(
+        pipe: &str,
+        pipe_dir: &Path,
+    ) -> Result<(), PipeValidationError> {
+        if find_pipe_file(pipe_dir).is_ok() {
+            return Ok(());
+        }
+        let nextjs = load_config(&pipe_dir.join("package.json"))
+            .await
+            .is_ok_and(|package| package["dependencies"].get("next").is_some());
+        if nextjs {
+            return Ok(());
+        }
+        Err(PipeValidationError {
+            pipe: pipe.to_string(),
+            reason: "it has no pipe.ts or pipe.js at its root, nor a package.json depending \
+                     on next"
+                .to_string(),
+        })
+    }
+
+    /// Name a pipe from `source` is installed under when `pipe_name` is installed already,
+    /// as `on_conflict` says.
+    fn resolve_name_conflict(
+        pipe_name: &str,
+        source: &str,
+        screenpipe_dir: &Path,
+        on_conflict: ConflictPolicy,
+    ) -> Result<String> {
+        let pipes_dir = screenpipe_dir.join("pipes");
+        let installed = installed_from(&pipes_dir.join(pipe_name));
+        if on_conflict == ConflictPolicy::Replace
+            || installed.as_deref().is_some_and(|i| same_source(i, source))
+        {
+            return Ok(pipe_name.to_string());
+        }
+        let installed = installed.unwrap_or_else(|| "a source it has no record of".to_string());
+        if on_conflict == ConflictPolicy::Error {
+            anyhow::bail!(
+                "pipe {} is installed already from {}, not from {}. rename or replace it with \
+                 on_conflict",
+                pipe_name,
+                installed,
+                source
+            );
+        }
+
+        let owner = source_owner(source)
+            .map(|owner| sanitize_pipe_name(&format!("{}-{}", pipe_name, owner)));
+        let numbered = (2..100).map(|n| format!("{}-{}", pipe_name, n));
+        for candidate in owner.into_iter().chain(numbered) {
+            let dir = pipes_dir.join(&candidate);
+            if !dir.exists() || installed_from(&dir).is_some_and(|i| same_source(&i, source)) {
+                info!(
+                    "pipe {} is installed already from {}, installing {} as {}",
+                    pipe_name, installed, source, candidate
+                );
+                return Ok(candidate);
+            }
+        }
+        anyhow::bail!("no free name to install {} under", source)
+    }
+
+    /// The source the pipe in `pipe_dir` was downloaded from, `None` without a record.
+    fn installed_from(pipe_dir: &Path) -> Option<String> {
+        PipeLock::load(pipe_dir).ok()?.map(|lock| lock.source)
+    }
+
+    /// Sources at another ref of the same repo are the same, and local paths are compared
+    /// once resolved, `./notes` is the same as `/home/me/notes`.
+    fn same_source(a: &str, b: &str) -> bool {
+        if a == b {
+            return true;
+        }
+        if let (Some(a), Some(b)) = (repo_of(a), repo_of(b)) {
+            return a == b;
+        }
+        matches!(
+            (fs::canonicalize(a), fs::canonicalize(b)),
+            (Ok(a), Ok(b)) if a == b
+        )
+    }
+
+    /// The repo, and folder of it for git, `source` downloads a pipe from, without a ref.
+    fn repo_of(source: &str) -> Option<String> {
+        if let Some(github) = GithubSource::parse(source) {
+            return Some(format!("github.com/{}/{}", github.owner, github.repo));
+        }
+        if let Some(gitlab) = GitlabSource::parse(source) {
+            return Some(format!("{}/{}", gitlab.base_url, gitlab.project));
+        }
+        if let Some(bitbucket) = BitbucketSource::parse(source) {
+            return Some(format!(
+                "bitbucket.org/{}/{}",
+                bitbucket.workspace, bitbucket.repo
+            ));
+        }
+        GitSource::parse(source).map(|git| format!("{}:{}", git.url, git.folder))
+    }
+
+    /// Who publishes `source`: its github owner, gitlab group or bitbucket workspace.
+    fn source_owner(source: &str) -> Option<String> {
+        if let Some(github) = GithubSource::parse(source) {
+            return Some(github.owner);
+        }
+        if let Some(gitlab) = GitlabSource::parse(source) {
+            return gitlab.project.split('/').next().map(str::to_string);
+        }
+        BitbucketSource::parse(source).map(|bitbucket| bitbucket.workspace)
+    }
+
+    /// The source the pipe registry lists for `source` when it is the name of a pipe
+    /// rather than a url or a local path.
+    async fn registry_source(
+        source: &str,
+        screenpipe_dir: &Path,
+        options: &DownloadOptions,
+        client: &Client,
+    ) -> Result<Option<String>> {
+        if !is_registry_name(source) || Path::new(source).exists() {
+            return Ok(None);
+        }
+        let registry_options = options.registry.clone().unwrap_or_default();
+        let registry = PipeRegistry::load(client, screenpipe_dir, &registry_options)
+            .await
+            .map_err(|e| {
+                anyhow::anyhow!(
+                    "{} isn't a url or a local path, and the pipe registry can't be loaded: {}",
+                    source,
+                    e
+                )
+            })?;
+        match registry.find(source) {
+            Some(entry) => {
+                info!(
+                    "installing {} of the pipe registry from {}",
+                    entry.name, entry.source
+                );
+                Ok(Some(entry.source.clone()))
+            }
+            None => anyhow::bail!(
+                "{} isn't a url, a local path or a pipe of the registry",
+                source
+            ),
+        }
+    }
+
+    /// A version of an installed pipe newer than its own, at the source it was installed
+    /// from.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct PipeUpdateInfo {
+        pub current: semver::Version,
+        pub latest: semver::Version,
+        pub source: String,
+    }
+
+    /// Manifests a newer version is read from, the version is in either.
+    const MANIFEST_FILES: [&str; 2] = ["pipe.json", "package.json"];
+
+    /// The version of the pipe at the source `pipe_dir` was installed from when it is
+    /// newer than the installed one. Versions are read from pipe.json, or package.json,
+    /// and compared as semver. Fails for pipes without a source or a version.
+    #[deprecated(note = "use PipeManager::check_pipe_update")]
+    pub async fn check_pipe_update(
+        pipe_dir: &Path,
+        client: &Client,
+    ) -> Result<Option<PipeUpdateInfo>> {
+        fetch_pipe_update(pipe_dir, client, GITHUB_API, GITHUB_RAW).await
+    }
+
+    /// [`check_pipe_update`], with github at `api` and `raw`.
+    #[deprecated(note = "use PipeManager::check_pipe_update")]
+    pub async fn check_pipe_update_with(
+        pipe_dir: &Path,
+        client: &Client,
+        api: &str,
+        raw: &str,
+    ) -> Result<Option<PipeUpdateInfo>> {
+        fetch_pipe_update(pipe_dir, client, api, raw).await
+    }
+
+    /// [`check_pipe_update`] with github at `api` and `raw`.
+    pub(crate) async fn fetch_pipe_update(
+        pipe_dir: &Path,
+        client: &Client,
+        api: &str,
+        raw: &str,
+    ) -> Result<Option<PipeUpdateInfo>> {
+        let source = installed_source(pipe_dir).await.ok_or_else(|| {
+            anyhow::anyhow!("{}: pipe has no source to update from", pipe_dir.display())
+        })?;
+        let current = manifest_version(pipe_dir).await?;
+
+        let fetched = tempfile::tempdir()?;
+        let latest_dir = match fetch_manifests(client, &source, fetched.path(), api, raw)
+            .await
+            .map_err(|e| rate_limited_source(e, &source))?
+        {
+            Some(local) => local,
+            None => fetched.path().to_path_buf(),
+        };
+        let latest = manifest_version(&latest_dir)
+            .await
+            .map_err(|e| anyhow::anyhow!("latest version of {}: {}", source, e))?;
+
+        debug!(
+            "{} is at {}, {} has {}",
+            pipe_dir.display(),
+            current,
+            source,
+            latest
+        );
+        Ok((latest > current).then_some(PipeUpdateInfo {
+            current,
+            latest,
+            source,
+        }))
+    }
+
+    /// Downloads the pipe in `pipe_dir` again from its source when that has a newer
+    /// version, every file is fetched. [`update_pipe`] goes by commit instead.
+    #[deprecated(note = "use PipeManager::update_pipe_version")]
+    pub async fn update_pipe_version(
+        pipe_dir: &Path,
+        screenpipe_dir: &Path,
+        client: &Client,
+    ) -> Result<()> {
+        let update = fetch_pipe_update(pipe_dir, client, GITHUB_API, GITHUB_RAW).await?;
+        let options = DownloadOptions::default();
+        let client = options.http.client()?;
+        install_pipe_update(pipe_dir, screenpipe_dir, update, options, &client).await
+    }
+
+    /// Downloads `update` of the pipe in `pipe_dir` with `options` and `client`, nothing
+    /// when it is `None`.
+    pub(crate) async fn install_pipe_update(
+        pipe_dir: &Path,
+        screenpipe_dir: &Path,
+        update: Option<PipeUpdateInfo>,
+        options: DownloadOptions,
+        client: &Client,
+    ) -> Result<()> {
+        let Some(update) = update else {
+            info!("{} is up to date", pipe_dir.display());
+            return Ok(());
+        };
+        // Installed under another id the update would sit next to the pipe
+        let pipe_id = pipe_dir.file_name().and_then(|name| name.to_str());
+        if pipe_id_from_source(&update.source).as_deref() != pipe_id {
+            anyhow::bail!(
+                "{}: pipe isn't installed under the id of its source {}",
+                pipe_dir.display(),
+                update.source
+            );
+        }
+
+        info!(
+            "updating {} from {} to {}",
+            pipe_dir.display(),
+            update.current,
+            update.latest
+        );
+        let options = DownloadOptions {
+            force: true,
+            on_conflict: ConflictPolicy::Replace,
+            ..options
+        };
+        install_pipe(
+            &update.source,
+            screenpipe_dir.to_path_buf(),
+            options,
+            client,
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Url or path a pipe was installed from, set in its pipe.json by the pipe manager or
+    /// in its [`PIPE_LOCK_FILE`].
+    pub(crate) async fn installed_source(pipe_dir: &Path) -> Option<String> {
+        let pipe_json = load_config(&pipe_dir.join("pipe.json")).await.ok();
+        let source = pipe_json
+            .as_ref()
+            .and_then(|config| config.get("source"))
+            .and_then(Value::as_str)
+            .filter(|source| !source.is_empty());
+        match source {
+            Some(source) => Some(source.to_string()),
+            None => Some(downloaded_pipe(pipe_dir).await?.source),
+        }
+    }
+
+    /// A pipe installed in the pipes folder, as [`list_pipes`] finds it.
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct InstalledPipe {
+        /// Name of its folder, the id it runs under
+        pub id: String,
+        /// `name` of its pipe.json, the id when it has none
+        pub name: String,
+        /// `version` of its pipe.json, else of its package.json
+        pub version: Option<String>,
+        /// Url or path it was installed from
+        pub source: Option<String>,
+        /// When it was last downloaded, as its [`PIPE_LOCK_FILE`] says, else the
+        /// modification time of the lock or its folder
+        pub installed_at: Option<DateTime<Utc>>,
+        /// Enabled in its pipe.json and not stopped with [`disable_pipe`]
+        pub enabled: bool,
+        /// Linked to a folder with [`crate::pipe_link::link_pipe`] rather than installed
+        #[serde(default)]
+        pub linked: bool,
+    }
+
+    /// The pipes installed in `screenpipe_dir`, by name. Hidden folders, downloads in
+    /// progress among them, are left out, and a pipe with a broken pipe.json is listed
+    /// with the defaults.
+    #[deprecated(note = "use PipeManager::list_pipes")]
+    pub async fn list_pipes(screenpipe_dir: &Path) -> Result<Vec<InstalledPipe>> {
+        PipeManager::new(screenpipe_dir.to_path_buf(), RuntimeConfig::default())?
+            .list_pipes()
+            .await
+    }
+
+    /// The pipes installed in `screenpipe_dir`, by name.
+    pub(crate) async fn installed_pipes(screenpipe_dir: &Path) -> Result<Vec<InstalledPipe>> {
+        let pipes_dir = screenpipe_dir.join("pipes");
+        let mut entries = match tokio::fs::read_dir(&pipes_dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut pipes = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            let id = entry.file_name().to_string_lossy().into_owned();
+            // A linked pipe is a link to a folder
+            let is_dir = tokio::fs::metadata(entry.path())
+                .await
+                .is_ok_and(|metadata| metadata.is_dir());
+            if id.starts_with('.') || !is_dir {
+                continue;
+            }
+            pipes.push(installed_pipe(id, &entry.path()).await);
+        }
+        pipes.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.id.cmp(&b.id)));
+        Ok(pipes)
+    }
+
+    async fn installed_pipe(id: String, pipe_dir: &Path) -> InstalledPipe {
+        let config = match load_config(&pipe_dir.join("pipe.json")).await {
+            Ok(config) => config,
+            Err(e) => {
+                if !e.is_not_found() {
+                    warn!("{}", e);
+                }
+                Value::Null
+            }
+        };
+        let string = |config: &Value, key: &str| {
+            config
+                .get(key)
+                .and_then(Value::as_str)
+                .filter(|value| !value.is_empty())
+                .map(str::to_string)
+        };
+        let version = match string(&config, "version") {
+            Some(version) => Some(version),
+            None => load_config(&pipe_dir.join("package.json"))
+                .await
+                .ok()
+                .and_then(|package| string(&package, "version")),
+        };
+        let mut modified = tokio::fs::metadata(pipe_dir.join(PIPE_LOCK_FILE)).await;
+        if modified.is_err() {
+            modified = tokio::fs::metadata(pipe_dir).await;
+        }
+        let installed_at = match read_pipe_metadata(pipe_dir) {
+            Ok(metadata) => Some(metadata.installed_at),
+            Err(_) => modified
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .map(DateTime::<Utc>::from),
+        };
+        InstalledPipe {
+            name: string(&config, "name").unwrap_or_else(|| id.clone()),
+            version,
+            source: installed_source(pipe_dir).await,
+            installed_at,
+            enabled: config
+                .get("enabled")
+                .and_then(Value::as_bool)
+                .unwrap_or(false)
+                && !is_pipe_disabled(pipe_dir),
+            linked: is_linked_pipe(pipe_dir),
+            id,
+        }
+    }
+
+    async fn manifest_version(pipe_dir: &Path) -> Result<semver::Version> {
+        let manifest = validate_pipe_manifest(pipe_dir).await?;
+        let version = manifest.version.ok_or_else(|| {
+            anyhow::anyhow!(
+                "{}: pipe has no version, set 'version' in its pipe.json or package.json",
+                pipe_dir.display()
+            )
+        })?;
+        // Checked by validate_pipe_manifest
+        Ok(semver::Version::parse(&version)?)
+    }
+
+    /// Writes the manifests of the pipe at `source` into `dest_dir`, fetched with `client`
+    /// whichever the host. A local source isn't copied, its path is returned.
+    async fn fetch_manifests(
+        client: &Client,
+        source: &str,
+        dest_dir: &Path,
+        api: &str,
+        raw: &str,
+    ) -> Result<Option<PathBuf>> {
+        let expanded = expand_github_shorthand(source);
+        let source = expanded.as_deref().unwrap_or(source);
+        if ArchiveKind::from_source(source).is_some() {
+            // An archive has no manifest of its own
+            download_archive(client, source, dest_dir).await?;
+            return Ok(None);
+        }
+        if let Some(npm) = NpmSource::parse(source) {
+            // Nor does a package
+            npm.download(client, &npm_registry(), dest_dir).await?;
+            return Ok(None);
+        }
+        let Some(host) = PipeSource::parse(source) else {
+            anyhow::bail!("Unsupported URL format");
+        };
+        match host {
+            PipeSource::GitHub(github) => {
+                let commit = github.commit(client, api).await?;
+                for name in MANIFEST_FILES {
+                    if let Some(content) =
+                        fetch_raw_github_file(client, &github, &commit, raw, name).await?
+                    {
+                        tokio::fs::write(dest_dir.join(name), content).await?;
+                    }
+                }
+            }
+            PipeSource::GitLab(gitlab) => {
+                let commit = gitlab.commit(client).await?;
+                for name in MANIFEST_FILES {
+                    let path = match commit.path.as_str() {
+                        "" => name.to_string(),
+                        folder => format!("{}/{}", folder, name),
+                    };
+                    if let Some(content) = gitlab.file(client, &commit, &path).await? {
+                        tokio::fs::write(dest_dir.join(name), content).await?;
+                    }
+                }
+            }
+            PipeSource::Bitbucket(bitbucket) => {
+                let commit = bitbucket.commit(client, BITBUCKET_API).await?;
+                for name in MANIFEST_FILES {
+                    let path = match commit.path.as_str() {
+                        "" => name.to_string(),
+                        folder => format!("{}/{}", folder, name),
+                    };
+                    let content = bitbucket
+                        .file(client, BITBUCKET_API, &commit, &path)
+                        .await?;
+                    if let Some(content) = content {
+                        tokio::fs::write(dest_dir.join(name), content).await?;
+                    }
+                }
+            }
+            PipeSource::Local(path) => return Ok(Some(path)),
+        }
+        Ok(None)
+    }
+
+    /// Copies the pipe source in `src` to `dst`, without what its [`PIPE_IGNORE_FILE`]
+    /// leaves out. Its symlinks are skipped, followed or copied as `symlinks` says.
+    pub(crate) async fn copy_dir_all(
+        src: impl AsRef<Path>,
+        dst: impl AsRef<Path>,
+        symlinks: SymlinkPolicy,
+    ) -> anyhow::Result<()> {
         let src = src.as_ref();
-        let dst = dst.as_ref();
+        let ignore = Arc::new(PipeIgnore::load(src).await?);
+        copy_dir_filtered(src, dst.as_ref(), "", ignore, symlinks, Vec::new()).await
+    }
+
+    /// Copies `src`, the folder at `rel` below the pipe root, to `dst`. `ancestors` are
+    /// the folders it is in, resolved, a followed link to one of them or a folder they
+    /// are in is a loop.
+    async fn copy_dir_filtered(
+        src: &Path,
+        dst: &Path,
+        rel: &str,
+        ignore: Arc<PipeIgnore>,
+        symlinks: SymlinkPolicy,
+        mut ancestors: Vec<PathBuf>,
+    ) -> anyhow::Result<()> {
         debug!("copy_dir_all: src={:?}, dst={:?}", src, dst);
+        ancestors.push(tokio::fs::canonicalize(src).await?);
 
         tokio::fs::create_dir_all(&dst).await?;
         debug!("Created destination directory: {:?}", dst);
@@ -455,10 +3284,58 @@ mod pipes {
                 debug!("Skipping ignored file/directory: {:?}", entry.file_name());
                 continue;
             }
+            let entry_rel = match rel {
+                "" => entry.file_name().to_string_lossy().into_owned(),
+                rel => format!("{}/{}", rel, entry.file_name().to_string_lossy()),
+            };
+
+            let is_dir = if ty.is_symlink() {
+                if symlinks == SymlinkPolicy::Skip {
+                    info!("skipping the symlink {}, symlinks aren't copied", entry_rel);
+                    continue;
+                }
+                match tokio::fs::metadata(&src_path).await {
+                    Ok(target) => target.is_dir(),
+                    Err(e) => {
+                        warn!("skipping the broken symlink {}: {}", entry_rel, e);
+                        continue;
+                    }
+                }
+            } else {
+                ty.is_dir()
+            };
+            if ignore.is_ignored(&entry_rel, is_dir) {
+                debug!("Skipping {}, in {}", entry_rel, PIPE_IGNORE_FILE);
+                continue;
+            }
 
-            if ty.is_dir() {
+            if ty.is_symlink() && symlinks == SymlinkPolicy::CopyAsLink {
+                debug!("Copying symlink: {:?} to {:?}", src_path, dst_path);
+                copy_symlink(&src_path, &dst_path).await?;
+            } else if is_dir {
+                if ty.is_symlink() {
+                    let target = tokio::fs::canonicalize(&src_path).await?;
+                    if ancestors
+                        .iter()
+                        .any(|ancestor| ancestor.starts_with(&target))
+                    {
+                        warn!(
+                            "skipping the symlink {}, it loops back to {:?}",
+                            entry_rel, target
+                        );
+                        continue;
+                    }
+                }
                 debug!("Entry is a directory, recursing: {:?}", src_path);
-                copy_dir_all_boxed(src_path, dst_path).await?;
+                copy_dir_all_boxed(
+                    src_path,
+                    dst_path,
+                    entry_rel,
+                    ignore.clone(),
+                    symlinks,
+                    ancestors.clone(),
+                )
+                .await?;
             } else {
                 debug!("Copying file: {:?} to {:?}", src_path, dst_path);
                 tokio::fs::copy(&src_path, &dst_path).await?;
@@ -470,10 +3347,34 @@ mod pipes {
     }
 
     fn copy_dir_all_boxed(
-        src: impl AsRef<Path> + Send + 'static,
-        dst: impl AsRef<Path> + Send + 'static,
+        src: PathBuf,
+        dst: PathBuf,
+        rel: String,
+        ignore: Arc<PipeIgnore>,
+        symlinks: SymlinkPolicy,
+        ancestors: Vec<PathBuf>,
     ) -> Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send>> {
-        Box::pin(copy_dir_all(src, dst))
+        Box::pin(
+            async move { copy_dir_filtered(&src, &dst, &rel, ignore, symlinks, ancestors).await },
+        )
+    }
+
+    /// Makes `dst` a link to what the link `src` points to.
+    async fn copy_symlink(src: &Path, dst: &Path) -> anyhow::Result<()> {
+        let target = tokio::fs::read_link(src).await?;
+        #[cfg(unix)]
+        {
+            tokio::fs::symlink(target, dst).await?;
+        }
+        #[cfg(windows)]
+        {
+            if tokio::fs::metadata(src).await?.is_dir() {
+                tokio::fs::symlink_dir(target, dst).await?;
+            } else {
+                tokio::fs::symlink_file(target, dst).await?;
+            }
+        }
+        Ok(())
     }
 
     fn should_ignore(file_name: &std::ffi::OsStr) -> bool {
@@ -496,95 +3397,6 @@ mod pipes {
             || file_name.to_str().map_or(false, |s| s.starts_with('.'))
     }
 
-    fn download_github_folder(
-        url: &Url,
-        dest_dir: &Path,
-    ) -> Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send>> {
-        let url = url.clone();
-        let dest_dir = dest_dir.to_path_buf();
-
-        Box::pin(async move {
-            let client = Client::new();
-            let api_url = get_raw_github_url(url.as_str())?;
-
-            let response = client
-                .get(&api_url)
-                .header("Accept", "application/vnd.github.v3+json")
-                .header("User-Agent", "screenpipe")
-                .send()
-                .await?;
-
-            let contents: Value = response.json().await?;
-
-            if !contents.is_array() {
-                anyhow::bail!("invalid response from github api");
-            }
-
-            for item in contents.as_array().unwrap() {
-                let file_name = item["name"]
-                    .as_str()
-                    .ok_or_else(|| anyhow::anyhow!("missing name in github response"))?;
-
-                if !is_hidden_file(std::ffi::OsStr::new(file_name)) {
-                    let file_type = item["type"]
-                        .as_str()
-                        .ok_or_else(|| anyhow::anyhow!("missing type in github response"))?;
-
-                    match file_type {
-                        "file" => {
-                            let download_url = item["download_url"].as_str().ok_or_else(|| {
-                                anyhow::anyhow!("missing download_url in github response")
-                            })?;
-
-                            let file_content =
-                                client.get(download_url).send().await?.bytes().await?;
-                            let file_path = dest_dir.join(file_name);
-                            tokio::fs::write(&file_path, &file_content).await?;
-                            debug!("downloaded file: {:?}", file_path);
-                        }
-                        "dir" => {
-                            let new_dest_dir = dest_dir.join(file_name);
-                            tokio::fs::create_dir_all(&new_dest_dir).await?;
-
-                            let new_url = format!("{}/{}", url.as_str(), file_name);
-                            download_github_folder(&Url::parse(&new_url)?, &new_dest_dir).await?;
-                            debug!("downloaded directory: {:?}", new_dest_dir);
-                        }
-                        _ => debug!("skipping unknown type: {}", file_type),
-                    }
-                } else {
-                    debug!("skipping hidden file: {}", file_name);
-                }
-            }
-
-            Ok(())
-        })
-    }
-
-    fn get_raw_github_url(url: &str) -> anyhow::Result<String> {
-        info!("Attempting to get raw GitHub URL for: {}", url);
-        let parsed_url = Url::parse(url)?;
-        if parsed_url.host_str() == Some("github.com") {
-            let path_segments: Vec<&str> = parsed_url.path_segments().unwrap().collect();
-            if path_segments.len() >= 5 && path_segments[2] == "tree" {
-                let (owner, repo, _, branch) = (
-                    path_segments[0],
-                    path_segments[1],
-                    path_segments[2],
-                    path_segments[3],
-                );
-                let raw_path = path_segments[4..].join("/");
-                let raw_url = format!(
-                    "https://api.github.com/repos/{}/{}/contents/{}?ref={}",
-                    owner, repo, raw_path, branch
-                );
-                info!("Converted to GitHub API URL: {}", raw_url);
-                return Ok(raw_url);
-            }
-        }
-        anyhow::bail!("Invalid GitHub URL format")
-    }
-
     fn find_pipe_file(pipe_dir: &Path) -> anyhow::Result<PathBuf> {
         for entry in fs::read_dir(pipe_dir)? {
             let entry = entry?;
@@ -599,7 +3411,7 @@ mod pipes {
         anyhow::bail!("No pipe.js/pipe.ts found in the pipe/dist directory")
     }
 
-    fn is_hidden_file(file_name: &std::ffi::OsStr) -> bool {
+    pub(crate) fn is_hidden_file(file_name: &std::ffi::OsStr) -> bool {
         file_name
             .to_str()
             .map(|s| s.starts_with('.') || s == "Thumbs.db")
@@ -612,6 +3424,9 @@ mod pipes {
     #[cfg(windows)]
     const BUN_EXECUTABLE_NAME: &str = "bun.exe";
 
+    /// Overrides where bun is looked up, e.g. in a container with bun outside of `PATH`.
+    pub const BUN_PATH_ENV: &str = "SCREENPIPE_BUN_PATH";
+
     static BUN_PATH: Lazy<Option<PathBuf>> = Lazy::new(find_bun_path_internal);
 
     pub fn find_bun_path() -> Option<PathBuf> {
@@ -621,6 +3436,15 @@ mod pipes {
     fn find_bun_path_internal() -> Option<PathBuf> {
         debug!("starting search for bun executable");
 
+        if let Some(path) = std::env::var_os(BUN_PATH_ENV) {
+            let path = PathBuf::from(path);
+            if path.is_file() {
+                debug!("found bun in {}: {:?}", BUN_PATH_ENV, path);
+                return Some(path);
+            }
+            warn!("{} is set to {:?}, which is not a file", BUN_PATH_ENV, path);
+        }
+
         // Check if bun is in PATH
         if let Ok(path) = which(BUN_EXECUTABLE_NAME) {
             debug!("found bun in PATH: {:?}", path);
@@ -681,6 +3505,62 @@ mod pipes {
         None
     }
 
+    /// Overrides where node is looked up, when a pipe runs with it.
+    pub const NODE_PATH_ENV: &str = "SCREENPIPE_NODE_PATH";
+
+    /// Overrides where deno is looked up, when a pipe runs with it.
+    pub const DENO_PATH_ENV: &str = "SCREENPIPE_DENO_PATH";
+
+    /// Overrides where git is looked up, to clone pipes from hosts other than github and
+    /// gitlab.
+    pub const GIT_PATH_ENV: &str = "SCREENPIPE_GIT_PATH";
+
+    static NODE_PATH: Lazy<Option<PathBuf>> = Lazy::new(|| find_runtime("node", NODE_PATH_ENV));
+
+    static DENO_PATH: Lazy<Option<PathBuf>> = Lazy::new(|| find_runtime("deno", DENO_PATH_ENV));
+
+    static GIT_PATH: Lazy<Option<PathBuf>> = Lazy::new(|| find_runtime("git", GIT_PATH_ENV));
+
+    pub fn find_node_path() -> Option<PathBuf> {
+        NODE_PATH.clone()
+    }
+
+    #[deprecated(note = "use PipeManager::find_deno")]
+    pub fn find_deno_path() -> Option<PathBuf> {
+        deno_path()
+    }
+
+    /// Deno, looked up once for [`PipeManager::find_deno`].
+    pub(crate) fn deno_path() -> Option<PathBuf> {
+        DENO_PATH.clone()
+    }
+
+    pub fn find_git_path() -> Option<PathBuf> {
+        GIT_PATH.clone()
+    }
+
+    /// Looks up a program screenpipe doesn't ship, in `env` then in `PATH`.
+    fn find_runtime(name: &str, env: &str) -> Option<PathBuf> {
+        if let Some(path) = std::env::var_os(env) {
+            let path = PathBuf::from(path);
+            if path.is_file() {
+                debug!("found {} in {}: {:?}", name, env, path);
+                return Some(path);
+            }
+            warn!("{} is set to {:?}, which is not a file", env, path);
+        }
+        match which(name) {
+            Ok(path) => {
+                debug!("found {} in PATH: {:?}", name, path);
+                Some(path)
+            }
+            Err(_) => {
+                debug!("{} not found in PATH", name);
+                None
+            }
+        }
+    }
+
     // Add this function to handle cron state persistence
     pub async fn get_last_cron_execution(
         pipe_dir: &Path,
@@ -734,6 +3614,19 @@ mod pipes {
         Ok(())
     }
 
+    /// Missed runs are spread over this window instead of all firing at once,
+    /// e.g. after the machine wakes up from sleep.
+    pub const CRON_CATCH_UP_WINDOW: std::time::Duration = std::time::Duration::from_secs(120);
+
+    /// Delay before catching up on a missed run of `path`. Stable per cron job, so the
+    /// jobs of all pipes land at different points of [`CRON_CATCH_UP_WINDOW`].
+    pub fn cron_catch_up_delay(pipe: &str, path: &str) -> std::time::Duration {
+        use std::hash::{Hash, Hasher};
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        (pipe, path).hash(&mut hasher);
+        std::time::Duration::from_millis(hasher.finish() % CRON_CATCH_UP_WINDOW.as_millis() as u64)
+    }
+
     // Update the run_cron_schedule function
     async fn run_cron_schedule(
         pipe: &str,
@@ -762,7 +3655,7 @@ mod pipes {
         let pipe_dir = PathBuf::from(screenpipe_dir).join("pipes").join(pipe);
 
         // Get last execution time
-        let last_run = match get_last_cron_execution(&pipe_dir, path).await {
+        let mut last_run = match get_last_cron_execution(&pipe_dir, path).await {
             Ok(time) => time,
             Err(e) => {
                 error!("failed to get last cron execution: {}", e);
@@ -770,6 +3663,11 @@ mod pipes {
             }
         };
 
+        let power = power_state();
+        let subsystem = format!("pipe {} cron {}", pipe, path);
+        power.register(&subsystem);
+        let mut power_events = power.subscribe();
+
         loop {
             let now = chrono::Utc::now();
             let next = if let Some(last) = last_run {
@@ -785,12 +3683,43 @@ mod pipes {
                 None => continue,
             };
 
-            let duration = (next - now)
-                .to_std()
-                .unwrap_or(tokio::time::Duration::from_secs(1));
-            tokio::time::sleep(duration).await;
+            if next > now {
+                let duration = (next - now)
+                    .to_std()
+                    .unwrap_or(tokio::time::Duration::from_secs(1));
+                tokio::select! {
+                    _ = tokio::time::sleep(duration) => {}
+                    event = power_events.recv() => {
+                        // The timer may not have advanced while asleep, recompute from the wall clock
+                        if let Ok(PowerEvent::Wake { epoch, .. }) = event {
+                            let outcome = if next <= chrono::Utc::now() {
+                                format!(
+                                    "missed run, catching up in {}s",
+                                    cron_catch_up_delay(pipe, path).as_secs()
+                                )
+                            } else {
+                                format!("next run at {}", next)
+                            };
+                            power.report(&subsystem, epoch, SubsystemOutcome::Ok(outcome));
+                        }
+                        continue;
+                    }
+                }
+            } else {
+                let delay = cron_catch_up_delay(pipe, path);
+                info!(
+                    "pipe {} missed its run of {} at {}, catching up in {}s",
+                    pipe,
+                    path,
+                    next,
+                    delay.as_secs()
+                );
+                tokio::time::sleep(delay).await;
+            }
 
             debug!("executing cron job for pipe {} at path {}", pipe, path);
+            // Failed runs wait for the next slot too, only successes are persisted
+            last_run = Some(SystemTime::now());
 
             match client
                 .get(&format!("{}{}", base_url, path))
@@ -0,0 +1,516 @@
+//! System sleep/wake detection shared by every capture subsystem.
+//!
+//! Native notifications are used where we have them (IOKit on macOS, logind on linux).
+//! Everywhere, a clock jump heuristic runs as a fallback: a process is frozen while the
+//! machine sleeps, so a tick that arrives with the wall clock far ahead of where it
+//! should be means we were asleep.
+//!
+//! Subsystems either poll [`PowerState::is_asleep`] / [`PowerState::epoch`] from their
+//! loops or [`PowerState::subscribe`] to events, and report what they did with
+//! [`PowerState::report`] so the wake sequence can be logged as a single event.
+
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
+use std::sync::{Condvar, Mutex, Once};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use tracing::{debug, info, warn};
+
+/// How often the clock jump heuristic samples the clocks.
+pub const CLOCK_TICK: Duration = Duration::from_secs(5);
+/// Wall clock advance beyond the expected tick that counts as a sleep. Large enough that
+/// ntp adjustments and a loaded machine don't trigger it.
+pub const CLOCK_JUMP_THRESHOLD: Duration = Duration::from_secs(60);
+/// How long the os sleep is held back so subsystems can close their files.
+pub const SLEEP_FINALIZE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PowerSource {
+    /// Notification from the os
+    Native,
+    /// Inferred from the wall clock jumping ahead
+    ClockJump,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PowerEvent {
+    Sleep {
+        epoch: u64,
+        at: DateTime<Utc>,
+    },
+    Wake {
+        epoch: u64,
+        slept_at: DateTime<Utc>,
+        woke_at: DateTime<Utc>,
+        source: PowerSource,
+    },
+}
+
+/// What a subsystem did about a sleep or wake.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "status", content = "detail", rename_all = "snake_case")]
+pub enum SubsystemOutcome {
+    Ok(String),
+    Failed(String),
+    /// Registered but did not report in time
+    TimedOut,
+}
+
+#[derive(Default)]
+struct Reports {
+    registered: BTreeSet<String>,
+    outcomes: HashMap<String, (u64, SubsystemOutcome)>,
+}
+
+pub struct PowerState {
+    asleep: AtomicBool,
+    /// Bumped on every sleep and wake, so loops can notice a transition they slept through
+    epoch: AtomicU64,
+    slept_at_ms: AtomicI64,
+    last_wake_ms: AtomicI64,
+    events: broadcast::Sender<PowerEvent>,
+    reports: Mutex<Reports>,
+    reported: Condvar,
+}
+
+static POWER_STATE: Lazy<PowerState> = Lazy::new(PowerState::new);
+
+/// Process wide power state fed by [`start_power_monitor`].
+pub fn power_state() -> &'static PowerState {
+    &POWER_STATE
+}
+
+impl Default for PowerState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PowerState {
+    pub fn new() -> Self {
+        let (events, _) = broadcast::channel(16);
+        Self {
+            asleep: AtomicBool::new(false),
+            epoch: AtomicU64::new(0),
+            slept_at_ms: AtomicI64::new(0),
+            last_wake_ms: AtomicI64::new(0),
+            events,
+            reports: Mutex::new(Reports::default()),
+            reported: Condvar::new(),
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<PowerEvent> {
+        self.events.subscribe()
+    }
+
+    pub fn is_asleep(&self) -> bool {
+        self.asleep.load(Ordering::SeqCst)
+    }
+
+    pub fn epoch(&self) -> u64 {
+        self.epoch.load(Ordering::SeqCst)
+    }
+
+    /// When the machine last woke up, used as the watchdog baseline.
+    pub fn last_wake(&self) -> Option<DateTime<Utc>> {
+        match self.last_wake_ms.load(Ordering::SeqCst) {
+            0 => None,
+            ms => DateTime::from_timestamp_millis(ms),
+        }
+    }
+
+    /// How long the sleep announced with [`PowerState::sleep`] has been going on.
+    pub fn asleep_since(&self) -> Option<DateTime<Utc>> {
+        if self.is_asleep() {
+            DateTime::from_timestamp_millis(self.slept_at_ms.load(Ordering::SeqCst))
+        } else {
+            None
+        }
+    }
+
+    /// The os announced it is going to sleep. `None` if we already knew.
+    pub fn sleep(&self, at: DateTime<Utc>) -> Option<PowerEvent> {
+        if self.asleep.swap(true, Ordering::SeqCst) {
+            return None;
+        }
+        self.slept_at_ms
+            .store(at.timestamp_millis(), Ordering::SeqCst);
+        let epoch = self.epoch.fetch_add(1, Ordering::SeqCst) + 1;
+        let event = PowerEvent::Sleep { epoch, at };
+        info!("system is going to sleep, finalizing capture");
+        let _ = self.events.send(event.clone());
+        Some(event)
+    }
+
+    /// The os announced it woke up. `None` if no sleep was announced, e.g. the clock
+    /// jump heuristic already handled this wake.
+    pub fn wake_from_sleep(&self, woke_at: DateTime<Utc>) -> Option<PowerEvent> {
+        let slept_at = self.asleep_since()?;
+        self.wake(slept_at, woke_at, PowerSource::Native)
+    }
+
+    /// The clock jump heuristic saw the machine was asleep between `slept_at` and `woke_at`.
+    /// If a sleep was announced, its start time is kept.
+    pub fn clock_jump(
+        &self,
+        slept_at: DateTime<Utc>,
+        woke_at: DateTime<Utc>,
+    ) -> Option<PowerEvent> {
+        let slept_at = self.asleep_since().unwrap_or(slept_at);
+        self.wake(slept_at, woke_at, PowerSource::ClockJump)
+    }
+
+    fn wake(
+        &self,
+        slept_at: DateTime<Utc>,
+        woke_at: DateTime<Utc>,
+        source: PowerSource,
+    ) -> Option<PowerEvent> {
+        if source == PowerSource::Native && !self.asleep.load(Ordering::SeqCst) {
+            return None;
+        }
+        self.asleep.store(false, Ordering::SeqCst);
+        self.last_wake_ms
+            .store(woke_at.timestamp_millis(), Ordering::SeqCst);
+        let epoch = self.epoch.fetch_add(1, Ordering::SeqCst) + 1;
+        let event = PowerEvent::Wake {
+            epoch,
+            slept_at,
+            woke_at,
+            source,
+        };
+        let _ = self.events.send(event.clone());
+        Some(event)
+    }
+
+    /// Adds a subsystem the wake sequence waits for.
+    pub fn register(&self, subsystem: &str) {
+        let mut reports = self.reports.lock().unwrap();
+        reports.registered.insert(subsystem.to_string());
+    }
+
+    pub fn unregister(&self, subsystem: &str) {
+        let mut reports = self.reports.lock().unwrap();
+        reports.registered.remove(subsystem);
+        reports.outcomes.remove(subsystem);
+        self.reported.notify_all();
+    }
+
+    /// Records what `subsystem` did after the transition to `epoch`.
+    pub fn report(&self, subsystem: &str, epoch: u64, outcome: SubsystemOutcome) {
+        debug!(
+            "{} after power transition {}: {:?}",
+            subsystem, epoch, outcome
+        );
+        let mut reports = self.reports.lock().unwrap();
+        reports
+            .outcomes
+            .insert(subsystem.to_string(), (epoch, outcome));
+        self.reported.notify_all();
+    }
+
+    /// Blocks until every registered subsystem reported on `epoch` or later, or `timeout`
+    /// passes. Subsystems that stayed silent are [`SubsystemOutcome::TimedOut`].
+    pub fn wait_for_reports(
+        &self,
+        epoch: u64,
+        timeout: Duration,
+    ) -> BTreeMap<String, SubsystemOutcome> {
+        let deadline = Instant::now() + timeout;
+        let mut reports = self.reports.lock().unwrap();
+        loop {
+            let pending = reports.registered.iter().any(|name| {
+                !matches!(reports.outcomes.get(name), Some((reported, _)) if *reported >= epoch)
+            });
+            let now = Instant::now();
+            if !pending || now >= deadline {
+                break;
+            }
+            reports = self
+                .reported
+                .wait_timeout(reports, deadline - now)
+                .unwrap()
+                .0;
+        }
+
+        reports
+            .registered
+            .iter()
+            .map(|name| {
+                let outcome = match reports.outcomes.get(name) {
+                    Some((reported, outcome)) if *reported >= epoch => outcome.clone(),
+                    _ => SubsystemOutcome::TimedOut,
+                };
+                (name.clone(), outcome)
+            })
+            .collect()
+    }
+}
+
+/// Fallback sleep detection from the gap between wall clock and monotonic time.
+///
+/// Where the monotonic clock stops during suspend (macOS, linux) the wall clock runs
+/// ahead of it. Where it keeps counting (windows) the tick simply arrives long after
+/// it was due. Either way the wall clock moved much further than one tick.
+pub struct ClockJumpDetector {
+    interval: Duration,
+    threshold: Duration,
+    last_wall: DateTime<Utc>,
+    last_mono: Instant,
+}
+
+impl ClockJumpDetector {
+    pub fn new(interval: Duration, threshold: Duration) -> Self {
+        Self::starting_at(interval, threshold, Utc::now(), Instant::now())
+    }
+
+    pub fn starting_at(
+        interval: Duration,
+        threshold: Duration,
+        wall: DateTime<Utc>,
+        mono: Instant,
+    ) -> Self {
+        Self {
+            interval,
+            threshold,
+            last_wall: wall,
+            last_mono: mono,
+        }
+    }
+
+    /// Feeds one sample of both clocks. Returns the `(slept_at, woke_at)` span when the
+    /// machine was asleep since the previous sample.
+    pub fn observe(
+        &mut self,
+        wall: DateTime<Utc>,
+        mono: Instant,
+    ) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+        // Clocks set backwards are not a sleep
+        let wall_elapsed = (wall - self.last_wall).to_std().unwrap_or_default();
+        let mono_elapsed = mono.saturating_duration_since(self.last_mono);
+        let unaccounted = wall_elapsed.saturating_sub(mono_elapsed.min(self.interval));
+
+        let slept_at = self.last_wall;
+        self.last_wall = wall;
+        self.last_mono = mono;
+
+        if unaccounted > self.threshold {
+            Some((slept_at, wall))
+        } else {
+            None
+        }
+    }
+}
+
+/// Starts native sleep/wake notifications where available and the clock jump fallback.
+/// Must be called from within a tokio runtime; later calls do nothing.
+pub fn start_power_monitor() {
+    static STARTED: Once = Once::new();
+    STARTED.call_once(|| {
+        native::listen();
+
+        tokio::spawn(async {
+            let state = power_state();
+            let mut detector = ClockJumpDetector::new(CLOCK_TICK, CLOCK_JUMP_THRESHOLD);
+            loop {
+                let epoch = state.epoch();
+                tokio::time::sleep(CLOCK_TICK).await;
+                match detector.observe(Utc::now(), Instant::now()) {
+                    // Unless a native notification already covered this wake
+                    Some((slept_at, woke_at)) if state.epoch() == epoch || state.is_asleep() => {
+                        state.clock_jump(slept_at, woke_at);
+                    }
+                    Some(_) => {}
+                    None => {
+                        // Ticking normally means we're awake, the wake notification got lost
+                        if let Some(since) = state.asleep_since() {
+                            if Utc::now() - since > chrono::Duration::seconds(60) {
+                                warn!("sleep was announced but no wake followed, resuming capture");
+                                state.wake_from_sleep(Utc::now());
+                            }
+                        }
+                    }
+                }
+            }
+        });
+    });
+}
+
+#[cfg(target_os = "macos")]
+mod native {
+    use super::{power_state, PowerEvent, SLEEP_FINALIZE_TIMEOUT};
+    use chrono::Utc;
+    use std::ffi::c_void;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use tracing::{debug, warn};
+
+    type IoConnect = u32;
+    type IoObject = u32;
+    type NotificationPort = *mut c_void;
+    type RunLoopSource = *mut c_void;
+    type RunLoop = *mut c_void;
+    type CfString = *const c_void;
+
+    const CAN_SYSTEM_SLEEP: u32 = 0xe000_0270;
+    const SYSTEM_WILL_SLEEP: u32 = 0xe000_0280;
+    const SYSTEM_WILL_NOT_SLEEP: u32 = 0xe000_0290;
+    const SYSTEM_HAS_POWERED_ON: u32 = 0xe000_0300;
+
+    #[link(name = "IOKit", kind = "framework")]
+    extern "C" {
+        fn IORegisterForSystemPower(
+            refcon: *mut c_void,
+            port: *mut NotificationPort,
+            callback: extern "C" fn(*mut c_void, IoObject, u32, *mut c_void),
+            notifier: *mut IoObject,
+        ) -> IoConnect;
+        fn IONotificationPortGetRunLoopSource(port: NotificationPort) -> RunLoopSource;
+        fn IOAllowPowerChange(kernel_port: IoConnect, notification_id: isize) -> i32;
+    }
+
+    #[link(name = "CoreFoundation", kind = "framework")]
+    extern "C" {
+        static kCFRunLoopCommonModes: CfString;
+        fn CFRunLoopGetCurrent() -> RunLoop;
+        fn CFRunLoopAddSource(run_loop: RunLoop, source: RunLoopSource, mode: CfString);
+        fn CFRunLoopRun();
+    }
+
+    static ROOT_PORT: AtomicU32 = AtomicU32::new(0);
+
+    extern "C" fn on_power_message(
+        _refcon: *mut c_void,
+        _service: IoObject,
+        message_type: u32,
+        argument: *mut c_void,
+    ) {
+        let root_port = ROOT_PORT.load(Ordering::SeqCst);
+        match message_type {
+            CAN_SYSTEM_SLEEP => unsafe {
+                IOAllowPowerChange(root_port, argument as isize);
+            },
+            SYSTEM_WILL_SLEEP => {
+                let state = power_state();
+                if let Some(PowerEvent::Sleep { epoch, .. }) = state.sleep(Utc::now()) {
+                    // macOS waits up to 30s for the ack, give capture a moment to close files
+                    state.wait_for_reports(epoch, SLEEP_FINALIZE_TIMEOUT);
+                }
+                unsafe {
+                    IOAllowPowerChange(root_port, argument as isize);
+                }
+            }
+            SYSTEM_WILL_NOT_SLEEP | SYSTEM_HAS_POWERED_ON => {
+                power_state().wake_from_sleep(Utc::now());
+            }
+            _ => {}
+        }
+    }
+
+    pub(super) fn listen() {
+        let spawned = std::thread::Builder::new()
+            .name("power-notifications".to_string())
+            .spawn(|| unsafe {
+                let mut port: NotificationPort = std::ptr::null_mut();
+                let mut notifier: IoObject = 0;
+                let root_port = IORegisterForSystemPower(
+                    std::ptr::null_mut(),
+                    &mut port,
+                    on_power_message,
+                    &mut notifier,
+                );
+                if root_port == 0 {
+                    warn!("failed to register for power notifications, relying on clock jump detection");
+                    return;
+                }
+                ROOT_PORT.store(root_port, Ordering::SeqCst);
+                CFRunLoopAddSource(
+                    CFRunLoopGetCurrent(),
+                    IONotificationPortGetRunLoopSource(port),
+                    kCFRunLoopCommonModes,
+                );
+                debug!("listening for iokit power notifications");
+                CFRunLoopRun();
+            });
+        if let Err(e) = spawned {
+            warn!("failed to start power notification thread: {}", e);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod native {
+    use super::power_state;
+    use chrono::Utc;
+    use std::io::{BufRead, BufReader};
+    use std::process::{Command, Stdio};
+    use tracing::{debug, warn};
+
+    /// logind emits `PrepareForSleep(true)` before suspend and `PrepareForSleep(false)`
+    /// after resume. Without an inhibitor lock it does not wait for us, so sleep is
+    /// best effort here.
+    pub(super) fn listen() {
+        let child = Command::new("dbus-monitor")
+            .args([
+                "--system",
+                "type='signal',interface='org.freedesktop.login1.Manager',member='PrepareForSleep'",
+            ])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn();
+        let mut child = match child {
+            Ok(child) => child,
+            Err(e) => {
+                debug!(
+                    "dbus-monitor unavailable ({}), relying on clock jump detection",
+                    e
+                );
+                return;
+            }
+        };
+        let Some(stdout) = child.stdout.take() else {
+            return;
+        };
+
+        let spawned = std::thread::Builder::new()
+            .name("power-notifications".to_string())
+            .spawn(move || {
+                let mut in_signal = false;
+                for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                    if line.contains("member=PrepareForSleep") {
+                        in_signal = true;
+                        continue;
+                    }
+                    if !in_signal {
+                        continue;
+                    }
+                    match line.trim() {
+                        "boolean true" => {
+                            power_state().sleep(Utc::now());
+                        }
+                        "boolean false" => {
+                            power_state().wake_from_sleep(Utc::now());
+                        }
+                        _ => continue,
+                    }
+                    in_signal = false;
+                }
+                let _ = child.wait();
+                debug!("dbus-monitor exited, relying on clock jump detection");
+            });
+        if let Err(e) = spawned {
+            warn!("failed to start power notification thread: {}", e);
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+mod native {
+    pub(super) fn listen() {
+        tracing::debug!("no native power notifications, relying on clock jump detection");
+    }
+}
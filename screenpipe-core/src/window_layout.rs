@@ -0,0 +1,133 @@
+//! Where windows were on screen when a frame was captured.
+//!
+//! The vision pipeline already lists every window to capture the focused ones. The
+//! same listing gives each window's bounds and stacking order, kept per frame so
+//! search can filter on the focused window's geometry and uis can draw a miniature
+//! map of the screen at that moment.
+
+use serde::{Deserialize, Serialize};
+
+/// Bounds of a window or monitor, in pixels.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WindowRect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl WindowRect {
+    pub fn new(x: i32, y: i32, width: u32, height: u32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// The part of `self` inside `bounds`, relative to the top left corner of `bounds`.
+    pub fn clip_to(&self, bounds: &WindowRect) -> Option<WindowRect> {
+        let left = (self.x as i64).max(bounds.x as i64);
+        let top = (self.y as i64).max(bounds.y as i64);
+        let right = (self.x as i64 + self.width as i64).min(bounds.x as i64 + bounds.width as i64);
+        let bottom =
+            (self.y as i64 + self.height as i64).min(bounds.y as i64 + bounds.height as i64);
+        if right <= left || bottom <= top {
+            return None;
+        }
+        Some(WindowRect {
+            x: (left - bounds.x as i64) as i32,
+            y: (top - bounds.y as i64) as i32,
+            width: (right - left) as u32,
+            height: (bottom - top) as u32,
+        })
+    }
+}
+
+/// A window visible on the monitor when the frame was captured.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LayoutWindow {
+    pub app_name: String,
+    pub window_name: String,
+    /// Relative to the monitor's top left corner, cut to the monitor
+    pub rect: WindowRect,
+    /// Stacking order, 0 is the frontmost window
+    pub z: u32,
+    #[serde(default)]
+    pub focused: bool,
+}
+
+/// The windows on a monitor at one frame, frontmost first.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WindowLayout {
+    pub monitor_width: u32,
+    pub monitor_height: u32,
+    pub windows: Vec<LayoutWindow>,
+}
+
+impl WindowLayout {
+    pub fn focused(&self) -> Option<&LayoutWindow> {
+        self.windows.iter().find(|window| window.focused)
+    }
+}
+
+/// A window as the platform lists it, in desktop coordinates.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListedWindow<'a> {
+    pub app_name: &'a str,
+    pub window_name: &'a str,
+    pub rect: WindowRect,
+    pub minimized: bool,
+    /// Only reported by some platforms, elsewhere the frontmost window is the focused one
+    pub focused: bool,
+}
+
+/// The layout of `monitor` from windows listed front to back. Minimized windows,
+/// windows off the monitor and those `is_ignored` matches are left out, the stacking
+/// order counts the kept windows only.
+pub fn build_window_layout<'a>(
+    monitor: WindowRect,
+    windows: impl IntoIterator<Item = ListedWindow<'a>>,
+    is_ignored: impl Fn(&str, &str) -> bool,
+) -> WindowLayout {
+    let mut layout = WindowLayout {
+        monitor_width: monitor.width,
+        monitor_height: monitor.height,
+        windows: Vec::new(),
+    };
+    for window in windows {
+        if window.minimized || is_ignored(window.app_name, window.window_name) {
+            continue;
+        }
+        let Some(rect) = window.rect.clip_to(&monitor) else {
+            continue;
+        };
+        layout.windows.push(LayoutWindow {
+            app_name: window.app_name.to_string(),
+            window_name: window.window_name.to_string(),
+            rect,
+            z: layout.windows.len() as u32,
+            focused: window.focused,
+        });
+    }
+    if layout.focused().is_none() {
+        if let Some(front) = layout.windows.first_mut() {
+            front.focused = true;
+        }
+    }
+    layout
+}
+
+/// Whether other apps' windows can be listed with their positions. Wayland keeps them
+/// from clients, frames there are stored without a layout.
+pub fn window_layout_supported() -> bool {
+    if cfg!(target_os = "linux") {
+        let wayland_session = std::env::var("XDG_SESSION_TYPE")
+            .map(|session| session.eq_ignore_ascii_case("wayland"))
+            .unwrap_or(false);
+        !wayland_session && std::env::var_os("WAYLAND_DISPLAY").is_none()
+    } else {
+        true
+    }
+}
@@ -0,0 +1,204 @@
+#[cfg(feature = "pipes")]
+#[cfg(test)]
+#[allow(deprecated)]
+mod tests {
+    use httpmock::prelude::*;
+    use screenpipe_core::pipe_archive::{extract_archive, ArchiveKind, MAX_ARCHIVE_SIZE};
+    use screenpipe_core::pipe_metadata::{read_pipe_metadata, PipeSourceKind};
+    use screenpipe_core::{download_pipe, pipe_id_from_source};
+    use std::io::Write;
+    use std::path::Path;
+    use tempfile::tempdir;
+
+    const PIPE_JSON: &str = r#"{"name": "notes", "version": "1.0.0"}"#;
+
+    fn tar_gz(files: &[(&str, &str)]) -> Vec<u8> {
+        let gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        let mut tar = tar::Builder::new(gz);
+        for (path, content) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            // set_path refuses the `..` of the archives refused below
+            header.as_gnu_mut().unwrap().name[..path.len()].copy_from_slice(path.as_bytes());
+            header.set_cksum();
+            tar.append(&header, content.as_bytes()).unwrap();
+        }
+        tar.into_inner().unwrap().finish().unwrap()
+    }
+
+    fn zip(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        for (path, content) in files {
+            zip.start_file(*path, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    fn extract(kind: ArchiveKind, archive: &[u8], dest: &Path, max_size: u64) -> String {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), archive).unwrap();
+        match extract_archive(kind, file.path(), dest, max_size) {
+            Ok(()) => String::new(),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_archives_are_extracted_without_their_root_folder() {
+        for source in [
+            "https://github.com/acme/notes/releases/download/v1.0.0/notes.tar.gz",
+            "https://example.com/pipes/notes.TGZ",
+            "https://example.com/pipes/notes.zip?download=1",
+        ] {
+            assert_eq!(pipe_id_from_source(source).as_deref(), Some("notes"));
+        }
+        assert_eq!(
+            ArchiveKind::from_source("/pipes/notes.zip"),
+            Some(ArchiveKind::Zip)
+        );
+        assert_eq!(ArchiveKind::from_source("/pipes/notes"), None);
+        assert_eq!(
+            ArchiveKind::from_source("ftp://example.com/notes.zip"),
+            None
+        );
+
+        let files = [
+            ("notes-1.0.0/pipe.json", PIPE_JSON),
+            ("notes-1.0.0/pipe.ts", "console.log('notes')"),
+            ("notes-1.0.0/src/lib.ts", "export {}"),
+            ("notes-1.0.0/.env", "KEY=secret"),
+        ];
+        for (kind, archive) in [
+            (ArchiveKind::TarGz, tar_gz(&files)),
+            (ArchiveKind::Zip, zip(&files)),
+        ] {
+            let dest = tempdir().unwrap();
+            assert_eq!(extract(kind, &archive, dest.path(), MAX_ARCHIVE_SIZE), "");
+            assert!(dest.path().join("pipe.ts").exists());
+            assert!(dest.path().join("src/lib.ts").exists());
+            assert!(!dest.path().join(".env").exists());
+            assert!(!dest.path().join("notes-1.0.0").exists());
+        }
+
+        // Files side by side are kept as they are
+        let dest = tempdir().unwrap();
+        let archive = zip(&[("pipe.ts", "console.log('notes')"), ("src/lib.ts", "")]);
+        assert_eq!(extract(ArchiveKind::Zip, &archive, dest.path(), 1024), "");
+        assert!(dest.path().join("src/lib.ts").exists());
+    }
+
+    #[test]
+    fn test_unsafe_archives_are_refused() {
+        for kind in [ArchiveKind::TarGz, ArchiveKind::Zip] {
+            let build = |files: &[(&str, &str)]| match kind {
+                ArchiveKind::TarGz => tar_gz(files),
+                ArchiveKind::Zip => zip(files),
+            };
+            let root = tempdir().unwrap();
+            let dest = root.path().join("notes");
+            let archive = build(&[("pipe.ts", ""), ("../evil.sh", "rm -rf ~")]);
+            assert_eq!(
+                extract(kind, &archive, &dest, MAX_ARCHIVE_SIZE),
+                "pipe archive entry ../evil.sh is outside of the archive"
+            );
+            assert!(!root.path().join("evil.sh").exists());
+            assert!(!dest.exists());
+
+            let dest = tempdir().unwrap();
+            let archive = build(&[("pipe.ts", &"a".repeat(2 * 1024 * 1024))]);
+            assert_eq!(
+                extract(kind, &archive, dest.path(), 1024 * 1024),
+                "the pipe archive is over 1 MB extracted"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_a_release_archive_is_installed() {
+        let server = MockServer::start_async().await;
+        let mock = server
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path("/acme/notes/releases/download/v1.0.0/notes.tar.gz");
+                then.status(200).body(tar_gz(&[
+                    ("notes/pipe.json", PIPE_JSON),
+                    ("notes/pipe.ts", "console.log('notes')"),
+                ]));
+            })
+            .await;
+
+        let dir = tempdir().unwrap();
+        let source = server.url("/acme/notes/releases/download/v1.0.0/notes.tar.gz");
+        let pipe_dir = download_pipe(&source, dir.path().to_path_buf())
+            .await
+            .unwrap();
+        mock.assert_async().await;
+        assert_eq!(pipe_dir, dir.path().join("pipes").join("notes"));
+        assert_eq!(
+            std::fs::read_to_string(pipe_dir.join("pipe.ts")).unwrap(),
+            "console.log('notes')"
+        );
+        let pipes = std::fs::read_dir(dir.path().join("pipes")).unwrap();
+        assert_eq!(pipes.count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_a_local_archive_is_installed() {
+        let dir = tempdir().unwrap();
+        let files = [
+            ("notes-1.0.0/pipe.json", PIPE_JSON),
+            ("notes-1.0.0/pipe.ts", "console.log('notes')"),
+        ];
+        for (name, archive) in [("notes.zip", zip(&files)), ("notes.tar.gz", tar_gz(&files))] {
+            let screenpipe_dir = dir.path().join(name).with_extension("screenpipe");
+            let source = dir.path().join(name);
+            std::fs::write(&source, archive).unwrap();
+            assert_eq!(
+                pipe_id_from_source(source.to_str().unwrap()).as_deref(),
+                Some("notes")
+            );
+
+            let pipe_dir = download_pipe(source.to_str().unwrap(), screenpipe_dir.clone())
+                .await
+                .unwrap();
+            assert_eq!(pipe_dir, screenpipe_dir.join("pipes").join("notes"));
+            assert_eq!(
+                std::fs::read_to_string(pipe_dir.join("pipe.ts")).unwrap(),
+                "console.log('notes')"
+            );
+            let metadata = read_pipe_metadata(&pipe_dir).unwrap();
+            assert_eq!(metadata.kind, PipeSourceKind::Archive);
+        }
+
+        let e = download_pipe(
+            dir.path().join("missing.zip").to_str().unwrap(),
+            dir.path().to_path_buf(),
+        )
+        .await
+        .unwrap_err();
+        assert!(e.to_string().ends_with("missing.zip isn't a file"), "{}", e);
+    }
+
+    #[tokio::test]
+    async fn test_an_archive_without_a_pipe_is_refused() {
+        let dir = tempdir().unwrap();
+        let source = dir.path().join("notes.zip");
+        std::fs::write(&source, zip(&[("notes/README.md", "# notes")])).unwrap();
+        let screenpipe_dir = dir.path().join("screenpipe");
+
+        let e = download_pipe(source.to_str().unwrap(), screenpipe_dir.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            format!(
+                "{} has no pipe.json, pipe.ts or pipe.js at its root",
+                source.display()
+            )
+        );
+        assert!(!screenpipe_dir.join("pipes").join("notes").exists());
+    }
+}
@@ -0,0 +1,197 @@
+#[cfg(feature = "pipes")]
+#[cfg(test)]
+mod tests {
+    use httpmock::prelude::*;
+    use screenpipe_core::pipe_bitbucket::{bitbucket_client, BitbucketSource};
+    use screenpipe_core::pipe_id_from_source;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    const SHA: &str = "9fceb02d0ae598e95dc970b74767f19372d61af8";
+
+    #[test]
+    fn test_bitbucket_urls_are_parsed() {
+        let source =
+            BitbucketSource::parse("https://bitbucket.org/acme/pipes/src/main/pipes/notes/")
+                .unwrap();
+        assert_eq!(source.workspace, "acme");
+        assert_eq!(source.repo, "pipes");
+        assert_eq!(source.tree, ["main", "pipes", "notes"]);
+
+        // Installed under the same ids as github pipes
+        for (bitbucket, github) in [
+            (
+                "https://bitbucket.org/acme/pipes/src/main/pipes/notes",
+                "https://github.com/acme/pipes/tree/main/pipes/notes",
+            ),
+            (
+                "https://bitbucket.org/acme/my-pipe.git",
+                "https://github.com/acme/my-pipe.git",
+            ),
+            (
+                "https://bitbucket.org/acme/my-pipe/src/dev",
+                "https://github.com/acme/my-pipe/tree/dev",
+            ),
+        ] {
+            assert_eq!(pipe_id_from_source(bitbucket), pipe_id_from_source(github));
+        }
+
+        assert_eq!(
+            BitbucketSource::parse("https://bitbucket.org/acme/pipes/pull-requests"),
+            None
+        );
+        assert_eq!(BitbucketSource::parse("https://bitbucket.org/acme"), None);
+        assert_eq!(
+            BitbucketSource::parse("https://example.com/acme/pipes"),
+            None
+        );
+    }
+
+    fn entry(path: &str, kind: &str, attributes: &[&str]) -> serde_json::Value {
+        json!({
+            "path": path,
+            "type": kind,
+            "attributes": attributes,
+            "commit": { "hash": SHA },
+        })
+    }
+
+    #[tokio::test]
+    async fn test_a_folder_is_downloaded_folder_by_folder() {
+        let server = MockServer::start_async().await;
+        let repo = "/2.0/repositories/acme/pipes";
+        let branch = server
+            .mock_async(|when, then| {
+                when.method(GET).path(format!("{}/commit/feature", repo));
+                then.status(404).json_body(json!({
+                    "type": "error",
+                    "error": { "message": "Commit not found" },
+                }));
+            })
+            .await;
+        server
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path(format!("{}/commit/feature%2Fnotes", repo));
+                then.status(200).json_body(json!({ "hash": SHA }));
+            })
+            .await;
+        let next = server.url(format!("{}/src/{}/pipes/notes/?page=2", repo, SHA));
+        let first_page = server
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path(format!("{}/src/{}/pipes/notes/", repo, SHA))
+                    .query_param("pagelen", "100")
+                    .header("authorization", "Bearer secret");
+                then.status(200).json_body(json!({
+                    "values": [
+                        entry("pipes/notes/pipe.ts", "commit_file", &[]),
+                        entry("pipes/notes/.env", "commit_file", &[]),
+                        entry("pipes/notes/src", "commit_directory", &[]),
+                    ],
+                    "next": next,
+                }));
+            })
+            .await;
+        let second_page = server
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path(format!("{}/src/{}/pipes/notes/", repo, SHA))
+                    .query_param("page", "2");
+                then.status(200).json_body(json!({
+                    "values": [entry("pipes/notes/latest", "commit_file", &["link"])],
+                }));
+            })
+            .await;
+        let nested = server
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path(format!("{}/src/{}/pipes/notes/src/", repo, SHA));
+                then.status(200).json_body(json!({
+                    "values": [entry("pipes/notes/src/lib.ts", "commit_file", &[])],
+                }));
+            })
+            .await;
+        let mut raw = Vec::new();
+        for file in ["pipe.ts", "src/lib.ts"] {
+            raw.push(
+                server
+                    .mock_async(|when, then| {
+                        when.method(GET)
+                            .path(format!("{}/src/{}/pipes/notes/{}", repo, SHA, file));
+                        then.status(200).body(format!("// {}", file));
+                    })
+                    .await,
+            );
+        }
+
+        let source = BitbucketSource::parse(
+            "https://bitbucket.org/acme/pipes/src/feature/notes/pipes/notes",
+        )
+        .unwrap();
+        let client = bitbucket_client(Some("secret")).unwrap();
+        assert!(!format!("{:?}", client).contains("secret"));
+        let api = server.base_url();
+        let commit = source.commit(&client, &api).await.unwrap();
+        branch.assert_async().await;
+        assert_eq!(commit.git_ref, "feature/notes");
+        assert_eq!(commit.path, "pipes/notes");
+        assert_eq!(commit.sha, SHA);
+
+        let dest = tempfile::tempdir().unwrap();
+        let files = source
+            .download(
+                &client,
+                &api,
+                &commit,
+                dest.path(),
+                Arc::default(),
+                2,
+                3,
+                None,
+            )
+            .await
+            .unwrap();
+        first_page.assert_async().await;
+        second_page.assert_async().await;
+        nested.assert_async().await;
+        for mock in &raw {
+            mock.assert_async().await;
+        }
+        assert_eq!(
+            files.files.keys().collect::<Vec<_>>(),
+            ["pipe.ts", "src/lib.ts"]
+        );
+        assert_eq!(
+            std::fs::read_to_string(dest.path().join("src/lib.ts")).unwrap(),
+            "// src/lib.ts"
+        );
+        assert!(!dest.path().join(".env").exists());
+        assert!(!dest.path().join("latest").exists());
+    }
+
+    #[tokio::test]
+    async fn test_a_repo_bitbucket_doesnt_show_asks_for_a_token() {
+        let server = MockServer::start_async().await;
+        server
+            .mock_async(|when, then| {
+                when.method(GET).path_contains("/2.0/repositories/");
+                then.status(404).json_body(json!({
+                    "type": "error",
+                    "error": { "message": "Repository acme/secret not found" },
+                }));
+            })
+            .await;
+
+        let source = BitbucketSource::parse("https://bitbucket.org/acme/secret").unwrap();
+        let e = source
+            .commit(&bitbucket_client(None).unwrap(), &server.base_url())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "bitbucket repo acme/secret not found or token missing, set BITBUCKET_TOKEN to \
+             download pipes from a private repo"
+        );
+    }
+}
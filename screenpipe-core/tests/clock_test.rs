@@ -0,0 +1,150 @@
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Duration as ChronoDuration, Utc};
+    use screenpipe_core::clock::{Clock, ClockAdjustment, ClockConfig, Stamp, TimestampSource};
+    use std::time::{Duration, Instant};
+    use tokio::sync::broadcast::Receiver;
+
+    const STEP: Duration = Duration::from_secs(1);
+
+    /// Stamps one item per second of monotonic time, the wall clock jumping by
+    /// `jumps[i]` before item `i`.
+    fn stamp_stream(clock: &Clock, start: (DateTime<Utc>, Instant), jumps: &[i64]) -> Vec<Stamp> {
+        let (mut wall, mut mono) = start;
+        jumps
+            .iter()
+            .map(|jump| {
+                wall += ChronoDuration::seconds(1) + ChronoDuration::seconds(*jump);
+                mono += STEP;
+                clock.stamp_at(wall, mono)
+            })
+            .collect()
+    }
+
+    fn clock(source: TimestampSource) -> (Clock, (DateTime<Utc>, Instant)) {
+        let start = (Utc::now(), Instant::now());
+        let config = ClockConfig {
+            source,
+            ..ClockConfig::default()
+        };
+        (Clock::starting_at(config, start.0, start.1), start)
+    }
+
+    fn assert_never_backwards(stamps: &[Stamp]) {
+        for pair in stamps.windows(2) {
+            assert!(
+                pair[1].wall >= pair[0].wall,
+                "{:?} after {:?}",
+                pair[1],
+                pair[0]
+            );
+            assert!(pair[1].monotonic_ns > pair[0].monotonic_ns);
+        }
+    }
+
+    fn adjustments(events: &mut Receiver<ClockAdjustment>) -> Vec<ClockAdjustment> {
+        std::iter::from_fn(|| events.try_recv().ok()).collect()
+    }
+
+    #[test]
+    fn test_backward_jump_never_goes_back() {
+        let (clock, start) = clock(TimestampSource::Monotonic);
+        let mut events = clock.subscribe();
+        let mut jumps = vec![0; 7200];
+        jumps[10] = -600;
+
+        let stamps = stamp_stream(&clock, start, &jumps);
+        assert_never_backwards(&stamps);
+
+        // Stamps right after the jump follow the monotonic clock, not the system clock
+        let right_after = stamps[10].wall - stamps[9].wall;
+        assert!(right_after >= ChronoDuration::milliseconds(900));
+        let adjustments = adjustments(&mut events);
+        assert_eq!(adjustments.len(), 1);
+        assert_eq!(adjustments[0].jump_ms, -600_000);
+        assert_eq!(adjustments[0].detected_at, stamps[10].wall);
+
+        // Re-anchored on the system clock after 10 minutes / 10%
+        let system_end = start.0 + ChronoDuration::seconds(7200 - 600);
+        assert_eq!(stamps.last().unwrap().wall, system_end);
+    }
+
+    #[test]
+    fn test_forward_jump_is_absorbed_gradually() {
+        let (clock, start) = clock(TimestampSource::Monotonic);
+        let mut events = clock.subscribe();
+        let mut jumps = vec![0; 2000];
+        jumps[100] = 60;
+
+        let stamps = stamp_stream(&clock, start, &jumps);
+        assert_never_backwards(&stamps);
+        let step = stamps[100].wall - stamps[99].wall;
+        assert!(step <= ChronoDuration::milliseconds(1100), "{}", step);
+        assert_eq!(adjustments(&mut events)[0].jump_ms, 60_000);
+
+        let system_end = start.0 + ChronoDuration::seconds(2000 + 60);
+        assert_eq!(stamps.last().unwrap().wall, system_end);
+    }
+
+    #[test]
+    fn test_small_drift_is_followed_without_adjustments() {
+        let (clock, start) = clock(TimestampSource::Monotonic);
+        let mut events = clock.subscribe();
+        let (mut wall, mut mono) = start;
+        for _ in 0..100 {
+            // ntp slewing the clock by 50ms per second
+            wall += ChronoDuration::milliseconds(1050);
+            mono += STEP;
+            let stamp = clock.stamp_at(wall, mono);
+            assert_eq!(stamp.wall, wall);
+        }
+        assert!(adjustments(&mut events).is_empty());
+    }
+
+    #[test]
+    fn test_repeated_jumps_from_both_directions() {
+        let (clock, start) = clock(TimestampSource::Monotonic);
+        let mut events = clock.subscribe();
+        let jumps: Vec<i64> = (0..2500)
+            .map(|i| match i % 500 {
+                50 => -3600,
+                120 => 900,
+                300 => -5,
+                _ => 0,
+            })
+            .collect();
+
+        let stamps = stamp_stream(&clock, start, &jumps);
+        assert_never_backwards(&stamps);
+        assert_eq!(adjustments(&mut events).len(), 15);
+    }
+
+    #[test]
+    fn test_system_source_trusts_the_wall_clock() {
+        let (clock, start) = clock(TimestampSource::System);
+        let mut events = clock.subscribe();
+        let stamps = stamp_stream(&clock, start, &[0, -600, 0]);
+        assert_eq!(stamps[1].wall, start.0 + ChronoDuration::seconds(2 - 600));
+        assert!(stamps[1].wall < stamps[0].wall);
+        assert!(stamps[1].monotonic_ns > stamps[0].monotonic_ns);
+        assert_eq!(adjustments(&mut events)[0].source, TimestampSource::System);
+    }
+
+    #[test]
+    fn test_resync_after_sleep_jumps_forward() {
+        let (clock, start) = clock(TimestampSource::Monotonic);
+        let before = clock.stamp_at(start.0 + ChronoDuration::seconds(1), start.1 + STEP);
+
+        // The monotonic clock stood still during 8 hours of sleep
+        let woke_at = start.0 + ChronoDuration::hours(8);
+        clock.resync_at(woke_at, start.1 + STEP);
+        let after = clock.stamp_at(woke_at + ChronoDuration::seconds(1), start.1 + STEP * 2);
+        assert_eq!(after.wall, woke_at + ChronoDuration::seconds(1));
+        assert!(after > before);
+
+        // Never backwards, even if the wall clock reads earlier on wake
+        clock.resync_at(start.0, start.1 + STEP * 2);
+        let later = clock.stamp_at(start.0, start.1 + STEP * 3);
+        assert!(later.wall >= after.wall);
+    }
+}
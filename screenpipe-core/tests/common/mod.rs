@@ -0,0 +1,35 @@
+//! Pipe folders the pipe tests download from. Each test file uses some of them.
+#![allow(dead_code)]
+
+use serde_json::{json, Value};
+use std::path::Path;
+
+/// Writes a pipe named `notes` to `dir` with `files`, by path below it, and returns `dir`
+/// as the source to download it from.
+pub fn write_pipe(dir: &Path, files: &[(&str, &str)]) -> String {
+    write_pipe_with(dir, Some(json!({ "name": "notes" })), None, files)
+}
+
+/// [`write_pipe`] with `pipe_json` and `package_json` as its manifests. A manifest that is
+/// `None` is removed, for a pipe written over another.
+pub fn write_pipe_with(
+    dir: &Path,
+    pipe_json: Option<Value>,
+    package_json: Option<Value>,
+    files: &[(&str, &str)],
+) -> String {
+    std::fs::create_dir_all(dir).unwrap();
+    for (file, content) in [("pipe.json", pipe_json), ("package.json", package_json)] {
+        let path = dir.join(file);
+        match content {
+            Some(content) => std::fs::write(path, content.to_string()).unwrap(),
+            None => {
+                let _ = std::fs::remove_file(path);
+            }
+        }
+    }
+    for (path, content) in files {
+        std::fs::write(dir.join(path), content).unwrap();
+    }
+    dir.to_str().unwrap().to_string()
+}
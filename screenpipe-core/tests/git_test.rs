@@ -0,0 +1,127 @@
+#[cfg(feature = "pipes")]
+#[cfg(test)]
+#[allow(deprecated)]
+mod tests {
+    use screenpipe_core::pipe_git::GitSource;
+    use screenpipe_core::pipe_metadata::{read_pipe_metadata, PipeSourceKind};
+    use screenpipe_core::{
+        download_pipe, download_pipe_with, find_git_path, pipe_id_from_source, DownloadOptions,
+    };
+    use std::path::Path;
+    use std::process::Command;
+
+    #[test]
+    fn test_git_sources_are_parsed() {
+        let source = GitSource::parse("git@codeberg.org:acme/pipes.git#main:pipes/notes/").unwrap();
+        assert_eq!(source.url, "git@codeberg.org:acme/pipes.git");
+        assert_eq!(source.branch.as_deref(), Some("main"));
+        assert_eq!(source.folder, "pipes/notes");
+
+        for (source, id) in [
+            ("git@codeberg.org:acme/my-pipe.git", "my-pipe"),
+            ("ssh://git@git.example.com:2222/acme/my-pipe.git", "my-pipe"),
+            ("https://codeberg.org/acme/pipes.git#:pipes/notes", "notes"),
+            ("https://gitea.example.com/acme/my-pipe#v1.0.0", "my-pipe"),
+        ] {
+            assert_eq!(
+                pipe_id_from_source(source).as_deref(),
+                Some(id),
+                "{}",
+                source
+            );
+        }
+        let source = GitSource::parse("https://codeberg.org/acme/pipes.git#:notes").unwrap();
+        assert_eq!(source.branch, None);
+
+        for source in [
+            "/home/me/pipes/notes",
+            "https://codeberg.org",
+            "git@codeberg.org:acme/pipes.git#main:../secrets",
+            "git@codeberg.org:acme/pipes.git#--upload-pack=evil",
+        ] {
+            assert_eq!(GitSource::parse(source), None, "{}", source);
+        }
+    }
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .current_dir(dir)
+            .status()
+            .unwrap();
+        assert!(status.success(), "git {:?}", args);
+    }
+
+    #[tokio::test]
+    async fn test_a_folder_of_a_repo_is_cloned() {
+        if find_git_path().is_none() {
+            return;
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path().join("pipes");
+        let pipe = repo.join("pipes").join("notes");
+        std::fs::create_dir_all(&pipe).unwrap();
+        std::fs::write(pipe.join("pipe.json"), r#"{"name": "notes"}"#).unwrap();
+        std::fs::write(pipe.join("pipe.ts"), "console.log('main')").unwrap();
+        git(&repo, &["init", "--quiet", "--initial-branch", "main"]);
+        git(&repo, &["add", "."]);
+        git(&repo, &["commit", "--quiet", "-m", "notes"]);
+        let output = Command::new("git")
+            .args(["rev-parse", "HEAD"])
+            .current_dir(&repo)
+            .output()
+            .unwrap();
+        let first_commit = String::from_utf8(output.stdout).unwrap().trim().to_string();
+        git(&repo, &["checkout", "--quiet", "-b", "dev"]);
+        std::fs::write(pipe.join("pipe.ts"), "console.log('dev')").unwrap();
+        git(&repo, &["commit", "--quiet", "-am", "dev"]);
+
+        let url = url::Url::from_file_path(&repo).unwrap().to_string();
+        let screenpipe_dir = dir.path().join("screenpipe");
+        let installed = download_pipe(&format!("{}#dev:pipes/notes", url), screenpipe_dir.clone())
+            .await
+            .unwrap();
+        assert_eq!(installed, screenpipe_dir.join("pipes").join("notes"));
+        assert_eq!(
+            std::fs::read_to_string(installed.join("pipe.ts")).unwrap(),
+            "console.log('dev')"
+        );
+        assert!(!installed.join(".git").exists());
+        let metadata = read_pipe_metadata(&installed).unwrap();
+        assert_eq!(metadata.kind, PipeSourceKind::Git);
+        let dev_commit = metadata.resolved_ref.unwrap();
+        assert_eq!(dev_commit.len(), 40);
+        assert_ne!(dev_commit, first_commit);
+
+        // A commit rather than a branch
+        let options = DownloadOptions {
+            git_ref: Some(first_commit.clone()),
+            ..Default::default()
+        };
+        let installed = download_pipe_with(
+            &format!("{}#dev:pipes/notes", url),
+            screenpipe_dir.clone(),
+            options,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(installed.join("pipe.ts")).unwrap(),
+            "console.log('main')"
+        );
+        let metadata = read_pipe_metadata(&installed).unwrap();
+        assert_eq!(metadata.resolved_ref, Some(first_commit));
+
+        let e = download_pipe(&format!("{}#main:pipes/todo", url), screenpipe_dir.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(e.to_string(), format!("{} has no folder pipes/todo", url));
+        let e = download_pipe(&format!("{}#missing:pipes/notes", url), screenpipe_dir)
+            .await
+            .unwrap_err();
+        assert!(e
+            .to_string()
+            .starts_with(&format!("git clone of {} failed: ", url)));
+    }
+}
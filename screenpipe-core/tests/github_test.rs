@@ -0,0 +1,1330 @@
+#[cfg(feature = "pipes")]
+#[cfg(test)]
+#[allow(deprecated)]
+mod tests {
+    use httpmock::prelude::*;
+    use screenpipe_core::pipe_metadata::PipeSourceKind;
+    use screenpipe_core::{
+        check_pipe_update_with, download_github_listing, download_github_source,
+        download_github_source_with, download_pipe, downloaded_pipe, expand_github_shorthand,
+        github_client, github_tree_files, is_commit_sha, load_github_token, parse_github_contents,
+        pin_github_source, pipe_id_from_source, request_with_backoff, store_github_token,
+        update_pipe_version, with_rate_limit_wait, DownloadOptions, GithubCommit,
+        GithubContentType, GithubFetch, GithubGitTree, GithubRateLimited, GithubSource, GithubTree,
+        HttpOptions, InstalledFiles, PipeSource, DEFAULT_DOWNLOAD_ATTEMPTS,
+        DEFAULT_DOWNLOAD_CONCURRENCY, GITHUB_TOKEN_FILE, MAX_GITHUB_DEPTH, PIPE_FILES_FILE,
+        PIPE_LOCK_FILE,
+    };
+    use serde_json::{json, Value};
+    use std::path::Path;
+    use std::sync::Arc;
+
+    const NOT_FOUND: &str = include_str!("fixtures/github/not_found.json");
+    const RATE_LIMITED: &str = include_str!("fixtures/github/rate_limited.json");
+    const DIRECTORY: &str = include_str!("fixtures/github/directory.json");
+    const SUBMODULE: &str = include_str!("fixtures/github/submodule.json");
+    const LARGE_FILE: &str = include_str!("fixtures/github/large_file.json");
+    const FILE: &str = include_str!("fixtures/github/file.json");
+    const TREE: &str = include_str!("fixtures/github/tree.json");
+
+    #[test]
+    fn test_api_error_objects_become_readable_errors() {
+        let e = parse_github_contents(404, NOT_FOUND).unwrap_err();
+        assert_eq!(e.to_string(), "github api error (404): Not Found");
+
+        let e = parse_github_contents(403, RATE_LIMITED).unwrap_err();
+        assert!(e
+            .to_string()
+            .starts_with("github api error (403): API rate limit exceeded"));
+
+        // Error bodies that aren't json, e.g. from a proxy
+        let e = parse_github_contents(502, "<html>bad gateway</html>").unwrap_err();
+        assert_eq!(e.to_string(), "github api returned status 502");
+        let e = parse_github_contents(200, r#"{"unexpected": true}"#).unwrap_err();
+        assert!(e
+            .to_string()
+            .starts_with("unexpected response from github api"));
+    }
+
+    #[test]
+    fn test_directory_entries_are_fetched_by_type() {
+        let items = parse_github_contents(200, DIRECTORY).unwrap();
+        let fetches: Vec<(&str, GithubFetch)> = items
+            .iter()
+            .map(|item| (item.name.as_str(), item.fetch()))
+            .collect();
+        assert_eq!(
+            fetches,
+            vec![
+                (
+                    ".gitignore",
+                    GithubFetch::Download(
+                        "https://raw.githubusercontent.com/acme/pipes/main/pipes/example/.gitignore"
+                            .to_string()
+                    )
+                ),
+                (
+                    "pipe.ts",
+                    GithubFetch::Download(
+                        "https://raw.githubusercontent.com/acme/pipes/main/pipes/example/pipe.ts"
+                            .to_string()
+                    )
+                ),
+                (
+                    "src",
+                    GithubFetch::Listing(
+                        "https://api.github.com/repos/acme/pipes/contents/pipes/example/src?ref=main"
+                            .to_string()
+                    )
+                ),
+                (
+                    "README.md",
+                    GithubFetch::Resolve(
+                        "https://api.github.com/repos/acme/pipes/contents/pipes/example/README.md?ref=main"
+                            .to_string()
+                    )
+                ),
+            ]
+        );
+        assert_eq!(items[1].size, 2048);
+        assert_eq!(items[2].kind, GithubContentType::Dir);
+    }
+
+    #[test]
+    fn test_submodules_are_skipped() {
+        let items = parse_github_contents(200, SUBMODULE).unwrap();
+        // Listed as a file for backwards compatibility, and with its own type
+        assert_eq!(items[0].kind, GithubContentType::File);
+        assert_eq!(items[1].kind, GithubContentType::Submodule);
+        for item in &items {
+            assert_eq!(item.download_url, None);
+            assert_eq!(item.fetch(), GithubFetch::Skip("submodule"));
+        }
+    }
+
+    #[test]
+    fn test_large_files_come_from_the_blobs_api() {
+        let items = parse_github_contents(200, LARGE_FILE).unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].download_url, None);
+        assert_eq!(
+            items[0].fetch(),
+            GithubFetch::Blob(
+                "https://api.github.com/repos/acme/pipes/git/blobs/9fceb02d0ae598e95dc970b74767f19372d61af8"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_a_path_to_a_file_is_a_single_entry() {
+        let items = parse_github_contents(200, FILE).unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].name, "pipe.ts");
+        assert!(matches!(items[0].fetch(), GithubFetch::Download(_)));
+
+        // Types the api may add later are skipped, not an error
+        let unknown = FILE.replace(r#""type": "file""#, r#""type": "lfs-pointer""#);
+        let items = parse_github_contents(200, &unknown).unwrap();
+        assert_eq!(items[0].kind, GithubContentType::Unknown);
+        assert_eq!(items[0].fetch(), GithubFetch::Skip("unknown type"));
+    }
+
+    #[test]
+    fn test_source_url_shapes() {
+        let tree = |git_ref: &str, path: &str| GithubTree {
+            git_ref: git_ref.to_string(),
+            path: path.to_string(),
+        };
+        // url, tree segments, pipe id, ways the tree splits into a ref and a folder
+        let cases: Vec<(&str, Vec<&str>, &str, Vec<GithubTree>)> = vec![
+            ("https://github.com/acme/my-pipe", vec![], "my-pipe", vec![]),
+            (
+                "https://github.com/acme/my-pipe/",
+                vec![],
+                "my-pipe",
+                vec![],
+            ),
+            (
+                "https://github.com/acme/my-pipe.git",
+                vec![],
+                "my-pipe",
+                vec![],
+            ),
+            (
+                "https://github.com/acme/my-pipe/tree/main",
+                vec!["main"],
+                "my-pipe",
+                vec![tree("main", "")],
+            ),
+            (
+                "https://github.com/acme/pipes/tree/main/pipes/notes",
+                vec!["main", "pipes", "notes"],
+                "notes",
+                vec![
+                    tree("main", "pipes/notes"),
+                    tree("main/pipes", "notes"),
+                    tree("main/pipes/notes", ""),
+                ],
+            ),
+            (
+                "https://github.com/acme/pipes/tree/feature/foo/notes/",
+                vec!["feature", "foo", "notes"],
+                "notes",
+                vec![
+                    tree("feature", "foo/notes"),
+                    tree("feature/foo", "notes"),
+                    tree("feature/foo/notes", ""),
+                ],
+            ),
+        ];
+        for (url, segments, id, candidates) in cases {
+            let source = GithubSource::parse(url).unwrap_or_else(|| panic!("{}", url));
+            assert_eq!(source.owner, "acme", "{}", url);
+            assert_eq!(source.tree, segments, "{}", url);
+            assert_eq!(source.pipe_id(), id, "{}", url);
+            assert_eq!(pipe_id_from_source(url).as_deref(), Some(id), "{}", url);
+            assert_eq!(source.ref_candidates(), candidates, "{}", url);
+        }
+
+        for url in [
+            "https://github.com/acme",
+            "https://github.com/acme/pipes/tree",
+            "https://github.com/acme/pipes/blob/main/pipe.ts",
+            "https://gitlab.com/acme/pipes",
+            "/home/me/pipes/notes",
+        ] {
+            assert_eq!(GithubSource::parse(url), None, "{}", url);
+        }
+        assert_eq!(
+            pipe_id_from_source("/home/me/pipes/notes").as_deref(),
+            Some("notes")
+        );
+    }
+
+    #[test]
+    fn test_sources_are_parsed_by_host() {
+        let kinds = [
+            (
+                "https://github.com/acme/pipes/tree/main/notes",
+                PipeSourceKind::Github,
+            ),
+            ("https://gitlab.com/acme/pipes", PipeSourceKind::Gitlab),
+            (
+                "https://bitbucket.org/acme/pipes",
+                PipeSourceKind::Bitbucket,
+            ),
+            ("/home/me/pipes/notes", PipeSourceKind::Local),
+        ];
+        for (source, kind) in kinds {
+            let parsed = PipeSource::parse(source).unwrap_or_else(|| panic!("{}", source));
+            assert_eq!(parsed.kind(), kind, "{}", source);
+        }
+        assert_eq!(
+            PipeSource::parse("/home/me/pipes/notes"),
+            Some(PipeSource::Local("/home/me/pipes/notes".into()))
+        );
+        for source in ["https://github.com/acme", "https://example.com/acme/pipes"] {
+            assert_eq!(PipeSource::parse(source), None, "{}", source);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_shorthands_are_github_sources_unless_they_exist_locally() {
+        for (shorthand, url, id) in [
+            ("acme/my-pipe", "https://github.com/acme/my-pipe", "my-pipe"),
+            (
+                "acme/my-pipe@dev",
+                "https://github.com/acme/my-pipe/tree/dev",
+                "my-pipe",
+            ),
+            (
+                "acme/pipes/pipes/notes",
+                "https://github.com/acme/pipes/tree/HEAD/pipes/notes",
+                "notes",
+            ),
+            (
+                "acme/pipes/pipes/notes@feature/foo",
+                "https://github.com/acme/pipes/tree/feature/foo/pipes/notes",
+                "notes",
+            ),
+            ("acme/.github", "https://github.com/acme/.github", "-github"),
+        ] {
+            assert_eq!(
+                expand_github_shorthand(shorthand).as_deref(),
+                Some(url),
+                "{}",
+                shorthand
+            );
+            assert_eq!(pipe_id_from_source(shorthand).as_deref(), Some(id));
+        }
+
+        // A folder that exists, relative to the crate, is a local pipe
+        assert!(Path::new("tests/fixtures").is_dir());
+        assert_eq!(expand_github_shorthand("tests/fixtures"), None);
+        for source in [
+            "https://github.com/acme/my-pipe",
+            "acme",
+            "./acme/my-pipe",
+            "../acme/my-pipe",
+            "/acme/my-pipe",
+            "acme/my pipe",
+            "acme//my-pipe",
+            "acme/my-pipe@",
+            "git@github.com:acme/my-pipe.git",
+        ] {
+            assert_eq!(expand_github_shorthand(source), None, "{}", source);
+        }
+
+        // What isn't a shorthand is still a local path that doesn't exist
+        let dir = tempfile::tempdir().unwrap();
+        let e = download_pipe("./acme/my-pipe", dir.path().to_path_buf())
+            .await
+            .unwrap_err();
+        assert_eq!(e.to_string(), "Invalid local source path");
+    }
+
+    #[test]
+    fn test_pinned_sources_point_at_the_commit() {
+        let sha = "9fceb02d0ae598e95dc970b74767f19372d61af8";
+        assert_eq!(
+            pin_github_source(
+                "https://github.com/mediar-ai/screenpipe/tree/main/pipes/pipe-obsidian-time-logs",
+                "main",
+                sha
+            )
+            .unwrap(),
+            format!(
+                "https://github.com/mediar-ai/screenpipe/tree/{}/pipes/pipe-obsidian-time-logs",
+                sha
+            )
+        );
+        assert_eq!(
+            pin_github_source(
+                "https://github.com/acme/pipes/tree/feature/foo/notes",
+                "feature/foo",
+                sha
+            )
+            .unwrap(),
+            format!("https://github.com/acme/pipes/tree/{}/notes", sha)
+        );
+        // A repo root is pinned at the commit of its default branch
+        assert_eq!(
+            pin_github_source("https://github.com/acme/my-pipe", "main", sha).unwrap(),
+            format!("https://github.com/acme/my-pipe/tree/{}", sha)
+        );
+
+        // A ref the source isn't at
+        assert_eq!(
+            pin_github_source("https://github.com/acme/pipes/tree/main/notes", "dev", sha),
+            None
+        );
+        // Pinned, a ref with slashes and no folder would install under the repo's name
+        assert_eq!(
+            pin_github_source(
+                "https://github.com/acme/pipes/tree/feature/foo",
+                "feature/foo",
+                sha
+            ),
+            None
+        );
+        assert_eq!(pin_github_source("/home/me/pipes/notes", "main", sha), None);
+        assert_eq!(
+            pin_github_source("https://gitlab.com/a/b/tree/main/c", "main", sha),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_refs_with_slashes_and_default_branches_are_resolved() {
+        let server = MockServer::start_async().await;
+        server
+            .mock_async(|when, then| {
+                when.method(GET).path("/repos/acme/pipes");
+                then.status(200)
+                    .json_body(json!({ "name": "pipes", "default_branch": "trunk" }));
+            })
+            .await;
+        let missing = server
+            .mock_async(|when, then| {
+                when.method(GET).path("/repos/acme/pipes/commits/feature");
+                then.status(404).body(NOT_FOUND);
+            })
+            .await;
+        server
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path("/repos/acme/pipes/commits/feature/foo");
+                then.status(200)
+                    .body("9fceb02d0ae598e95dc970b74767f19372d61af8");
+            })
+            .await;
+        let client = reqwest::Client::new();
+        let api = server.base_url();
+
+        let root = GithubSource::parse("https://github.com/acme/pipes").unwrap();
+        let tree = root.resolve(&client, &api).await.unwrap();
+        assert_eq!(tree.git_ref, "trunk");
+        assert_eq!(tree.path, "");
+        assert_eq!(
+            root.contents_url(&api, &tree),
+            format!("{}/repos/acme/pipes/contents/?ref=trunk", api)
+        );
+
+        let branch =
+            GithubSource::parse("https://github.com/acme/pipes/tree/feature/foo/notes").unwrap();
+        let tree = branch.resolve(&client, &api).await.unwrap();
+        assert_eq!(
+            (tree.git_ref.as_str(), tree.path.as_str()),
+            ("feature/foo", "notes")
+        );
+        missing.assert_async().await;
+
+        let unknown = GithubSource::parse("https://github.com/acme/pipes/tree/nope/notes").unwrap();
+        let e = unknown.resolve(&client, &api).await.unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "no branch, tag or commit of acme/pipes matches nope/notes"
+        );
+    }
+
+    fn file_entry(server: &MockServer, path: &str) -> Value {
+        json!({
+            "name": path.rsplit('/').next().unwrap(),
+            "path": path,
+            "type": "file",
+            "size": 16,
+            "sha": "3b18e512dba79e4c8300dd08aeb37f8e728b8dad",
+            "url": server.url(format!("/repos/acme/pipes/contents/{}?ref=main", path)),
+            "download_url": server.url(format!("/raw/{}", path)),
+        })
+    }
+
+    fn dir_entry(server: &MockServer, path: &str) -> Value {
+        json!({
+            "name": path.rsplit('/').next().unwrap(),
+            "path": path,
+            "type": "dir",
+            "size": 0,
+            "sha": "8c3b8e0b6b58f2a1e1d7b3a6d7e0c6b2f0a1d2e3",
+            "url": server.url(format!("/repos/acme/pipes/contents/{}?ref=main", path)),
+            "download_url": null,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_nested_directories_are_downloaded_recursively() {
+        let server = MockServer::start_async().await;
+
+        // pipe/ -> src/ -> components/ -> ui/, with a file at every level
+        let levels = [
+            ("pipe", "pipe.json", Some("src")),
+            ("pipe/src", "index.ts", Some("components")),
+            ("pipe/src/components", "list.tsx", Some("ui")),
+            ("pipe/src/components/ui", "button.tsx", None),
+        ];
+        let mut mocks = Vec::new();
+        for (dir, file, subdir) in levels {
+            let mut listing = vec![file_entry(&server, &format!("{}/{}", dir, file))];
+            if let Some(subdir) = subdir {
+                listing.push(dir_entry(&server, &format!("{}/{}", dir, subdir)));
+            }
+            mocks.push(
+                server
+                    .mock_async(|when, then| {
+                        when.method(GET)
+                            .path(format!("/repos/acme/pipes/contents/{}", dir))
+                            .query_param("ref", "main");
+                        then.status(200).json_body(Value::Array(listing));
+                    })
+                    .await,
+            );
+            let content = format!("contents of {}", file);
+            mocks.push(
+                server
+                    .mock_async(|when, then| {
+                        when.method(GET).path(format!("/raw/{}/{}", dir, file));
+                        then.status(200).body(content);
+                    })
+                    .await,
+            );
+        }
+
+        let dest = tempfile::tempdir().unwrap();
+        download_github_listing(
+            &server.url("/repos/acme/pipes/contents/pipe?ref=main"),
+            dest.path(),
+        )
+        .await
+        .unwrap();
+
+        for mock in &mocks {
+            mock.assert_async().await;
+        }
+        for (path, file) in [
+            ("pipe.json", "pipe.json"),
+            ("src/index.ts", "index.ts"),
+            ("src/components/list.tsx", "list.tsx"),
+            ("src/components/ui/button.tsx", "button.tsx"),
+        ] {
+            let content = std::fs::read_to_string(dest.path().join(path)).unwrap();
+            assert_eq!(content, format!("contents of {}", file));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_a_failing_subdirectory_fails_the_download() {
+        let server = MockServer::start_async().await;
+        let listing = vec![dir_entry(&server, "pipe/src")];
+        server
+            .mock_async(|when, then| {
+                when.method(GET).path("/repos/acme/pipes/contents/pipe");
+                then.status(200).json_body(Value::Array(listing));
+            })
+            .await;
+        server
+            .mock_async(|when, then| {
+                when.method(GET).path("/repos/acme/pipes/contents/pipe/src");
+                then.status(404).body(NOT_FOUND);
+            })
+            .await;
+
+        let dest = tempfile::tempdir().unwrap();
+        let e = download_github_listing(
+            &server.url("/repos/acme/pipes/contents/pipe?ref=main"),
+            dest.path(),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "failed to list src: github api error (404): Not Found"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_nesting_is_limited() {
+        let server = MockServer::start_async().await;
+        // A folder that lists itself as its own subfolder never ends
+        let listing = vec![json!({
+            "name": "again",
+            "type": "dir",
+            "sha": "8c3b8e0b6b58f2a1e1d7b3a6d7e0c6b2f0a1d2e3",
+            "url": server.url("/repos/acme/pipes/contents/pipe?ref=main"),
+        })];
+        let mock = server
+            .mock_async(|when, then| {
+                when.method(GET).path("/repos/acme/pipes/contents/pipe");
+                then.status(200).json_body(Value::Array(listing));
+            })
+            .await;
+
+        let dest = tempfile::tempdir().unwrap();
+        let e = download_github_listing(
+            &server.url("/repos/acme/pipes/contents/pipe?ref=main"),
+            dest.path(),
+        )
+        .await
+        .unwrap_err();
+        assert!(e.to_string().ends_with("is nested deeper than 10 folders"));
+        assert_eq!(mock.hits_async().await, MAX_GITHUB_DEPTH + 1);
+    }
+
+    #[tokio::test]
+    async fn test_commits_are_resolved_to_a_sha() {
+        let sha = "9fceb02d0ae598e95dc970b74767f19372d61af8";
+        assert!(is_commit_sha(sha));
+        assert!(is_commit_sha(&sha.to_uppercase()));
+        assert!(!is_commit_sha("9fceb02"));
+        assert!(!is_commit_sha("main"));
+
+        let server = MockServer::start_async().await;
+        let commits = server
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path("/repos/acme/pipes/commits/main")
+                    .header("accept", "application/vnd.github.sha");
+                then.status(200).body(format!("{}\n", sha));
+            })
+            .await;
+        let client = reqwest::Client::new();
+        let api = server.base_url();
+
+        let branch = GithubSource::parse("https://github.com/acme/pipes/tree/main/notes").unwrap();
+        let commit = branch.commit(&client, &api).await.unwrap();
+        assert_eq!(
+            (
+                commit.git_ref.as_str(),
+                commit.path.as_str(),
+                commit.sha.as_str()
+            ),
+            ("main", "notes", sha)
+        );
+
+        // A sha in the url is the commit, github isn't asked about it
+        let url = format!(
+            "https://github.com/acme/pipes/blob/{}/pipes/notes",
+            sha.to_uppercase()
+        );
+        let pinned = GithubSource::parse(&url).unwrap();
+        assert_eq!(pipe_id_from_source(&url).unwrap(), "notes");
+        let commit = pinned.commit(&client, &api).await.unwrap();
+        assert_eq!(
+            (commit.path.as_str(), commit.sha.as_str()),
+            ("pipes/notes", sha)
+        );
+        commits.assert_hits_async(1).await;
+    }
+
+    #[tokio::test]
+    async fn test_only_downloaded_pipes_have_a_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("notes");
+        std::fs::create_dir_all(&source).unwrap();
+        std::fs::write(source.join("pipe.json"), r#"{"name": "notes"}"#).unwrap();
+        std::fs::write(source.join("pipe.ts"), "console.log('hi')").unwrap();
+        // Copied from a pipe that was downloaded from github
+        let lock = json!({
+            "source": "https://github.com/acme/pipes/tree/main/notes",
+            "git_ref": "main",
+            "path": "notes",
+            "sha": "9fceb02d0ae598e95dc970b74767f19372d61af8",
+        });
+        std::fs::write(source.join(PIPE_LOCK_FILE), lock.to_string()).unwrap();
+        let downloaded = downloaded_pipe(&source).await.unwrap();
+        assert_eq!(downloaded.source, lock["source"]);
+        assert_eq!(downloaded.commit.sha, lock["sha"]);
+
+        let installed = download_pipe(source.to_str().unwrap(), dir.path().join("screenpipe"))
+            .await
+            .unwrap();
+        assert!(installed.join("pipe.ts").exists());
+        // Its own lock has no commit
+        let lock: Value =
+            serde_json::from_slice(&std::fs::read(installed.join(PIPE_LOCK_FILE)).unwrap())
+                .unwrap();
+        assert_eq!(lock["source"], source.to_str().unwrap());
+        assert!(lock.get("sha").is_none());
+        assert!(downloaded_pipe(&installed).await.is_none());
+    }
+
+    fn files(tree: &GithubGitTree, folder: &str) -> Option<Vec<String>> {
+        let files = github_tree_files(tree, folder).unwrap()?;
+        Some(files.into_iter().map(|(_, rel)| rel).collect())
+    }
+
+    #[test]
+    fn test_tree_files_are_filtered_by_folder() {
+        let mut tree: GithubGitTree = serde_json::from_str(TREE).unwrap();
+
+        // Not notes-old, hidden files, or the submodule
+        assert_eq!(
+            github_tree_files(&tree, "pipes/notes").unwrap().unwrap(),
+            [
+                ("pipes/notes/pipe.json", "pipe.json"),
+                ("pipes/notes/pipe.ts", "pipe.ts"),
+                ("pipes/notes/src/lib.ts", "src/lib.ts"),
+            ]
+            .map(|(path, rel)| (path.to_string(), rel.to_string()))
+        );
+        assert_eq!(files(&tree, "pipes/notes/src").unwrap(), ["lib.ts"]);
+        assert_eq!(files(&tree, "pipes/notes/pipe.ts").unwrap(), ["pipe.ts"]);
+        assert_eq!(files(&tree, "").unwrap().len(), 5);
+        assert_eq!(
+            github_tree_files(&tree, "pipes/missing")
+                .unwrap_err()
+                .to_string(),
+            "pipes/missing isn't in the repo"
+        );
+
+        // A symlink is left to the contents api
+        tree.tree[7].mode = "120000".to_string();
+        assert_eq!(files(&tree, "pipes/notes"), None);
+        tree.tree[7].mode = "100644".to_string();
+        tree.truncated = true;
+        assert_eq!(files(&tree, "pipes/notes"), None);
+    }
+
+    fn commit(path: &str) -> GithubCommit {
+        GithubCommit {
+            git_ref: "main".to_string(),
+            path: path.to_string(),
+            sha: "9fceb02d0ae598e95dc970b74767f19372d61af8".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_a_pipe_is_downloaded_from_one_tree_listing() {
+        let server = MockServer::start_async().await;
+        let commit = commit("pipes/notes");
+        let tree = server
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path(format!("/repos/acme/pipes/git/trees/{}", commit.sha))
+                    .query_param("recursive", "1");
+                then.status(200).body(TREE);
+            })
+            .await;
+        let mut raw = Vec::new();
+        for file in ["pipe.json", "pipe.ts", "src/lib.ts"] {
+            raw.push(
+                server
+                    .mock_async(|when, then| {
+                        when.method(GET)
+                            .path(format!("/acme/pipes/{}/pipes/notes/{}", commit.sha, file));
+                        then.status(200).body(format!("// {}", file));
+                    })
+                    .await,
+            );
+        }
+        let contents = server
+            .mock_async(|when, then| {
+                when.method(GET).path_contains("/contents/");
+                then.status(500);
+            })
+            .await;
+
+        let source =
+            GithubSource::parse("https://github.com/acme/pipes/tree/main/pipes/notes").unwrap();
+        let dest = tempfile::tempdir().unwrap();
+        let client = reqwest::Client::new();
+        let api = server.base_url();
+        download_github_source(&client, &source, &commit, dest.path(), &api, &api)
+            .await
+            .unwrap();
+
+        tree.assert_async().await;
+        for mock in &raw {
+            mock.assert_async().await;
+        }
+        contents.assert_hits_async(0).await;
+        assert_eq!(
+            std::fs::read_to_string(dest.path().join("src/lib.ts")).unwrap(),
+            "// src/lib.ts"
+        );
+        assert!(!dest.path().join(".env").exists());
+    }
+
+    #[tokio::test]
+    async fn test_a_truncated_tree_falls_back_to_the_contents_api() {
+        let server = MockServer::start_async().await;
+        let commit = commit("pipe");
+        server
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path(format!("/repos/acme/pipes/git/trees/{}", commit.sha));
+                then.status(200)
+                    .json_body(json!({ "sha": commit.sha, "tree": [], "truncated": true }));
+            })
+            .await;
+        let listing = server
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path("/repos/acme/pipes/contents/pipe")
+                    .query_param("ref", &commit.sha);
+                then.status(200)
+                    .json_body(json!([file_entry(&server, "pipe/pipe.ts")]));
+            })
+            .await;
+        server
+            .mock_async(|when, then| {
+                when.method(GET).path("/raw/pipe/pipe.ts");
+                then.status(200).body("console.log('hi')");
+            })
+            .await;
+
+        let source = GithubSource::parse("https://github.com/acme/pipes/tree/main/pipe").unwrap();
+        let dest = tempfile::tempdir().unwrap();
+        let client = reqwest::Client::new();
+        let api = server.base_url();
+        download_github_source(&client, &source, &commit, dest.path(), &api, &api)
+            .await
+            .unwrap();
+
+        listing.assert_async().await;
+        assert!(dest.path().join("pipe.ts").exists());
+    }
+
+    #[tokio::test]
+    async fn test_a_failing_raw_file_fails_the_download() {
+        let server = MockServer::start_async().await;
+        let commit = commit("pipes/notes/src");
+        server
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path(format!("/repos/acme/pipes/git/trees/{}", commit.sha));
+                then.status(200).body(TREE);
+            })
+            .await;
+        server
+            .mock_async(|when, then| {
+                when.method(GET).path_contains("/lib.ts");
+                then.status(404).body("404: Not Found");
+            })
+            .await;
+
+        let source = GithubSource::parse("https://github.com/acme/pipes").unwrap();
+        let dest = tempfile::tempdir().unwrap();
+        let api = server.base_url();
+        let e = download_github_source(
+            &reqwest::Client::new(),
+            &source,
+            &commit,
+            dest.path(),
+            &api,
+            &api,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "failed to download lib.ts: github api returned status 404"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_a_flaky_file_is_fetched_again() {
+        let server = MockServer::start_async().await;
+        let commit = commit("pipes/notes/src");
+        let tree = server
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path(format!("/repos/acme/pipes/git/trees/{}", commit.sha));
+                then.status(200).body(TREE);
+            })
+            .await;
+        let failing = server
+            .mock_async(|when, then| {
+                when.method(GET).path_contains("/lib.ts");
+                then.status(502).body("502: Bad Gateway");
+            })
+            .await;
+
+        let source = GithubSource::parse("https://github.com/acme/pipes").unwrap();
+        let api = server.base_url();
+        let download = |dest: std::path::PathBuf, attempts| {
+            let (source, commit, api) = (source.clone(), commit.clone(), api.clone());
+            async move {
+                download_github_source_with(
+                    &reqwest::Client::new(),
+                    &source,
+                    &commit,
+                    &dest,
+                    &api,
+                    &api,
+                    Arc::default(),
+                    DEFAULT_DOWNLOAD_CONCURRENCY,
+                    attempts,
+                    None,
+                )
+                .await
+            }
+        };
+
+        let dest = tempfile::tempdir().unwrap();
+        let e = download(dest.path().to_path_buf(), 2).await.unwrap_err();
+        failing.assert_hits_async(2).await;
+        assert_eq!(
+            e.to_string(),
+            "failed to download lib.ts after 2 attempts: github api returned status 502"
+        );
+
+        // Answered once the server is back
+        failing.delete_async().await;
+        let failing = server
+            .mock_async(|when, then| {
+                when.method(GET).path_contains("/lib.ts");
+                then.status(503);
+            })
+            .await;
+        let dest = tempfile::tempdir().unwrap();
+        let retried = tokio::spawn(download(dest.path().to_path_buf(), 3));
+        while failing.hits_async().await == 0 {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        failing.delete_async().await;
+        let file = server
+            .mock_async(|when, then| {
+                when.method(GET).path_contains("/lib.ts");
+                then.status(200).body("export {}");
+            })
+            .await;
+        retried.await.unwrap().unwrap();
+        file.assert_async().await;
+        tree.assert_hits_async(2).await;
+        assert_eq!(
+            std::fs::read_to_string(dest.path().join("lib.ts")).unwrap(),
+            "export {}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_files_are_downloaded_concurrently() {
+        let server = MockServer::start_async().await;
+        let commit = commit("pipes/many");
+        let paths: Vec<String> = (0..24)
+            .map(|i| format!("pipes/many/src/{}/file.ts", i))
+            .collect();
+        let mut tree: Vec<Value> = paths
+            .iter()
+            .map(
+                |path| json!({ "path": path, "mode": "100644", "type": "blob", "sha": commit.sha }),
+            )
+            .collect();
+        tree.push(
+            json!({ "path": "pipes/many", "mode": "040000", "type": "tree", "sha": commit.sha }),
+        );
+        server
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path(format!("/repos/acme/pipes/git/trees/{}", commit.sha));
+                then.status(200)
+                    .json_body(json!({ "sha": commit.sha, "tree": tree, "truncated": false }));
+            })
+            .await;
+        let raw = server
+            .mock_async(|when, then| {
+                when.method(GET).path_contains("/pipes/many/src/");
+                then.status(200)
+                    .delay(std::time::Duration::from_millis(300))
+                    .body("export {}");
+            })
+            .await;
+
+        let source = GithubSource::parse("https://github.com/acme/pipes").unwrap();
+        let dest = tempfile::tempdir().unwrap();
+        let api = server.base_url();
+        let started = std::time::Instant::now();
+        let files = download_github_source_with(
+            &reqwest::Client::new(),
+            &source,
+            &commit,
+            dest.path(),
+            &api,
+            &api,
+            Arc::default(),
+            DEFAULT_DOWNLOAD_CONCURRENCY,
+            DEFAULT_DOWNLOAD_ATTEMPTS,
+            None,
+        )
+        .await
+        .unwrap();
+        // 7.2s one after the other
+        assert!(started.elapsed() < std::time::Duration::from_secs(4));
+        raw.assert_hits_async(24).await;
+        assert_eq!(files.files.len(), 24);
+        for i in 0..24 {
+            let path = dest.path().join(format!("src/{}/file.ts", i));
+            assert_eq!(std::fs::read_to_string(path).unwrap(), "export {}");
+        }
+    }
+
+    async fn reinstall(server: &MockServer, installed: &Path) -> tempfile::TempDir {
+        let source = GithubSource::parse("https://github.com/acme/pipes").unwrap();
+        let dest = tempfile::tempdir().unwrap();
+        let api = server.base_url();
+        let files = download_github_source_with(
+            &reqwest::Client::new(),
+            &source,
+            &commit("pipes/notes"),
+            dest.path(),
+            &api,
+            &api,
+            Arc::new(InstalledFiles::load(installed).await),
+            DEFAULT_DOWNLOAD_CONCURRENCY,
+            DEFAULT_DOWNLOAD_ATTEMPTS,
+            None,
+        )
+        .await
+        .unwrap();
+        files.save(dest.path()).await.unwrap();
+        dest
+    }
+
+    #[tokio::test]
+    async fn test_unchanged_files_are_kept_on_reinstall() {
+        let server = MockServer::start_async().await;
+        let commit = commit("pipes/notes");
+        let mut tree = server
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path(format!("/repos/acme/pipes/git/trees/{}", commit.sha));
+                then.status(200).body(TREE);
+            })
+            .await;
+        let mut raw = Vec::new();
+        for file in ["pipe.json", "pipe.ts", "src/lib.ts"] {
+            raw.push(
+                server
+                    .mock_async(|when, then| {
+                        when.method(GET)
+                            .path(format!("/acme/pipes/{}/pipes/notes/{}", commit.sha, file));
+                        then.status(200).body(format!("// {}", file));
+                    })
+                    .await,
+            );
+        }
+        let hits = |raw: &[httpmock::Mock]| raw.iter().map(|mock| mock.hits()).collect::<Vec<_>>();
+
+        let installed = reinstall(&server, Path::new("/nonexistent")).await;
+        let record: Value =
+            serde_json::from_slice(&std::fs::read(installed.path().join(PIPE_FILES_FILE)).unwrap())
+                .unwrap();
+        assert_eq!(
+            record["files"]["src/lib.ts"]["sha"],
+            "3b18e512dba79e4c8300dd08aeb37f8e728b8dad"
+        );
+        assert_eq!(record["files"]["src/lib.ts"]["size"], 13);
+
+        // Nothing changed, nothing is fetched
+        let installed = reinstall(&server, installed.path()).await;
+        assert_eq!(hits(&raw), [1, 1, 1]);
+        assert_eq!(
+            std::fs::read_to_string(installed.path().join("src/lib.ts")).unwrap(),
+            "// src/lib.ts"
+        );
+
+        // A file edited since, or at another sha now
+        std::fs::write(installed.path().join("pipe.ts"), "console.log('edited')").unwrap();
+        tree.delete_async().await;
+        let mut changed: Value = serde_json::from_str(TREE).unwrap();
+        for entry in changed["tree"].as_array_mut().unwrap() {
+            if entry["path"] == "pipes/notes/src/lib.ts" {
+                entry["sha"] = json!("0".repeat(40));
+            }
+        }
+        tree = server
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path(format!("/repos/acme/pipes/git/trees/{}", commit.sha));
+                then.status(200).json_body(changed);
+            })
+            .await;
+        let installed = reinstall(&server, installed.path()).await;
+        assert_eq!(hits(&raw), [1, 2, 2]);
+        assert_eq!(
+            std::fs::read_to_string(installed.path().join("pipe.ts")).unwrap(),
+            "// pipe.ts"
+        );
+
+        // A corrupt record downloads every file
+        std::fs::write(installed.path().join(PIPE_FILES_FILE), "{").unwrap();
+        reinstall(&server, installed.path()).await;
+        assert_eq!(hits(&raw), [2, 3, 3]);
+        tree.assert_hits_async(2).await;
+    }
+
+    #[tokio::test]
+    async fn test_private_repos_are_downloaded_with_the_token() {
+        let server = MockServer::start_async().await;
+        let commit = commit("pipes/notes/src");
+        let tree = server
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path(format!("/repos/acme/pipes/git/trees/{}", commit.sha))
+                    .header("authorization", "Bearer ghp_secret");
+                then.status(200).body(TREE);
+            })
+            .await;
+        let raw = server
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path_contains("/lib.ts")
+                    .header("authorization", "Bearer ghp_secret");
+                then.status(200).body("export {}");
+            })
+            .await;
+
+        let client = github_client(Some("ghp_secret")).unwrap();
+        assert!(!format!("{:?}", client).contains("ghp_secret"));
+        let source = GithubSource::parse("https://github.com/acme/pipes").unwrap();
+        let dest = tempfile::tempdir().unwrap();
+        let api = server.base_url();
+        download_github_source(&client, &source, &commit, dest.path(), &api, &api)
+            .await
+            .unwrap();
+        tree.assert_async().await;
+        raw.assert_async().await;
+
+        let options = DownloadOptions {
+            locked: true,
+            token: Some("ghp_secret".to_string()),
+            ..Default::default()
+        };
+        assert!(!format!("{:?}", options).contains("ghp_secret"));
+    }
+
+    #[tokio::test]
+    async fn test_a_stored_token_is_loaded_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let screenpipe_dir = dir.path().join("screenpipe");
+        assert_eq!(load_github_token(&screenpipe_dir).await.unwrap(), None);
+
+        store_github_token(" ghp_secret\n", &screenpipe_dir)
+            .await
+            .unwrap();
+        assert_eq!(
+            load_github_token(&screenpipe_dir).await.unwrap().as_deref(),
+            Some("ghp_secret")
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let path = screenpipe_dir.join(GITHUB_TOKEN_FILE);
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+            store_github_token("ghp_other", &screenpipe_dir)
+                .await
+                .unwrap();
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        for token in ["", "  ", "ghp\nsecret"] {
+            assert!(store_github_token(token, &screenpipe_dir).await.is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_a_repo_github_doesnt_show_asks_for_a_token() {
+        let server = MockServer::start_async().await;
+        server
+            .mock_async(|when, then| {
+                when.method(GET).path_contains("/repos/acme/internal");
+                then.status(404).body(NOT_FOUND);
+            })
+            .await;
+        let api = server.base_url();
+        let client = reqwest::Client::new();
+
+        for url in [
+            "https://github.com/acme/internal",
+            "https://github.com/acme/internal/tree/main/notes",
+            "https://github.com/acme/internal/tree/feature/foo/notes",
+        ] {
+            let source = GithubSource::parse(url).unwrap();
+            let e = source.commit(&client, &api).await.unwrap_err();
+            assert_eq!(
+                e.to_string(),
+                "github repo acme/internal not found or token missing, set GITHUB_TOKEN to \
+                 download pipes from a private repo",
+                "{}",
+                url
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rate_limits_tell_when_they_reset() {
+        let server = MockServer::start_async().await;
+        let reset = chrono::Utc::now().timestamp() + 600;
+        server
+            .mock_async(|when, then| {
+                when.method(GET).path("/repos/acme/pipes/contents/pipe");
+                then.status(403)
+                    .header("x-ratelimit-limit", "60")
+                    .header("x-ratelimit-remaining", "0")
+                    .header("x-ratelimit-reset", reset.to_string())
+                    .body(RATE_LIMITED);
+            })
+            .await;
+        server
+            .mock_async(|when, then| {
+                when.method(GET).path("/repos/acme/pipes/commits/main");
+                then.status(429).header("retry-after", "30");
+            })
+            .await;
+
+        let dest = tempfile::tempdir().unwrap();
+        let e = download_github_listing(
+            &server.url("/repos/acme/pipes/contents/pipe?ref=main"),
+            dest.path(),
+        )
+        .await
+        .unwrap_err();
+        let limited = e.downcast_ref::<GithubRateLimited>().unwrap();
+        assert_eq!(limited.reset_at.unwrap().timestamp(), reset);
+        let message = e.to_string();
+        assert!(message.starts_with("github api rate limit exceeded, it resets at "));
+        assert!(message.contains("(in 10 min)"), "{}", message);
+        assert!(message.ends_with("set GITHUB_TOKEN for a higher limit"));
+
+        let source = GithubSource::parse("https://github.com/acme/pipes/tree/main/pipe").unwrap();
+        let e = source
+            .commit(&reqwest::Client::new(), &server.base_url())
+            .await
+            .unwrap_err();
+        assert!(e.to_string().contains("(in 1 min)"), "{}", e);
+
+        let limited = GithubRateLimited {
+            reset_at: None,
+            source: Some("https://github.com/acme/pipes/tree/main/pipe".to_string()),
+        };
+        assert_eq!(
+            limited.to_string(),
+            "github api rate limit exceeded downloading \
+             https://github.com/acme/pipes/tree/main/pipe, set GITHUB_TOKEN for a higher limit"
+        );
+
+        // Other 403s, e.g. a repo blocked for legal reasons, are what github says
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert("x-ratelimit-remaining", "59".parse().unwrap());
+        assert_eq!(GithubRateLimited::from_headers(403, &headers), None);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_requests_are_retried_once_it_resets() {
+        let server = MockServer::start_async().await;
+        let limited = server
+            .mock_async(|when, then| {
+                when.method(GET).path("/repos/acme/pipes");
+                then.status(429).header("retry-after", "1");
+            })
+            .await;
+
+        let url = server.url("/repos/acme/pipes");
+        let request =
+            tokio::spawn(
+                async move { request_with_backoff(&reqwest::Client::new(), &url, 3).await },
+            );
+        while limited.hits_async().await == 0 {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        limited.delete_async().await;
+        let ok = server
+            .mock_async(|when, then| {
+                when.method(GET).path("/repos/acme/pipes");
+                then.status(200).body("{}");
+            })
+            .await;
+        let response = request.await.unwrap().unwrap();
+        assert_eq!(response.text().await.unwrap(), "{}");
+        ok.assert_async().await;
+
+        // Given up once the attempts are used up
+        let limited = server
+            .mock_async(|when, then| {
+                when.method(GET).path("/repos/acme/other");
+                then.status(403)
+                    .header("x-ratelimit-remaining", "0")
+                    .header("retry-after", "0")
+                    .body(RATE_LIMITED);
+            })
+            .await;
+        let e = request_with_backoff(&reqwest::Client::new(), &server.url("/repos/acme/other"), 2)
+            .await
+            .unwrap_err();
+        limited.assert_hits_async(2).await;
+        assert!(e.downcast_ref::<GithubRateLimited>().is_some());
+        assert!(e.to_string().starts_with("github api rate limit exceeded"));
+    }
+
+    #[tokio::test]
+    async fn test_a_rate_limit_resetting_after_the_max_wait_fails_right_away() {
+        let server = MockServer::start_async().await;
+        let limited = server
+            .mock_async(|when, then| {
+                when.method(GET).path("/repos/acme/pipes");
+                then.status(429).header("retry-after", "2");
+            })
+            .await;
+
+        let http = HttpOptions {
+            max_rate_limit_wait: Some(std::time::Duration::from_secs(1)),
+            ..Default::default()
+        };
+        let started = std::time::Instant::now();
+        let client = reqwest::Client::new();
+        let url = server.url("/repos/acme/pipes");
+        let request = request_with_backoff(&client, &url, 3);
+        let e = with_rate_limit_wait(&http, request).await.unwrap_err();
+        assert!(started.elapsed() < std::time::Duration::from_secs(2));
+        limited.assert_hits_async(1).await;
+        assert!(e.downcast_ref::<GithubRateLimited>().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_updates_are_found_by_semver() {
+        let sha = "9fceb02d0ae598e95dc970b74767f19372d61af8";
+        let server = MockServer::start_async().await;
+        server
+            .mock_async(|when, then| {
+                when.method(GET).path("/repos/acme/pipes/commits/main");
+                then.status(200).body(sha);
+            })
+            .await;
+        server
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path(format!("/acme/pipes/{}/notes/pipe.json", sha));
+                then.status(404).body("404: Not Found");
+            })
+            .await;
+        server
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path(format!("/acme/pipes/{}/notes/package.json", sha));
+                then.status(200)
+                    .json_body(json!({ "name": "notes", "version": "1.10.0" }));
+            })
+            .await;
+        let client = reqwest::Client::new();
+        let source = "https://github.com/acme/pipes/tree/main/notes";
+
+        let dir = tempfile::tempdir().unwrap();
+        let installed = |version: &str| {
+            let pipe_json = json!({ "name": "notes", "version": version, "source": source });
+            std::fs::write(dir.path().join("pipe.json"), pipe_json.to_string()).unwrap();
+        };
+        installed("1.9.0");
+        let update =
+            check_pipe_update_with(dir.path(), &client, &server.base_url(), &server.base_url())
+                .await
+                .unwrap()
+                .unwrap();
+        assert_eq!(update.current.to_string(), "1.9.0");
+        assert_eq!(update.latest.to_string(), "1.10.0");
+        assert_eq!(update.source, source);
+
+        installed("1.10.0");
+        let update =
+            check_pipe_update_with(dir.path(), &client, &server.base_url(), &server.base_url())
+                .await
+                .unwrap();
+        assert_eq!(update, None);
+
+        // A pipe from a local path is updated from it
+        let screenpipe_dir = tempfile::tempdir().unwrap();
+        let local = dir.path().join("notes");
+        std::fs::create_dir_all(&local).unwrap();
+        std::fs::write(
+            local.join("pipe.json"),
+            r#"{"name": "notes", "version": "1.0.0"}"#,
+        )
+        .unwrap();
+        std::fs::write(local.join("pipe.ts"), "").unwrap();
+        let pipe_dir = download_pipe(local.to_str().unwrap(), screenpipe_dir.path().to_path_buf())
+            .await
+            .unwrap();
+        let e = update_pipe_version(&pipe_dir, screenpipe_dir.path(), &client)
+            .await
+            .unwrap_err();
+        assert!(e.to_string().ends_with("pipe has no source to update from"));
+
+        let pipe_json =
+            json!({ "name": "notes", "version": "1.0.0", "source": local, "enabled": true });
+        std::fs::write(pipe_dir.join("pipe.json"), pipe_json.to_string()).unwrap();
+        std::fs::write(
+            local.join("pipe.json"),
+            r#"{"name": "notes", "version": "1.1.0"}"#,
+        )
+        .unwrap();
+        update_pipe_version(&pipe_dir, screenpipe_dir.path(), &client)
+            .await
+            .unwrap();
+        let updated: Value =
+            serde_json::from_str(&std::fs::read_to_string(pipe_dir.join("pipe.json")).unwrap())
+                .unwrap();
+        assert_eq!(updated["version"], "1.1.0");
+        assert_eq!(updated["enabled"], true);
+    }
+}
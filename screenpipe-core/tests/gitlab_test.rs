@@ -0,0 +1,358 @@
+#[cfg(feature = "pipes")]
+#[cfg(test)]
+#[allow(deprecated)]
+mod tests {
+    use httpmock::prelude::*;
+    use screenpipe_core::pipe_gitlab::{gitlab_client, GitlabSource, GITLAB_HOSTS_ENV};
+    use screenpipe_core::{
+        download_pipe_with, download_pipe_with_progress, downloaded_pipe, pipe_id_from_source,
+        DownloadOptions, DownloadProgress,
+    };
+    use serde_json::json;
+    use std::sync::Arc;
+
+    const SHA: &str = "9fceb02d0ae598e95dc970b74767f19372d61af8";
+
+    #[test]
+    fn test_gitlab_urls_are_parsed() {
+        let source =
+            GitlabSource::parse("https://gitlab.com/acme/tools/pipes/-/tree/main/pipes/notes")
+                .unwrap();
+        assert_eq!(source.base_url, "https://gitlab.com");
+        assert_eq!(source.project, "acme/tools/pipes");
+        assert_eq!(source.tree, ["main", "pipes", "notes"]);
+
+        // Installed under the same ids as github pipes
+        for (gitlab, github) in [
+            (
+                "https://gitlab.com/acme/pipes/-/tree/main/pipes/notes",
+                "https://github.com/acme/pipes/tree/main/pipes/notes",
+            ),
+            (
+                "https://gitlab.com/acme/my-pipe",
+                "https://github.com/acme/my-pipe",
+            ),
+            (
+                "https://gitlab.com/acme/my-pipe.git",
+                "https://github.com/acme/my-pipe.git",
+            ),
+            (
+                "https://gitlab.com/acme/my-pipe/-/tree/dev",
+                "https://github.com/acme/my-pipe/tree/dev",
+            ),
+        ] {
+            assert_eq!(pipe_id_from_source(gitlab), pipe_id_from_source(github));
+        }
+
+        let pinned = format!("https://gitlab.com/acme/pipes/-/blob/{}/pipes/notes", SHA);
+        assert_eq!(GitlabSource::parse(&pinned).unwrap().tree[0], SHA);
+        assert_eq!(
+            GitlabSource::parse("https://gitlab.com/acme/pipes/-/blob/main/pipe.ts"),
+            None
+        );
+        assert_eq!(
+            GitlabSource::parse("https://gitlab.com/acme/pipes/-/issues"),
+            None
+        );
+        assert_eq!(GitlabSource::parse("https://gitlab.com/acme"), None);
+
+        // Self-hosted, by the shape of the url or a listed host
+        let source =
+            GitlabSource::parse("https://git.example.com:8443/tools/pipes/-/tree/main").unwrap();
+        assert_eq!(source.base_url, "https://git.example.com:8443");
+        assert_eq!(source.project, "tools/pipes");
+        assert_eq!(
+            GitlabSource::parse("https://git.example.com/tools/pipes"),
+            None
+        );
+        std::env::set_var(GITLAB_HOSTS_ENV, "gitlab.internal, git.example.com");
+        let source = GitlabSource::parse("https://git.example.com/tools/pipes").unwrap();
+        assert!(source.tree.is_empty());
+        std::env::remove_var(GITLAB_HOSTS_ENV);
+    }
+
+    fn entry(path: &str, kind: &str) -> serde_json::Value {
+        json!({
+            "id": "3b18e512dba79e4c8300dd08aeb37f8e728b8dad",
+            "name": path.rsplit('/').next().unwrap(),
+            "type": kind,
+            "path": path,
+            "mode": if kind == "tree" { "040000" } else { "100644" },
+        })
+    }
+
+    #[tokio::test]
+    async fn test_a_folder_is_downloaded_page_by_page() {
+        let server = MockServer::start_async().await;
+        let project = "/api/v4/projects/acme%2Ftools%2Fpipes";
+        let branch = server
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path(format!("{}/repository/commits/feature", project));
+                then.status(404)
+                    .json_body(json!({ "message": "404 Commit Not Found" }));
+            })
+            .await;
+        server
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path(format!("{}/repository/commits/feature%2Fnotes", project));
+                then.status(200).json_body(json!({ "id": SHA }));
+            })
+            .await;
+        let first_page = server
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path(format!("{}/repository/tree", project))
+                    .query_param("ref", SHA)
+                    .query_param("path", "pipes/notes")
+                    .query_param("recursive", "true")
+                    .query_param("page", "1")
+                    .header("private-token", "glpat-secret");
+                then.status(200)
+                    .header("x-next-page", "2")
+                    .json_body(json!([
+                        entry("pipes/notes/pipe.ts", "blob"),
+                        entry("pipes/notes/.env", "blob"),
+                        entry("pipes/notes/src", "tree"),
+                    ]));
+            })
+            .await;
+        let second_page = server
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path(format!("{}/repository/tree", project))
+                    .query_param("page", "2");
+                then.status(200).header("x-next-page", "").json_body(json!([
+                    entry("pipes/notes/src/lib.ts", "blob"),
+                    entry("pipes/notes/vendor", "commit"),
+                ]));
+            })
+            .await;
+        let mut raw = Vec::new();
+        for file in ["pipe.ts", "src/lib.ts"] {
+            raw.push(
+                server
+                    .mock_async(|when, then| {
+                        when.method(GET)
+                            .path(format!(
+                                "{}/repository/files/pipes%2Fnotes%2F{}/raw",
+                                project,
+                                file.replace('/', "%2F")
+                            ))
+                            .query_param("ref", SHA);
+                        then.status(200).body(format!("// {}", file));
+                    })
+                    .await,
+            );
+        }
+
+        let source =
+            GitlabSource::parse(&server.url("/acme/tools/pipes/-/tree/feature/notes/pipes/notes"))
+                .unwrap();
+        assert_eq!(source.base_url, server.base_url());
+        let client = gitlab_client(Some("glpat-secret")).unwrap();
+        assert!(!format!("{:?}", client).contains("glpat-secret"));
+        let commit = source.commit(&client).await.unwrap();
+        branch.assert_async().await;
+        assert_eq!(commit.git_ref, "feature/notes");
+        assert_eq!(commit.path, "pipes/notes");
+        assert_eq!(commit.sha, SHA);
+
+        let dest = tempfile::tempdir().unwrap();
+        let files = source
+            .download(&client, &commit, dest.path(), Arc::default(), 2, 3, None)
+            .await
+            .unwrap();
+        first_page.assert_async().await;
+        second_page.assert_async().await;
+        for mock in &raw {
+            mock.assert_async().await;
+        }
+        assert_eq!(
+            files.files.keys().collect::<Vec<_>>(),
+            ["pipe.ts", "src/lib.ts"]
+        );
+        assert_eq!(
+            std::fs::read_to_string(dest.path().join("src/lib.ts")).unwrap(),
+            "// src/lib.ts"
+        );
+        assert!(!dest.path().join(".env").exists());
+        assert!(!dest.path().join("vendor").exists());
+    }
+
+    #[tokio::test]
+    async fn test_a_project_gitlab_doesnt_show_asks_for_a_token() {
+        let server = MockServer::start_async().await;
+        server
+            .mock_async(|when, then| {
+                when.method(GET).path_contains("/api/v4/projects/");
+                then.status(404)
+                    .json_body(json!({ "message": "404 Project Not Found" }));
+            })
+            .await;
+
+        let source = GitlabSource::parse(&server.url("/acme/secret/-/tree/main")).unwrap();
+        let e = source
+            .commit(&gitlab_client(None).unwrap())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "gitlab project acme/secret not found or token missing, set GITLAB_TOKEN to \
+             download pipes from a private project"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_a_pipe_pinned_to_a_commit_is_downloaded_once() {
+        let server = MockServer::start_async().await;
+        let project = "/api/v4/projects/acme%2Fpipes";
+        let branch = server
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path(format!("{}/repository/commits/main", project));
+                then.status(200).json_body(json!({ "id": "0".repeat(40) }));
+            })
+            .await;
+        server
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path(format!("{}/repository/commits/{}", project, SHA));
+                then.status(200).json_body(json!({ "id": SHA }));
+            })
+            .await;
+        let tree = server
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path(format!("{}/repository/tree", project))
+                    .query_param("ref", SHA)
+                    .query_param("path", "notes");
+                then.status(200).json_body(json!([
+                    entry("notes/pipe.json", "blob"),
+                    entry("notes/pipe.ts", "blob"),
+                ]));
+            })
+            .await;
+        for (file, content) in [
+            ("pipe.json", r#"{"name": "notes", "version": "1.0.0"}"#),
+            ("pipe.ts", "console.log('notes')"),
+        ] {
+            server
+                .mock_async(|when, then| {
+                    when.method(GET)
+                        .path(format!("{}/repository/files/notes%2F{}/raw", project, file))
+                        .query_param("ref", SHA);
+                    then.status(200).body(content);
+                })
+                .await;
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let source = server.url("/acme/pipes/-/tree/main/notes");
+        let options = || DownloadOptions {
+            git_ref: Some(SHA.to_string()),
+            ..Default::default()
+        };
+        let pipe_dir = download_pipe_with(&source, dir.path().to_path_buf(), options())
+            .await
+            .unwrap();
+        branch.assert_async().await;
+        let installed = downloaded_pipe(&pipe_dir).await.unwrap();
+        assert_eq!(installed.commit.git_ref, SHA);
+        assert_eq!(installed.commit.sha, SHA);
+        assert_eq!(installed.commit.path, "notes");
+
+        // The commit can't change, the installed copy is kept
+        download_pipe_with(&source, dir.path().to_path_buf(), options())
+            .await
+            .unwrap();
+        tree.assert_hits_async(1).await;
+
+        let e = download_pipe_with("/tmp/pipes/notes", dir.path().to_path_buf(), options())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "/tmp/pipes/notes has no refs, a ref is only given for pipes from github, gitlab, bitbucket or git"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_a_download_reports_its_progress() {
+        let server = MockServer::start_async().await;
+        let project = "/api/v4/projects/acme%2Fpipes";
+        server
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path(format!("{}/repository/commits/main", project));
+                then.status(200).json_body(json!({ "id": SHA }));
+            })
+            .await;
+        server
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path(format!("{}/repository/tree", project));
+                then.status(200).json_body(json!([
+                    entry("notes/pipe.json", "blob"),
+                    entry("notes/pipe.ts", "blob"),
+                ]));
+            })
+            .await;
+        for (file, content) in [
+            ("pipe.json", r#"{"name": "notes", "version": "1.0.0"}"#),
+            ("pipe.ts", "console.log('notes')"),
+        ] {
+            server
+                .mock_async(|when, then| {
+                    when.method(GET)
+                        .path(format!("{}/repository/files/notes%2F{}/raw", project, file));
+                    then.status(200).body(content);
+                })
+                .await;
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let (tx, mut rx) = tokio::sync::mpsc::channel(16);
+        let source = server.url("/acme/pipes/-/tree/main/notes");
+        let pipe_dir = download_pipe_with_progress(&source, dir.path().to_path_buf(), tx)
+            .await
+            .unwrap();
+        let mut events = Vec::new();
+        while let Some(event) = rx.recv().await {
+            events.push(event);
+        }
+        assert_eq!(events.len(), 3);
+        let mut written = Vec::new();
+        for (i, event) in events[..2].iter().enumerate() {
+            let DownloadProgress::File {
+                total_files,
+                files_done,
+                file,
+                bytes_downloaded,
+            } = event
+            else {
+                panic!("{:?} isn't a file", event);
+            };
+            assert_eq!((*total_files, *files_done), (2, i + 1));
+            assert!(*bytes_downloaded > 0);
+            written.push(file.as_str());
+        }
+        written.sort();
+        assert_eq!(written, ["pipe.json", "pipe.ts"]);
+        assert_eq!(events[2], DownloadProgress::Completed { pipe_dir });
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(16);
+        let missing = dir.path().join("missing");
+        download_pipe_with_progress(missing.to_str().unwrap(), dir.path().to_path_buf(), tx)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            rx.recv().await,
+            Some(DownloadProgress::Failed {
+                error: "Invalid local source path".to_string()
+            })
+        );
+        assert_eq!(rx.recv().await, None);
+    }
+}
@@ -0,0 +1,190 @@
+#[cfg(test)]
+mod tests {
+    use screenpipe_core::latency::{
+        LatencyBudget, LatencyEvent, LatencyStage, LatencyStamps, LatencyTracker, Percentiles,
+        PipelineKind, LATENCY_WINDOW,
+    };
+    use std::time::{Duration, Instant};
+
+    fn stamps(processing_ms: u64, commit_ms: u64) -> LatencyStamps {
+        let captured_at = Instant::now();
+        let processed_at = captured_at + Duration::from_millis(processing_ms);
+        LatencyStamps {
+            captured_at,
+            processed_at: Some(processed_at),
+            committed_at: Some(processed_at + Duration::from_millis(commit_ms)),
+        }
+    }
+
+    fn record(tracker: &LatencyTracker, pipeline: PipelineKind, end_to_end_ms: u64, count: usize) {
+        for _ in 0..count {
+            tracker.record(pipeline, &stamps(end_to_end_ms / 2, end_to_end_ms / 2));
+        }
+    }
+
+    #[test]
+    fn test_stamps_are_monotonic_per_item() {
+        // Stamped the way the pipelines do it, one item after the other
+        let mut previous: Option<LatencyStamps> = None;
+        for _ in 0..100 {
+            let mut item = LatencyStamps::now();
+            assert!(item.is_monotonic());
+            let mut item_processed = item.processed();
+            assert!(item_processed.is_monotonic());
+            item_processed.mark_committed();
+            assert!(item_processed.is_monotonic());
+            item.mark_processed();
+            item.mark_committed();
+            assert!(item.is_monotonic());
+
+            for stamps in [item, item_processed] {
+                let processing = stamps.stage(LatencyStage::Processing).unwrap();
+                let commit = stamps.stage(LatencyStage::Commit).unwrap();
+                assert_eq!(
+                    stamps.stage(LatencyStage::EndToEnd).unwrap(),
+                    processing + commit
+                );
+            }
+            if let Some(previous) = previous {
+                assert!(item.captured_at >= previous.captured_at);
+            }
+            previous = Some(item);
+        }
+
+        let now = Instant::now();
+        let backwards = LatencyStamps {
+            captured_at: now + Duration::from_secs(1),
+            processed_at: Some(now),
+            committed_at: Some(now + Duration::from_secs(2)),
+        };
+        assert!(!backwards.is_monotonic());
+        let skipped = LatencyStamps {
+            captured_at: now,
+            processed_at: None,
+            committed_at: Some(now),
+        };
+        assert!(!skipped.is_monotonic());
+        assert_eq!(skipped.stage(LatencyStage::EndToEnd), Some(Duration::ZERO));
+
+        let tracker = LatencyTracker::new();
+        tracker.record(PipelineKind::Vision, &backwards);
+        tracker.record(PipelineKind::Vision, &skipped);
+        tracker.record(PipelineKind::Vision, &LatencyStamps::now());
+        let snapshot = tracker.snapshot();
+        assert_eq!(snapshot.non_monotonic, 3);
+        assert!(snapshot.pipelines.is_empty());
+    }
+
+    #[test]
+    fn test_percentiles_per_stage() {
+        let samples: Vec<Duration> = (1..=100).map(Duration::from_millis).collect();
+        let percentiles = Percentiles::of(&samples).unwrap();
+        assert_eq!(percentiles.p50_ms, 50.0);
+        assert_eq!(percentiles.p95_ms, 95.0);
+        assert_eq!(percentiles.p99_ms, 99.0);
+        assert_eq!(Percentiles::of(&[]), None);
+
+        let tracker = LatencyTracker::new();
+        for ms in 1..=100 {
+            tracker.record(PipelineKind::Audio, &stamps(ms * 10, ms));
+        }
+        let snapshot = tracker.snapshot();
+        let audio = &snapshot.pipelines[&PipelineKind::Audio];
+        assert_eq!(audio.samples, 100);
+        assert_eq!(audio.stages[&LatencyStage::Processing].p95_ms, 950.0);
+        assert_eq!(audio.stages[&LatencyStage::Commit].p50_ms, 50.0);
+        assert_eq!(audio.stages[&LatencyStage::EndToEnd].p99_ms, 1089.0);
+        assert_eq!(audio.searchable_within_ms, Some(1045.0));
+        assert_eq!(
+            snapshot.searchable_within(),
+            Some(Duration::from_millis(1045))
+        );
+
+        // The window only keeps the latest items
+        record(&tracker, PipelineKind::Audio, 10, LATENCY_WINDOW);
+        let audio = &tracker.snapshot().pipelines[&PipelineKind::Audio];
+        assert_eq!(audio.samples, LATENCY_WINDOW);
+        assert_eq!(audio.searchable_within_ms, Some(10.0));
+    }
+
+    #[test]
+    fn test_budget_alerts_only_when_sustained() {
+        let tracker = LatencyTracker::new();
+        assert!(tracker.check_budget().is_empty());
+
+        tracker.set_budget(Some(LatencyBudget {
+            end_to_end: Duration::from_secs(2),
+            auto_degrade: false,
+            sustained_checks: 3,
+        }));
+
+        // One slow check between fast ones never alerts
+        for end_to_end_ms in [5000, 100, 5000, 5000] {
+            record(&tracker, PipelineKind::Vision, end_to_end_ms, 20);
+            assert!(tracker.check_budget().is_empty());
+        }
+        // Checks without new items don't count either way
+        assert!(tracker.check_budget().is_empty());
+
+        record(&tracker, PipelineKind::Vision, 5000, 20);
+        assert_eq!(
+            tracker.check_budget(),
+            vec![LatencyEvent::BudgetExceeded {
+                pipeline: PipelineKind::Vision,
+                p95_ms: 5000,
+                budget_ms: 2000,
+            }]
+        );
+        assert!(tracker.snapshot().over_budget());
+        assert!(!tracker.degradation(PipelineKind::Vision).is_active());
+    }
+
+    #[test]
+    fn test_auto_degrade_and_recover() {
+        let tracker = LatencyTracker::new();
+        tracker.set_budget(Some(LatencyBudget {
+            end_to_end: Duration::from_secs(2),
+            auto_degrade: true,
+            sustained_checks: 2,
+        }));
+        let interval = Duration::from_millis(500);
+
+        for _ in 0..2 {
+            record(&tracker, PipelineKind::Vision, 5000, 10);
+            record(&tracker, PipelineKind::Audio, 100, 10);
+            tracker.check_budget();
+        }
+        let vision = tracker.degradation(PipelineKind::Vision);
+        assert_eq!(vision.level, 1);
+        assert_eq!(tracker.capture_interval(interval), interval * 2);
+        assert!(!tracker.degradation(PipelineKind::Audio).small_model());
+
+        // Degrades one level per sustained breach, up to the max
+        for _ in 0..6 {
+            record(&tracker, PipelineKind::Vision, 5000, 10);
+            tracker.check_budget();
+        }
+        assert_eq!(tracker.capture_interval(interval), interval * 4);
+
+        // Between half the budget and the budget holds the level
+        for _ in 0..4 {
+            record(&tracker, PipelineKind::Vision, 1500, 10);
+            assert!(tracker.check_budget().is_empty());
+        }
+
+        record(&tracker, PipelineKind::Vision, 100, 10);
+        tracker.check_budget();
+        record(&tracker, PipelineKind::Vision, 100, 10);
+        assert_eq!(
+            tracker.check_budget(),
+            vec![LatencyEvent::Recovered {
+                pipeline: PipelineKind::Vision,
+                level: 1,
+            }]
+        );
+
+        // Turning auto degrade off restores full capture straight away
+        tracker.set_budget(Some(LatencyBudget::new(Duration::from_secs(2))));
+        assert_eq!(tracker.capture_interval(interval), interval);
+    }
+}
@@ -0,0 +1,117 @@
+#[cfg(test)]
+mod tests {
+    use screenpipe_core::models::{model_registry, ModelSlot, ResidentModel};
+    use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+    use std::sync::{mpsc, Arc};
+    use std::thread;
+    use std::time::Duration;
+
+    /// A slot whose model is the number of times it was loaded.
+    fn counting_slot(name: &str) -> (Arc<ModelSlot<u32>>, Arc<AtomicU32>) {
+        let loads = Arc::new(AtomicU32::new(0));
+        let counter = loads.clone();
+        let slot = ModelSlot::new(name, move || {
+            thread::sleep(Duration::from_millis(20));
+            Ok(counter.fetch_add(1, Ordering::SeqCst) + 1)
+        });
+        (slot, loads)
+    }
+
+    #[test]
+    fn test_models_load_on_demand_and_reload_after_unload() {
+        let (slot, loads) = counting_slot("lazy-test");
+        assert!(!slot.status().resident);
+        assert_eq!(loads.load(Ordering::SeqCst), 0);
+
+        let (value, load) = slot.with(|model| *model).unwrap();
+        assert_eq!(value, 1);
+        assert!(load.unwrap() >= Duration::from_millis(20));
+        let (_, load) = slot.with(|model| *model).unwrap();
+        assert_eq!(load, None);
+
+        assert!(slot.unload());
+        assert!(!slot.unload());
+        let status = slot.status();
+        assert!(!status.resident && status.last_used.is_some());
+
+        assert_eq!(slot.with(|model| *model).unwrap().0, 2);
+        let status = slot.status();
+        assert_eq!((status.loads, status.unloads), (2, 1));
+        assert!(status.resident && status.avg_load_ms.unwrap() >= 20.0);
+    }
+
+    #[test]
+    fn test_load_failures_are_returned_and_retried() {
+        let attempts = Arc::new(AtomicU32::new(0));
+        let counter = attempts.clone();
+        let slot = ModelSlot::new("failing-test", move || {
+            match counter.fetch_add(1, Ordering::SeqCst) {
+                0 => anyhow::bail!("weights missing"),
+                _ => Ok(()),
+            }
+        });
+        assert!(slot.load().is_err());
+        assert!(!slot.status().resident);
+        assert!(slot.with(|_| ()).is_ok());
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_unloading_waits_for_inference() {
+        let (slot, _) = counting_slot("busy-test");
+        slot.load().unwrap();
+
+        let (started_tx, started_rx) = mpsc::channel();
+        let finished = Arc::new(AtomicBool::new(false));
+        let (busy, done) = (slot.clone(), finished.clone());
+        let inference = thread::spawn(move || {
+            busy.with(|_| {
+                started_tx.send(()).unwrap();
+                thread::sleep(Duration::from_millis(200));
+                done.store(true, Ordering::SeqCst);
+            })
+            .unwrap();
+        });
+        started_rx.recv().unwrap();
+        assert!(slot.status().in_use && slot.status().resident);
+
+        // Never idle while in use
+        assert!(!slot.unload_if_idle(Duration::ZERO));
+        assert!(slot.unload());
+        // The inference finished before the model went away
+        assert!(finished.load(Ordering::SeqCst));
+        inference.join().unwrap();
+        assert!(!slot.status().resident);
+    }
+
+    #[test]
+    fn test_registry_unloads_idle_models() {
+        let (used, _) = counting_slot("idle-used-test");
+        let (unused, _) = counting_slot("idle-unused-test");
+        used.load().unwrap();
+        unused.load().unwrap();
+
+        thread::sleep(Duration::from_millis(150));
+        used.with(|_| ()).unwrap();
+        let unloaded = model_registry().unload_idle(Duration::from_millis(100));
+        assert!(unloaded.contains(&"idle-unused-test".to_string()));
+        assert!(!unloaded.contains(&"idle-used-test".to_string()));
+        assert!(used.status().resident && !unused.status().resident);
+
+        let names: Vec<String> = model_registry()
+            .statuses()
+            .into_iter()
+            .map(|status| status.name)
+            .collect();
+        assert!(names.contains(&"idle-used-test".to_string()));
+
+        // Dropped slots leave the registry
+        drop(unused);
+        let names: Vec<String> = model_registry()
+            .statuses()
+            .into_iter()
+            .map(|status| status.name)
+            .collect();
+        assert!(!names.contains(&"idle-unused-test".to_string()));
+    }
+}
@@ -0,0 +1,142 @@
+#[cfg(feature = "pipes")]
+#[cfg(test)]
+mod tests {
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use httpmock::prelude::*;
+    use reqwest::Client;
+    use screenpipe_core::pipe_id_from_source;
+    use screenpipe_core::pipe_npm::NpmSource;
+    use serde_json::json;
+    use sha2::{Digest, Sha512};
+    use tempfile::tempdir;
+
+    fn tar_gz(files: &[(&str, &str)]) -> Vec<u8> {
+        let gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        let mut tar = tar::Builder::new(gz);
+        for (path, content) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            tar.append_data(&mut header, path, content.as_bytes())
+                .unwrap();
+        }
+        tar.into_inner().unwrap().finish().unwrap()
+    }
+
+    fn integrity(tarball: &[u8]) -> String {
+        format!("sha512-{}", STANDARD.encode(Sha512::digest(tarball)))
+    }
+
+    #[test]
+    fn test_npm_sources_are_parsed() {
+        let source = NpmSource::parse("npm:@scope/my-pipe@1.2.3").unwrap();
+        assert_eq!(source.package, "@scope/my-pipe");
+        assert_eq!(source.version.as_deref(), Some("1.2.3"));
+        assert_eq!(source.pipe_id(), "scope-my-pipe");
+        assert_eq!(
+            NpmSource::parse("npm:my-pipe"),
+            Some(NpmSource {
+                package: "my-pipe".to_string(),
+                version: None,
+            })
+        );
+        assert_eq!(
+            pipe_id_from_source("npm:@scope/my-pipe@^1.2").as_deref(),
+            Some("scope-my-pipe")
+        );
+        for source in ["npm:", "npm:@scope", "npm:my pipe", "my-pipe@1.2.3"] {
+            assert_eq!(NpmSource::parse(source), None, "{}", source);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_the_highest_version_in_range_is_installed() {
+        let server = MockServer::start_async().await;
+        let tarballs = ["1.1.0", "1.2.0", "1.4.2", "2.0.0"].map(|version| {
+            let pipe_json = json!({ "name": "my-pipe", "version": version }).to_string();
+            let tarball = tar_gz(&[
+                ("package/pipe.json", &pipe_json),
+                ("package/pipe.ts", "console.log('my pipe')"),
+            ]);
+            (version, tarball)
+        });
+        let mut versions = serde_json::Map::new();
+        for (version, tarball) in &tarballs {
+            let path = format!("/@scope/my-pipe/-/my-pipe-{}.tgz", version);
+            versions.insert(
+                version.to_string(),
+                json!({ "dist": {
+                    "tarball": server.url(&path),
+                    "integrity": integrity(tarball),
+                } }),
+            );
+            server
+                .mock_async(|when, then| {
+                    when.method(GET).path(path);
+                    then.status(200).body(tarball);
+                })
+                .await;
+        }
+        // 1.2.0 was published with a tarball that changed since
+        versions["1.2.0"]["dist"]["integrity"] = json!(integrity(b"another tarball"));
+        server
+            .mock_async(|when, then| {
+                when.method(GET).path("/@scope%2fmy-pipe");
+                then.status(200).json_body(json!({
+                    "dist-tags": { "latest": "2.0.0", "next": "1.1.0" },
+                    "versions": versions,
+                }));
+            })
+            .await;
+        server
+            .mock_async(|when, then| {
+                when.method(GET).path("/unpublished");
+                then.status(404);
+            })
+            .await;
+
+        let client = Client::new();
+        let registry = server.base_url();
+        let download = |source: &str| {
+            let (client, registry) = (client.clone(), registry.clone());
+            let source = NpmSource::parse(source).unwrap();
+            async move {
+                let dest = tempdir().unwrap();
+                let version = source.download(&client, &registry, dest.path()).await;
+                version.map(|version| (version.version, dest))
+            }
+        };
+
+        for (source, expected) in [
+            ("npm:@scope/my-pipe@^1.2", "1.4.2"),
+            ("npm:@scope/my-pipe@>=1.0 <1.2", "1.1.0"),
+            ("npm:@scope/my-pipe", "2.0.0"),
+            ("npm:@scope/my-pipe@next", "1.1.0"),
+            ("npm:@scope/my-pipe@1.4.2", "1.4.2"),
+        ] {
+            let (version, dest) = download(source).await.unwrap();
+            assert_eq!(version, expected, "{}", source);
+            let pipe_json = std::fs::read_to_string(dest.path().join("pipe.json")).unwrap();
+            assert!(pipe_json.contains(expected));
+            assert!(dest.path().join("pipe.ts").exists());
+            assert!(!dest.path().join("package").exists());
+        }
+
+        let failed = |result: anyhow::Result<(String, tempfile::TempDir)>| {
+            result.err().map(|e| e.to_string()).unwrap_or_default()
+        };
+        assert_eq!(
+            failed(download("npm:@scope/my-pipe@1.2.0").await),
+            "tarball of @scope/my-pipe@1.2.0: it doesn't match its sha512 integrity"
+        );
+        assert_eq!(
+            failed(download("npm:@scope/my-pipe@^3").await),
+            "no version of @scope/my-pipe matches ^3"
+        );
+        assert_eq!(
+            failed(download("npm:unpublished").await),
+            "npm package unpublished not found"
+        );
+    }
+}
@@ -0,0 +1,337 @@
+#[cfg(feature = "pipes")]
+mod common;
+
+#[cfg(feature = "pipes")]
+#[cfg(test)]
+#[allow(deprecated)]
+mod tests {
+    use crate::common::write_pipe_with;
+    use httpmock::prelude::*;
+    use screenpipe_core::pipe_bundle::{
+        bundle_sources, download_pipe_bundle, download_pipe_bundle_with, BundleEntry, PipeBundle,
+        PIPE_BUNDLE_FILE,
+    };
+    use screenpipe_core::{download_pipe, ConflictPolicy, DownloadOptions, OverwritePolicy};
+    use serde_json::{json, Value};
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    fn write_bundle(dir: &Path, bundle: Value) {
+        std::fs::create_dir_all(dir).unwrap();
+        std::fs::write(dir.join(PIPE_BUNDLE_FILE), bundle.to_string()).unwrap();
+    }
+
+    #[test]
+    fn test_a_bundle_lists_folders_below_its_root() {
+        let bundle = PipeBundle::parse(br#"[{ "name": "notes", "path": "pipes/notes" }]"#).unwrap();
+        assert_eq!(
+            bundle.pipes,
+            vec![BundleEntry {
+                name: "notes".to_string(),
+                path: "pipes/notes".to_string(),
+            }]
+        );
+
+        let e = PipeBundle::parse(b"[]").unwrap_err();
+        assert_eq!(e.to_string(), "bundle.json lists no pipe");
+        let e = PipeBundle::parse(br#"[{ "name": " ", "path": "notes" }]"#).unwrap_err();
+        assert_eq!(e.to_string(), "bundle.json: a pipe has no name");
+        for path in ["", "../notes", "/notes", "pipes/../notes", "pipes\\notes"] {
+            let content = json!([{ "name": "notes", "path": path }]).to_string();
+            let e = PipeBundle::parse(content.as_bytes()).unwrap_err();
+            assert!(
+                e.to_string()
+                    .ends_with("of notes isn't a folder of the bundle"),
+                "{}: {}",
+                path,
+                e
+            );
+        }
+        let e = PipeBundle::parse(br#"{ "name": "notes" }"#).unwrap_err();
+        assert!(
+            e.to_string().starts_with("bundle.json: invalid type"),
+            "{}",
+            e
+        );
+    }
+
+    #[tokio::test]
+    async fn test_the_pipes_of_a_local_bundle_are_installed() {
+        let dir = TempDir::new().unwrap();
+        let bundle = dir.path().join("bundle");
+        write_pipe_with(
+            &bundle.join("pipes/notes"),
+            Some(json!({ "name": "notes", "version": "1.0.0" })),
+            None,
+            &[("pipe.ts", "// notes")],
+        );
+        write_pipe_with(
+            &bundle.join("pipes/digest"),
+            Some(json!({ "name": "digest", "version": "1.0.0" })),
+            None,
+            &[("pipe.ts", "// digest")],
+        );
+        write_bundle(
+            &bundle,
+            json!([
+                { "name": "notes", "path": "pipes/notes" },
+                { "name": "digest", "path": "pipes/digest" },
+            ]),
+        );
+
+        let screenpipe_dir = dir.path().join("screenpipe");
+        let installed = download_pipe_bundle(bundle.to_str().unwrap(), screenpipe_dir.clone())
+            .await
+            .unwrap();
+        let pipes = screenpipe_dir.join("pipes");
+        assert_eq!(installed, vec![pipes.join("notes"), pipes.join("digest")]);
+        for id in ["notes", "digest"] {
+            let script = std::fs::read_to_string(pipes.join(id).join("pipe.ts")).unwrap();
+            assert_eq!(script, format!("// {}", id));
+        }
+        // No leftovers next to the pipes
+        let mut entries: Vec<String> = std::fs::read_dir(&pipes)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        entries.sort();
+        assert_eq!(entries, ["digest", "notes"]);
+
+        // A bundle isn't a pipe of its own
+        let e = download_pipe(bundle.to_str().unwrap(), screenpipe_dir)
+            .await
+            .unwrap_err();
+        assert!(
+            e.to_string().ends_with(
+                "is a bundle of pipes, install the ones its bundle.json lists with \
+                 download_pipe_bundle"
+            ),
+            "{}",
+            e
+        );
+    }
+
+    /// Installs `notes` from a folder of its own and writes a bundle of another `notes`
+    /// and `digest`, returning the bundle.
+    async fn bundle_over_installed_notes(dir: &Path, screenpipe_dir: &Path) -> String {
+        let installed = write_pipe_with(
+            &dir.join("installed/notes"),
+            Some(json!({ "name": "notes", "version": "1.0.0" })),
+            None,
+            &[("pipe.ts", "// installed")],
+        );
+        download_pipe(&installed, screenpipe_dir.to_path_buf())
+            .await
+            .unwrap();
+        let bundle = dir.join("bundle");
+        for id in ["notes", "digest"] {
+            write_pipe_with(
+                &bundle.join(id),
+                Some(json!({ "name": id, "version": "1.0.0" })),
+                None,
+                &[("pipe.ts", "// bundled")],
+            );
+        }
+        write_bundle(
+            &bundle,
+            json!([
+                { "name": "notes", "path": "notes" },
+                { "name": "digest", "path": "digest" },
+            ]),
+        );
+        bundle.to_str().unwrap().to_string()
+    }
+
+    fn pipe_entries(pipes: &Path) -> Vec<String> {
+        let mut entries: Vec<String> = std::fs::read_dir(pipes)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        entries.sort();
+        entries
+    }
+
+    #[tokio::test]
+    async fn test_a_skipping_bundle_keeps_the_installed_pipe() {
+        let dir = TempDir::new().unwrap();
+        let screenpipe_dir = dir.path().join("screenpipe");
+        let bundle = bundle_over_installed_notes(dir.path(), &screenpipe_dir).await;
+
+        let options = DownloadOptions {
+            overwrite: OverwritePolicy::Skip,
+            ..Default::default()
+        };
+        let installed = download_pipe_bundle_with(&bundle, screenpipe_dir.clone(), options)
+            .await
+            .unwrap();
+        let pipes = screenpipe_dir.join("pipes");
+        assert_eq!(installed, vec![pipes.join("notes"), pipes.join("digest")]);
+        let script = std::fs::read_to_string(pipes.join("notes/pipe.ts")).unwrap();
+        assert_eq!(script, "// installed");
+        assert_eq!(pipe_entries(&pipes), ["digest", "notes"]);
+    }
+
+    #[tokio::test]
+    async fn test_a_renaming_bundle_keeps_the_installed_pipe() {
+        let dir = TempDir::new().unwrap();
+        let screenpipe_dir = dir.path().join("screenpipe");
+        let bundle = bundle_over_installed_notes(dir.path(), &screenpipe_dir).await;
+
+        let options = DownloadOptions {
+            on_conflict: ConflictPolicy::Rename,
+            ..Default::default()
+        };
+        let installed = download_pipe_bundle_with(&bundle, screenpipe_dir.clone(), options)
+            .await
+            .unwrap();
+        let pipes = screenpipe_dir.join("pipes");
+        assert_eq!(installed, vec![pipes.join("notes-2"), pipes.join("digest")]);
+        let script = std::fs::read_to_string(pipes.join("notes/pipe.ts")).unwrap();
+        assert_eq!(script, "// installed");
+        let script = std::fs::read_to_string(pipes.join("notes-2/pipe.ts")).unwrap();
+        assert_eq!(script, "// bundled");
+        assert_eq!(pipe_entries(&pipes), ["digest", "notes", "notes-2"]);
+    }
+
+    #[tokio::test]
+    async fn test_a_failing_pipe_rolls_the_bundle_back() {
+        let dir = TempDir::new().unwrap();
+        let screenpipe_dir = dir.path().join("screenpipe");
+        let bundle = dir.path().join("bundle");
+        let installed = bundle.join("notes");
+        write_pipe_with(
+            &installed,
+            Some(json!({ "name": "notes", "version": "1.0.0" })),
+            None,
+            &[("pipe.ts", "// installed")],
+        );
+        let notes_dir = download_pipe(installed.to_str().unwrap(), screenpipe_dir.clone())
+            .await
+            .unwrap();
+        let mut pipe_json: Value =
+            serde_json::from_str(&std::fs::read_to_string(notes_dir.join("pipe.json")).unwrap())
+                .unwrap();
+        pipe_json["enabled"] = json!(true);
+        std::fs::write(notes_dir.join("pipe.json"), pipe_json.to_string()).unwrap();
+
+        write_pipe_with(
+            &bundle.join("notes"),
+            Some(json!({ "name": "notes", "version": "1.0.0" })),
+            None,
+            &[("pipe.ts", "// bundled")],
+        );
+        write_pipe_with(
+            &bundle.join("digest"),
+            Some(json!({ "name": "digest", "version": "1.0.0" })),
+            None,
+            &[("pipe.ts", "// digest")],
+        );
+        std::fs::create_dir_all(bundle.join("broken")).unwrap();
+        std::fs::write(bundle.join("broken/pipe.json"), "{ not json").unwrap();
+        write_bundle(
+            &bundle,
+            json!([
+                { "name": "notes", "path": "notes" },
+                { "name": "digest", "path": "digest" },
+                { "name": "broken", "path": "broken" },
+            ]),
+        );
+
+        let e = download_pipe_bundle(bundle.to_str().unwrap(), screenpipe_dir.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(e.to_string(), "pipe broken of the bundle");
+
+        let pipes = screenpipe_dir.join("pipes");
+        let script = std::fs::read_to_string(notes_dir.join("pipe.ts")).unwrap();
+        assert_eq!(script, "// installed");
+        let restored: Value =
+            serde_json::from_str(&std::fs::read_to_string(notes_dir.join("pipe.json")).unwrap())
+                .unwrap();
+        assert_eq!(restored, pipe_json);
+        assert!(!pipes.join("digest").exists());
+        assert!(!pipes.join("broken").exists());
+        assert_eq!(std::fs::read_dir(&pipes).unwrap().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_the_pipes_of_a_github_bundle_download_at_its_ref() {
+        let server = MockServer::start_async().await;
+        let sha = "9fceb02d0ae598e95dc970b74767f19372d61af8";
+        server
+            .mock_async(|when, then| {
+                when.method(GET).path("/repos/acme/pipes/commits/main");
+                then.status(200).body(sha);
+            })
+            .await;
+        let bundle = server
+            .mock_async(|when, then| {
+                when.method(GET).path(format!(
+                    "/acme/pipes/{}/bundles/daily/{}",
+                    sha, PIPE_BUNDLE_FILE
+                ));
+                then.status(200).json_body(json!([
+                    { "name": "notes", "path": "notes" },
+                    { "name": "digest", "path": "pipes/digest" },
+                ]));
+            })
+            .await;
+
+        let api = server.base_url();
+        let client = reqwest::Client::new();
+        let sources = bundle_sources(
+            &client,
+            "https://github.com/acme/pipes/tree/main/bundles/daily",
+            &api,
+            &api,
+        )
+        .await
+        .unwrap();
+        bundle.assert_async().await;
+        let urls: Vec<&str> = sources.iter().map(|(_, url)| url.as_str()).collect();
+        assert_eq!(
+            urls,
+            [
+                "https://github.com/acme/pipes/tree/main/bundles/daily/notes",
+                "https://github.com/acme/pipes/tree/main/bundles/daily/pipes/digest",
+            ]
+        );
+
+        // Two folders named alike would install over each other
+        server
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path(format!("/acme/pipes/{}/{}", sha, PIPE_BUNDLE_FILE));
+                then.status(200).json_body(json!([
+                    { "name": "notes", "path": "notes" },
+                    { "name": "old notes", "path": "old/notes" },
+                ]));
+            })
+            .await;
+        let e = bundle_sources(
+            &client,
+            "https://github.com/acme/pipes/tree/main",
+            &api,
+            &api,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "bundle.json: old notes installs as pipe notes, as another pipe of the bundle does"
+        );
+
+        let e = bundle_sources(
+            &client,
+            "https://github.com/acme/pipes/tree/main/bundles/weekly",
+            &api,
+            &api,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "https://github.com/acme/pipes/tree/main/bundles/weekly has no bundle.json"
+        );
+    }
+}
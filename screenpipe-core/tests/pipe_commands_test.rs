@@ -0,0 +1,96 @@
+#[cfg(feature = "pipes")]
+#[cfg(test)]
+#[allow(deprecated)]
+mod tests {
+    use screenpipe_core::pipe_commands::{parse_output_command, OutputCommand, PipeOutput};
+    use screenpipe_core::{run_pipe_with, PipeRunOptions, PipeRuntime};
+    use serde_json::json;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_commands_are_parsed() {
+        let set_output = |name: &str, value: &str| OutputCommand::SetOutput {
+            name: name.to_string(),
+            value: value.to_string(),
+        };
+        for (line, command) in [
+            (
+                "::screenpipe-set-output name=notes::3",
+                set_output("notes", "3"),
+            ),
+            (
+                "::screenpipe-set-output name=summary::a%0Ab%3A 100%25%250A\r",
+                set_output("summary", "a\nb%3A 100%%0A"),
+            ),
+            (
+                "::screenpipe-set-output kind=count, name=a%3Ab%2Cc::",
+                set_output("a:b,c", ""),
+            ),
+            (
+                "::screenpipe-warning::disk almost full",
+                OutputCommand::Warning("disk almost full".to_string()),
+            ),
+            (
+                "::screenpipe-error file=pipe.ts::failed: 2 of 3",
+                OutputCommand::Error("failed: 2 of 3".to_string()),
+            ),
+        ] {
+            assert_eq!(parse_output_command(line), Some(command), "{}", line);
+        }
+
+        for line in [
+            "notes written",
+            " ::screenpipe-warning::indented",
+            "::warning::of github",
+            "::screenpipe-restart::now",
+            "::screenpipe-warning no value",
+            "::screenpipe-set-output::no name",
+            "::screenpipe-set-output name=::empty name",
+        ] {
+            assert_eq!(parse_output_command(line), None, "{}", line);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_the_outputs_of_a_pipe_are_kept() {
+        if PipeRuntime::Node.executable().is_none() {
+            return;
+        }
+        let temp_dir = TempDir::new().unwrap();
+        let pipe_dir = temp_dir.path().join("pipes/counter");
+        std::fs::create_dir_all(&pipe_dir).unwrap();
+        let manifest = json!({ "enabled": true, "runtime": "node" });
+        std::fs::write(pipe_dir.join("pipe.json"), manifest.to_string()).unwrap();
+        std::fs::write(
+            pipe_dir.join("pipe.js"),
+            r#"
+            console.log("::screenpipe-set-output name=notes::1");
+            console.log("::screenpipe-warning::no screen recorded");
+            console.log("::screenpipe-set-output name=notes::2");
+            console.log("::screenpipe-set-output name=summary::two%0Anotes");
+            "#,
+        )
+        .unwrap();
+
+        let output = PipeOutput::default();
+        let options = PipeRunOptions {
+            output: Some(output.clone()),
+            ..Default::default()
+        };
+        let mut child = run_pipe_with("counter", temp_dir.path().to_path_buf(), options)
+            .await
+            .unwrap();
+        assert!(child.wait().await.unwrap().success());
+        // Read until the pipe's stdout is closed
+        for _ in 0..50 {
+            if output.outputs().len() == 2 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert_eq!(output.get("notes").as_deref(), Some("2"));
+        assert_eq!(output.get("summary").as_deref(), Some("two\nnotes"));
+        assert_eq!(output.outputs().len(), 2);
+    }
+}
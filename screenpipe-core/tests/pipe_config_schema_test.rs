@@ -0,0 +1,102 @@
+#[cfg(feature = "pipes")]
+mod common;
+
+#[cfg(feature = "pipes")]
+#[cfg(test)]
+#[allow(deprecated)]
+mod tests {
+    use crate::common::write_pipe_with;
+    use screenpipe_core::download_pipe;
+    use screenpipe_core::pipe_config_schema::{get_pipe_config_schema, PIPE_CONFIG_SCHEMA_FILE};
+    use serde_json::{json, Value};
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_the_fields_of_a_pipe_become_its_config_schema() {
+        let dir = TempDir::new().unwrap();
+        let source = dir.path().join("notes");
+        write_pipe_with(
+            &source,
+            Some(json!({
+                "name": "notes",
+                "fields": [
+                    { "name": "interval", "type": "number", "default": 5, "description": "minutes" },
+                    { "name": "vault", "type": "path" },
+                    { "name": "summarize", "default": true },
+                    { "name": "prompt", "type": "string", "optional": true },
+                ],
+            })),
+            None,
+            &[("pipe.ts", "console.log('notes')")],
+        );
+        let screenpipe_dir = dir.path().join("screenpipe");
+        let pipe_dir = download_pipe(source.to_str().unwrap(), screenpipe_dir.clone())
+            .await
+            .unwrap();
+
+        let expected = json!({
+            "$schema": "https://json-schema.org/draft/2020-12/schema",
+            "type": "object",
+            "properties": {
+                "interval": { "type": "number", "default": 5, "description": "minutes" },
+                "vault": { "type": "string", "x-screenpipe-type": "path" },
+                "summarize": { "type": "boolean", "default": true },
+                "prompt": { "type": "string" },
+            },
+            "required": ["vault"],
+            "additionalProperties": false,
+        });
+        let written: Value = serde_json::from_str(
+            &std::fs::read_to_string(pipe_dir.join(PIPE_CONFIG_SCHEMA_FILE)).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(written, expected);
+        assert_eq!(get_pipe_config_schema(&pipe_dir).await.unwrap(), expected);
+        // Not written into the source
+        assert!(!source.join(PIPE_CONFIG_SCHEMA_FILE).exists());
+        // Generated when the file is missing
+        std::fs::remove_file(pipe_dir.join(PIPE_CONFIG_SCHEMA_FILE)).unwrap();
+        assert_eq!(get_pipe_config_schema(&pipe_dir).await.unwrap(), expected);
+    }
+
+    #[tokio::test]
+    async fn test_a_shipped_config_schema_is_kept() {
+        let dir = TempDir::new().unwrap();
+        let source = dir.path().join("digest");
+        write_pipe_with(
+            &source,
+            Some(json!({ "name": "digest", "fields": [{ "name": "interval" }] })),
+            None,
+            &[("pipe.ts", "console.log('notes')")],
+        );
+        let shipped = json!({
+            "type": "object",
+            "properties": { "interval": { "type": "integer", "minimum": 1 } },
+        });
+        std::fs::write(source.join(PIPE_CONFIG_SCHEMA_FILE), shipped.to_string()).unwrap();
+        let screenpipe_dir = dir.path().join("screenpipe");
+        let pipe_dir = download_pipe(source.to_str().unwrap(), screenpipe_dir.clone())
+            .await
+            .unwrap();
+        assert_eq!(get_pipe_config_schema(&pipe_dir).await.unwrap(), shipped);
+
+        std::fs::write(pipe_dir.join(PIPE_CONFIG_SCHEMA_FILE), "[]").unwrap();
+        let e = get_pipe_config_schema(&pipe_dir).await.unwrap_err();
+        assert!(e.to_string().ends_with("expected object, found array"));
+
+        // No fields, no settings
+        let plain = dir.path().join("plain");
+        write_pipe_with(
+            &plain,
+            Some(json!({ "name": "plain" })),
+            None,
+            &[("pipe.ts", "console.log('notes')")],
+        );
+        let pipe_dir = download_pipe(plain.to_str().unwrap(), screenpipe_dir)
+            .await
+            .unwrap();
+        assert!(!pipe_dir.join(PIPE_CONFIG_SCHEMA_FILE).exists());
+        let schema = get_pipe_config_schema(&pipe_dir).await.unwrap();
+        assert_eq!(schema["properties"], json!({}));
+    }
+}
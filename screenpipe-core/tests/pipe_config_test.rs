@@ -0,0 +1,131 @@
+#[cfg(feature = "pipes")]
+#[cfg(test)]
+mod tests {
+    use screenpipe_core::pipe_config::{
+        load_config, load_config_with, parse_config, ConfigError, LoadOptions, MAX_CONFIG_BYTES,
+    };
+    use serde_json::Value;
+    use std::path::{Path, PathBuf};
+    use tempfile::tempdir;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/pipe_config")
+            .join(name)
+    }
+
+    async fn load(name: &str, lenient: bool) -> Result<Value, String> {
+        let path = fixture(name);
+        let options = LoadOptions {
+            lenient,
+            ..Default::default()
+        };
+        load_config_with(&path, options).await.map_err(|e| {
+            // Errors start with the file, the rest is what the corpus locks in
+            let message = e.to_string();
+            let prefix = format!("{}: ", path.display());
+            assert!(message.starts_with(&prefix), "{}", message);
+            message[prefix.len()..].to_string()
+        })
+    }
+
+    #[tokio::test]
+    async fn test_corpus_strict() {
+        let valid = load("valid.json", false).await.unwrap();
+        assert_eq!(valid["port"], 3100);
+        assert_eq!(load("bom.json", false).await.unwrap(), valid);
+
+        assert_eq!(
+            load("trailing_commas.json", false).await.unwrap_err(),
+            "invalid json at line 5, column 56: trailing comma"
+        );
+        assert_eq!(
+            load("truncated.json", false).await.unwrap_err(),
+            "invalid json at line 5, column 0: EOF while parsing an object"
+        );
+        assert_eq!(
+            load("not_utf8.json", false).await.unwrap_err(),
+            "not valid utf-8 at line 3, column 16"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_corpus_lenient() {
+        let config = load("trailing_commas.json", true).await.unwrap();
+        assert_eq!(config["crons"][0]["path"], "/api/log");
+        assert_eq!(config["fields"], serde_json::json!([]));
+        // Commas in strings are left alone
+        assert_eq!(
+            config["description"],
+            "commas, like these ,} and ,] stay in strings"
+        );
+
+        // Lenient only forgives trailing commas
+        assert_eq!(
+            load("truncated.json", true).await.unwrap_err(),
+            "invalid json at line 5, column 0: EOF while parsing an object"
+        );
+        assert_eq!(
+            load("not_utf8.json", true).await.unwrap_err(),
+            "not valid utf-8 at line 3, column 16"
+        );
+        assert_eq!(
+            parse_config(b"{\"a\": [1,,]}", Path::new("pipe.json"), true)
+                .unwrap_err()
+                .to_string(),
+            "pipe.json: invalid json at line 1, column 10: expected value"
+        );
+        // A bom before trailing commas
+        let mut bom = b"\xEF\xBB\xBF".to_vec();
+        bom.extend_from_slice(b"{\"enabled\": true,}");
+        assert_eq!(
+            parse_config(&bom, Path::new("pipe.json"), true).unwrap()["enabled"],
+            true
+        );
+    }
+
+    #[tokio::test]
+    async fn test_huge_files_are_refused_before_parsing() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("pipe.json");
+        let padding = "x".repeat(MAX_CONFIG_BYTES as usize - 11);
+        let at_limit = format!("{{\"pad\": \"{}\"}}", padding);
+        assert_eq!(at_limit.len() as u64, MAX_CONFIG_BYTES);
+        std::fs::write(&path, &at_limit).unwrap();
+        assert!(load_config(&path).await.is_ok());
+
+        std::fs::write(&path, format!("{} ", at_limit)).unwrap();
+        let error = load_config(&path).await.unwrap_err();
+        assert!(matches!(error, ConfigError::TooLarge { .. }));
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "{}: file is 1048577 bytes, config files are limited to 1048576 bytes",
+                path.display()
+            )
+        );
+
+        // Not even json, refused all the same
+        std::fs::write(&path, vec![0u8; 2 * MAX_CONFIG_BYTES as usize]).unwrap();
+        assert!(matches!(
+            load_config(&path).await,
+            Err(ConfigError::TooLarge { size, .. }) if size == 2 * MAX_CONFIG_BYTES
+        ));
+
+        let small = LoadOptions {
+            max_bytes: 8,
+            lenient: false,
+        };
+        assert!(matches!(
+            load_config_with(&fixture("valid.json"), small).await,
+            Err(ConfigError::TooLarge { limit: 8, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_missing_file() {
+        let error = load_config(&fixture("missing.json")).await.unwrap_err();
+        assert!(error.is_not_found());
+        assert_eq!(error.path(), fixture("missing.json"));
+    }
+}
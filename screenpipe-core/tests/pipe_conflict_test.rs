@@ -0,0 +1,139 @@
+#[cfg(feature = "pipes")]
+mod common;
+
+#[cfg(feature = "pipes")]
+#[cfg(test)]
+#[allow(deprecated)]
+mod tests {
+    use crate::common::write_pipe;
+    use screenpipe_core::pipe_metadata::read_pipe_metadata;
+    use screenpipe_core::{download_pipe, download_pipe_with, ConflictPolicy, DownloadOptions};
+    use std::path::{Path, PathBuf};
+    use tempfile::TempDir;
+
+    async fn download(
+        source: &str,
+        screenpipe_dir: &Path,
+        on_conflict: ConflictPolicy,
+    ) -> anyhow::Result<PathBuf> {
+        let options = DownloadOptions {
+            on_conflict,
+            ..Default::default()
+        };
+        download_pipe_with(source, screenpipe_dir.to_path_buf(), options).await
+    }
+
+    fn script(pipe_dir: &Path) -> String {
+        std::fs::read_to_string(pipe_dir.join("pipe.ts")).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_a_pipe_is_reinstalled_from_its_source() {
+        let dir = TempDir::new().unwrap();
+        let source = write_pipe(&dir.path().join("alice/notes"), &[("pipe.ts", "// alice")]);
+        let screenpipe_dir = dir.path().join("screenpipe");
+
+        let pipe_dir = download_pipe(&source, screenpipe_dir.clone())
+            .await
+            .unwrap();
+        write_pipe(
+            &dir.path().join("alice/notes"),
+            &[("pipe.ts", "// alice, updated")],
+        );
+        let reinstalled = download(&source, &screenpipe_dir, ConflictPolicy::Error)
+            .await
+            .unwrap();
+        assert_eq!(reinstalled, pipe_dir);
+        assert_eq!(script(&pipe_dir), "// alice, updated");
+    }
+
+    #[tokio::test]
+    async fn test_a_pipe_of_another_source_is_a_conflict() {
+        let dir = TempDir::new().unwrap();
+        let alice = write_pipe(&dir.path().join("alice/notes"), &[("pipe.ts", "// alice")]);
+        let bob = write_pipe(&dir.path().join("bob/notes"), &[("pipe.ts", "// bob")]);
+        let screenpipe_dir = dir.path().join("screenpipe");
+
+        let pipe_dir = download_pipe(&alice, screenpipe_dir.clone()).await.unwrap();
+        let e = download_pipe(&bob, screenpipe_dir.clone())
+            .await
+            .unwrap_err();
+        assert!(
+            e.to_string()
+                .starts_with(&format!("pipe notes is installed already from {}", alice)),
+            "{}",
+            e
+        );
+        assert_eq!(script(&pipe_dir), "// alice");
+        assert_eq!(read_pipe_metadata(&pipe_dir).unwrap().source, alice);
+
+        let replaced = download(&bob, &screenpipe_dir, ConflictPolicy::Replace)
+            .await
+            .unwrap();
+        assert_eq!(replaced, pipe_dir);
+        assert_eq!(script(&pipe_dir), "// bob");
+        assert_eq!(read_pipe_metadata(&pipe_dir).unwrap().source, bob);
+    }
+
+    #[tokio::test]
+    async fn test_a_pipe_without_metadata_is_a_conflict() {
+        let dir = TempDir::new().unwrap();
+        let source = write_pipe(&dir.path().join("alice/notes"), &[("pipe.ts", "// alice")]);
+        let screenpipe_dir = dir.path().join("screenpipe");
+        // Installed before its source was recorded
+        write_pipe(
+            &screenpipe_dir.join("pipes/notes"),
+            &[("pipe.ts", "// legacy")],
+        );
+
+        let e = download_pipe(&source, screenpipe_dir.clone())
+            .await
+            .unwrap_err();
+        assert!(
+            e.to_string()
+                .contains("installed already from a source it has no record of"),
+            "{}",
+            e
+        );
+        assert_eq!(script(&screenpipe_dir.join("pipes/notes")), "// legacy");
+    }
+
+    #[tokio::test]
+    async fn test_a_conflicting_pipe_is_renamed() {
+        let dir = TempDir::new().unwrap();
+        let alice = write_pipe(&dir.path().join("alice/notes"), &[("pipe.ts", "// alice")]);
+        let bob = write_pipe(&dir.path().join("bob/notes"), &[("pipe.ts", "// bob")]);
+        let carol = write_pipe(&dir.path().join("carol/notes"), &[("pipe.ts", "// carol")]);
+        let screenpipe_dir = dir.path().join("screenpipe");
+
+        let pipe_dir = download_pipe(&alice, screenpipe_dir.clone()).await.unwrap();
+        // A local folder has no owner, the pipe is numbered
+        let renamed = download(&bob, &screenpipe_dir, ConflictPolicy::Rename)
+            .await
+            .unwrap();
+        assert_eq!(renamed, screenpipe_dir.join("pipes/notes-2"));
+        assert_eq!(script(&pipe_dir), "// alice");
+        assert_eq!(script(&renamed), "// bob");
+        assert_eq!(read_pipe_metadata(&renamed).unwrap().source, bob);
+
+        // Installed again under the name it was given
+        let again = download(&bob, &screenpipe_dir, ConflictPolicy::Rename)
+            .await
+            .unwrap();
+        assert_eq!(again, renamed);
+        let third = download(&carol, &screenpipe_dir, ConflictPolicy::Rename)
+            .await
+            .unwrap();
+        assert_eq!(third, screenpipe_dir.join("pipes/notes-3"));
+    }
+
+    #[test]
+    fn test_conflicts_fail_by_default() {
+        assert_eq!(
+            DownloadOptions::default().on_conflict,
+            ConflictPolicy::Error
+        );
+        let policy: ConflictPolicy = serde_json::from_str(r#""rename""#).unwrap();
+        assert_eq!(policy, ConflictPolicy::Rename);
+    }
+}
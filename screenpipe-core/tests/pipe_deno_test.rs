@@ -0,0 +1,101 @@
+#[cfg(feature = "pipes")]
+#[cfg(test)]
+mod tests {
+    use screenpipe_core::pipe_deno::{
+        parse_permissions, permission_to_flag, pipe_deno_permissions, DenoPermission,
+    };
+    use screenpipe_core::pipe_manifest::PipeManifest;
+    use screenpipe_core::{requested_permissions, PipeError};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_deno_permissions_are_read_from_the_manifest() {
+        let manifest = PipeManifest {
+            permissions: ["read:ocr", "net", "env", " net", "all"]
+                .map(str::to_string)
+                .to_vec(),
+            ..Default::default()
+        };
+        let permissions = parse_permissions(&manifest);
+        assert_eq!(
+            permissions,
+            [
+                DenoPermission::Net,
+                DenoPermission::Env,
+                DenoPermission::All
+            ]
+        );
+        assert_eq!(
+            permissions
+                .into_iter()
+                .map(permission_to_flag)
+                .collect::<Vec<_>>(),
+            ["--allow-net", "--allow-env", "--allow-all"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_a_pipe_only_gets_the_permissions_the_policy_allows() {
+        let dir = tempdir().unwrap();
+        let pipe_dir = dir.path().join("pipes").join("notes");
+        std::fs::create_dir_all(&pipe_dir).unwrap();
+        let manifest = |permissions: &str| {
+            std::fs::write(
+                pipe_dir.join("pipe.json"),
+                format!(r#"{{"name": "notes", "permissions": {}}}"#, permissions),
+            )
+            .unwrap()
+        };
+
+        manifest(r#"["read:ocr", "net", "env"]"#);
+        assert_eq!(
+            pipe_deno_permissions("notes", &pipe_dir, dir.path())
+                .await
+                .unwrap(),
+            [DenoPermission::Net, DenoPermission::Env]
+        );
+        // Deno's aren't the user's to grant
+        assert_eq!(
+            requested_permissions("notes", dir.path()).await,
+            ["read:ocr"]
+        );
+
+        manifest(r#"["net", "run", "ffi"]"#);
+        let e = pipe_deno_permissions("notes", &pipe_dir, dir.path())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            e.downcast_ref::<PipeError>(),
+            Some(&PipeError::NotAllowed {
+                pipe: "notes".to_string(),
+                permissions: vec!["run".to_string(), "ffi".to_string()],
+            })
+        );
+
+        std::fs::write(
+            dir.path().join("policy.json"),
+            r#"{"allowed_deno_permissions": ["net", "run", "ffi"]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            pipe_deno_permissions("notes", &pipe_dir, dir.path())
+                .await
+                .unwrap(),
+            [
+                DenoPermission::Net,
+                DenoPermission::Run,
+                DenoPermission::Ffi
+            ]
+        );
+
+        // A policy that can't be read doesn't let anything through
+        std::fs::write(
+            dir.path().join("policy.json"),
+            r#"{"allowed_deno_permissions": ["network"]}"#,
+        )
+        .unwrap();
+        pipe_deno_permissions("notes", &pipe_dir, dir.path())
+            .await
+            .unwrap_err();
+    }
+}
@@ -0,0 +1,84 @@
+#[cfg(feature = "pipes")]
+#[cfg(unix)]
+#[cfg(test)]
+#[allow(deprecated)]
+mod tests {
+    use screenpipe_core::{download_pipe, BUN_PATH_ENV};
+    use serde_json::{json, Value};
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::Path;
+    use std::sync::Once;
+    use tempfile::TempDir;
+
+    static FAKE_BUN: Once = Once::new();
+
+    /// A bun whose install fails in a pipe with a `fail` file, set before any test looks
+    /// it up.
+    fn fake_bun() {
+        FAKE_BUN.call_once(|| {
+            let dir = std::env::temp_dir().join(format!("fake-bun-{}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let bun = dir.join("bun");
+            std::fs::write(
+                &bun,
+                "#!/bin/sh\n[ -f fail ] && exit 1\nmkdir -p node_modules/next\n",
+            )
+            .unwrap();
+            std::fs::set_permissions(&bun, std::fs::Permissions::from_mode(0o755)).unwrap();
+            std::env::set_var(BUN_PATH_ENV, &bun);
+        });
+    }
+
+    fn write_nextjs_pipe(dir: &Path, version: &str) {
+        std::fs::create_dir_all(dir).unwrap();
+        let pipe_json = json!({ "name": "notes", "version": version });
+        std::fs::write(dir.join("pipe.json"), pipe_json.to_string()).unwrap();
+        let package_json = json!({ "dependencies": { "next": "14.0.0" } });
+        std::fs::write(dir.join("package.json"), package_json.to_string()).unwrap();
+    }
+
+    fn pipe_json(pipe_dir: &Path) -> Value {
+        serde_json::from_slice(&std::fs::read(pipe_dir.join("pipe.json")).unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_a_pipe_is_installed_with_its_dependencies() {
+        fake_bun();
+        let dir = TempDir::new().unwrap();
+        let source = dir.path().join("notes");
+        write_nextjs_pipe(&source, "1.0.0");
+
+        let pipe_dir = download_pipe(source.to_str().unwrap(), dir.path().join("screenpipe"))
+            .await
+            .unwrap();
+        assert!(pipe_dir.join("node_modules/next").is_dir());
+        let config = pipe_json(&pipe_dir);
+        assert_eq!(config["is_nextjs"], json!(true));
+        assert_eq!(config["version"], "1.0.0");
+    }
+
+    #[tokio::test]
+    async fn test_a_failed_dependency_install_keeps_the_installed_copy() {
+        fake_bun();
+        let dir = TempDir::new().unwrap();
+        let source = dir.path().join("notes");
+        write_nextjs_pipe(&source, "1.0.0");
+        let screenpipe_dir = dir.path().join("screenpipe");
+        let pipe_dir = download_pipe(source.to_str().unwrap(), screenpipe_dir.clone())
+            .await
+            .unwrap();
+
+        write_nextjs_pipe(&source, "2.0.0");
+        std::fs::write(source.join("fail"), "").unwrap();
+        download_pipe(source.to_str().unwrap(), screenpipe_dir.clone())
+            .await
+            .unwrap_err();
+
+        assert_eq!(pipe_json(&pipe_dir)["version"], "1.0.0");
+        assert!(pipe_dir.join("node_modules/next").is_dir());
+        assert!(!pipe_dir.join("fail").exists());
+        // Nor is the half installed copy left behind
+        let pipes = std::fs::read_dir(screenpipe_dir.join("pipes")).unwrap();
+        assert_eq!(pipes.count(), 1);
+    }
+}
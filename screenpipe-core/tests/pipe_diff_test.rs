@@ -0,0 +1,171 @@
+#[cfg(feature = "pipes")]
+#[cfg(test)]
+#[allow(deprecated)]
+mod tests {
+    use httpmock::prelude::*;
+    use screenpipe_core::download_pipe;
+    use screenpipe_core::pipe_diff::{diff_dirs, diff_pipe, diff_pipe_with};
+    use serde_json::json;
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    const SHA: &str = "9fceb02d0ae598e95dc970b74767f19372d61af8";
+
+    fn write(dir: &Path, path: &str, content: &[u8]) {
+        let path = dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn test_files_are_added_removed_or_modified() {
+        let dir = TempDir::new().unwrap();
+        let installed = dir.path().join("installed");
+        let remote = dir.path().join("remote");
+        for (path, content) in [
+            ("pipe.json", &b"{}"[..]),
+            ("pipe.ts", b"const a = 1;\nconst b = 2;\n"),
+            ("src/old.ts", b"// old"),
+            ("icon.png", b"\x89PNG\x00\xff"),
+            // Written by screenpipe or the pipe's runs
+            ("pipe.lock", b"{}"),
+            ("stats.json", b"{}"),
+            ("node_modules/react/index.js", b"// react"),
+            (".disabled", b""),
+        ] {
+            write(&installed, path, content);
+        }
+        for (path, content) in [
+            ("pipe.json", &b"{}"[..]),
+            ("pipe.ts", b"const a = 1;\nconst b = 3;\n"),
+            ("src/new.ts", b"// new"),
+            ("icon.png", b"\x89PNG\x00\xfe"),
+        ] {
+            write(&remote, path, content);
+        }
+
+        let diff = diff_dirs(&installed, &remote).unwrap();
+        assert_eq!(diff.added, ["src/new.ts"]);
+        assert_eq!(diff.removed, ["src/old.ts"]);
+        let modified: Vec<&str> = diff.modified.iter().map(|(p, _)| p.as_str()).collect();
+        assert_eq!(modified, ["icon.png", "pipe.ts"]);
+        let (_, icon) = &diff.modified[0];
+        assert!(icon.binary);
+        assert!(icon.unified.is_empty());
+        let (_, script) = &diff.modified[1];
+        assert!(!script.binary);
+        assert_eq!(
+            script.unified,
+            "--- a/pipe.ts\n+++ b/pipe.ts\n@@ -1,2 +1,2 @@\n const a = 1;\n-const b = 2;\n+const b = 3;\n"
+        );
+        assert!(!diff.is_empty());
+        assert!(diff_dirs(&remote, &remote).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_a_github_pipe_is_diffed_with_its_ref() {
+        let server = MockServer::start_async().await;
+        server
+            .mock_async(|when, then| {
+                when.method(GET).path("/repos/acme/pipes/commits/main");
+                then.status(200).body(SHA);
+            })
+            .await;
+        let blob = |path: &str| {
+            json!({
+                "path": format!("pipes/notes/{}", path),
+                "mode": "100644",
+                "type": "blob",
+                "sha": "3b18e512dba79e4c8300dd08aeb37f8e728b8dad",
+                "size": 16,
+            })
+        };
+        server
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path(format!("/repos/acme/pipes/git/trees/{}", SHA));
+                then.status(200).json_body(json!({
+                    "sha": SHA,
+                    "truncated": false,
+                    "tree": [
+                        { "path": "pipes/notes", "mode": "040000", "type": "tree", "sha": "3b18e512dba79e4c8300dd08aeb37f8e728b8dad" },
+                        blob("pipe.json"),
+                        blob("pipe.ts"),
+                        blob("digest.ts"),
+                    ],
+                }));
+            })
+            .await;
+        for (file, content) in [
+            ("pipe.json", r#"{"name": "notes"}"#),
+            ("pipe.ts", "// notes v2\n"),
+            ("digest.ts", "// digest\n"),
+        ] {
+            server
+                .mock_async(|when, then| {
+                    when.method(GET)
+                        .path(format!("/acme/pipes/{}/pipes/notes/{}", SHA, file));
+                    then.status(200).body(content);
+                })
+                .await;
+        }
+
+        let dir = TempDir::new().unwrap();
+        let screenpipe_dir = dir.path().join("screenpipe");
+        let pipe_dir = screenpipe_dir.join("pipes/notes");
+        let source = "https://github.com/acme/pipes/tree/main/pipes/notes";
+        write(&pipe_dir, "pipe.json", br#"{"name": "notes"}"#);
+        write(&pipe_dir, "pipe.ts", b"// notes\n");
+        write(&pipe_dir, "old.ts", b"// old\n");
+        let lock =
+            json!({ "source": source, "git_ref": "main", "path": "pipes/notes", "sha": SHA });
+        write(&pipe_dir, "pipe.lock", lock.to_string().as_bytes());
+
+        let api = server.base_url();
+        let client = reqwest::Client::new();
+        let diff = diff_pipe_with("notes", &screenpipe_dir, &client, &api, &api)
+            .await
+            .unwrap();
+        assert_eq!(diff.added, ["digest.ts"]);
+        assert_eq!(diff.removed, ["old.ts"]);
+        assert_eq!(diff.modified.len(), 1);
+        let (path, script) = &diff.modified[0];
+        assert_eq!(path, "pipe.ts");
+        assert!(script.unified.ends_with("-// notes\n+// notes v2\n"));
+        // The installed pipe is left as it is
+        assert!(pipe_dir.join("old.ts").exists());
+    }
+
+    #[tokio::test]
+    async fn test_a_pipe_is_diffed_with_the_path_it_was_installed_from() {
+        let dir = TempDir::new().unwrap();
+        let source = dir.path().join("notes");
+        write(&source, "pipe.json", br#"{"name": "notes"}"#);
+        write(&source, "pipe.ts", b"// notes\n");
+        let screenpipe_dir = dir.path().join("screenpipe");
+        let pipe_dir = download_pipe(source.to_str().unwrap(), screenpipe_dir.clone())
+            .await
+            .unwrap();
+        let client = reqwest::Client::new();
+
+        // Nothing tells where a copy of a local folder is from
+        let e = diff_pipe("notes", &screenpipe_dir, &client)
+            .await
+            .unwrap_err();
+        assert_eq!(e.to_string(), "pipe notes has no source to diff with");
+
+        // The pipe manager records it in its pipe.json
+        let pipe_json = json!({ "name": "notes", "source": source });
+        write(&pipe_dir, "pipe.json", pipe_json.to_string().as_bytes());
+        write(&source, "pipe.ts", b"// notes v2\n");
+        let diff = diff_pipe("notes", &screenpipe_dir, &client).await.unwrap();
+        let modified: Vec<&str> = diff.modified.iter().map(|(p, _)| p.as_str()).collect();
+        assert_eq!(modified, ["pipe.json", "pipe.ts"]);
+        assert!(diff.added.is_empty() && diff.removed.is_empty());
+
+        let e = diff_pipe("digest", &screenpipe_dir, &client)
+            .await
+            .unwrap_err();
+        assert_eq!(e.to_string(), "pipe digest isn't installed");
+    }
+}
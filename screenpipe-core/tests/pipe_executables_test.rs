@@ -0,0 +1,69 @@
+#[cfg(feature = "pipes")]
+#[cfg(unix)]
+#[cfg(test)]
+#[allow(deprecated)]
+mod tests {
+    use screenpipe_core::download_pipe;
+    use serde_json::json;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    fn write(dir: &Path, path: &str, content: &str, mode: u32) {
+        let path = dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, content).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode)).unwrap();
+    }
+
+    fn mode(path: &Path) -> u32 {
+        std::fs::metadata(path).unwrap().permissions().mode() & 0o777
+    }
+
+    #[tokio::test]
+    async fn test_the_executables_of_a_pipe_stay_executable() {
+        let dir = TempDir::new().unwrap();
+        let source = dir.path().join("notes");
+        let pipe_json = json!({
+            "name": "notes",
+            "executables": ["bin/helper", "scripts/missing.sh"],
+        });
+        write(&source, "pipe.json", &pipe_json.to_string(), 0o644);
+        write(&source, "pipe.ts", "// notes", 0o644);
+        write(&source, "bin/helper", "#!/bin/sh\necho notes", 0o644);
+        write(&source, "bin/private", "#!/bin/sh\necho notes", 0o600);
+        // Copied with its mode, listed or not
+        write(&source, "run.sh", "#!/bin/sh\necho notes", 0o750);
+
+        let pipe_dir = download_pipe(source.to_str().unwrap(), dir.path().join("screenpipe"))
+            .await
+            .unwrap();
+        assert_eq!(mode(&pipe_dir.join("bin/helper")), 0o755);
+        assert_eq!(mode(&pipe_dir.join("bin/private")), 0o600);
+        assert_eq!(mode(&pipe_dir.join("run.sh")), 0o750);
+        assert_eq!(mode(&pipe_dir.join("pipe.ts")), 0o644);
+        // Not the source's
+        assert_eq!(mode(&source.join("bin/helper")), 0o644);
+    }
+
+    #[tokio::test]
+    async fn test_an_executable_outside_the_pipe_fails_the_download() {
+        let dir = TempDir::new().unwrap();
+        write(dir.path(), "outside.sh", "#!/bin/sh", 0o644);
+        let source = dir.path().join("notes");
+        let pipe_json = json!({ "name": "notes", "executables": ["../outside.sh"] });
+        write(&source, "pipe.json", &pipe_json.to_string(), 0o644);
+        write(&source, "pipe.ts", "// notes", 0o644);
+
+        let screenpipe_dir = dir.path().join("screenpipe");
+        let e = download_pipe(source.to_str().unwrap(), screenpipe_dir.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "pipe.json: $.executables: '../outside.sh' isn't a path below the pipe root"
+        );
+        assert_eq!(mode(&dir.path().join("outside.sh")), 0o644);
+        assert!(!screenpipe_dir.join("pipes/notes").exists());
+    }
+}
@@ -0,0 +1,171 @@
+#[cfg(feature = "pipes")]
+#[cfg(test)]
+#[allow(deprecated)]
+mod tests {
+    use httpmock::prelude::*;
+    use screenpipe_core::pipe_ignore::{PipeIgnore, PIPE_IGNORE_FILE};
+    use screenpipe_core::{download_github_source, download_pipe, GithubCommit, GithubSource};
+    use serde_json::json;
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    const IGNORE: &str = "# kept out of the installed pipe\ntests/\n/docs\n!important.log\n";
+
+    fn write(dir: &Path, path: &str, content: &str) {
+        let path = dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn test_ignore_patterns_follow_gitignore() {
+        let ignore = PipeIgnore::parse(Some(IGNORE)).unwrap();
+        for (path, ignored) in [
+            ("pipe.ts", false),
+            ("node_modules/react/index.js", true),
+            ("target/debug/pipe", true),
+            ("dist-cache/chunk.js", true),
+            ("debug.log", true),
+            ("src/debug.log", true),
+            ("important.log", false),
+            ("tests/pipe.test.ts", true),
+            ("src/tests/lib.test.ts", true),
+            ("docs/usage.md", true),
+            ("src/docs/usage.md", false),
+        ] {
+            assert_eq!(ignore.is_ignored(path, false), ignored, "{}", path);
+        }
+        // Only the defaults without an ignore file
+        let defaults = PipeIgnore::parse(None).unwrap();
+        assert!(defaults.is_ignored("debug.log", false));
+        assert!(!defaults.is_ignored("tests/pipe.test.ts", false));
+    }
+
+    #[tokio::test]
+    async fn test_a_local_pipe_is_copied_without_its_ignored_files() {
+        let dir = TempDir::new().unwrap();
+        let source = dir.path().join("notes");
+        for path in [
+            "pipe.json",
+            "pipe.ts",
+            "src/lib.ts",
+            "important.log",
+            "debug.log",
+            "src/trace.log",
+            "target/debug/pipe",
+            "tests/pipe.test.ts",
+            "src/tests/lib.test.ts",
+            "docs/usage.md",
+        ] {
+            write(&source, path, r#"{"name": "notes"}"#);
+        }
+        write(&source, PIPE_IGNORE_FILE, IGNORE);
+
+        let pipe_dir = download_pipe(source.to_str().unwrap(), dir.path().join("screenpipe"))
+            .await
+            .unwrap();
+        for path in ["pipe.json", "pipe.ts", "src/lib.ts", "important.log"] {
+            assert!(pipe_dir.join(path).exists(), "{}", path);
+        }
+        for path in [
+            "debug.log",
+            "src/trace.log",
+            "target",
+            "tests",
+            "src/tests",
+            "docs",
+            PIPE_IGNORE_FILE,
+        ] {
+            assert!(!pipe_dir.join(path).exists(), "{}", path);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_a_github_pipe_is_downloaded_without_its_ignored_files() {
+        let server = MockServer::start_async().await;
+        let commit = GithubCommit {
+            git_ref: "main".to_string(),
+            path: "pipes/notes".to_string(),
+            sha: "9fceb02d0ae598e95dc970b74767f19372d61af8".to_string(),
+        };
+        let blob = |path: &str| {
+            json!({
+                "path": format!("pipes/notes/{}", path),
+                "mode": "100644",
+                "type": "blob",
+                "sha": "3b18e512dba79e4c8300dd08aeb37f8e728b8dad",
+                "size": 16,
+            })
+        };
+        let tree = json!({
+            "sha": commit.sha,
+            "truncated": false,
+            "tree": [
+                { "path": "pipes/notes", "mode": "040000", "type": "tree", "sha": "3b18e512dba79e4c8300dd08aeb37f8e728b8dad" },
+                blob(PIPE_IGNORE_FILE),
+                blob("pipe.json"),
+                blob("pipe.ts"),
+                blob("tests/pipe.test.ts"),
+                blob("debug.log"),
+            ],
+        });
+        server
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path(format!("/repos/acme/pipes/git/trees/{}", commit.sha));
+                then.status(200).json_body(tree);
+            })
+            .await;
+        server
+            .mock_async(|when, then| {
+                when.method(GET).path(format!(
+                    "/acme/pipes/{}/pipes/notes/{}",
+                    commit.sha, PIPE_IGNORE_FILE
+                ));
+                then.status(200).body(IGNORE);
+            })
+            .await;
+        let mut kept = Vec::new();
+        for file in ["pipe.json", "pipe.ts"] {
+            kept.push(
+                server
+                    .mock_async(|when, then| {
+                        when.method(GET)
+                            .path(format!("/acme/pipes/{}/pipes/notes/{}", commit.sha, file));
+                        then.status(200).body(format!("// {}", file));
+                    })
+                    .await,
+            );
+        }
+        let ignored = server
+            .mock_async(|when, then| {
+                when.method(GET).matches(|request| {
+                    request.path.ends_with(".test.ts") || request.path.ends_with(".log")
+                });
+                then.status(200).body("// ignored");
+            })
+            .await;
+
+        let source =
+            GithubSource::parse("https://github.com/acme/pipes/tree/main/pipes/notes").unwrap();
+        let dest = tempfile::tempdir().unwrap();
+        let api = server.base_url();
+        download_github_source(
+            &reqwest::Client::new(),
+            &source,
+            &commit,
+            dest.path(),
+            &api,
+            &api,
+        )
+        .await
+        .unwrap();
+        for mock in &kept {
+            mock.assert_async().await;
+        }
+        ignored.assert_hits_async(0).await;
+        assert!(dest.path().join("pipe.ts").exists());
+        assert!(!dest.path().join("tests").exists());
+        assert!(!dest.path().join(PIPE_IGNORE_FILE).exists());
+    }
+}
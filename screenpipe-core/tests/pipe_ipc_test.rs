@@ -0,0 +1,76 @@
+#[cfg(feature = "pipes")]
+#[cfg(unix)]
+#[cfg(test)]
+mod tests {
+    use screenpipe_core::pipe_ipc::{ipc_path, IpcMessage, PipeIpcEvent, PipeIpcServer};
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::UnixStream;
+    use tokio::sync::mpsc;
+    use tokio_util::sync::CancellationToken;
+
+    #[tokio::test]
+    async fn test_messages_of_a_pipe_are_passed_on() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("pipes")).unwrap();
+        let path = ipc_path("notes", dir.path());
+        assert_eq!(path, dir.path().join("pipes").join("notes.sock"));
+        // Left behind by a run that was killed
+        std::fs::write(&path, "").unwrap();
+
+        let (tx, mut rx) = mpsc::channel(8);
+        let server = PipeIpcServer::bind("notes", dir.path(), Some(tx))
+            .await
+            .unwrap();
+        let exited = CancellationToken::new();
+        let handle = server.spawn(exited.clone());
+
+        let mut stream = UnixStream::connect(&path).await.unwrap();
+        stream
+            .write_all(
+                concat!(
+                    r#"{"type": "log", "level": "warn", "message": "quota exceeded"}"#,
+                    "\n",
+                    "not json\n",
+                    "\n",
+                    r#"{"type": "metric", "key": "notes_written", "value": 3}"#,
+                    "\n",
+                    r#"{"type": "notification", "title": "notes", "body": "3 new notes"}"#,
+                    "\n",
+                )
+                .as_bytes(),
+            )
+            .await
+            .unwrap();
+        drop(stream);
+
+        let mut events = Vec::new();
+        for _ in 0..3 {
+            events.push(rx.recv().await.unwrap());
+        }
+        assert_eq!(
+            events,
+            [
+                IpcMessage::Log {
+                    level: Some("warn".to_string()),
+                    message: "quota exceeded".to_string(),
+                },
+                IpcMessage::Metric {
+                    key: "notes_written".to_string(),
+                    value: 3.0,
+                },
+                IpcMessage::Notification {
+                    title: "notes".to_string(),
+                    body: "3 new notes".to_string(),
+                },
+            ]
+            .map(|message| PipeIpcEvent {
+                pipe: "notes".to_string(),
+                message,
+            })
+        );
+
+        exited.cancel();
+        handle.await.unwrap();
+        assert!(!path.exists());
+    }
+}
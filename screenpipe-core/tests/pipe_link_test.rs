@@ -0,0 +1,75 @@
+#[cfg(feature = "pipes")]
+#[cfg(unix)]
+#[cfg(test)]
+#[allow(deprecated)]
+mod tests {
+    use screenpipe_core::pipe_link::{
+        is_linked_pipe, link_pipe, remove_pipe_dir, resolve_pipe_dir,
+    };
+    use screenpipe_core::{download_pipe, list_pipes, update_pipe, UpdateResult};
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_a_linked_pipe_runs_the_files_of_its_folder() {
+        let dir = TempDir::new().unwrap();
+        let source = dir.path().join("notes");
+        std::fs::create_dir_all(&source).unwrap();
+        std::fs::write(
+            source.join("pipe.json"),
+            r#"{"name": "notes", "enabled": true}"#,
+        )
+        .unwrap();
+        std::fs::write(source.join("pipe.ts"), "console.log('notes')").unwrap();
+        let screenpipe_dir = dir.path().join("screenpipe");
+
+        let pipe_dir = link_pipe(source.to_str().unwrap(), screenpipe_dir.clone())
+            .await
+            .unwrap();
+        assert_eq!(pipe_dir, screenpipe_dir.join("pipes").join("notes"));
+        assert!(is_linked_pipe(&pipe_dir));
+        assert_eq!(resolve_pipe_dir(pipe_dir.clone()), source);
+
+        // An edit is there without downloading the pipe again
+        std::fs::write(source.join("pipe.ts"), "console.log('edited')").unwrap();
+        assert_eq!(
+            std::fs::read_to_string(pipe_dir.join("pipe.ts")).unwrap(),
+            "console.log('edited')"
+        );
+        let pipes = list_pipes(&screenpipe_dir).await.unwrap();
+        assert_eq!(pipes.len(), 1);
+        assert!(pipes[0].linked && pipes[0].enabled);
+        assert_eq!(
+            update_pipe("notes", screenpipe_dir.clone()).await.unwrap(),
+            UpdateResult::Linked
+        );
+
+        // Linked again, e.g. once the folder moved
+        link_pipe(source.to_str().unwrap(), screenpipe_dir.clone())
+            .await
+            .unwrap();
+        remove_pipe_dir(&pipe_dir).await.unwrap();
+        assert!(!pipe_dir.exists() && !is_linked_pipe(&pipe_dir));
+        assert!(source.join("pipe.ts").exists());
+
+        // An installed copy isn't replaced by a link
+        download_pipe(source.to_str().unwrap(), screenpipe_dir.clone())
+            .await
+            .unwrap();
+        assert!(!is_linked_pipe(&pipe_dir));
+        let e = link_pipe(source.to_str().unwrap(), screenpipe_dir.clone())
+            .await
+            .unwrap_err();
+        assert!(e.to_string().contains("pipe notes is installed already"));
+        assert!(!list_pipes(&screenpipe_dir).await.unwrap()[0].linked);
+        remove_pipe_dir(&pipe_dir).await.unwrap();
+        assert!(!pipe_dir.exists());
+
+        let e = link_pipe(
+            dir.path().join("missing").to_str().unwrap(),
+            screenpipe_dir.clone(),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(e.to_string(), "Invalid local source path");
+    }
+}
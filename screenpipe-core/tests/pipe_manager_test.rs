@@ -0,0 +1,167 @@
+#[cfg(feature = "pipes")]
+mod common;
+
+#[cfg(feature = "pipes")]
+#[cfg(test)]
+mod tests {
+    use crate::common::write_pipe;
+    use httpmock::prelude::*;
+    use screenpipe_core::pipe_manager::{PipeManager, RuntimeConfig};
+    use screenpipe_core::{DownloadOptions, HttpOptions, UpdateResult};
+    use std::time::{Duration, Instant};
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_a_manager_installs_and_lists_pipes() {
+        let dir = TempDir::new().unwrap();
+        let source = dir.path().join("notes");
+        std::fs::create_dir_all(&source).unwrap();
+        std::fs::write(
+            source.join("pipe.json"),
+            r#"{"name": "notes", "enabled": true}"#,
+        )
+        .unwrap();
+        std::fs::write(source.join("pipe.ts"), "// notes").unwrap();
+        let manager =
+            PipeManager::new(dir.path().join("screenpipe"), RuntimeConfig::default()).unwrap();
+
+        let pipe_dir = manager
+            .download_pipe(source.to_str().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(pipe_dir, manager.screenpipe_dir().join("pipes/notes"));
+        manager.disable_pipe("notes").await.unwrap();
+        let pipes = manager.list_pipes().await.unwrap();
+        assert_eq!(pipes.len(), 1);
+        assert_eq!(pipes[0].id, "notes");
+        assert!(!pipes[0].enabled);
+        manager.enable_pipe("notes").await.unwrap();
+        assert!(manager.list_pipes().await.unwrap()[0].enabled);
+        // A pipe from a local path has no ref to update from
+        assert_eq!(
+            manager.update_pipe("notes").await.unwrap(),
+            UpdateResult::SourceUnknown
+        );
+    }
+
+    #[tokio::test]
+    async fn test_a_manager_links_checks_and_resets_pipes() {
+        let dir = TempDir::new().unwrap();
+        let source = write_pipe(&dir.path().join("notes"), &[("pipe.ts", "// notes")]);
+        let linked = write_pipe(&dir.path().join("digest"), &[("pipe.ts", "// digest")]);
+        let manager =
+            PipeManager::new(dir.path().join("screenpipe"), RuntimeConfig::default()).unwrap();
+
+        let pipe_dir = manager.download_pipe(&source).await.unwrap();
+        assert!(manager.verify_pipe_integrity("notes").unwrap().is_intact());
+        std::fs::write(pipe_dir.join("pipe.ts"), "// changed").unwrap();
+        assert!(!manager.verify_pipe_integrity("notes").unwrap().is_intact());
+
+        let link = manager.link_pipe(&linked).await.unwrap();
+        assert_eq!(link, manager.screenpipe_dir().join("pipes/digest"));
+        assert!(manager.pipe_stats("digest").await.unwrap().is_empty());
+        manager.reset_pipe_stats("digest").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_a_manager_updates_a_pipe_to_a_newer_version() {
+        let dir = TempDir::new().unwrap();
+        let source = write_pipe(&dir.path().join("notes"), &[("pipe.ts", "// notes")]);
+        let manifest = |version: &str| {
+            serde_json::json!({ "name": "notes", "version": version, "source": source }).to_string()
+        };
+        std::fs::write(dir.path().join("notes/pipe.json"), manifest("1.0.0")).unwrap();
+        let manager =
+            PipeManager::new(dir.path().join("screenpipe"), RuntimeConfig::default()).unwrap();
+        let pipe_dir = manager.download_pipe(&source).await.unwrap();
+        assert_eq!(manager.check_pipe_update("notes").await.unwrap(), None);
+
+        std::fs::write(dir.path().join("notes/pipe.json"), manifest("1.1.0")).unwrap();
+        std::fs::write(dir.path().join("notes/pipe.ts"), "// notes 1.1").unwrap();
+        let update = manager.check_pipe_update("notes").await.unwrap().unwrap();
+        assert_eq!(update.latest.to_string(), "1.1.0");
+        manager.update_pipe_version("notes").await.unwrap();
+        assert_eq!(
+            std::fs::read_to_string(pipe_dir.join("pipe.ts")).unwrap(),
+            "// notes 1.1"
+        );
+        assert_eq!(manager.check_pipe_update("notes").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_downloads_time_out_as_configured() {
+        let server = MockServer::start_async().await;
+        server
+            .mock_async(|when, then| {
+                when.method(GET).path("/notes.zip");
+                then.status(200).delay(Duration::from_secs(5)).body("");
+            })
+            .await;
+        let dir = TempDir::new().unwrap();
+        let config = RuntimeConfig {
+            http: HttpOptions {
+                timeout: Some(Duration::from_millis(200)),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let manager = PipeManager::new(dir.path().to_path_buf(), config).unwrap();
+
+        let started = Instant::now();
+        let e = manager
+            .download_pipe(&server.url("/notes.zip"))
+            .await
+            .unwrap_err();
+        assert!(started.elapsed() < Duration::from_secs(4), "{}", e);
+        assert!(
+            e.to_string()
+                .starts_with("failed to download the pipe archive"),
+            "{}",
+            e
+        );
+    }
+
+    #[tokio::test]
+    async fn test_the_http_settings_of_a_download_come_before_the_managers() {
+        let server = MockServer::start_async().await;
+        server
+            .mock_async(|when, then| {
+                when.method(GET).path("/notes.zip");
+                then.status(200).delay(Duration::from_secs(5)).body("");
+            })
+            .await;
+        let dir = TempDir::new().unwrap();
+        let manager = PipeManager::new(dir.path().to_path_buf(), RuntimeConfig::default()).unwrap();
+        let options = DownloadOptions {
+            http: HttpOptions {
+                timeout: Some(Duration::from_millis(200)),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let started = Instant::now();
+        let e = manager
+            .download_pipe_with(&server.url("/notes.zip"), options)
+            .await
+            .unwrap_err();
+        assert!(started.elapsed() < Duration::from_secs(4), "{}", e);
+    }
+
+    #[test]
+    fn test_an_invalid_proxy_is_refused() {
+        let config = RuntimeConfig {
+            http: HttpOptions {
+                proxy: Some("not a proxy".to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let e = PipeManager::new("/tmp/screenpipe".into(), config).unwrap_err();
+        assert!(
+            e.to_string().starts_with("invalid proxy not a proxy"),
+            "{}",
+            e
+        );
+    }
+}
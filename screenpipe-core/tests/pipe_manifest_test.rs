@@ -0,0 +1,179 @@
+#[cfg(feature = "pipes")]
+mod common;
+
+#[cfg(feature = "pipes")]
+#[cfg(test)]
+#[allow(deprecated)]
+mod tests {
+    use crate::common::write_pipe_with;
+    use screenpipe_core::download_pipe;
+    use screenpipe_core::pipe_manifest::{validate_pipe_manifest, ManifestCron};
+    use serde_json::json;
+    use std::path::{Path, PathBuf};
+    use tempfile::tempdir;
+
+    async fn error(dir: &Path) -> String {
+        validate_pipe_manifest(dir).await.unwrap_err().to_string()
+    }
+
+    #[tokio::test]
+    async fn test_the_pipes_in_this_repo_are_valid() {
+        let pipes = Path::new(env!("CARGO_MANIFEST_DIR")).join("../pipes");
+        let mut checked = 0;
+        for entry in std::fs::read_dir(pipes).unwrap() {
+            let dir: PathBuf = entry.unwrap().path();
+            if dir.join("package.json").exists() {
+                let manifest = validate_pipe_manifest(&dir).await.unwrap();
+                assert!(!manifest.name.is_empty(), "{}", dir.display());
+                checked += 1;
+            }
+        }
+        assert!(checked >= 10, "pipes went missing: {}", checked);
+    }
+
+    #[tokio::test]
+    async fn test_name_and_version_come_from_either_file() {
+        let dir = tempdir().unwrap();
+        let pipe = dir.path().join("notes");
+        write_pipe_with(
+            &pipe,
+            Some(json!({
+                "is_nextjs": true,
+                "permissions": ["read:ocr"],
+                "crons": [{ "path": "/api/log", "schedule": "0 */5 * * * *" }],
+                "fields": [{ "name": "interval", "type": "number", "default": 60 }],
+            })),
+            Some(json!({ "name": "notes", "version": "1.2.0-beta.1", "private": true })),
+            &[("pipe.ts", "console.log('hi')")],
+        );
+        let manifest = validate_pipe_manifest(&pipe).await.unwrap();
+        assert_eq!(manifest.name, "notes");
+        assert_eq!(manifest.version.as_deref(), Some("1.2.0-beta.1"));
+        assert_eq!(manifest.permissions, vec!["read:ocr"]);
+        assert_eq!(
+            manifest.crons,
+            vec![ManifestCron {
+                path: "/api/log".to_string(),
+                schedule: "0 */5 * * * *".to_string(),
+            }]
+        );
+        assert_eq!(manifest.fields[0].default, Some(json!(60)));
+
+        // pipe.json wins
+        write_pipe_with(
+            &pipe,
+            Some(json!({ "name": "my notes", "version": "2.0.0" })),
+            None,
+            &[("pipe.ts", "console.log('hi')")],
+        );
+        let manifest = validate_pipe_manifest(&pipe).await.unwrap();
+        assert_eq!(manifest.name, "my notes");
+        assert_eq!(manifest.version.as_deref(), Some("2.0.0"));
+
+        // A pipe without a pipe.json
+        let bare = dir.path().join("bare");
+        write_pipe_with(
+            &bare,
+            None,
+            Some(json!({ "name": "bare" })),
+            &[("pipe.ts", "console.log('hi')")],
+        );
+        assert_eq!(validate_pipe_manifest(&bare).await.unwrap().version, None);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_manifests_fail_with_a_readable_message() {
+        let dir = tempdir().unwrap();
+        let pipe = dir.path().join("notes");
+
+        write_pipe_with(&pipe, None, None, &[("pipe.ts", "console.log('hi')")]);
+        assert!(error(&pipe)
+            .await
+            .ends_with("notes: pipe has no pipe.json or package.json"));
+
+        write_pipe_with(
+            &pipe,
+            Some(json!({ "fields": [] })),
+            None,
+            &[("pipe.ts", "console.log('hi')")],
+        );
+        assert!(error(&pipe)
+            .await
+            .ends_with("set 'name' in its pipe.json or package.json"));
+
+        write_pipe_with(
+            &pipe,
+            Some(json!({ "name": "notes", "version": "1.0" })),
+            None,
+            &[("pipe.ts", "console.log('hi')")],
+        );
+        assert!(error(&pipe)
+            .await
+            .contains("version '1.0' isn't semver, e.g. 1.0.0"));
+
+        write_pipe_with(
+            &pipe,
+            None,
+            Some(json!({ "name": "notes", "version": 1 })),
+            &[("pipe.ts", "console.log('hi')")],
+        );
+        assert!(error(&pipe)
+            .await
+            .ends_with("package.json: $.version: expected string, found integer"));
+
+        // Every schema error at once, warnings such as unknown keys don't fail it
+        write_pipe_with(
+            &pipe,
+            Some(json!({
+                "name": "notes",
+                "port": "3000",
+                "permissions": "read:ocr",
+                "intervall": 60,
+            })),
+            None,
+            &[("pipe.ts", "console.log('hi')")],
+        );
+        let message = error(&pipe).await;
+        assert!(message.contains("pipe.json: "), "{}", message);
+        assert!(message.contains("$.port: expected integer, found string"));
+        assert!(message.contains("$.permissions: expected array, found string"));
+        assert!(!message.contains("intervall"));
+    }
+
+    #[tokio::test]
+    async fn test_a_broken_download_keeps_the_installed_pipe() {
+        let dir = tempdir().unwrap();
+        let screenpipe_dir = dir.path().join("screenpipe");
+        let source = dir.path().join("sources").join("notes");
+
+        write_pipe_with(
+            &source,
+            Some(json!({ "name": "notes" })),
+            None,
+            &[("pipe.ts", "console.log('hi')")],
+        );
+        let installed = download_pipe(source.to_str().unwrap(), screenpipe_dir.clone())
+            .await
+            .unwrap();
+
+        write_pipe_with(
+            &source,
+            Some(json!({ "version": "next" })),
+            None,
+            &[("pipe.ts", "console.log('hi')")],
+        );
+        std::fs::write(source.join("pipe.ts"), "broken").unwrap();
+        assert!(
+            download_pipe(source.to_str().unwrap(), screenpipe_dir.clone())
+                .await
+                .is_err()
+        );
+        assert_eq!(
+            std::fs::read_to_string(installed.join("pipe.ts")).unwrap(),
+            "console.log('hi')"
+        );
+        // Nothing but the installed copy is left in the pipes folder
+        let pipes = std::fs::read_dir(installed.parent().unwrap()).unwrap();
+        assert_eq!(pipes.count(), 1);
+    }
+}
@@ -0,0 +1,112 @@
+#[cfg(feature = "pipes")]
+mod common;
+
+#[cfg(feature = "pipes")]
+#[cfg(test)]
+#[allow(deprecated)]
+mod tests {
+    use crate::common::write_pipe;
+    use chrono::Utc;
+    use httpmock::prelude::*;
+    use screenpipe_core::pipe_metadata::{read_pipe_metadata, PipeSourceKind};
+    use screenpipe_core::{
+        download_pipe, download_pipe_with, list_pipes, ConflictPolicy, DownloadOptions,
+        PIPE_LOCK_FILE,
+    };
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_an_installed_pipe_records_where_it_is_from() {
+        let dir = TempDir::new().unwrap();
+        let source = dir.path().join("notes");
+        write_pipe(&source, &[("pipe.ts", "// notes")]);
+        let screenpipe_dir = dir.path().join("screenpipe");
+
+        let before = Utc::now();
+        let pipe_dir = download_pipe(source.to_str().unwrap(), screenpipe_dir.clone())
+            .await
+            .unwrap();
+        let metadata = read_pipe_metadata(&pipe_dir).unwrap();
+        assert_eq!(metadata.source, source.to_str().unwrap());
+        assert_eq!(metadata.kind, PipeSourceKind::Local);
+        assert_eq!(metadata.resolved_ref, None);
+        assert!(metadata.installed_at >= before && metadata.installed_at <= Utc::now());
+        assert_eq!(metadata.installer_version, env!("CARGO_PKG_VERSION"));
+        let pipes = list_pipes(&screenpipe_dir).await.unwrap();
+        assert_eq!(pipes[0].installed_at, Some(metadata.installed_at));
+
+        // Installed again from another folder, the one installed before isn't kept
+        let other = dir.path().join("other/notes");
+        write_pipe(&other, &[("pipe.ts", "// other notes")]);
+        std::fs::copy(pipe_dir.join(PIPE_LOCK_FILE), other.join(PIPE_LOCK_FILE)).unwrap();
+        let options = DownloadOptions {
+            on_conflict: ConflictPolicy::Replace,
+            ..Default::default()
+        };
+        download_pipe_with(other.to_str().unwrap(), screenpipe_dir, options)
+            .await
+            .unwrap();
+        let reinstalled = read_pipe_metadata(&pipe_dir).unwrap();
+        assert_eq!(reinstalled.source, other.to_str().unwrap());
+        assert!(reinstalled.installed_at >= metadata.installed_at);
+    }
+
+    #[tokio::test]
+    async fn test_an_archive_has_no_ref() {
+        let server = MockServer::start_async().await;
+        let archive = {
+            let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+            for (path, content) in [("pipe.json", r#"{"name": "notes"}"#), ("pipe.ts", "")] {
+                zip.start_file(path, zip::write::SimpleFileOptions::default())
+                    .unwrap();
+                std::io::Write::write_all(&mut zip, content.as_bytes()).unwrap();
+            }
+            zip.finish().unwrap().into_inner()
+        };
+        server
+            .mock_async(|when, then| {
+                when.method(GET).path("/notes.zip");
+                then.status(200).body(archive);
+            })
+            .await;
+
+        let dir = TempDir::new().unwrap();
+        let source = server.url("/notes.zip");
+        let pipe_dir = download_pipe(&source, dir.path().to_path_buf())
+            .await
+            .unwrap();
+        let metadata = read_pipe_metadata(&pipe_dir).unwrap();
+        assert_eq!(metadata.source, source);
+        assert_eq!(metadata.kind, PipeSourceKind::Archive);
+        assert_eq!(metadata.resolved_ref, None);
+    }
+
+    #[test]
+    fn test_a_pipe_without_metadata_fails_to_read() {
+        let dir = TempDir::new().unwrap();
+        assert!(read_pipe_metadata(dir.path()).is_err());
+        std::fs::write(dir.path().join(PIPE_LOCK_FILE), "{}").unwrap();
+        let e = read_pipe_metadata(dir.path()).unwrap_err();
+        assert!(
+            e.to_string()
+                .starts_with("invalid pipe.lock: missing field `source`"),
+            "{}",
+            e
+        );
+
+        // Written by an earlier screenpipe, with the commit only
+        let lock = serde_json::json!({
+            "source": "https://github.com/acme/pipes/tree/main/notes",
+            "git_ref": "main",
+            "path": "notes",
+            "sha": "9fceb02d0ae598e95dc970b74767f19372d61af8",
+        });
+        std::fs::write(dir.path().join(PIPE_LOCK_FILE), lock.to_string()).unwrap();
+        let e = read_pipe_metadata(dir.path()).unwrap_err();
+        assert!(
+            e.to_string().contains("doesn't say where the pipe is from"),
+            "{}",
+            e
+        );
+    }
+}
@@ -0,0 +1,238 @@
+#[cfg(feature = "pipes")]
+#[cfg(test)]
+#[allow(deprecated)]
+mod tests {
+    use httpmock::prelude::*;
+    use screenpipe_core::pipe_manager::{PipeManager, RuntimeConfig};
+    use screenpipe_core::pipe_registry::{
+        registry_cache_path, PipeEntry, PipeRegistry, RegistryOptions,
+    };
+    use screenpipe_core::{download_pipe_with, DownloadOptions};
+    use serde_json::{json, Value};
+    use std::time::Duration;
+    use tempfile::tempdir;
+
+    fn index() -> Value {
+        json!({
+            "pipes": [
+                {
+                    "name": "pipe-obsidian-time-logs",
+                    "description": "log your activities to obsidian",
+                    "author": "mediar-ai",
+                    "version": "0.1.4",
+                    "source": "https://github.com/mediar-ai/screenpipe/tree/main/pipes/pipe-obsidian-time-logs",
+                },
+                {
+                    "name": "pipe-notion-table-logs",
+                    "description": "team work logs in a notion table",
+                    "source": "https://github.com/mediar-ai/screenpipe/tree/main/pipes/pipe-notion-table-logs",
+                },
+                {
+                    "name": "loom",
+                    "description": "generate looms from your screen time",
+                    "source": "https://github.com/acme/loom",
+                },
+            ]
+        })
+    }
+
+    fn names(entries: Vec<&PipeEntry>) -> Vec<&str> {
+        entries.iter().map(|entry| entry.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_search_matches_names_then_descriptions() {
+        let registry: PipeRegistry = serde_json::from_value(index()).unwrap();
+        assert_eq!(registry.pipes[0].author.as_deref(), Some("mediar-ai"));
+        assert_eq!(registry.pipes[1].version, None);
+
+        assert_eq!(registry.search("").len(), 3);
+        assert_eq!(
+            names(registry.search("time")),
+            ["pipe-obsidian-time-logs", "loom"]
+        );
+        assert_eq!(
+            names(registry.search("LOGS")),
+            ["pipe-notion-table-logs", "pipe-obsidian-time-logs"]
+        );
+        assert_eq!(
+            names(registry.search("obsidian logs")),
+            ["pipe-obsidian-time-logs"]
+        );
+        assert_eq!(
+            names(registry.search("time_logs")),
+            ["pipe-obsidian-time-logs"]
+        );
+        // Letters in order
+        assert_eq!(names(registry.search("obsd")), ["pipe-obsidian-time-logs"]);
+        assert!(registry.search("slack").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_the_index_is_cached_for_its_ttl() {
+        let server = MockServer::start_async().await;
+        let mock = server
+            .mock_async(|when, then| {
+                when.method(GET).path("/pipes/index.json");
+                then.status(200).json_body(index());
+            })
+            .await;
+        let dir = tempdir().unwrap();
+        let client = reqwest::Client::new();
+        let options = RegistryOptions {
+            url: server.url("/pipes/index.json"),
+            ..Default::default()
+        };
+
+        let registry = PipeRegistry::load(&client, dir.path(), &options)
+            .await
+            .unwrap();
+        assert_eq!(registry.pipes.len(), 3);
+        assert!(registry_cache_path(dir.path()).exists());
+        PipeRegistry::load(&client, dir.path(), &options)
+            .await
+            .unwrap();
+        mock.assert_hits_async(1).await;
+
+        // Expired
+        let expired = RegistryOptions {
+            ttl: Duration::ZERO,
+            ..options.clone()
+        };
+        PipeRegistry::load(&client, dir.path(), &expired)
+            .await
+            .unwrap();
+        mock.assert_hits_async(2).await;
+
+        // Offline, the stale cache is used
+        mock.delete_async().await;
+        let registry = PipeRegistry::load(&client, dir.path(), &expired)
+            .await
+            .unwrap();
+        assert_eq!(registry.pipes.len(), 3);
+
+        // Unless it is of another index
+        let other = RegistryOptions {
+            url: server.url("/other/index.json"),
+            ..options
+        };
+        let e = PipeRegistry::load(&client, dir.path(), &other)
+            .await
+            .unwrap_err();
+        assert!(e
+            .to_string()
+            .starts_with("failed to fetch the pipe registry: "));
+    }
+
+    #[tokio::test]
+    async fn test_a_manager_searches_the_registry_of_its_options() {
+        let server = MockServer::start_async().await;
+        let mock = server
+            .mock_async(|when, then| {
+                when.method(GET).path("/pipes/index.json");
+                then.status(200).json_body(index());
+            })
+            .await;
+        let dir = tempdir().unwrap();
+        let manager = PipeManager::new(dir.path().to_path_buf(), RuntimeConfig::default()).unwrap();
+        let options = RegistryOptions {
+            url: server.url("/pipes/index.json"),
+            ..Default::default()
+        };
+
+        let found = manager
+            .search_registry_with("loom", &options)
+            .await
+            .unwrap();
+        mock.assert_hits_async(1).await;
+        let names: Vec<&str> = found.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, ["loom"]);
+    }
+
+    #[tokio::test]
+    async fn test_the_cache_of_the_pipes_dir_is_moved_to_the_cache_dir() {
+        let dir = tempdir().unwrap();
+        let url = "http://127.0.0.1:9/pipes/index.json";
+        let mut cache = index();
+        cache["url"] = json!(url);
+        cache["fetched_at"] = json!(chrono::Utc::now());
+        let legacy = dir.path().join("pipes").join("registry_cache.json");
+        std::fs::create_dir_all(legacy.parent().unwrap()).unwrap();
+        std::fs::write(&legacy, cache.to_string()).unwrap();
+
+        let options = RegistryOptions {
+            url: url.to_string(),
+            ..Default::default()
+        };
+        let registry = PipeRegistry::load(&reqwest::Client::new(), dir.path(), &options)
+            .await
+            .unwrap();
+        assert_eq!(registry.pipes.len(), 3);
+        assert!(!legacy.exists());
+        assert!(registry_cache_path(dir.path()).exists());
+
+        // Removed when the cache dir has one already
+        std::fs::write(&legacy, "{}").unwrap();
+        PipeRegistry::load(&reqwest::Client::new(), dir.path(), &options)
+            .await
+            .unwrap();
+        assert!(!legacy.exists());
+    }
+
+    #[tokio::test]
+    async fn test_a_pipe_of_the_registry_is_installed_by_name() {
+        let dir = tempdir().unwrap();
+        let source = dir.path().join("published").join("obsidian-sync");
+        std::fs::create_dir_all(&source).unwrap();
+        std::fs::write(source.join("pipe.json"), r#"{"name": "obsidian-sync"}"#).unwrap();
+        std::fs::write(source.join("pipe.ts"), "console.log('sync')").unwrap();
+
+        let server = MockServer::start_async().await;
+        let mock = server
+            .mock_async(|when, then| {
+                when.method(GET).path("/pipes/index.json");
+                then.status(200).json_body(json!({
+                    "pipes": [{
+                        "name": "obsidian-sync",
+                        "description": "sync your notes to obsidian",
+                        "version": "0.2.0",
+                        "source": source.to_str().unwrap(),
+                    }]
+                }));
+            })
+            .await;
+        let screenpipe_dir = dir.path().join("screenpipe");
+        let options = DownloadOptions {
+            registry: Some(RegistryOptions {
+                url: server.url("/pipes/index.json"),
+                ttl: Duration::ZERO,
+            }),
+            ..Default::default()
+        };
+
+        let installed =
+            download_pipe_with("obsidian-sync", screenpipe_dir.clone(), options.clone())
+                .await
+                .unwrap();
+        assert_eq!(
+            installed,
+            screenpipe_dir.join("pipes").join("obsidian-sync")
+        );
+        assert!(installed.join("pipe.ts").exists());
+        assert!(screenpipe_dir.join("cache").join("registry.json").exists());
+
+        let e = download_pipe_with("slack-digest", screenpipe_dir.clone(), options.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "slack-digest isn't a url, a local path or a pipe of the registry"
+        );
+
+        // The registry is unreachable, its stale copy is used
+        mock.delete_async().await;
+        download_pipe_with("obsidian-sync", screenpipe_dir.clone(), options)
+            .await
+            .unwrap();
+    }
+}
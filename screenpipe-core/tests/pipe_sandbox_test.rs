@@ -0,0 +1,83 @@
+#[cfg(feature = "pipes")]
+#[cfg(target_os = "linux")]
+#[cfg(test)]
+mod tests {
+    use screenpipe_core::pipe_sandbox::{sandbox_command, SandboxPolicy};
+    use screenpipe_core::{limit_command, PipeRunOptions};
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::Output;
+    use tokio::process::Command;
+
+    async fn run(policy: SandboxPolicy, program: &str, args: &[&str]) -> Output {
+        let mut command = Command::new(program);
+        command.args(args);
+        sandbox_command(&mut command, policy).unwrap();
+        // Set once the filter is in, as for a pipe
+        limit_command(&mut command, &PipeRunOptions::default());
+        command.output().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_a_sandboxed_process_runs() {
+        for policy in [
+            SandboxPolicy::None,
+            SandboxPolicy::Standard,
+            SandboxPolicy::Strict,
+        ] {
+            // Builtins only, the strict policy starts no process
+            let script = "while read -r line; do echo \"$line\"; done < /proc/self/status; echo ok";
+            let output = run(policy, "sh", &["-c", script]).await;
+            assert!(output.status.success(), "{:?}: {:?}", policy, output);
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            assert!(stdout.ends_with("ok\n"), "{:?}", policy);
+            let seccomp = if policy == SandboxPolicy::None {
+                "Seccomp:\t0"
+            } else {
+                "Seccomp:\t2"
+            };
+            assert!(stdout.contains(seccomp), "{:?}", policy);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_a_syscall_outside_the_policy_kills_the_process() {
+        // chroot(2), allowed or not, isn't in either policy
+        for policy in [SandboxPolicy::Standard, SandboxPolicy::Strict] {
+            let output = run(policy, "chroot", &["/", "true"]).await;
+            assert_eq!(output.status.signal(), Some(libc::SIGSYS), "{:?}", policy);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_only_the_standard_policy_starts_processes() {
+        // A program of its own, not a shell builtin
+        let script = ["-c", "cat /proc/self/comm && echo done"];
+        let output = run(SandboxPolicy::Standard, "sh", &script).await;
+        assert!(output.status.success(), "{:?}", output);
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "cat\ndone\n");
+
+        let output = run(SandboxPolicy::Strict, "sh", &script).await;
+        assert_eq!(output.status.signal(), Some(libc::SIGSYS));
+        assert!(output.stdout.is_empty());
+    }
+
+    #[test]
+    fn test_the_stricter_policy_wins() {
+        let (none, standard, strict) = (
+            SandboxPolicy::None,
+            SandboxPolicy::Standard,
+            SandboxPolicy::Strict,
+        );
+        assert_eq!(none.stricter(standard), standard);
+        assert_eq!(strict.stricter(standard), strict);
+        assert_eq!(standard.stricter(strict), strict);
+        assert_eq!(none.stricter(none), none);
+    }
+
+    #[test]
+    fn test_the_policy_is_off_by_default() {
+        assert_eq!(PipeRunOptions::default().sandbox, SandboxPolicy::None);
+        let policy: SandboxPolicy = serde_json::from_str(r#""strict""#).unwrap();
+        assert_eq!(policy, SandboxPolicy::Strict);
+    }
+}
@@ -0,0 +1,78 @@
+#[cfg(feature = "pipes")]
+#[cfg(unix)]
+#[cfg(test)]
+#[allow(deprecated)]
+mod tests {
+    use screenpipe_core::download_pipe;
+    use screenpipe_core::pipe_stats::{
+        read_pipe_stats, reset_pipe_stats, PipeRun, PIPE_LATEST_STATS_FILE,
+    };
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::ExitStatus;
+    use std::time::{Duration, SystemTime};
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_each_run_of_a_pipe_is_recorded() {
+        let dir = TempDir::new().unwrap();
+        let source = dir.path().join("notes");
+        std::fs::create_dir_all(&source).unwrap();
+        std::fs::write(source.join("pipe.json"), r#"{"name": "notes"}"#).unwrap();
+        std::fs::write(source.join("pipe.ts"), "console.log('notes')").unwrap();
+        let screenpipe_dir = dir.path().join("screenpipe");
+        download_pipe(source.to_str().unwrap(), screenpipe_dir.clone())
+            .await
+            .unwrap();
+        assert_eq!(read_pipe_stats("notes", &screenpipe_dir).await.unwrap(), []);
+
+        let before = SystemTime::now();
+        let run = PipeRun::start("notes");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        // Exited with 3
+        run.finish(Some(ExitStatus::from_raw(3 << 8)), &screenpipe_dir)
+            .await;
+        // Killed
+        PipeRun::start("notes").finish(None, &screenpipe_dir).await;
+
+        let stats = read_pipe_stats("notes", &screenpipe_dir).await.unwrap();
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].exit_code, Some(3));
+        assert_eq!(stats[0].restart_count, 0);
+        assert!(stats[0].wall_time_ms >= 20);
+        assert!(stats[0].last_run_at >= before);
+        assert_eq!(stats[1].exit_code, None);
+        assert_eq!(stats[1].restart_count, 1);
+        let installed = screenpipe_dir.join("pipes").join("notes");
+        let latest: serde_json::Value = serde_json::from_str(
+            &std::fs::read_to_string(installed.join(PIPE_LATEST_STATS_FILE)).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(latest["restart_count"], 1);
+
+        // Kept once updated
+        download_pipe(source.to_str().unwrap(), screenpipe_dir.clone())
+            .await
+            .unwrap();
+        assert_eq!(
+            read_pipe_stats("notes", &screenpipe_dir).await.unwrap(),
+            stats
+        );
+
+        reset_pipe_stats("notes", &screenpipe_dir).await.unwrap();
+        reset_pipe_stats("notes", &screenpipe_dir).await.unwrap();
+        assert_eq!(read_pipe_stats("notes", &screenpipe_dir).await.unwrap(), []);
+        PipeRun::start("notes")
+            .finish(Some(ExitStatus::from_raw(0)), &screenpipe_dir)
+            .await;
+        let stats = read_pipe_stats("notes", &screenpipe_dir).await.unwrap();
+        assert_eq!((stats[0].exit_code, stats[0].restart_count), (Some(0), 0));
+
+        assert_eq!(
+            read_pipe_stats("digest", &screenpipe_dir)
+                .await
+                .unwrap_err()
+                .to_string(),
+            "pipe digest isn't installed"
+        );
+    }
+}
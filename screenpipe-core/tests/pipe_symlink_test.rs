@@ -0,0 +1,143 @@
+#[cfg(feature = "pipes")]
+#[cfg(unix)]
+#[cfg(test)]
+#[allow(deprecated)]
+mod tests {
+    use screenpipe_core::{download_pipe_with, DownloadOptions, SymlinkPolicy};
+    use std::os::unix::fs::symlink;
+    use std::path::{Path, PathBuf};
+    use tempfile::TempDir;
+
+    fn write(dir: &Path, path: &str, content: &str) {
+        let path = dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    /// A pipe in `dir/notes` with links to a file and a folder outside it, to itself, to
+    /// the folder they're in and to nothing.
+    fn linked_pipe(dir: &Path) -> PathBuf {
+        let source = dir.join("notes");
+        write(&source, "pipe.json", r#"{"name": "notes"}"#);
+        write(&source, "pipe.ts", "// notes");
+        write(dir, "shared/prompt.md", "# prompt");
+        write(dir, "shared/lib/format.ts", "// format");
+        symlink("../shared/prompt.md", source.join("prompt.md")).unwrap();
+        symlink("../shared/lib", source.join("lib")).unwrap();
+        symlink("pipe.ts", source.join("main.ts")).unwrap();
+        std::fs::create_dir_all(source.join("src")).unwrap();
+        symlink("..", source.join("src/root")).unwrap();
+        symlink("missing.ts", source.join("src/missing.ts")).unwrap();
+        symlink("self", source.join("src/self")).unwrap();
+        source
+    }
+
+    async fn install(source: &Path, screenpipe_dir: PathBuf, symlinks: SymlinkPolicy) -> PathBuf {
+        let options = DownloadOptions {
+            symlinks,
+            ..Default::default()
+        };
+        download_pipe_with(source.to_str().unwrap(), screenpipe_dir, options)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_symlinks_are_skipped_by_default() {
+        let dir = TempDir::new().unwrap();
+        let source = linked_pipe(dir.path());
+        let pipe_dir = install(
+            &source,
+            dir.path().join("screenpipe"),
+            SymlinkPolicy::default(),
+        )
+        .await;
+        assert!(pipe_dir.join("pipe.ts").exists());
+        for path in ["prompt.md", "lib", "main.ts", "src/root", "src/missing.ts"] {
+            assert!(pipe_dir.join(path).symlink_metadata().is_err(), "{}", path);
+        }
+        assert!(pipe_dir.join("src").is_dir());
+    }
+
+    #[tokio::test]
+    async fn test_followed_symlinks_are_copied_without_looping() {
+        let dir = TempDir::new().unwrap();
+        let source = linked_pipe(dir.path());
+        let pipe_dir = install(
+            &source,
+            dir.path().join("screenpipe"),
+            SymlinkPolicy::Follow,
+        )
+        .await;
+
+        for (path, content) in [
+            ("prompt.md", "# prompt"),
+            ("lib/format.ts", "// format"),
+            ("main.ts", "// notes"),
+        ] {
+            let metadata = pipe_dir.join(path).symlink_metadata().unwrap();
+            assert!(metadata.is_file(), "{}", path);
+            let copied = std::fs::read_to_string(pipe_dir.join(path)).unwrap();
+            assert_eq!(copied, content);
+        }
+        assert!(pipe_dir.join("lib").symlink_metadata().unwrap().is_dir());
+        // The link back to the pipe root, and the broken ones, are left out
+        for path in ["src/root", "src/missing.ts", "src/self"] {
+            assert!(pipe_dir.join(path).symlink_metadata().is_err(), "{}", path);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_a_link_to_a_folder_it_is_in_is_skipped() {
+        let dir = TempDir::new().unwrap();
+        let source = dir.path().join("notes");
+        write(&source, "pipe.json", r#"{"name": "notes"}"#);
+        write(&source, "pipe.ts", "// notes");
+        write(&source, "a/b/c.ts", "// c");
+        symlink("../../a", source.join("a/b/up")).unwrap();
+        symlink(".", source.join("a/b/here")).unwrap();
+        // Not a loop, the same folder twice
+        symlink("a/b", source.join("b")).unwrap();
+
+        let pipe_dir = install(
+            &source,
+            dir.path().join("screenpipe"),
+            SymlinkPolicy::Follow,
+        )
+        .await;
+        assert!(pipe_dir.join("a/b/c.ts").exists());
+        assert!(pipe_dir.join("b/c.ts").exists());
+        for path in ["a/b/up", "a/b/here", "b/up", "b/here"] {
+            assert!(!pipe_dir.join(path).exists(), "{}", path);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_symlinks_can_be_copied_as_links() {
+        let dir = TempDir::new().unwrap();
+        let source = linked_pipe(dir.path());
+        let pipe_dir = install(
+            &source,
+            dir.path().join("screenpipe"),
+            SymlinkPolicy::CopyAsLink,
+        )
+        .await;
+
+        assert_eq!(
+            std::fs::read_link(pipe_dir.join("main.ts")).unwrap(),
+            Path::new("pipe.ts")
+        );
+        assert_eq!(
+            std::fs::read_link(pipe_dir.join("src/root")).unwrap(),
+            Path::new("..")
+        );
+        assert_eq!(
+            std::fs::read_to_string(pipe_dir.join("main.ts")).unwrap(),
+            "// notes"
+        );
+        // Broken links are left out here too
+        for path in ["src/missing.ts", "src/self"] {
+            assert!(pipe_dir.join(path).symlink_metadata().is_err(), "{}", path);
+        }
+    }
+}
@@ -0,0 +1,82 @@
+#[cfg(feature = "pipes")]
+mod common;
+
+#[cfg(feature = "pipes")]
+#[cfg(test)]
+#[allow(deprecated)]
+mod tests {
+    use crate::common::write_pipe;
+    use screenpipe_core::{
+        detect_pipe_runtime, download_pipe, download_pipe_with, DownloadOptions, PipeRuntime,
+        PipeValidationError,
+    };
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_a_pipe_without_a_main_file_isnt_installed() {
+        let dir = TempDir::new().unwrap();
+        let source = write_pipe(&dir.path().join("notes"), &[("main.ts", "")]);
+        let screenpipe_dir = dir.path().join("screenpipe");
+
+        let e = download_pipe(&source, screenpipe_dir.clone())
+            .await
+            .unwrap_err();
+        let invalid = e.downcast_ref::<PipeValidationError>().unwrap();
+        assert_eq!(invalid.pipe, "notes");
+        assert_eq!(
+            invalid.reason,
+            "it has no pipe.ts or pipe.js at its root, nor a package.json depending on next"
+        );
+        assert!(!screenpipe_dir.join("pipes").join("notes").exists());
+        assert_eq!(
+            std::fs::read_dir(screenpipe_dir.join("pipes"))
+                .unwrap()
+                .count(),
+            0
+        );
+
+        // Nor does it replace the one installed
+        write_pipe(&dir.path().join("notes"), &[("pipe.ts", "// v1")]);
+        let pipe_dir = download_pipe(&source, screenpipe_dir.clone())
+            .await
+            .unwrap();
+        std::fs::remove_file(dir.path().join("notes/pipe.ts")).unwrap();
+        assert!(download_pipe(&source, screenpipe_dir).await.is_err());
+        assert_eq!(
+            std::fs::read_to_string(pipe_dir.join("pipe.ts")).unwrap(),
+            "// v1"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_validation_can_be_skipped() {
+        let dir = TempDir::new().unwrap();
+        let source = write_pipe(&dir.path().join("notes"), &[("main.ts", "")]);
+        let options = DownloadOptions {
+            skip_validation: true,
+            ..Default::default()
+        };
+        let pipe_dir = download_pipe_with(&source, dir.path().join("screenpipe"), options)
+            .await
+            .unwrap();
+        assert!(pipe_dir.join("main.ts").exists());
+    }
+
+    #[tokio::test]
+    async fn test_a_runnable_pipe_is_installed() {
+        let dir = TempDir::new().unwrap();
+        let screenpipe_dir = dir.path().join("screenpipe");
+        let js = write_pipe(&dir.path().join("js/notes"), &[("pipe.js", "")]);
+        download_pipe(&js, screenpipe_dir.clone()).await.unwrap();
+
+        // Deno runs it with its defaults, without a deno.json
+        let deno = write_pipe(&dir.path().join("deno/digest"), &[("pipe.ts", "")]);
+        std::fs::write(
+            dir.path().join("deno/digest/pipe.json"),
+            r#"{"name": "digest", "runtime": "deno"}"#,
+        )
+        .unwrap();
+        let pipe_dir = download_pipe(&deno, screenpipe_dir).await.unwrap();
+        assert_eq!(detect_pipe_runtime(&pipe_dir).await, PipeRuntime::Deno);
+    }
+}
@@ -1,10 +1,23 @@
 #[cfg(feature = "pipes")]
 #[cfg(test)]
+#[allow(deprecated)]
 mod tests {
     use chrono::{TimeZone, Utc};
     use reqwest;
-    use screenpipe_core::{download_pipe, get_last_cron_execution, run_pipe, save_cron_execution};
+    use screenpipe_core::pipe_deno::DenoPermission;
+    use screenpipe_core::{
+        detect_pipe_runtime, disable_pipe, download_pipe, download_pipe_with, enable_pipe,
+        get_last_cron_execution, is_pipe_running, limit_command, list_pipes, parse_pipe_log_line,
+        pipe_dotenv, pipe_timeout, run_pipe, run_pipe_once, run_pipe_with, save_cron_execution,
+        update_all_pipes, update_pipe, verify_pipe_integrity, wait_pipe, watch_pipe_with,
+        ConflictPolicy, DownloadOptions, DownloadedPipe, FileIntegrity, GithubCommit,
+        OverwritePolicy, PipeError, PipeIntegrity, PipeLogLine, PipeReplSession, PipeRunOptions,
+        PipeRuntime, ShutdownToken, UpdateResult, DEFAULT_PIPE_MEMORY_LIMIT, PIPE_LOCK_FILE,
+        PIPE_REPL_ID,
+    };
     use serde_json::json;
+    use sha2::{Digest, Sha256};
+    use std::collections::HashMap;
     use std::sync::Arc;
     use std::time::{Duration, SystemTime, UNIX_EPOCH};
     use std::{path::PathBuf, sync::Once};
@@ -500,4 +513,823 @@ mod tests {
             time_diff
         );
     }
+
+    #[tokio::test]
+    async fn test_repl_session_runs_as_the_pipe_and_cleans_up() {
+        let temp_dir = TempDir::new().unwrap();
+        let screenpipe_dir = temp_dir.path();
+        create_dir_all(screenpipe_dir.join("pipes").join("my-pipe"))
+            .await
+            .unwrap();
+
+        let session = PipeReplSession::new(
+            Some("my-pipe"),
+            screenpipe_dir,
+            "http://localhost:3035/",
+            Some(vec!["search".to_string(), "notify".to_string()]),
+        )
+        .await
+        .unwrap();
+        let env = |name: &str| {
+            session
+                .env()
+                .iter()
+                .rev()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.clone())
+        };
+        assert_eq!(env("PIPE_ID").as_deref(), Some("my-pipe"));
+        assert_eq!(
+            env("SCREENPIPE_SERVER_URL").as_deref(),
+            Some("http://localhost:3035")
+        );
+        assert_eq!(
+            env("SCREENPIPE_PERMISSIONS").as_deref(),
+            Some("search,notify")
+        );
+        assert_eq!(
+            session.pipe_dir(),
+            screenpipe_dir.join("pipes").join("my-pipe")
+        );
+
+        let entrypoint = session.entrypoint();
+        assert!(tokio::fs::read_to_string(&entrypoint)
+            .await
+            .unwrap()
+            .contains("globalThis.sp = sp"));
+        drop(session);
+        assert!(!entrypoint.parent().unwrap().exists());
+    }
+
+    #[tokio::test]
+    async fn test_repl_session_without_a_pipe() {
+        let temp_dir = TempDir::new().unwrap();
+        let session = PipeReplSession::new(None, temp_dir.path(), "http://localhost:3030", None)
+            .await
+            .unwrap();
+        assert!(session
+            .env()
+            .contains(&("PIPE_ID".to_string(), PIPE_REPL_ID.to_string())));
+        // No permission set, like a pipe that asks for none
+        assert!(!session
+            .env()
+            .iter()
+            .any(|(key, _)| key == "SCREENPIPE_PERMISSIONS"));
+        assert_eq!(session.pipe_dir(), session.entrypoint().parent().unwrap());
+
+        assert!(PipeReplSession::new(
+            Some("missing"),
+            temp_dir.path(),
+            "http://localhost:3030",
+            None
+        )
+        .await
+        .is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_pipe_processes_are_limited() {
+        async fn limits(options: &PipeRunOptions) -> String {
+            let mut command = tokio::process::Command::new("sh");
+            command.args(["-c", "ulimit -d; ulimit -t"]);
+            limit_command(&mut command, options);
+            let output = command.output().await.unwrap();
+            String::from_utf8(output.stdout).unwrap()
+        }
+
+        let options = PipeRunOptions::default();
+        assert_eq!(options.memory_limit, Some(DEFAULT_PIPE_MEMORY_LIMIT));
+        // In KiB and seconds
+        assert_eq!(limits(&options).await, "524288\nunlimited\n");
+
+        let options = PipeRunOptions {
+            memory_limit: Some(64 * 1024 * 1024),
+            cpu_time_limit: Some(Duration::from_secs(30)),
+            ..Default::default()
+        };
+        assert_eq!(limits(&options).await, "65536\n30\n");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_a_pipe_json_memory_limit_takes_the_place_of_the_default() {
+        if PipeRuntime::Node.executable().is_none() {
+            return;
+        }
+        let temp_dir = TempDir::new().unwrap();
+        let pipe_dir = setup_test_pipe(&temp_dir, "pipes/limited", "").await;
+        tokio::fs::remove_file(pipe_dir.join("pipe.ts"))
+            .await
+            .unwrap();
+        // Writes the data limit it runs with outside of its folder
+        let code = "require('fs').writeFileSync(process.env.SCREENPIPE_DIR + '/limit', \
+                    require('child_process').execSync('ulimit -d', { shell: '/bin/sh' }));";
+        tokio::fs::write(pipe_dir.join("pipe.js"), code)
+            .await
+            .unwrap();
+        async fn limit(temp_dir: &TempDir, manifest: serde_json::Value) -> String {
+            let pipe_json = temp_dir.path().join("pipes/limited/pipe.json");
+            tokio::fs::write(pipe_json, manifest.to_string())
+                .await
+                .unwrap();
+            let options = PipeRunOptions {
+                memory_limit: Some(768 * 1024 * 1024),
+                ..Default::default()
+            };
+            let mut child = run_pipe_once("limited", temp_dir.path().to_path_buf(), "{}", options)
+                .await
+                .unwrap();
+            assert!(child.wait().await.unwrap().success());
+            std::fs::read_to_string(temp_dir.path().join("limit")).unwrap()
+        }
+
+        let manifest = json!({ "enabled": true, "runtime": "node" });
+        assert_eq!(limit(&temp_dir, manifest).await, "786432\n");
+        let manifest = json!({ "enabled": true, "runtime": "node", "memory_limit_mb": 1024 });
+        assert_eq!(limit(&temp_dir, manifest).await, "1048576\n");
+        let manifest = json!({ "enabled": true, "runtime": "node", "memory_limit_mb": 0 });
+        assert_eq!(limit(&temp_dir, manifest).await, "unlimited\n");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_a_pipe_past_its_timeout_is_killed() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let pipe_dir = temp_dir.path().join("pipes").join("slow");
+        create_dir_all(&pipe_dir).await.unwrap();
+        assert_eq!(pipe_timeout("slow", temp_dir.path()).await, None);
+        tokio::fs::write(pipe_dir.join("pipe.json"), r#"{"timeout_secs": 2}"#)
+            .await
+            .unwrap();
+        assert_eq!(
+            pipe_timeout("slow", temp_dir.path()).await,
+            Some(Duration::from_secs(2))
+        );
+
+        let mut child = tokio::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+        let started = std::time::Instant::now();
+        let e = wait_pipe("slow", &mut child, Some(Duration::from_millis(200)))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            e.downcast_ref::<PipeError>(),
+            Some(&PipeError::Timeout {
+                pipe_name: "slow".to_string(),
+                elapsed: Duration::from_millis(200),
+            })
+        );
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(child.try_wait().unwrap().unwrap().signal(), Some(9));
+
+        let mut child = tokio::process::Command::new("true").spawn().unwrap();
+        let status = wait_pipe("slow", &mut child, Some(Duration::from_secs(30)))
+            .await
+            .unwrap();
+        assert!(status.success());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_pipes_are_stopped_on_shutdown() {
+        use std::os::unix::process::ExitStatusExt;
+
+        if PipeRuntime::Node.executable().is_none() {
+            return;
+        }
+        let temp_dir = TempDir::new().unwrap();
+        // A node pipe running `on_term` for SIGTERM
+        async fn start(
+            temp_dir: &TempDir,
+            name: &str,
+            on_term: &str,
+            shutdown: &ShutdownToken,
+        ) -> tokio::process::Child {
+            let code = format!(
+                "process.on('SIGTERM', () => {{ {} }}); setInterval(() => {{}}, 1000);",
+                on_term
+            );
+            let pipe_dir = setup_test_pipe(temp_dir, &format!("pipes/{}", name), "").await;
+            tokio::fs::remove_file(pipe_dir.join("pipe.ts"))
+                .await
+                .unwrap();
+            tokio::fs::write(pipe_dir.join("pipe.js"), code)
+                .await
+                .unwrap();
+            let manifest = json!({ "enabled": true, "runtime": "node" }).to_string();
+            tokio::fs::write(pipe_dir.join("pipe.json"), manifest)
+                .await
+                .unwrap();
+            let options = PipeRunOptions {
+                shutdown: Some(shutdown.clone()),
+                ..Default::default()
+            };
+            let child = run_pipe_with(name, temp_dir.path().to_path_buf(), options)
+                .await
+                .unwrap();
+            // Until its handler is set
+            sleep(Duration::from_secs(1)).await;
+            child
+        }
+
+        let shutdown = ShutdownToken::new();
+        let mut child = start(&temp_dir, "polite", "process.exit(0);", &shutdown).await;
+        let started = std::time::Instant::now();
+        shutdown.shutdown().await;
+        assert!(started.elapsed() < shutdown.grace());
+        assert!(child.wait().await.unwrap().success());
+
+        // Killed after the grace period
+        let shutdown = ShutdownToken::with_grace(Duration::from_millis(300));
+        let mut child = start(&temp_dir, "stubborn", "", &shutdown).await;
+        shutdown.shutdown().await;
+        assert_eq!(child.wait().await.unwrap().signal(), Some(9));
+
+        // A pipe that exited isn't waited for
+        let shutdown = ShutdownToken::new();
+        let mut child = start(&temp_dir, "done", "", &shutdown).await;
+        child.kill().await.unwrap();
+        let started = std::time::Instant::now();
+        shutdown.shutdown().await;
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_a_watched_pipe_restarts_once_per_burst_of_changes() {
+        if PipeRuntime::Node.executable().is_none() {
+            return;
+        }
+        let temp_dir = TempDir::new().unwrap();
+        let pipe_dir = setup_test_pipe(&temp_dir, "pipes/watched", "").await;
+        tokio::fs::remove_file(pipe_dir.join("pipe.ts"))
+            .await
+            .unwrap();
+        // Counts its starts outside of its folder
+        let code = "require('fs').appendFileSync(process.env.SCREENPIPE_DIR + '/starts', 'x'); \
+                    setInterval(() => {}, 1000);";
+        tokio::fs::write(pipe_dir.join("pipe.js"), code)
+            .await
+            .unwrap();
+        let manifest = json!({ "enabled": true, "runtime": "node" }).to_string();
+        tokio::fs::write(pipe_dir.join("pipe.json"), manifest)
+            .await
+            .unwrap();
+        let starts_path = temp_dir.path().join("starts");
+        let starts = || {
+            std::fs::read_to_string(&starts_path)
+                .unwrap_or_default()
+                .len()
+        };
+        async fn wait_for(count: impl Fn() -> bool) {
+            for _ in 0..100 {
+                if count() {
+                    return;
+                }
+                sleep(Duration::from_millis(100)).await;
+            }
+        }
+
+        let shutdown = ShutdownToken::with_grace(Duration::from_millis(500));
+        let options = PipeRunOptions {
+            shutdown: Some(shutdown.clone()),
+            ..Default::default()
+        };
+        let watch = tokio::spawn(watch_pipe_with(
+            "watched",
+            temp_dir.path().to_path_buf(),
+            options,
+        ));
+        wait_for(|| starts() == 1).await;
+        assert_eq!(starts(), 1);
+        // Changes as it starts are its own
+        sleep(Duration::from_millis(500)).await;
+
+        for i in 0..5 {
+            tokio::fs::write(pipe_dir.join("lib.js"), i.to_string())
+                .await
+                .unwrap();
+            sleep(Duration::from_millis(50)).await;
+        }
+        wait_for(|| starts() == 2).await;
+        sleep(Duration::from_secs(1)).await;
+        assert_eq!(starts(), 2);
+
+        // What the pipe and its runtime write isn't a change
+        for dir in [".cache", "node_modules"] {
+            create_dir_all(pipe_dir.join(dir)).await.unwrap();
+            tokio::fs::write(pipe_dir.join(dir).join("state"), "")
+                .await
+                .unwrap();
+        }
+        sleep(Duration::from_secs(1)).await;
+        assert_eq!(starts(), 2);
+
+        shutdown.shutdown().await;
+        watch.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_pipe_runtimes_are_detected() {
+        let temp_dir = TempDir::new().unwrap();
+        let pipe_dir = setup_test_pipe(&temp_dir, "runtime-pipe", "console.log('hi')").await;
+        let write = |file: &str, content: &str| std::fs::write(pipe_dir.join(file), content);
+
+        assert_eq!(detect_pipe_runtime(&pipe_dir).await, PipeRuntime::Bun);
+        write("deno.json", "{}").unwrap();
+        assert_eq!(detect_pipe_runtime(&pipe_dir).await, PipeRuntime::Deno);
+        write("package.json", r#"{"name": "runtime-pipe"}"#).unwrap();
+        assert_eq!(detect_pipe_runtime(&pipe_dir).await, PipeRuntime::Bun);
+
+        // pipe.json wins
+        write("pipe.json", r#"{"runtime": "node"}"#).unwrap();
+        assert_eq!(detect_pipe_runtime(&pipe_dir).await, PipeRuntime::Node);
+        write("pipe.json", r#"{"runtime": "python"}"#).unwrap();
+        assert_eq!(detect_pipe_runtime(&pipe_dir).await, PipeRuntime::Bun);
+        let e = screenpipe_core::pipe_manifest::validate_pipe_manifest(&pipe_dir)
+            .await
+            .unwrap_err();
+        assert!(e.to_string().contains("$.runtime"), "{}", e);
+
+        let args = |runtime: PipeRuntime, file: &str| {
+            runtime
+                .run_args(&PathBuf::from(file), &[DenoPermission::Net])
+                .into_iter()
+                .map(|arg| arg.into_string().unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(args(PipeRuntime::Bun, "pipe.ts"), ["run", "pipe.ts"]);
+        assert_eq!(
+            args(PipeRuntime::Node, "pipe.ts"),
+            ["--experimental-strip-types", "pipe.ts"]
+        );
+        assert_eq!(args(PipeRuntime::Node, "pipe.js"), ["pipe.js"]);
+        assert_eq!(
+            args(PipeRuntime::Deno, "pipe.ts"),
+            ["run", "--no-prompt", "--allow-net", "pipe.ts"]
+        );
+    }
+
+    #[test]
+    fn test_json_log_lines_are_parsed() {
+        let line = r#"{"level":"warn","msg":"quota exceeded","pipe":"my-pipe","left":0}"#;
+        let PipeLogLine::Structured {
+            level,
+            message,
+            fields,
+        } = parse_pipe_log_line(line)
+        else {
+            panic!("{} wasn't parsed", line);
+        };
+        assert_eq!(level, Some(tracing::Level::WARN));
+        assert_eq!(message, "quota exceeded");
+        assert_eq!(fields["pipe"], "my-pipe");
+        assert_eq!(fields["left"], 0);
+        assert!(!fields.contains_key("level"));
+
+        let level = |line: &str| match parse_pipe_log_line(line) {
+            PipeLogLine::Structured { level, .. } => level,
+            PipeLogLine::Raw(_) => panic!("{} wasn't parsed", line),
+        };
+        // pino
+        assert_eq!(
+            level(r#"{"level":50,"time":1,"msg":"failed"}"#),
+            Some(tracing::Level::ERROR)
+        );
+        assert_eq!(
+            level(r#"{"level":"FATAL","message":"crashed"}"#),
+            Some(tracing::Level::ERROR)
+        );
+        assert_eq!(level(r#"{"message":"no level"}"#), None);
+        assert_eq!(level(r#"{"level":"loud","msg":"unknown level"}"#), None);
+
+        for line in [
+            "plain text",
+            r#"{"level":"info"}"#,
+            r#"["msg"]"#,
+            "{not json",
+            "",
+        ] {
+            assert_eq!(
+                parse_pipe_log_line(line),
+                PipeLogLine::Raw(line.to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn test_a_pipe_env_file_gives_defaults() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(
+            dir.path().join(".env"),
+            "# thresholds\nDOTENV_TEST_THRESHOLD=0.8\nDOTENV_TEST_HOST_KEY=from-file\n\
+             PIPE_ID=other-pipe\nDOTENV_TEST_QUOTED=\"a b\"\n",
+        )
+        .unwrap();
+        std::env::set_var("DOTENV_TEST_HOST_KEY", "from-host");
+
+        assert_eq!(
+            pipe_dotenv(dir.path()),
+            [
+                ("DOTENV_TEST_THRESHOLD".to_string(), "0.8".to_string()),
+                ("DOTENV_TEST_QUOTED".to_string(), "a b".to_string()),
+            ]
+        );
+        assert!(pipe_dotenv(&dir.path().join("missing")).is_empty());
+        std::env::remove_var("DOTENV_TEST_HOST_KEY");
+    }
+
+    #[tokio::test]
+    async fn test_downloaded_files_are_checked_against_their_checksums() {
+        let dir = TempDir::new().unwrap();
+        let source = dir.path().join("notes");
+        std::fs::create_dir_all(source.join("src")).unwrap();
+        let files = [
+            ("pipe.json", r#"{"name": "notes", "version": "1.0.0"}"#),
+            ("pipe.ts", "console.log('notes')"),
+            ("src/lib.ts", "export {}"),
+        ];
+        let mut checksums = HashMap::new();
+        for (path, content) in files {
+            std::fs::write(source.join(path), content).unwrap();
+            checksums.insert(
+                path.to_string(),
+                format!("{:x}", Sha256::digest(content.as_bytes())),
+            );
+        }
+        std::fs::write(
+            source.join("checksums.json"),
+            serde_json::to_string(&checksums).unwrap(),
+        )
+        .unwrap();
+        let screenpipe_dir = dir.path().join("screenpipe");
+        let download = |checksums: Option<HashMap<String, String>>| {
+            download_pipe_with(
+                source.to_str().unwrap(),
+                screenpipe_dir.clone(),
+                DownloadOptions {
+                    checksums,
+                    ..Default::default()
+                },
+            )
+        };
+
+        // The source's checksums.json
+        let pipe_dir = download(None).await.unwrap();
+        assert!(pipe_dir.join("src/lib.ts").exists());
+
+        // A tampered file fails the install, the installed copy is kept
+        std::fs::write(source.join("src/lib.ts"), "fetch('https://evil.example')").unwrap();
+        let e = download(None).await.unwrap_err();
+        assert!(
+            e.to_string()
+                .starts_with("src/lib.ts doesn't match its checksum"),
+            "{}",
+            e
+        );
+        let pipes = std::fs::read_dir(screenpipe_dir.join("pipes")).unwrap();
+        assert_eq!(pipes.count(), 1);
+        assert_eq!(
+            std::fs::read_to_string(pipe_dir.join("src/lib.ts")).unwrap(),
+            "export {}"
+        );
+
+        // Given checksums are used over the source's
+        let mut given = checksums.clone();
+        given.insert(
+            "src/lib.ts".to_string(),
+            format!("{:x}", Sha256::digest(b"fetch('https://evil.example')")),
+        );
+        download(Some(given.clone())).await.unwrap();
+
+        // A file the checksums don't list
+        std::fs::write(source.join("extra.ts"), "").unwrap();
+        let e = download(Some(given)).await.unwrap_err();
+        assert_eq!(e.to_string(), "extra.ts has no checksum");
+        assert!(!pipe_dir.join("extra.ts").exists());
+    }
+
+    #[tokio::test]
+    async fn test_downloaded_files_are_recorded_for_integrity_checks() {
+        let dir = TempDir::new().unwrap();
+        let source = dir.path().join("notes");
+        std::fs::create_dir_all(source.join("src")).unwrap();
+        std::fs::write(
+            source.join("pipe.json"),
+            r#"{"name": "notes", "version": "1.0.0"}"#,
+        )
+        .unwrap();
+        std::fs::write(source.join("pipe.ts"), "console.log('notes')").unwrap();
+        std::fs::write(source.join("src/lib.ts"), "export {}").unwrap();
+        let screenpipe_dir = dir.path().join("screenpipe");
+        let download = || {
+            download_pipe_with(
+                source.to_str().unwrap(),
+                screenpipe_dir.clone(),
+                DownloadOptions {
+                    verify_integrity: true,
+                    ..Default::default()
+                },
+            )
+        };
+
+        let pipe_dir = download().await.unwrap();
+        let integrity = PipeIntegrity::load(&pipe_dir).unwrap().unwrap();
+        assert_eq!(integrity.version.as_deref(), Some("1.0.0"));
+        assert_eq!(
+            integrity.files.keys().collect::<Vec<_>>(),
+            ["pipe.ts", "src/lib.ts"]
+        );
+        assert_eq!(
+            integrity.files["pipe.ts"],
+            format!("{:x}", Sha256::digest(b"console.log('notes')"))
+        );
+        assert!(verify_pipe_integrity(&pipe_dir).unwrap().is_intact());
+
+        std::fs::write(pipe_dir.join("pipe.ts"), "console.log('edited')").unwrap();
+        std::fs::remove_file(pipe_dir.join("src/lib.ts")).unwrap();
+        let report = verify_pipe_integrity(&pipe_dir).unwrap();
+        assert!(!report.is_intact());
+        assert_eq!(report.files["pipe.ts"], FileIntegrity::Modified);
+        assert_eq!(report.files["src/lib.ts"], FileIntegrity::Missing);
+
+        // An update changing a file at the same version is refused, the installed copy
+        // is kept
+        download().await.unwrap();
+        std::fs::write(source.join("src/lib.ts"), "fetch('https://evil.example')").unwrap();
+        let e = download().await.unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "src/lib.ts changed but the pipe is still at version 1.0.0"
+        );
+        assert_eq!(
+            std::fs::read_to_string(pipe_dir.join("src/lib.ts")).unwrap(),
+            "export {}"
+        );
+
+        // Not once it is released as a new version
+        std::fs::write(
+            source.join("pipe.json"),
+            r#"{"name": "notes", "version": "1.1.0"}"#,
+        )
+        .unwrap();
+        download().await.unwrap();
+        assert!(verify_pipe_integrity(&pipe_dir).unwrap().is_intact());
+
+        let e = verify_pipe_integrity(&source).unwrap_err();
+        assert!(
+            e.to_string().contains("has no file hashes in a pipe.lock"),
+            "{}",
+            e
+        );
+    }
+
+    #[tokio::test]
+    async fn test_a_reinstalled_pipe_replaces_the_installed_copy_whole() {
+        let dir = TempDir::new().unwrap();
+        let source = dir.path().join("notes");
+        std::fs::create_dir_all(&source).unwrap();
+        std::fs::write(
+            source.join("pipe.json"),
+            r#"{"name": "notes", "version": "1.0.0"}"#,
+        )
+        .unwrap();
+        std::fs::write(source.join("pipe.ts"), "console.log('notes')").unwrap();
+        std::fs::write(source.join("old.ts"), "export {}").unwrap();
+        let screenpipe_dir = dir.path().join("screenpipe");
+
+        let pipe_dir = download_pipe(source.to_str().unwrap(), screenpipe_dir.clone())
+            .await
+            .unwrap();
+        assert!(pipe_dir.join("old.ts").exists());
+
+        // A file removed upstream is gone from the update
+        std::fs::remove_file(source.join("old.ts")).unwrap();
+        download_pipe(source.to_str().unwrap(), screenpipe_dir.clone())
+            .await
+            .unwrap();
+        assert!(!pipe_dir.join("old.ts").exists());
+        assert!(pipe_dir.join("pipe.ts").exists());
+        let pipes = std::fs::read_dir(screenpipe_dir.join("pipes")).unwrap();
+        assert_eq!(pipes.count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_installed_pipes_are_listed_by_name() {
+        let dir = TempDir::new().unwrap();
+        let screenpipe_dir = dir.path().join("screenpipe");
+        assert!(list_pipes(&screenpipe_dir).await.unwrap().is_empty());
+
+        let source = dir.path().join("notes");
+        std::fs::create_dir_all(&source).unwrap();
+        std::fs::write(
+            source.join("pipe.json"),
+            r#"{"name": "zettel", "version": "1.2.0"}"#,
+        )
+        .unwrap();
+        std::fs::write(source.join("pipe.ts"), "console.log('notes')").unwrap();
+        download_pipe(source.to_str().unwrap(), screenpipe_dir.clone())
+            .await
+            .unwrap();
+
+        let pipes_dir = screenpipe_dir.join("pipes");
+        std::fs::create_dir_all(pipes_dir.join("digest")).unwrap();
+        std::fs::write(
+            pipes_dir.join("digest").join("pipe.json"),
+            r#"{"enabled": true, "source": "https://github.com/acme/digest"}"#,
+        )
+        .unwrap();
+        std::fs::create_dir_all(pipes_dir.join(".tmp-digest-x1y2z3")).unwrap();
+        std::fs::write(pipes_dir.join("README.md"), "").unwrap();
+
+        let pipes = list_pipes(&screenpipe_dir).await.unwrap();
+        assert_eq!(
+            pipes.iter().map(|p| p.id.as_str()).collect::<Vec<_>>(),
+            ["digest", "notes"]
+        );
+        assert_eq!(pipes[0].name, "digest");
+        assert!(pipes[0].enabled);
+        assert_eq!(
+            pipes[0].source.as_deref(),
+            Some("https://github.com/acme/digest")
+        );
+        assert_eq!(pipes[0].version, None);
+        assert_eq!(pipes[1].name, "zettel");
+        assert_eq!(pipes[1].version.as_deref(), Some("1.2.0"));
+        assert!(!pipes[1].enabled);
+        assert!(pipes[1].installed_at.unwrap() <= Utc::now());
+    }
+
+    #[tokio::test]
+    async fn test_a_disabled_pipe_doesnt_run_until_enabled() {
+        let dir = TempDir::new().unwrap();
+        let source = dir.path().join("notes");
+        std::fs::create_dir_all(&source).unwrap();
+        std::fs::write(
+            source.join("pipe.json"),
+            r#"{"name": "notes", "enabled": true}"#,
+        )
+        .unwrap();
+        std::fs::write(source.join("pipe.ts"), "console.log('notes')").unwrap();
+        let screenpipe_dir = dir.path().join("screenpipe");
+        download_pipe(source.to_str().unwrap(), screenpipe_dir.clone())
+            .await
+            .unwrap();
+
+        disable_pipe("notes", &screenpipe_dir).await.unwrap();
+        let e = run_pipe("notes", screenpipe_dir.clone()).await.unwrap_err();
+        assert_eq!(
+            e.downcast_ref::<PipeError>(),
+            Some(&PipeError::Disabled("notes".to_string()))
+        );
+        assert!(!list_pipes(&screenpipe_dir).await.unwrap()[0].enabled);
+
+        // Still disabled once updated, its pipe.json untouched
+        download_pipe(source.to_str().unwrap(), screenpipe_dir.clone())
+            .await
+            .unwrap();
+        assert!(!list_pipes(&screenpipe_dir).await.unwrap()[0].enabled);
+        let installed = screenpipe_dir.join("pipes").join("notes");
+        let config: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(installed.join("pipe.json")).unwrap())
+                .unwrap();
+        assert_eq!(config["enabled"], true);
+
+        enable_pipe("notes", &screenpipe_dir).await.unwrap();
+        enable_pipe("notes", &screenpipe_dir).await.unwrap();
+        assert!(list_pipes(&screenpipe_dir).await.unwrap()[0].enabled);
+        assert!(!installed.join(".disabled").exists());
+
+        assert_eq!(
+            disable_pipe("digest", &screenpipe_dir)
+                .await
+                .unwrap_err()
+                .to_string(),
+            "pipe digest isn't installed"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_only_pipes_behind_their_source_are_updated() {
+        let dir = TempDir::new().unwrap();
+        let source = dir.path().join("notes");
+        std::fs::create_dir_all(&source).unwrap();
+        std::fs::write(source.join("pipe.json"), r#"{"name": "notes"}"#).unwrap();
+        std::fs::write(source.join("pipe.ts"), "console.log('notes')").unwrap();
+        let screenpipe_dir = dir.path().join("screenpipe");
+        download_pipe(source.to_str().unwrap(), screenpipe_dir.clone())
+            .await
+            .unwrap();
+
+        // Copied from a local path, nothing records where to look for changes
+        assert_eq!(
+            update_pipe("notes", screenpipe_dir.clone()).await.unwrap(),
+            UpdateResult::SourceUnknown
+        );
+
+        // A pipe pinned to a commit is up to date without asking github
+        let pinned = screenpipe_dir.join("pipes").join("pinned");
+        std::fs::create_dir_all(&pinned).unwrap();
+        let sha = "9fceb02d0ae598e95dc970b74767f19372d61af8";
+        let downloaded = DownloadedPipe {
+            source: format!("https://github.com/acme/pinned/tree/{}", sha),
+            commit: GithubCommit {
+                git_ref: sha.to_string(),
+                path: String::new(),
+                sha: sha.to_string(),
+            },
+        };
+        std::fs::write(
+            pinned.join(PIPE_LOCK_FILE),
+            serde_json::to_string(&downloaded).unwrap(),
+        )
+        .unwrap();
+        assert!(!is_pipe_running("pinned"));
+
+        let updates = update_all_pipes(screenpipe_dir.clone()).await.unwrap();
+        assert_eq!(
+            updates
+                .iter()
+                .map(|u| (u.id.as_str(), u.result.as_ref().unwrap()))
+                .collect::<Vec<_>>(),
+            [
+                ("notes", &UpdateResult::SourceUnknown),
+                ("pinned", &UpdateResult::UpToDate)
+            ]
+        );
+
+        assert_eq!(
+            update_pipe("digest", screenpipe_dir)
+                .await
+                .unwrap_err()
+                .to_string(),
+            "pipe digest isn't installed"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_an_installed_pipe_is_kept_replaced_or_refused_by_policy() {
+        let dir = TempDir::new().unwrap();
+        let source = dir.path().join("notes");
+        std::fs::create_dir_all(&source).unwrap();
+        std::fs::write(
+            source.join("pipe.json"),
+            r#"{"name": "notes", "version": "1.1.0"}"#,
+        )
+        .unwrap();
+        std::fs::write(source.join("pipe.ts"), "console.log('new')").unwrap();
+        let screenpipe_dir = dir.path().join("screenpipe");
+        let installed = screenpipe_dir.join("pipes").join("notes");
+        let populate = || {
+            std::fs::create_dir_all(&installed).unwrap();
+            std::fs::write(installed.join("pipe.ts"), "console.log('edited')").unwrap();
+            std::fs::write(installed.join("notes.md"), "local notes").unwrap();
+        };
+        let download = |overwrite| {
+            download_pipe_with(
+                source.to_str().unwrap(),
+                screenpipe_dir.clone(),
+                DownloadOptions {
+                    overwrite,
+                    // Populated by hand, without a record of its source
+                    on_conflict: ConflictPolicy::Replace,
+                    ..Default::default()
+                },
+            )
+        };
+        populate();
+
+        let pipe_dir = download(OverwritePolicy::Skip).await.unwrap();
+        assert_eq!(pipe_dir, installed);
+        assert_eq!(
+            std::fs::read_to_string(installed.join("pipe.ts")).unwrap(),
+            "console.log('edited')"
+        );
+
+        let e = download(OverwritePolicy::ErrorIfExists).await.unwrap_err();
+        assert!(
+            e.to_string().starts_with("pipe notes is installed already"),
+            "{}",
+            e
+        );
+        assert!(installed.join("notes.md").exists());
+
+        download(OverwritePolicy::Overwrite).await.unwrap();
+        assert_eq!(
+            std::fs::read_to_string(installed.join("pipe.ts")).unwrap(),
+            "console.log('new')"
+        );
+        assert!(!installed.join("notes.md").exists());
+
+        // Nothing installed, every policy downloads
+        std::fs::remove_dir_all(&installed).unwrap();
+        download(OverwritePolicy::ErrorIfExists).await.unwrap();
+        std::fs::remove_dir_all(&installed).unwrap();
+        download(OverwritePolicy::Skip).await.unwrap();
+        assert!(installed.join("pipe.ts").exists());
+    }
 }
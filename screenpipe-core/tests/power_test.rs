@@ -0,0 +1,174 @@
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration as ChronoDuration, Utc};
+    use screenpipe_core::power::{
+        ClockJumpDetector, PowerEvent, PowerSource, PowerState, SubsystemOutcome,
+    };
+    use std::time::{Duration, Instant};
+
+    const TICK: Duration = Duration::from_secs(5);
+    const THRESHOLD: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn test_clock_jump_detects_overnight_sleep() {
+        let wall = Utc::now();
+        let mono = Instant::now();
+        let mut detector = ClockJumpDetector::starting_at(TICK, THRESHOLD, wall, mono);
+
+        // Normal ticks
+        assert_eq!(
+            detector.observe(wall + ChronoDuration::seconds(5), mono + TICK),
+            None
+        );
+        assert_eq!(
+            detector.observe(wall + ChronoDuration::seconds(10), mono + TICK * 2),
+            None
+        );
+
+        // Monotonic clock paused while the wall clock moved 8 hours (macOS, linux)
+        let woke_at = wall + ChronoDuration::hours(8);
+        assert_eq!(
+            detector.observe(woke_at, mono + TICK * 3),
+            Some((wall + ChronoDuration::seconds(10), woke_at))
+        );
+
+        // Back to normal afterwards
+        assert_eq!(
+            detector.observe(woke_at + ChronoDuration::seconds(5), mono + TICK * 4),
+            None
+        );
+    }
+
+    #[test]
+    fn test_clock_jump_detects_late_tick() {
+        // Monotonic clock kept counting through the sleep (windows)
+        let wall = Utc::now();
+        let mono = Instant::now();
+        let mut detector = ClockJumpDetector::starting_at(TICK, THRESHOLD, wall, mono);
+        let slept = Duration::from_secs(3 * 3600);
+        assert!(detector
+            .observe(wall + ChronoDuration::seconds(3 * 3600), mono + slept)
+            .is_some());
+    }
+
+    #[test]
+    fn test_clock_adjustments_are_not_sleep() {
+        let wall = Utc::now();
+        let mono = Instant::now();
+        let mut detector = ClockJumpDetector::starting_at(TICK, THRESHOLD, wall, mono);
+
+        // ntp nudging the clock forward a bit
+        assert_eq!(
+            detector.observe(wall + ChronoDuration::seconds(20), mono + TICK),
+            None
+        );
+        // clock set back an hour
+        assert_eq!(
+            detector.observe(wall - ChronoDuration::hours(1), mono + TICK * 2),
+            None
+        );
+        // a slow tick on a loaded machine
+        assert_eq!(
+            detector.observe(
+                wall - ChronoDuration::hours(1) + ChronoDuration::seconds(30),
+                mono + TICK * 2 + Duration::from_secs(30)
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_native_sleep_then_wake() {
+        let state = PowerState::new();
+        let mut events = state.subscribe();
+        let slept_at = Utc::now() - ChronoDuration::hours(8);
+        let woke_at = Utc::now();
+
+        assert!(state.wake_from_sleep(woke_at).is_none());
+        let sleep = state.sleep(slept_at).unwrap();
+        assert!(state.is_asleep());
+        assert!(state.sleep(slept_at).is_none());
+
+        let wake = state.wake_from_sleep(woke_at).unwrap();
+        assert!(!state.is_asleep());
+        assert_eq!(state.epoch(), 2);
+        assert_eq!(
+            state.last_wake().unwrap().timestamp_millis(),
+            woke_at.timestamp_millis()
+        );
+        assert_eq!(events.try_recv().unwrap(), sleep);
+        match events.try_recv().unwrap() {
+            PowerEvent::Wake {
+                epoch,
+                slept_at: from,
+                source,
+                ..
+            } => {
+                assert_eq!(epoch, 2);
+                assert_eq!(from.timestamp_millis(), slept_at.timestamp_millis());
+                assert_eq!(source, PowerSource::Native);
+            }
+            event => panic!("expected wake, got {:?}", event),
+        }
+        assert!(matches!(wake, PowerEvent::Wake { epoch: 2, .. }));
+    }
+
+    #[test]
+    fn test_clock_jump_wakes_without_native_notifications() {
+        let state = PowerState::new();
+        let slept_at = Utc::now() - ChronoDuration::hours(8);
+        let woke_at = Utc::now();
+
+        match state.clock_jump(slept_at, woke_at).unwrap() {
+            PowerEvent::Wake { source, .. } => assert_eq!(source, PowerSource::ClockJump),
+            event => panic!("expected wake, got {:?}", event),
+        }
+        assert_eq!(state.epoch(), 1);
+        // A late native wake for the same sleep is ignored
+        assert!(state.wake_from_sleep(Utc::now()).is_none());
+
+        // A sleep announcement whose wake got lost keeps its start time
+        let announced = Utc::now() - ChronoDuration::hours(2);
+        state.sleep(announced);
+        match state.clock_jump(Utc::now() - ChronoDuration::minutes(1), Utc::now()) {
+            Some(PowerEvent::Wake { slept_at, .. }) => {
+                assert_eq!(slept_at.timestamp_millis(), announced.timestamp_millis())
+            }
+            event => panic!("expected wake, got {:?}", event),
+        }
+    }
+
+    #[test]
+    fn test_wait_for_reports_collects_outcomes() {
+        let state = PowerState::new();
+        state.register("video monitor 1");
+        state.register("audio mic");
+        state.report("audio mic", 0, SubsystemOutcome::Ok("stale".to_string()));
+
+        let epoch = state
+            .clock_jump(Utc::now(), Utc::now())
+            .map(|_| state.epoch())
+            .unwrap();
+        state.report(
+            "video monitor 1",
+            epoch,
+            SubsystemOutcome::Ok("new chunk".to_string()),
+        );
+
+        let started = Instant::now();
+        let outcomes = state.wait_for_reports(epoch, Duration::from_millis(200));
+        assert!(started.elapsed() >= Duration::from_millis(200));
+        assert_eq!(
+            outcomes["video monitor 1"],
+            SubsystemOutcome::Ok("new chunk".to_string())
+        );
+        // Reported before the wake, so it doesn't count
+        assert_eq!(outcomes["audio mic"], SubsystemOutcome::TimedOut);
+
+        // Returns as soon as everyone reported
+        state.unregister("audio mic");
+        let started = Instant::now();
+        state.wait_for_reports(epoch, Duration::from_secs(10));
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,85 @@
+#[cfg(test)]
+mod tests {
+    use screenpipe_core::window_layout::{build_window_layout, ListedWindow, WindowRect};
+
+    fn listed(app_name: &str, rect: WindowRect) -> ListedWindow<'_> {
+        ListedWindow {
+            app_name,
+            window_name: "window",
+            rect,
+            minimized: false,
+            focused: false,
+        }
+    }
+
+    #[test]
+    fn test_clip_to_monitor() {
+        // Second monitor to the right of a 1920 wide one
+        let monitor = WindowRect::new(1920, 0, 1280, 800);
+        assert_eq!(
+            WindowRect::new(1800, -20, 400, 300).clip_to(&monitor),
+            Some(WindowRect::new(0, 0, 280, 280))
+        );
+        assert_eq!(
+            WindowRect::new(2000, 100, 200, 100).clip_to(&monitor),
+            Some(WindowRect::new(80, 100, 200, 100))
+        );
+        assert_eq!(WindowRect::new(0, 0, 1920, 1080).clip_to(&monitor), None);
+    }
+
+    #[test]
+    fn test_layout_keeps_visible_windows_in_stacking_order() {
+        let monitor = WindowRect::new(0, 0, 1920, 1080);
+        let windows = vec![
+            ListedWindow {
+                minimized: true,
+                ..listed("minimized", WindowRect::new(0, 0, 100, 100))
+            },
+            listed("editor", WindowRect::new(-10, 0, 970, 1080)),
+            listed("other monitor", WindowRect::new(1920, 0, 800, 600)),
+            listed("Window Server", WindowRect::new(0, 0, 1920, 25)),
+            listed("browser", WindowRect::new(0, 0, 1920, 1080)),
+        ];
+
+        let layout = build_window_layout(monitor, windows, |app, _| app == "Window Server");
+        assert_eq!((layout.monitor_width, layout.monitor_height), (1920, 1080));
+        let kept: Vec<(&str, u32, WindowRect)> = layout
+            .windows
+            .iter()
+            .map(|window| (window.app_name.as_str(), window.z, window.rect))
+            .collect();
+        assert_eq!(
+            kept,
+            vec![
+                ("editor", 0, WindowRect::new(0, 0, 960, 1080)),
+                ("browser", 1, WindowRect::new(0, 0, 1920, 1080)),
+            ]
+        );
+        // No focus reported, the frontmost window is taken
+        assert_eq!(layout.focused().unwrap().app_name, "editor");
+    }
+
+    #[test]
+    fn test_reported_focus_is_kept() {
+        let monitor = WindowRect::new(0, 0, 1920, 1080);
+        let windows = vec![
+            listed("palette", WindowRect::new(700, 100, 500, 300)),
+            ListedWindow {
+                focused: true,
+                ..listed("editor", WindowRect::new(0, 0, 1920, 1080))
+            },
+        ];
+
+        let layout = build_window_layout(monitor, windows, |_, _| false);
+        let focused: Vec<&str> = layout
+            .windows
+            .iter()
+            .filter(|window| window.focused)
+            .map(|window| window.app_name.as_str())
+            .collect();
+        assert_eq!(focused, vec!["editor"]);
+        assert!(build_window_layout(monitor, Vec::new(), |_, _| false)
+            .focused()
+            .is_none());
+    }
+}
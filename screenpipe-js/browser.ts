@@ -5,20 +5,100 @@ import type {
   ScreenpipeResponse,
   InputAction,
   InputControlResponse,
+  ProblemDetails,
+  ScreenpipeErrorCode,
 } from "./types";
 
 import { toSnakeCase, convertToCamelCase } from "./next";
 
-async function sendInputControl(action: InputAction): Promise<boolean> {
+/** Api version the bindings are written against, every call goes to this prefix */
+export const API_VERSION = "v1";
+
+function apiBase(): string {
   const apiUrl = process.env.SCREENPIPE_SERVER_URL || "http://localhost:3030";
+  return `${apiUrl}/${API_VERSION}`;
+}
+
+/** Headers of an api call, with the token screenpipe gave the pipe it started */
+function apiHeaders(
+  headers: Record<string, string> = {}
+): Record<string, string> {
+  const token = process.env.SCREENPIPE_API_TOKEN;
+  return token ? { ...headers, Authorization: `Bearer ${token}` } : headers;
+}
+
+function warnIfDeprecated(response: Response) {
+  if (response.headers.get("deprecation")) {
+    const sunset = response.headers.get("sunset");
+    console.warn(
+      `screenpipe: ${response.url} uses a deprecated api` +
+        (sunset ? `, removed after ${sunset}` : "")
+    );
+  }
+}
+
+/**
+ * Error thrown for a failed screenpipe api call, carrying the server's problem details
+ */
+export class ScreenpipeApiError extends Error {
+  readonly status: number;
+  readonly code: ScreenpipeErrorCode;
+  readonly traceId: string;
+  readonly problem: ProblemDetails;
+
+  constructor(problem: ProblemDetails) {
+    super(`${problem.code}: ${problem.detail}`);
+    this.name = "ScreenpipeApiError";
+    this.status = problem.status;
+    this.code = problem.code;
+    this.traceId = problem.traceId;
+    this.problem = problem;
+  }
+}
+
+/**
+ * Reads the problem details out of a failed response. Older servers send
+ * `{ error }` or plain text, those are mapped onto the same shape.
+ */
+export async function parseApiError(
+  response: Response
+): Promise<ScreenpipeApiError> {
+  const text = await response.text();
+  let body: any = null;
   try {
-    const response = await fetch(`${apiUrl}/experimental/input_control`, {
+    body = JSON.parse(text);
+  } catch {}
+
+  const fallbackCode: ScreenpipeErrorCode =
+    response.status === 404
+      ? "not_found"
+      : response.status < 500
+      ? "invalid_request"
+      : "internal_error";
+  const detail = body?.detail ?? body?.error ?? (text || response.statusText);
+
+  return new ScreenpipeApiError({
+    type: body?.type ?? `urn:screenpipe:error:${body?.code ?? fallbackCode}`,
+    title: body?.title ?? response.statusText,
+    status: body?.status ?? response.status,
+    detail,
+    code: body?.code ?? fallbackCode,
+    traceId: body?.trace_id ?? response.headers.get("x-trace-id") ?? "",
+    span: body?.span,
+    hint: body?.hint,
+  });
+}
+
+async function sendInputControl(action: InputAction): Promise<boolean> {
+  try {
+    const response = await fetch(`${apiBase()}/experimental/input_control`, {
       method: "POST",
-      headers: { "Content-Type": "application/json" },
+      headers: apiHeaders({ "Content-Type": "application/json" }),
       body: JSON.stringify({ action }),
     });
+    warnIfDeprecated(response);
     if (!response.ok) {
-      throw new Error(`http error! status: ${response.status}`);
+      throw await parseApiError(response);
     }
     const data: InputControlResponse = await response.json();
     return data.success;
@@ -57,6 +137,11 @@ export const pipe = {
           if (value.length > 0) {
             queryParams.append(toSnakeCase(key), value.join(","));
           }
+        } else if (key === "fields" && typeof value === "object") {
+          // Field names are sent as is, they come from the pipe's schema
+          Object.entries(value).forEach(([field, fieldValue]) => {
+            queryParams.append(`fields.${field}`, String(fieldValue));
+          });
         } else {
           const snakeKey = toSnakeCase(key);
           queryParams.append(snakeKey, value!.toString());
@@ -64,25 +149,14 @@ export const pipe = {
       }
     });
 
-    const url = `http://localhost:3030/search?${queryParams}`;
+    const url = `${apiBase()}/search?${queryParams}`;
     try {
-      const response = await fetch(url);
+      const response = await fetch(url, { headers: apiHeaders() });
+      warnIfDeprecated(response);
       if (!response.ok) {
-        const errorText = await response.text();
-        let errorJson;
-        try {
-          errorJson = JSON.parse(errorText);
-          console.error("screenpipe api error:", {
-            status: response.status,
-            error: errorJson,
-          });
-        } catch {
-          console.error("screenpipe api error:", {
-            status: response.status,
-            error: errorText,
-          });
-        }
-        throw new Error(`http error! status: ${response.status}`);
+        const apiError = await parseApiError(response);
+        console.error("screenpipe api error:", apiError.problem);
+        throw apiError;
       }
       const data = await response.json();
       return convertToCamelCase(data) as ScreenpipeResponse;
@@ -0,0 +1,32 @@
+// commands.ts
+// Lines a pipe prints to talk to screenpipe, `::screenpipe-<command> <params>::<value>`.
+// screenpipe reads them from the pipe's stdout instead of logging them
+
+function escapeData(value: string): string {
+  return value.replace(/%/g, "%25").replace(/\r/g, "%0D").replace(/\n/g, "%0A");
+}
+
+function escapeProperty(value: string): string {
+  return escapeData(value).replace(/:/g, "%3A").replace(/,/g, "%2C");
+}
+
+function issue(command: string, value: string, params = ""): void {
+  const line = `::screenpipe-${command}${params ? ` ${params}` : ""}::${escapeData(value)}`;
+  console.log(line);
+}
+
+export const commands = {
+  // Sets an output of the run, the last value set is kept
+  setOutput(name: string, value: unknown): void {
+    const text = typeof value === "string" ? value : JSON.stringify(value);
+    issue("set-output", text, `name=${escapeProperty(name)}`);
+  },
+  // Logged by screenpipe as a warning of the pipe
+  warning(message: string): void {
+    issue("warning", message);
+  },
+  // Logged by screenpipe as an error of the pipe, the pipe goes on running
+  error(message: string): void {
+    issue("error", message);
+  },
+};
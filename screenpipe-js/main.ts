@@ -1,9 +1,11 @@
 // main.ts - Universal entry point
 export * from "./types";
 export { toCamelCase, toSnakeCase, convertToCamelCase } from "./next";
+export { commands } from "./commands";
 
 // Browser-only exports
 export { sendDesktopNotification, queryScreenpipe, input } from "./browser";
+export { ScreenpipeApiError, parseApiError } from "./browser";
 
 // Export browser pipe as default
 export { pipe as default } from "./browser";
@@ -322,6 +322,7 @@ async function getAvailablePort(): Promise<number> {
 
 // Re-export browser functionality
 export * from "./browser";
+export { commands } from "./commands";
 
 // Node-specific pipe implementation
 export const pipe = {
@@ -0,0 +1,21 @@
+import { afterEach, describe, expect, spyOn, test } from "bun:test";
+import { commands } from "../commands";
+
+describe("commands", () => {
+  const log = spyOn(console, "log").mockImplementation(() => {});
+
+  afterEach(() => log.mockClear());
+
+  test("are printed escaped", () => {
+    commands.setOutput("notes, today", "2 of 3\nwritten: 100%");
+    commands.setOutput("stats", { notes: 2 });
+    commands.warning("disk almost full");
+    commands.error("failed");
+    expect(log.mock.calls.map((call) => call[0])).toEqual([
+      "::screenpipe-set-output name=notes%2C today::2 of 3%0Awritten: 100%25",
+      '::screenpipe-set-output name=stats::{"notes":2}',
+      "::screenpipe-warning::disk almost full",
+      "::screenpipe-error::failed",
+    ]);
+  });
+});
@@ -0,0 +1,48 @@
+import { describe, expect, test } from "bun:test";
+import { parseApiError, ScreenpipeApiError } from "../browser";
+
+describe("parseApiError", () => {
+  test("reads problem details", async () => {
+    const response = new Response(
+      JSON.stringify({
+        type: "urn:screenpipe:error:pipe_not_found",
+        title: "pipe not found",
+        status: 404,
+        detail: "pipe 'foo' does not exist",
+        code: "pipe_not_found",
+        trace_id: "abc123",
+        error: "pipe 'foo' does not exist",
+        success: false,
+      }),
+      {
+        status: 404,
+        headers: { "content-type": "application/problem+json" },
+      }
+    );
+
+    const error = await parseApiError(response);
+    expect(error).toBeInstanceOf(ScreenpipeApiError);
+    expect(error.code).toBe("pipe_not_found");
+    expect(error.status).toBe(404);
+    expect(error.traceId).toBe("abc123");
+    expect(error.problem.detail).toBe("pipe 'foo' does not exist");
+  });
+
+  test("maps legacy error bodies", async () => {
+    const response = new Response(
+      JSON.stringify({ error: "bad limit", success: false }),
+      { status: 400, headers: { "x-trace-id": "def456" } }
+    );
+
+    const error = await parseApiError(response);
+    expect(error.code).toBe("invalid_request");
+    expect(error.problem.detail).toBe("bad limit");
+    expect(error.traceId).toBe("def456");
+  });
+
+  test("maps plain text errors", async () => {
+    const error = await parseApiError(new Response("boom", { status: 500 }));
+    expect(error.code).toBe("internal_error");
+    expect(error.problem.detail).toBe("boom");
+  });
+});
@@ -10,7 +10,9 @@ export type ContentType =
   | "ui"
   | "audio+ui"
   | "ocr+ui"
-  | "audio+ocr";
+  | "audio+ocr"
+  | "document"
+  | "pipe";
 
 /**
  * Parameters for querying Screenpipe.
@@ -28,6 +30,17 @@ export interface ScreenpipeQueryParams {
   minLength?: number;
   maxLength?: number;
   speakerIds?: number[];
+  /** Only what was captured during the latest capture session with this name. */
+  session?: string;
+  /** Defaults to "relevance" when `q` is set, "recent" otherwise. */
+  sort?: "relevance" | "recent";
+  /** Pipe content type to search, needed by `fields`. */
+  type?: string;
+  /**
+   * Filters on facetable fields of `type`, sent as `fields.<key>=<value>`.
+   * Use `<name>.gte` / `<name>.lte` keys for ranges on numbers and dates.
+   */
+  fields?: Record<string, string | number | boolean>;
 }
 
 /**
@@ -43,6 +56,12 @@ export interface OCRContent {
   windowName: string;
   tags: string[];
   frame?: string;
+  /** The text was post-corrected, `rawText` is what the OCR engine read. */
+  corrected?: boolean;
+  correctedBy?: "dictionary" | "llm";
+  rawText?: string;
+  /** Ranking score, only set when results are sorted by relevance. */
+  score?: number;
 }
 
 /**
@@ -60,6 +79,8 @@ export interface AudioContent {
   speaker?: Speaker;
   startTime?: number;
   endTime?: number;
+  /** Ranking score, only set when results are sorted by relevance. */
+  score?: number;
 }
 
 /**
@@ -74,6 +95,37 @@ export interface UiContent {
   initialTraversalAt?: string;
   filePath: string;
   offsetIndex: number;
+  /** Ranking score, only set when results are sorted by relevance. */
+  score?: number;
+}
+
+/**
+ * Structure of a document indexed from a watch folder.
+ */
+export interface DocumentContent {
+  id: number;
+  text: string;
+  timestamp: string;
+  filePath: string;
+  kind: "text" | "pdf" | "image";
+  fileSize: number;
+  /** Ranking score, only set when results are sorted by relevance. */
+  score?: number;
+}
+
+/**
+ * Structure of a record a pipe added through `/pipes/content`.
+ */
+export interface PipeContent {
+  id: number;
+  contentType: string;
+  pipeId: string;
+  text: string;
+  timestamp: string;
+  /** Fields as defined by the content type's schema, keys camelCased like the rest of the response. */
+  fields: Record<string, unknown>;
+  /** Ranking score, only set when results are sorted by relevance. */
+  score?: number;
 }
 
 /**
@@ -91,7 +143,9 @@ export interface Speaker {
 export type ContentItem =
   | { type: "OCR"; content: OCRContent }
   | { type: "Audio"; content: AudioContent }
-  | { type: "UI"; content: UiContent };
+  | { type: "UI"; content: UiContent }
+  | { type: "Document"; content: DocumentContent }
+  | { type: "Pipe"; content: PipeContent };
 
 /**
  * Pagination information for search results.
@@ -223,3 +277,55 @@ export interface ParsedConfig<T = unknown> {
     default?: T;
   }[];
 }
+
+/**
+ * Error codes returned in the `code` field of screenpipe api errors
+ */
+export type ScreenpipeErrorCode =
+  | "invalid_request"
+  | "invalid_query"
+  | "unauthorized"
+  | "forbidden"
+  | "not_found"
+  | "method_not_allowed"
+  | "conflict"
+  | "payload_too_large"
+  | "pipe_not_found"
+  | "pipe_error"
+  | "database_error"
+  | "feature_disabled"
+  | "unavailable"
+  | "archive_unavailable"
+  | "internal_error";
+
+/**
+ * RFC 7807 problem details, sent as `application/problem+json` by every failing endpoint
+ */
+export interface ProblemDetails {
+  type: string;
+  title: string;
+  status: number;
+  detail: string;
+  code: ScreenpipeErrorCode;
+  traceId: string;
+  /** Character range of `q` at fault, for `invalid_query` */
+  span?: { start: number; end: number };
+  /** How to fix the query, for `invalid_query` */
+  hint?: string;
+  /** Deprecated parts of the api the request used */
+  warnings?: ApiWarning[];
+}
+
+/**
+ * Added to the `warnings` of a response that used a deprecated endpoint, param or field
+ */
+export interface ApiWarning {
+  code: "deprecated";
+  message: string;
+  kind: "endpoint" | "param" | "problem_field";
+  method?: string;
+  path?: string;
+  name?: string;
+  /** Planned removal, as an http date */
+  sunset?: string | null;
+}
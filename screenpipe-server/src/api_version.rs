@@ -0,0 +1,351 @@
+//! Api versioning and deprecations.
+//!
+//! Every route is served under `/v1` and, for now, at its old unversioned path too.
+//! Every response carries `x-screenpipe-api-version`. Endpoints, query params and
+//! response fields slated for removal are listed in [`DEPRECATIONS`]: a request that
+//! touches one gets a `deprecation` header (plus `sunset` once a removal date is set)
+//! and a warning in the `warnings` member of its json body, and is counted so
+//! `/v1/deprecations` shows when it is safe to remove.
+
+use crate::problem::{is_problem, PROBLEM_CONTENT_TYPE};
+use axum::body::{to_bytes, Body, HttpBody};
+use axum::extract::{Request, State};
+use axum::http::{header, HeaderValue};
+use axum::middleware::{self, Next};
+use axum::response::Response;
+use axum::Router;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+pub const API_VERSION: &str = "1";
+pub const API_PREFIX: &str = "/v1";
+pub const API_VERSION_HEADER: &str = "x-screenpipe-api-version";
+pub const DEPRECATION_HEADER: &str = "deprecation";
+pub const SUNSET_HEADER: &str = "sunset";
+
+/// Largest body a warning is added to, bigger ones only get the headers.
+const MAX_WARNED_BODY: u64 = 32 * 1024 * 1024;
+
+/// Part of the api a deprecation applies to. Paths use the router syntax and are
+/// relative to the version prefix, e.g. `/pipes/info/:pipe_id`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Surface {
+    Endpoint {
+        method: &'static str,
+        path: &'static str,
+    },
+    /// A query parameter of an endpoint
+    Param {
+        method: &'static str,
+        path: &'static str,
+        name: &'static str,
+    },
+    /// A member of every problem details body
+    ProblemField { name: &'static str },
+}
+
+impl Surface {
+    fn describe(&self) -> String {
+        match self {
+            Surface::Endpoint { method, path } => format!("{} {}", method, path),
+            Surface::Param { method, path, name } => {
+                format!("param `{}` of {} {}", name, method, path)
+            }
+            Surface::ProblemField { name } => format!("field `{}` of error responses", name),
+        }
+    }
+
+    fn matches_request(&self, method: &str, path: &str, params: &[String]) -> bool {
+        match self {
+            Surface::Endpoint {
+                method: m,
+                path: pattern,
+            } => m.eq_ignore_ascii_case(method) && path_matches(pattern, path),
+            Surface::Param {
+                method: m,
+                path: pattern,
+                name,
+            } => {
+                m.eq_ignore_ascii_case(method)
+                    && path_matches(pattern, path)
+                    && params.iter().any(|p| p == name)
+            }
+            Surface::ProblemField { .. } => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Deprecation {
+    #[serde(flatten)]
+    pub surface: Surface,
+    /// Api version it was deprecated in
+    pub since: &'static str,
+    /// Planned removal, as an http date
+    pub sunset: Option<&'static str>,
+    pub replacement: &'static str,
+}
+
+impl Deprecation {
+    pub fn message(&self) -> String {
+        let mut message = format!(
+            "{} is deprecated since v{}, use {} instead",
+            self.surface.describe(),
+            self.since,
+            self.replacement
+        );
+        if let Some(sunset) = self.sunset {
+            message.push_str(&format!(", it will be removed after {}", sunset));
+        }
+        message
+    }
+}
+
+/// Everything currently slated for removal, documented in the api reference.
+pub const DEPRECATIONS: &[Deprecation] = &[
+    Deprecation {
+        surface: Surface::ProblemField { name: "error" },
+        since: "1",
+        sunset: None,
+        replacement: "`detail`",
+    },
+    Deprecation {
+        surface: Surface::ProblemField { name: "success" },
+        since: "1",
+        sunset: None,
+        replacement: "the http status",
+    },
+];
+
+/// Entry of the `warnings` member added to deprecated responses.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ApiWarning {
+    pub code: &'static str,
+    pub message: String,
+    #[serde(flatten)]
+    pub surface: Surface,
+    pub sunset: Option<&'static str>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DeprecationUsage {
+    #[serde(flatten)]
+    pub deprecation: Deprecation,
+    pub requests: u64,
+    pub last_used: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DeprecationReport {
+    pub api_version: &'static str,
+    pub versioned_requests: u64,
+    /// Requests still using the unversioned aliases
+    pub unversioned_requests: u64,
+    pub deprecations: Vec<DeprecationUsage>,
+}
+
+/// The deprecation registry and how often each entry was hit.
+pub struct ApiVersioning {
+    deprecations: Vec<Deprecation>,
+    usage: Mutex<Vec<DeprecationUsage>>,
+    versioned: AtomicU64,
+    unversioned: AtomicU64,
+}
+
+static API_VERSIONING: OnceLock<Arc<ApiVersioning>> = OnceLock::new();
+
+/// Process wide registry behind the server's routes.
+pub fn api_versioning() -> Arc<ApiVersioning> {
+    API_VERSIONING
+        .get_or_init(|| Arc::new(ApiVersioning::new(DEPRECATIONS.to_vec())))
+        .clone()
+}
+
+impl ApiVersioning {
+    pub fn new(deprecations: Vec<Deprecation>) -> Self {
+        let usage = deprecations
+            .iter()
+            .map(|d| DeprecationUsage {
+                deprecation: *d,
+                requests: 0,
+                last_used: None,
+            })
+            .collect();
+        Self {
+            deprecations,
+            usage: Mutex::new(usage),
+            versioned: AtomicU64::new(0),
+            unversioned: AtomicU64::new(0),
+        }
+    }
+
+    pub fn report(&self) -> DeprecationReport {
+        DeprecationReport {
+            api_version: API_VERSION,
+            versioned_requests: self.versioned.load(Ordering::Relaxed),
+            unversioned_requests: self.unversioned.load(Ordering::Relaxed),
+            deprecations: self.usage.lock().unwrap().clone(),
+        }
+    }
+
+    fn record(&self, matched: &[usize]) {
+        let now = Utc::now();
+        let mut usage = self.usage.lock().unwrap();
+        for index in matched {
+            usage[*index].requests += 1;
+            usage[*index].last_used = Some(now);
+        }
+    }
+}
+
+/// Serves `router` under [`API_PREFIX`] and at its unversioned paths, with the version
+/// header and deprecation warnings on every response. Goes outside
+/// [`crate::problem::with_problem_details`] so error bodies get warnings too.
+pub fn with_api_version<S>(router: Router<S>, versioning: Arc<ApiVersioning>) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    Router::new()
+        .nest(API_PREFIX, router.clone())
+        .merge(router)
+        .layer(middleware::from_fn_with_state(versioning, api_version))
+}
+
+async fn api_version(
+    State(versioning): State<Arc<ApiVersioning>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let path = request.uri().path();
+    let (versioned, route_path) = match path.strip_prefix(API_PREFIX) {
+        Some("") => (true, "/".to_string()),
+        Some(rest) if rest.starts_with('/') => (true, rest.to_string()),
+        _ => (false, path.to_string()),
+    };
+    let counter = if versioned {
+        &versioning.versioned
+    } else {
+        &versioning.unversioned
+    };
+    counter.fetch_add(1, Ordering::Relaxed);
+
+    let method = request.method().as_str().to_string();
+    let params = param_names(request.uri().query());
+    let mut matched: Vec<usize> = versioning
+        .deprecations
+        .iter()
+        .enumerate()
+        .filter(|(_, d)| d.surface.matches_request(&method, &route_path, &params))
+        .map(|(i, _)| i)
+        .collect();
+
+    let mut response = next.run(request).await;
+    if is_problem(&response) {
+        matched.extend(
+            versioning
+                .deprecations
+                .iter()
+                .enumerate()
+                .filter(|(_, d)| matches!(d.surface, Surface::ProblemField { .. }))
+                .map(|(i, _)| i),
+        );
+    }
+
+    if !matched.is_empty() {
+        versioning.record(&matched);
+        let deprecations: Vec<&Deprecation> = matched
+            .iter()
+            .map(|i| &versioning.deprecations[*i])
+            .collect();
+        response = with_deprecation(response, &deprecations).await;
+    }
+
+    response
+        .headers_mut()
+        .insert(API_VERSION_HEADER, HeaderValue::from_static(API_VERSION));
+    response
+}
+
+async fn with_deprecation(response: Response, deprecations: &[&Deprecation]) -> Response {
+    let (mut parts, body) = response.into_parts();
+    parts
+        .headers
+        .insert(DEPRECATION_HEADER, HeaderValue::from_static("true"));
+    // The earliest removal is the one that matters to the client
+    if let Some(sunset) = deprecations
+        .iter()
+        .filter_map(|d| d.sunset)
+        .min_by_key(|s| DateTime::parse_from_rfc2822(s).map_or(i64::MAX, |date| date.timestamp()))
+    {
+        if let Ok(value) = HeaderValue::from_str(sunset) {
+            parts.headers.insert(SUNSET_HEADER, value);
+        }
+    }
+
+    let content_type = parts
+        .headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    let is_json = content_type.starts_with("application/json")
+        || content_type.starts_with(PROBLEM_CONTENT_TYPE);
+    let small = body
+        .size_hint()
+        .upper()
+        .is_some_and(|size| size <= MAX_WARNED_BODY);
+    if !is_json || !small {
+        return Response::from_parts(parts, body);
+    }
+
+    let bytes = match to_bytes(body, MAX_WARNED_BODY as usize).await {
+        Ok(bytes) => bytes,
+        Err(_) => return Response::from_parts(parts, Body::empty()),
+    };
+    let Ok(Value::Object(mut object)) = serde_json::from_slice::<Value>(&bytes) else {
+        return Response::from_parts(parts, Body::from(bytes));
+    };
+
+    let warnings = object
+        .entry("warnings")
+        .or_insert_with(|| Value::Array(Vec::new()));
+    if let Value::Array(warnings) = warnings {
+        for deprecation in deprecations {
+            let warning = ApiWarning {
+                code: "deprecated",
+                message: deprecation.message(),
+                surface: deprecation.surface,
+                sunset: deprecation.sunset,
+            };
+            warnings.extend(serde_json::to_value(warning));
+        }
+    }
+
+    parts.headers.remove(header::CONTENT_LENGTH);
+    let body = serde_json::to_vec(&object).unwrap_or_else(|_| bytes.to_vec());
+    Response::from_parts(parts, Body::from(body))
+}
+
+fn param_names(query: Option<&str>) -> Vec<String> {
+    query
+        .unwrap_or_default()
+        .split('&')
+        .filter_map(|pair| pair.split('=').next())
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// `pattern` in router syntax, `:name` segments match any non empty segment.
+fn path_matches(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<&str> = pattern.trim_end_matches('/').split('/').collect();
+    let path: Vec<&str> = path.trim_end_matches('/').split('/').collect();
+    pattern.len() == path.len()
+        && pattern
+            .iter()
+            .zip(&path)
+            .all(|(p, s)| *p == *s || (p.starts_with(':') && !s.is_empty()))
+}
@@ -0,0 +1,276 @@
+use crate::db_types::{ArchiveCandidate, MediaChunkKind};
+use crate::storage::{verify_copy, volume_available, MediaVolume};
+use crate::DatabaseManager;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, error, info, warn};
+
+/// How often the server archives media that got old enough.
+pub const ARCHIVE_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Chunks listed per query while archiving.
+const ARCHIVE_BATCH: u32 = 200;
+
+/// Chunks an archive run would move, from `Archiver::plan`.
+#[derive(Debug, Default, Serialize)]
+pub struct ArchivePlan {
+    pub chunks: Vec<ArchiveCandidate>,
+    pub bytes: u64,
+    /// Old enough, but their file is already gone from the media directory
+    pub missing: usize,
+}
+
+/// What one archive run did.
+#[derive(Debug, Default, Serialize)]
+pub struct ArchiveSummary {
+    pub moved: usize,
+    pub bytes: u64,
+    /// Originals left by an interrupted run, deleted now
+    pub resumed: usize,
+    pub missing: usize,
+    pub failed: usize,
+}
+
+/// Moves video and audio chunks older than the archive age to the archive directory.
+///
+/// Each file is copied, verified by size and sha256, then the chunk row is pointed at
+/// the copy and flagged `archived`, and only then is the original deleted. Chunks with
+/// frames or transcriptions tagged by hand stay where they are. The text of archived
+/// chunks stays in the database and searchable.
+pub struct Archiver {
+    db: Arc<DatabaseManager>,
+    media_dir: PathBuf,
+    archive_dir: PathBuf,
+    volume_id: Option<String>,
+    after_days: Option<u32>,
+    media_volume: Option<Arc<MediaVolume>>,
+}
+
+impl Archiver {
+    pub fn new(
+        db: Arc<DatabaseManager>,
+        media_dir: PathBuf,
+        archive_dir: PathBuf,
+        volume_id: Option<String>,
+        after_days: Option<u32>,
+    ) -> Self {
+        Self {
+            db,
+            media_dir,
+            archive_dir,
+            volume_id,
+            after_days,
+            media_volume: None,
+        }
+    }
+
+    /// Skips runs while the media drive is unplugged, the originals can't be read.
+    pub fn with_media_volume(mut self, media_volume: Arc<MediaVolume>) -> Self {
+        self.media_volume = Some(media_volume);
+        self
+    }
+
+    pub fn archive_dir(&self) -> &Path {
+        &self.archive_dir
+    }
+
+    pub fn after_days(&self) -> Option<u32> {
+        self.after_days
+    }
+
+    /// Whether the archive volume is mounted, checked on every call.
+    pub fn is_available(&self) -> bool {
+        volume_available(&self.archive_dir, self.volume_id.as_deref())
+    }
+
+    /// Where a chunk file goes: the same path relative to the media directory, or
+    /// `external/` for files written outside of it.
+    pub fn archive_path(&self, candidate: &ArchiveCandidate) -> PathBuf {
+        let original = Path::new(&candidate.file_path);
+        match original.strip_prefix(&self.media_dir) {
+            Ok(relative) if relative.file_name().is_some() => self.archive_dir.join(relative),
+            _ => {
+                let name = original
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                let kind = match candidate.kind {
+                    MediaChunkKind::Video => "video",
+                    MediaChunkKind::Audio => "audio",
+                };
+                self.archive_dir
+                    .join("external")
+                    .join(format!("{}-{}-{}", kind, candidate.id, name))
+            }
+        }
+    }
+
+    /// Chunks captured before the archive age that a run would move, and their size.
+    pub async fn plan(&self, now: DateTime<Utc>) -> Result<ArchivePlan> {
+        let mut plan = ArchivePlan::default();
+        let Some(before) = self.cutoff(now) else {
+            return Ok(plan);
+        };
+        for kind in [MediaChunkKind::Video, MediaChunkKind::Audio] {
+            let mut after_id = 0;
+            loop {
+                let candidates = self
+                    .db
+                    .get_archive_candidates(kind, before, after_id, ARCHIVE_BATCH)
+                    .await?;
+                let Some(last) = candidates.last() else {
+                    break;
+                };
+                after_id = last.id;
+                let full_batch = candidates.len() == ARCHIVE_BATCH as usize;
+
+                for candidate in candidates {
+                    match tokio::fs::metadata(&candidate.file_path).await {
+                        Ok(metadata) => {
+                            plan.bytes += metadata.len();
+                            plan.chunks.push(candidate);
+                        }
+                        Err(_) => plan.missing += 1,
+                    }
+                }
+                if !full_batch {
+                    break;
+                }
+            }
+        }
+        Ok(plan)
+    }
+
+    pub async fn run(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(ARCHIVE_INTERVAL);
+        loop {
+            interval.tick().await;
+            match self.archive(Utc::now()).await {
+                Ok(summary) => match serde_json::to_string(&summary) {
+                    Ok(json) => info!("archive: {}", json),
+                    Err(e) => error!("failed to serialize archive summary: {}", e),
+                },
+                Err(e) => warn!("archive run skipped: {}", e),
+            }
+        }
+    }
+
+    /// Finishes what an interrupted run left, then moves every chunk in the plan.
+    pub async fn archive(&self, now: DateTime<Utc>) -> Result<ArchiveSummary> {
+        if !self.is_available() {
+            return Err(anyhow!(
+                "archive directory {} is not available",
+                self.archive_dir.display()
+            ));
+        }
+        if self
+            .media_volume
+            .as_ref()
+            .is_some_and(|v| !v.is_available())
+        {
+            return Err(anyhow!("media directory is not available"));
+        }
+
+        let mut summary = ArchiveSummary {
+            resumed: self.finish_pending().await?,
+            ..Default::default()
+        };
+        let plan = self.plan(now).await?;
+        summary.missing = plan.missing;
+        for candidate in plan.chunks {
+            match self.move_chunk(&candidate).await {
+                Ok(Some(bytes)) => {
+                    summary.moved += 1;
+                    summary.bytes += bytes;
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    warn!("failed to archive {}: {}", candidate.file_path, e);
+                    summary.failed += 1;
+                }
+            }
+        }
+        Ok(summary)
+    }
+
+    /// Deletes originals whose chunk was pointed at the archive before a crash.
+    async fn finish_pending(&self) -> Result<usize> {
+        let mut finished = 0;
+        for pending in self.db.get_pending_archive_moves().await? {
+            // The copy was verified before the move was recorded, it only has to be there
+            if !Path::new(&pending.archive_path).is_file() {
+                warn!(
+                    "archived copy {} is missing, keeping {}",
+                    pending.archive_path, pending.original_path
+                );
+                continue;
+            }
+            match tokio::fs::remove_file(&pending.original_path).await {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => {
+                    warn!("failed to remove {}: {}", pending.original_path, e);
+                    continue;
+                }
+            }
+            self.db.finish_archive_move(pending.id).await?;
+            finished += 1;
+        }
+        Ok(finished)
+    }
+
+    /// Copies, verifies and switches one chunk, returning the bytes moved. `None` when
+    /// the chunk changed since it was listed and was left alone.
+    async fn move_chunk(&self, candidate: &ArchiveCandidate) -> Result<Option<u64>> {
+        let original = PathBuf::from(&candidate.file_path);
+        let destination = self.archive_path(candidate);
+        // A copy left by an interrupted run is overwritten, the row never pointed at it
+        let copy_to = destination.clone();
+        let bytes = tokio::task::spawn_blocking(move || -> Result<u64> {
+            if let Some(parent) = copy_to.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            let bytes = std::fs::copy(&original, &copy_to)?;
+            verify_copy(&original, &copy_to)?;
+            Ok(bytes)
+        })
+        .await??;
+
+        let archive_path = destination.to_string_lossy().into_owned();
+        let Some(move_id) = self
+            .db
+            .mark_chunk_archived(
+                candidate.kind,
+                candidate.id,
+                &candidate.file_path,
+                &archive_path,
+            )
+            .await?
+        else {
+            debug!(
+                "{} changed while archiving, keeping it",
+                candidate.file_path
+            );
+            let _ = tokio::fs::remove_file(&destination).await;
+            return Ok(None);
+        };
+        // Left for the next run's `finish_pending` if this fails
+        match tokio::fs::remove_file(&candidate.file_path).await {
+            Ok(()) => self.db.finish_archive_move(move_id).await?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                self.db.finish_archive_move(move_id).await?
+            }
+            Err(e) => warn!("failed to remove {}: {}", candidate.file_path, e),
+        }
+        Ok(Some(bytes))
+    }
+
+    fn cutoff(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.after_days
+            .map(|days| now - ChronoDuration::days(days as i64))
+    }
+}
@@ -0,0 +1,248 @@
+//! Playback of stored audio on `GET /audio/:chunk_id`.
+//!
+//! Chunks are written as aac in mp4, which browsers play as is. A clip of a chunk,
+//! a chunk in a format browsers don't play, or one whose transcriptions don't cover
+//! all of it is transcoded by ffmpeg to aac instead. Audio plays only where its text
+//! is still kept: the rest of the chunk is silenced, so deleting a speaker's
+//! transcriptions takes their voice out of playback as well.
+//!
+//! A chunk row is only written once its file is complete, and playback reads the
+//! file without holding it open between requests, so it never touches the chunk
+//! being recorded. Transcodes are limited to [`MAX_TRANSCODES`] at a time to leave
+//! the cpu to the recording's own encoding.
+
+use anyhow::{anyhow, Result};
+use screenpipe_core::find_ffmpeg_path;
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::OnceLock;
+use tokio::process::Command;
+use tokio::sync::Semaphore;
+use tracing::debug;
+
+use crate::db_types::AudioChunkSource;
+
+/// Kept audible around each transcription, its bounds can cut a word short.
+pub const SEGMENT_PADDING_SECS: f64 = 0.5;
+
+/// Transcodes running at once, more requests wait for one to finish.
+pub const MAX_TRANSCODES: usize = 2;
+
+/// What transcoded audio is sent as, aac in adts frames.
+pub const TRANSCODED_CONTENT_TYPE: &str = "audio/aac";
+
+static TRANSCODES: OnceLock<Semaphore> = OnceLock::new();
+
+/// Part of a chunk to play, in milliseconds from its start. Open ends run to the
+/// start or the end of the chunk.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Clip {
+    pub start_ms: Option<u64>,
+    pub end_ms: Option<u64>,
+}
+
+impl Clip {
+    pub fn new(start_ms: Option<u64>, end_ms: Option<u64>) -> Result<Self, String> {
+        if let (Some(start), Some(end)) = (start_ms, end_ms) {
+            if end <= start {
+                return Err(format!(
+                    "end_ms ({}) must be after start_ms ({})",
+                    end, start
+                ));
+            }
+        }
+        Ok(Self { start_ms, end_ms })
+    }
+
+    pub fn is_whole(&self) -> bool {
+        self.start_ms.unwrap_or(0) == 0 && self.end_ms.is_none()
+    }
+}
+
+/// One satisfiable byte range of a `Range` header, `end` included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    /// `Content-Range` of this range out of `total` bytes.
+    pub fn content_range(&self, total: u64) -> String {
+        format!("bytes {}-{}/{}", self.start, self.end, total)
+    }
+}
+
+/// The range a `Range` header asks for out of `len` bytes. `Ok(None)` sends the
+/// whole body: no header, another unit or several ranges, which aren't supported.
+/// `Err` when no byte of the range exists, a 416.
+pub fn byte_range(header: Option<&str>, len: u64) -> Result<Option<ByteRange>, String> {
+    let Some(spec) = header.and_then(|h| h.trim().strip_prefix("bytes=")) else {
+        return Ok(None);
+    };
+    if spec.contains(',') {
+        return Ok(None);
+    }
+    let invalid = || format!("invalid range {}", spec);
+    let (start, end) = spec.split_once('-').ok_or_else(invalid)?;
+    let (start, end) = (start.trim(), end.trim());
+    let range = if start.is_empty() {
+        // The last `end` bytes
+        let suffix: u64 = end.parse().map_err(|_| invalid())?;
+        if suffix == 0 || len == 0 {
+            return Err(format!("range {} is empty", spec));
+        }
+        ByteRange {
+            start: len.saturating_sub(suffix),
+            end: len - 1,
+        }
+    } else {
+        let start: u64 = start.parse().map_err(|_| invalid())?;
+        let end = match end {
+            "" => len.saturating_sub(1),
+            end => end
+                .parse::<u64>()
+                .map_err(|_| invalid())?
+                .min(len.saturating_sub(1)),
+        };
+        if start >= len || end < start {
+            return Err(format!("range {} is outside the {} bytes", spec, len));
+        }
+        ByteRange { start, end }
+    };
+    Ok(Some(range))
+}
+
+/// Seconds of a chunk that may be heard: its kept transcriptions padded by
+/// [`SEGMENT_PADDING_SECS`] and merged, in order. `None` when one of them covers the
+/// whole chunk, as transcriptions written before segment times were stored do.
+pub fn audible_ranges(segments: &[(Option<f64>, Option<f64>)]) -> Option<Vec<(f64, f64)>> {
+    let mut ranges = Vec::with_capacity(segments.len());
+    for segment in segments {
+        match segment {
+            (Some(start), Some(end)) => ranges.push((
+                (start - SEGMENT_PADDING_SECS).max(0.0),
+                end + SEGMENT_PADDING_SECS,
+            )),
+            _ => return None,
+        }
+    }
+    ranges.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let mut merged: Vec<(f64, f64)> = Vec::with_capacity(ranges.len());
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    Some(merged)
+}
+
+/// Url playing a transcription of a chunk: its segment, or the whole chunk for
+/// transcriptions without segment times.
+pub fn playback_url(chunk_id: i64, start_time: Option<f64>, end_time: Option<f64>) -> String {
+    match (start_time, end_time) {
+        (Some(start), Some(end)) if end > start => format!(
+            "/audio/{}?start_ms={}&end_ms={}",
+            chunk_id,
+            (start * 1000.0).round() as u64,
+            (end * 1000.0).round() as u64
+        ),
+        (Some(start), _) => format!(
+            "/audio/{}?start_ms={}",
+            chunk_id,
+            (start * 1000.0).round() as u64
+        ),
+        _ => format!("/audio/{}", chunk_id),
+    }
+}
+
+/// Content type of an audio file browsers play as is, by extension.
+pub fn browser_content_type(path: &str) -> Option<&'static str> {
+    let extension = Path::new(path).extension()?.to_str()?.to_lowercase();
+    match extension.as_str() {
+        "mp4" | "m4a" => Some("audio/mp4"),
+        "mp3" => Some("audio/mpeg"),
+        "aac" => Some("audio/aac"),
+        "wav" => Some("audio/wav"),
+        "ogg" | "opus" => Some("audio/ogg"),
+        "webm" => Some("audio/webm"),
+        _ => None,
+    }
+}
+
+/// ffmpeg arguments writing `clip` of `file_path` to stdout as aac, silent outside
+/// `audible` when given.
+pub fn transcode_args(file_path: &str, clip: Clip, audible: Option<&[(f64, f64)]>) -> Vec<String> {
+    let mut args: Vec<String> = ["-hide_banner", "-loglevel", "error", "-i", file_path, "-vn"]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+    if let Some(audible) = audible {
+        // Times are the chunk's, the clip is cut after the filter
+        let heard: Vec<String> = audible
+            .iter()
+            .map(|(start, end)| format!("between(t,{:.3},{:.3})", start, end))
+            .collect();
+        let filter = if heard.is_empty() {
+            "volume=0".to_string()
+        } else {
+            format!("volume=enable='not({})':volume=0", heard.join("+"))
+        };
+        args.extend(["-af".to_string(), filter]);
+    }
+    let start_ms = clip.start_ms.unwrap_or(0);
+    if start_ms > 0 {
+        args.extend(["-ss".to_string(), seconds(start_ms)]);
+    }
+    if let Some(end_ms) = clip.end_ms {
+        args.extend(["-t".to_string(), seconds(end_ms.saturating_sub(start_ms))]);
+    }
+    args.extend(
+        ["-c:a", "aac", "-b:a", "128k", "-f", "adts", "pipe:1"]
+            .iter()
+            .map(|arg| arg.to_string()),
+    );
+    args
+}
+
+fn seconds(ms: u64) -> String {
+    format!("{:.3}", ms as f64 / 1000.0)
+}
+
+/// The audio of `clip` and its content type: the file as stored when browsers play
+/// it and all of it may be heard, otherwise transcoded.
+pub async fn load_audio(source: &AudioChunkSource, clip: Clip) -> Result<(Vec<u8>, &'static str)> {
+    let audible = audible_ranges(&source.segments);
+    if let (true, None, Some(content_type)) = (
+        clip.is_whole(),
+        &audible,
+        browser_content_type(&source.file_path),
+    ) {
+        return Ok((tokio::fs::read(&source.file_path).await?, content_type));
+    }
+
+    let _permit = TRANSCODES
+        .get_or_init(|| Semaphore::new(MAX_TRANSCODES))
+        .acquire()
+        .await?;
+    let ffmpeg_path = find_ffmpeg_path().ok_or_else(|| anyhow!("ffmpeg not found"))?;
+    let args = transcode_args(&source.file_path, clip, audible.as_deref());
+    debug!("transcoding audio chunk {}: {:?}", source.chunk_id, args);
+    let output = Command::new(ffmpeg_path)
+        .args(&args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "ffmpeg failed to transcode {}: {}",
+            source.file_path,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok((output.stdout, TRANSCODED_CONTENT_TYPE))
+}
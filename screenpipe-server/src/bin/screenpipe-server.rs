@@ -4,11 +4,12 @@ use std::{
     io::Write,
     net::SocketAddr,
     ops::Deref,
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::{atomic::AtomicBool, Arc},
     time::Duration,
 };
 
+use chrono::Utc;
 use clap::Parser;
 #[allow(unused_imports)]
 use colored::Colorize;
@@ -20,16 +21,52 @@ use screenpipe_audio::{
     default_input_device, default_output_device, list_audio_devices, parse_audio_device,
     AudioDevice, DeviceControl,
 };
+use screenpipe_core::clock::{capture_clock, ClockConfig};
 use screenpipe_core::find_ffmpeg_path;
+use screenpipe_core::latency::{latency_tracker, start_latency_monitor, LatencyBudget};
+use screenpipe_core::models::run_idle_unloader;
+use screenpipe_core::pipe_config;
+use screenpipe_core::power::{power_state, start_power_monitor};
+use screenpipe_core::{ConflictPolicy, DownloadOptions, OverwritePolicy};
 use screenpipe_server::{
-    cli::{Cli, CliAudioTranscriptionEngine, CliOcrEngine, Command, OutputFormat, PipeCommand},
+    archive::Archiver,
+    cli::{
+        Cli, CliAudioTranscriptionEngine, CliOcrEngine, Command, OutputFormat, PipeCommand,
+        SessionCommand, StorageCommand,
+    },
+    copy::{copy_to_clipboard, ClipboardContent, CopyLink, CopyWhat, CLIPBOARD_HOLD},
+    data_dir::{init_data_dir, InitReport, LOGS_DIR},
+    db_retry::{drain_spill_journal, SPILL_DRAIN_INTERVAL, SPILL_JOURNAL_FILE},
+    db_types::{CaptureSession, ExportFormat},
+    events::EventRecorder,
     highlight::{Highlight, HighlightConfig},
+    ocr_correction::{Dictionary, OcrCorrectionConfig, OcrCorrector, SYSTEM_WORD_LIST},
+    pipe_batch::{plan_manifest, BatchReport, OperationStatus, PipeManifest},
+    pipe_lock::{
+        generate_lock, installed_pipes, plan_sync, sync_pipes, PipeLock, SyncAction, SyncReport,
+    },
     pipe_manager::PipeInfo,
-    start_continuous_recording, watch_pid, DatabaseManager, PipeManager, ResourceMonitor, Server,
+    pipe_schedule::PipeScheduler,
+    privacy::{ignored_apps, run_rollups, set_ignored_windows},
+    replay::{run_replay, ReplayOptions},
+    retention::RetentionManager,
+    sessions::SessionManager,
+    start_continuous_recording,
+    storage::{
+        configure_archive, copy_storage, path_prefix, MediaVolume, StorageConfig, StorageDirs,
+        StorageKind,
+    },
+    storage_mode::storage_mode,
+    tag_rules::TagRules,
+    telemetry::{do_not_track, Telemetry},
+    wake::{handle_power_events, record_clock_adjustments, unload_models_on_sleep},
+    watch_folder::{WatchFolder, WatchFolderConfig},
+    watch_pid, DatabaseManager, PipeManager, ResourceMonitor, Server,
 };
 use screenpipe_vision::monitor::list_monitors;
 #[cfg(target_os = "macos")]
 use screenpipe_vision::run_ui;
+use screenpipe_vision::{OcrEngine, OcrScheduler, OcrSchedulerConfig};
 use serde_json::{json, Value};
 use tokio::{runtime::Runtime, signal, sync::broadcast};
 use tracing::{debug, error, info, warn};
@@ -64,14 +101,104 @@ fn get_base_dir(custom_path: &Option<String>) -> anyhow::Result<PathBuf> {
         .ok_or_else(|| anyhow::anyhow!("failed to get home directory"))?
         .join(".screenpipe");
 
-    let base_dir = custom_path
+    Ok(custom_path
         .as_ref()
         .map(PathBuf::from)
-        .unwrap_or(default_path);
-    let data_dir = base_dir.join("data");
+        .unwrap_or(default_path))
+}
+
+fn print_init_report(base_dir: &Path, report: &InitReport) {
+    println!("{}", base_dir.display());
+    for dir in &report.created {
+        println!("  created {}", dir.display());
+    }
+    for migration in &report.migrated {
+        println!(
+            "  moved {} to {}",
+            migration.from.display(),
+            migration.to.display()
+        );
+    }
+    if let Some(backup_dir) = &report.backup_dir {
+        println!(
+            "  copies of the moved files are in {}",
+            backup_dir.display()
+        );
+    }
+    for skipped in &report.skipped {
+        println!("  {} {}", "skipped".yellow(), skipped);
+    }
+    if !report.changed() && report.skipped.is_empty() {
+        println!("  {}", "up to date".green());
+    }
+}
+
+/// The dictionary pass of --ocr-correction, over the word lists given or the system one,
+/// plus the user's own list.
+fn load_ocr_corrector(cli: &Cli, local_data_dir: &Path) -> anyhow::Result<OcrCorrector> {
+    let mut word_lists = cli.ocr_dictionaries.clone();
+    if word_lists.is_empty() && Path::new(SYSTEM_WORD_LIST).is_file() {
+        word_lists.push(PathBuf::from(SYSTEM_WORD_LIST));
+    }
+    let user_words = Dictionary::user_path(local_data_dir);
+    if user_words.is_file() {
+        word_lists.push(user_words);
+    }
+    let dictionary = Dictionary::load(&word_lists)?;
+    if dictionary.is_empty() {
+        warn!("no word list for ocr correction, only ligatures are fixed. set one with --ocr-dictionary");
+        return Ok(OcrCorrector::new(OcrCorrectionConfig::default(), None));
+    }
+    info!("ocr correction knows {} words", dictionary.len());
+    Ok(OcrCorrector::new(
+        OcrCorrectionConfig::default(),
+        Some(dictionary),
+    ))
+}
 
-    fs::create_dir_all(&data_dir)?;
-    Ok(base_dir)
+/// Runs the ocr correction prompt through the local llm, one window at a time.
+#[cfg(feature = "llm")]
+fn llm_correction_model(
+    llm: Arc<std::sync::Mutex<screenpipe_core::LLM>>,
+) -> screenpipe_server::ocr_correction::CorrectionModel {
+    use screenpipe_core::{ChatMessage, ChatRequest};
+    use screenpipe_server::ocr_correction::CorrectionPrompt;
+
+    Arc::new(move |prompt: CorrectionPrompt| {
+        let llm = llm.clone();
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || {
+                let request = ChatRequest {
+                    messages: vec![
+                        ChatMessage {
+                            role: "system".to_string(),
+                            content: prompt.system.to_string(),
+                        },
+                        ChatMessage {
+                            role: "user".to_string(),
+                            content: prompt.text,
+                        },
+                    ],
+                    stream: false,
+                    max_completion_tokens: Some(prompt.max_tokens),
+                    temperature: Some(0.0),
+                    top_p: None,
+                    top_k: None,
+                    seed: None,
+                };
+                let llm = llm
+                    .lock()
+                    .map_err(|_| anyhow::anyhow!("the llm panicked on an earlier call"))?;
+                llm.chat(request)?
+                    .choices
+                    .into_iter()
+                    .next()
+                    .map(|choice| choice.message.content)
+                    .ok_or_else(|| anyhow::anyhow!("the llm returned no choice"))
+            })
+            .await?
+        })
+    })
 }
 
 fn setup_logging(local_data_dir: &PathBuf, cli: &Cli) -> anyhow::Result<WorkerGuard> {
@@ -80,7 +207,7 @@ fn setup_logging(local_data_dir: &PathBuf, cli: &Cli) -> anyhow::Result<WorkerGu
         .filename_prefix("screenpipe")
         .filename_suffix("log")
         .max_log_files(5)
-        .build(local_data_dir)?;
+        .build(local_data_dir.join(LOGS_DIR))?;
 
     let (non_blocking, guard) = tracing_appender::non_blocking(file_appender);
 
@@ -132,9 +259,14 @@ fn setup_logging(local_data_dir: &PathBuf, cli: &Cli) -> anyhow::Result<WorkerGu
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     debug!("starting screenpipe server");
-    let cli = Cli::parse();
+    let mut cli = Cli::parse();
 
-    if !is_local_ipv4_port_free(cli.port) {
+    // Session and copy commands talk to the server already listening on the port
+    let server_command = matches!(
+        cli.command,
+        Some(Command::Session { .. } | Command::Copy { .. })
+    );
+    if !server_command && !is_local_ipv4_port_free(cli.port) {
         error!(
             "you're likely already running screenpipe instance in a different environment, e.g. terminal/ide, close it and restart or use different port"
         );
@@ -143,6 +275,8 @@ async fn main() -> anyhow::Result<()> {
 
     let local_data_dir = get_base_dir(&cli.data_dir)?;
     let local_data_dir_clone = local_data_dir.clone();
+    // Fails here, before anything is written, when the directory can't be used
+    let init_report = init_data_dir(&local_data_dir)?;
 
     // Only set up logging if we're not running a pipe command with JSON output
     let should_log = match &cli.command {
@@ -158,13 +292,22 @@ async fn main() -> anyhow::Result<()> {
                 } | PipeCommand::Info {
                     output: OutputFormat::Text,
                     ..
+                } | PipeCommand::Diff {
+                    output: OutputFormat::Text,
+                    ..
                 } | PipeCommand::Enable { .. }
                     | PipeCommand::Disable { .. }
                     | PipeCommand::Update { .. }
                     | PipeCommand::Purge { .. }
                     | PipeCommand::Delete { .. }
+                    | PipeCommand::Lock { .. }
+                    | PipeCommand::Sync {
+                        output: OutputFormat::Text,
+                        ..
+                    }
             )
         }
+        Some(Command::Init { output }) => matches!(output, OutputFormat::Text),
         _ => true,
     };
 
@@ -174,6 +317,9 @@ async fn main() -> anyhow::Result<()> {
     } else {
         None
     };
+    if !matches!(cli.command, Some(Command::Init { .. })) {
+        init_report.log();
+    }
 
     let h = Highlight::init(HighlightConfig {
         project_id: String::from("82688"),
@@ -181,12 +327,31 @@ async fn main() -> anyhow::Result<()> {
     })
     .expect("Failed to initialize Highlight.io");
 
-    let pipe_manager = Arc::new(PipeManager::new(local_data_dir_clone.clone()));
+    let mut storage = StorageDirs::resolve(&local_data_dir)?;
+    if storage.custom_models_dir() {
+        // Read by the audio crate's model downloads and by hf-hub
+        env::set_var("SCREENPIPE_MODELS_DIR", &storage.models_dir);
+        env::set_var("HF_HOME", storage.models_dir.join("huggingface"));
+    }
+
+    // Before anything reads a pipe.json, including the pipe subcommands
+    pipe_config::set_lenient(cli.lenient_pipe_json);
+    let pipe_manager = Arc::new(
+        PipeManager::new(local_data_dir_clone.clone())
+            .with_auto_approve_pipes(cli.auto_approve_pipes)
+            .with_network_proxy(cli.pipe_network_proxy)
+            .with_strict_manifests(cli.strict_manifests)
+            .with_shutdown_grace(Duration::from_secs(cli.pipe_shutdown_grace_secs))
+            .with_memory_limit(
+                (cli.pipe_memory_limit_mb > 0).then(|| cli.pipe_memory_limit_mb * 1024 * 1024),
+            )
+            .with_sandbox(cli.pipe_sandbox.clone().into()),
+    );
 
     if let Some(command) = cli.command {
         match command {
             Command::Pipe { subcommand } => {
-                handle_pipe_command(subcommand, &pipe_manager).await?;
+                handle_pipe_command(subcommand, &pipe_manager, &storage).await?;
                 return Ok(());
             }
             #[allow(unused_variables)]
@@ -259,7 +424,7 @@ async fn main() -> anyhow::Result<()> {
             }
             Command::Migrate => {
                 info!("running database migrations...");
-                DatabaseManager::new(&format!("{}/db.sqlite", local_data_dir.to_string_lossy()))
+                DatabaseManager::new(&storage.db_path()?.to_string_lossy())
                     .await
                     .map_err(|e| {
                         error!("failed to initialize database: {:?}", e);
@@ -268,6 +433,53 @@ async fn main() -> anyhow::Result<()> {
                 info!("database migrations completed successfully");
                 return Ok(());
             }
+            Command::Init { output } => {
+                match output {
+                    OutputFormat::Json => {
+                        println!("{}", serde_json::to_string_pretty(&init_report)?)
+                    }
+                    OutputFormat::Text => print_init_report(&local_data_dir, &init_report),
+                }
+                return Ok(());
+            }
+            Command::Replay {
+                from,
+                speed,
+                preserve_timestamps,
+            } => {
+                let db_path = run_replay(ReplayOptions {
+                    from: PathBuf::from(from),
+                    speed,
+                    preserve_timestamps,
+                    output_dir: local_data_dir.join("replay"),
+                    fps: cli.fps,
+                    video_chunk_duration: Duration::from_secs(cli.video_chunk_duration),
+                    ocr_engine: Arc::new(cli.ocr_engine.clone().into()),
+                    audio_transcription_engine: Arc::new(
+                        cli.audio_transcription_engine.clone().into(),
+                    ),
+                    vad_engine: cli.vad_engine.clone(),
+                    vad_sensitivity: cli.vad_sensitivity.clone(),
+                    deepgram_api_key: cli.deepgram_api_key.clone(),
+                    languages: cli.language.clone(),
+                    use_pii_removal: cli.use_pii_removal,
+                })
+                .await?;
+                println!("replay written to {}", db_path.display());
+                return Ok(());
+            }
+            Command::Storage { subcommand } => {
+                handle_storage_command(subcommand, &local_data_dir, &storage).await?;
+                return Ok(());
+            }
+            Command::Session { subcommand } => {
+                handle_session_command(subcommand).await?;
+                return Ok(());
+            }
+            Command::Copy { id, what, port } => {
+                handle_copy_command(id, what.into(), port).await?;
+                return Ok(());
+            }
         }
     }
 
@@ -282,18 +494,42 @@ async fn main() -> anyhow::Result<()> {
         }
     }
 
+    // Only the api, database and pipes run, fed by other machines' pushes
+    let headless = cli.is_headless();
+    if headless {
+        if !cli.headless {
+            info!("no display server found, running headless");
+        }
+        cli.disable_vision = true;
+        cli.disable_audio = true;
+        cli.enable_ui_monitoring = false;
+    }
+
     if find_ffmpeg_path().is_none() {
-        eprintln!("ffmpeg not found. please install ffmpeg and ensure it is in your path.");
-        std::process::exit(1);
+        if headless {
+            warn!("ffmpeg not found, frames of pushed video chunks can't be served");
+        } else {
+            eprintln!("ffmpeg not found. please install ffmpeg and ensure it is in your path.");
+            std::process::exit(1);
+        }
     }
 
-    let all_audio_devices = list_audio_devices().await?;
+    // A container has no audio host or display to list devices from
+    let all_audio_devices = if headless && !cli.list_audio_devices {
+        Vec::new()
+    } else {
+        list_audio_devices().await?
+    };
     let mut devices_status = HashMap::new();
     if cli.list_audio_devices {
         print_devices(&all_audio_devices);
         return Ok(());
     }
-    let all_monitors = list_monitors().await;
+    let all_monitors = if headless && !cli.list_monitors {
+        Vec::new()
+    } else {
+        list_monitors().await
+    };
     if cli.list_monitors {
         println!("available monitors:");
         for monitor in all_monitors.iter() {
@@ -373,14 +609,151 @@ async fn main() -> anyhow::Result<()> {
     let resource_monitor = ResourceMonitor::new();
     resource_monitor.start_monitoring(Duration::from_secs(10));
 
+    let db_path = storage.db_path()?;
     let db = Arc::new(
-        DatabaseManager::new(&format!("{}/db.sqlite", local_data_dir.to_string_lossy()))
+        DatabaseManager::new(&db_path.to_string_lossy())
             .await
             .map_err(|e| {
                 eprintln!("failed to initialize database: {:?}", e);
                 e
-            })?,
+            })?
+            .with_spill_journal(db_path.with_file_name(SPILL_JOURNAL_FILE))
+            .with_tag_rules(TagRules::load(&local_data_dir)),
+    );
+
+    // Commit what the last run spilled while the database was locked, before new captures
+    match db.replay_spill_journal().await {
+        Ok(0) => {}
+        Ok(replayed) => info!("replayed {} writes spilled by the last run", replayed),
+        Err(e) => warn!("failed to replay the spill journal: {}", e),
+    }
+    tokio::spawn(drain_spill_journal(db.clone(), SPILL_DRAIN_INTERVAL));
+
+    // The media drive came back at another mount point, point stored chunks at it
+    if let Some(from) = storage.media.relocated_from.clone() {
+        let updated = db
+            .rebase_media_paths(&path_prefix(&from), &path_prefix(&storage.media.path))
+            .await?;
+        info!(
+            "media directory moved to {}, updated {} chunk paths",
+            storage.media.path.display(),
+            updated
+        );
+    }
+    if let Some(archive) = &storage.archive {
+        if let Some(from) = archive.relocated_from.clone() {
+            let updated = db
+                .rebase_media_paths(&path_prefix(&from), &path_prefix(&archive.path))
+                .await?;
+            info!(
+                "archive directory moved to {}, updated {} chunk paths",
+                archive.path.display(),
+                updated
+            );
+        }
+    }
+    storage.commit_relocations(&local_data_dir)?;
+
+    // Every subsystem records its lifecycle events through this, kept for a year
+    let events = Arc::new(EventRecorder::new(db.clone()));
+    tokio::spawn(events.clone().run_retention());
+
+    let media_volume = Arc::new(MediaVolume::new(
+        storage.media.path.clone(),
+        storage.media.volume_id.clone(),
+    ));
+    media_volume.record_events(events.clone());
+    media_volume.spawn_watcher(Duration::from_secs(2));
+
+    // Moves media older than the archive age to the archive directory, frames are
+    // served from there while its volume is mounted
+    let archiver = storage.archive.as_ref().map(|archive| {
+        Arc::new(
+            Archiver::new(
+                db.clone(),
+                storage.media.path.clone(),
+                archive.path.clone(),
+                archive.volume_id.clone(),
+                storage.archive_after_days(),
+            )
+            .with_media_volume(media_volume.clone()),
+        )
+    });
+    if let Some(archiver) = archiver.as_ref().filter(|a| a.after_days().is_some()) {
+        tokio::spawn(archiver.clone().run());
+    }
+
+    // Capture loops and pipe crons react to sleep/wake themselves, this records the
+    // gap and what each of them did
+    start_power_monitor();
+    tokio::spawn(handle_power_events(events.clone(), power_state()));
+    if cli.unload_models_on_sleep {
+        tokio::spawn(unload_models_on_sleep(power_state()));
+    }
+    if let Some(minutes) = cli.model_idle_minutes {
+        tokio::spawn(run_idle_unloader(Duration::from_secs(minutes * 60)));
+    }
+
+    // Stamps never go backwards with the monotonic source, jumps are recorded as events
+    capture_clock().set_config(ClockConfig {
+        source: cli.timestamp_source.clone().into(),
+        ..ClockConfig::default()
+    });
+    tokio::spawn(record_clock_adjustments(events.clone(), capture_clock()));
+
+    // Switched at runtime through the server, every frame records its own mode
+    storage_mode().set(cli.storage_mode.clone().into());
+    ignored_apps().load(&local_data_dir);
+    set_ignored_windows(&cli.ignored_windows);
+
+    // Latency is always measured, the budget only adds alerts and degradation
+    if let Some(budget) = cli.latency_budget {
+        latency_tracker().set_budget(Some(LatencyBudget {
+            auto_degrade: cli.latency_auto_degrade,
+            ..LatencyBudget::new(Duration::from_secs_f64(budget))
+        }));
+        start_latency_monitor();
+    }
+
+    // Prunes content past its retention policy, the server edits the same settings
+    let retention = Arc::new(RetentionManager::new(
+        db.clone(),
+        local_data_dir.clone(),
+        Some(media_volume.clone()),
+    ));
+    tokio::spawn(retention.clone().run());
+
+    // Keeps the per app counts of /privacy/summary up to date
+    tokio::spawn(run_rollups(db.clone()));
+
+    pipe_manager.record_network_stats(db.clone());
+    pipe_manager.record_events(events.clone());
+
+    // Fires the one-off jobs pipes schedule for themselves, including those left from
+    // before a restart
+    let pipe_scheduler = Arc::new(PipeScheduler::new(db.clone(), pipe_manager.clone()));
+    pipe_scheduler.spawn();
+
+    // Closes a capture session left open too long, also one left from before a restart
+    let sessions = Arc::new(
+        SessionManager::new(
+            db.clone(),
+            cli.session_export_dir
+                .clone()
+                .unwrap_or_else(|| local_data_dir.join("exports").join("sessions")),
+        )
+        .with_max_duration(Duration::from_secs(cli.session_max_minutes as u64 * 60))
+        .with_pipe_manager(pipe_manager.clone()),
+    );
+    sessions.spawn();
+
+    // Daily anonymous usage pings, sent only once opted into on /telemetry
+    let telemetry = Arc::new(
+        Telemetry::new(db.clone(), pipe_manager.clone(), local_data_dir.clone())
+            .with_features(cli.enabled_features())
+            .with_do_not_track(do_not_track() || cli.disable_telemetry),
     );
+    tokio::spawn(telemetry.clone().run());
 
     let db_server = db.clone();
 
@@ -413,7 +786,8 @@ async fn main() -> anyhow::Result<()> {
     let vision_handle = vision_runtime.handle().clone();
 
     let db_clone = Arc::clone(&db);
-    let output_path_clone = Arc::new(local_data_dir.join("data").to_string_lossy().into_owned());
+    let events_clone = Arc::clone(&events);
+    let output_path_clone = Arc::new(storage.media.path.to_string_lossy().into_owned());
     let vision_control_clone = Arc::clone(&vision_control);
     let shutdown_tx_clone = shutdown_tx.clone();
     let monitor_ids_clone = monitor_ids.clone();
@@ -429,6 +803,61 @@ async fn main() -> anyhow::Result<()> {
 
     let audio_chunk_duration = Duration::from_secs(cli.audio_chunk_duration);
 
+    // A single monitor keeps the plain per-monitor fifo unless a cap is asked for
+    let ocr_scheduler = if !cli.disable_vision
+        && (monitor_ids.len() > 1 || cli.ocr_max_frames_per_minute.is_some())
+    {
+        let ocr_engine: OcrEngine = cli.ocr_engine.clone().into();
+        let scheduler = Arc::new(OcrScheduler::new(
+            OcrSchedulerConfig {
+                max_frames_per_minute: cli.ocr_max_frames_per_minute,
+                ..Default::default()
+            },
+            ocr_engine,
+            languages.clone(),
+        ));
+        let _guard = vision_handle.enter();
+        scheduler.spawn();
+        Some(scheduler)
+    } else {
+        None
+    };
+    let ocr_scheduler_clone = ocr_scheduler.clone();
+    let media_volume_clone = media_volume.clone();
+
+    #[cfg(feature = "llm")]
+    debug!("LLM initializing");
+
+    #[cfg(feature = "llm")]
+    let llm = {
+        match cli.enable_llm {
+            true => Some(Arc::new(std::sync::Mutex::new(screenpipe_core::LLM::new(
+                screenpipe_core::ModelName::Llama,
+            )?))),
+            false => None,
+        }
+    };
+
+    #[cfg(feature = "llm")]
+    debug!("LLM initialized");
+
+    // Corrects the OCR of each window before it is written, the raw text is kept
+    let ocr_corrector = if cli.ocr_correction && !cli.disable_vision {
+        let corrector = load_ocr_corrector(&cli, &local_data_dir)?;
+        #[cfg(feature = "llm")]
+        let corrector = match (&llm, cli.ocr_correction_llm) {
+            (Some(llm), true) => corrector.with_model(llm_correction_model(llm.clone())),
+            _ => corrector,
+        };
+        #[cfg(not(feature = "llm"))]
+        if cli.ocr_correction_llm {
+            warn!("--ocr-correction-llm needs a build with the llm feature, only the dictionary pass runs");
+        }
+        Some(Arc::new(corrector))
+    } else {
+        None
+    };
+
     let handle = {
         let runtime = &tokio::runtime::Handle::current();
         runtime.spawn(async move {
@@ -458,6 +887,10 @@ async fn main() -> anyhow::Result<()> {
                     cli.vad_sensitivity.clone(),
                     languages.clone(),
                     cli.capture_unfocused_windows,
+                    ocr_scheduler_clone.clone(),
+                    Some(media_volume_clone.clone()),
+                    ocr_corrector.clone(),
+                    events_clone.clone(),
                 );
 
                 let result = tokio::select! {
@@ -479,21 +912,6 @@ async fn main() -> anyhow::Result<()> {
     };
 
     let local_data_dir_clone_2 = local_data_dir_clone.clone();
-    #[cfg(feature = "llm")]
-    debug!("LLM initializing");
-
-    #[cfg(feature = "llm")]
-    let llm = {
-        match cli.enable_llm {
-            true => Some(screenpipe_core::LLM::new(
-                screenpipe_core::ModelName::Llama,
-            )?),
-            false => None,
-        }
-    };
-
-    #[cfg(feature = "llm")]
-    debug!("LLM initialized");
 
     let api_plugin = |req: &axum::http::Request<axum::body::Body>| {
         if req.uri().path() == "/search" {
@@ -510,6 +928,14 @@ async fn main() -> anyhow::Result<()> {
         cli.disable_vision,
         cli.disable_audio,
         cli.enable_ui_monitoring,
+        ocr_scheduler,
+        Some(media_volume),
+        archiver,
+        retention,
+        pipe_scheduler,
+        sessions,
+        telemetry,
+        events,
     );
 
     // print screenpipe in gradient
@@ -538,6 +964,7 @@ async fn main() -> anyhow::Result<()> {
         format!("{} seconds", cli.video_chunk_duration)
     );
     println!("│ port                │ {:<34} │", cli.port);
+    println!("│ headless            │ {:<34} │", headless);
     println!("│ audio disabled      │ {:<34} │", cli.disable_audio);
     println!("│ vision disabled     │ {:<34} │", cli.disable_vision);
     println!(
@@ -560,11 +987,27 @@ async fn main() -> anyhow::Result<()> {
         "│ data directory      │ {:<34} │",
         local_data_dir_clone.display()
     );
+    println!(
+        "│ media directory     │ {:<34} │",
+        format_cell(&storage.media.path.display().to_string(), VALUE_WIDTH)
+    );
+    println!(
+        "│ storage mode        │ {:<34} │",
+        storage_mode().get().as_str()
+    );
     println!("│ debug mode          │ {:<34} │", cli.debug);
     println!("│ telemetry           │ {:<34} │", !cli.disable_telemetry);
     println!("│ local llm           │ {:<34} │", cli.enable_llm);
 
     println!("│ use pii removal     │ {:<34} │", cli.use_pii_removal);
+    println!(
+        "│ ocr correction      │ {:<34} │",
+        match (cli.ocr_correction, cli.ocr_correction_llm) {
+            (false, _) => "off",
+            (true, false) => "dictionary",
+            (true, true) => "dictionary + llm",
+        }
+    );
     println!(
         "│ ignored windows     │ {:<34} │",
         format_cell(&format!("{:?}", &ignored_windows_clone), VALUE_WIDTH)
@@ -575,6 +1018,10 @@ async fn main() -> anyhow::Result<()> {
     );
     println!("│ ui monitoring       │ {:<34} │", cli.enable_ui_monitoring);
     println!("│ frame cache         │ {:<34} │", cli.enable_frame_cache);
+    println!(
+        "│ watch folders       │ {:<34} │",
+        format_cell(&format!("{:?}", &cli.watch_folders), VALUE_WIDTH)
+    );
 
     const VALUE_WIDTH: usize = 34;
 
@@ -776,8 +1223,8 @@ async fn main() -> anyhow::Result<()> {
         });
     }
 
-    let ctrl_c_future = signal::ctrl_c();
-    pin_mut!(ctrl_c_future);
+    let shutdown_signal = shutdown_signal();
+    pin_mut!(shutdown_signal);
 
     // only in beta and on macos
     #[cfg(feature = "beta")]
@@ -833,6 +1280,33 @@ async fn main() -> anyhow::Result<()> {
         });
     }
 
+    if !cli.watch_folders.is_empty() {
+        let watch_folder = Arc::new(WatchFolder::new(
+            db.clone(),
+            WatchFolderConfig {
+                dirs: cli.watch_folders.clone(),
+                max_file_size: cli.watch_folder_max_file_mb * 1024 * 1024,
+                extraction_timeout: Duration::from_secs(cli.watch_folder_timeout_secs),
+                ocr_engine: Some(Arc::new(ocr_engine_clone.clone().into())),
+                languages: languages_clone.clone(),
+            },
+        ));
+        let shutdown_tx_clone = shutdown_tx.clone();
+        tokio::spawn(async move {
+            let mut shutdown_rx = shutdown_tx_clone.subscribe();
+            tokio::select! {
+                result = watch_folder.run() => {
+                    if let Err(e) = result {
+                        error!("watch folders stopped: {}", e);
+                    }
+                }
+                _ = shutdown_rx.recv() => {
+                    info!("received shutdown signal, stopping watch folders");
+                }
+            }
+        });
+    }
+
     tokio::select! {
         _ = handle => info!("recording completed"),
         result = &mut server_future => {
@@ -841,21 +1315,331 @@ async fn main() -> anyhow::Result<()> {
                 Err(e) => error!("server stopped with error: {:?}", e),
             }
         }
-        _ = ctrl_c_future => {
-            info!("received ctrl+c, initiating shutdown");
+        signal = shutdown_signal => {
+            info!("received {}, initiating shutdown", signal);
             let _ = shutdown_tx.send(());
         }
     }
 
+    // Pipes would outlive screenpipe otherwise
+    pipe_manager.shutdown().await;
     h.shutdown();
     info!("shutdown complete");
 
     Ok(())
 }
 
+/// Resolves with the name of the signal asking screenpipe to stop: ctrl+c, or SIGTERM on
+/// unix, which service managers send.
+async fn shutdown_signal() -> &'static str {
+    #[cfg(unix)]
+    {
+        let mut terminate = match signal::unix::signal(signal::unix::SignalKind::terminate()) {
+            Ok(terminate) => terminate,
+            Err(e) => {
+                warn!("failed to listen for SIGTERM: {}", e);
+                let _ = signal::ctrl_c().await;
+                return "ctrl+c";
+            }
+        };
+        tokio::select! {
+            _ = signal::ctrl_c() => "ctrl+c",
+            _ = terminate.recv() => "SIGTERM",
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = signal::ctrl_c().await;
+        "ctrl+c"
+    }
+}
+
+async fn handle_storage_command(
+    subcommand: StorageCommand,
+    base_dir: &Path,
+    storage: &StorageDirs,
+) -> anyhow::Result<()> {
+    match subcommand {
+        StorageCommand::Show => {
+            let mut locations = vec![
+                ("db", &storage.db.path, storage.db.available),
+                ("media", &storage.media.path, storage.media.available),
+                ("models", &storage.models_dir, true),
+            ];
+            if let Some(archive) = &storage.archive {
+                locations.push(("archive", &archive.path, archive.available));
+            }
+            for (kind, path, available) in locations {
+                let status = if available { "" } else { " (unavailable)" };
+                println!("{:<7} {}{}", kind, path.display(), status);
+            }
+            if let Some(days) = storage.archive_after_days() {
+                println!("media older than {} days is archived", days);
+            }
+        }
+        StorageCommand::Move { kind, destination } => {
+            let kind: StorageKind = kind.into();
+            let base = base_dir.to_path_buf();
+            println!("copying {} storage to {}...", kind, destination);
+            let pending = tokio::task::spawn_blocking(move || {
+                copy_storage(&base, kind, Path::new(&destination))
+            })
+            .await??;
+            println!(
+                "copied and verified {} files ({:.1} MB)",
+                pending.files.len(),
+                pending.bytes as f64 / 1_048_576.0
+            );
+
+            if kind == StorageKind::Media || kind == StorageKind::Archive {
+                let db = DatabaseManager::new(&storage.db_path()?.to_string_lossy()).await?;
+                let updated = db
+                    .rebase_media_paths(&path_prefix(&pending.from), &path_prefix(&pending.to))
+                    .await?;
+                println!("updated {} media paths in the database", updated);
+            }
+
+            pending.switch_config(base_dir)?;
+            pending.remove_originals()?;
+            println!("{} storage now lives at {}", kind, pending.to.display());
+        }
+        StorageCommand::Archive {
+            to,
+            older_than_days,
+            dry_run,
+        } => {
+            if older_than_days == Some(0) {
+                return Err(anyhow::anyhow!("--older-than-days must be at least 1"));
+            }
+            // A dry run leaves the config alone
+            let config = if dry_run {
+                StorageConfig::load(base_dir)?
+            } else {
+                configure_archive(base_dir, to.as_deref().map(Path::new), older_than_days)?
+            };
+            let after_days = older_than_days
+                .or(config.archive_after_days)
+                .ok_or_else(|| {
+                    anyhow::anyhow!("no archive age configured, pass --older-than-days <days>")
+                })?;
+            let (archive_dir, volume_id) = match &to {
+                Some(to) if dry_run => (Path::new(to).join("screenpipe").join("archive"), None),
+                _ if config.archive_dir.is_some() => {
+                    let archive = config.resolve(base_dir, StorageKind::Archive);
+                    (archive.path, archive.volume_id)
+                }
+                _ => {
+                    return Err(anyhow::anyhow!(
+                        "no archive directory configured, pass --to <dir>"
+                    ))
+                }
+            };
+            let db = Arc::new(DatabaseManager::new(&storage.db_path()?.to_string_lossy()).await?);
+            let archiver = Archiver::new(
+                db,
+                storage.media.path.clone(),
+                archive_dir.clone(),
+                volume_id,
+                Some(after_days),
+            );
+
+            if dry_run {
+                let plan = archiver.plan(Utc::now()).await?;
+                for chunk in &plan.chunks {
+                    println!(
+                        "{} -> {}",
+                        chunk.file_path,
+                        archiver.archive_path(chunk).display()
+                    );
+                }
+                println!(
+                    "would archive {} files ({:.1} MB) older than {} days to {}",
+                    plan.chunks.len(),
+                    plan.bytes as f64 / 1_048_576.0,
+                    after_days,
+                    archive_dir.display()
+                );
+                if plan.missing > 0 {
+                    println!("{} old chunks have no file left to move", plan.missing);
+                }
+                return Ok(());
+            }
+
+            println!(
+                "archiving media older than {} days to {}...",
+                after_days,
+                archive_dir.display()
+            );
+            let summary = archiver.archive(Utc::now()).await?;
+            println!(
+                "archived and verified {} files ({:.1} MB)",
+                summary.moved,
+                summary.bytes as f64 / 1_048_576.0
+            );
+            if summary.resumed > 0 {
+                println!(
+                    "removed {} originals left by an interrupted run",
+                    summary.resumed
+                );
+            }
+            if summary.failed > 0 {
+                println!(
+                    "{} files failed and stay in place, see the log",
+                    summary.failed
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Removes a deleted pipe's content types and records when no server is running.
+async fn handle_session_command(command: SessionCommand) -> anyhow::Result<()> {
+    let client = reqwest::Client::new();
+    let server_url = "http://localhost";
+
+    match command {
+        SessionCommand::Start {
+            name,
+            auto_export,
+            port,
+        } => {
+            let auto_export = auto_export.map(ExportFormat::from);
+            let session: CaptureSession = session_api(
+                client
+                    .post(format!("{}:{}/v1/sessions/start", server_url, port))
+                    .json(&json!({ "name": name, "auto_export": auto_export })),
+                port,
+            )
+            .await?;
+            println!("session '{}' started", session.name);
+            if let Some(format) = session.auto_export {
+                println!("it is exported as {} when it stops", format.as_str());
+            }
+        }
+        SessionCommand::Stop { port } => {
+            let session: CaptureSession = session_api(
+                client.post(format!("{}:{}/v1/sessions/stop", server_url, port)),
+                port,
+            )
+            .await?;
+            let minutes = session
+                .ended_at
+                .map(|ended_at| (ended_at - session.started_at).num_minutes())
+                .unwrap_or_default();
+            println!(
+                "session '{}' stopped after {}h {:02}m",
+                session.name,
+                minutes / 60,
+                minutes % 60
+            );
+            if let Some(path) = session.export_path {
+                println!("exported to {}", path);
+            } else if session.auto_export.is_some() {
+                println!("export failed, see the server logs");
+            }
+        }
+        SessionCommand::List { name, output, port } => {
+            let mut request = client.get(format!("{}:{}/v1/sessions", server_url, port));
+            if let Some(name) = &name {
+                request = request.query(&[("name", name)]);
+            }
+            let sessions: Vec<CaptureSession> = session_api(request, port).await?;
+            match output {
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&sessions)?),
+                OutputFormat::Text => {
+                    for session in sessions {
+                        let ended = match session.ended_at {
+                            Some(ended_at) => ended_at.format("%Y-%m-%d %H:%M").to_string(),
+                            None => "open".to_string(),
+                        };
+                        println!(
+                            "  {}  {} -> {}  {}",
+                            session.id,
+                            session.started_at.format("%Y-%m-%d %H:%M"),
+                            ended,
+                            session.name
+                        );
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Sends a sessions api `request` to the server on `port`. Errors carry the detail of
+/// the problem the server answered with.
+async fn session_api<T: serde::de::DeserializeOwned>(
+    request: reqwest::RequestBuilder,
+    port: u16,
+) -> anyhow::Result<T> {
+    let response = request
+        .send()
+        .await
+        .map_err(|_| anyhow::anyhow!("server not running on port {}", port))?;
+    let status = response.status();
+    let body: Value = response.json().await?;
+    if !status.is_success() {
+        let detail = body
+            .get("detail")
+            .and_then(Value::as_str)
+            .unwrap_or("request failed");
+        anyhow::bail!("{}", detail);
+    }
+    Ok(serde_json::from_value(body)?)
+}
+
+/// Puts the text, image or link of a frame, from the server on `port`, on the
+/// clipboard.
+async fn handle_copy_command(id: i64, what: CopyWhat, port: u16) -> anyhow::Result<()> {
+    let response = reqwest::Client::new()
+        .get(format!("http://localhost:{}/v1/content/{}/copy", port, id))
+        .query(&[("what", what.as_str())])
+        .send()
+        .await
+        .map_err(|_| anyhow::anyhow!("server not running on port {}", port))?;
+    if !response.status().is_success() {
+        let body: Value = response.json().await.unwrap_or_default();
+        let detail = body
+            .get("detail")
+            .and_then(Value::as_str)
+            .unwrap_or("request failed");
+        anyhow::bail!("{}", detail);
+    }
+    let content = match what {
+        CopyWhat::Text => ClipboardContent::Text(response.text().await?),
+        CopyWhat::Image => ClipboardContent::Image(response.bytes().await?.to_vec()),
+        CopyWhat::Link => {
+            let link: CopyLink = response.json().await?;
+            println!("{}", link.deep_link);
+            println!("without the app: {}", link.http_url);
+            ClipboardContent::Text(link.deep_link)
+        }
+    };
+    if cfg!(target_os = "linux") {
+        println!(
+            "keeping the clipboard for up to {}s, until a clipboard manager or another copy takes it over",
+            CLIPBOARD_HOLD.as_secs()
+        );
+    }
+    tokio::task::spawn_blocking(move || copy_to_clipboard(&content, CLIPBOARD_HOLD)).await??;
+    println!("copied the {} of frame {}", what.as_str(), id);
+    Ok(())
+}
+
+async fn delete_local_pipe_content(
+    storage: &StorageDirs,
+    pipe_id: &str,
+) -> anyhow::Result<(u64, u64)> {
+    let db = DatabaseManager::new(&storage.db_path()?.to_string_lossy()).await?;
+    Ok(db.delete_pipe_content(pipe_id).await?)
+}
+
 async fn handle_pipe_command(
     command: PipeCommand,
     pipe_manager: &Arc<PipeManager>,
+    storage: &StorageDirs,
 ) -> anyhow::Result<()> {
     let client = reqwest::Client::new();
     let server_url = "http://localhost";
@@ -864,7 +1648,7 @@ async fn handle_pipe_command(
         PipeCommand::List { output, port } => {
             let server_url = format!("{}:{}", server_url, port);
             let pipes = match client
-                .get(&format!("{}/pipes/list", server_url))
+                .get(&format!("{}/v1/pipes/list", server_url))
                 .send()
                 .await
             {
@@ -906,10 +1690,43 @@ async fn handle_pipe_command(
             }
         }
 
-        PipeCommand::Download { url, output, port } => {
+        PipeCommand::Download {
+            url,
+            locked,
+            force,
+            git_ref,
+            checksums,
+            verify_integrity,
+            if_exists,
+            on_conflict,
+            skip_validation,
+            output,
+            port,
+        } => {
+            let overwrite = OverwritePolicy::from(if_exists);
+            let on_conflict = ConflictPolicy::from(on_conflict);
+            let checksums: Option<HashMap<String, String>> = match checksums {
+                Some(path) => {
+                    let content = std::fs::read_to_string(&path)?;
+                    Some(serde_json::from_str(&content).map_err(|e| {
+                        anyhow::anyhow!("invalid checksums {}: {}", path.display(), e)
+                    })?)
+                }
+                None => None,
+            };
             match client
-                .post(&format!("{}:{}/pipes/download", server_url, port))
-                .json(&json!({ "url": url }))
+                .post(&format!("{}:{}/v1/pipes/download", server_url, port))
+                .json(&json!({
+                    "url": url,
+                    "locked": locked,
+                    "force": force,
+                    "ref": git_ref,
+                    "checksums": checksums,
+                    "verify_integrity": verify_integrity,
+                    "overwrite": overwrite,
+                    "on_conflict": on_conflict,
+                    "skip_validation": skip_validation,
+                }))
                 .send()
                 .await
             {
@@ -923,7 +1740,23 @@ async fn handle_pipe_command(
                         ),
                     }
                 }
-                _ => match pipe_manager.download_pipe(&url).await {
+                _ => match pipe_manager
+                    .download_pipe_with(
+                        &url,
+                        DownloadOptions {
+                            locked,
+                            force,
+                            git_ref,
+                            checksums,
+                            verify_integrity,
+                            overwrite,
+                            on_conflict,
+                            skip_validation,
+                            ..Default::default()
+                        },
+                    )
+                    .await
+                {
                     Ok(pipe_id) => match output {
                         OutputFormat::Json => println!(
                             "{}",
@@ -958,7 +1791,7 @@ async fn handle_pipe_command(
 
         PipeCommand::Info { id, output, port } => {
             let info = match client
-                .get(&format!("{}:{}/pipes/info/{}", server_url, port, id))
+                .get(&format!("{}:{}/v1/pipes/info/{}", server_url, port, id))
                 .send()
                 .await
             {
@@ -977,9 +1810,36 @@ async fn handle_pipe_command(
                 OutputFormat::Text => println!("pipe info: {:?}", info),
             }
         }
+        PipeCommand::Diff { id, output } => {
+            let diff = pipe_manager.diff_pipe(&id).await?;
+            match output {
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&diff)?),
+                OutputFormat::Text => {
+                    if diff.is_empty() {
+                        println!("pipe {} has the files of its source", id);
+                    }
+                    for path in &diff.added {
+                        println!("added    {}", path);
+                    }
+                    for path in &diff.removed {
+                        println!("removed  {}", path);
+                    }
+                    for (path, file) in &diff.modified {
+                        if file.binary {
+                            println!("modified {} (binary)", path);
+                        } else {
+                            println!("modified {}", path);
+                        }
+                    }
+                    for (_, file) in &diff.modified {
+                        print!("{}", file.unified);
+                    }
+                }
+            }
+        }
         PipeCommand::Enable { id, port } => {
             match client
-                .post(&format!("{}:{}/pipes/enable", server_url, port))
+                .post(&format!("{}:{}/v1/pipes/enable", server_url, port))
                 .json(&json!({ "pipe_id": id }))
                 .send()
                 .await
@@ -998,7 +1858,7 @@ async fn handle_pipe_command(
 
         PipeCommand::Disable { id, port } => {
             match client
-                .post(&format!("{}:{}/pipes/disable", server_url, port))
+                .post(&format!("{}:{}/v1/pipes/disable", server_url, port))
                 .json(&json!({ "pipe_id": id }))
                 .send()
                 .await
@@ -1020,7 +1880,7 @@ async fn handle_pipe_command(
                 .map_err(|e| anyhow::anyhow!("invalid json: {}", e))?;
 
             match client
-                .post(&format!("{}:{}/pipes/update", server_url, port))
+                .post(&format!("{}:{}/v1/pipes/update", server_url, port))
                 .json(&json!({
                     "pipe_id": id,
                     "config": config
@@ -1038,7 +1898,12 @@ async fn handle_pipe_command(
             }
         }
 
-        PipeCommand::Delete { id, yes, port } => {
+        PipeCommand::Delete {
+            id,
+            yes,
+            keep_content,
+            port,
+        } => {
             if !yes {
                 print!("are you sure you want to delete pipe '{}'? [y/N] ", id);
                 std::io::stdout().flush()?;
@@ -1051,7 +1916,8 @@ async fn handle_pipe_command(
             }
 
             match client
-                .delete(&format!("{}:{}/pipes/delete/{}", server_url, port, id))
+                .post(&format!("{}:{}/v1/pipes/delete", server_url, port))
+                .json(&json!({ "pipe_id": id, "keep_content": keep_content }))
                 .send()
                 .await
             {
@@ -1059,7 +1925,20 @@ async fn handle_pipe_command(
                     println!("pipe '{}' deleted from running server", id);
                 }
                 _ => match pipe_manager.delete_pipe(&id).await {
-                    Ok(_) => println!("pipe '{}' deleted from local files", id),
+                    Ok(_) => {
+                        println!("pipe '{}' deleted from local files", id);
+                        if keep_content {
+                            println!("its indexed content was kept");
+                        } else {
+                            match delete_local_pipe_content(storage, &id).await {
+                                Ok((types, records)) => println!(
+                                    "removed {} content types and {} records it indexed",
+                                    types, records
+                                ),
+                                Err(e) => println!("failed to remove its indexed content: {}", e),
+                            }
+                        }
+                    }
                     Err(e) => println!("failed to delete pipe: {}", e),
                 },
             }
@@ -1078,7 +1957,7 @@ async fn handle_pipe_command(
             }
 
             match client
-                .post(&format!("{}:{}/pipes/purge", server_url, port))
+                .post(&format!("{}:{}/v1/pipes/purge", server_url, port))
                 .send()
                 .await
             {
@@ -1091,10 +1970,242 @@ async fn handle_pipe_command(
                 },
             }
         }
+
+        PipeCommand::Apply {
+            manifest,
+            dry_run,
+            atomic,
+            output,
+            port,
+        } => {
+            let content = std::fs::read_to_string(&manifest)?;
+            // Yaml is a superset of json, so json manifests parse too
+            let manifest: PipeManifest = serde_yaml::from_str(&content)
+                .map_err(|e| anyhow::anyhow!("invalid manifest {}: {}", manifest.display(), e))?;
+
+            let server_url = format!("{}:{}", server_url, port);
+            let server_pipes = match client
+                .get(&format!("{}/v1/pipes/list", server_url))
+                .send()
+                .await
+            {
+                Ok(response) if response.status().is_success() => {
+                    let response: Value = response.json().await?;
+                    Some(serde_json::from_value::<Vec<PipeInfo>>(
+                        response.get("data").cloned().unwrap_or_default(),
+                    )?)
+                }
+                _ => None,
+            };
+            let installed = match &server_pipes {
+                Some(pipes) => pipes.clone(),
+                None => pipe_manager.list_pipes().await,
+            };
+
+            let operations =
+                plan_manifest(&manifest, &installed).map_err(|e| anyhow::anyhow!(e))?;
+            if operations.is_empty() {
+                println!("pipes already match the manifest");
+                return Ok(());
+            }
+            if dry_run {
+                match output {
+                    OutputFormat::Json => {
+                        println!("{}", serde_json::to_string_pretty(&operations)?)
+                    }
+                    OutputFormat::Text => {
+                        println!("would run {} operations:", operations.len());
+                        for operation in &operations {
+                            println!("  {}", serde_json::to_string(operation)?);
+                        }
+                    }
+                }
+                return Ok(());
+            }
+            if server_pipes.is_none() {
+                anyhow::bail!(
+                    "server not running on port {}, start it to apply the manifest or use --dry-run",
+                    port
+                );
+            }
+
+            let report: BatchReport = client
+                .post(&format!("{}/v1/pipes/batch", server_url))
+                .json(&json!({ "operations": operations, "atomic": atomic }))
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            match output {
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+                OutputFormat::Text => {
+                    for result in &report.results {
+                        let status = match result.status {
+                            OperationStatus::Ok => "ok",
+                            OperationStatus::Failed => "failed",
+                            OperationStatus::Skipped => "skipped",
+                            OperationStatus::RolledBack => "rolled back",
+                        };
+                        println!(
+                            "  {} {} {}: {}{}",
+                            result.index,
+                            result.op,
+                            result.pipe_id.as_deref().unwrap_or("-"),
+                            status,
+                            result
+                                .detail
+                                .as_ref()
+                                .map(|d| format!(" ({})", d))
+                                .unwrap_or_default()
+                        );
+                    }
+                    if report.rolled_back {
+                        println!("a step failed, installs from this run were rolled back");
+                    }
+                }
+            }
+            if !report.success {
+                anyhow::bail!("the manifest was only partly applied");
+            }
+        }
+
+        PipeCommand::Lock { path } => {
+            let lock = generate_lock(pipe_manager).await?;
+            std::fs::write(&path, serde_json::to_string_pretty(&lock)? + "\n")?;
+            println!("locked {} pipes in {}", lock.pipes.len(), path.display());
+        }
+
+        PipeCommand::Sync {
+            lock,
+            prune,
+            force,
+            dry_run,
+            report: report_path,
+            output,
+            port,
+        } => {
+            let content = std::fs::read_to_string(&lock)?;
+            let lock: PipeLock = serde_json::from_str(&content)
+                .map_err(|e| anyhow::anyhow!("invalid lock {}: {}", lock.display(), e))?;
+
+            if dry_run {
+                let installed = installed_pipes(pipe_manager).await?;
+                let steps =
+                    plan_sync(&lock, &installed, prune, force).map_err(|e| anyhow::anyhow!(e))?;
+                match output {
+                    OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&steps)?),
+                    OutputFormat::Text => {
+                        for step in &steps {
+                            println!(
+                                "  {} {}{}",
+                                sync_action_name(step.action),
+                                step.pipe_id,
+                                if step.config_differs {
+                                    " (config differs)"
+                                } else {
+                                    ""
+                                }
+                            );
+                        }
+                    }
+                }
+                return Ok(());
+            }
+
+            // The server runs the pipes, so it syncs them when it is up
+            let server_url = format!("{}:{}", server_url, port);
+            let report: SyncReport = if client
+                .get(&format!("{}/v1/health", server_url))
+                .send()
+                .await
+                .is_ok()
+            {
+                session_api(
+                    client
+                        .post(&format!("{}/v1/pipes/sync", server_url))
+                        .json(&json!({ "lock": lock, "prune": prune, "force": force })),
+                    port,
+                )
+                .await?
+            } else {
+                sync_pipes(pipe_manager, &lock, prune, force)
+                    .await
+                    .map_err(|e| anyhow::anyhow!(e))?
+            };
+            if let Some(report_path) = &report_path {
+                std::fs::write(report_path, serde_json::to_string_pretty(&report)?)?;
+            }
+            match output {
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+                OutputFormat::Text => {
+                    for result in &report.results {
+                        let status = match result.status {
+                            OperationStatus::Ok => "ok",
+                            OperationStatus::Failed => "failed",
+                            OperationStatus::Skipped => "skipped",
+                            OperationStatus::RolledBack => "rolled back",
+                        };
+                        println!(
+                            "  {} {}: {}{}{}",
+                            sync_action_name(result.action),
+                            result.pipe_id,
+                            status,
+                            result
+                                .detail
+                                .as_ref()
+                                .map(|d| format!(" ({})", d))
+                                .unwrap_or_default(),
+                            if result.config_differs {
+                                ", config differs"
+                            } else {
+                                ""
+                            }
+                        );
+                    }
+                }
+            }
+            if !report.success {
+                anyhow::bail!("the pipes don't match the lock");
+            }
+        }
+
+        PipeCommand::Repl { pipe, port } => {
+            let server_url = format!("{}:{}", server_url, port);
+            if client
+                .get(&format!("{}/v1/health", server_url))
+                .send()
+                .await
+                .is_err()
+            {
+                println!(
+                    "server not running on port {}, api calls fail until it is started",
+                    port
+                );
+            }
+            let session = pipe_manager
+                .repl_session(pipe.as_deref(), &server_url)
+                .await?;
+            let status = session.run().await?;
+            if !status.success() {
+                anyhow::bail!("repl exited with {}", status);
+            }
+        }
     }
     Ok(())
 }
 
+fn sync_action_name(action: SyncAction) -> &'static str {
+    match action {
+        SyncAction::Install => "install",
+        SyncAction::Update => "update",
+        SyncAction::Remove => "remove",
+        SyncAction::Unchanged => "unchanged",
+        SyncAction::Conflict => "conflict",
+        SyncAction::Unlisted => "unlisted",
+    }
+}
+
 async fn ensure_screenpipe_in_path() -> anyhow::Result<()> {
     use tokio::process::Command;
 
@@ -1,9 +1,19 @@
-use clap::{Parser, Subcommand};
-use screenpipe_audio::{vad_engine::VadSensitivity, AudioTranscriptionEngine as CoreAudioTranscriptionEngine};
-use screenpipe_vision::utils::OcrEngine as CoreOcrEngine;
+use crate::copy::CopyWhat;
+use crate::db_types::ExportFormat;
+use crate::storage::StorageKind;
+use crate::storage_mode::StorageMode;
 use clap::ValueEnum;
+use clap::{Parser, Subcommand};
 use screenpipe_audio::vad_engine::VadEngineEnum;
+use screenpipe_audio::{
+    vad_engine::VadSensitivity, AudioTranscriptionEngine as CoreAudioTranscriptionEngine,
+};
+use screenpipe_core::clock::TimestampSource;
+use screenpipe_core::pipe_sandbox::SandboxPolicy;
 use screenpipe_core::Language;
+use screenpipe_core::{ConflictPolicy, OverwritePolicy};
+use screenpipe_vision::utils::OcrEngine as CoreOcrEngine;
+use std::path::PathBuf;
 
 #[derive(Clone, Debug, ValueEnum, PartialEq)]
 pub enum CliAudioTranscriptionEngine {
@@ -90,6 +100,141 @@ impl From<CliVadSensitivity> for VadSensitivity {
     }
 }
 
+#[derive(Clone, Debug, ValueEnum, PartialEq)]
+pub enum CliStorageKind {
+    Db,
+    Media,
+    Models,
+    Archive,
+}
+
+impl From<CliStorageKind> for StorageKind {
+    fn from(cli_kind: CliStorageKind) -> Self {
+        match cli_kind {
+            CliStorageKind::Db => StorageKind::Db,
+            CliStorageKind::Media => StorageKind::Media,
+            CliStorageKind::Models => StorageKind::Models,
+            CliStorageKind::Archive => StorageKind::Archive,
+        }
+    }
+}
+
+#[derive(Clone, Debug, ValueEnum, PartialEq)]
+pub enum CliOverwritePolicy {
+    Overwrite,
+    Skip,
+    Error,
+}
+
+impl From<CliOverwritePolicy> for OverwritePolicy {
+    fn from(cli_policy: CliOverwritePolicy) -> Self {
+        match cli_policy {
+            CliOverwritePolicy::Overwrite => OverwritePolicy::Overwrite,
+            CliOverwritePolicy::Skip => OverwritePolicy::Skip,
+            CliOverwritePolicy::Error => OverwritePolicy::ErrorIfExists,
+        }
+    }
+}
+
+#[derive(Clone, Debug, ValueEnum, PartialEq)]
+pub enum CliConflictPolicy {
+    Error,
+    Rename,
+    Replace,
+}
+
+impl From<CliConflictPolicy> for ConflictPolicy {
+    fn from(cli_policy: CliConflictPolicy) -> Self {
+        match cli_policy {
+            CliConflictPolicy::Error => ConflictPolicy::Error,
+            CliConflictPolicy::Rename => ConflictPolicy::Rename,
+            CliConflictPolicy::Replace => ConflictPolicy::Replace,
+        }
+    }
+}
+
+#[derive(Clone, Debug, ValueEnum, PartialEq)]
+pub enum CliSandboxPolicy {
+    None,
+    Standard,
+    Strict,
+}
+
+impl From<CliSandboxPolicy> for SandboxPolicy {
+    fn from(cli_policy: CliSandboxPolicy) -> Self {
+        match cli_policy {
+            CliSandboxPolicy::None => SandboxPolicy::None,
+            CliSandboxPolicy::Standard => SandboxPolicy::Standard,
+            CliSandboxPolicy::Strict => SandboxPolicy::Strict,
+        }
+    }
+}
+
+#[derive(Clone, Debug, ValueEnum, PartialEq)]
+pub enum CliTimestampSource {
+    System,
+    Monotonic,
+}
+
+impl From<CliTimestampSource> for TimestampSource {
+    fn from(cli_source: CliTimestampSource) -> Self {
+        match cli_source {
+            CliTimestampSource::System => TimestampSource::System,
+            CliTimestampSource::Monotonic => TimestampSource::Monotonic,
+        }
+    }
+}
+
+#[derive(Clone, Debug, ValueEnum, PartialEq)]
+pub enum CliStorageMode {
+    Full,
+    TextOnly,
+    TextPlusThumbnails,
+}
+
+impl From<CliStorageMode> for StorageMode {
+    fn from(cli_mode: CliStorageMode) -> Self {
+        match cli_mode {
+            CliStorageMode::Full => StorageMode::Full,
+            CliStorageMode::TextOnly => StorageMode::TextOnly,
+            CliStorageMode::TextPlusThumbnails => StorageMode::TextPlusThumbnails,
+        }
+    }
+}
+
+#[derive(Clone, Debug, ValueEnum, PartialEq)]
+pub enum CliExportFormat {
+    #[clap(name = "md")]
+    Markdown,
+    Json,
+}
+
+impl From<CliExportFormat> for ExportFormat {
+    fn from(cli_format: CliExportFormat) -> Self {
+        match cli_format {
+            CliExportFormat::Markdown => ExportFormat::Markdown,
+            CliExportFormat::Json => ExportFormat::Json,
+        }
+    }
+}
+
+#[derive(Clone, Debug, ValueEnum, PartialEq)]
+pub enum CliCopyWhat {
+    Text,
+    Image,
+    Link,
+}
+
+impl From<CliCopyWhat> for CopyWhat {
+    fn from(cli_what: CliCopyWhat) -> Self {
+        match cli_what {
+            CliCopyWhat::Text => CopyWhat::Text,
+            CliCopyWhat::Image => CopyWhat::Image,
+            CliCopyWhat::Link => CopyWhat::Link,
+        }
+    }
+}
+
 #[derive(Parser)]
 #[command(
     author, 
@@ -239,6 +384,120 @@ pub struct Cli {
     #[arg(long, default_value_t = false)]
     pub capture_unfocused_windows: bool,
 
+    /// Grant pipes every permission they request without waiting for a prompt (headless setups)
+    #[arg(long, default_value_t = false)]
+    pub auto_approve_pipes: bool,
+
+    /// Send each pipe's http traffic through a localhost proxy that counts the bytes per
+    /// host and refuses hosts the pipe didn't declare in its pipe.json `hosts`
+    #[arg(long, default_value_t = false)]
+    pub pipe_network_proxy: bool,
+
+    /// Refuse to install or run pipes whose pipe.json has keys the manifest schema
+    /// doesn't know, instead of logging a warning
+    #[arg(long, default_value_t = false)]
+    pub strict_manifests: bool,
+
+    /// Seconds pipes get to exit after SIGTERM when screenpipe shuts down, before they
+    /// are killed
+    #[arg(long, default_value_t = 5)]
+    pub pipe_shutdown_grace_secs: u64,
+
+    /// MB of memory each pipe may use, 0 for no limit. A pipe's pipe.json
+    /// `memory_limit_mb` takes its place, and next.js pipes are only limited by theirs
+    #[arg(long, default_value_t = 512)]
+    pub pipe_memory_limit_mb: u64,
+
+    /// Syscalls deno pipes may make on linux, enforced with seccomp: `standard` allows
+    /// what deno needs to run a script, `strict` also takes away starting processes and
+    /// serving sockets. A stricter pipe.json `sandbox` takes its place
+    #[arg(long, value_enum, default_value_t = CliSandboxPolicy::None)]
+    pub pipe_sandbox: CliSandboxPolicy,
+
+    /// Accept trailing commas in the pipe.json and package.json of pipes, as hand
+    /// edited files often have
+    #[arg(long, default_value_t = false)]
+    pub lenient_pipe_json: bool,
+
+    /// Cap on OCR'd frames per minute across all monitors. Frames beyond the cap are
+    /// folded into the next OCR of the same window instead of queueing up
+    #[arg(long)]
+    pub ocr_max_frames_per_minute: Option<u32>,
+
+    /// Fix misread words in OCR text before it is stored, against a word list. The text
+    /// the engine read is kept and returned as `raw_text` in search results
+    #[arg(long, default_value_t = false)]
+    pub ocr_correction: bool,
+
+    /// Word list for --ocr-correction, one word per line with an optional count. Can be
+    /// repeated, defaults to /usr/share/dict/words. <data dir>/ocr_dictionary.txt is
+    /// always added when present
+    #[arg(long = "ocr-dictionary", requires = "ocr_correction")]
+    pub ocr_dictionaries: Vec<PathBuf>,
+
+    /// Also run OCR text the engine was unsure of through the local llm, needs
+    /// --enable-llm. Skipped while over --latency-budget
+    #[arg(long, default_value_t = false, requires_all = ["ocr_correction", "enable_llm"])]
+    pub ocr_correction_llm: bool,
+
+    /// Directory to index documents and screenshots from (pdf, text, markdown, images).
+    /// Can be repeated, nothing is watched unless set. Example: --watch-folder ~/Documents/inbox
+    #[arg(long = "watch-folder")]
+    pub watch_folders: Vec<PathBuf>,
+
+    /// Watch folder files larger than this (in MB) are skipped
+    #[arg(long, default_value_t = 50)]
+    pub watch_folder_max_file_mb: u64,
+
+    /// Give up extracting text from a single watch folder file after this many seconds
+    #[arg(long, default_value_t = 60)]
+    pub watch_folder_timeout_secs: u64,
+
+    /// Seconds a frame or audio chunk may take from capture until it is searchable.
+    /// An alert is logged when the p95 stays over it. Example: --latency-budget 10
+    #[arg(long, value_parser = parse_latency_budget)]
+    pub latency_budget: Option<f64>,
+
+    /// While over --latency-budget, lower the capture fps and transcribe with whisper-tiny
+    /// until back under
+    #[arg(long, default_value_t = false, requires = "latency_budget")]
+    pub latency_auto_degrade: bool,
+
+    /// Unload the transcription models after this many minutes without audio to
+    /// transcribe, they are loaded again when needed. Kept in memory when not set
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub model_idle_minutes: Option<u64>,
+
+    /// Unload the transcription models when the machine goes to sleep
+    #[arg(long, default_value_t = false)]
+    pub unload_models_on_sleep: bool,
+
+    /// Clock stamping frames and audio. "monotonic" keeps timestamps from going backwards
+    /// when the system clock is changed or stepped by ntp, "system" uses it as is
+    #[arg(long, value_enum, default_value_t = CliTimestampSource::Monotonic)]
+    pub timestamp_source: CliTimestampSource,
+
+    /// What is kept of captured screens. "text_only" stores the OCR text and metadata
+    /// but never writes pixels to disk, "text_plus_thumbnails" adds a small jpeg per
+    /// frame. Can be changed while recording with POST /storage/mode
+    #[arg(long, value_enum, default_value_t = CliStorageMode::Full)]
+    pub storage_mode: CliStorageMode,
+
+    /// Run as an api, database and pipe host only, e.g. in a container. Screen, audio and
+    /// ui capture are never started. On by itself on linux when no display server is set
+    #[arg(long, default_value_t = false)]
+    pub headless: bool,
+
+    /// Directory capture sessions started with an auto export are written to when they
+    /// stop. Defaults to <data dir>/exports/sessions
+    #[arg(long)]
+    pub session_export_dir: Option<PathBuf>,
+
+    /// A capture session still open after this many minutes is closed, with a warning
+    /// on GET /sessions/events
+    #[arg(long, default_value_t = 240, value_parser = clap::value_parser!(u32).range(1..))]
+    pub session_max_minutes: u32,
+
     #[command(subcommand)]
     pub command: Option<Command>,
 
@@ -246,6 +505,16 @@ pub struct Cli {
 
 
 
+fn parse_latency_budget(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(seconds) if seconds.is_finite() && seconds > 0.0 => Ok(seconds),
+        _ => Err(format!(
+            "invalid latency budget '{}', expected seconds like 10 or 2.5",
+            value
+        )),
+    }
+}
+
 impl Cli {
     pub fn unique_languages(&self) -> Result<Vec<Language>, String> {
         let mut unique_langs = std::collections::HashSet::new();
@@ -256,6 +525,48 @@ impl Cli {
         }
         Ok(unique_langs.into_iter().collect())
     }
+
+    /// Whether capture is skipped, asked for with `--headless` or detected.
+    pub fn is_headless(&self) -> bool {
+        self.headless || !display_server_available()
+    }
+
+    /// Optional features turned on, by flag name, for usage pings.
+    pub fn enabled_features(&self) -> Vec<String> {
+        [
+            ("disable-audio", self.disable_audio),
+            ("disable-vision", self.disable_vision),
+            ("use-pii-removal", self.use_pii_removal),
+            ("enable-llm", self.enable_llm),
+            ("enable-ui-monitoring", self.enable_ui_monitoring),
+            ("enable-frame-cache", self.enable_frame_cache),
+            ("capture-unfocused-windows", self.capture_unfocused_windows),
+            ("auto-approve-pipes", self.auto_approve_pipes),
+            ("pipe-network-proxy", self.pipe_network_proxy),
+            ("strict-manifests", self.strict_manifests),
+            ("lenient-pipe-json", self.lenient_pipe_json),
+            ("ocr-correction", self.ocr_correction),
+            ("watch-folder", !self.watch_folders.is_empty()),
+            ("latency-budget", self.latency_budget.is_some()),
+            ("model-idle-minutes", self.model_idle_minutes.is_some()),
+            ("unload-models-on-sleep", self.unload_models_on_sleep),
+            ("headless", self.headless),
+        ]
+        .into_iter()
+        .filter(|(_, on)| *on)
+        .map(|(name, _)| name.to_string())
+        .collect()
+    }
+}
+
+/// Whether there is a screen to capture. Only linux can tell, a container or a server
+/// there has neither `DISPLAY` nor `WAYLAND_DISPLAY` set.
+pub fn display_server_available() -> bool {
+    if cfg!(target_os = "linux") {
+        std::env::var_os("DISPLAY").is_some() || std::env::var_os("WAYLAND_DISPLAY").is_some()
+    } else {
+        true
+    }
 }
 
 #[derive(Subcommand)]
@@ -273,6 +584,111 @@ pub enum Command {
     },
     /// Run database migrations
     Migrate,
+    /// Create the data directory tree and move files older versions left elsewhere into
+    /// place. Also done on every start
+    Init {
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Text)]
+        output: OutputFormat,
+    },
+    /// Replay recorded frames and audio through the pipeline into a scratch database
+    Replay {
+        /// Export directory, screenpipe data directory or database file to replay
+        #[arg(long)]
+        from: String,
+        /// Playback speed, e.g. 1x, 10x or 0.5x
+        #[arg(long, default_value = "1x", value_parser = crate::replay::parse_speed)]
+        speed: f64,
+        /// Keep the original capture timestamps instead of offsetting them to now
+        #[arg(long, default_value_t = false)]
+        preserve_timestamps: bool,
+    },
+    /// Storage location management (database, media, models and archive)
+    Storage {
+        #[command(subcommand)]
+        subcommand: StorageCommand,
+    },
+    /// Named capture sessions on the running server
+    Session {
+        #[command(subcommand)]
+        subcommand: SessionCommand,
+    },
+    /// Put the text, image or a link of a search result on the clipboard, e.g.
+    /// screenpipe copy 4812 --what image
+    Copy {
+        /// Frame id of the result
+        id: i64,
+        /// What to copy
+        #[arg(long, value_enum, default_value_t = CliCopyWhat::Text)]
+        what: CliCopyWhat,
+        /// Server port
+        #[arg(short = 'p', long, default_value_t = 3030)]
+        port: u16,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum SessionCommand {
+    /// Start a named capture session, e.g. screenpipe session start "ux interview #4".
+    /// Fails while another session is open
+    Start {
+        /// Name to find the session by
+        name: String,
+        /// Export what was captured when the session stops
+        #[arg(long, value_enum)]
+        auto_export: Option<CliExportFormat>,
+        /// Server port
+        #[arg(short = 'p', long, default_value_t = 3030)]
+        port: u16,
+    },
+    /// Stop the open capture session
+    Stop {
+        /// Server port
+        #[arg(short = 'p', long, default_value_t = 3030)]
+        port: u16,
+    },
+    /// List capture sessions, latest first
+    List {
+        /// Only sessions with this name
+        #[arg(long)]
+        name: Option<String>,
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Text)]
+        output: OutputFormat,
+        /// Server port
+        #[arg(short = 'p', long, default_value_t = 3030)]
+        port: u16,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum StorageCommand {
+    /// Show where the database, media and models are stored
+    Show,
+    /// Copy a storage location to a new directory, verify it and switch to it.
+    /// Originals are deleted only after every file is verified
+    Move {
+        /// What to move
+        #[arg(value_enum)]
+        kind: CliStorageKind,
+        /// Destination, e.g. /Volumes/Big. Files go to <destination>/screenpipe/<kind>
+        destination: String,
+    },
+    /// Move media older than the archive age to the archive directory, e.g. a nas.
+    /// Text stays searchable, files are verified before the originals are deleted and
+    /// media with tagged content is never moved
+    Archive {
+        /// Archive directory to use from now on, e.g. /Volumes/nas. Files go to
+        /// <to>/screenpipe/archive
+        #[arg(long)]
+        to: Option<String>,
+        /// Archive media older than this many days, also used by the server from now on
+        #[arg(long)]
+        older_than_days: Option<u32>,
+        /// Only report what would move and how many bytes
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+    },
 }
 
 
@@ -291,6 +707,33 @@ pub enum PipeCommand {
     Download {
         /// URL of the pipe to download
         url: String,
+        /// Keep a github pipe already at the commit its url points to, see its pipe.lock
+        #[arg(long)]
+        locked: bool,
+        /// Download every file of a github pipe, even those the installed copy has unchanged
+        #[arg(long)]
+        force: bool,
+        /// Branch, tag or commit sha to download the pipe at, in place of the one in its url
+        #[arg(long = "ref", value_name = "REF")]
+        git_ref: Option<String>,
+        /// Json file with the sha256 of each file of the pipe, by path, checked in place of
+        /// the `checksums.json` of its source
+        #[arg(long, value_name = "FILE")]
+        checksums: Option<PathBuf>,
+        /// Fail when a file of the installed pipe changed in the download while its version
+        /// stayed the same, see its pipe.lock
+        #[arg(long)]
+        verify_integrity: bool,
+        /// What to do when the pipe is installed already: replace it, keep it, or fail
+        #[arg(long, value_enum, default_value_t = CliOverwritePolicy::Overwrite)]
+        if_exists: CliOverwritePolicy,
+        /// What to do when the pipe installed under the same name is from another source:
+        /// fail, install this one under another name, or replace it
+        #[arg(long, value_enum, default_value_t = CliConflictPolicy::Error)]
+        on_conflict: CliConflictPolicy,
+        /// Install the pipe even without a pipe.ts, pipe.js or next.js app to start
+        #[arg(long)]
+        skip_validation: bool,
         /// Output format
         #[arg(short, long, value_enum, default_value_t = OutputFormat::Text)]
         output: OutputFormat,
@@ -309,6 +752,14 @@ pub enum PipeCommand {
         #[arg(short = 'p', long, default_value_t = 3030)]
         port: u16,
     },
+    /// Show what changed between an installed pipe and its source, before updating it
+    Diff {
+        /// ID of the pipe
+        id: String,
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Text)]
+        output: OutputFormat,
+    },
     /// Enable a pipe
     Enable {
         /// ID of the pipe to enable
@@ -351,6 +802,65 @@ pub enum PipeCommand {
         /// Automatically confirm deletion without prompting
         #[arg(short = 'y', long)]
         yes: bool,
+        /// Keep the content the pipe added to the search index
+        #[arg(long)]
+        keep_content: bool,
+        /// Server port
+        #[arg(short = 'p', long, default_value_t = 3030)]
+        port: u16,
+    },
+    /// Install, configure and enable pipes to match a manifest file
+    Apply {
+        /// Yaml (or json) file listing the pipes with their source, enabled and config
+        manifest: PathBuf,
+        /// Print the operations without running them
+        #[arg(long)]
+        dry_run: bool,
+        /// Stop at the first failure and undo the installs done so far
+        #[arg(long)]
+        atomic: bool,
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Text)]
+        output: OutputFormat,
+        /// Server port
+        #[arg(short = 'p', long, default_value_t = 3030)]
+        port: u16,
+    },
+    /// Write a lock file of the installed pipes: sources pinned to commits and checksums
+    Lock {
+        /// Where to write the lock
+        #[arg(default_value = "pipes.lock.json")]
+        path: PathBuf,
+    },
+    /// Install, update and optionally remove pipes to match a lock file
+    Sync {
+        /// Lock file written by `screenpipe pipe lock`
+        #[arg(default_value = "pipes.lock.json")]
+        lock: PathBuf,
+        /// Remove installed pipes the lock doesn't list
+        #[arg(long)]
+        prune: bool,
+        /// Replace or remove pipes edited since they were installed
+        #[arg(long)]
+        force: bool,
+        /// Print what would change without changing anything
+        #[arg(long)]
+        dry_run: bool,
+        /// Also write the json report of the actions taken to this file
+        #[arg(long)]
+        report: Option<PathBuf>,
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Text)]
+        output: OutputFormat,
+        /// Server port
+        #[arg(short = 'p', long, default_value_t = 3030)]
+        port: u16,
+    },
+    /// Interactive shell to try the api from, with the environment a pipe runs with
+    Repl {
+        /// Run as this installed pipe, with the permissions it was granted
+        #[arg(long)]
+        pipe: Option<String>,
         /// Server port
         #[arg(short = 'p', long, default_value_t = 3030)]
         port: u16,
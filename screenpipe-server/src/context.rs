@@ -0,0 +1,124 @@
+//! The context bundle of `GET /context/now`: what a pipe usually stitches together
+//! from several calls before prompting an llm, read in one go.
+//!
+//! Every part comes from one indexed query: the focused window of the latest frame,
+//! the transcriptions of the lookback, the open capture session and the latest pipe
+//! records flagged as context. Texts are cut to a shared character budget.
+
+use crate::db_types::{CaptureSession, ContextPipeResult, FocusedWindow, RecentTranscript};
+use crate::DatabaseManager;
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_LOOKBACK: Duration = Duration::from_secs(120);
+pub const MAX_LOOKBACK: Duration = Duration::from_secs(3600);
+pub const DEFAULT_MAX_CHARS: usize = 4000;
+pub const MAX_CHARS: usize = 100_000;
+
+/// Most transcriptions read for a bundle, newest first.
+const MAX_TRANSCRIPTS: u32 = 200;
+
+/// Pipe records in a bundle, "the last few".
+const PIPE_RESULTS: u32 = 5;
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ContextBundle {
+    pub now: DateTime<Utc>,
+    pub lookback_seconds: u64,
+    pub max_chars: usize,
+    /// `None` when nothing was captured in the lookback
+    pub focused: Option<FocusedWindow>,
+    /// Oldest first
+    pub transcripts: Vec<RecentTranscript>,
+    pub session: Option<CaptureSession>,
+    /// Newest first
+    pub pipe_results: Vec<ContextPipeResult>,
+    /// Characters of text in the bundle, at most `max_chars`
+    pub chars: usize,
+    /// Some text was cut or left out to fit `max_chars`
+    pub truncated: bool,
+    /// Time spent reading and trimming the bundle
+    pub assembly_ms: f64,
+}
+
+/// Characters left for the texts of a bundle.
+struct Budget {
+    left: usize,
+    truncated: bool,
+}
+
+impl Budget {
+    /// `text` cut to at most `cap` characters and to what's left, `None` once nothing is.
+    fn take(&mut self, text: &str, cap: usize) -> Option<String> {
+        let max = self.left.min(cap);
+        if max == 0 {
+            self.truncated |= !text.is_empty();
+            return None;
+        }
+        let kept: String = text.chars().take(max).collect();
+        let used = kept.chars().count();
+        self.truncated |= kept.len() < text.len();
+        self.left -= used;
+        Some(kept)
+    }
+}
+
+/// Reads the bundle for the `lookback` up to `now`. The focused window's text gets up
+/// to half of `max_chars`, pipe results up to a quarter, transcripts what's left, the
+/// newest kept first.
+pub async fn assemble(
+    db: &DatabaseManager,
+    now: DateTime<Utc>,
+    lookback: Duration,
+    max_chars: usize,
+) -> Result<ContextBundle, sqlx::Error> {
+    let started = Instant::now();
+    let since = now - ChronoDuration::milliseconds(lookback.as_millis() as i64);
+
+    let (focused, transcripts, session, pipe_results) = tokio::try_join!(
+        db.latest_focused_window(since, now),
+        db.recent_transcripts(since, now, MAX_TRANSCRIPTS),
+        db.get_open_capture_session(),
+        db.context_pipe_results(PIPE_RESULTS),
+    )?;
+
+    let mut budget = Budget {
+        left: max_chars,
+        truncated: false,
+    };
+    let focused = focused.map(|mut window| {
+        window.text = budget.take(&window.text, max_chars / 2).unwrap_or_default();
+        window
+    });
+    let mut pipe_share = max_chars / 4;
+    let pipe_results = pipe_results
+        .into_iter()
+        .map_while(|mut result| {
+            result.text = budget.take(&result.text, pipe_share)?;
+            pipe_share -= result.text.chars().count();
+            Some(result)
+        })
+        .collect();
+    let mut transcripts: Vec<RecentTranscript> = transcripts
+        .into_iter()
+        .map_while(|mut transcript| {
+            transcript.text = budget.take(&transcript.text, usize::MAX)?;
+            Some(transcript)
+        })
+        .collect();
+    transcripts.reverse();
+
+    Ok(ContextBundle {
+        now,
+        lookback_seconds: lookback.as_secs(),
+        max_chars,
+        focused,
+        transcripts,
+        session,
+        pipe_results,
+        chars: max_chars - budget.left,
+        truncated: budget.truncated,
+        assembly_ms: started.elapsed().as_secs_f64() * 1000.0,
+    })
+}
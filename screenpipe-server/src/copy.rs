@@ -0,0 +1,351 @@
+//! Getting a search result out of screenpipe, through `GET /content/:id/copy` and
+//! `screenpipe copy`: the text of a frame, its image or a link back to it.
+//!
+//! An image is only handed out when nothing on the frame is meant to stay private:
+//! no window of an ignored app or matching `--ignored-windows`, and no text the pii
+//! removal redacts, since the pixels would still show it. Text is copied as stored,
+//! so it is already redacted when pii removal is on.
+//!
+//! Links use the `screenpipe://` scheme the desktop app registers. It looks up the
+//! handlers in [`deep_link_document`] and resolves a link to a timeline position with
+//! `GET /deep-links/resolve`, the http url of a link works without the app.
+
+use crate::db_types::FrameContent;
+use crate::privacy::IgnoredApps;
+use anyhow::Result;
+use chrono::{DateTime, SecondsFormat, Utc};
+use screenpipe_core::pii_removal::contains_pii;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::Cursor;
+use std::time::Duration;
+
+pub const DEEP_LINK_SCHEME: &str = "screenpipe";
+
+/// How long `screenpipe copy` keeps serving the clipboard on linux, where it is gone
+/// with the process unless a clipboard manager took it over.
+pub const CLIPBOARD_HOLD: Duration = Duration::from_secs(30);
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// What is copied of a result.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CopyWhat {
+    /// The OCR text of the frame, as `text/plain`
+    #[default]
+    Text,
+    /// The frame as a png
+    Image,
+    /// A `screenpipe://` link with an http fallback
+    Link,
+}
+
+impl CopyWhat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CopyWhat::Text => "text",
+            CopyWhat::Image => "image",
+            CopyWhat::Link => "link",
+        }
+    }
+}
+
+/// Why the image of a frame isn't copied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Sensitive {
+    /// A window of an app on the ignore list is on screen
+    IgnoredApp(String),
+    /// A window matches one of `--ignored-windows`
+    IgnoredWindow(String),
+    /// The text has pii, or had it before it was redacted
+    Pii,
+    /// A tag rule marked it sensitive, see [`crate::tag_rules`]
+    Rule,
+}
+
+impl Sensitive {
+    /// The `reason` of the problem returned.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Sensitive::IgnoredApp(_) => "ignored_app",
+            Sensitive::IgnoredWindow(_) => "ignored_window",
+            Sensitive::Pii => "pii",
+            Sensitive::Rule => "tag_rule",
+        }
+    }
+}
+
+impl fmt::Display for Sensitive {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Sensitive::IgnoredApp(app) => write!(f, "shows {}, which is ignored", app),
+            Sensitive::IgnoredWindow(pattern) => {
+                write!(f, "shows a window matching ignored '{}'", pattern)
+            }
+            Sensitive::Pii => write!(f, "shows text redacted as pii"),
+            Sensitive::Rule => write!(f, "was marked sensitive by a tag rule"),
+        }
+    }
+}
+
+/// Whether the image of `frame` must not leave screenpipe, and why. `ignored_windows`
+/// are lowercased like [`crate::privacy::ignored_windows`].
+pub fn sensitive(
+    frame: &FrameContent,
+    ignored_apps: &IgnoredApps,
+    ignored_windows: &[String],
+) -> Option<Sensitive> {
+    if frame.sensitive {
+        return Some(Sensitive::Rule);
+    }
+    let texts = frame
+        .windows
+        .iter()
+        .map(|window| (window.app_name.as_str(), window.window_name.as_str()));
+    let laid_out = frame.layout.iter().flat_map(|layout| {
+        layout
+            .windows
+            .iter()
+            .map(|window| (window.app_name.as_str(), window.window_name.as_str()))
+    });
+    for (app_name, window_name) in texts.chain(laid_out) {
+        if ignored_apps.contains(app_name) {
+            return Some(Sensitive::IgnoredApp(app_name.to_string()));
+        }
+        let (app_lower, title_lower) = (app_name.to_lowercase(), window_name.to_lowercase());
+        if let Some(pattern) = ignored_windows
+            .iter()
+            .find(|pattern| app_lower.contains(*pattern) || title_lower.contains(*pattern))
+        {
+            return Some(Sensitive::IgnoredWindow(pattern.clone()));
+        }
+    }
+    frame
+        .windows
+        .iter()
+        .any(|window| contains_pii(&window.text))
+        .then_some(Sensitive::Pii)
+}
+
+/// The text of every window of `frame`, focused window first, `None` without any.
+pub fn frame_text(frame: &FrameContent) -> Option<String> {
+    let texts: Vec<&str> = frame
+        .windows
+        .iter()
+        .map(|window| window.text.trim())
+        .filter(|text| !text.is_empty())
+        .collect();
+    (!texts.is_empty()).then(|| texts.join("\n\n"))
+}
+
+/// `image` as a png, converting thumbnails.
+pub fn to_png(image: &[u8]) -> Result<Vec<u8>> {
+    if image.starts_with(PNG_SIGNATURE) {
+        return Ok(image.to_vec());
+    }
+    let mut png = Vec::new();
+    image::load_from_memory(image)?
+        .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)?;
+    Ok(png)
+}
+
+/// The link returned for `what=link`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CopyLink {
+    pub frame_id: i64,
+    pub timestamp: DateTime<Utc>,
+    /// Opens the frame in the timeline of the desktop app
+    pub deep_link: String,
+    /// The image of the frame from the server, for where the app isn't installed
+    pub http_url: String,
+}
+
+impl CopyLink {
+    /// `base_url` is where the server is reached, e.g. `http://localhost:3030`.
+    pub fn new(frame_id: i64, timestamp: DateTime<Utc>, base_url: &str) -> Self {
+        Self {
+            frame_id,
+            timestamp,
+            deep_link: deep_link(frame_id, timestamp),
+            http_url: http_url(frame_id, base_url),
+        }
+    }
+}
+
+/// `screenpipe://frame/<id>?timestamp=<rfc 3339>`. The timestamp still finds the
+/// position once the frame is deleted, or in another copy of the database.
+pub fn deep_link(frame_id: i64, timestamp: DateTime<Utc>) -> String {
+    format!(
+        "{}://frame/{}?timestamp={}",
+        DEEP_LINK_SCHEME,
+        frame_id,
+        timestamp.to_rfc3339_opts(SecondsFormat::Millis, true)
+    )
+}
+
+fn http_url(frame_id: i64, base_url: &str) -> String {
+    format!(
+        "{}/v1/content/{}/copy?what=image",
+        base_url.trim_end_matches('/'),
+        frame_id
+    )
+}
+
+/// What a deep link points at.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeepLinkTarget {
+    pub frame_id: i64,
+    pub timestamp: Option<DateTime<Utc>>,
+}
+
+/// Reads a link made by [`deep_link`].
+pub fn parse_deep_link(url: &str) -> Result<DeepLinkTarget, String> {
+    let rest = url
+        .strip_prefix(DEEP_LINK_SCHEME)
+        .and_then(|rest| rest.strip_prefix("://"))
+        .ok_or_else(|| format!("not a {}:// link: {}", DEEP_LINK_SCHEME, url))?;
+    let (path, query) = rest.split_once('?').unwrap_or((rest, ""));
+    let frame_id = path
+        .trim_end_matches('/')
+        .strip_prefix("frame/")
+        .and_then(|id| id.parse().ok())
+        .ok_or_else(|| format!("unknown deep link: {}", url))?;
+    let mut timestamp = None;
+    for (key, value) in query.split('&').filter_map(|pair| pair.split_once('=')) {
+        if key == "timestamp" {
+            timestamp = Some(
+                DateTime::parse_from_rfc3339(value)
+                    .map_err(|e| format!("invalid timestamp '{}': {}", value, e))?
+                    .with_timezone(&Utc),
+            );
+        }
+    }
+    Ok(DeepLinkTarget {
+        frame_id,
+        timestamp,
+    })
+}
+
+/// Where the timeline opens for a deep link.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimelinePosition {
+    pub frame_id: i64,
+    pub timestamp: DateTime<Utc>,
+    /// Whether the frame still exists. When it doesn't the timeline opens at the
+    /// timestamp of the link
+    pub found: bool,
+    /// Of the focused window
+    pub app_name: Option<String>,
+    pub window_name: Option<String>,
+    pub http_url: Option<String>,
+}
+
+/// The position of `target`, its `frame` if it still exists. `None` when neither the
+/// frame nor the link tells when it was.
+pub fn resolve_deep_link(
+    target: &DeepLinkTarget,
+    frame: Option<&FrameContent>,
+    base_url: &str,
+) -> Option<TimelinePosition> {
+    let Some(frame) = frame else {
+        return target.timestamp.map(|timestamp| TimelinePosition {
+            frame_id: target.frame_id,
+            timestamp,
+            found: false,
+            app_name: None,
+            window_name: None,
+            http_url: None,
+        });
+    };
+    let focused = frame
+        .windows
+        .first()
+        .map(|window| (window.app_name.clone(), window.window_name.clone()))
+        .or_else(|| {
+            let window = frame.layout.as_ref()?.focused()?;
+            Some((window.app_name.clone(), window.window_name.clone()))
+        });
+    let (app_name, window_name) = focused.unzip();
+    Some(TimelinePosition {
+        frame_id: frame.frame_id,
+        timestamp: frame.timestamp,
+        found: true,
+        app_name,
+        window_name,
+        http_url: Some(http_url(frame.frame_id, base_url)),
+    })
+}
+
+/// A kind of link the desktop app handles.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeepLinkHandler {
+    pub name: String,
+    pub pattern: String,
+    /// Api path answering the position to open, `{url}` is the link, url encoded
+    pub resolve: String,
+    /// Where the app opens the link
+    pub opens: String,
+}
+
+/// The links registered under [`DEEP_LINK_SCHEME`], served on `GET /deep-links`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeepLinkDocument {
+    pub scheme: String,
+    pub handlers: Vec<DeepLinkHandler>,
+}
+
+pub fn deep_link_document() -> DeepLinkDocument {
+    DeepLinkDocument {
+        scheme: DEEP_LINK_SCHEME.to_string(),
+        handlers: vec![DeepLinkHandler {
+            name: "frame".to_string(),
+            pattern: format!(
+                "{}://frame/{{frame_id}}?timestamp={{timestamp}}",
+                DEEP_LINK_SCHEME
+            ),
+            resolve: "/v1/deep-links/resolve?url={url}".to_string(),
+            opens: "timeline".to_string(),
+        }],
+    }
+}
+
+/// What `screenpipe copy` puts on the clipboard.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClipboardContent {
+    Text(String),
+    /// Png bytes
+    Image(Vec<u8>),
+}
+
+/// Puts `content` on the system clipboard. On linux the clipboard is served by the
+/// process that set it, so this blocks until another program owns it or `hold` is
+/// over, and works under wayland through the data control protocol where the
+/// compositor supports it, through xwayland elsewhere.
+pub fn copy_to_clipboard(content: &ClipboardContent, hold: Duration) -> Result<()> {
+    let mut clipboard = arboard::Clipboard::new()?;
+    match content {
+        ClipboardContent::Text(text) => set(&mut clipboard, hold).text(text.as_str())?,
+        ClipboardContent::Image(png) => {
+            let rgba = image::load_from_memory(png)?.to_rgba8();
+            let image = arboard::ImageData {
+                width: rgba.width() as usize,
+                height: rgba.height() as usize,
+                bytes: rgba.into_raw().into(),
+            };
+            set(&mut clipboard, hold).image(image)?
+        }
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn set(clipboard: &mut arboard::Clipboard, hold: Duration) -> arboard::Set<'_> {
+    use arboard::SetExtLinux;
+    clipboard.set().wait_until(std::time::Instant::now() + hold)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set(clipboard: &mut arboard::Clipboard, _hold: Duration) -> arboard::Set<'_> {
+    clipboard.set()
+}
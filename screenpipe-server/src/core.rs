@@ -1,21 +1,31 @@
 use crate::cli::{CliVadEngine, CliVadSensitivity};
-use crate::db_types::Speaker;
+use crate::db_types::{
+    CaptureOutcome, CaptureWrite, FrameWrite, Speaker, TranscriptionWrite, WindowOcrWrite,
+};
+use crate::events::{EventKind, EventRecorder};
+use crate::ocr_correction::OcrCorrector;
+use crate::privacy::ignored_apps;
+use crate::sources::{AudioSource, FrameSource, LiveAudioSource, LiveFrameSource};
+use crate::storage::MediaVolume;
+use crate::storage_mode::{storage_mode, thumbnail_path, write_thumbnail, StorageMode};
+use crate::video_guard::VideoGap;
 use crate::{DatabaseManager, VideoCapture};
 use anyhow::Result;
+use chrono::Utc;
 use crossbeam::queue::SegQueue;
 use futures::future::join_all;
 use log::{debug, error, info, warn};
 use screenpipe_audio::vad_engine::VadSensitivity;
-use screenpipe_audio::AudioStream;
 use screenpipe_audio::{
-    create_whisper_channel, record_and_transcribe, vad_engine::VadEngineEnum, AudioDevice,
-    AudioInput, AudioTranscriptionEngine, DeviceControl, TranscriptionResult,
+    create_whisper_channel, vad_engine::VadEngineEnum, AudioDevice, AudioInput,
+    AudioTranscriptionEngine, DeviceControl, DeviceType, TranscriptionResult,
 };
+use screenpipe_core::latency::{latency_tracker, PipelineKind};
 use screenpipe_core::pii_removal::remove_pii;
 use screenpipe_core::Language;
-use screenpipe_vision::OcrEngine;
+use screenpipe_vision::{OcrEngine, OcrScheduler};
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
@@ -45,6 +55,10 @@ pub async fn start_continuous_recording(
     vad_sensitivity: CliVadSensitivity,
     languages: Vec<Language>,
     capture_unfocused_windows: bool,
+    ocr_scheduler: Option<Arc<OcrScheduler>>,
+    media_volume: Option<Arc<MediaVolume>>,
+    ocr_corrector: Option<Arc<OcrCorrector>>,
+    events: Arc<EventRecorder>,
 ) -> Result<()> {
     debug!("Starting video recording for monitor {:?}", monitor_ids);
     let video_tasks = if !vision_disabled {
@@ -55,10 +69,19 @@ pub async fn start_continuous_recording(
                 let output_path_video = Arc::clone(&output_path);
                 let is_running_video = Arc::clone(&vision_control);
                 let ocr_engine = Arc::clone(&ocr_engine);
-                let ignored_windows_video = ignored_windows.to_vec();
-                let include_windows_video = include_windows.to_vec();
-
-                let languages = languages.clone();
+                let frame_source: Arc<dyn FrameSource> = Arc::new(LiveFrameSource::new(
+                    fps,
+                    Arc::clone(&ocr_engine),
+                    monitor_id,
+                    ignored_windows,
+                    include_windows,
+                    languages.clone(),
+                    capture_unfocused_windows,
+                    ocr_scheduler.clone(),
+                ));
+                let media_volume = media_volume.clone();
+                let ocr_corrector = ocr_corrector.clone();
+                let events = events.clone();
 
                 debug!("Starting video recording for monitor {}", monitor_id);
                 vision_handle.spawn(async move {
@@ -70,11 +93,11 @@ pub async fn start_continuous_recording(
                         ocr_engine,
                         monitor_id,
                         use_pii_removal,
-                        &ignored_windows_video,
-                        &include_windows_video,
                         video_chunk_duration,
-                        languages.clone(),
-                        capture_unfocused_windows,
+                        frame_source,
+                        media_volume,
+                        ocr_corrector,
+                        events,
                     )
                     .await
                 })
@@ -125,6 +148,7 @@ pub async fn start_continuous_recording(
                 whisper_receiver,
                 audio_devices_control,
                 audio_transcription_engine,
+                media_volume,
             )
             .await
         })
@@ -160,7 +184,7 @@ pub async fn start_continuous_recording(
     Ok(())
 }
 
-async fn record_video(
+pub(crate) async fn record_video(
     db: Arc<DatabaseManager>,
     output_path: Arc<String>,
     fps: f64,
@@ -168,11 +192,11 @@ async fn record_video(
     ocr_engine: Arc<OcrEngine>,
     monitor_id: u32,
     use_pii_removal: bool,
-    ignored_windows: &[String],
-    include_windows: &[String],
     video_chunk_duration: Duration,
-    languages: Vec<Language>,
-    capture_unfocused_windows: bool,
+    frame_source: Arc<dyn FrameSource>,
+    media_volume: Option<Arc<MediaVolume>>,
+    ocr_corrector: Option<Arc<OcrCorrector>>,
+    events: Arc<EventRecorder>,
 ) -> Result<()> {
     debug!("record_video: Starting");
     let db_chunk_callback = Arc::clone(&db);
@@ -198,58 +222,181 @@ async fn record_video(
         }
     };
 
+    // Frames left out of the video, so the timeline can tell a gap from a bug
+    let video_gap = {
+        let device_name = Arc::clone(&device_name);
+        let events = Arc::clone(&events);
+        let rt = Handle::current();
+        move |gap: VideoGap| {
+            let events = Arc::clone(&events);
+            let start_time = gap.start_time;
+            let kind = EventKind::VideoGap {
+                device_name: device_name.to_string(),
+                start_time: gap.start_time,
+                end_time: gap.end_time,
+                frames: gap.frames,
+                reason: gap.reason,
+            };
+            rt.spawn(async move {
+                if let Err(e) = events.record_at(kind, start_time).await {
+                    error!("Failed to record video gap: {}", e);
+                }
+            });
+        }
+    };
+
+    // Recorded so the timeline can explain the seam between the two chunks
+    let display_changed = {
+        let device_name = Arc::clone(&device_name);
+        let rt = Handle::current();
+        move |previous: (u32, u32), current: (u32, u32)| {
+            let events = Arc::clone(&events);
+            let kind = EventKind::DisplayChanged {
+                device_name: device_name.to_string(),
+                previous_width: previous.0,
+                previous_height: previous.1,
+                width: current.0,
+                height: current.1,
+            };
+            rt.spawn(async move {
+                if let Err(e) = events.record(kind).await {
+                    error!("Failed to record display change: {}", e);
+                }
+            });
+        }
+    };
+
     let video_capture = VideoCapture::new(
         &output_path,
         fps,
         video_chunk_duration,
         new_chunk_callback,
-        Arc::clone(&ocr_engine),
+        Arc::clone(&frame_source),
         monitor_id,
-        ignored_windows,
-        include_windows,
-        languages,
-        capture_unfocused_windows,
+        media_volume.clone(),
+        display_changed,
+        video_gap,
     );
+    let lossless = frame_source.lossless();
 
     while is_running.load(Ordering::SeqCst) {
-        if let Some(frame) = video_capture.ocr_frame_queue.pop() {
+        // No video or thumbnail is written while the media volume is gone, so only text
+        // only frames can be stored
+        let media_unavailable = media_volume.as_ref().is_some_and(|v| !v.is_available());
+        if media_unavailable && storage_mode().get() != StorageMode::TextOnly {
+            while video_capture.ocr_frame_queue.pop().is_some() {}
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            continue;
+        }
+        if let Some((frame, storage_mode)) = video_capture.ocr_frame_queue.pop() {
+            if media_unavailable && storage_mode != StorageMode::TextOnly {
+                continue;
+            }
+            if let Some(original) = frame.duplicate_of {
+                debug!(
+                    "record_video: frame {} reuses ocr of frame {}",
+                    frame.frame_number, original
+                );
+            }
+            let captured_at = frame_source.captured_at(frame.frame_number);
+            let thumbnail_path = if storage_mode.keeps_thumbnails() {
+                let path = thumbnail_path(
+                    Path::new(output_path.as_str()),
+                    monitor_id,
+                    captured_at.unwrap_or_else(Utc::now),
+                );
+                let image = frame.image.clone();
+                let written = tokio::task::spawn_blocking({
+                    let path = path.clone();
+                    move || write_thumbnail(&image, &path)
+                })
+                .await;
+                match written {
+                    Ok(Ok(())) => Some(path.to_string_lossy().into_owned()),
+                    Ok(Err(e)) => {
+                        warn!("Failed to write thumbnail {}: {}", path.display(), e);
+                        None
+                    }
+                    Err(e) => {
+                        warn!("Thumbnail task failed: {}", e);
+                        None
+                    }
+                }
+            } else {
+                None
+            };
+            let mut committed = false;
             for window_result in &frame.window_ocr_results {
-                match db.insert_frame(&device_name, None).await {
-                    Ok(frame_id) => {
-                        let text_json =
-                            serde_json::to_string(&window_result.text_json).unwrap_or_default();
-
-                        let text = if use_pii_removal {
-                            &remove_pii(&window_result.text)
-                        } else {
-                            &window_result.text
-                        };
-                        if let Err(e) = db
-                            .insert_ocr_text(
-                                frame_id,
-                                text,
-                                &text_json,
-                                &window_result.app_name,
-                                &window_result.window_name,
-                                Arc::clone(&ocr_engine),
-                                window_result.focused, // Add this line
-                            )
-                            .await
-                        {
-                            error!(
-                                "Failed to insert OCR text: {}, skipping window {} of frame {}",
-                                e, window_result.window_name, frame_id
-                            );
-                            continue;
-                        }
+                // Checked for every window, the list changes while recording
+                if ignored_apps().contains(&window_result.app_name) {
+                    continue;
+                }
+                let text = if use_pii_removal {
+                    remove_pii(&window_result.text)
+                } else {
+                    window_result.text.clone()
+                };
+                let text_json = serde_json::to_string(&window_result.text_json).unwrap_or_default();
+                // The corrected text is the one indexed, what the engine read is kept
+                let correction = match &ocr_corrector {
+                    Some(corrector) => corrector.correct(&text, &text_json).await,
+                    None => None,
+                };
+                let (text, raw_text, corrected_by) = match correction {
+                    Some(correction) => (correction.text, Some(text), Some(correction.source)),
+                    None => (text, None, None),
+                };
+                let write = CaptureWrite::Frame(FrameWrite {
+                    device_name: device_name.to_string(),
+                    video_chunk_id: None,
+                    timestamp: captured_at,
+                    windows: vec![WindowOcrWrite {
+                        text,
+                        text_json,
+                        app_name: window_result.app_name.clone(),
+                        window_name: window_result.window_name.clone(),
+                        ocr_engine: format!("{:?}", *ocr_engine),
+                        focused: window_result.focused,
+                        raw_text,
+                        corrected_by,
+                    }],
+                    storage_mode,
+                    thumbnail_path: thumbnail_path.clone(),
+                    window_layout: frame.window_layout.clone(),
+                });
+                let mut written = db.write_capture(write.clone()).await;
+                // The first video chunk row is written asynchronously; a lossless
+                // source must not lose frames that arrive before it exists.
+                while lossless && matches!(written, Ok(CaptureOutcome::Written(0))) {
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    written = db.write_capture(write.clone()).await;
+                }
+                match written {
+                    Ok(CaptureOutcome::Written(0)) => {
+                        debug!("record_video: no video chunk yet, skipping frame");
+                    }
+                    Ok(CaptureOutcome::Written(_)) => committed = true,
+                    Ok(CaptureOutcome::Spilled) => {
+                        debug!(
+                            "record_video: database locked, spilled window {} of frame {}",
+                            window_result.window_name, frame.frame_number
+                        );
                     }
                     Err(e) => {
-                        warn!("Failed to insert frame: {}", e);
+                        warn!(
+                            "Failed to insert frame: {}, skipping window {}",
+                            e, window_result.window_name
+                        );
                         tokio::time::sleep(Duration::from_millis(100)).await;
                         continue;
                     }
                 }
             }
+            if committed {
+                let mut latency = frame.latency;
+                latency.mark_committed();
+                latency_tracker().record(PipelineKind::Vision, &latency);
+            }
         }
         tokio::time::sleep(Duration::from_secs_f64(1.0 / fps)).await;
     }
@@ -264,6 +411,7 @@ async fn record_audio(
     whisper_receiver: crossbeam::channel::Receiver<TranscriptionResult>,
     audio_devices_control: Arc<SegQueue<(AudioDevice, DeviceControl)>>,
     audio_transcription_engine: Arc<AudioTranscriptionEngine>,
+    media_volume: Option<Arc<MediaVolume>>,
 ) -> Result<()> {
     let mut handles: HashMap<String, JoinHandle<()>> = HashMap::new();
     let mut previous_transcript = "".to_string();
@@ -283,68 +431,14 @@ async fn record_audio(
             }
 
             let whisper_sender_clone = whisper_sender.clone();
-
             let audio_device = Arc::new(audio_device);
-            let device_control = Arc::new(device_control);
-
-            let handle = tokio::spawn(async move {
-                let audio_device_clone = Arc::clone(&audio_device);
-                // let error = Arc::new(AtomicBool::new(false));
-                debug!(
-                    "Starting audio capture thread for device: {}",
-                    &audio_device
-                );
-
-                let mut did_warn = false;
-                let is_running = Arc::new(AtomicBool::new(device_control.is_running));
-
-                while is_running.load(Ordering::Relaxed) {
-                    let is_running_loop = Arc::clone(&is_running); // Create separate reference for the loop
-                    let audio_stream = match AudioStream::from_device(
-                        audio_device_clone.clone(),
-                        Arc::clone(&is_running_loop), // Clone from original Arc
-                    )
-                    .await
-                    {
-                        Ok(stream) => stream,
-                        Err(e) => {
-                            if e.to_string().contains("Audio device not found") {
-                                if !did_warn {
-                                    warn!("Audio device not found: {}", audio_device.name);
-                                    did_warn = true;
-                                }
-                                tokio::time::sleep(Duration::from_secs(1)).await;
-                                continue;
-                            } else {
-                                error!("Failed to create audio stream: {}", e);
-                                return;
-                            }
-                        }
-                    };
-
-                    let audio_stream = Arc::new(audio_stream);
-                    let whisper_sender_clone = whisper_sender_clone.clone();
-                    let record_handle = Some(tokio::spawn(async move {
-                        let _ = record_and_transcribe(
-                            audio_stream,
-                            chunk_duration,
-                            whisper_sender_clone.clone(),
-                            is_running_loop.clone(),
-                        )
-                        .await;
-                    }));
-
-                    // let live_transcription_handle = tokio::spawn(async move {
-                    //     let _ = live_transcription(audio_stream, whisper_sender_clone.clone()).await;
-                    // });
-
-                    if let Some(handle) = record_handle {
-                        handle.await.unwrap();
-                    }
-                }
-
-                info!("exiting audio capture thread for device: {}", &audio_device);
-            });
+            let source = Arc::new(LiveAudioSource::new(
+                audio_device,
+                chunk_duration,
+                media_volume.clone(),
+            ));
+            let is_running = Arc::new(AtomicBool::new(device_control.is_running));
+            let handle = tokio::spawn(source.start(whisper_sender_clone, is_running));
 
             handles.insert(device_id, handle);
         }
@@ -358,53 +452,75 @@ async fn record_audio(
             }
         });
 
-        while let Ok(mut transcription) = whisper_receiver.try_recv() {
-            info!(
-                "device {} received transcription {:?}",
-                transcription.input.device, transcription.transcription
-            );
+        drain_transcriptions(
+            &db,
+            &whisper_receiver,
+            &audio_transcription_engine,
+            &mut previous_transcript,
+            &mut previous_transcript_id,
+        )
+        .await;
 
-            // Insert the new transcript after fetching
-            let mut current_transcript: Option<String> = transcription.transcription.clone();
-            let mut processed_previous: Option<String> = None;
-            if let Some((previous, current)) =
-                transcription.cleanup_overlap(previous_transcript.clone())
-            {
-                if !previous.is_empty() && !current.is_empty() {
-                    if previous != previous_transcript {
-                        processed_previous = Some(previous);
-                    }
-                    if current_transcript.is_some()
-                        && current != current_transcript.clone().unwrap_or_default()
-                    {
-                        current_transcript = Some(current);
-                    }
-                }
-            }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
 
-            transcription.transcription = current_transcript.clone();
-            if current_transcript.is_some() {
-                previous_transcript = current_transcript.unwrap();
-            } else {
-                continue;
-            }
-            // Process the audio result
-            match process_audio_result(
-                &db,
-                transcription,
-                audio_transcription_engine.clone(),
-                processed_previous,
-                previous_transcript_id,
-            )
-            .await
-            {
-                Err(e) => error!("Error processing audio result: {}", e),
-                Ok(id) => previous_transcript_id = id,
+/// Stores every transcription currently waiting on the whisper channel and
+/// returns how many were received.
+pub(crate) async fn drain_transcriptions(
+    db: &DatabaseManager,
+    whisper_receiver: &crossbeam::channel::Receiver<TranscriptionResult>,
+    audio_transcription_engine: &Arc<AudioTranscriptionEngine>,
+    previous_transcript: &mut String,
+    previous_transcript_id: &mut Option<i64>,
+) -> usize {
+    let mut processed = 0;
+    while let Ok(mut transcription) = whisper_receiver.try_recv() {
+        processed += 1;
+        info!(
+            "device {} received transcription {:?}",
+            transcription.input.device, transcription.transcription
+        );
+
+        // Insert the new transcript after fetching
+        let mut current_transcript: Option<String> = transcription.transcription.clone();
+        let mut processed_previous: Option<String> = None;
+        if let Some((previous, current)) =
+            transcription.cleanup_overlap(previous_transcript.clone())
+        {
+            if !previous.is_empty() && !current.is_empty() {
+                if previous != *previous_transcript {
+                    processed_previous = Some(previous);
+                }
+                if current_transcript.is_some()
+                    && current != current_transcript.clone().unwrap_or_default()
+                {
+                    current_transcript = Some(current);
+                }
             }
         }
 
-        tokio::time::sleep(Duration::from_millis(100)).await;
+        transcription.transcription = current_transcript.clone();
+        if current_transcript.is_some() {
+            *previous_transcript = current_transcript.unwrap();
+        } else {
+            continue;
+        }
+        // Process the audio result
+        match process_audio_result(
+            db,
+            transcription,
+            audio_transcription_engine.clone(),
+            processed_previous,
+            *previous_transcript_id,
+        )
+        .await
+        {
+            Err(e) => error!("Error processing audio result: {}", e),
+            Ok(id) => *previous_transcript_id = id,
+        }
     }
+    processed
 }
 
 async fn process_audio_result(
@@ -448,40 +564,38 @@ async fn process_audio_result(
             }
         }
     }
-    match db.get_or_insert_audio_chunk(&result.path).await {
-        Ok(audio_chunk_id) => {
+    let write = CaptureWrite::Transcription(TranscriptionWrite {
+        file_path: result.path.clone(),
+        transcription: transcription.clone(),
+        offset_index: 0,
+        transcription_engine: transcription_engine.clone(),
+        device_name: result.input.device.name.clone(),
+        is_input_device: result.input.device.device_type == DeviceType::Input,
+        speaker_id: Some(speaker.id),
+        start_time: Some(result.start_time),
+        end_time: Some(result.end_time),
+    });
+    match db.write_capture(write).await {
+        Ok(CaptureOutcome::Written(audio_chunk_id)) => {
             if transcription.is_empty() {
                 return Ok(Some(audio_chunk_id));
             }
-
-            if let Err(e) = db
-                .insert_audio_transcription(
-                    audio_chunk_id,
-                    &transcription,
-                    0,
-                    &transcription_engine,
-                    &result.input.device,
-                    Some(speaker.id),
-                    Some(result.start_time),
-                    Some(result.end_time),
-                )
-                .await
-            {
-                error!(
-                    "Failed to insert audio transcription for device {}: {}",
-                    result.input.device, e
-                );
-                return Ok(Some(audio_chunk_id));
-            } else {
-                debug!(
-                    "Inserted audio transcription for chunk {} from device {} using {}",
-                    audio_chunk_id, result.input.device, transcription_engine
-                );
-                chunk_id = Some(audio_chunk_id);
-            }
+            debug!(
+                "Inserted audio transcription for chunk {} from device {} using {}",
+                audio_chunk_id, result.input.device, transcription_engine
+            );
+            let mut latency = result.input.latency;
+            latency.mark_committed();
+            latency_tracker().record(PipelineKind::Audio, &latency);
+            chunk_id = Some(audio_chunk_id);
         }
+        // Committed later from the spill journal, the next transcript can't extend it
+        Ok(CaptureOutcome::Spilled) => debug!(
+            "database locked, spilled audio transcription for device {}",
+            result.input.device
+        ),
         Err(e) => error!(
-            "Failed to insert audio chunk for device {}: {}",
+            "Failed to insert audio transcription for device {}: {}",
             result.input.device, e
         ),
     }
@@ -0,0 +1,342 @@
+//! Layout of the screenpipe directory (`--data-dir`, `~/.screenpipe` by default), set
+//! up on every start and by `screenpipe init`:
+//!
+//! ```text
+//! <screenpipe_dir>/
+//!   db.sqlite    the database, unless moved with `screenpipe storage`
+//!   data/        video and audio chunks, unless moved
+//!   pipes/<id>/  each pipe with its pipe.json and pipe.ts or pipe.js
+//!   logs/        server logs
+//! ```
+//!
+//! Files older versions left elsewhere are moved into place: the database under its
+//! old names, logs in the root, single file pipes in `pipes/`, pipe folders in the
+//! root and pipes whose entry point is an `index` or `main` file. Everything moved is
+//! copied to `backups/layout-<time>/` first, dependencies aside. A legacy file whose
+//! new place is taken is left alone. Running it again changes nothing.
+
+use chrono::Utc;
+use serde::Serialize;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+pub const DB_FILE: &str = "db.sqlite";
+pub const MEDIA_DIR: &str = "data";
+pub const PIPES_DIR: &str = "pipes";
+pub const LOGS_DIR: &str = "logs";
+pub const BACKUPS_DIR: &str = "backups";
+
+/// Directories every screenpipe directory has.
+pub const SUBDIRS: [&str; 3] = [MEDIA_DIR, PIPES_DIR, LOGS_DIR];
+
+/// Names older versions gave the database, with its journal files.
+const LEGACY_DB_FILES: [&str; 2] = ["screenpipe.db", "screenpipe.sqlite"];
+const DB_SUFFIXES: [&str; 3] = ["", "-wal", "-shm"];
+
+/// Entry points of pipes written before they had to be `pipe.ts` or `pipe.js`.
+const LEGACY_ENTRY_POINTS: [&str; 2] = ["index", "main"];
+const PIPE_EXTENSIONS: [&str; 2] = ["ts", "js"];
+
+/// Left out of backups, reinstalled when the pipe runs.
+const NOT_BACKED_UP: &str = "node_modules";
+
+const PROBE_FILE: &str = ".screenpipe-write-probe";
+
+/// Why the screenpipe directory can't be used, each saying what to do about it.
+#[derive(Debug, thiserror::Error)]
+pub enum InitError {
+    #[error("{} is not a directory, move it away or pass --data-dir to use another location", .0.display())]
+    NotADirectory(PathBuf),
+    #[error("{} is on a read-only volume, pass --data-dir to use a writable location", .0.display())]
+    ReadOnly(PathBuf),
+    #[error("no permission to write to {}, make it writable by this user (e.g. `chown -R $USER {}`) or pass --data-dir to use another location", .0.display(), .0.display())]
+    PermissionDenied(PathBuf),
+    #[error("no space left on the volume of {}, free some space or pass --data-dir to use another volume", .0.display())]
+    NoSpace(PathBuf),
+    #[error("failed to set up {}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+}
+
+impl InitError {
+    fn from_io(path: &Path, source: io::Error) -> Self {
+        let path = path.to_path_buf();
+        match source.kind() {
+            ErrorKind::PermissionDenied => InitError::PermissionDenied(path),
+            ErrorKind::ReadOnlyFilesystem => InitError::ReadOnly(path),
+            ErrorKind::StorageFull => InitError::NoSpace(path),
+            ErrorKind::NotADirectory => InitError::NotADirectory(path),
+            _ => InitError::Io { path, source },
+        }
+    }
+}
+
+/// A legacy file or folder moved into place.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Migration {
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+/// What `init_data_dir` did.
+#[derive(Debug, Default, Serialize)]
+pub struct InitReport {
+    pub created: Vec<PathBuf>,
+    pub migrated: Vec<Migration>,
+    /// Legacy files left where they are, and why
+    pub skipped: Vec<String>,
+    /// Copies of what was migrated, when something was
+    pub backup_dir: Option<PathBuf>,
+}
+
+impl InitReport {
+    pub fn changed(&self) -> bool {
+        !self.created.is_empty() || !self.migrated.is_empty()
+    }
+
+    pub fn log(&self) {
+        for dir in &self.created {
+            info!("created {}", dir.display());
+        }
+        for migration in &self.migrated {
+            info!(
+                "moved {} to {}",
+                migration.from.display(),
+                migration.to.display()
+            );
+        }
+        if let Some(backup_dir) = &self.backup_dir {
+            info!("copies of the moved files are in {}", backup_dir.display());
+        }
+        for skipped in &self.skipped {
+            warn!("{}", skipped);
+        }
+    }
+}
+
+/// Creates the directory tree, checks it can be written to and moves legacy files
+/// into place. Fails before moving anything when the directory can't be used.
+pub fn init_data_dir(base_dir: &Path) -> Result<InitReport, InitError> {
+    let mut report = InitReport::default();
+    if base_dir.exists() && !base_dir.is_dir() {
+        return Err(InitError::NotADirectory(base_dir.to_path_buf()));
+    }
+    for dir in std::iter::once(base_dir.to_path_buf()).chain(SUBDIRS.map(|d| base_dir.join(d))) {
+        if dir.exists() && !dir.is_dir() {
+            return Err(InitError::NotADirectory(dir));
+        }
+        if !dir.exists() {
+            create_private_dir(&dir).map_err(|e| InitError::from_io(&dir, e))?;
+            report.created.push(dir.clone());
+        }
+        probe(&dir).map_err(|e| InitError::from_io(&dir, e))?;
+    }
+
+    let (migrations, skipped) =
+        plan_migrations(base_dir).map_err(|e| InitError::from_io(base_dir, e))?;
+    report.skipped = skipped;
+    if migrations.is_empty() {
+        return Ok(report);
+    }
+
+    let backup_dir = base_dir
+        .join(BACKUPS_DIR)
+        .join(format!("layout-{}", Utc::now().format("%Y-%m-%d_%H-%M-%S")));
+    for migration in &migrations {
+        let relative = migration
+            .from
+            .strip_prefix(base_dir)
+            .unwrap_or(&migration.from);
+        let backup = backup_dir.join(relative);
+        copy_recursive(&migration.from, &backup).map_err(|e| InitError::from_io(&backup, e))?;
+    }
+    report.backup_dir = Some(backup_dir);
+    for migration in migrations {
+        if let Some(parent) = migration.to.parent() {
+            fs::create_dir_all(parent).map_err(|e| InitError::from_io(parent, e))?;
+        }
+        fs::rename(&migration.from, &migration.to)
+            .map_err(|e| InitError::from_io(&migration.from, e))?;
+        report.migrated.push(migration);
+    }
+    Ok(report)
+}
+
+/// Legacy files to move, and those that can't be because their place is taken.
+pub fn plan_migrations(base_dir: &Path) -> io::Result<(Vec<Migration>, Vec<String>)> {
+    let mut plan = Plan::default();
+
+    // The database under an older name, the first one found with its journal files
+    if let Some(legacy) = LEGACY_DB_FILES
+        .iter()
+        .find(|name| base_dir.join(name).is_file())
+    {
+        let moves: Vec<(PathBuf, PathBuf)> = DB_SUFFIXES
+            .iter()
+            .map(|suffix| {
+                (
+                    base_dir.join(format!("{}{}", legacy, suffix)),
+                    base_dir.join(format!("{}{}", DB_FILE, suffix)),
+                )
+            })
+            .filter(|(from, _)| from.exists())
+            .collect();
+        plan.add_together(base_dir.join(DB_FILE), moves);
+    }
+
+    for entry in sorted_entries(base_dir)? {
+        let name = entry
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        if entry.is_file() && is_server_log(&name) {
+            let to = base_dir.join(LOGS_DIR).join(&name);
+            plan.add_together(to.clone(), vec![(entry, to)]);
+        } else if entry.is_dir() && !is_reserved(&name) && entry.join("pipe.json").is_file() {
+            // A pipe folder in the root
+            let to = base_dir.join(PIPES_DIR).join(&name);
+            plan.add_together(to.clone(), vec![(entry, to)]);
+        }
+    }
+
+    let pipes_dir = base_dir.join(PIPES_DIR);
+    for entry in sorted_entries(&pipes_dir)? {
+        let stem = entry
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        let extension = entry
+            .extension()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        if stem.is_empty() || stem.starts_with('.') {
+            continue;
+        }
+        if entry.is_file() && PIPE_EXTENSIONS.contains(&extension.as_str()) {
+            // A pipe that was a single file, with its config next to it
+            let pipe_dir = pipes_dir.join(&stem);
+            let mut moves = vec![(entry.clone(), pipe_dir.join(format!("pipe.{}", extension)))];
+            let config = pipes_dir.join(format!("{}.json", stem));
+            if config.is_file() {
+                moves.push((config, pipe_dir.join("pipe.json")));
+            }
+            plan.add_together(pipe_dir, moves);
+        } else if entry.is_dir() && find_entry_point(&entry).is_none() {
+            if let Some(legacy) = legacy_entry_point(&entry) {
+                let extension = legacy.extension().unwrap_or_default().to_string_lossy();
+                let to = entry.join(format!("pipe.{}", extension));
+                plan.add_together(to.clone(), vec![(legacy, to)]);
+            }
+        }
+    }
+    Ok((plan.migrations, plan.skipped))
+}
+
+#[derive(Default)]
+struct Plan {
+    migrations: Vec<Migration>,
+    skipped: Vec<String>,
+}
+
+impl Plan {
+    /// Plans `moves` unless `target`, where they go, exists or is taken by another
+    /// move. They are skipped together, half a database or pipe is worse than none.
+    fn add_together(&mut self, target: PathBuf, moves: Vec<(PathBuf, PathBuf)>) {
+        let taken = target.exists()
+            || self
+                .migrations
+                .iter()
+                .any(|m| m.to.starts_with(&target) || target.starts_with(&m.to));
+        if taken {
+            for (from, _) in moves {
+                self.skipped.push(format!(
+                    "{} was left in place, {} already exists",
+                    from.display(),
+                    target.display()
+                ));
+            }
+            return;
+        }
+        self.migrations
+            .extend(moves.into_iter().map(|(from, to)| Migration { from, to }));
+    }
+}
+
+/// Daily log files of the server, `screenpipe.<date>.log`, not those of the app.
+fn is_server_log(name: &str) -> bool {
+    name.starts_with("screenpipe.") && name.ends_with(".log")
+}
+
+fn is_reserved(name: &str) -> bool {
+    SUBDIRS.contains(&name) || name == BACKUPS_DIR || name.starts_with('.')
+}
+
+fn find_entry_point(pipe_dir: &Path) -> Option<PathBuf> {
+    PIPE_EXTENSIONS
+        .iter()
+        .map(|extension| pipe_dir.join(format!("pipe.{}", extension)))
+        .find(|path| path.is_file())
+}
+
+fn legacy_entry_point(pipe_dir: &Path) -> Option<PathBuf> {
+    LEGACY_ENTRY_POINTS.iter().find_map(|name| {
+        PIPE_EXTENSIONS
+            .iter()
+            .map(|extension| pipe_dir.join(format!("{}.{}", name, extension)))
+            .find(|path| path.is_file())
+    })
+}
+
+fn sorted_entries(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut entries = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    entries.sort();
+    Ok(entries)
+}
+
+/// Only the user can read the recordings.
+fn create_private_dir(dir: &Path) -> io::Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder.create(dir)
+}
+
+/// Writes and removes a file, the only reliable check a directory is writable.
+fn probe(dir: &Path) -> io::Result<()> {
+    let path = dir.join(PROBE_FILE);
+    fs::write(&path, b"screenpipe")?;
+    fs::remove_file(&path)
+}
+
+fn copy_recursive(from: &Path, to: &Path) -> io::Result<()> {
+    if from.is_dir() {
+        fs::create_dir_all(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            if entry.file_name() == NOT_BACKED_UP {
+                continue;
+            }
+            copy_recursive(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        Ok(())
+    } else {
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(from, to).map(|_| ())
+    }
+}
@@ -1,40 +1,85 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
 use libsqlite3_sys::sqlite3_auto_extension;
 use log::{debug, error, warn};
 use screenpipe_audio::{AudioDevice, DeviceType};
+use screenpipe_core::clock::{capture_clock, Clock};
 use screenpipe_vision::OcrEngine;
 use sqlite_vec::sqlite3_vec_init;
 use sqlx::migrate::MigrateDatabase;
-use sqlx::sqlite::{SqlitePool, SqlitePoolOptions};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteConnection, SqlitePool, SqlitePoolOptions};
 use sqlx::Column;
 use sqlx::Error as SqlxError;
 use sqlx::Row;
 use sqlx::TypeInfo;
 use sqlx::ValueRef;
-use std::sync::Arc;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, RwLock};
 use std::time::Duration;
 use tracing::info;
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 use tokio::time::{timeout, Duration as TokioDuration};
 
 use zerocopy::AsBytes;
 
+use crate::db_retry::{
+    is_busy, retry_busy, BusyRetry, DbWriteMetrics, SpillEntry, SpillJournal, DEFAULT_BUSY_TIMEOUT,
+};
 use crate::db_types::{
-    AudioChunksResponse, AudioEntry, AudioResult, AudioResultRaw, FrameData, OCREntry, OCRResult,
-    OCRResultRaw, Speaker, TagContentType,
+    AppRollup, AppUsage, ArchiveCandidate, AudioChunkSource, AudioChunksResponse, AudioEntry,
+    AudioResult, AudioResultRaw, CaptureCounts, CaptureGap, CaptureOutcome, CaptureSession,
+    CaptureSessionRaw, CaptureWrite, ClockAdjustmentRow, ContextPipeResult, DisplayChange,
+    DocumentResult, DocumentState, EventRow, ExportFormat, FocusedWindow, FrameData, FrameWrite,
+    MediaChunkKind, OCREntry, OCRResult, OCRResultRaw, PendingArchiveMove, PipeContentResult,
+    PipeContentResultRaw, PipeContentTypeRow, PipeJob, PipeJobRaw, PipeJobStatus, PipeNetworkStats,
+    RecentTranscript, RetentionKind, RetentionRow, RetentionUsage, RollupProgress, RollupState,
+    RuleSampleRow, SearchFilters, SearchOrder, SessionEndReason, Speaker, TagContentType,
+    TranscriptionWrite, UsageBucket,
 };
-use crate::db_types::{ContentType, UiContent};
+use crate::db_types::{ContentType, FrameContent, FrameFindRow, FrameImageSource, UiContent};
 use crate::db_types::{SearchResult, TimeSeriesChunk};
+use crate::events::EventFilter;
+use crate::ocr_correction::CorrectionSource;
+use crate::storage_mode::StorageMode;
+use crate::tag_rules::{rule_metrics, RuleInput, RuleOutcome, RuleWindow, TagRules};
+use screenpipe_core::window_layout::WindowLayout;
 
 use futures::future::try_join_all;
 
+/// Longest a focused app is credited with after a frame, when the next frame is later
+/// than that the screen was idle, asleep or not recorded.
+pub const MAX_FOCUS_GAP_SECONDS: f64 = 60.0;
+
+/// The device's most recent chunk with video, text only chunks have no file path.
+const LATEST_VIDEO_CHUNK: &str =
+    "SELECT id FROM video_chunks WHERE device_name = ?1 AND file_path != '' ORDER BY id DESC LIMIT 1";
+
 pub struct DatabaseManager {
     pub pool: SqlitePool,
+    /// Stamps rows of live captures, see [`screenpipe_core::clock`]
+    clock: Arc<Clock>,
+    /// How capture writes ride out a database locked by another connection
+    write_retry: BusyRetry,
+    write_metrics: Arc<DbWriteMetrics>,
+    /// Where capture writes go when the database stays locked, see [`crate::db_retry`]
+    spill: Option<SpillJournal>,
+    /// Run on every captured frame and transcription, see [`crate::tag_rules`]
+    tag_rules: RwLock<Arc<TagRules>>,
 }
 
 impl DatabaseManager {
     pub async fn new(database_path: &str) -> Result<Self, sqlx::Error> {
+        Self::new_with_busy_timeout(database_path, DEFAULT_BUSY_TIMEOUT).await
+    }
+
+    /// Opens the database with every pooled connection waiting up to `busy_timeout`
+    /// for another connection's lock before failing with SQLITE_BUSY.
+    pub async fn new_with_busy_timeout(
+        database_path: &str,
+        busy_timeout: Duration,
+    ) -> Result<Self, sqlx::Error> {
         debug!(
             "Initializing DatabaseManager with database path: {}",
             database_path
@@ -54,29 +99,35 @@ impl DatabaseManager {
             sqlx::Sqlite::create_database(&connection_string).await?;
         }
 
+        // Connection pragmas are set on every connection the pool opens, not just the
+        // one that happens to run a query first
+        // PRAGMA cache_size = -2000; -- Set cache size to 2MB
+        // PRAGMA temp_store = MEMORY; -- Store temporary tables and indices in memory
+        let options = SqliteConnectOptions::from_str(&connection_string)?
+            .busy_timeout(busy_timeout)
+            .pragma("cache_size", "-2000")
+            .pragma("temp_store", "MEMORY");
+
         let pool = SqlitePoolOptions::new()
             .max_connections(50)
             .min_connections(3) // Minimum number of idle connections
             .acquire_timeout(Duration::from_secs(10))
-            .connect(&connection_string)
+            .connect_with(options)
             .await?;
 
-        // Enable WAL mode
+        // Enable WAL mode, this one is stored in the database file
         sqlx::query("PRAGMA journal_mode = WAL;")
             .execute(&pool)
             .await?;
 
-        // Enable SQLite's query result caching
-        // PRAGMA cache_size = -2000; -- Set cache size to 2MB
-        // PRAGMA temp_store = MEMORY; -- Store temporary tables and indices in memory
-        sqlx::query("PRAGMA cache_size = -2000;")
-            .execute(&pool)
-            .await?;
-        sqlx::query("PRAGMA temp_store = MEMORY;")
-            .execute(&pool)
-            .await?;
-
-        let db_manager = DatabaseManager { pool };
+        let db_manager = DatabaseManager {
+            pool,
+            clock: capture_clock(),
+            write_retry: BusyRetry::default(),
+            write_metrics: Arc::new(DbWriteMetrics::default()),
+            spill: None,
+            tag_rules: RwLock::default(),
+        };
 
         info!("running migrations");
 
@@ -90,6 +141,56 @@ impl DatabaseManager {
         Ok(db_manager)
     }
 
+    /// Stamps captured rows with `clock` instead of the process wide capture clock.
+    pub fn with_clock(mut self, clock: Arc<Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn with_write_retry(mut self, write_retry: BusyRetry) -> Self {
+        self.write_retry = write_retry;
+        self
+    }
+
+    /// Spills capture writes the database stays locked for to the journal at `path`.
+    /// Writes spilled before, by this or an earlier process, wait for
+    /// [`Self::replay_spill_journal`].
+    pub fn with_spill_journal(mut self, path: impl Into<PathBuf>) -> Self {
+        let journal = SpillJournal::new(path);
+        let pending = match journal.read() {
+            Ok(entries) => entries.len() as u64,
+            Err(e) => {
+                error!(
+                    "failed to read spill journal {}: {}",
+                    journal.path().display(),
+                    e
+                );
+                0
+            }
+        };
+        self.write_metrics.pending.store(pending, Ordering::Relaxed);
+        self.spill = Some(journal);
+        self
+    }
+
+    pub fn write_metrics(&self) -> &DbWriteMetrics {
+        &self.write_metrics
+    }
+
+    pub fn with_tag_rules(self, tag_rules: TagRules) -> Self {
+        self.set_tag_rules(tag_rules);
+        self
+    }
+
+    /// Runs `tag_rules` on captures from now on, rows already written keep their tags.
+    pub fn set_tag_rules(&self, tag_rules: TagRules) {
+        *self.tag_rules.write().unwrap() = Arc::new(tag_rules);
+    }
+
+    pub fn tag_rules(&self) -> Arc<TagRules> {
+        self.tag_rules.read().unwrap().clone()
+    }
+
     async fn run_migrations(pool: &SqlitePool) -> Result<(), sqlx::Error> {
         let mut migrator = sqlx::migrate!("./src/migrations");
         migrator.set_ignore_missing(true);
@@ -100,15 +201,42 @@ impl DatabaseManager {
     }
 
     pub async fn insert_audio_chunk(&self, file_path: &str) -> Result<i64, sqlx::Error> {
-        let mut tx = self.pool.begin().await?;
-        let id = sqlx::query("INSERT INTO audio_chunks (file_path, timestamp) VALUES (?1, ?2)")
-            .bind(file_path)
-            .bind(Utc::now())
-            .execute(&mut *tx)
-            .await?
-            .last_insert_rowid();
-        tx.commit().await?;
-        Ok(id)
+        let stamp = self.clock.now();
+        retry_busy(
+            &self.write_retry,
+            &self.write_metrics,
+            "insert_audio_chunk",
+            || async move {
+                let mut tx = self.pool.begin().await?;
+                let id = Self::insert_audio_chunk_row(
+                    &mut tx,
+                    file_path,
+                    stamp.wall,
+                    Some(stamp.monotonic_ns as i64),
+                )
+                .await?;
+                tx.commit().await?;
+                Ok(id)
+            },
+        )
+        .await
+    }
+
+    async fn insert_audio_chunk_row(
+        conn: &mut SqliteConnection,
+        file_path: &str,
+        timestamp: DateTime<Utc>,
+        monotonic_ns: Option<i64>,
+    ) -> Result<i64, sqlx::Error> {
+        Ok(sqlx::query(
+            "INSERT INTO audio_chunks (file_path, timestamp, monotonic_ns) VALUES (?1, ?2, ?3)",
+        )
+        .bind(file_path)
+        .bind(timestamp)
+        .bind(monotonic_ns)
+        .execute(conn)
+        .await?
+        .last_insert_rowid())
     }
 
     async fn get_audio_chunk_id(&self, file_path: &str) -> Result<i64, sqlx::Error> {
@@ -138,30 +266,40 @@ impl DatabaseManager {
         start_time: Option<f64>,
         end_time: Option<f64>,
     ) -> Result<i64, sqlx::Error> {
-        let mut tx = self.pool.begin().await?;
-
-        // Insert the full transcription
-        let id = sqlx::query(
-            "INSERT INTO audio_transcriptions (audio_chunk_id, transcription, offset_index, timestamp, transcription_engine, device, is_input_device, speaker_id, start_time, end_time) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
-        )
-        .bind(audio_chunk_id)
-        .bind(transcription)
-        .bind(offset_index)
-        .bind(Utc::now())
-        .bind(transcription_engine)
-        .bind(&device.name)
-        .bind(device.device_type == DeviceType::Input)
-        .bind(speaker_id)
-        .bind(start_time)
-        .bind(end_time)
-        .execute(&mut *tx)
-        .await?
-        .last_insert_rowid();
+        let stamp = self.clock.now();
+        retry_busy(
+            &self.write_retry,
+            &self.write_metrics,
+            "insert_audio_transcription",
+            || async move {
+                let mut tx = self.pool.begin().await?;
+
+                // Insert the full transcription
+                let id = sqlx::query(
+                    "INSERT INTO audio_transcriptions (audio_chunk_id, transcription, offset_index, timestamp, transcription_engine, device, is_input_device, speaker_id, start_time, end_time, monotonic_ns) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                )
+                .bind(audio_chunk_id)
+                .bind(transcription)
+                .bind(offset_index)
+                .bind(stamp.wall)
+                .bind(transcription_engine)
+                .bind(&device.name)
+                .bind(device.device_type == DeviceType::Input)
+                .bind(speaker_id)
+                .bind(start_time)
+                .bind(end_time)
+                .bind(stamp.monotonic_ns as i64)
+                .execute(&mut *tx)
+                .await?
+                .last_insert_rowid();
 
-        // Commit the transaction for the full transcription
-        tx.commit().await?;
+                // Commit the transaction for the full transcription
+                tx.commit().await?;
 
-        Ok(id)
+                Ok(id)
+            },
+        )
+        .await
     }
 
     pub async fn update_audio_transcription(
@@ -169,22 +307,30 @@ impl DatabaseManager {
         audio_chunk_id: i64,
         transcription: &str,
     ) -> Result<i64, sqlx::Error> {
-        let mut tx = self.pool.begin().await?;
-
-        // Insert the full transcription
-        let affected = sqlx::query(
-            "UPDATE audio_transcriptions SET transcription = ?1 WHERE audio_chunk_id = ?2",
-        )
-        .bind(transcription)
-        .bind(audio_chunk_id)
-        .execute(&mut *tx)
-        .await?
-        .rows_affected();
+        retry_busy(
+            &self.write_retry,
+            &self.write_metrics,
+            "update_audio_transcription",
+            || async move {
+                let mut tx = self.pool.begin().await?;
+
+                // Insert the full transcription
+                let affected = sqlx::query(
+                    "UPDATE audio_transcriptions SET transcription = ?1 WHERE audio_chunk_id = ?2",
+                )
+                .bind(transcription)
+                .bind(audio_chunk_id)
+                .execute(&mut *tx)
+                .await?
+                .rows_affected();
 
-        // Commit the transaction for the full transcription
-        tx.commit().await?;
+                // Commit the transaction for the full transcription
+                tx.commit().await?;
 
-        Ok(affected as i64)
+                Ok(affected as i64)
+            },
+        )
+        .await
     }
 
     pub async fn insert_speaker(&self, embedding: &[f32]) -> Result<Speaker, SqlxError> {
@@ -278,15 +424,44 @@ impl DatabaseManager {
         file_path: &str,
         device_name: &str,
     ) -> Result<i64, sqlx::Error> {
+        retry_busy(
+            &self.write_retry,
+            &self.write_metrics,
+            "insert_video_chunk",
+            || async move {
+                let mut tx = self.pool.begin().await?;
+                let id = sqlx::query(
+                    "INSERT INTO video_chunks (file_path, device_name) VALUES (?1, ?2)",
+                )
+                .bind(file_path)
+                .bind(device_name)
+                .execute(&mut *tx)
+                .await?
+                .last_insert_rowid();
+                tx.commit().await?;
+                Ok(id)
+            },
+        )
+        .await
+    }
+
+    /// Rewrites video and audio chunk paths starting with `from` to start with `to`.
+    pub async fn rebase_media_paths(&self, from: &str, to: &str) -> Result<u64, sqlx::Error> {
         let mut tx = self.pool.begin().await?;
-        let id = sqlx::query("INSERT INTO video_chunks (file_path, device_name) VALUES (?1, ?2)")
-            .bind(file_path)
-            .bind(device_name)
+        let mut updated = 0;
+        for table in ["video_chunks", "audio_chunks"] {
+            updated += sqlx::query(&format!(
+                "UPDATE {} SET file_path = ?2 || substr(file_path, length(?1) + 1) WHERE substr(file_path, 1, length(?1)) = ?1",
+                table
+            ))
+            .bind(from)
+            .bind(to)
             .execute(&mut *tx)
             .await?
-            .last_insert_rowid();
+            .rows_affected();
+        }
         tx.commit().await?;
-        Ok(id)
+        Ok(updated)
     }
 
     pub async fn insert_frame(
@@ -294,25 +469,73 @@ impl DatabaseManager {
         device_name: &str,
         timestamp: Option<DateTime<Utc>>,
     ) -> Result<i64, sqlx::Error> {
-        let mut tx = self.pool.begin().await?;
-        debug!("insert_frame Transaction started");
+        // Frames with a timestamp of their own (replays, imports) keep it as is
+        let (timestamp, monotonic_ns) = match timestamp {
+            Some(timestamp) => (timestamp, None),
+            None => {
+                let stamp = self.clock.now();
+                (stamp.wall, Some(stamp.monotonic_ns as i64))
+            }
+        };
+        retry_busy(
+            &self.write_retry,
+            &self.write_metrics,
+            "insert_frame",
+            || async move {
+                let mut tx = self.pool.begin().await?;
+                debug!("insert_frame Transaction started");
+                let id = Self::insert_frame_row(
+                    &mut tx,
+                    device_name,
+                    None,
+                    timestamp,
+                    monotonic_ns,
+                    StorageMode::Full,
+                    None,
+                )
+                .await?;
+                if id == 0 {
+                    debug!("No video chunk found, rolling back transaction");
+                    tx.rollback().await?;
+                    return Ok(0);
+                }
+
+                // Commit the transaction
+                tx.commit().await?;
+                // debug!("insert_frame Transaction committed");
 
-        // Get the most recent video_chunk_id
-        let video_chunk_id: Option<i64> = sqlx::query_scalar(
-            "SELECT id FROM video_chunks WHERE device_name = ?1 ORDER BY id DESC LIMIT 1",
+                Ok(id)
+            },
         )
-        .bind(device_name)
-        .fetch_optional(&mut *tx)
-        .await?;
-        debug!("Fetched most recent video_chunk_id: {:?}", video_chunk_id);
+        .await
+    }
 
-        // If no video chunk is found, return 0
+    /// Inserts a frame into `video_chunk_id`, or into the device's most recent video
+    /// chunk. Returns 0 when the device has no chunk.
+    async fn insert_frame_row(
+        conn: &mut SqliteConnection,
+        device_name: &str,
+        video_chunk_id: Option<i64>,
+        timestamp: DateTime<Utc>,
+        monotonic_ns: Option<i64>,
+        storage_mode: StorageMode,
+        thumbnail_path: Option<&str>,
+    ) -> Result<i64, sqlx::Error> {
         let video_chunk_id = match video_chunk_id {
             Some(id) => id,
             None => {
-                debug!("No video chunk found, rolling back transaction");
-                tx.rollback().await?;
-                return Ok(0);
+                // Get the most recent video_chunk_id
+                let latest: Option<i64> = sqlx::query_scalar(LATEST_VIDEO_CHUNK)
+                    .bind(device_name)
+                    .fetch_optional(&mut *conn)
+                    .await?;
+                debug!("Fetched most recent video_chunk_id: {:?}", latest);
+
+                // If no video chunk is found, return 0
+                match latest {
+                    Some(id) => id,
+                    None => return Ok(0),
+                }
             }
         };
 
@@ -321,29 +544,342 @@ impl DatabaseManager {
             "SELECT COALESCE(MAX(offset_index), -1) + 1 FROM frames WHERE video_chunk_id = ?1",
         )
         .bind(video_chunk_id)
-        .fetch_one(&mut *tx)
+        .fetch_one(&mut *conn)
         .await?;
         debug!("insert_frame Calculated offset_index: {}", offset_index);
 
-        let timestamp = timestamp.unwrap_or_else(Utc::now);
-
         // Insert the new frame
         let id = sqlx::query(
-            "INSERT INTO frames (video_chunk_id, offset_index, timestamp) VALUES (?1, ?2, ?3)",
+            "INSERT INTO frames (video_chunk_id, offset_index, timestamp, monotonic_ns, storage_mode, thumbnail_path) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
         )
         .bind(video_chunk_id)
         .bind(offset_index)
         .bind(timestamp)
-        .execute(&mut *tx)
+        .bind(monotonic_ns)
+        .bind(storage_mode.as_str())
+        .bind(thumbnail_path)
+        .execute(&mut *conn)
         .await?
         .last_insert_rowid();
         debug!("insert_frame Inserted new frame with id: {}", id);
+        Ok(id)
+    }
 
-        // Commit the transaction
-        tx.commit().await?;
-        // debug!("insert_frame Transaction committed");
+    /// Writes a captured frame with its ocr, or a transcription with its chunk, in one
+    /// transaction. Busy databases are retried, and with a spill journal configured a
+    /// write the database stays locked for is spilled instead of failing.
+    pub async fn write_capture(&self, write: CaptureWrite) -> Result<CaptureOutcome, sqlx::Error> {
+        let (timestamp, monotonic_ns) = match &write {
+            CaptureWrite::Frame(FrameWrite {
+                timestamp: Some(timestamp),
+                ..
+            }) => (*timestamp, None),
+            _ => {
+                let stamp = self.clock.now();
+                (stamp.wall, Some(stamp.monotonic_ns))
+            }
+        };
+        let Some(spill) = &self.spill else {
+            return self
+                .apply_capture(&write, timestamp, monotonic_ns)
+                .await
+                .map(CaptureOutcome::Written);
+        };
 
-        Ok(id)
+        let _order = spill.order().await;
+        // Nothing overtakes spilled writes, frame offsets follow the order of writes
+        if self.write_metrics.pending.load(Ordering::Relaxed) == 0 {
+            match self.apply_capture(&write, timestamp, monotonic_ns).await {
+                Err(e) if is_busy(&e) => {}
+                result => return result.map(CaptureOutcome::Written),
+            }
+        }
+
+        let mut write = write;
+        if let CaptureWrite::Frame(frame) = &mut write {
+            // Pin the chunk the frame was encoded into, another may start before the replay
+            if frame.video_chunk_id.is_none() && frame.storage_mode.keeps_video() {
+                frame.video_chunk_id = sqlx::query_scalar(LATEST_VIDEO_CHUNK)
+                    .bind(&frame.device_name)
+                    .fetch_optional(&self.pool)
+                    .await
+                    .unwrap_or_default();
+            }
+        }
+        spill.append(&SpillEntry {
+            write,
+            timestamp,
+            monotonic_ns,
+            pid: std::process::id(),
+        })?;
+        self.write_metrics.spilled.fetch_add(1, Ordering::Relaxed);
+        self.write_metrics.pending.fetch_add(1, Ordering::Relaxed);
+        Ok(CaptureOutcome::Spilled)
+    }
+
+    /// Commits the spilled writes in the order they were spilled, until the database
+    /// is busy again. Returns how many were committed.
+    pub async fn replay_spill_journal(&self) -> Result<usize, sqlx::Error> {
+        let Some(spill) = &self.spill else {
+            return Ok(0);
+        };
+        let _order = spill.order().await;
+        let entries = spill.read()?;
+        let mut done = 0;
+        let mut replayed = 0;
+        for entry in &entries {
+            // Monotonic stamps only order the items of the process that took them
+            let monotonic_ns = entry
+                .monotonic_ns
+                .filter(|_| entry.pid == std::process::id());
+            match self
+                .apply_capture(&entry.write, entry.timestamp, monotonic_ns)
+                .await
+            {
+                Ok(_) => replayed += 1,
+                Err(e) if is_busy(&e) => break,
+                Err(e) => {
+                    error!("dropping spilled write {:?}: {}", entry.write, e);
+                    self.write_metrics.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            done += 1;
+        }
+        spill.rewrite(&entries[done..])?;
+        self.write_metrics
+            .pending
+            .store((entries.len() - done) as u64, Ordering::Relaxed);
+        self.write_metrics
+            .replayed
+            .fetch_add(replayed as u64, Ordering::Relaxed);
+        Ok(replayed)
+    }
+
+    async fn apply_capture(
+        &self,
+        write: &CaptureWrite,
+        timestamp: DateTime<Utc>,
+        monotonic_ns: Option<u64>,
+    ) -> Result<i64, sqlx::Error> {
+        let monotonic_ns = monotonic_ns.map(|ns| ns as i64);
+        // Once, whatever the retries
+        let outcome = &match rule_input(write, timestamp) {
+            Some(input) => self.tag_rules().evaluate(&input, rule_metrics()),
+            None => RuleOutcome::default(),
+        };
+        retry_busy(
+            &self.write_retry,
+            &self.write_metrics,
+            "write_capture",
+            || async move {
+                let mut tx = self.pool.begin().await?;
+                let (content, id) = match write {
+                    CaptureWrite::Frame(frame) => (
+                        TagContentType::Vision,
+                        Self::insert_captured_frame(&mut tx, frame, timestamp, monotonic_ns)
+                            .await?,
+                    ),
+                    CaptureWrite::Transcription(transcription) => (
+                        TagContentType::Audio,
+                        Self::insert_captured_transcription(
+                            &mut tx,
+                            transcription,
+                            timestamp,
+                            monotonic_ns,
+                        )
+                        .await?,
+                    ),
+                };
+                if id != 0 && !outcome.is_empty() {
+                    Self::apply_rule_outcome(&mut tx, content, id, outcome).await?;
+                }
+                tx.commit().await?;
+                Ok(id)
+            },
+        )
+        .await
+    }
+
+    /// Writes what the tag rules decided for a frame or an audio chunk. An audio chunk
+    /// stays sensitive, and keeps its first class, whatever later transcriptions match.
+    async fn apply_rule_outcome(
+        conn: &mut SqliteConnection,
+        content: TagContentType,
+        id: i64,
+        outcome: &RuleOutcome,
+    ) -> Result<(), sqlx::Error> {
+        let (table, tags_table, id_column) = match content {
+            TagContentType::Vision => ("frames", "vision_tags", "vision_id"),
+            TagContentType::Audio => ("audio_chunks", "audio_tags", "audio_chunk_id"),
+        };
+        for tag in &outcome.tags {
+            let tag_id: i64 = sqlx::query_scalar(
+                "INSERT INTO tags (name) VALUES (?1) ON CONFLICT(name) DO UPDATE SET name=name RETURNING id",
+            )
+            .bind(&tag.tag)
+            .fetch_one(&mut *conn)
+            .await?;
+            sqlx::query(&format!(
+                "INSERT INTO {} ({}, tag_id, rule) VALUES (?1, ?2, ?3) ON CONFLICT DO NOTHING",
+                tags_table, id_column
+            ))
+            .bind(id)
+            .bind(tag_id)
+            .bind(&tag.rule)
+            .execute(&mut *conn)
+            .await?;
+        }
+        if outcome.sensitive || outcome.retention_class.is_some() {
+            sqlx::query(&format!(
+                "UPDATE {} SET sensitive = MAX(sensitive, ?1), retention_class = COALESCE(retention_class, ?2) WHERE id = ?3",
+                table
+            ))
+            .bind(outcome.sensitive)
+            .bind(&outcome.retention_class)
+            .bind(id)
+            .execute(&mut *conn)
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn insert_captured_frame(
+        conn: &mut SqliteConnection,
+        frame: &FrameWrite,
+        timestamp: DateTime<Utc>,
+        monotonic_ns: Option<i64>,
+    ) -> Result<i64, sqlx::Error> {
+        let video_chunk_id = match frame.video_chunk_id {
+            None if !frame.storage_mode.keeps_video() => {
+                Some(Self::text_only_chunk(conn, &frame.device_name).await?)
+            }
+            video_chunk_id => video_chunk_id,
+        };
+        let frame_id = Self::insert_frame_row(
+            conn,
+            &frame.device_name,
+            video_chunk_id,
+            timestamp,
+            monotonic_ns,
+            frame.storage_mode,
+            frame.thumbnail_path.as_deref(),
+        )
+        .await?;
+        if frame_id == 0 {
+            return Ok(0);
+        }
+        for window in &frame.windows {
+            sqlx::query("INSERT INTO ocr_text (frame_id, text, text_json, app_name, ocr_engine, window_name, focused, raw_text, corrected_by) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)")
+                .bind(frame_id)
+                .bind(&window.text)
+                .bind(&window.text_json)
+                .bind(&window.app_name)
+                .bind(&window.ocr_engine)
+                .bind(&window.window_name)
+                .bind(window.focused)
+                .bind(&window.raw_text)
+                .bind(window.corrected_by.map(|source| source.as_str()))
+                .execute(&mut *conn)
+                .await?;
+        }
+        if let Some(layout) = &frame.window_layout {
+            Self::insert_frame_layout(conn, frame_id, frame, layout).await?;
+        }
+        Ok(frame_id)
+    }
+
+    /// Stores the window layout of a frame row, with the bounds of the window its text
+    /// was read from, or of the focused window when that one isn't in the layout.
+    async fn insert_frame_layout(
+        conn: &mut SqliteConnection,
+        frame_id: i64,
+        frame: &FrameWrite,
+        layout: &WindowLayout,
+    ) -> Result<(), sqlx::Error> {
+        let window = frame
+            .windows
+            .first()
+            .and_then(|ocr| {
+                layout.windows.iter().find(|window| {
+                    window.app_name == ocr.app_name && window.window_name == ocr.window_name
+                })
+            })
+            .or_else(|| layout.focused());
+        let rect = window.map(|window| window.rect);
+        sqlx::query("INSERT OR REPLACE INTO frame_layouts (frame_id, window_x, window_y, window_width, window_height, layout) VALUES (?1, ?2, ?3, ?4, ?5, ?6)")
+            .bind(frame_id)
+            .bind(rect.map(|rect| rect.x))
+            .bind(rect.map(|rect| rect.y))
+            .bind(rect.map(|rect| rect.width as i64))
+            .bind(rect.map(|rect| rect.height as i64))
+            .bind(serde_json::to_string(layout).unwrap_or_default())
+            .execute(&mut *conn)
+            .await?;
+        Ok(())
+    }
+
+    /// The chunk frames without video of `device_name` go to. Its file path is empty,
+    /// like the chunk paths of rows with nothing on disk elsewhere.
+    async fn text_only_chunk(
+        conn: &mut SqliteConnection,
+        device_name: &str,
+    ) -> Result<i64, sqlx::Error> {
+        let id: Option<i64> = sqlx::query_scalar(
+            "SELECT id FROM video_chunks WHERE device_name = ?1 AND file_path = '' ORDER BY id DESC LIMIT 1",
+        )
+        .bind(device_name)
+        .fetch_optional(&mut *conn)
+        .await?;
+        match id {
+            Some(id) => Ok(id),
+            None => Ok(sqlx::query(
+                "INSERT INTO video_chunks (file_path, device_name) VALUES ('', ?1)",
+            )
+            .bind(device_name)
+            .execute(&mut *conn)
+            .await?
+            .last_insert_rowid()),
+        }
+    }
+
+    async fn insert_captured_transcription(
+        conn: &mut SqliteConnection,
+        write: &TranscriptionWrite,
+        timestamp: DateTime<Utc>,
+        monotonic_ns: Option<i64>,
+    ) -> Result<i64, sqlx::Error> {
+        let chunk_id: Option<i64> =
+            sqlx::query_scalar("SELECT id FROM audio_chunks WHERE file_path = ?1")
+                .bind(&write.file_path)
+                .fetch_optional(&mut *conn)
+                .await?;
+        let chunk_id = match chunk_id {
+            Some(id) => id,
+            None => {
+                Self::insert_audio_chunk_row(conn, &write.file_path, timestamp, monotonic_ns)
+                    .await?
+            }
+        };
+        if write.transcription.is_empty() {
+            return Ok(chunk_id);
+        }
+        sqlx::query(
+            "INSERT INTO audio_transcriptions (audio_chunk_id, transcription, offset_index, timestamp, transcription_engine, device, is_input_device, speaker_id, start_time, end_time, monotonic_ns) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+        )
+        .bind(chunk_id)
+        .bind(&write.transcription)
+        .bind(write.offset_index)
+        .bind(timestamp)
+        .bind(&write.transcription_engine)
+        .bind(&write.device_name)
+        .bind(write.is_input_device)
+        .bind(write.speaker_id)
+        .bind(write.start_time)
+        .bind(write.end_time)
+        .bind(monotonic_ns)
+        .execute(&mut *conn)
+        .await?;
+        Ok(chunk_id)
     }
 
     pub async fn insert_ocr_text(
@@ -468,229 +1004,76 @@ impl DatabaseManager {
         max_length: Option<usize>,
         speaker_ids: Option<Vec<i64>>,
     ) -> Result<Vec<SearchResult>, sqlx::Error> {
-        let mut results = Vec::new();
+        self.search_with_filters(
+            query,
+            content_type,
+            limit,
+            offset,
+            start_time,
+            end_time,
+            app_name,
+            window_name,
+            min_length,
+            max_length,
+            speaker_ids,
+            &SearchFilters::default(),
+        )
+        .await
+    }
 
-        match content_type {
-            ContentType::All => {
-                let (ocr_results, audio_results, ui_results) =
-                    if app_name.is_none() && window_name.is_none() {
-                        // Run all three queries in parallel
-                        let (ocr, audio, ui) = tokio::try_join!(
-                            self.search_ocr(
-                                query,
-                                limit,
-                                offset,
-                                start_time,
-                                end_time,
-                                app_name,
-                                window_name,
-                                min_length,
-                                max_length,
-                            ),
-                            self.search_audio(
-                                query,
-                                limit,
-                                offset,
-                                start_time,
-                                end_time,
-                                min_length,
-                                max_length,
-                                speaker_ids
-                            ),
-                            self.search_ui_monitoring(
-                                query,
-                                app_name,
-                                window_name,
-                                start_time,
-                                end_time,
-                                limit,
-                                offset,
-                            )
-                        )?;
-                        (ocr, Some(audio), ui)
-                    } else {
-                        // Run only OCR and UI queries in parallel when app/window filters are present
-                        let (ocr, ui) = tokio::try_join!(
-                            self.search_ocr(
-                                query,
-                                limit,
-                                offset,
-                                start_time,
-                                end_time,
-                                app_name,
-                                window_name,
-                                min_length,
-                                max_length,
-                            ),
-                            self.search_ui_monitoring(
-                                query,
-                                app_name,
-                                window_name,
-                                start_time,
-                                end_time,
-                                limit,
-                                offset,
-                            )
-                        )?;
-                        (ocr, None, ui)
-                    };
+    /// `search` with the extra filters of the search query language.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn search_with_filters(
+        &self,
+        query: &str,
+        content_type: ContentType,
+        limit: u32,
+        offset: u32,
+        start_time: Option<DateTime<Utc>>,
+        end_time: Option<DateTime<Utc>>,
+        app_name: Option<&str>,
+        window_name: Option<&str>,
+        min_length: Option<usize>,
+        max_length: Option<usize>,
+        speaker_ids: Option<Vec<i64>>,
+        filters: &SearchFilters,
+    ) -> Result<Vec<SearchResult>, sqlx::Error> {
+        let mut results = self
+            .fetch_search_results(
+                query,
+                content_type,
+                limit,
+                offset,
+                start_time,
+                end_time,
+                app_name,
+                window_name,
+                min_length,
+                max_length,
+                speaker_ids,
+                filters,
+                SearchOrder::Recent,
+            )
+            .await?;
 
-                results.extend(ocr_results.into_iter().map(SearchResult::OCR));
-                if let Some(audio) = audio_results {
-                    results.extend(audio.into_iter().map(SearchResult::Audio));
-                }
-                results.extend(ui_results.into_iter().map(SearchResult::UI));
-            }
-            ContentType::OCR => {
-                let ocr_results = self
-                    .search_ocr(
-                        query,
-                        limit,
-                        offset,
-                        start_time,
-                        end_time,
-                        app_name,
-                        window_name,
-                        min_length,
-                        max_length,
-                    )
-                    .await?;
-                results.extend(ocr_results.into_iter().map(SearchResult::OCR));
-            }
-            ContentType::Audio => {
-                if app_name.is_none() && window_name.is_none() {
-                    let audio_results = self
-                        .search_audio(
-                            query,
-                            limit,
-                            offset,
-                            start_time,
-                            end_time,
-                            min_length,
-                            max_length,
-                            speaker_ids,
-                        )
-                        .await?;
-                    results.extend(audio_results.into_iter().map(SearchResult::Audio));
-                }
-            }
-            ContentType::UI => {
-                let ui_results = self
-                    .search_ui_monitoring(
-                        query,
-                        app_name,
-                        window_name,
-                        start_time,
-                        end_time,
-                        limit,
-                        offset,
-                    )
-                    .await?;
-                results.extend(ui_results.into_iter().map(SearchResult::UI));
-            }
-            ContentType::AudioAndUi => {
-                let audio_results = self
-                    .search_audio(
-                        query,
-                        limit / 2,
-                        offset,
-                        start_time,
-                        end_time,
-                        min_length,
-                        max_length,
-                        speaker_ids,
-                    )
-                    .await?;
-                let ui_results = self
-                    .search_ui_monitoring(
-                        query,
-                        app_name,
-                        window_name,
-                        start_time,
-                        end_time,
-                        limit / 2,
-                        offset,
-                    )
-                    .await?;
-
-                results.extend(audio_results.into_iter().map(SearchResult::Audio));
-                results.extend(ui_results.into_iter().map(SearchResult::UI));
-            }
-            ContentType::OcrAndUi => {
-                let ocr_results = self
-                    .search_ocr(
-                        query,
-                        limit / 2,
-                        offset,
-                        start_time,
-                        end_time,
-                        app_name,
-                        window_name,
-                        min_length,
-                        max_length,
-                    )
-                    .await?;
-                let ui_results = self
-                    .search_ui_monitoring(
-                        query,
-                        app_name,
-                        window_name,
-                        start_time,
-                        end_time,
-                        limit / 2,
-                        offset,
-                    )
-                    .await?;
-
-                results.extend(ocr_results.into_iter().map(SearchResult::OCR));
-                results.extend(ui_results.into_iter().map(SearchResult::UI));
-            }
-            ContentType::AudioAndOcr => {
-                let audio_results = self
-                    .search_audio(
-                        query,
-                        limit / 2,
-                        offset,
-                        start_time,
-                        end_time,
-                        min_length,
-                        max_length,
-                        speaker_ids,
-                    )
-                    .await?;
-                let ocr_results = self
-                    .search_ocr(
-                        query,
-                        limit / 2,
-                        offset,
-                        start_time,
-                        end_time,
-                        app_name,
-                        window_name,
-                        min_length,
-                        max_length,
-                    )
-                    .await?;
-
-                results.extend(audio_results.into_iter().map(SearchResult::Audio));
-                results.extend(ocr_results.into_iter().map(SearchResult::OCR));
-            }
-        }
-
-        // Sort results by timestamp in descending order
-        results.sort_by(|a, b| {
-            let timestamp_a = match a {
-                SearchResult::OCR(ocr) => ocr.timestamp,
-                SearchResult::Audio(audio) => audio.timestamp,
-                SearchResult::UI(ui) => ui.timestamp,
-            };
-            let timestamp_b = match b {
-                SearchResult::OCR(ocr) => ocr.timestamp,
-                SearchResult::Audio(audio) => audio.timestamp,
-                SearchResult::UI(ui) => ui.timestamp,
-            };
-            timestamp_b.cmp(&timestamp_a)
-        });
+        // Sort results by timestamp in descending order
+        results.sort_by(|a, b| {
+            let timestamp_a = match a {
+                SearchResult::OCR(ocr) => ocr.timestamp,
+                SearchResult::Audio(audio) => audio.timestamp,
+                SearchResult::UI(ui) => ui.timestamp,
+                SearchResult::Document(document) => document.timestamp,
+                SearchResult::Pipe(record) => record.timestamp,
+            };
+            let timestamp_b = match b {
+                SearchResult::OCR(ocr) => ocr.timestamp,
+                SearchResult::Audio(audio) => audio.timestamp,
+                SearchResult::UI(ui) => ui.timestamp,
+                SearchResult::Document(document) => document.timestamp,
+                SearchResult::Pipe(record) => record.timestamp,
+            };
+            timestamp_b.cmp(&timestamp_a)
+        });
 
         // Apply offset and limit after sorting
         results = results
@@ -702,6 +1085,163 @@ impl DatabaseManager {
         Ok(results)
     }
 
+    /// Candidates for relevance ranking: the `pool` best bm25 matches of each content type.
+    /// Always fetched from the top so every page is ranked from the same set.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn search_candidates(
+        &self,
+        query: &str,
+        content_type: ContentType,
+        pool: u32,
+        start_time: Option<DateTime<Utc>>,
+        end_time: Option<DateTime<Utc>>,
+        app_name: Option<&str>,
+        window_name: Option<&str>,
+        min_length: Option<usize>,
+        max_length: Option<usize>,
+        speaker_ids: Option<Vec<i64>>,
+        filters: &SearchFilters,
+    ) -> Result<Vec<SearchResult>, sqlx::Error> {
+        self.fetch_search_results(
+            query,
+            content_type,
+            pool,
+            0,
+            start_time,
+            end_time,
+            app_name,
+            window_name,
+            min_length,
+            max_length,
+            speaker_ids,
+            filters,
+            SearchOrder::Relevance,
+        )
+        .await
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn fetch_search_results(
+        &self,
+        query: &str,
+        content_type: ContentType,
+        limit: u32,
+        offset: u32,
+        start_time: Option<DateTime<Utc>>,
+        end_time: Option<DateTime<Utc>>,
+        app_name: Option<&str>,
+        window_name: Option<&str>,
+        min_length: Option<usize>,
+        max_length: Option<usize>,
+        speaker_ids: Option<Vec<i64>>,
+        filters: &SearchFilters,
+        order: SearchOrder,
+    ) -> Result<Vec<SearchResult>, sqlx::Error> {
+        // Audio, documents and pipe content have no app or window, so those filters
+        // exclude them
+        let types: Vec<ContentType> = content_type
+            .parts()
+            .into_iter()
+            .filter(|t| filters.allows(t))
+            .filter(|t| {
+                !matches!(
+                    t,
+                    ContentType::Audio | ContentType::Document | ContentType::Pipe
+                ) || (app_name.is_none() && window_name.is_none())
+            })
+            .collect();
+
+        // Pairs split the page between their two types
+        let limit = match content_type {
+            ContentType::AudioAndUi | ContentType::OcrAndUi | ContentType::AudioAndOcr => limit / 2,
+            _ => limit,
+        };
+
+        let (ocr, audio, ui, documents, pipe_content) = tokio::try_join!(
+            async {
+                if !types.contains(&ContentType::OCR) {
+                    return Ok(Vec::new());
+                }
+                self.search_ocr(
+                    query,
+                    limit,
+                    offset,
+                    start_time,
+                    end_time,
+                    app_name,
+                    window_name,
+                    min_length,
+                    max_length,
+                    filters,
+                    order,
+                )
+                .await
+            },
+            async {
+                if !types.contains(&ContentType::Audio) {
+                    return Ok(Vec::new());
+                }
+                self.search_audio(
+                    query,
+                    limit,
+                    offset,
+                    start_time,
+                    end_time,
+                    min_length,
+                    max_length,
+                    speaker_ids,
+                    filters,
+                    order,
+                )
+                .await
+            },
+            async {
+                if !types.contains(&ContentType::UI) {
+                    return Ok(Vec::new());
+                }
+                self.search_ui_monitoring(
+                    query,
+                    app_name,
+                    window_name,
+                    start_time,
+                    end_time,
+                    limit,
+                    offset,
+                    filters,
+                    order,
+                )
+                .await
+            },
+            async {
+                if !types.contains(&ContentType::Document) {
+                    return Ok(Vec::new());
+                }
+                self.search_documents(
+                    query, limit, offset, start_time, end_time, min_length, max_length, order,
+                )
+                .await
+            },
+            async {
+                if !types.contains(&ContentType::Pipe) {
+                    return Ok(Vec::new());
+                }
+                self.search_pipe_content(
+                    query, limit, offset, start_time, end_time, min_length, max_length, filters,
+                    order,
+                )
+                .await
+            }
+        )?;
+
+        let mut results = Vec::new();
+        results.extend(ocr.into_iter().map(SearchResult::OCR));
+        results.extend(audio.into_iter().map(SearchResult::Audio));
+        results.extend(ui.into_iter().map(SearchResult::UI));
+        results.extend(documents.into_iter().map(SearchResult::Document));
+        results.extend(pipe_content.into_iter().map(SearchResult::Pipe));
+        Ok(results)
+    }
+
     async fn search_ocr(
         &self,
         query: &str,
@@ -713,6 +1253,8 @@ impl DatabaseManager {
         window_name: Option<&str>,
         min_length: Option<usize>,
         max_length: Option<usize>,
+        filters: &SearchFilters,
+        order: SearchOrder,
     ) -> Result<Vec<OCRResult>, sqlx::Error> {
         let base_sql = if query.is_empty() {
             "ocr_text"
@@ -720,6 +1262,17 @@ impl DatabaseManager {
             "ocr_text_fts JOIN ocr_text ON ocr_text_fts.frame_id = ocr_text.frame_id"
         };
 
+        let rank = if query.is_empty() {
+            "0.0"
+        } else {
+            "MIN(ocr_text_fts.rank)"
+        };
+
+        let order_by = match order {
+            SearchOrder::Recent => "frames.timestamp DESC",
+            SearchOrder::Relevance => "rank ASC, ocr_text.frame_id DESC",
+        };
+
         let where_clause = if query.is_empty() {
             "WHERE 1=1"
         } else {
@@ -738,10 +1291,17 @@ impl DatabaseManager {
                 ocr_text.app_name,
                 ocr_text.ocr_engine,
                 ocr_text.window_name,
-                GROUP_CONCAT(tags.name, ',') as tags
+                GROUP_CONCAT(tags.name, ',') as tags,
+                {} as rank,
+                frames.storage_mode,
+                frames.thumbnail_path,
+                video_chunks.archived,
+                ocr_text.raw_text,
+                ocr_text.corrected_by
             FROM {}
             JOIN frames ON ocr_text.frame_id = frames.id
             JOIN video_chunks ON frames.video_chunk_id = video_chunks.id
+            LEFT JOIN frame_layouts ON frame_layouts.frame_id = frames.id
             LEFT JOIN vision_tags ON frames.id = vision_tags.vision_id
             LEFT JOIN tags ON vision_tags.tag_id = tags.id
             {}
@@ -752,11 +1312,18 @@ impl DatabaseManager {
                 AND (?5 IS NULL OR LENGTH(ocr_text.text) <= ?5)
                 AND (?6 IS NULL OR ocr_text.app_name LIKE '%' || ?6 || '%' COLLATE NOCASE)
                 AND (?7 IS NULL OR ocr_text.window_name LIKE '%' || ?7 || '%' COLLATE NOCASE)
+                AND {}
+                AND {}
             GROUP BY ocr_text.frame_id
-            ORDER BY frames.timestamp DESC
+            ORDER BY {}
             LIMIT ?8 OFFSET ?9
             "#,
-            base_sql, where_clause
+            rank,
+            base_sql,
+            where_clause,
+            search_filters_sql(10, OCR_FILTERS),
+            window_geometry_sql(10),
+            order_by
         );
 
         let raw_results: Vec<OCRResultRaw> = sqlx::query_as(&sql)
@@ -769,6 +1336,7 @@ impl DatabaseManager {
             .bind(window_name)
             .bind(limit)
             .bind(offset)
+            .bind(filters.to_json())
             .fetch_all(&self.pool)
             .await?;
 
@@ -788,6 +1356,15 @@ impl DatabaseManager {
                     .tags
                     .map(|t| t.split(',').map(String::from).collect())
                     .unwrap_or_default(),
+                rank: raw.rank,
+                storage_mode: StorageMode::from_db(&raw.storage_mode),
+                thumbnail_path: raw.thumbnail_path,
+                archived: raw.archived,
+                raw_text: raw.raw_text,
+                corrected_by: raw
+                    .corrected_by
+                    .as_deref()
+                    .and_then(CorrectionSource::from_db),
             })
             .collect())
     }
@@ -802,6 +1379,8 @@ impl DatabaseManager {
         min_length: Option<usize>,
         max_length: Option<usize>,
         speaker_ids: Option<Vec<i64>>,
+        filters: &SearchFilters,
+        order: SearchOrder,
     ) -> Result<Vec<AudioResult>, sqlx::Error> {
         let mut json_array: String = "[]".to_string();
         if let Some(ids) = speaker_ids {
@@ -822,6 +1401,19 @@ impl DatabaseManager {
             "WHERE audio_transcriptions_fts MATCH ?1"
         };
 
+        let rank = if query.is_empty() {
+            "0.0"
+        } else {
+            "MIN(audio_transcriptions_fts.rank)"
+        };
+
+        let order_by = match order {
+            SearchOrder::Recent => "audio_transcriptions.timestamp DESC",
+            SearchOrder::Relevance => {
+                "rank ASC, audio_transcriptions.audio_chunk_id DESC, audio_transcriptions.offset_index DESC"
+            }
+        };
+
         let sql = format!(
             r#"
             SELECT
@@ -836,7 +1428,8 @@ impl DatabaseManager {
                 audio_transcriptions.is_input_device,
                 audio_transcriptions.speaker_id,
                 audio_transcriptions.start_time,
-                audio_transcriptions.end_time
+                audio_transcriptions.end_time,
+                {} as rank
             FROM {}
             JOIN audio_chunks ON audio_transcriptions.audio_chunk_id = audio_chunks.id
             LEFT JOIN speakers on audio_transcriptions.speaker_id = speakers.id
@@ -849,11 +1442,16 @@ impl DatabaseManager {
                 AND (?5 IS NULL OR LENGTH(audio_transcriptions.transcription) <= ?5)
                 AND (speakers.id IS NULL OR speakers.hallucination = 0)
                 AND (json_array_length(?6) = 0 OR audio_transcriptions.speaker_id IN (SELECT value FROM json_each(?6)))
+                AND {}
             GROUP BY audio_transcriptions.audio_chunk_id, audio_transcriptions.offset_index
-            ORDER BY audio_transcriptions.timestamp DESC
+            ORDER BY {}
             LIMIT ?7 OFFSET ?8
             "#,
-            base_sql, where_clause
+            rank,
+            base_sql,
+            where_clause,
+            search_filters_sql(9, AUDIO_FILTERS),
+            order_by
         );
 
         let raw_results: Vec<AudioResultRaw> = sqlx::query_as(&sql)
@@ -865,6 +1463,7 @@ impl DatabaseManager {
             .bind(json_array)
             .bind(limit)
             .bind(offset)
+            .bind(filters.to_json())
             .fetch_all(&self.pool)
             .await?;
 
@@ -897,6 +1496,7 @@ impl DatabaseManager {
                 speaker,
                 start_time: raw.start_time,
                 end_time: raw.end_time,
+                rank: raw.rank,
             })
         });
 
@@ -934,6 +1534,36 @@ impl DatabaseManager {
         min_length: Option<usize>,
         max_length: Option<usize>,
         speaker_ids: Option<Vec<i64>>,
+    ) -> Result<usize, sqlx::Error> {
+        self.count_search_results_with_filters(
+            query,
+            content_type,
+            start_time,
+            end_time,
+            app_name,
+            window_name,
+            min_length,
+            max_length,
+            speaker_ids,
+            &SearchFilters::default(),
+        )
+        .await
+    }
+
+    /// `count_search_results` with the extra filters of the search query language.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn count_search_results_with_filters(
+        &self,
+        query: &str,
+        content_type: ContentType,
+        start_time: Option<DateTime<Utc>>,
+        end_time: Option<DateTime<Utc>>,
+        app_name: Option<&str>,
+        window_name: Option<&str>,
+        min_length: Option<usize>,
+        max_length: Option<usize>,
+        speaker_ids: Option<Vec<i64>>,
+        filters: &SearchFilters,
     ) -> Result<usize, sqlx::Error> {
         let mut json_array: String = "[]".to_string();
         if let Some(ids) = speaker_ids {
@@ -942,14 +1572,58 @@ impl DatabaseManager {
             }
         }
 
-        let sql = match content_type {
-            ContentType::OCR => {
-                format!(
+        let (ocr_from, ocr_match) = if query.is_empty() {
+            ("ocr_text", "1=1")
+        } else {
+            (
+                "ocr_text_fts JOIN ocr_text ON ocr_text_fts.frame_id = ocr_text.frame_id",
+                "ocr_text_fts MATCH ?1",
+            )
+        };
+        let (audio_from, audio_match) = if query.is_empty() {
+            ("audio_transcriptions", "1=1")
+        } else {
+            (
+                "audio_transcriptions_fts JOIN audio_transcriptions ON audio_transcriptions_fts.rowid = audio_transcriptions.id",
+                "audio_transcriptions_fts MATCH ?1",
+            )
+        };
+        let (ui_from, ui_match) = if query.is_empty() {
+            ("ui_monitoring", "1=1")
+        } else {
+            (
+                "ui_monitoring_fts JOIN ui_monitoring ON ui_monitoring_fts.ui_id = ui_monitoring.id",
+                "ui_monitoring_fts MATCH ?1",
+            )
+        };
+        let (documents_from, documents_match) = if query.is_empty() {
+            ("documents", "1=1")
+        } else {
+            (
+                "documents_fts JOIN documents ON documents_fts.document_id = documents.id",
+                "documents_fts MATCH ?1",
+            )
+        };
+        let (pipe_from, pipe_match) = if query.is_empty() {
+            ("pipe_content", "1=1")
+        } else {
+            (
+                "pipe_content_fts JOIN pipe_content ON pipe_content_fts.content_id = pipe_content.id",
+                "pipe_content_fts MATCH ?1",
+            )
+        };
+
+        let counts: Vec<String> = content_type
+            .parts()
+            .into_iter()
+            .filter(|t| filters.allows(t))
+            .map(|t| match t {
+                ContentType::OCR => format!(
                     r#"
-                    SELECT COUNT(DISTINCT frames.id)
-                    FROM ocr_text_fts
-                    JOIN ocr_text ON ocr_text_fts.frame_id = ocr_text.frame_id
+                    SELECT DISTINCT frames.id
+                    FROM {}
                     JOIN frames ON ocr_text.frame_id = frames.id
+                    LEFT JOIN frame_layouts ON frame_layouts.frame_id = frames.id
                     WHERE {}
                         AND (?2 IS NULL OR frames.timestamp >= ?2)
                         AND (?3 IS NULL OR frames.timestamp <= ?3)
@@ -957,40 +1631,36 @@ impl DatabaseManager {
                         AND (?5 IS NULL OR ocr_text.window_name LIKE '%' || ?5 || '%')
                         AND (?6 IS NULL OR LENGTH(ocr_text.text) >= ?6)
                         AND (?7 IS NULL OR LENGTH(ocr_text.text) <= ?7)
+                        AND ocr_text.text != 'No text found'
+                        AND {}
+                        AND {}
                     "#,
-                    if query.is_empty() {
-                        "1=1"
-                    } else {
-                        "ocr_text_fts MATCH ?1"
-                    }
-                )
-            }
-            ContentType::Audio => {
-                format!(
+                    ocr_from,
+                    ocr_match,
+                    search_filters_sql(9, OCR_FILTERS),
+                    window_geometry_sql(9)
+                ),
+                ContentType::Audio => format!(
                     r#"
-                    SELECT COUNT(DISTINCT audio_transcriptions.audio_chunk_id || '_' || COALESCE(audio_transcriptions.start_time, '') || '_' || COALESCE(audio_transcriptions.end_time, ''))
-                    FROM audio_transcriptions_fts
-                    JOIN audio_transcriptions ON audio_transcriptions_fts.audio_chunk_id = audio_transcriptions.audio_chunk_id
+                    SELECT DISTINCT audio_transcriptions.id
+                    FROM {}
                     WHERE {}
                         AND (?2 IS NULL OR audio_transcriptions.timestamp >= ?2)
                         AND (?3 IS NULL OR audio_transcriptions.timestamp <= ?3)
                         AND (?6 IS NULL OR LENGTH(audio_transcriptions.transcription) >= ?6)
                         AND (?7 IS NULL OR LENGTH(audio_transcriptions.transcription) <= ?7)
+                        AND audio_transcriptions.transcription != ''
                         AND (json_array_length(?8) = 0 OR audio_transcriptions.speaker_id IN (SELECT value FROM json_each(?8)))
+                        AND {}
                     "#,
-                    if query.is_empty() {
-                        "1=1"
-                    } else {
-                        "audio_transcriptions_fts MATCH ?1"
-                    }
-                )
-            }
-            ContentType::UI => {
-                format!(
+                    audio_from,
+                    audio_match,
+                    search_filters_sql(9, AUDIO_FILTERS)
+                ),
+                ContentType::UI => format!(
                     r#"
-                    SELECT COUNT(DISTINCT ui_monitoring.id)
-                    FROM ui_monitoring_fts
-                    JOIN ui_monitoring ON ui_monitoring_fts.ui_id = ui_monitoring.id
+                    SELECT DISTINCT ui_monitoring.id
+                    FROM {}
                     WHERE {}
                         AND (?2 IS NULL OR ui_monitoring.timestamp >= ?2)
                         AND (?3 IS NULL OR ui_monitoring.timestamp <= ?3)
@@ -998,89 +1668,53 @@ impl DatabaseManager {
                         AND (?5 IS NULL OR ui_monitoring.window LIKE '%' || ?5 || '%')
                         AND (?6 IS NULL OR LENGTH(ui_monitoring.text_output) >= ?6)
                         AND (?7 IS NULL OR LENGTH(ui_monitoring.text_output) <= ?7)
+                        AND ui_monitoring.text_output != ''
+                        AND {}
                     "#,
-                    if query.is_empty() {
-                        "1=1"
-                    } else {
-                        "ui_monitoring_fts MATCH ?1"
-                    }
-                )
-            }
-            ContentType::All => {
-                format!(
+                    ui_from,
+                    ui_match,
+                    search_filters_sql(9, UI_FILTERS)
+                ),
+                ContentType::Pipe => format!(
                     r#"
-                    SELECT COUNT(*) FROM (
-                        SELECT DISTINCT frames.id
-                        FROM {}
-                        JOIN frames ON ocr_text.frame_id = frames.id
-                        WHERE {}
-                            AND (?2 IS NULL OR frames.timestamp >= ?2)
-                            AND (?3 IS NULL OR frames.timestamp <= ?3)
-                            AND (?4 IS NULL OR ocr_text.app_name LIKE '%' || ?4 || '%')
-                            AND (?5 IS NULL OR ocr_text.window_name LIKE '%' || ?5 || '%')
-                            AND (?6 IS NULL OR LENGTH(ocr_text.text) >= ?6)
-                            AND (?7 IS NULL OR LENGTH(ocr_text.text) <= ?7)
-                            AND ocr_text.text != 'No text found'
-
-                        UNION ALL
-
-                        SELECT DISTINCT audio_transcriptions.id
-                        FROM {}
-                        WHERE {}
-                            AND (?2 IS NULL OR audio_transcriptions.timestamp >= ?2)
-                            AND (?3 IS NULL OR audio_transcriptions.timestamp <= ?3)
-                            AND (?6 IS NULL OR LENGTH(audio_transcriptions.transcription) >= ?6)
-                            AND (?7 IS NULL OR LENGTH(audio_transcriptions.transcription) <= ?7)
-                            AND audio_transcriptions.transcription != ''
-                            AND (json_array_length(?8) = 0 OR audio_transcriptions.speaker_id IN (SELECT value FROM json_each(?8)))
-
-                        UNION ALL
-
-                        SELECT DISTINCT ui_monitoring.id
-                        FROM {}
-                        WHERE {}
-                            AND (?2 IS NULL OR ui_monitoring.timestamp >= ?2)
-                            AND (?3 IS NULL OR ui_monitoring.timestamp <= ?3)
-                            AND (?4 IS NULL OR ui_monitoring.app LIKE '%' || ?4 || '%')
-                            AND (?5 IS NULL OR ui_monitoring.window LIKE '%' || ?5 || '%')
-                            AND (?6 IS NULL OR LENGTH(ui_monitoring.text_output) >= ?6)
-                            AND (?7 IS NULL OR LENGTH(ui_monitoring.text_output) <= ?7)
-                            AND ui_monitoring.text_output != ''
-                    )"#,
-                    if query.is_empty() {
-                        "ocr_text"
-                    } else {
-                        "ocr_text_fts JOIN ocr_text ON ocr_text_fts.frame_id = ocr_text.frame_id"
-                    },
-                    if query.is_empty() {
-                        "1=1"
-                    } else {
-                        "ocr_text_fts MATCH ?1"
-                    },
-                    if query.is_empty() {
-                        "audio_transcriptions"
-                    } else {
-                        "audio_transcriptions_fts JOIN audio_transcriptions ON audio_transcriptions_fts.rowid = audio_transcriptions.id"
-                    },
-                    if query.is_empty() {
-                        "1=1"
-                    } else {
-                        "audio_transcriptions_fts MATCH ?1"
-                    },
-                    if query.is_empty() {
-                        "ui_monitoring"
-                    } else {
-                        "ui_monitoring_fts JOIN ui_monitoring ON ui_monitoring_fts.ui_id = ui_monitoring.id"
-                    },
-                    if query.is_empty() {
-                        "1=1"
-                    } else {
-                        "ui_monitoring_fts MATCH ?1"
-                    }
-                )
-            }
-            _ => return Ok(0),
-        };
+                    SELECT DISTINCT pipe_content.id
+                    FROM {}
+                    WHERE {}
+                        AND ?4 IS NULL
+                        AND ?5 IS NULL
+                        AND (?2 IS NULL OR pipe_content.timestamp >= ?2)
+                        AND (?3 IS NULL OR pipe_content.timestamp <= ?3)
+                        AND (?6 IS NULL OR LENGTH(pipe_content.text) >= ?6)
+                        AND (?7 IS NULL OR LENGTH(pipe_content.text) <= ?7)
+                        AND (?10 IS NULL OR pipe_content.content_type = ?10)
+                        AND {}
+                    "#,
+                    pipe_from,
+                    pipe_match,
+                    pipe_fields_sql(11)
+                ),
+                _ => format!(
+                    r#"
+                    SELECT DISTINCT documents.id
+                    FROM {}
+                    WHERE {}
+                        AND documents.deleted_at IS NULL
+                        AND ?4 IS NULL
+                        AND ?5 IS NULL
+                        AND (?2 IS NULL OR documents.timestamp >= ?2)
+                        AND (?3 IS NULL OR documents.timestamp <= ?3)
+                        AND (?6 IS NULL OR LENGTH(documents.text) >= ?6)
+                        AND (?7 IS NULL OR LENGTH(documents.text) <= ?7)
+                    "#,
+                    documents_from, documents_match
+                ),
+            })
+            .collect();
+
+        if counts.is_empty() {
+            return Ok(0);
+        }
+        let sql = format!("SELECT COUNT(*) FROM ({})", counts.join("\n UNION ALL \n"));
 
         let count: (i64,) = sqlx::query_as(&sql)
             .bind(query)
@@ -1091,90 +1725,373 @@ impl DatabaseManager {
             .bind(min_length.map(|len| len as i64))
             .bind(max_length.map(|len| len as i64))
             .bind(json_array)
+            .bind(filters.to_json())
+            .bind(filters.pipe_type.as_deref())
+            .bind(serde_json::to_string(&filters.fields).unwrap_or_else(|_| "[]".to_string()))
             .fetch_one(&self.pool)
             .await?;
 
         Ok(count.0 as usize)
     }
 
-    pub async fn get_latest_timestamps(
+    pub async fn get_frame_image_source(
         &self,
-    ) -> Result<
-        (
-            Option<DateTime<Utc>>,
-            Option<DateTime<Utc>>,
-            Option<DateTime<Utc>>,
-        ),
-        sqlx::Error,
-    > {
-        let latest_frame: Option<(DateTime<Utc>,)> =
-            sqlx::query_as("SELECT timestamp FROM frames ORDER BY timestamp DESC LIMIT 1")
-                .fetch_optional(&self.pool)
-                .await?;
+        frame_id: i64,
+    ) -> Result<Option<FrameImageSource>, sqlx::Error> {
+        let row = sqlx::query(
+            r#"
+            SELECT f.id, vc.file_path, f.offset_index, f.storage_mode, f.thumbnail_path, vc.archived
+            FROM frames f
+            JOIN video_chunks vc ON vc.id = f.video_chunk_id
+            WHERE f.id = ?1
+            "#,
+        )
+        .bind(frame_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row.map(|row| FrameImageSource {
+            frame_id: row.get("id"),
+            file_path: row.get("file_path"),
+            offset_index: row.get("offset_index"),
+            storage_mode: StorageMode::from_db(row.get("storage_mode")),
+            thumbnail_path: row.get("thumbnail_path"),
+            archived: row.get("archived"),
+        }))
+    }
 
-        let latest_audio: Option<(DateTime<Utc>,)> =
-            sqlx::query_as("SELECT timestamp FROM audio_chunks ORDER BY timestamp DESC LIMIT 1")
+    /// The OCR text of a frame's windows and its window layout.
+    pub async fn get_frame_content(
+        &self,
+        frame_id: i64,
+    ) -> Result<Option<FrameContent>, sqlx::Error> {
+        let frame: Option<(DateTime<Utc>, bool)> =
+            sqlx::query_as("SELECT timestamp, sensitive FROM frames WHERE id = ?1")
+                .bind(frame_id)
                 .fetch_optional(&self.pool)
                 .await?;
-
-        // Check if ui_monitoring table exists first
-        let latest_ui: Option<(DateTime<Utc>,)> = match sqlx::query_scalar::<_, i32>(
-            "SELECT 1 FROM sqlite_master WHERE type='table' AND name='ui_monitoring'",
+        let Some((timestamp, sensitive)) = frame else {
+            return Ok(None);
+        };
+        let windows = sqlx::query_as(
+            r#"
+            SELECT app_name, COALESCE(window_name, '') AS window_name, text, COALESCE(focused, 0) AS focused
+            FROM ocr_text
+            WHERE frame_id = ?1
+            ORDER BY focused DESC, rowid
+            "#,
         )
-        .fetch_optional(&self.pool)
-        .await?
-        {
-            Some(_) => {
-                sqlx::query_as(
-                    "SELECT timestamp FROM ui_monitoring ORDER BY timestamp DESC LIMIT 1",
-                )
-                .fetch_optional(&self.pool)
-                .await?
+        .bind(frame_id)
+        .fetch_all(&self.pool)
+        .await?;
+        let layout = self.get_frame_layouts(&[frame_id]).await?.remove(&frame_id);
+        Ok(Some(FrameContent {
+            frame_id,
+            timestamp,
+            windows,
+            layout,
+            sensitive,
+        }))
+    }
+
+    /// The last `limit` frames or audio chunks with transcripts, most recent first, as
+    /// tag rules see them. A frame has a row per window, focused window first.
+    pub async fn get_rule_samples(
+        &self,
+        content: TagContentType,
+        limit: u32,
+    ) -> Result<Vec<RuleSampleRow>, sqlx::Error> {
+        let sql = match content {
+            TagContentType::Vision => {
+                r#"
+                SELECT f.id, f.timestamp, o.app_name, o.window_name, o.text
+                FROM (SELECT id, timestamp FROM frames ORDER BY id DESC LIMIT ?1) f
+                LEFT JOIN ocr_text o ON o.frame_id = f.id
+                ORDER BY f.id DESC, o.focused DESC, o.rowid
+                "#
             }
-            None => {
-                debug!("ui_monitoring table does not exist");
-                None
+            TagContentType::Audio => {
+                r#"
+                SELECT
+                    ac.id,
+                    COALESCE(ac.timestamp, MIN(at.timestamp)) AS timestamp,
+                    NULL AS app_name,
+                    NULL AS window_name,
+                    group_concat(at.transcription, ' ') AS text
+                FROM (
+                    SELECT id, timestamp FROM audio_chunks c
+                    WHERE EXISTS (SELECT 1 FROM audio_transcriptions WHERE audio_chunk_id = c.id)
+                    ORDER BY id DESC
+                    LIMIT ?1
+                ) ac
+                JOIN audio_transcriptions at ON at.audio_chunk_id = ac.id
+                GROUP BY ac.id
+                ORDER BY ac.id DESC
+                "#
             }
         };
-
-        Ok((
-            latest_frame.map(|f| f.0),
-            latest_audio.map(|a| a.0),
-            latest_ui.map(|u| u.0),
-        ))
+        sqlx::query_as(sql).bind(limit).fetch_all(&self.pool).await
     }
 
-    pub async fn add_tags(
+    /// The windows of a frame whose OCR text matches `fts_query`, focused window first,
+    /// with the matches between `marks` by FTS5's `highlight()`. `None` if there is no
+    /// such frame.
+    pub async fn find_in_frame(
         &self,
-        id: i64,
-        content_type: TagContentType,
-        tags: Vec<String>,
-    ) -> Result<(), SqlxError> {
-        match content_type {
-            TagContentType::Vision => self.add_tags_to_vision(id, tags).await,
-            TagContentType::Audio => self.add_tags_to_audio(id, tags).await,
-        }
-    }
-
-    async fn add_tags_to_vision(&self, frame_id: i64, tags: Vec<String>) -> Result<(), SqlxError> {
-        let mut tx = self.pool.begin().await?;
-
-        for tag in tags {
-            // Insert tag if it doesn't exist
-            let tag_id: i64 = sqlx::query_scalar(
-                "INSERT INTO tags (name) VALUES (?) ON CONFLICT(name) DO UPDATE SET name=name RETURNING id",
-            )
-            .bind(&tag)
-            .fetch_one(&mut *tx)
-            .await?;
-
-            // Insert into vision_tags
-            sqlx::query(
-                "INSERT INTO vision_tags (vision_id, tag_id) VALUES (?, ?) ON CONFLICT DO NOTHING",
-            )
+        frame_id: i64,
+        fts_query: &str,
+        marks: (char, char),
+    ) -> Result<Option<Vec<FrameFindRow>>, sqlx::Error> {
+        let exists: Option<i64> = sqlx::query_scalar("SELECT id FROM frames WHERE id = ?1")
             .bind(frame_id)
-            .bind(tag_id)
-            .execute(&mut *tx)
+            .fetch_optional(&self.pool)
+            .await?;
+        if exists.is_none() {
+            return Ok(None);
+        }
+        let rows = sqlx::query_as(
+            r#"
+            SELECT
+                ocr_text.app_name,
+                COALESCE(ocr_text.window_name, '') AS window_name,
+                COALESCE(ocr_text.focused, 0) AS focused,
+                highlight(ocr_text_fts, 0, ?3, ?4) AS highlighted,
+                ocr_text.text_json
+            FROM ocr_text_fts
+            JOIN ocr_text ON ocr_text.frame_id = ocr_text_fts.frame_id
+                AND ocr_text.text = ocr_text_fts.text
+            WHERE ocr_text_fts MATCH ?1 AND ocr_text_fts.frame_id = ?2
+            ORDER BY focused DESC, ocr_text.rowid
+            "#,
+        )
+        .bind(fts_query)
+        .bind(frame_id)
+        .bind(marks.0.to_string())
+        .bind(marks.1.to_string())
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(Some(rows))
+    }
+
+    /// Closest frames captured before and after `frame_id` whose OCR text matches
+    /// `fts_query`, within the same filters as the OCR results of a search. Both are
+    /// looked up through the FTS index.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn adjacent_matching_frames(
+        &self,
+        frame_id: i64,
+        fts_query: &str,
+        start_time: Option<DateTime<Utc>>,
+        end_time: Option<DateTime<Utc>>,
+        app_name: Option<&str>,
+        window_name: Option<&str>,
+        filters: &SearchFilters,
+    ) -> Result<(Option<i64>, Option<i64>), sqlx::Error> {
+        let timestamp: Option<DateTime<Utc>> =
+            sqlx::query_scalar("SELECT timestamp FROM frames WHERE id = ?1")
+                .bind(frame_id)
+                .fetch_optional(&self.pool)
+                .await?;
+        let Some(timestamp) = timestamp else {
+            return Ok((None, None));
+        };
+
+        let adjacent = |before: bool| {
+            let (comparison, order) = if before { ("<", "DESC") } else { (">", "ASC") };
+            let sql = format!(
+                r#"
+                SELECT frames.id
+                FROM ocr_text_fts
+                JOIN ocr_text ON ocr_text.frame_id = ocr_text_fts.frame_id
+                JOIN frames ON frames.id = ocr_text.frame_id
+                WHERE ocr_text_fts MATCH ?1
+                    AND (frames.timestamp {comparison} ?2 OR (frames.timestamp = ?2 AND frames.id {comparison} ?3))
+                    AND (?4 IS NULL OR frames.timestamp >= ?4)
+                    AND (?5 IS NULL OR frames.timestamp <= ?5)
+                    AND (?6 IS NULL OR ocr_text.app_name LIKE '%' || ?6 || '%' COLLATE NOCASE)
+                    AND (?7 IS NULL OR ocr_text.window_name LIKE '%' || ?7 || '%' COLLATE NOCASE)
+                    AND {filters}
+                ORDER BY frames.timestamp {order}, frames.id {order}
+                LIMIT 1
+                "#,
+                filters = search_filters_sql(8, OCR_FILTERS),
+            );
+            async move {
+                sqlx::query_scalar::<_, i64>(&sql)
+                    .bind(fts_query)
+                    .bind(timestamp)
+                    .bind(frame_id)
+                    .bind(start_time)
+                    .bind(end_time)
+                    .bind(app_name)
+                    .bind(window_name)
+                    .bind(filters.to_json())
+                    .fetch_optional(&self.pool)
+                    .await
+            }
+        };
+        tokio::try_join!(adjacent(true), adjacent(false))
+    }
+
+    /// An audio chunk with the segments of its transcriptions, oldest first.
+    pub async fn get_audio_chunk_source(
+        &self,
+        chunk_id: i64,
+    ) -> Result<Option<AudioChunkSource>, sqlx::Error> {
+        let chunk: Option<(String, bool)> =
+            sqlx::query_as("SELECT file_path, archived FROM audio_chunks WHERE id = ?1")
+                .bind(chunk_id)
+                .fetch_optional(&self.pool)
+                .await?;
+        let Some((file_path, archived)) = chunk else {
+            return Ok(None);
+        };
+        let segments = sqlx::query_as(
+            "SELECT start_time, end_time FROM audio_transcriptions WHERE audio_chunk_id = ?1 ORDER BY start_time, offset_index",
+        )
+        .bind(chunk_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(Some(AudioChunkSource {
+            chunk_id,
+            file_path,
+            archived,
+            segments,
+        }))
+    }
+
+    /// Whether an audio chunk with this id was written and has since been deleted.
+    /// Chunk ids are never reused, so any id up to the latest one handed out was.
+    pub async fn audio_chunk_deleted(&self, chunk_id: i64) -> Result<bool, sqlx::Error> {
+        sqlx::query_scalar(
+            r#"
+            SELECT EXISTS(SELECT 1 FROM sqlite_sequence WHERE name = 'audio_chunks' AND seq >= ?1)
+                AND NOT EXISTS(SELECT 1 FROM audio_chunks WHERE id = ?1)
+            "#,
+        )
+        .bind(chunk_id)
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    /// Window layouts of the frames among `frame_ids` that have one.
+    pub async fn get_frame_layouts(
+        &self,
+        frame_ids: &[i64],
+    ) -> Result<HashMap<i64, WindowLayout>, sqlx::Error> {
+        let ids = serde_json::to_string(frame_ids).unwrap_or_else(|_| "[]".to_string());
+        let rows: Vec<(i64, String)> = sqlx::query_as(
+            "SELECT frame_id, layout FROM frame_layouts WHERE frame_id IN (SELECT value FROM json_each(?1))",
+        )
+        .bind(ids)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .filter_map(|(frame_id, layout)| match serde_json::from_str(&layout) {
+                Ok(layout) => Some((frame_id, layout)),
+                Err(e) => {
+                    warn!("unreadable window layout of frame {}: {}", frame_id, e);
+                    None
+                }
+            })
+            .collect())
+    }
+
+    /// Frames captured since `since` in each storage mode.
+    pub async fn count_frames_since(
+        &self,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<(StorageMode, i64)>, sqlx::Error> {
+        let counts: Vec<(String, i64)> = sqlx::query_as(
+            "SELECT storage_mode, COUNT(*) FROM frames WHERE timestamp >= ?1 GROUP BY storage_mode",
+        )
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(counts
+            .into_iter()
+            .map(|(mode, count)| (StorageMode::from_db(&mode), count))
+            .collect())
+    }
+
+    pub async fn get_latest_timestamps(
+        &self,
+    ) -> Result<
+        (
+            Option<DateTime<Utc>>,
+            Option<DateTime<Utc>>,
+            Option<DateTime<Utc>>,
+        ),
+        sqlx::Error,
+    > {
+        let latest_frame: Option<(DateTime<Utc>,)> =
+            sqlx::query_as("SELECT timestamp FROM frames ORDER BY timestamp DESC LIMIT 1")
+                .fetch_optional(&self.pool)
+                .await?;
+
+        let latest_audio: Option<(DateTime<Utc>,)> =
+            sqlx::query_as("SELECT timestamp FROM audio_chunks ORDER BY timestamp DESC LIMIT 1")
+                .fetch_optional(&self.pool)
+                .await?;
+
+        // Check if ui_monitoring table exists first
+        let latest_ui: Option<(DateTime<Utc>,)> = match sqlx::query_scalar::<_, i32>(
+            "SELECT 1 FROM sqlite_master WHERE type='table' AND name='ui_monitoring'",
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        {
+            Some(_) => {
+                sqlx::query_as(
+                    "SELECT timestamp FROM ui_monitoring ORDER BY timestamp DESC LIMIT 1",
+                )
+                .fetch_optional(&self.pool)
+                .await?
+            }
+            None => {
+                debug!("ui_monitoring table does not exist");
+                None
+            }
+        };
+
+        Ok((
+            latest_frame.map(|f| f.0),
+            latest_audio.map(|a| a.0),
+            latest_ui.map(|u| u.0),
+        ))
+    }
+
+    pub async fn add_tags(
+        &self,
+        id: i64,
+        content_type: TagContentType,
+        tags: Vec<String>,
+    ) -> Result<(), SqlxError> {
+        match content_type {
+            TagContentType::Vision => self.add_tags_to_vision(id, tags).await,
+            TagContentType::Audio => self.add_tags_to_audio(id, tags).await,
+        }
+    }
+
+    async fn add_tags_to_vision(&self, frame_id: i64, tags: Vec<String>) -> Result<(), SqlxError> {
+        let mut tx = self.pool.begin().await?;
+
+        for tag in tags {
+            // Insert tag if it doesn't exist
+            let tag_id: i64 = sqlx::query_scalar(
+                "INSERT INTO tags (name) VALUES (?) ON CONFLICT(name) DO UPDATE SET name=name RETURNING id",
+            )
+            .bind(&tag)
+            .fetch_one(&mut *tx)
+            .await?;
+
+            // Insert into vision_tags, a tag a rule added is now set by hand
+            sqlx::query(
+                "INSERT INTO vision_tags (vision_id, tag_id) VALUES (?, ?) ON CONFLICT DO UPDATE SET rule = NULL",
+            )
+            .bind(frame_id)
+            .bind(tag_id)
+            .execute(&mut *tx)
             .await?;
         }
 
@@ -1198,9 +2115,9 @@ impl DatabaseManager {
             .fetch_one(&mut *tx)
             .await?;
 
-            // Insert into audio_tags
+            // Insert into audio_tags, a tag a rule added is now set by hand
             sqlx::query(
-                "INSERT INTO audio_tags (audio_chunk_id, tag_id) VALUES (?, ?) ON CONFLICT DO NOTHING",
+                "INSERT INTO audio_tags (audio_chunk_id, tag_id) VALUES (?, ?) ON CONFLICT DO UPDATE SET rule = NULL",
             )
             .bind(audio_chunk_id)
             .bind(tag_id)
@@ -1341,178 +2258,1628 @@ impl DatabaseManager {
             })
             .collect();
 
-        Ok(serde_json::Value::Array(
-            result.into_iter().map(serde_json::Value::Object).collect(),
-        ))
+        Ok(serde_json::Value::Array(
+            result.into_iter().map(serde_json::Value::Object).collect(),
+        ))
+    }
+
+    // ! TODO: atm not sure what will happen if we have multiple transcriptions, OCR, etc for same timestamp (multi monitor, multi audio device...)
+    // ! just merging
+    // ! the offset is not quite right but we try to index around frames which is the central human experience and most important sense
+    // ! there should be a way to properly sync audio and video indexes
+    pub async fn find_video_chunks(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<TimeSeriesChunk, SqlxError> {
+        // First get all frames in time range with their OCR data
+        let frames_query = r#"
+            SELECT
+                f.timestamp,
+                f.offset_index,
+                ot.text,
+                ot.app_name,
+                ot.window_name,
+                vc.device_name as screen_device,
+                vc.file_path as video_path,
+                f.storage_mode,
+                f.thumbnail_path
+            FROM frames f
+            JOIN video_chunks vc ON f.video_chunk_id = vc.id
+            LEFT JOIN ocr_text ot ON f.id = ot.frame_id
+            WHERE f.timestamp >= ?1 AND f.timestamp <= ?2
+            ORDER BY f.timestamp DESC, f.offset_index DESC
+        "#;
+
+        // Then get audio data that overlaps with these frames
+        let audio_query = r#"
+            SELECT
+                at.timestamp,
+                at.transcription,
+                at.device as audio_device,
+                at.is_input_device,
+                ac.file_path as audio_path
+            FROM audio_transcriptions at
+            JOIN audio_chunks ac ON at.audio_chunk_id = ac.id
+            WHERE at.timestamp >= ?1 AND at.timestamp <= ?2
+            ORDER BY at.timestamp DESC
+        "#;
+
+        // Execute both queries
+        let (frame_rows, audio_rows) = tokio::try_join!(
+            sqlx::query(frames_query)
+                .bind(start)
+                .bind(end)
+                .fetch_all(&self.pool),
+            sqlx::query(audio_query)
+                .bind(start)
+                .bind(end)
+                .fetch_all(&self.pool)
+        )?;
+
+        // Process into structured data
+        let mut frames_map: BTreeMap<(DateTime<Utc>, i64), FrameData> = BTreeMap::new();
+
+        // Process frame/OCR data
+        for row in frame_rows {
+            let timestamp: DateTime<Utc> = row.get("timestamp");
+            let offset_index: i64 = row.get("offset_index");
+            let key = (timestamp, offset_index);
+
+            let frame_data = frames_map.entry(key).or_insert_with(|| FrameData {
+                timestamp,
+                offset_index,
+                ocr_entries: Vec::new(),
+                audio_entries: Vec::new(),
+            });
+
+            if let Ok(text) = row.try_get::<String, _>("text") {
+                frame_data.ocr_entries.push(OCREntry {
+                    text,
+                    app_name: row.get("app_name"),
+                    window_name: row.get("window_name"),
+                    device_name: row.get("screen_device"),
+                    video_file_path: row.get("video_path"),
+                    storage_mode: StorageMode::from_db(row.get("storage_mode")),
+                    thumbnail_path: row.get("thumbnail_path"),
+                });
+            }
+        }
+
+        // Process audio data
+        for row in audio_rows {
+            let timestamp: DateTime<Utc> = row.get("timestamp");
+
+            // Find the closest frame
+            if let Some((&key, _)) = frames_map.range(..(timestamp, i64::MAX)).next_back() {
+                if let Some(frame_data) = frames_map.get_mut(&key) {
+                    frame_data.audio_entries.push(AudioEntry {
+                        transcription: row.get("transcription"),
+                        device_name: row.get("audio_device"),
+                        is_input: row.get("is_input_device"),
+                        audio_file_path: row.get("audio_path"),
+                        // duration_secs: row.get("duration_secs"),
+                        duration_secs: 0.0, // TODO
+                    });
+                }
+            }
+        }
+
+        Ok(TimeSeriesChunk {
+            frames: frames_map.into_values().rev().collect(),
+            start_time: start,
+            end_time: end,
+        })
+    }
+
+    pub async fn search_ui_monitoring(
+        &self,
+        query: &str,
+        app_name: Option<&str>,
+        window_name: Option<&str>,
+        start_time: Option<DateTime<Utc>>,
+        end_time: Option<DateTime<Utc>>,
+        limit: u32,
+        offset: u32,
+        filters: &SearchFilters,
+        order: SearchOrder,
+    ) -> Result<Vec<UiContent>, sqlx::Error> {
+        let base_sql = if query.is_empty() {
+            "ui_monitoring"
+        } else {
+            "ui_monitoring_fts JOIN ui_monitoring ON ui_monitoring_fts.ui_id = ui_monitoring.id"
+        };
+
+        let where_clause = if query.is_empty() {
+            "WHERE 1=1"
+        } else {
+            "WHERE ui_monitoring_fts MATCH ?1"
+        };
+
+        let rank = if query.is_empty() {
+            "0.0"
+        } else {
+            "ui_monitoring_fts.rank"
+        };
+
+        let order_by = match order {
+            SearchOrder::Recent => "ui_monitoring.timestamp DESC",
+            SearchOrder::Relevance => "rank ASC, ui_monitoring.id DESC",
+        };
+
+        let sql = format!(
+            r#"
+            SELECT
+                ui_monitoring.id,
+                ui_monitoring.text_output,
+                ui_monitoring.timestamp,
+                ui_monitoring.app,
+                ui_monitoring.window,
+                ui_monitoring.initial_traversal_at,
+                video_chunks.file_path,
+                frames.offset_index,
+                {} as rank
+            FROM {}
+            LEFT JOIN frames ON
+                frames.timestamp BETWEEN
+                    datetime(ui_monitoring.timestamp, '-1 seconds')
+                    AND datetime(ui_monitoring.timestamp, '+1 seconds')
+            LEFT JOIN video_chunks ON frames.video_chunk_id = video_chunks.id
+            {}
+                AND (?2 IS NULL OR ui_monitoring.timestamp >= ?2)
+                AND (?3 IS NULL OR ui_monitoring.timestamp <= ?3)
+                AND (?4 IS NULL OR ui_monitoring.app LIKE '%' || ?4 || '%')
+                AND (?5 IS NULL OR ui_monitoring.window LIKE '%' || ?5 || '%')
+                AND {}
+            ORDER BY {}
+            LIMIT ?6 OFFSET ?7
+            "#,
+            rank,
+            base_sql,
+            where_clause,
+            search_filters_sql(8, UI_FILTERS),
+            order_by
+        );
+
+        sqlx::query_as(&sql)
+            .bind(query)
+            .bind(start_time)
+            .bind(end_time)
+            .bind(app_name)
+            .bind(window_name)
+            .bind(limit)
+            .bind(offset)
+            .bind(filters.to_json())
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub async fn search_documents(
+        &self,
+        query: &str,
+        limit: u32,
+        offset: u32,
+        start_time: Option<DateTime<Utc>>,
+        end_time: Option<DateTime<Utc>>,
+        min_length: Option<usize>,
+        max_length: Option<usize>,
+        order: SearchOrder,
+    ) -> Result<Vec<DocumentResult>, sqlx::Error> {
+        let (base_sql, where_clause, rank) = if query.is_empty() {
+            ("documents", "WHERE 1=1", "0.0")
+        } else {
+            (
+                "documents_fts JOIN documents ON documents_fts.document_id = documents.id",
+                "WHERE documents_fts MATCH ?1",
+                "documents_fts.rank",
+            )
+        };
+
+        let order_by = match order {
+            SearchOrder::Recent => "documents.timestamp DESC",
+            SearchOrder::Relevance => "rank ASC, documents.id DESC",
+        };
+
+        let sql = format!(
+            r#"
+            SELECT
+                documents.id,
+                documents.file_path,
+                documents.kind,
+                documents.text,
+                documents.timestamp,
+                documents.file_size,
+                {} as rank
+            FROM {}
+            {}
+                AND documents.deleted_at IS NULL
+                AND (?2 IS NULL OR documents.timestamp >= ?2)
+                AND (?3 IS NULL OR documents.timestamp <= ?3)
+                AND (?4 IS NULL OR LENGTH(documents.text) >= ?4)
+                AND (?5 IS NULL OR LENGTH(documents.text) <= ?5)
+            ORDER BY {}
+            LIMIT ?6 OFFSET ?7
+            "#,
+            rank, base_sql, where_clause, order_by
+        );
+
+        sqlx::query_as(&sql)
+            .bind(query)
+            .bind(start_time)
+            .bind(end_time)
+            .bind(min_length.map(|len| len as i64))
+            .bind(max_length.map(|len| len as i64))
+            .bind(limit)
+            .bind(offset)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    /// Inserts or re-indexes a watched file, clearing any previous tombstone.
+    pub async fn upsert_document(
+        &self,
+        file_path: &str,
+        kind: &str,
+        text: &str,
+        timestamp: DateTime<Utc>,
+        file_size: i64,
+    ) -> Result<i64, sqlx::Error> {
+        sqlx::query_scalar(
+            r#"
+            INSERT INTO documents (file_path, kind, text, timestamp, file_size, indexed_at, deleted_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, CURRENT_TIMESTAMP, NULL)
+            ON CONFLICT(file_path) DO UPDATE SET
+                kind = excluded.kind,
+                text = excluded.text,
+                timestamp = excluded.timestamp,
+                file_size = excluded.file_size,
+                indexed_at = CURRENT_TIMESTAMP,
+                deleted_at = NULL
+            RETURNING id
+            "#,
+        )
+        .bind(file_path)
+        .bind(kind)
+        .bind(text)
+        .bind(timestamp)
+        .bind(file_size)
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    pub async fn get_document_state(
+        &self,
+        file_path: &str,
+    ) -> Result<Option<DocumentState>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT id, timestamp, file_size, deleted_at FROM documents WHERE file_path = ?1",
+        )
+        .bind(file_path)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    /// Marks a document as deleted and drops its text. Returns false if it wasn't indexed.
+    pub async fn tombstone_document(&self, file_path: &str) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            "UPDATE documents SET deleted_at = CURRENT_TIMESTAMP, text = '' WHERE file_path = ?1 AND deleted_at IS NULL",
+        )
+        .bind(file_path)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Paths of live documents under `prefix`.
+    pub async fn list_document_paths(&self, prefix: &str) -> Result<Vec<String>, sqlx::Error> {
+        sqlx::query_scalar(
+            "SELECT file_path FROM documents WHERE deleted_at IS NULL AND substr(file_path, 1, length(?1)) = ?1",
+        )
+        .bind(prefix)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub async fn search_pipe_content(
+        &self,
+        query: &str,
+        limit: u32,
+        offset: u32,
+        start_time: Option<DateTime<Utc>>,
+        end_time: Option<DateTime<Utc>>,
+        min_length: Option<usize>,
+        max_length: Option<usize>,
+        filters: &SearchFilters,
+        order: SearchOrder,
+    ) -> Result<Vec<PipeContentResult>, sqlx::Error> {
+        let (base_sql, where_clause, rank) = if query.is_empty() {
+            ("pipe_content", "WHERE 1=1", "0.0")
+        } else {
+            (
+                "pipe_content_fts JOIN pipe_content ON pipe_content_fts.content_id = pipe_content.id",
+                "WHERE pipe_content_fts MATCH ?1",
+                "pipe_content_fts.rank",
+            )
+        };
+
+        let order_by = match order {
+            SearchOrder::Recent => "pipe_content.timestamp DESC",
+            SearchOrder::Relevance => "rank ASC, pipe_content.id DESC",
+        };
+
+        let sql = format!(
+            r#"
+            SELECT
+                pipe_content.id,
+                pipe_content.content_type,
+                pipe_content.pipe_id,
+                pipe_content.timestamp,
+                pipe_content.text,
+                pipe_content.fields,
+                {} as rank
+            FROM {}
+            {}
+                AND (?2 IS NULL OR pipe_content.timestamp >= ?2)
+                AND (?3 IS NULL OR pipe_content.timestamp <= ?3)
+                AND (?4 IS NULL OR LENGTH(pipe_content.text) >= ?4)
+                AND (?5 IS NULL OR LENGTH(pipe_content.text) <= ?5)
+                AND (?6 IS NULL OR pipe_content.content_type = ?6)
+                AND {}
+            ORDER BY {}
+            LIMIT ?8 OFFSET ?9
+            "#,
+            rank,
+            base_sql,
+            where_clause,
+            pipe_fields_sql(7),
+            order_by
+        );
+
+        let rows: Vec<PipeContentResultRaw> = sqlx::query_as(&sql)
+            .bind(query)
+            .bind(start_time)
+            .bind(end_time)
+            .bind(min_length.map(|len| len as i64))
+            .bind(max_length.map(|len| len as i64))
+            .bind(filters.pipe_type.as_deref())
+            .bind(serde_json::to_string(&filters.fields).unwrap_or_else(|_| "[]".to_string()))
+            .bind(limit)
+            .bind(offset)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows.into_iter().map(PipeContentResult::from).collect())
+    }
+
+    pub async fn get_pipe_content_type(
+        &self,
+        name: &str,
+    ) -> Result<Option<PipeContentTypeRow>, sqlx::Error> {
+        sqlx::query_as("SELECT * FROM pipe_content_types WHERE name = ?1")
+            .bind(name)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    pub async fn list_pipe_content_types(&self) -> Result<Vec<PipeContentTypeRow>, sqlx::Error> {
+        sqlx::query_as("SELECT * FROM pipe_content_types ORDER BY name")
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    /// Saves a content type's schema and, in the same transaction, the records
+    /// rewritten for it as `(id, fields, search_text)`.
+    pub async fn save_pipe_content_type(
+        &self,
+        name: &str,
+        pipe_id: &str,
+        schema: &str,
+        version: i64,
+        rewrites: &[(i64, String, String)],
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let now = Utc::now();
+        sqlx::query(
+            r#"
+            INSERT INTO pipe_content_types (name, pipe_id, schema, version, created_at, updated_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?5)
+            ON CONFLICT(name) DO UPDATE SET
+                schema = excluded.schema,
+                version = excluded.version,
+                updated_at = excluded.updated_at
+            "#,
+        )
+        .bind(name)
+        .bind(pipe_id)
+        .bind(schema)
+        .bind(version)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+
+        for (id, fields, search_text) in rewrites {
+            sqlx::query("UPDATE pipe_content SET fields = ?1, search_text = ?2 WHERE id = ?3")
+                .bind(fields)
+                .bind(search_text)
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Every record of a content type as `(id, text, fields)`, for schema migrations.
+    pub async fn get_pipe_content_records(
+        &self,
+        content_type: &str,
+    ) -> Result<Vec<(i64, String, String)>, sqlx::Error> {
+        sqlx::query_as("SELECT id, text, fields FROM pipe_content WHERE content_type = ?1")
+            .bind(content_type)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub async fn insert_pipe_content(
+        &self,
+        content_type: &str,
+        pipe_id: &str,
+        timestamp: DateTime<Utc>,
+        text: &str,
+        fields: &str,
+        search_text: &str,
+        context: bool,
+    ) -> Result<i64, sqlx::Error> {
+        let id = sqlx::query(
+            r#"
+            INSERT INTO pipe_content (content_type, pipe_id, timestamp, text, fields, search_text, context)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+            "#,
+        )
+        .bind(content_type)
+        .bind(pipe_id)
+        .bind(timestamp)
+        .bind(text)
+        .bind(fields)
+        .bind(search_text)
+        .bind(context)
+        .execute(&self.pool)
+        .await?
+        .last_insert_rowid();
+        Ok(id)
+    }
+
+    /// Removes the records and content types a pipe owns. Returns how many of each.
+    pub async fn delete_pipe_content(&self, pipe_id: &str) -> Result<(u64, u64), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let records = sqlx::query(
+            r#"
+            DELETE FROM pipe_content
+            WHERE pipe_id = ?1
+                OR content_type IN (SELECT name FROM pipe_content_types WHERE pipe_id = ?1)
+            "#,
+        )
+        .bind(pipe_id)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        let types = sqlx::query("DELETE FROM pipe_content_types WHERE pipe_id = ?1")
+            .bind(pipe_id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        tx.commit().await?;
+        Ok((types, records))
+    }
+
+    /// Schedules a run of `pipe_id`. `None` when the pipe already has `max_active` jobs
+    /// pending or running.
+    pub async fn insert_pipe_job(
+        &self,
+        pipe_id: &str,
+        run_at: DateTime<Utc>,
+        payload: &serde_json::Value,
+        max_attempts: i64,
+        backoff_seconds: i64,
+        max_active: i64,
+    ) -> Result<Option<i64>, sqlx::Error> {
+        // Counted in the insert itself so concurrent requests can't go over the cap
+        let result = sqlx::query(
+            r#"
+            INSERT INTO pipe_jobs (pipe_id, run_at, payload, max_attempts, backoff_seconds, created_at)
+            SELECT ?1, ?2, ?3, ?4, ?5, ?6
+            WHERE (
+                SELECT COUNT(*) FROM pipe_jobs
+                WHERE pipe_id = ?1 AND status IN ('pending', 'running')
+            ) < ?7
+            "#,
+        )
+        .bind(pipe_id)
+        .bind(run_at)
+        .bind(payload.to_string())
+        .bind(max_attempts)
+        .bind(backoff_seconds)
+        .bind(Utc::now())
+        .bind(max_active)
+        .execute(&self.pool)
+        .await?;
+        Ok((result.rows_affected() > 0).then(|| result.last_insert_rowid()))
+    }
+
+    pub async fn get_pipe_job(&self, id: i64) -> Result<Option<PipeJob>, sqlx::Error> {
+        let raw: Option<PipeJobRaw> = sqlx::query_as("SELECT * FROM pipe_jobs WHERE id = ?1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(raw.map(Into::into))
+    }
+
+    /// Jobs of `pipe_id`, optionally only those in `status`, soonest first.
+    pub async fn get_pipe_jobs(
+        &self,
+        pipe_id: &str,
+        status: Option<PipeJobStatus>,
+    ) -> Result<Vec<PipeJob>, sqlx::Error> {
+        let raw: Vec<PipeJobRaw> = sqlx::query_as(
+            "SELECT * FROM pipe_jobs WHERE pipe_id = ?1 AND (?2 IS NULL OR status = ?2) ORDER BY run_at, id",
+        )
+        .bind(pipe_id)
+        .bind(status.map(|s| s.as_str()))
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(raw.into_iter().map(Into::into).collect())
+    }
+
+    /// Cancels a job that hasn't started yet. False if there is no such pending job.
+    pub async fn cancel_pipe_job(&self, pipe_id: &str, id: i64) -> Result<bool, sqlx::Error> {
+        let cancelled = sqlx::query(
+            "UPDATE pipe_jobs SET status = 'cancelled', finished_at = ?3 WHERE id = ?1 AND pipe_id = ?2 AND status = 'pending'",
+        )
+        .bind(id)
+        .bind(pipe_id)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        Ok(cancelled > 0)
+    }
+
+    /// When the soonest pending job is due.
+    pub async fn next_pipe_job_due(&self) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+        sqlx::query_scalar(
+            "SELECT run_at FROM pipe_jobs WHERE status = 'pending' ORDER BY run_at LIMIT 1",
+        )
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    /// Marks the pending jobs due at `now` as running and returns them, counting the attempt.
+    pub async fn claim_due_pipe_jobs(
+        &self,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<PipeJob>, sqlx::Error> {
+        let raw: Vec<PipeJobRaw> = sqlx::query_as(
+            "UPDATE pipe_jobs SET status = 'running', attempts = attempts + 1 WHERE status = 'pending' AND run_at <= ?1 RETURNING *",
+        )
+        .bind(now)
+        .fetch_all(&self.pool)
+        .await?;
+        let mut jobs: Vec<PipeJob> = raw.into_iter().map(Into::into).collect();
+        jobs.sort_by_key(|job| (job.run_at, job.id));
+        Ok(jobs)
+    }
+
+    /// Puts a failed job back in the queue for another attempt at `run_at`.
+    pub async fn retry_pipe_job(
+        &self,
+        id: i64,
+        run_at: DateTime<Utc>,
+        error: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE pipe_jobs SET status = 'pending', run_at = ?2, last_error = ?3 WHERE id = ?1",
+        )
+        .bind(id)
+        .bind(run_at)
+        .bind(error)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn finish_pipe_job(
+        &self,
+        id: i64,
+        status: PipeJobStatus,
+        error: Option<&str>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE pipe_jobs SET status = ?2, last_error = COALESCE(?3, last_error), finished_at = ?4 WHERE id = ?1",
+        )
+        .bind(id)
+        .bind(status.as_str())
+        .bind(error)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Jobs left running by a process that exited go back to pending, their attempt
+    /// doesn't count. Returns how many.
+    pub async fn requeue_running_pipe_jobs(&self) -> Result<u64, sqlx::Error> {
+        let requeued = sqlx::query(
+            "UPDATE pipe_jobs SET status = 'pending', attempts = MAX(attempts - 1, 0) WHERE status = 'running'",
+        )
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        Ok(requeued)
+    }
+
+    /// Forgets jobs that finished before `before`.
+    pub async fn delete_finished_pipe_jobs(
+        &self,
+        before: DateTime<Utc>,
+    ) -> Result<u64, sqlx::Error> {
+        let deleted = sqlx::query(
+            "DELETE FROM pipe_jobs WHERE status IN ('done', 'failed', 'cancelled') AND finished_at < ?1",
+        )
+        .bind(before)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        Ok(deleted)
+    }
+
+    /// Adds one proxied request of `pipe_id` to `host` to the pipe's network stats.
+    pub async fn record_pipe_network(
+        &self,
+        pipe_id: &str,
+        host: &str,
+        bytes_up: u64,
+        bytes_down: u64,
+        blocked: bool,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO pipe_network_stats (pipe_id, host, bytes_up, bytes_down, requests, blocked, last_seen)
+            VALUES (?1, ?2, ?3, ?4, 1, ?5, ?6)
+            ON CONFLICT (pipe_id, host) DO UPDATE SET
+                bytes_up = bytes_up + excluded.bytes_up,
+                bytes_down = bytes_down + excluded.bytes_down,
+                requests = requests + 1,
+                blocked = blocked + excluded.blocked,
+                last_seen = excluded.last_seen
+            "#,
+        )
+        .bind(pipe_id)
+        .bind(host)
+        .bind(bytes_up as i64)
+        .bind(bytes_down as i64)
+        .bind(blocked as i64)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Network stats of `pipe_id` per host, the busiest first.
+    pub async fn get_pipe_network_stats(
+        &self,
+        pipe_id: &str,
+    ) -> Result<Vec<PipeNetworkStats>, sqlx::Error> {
+        sqlx::query_as(
+            r#"
+            SELECT host, bytes_up, bytes_down, requests, blocked, last_seen
+            FROM pipe_network_stats
+            WHERE pipe_id = ?1
+            ORDER BY bytes_up + bytes_down DESC, host
+            "#,
+        )
+        .bind(pipe_id)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Opens a capture session. `None` when another session is still open.
+    pub async fn start_capture_session(
+        &self,
+        name: &str,
+        started_at: DateTime<Utc>,
+        auto_export: Option<ExportFormat>,
+    ) -> Result<Option<CaptureSession>, sqlx::Error> {
+        // Checked in the insert itself so two concurrent starts can't both open one
+        let raw: Option<CaptureSessionRaw> = sqlx::query_as(
+            r#"
+            INSERT INTO capture_sessions (name, started_at, auto_export)
+            SELECT ?1, ?2, ?3
+            WHERE NOT EXISTS (SELECT 1 FROM capture_sessions WHERE ended_at IS NULL)
+            RETURNING *
+            "#,
+        )
+        .bind(name)
+        .bind(started_at)
+        .bind(auto_export.map(|f| f.as_str()))
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(raw.map(Into::into))
+    }
+
+    pub async fn get_open_capture_session(&self) -> Result<Option<CaptureSession>, sqlx::Error> {
+        let raw: Option<CaptureSessionRaw> = sqlx::query_as(
+            "SELECT * FROM capture_sessions WHERE ended_at IS NULL ORDER BY id DESC LIMIT 1",
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(raw.map(Into::into))
+    }
+
+    /// Closes session `id`. `None` if it was already closed, so a session ends once.
+    pub async fn end_capture_session(
+        &self,
+        id: i64,
+        ended_at: DateTime<Utc>,
+        reason: SessionEndReason,
+    ) -> Result<Option<CaptureSession>, sqlx::Error> {
+        let raw: Option<CaptureSessionRaw> = sqlx::query_as(
+            "UPDATE capture_sessions SET ended_at = ?2, end_reason = ?3 WHERE id = ?1 AND ended_at IS NULL RETURNING *",
+        )
+        .bind(id)
+        .bind(ended_at)
+        .bind(reason.as_str())
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(raw.map(Into::into))
+    }
+
+    pub async fn set_capture_session_export(
+        &self,
+        id: i64,
+        export_path: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE capture_sessions SET export_path = ?2 WHERE id = ?1")
+            .bind(id)
+            .bind(export_path)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Sessions named `name`, or all of them, latest first.
+    pub async fn get_capture_sessions(
+        &self,
+        name: Option<&str>,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<CaptureSession>, sqlx::Error> {
+        let raw: Vec<CaptureSessionRaw> = sqlx::query_as(
+            "SELECT * FROM capture_sessions WHERE (?1 IS NULL OR name = ?1) ORDER BY started_at DESC, id DESC LIMIT ?2 OFFSET ?3",
+        )
+        .bind(name)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(raw.into_iter().map(Into::into).collect())
+    }
+
+    /// The focused window of the latest frame captured between `start` and `end`.
+    pub async fn latest_focused_window(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Option<FocusedWindow>, sqlx::Error> {
+        sqlx::query_as(
+            r#"
+            SELECT frames.id AS frame_id, frames.timestamp, ocr_text.app_name,
+                ocr_text.window_name, ocr_text.text
+            FROM frames
+            JOIN ocr_text ON ocr_text.frame_id = frames.id AND ocr_text.focused = 1
+            WHERE frames.timestamp >= ?1 AND frames.timestamp <= ?2
+            ORDER BY frames.timestamp DESC, frames.id DESC
+            LIMIT 1
+            "#,
+        )
+        .bind(start)
+        .bind(end)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    /// Up to `limit` transcriptions between `start` and `end`, newest first.
+    pub async fn recent_transcripts(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        limit: u32,
+    ) -> Result<Vec<RecentTranscript>, sqlx::Error> {
+        sqlx::query_as(
+            r#"
+            SELECT id, timestamp, device AS device_name, is_input_device, speaker_id,
+                transcription AS text
+            FROM audio_transcriptions
+            WHERE timestamp >= ?1 AND timestamp <= ?2 AND transcription != ''
+            ORDER BY timestamp DESC, id DESC
+            LIMIT ?3
+            "#,
+        )
+        .bind(start)
+        .bind(end)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// The latest `limit` pipe records flagged as context, newest first.
+    pub async fn context_pipe_results(
+        &self,
+        limit: u32,
+    ) -> Result<Vec<ContextPipeResult>, sqlx::Error> {
+        sqlx::query_as(
+            r#"
+            SELECT id, pipe_id, content_type, timestamp, text
+            FROM pipe_content
+            WHERE context = 1
+            ORDER BY timestamp DESC, id DESC
+            LIMIT ?1
+            "#,
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Seconds each app was focused per bucket between `start` and `end`, from the
+    /// focused window of consecutive frames. A frame's time goes to its own bucket, and
+    /// gaps are rounded to the millisecond since julianday isn't exact.
+    pub async fn app_usage(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        bucket: UsageBucket,
+    ) -> Result<Vec<AppUsage>, sqlx::Error> {
+        let rows: Vec<(String, String, f64)> = sqlx::query_as(
+            r#"
+            WITH focus AS (
+                SELECT frames.timestamp AS ts, ocr_text.app_name AS app_name,
+                    LEAD(frames.timestamp) OVER (ORDER BY frames.timestamp, frames.id) AS next_ts
+                FROM frames
+                JOIN ocr_text ON ocr_text.frame_id = frames.id AND ocr_text.focused = 1
+                WHERE frames.timestamp >= ?1 AND frames.timestamp < ?2
+            )
+            SELECT strftime(?3, ts) AS bucket, app_name,
+                SUM(MIN(MAX(ROUND((julianday(next_ts) - julianday(ts)) * 86400.0, 3), 0), ?4)) AS seconds
+            FROM focus
+            WHERE next_ts IS NOT NULL AND app_name != ''
+            GROUP BY bucket, app_name
+            ORDER BY bucket, seconds DESC, app_name
+            "#,
+        )
+        .bind(start)
+        .bind(end)
+        .bind(bucket.strftime())
+        .bind(MAX_FOCUS_GAP_SECONDS)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .filter_map(|(bucket, app_name, seconds)| {
+                Some(AppUsage {
+                    bucket: parse_bucket(&bucket)?,
+                    app_name,
+                    seconds,
+                })
+            })
+            .collect())
+    }
+
+    /// Frames and transcriptions captured per bucket between `start` and `end`, buckets
+    /// without either are left out.
+    pub async fn capture_counts(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        bucket: UsageBucket,
+    ) -> Result<Vec<CaptureCounts>, sqlx::Error> {
+        let rows: Vec<(String, i64, i64)> = sqlx::query_as(
+            r#"
+            SELECT bucket, SUM(frames), SUM(transcriptions) FROM (
+                SELECT strftime(?3, timestamp) AS bucket, COUNT(*) AS frames, 0 AS transcriptions
+                FROM frames
+                WHERE timestamp >= ?1 AND timestamp < ?2
+                GROUP BY bucket
+                UNION ALL
+                SELECT strftime(?3, timestamp) AS bucket, 0 AS frames, COUNT(*) AS transcriptions
+                FROM audio_transcriptions
+                WHERE timestamp >= ?1 AND timestamp < ?2
+                GROUP BY bucket
+            )
+            GROUP BY bucket
+            ORDER BY bucket
+            "#,
+        )
+        .bind(start)
+        .bind(end)
+        .bind(bucket.strftime())
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .filter_map(|(bucket, frames, transcriptions)| {
+                Some(CaptureCounts {
+                    bucket: parse_bucket(&bucket)?,
+                    frames,
+                    transcriptions,
+                })
+            })
+            .collect())
+    }
+
+    /// Counts frames and transcriptions into the daily app rollups, at most `limit` of
+    /// each, after rebuilding the days deletions marked dirty. Rows are taken in id
+    /// order up to the first one captured after `settled_before`, text written after
+    /// its frame would be missed otherwise.
+    pub async fn update_app_rollups(
+        &self,
+        settled_before: DateTime<Utc>,
+        limit: u32,
+    ) -> Result<RollupProgress, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let (last_frame_id, last_transcription_id): (i64, i64) = sqlx::query_as(
+            "SELECT last_frame_id, last_transcription_id FROM app_rollup_state WHERE id = 1",
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        let mut progress = RollupProgress::default();
+
+        // Days are counted again from scratch, up to where the job got
+        let days: Vec<String> = sqlx::query_scalar("SELECT day FROM app_rollup_dirty_days")
+            .fetch_all(&mut *tx)
+            .await?;
+        for day in days {
+            sqlx::query("DELETE FROM app_daily_rollups WHERE day = ?1")
+                .bind(&day)
+                .execute(&mut *tx)
+                .await?;
+            if let Ok(date) = NaiveDate::parse_from_str(&day, "%Y-%m-%d") {
+                let start = date.and_time(NaiveTime::MIN).and_utc();
+                Self::rollup_range(
+                    &mut tx,
+                    (0, last_frame_id),
+                    (0, last_transcription_id),
+                    start,
+                    start + chrono::Duration::days(1),
+                )
+                .await?;
+            }
+            sqlx::query("DELETE FROM app_rollup_dirty_days WHERE day = ?1")
+                .bind(&day)
+                .execute(&mut *tx)
+                .await?;
+            progress.days_rebuilt += 1;
+        }
+
+        let frames_to =
+            Self::rollup_bound(&mut tx, "frames", last_frame_id, settled_before, limit).await?;
+        let transcriptions_to = Self::rollup_bound(
+            &mut tx,
+            "audio_transcriptions",
+            last_transcription_id,
+            settled_before,
+            limit,
+        )
+        .await?;
+        Self::rollup_range(
+            &mut tx,
+            (last_frame_id, frames_to),
+            (last_transcription_id, transcriptions_to),
+            // The epoch
+            DateTime::<Utc>::default(),
+            settled_before,
+        )
+        .await?;
+        progress.frames =
+            sqlx::query_scalar("SELECT COUNT(*) FROM frames WHERE id > ?1 AND id <= ?2")
+                .bind(last_frame_id)
+                .bind(frames_to)
+                .fetch_one(&mut *tx)
+                .await?;
+        progress.transcriptions = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM audio_transcriptions WHERE id > ?1 AND id <= ?2",
+        )
+        .bind(last_transcription_id)
+        .bind(transcriptions_to)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            "UPDATE app_rollup_state SET last_frame_id = ?1, last_transcription_id = ?2, updated_at = ?3 WHERE id = 1",
+        )
+        .bind(frames_to)
+        .bind(transcriptions_to)
+        .bind(Utc::now())
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(progress)
+    }
+
+    /// Last id of `table` the rollup job may count next: `limit` rows on at most, and
+    /// before the first row captured after `settled_before`.
+    async fn rollup_bound(
+        conn: &mut SqliteConnection,
+        table: &str,
+        after_id: i64,
+        settled_before: DateTime<Utc>,
+        limit: u32,
+    ) -> Result<i64, sqlx::Error> {
+        let first_unsettled: Option<i64> = sqlx::query_scalar(&format!(
+            "SELECT MIN(id) FROM {} WHERE id > ?1 AND timestamp >= ?2",
+            table
+        ))
+        .bind(after_id)
+        .bind(settled_before)
+        .fetch_one(&mut *conn)
+        .await?;
+        let last: Option<i64> = sqlx::query_scalar(&format!("SELECT MAX(id) FROM {}", table))
+            .fetch_one(&mut *conn)
+            .await?;
+        let bound = first_unsettled
+            .map(|id| id - 1)
+            .or(last)
+            .unwrap_or(after_id);
+        Ok(bound.min(after_id + limit as i64).max(after_id))
+    }
+
+    async fn rollup_range(
+        conn: &mut SqliteConnection,
+        frames: (i64, i64),
+        transcriptions: (i64, i64),
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(ROLLUP_FRAMES)
+            .bind(frames.0)
+            .bind(frames.1)
+            .bind(start)
+            .bind(end)
+            .execute(&mut *conn)
+            .await?;
+        sqlx::query(ROLLUP_TRANSCRIPTIONS)
+            .bind(transcriptions.0)
+            .bind(transcriptions.1)
+            .bind(start)
+            .bind(end)
+            .bind(MAX_FOCUS_GAP_SECONDS)
+            .execute(&mut *conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Rollups summed over every day, one row per app as captured.
+    pub async fn get_app_rollups(&self) -> Result<Vec<AppRollup>, sqlx::Error> {
+        sqlx::query_as(
+            r#"
+            SELECT app_name, SUM(frames) AS frames, SUM(ocr_chars) AS ocr_chars,
+                SUM(audio_seconds) AS audio_seconds, MIN(first_seen) AS first_seen,
+                MAX(last_seen) AS last_seen
+            FROM app_daily_rollups
+            GROUP BY app_name
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    pub async fn get_rollup_state(&self) -> Result<RollupState, sqlx::Error> {
+        let (last_frame_id, last_transcription_id, updated_at): (i64, i64, Option<DateTime<Utc>>) =
+            sqlx::query_as(
+                "SELECT last_frame_id, last_transcription_id, updated_at FROM app_rollup_state WHERE id = 1",
+            )
+            .fetch_one(&self.pool)
+            .await?;
+        let (pending_frames, pending_transcriptions, dirty_days): (i64, i64, i64) = sqlx::query_as(
+            r#"
+                SELECT
+                    (SELECT COUNT(*) FROM frames WHERE id > ?1),
+                    (SELECT COUNT(*) FROM audio_transcriptions WHERE id > ?2),
+                    (SELECT COUNT(*) FROM app_rollup_dirty_days)
+                "#,
+        )
+        .bind(last_frame_id)
+        .bind(last_transcription_id)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(RollupState {
+            last_frame_id,
+            last_transcription_id,
+            updated_at,
+            pending_frames,
+            pending_transcriptions,
+            dirty_days,
+        })
+    }
+
+    pub async fn insert_event(
+        &self,
+        event_type: &str,
+        timestamp: DateTime<Utc>,
+        subsystem: &str,
+        severity: &str,
+        payload: &str,
+    ) -> Result<i64, sqlx::Error> {
+        let id = sqlx::query(
+            "INSERT INTO events (type, timestamp, subsystem, severity, payload) VALUES (?1, ?2, ?3, ?4, ?5)",
+        )
+        .bind(event_type)
+        .bind(timestamp)
+        .bind(subsystem)
+        .bind(severity)
+        .bind(payload)
+        .execute(&self.pool)
+        .await?
+        .last_insert_rowid();
+        Ok(id)
+    }
+
+    /// Events matching `filter`, newest first, or in the order recorded after `after_id`.
+    pub async fn get_events(&self, filter: &EventFilter) -> Result<Vec<EventRow>, sqlx::Error> {
+        let types = (!filter.types.is_empty())
+            .then(|| serde_json::to_string(&filter.types).unwrap_or_default());
+        let severities = filter.severity.map(|severity| {
+            let names: Vec<&str> = severity.and_above().iter().map(|s| s.as_str()).collect();
+            serde_json::to_string(&names).unwrap_or_default()
+        });
+        let order = if filter.after_id.is_some() {
+            "id"
+        } else {
+            "timestamp DESC, id DESC"
+        };
+        sqlx::query_as(&format!(
+            "SELECT id, type AS event_type, timestamp, subsystem, severity, payload FROM events \
+             WHERE (?1 IS NULL OR type IN (SELECT value FROM json_each(?1))) \
+             AND (?2 IS NULL OR timestamp >= ?2) \
+             AND (?3 IS NULL OR timestamp <= ?3) \
+             AND (?4 IS NULL OR severity IN (SELECT value FROM json_each(?4))) \
+             AND (?5 IS NULL OR id > ?5) \
+             ORDER BY {} LIMIT ?6",
+            order
+        ))
+        .bind(types)
+        .bind(filter.since)
+        .bind(filter.until)
+        .bind(severities)
+        .bind(filter.after_id)
+        .bind(filter.limit)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Deletes the events recorded before `before`. Returns how many.
+    pub async fn delete_events_before(&self, before: DateTime<Utc>) -> Result<u64, sqlx::Error> {
+        Ok(sqlx::query("DELETE FROM events WHERE timestamp < ?1")
+            .bind(before)
+            .execute(&self.pool)
+            .await?
+            .rows_affected())
+    }
+
+    /// `display_changed` events between `start_time` and `end_time`, oldest first.
+    pub async fn get_display_changes(
+        &self,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+    ) -> Result<Vec<DisplayChange>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT id, json_extract(payload, '$.device_name') AS device_name, timestamp AS changed_at, \
+             json_extract(payload, '$.previous_width') AS previous_width, \
+             json_extract(payload, '$.previous_height') AS previous_height, \
+             json_extract(payload, '$.width') AS width, json_extract(payload, '$.height') AS height \
+             FROM events WHERE type = 'display_changed' AND timestamp BETWEEN ?1 AND ?2 ORDER BY timestamp, id",
+        )
+        .bind(start_time)
+        .bind(end_time)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// `clock_adjusted` events between `start_time` and `end_time`, oldest first.
+    pub async fn get_clock_adjustments(
+        &self,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+    ) -> Result<Vec<ClockAdjustmentRow>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT id, timestamp AS detected_at, json_extract(payload, '$.system_time') AS system_time, \
+             json_extract(payload, '$.monotonic_ns') AS monotonic_ns, json_extract(payload, '$.jump_ms') AS jump_ms, \
+             json_extract(payload, '$.timestamp_source') AS timestamp_source \
+             FROM events WHERE type = 'clock_adjusted' AND timestamp BETWEEN ?1 AND ?2 ORDER BY id",
+        )
+        .bind(start_time)
+        .bind(end_time)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// `capture_gap` events overlapping `[start_time, end_time]`, oldest first. A gap is
+    /// recorded at its start.
+    pub async fn get_capture_gaps(
+        &self,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+    ) -> Result<Vec<CaptureGap>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT id, timestamp AS start_time, json_extract(payload, '$.end_time') AS end_time, \
+             json_extract(payload, '$.reason') AS reason \
+             FROM events WHERE type = 'capture_gap' AND timestamp <= ?2 \
+             AND julianday(json_extract(payload, '$.end_time')) >= julianday(?1) ORDER BY timestamp",
+        )
+        .bind(start_time)
+        .bind(end_time)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Rows of `kind` captured before `before` with an id above `after_id`, oldest id
+    /// first. Frames carry the apps of their OCR text.
+    pub async fn get_retention_rows(
+        &self,
+        kind: RetentionKind,
+        before: DateTime<Utc>,
+        after_id: i64,
+        limit: u32,
+    ) -> Result<Vec<RetentionRow>, sqlx::Error> {
+        let sql = match kind {
+            RetentionKind::Frame => {
+                r#"
+                SELECT
+                    f.id,
+                    f.timestamp,
+                    COALESCE((SELECT group_concat(app_name, char(10)) FROM ocr_text WHERE frame_id = f.id), '') AS app_names,
+                    COALESCE(vc.file_path, '') AS file_path,
+                    EXISTS(SELECT 1 FROM vision_tags WHERE vision_id = f.id AND rule IS NULL) AS tagged,
+                    f.retention_class
+                FROM frames f
+                LEFT JOIN video_chunks vc ON vc.id = f.video_chunk_id
+                WHERE f.id > ?1 AND f.timestamp < ?2
+                ORDER BY f.id
+                LIMIT ?3
+                "#
+            }
+            RetentionKind::Audio => {
+                // Older chunks have no timestamp, their first transcription stands in
+                r#"
+                SELECT id, timestamp, '' AS app_names, file_path, tagged, retention_class
+                FROM (
+                    SELECT
+                        ac.id,
+                        COALESCE(ac.timestamp, (SELECT MIN(timestamp) FROM audio_transcriptions WHERE audio_chunk_id = ac.id)) AS timestamp,
+                        ac.file_path,
+                        EXISTS(SELECT 1 FROM audio_tags WHERE audio_chunk_id = ac.id AND rule IS NULL) AS tagged,
+                        ac.retention_class
+                    FROM audio_chunks ac
+                    WHERE ac.id > ?1
+                )
+                WHERE timestamp < ?2
+                ORDER BY id
+                LIMIT ?3
+                "#
+            }
+            RetentionKind::Ui => {
+                r#"
+                SELECT
+                    u.id,
+                    u.timestamp,
+                    u.app AS app_names,
+                    '' AS file_path,
+                    EXISTS(SELECT 1 FROM ui_monitoring_tags WHERE ui_monitoring_id = u.id) AS tagged,
+                    NULL AS retention_class
+                FROM ui_monitoring u
+                WHERE u.id > ?1 AND u.timestamp < ?2
+                ORDER BY u.id
+                LIMIT ?3
+                "#
+            }
+        };
+
+        sqlx::query_as(sql)
+            .bind(after_id)
+            .bind(before)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await
     }
 
-    // ! TODO: atm not sure what will happen if we have multiple transcriptions, OCR, etc for same timestamp (multi monitor, multi audio device...)
-    // ! just merging
-    // ! the offset is not quite right but we try to index around frames which is the central human experience and most important sense
-    // ! there should be a way to properly sync audio and video indexes
-    pub async fn find_video_chunks(
+    /// Deletes rows of `kind` with everything attached to them, marking the days the
+    /// app rollups counted them in to be rebuilt. Returns the chunk files left without
+    /// rows, which the caller removes from disk.
+    pub async fn delete_retention_rows(
         &self,
-        start: DateTime<Utc>,
-        end: DateTime<Utc>,
-    ) -> Result<TimeSeriesChunk, SqlxError> {
-        // First get all frames in time range with their OCR data
-        let frames_query = r#"
-            SELECT
-                f.timestamp,
-                f.offset_index,
-                ot.text,
-                ot.app_name,
-                ot.window_name,
-                vc.device_name as screen_device,
-                vc.file_path as video_path
-            FROM frames f
-            JOIN video_chunks vc ON f.video_chunk_id = vc.id
-            LEFT JOIN ocr_text ot ON f.id = ot.frame_id
-            WHERE f.timestamp >= ?1 AND f.timestamp <= ?2
-            ORDER BY f.timestamp DESC, f.offset_index DESC
-        "#;
+        kind: RetentionKind,
+        ids: &[i64],
+    ) -> Result<Vec<String>, sqlx::Error> {
+        let ids = serde_json::to_string(ids).unwrap_or_else(|_| "[]".to_string());
+        let mut tx = self.pool.begin().await?;
 
-        // Then get audio data that overlaps with these frames
-        let audio_query = r#"
-            SELECT
-                at.timestamp,
-                at.transcription,
-                at.device as audio_device,
-                at.is_input_device,
-                ac.file_path as audio_path
-            FROM audio_transcriptions at
-            JOIN audio_chunks ac ON at.audio_chunk_id = ac.id
-            WHERE at.timestamp >= ?1 AND at.timestamp <= ?2
-            ORDER BY at.timestamp DESC
-        "#;
+        let statements: &[&str] = match kind {
+            RetentionKind::Frame => &[
+                "INSERT OR IGNORE INTO app_rollup_dirty_days (day) SELECT DISTINCT date(timestamp) FROM frames WHERE id IN (SELECT value FROM json_each(?1)) AND id <= (SELECT last_frame_id FROM app_rollup_state)",
+                "DELETE FROM ocr_text WHERE frame_id IN (SELECT value FROM json_each(?1))",
+                "DELETE FROM vision_tags WHERE vision_id IN (SELECT value FROM json_each(?1))",
+                "DELETE FROM chunked_text_entries WHERE frame_id IN (SELECT value FROM json_each(?1))",
+                "DELETE FROM frame_layouts WHERE frame_id IN (SELECT value FROM json_each(?1))",
+            ],
+            RetentionKind::Audio => &[
+                "INSERT OR IGNORE INTO app_rollup_dirty_days (day) SELECT DISTINCT date(timestamp) FROM audio_transcriptions WHERE audio_chunk_id IN (SELECT value FROM json_each(?1)) AND id <= (SELECT last_transcription_id FROM app_rollup_state)",
+                "DELETE FROM audio_transcriptions WHERE audio_chunk_id IN (SELECT value FROM json_each(?1))",
+                "DELETE FROM audio_tags WHERE audio_chunk_id IN (SELECT value FROM json_each(?1))",
+                "DELETE FROM chunked_text_entries WHERE audio_chunk_id IN (SELECT value FROM json_each(?1))",
+            ],
+            RetentionKind::Ui => &[
+                "DELETE FROM ui_monitoring_tags WHERE ui_monitoring_id IN (SELECT value FROM json_each(?1))",
+                "DELETE FROM ui_monitoring WHERE id IN (SELECT value FROM json_each(?1))",
+            ],
+        };
+        for statement in statements {
+            sqlx::query(statement).bind(&ids).execute(&mut *tx).await?;
+        }
 
-        // Execute both queries
-        let (frame_rows, audio_rows) = tokio::try_join!(
-            sqlx::query(frames_query)
-                .bind(start)
-                .bind(end)
-                .fetch_all(&self.pool),
-            sqlx::query(audio_query)
-                .bind(start)
-                .bind(end)
-                .fetch_all(&self.pool)
-        )?;
+        let files = match kind {
+            RetentionKind::Frame => {
+                let chunk_ids: Vec<i64> = sqlx::query_scalar(
+                    "SELECT DISTINCT video_chunk_id FROM frames WHERE id IN (SELECT value FROM json_each(?1))",
+                )
+                .bind(&ids)
+                .fetch_all(&mut *tx)
+                .await?;
+                let thumbnails: Vec<String> = sqlx::query_scalar(
+                    "SELECT DISTINCT thumbnail_path FROM frames WHERE id IN (SELECT value FROM json_each(?1)) AND thumbnail_path IS NOT NULL",
+                )
+                .bind(&ids)
+                .fetch_all(&mut *tx)
+                .await?;
+                sqlx::query("DELETE FROM frames WHERE id IN (SELECT value FROM json_each(?1))")
+                    .bind(&ids)
+                    .execute(&mut *tx)
+                    .await?;
 
-        // Process into structured data
-        let mut frames_map: BTreeMap<(DateTime<Utc>, i64), FrameData> = BTreeMap::new();
+                let chunk_ids = serde_json::to_string(&chunk_ids).unwrap_or_else(|_| "[]".to_string());
+                let emptied = r#"
+                    FROM video_chunks
+                    WHERE id IN (SELECT value FROM json_each(?1))
+                        AND NOT EXISTS (SELECT 1 FROM frames WHERE video_chunk_id = video_chunks.id)
+                "#;
+                let mut files: Vec<String> =
+                    sqlx::query_scalar(&format!("SELECT file_path {}", emptied))
+                        .bind(&chunk_ids)
+                        .fetch_all(&mut *tx)
+                        .await?;
+                sqlx::query(&format!("DELETE {}", emptied))
+                    .bind(&chunk_ids)
+                    .execute(&mut *tx)
+                    .await?;
+                // Text only chunks have no file
+                files.retain(|file| !file.is_empty());
+                files.extend(thumbnails);
+                files
+            }
+            RetentionKind::Audio => {
+                sqlx::query_scalar(
+                    "DELETE FROM audio_chunks WHERE id IN (SELECT value FROM json_each(?1)) RETURNING file_path",
+                )
+                .bind(&ids)
+                .fetch_all(&mut *tx)
+                .await?
+            }
+            RetentionKind::Ui => Vec::new(),
+        };
 
-        // Process frame/OCR data
-        for row in frame_rows {
-            let timestamp: DateTime<Utc> = row.get("timestamp");
-            let offset_index: i64 = row.get("offset_index");
-            let key = (timestamp, offset_index);
+        tx.commit().await?;
+        Ok(files)
+    }
 
-            let frame_data = frames_map.entry(key).or_insert_with(|| FrameData {
-                timestamp,
-                offset_index,
-                ocr_entries: Vec::new(),
-                audio_entries: Vec::new(),
-            });
+    /// Every app name frames and ui text were captured with, as captured.
+    pub async fn get_app_names(&self) -> Result<Vec<String>, sqlx::Error> {
+        sqlx::query_scalar(
+            "SELECT app_name FROM ocr_text WHERE app_name != '' UNION SELECT app FROM ui_monitoring WHERE app != ''",
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
 
-            if let Ok(text) = row.try_get::<String, _>("text") {
-                frame_data.ocr_entries.push(OCREntry {
-                    text,
-                    app_name: row.get("app_name"),
-                    window_name: row.get("window_name"),
-                    device_name: row.get("screen_device"),
-                    video_file_path: row.get("video_path"),
-                });
+    /// Ids of the first `limit` rows of `kind` showing any of `app_names`. Audio has no
+    /// app, none are returned for it.
+    pub async fn get_app_row_ids(
+        &self,
+        kind: RetentionKind,
+        app_names: &[String],
+        limit: u32,
+    ) -> Result<Vec<i64>, sqlx::Error> {
+        let sql = match kind {
+            RetentionKind::Frame => {
+                "SELECT DISTINCT frame_id FROM ocr_text WHERE app_name IN (SELECT value FROM json_each(?1)) ORDER BY frame_id LIMIT ?2"
             }
-        }
-
-        // Process audio data
-        for row in audio_rows {
-            let timestamp: DateTime<Utc> = row.get("timestamp");
+            RetentionKind::Ui => {
+                "SELECT id FROM ui_monitoring WHERE app IN (SELECT value FROM json_each(?1)) ORDER BY id LIMIT ?2"
+            }
+            RetentionKind::Audio => return Ok(Vec::new()),
+        };
+        sqlx::query_scalar(sql)
+            .bind(serde_json::to_string(app_names).unwrap_or_else(|_| "[]".to_string()))
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await
+    }
 
-            // Find the closest frame
-            if let Some((&key, _)) = frames_map.range(..(timestamp, i64::MAX)).next_back() {
-                if let Some(frame_data) = frames_map.get_mut(&key) {
-                    frame_data.audio_entries.push(AudioEntry {
-                        transcription: row.get("transcription"),
-                        device_name: row.get("audio_device"),
-                        is_input: row.get("is_input_device"),
-                        audio_file_path: row.get("audio_path"),
-                        // duration_secs: row.get("duration_secs"),
-                        duration_secs: 0.0, // TODO
-                    });
-                }
+    /// Row counts per chunk file and app, for the retention report. A frame showing
+    /// several apps counts toward the first one by name.
+    pub async fn get_retention_usage(
+        &self,
+        kind: RetentionKind,
+    ) -> Result<Vec<RetentionUsage>, sqlx::Error> {
+        let sql = match kind {
+            RetentionKind::Frame => {
+                r#"
+                SELECT
+                    vc.file_path,
+                    COALESCE(o.app_name, '') AS app_name,
+                    COUNT(*) AS rows,
+                    MIN(f.timestamp) AS oldest
+                FROM frames f
+                JOIN video_chunks vc ON vc.id = f.video_chunk_id
+                LEFT JOIN (
+                    SELECT frame_id, MIN(NULLIF(app_name, '')) AS app_name FROM ocr_text GROUP BY frame_id
+                ) o ON o.frame_id = f.id
+                GROUP BY vc.id, o.app_name
+                "#
             }
-        }
+            RetentionKind::Audio => {
+                r#"
+                SELECT file_path, '' AS app_name, 1 AS rows, oldest
+                FROM (
+                    SELECT
+                        ac.file_path,
+                        COALESCE(ac.timestamp, (SELECT MIN(timestamp) FROM audio_transcriptions WHERE audio_chunk_id = ac.id)) AS oldest
+                    FROM audio_chunks ac
+                )
+                WHERE oldest IS NOT NULL
+                "#
+            }
+            RetentionKind::Ui => {
+                r#"
+                SELECT '' AS file_path, app AS app_name, COUNT(*) AS rows, MIN(timestamp) AS oldest
+                FROM ui_monitoring
+                GROUP BY app
+                "#
+            }
+        };
 
-        Ok(TimeSeriesChunk {
-            frames: frames_map.into_values().rev().collect(),
-            start_time: start,
-            end_time: end,
-        })
+        sqlx::query_as(sql).fetch_all(&self.pool).await
     }
 
-    pub async fn search_ui_monitoring(
+    /// Chunks of `kind` with an id above `after_id` whose content was all captured
+    /// before `before`, oldest id first. Archived chunks, chunks without a file and
+    /// chunks with tagged content are left out.
+    pub async fn get_archive_candidates(
         &self,
-        query: &str,
-        app_name: Option<&str>,
-        window_name: Option<&str>,
-        start_time: Option<DateTime<Utc>>,
-        end_time: Option<DateTime<Utc>>,
+        kind: MediaChunkKind,
+        before: DateTime<Utc>,
+        after_id: i64,
         limit: u32,
-        offset: u32,
-    ) -> Result<Vec<UiContent>, sqlx::Error> {
-        let base_sql = if query.is_empty() {
-            "ui_monitoring"
-        } else {
-            "ui_monitoring_fts JOIN ui_monitoring ON ui_monitoring_fts.ui_id = ui_monitoring.id"
+    ) -> Result<Vec<ArchiveCandidate>, sqlx::Error> {
+        let sql = match kind {
+            MediaChunkKind::Video => format!(
+                r#"
+                SELECT video_chunks.id, video_chunks.file_path, MAX(f.timestamp) AS newest
+                FROM video_chunks
+                JOIN frames f ON f.video_chunk_id = video_chunks.id
+                WHERE video_chunks.id > ?1
+                    AND video_chunks.archived = 0
+                    AND video_chunks.file_path != ''
+                    AND {}
+                GROUP BY video_chunks.id
+                HAVING MAX(f.timestamp) < ?2
+                ORDER BY video_chunks.id
+                LIMIT ?3
+                "#,
+                untagged_chunk_sql(kind)
+            ),
+            // Older chunks have no timestamp, their last transcription stands in
+            MediaChunkKind::Audio => format!(
+                r#"
+                SELECT id, file_path, newest
+                FROM (
+                    SELECT
+                        audio_chunks.id,
+                        audio_chunks.file_path,
+                        COALESCE(audio_chunks.timestamp, (SELECT MAX(timestamp) FROM audio_transcriptions WHERE audio_chunk_id = audio_chunks.id)) AS newest
+                    FROM audio_chunks
+                    WHERE audio_chunks.id > ?1
+                        AND audio_chunks.archived = 0
+                        AND audio_chunks.file_path != ''
+                        AND {}
+                )
+                WHERE newest < ?2
+                ORDER BY id
+                LIMIT ?3
+                "#,
+                untagged_chunk_sql(kind)
+            ),
         };
 
-        let where_clause = if query.is_empty() {
-            "WHERE 1=1"
-        } else {
-            "WHERE ui_monitoring_fts MATCH ?1"
-        };
+        let rows: Vec<(i64, String, DateTime<Utc>)> = sqlx::query_as(&sql)
+            .bind(after_id)
+            .bind(before)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows
+            .into_iter()
+            .map(|(id, file_path, newest)| ArchiveCandidate {
+                kind,
+                id,
+                file_path,
+                newest,
+            })
+            .collect())
+    }
 
-        let sql = format!(
-            r#"
-            SELECT
-                ui_monitoring.id,
-                ui_monitoring.text_output,
-                ui_monitoring.timestamp,
-                ui_monitoring.app,
-                ui_monitoring.window,
-                ui_monitoring.initial_traversal_at,
-                video_chunks.file_path,
-                frames.offset_index
-            FROM {}
-            LEFT JOIN frames ON
-                frames.timestamp BETWEEN
-                    datetime(ui_monitoring.timestamp, '-1 seconds')
-                    AND datetime(ui_monitoring.timestamp, '+1 seconds')
-            LEFT JOIN video_chunks ON frames.video_chunk_id = video_chunks.id
-            {}
-                AND (?2 IS NULL OR ui_monitoring.timestamp >= ?2)
-                AND (?3 IS NULL OR ui_monitoring.timestamp <= ?3)
-                AND (?4 IS NULL OR ui_monitoring.app LIKE '%' || ?4 || '%')
-                AND (?5 IS NULL OR ui_monitoring.window LIKE '%' || ?5 || '%')
-            ORDER BY ui_monitoring.timestamp DESC
-            LIMIT ?6 OFFSET ?7
-            "#,
-            base_sql, where_clause
-        );
+    /// Points a chunk at its archived copy and records the original for deletion,
+    /// returning the id of the pending move. `None`, changing nothing, if the chunk is
+    /// gone, was moved or got tagged since it was listed.
+    pub async fn mark_chunk_archived(
+        &self,
+        kind: MediaChunkKind,
+        id: i64,
+        original_path: &str,
+        archive_path: &str,
+    ) -> Result<Option<i64>, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let updated = sqlx::query(&format!(
+            "UPDATE {table} SET file_path = ?3, archived = 1 WHERE id = ?1 AND file_path = ?2 AND archived = 0 AND {untagged}",
+            table = kind.table(),
+            untagged = untagged_chunk_sql(kind)
+        ))
+        .bind(id)
+        .bind(original_path)
+        .bind(archive_path)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        if updated == 0 {
+            return Ok(None);
+        }
+        let move_id =
+            sqlx::query("INSERT INTO archive_moves (original_path, archive_path) VALUES (?1, ?2)")
+                .bind(original_path)
+                .bind(archive_path)
+                .execute(&mut *tx)
+                .await?
+                .last_insert_rowid();
+        tx.commit().await?;
+        Ok(Some(move_id))
+    }
 
-        sqlx::query_as(&sql)
-            .bind(query)
-            .bind(start_time)
-            .bind(end_time)
-            .bind(app_name)
-            .bind(window_name)
-            .bind(limit)
-            .bind(offset)
+    /// Archived chunks whose original file wasn't confirmed deleted.
+    pub async fn get_pending_archive_moves(&self) -> Result<Vec<PendingArchiveMove>, sqlx::Error> {
+        sqlx::query_as("SELECT id, original_path, archive_path FROM archive_moves ORDER BY id")
             .fetch_all(&self.pool)
             .await
     }
 
+    pub async fn finish_archive_move(&self, id: i64) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM archive_moves WHERE id = ?1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
     // Add tags to UI monitoring entry
     pub async fn add_tags_to_ui_monitoring(
         &self,
@@ -1709,6 +4076,10 @@ impl DatabaseManager {
 
         // Array of (query, operation description) tuples
         let operations = [
+            (
+                "INSERT OR IGNORE INTO app_rollup_dirty_days (day) SELECT DISTINCT date(timestamp) FROM audio_transcriptions WHERE speaker_id = ? AND id <= (SELECT last_transcription_id FROM app_rollup_state)",
+                "rollup days",
+            ),
             (
                 "DELETE FROM audio_transcriptions WHERE speaker_id = ?",
                 "audio transcriptions",
@@ -1823,3 +4194,174 @@ impl DatabaseManager {
         Ok(())
     }
 }
+
+/// The search query language filters, read as json from `?{param}`. Each condition
+/// tests one filter value, `alt.value`, against the current row: app, window, tag.
+fn search_filters_sql(param: u32, conditions: [&str; 3]) -> String {
+    let [app, window, tag] = conditions;
+    let checks: Vec<String> = [("app_name", app), ("window_name", window), ("tags", tag)]
+        .iter()
+        .map(|(field, condition)| {
+            format!(
+                "NOT EXISTS (SELECT 1 FROM json_each(?{param}, '$.{field}.all_of') AS required \
+                 WHERE NOT EXISTS (SELECT 1 FROM json_each(required.value) AS alt WHERE {condition})) \
+                 AND NOT EXISTS (SELECT 1 FROM json_each(?{param}, '$.{field}.none_of') AS alt WHERE {condition})"
+            )
+        })
+        .collect();
+    format!("(?{} IS NULL OR ({}))", param, checks.join(" AND "))
+}
+
+/// Bounds on the row's window from the `window` object of the search filters json in
+/// `?{param}`. Frames without a layout have no bounds and fail every bound.
+fn window_geometry_sql(param: u32) -> String {
+    let checks: Vec<String> = [
+        ("width_gt", "frame_layouts.window_width >"),
+        ("width_lt", "frame_layouts.window_width <"),
+        ("height_gt", "frame_layouts.window_height >"),
+        ("height_lt", "frame_layouts.window_height <"),
+        ("x_gt", "frame_layouts.window_x >"),
+        ("x_lt", "frame_layouts.window_x <"),
+        ("y_gt", "frame_layouts.window_y >"),
+        ("y_lt", "frame_layouts.window_y <"),
+    ]
+    .iter()
+    .map(|(bound, comparison)| {
+        format!(
+            "(json_extract(?{param}, '$.window.{bound}') IS NULL OR {comparison} json_extract(?{param}, '$.window.{bound}'))"
+        )
+    })
+    .collect();
+    format!("({})", checks.join(" AND "))
+}
+
+/// Start of a usage bucket as formatted by `UsageBucket::strftime`.
+fn parse_bucket(bucket: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(bucket)
+        .ok()
+        .map(|t| t.with_timezone(&Utc))
+}
+
+/// No frame or transcription of the `kind` chunk table row is tagged by hand.
+fn untagged_chunk_sql(kind: MediaChunkKind) -> &'static str {
+    match kind {
+        MediaChunkKind::Video => {
+            "NOT EXISTS (SELECT 1 FROM frames tf JOIN vision_tags vt ON vt.vision_id = tf.id WHERE tf.video_chunk_id = video_chunks.id AND vt.rule IS NULL)"
+        }
+        MediaChunkKind::Audio => {
+            "NOT EXISTS (SELECT 1 FROM audio_tags WHERE audio_chunk_id = audio_chunks.id AND rule IS NULL)"
+        }
+    }
+}
+
+/// A capture write as tag rules see it. A transcription without text has nothing for
+/// them, it only creates its chunk.
+fn rule_input(write: &CaptureWrite, timestamp: DateTime<Utc>) -> Option<RuleInput<'_>> {
+    match write {
+        CaptureWrite::Frame(frame) => Some(RuleInput {
+            content: TagContentType::Vision,
+            timestamp,
+            windows: frame
+                .windows
+                .iter()
+                .map(|window| RuleWindow {
+                    app_name: Some(&window.app_name),
+                    window_name: Some(&window.window_name),
+                    text: &window.text,
+                })
+                .collect(),
+        }),
+        CaptureWrite::Transcription(transcription) if transcription.transcription.is_empty() => {
+            None
+        }
+        CaptureWrite::Transcription(transcription) => Some(RuleInput {
+            content: TagContentType::Audio,
+            timestamp,
+            windows: vec![RuleWindow {
+                app_name: None,
+                window_name: None,
+                text: &transcription.transcription,
+            }],
+        }),
+    }
+}
+
+/// Pipe content field filters, a json array of `FieldFilter` read from `?{param}`.
+/// A missing field fails every comparison.
+fn pipe_fields_sql(param: u32) -> String {
+    format!(
+        "NOT EXISTS (SELECT 1 FROM json_each(?{param}) AS f WHERE COALESCE(\
+         CASE json_extract(f.value, '$.op') \
+         WHEN 'eq' THEN json_extract(pipe_content.fields, json_extract(f.value, '$.path')) = json_extract(f.value, '$.value') \
+         WHEN 'eq_text' THEN lower(json_extract(pipe_content.fields, json_extract(f.value, '$.path'))) = lower(json_extract(f.value, '$.value')) \
+         WHEN 'gte' THEN json_extract(pipe_content.fields, json_extract(f.value, '$.path')) >= json_extract(f.value, '$.value') \
+         WHEN 'lte' THEN json_extract(pipe_content.fields, json_extract(f.value, '$.path')) <= json_extract(f.value, '$.value') \
+         END, 0) = 0)"
+    )
+}
+
+const OCR_FILTERS: [&str; 3] = [
+    "ocr_text.app_name LIKE '%' || alt.value || '%' COLLATE NOCASE",
+    "ocr_text.window_name LIKE '%' || alt.value || '%' COLLATE NOCASE",
+    "EXISTS (SELECT 1 FROM vision_tags JOIN tags ON vision_tags.tag_id = tags.id WHERE vision_tags.vision_id = frames.id AND tags.name = alt.value COLLATE NOCASE)",
+];
+
+// Audio has no app or window, rows only pass filters that exclude them
+const AUDIO_FILTERS: [&str; 3] = [
+    "0",
+    "0",
+    "EXISTS (SELECT 1 FROM audio_tags JOIN tags ON audio_tags.tag_id = tags.id WHERE audio_tags.audio_chunk_id = audio_transcriptions.audio_chunk_id AND tags.name = alt.value COLLATE NOCASE)",
+];
+
+const UI_FILTERS: [&str; 3] = [
+    "ui_monitoring.app LIKE '%' || alt.value || '%' COLLATE NOCASE",
+    "ui_monitoring.window LIKE '%' || alt.value || '%' COLLATE NOCASE",
+    "EXISTS (SELECT 1 FROM ui_monitoring_tags JOIN tags ON ui_monitoring_tags.tag_id = tags.id WHERE ui_monitoring_tags.ui_monitoring_id = ui_monitoring.id AND tags.name = alt.value COLLATE NOCASE)",
+];
+
+// Frames with their OCR text counted per day and app, over ids `(?1, ?2]` captured in
+// `[?3, ?4)`. A frame counts once for every app on it
+const ROLLUP_FRAMES: &str = r#"
+    INSERT INTO app_daily_rollups (day, app_name, frames, ocr_chars, audio_seconds, first_seen, last_seen)
+    SELECT date(f.timestamp), o.app_name, COUNT(DISTINCT f.id), SUM(LENGTH(COALESCE(o.text, ''))), 0,
+        MIN(f.timestamp), MAX(f.timestamp)
+    FROM frames f
+    JOIN ocr_text o ON o.frame_id = f.id
+    WHERE f.id > ?1 AND f.id <= ?2 AND f.timestamp >= ?3 AND f.timestamp < ?4 AND o.app_name != ''
+    GROUP BY date(f.timestamp), o.app_name
+    ON CONFLICT (day, app_name) DO UPDATE SET
+        frames = frames + excluded.frames,
+        ocr_chars = ocr_chars + excluded.ocr_chars,
+        first_seen = MIN(first_seen, excluded.first_seen),
+        last_seen = MAX(last_seen, excluded.last_seen)
+"#;
+
+// Transcribed seconds over the same bounds, credited to the app focused on the latest
+// frame before them unless it is more than `?5` seconds older
+const ROLLUP_TRANSCRIPTIONS: &str = r#"
+    INSERT INTO app_daily_rollups (day, app_name, frames, ocr_chars, audio_seconds, first_seen, last_seen)
+    SELECT date(heard.timestamp), o.app_name, 0, 0, SUM(heard.seconds),
+        MIN(heard.timestamp), MAX(heard.timestamp)
+    FROM (
+        SELECT t.timestamp,
+            MAX(COALESCE(t.end_time - t.start_time, 0), 0) AS seconds,
+            (
+                SELECT focused.rowid FROM frames f
+                JOIN ocr_text focused ON focused.frame_id = f.id AND focused.focused = 1
+                WHERE f.timestamp <= t.timestamp
+                ORDER BY f.timestamp DESC
+                LIMIT 1
+            ) AS focus_rowid
+        FROM audio_transcriptions t
+        WHERE t.id > ?1 AND t.id <= ?2 AND t.timestamp >= ?3 AND t.timestamp < ?4
+    ) heard
+    JOIN ocr_text o ON o.rowid = heard.focus_rowid
+    JOIN frames f ON f.id = o.frame_id
+    WHERE o.app_name != ''
+        AND (julianday(heard.timestamp) - julianday(f.timestamp)) * 86400.0 <= ?5
+    GROUP BY date(heard.timestamp), o.app_name
+    ON CONFLICT (day, app_name) DO UPDATE SET
+        audio_seconds = audio_seconds + excluded.audio_seconds,
+        first_seen = MIN(first_seen, excluded.first_seen),
+        last_seen = MAX(last_seen, excluded.last_seen)
+"#;
@@ -0,0 +1,265 @@
+//! Riding out a locked database.
+//!
+//! Sqlite lets one connection write at a time. Another process holding the lock (a
+//! backup, a `sqlite3` shell, a long migration) makes our writes fail with
+//! SQLITE_BUSY once the connection's busy timeout is up. Capture writes are retried
+//! with a jittered backoff, and when the lock outlasts the retries they go to a spill
+//! journal next to the database that is replayed once it can be written again.
+
+use crate::db_types::CaptureWrite;
+use crate::DatabaseManager;
+use chrono::{DateTime, Utc};
+use libsqlite3_sys::{SQLITE_BUSY, SQLITE_LOCKED};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::future::Future;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, MutexGuard};
+use tracing::{debug, error, info, warn};
+
+/// How long a connection waits on another connection's lock before SQLITE_BUSY.
+pub const DEFAULT_BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often the spill journal is replayed while it holds writes.
+pub const SPILL_DRAIN_INTERVAL: Duration = Duration::from_secs(5);
+
+/// File name of the spill journal in the data directory.
+pub const SPILL_JOURNAL_FILE: &str = "db_spill.jsonl";
+
+/// Whether `e` means another connection holds the lock, so trying again can succeed.
+pub fn is_busy(e: &sqlx::Error) -> bool {
+    match e {
+        sqlx::Error::Database(e) => e
+            .code()
+            .and_then(|code| code.parse::<i32>().ok())
+            // Extended codes like SQLITE_BUSY_SNAPSHOT keep the primary code in the low byte
+            .is_some_and(|code| matches!(code & 0xff, SQLITE_BUSY | SQLITE_LOCKED)),
+        _ => false,
+    }
+}
+
+/// How a write is retried while the database is busy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BusyRetry {
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled for every retry after it
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// No retry starts after this long, whatever `max_retries` says
+    pub deadline: Duration,
+}
+
+impl Default for BusyRetry {
+    fn default() -> Self {
+        Self {
+            max_retries: 8,
+            base_delay: Duration::from_millis(50),
+            max_delay: Duration::from_secs(2),
+            deadline: Duration::from_secs(15),
+        }
+    }
+}
+
+impl BusyRetry {
+    /// Delay before retry `retry` (from 1), between half and all of the backoff so
+    /// writers that collided don't collide again.
+    pub fn delay(&self, retry: u32) -> Duration {
+        let backoff = self
+            .base_delay
+            .saturating_mul(1 << (retry.max(1) - 1).min(16))
+            .min(self.max_delay);
+        backoff / 2 + backoff.mul_f64(fastrand::f64() / 2.0)
+    }
+}
+
+/// Runs `op` until it succeeds, fails with something else than a busy database, or
+/// `policy` gives up.
+pub async fn retry_busy<T, F, Fut>(
+    policy: &BusyRetry,
+    metrics: &DbWriteMetrics,
+    what: &str,
+    mut op: F,
+) -> Result<T, sqlx::Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, sqlx::Error>>,
+{
+    let started = Instant::now();
+    let mut retry = 0;
+    loop {
+        let e = match op().await {
+            Err(e) if is_busy(&e) => e,
+            result => return result,
+        };
+        let left = policy.deadline.saturating_sub(started.elapsed());
+        if retry >= policy.max_retries || left.is_zero() {
+            metrics.exhausted.fetch_add(1, Ordering::Relaxed);
+            warn!(
+                "{}: database still busy after {} retries in {:.1}s: {}",
+                what,
+                retry,
+                started.elapsed().as_secs_f64(),
+                e
+            );
+            return Err(e);
+        }
+        retry += 1;
+        metrics.retried.fetch_add(1, Ordering::Relaxed);
+        let delay = policy.delay(retry).min(left);
+        debug!("{}: database busy, retry {} in {:?}", what, retry, delay);
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Counters of writes that met a busy database.
+#[derive(Debug, Default)]
+pub struct DbWriteMetrics {
+    /// Attempts that were retried after SQLITE_BUSY or SQLITE_LOCKED
+    pub retried: AtomicU64,
+    /// Writes that were still busy when their retries ran out
+    pub exhausted: AtomicU64,
+    pub spilled: AtomicU64,
+    /// Spilled writes committed from the journal
+    pub replayed: AtomicU64,
+    /// Spilled writes the database rejected for another reason than a lock
+    pub dropped: AtomicU64,
+    /// Writes waiting in the spill journal
+    pub pending: AtomicU64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct DbWriteMetricsSnapshot {
+    pub retried: u64,
+    pub exhausted: u64,
+    pub spilled: u64,
+    pub replayed: u64,
+    pub dropped: u64,
+    pub pending: u64,
+}
+
+impl DbWriteMetrics {
+    pub fn snapshot(&self) -> DbWriteMetricsSnapshot {
+        DbWriteMetricsSnapshot {
+            retried: self.retried.load(Ordering::Relaxed),
+            exhausted: self.exhausted.load(Ordering::Relaxed),
+            spilled: self.spilled.load(Ordering::Relaxed),
+            replayed: self.replayed.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            pending: self.pending.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// A capture write that couldn't be committed, one json line in the journal.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpillEntry {
+    pub write: CaptureWrite,
+    /// When the item was stamped, kept when it is replayed
+    pub timestamp: DateTime<Utc>,
+    pub monotonic_ns: Option<u64>,
+    /// Process that stamped the item, monotonic stamps mean nothing to another one
+    pub pid: u32,
+}
+
+/// Append only file of capture writes waiting for the database.
+///
+/// Writes go through [`SpillJournal::order`] so that, while the journal holds
+/// anything, new writes queue behind it instead of overtaking it. Replays are at
+/// least once: a crash between committing entries and truncating the journal
+/// replays them again.
+#[derive(Debug)]
+pub struct SpillJournal {
+    path: PathBuf,
+    order: Mutex<()>,
+}
+
+impl SpillJournal {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            order: Mutex::new(()),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Held by a writer until its write is committed or spilled.
+    pub(crate) async fn order(&self) -> MutexGuard<'_, ()> {
+        self.order.lock().await
+    }
+
+    pub(crate) fn append(&self, entry: &SpillEntry) -> std::io::Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        file.write_all(line.as_bytes())?;
+        file.sync_data()
+    }
+
+    /// The entries in the order they were spilled. A torn last line, from a crash
+    /// mid append, is skipped.
+    pub(crate) fn read(&self) -> std::io::Result<Vec<SpillEntry>> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut entries = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(&line) {
+                Ok(entry) => entries.push(entry),
+                Err(e) => error!("skipping unreadable line in {}: {}", self.path.display(), e),
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Replaces the journal with `entries`, removing it when there are none.
+    pub(crate) fn rewrite(&self, entries: &[SpillEntry]) -> std::io::Result<()> {
+        if entries.is_empty() {
+            return match fs::remove_file(&self.path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+                _ => Ok(()),
+            };
+        }
+        let tmp = self.path.with_extension("jsonl.tmp");
+        let mut file = File::create(&tmp)?;
+        for entry in entries {
+            let mut line = serde_json::to_string(entry)?;
+            line.push('\n');
+            file.write_all(line.as_bytes())?;
+        }
+        file.sync_data()?;
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+/// Replays the spill journal whenever it holds writes, until the process exits.
+pub async fn drain_spill_journal(db: Arc<DatabaseManager>, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        ticker.tick().await;
+        if db.write_metrics().pending.load(Ordering::Relaxed) == 0 {
+            continue;
+        }
+        match db.replay_spill_journal().await {
+            Ok(0) => {}
+            Ok(replayed) => info!("replayed {} spilled writes", replayed),
+            Err(e) => warn!("failed to replay the spill journal: {}", e),
+        }
+    }
+}
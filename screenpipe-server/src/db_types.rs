@@ -1,5 +1,9 @@
+use crate::ocr_correction::CorrectionSource;
+use crate::pipe_content::FieldFilter;
+use crate::storage_mode::StorageMode;
 use chrono::{DateTime, Utc};
 use screenpipe_audio::DeviceType;
+use screenpipe_core::window_layout::WindowLayout;
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use std::error::Error as StdError;
@@ -21,6 +25,8 @@ pub enum SearchResult {
     OCR(OCRResult),
     Audio(AudioResult),
     UI(UiContent),
+    Document(DocumentResult),
+    Pipe(PipeContentResult),
 }
 
 #[derive(FromRow, Debug)]
@@ -35,6 +41,12 @@ pub struct OCRResultRaw {
     pub ocr_engine: String,
     pub window_name: String,
     pub tags: Option<String>,
+    pub rank: f64,
+    pub storage_mode: String,
+    pub thumbnail_path: Option<String>,
+    pub archived: bool,
+    pub raw_text: Option<String>,
+    pub corrected_by: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -49,6 +61,19 @@ pub struct OCRResult {
     pub ocr_engine: String,
     pub window_name: String,
     pub tags: Vec<String>,
+    /// FTS5 bm25 rank, lower is more relevant. 0 without a text query
+    pub rank: f64,
+    /// Without video `file_path` is empty, the image is a thumbnail or nothing
+    pub storage_mode: StorageMode,
+    pub thumbnail_path: Option<String>,
+    /// The video chunk was moved to the archive directory
+    #[serde(default)]
+    pub archived: bool,
+    /// What the OCR engine read, when `ocr_text` was post-corrected
+    #[serde(default)]
+    pub raw_text: Option<String>,
+    #[serde(default)]
+    pub corrected_by: Option<CorrectionSource>,
 }
 
 #[derive(Debug, Deserialize, PartialEq, Default, Clone)]
@@ -68,6 +93,28 @@ pub enum ContentType {
     #[serde(rename = "audio+ocr")]
     #[serde(alias = "audio ocr")]
     AudioAndOcr,
+    Document,
+    /// Records pipes added through `/pipes/content`
+    Pipe,
+}
+
+impl ContentType {
+    /// The single content types this one covers.
+    pub fn parts(&self) -> Vec<ContentType> {
+        match self {
+            ContentType::All => vec![
+                ContentType::OCR,
+                ContentType::Audio,
+                ContentType::UI,
+                ContentType::Document,
+                ContentType::Pipe,
+            ],
+            ContentType::AudioAndUi => vec![ContentType::Audio, ContentType::UI],
+            ContentType::OcrAndUi => vec![ContentType::OCR, ContentType::UI],
+            ContentType::AudioAndOcr => vec![ContentType::Audio, ContentType::OCR],
+            single => vec![single.clone()],
+        }
+    }
 }
 
 #[derive(FromRow)]
@@ -84,6 +131,7 @@ pub struct AudioResultRaw {
     pub speaker_id: Option<i64>,
     pub start_time: Option<f64>,
     pub end_time: Option<f64>,
+    pub rank: f64,
 }
 
 #[derive(Debug, Serialize, Deserialize, FromRow, Clone)]
@@ -107,9 +155,122 @@ pub struct AudioResult {
     pub speaker: Option<Speaker>,
     pub start_time: Option<f64>,
     pub end_time: Option<f64>,
+    pub rank: f64,
+}
+
+/// How each content type's rows are picked and ordered before results are merged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SearchOrder {
+    #[default]
+    Recent,
+    /// Best bm25 match first, newest row first on ties
+    Relevance,
+}
+
+/// Substring conditions on one field: a row needs a match in every `all_of` group
+/// and no match in `none_of`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TextFilter {
+    pub all_of: Vec<Vec<String>>,
+    pub none_of: Vec<String>,
+}
+
+impl TextFilter {
+    pub fn is_empty(&self) -> bool {
+        self.all_of.is_empty() && self.none_of.is_empty()
+    }
+
+    /// Whether rows without this field can never match.
+    pub fn is_required(&self) -> bool {
+        !self.all_of.is_empty()
+    }
+}
+
+/// Bounds, in pixels, on the window a frame's text was read from. Frames stored
+/// without a window layout match none of them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct WindowGeometryFilter {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width_gt: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width_lt: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height_gt: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height_lt: Option<i64>,
+    /// Left edge, from the left of the monitor
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub x_gt: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub x_lt: Option<i64>,
+    /// Top edge, from the top of the monitor
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub y_gt: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub y_lt: Option<i64>,
 }
 
-#[derive(Debug, Deserialize, PartialEq)]
+impl WindowGeometryFilter {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Filters from the search query language that the plain search params can't
+/// express. They apply on top of the params.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SearchFilters {
+    pub app_name: TextFilter,
+    pub window_name: TextFilter,
+    pub tags: TextFilter,
+    /// Only frames have a window layout
+    #[serde(skip_serializing_if = "WindowGeometryFilter::is_empty")]
+    pub window: WindowGeometryFilter,
+    /// Single content types the query is limited to, `None` for all of them
+    #[serde(skip)]
+    pub content_types: Option<Vec<ContentType>>,
+    /// Pipe content type from the `type` search param, required by `fields`
+    #[serde(skip)]
+    pub pipe_type: Option<String>,
+    /// Typed filters on the fields of `pipe_type` records
+    #[serde(skip)]
+    pub fields: Vec<FieldFilter>,
+}
+
+impl SearchFilters {
+    /// Whether results of `content_type` can match, before looking at any row.
+    pub fn allows(&self, content_type: &ContentType) -> bool {
+        let allowed_type = match &self.content_types {
+            Some(types) => types.contains(content_type),
+            None => true,
+        } && (self.pipe_type.is_none() || *content_type == ContentType::Pipe);
+        let needs_app_or_window = self.app_name.is_required() || self.window_name.is_required();
+        allowed_type
+            && (self.window.is_empty() || *content_type == ContentType::OCR)
+            && match content_type {
+                ContentType::Audio => !needs_app_or_window,
+                ContentType::Document | ContentType::Pipe => {
+                    !needs_app_or_window && !self.tags.is_required()
+                }
+                _ => true,
+            }
+    }
+
+    /// The text and window filters as json for the search sql, `None` when there are none.
+    pub fn to_json(&self) -> Option<String> {
+        if self.app_name.is_empty()
+            && self.window_name.is_empty()
+            && self.tags.is_empty()
+            && self.window.is_empty()
+        {
+            None
+        } else {
+            serde_json::to_string(self).ok()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum TagContentType {
     Vision,
@@ -129,6 +290,453 @@ pub struct UiContent {
     pub initial_traversal_at: Option<DateTime<Utc>>,
     pub file_path: String,
     pub offset_index: i64,
+    pub rank: f64,
+}
+
+/// Text extracted from a file in a watch folder, timestamped with the file's mtime.
+#[derive(Debug, Serialize, Deserialize, FromRow, Clone)]
+pub struct DocumentResult {
+    pub id: i64,
+    pub file_path: String,
+    pub kind: String,
+    pub text: String,
+    pub timestamp: DateTime<Utc>,
+    pub file_size: i64,
+    pub rank: f64,
+}
+
+/// A record a pipe added to the index, `fields` follow its content type's schema.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PipeContentResult {
+    pub id: i64,
+    pub content_type: String,
+    pub pipe_id: String,
+    pub timestamp: DateTime<Utc>,
+    pub text: String,
+    pub fields: serde_json::Value,
+    pub rank: f64,
+}
+
+#[derive(FromRow)]
+pub struct PipeContentResultRaw {
+    pub id: i64,
+    pub content_type: String,
+    pub pipe_id: String,
+    pub timestamp: DateTime<Utc>,
+    pub text: String,
+    pub fields: String,
+    pub rank: f64,
+}
+
+impl From<PipeContentResultRaw> for PipeContentResult {
+    fn from(raw: PipeContentResultRaw) -> Self {
+        Self {
+            id: raw.id,
+            content_type: raw.content_type,
+            pipe_id: raw.pipe_id,
+            timestamp: raw.timestamp,
+            text: raw.text,
+            fields: serde_json::from_str(&raw.fields).unwrap_or_default(),
+            rank: raw.rank,
+        }
+    }
+}
+
+/// Where a scheduled pipe job is at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PipeJobStatus {
+    Pending,
+    Running,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+impl PipeJobStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PipeJobStatus::Pending => "pending",
+            PipeJobStatus::Running => "running",
+            PipeJobStatus::Done => "done",
+            PipeJobStatus::Failed => "failed",
+            PipeJobStatus::Cancelled => "cancelled",
+        }
+    }
+
+    pub fn from_db(status: &str) -> Self {
+        match status {
+            "running" => PipeJobStatus::Running,
+            "done" => PipeJobStatus::Done,
+            "failed" => PipeJobStatus::Failed,
+            "cancelled" => PipeJobStatus::Cancelled,
+            _ => PipeJobStatus::Pending,
+        }
+    }
+}
+
+/// A one-off run a pipe scheduled for itself, `payload` is handed to that run.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct PipeJob {
+    pub id: i64,
+    pub pipe_id: String,
+    pub run_at: DateTime<Utc>,
+    pub payload: serde_json::Value,
+    pub status: PipeJobStatus,
+    pub attempts: i64,
+    pub max_attempts: i64,
+    pub backoff_seconds: i64,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+#[derive(FromRow)]
+pub struct PipeJobRaw {
+    pub id: i64,
+    pub pipe_id: String,
+    pub run_at: DateTime<Utc>,
+    pub payload: String,
+    pub status: String,
+    pub attempts: i64,
+    pub max_attempts: i64,
+    pub backoff_seconds: i64,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+impl From<PipeJobRaw> for PipeJob {
+    fn from(raw: PipeJobRaw) -> Self {
+        Self {
+            id: raw.id,
+            pipe_id: raw.pipe_id,
+            run_at: raw.run_at,
+            payload: serde_json::from_str(&raw.payload).unwrap_or_default(),
+            status: PipeJobStatus::from_db(&raw.status),
+            attempts: raw.attempts,
+            max_attempts: raw.max_attempts,
+            backoff_seconds: raw.backoff_seconds,
+            last_error: raw.last_error,
+            created_at: raw.created_at,
+            finished_at: raw.finished_at,
+        }
+    }
+}
+
+/// Why a capture session ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionEndReason {
+    Stopped,
+    /// Still open after the longest a session may run, likely forgotten
+    TimedOut,
+}
+
+impl SessionEndReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SessionEndReason::Stopped => "stopped",
+            SessionEndReason::TimedOut => "timed_out",
+        }
+    }
+
+    pub fn from_db(reason: &str) -> Self {
+        match reason {
+            "timed_out" => SessionEndReason::TimedOut,
+            _ => SessionEndReason::Stopped,
+        }
+    }
+}
+
+/// What a capture session is exported to when it ends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[serde(alias = "md")]
+    Markdown,
+    Json,
+}
+
+impl ExportFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "markdown",
+            ExportFormat::Json => "json",
+        }
+    }
+
+    pub fn from_db(format: &str) -> Option<Self> {
+        match format {
+            "markdown" => Some(ExportFormat::Markdown),
+            "json" => Some(ExportFormat::Json),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "md",
+            ExportFormat::Json => "json",
+        }
+    }
+}
+
+/// A named span of capture, everything recorded between `started_at` and `ended_at`
+/// belongs to it. Open while `ended_at` is unset.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct CaptureSession {
+    pub id: i64,
+    pub name: String,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: Option<DateTime<Utc>>,
+    pub end_reason: Option<SessionEndReason>,
+    pub auto_export: Option<ExportFormat>,
+    pub export_path: Option<String>,
+}
+
+#[derive(FromRow)]
+pub struct CaptureSessionRaw {
+    pub id: i64,
+    pub name: String,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: Option<DateTime<Utc>>,
+    pub end_reason: Option<String>,
+    pub auto_export: Option<String>,
+    pub export_path: Option<String>,
+}
+
+impl From<CaptureSessionRaw> for CaptureSession {
+    fn from(raw: CaptureSessionRaw) -> Self {
+        Self {
+            id: raw.id,
+            name: raw.name,
+            started_at: raw.started_at,
+            ended_at: raw.ended_at,
+            end_reason: raw.end_reason.as_deref().map(SessionEndReason::from_db),
+            auto_export: raw.auto_export.as_deref().and_then(ExportFormat::from_db),
+            export_path: raw.export_path,
+        }
+    }
+}
+
+/// Width of the buckets usage aggregates are reported in. Buckets start on the UTC hour
+/// or day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum UsageBucket {
+    #[default]
+    Hour,
+    Day,
+}
+
+impl UsageBucket {
+    /// sqlite `strftime` format of the start of the bucket a timestamp falls in.
+    pub fn strftime(&self) -> &'static str {
+        match self {
+            UsageBucket::Hour => "%Y-%m-%dT%H:00:00Z",
+            UsageBucket::Day => "%Y-%m-%dT00:00:00Z",
+        }
+    }
+}
+
+/// Time an app was focused during one bucket.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AppUsage {
+    pub bucket: DateTime<Utc>,
+    pub app_name: String,
+    pub seconds: f64,
+}
+
+/// Frames and transcriptions captured during one bucket.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct CaptureCounts {
+    pub bucket: DateTime<Utc>,
+    pub frames: i64,
+    pub transcriptions: i64,
+}
+
+/// What a pipe sent to and received from one host through the network proxy.
+#[derive(Debug, Serialize, Deserialize, FromRow, Clone, PartialEq)]
+pub struct PipeNetworkStats {
+    pub host: String,
+    pub bytes_up: i64,
+    pub bytes_down: i64,
+    pub requests: i64,
+    /// Requests refused because the host isn't in the pipe's `hosts`
+    pub blocked: i64,
+    pub last_seen: DateTime<Utc>,
+}
+
+/// A content type registered by a pipe. `schema` is its json schema as stored.
+#[derive(Debug, Serialize, Deserialize, FromRow, Clone)]
+pub struct PipeContentTypeRow {
+    pub name: String,
+    pub pipe_id: String,
+    pub schema: String,
+    pub version: i64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// What the index knows about a watched file, used to skip unchanged files.
+#[derive(Debug, FromRow, Clone, PartialEq)]
+pub struct DocumentState {
+    pub id: i64,
+    pub timestamp: DateTime<Utc>,
+    pub file_size: i64,
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+/// A `capture_gap` event: a span where nothing was recorded, e.g. while the machine
+/// was asleep.
+#[derive(Debug, Serialize, Deserialize, FromRow, Clone, PartialEq)]
+pub struct CaptureGap {
+    pub id: i64,
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    pub reason: String,
+}
+
+/// The focused window of the latest frame, with the text read from it.
+#[derive(Debug, Serialize, Deserialize, FromRow, Clone, PartialEq)]
+pub struct FocusedWindow {
+    pub frame_id: i64,
+    pub timestamp: DateTime<Utc>,
+    pub app_name: String,
+    pub window_name: String,
+    pub text: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow, Clone, PartialEq)]
+pub struct RecentTranscript {
+    pub id: i64,
+    pub timestamp: DateTime<Utc>,
+    pub device_name: String,
+    pub is_input_device: bool,
+    pub speaker_id: Option<i64>,
+    pub text: String,
+}
+
+/// A pipe record flagged as context when it was added.
+#[derive(Debug, Serialize, Deserialize, FromRow, Clone, PartialEq)]
+pub struct ContextPipeResult {
+    pub id: i64,
+    pub pipe_id: String,
+    pub content_type: String,
+    pub timestamp: DateTime<Utc>,
+    pub text: String,
+}
+
+/// A `display_changed` event: the resolution of a monitor changed while recording,
+/// its video continues in a new chunk from `changed_at`.
+#[derive(Debug, Serialize, Deserialize, FromRow, Clone, PartialEq)]
+pub struct DisplayChange {
+    pub id: i64,
+    pub device_name: String,
+    pub changed_at: DateTime<Utc>,
+    pub previous_width: u32,
+    pub previous_height: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// A `clock_adjusted` event: a wall clock jump noticed while stamping captured items.
+#[derive(Debug, Serialize, Deserialize, FromRow, Clone, PartialEq)]
+pub struct ClockAdjustmentRow {
+    pub id: i64,
+    pub detected_at: DateTime<Utc>,
+    pub system_time: DateTime<Utc>,
+    pub monotonic_ns: i64,
+    /// Negative when the clock was set back
+    pub jump_ms: i64,
+    pub timestamp_source: String,
+}
+
+/// A row of the `events` table, read into a [`crate::events::Event`].
+#[derive(Debug, FromRow, Clone, PartialEq)]
+pub struct EventRow {
+    pub id: i64,
+    pub event_type: String,
+    pub timestamp: DateTime<Utc>,
+    pub subsystem: String,
+    pub severity: String,
+    /// Json
+    pub payload: String,
+}
+
+/// Captured content the retention job prunes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RetentionKind {
+    Frame,
+    Audio,
+    Ui,
+}
+
+/// A captured row as the retention job sees it.
+#[derive(Debug, FromRow, Clone, PartialEq)]
+pub struct RetentionRow {
+    pub id: i64,
+    pub timestamp: DateTime<Utc>,
+    /// Apps on the row separated by newlines, empty for audio
+    pub app_names: String,
+    /// Video or audio chunk holding the row, empty for ui
+    pub file_path: String,
+    /// Tagged by hand, tags added by rules don't count
+    pub tagged: bool,
+    /// Set by a tag rule, see [`crate::tag_rules`]
+    pub retention_class: Option<String>,
+}
+
+impl RetentionRow {
+    pub fn apps(&self) -> impl Iterator<Item = &str> {
+        self.app_names.split('\n').filter(|app| !app.is_empty())
+    }
+}
+
+/// Rows of one app in one chunk file, summed up for the disk usage breakdown.
+#[derive(Debug, FromRow, Clone, PartialEq)]
+pub struct RetentionUsage {
+    pub file_path: String,
+    pub app_name: String,
+    pub rows: i64,
+    pub oldest: DateTime<Utc>,
+}
+
+/// Chunk tables whose files the archive tier moves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MediaChunkKind {
+    Video,
+    Audio,
+}
+
+impl MediaChunkKind {
+    pub fn table(&self) -> &'static str {
+        match self {
+            MediaChunkKind::Video => "video_chunks",
+            MediaChunkKind::Audio => "audio_chunks",
+        }
+    }
+}
+
+/// A chunk whose content is all older than the archive age and none of it tagged.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ArchiveCandidate {
+    pub kind: MediaChunkKind,
+    pub id: i64,
+    pub file_path: String,
+    /// Capture time of the chunk's newest frame or transcription
+    pub newest: DateTime<Utc>,
+}
+
+/// An archived chunk whose original file may still be on disk.
+#[derive(Debug, FromRow, Clone, PartialEq)]
+pub struct PendingArchiveMove {
+    pub id: i64,
+    pub original_path: String,
+    pub archive_path: String,
 }
 
 #[derive(Debug, Clone)]
@@ -146,6 +754,8 @@ pub struct OCREntry {
     pub window_name: String,
     pub device_name: String,
     pub video_file_path: String,
+    pub storage_mode: StorageMode,
+    pub thumbnail_path: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -194,3 +804,176 @@ pub struct AudioChunksResponse {
     pub file_path: String,
     pub timestamp: DateTime<Utc>,
 }
+
+/// One captured item written as a unit, see [`crate::DatabaseManager::write_capture`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CaptureWrite {
+    Frame(FrameWrite),
+    Transcription(TranscriptionWrite),
+}
+
+/// A frame with the ocr of its windows.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FrameWrite {
+    pub device_name: String,
+    /// The chunk the frame was encoded into, the device's latest chunk when unset
+    pub video_chunk_id: Option<i64>,
+    /// Set on replays and imports, live frames are stamped when written
+    pub timestamp: Option<DateTime<Utc>>,
+    pub windows: Vec<WindowOcrWrite>,
+    /// Frames without video go to the device's text only chunk
+    #[serde(default)]
+    pub storage_mode: StorageMode,
+    #[serde(default)]
+    pub thumbnail_path: Option<String>,
+    /// Every window on the monitor when the frame was captured
+    #[serde(default)]
+    pub window_layout: Option<WindowLayout>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WindowOcrWrite {
+    pub text: String,
+    pub text_json: String,
+    pub app_name: String,
+    pub window_name: String,
+    pub ocr_engine: String,
+    pub focused: bool,
+    /// What the OCR engine read, when `text` was post-corrected
+    #[serde(default)]
+    pub raw_text: Option<String>,
+    #[serde(default)]
+    pub corrected_by: Option<CorrectionSource>,
+}
+
+/// A transcription of an audio file, the file's chunk is created with it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TranscriptionWrite {
+    pub file_path: String,
+    pub transcription: String,
+    pub offset_index: i64,
+    pub transcription_engine: String,
+    pub device_name: String,
+    pub is_input_device: bool,
+    pub speaker_id: Option<i64>,
+    pub start_time: Option<f64>,
+    pub end_time: Option<f64>,
+}
+
+/// Where a capture write ended up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureOutcome {
+    /// Committed, with the frame id or the audio chunk id of a transcription.
+    /// A frame id of 0 means the device has no video chunk yet.
+    Written(i64),
+    /// The database stayed locked, the write waits in the spill journal
+    Spilled,
+}
+
+/// What a frame's image can be loaded from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameImageSource {
+    pub frame_id: i64,
+    /// Empty without video
+    pub file_path: String,
+    pub offset_index: i64,
+    pub storage_mode: StorageMode,
+    pub thumbnail_path: Option<String>,
+    /// `file_path` is in the archive directory
+    pub archived: bool,
+}
+
+/// The text of a frame and the windows it shows, what copies of it are made from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameContent {
+    pub frame_id: i64,
+    pub timestamp: DateTime<Utc>,
+    /// Focused window first
+    pub windows: Vec<FrameWindowText>,
+    /// Every window on the monitor, also those whose text wasn't kept
+    pub layout: Option<WindowLayout>,
+    /// Marked sensitive by a tag rule
+    pub sensitive: bool,
+}
+
+/// The OCR text of one window of a frame.
+#[derive(Debug, Clone, PartialEq, FromRow)]
+pub struct FrameWindowText {
+    pub app_name: String,
+    pub window_name: String,
+    pub text: String,
+    pub focused: bool,
+}
+
+/// A window of a recent frame, or the transcripts of a recent audio chunk, that a tag
+/// rule is tested on. Frames without OCR text have a row without app or text.
+#[derive(Debug, Clone, PartialEq, FromRow)]
+pub struct RuleSampleRow {
+    pub id: i64,
+    pub timestamp: DateTime<Utc>,
+    pub app_name: Option<String>,
+    pub window_name: Option<String>,
+    pub text: Option<String>,
+}
+
+/// The OCR text of a window of a frame with the matches of a find marked, see
+/// [`crate::frame_find`].
+#[derive(Debug, Clone, PartialEq, FromRow)]
+pub struct FrameFindRow {
+    pub app_name: String,
+    pub window_name: String,
+    pub focused: bool,
+    pub highlighted: String,
+    pub text_json: Option<String>,
+}
+
+/// What an audio chunk can be played from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioChunkSource {
+    pub chunk_id: i64,
+    pub file_path: String,
+    /// `file_path` is in the archive directory
+    pub archived: bool,
+    /// Start and end in seconds of each transcription still kept, both `None` for
+    /// one covering the whole chunk
+    pub segments: Vec<(Option<f64>, Option<f64>)>,
+}
+
+/// Daily rollups of one app as captured, summed over every day.
+#[derive(Debug, FromRow, Clone, PartialEq)]
+pub struct AppRollup {
+    pub app_name: String,
+    pub frames: i64,
+    pub ocr_chars: i64,
+    pub audio_seconds: f64,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+}
+
+/// How far the rollup job got.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RollupState {
+    pub last_frame_id: i64,
+    pub last_transcription_id: i64,
+    /// End of the last run, `None` before the first one
+    pub updated_at: Option<DateTime<Utc>>,
+    /// Rows captured since, not counted yet
+    pub pending_frames: i64,
+    pub pending_transcriptions: i64,
+    pub dirty_days: i64,
+}
+
+/// What one rollup run counted.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct RollupProgress {
+    pub frames: i64,
+    pub transcriptions: i64,
+    pub days_rebuilt: i64,
+}
+
+impl RollupProgress {
+    pub fn is_empty(&self) -> bool {
+        self.frames == 0 && self.transcriptions == 0 && self.days_rebuilt == 0
+    }
+}
@@ -0,0 +1,357 @@
+//! What happened to capture, in one `events` table: sleep and wake, capture paused
+//! and resumed, display changes, clock adjustments, pipe crashes and revoked
+//! permissions.
+//!
+//! Every subsystem records through the one [`EventRecorder`] of the process. It stores
+//! the event, then publishes the stored row to `GET /events/stream`, so what is
+//! streamed live and what `GET /events` returns later are the same events. Events are
+//! kept for [`EVENT_RETENTION_DAYS`], whatever the retention of captured content.
+
+use crate::db_types::EventRow;
+use crate::wake::WakeSummary;
+use crate::DatabaseManager;
+use anyhow::Result;
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use screenpipe_core::power::SubsystemOutcome;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tracing::{error, info, warn};
+
+/// How long events are kept.
+pub const EVENT_RETENTION_DAYS: i64 = 365;
+
+/// How often events past [`EVENT_RETENTION_DAYS`] are deleted.
+pub const EVENT_PRUNE_INTERVAL: Duration = Duration::from_secs(24 * 3600);
+
+/// How far back health reports critical events.
+pub const CRITICAL_EVENTS_HOURS: i64 = 24;
+
+/// Most critical events health reports.
+pub const MAX_CRITICAL_EVENTS: u32 = 10;
+
+/// Every event type, as documented in the api reference.
+pub const EVENT_TYPES: &[&str] = &[
+    "sleep",
+    "wake",
+    "capture_gap",
+    "capture_paused",
+    "capture_resumed",
+    "display_changed",
+    "video_gap",
+    "clock_adjusted",
+    "pipe_crashed",
+    "permission_revoked",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Critical,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Critical => "critical",
+        }
+    }
+
+    /// This severity and the ones above it.
+    pub fn and_above(&self) -> Vec<Severity> {
+        [Severity::Info, Severity::Warning, Severity::Critical]
+            .into_iter()
+            .filter(|severity| severity >= self)
+            .collect()
+    }
+}
+
+impl std::str::FromStr for Severity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "info" => Ok(Severity::Info),
+            "warning" => Ok(Severity::Warning),
+            "critical" => Ok(Severity::Critical),
+            _ => Err(format!(
+                "unknown severity '{}', expected info, warning or critical",
+                s
+            )),
+        }
+    }
+}
+
+/// An event by type, with its payload.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "payload", rename_all = "snake_case")]
+pub enum EventKind {
+    /// The machine is going to sleep
+    Sleep {},
+    /// The machine woke up, with what each subsystem did about it
+    Wake(WakeSummary),
+    /// Nothing was captured between `start_time` and `end_time`, e.g. `sleep`
+    CaptureGap {
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+        reason: String,
+    },
+    /// Media writes stopped, e.g. `media_unavailable` when the drive is gone
+    CapturePaused { reason: String, media_dir: String },
+    CaptureResumed {
+        reason: String,
+        media_dir: String,
+        paused_secs: i64,
+    },
+    /// The resolution of a monitor changed, its video continues in a new chunk
+    DisplayChanged {
+        device_name: String,
+        previous_width: u32,
+        previous_height: u32,
+        width: u32,
+        height: u32,
+    },
+    /// `frames` captured between `start_time` and `end_time` are missing from the
+    /// video, e.g. `empty_frame` when they had no pixels. Their text is still indexed
+    VideoGap {
+        device_name: String,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+        frames: u64,
+        reason: String,
+    },
+    /// The wall clock jumped, `jump_ms` is negative when it was set back
+    ClockAdjusted {
+        system_time: DateTime<Utc>,
+        monotonic_ns: i64,
+        jump_ms: i64,
+        timestamp_source: String,
+    },
+    /// A pipe exited with an error
+    PipeCrashed { pipe_id: String, error: String },
+    /// The permissions granted to a pipe were revoked
+    PermissionRevoked { pipe_id: String },
+}
+
+impl EventKind {
+    pub fn event_type(&self) -> &'static str {
+        match self {
+            EventKind::Sleep {} => "sleep",
+            EventKind::Wake(_) => "wake",
+            EventKind::CaptureGap { .. } => "capture_gap",
+            EventKind::CapturePaused { .. } => "capture_paused",
+            EventKind::CaptureResumed { .. } => "capture_resumed",
+            EventKind::DisplayChanged { .. } => "display_changed",
+            EventKind::VideoGap { .. } => "video_gap",
+            EventKind::ClockAdjusted { .. } => "clock_adjusted",
+            EventKind::PipeCrashed { .. } => "pipe_crashed",
+            EventKind::PermissionRevoked { .. } => "permission_revoked",
+        }
+    }
+
+    /// Part of screenpipe the event comes from.
+    pub fn subsystem(&self) -> &'static str {
+        match self {
+            EventKind::Sleep {} | EventKind::Wake(_) | EventKind::CaptureGap { .. } => "power",
+            EventKind::CapturePaused { .. } | EventKind::CaptureResumed { .. } => "storage",
+            EventKind::DisplayChanged { .. } | EventKind::VideoGap { .. } => "video",
+            EventKind::ClockAdjusted { .. } => "clock",
+            EventKind::PipeCrashed { .. } | EventKind::PermissionRevoked { .. } => "pipes",
+        }
+    }
+
+    pub fn severity(&self) -> Severity {
+        match self {
+            EventKind::CapturePaused { .. } => Severity::Critical,
+            EventKind::Wake(summary) => {
+                let outcomes = summary.subsystems.values();
+                let mut severity = Severity::Info;
+                for outcome in outcomes {
+                    match outcome {
+                        SubsystemOutcome::Failed(_) => return Severity::Critical,
+                        SubsystemOutcome::TimedOut => severity = Severity::Warning,
+                        SubsystemOutcome::Ok(_) => {}
+                    }
+                }
+                severity
+            }
+            EventKind::VideoGap { .. }
+            | EventKind::ClockAdjusted { .. }
+            | EventKind::PipeCrashed { .. } => Severity::Warning,
+            _ => Severity::Info,
+        }
+    }
+}
+
+/// A recorded event, as returned by `GET /events` and streamed on
+/// `GET /events/stream`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Event {
+    pub id: i64,
+    pub timestamp: DateTime<Utc>,
+    pub subsystem: String,
+    pub severity: Severity,
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+impl TryFrom<EventRow> for Event {
+    type Error = serde_json::Error;
+
+    fn try_from(row: EventRow) -> Result<Self, Self::Error> {
+        let payload: serde_json::Value = serde_json::from_str(&row.payload)?;
+        let kind = serde_json::from_value(serde_json::json!({
+            "type": row.event_type,
+            "payload": payload,
+        }))?;
+        Ok(Event {
+            id: row.id,
+            timestamp: row.timestamp,
+            subsystem: row.subsystem,
+            severity: row.severity.parse().unwrap_or(Severity::Info),
+            kind,
+        })
+    }
+}
+
+/// Which events `GET /events` returns.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EventFilter {
+    /// Any type when empty
+    pub types: Vec<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    /// This severity and above
+    pub severity: Option<Severity>,
+    /// Only events recorded after this id, in the order recorded. Newest first
+    /// otherwise
+    pub after_id: Option<i64>,
+    pub limit: u32,
+}
+
+impl EventFilter {
+    /// Whether `event` passes the filter, ignoring `after_id` and `limit`. For events
+    /// streamed live, that didn't go through the database.
+    pub fn matches(&self, event: &Event) -> bool {
+        (self.types.is_empty() || self.types.iter().any(|t| t == event.kind.event_type()))
+            && self.since.is_none_or(|since| event.timestamp >= since)
+            && self.until.is_none_or(|until| event.timestamp <= until)
+            && self
+                .severity
+                .is_none_or(|severity| event.severity >= severity)
+    }
+}
+
+/// Stores events and publishes them once stored, see the module docs.
+pub struct EventRecorder {
+    db: Arc<DatabaseManager>,
+    live: broadcast::Sender<Event>,
+}
+
+impl EventRecorder {
+    pub fn new(db: Arc<DatabaseManager>) -> Self {
+        let (live, _) = broadcast::channel(256);
+        Self { db, live }
+    }
+
+    /// Records `kind` as happening now.
+    pub async fn record(&self, kind: EventKind) -> Result<Event> {
+        self.record_at(kind, Utc::now()).await
+    }
+
+    pub async fn record_at(&self, kind: EventKind, timestamp: DateTime<Utc>) -> Result<Event> {
+        let mut payload = serde_json::to_value(&kind)?;
+        let payload = payload
+            .get_mut("payload")
+            .map(serde_json::Value::take)
+            .unwrap_or_default();
+        let severity = kind.severity();
+        let id = self
+            .db
+            .insert_event(
+                kind.event_type(),
+                timestamp,
+                kind.subsystem(),
+                severity.as_str(),
+                &payload.to_string(),
+            )
+            .await?;
+        let event = Event {
+            id,
+            timestamp,
+            subsystem: kind.subsystem().to_string(),
+            severity,
+            kind,
+        };
+        // Nobody listening is fine
+        let _ = self.live.send(event.clone());
+        Ok(event)
+    }
+
+    /// Records `kind` in the background, for callers that can't wait on the database.
+    /// The timestamp is taken now. Must be called from within a tokio runtime.
+    pub fn emit(self: &Arc<Self>, kind: EventKind) {
+        let recorder = Arc::clone(self);
+        let timestamp = Utc::now();
+        tokio::spawn(async move {
+            let event_type = kind.event_type();
+            if let Err(e) = recorder.record_at(kind, timestamp).await {
+                error!("failed to record {} event: {}", event_type, e);
+            }
+        });
+    }
+
+    /// Events recorded from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.live.subscribe()
+    }
+
+    pub async fn query(&self, filter: &EventFilter) -> Result<Vec<Event>> {
+        let rows = self.db.get_events(filter).await?;
+        Ok(rows
+            .into_iter()
+            .filter_map(|row| {
+                let id = row.id;
+                Event::try_from(row)
+                    .map_err(|e| warn!("skipping unreadable event {}: {}", id, e))
+                    .ok()
+            })
+            .collect())
+    }
+
+    /// Critical events of the last [`CRITICAL_EVENTS_HOURS`], newest first.
+    pub async fn recent_critical(&self) -> Result<Vec<Event>> {
+        self.query(&EventFilter {
+            since: Some(Utc::now() - ChronoDuration::hours(CRITICAL_EVENTS_HOURS)),
+            severity: Some(Severity::Critical),
+            limit: MAX_CRITICAL_EVENTS,
+            ..Default::default()
+        })
+        .await
+    }
+
+    /// Deletes the events recorded more than [`EVENT_RETENTION_DAYS`] before `now`.
+    /// Returns how many.
+    pub async fn prune(&self, now: DateTime<Utc>) -> Result<u64> {
+        let before = now - ChronoDuration::days(EVENT_RETENTION_DAYS);
+        Ok(self.db.delete_events_before(before).await?)
+    }
+
+    /// Prunes old events every [`EVENT_PRUNE_INTERVAL`] until the process exits.
+    pub async fn run_retention(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(EVENT_PRUNE_INTERVAL);
+        loop {
+            interval.tick().await;
+            match self.prune(Utc::now()).await {
+                Ok(0) => {}
+                Ok(deleted) => info!("deleted {} events past retention", deleted),
+                Err(e) => error!("failed to prune events: {}", e),
+            }
+        }
+    }
+}
@@ -0,0 +1,185 @@
+//! Find on a frame, for the frame viewer: where a term is in the OCR text of one frame,
+//! with `GET /frames/:id/find?q=`.
+//!
+//! Matching is done by the `ocr_text_fts` index, with FTS5's `highlight()`, so a term
+//! matches here exactly where search would find it: case and diacritics don't count,
+//! and `cafe` finds `Café`. The frames before and after with the term come from the
+//! index too.
+//!
+//! Offsets are in unicode scalar values, and in utf-16 code units for javascript,
+//! never in bytes, so a highlight can't start or end in the middle of a character.
+
+use crate::db_types::FrameFindRow;
+use crate::search_query::Span;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Chars of text around a match in its snippet, on each side.
+pub const DEFAULT_FIND_CONTEXT: usize = 40;
+pub const MAX_FIND_CONTEXT: usize = 1000;
+
+/// Around the matches in the text `highlight()` returns. Private use characters, OCR
+/// doesn't produce them.
+pub const MATCH_START: char = '\u{E000}';
+pub const MATCH_END: char = '\u{E001}';
+
+/// Where a word of the OCR engine's output is on the frame, in the units of the engine:
+/// pixels for tesseract, fractions of the frame for apple's OCR.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BoundingBox {
+    pub left: f64,
+    pub top: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FindMatch {
+    /// The text matched, as OCR read it
+    pub text: String,
+    /// Where in the window's text, in unicode scalar values
+    pub range: Span,
+    /// The same in utf-16 code units, as javascript strings count
+    pub utf16_range: Span,
+    /// The match with up to `context` chars on each side
+    pub snippet: String,
+    /// Where the match is in the snippet, in unicode scalar values
+    pub snippet_range: Span,
+    /// Boxes of the words the match is in, empty when the engine gave none
+    pub boxes: Vec<BoundingBox>,
+}
+
+/// The matches in the text read from one window of the frame.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WindowMatches {
+    pub app_name: String,
+    pub window_name: String,
+    pub focused: bool,
+    pub matches: Vec<FindMatch>,
+}
+
+/// Response of `GET /frames/:id/find`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FrameFind {
+    pub frame_id: i64,
+    pub q: String,
+    /// Matches in every window of the frame, focused window first
+    pub total: usize,
+    pub windows: Vec<WindowMatches>,
+    /// Closest frames before and after this one with the term, within the filters
+    pub previous_frame_id: Option<i64>,
+    pub next_frame_id: Option<i64>,
+}
+
+/// FTS5 expression finding `term` as a phrase in the OCR text only, not in app or
+/// window names.
+pub fn fts_phrase(term: &str) -> String {
+    format!("text : \"{}\"", term.replace('"', "\"\""))
+}
+
+/// The matches of one window, from the text `highlight()` marked with [`MATCH_START`]
+/// and [`MATCH_END`].
+pub fn window_matches(row: FrameFindRow, context: usize) -> WindowMatches {
+    let (text, ranges) = unmark(&row.highlighted);
+    let words = word_boxes(&text, row.text_json.as_deref());
+    let chars: Vec<char> = text.chars().collect();
+    let matches = ranges
+        .into_iter()
+        .map(|range| {
+            let snippet_start = range.start.saturating_sub(context);
+            let snippet_end = (range.end + context).min(chars.len());
+            FindMatch {
+                text: chars[range.start..range.end].iter().collect(),
+                range,
+                utf16_range: Span {
+                    start: utf16_len(&chars[..range.start]),
+                    end: utf16_len(&chars[..range.end]),
+                },
+                snippet: chars[snippet_start..snippet_end].iter().collect(),
+                snippet_range: Span {
+                    start: range.start - snippet_start,
+                    end: range.end - snippet_start,
+                },
+                boxes: words
+                    .iter()
+                    .filter(|(word, _)| word.start < range.end && range.start < word.end)
+                    .map(|(_, bbox)| *bbox)
+                    .collect(),
+            }
+        })
+        .collect();
+    WindowMatches {
+        app_name: row.app_name,
+        window_name: row.window_name,
+        focused: row.focused,
+        matches,
+    }
+}
+
+/// Text without the markers, and the char ranges they were around.
+fn unmark(highlighted: &str) -> (String, Vec<Span>) {
+    let mut text = String::with_capacity(highlighted.len());
+    let mut ranges = Vec::new();
+    let mut len = 0;
+    let mut start = None;
+    for c in highlighted.chars() {
+        match c {
+            MATCH_START => start = Some(len),
+            MATCH_END => {
+                if let Some(start) = start.take() {
+                    ranges.push(Span { start, end: len });
+                }
+            }
+            c => {
+                text.push(c);
+                len += 1;
+            }
+        }
+    }
+    (text, ranges)
+}
+
+fn utf16_len(chars: &[char]) -> usize {
+    chars.iter().map(|c| c.len_utf16()).sum()
+}
+
+/// The words of the engine's `text_json` found in `text`, in order, with their char
+/// range. Words that aren't where expected, e.g. after OCR correction, are skipped.
+fn word_boxes(text: &str, text_json: Option<&str>) -> Vec<(Span, BoundingBox)> {
+    let Some(words) = text_json.and_then(|json| serde_json::from_str::<Vec<Value>>(json).ok())
+    else {
+        return Vec::new();
+    };
+    let mut boxes = Vec::new();
+    // Byte and char position searching continues from
+    let (mut byte, mut char) = (0, 0);
+    for word in &words {
+        let word_text = word["text"].as_str().unwrap_or_default().trim();
+        let (Some(bbox), Some(found)) = (bounding_box(word), text[byte..].find(word_text)) else {
+            continue;
+        };
+        if word_text.is_empty() {
+            continue;
+        }
+        let start = char + text[byte..byte + found].chars().count();
+        let end = start + word_text.chars().count();
+        boxes.push((Span { start, end }, bbox));
+        byte += found + word_text.len();
+        char = end;
+    }
+    boxes
+}
+
+/// Engines write the coordinates as strings, numbers are taken too.
+fn bounding_box(word: &Value) -> Option<BoundingBox> {
+    let number = |key: &str| match &word[key] {
+        Value::String(s) => s.parse::<f64>().ok(),
+        value => value.as_f64(),
+    };
+    Some(BoundingBox {
+        left: number("left")?,
+        top: number("top")?,
+        width: number("width")?,
+        height: number("height")?,
+    })
+}
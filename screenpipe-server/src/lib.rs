@@ -1,19 +1,52 @@
+pub mod api_version;
+pub mod archive;
+pub mod audio_playback;
 mod auto_destruct;
 pub mod chunking;
 pub mod cli;
+pub mod context;
+pub mod copy;
 pub mod core;
+pub mod data_dir;
 pub mod db;
+pub mod db_retry;
 pub mod db_types;
+pub mod events;
 pub mod filtering;
+pub mod frame_find;
 pub mod highlight;
+pub mod ocr_correction;
+pub mod pipe_batch;
+pub mod pipe_content;
+pub mod pipe_lock;
 pub mod pipe_manager;
+pub mod pipe_permissions;
+pub mod pipe_proxy;
+pub mod pipe_schedule;
+pub mod pipe_tokens;
 mod plugin;
+pub mod privacy;
+pub mod problem;
+pub mod ranking;
+pub mod replay;
+pub mod retention;
 mod resource_monitor;
+pub mod search_query;
 mod server;
+pub mod sessions;
+pub mod settings_watch;
+pub mod sources;
+pub mod storage;
+pub mod storage_mode;
+pub mod tag_rules;
+pub mod telemetry;
 mod video;
 pub mod video_cache;
 mod video_db;
+pub mod video_guard;
 mod video_utils;
+pub mod wake;
+pub mod watch_folder;
 
 pub use auto_destruct::watch_pid;
 pub use cli::Cli;
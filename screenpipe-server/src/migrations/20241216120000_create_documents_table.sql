@@ -0,0 +1,45 @@
+-- Documents indexed from watch folders
+CREATE TABLE IF NOT EXISTS documents (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    file_path TEXT NOT NULL UNIQUE,
+    kind TEXT NOT NULL,
+    text TEXT NOT NULL DEFAULT '',
+    timestamp TIMESTAMP NOT NULL,
+    file_size INTEGER NOT NULL DEFAULT 0,
+    indexed_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    deleted_at TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_documents_timestamp ON documents(timestamp);
+CREATE INDEX IF NOT EXISTS idx_documents_deleted_at ON documents(deleted_at);
+
+CREATE VIRTUAL TABLE IF NOT EXISTS documents_fts USING fts5(
+    text,
+    file_path,
+    document_id UNINDEXED,
+    tokenize='unicode61'
+);
+
+-- Tombstoned documents (deleted_at set) are kept out of the FTS index
+CREATE TRIGGER IF NOT EXISTS documents_ai AFTER INSERT ON documents
+WHEN NEW.deleted_at IS NULL AND NEW.text != ''
+BEGIN
+    INSERT INTO documents_fts(text, file_path, document_id)
+    VALUES (NEW.text, NEW.file_path, NEW.id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS documents_update AFTER UPDATE ON documents
+BEGIN
+    DELETE FROM documents_fts
+    WHERE document_id = OLD.id;
+
+    INSERT INTO documents_fts(text, file_path, document_id)
+    SELECT NEW.text, NEW.file_path, NEW.id
+    WHERE NEW.deleted_at IS NULL AND NEW.text != '';
+END;
+
+CREATE TRIGGER IF NOT EXISTS documents_delete AFTER DELETE ON documents
+BEGIN
+    DELETE FROM documents_fts
+    WHERE document_id = OLD.id;
+END;
@@ -0,0 +1,9 @@
+-- Periods with nothing captured because the machine was asleep
+CREATE TABLE IF NOT EXISTS capture_gaps (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    start_time TIMESTAMP NOT NULL,
+    end_time TIMESTAMP NOT NULL,
+    reason TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_capture_gaps_start_time ON capture_gaps(start_time);
@@ -0,0 +1,56 @@
+-- Content types registered by pipes, with the json schema their records follow
+CREATE TABLE IF NOT EXISTS pipe_content_types (
+    name TEXT PRIMARY KEY,
+    pipe_id TEXT NOT NULL,
+    schema TEXT NOT NULL,
+    version INTEGER NOT NULL DEFAULT 1,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_pipe_content_types_pipe_id ON pipe_content_types(pipe_id);
+
+-- Records inserted by pipes. `search_text` is the text plus the indexable fields
+CREATE TABLE IF NOT EXISTS pipe_content (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    content_type TEXT NOT NULL,
+    pipe_id TEXT NOT NULL,
+    timestamp TIMESTAMP NOT NULL,
+    text TEXT NOT NULL DEFAULT '',
+    fields TEXT NOT NULL DEFAULT '{}',
+    search_text TEXT NOT NULL DEFAULT '',
+    FOREIGN KEY (content_type) REFERENCES pipe_content_types(name)
+);
+
+CREATE INDEX IF NOT EXISTS idx_pipe_content_type_timestamp ON pipe_content(content_type, timestamp);
+CREATE INDEX IF NOT EXISTS idx_pipe_content_pipe_id ON pipe_content(pipe_id);
+
+CREATE VIRTUAL TABLE IF NOT EXISTS pipe_content_fts USING fts5(
+    search_text,
+    content_type UNINDEXED,
+    content_id UNINDEXED,
+    tokenize='unicode61'
+);
+
+CREATE TRIGGER IF NOT EXISTS pipe_content_ai AFTER INSERT ON pipe_content
+WHEN NEW.search_text != ''
+BEGIN
+    INSERT INTO pipe_content_fts(search_text, content_type, content_id)
+    VALUES (NEW.search_text, NEW.content_type, NEW.id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS pipe_content_update AFTER UPDATE ON pipe_content
+BEGIN
+    DELETE FROM pipe_content_fts
+    WHERE content_id = OLD.id;
+
+    INSERT INTO pipe_content_fts(search_text, content_type, content_id)
+    SELECT NEW.search_text, NEW.content_type, NEW.id
+    WHERE NEW.search_text != '';
+END;
+
+CREATE TRIGGER IF NOT EXISTS pipe_content_delete AFTER DELETE ON pipe_content
+BEGIN
+    DELETE FROM pipe_content_fts
+    WHERE content_id = OLD.id;
+END;
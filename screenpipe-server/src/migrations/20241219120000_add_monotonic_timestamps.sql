@@ -0,0 +1,17 @@
+-- Nanoseconds since the recording process started, set on live captures. Orders items
+-- of one process even when the wall clock was set back.
+ALTER TABLE frames ADD COLUMN monotonic_ns INTEGER;
+ALTER TABLE audio_chunks ADD COLUMN monotonic_ns INTEGER;
+ALTER TABLE audio_transcriptions ADD COLUMN monotonic_ns INTEGER;
+
+-- Wall clock jumps noticed while stamping captured items
+CREATE TABLE IF NOT EXISTS clock_adjustments (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    detected_at TIMESTAMP NOT NULL,
+    system_time TIMESTAMP NOT NULL,
+    monotonic_ns INTEGER NOT NULL,
+    jump_ms INTEGER NOT NULL,
+    timestamp_source TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_clock_adjustments_detected_at ON clock_adjustments(detected_at);
@@ -0,0 +1,17 @@
+-- One-off runs pipes schedule for themselves, fired by the pipe scheduler
+CREATE TABLE IF NOT EXISTS pipe_jobs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    pipe_id TEXT NOT NULL,
+    run_at TIMESTAMP NOT NULL,
+    payload TEXT NOT NULL DEFAULT 'null',
+    status TEXT NOT NULL DEFAULT 'pending',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    max_attempts INTEGER NOT NULL DEFAULT 1,
+    backoff_seconds INTEGER NOT NULL DEFAULT 60,
+    last_error TEXT,
+    created_at TIMESTAMP NOT NULL,
+    finished_at TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_pipe_jobs_status_run_at ON pipe_jobs(status, run_at);
+CREATE INDEX IF NOT EXISTS idx_pipe_jobs_pipe_id ON pipe_jobs(pipe_id);
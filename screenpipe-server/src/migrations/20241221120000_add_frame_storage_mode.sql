@@ -0,0 +1,4 @@
+-- The storage mode a frame was captured in: full, text_only or text_plus_thumbnails.
+-- Frames without video reference a per device chunk with an empty file path.
+ALTER TABLE frames ADD COLUMN storage_mode TEXT NOT NULL DEFAULT 'full';
+ALTER TABLE frames ADD COLUMN thumbnail_path TEXT;
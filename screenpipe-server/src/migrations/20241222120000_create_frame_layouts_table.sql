@@ -0,0 +1,12 @@
+-- Where windows were on screen when a frame was captured. window_* are the bounds of
+-- the window the frame's text was read from, for search filters; layout is every
+-- visible window on the monitor as json, frontmost first.
+CREATE TABLE IF NOT EXISTS frame_layouts (
+    frame_id INTEGER PRIMARY KEY,
+    window_x INTEGER,
+    window_y INTEGER,
+    window_width INTEGER,
+    window_height INTEGER,
+    layout TEXT NOT NULL,
+    FOREIGN KEY (frame_id) REFERENCES frames(id)
+);
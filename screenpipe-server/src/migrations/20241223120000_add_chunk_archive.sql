@@ -0,0 +1,12 @@
+-- Chunks whose file was moved to the archive directory, file_path points at the copy.
+ALTER TABLE video_chunks ADD COLUMN archived INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE audio_chunks ADD COLUMN archived INTEGER NOT NULL DEFAULT 0;
+
+-- Archived chunks whose original file is not deleted yet. A row is added in the same
+-- transaction that points the chunk at the archive and removed once the original is
+-- gone, so an interrupted archive run finishes the deletes on the next one.
+CREATE TABLE IF NOT EXISTS archive_moves (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    original_path TEXT NOT NULL,
+    archive_path TEXT NOT NULL
+);
@@ -0,0 +1,11 @@
+-- Traffic of pipes through the network proxy, one row per pipe and host
+CREATE TABLE IF NOT EXISTS pipe_network_stats (
+    pipe_id TEXT NOT NULL,
+    host TEXT NOT NULL,
+    bytes_up INTEGER NOT NULL DEFAULT 0,
+    bytes_down INTEGER NOT NULL DEFAULT 0,
+    requests INTEGER NOT NULL DEFAULT 0,
+    blocked INTEGER NOT NULL DEFAULT 0,
+    last_seen TIMESTAMP NOT NULL,
+    PRIMARY KEY (pipe_id, host)
+);
@@ -0,0 +1,4 @@
+-- Post-corrected OCR: `text` holds the corrected text the fts index is built from,
+-- `raw_text` what the OCR engine read. Both NULL for windows stored as read
+ALTER TABLE ocr_text ADD COLUMN raw_text TEXT;
+ALTER TABLE ocr_text ADD COLUMN corrected_by TEXT;
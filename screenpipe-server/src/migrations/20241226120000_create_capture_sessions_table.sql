@@ -0,0 +1,14 @@
+-- Named capture sessions started and stopped by hand, e.g. "ux interview #4". Captures
+-- belong to a session by time, everything between started_at and ended_at
+CREATE TABLE IF NOT EXISTS capture_sessions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    started_at TIMESTAMP NOT NULL,
+    ended_at TIMESTAMP,
+    end_reason TEXT,
+    auto_export TEXT,
+    export_path TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_capture_sessions_name ON capture_sessions(name);
+CREATE INDEX IF NOT EXISTS idx_capture_sessions_ended_at ON capture_sessions(ended_at);
@@ -0,0 +1,13 @@
+-- Seams in a monitor's video where its resolution changed, the chunk being written was
+-- finalized and the next one starts at the new size
+CREATE TABLE IF NOT EXISTS display_changes (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    device_name TEXT NOT NULL,
+    changed_at TIMESTAMP NOT NULL,
+    previous_width INTEGER NOT NULL,
+    previous_height INTEGER NOT NULL,
+    width INTEGER NOT NULL,
+    height INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_display_changes_changed_at ON display_changes(changed_at);
@@ -0,0 +1,4 @@
+-- Pipe records flagged as context for `GET /context/now`
+ALTER TABLE pipe_content ADD COLUMN context BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE INDEX IF NOT EXISTS idx_pipe_content_context_timestamp ON pipe_content(context, timestamp);
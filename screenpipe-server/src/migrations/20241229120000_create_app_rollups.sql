@@ -0,0 +1,28 @@
+-- What was captured per utc day and app, read by /privacy/summary instead of scanning
+-- frames. App names are stored as captured, they are normalized when summed
+CREATE TABLE IF NOT EXISTS app_daily_rollups (
+    day TEXT NOT NULL,
+    app_name TEXT NOT NULL,
+    frames INTEGER NOT NULL DEFAULT 0,
+    ocr_chars INTEGER NOT NULL DEFAULT 0,
+    -- Transcribed audio heard while the app was focused
+    audio_seconds REAL NOT NULL DEFAULT 0,
+    first_seen TIMESTAMP NOT NULL,
+    last_seen TIMESTAMP NOT NULL,
+    PRIMARY KEY (day, app_name)
+);
+
+-- How far the rollup job got, rows above these ids aren't counted yet
+CREATE TABLE IF NOT EXISTS app_rollup_state (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    last_frame_id INTEGER NOT NULL DEFAULT 0,
+    last_transcription_id INTEGER NOT NULL DEFAULT 0,
+    updated_at TIMESTAMP
+);
+
+INSERT OR IGNORE INTO app_rollup_state (id) VALUES (1);
+
+-- Days whose counted rows were deleted, the job rebuilds them
+CREATE TABLE IF NOT EXISTS app_rollup_dirty_days (
+    day TEXT PRIMARY KEY
+);
@@ -0,0 +1,38 @@
+-- Capture lifecycle events of every subsystem: sleep and wake, capture paused and
+-- resumed, display changes, clock adjustments, pipe crashes and revoked permissions.
+-- The payload is json, its fields depend on the type
+CREATE TABLE IF NOT EXISTS events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    type TEXT NOT NULL,
+    timestamp TIMESTAMP NOT NULL,
+    subsystem TEXT NOT NULL,
+    severity TEXT NOT NULL,
+    payload TEXT NOT NULL DEFAULT '{}'
+);
+
+CREATE INDEX IF NOT EXISTS idx_events_timestamp ON events(timestamp);
+CREATE INDEX IF NOT EXISTS idx_events_type_timestamp ON events(type, timestamp);
+
+-- The tables each subsystem wrote its own events to, moved over in time order
+INSERT INTO events (type, timestamp, subsystem, severity, payload)
+SELECT type, timestamp, subsystem, severity, payload FROM (
+    SELECT 'capture_gap' AS type, start_time AS timestamp, 'power' AS subsystem,
+        'info' AS severity,
+        json_object('start_time', start_time, 'end_time', end_time, 'reason', reason) AS payload
+    FROM capture_gaps
+    UNION ALL
+    SELECT 'display_changed', changed_at, 'video', 'info',
+        json_object('device_name', device_name, 'previous_width', previous_width,
+            'previous_height', previous_height, 'width', width, 'height', height)
+    FROM display_changes
+    UNION ALL
+    SELECT 'clock_adjusted', detected_at, 'clock', 'warning',
+        json_object('system_time', system_time, 'monotonic_ns', monotonic_ns,
+            'jump_ms', jump_ms, 'timestamp_source', timestamp_source)
+    FROM clock_adjustments
+)
+ORDER BY timestamp;
+
+DROP TABLE capture_gaps;
+DROP TABLE display_changes;
+DROP TABLE clock_adjustments;
@@ -0,0 +1,8 @@
+-- What tag rules set on captured content, see tag_rules.rs. Tags a rule added name
+-- it, tags set by hand have no rule
+ALTER TABLE frames ADD COLUMN sensitive INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE frames ADD COLUMN retention_class TEXT;
+ALTER TABLE audio_chunks ADD COLUMN sensitive INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE audio_chunks ADD COLUMN retention_class TEXT;
+ALTER TABLE vision_tags ADD COLUMN rule TEXT;
+ALTER TABLE audio_tags ADD COLUMN rule TEXT;
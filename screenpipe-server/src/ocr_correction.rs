@@ -0,0 +1,508 @@
+//! Post-correction of OCR text before it is stored and indexed.
+//!
+//! The dictionary pass is cheap and runs on every window: ligatures are spelled out,
+//! words hyphenated across a line break are joined, and words missing from the word
+//! lists are swapped for the known word one typical misread ("rn" read for "m") or one
+//! edit away, when a single such word stands out. With a language model configured,
+//! windows the OCR engine was unsure of get a second pass, skipped while capture runs
+//! over its latency budget. The text the engine read is kept next to the corrected
+//! one, which is what search and the fts index see.
+
+use crate::ranking::ocr_confidence;
+use anyhow::{anyhow, Result};
+use screenpipe_core::latency::{latency_tracker, PipelineKind};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+/// Word list found on most linux and macos installs.
+pub const SYSTEM_WORD_LIST: &str = "/usr/share/dict/words";
+
+/// Longest a language model call may take before the window is stored as is.
+const LLM_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Share of the characters the language model may change, more is taken as a rewrite.
+const MAX_LLM_EDIT_RATIO: f64 = 0.25;
+
+const SYSTEM_PROMPT: &str = "You fix OCR errors in text read from a screenshot. Fix \
+misread characters and words broken by the OCR, nothing else: do not rephrase, \
+complete, translate or remove anything. Reply with the corrected text only. Where \
+unsure, keep the text as it is.";
+
+/// Ligatures OCR engines return as single code points.
+const LIGATURES: [(char, &str); 7] = [
+    ('\u{fb00}', "ff"),
+    ('\u{fb01}', "fi"),
+    ('\u{fb02}', "fl"),
+    ('\u{fb03}', "ffi"),
+    ('\u{fb04}', "ffl"),
+    ('\u{fb05}', "st"),
+    ('\u{fb06}', "st"),
+];
+
+/// Typical misreads, as (read, meant).
+const CONFUSIONS: [(&str, &str); 11] = [
+    ("rn", "m"),
+    ("m", "rn"),
+    ("cl", "d"),
+    ("d", "cl"),
+    ("vv", "w"),
+    ("0", "o"),
+    ("1", "l"),
+    ("1", "i"),
+    ("l", "i"),
+    ("i", "l"),
+    ("5", "s"),
+];
+
+/// Which pass changed a window's text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CorrectionSource {
+    Dictionary,
+    Llm,
+}
+
+impl CorrectionSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CorrectionSource::Dictionary => "dictionary",
+            CorrectionSource::Llm => "llm",
+        }
+    }
+
+    pub fn from_db(value: &str) -> Option<Self> {
+        match value {
+            "dictionary" => Some(CorrectionSource::Dictionary),
+            "llm" => Some(CorrectionSource::Llm),
+            _ => None,
+        }
+    }
+}
+
+/// A window's text after correction, with the last pass that changed it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Correction {
+    pub text: String,
+    pub source: CorrectionSource,
+}
+
+/// Known words, with how common they are when the word list says so.
+#[derive(Debug, Clone, Default)]
+pub struct Dictionary {
+    words: HashMap<String, u64>,
+}
+
+impl Dictionary {
+    /// Words added by the user, e.g. names and jargon of their field.
+    pub fn user_path(screenpipe_dir: &Path) -> PathBuf {
+        screenpipe_dir.join("ocr_dictionary.txt")
+    }
+
+    /// Reads a word list with one word per line, optionally followed by its count as
+    /// in frequency lists. Lines starting with `#` are skipped.
+    pub fn parse(list: &str) -> Self {
+        let mut dictionary = Self::default();
+        dictionary.extend(list);
+        dictionary
+    }
+
+    pub fn load(paths: &[PathBuf]) -> Result<Self> {
+        let mut dictionary = Self::default();
+        for path in paths {
+            let list = std::fs::read_to_string(path)
+                .map_err(|e| anyhow!("failed to read word list {}: {}", path.display(), e))?;
+            dictionary.extend(&list);
+        }
+        Ok(dictionary)
+    }
+
+    fn extend(&mut self, list: &str) {
+        for line in list.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.split_whitespace();
+            let Some(word) = fields.next() else {
+                continue;
+            };
+            // Tokens are split on anything but letters and digits, "don't" can't match
+            if !word.chars().all(char::is_alphanumeric) {
+                continue;
+            }
+            let count = fields.next().and_then(|c| c.parse().ok()).unwrap_or(1);
+            let entry = self.words.entry(word.to_lowercase()).or_default();
+            *entry = (*entry).max(count);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    pub fn contains(&self, word: &str) -> bool {
+        self.words.contains_key(&word.to_lowercase())
+    }
+
+    /// The known word `word` was most likely misread from, keeping its case. `None`
+    /// when the word is known, too short to tell, or no candidate stands out.
+    pub fn suggest(&self, word: &str) -> Option<String> {
+        if word.chars().count() < 3 || !word.chars().any(char::is_alphabetic) {
+            return None;
+        }
+        let lower = word.to_lowercase();
+        if self.words.contains_key(&lower) {
+            return None;
+        }
+        // camelCase and the like are identifiers, not misread words
+        let capitalized = word.chars().skip(1).all(|c| !c.is_uppercase());
+        let upper = word.chars().all(|c| !c.is_lowercase());
+        if !capitalized && !upper {
+            return None;
+        }
+
+        let mut candidates = Vec::new();
+        for (read, meant) in CONFUSIONS {
+            for (i, _) in lower.match_indices(read) {
+                let candidate = format!("{}{}{}", &lower[..i], meant, &lower[i + read.len()..]);
+                if self.words.contains_key(&candidate) && !candidates.contains(&candidate) {
+                    candidates.push(candidate);
+                }
+            }
+        }
+        // Any single edit is too loose for short words, and would rename capitalized
+        // words that are mostly names the list doesn't know
+        let lowercase = word.chars().all(|c| !c.is_uppercase());
+        if candidates.is_empty()
+            && lowercase
+            && lower.chars().count() >= 4
+            && lower.chars().all(char::is_alphabetic)
+        {
+            candidates = edits(&lower)
+                .into_iter()
+                .filter(|candidate| self.words.contains_key(candidate))
+                .collect();
+            candidates.sort();
+            candidates.dedup();
+        }
+
+        let best = self.most_common(candidates)?;
+        Some(match_case(word, &best))
+    }
+
+    /// The only candidate, or the one at least twice as common as every other.
+    fn most_common(&self, mut candidates: Vec<String>) -> Option<String> {
+        let count = |word: &String| self.words.get(word).copied().unwrap_or(0);
+        candidates.sort_by_key(|word| std::cmp::Reverse(count(word)));
+        match candidates.as_slice() {
+            [] => None,
+            [only] => Some(only.clone()),
+            [first, second, ..] if count(first) >= 2 * count(second) => Some(first.clone()),
+            _ => None,
+        }
+    }
+
+    /// Runs the dictionary pass over `text`.
+    pub fn correct(&self, text: &str) -> String {
+        let text = spell_out_ligatures(text);
+        let tokens = tokens(&text);
+        let mut corrected = String::with_capacity(text.len());
+        let mut written = 0;
+        let mut i = 0;
+        while i < tokens.len() {
+            let (start, end) = tokens[i];
+            corrected.push_str(&text[written..start]);
+            written = end;
+            let word = &text[start..end];
+
+            // "recog-\nnition"
+            if let Some(&(next_start, next_end)) = tokens.get(i + 1) {
+                let gap = &text[end..next_start];
+                let next = &text[next_start..next_end];
+                let joined = format!("{}{}", word, next);
+                if gap.starts_with('-')
+                    && gap.contains('\n')
+                    && gap[1..].chars().all(char::is_whitespace)
+                    && self.contains(&joined)
+                    && !(self.contains(word) && self.contains(next))
+                {
+                    corrected.push_str(&joined);
+                    written = next_end;
+                    i += 2;
+                    continue;
+                }
+            }
+
+            match self.suggest(word) {
+                Some(suggestion) => corrected.push_str(&suggestion),
+                None => corrected.push_str(word),
+            }
+            i += 1;
+        }
+        corrected.push_str(&text[written..]);
+        corrected
+    }
+}
+
+fn spell_out_ligatures(text: &str) -> Cow<'_, str> {
+    if !text.chars().any(|c| LIGATURES.iter().any(|(l, _)| *l == c)) {
+        return Cow::Borrowed(text);
+    }
+    let mut spelled = String::with_capacity(text.len());
+    for c in text.chars() {
+        match LIGATURES.iter().find(|(l, _)| *l == c) {
+            Some((_, letters)) => spelled.push_str(letters),
+            None => spelled.push(c),
+        }
+    }
+    Cow::Owned(spelled)
+}
+
+/// Byte ranges of the runs of letters and digits in `text`.
+fn tokens(text: &str) -> Vec<(usize, usize)> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices() {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                tokens.push((s, i));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        tokens.push((s, text.len()));
+    }
+    tokens
+}
+
+/// Every word one deletion, transposition, substitution or insertion away.
+fn edits(word: &str) -> Vec<String> {
+    let chars: Vec<char> = word.chars().collect();
+    let joined = |chars: &[char]| chars.iter().collect::<String>();
+    let mut edits = Vec::new();
+    for i in 0..=chars.len() {
+        for letter in 'a'..='z' {
+            let mut inserted = chars.clone();
+            inserted.insert(i, letter);
+            edits.push(joined(&inserted));
+        }
+        if i == chars.len() {
+            break;
+        }
+        let mut deleted = chars.clone();
+        deleted.remove(i);
+        edits.push(joined(&deleted));
+        if i + 1 < chars.len() {
+            let mut transposed = chars.clone();
+            transposed.swap(i, i + 1);
+            edits.push(joined(&transposed));
+        }
+        for letter in 'a'..='z' {
+            if letter != chars[i] {
+                let mut substituted = chars.clone();
+                substituted[i] = letter;
+                edits.push(joined(&substituted));
+            }
+        }
+    }
+    edits
+}
+
+/// `replacement` in the case of `word`: all caps, capitalized or lowercase.
+fn match_case(word: &str, replacement: &str) -> String {
+    let letters = || word.chars().filter(|c| c.is_alphabetic());
+    if letters().count() > 1 && letters().all(char::is_uppercase) {
+        return replacement.to_uppercase();
+    }
+    if letters().next().is_some_and(char::is_uppercase) {
+        let mut chars = replacement.chars();
+        return match chars.next() {
+            Some(first) => first.to_uppercase().chain(chars).collect(),
+            None => String::new(),
+        };
+    }
+    replacement.to_string()
+}
+
+/// Levenshtein distance in characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = if ca == *cb {
+                diagonal
+            } else {
+                1 + diagonal.min(above).min(row[j])
+            };
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+/// What the language model pass sends for one window.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CorrectionPrompt {
+    pub system: &'static str,
+    pub text: String,
+    /// Completion tokens allowed, about the length of the text
+    pub max_tokens: usize,
+}
+
+/// Runs the language model pass on one window's text. Swappable so the corrector can
+/// be exercised without a model.
+pub type CorrectionModel = Arc<
+    dyn Fn(CorrectionPrompt) -> Pin<Box<dyn Future<Output = Result<String>> + Send>> + Send + Sync,
+>;
+
+#[derive(Debug, Clone)]
+pub struct OcrCorrectionConfig {
+    /// Windows with an OCR confidence under this, in `[0, 1]`, get the language model
+    /// pass. Windows without a confidence never do
+    pub llm_below_confidence: f64,
+    /// Longer windows skip the language model pass
+    pub llm_max_chars: usize,
+    /// Upper bound on language model calls per minute
+    pub llm_calls_per_minute: u32,
+}
+
+impl Default for OcrCorrectionConfig {
+    fn default() -> Self {
+        Self {
+            llm_below_confidence: 0.6,
+            llm_max_chars: 1500,
+            llm_calls_per_minute: 6,
+        }
+    }
+}
+
+/// Corrects the OCR of each window before it is written.
+pub struct OcrCorrector {
+    config: OcrCorrectionConfig,
+    dictionary: Option<Dictionary>,
+    model: Option<CorrectionModel>,
+    next_llm_slot: Mutex<Option<Instant>>,
+}
+
+impl OcrCorrector {
+    /// Without a dictionary only ligatures are spelled out.
+    pub fn new(config: OcrCorrectionConfig, dictionary: Option<Dictionary>) -> Self {
+        Self {
+            config,
+            dictionary,
+            model: None,
+            next_llm_slot: Mutex::new(None),
+        }
+    }
+
+    pub fn with_model(mut self, model: CorrectionModel) -> Self {
+        self.model = Some(model);
+        self
+    }
+
+    /// The corrected text of a window, `None` when it is left as read.
+    pub async fn correct(&self, text: &str, text_json: &str) -> Option<Correction> {
+        let mut corrected = match &self.dictionary {
+            Some(dictionary) => dictionary.correct(text),
+            None => spell_out_ligatures(text).into_owned(),
+        };
+        let mut source = (corrected != text).then_some(CorrectionSource::Dictionary);
+
+        if let Some(model) = &self.model {
+            if self.wants_llm(&corrected, text_json) {
+                if let Some(fixed) = self.llm_pass(model, &corrected).await {
+                    if fixed != corrected {
+                        corrected = fixed;
+                        source = Some(CorrectionSource::Llm);
+                    }
+                }
+            }
+        }
+
+        source.map(|source| Correction {
+            text: corrected,
+            source,
+        })
+    }
+
+    fn wants_llm(&self, text: &str, text_json: &str) -> bool {
+        if text.trim().is_empty() || text.chars().count() > self.config.llm_max_chars {
+            return false;
+        }
+        if !ocr_confidence(text_json).is_some_and(|c| c < self.config.llm_below_confidence) {
+            return false;
+        }
+        // The model competes with capture for the cpu, it waits until capture keeps up
+        let tracker = latency_tracker();
+        if tracker.degradation(PipelineKind::Vision).is_active() || tracker.snapshot().over_budget()
+        {
+            debug!("ocr correction: over the latency budget, skipping the llm pass");
+            return false;
+        }
+        self.take_llm_slot()
+    }
+
+    /// Whether a call fits in `llm_calls_per_minute`, never waits.
+    fn take_llm_slot(&self) -> bool {
+        let per_minute = self.config.llm_calls_per_minute;
+        if per_minute == 0 {
+            return false;
+        }
+        let spacing = Duration::from_secs_f64(60.0 / per_minute as f64);
+        let mut next_slot = self.next_llm_slot.lock().unwrap();
+        let now = Instant::now();
+        if next_slot.is_some_and(|slot| slot > now) {
+            return false;
+        }
+        *next_slot = Some(now + spacing);
+        true
+    }
+
+    async fn llm_pass(&self, model: &CorrectionModel, text: &str) -> Option<String> {
+        let prompt = CorrectionPrompt {
+            system: SYSTEM_PROMPT,
+            text: text.to_string(),
+            max_tokens: text.chars().count() / 3 + 32,
+        };
+        let fixed = match tokio::time::timeout(LLM_TIMEOUT, model(prompt)).await {
+            Ok(Ok(fixed)) => fixed,
+            Ok(Err(e)) => {
+                warn!("ocr correction: llm pass failed: {}", e);
+                return None;
+            }
+            Err(_) => {
+                warn!("ocr correction: llm pass timed out");
+                return None;
+            }
+        };
+
+        let fixed = fixed.trim();
+        let distance = edit_distance(text, fixed);
+        if fixed.is_empty() || distance as f64 > text.chars().count() as f64 * MAX_LLM_EDIT_RATIO {
+            debug!(
+                "ocr correction: llm changed {} of {} characters, keeping the text",
+                distance,
+                text.chars().count()
+            );
+            return None;
+        }
+        Some(fixed.to_string())
+    }
+}
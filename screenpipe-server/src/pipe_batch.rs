@@ -0,0 +1,390 @@
+//! Ordered pipe operations run server side in one request, and the manifest diff
+//! `screenpipe pipe apply` turns into such a batch.
+//!
+//! A batch locks every pipe it touches before its first operation, so the ui and the
+//! cli can't change them halfway through. In atomic mode the first failure stops the
+//! batch and the installs it already did are undone; enable, config and start/stop
+//! changes are kept.
+
+use crate::pipe_manager::{PipeError, PipeInfo, PipeManager};
+use crate::problem::{ApiError, ErrorCode};
+use screenpipe_core::{ConflictPolicy, DownloadOptions};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::path::PathBuf;
+use tracing::{info, warn};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum PipeOperation {
+    /// Download the pipe, replacing an installed copy, from any source, but keeping its
+    /// config
+    Install {
+        url: String,
+    },
+    Enable {
+        pipe_id: String,
+    },
+    Disable {
+        pipe_id: String,
+    },
+    /// Same as `POST /pipes/update`, top level keys are replaced and `enabled` starts or
+    /// stops the pipe
+    Update {
+        pipe_id: String,
+        config: Value,
+    },
+    /// Json merge patch of the config, a running pipe restarts to pick it up
+    SetConfig {
+        pipe_id: String,
+        patch: Value,
+    },
+    /// Run the pipe without enabling it
+    Start {
+        pipe_id: String,
+    },
+    /// Stop the pipe without disabling it
+    Stop {
+        pipe_id: String,
+    },
+}
+
+impl PipeOperation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PipeOperation::Install { .. } => "install",
+            PipeOperation::Enable { .. } => "enable",
+            PipeOperation::Disable { .. } => "disable",
+            PipeOperation::Update { .. } => "update",
+            PipeOperation::SetConfig { .. } => "set_config",
+            PipeOperation::Start { .. } => "start",
+            PipeOperation::Stop { .. } => "stop",
+        }
+    }
+
+    /// The pipe the operation changes, for an install the id it will be installed as.
+    pub fn pipe_id(&self) -> Option<String> {
+        match self {
+            PipeOperation::Install { url } => PipeManager::pipe_id_for_source(url),
+            PipeOperation::Enable { pipe_id }
+            | PipeOperation::Disable { pipe_id }
+            | PipeOperation::Update { pipe_id, .. }
+            | PipeOperation::SetConfig { pipe_id, .. }
+            | PipeOperation::Start { pipe_id }
+            | PipeOperation::Stop { pipe_id } => Some(pipe_id.clone()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BatchRequest {
+    pub operations: Vec<PipeOperation>,
+    /// Stop at the first failure and undo the installs done so far
+    #[serde(default)]
+    pub atomic: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationStatus {
+    Ok,
+    Failed,
+    /// Not run because an earlier operation of an atomic batch failed
+    Skipped,
+    /// Succeeded, then undone because a later operation of an atomic batch failed
+    RolledBack,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OperationResult {
+    pub index: usize,
+    pub op: String,
+    pub pipe_id: Option<String>,
+    pub status: OperationStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BatchReport {
+    /// Every operation succeeded
+    pub success: bool,
+    pub atomic: bool,
+    pub rolled_back: bool,
+    pub results: Vec<OperationResult>,
+}
+
+struct Install {
+    index: usize,
+    pipe_id: String,
+    backup: Option<PathBuf>,
+}
+
+/// Runs `operations` in order, see the module docs for locking and atomic mode.
+pub async fn run_batch(
+    manager: &PipeManager,
+    operations: Vec<PipeOperation>,
+    atomic: bool,
+) -> BatchReport {
+    let pipe_ids: Vec<String> = operations.iter().filter_map(|op| op.pipe_id()).collect();
+    let _guards = manager.lock_pipes(&pipe_ids).await;
+
+    let mut results: Vec<OperationResult> = Vec::with_capacity(operations.len());
+    let mut installs: Vec<Install> = Vec::new();
+    let mut failed = false;
+
+    for (index, operation) in operations.iter().enumerate() {
+        let mut result = OperationResult {
+            index,
+            op: operation.name().to_string(),
+            pipe_id: operation.pipe_id(),
+            status: OperationStatus::Ok,
+            code: None,
+            detail: None,
+        };
+        if failed && atomic {
+            result.status = OperationStatus::Skipped;
+            results.push(result);
+            continue;
+        }
+
+        let outcome = match operation {
+            PipeOperation::Install { url } => match install(manager, url).await {
+                Ok((pipe_id, backup)) => {
+                    if atomic {
+                        installs.push(Install {
+                            index,
+                            pipe_id,
+                            backup,
+                        });
+                    } else {
+                        discard_backup(backup).await;
+                    }
+                    Ok(())
+                }
+                Err(e) => Err(e),
+            },
+            operation => apply(manager, operation).await,
+        };
+
+        if let Err(e) = outcome {
+            warn!("batch operation {} ({}) failed: {}", index, result.op, e);
+            failed = true;
+            result.status = OperationStatus::Failed;
+            result.code = Some(e.code);
+            result.detail = Some(e.detail);
+        }
+        results.push(result);
+    }
+
+    let rolled_back = failed && atomic && !installs.is_empty();
+    for install in installs.into_iter().rev() {
+        if failed {
+            match manager
+                .restore_pipe_locked(&install.pipe_id, install.backup.as_ref())
+                .await
+            {
+                Ok(()) => results[install.index].status = OperationStatus::RolledBack,
+                Err(e) => warn!("failed to roll back install of {}: {}", install.pipe_id, e),
+            }
+        } else {
+            discard_backup(install.backup).await;
+        }
+    }
+    if rolled_back {
+        info!("pipe batch failed, installs rolled back");
+    }
+
+    BatchReport {
+        success: !failed,
+        atomic,
+        rolled_back,
+        results,
+    }
+}
+
+/// Installs with a backup of the previous copy, restored straight away if the install
+/// fails so a broken download never leaves the pipe half replaced.
+async fn install(manager: &PipeManager, url: &str) -> Result<(String, Option<PathBuf>), ApiError> {
+    let pipe_id = PipeManager::pipe_id_for_source(url)
+        .ok_or_else(|| ApiError::invalid_request(format!("invalid pipe source: {}", url)))?;
+    let backup = manager
+        .backup_pipe_locked(&pipe_id)
+        .await
+        .map_err(|e| ApiError::new(ErrorCode::PipeError, e.to_string()))?;
+
+    let options = DownloadOptions {
+        on_conflict: ConflictPolicy::Replace,
+        ..Default::default()
+    };
+    match manager.download_pipe_locked(url, options).await {
+        Ok(_) => Ok((pipe_id, backup)),
+        Err(e) => {
+            if let Err(e) = manager.restore_pipe_locked(&pipe_id, backup.as_ref()).await {
+                warn!(
+                    "failed to restore {} after a failed install: {}",
+                    pipe_id, e
+                );
+            }
+            Err(ApiError::new(
+                ErrorCode::PipeError,
+                format!("failed to download pipe: {}", e),
+            ))
+        }
+    }
+}
+
+pub(crate) async fn discard_backup(backup: Option<PathBuf>) {
+    if let Some(backup) = backup {
+        if let Err(e) = tokio::fs::remove_dir_all(&backup).await {
+            warn!("failed to remove pipe backup {:?}: {}", backup, e);
+        }
+    }
+}
+
+async fn apply(manager: &PipeManager, operation: &PipeOperation) -> Result<(), ApiError> {
+    let result: Result<(), PipeError> = match operation {
+        PipeOperation::Install { .. } => unreachable!("installs are handled by the batch"),
+        PipeOperation::Enable { pipe_id } => {
+            manager
+                .update_config_locked(pipe_id, serde_json::json!({ "enabled": true }))
+                .await
+        }
+        PipeOperation::Disable { pipe_id } => {
+            manager
+                .update_config_locked(pipe_id, serde_json::json!({ "enabled": false }))
+                .await
+        }
+        PipeOperation::Update { pipe_id, config } => {
+            manager.update_config_locked(pipe_id, config.clone()).await
+        }
+        PipeOperation::SetConfig { pipe_id, patch } => {
+            manager.patch_config_locked(pipe_id, patch.clone()).await
+        }
+        PipeOperation::Start { pipe_id } => manager.start_pipe_locked(pipe_id).await,
+        PipeOperation::Stop { pipe_id } => {
+            if manager.get_pipe_info(pipe_id).await.is_none() {
+                Err(PipeError::NotFound(pipe_id.clone()))
+            } else {
+                manager.stop_pipe(pipe_id).await.map_err(PipeError::from)
+            }
+        }
+    };
+    result.map_err(ApiError::from)
+}
+
+/// Desired pipes for `screenpipe pipe apply`, usually written as yaml.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PipeManifest {
+    pub pipes: Vec<ManifestPipe>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestPipe {
+    /// Github url or local path the pipe is installed from
+    pub source: String,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Config keys to set, keys left out keep their installed value
+    #[serde(default)]
+    pub config: Map<String, Value>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// The smallest batch that brings `installed` to the state in `manifest`. Pipes the
+/// manifest doesn't list are left alone.
+pub fn plan_manifest(
+    manifest: &PipeManifest,
+    installed: &[PipeInfo],
+) -> Result<Vec<PipeOperation>, String> {
+    let mut operations = Vec::new();
+    let mut seen = Vec::new();
+
+    for pipe in &manifest.pipes {
+        let pipe_id = PipeManager::pipe_id_for_source(&pipe.source)
+            .ok_or_else(|| format!("invalid pipe source: {}", pipe.source))?;
+        if seen.contains(&pipe_id) {
+            return Err(format!("pipe '{}' is listed twice", pipe_id));
+        }
+        if let Some(key) = ["enabled", "source"]
+            .iter()
+            .find(|k| pipe.config.contains_key(**k))
+        {
+            return Err(format!(
+                "pipe '{}': '{}' can't be set in config, it is a manifest field",
+                pipe_id, key
+            ));
+        }
+
+        // A reinstall from another source keeps the config, so it is diffed either way
+        let current = installed.iter().find(|info| info.id == pipe_id);
+        if !current.is_some_and(|info| same_source(&info.source, &pipe.source)) {
+            operations.push(PipeOperation::Install {
+                url: pipe.source.clone(),
+            });
+        }
+        let empty = Map::new();
+        let current_config = current
+            .and_then(|info| info.config.as_object())
+            .unwrap_or(&empty);
+        let enabled = current.is_some_and(|info| info.enabled);
+        let patch = config_patch(current_config, &pipe.config);
+
+        // Config first when starting so the pipe comes up with it, last when stopping
+        // so it doesn't restart for nothing
+        let set_config = (!patch.is_empty()).then(|| PipeOperation::SetConfig {
+            pipe_id: pipe_id.clone(),
+            patch: Value::Object(patch),
+        });
+        match (enabled, pipe.enabled) {
+            (false, true) => {
+                operations.extend(set_config);
+                operations.push(PipeOperation::Enable {
+                    pipe_id: pipe_id.clone(),
+                });
+            }
+            (true, false) => {
+                operations.push(PipeOperation::Disable {
+                    pipe_id: pipe_id.clone(),
+                });
+                operations.extend(set_config);
+            }
+            _ => operations.extend(set_config),
+        }
+        seen.push(pipe_id);
+    }
+
+    Ok(operations)
+}
+
+fn same_source(installed: &str, desired: &str) -> bool {
+    let normalize = |s: &str| s.trim_matches('"').replace('\\', "/");
+    normalize(installed).trim_end_matches('/') == normalize(desired).trim_end_matches('/')
+}
+
+/// Merge patch turning `current` into `current` plus `desired`, empty if nothing
+/// changes. A `null` in `desired` removes the key.
+fn config_patch(current: &Map<String, Value>, desired: &Map<String, Value>) -> Map<String, Value> {
+    let mut patch = Map::new();
+    for (key, value) in desired {
+        match (current.get(key), value) {
+            (None, Value::Null) => {}
+            (Some(Value::Object(current)), Value::Object(desired)) => {
+                let nested = config_patch(current, desired);
+                if !nested.is_empty() {
+                    patch.insert(key.clone(), Value::Object(nested));
+                }
+            }
+            (Some(current), value) if current == value => {}
+            (_, value) => {
+                patch.insert(key.clone(), value.clone());
+            }
+        }
+    }
+    patch
+}
@@ -0,0 +1,726 @@
+use crate::db_types::PipeContentTypeRow;
+use crate::DatabaseManager;
+use chrono::{DateTime, NaiveDate, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+
+/// Names taken by the built in content types.
+const RESERVED_NAMES: &[&str] = &[
+    "all",
+    "ocr",
+    "audio",
+    "ui",
+    "document",
+    "pipe",
+    "audio+ui",
+    "ocr+ui",
+    "audio+ocr",
+];
+
+/// Fields a content type can declare.
+const MAX_FIELDS: usize = 64;
+
+/// Type of a field in a pipe content schema.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FieldType {
+    String,
+    Number,
+    Integer,
+    Boolean,
+    /// `{"type": "string", "format": "date"}`, stored as `YYYY-MM-DD`
+    Date,
+    /// `{"type": "string", "format": "date-time"}`, stored as RFC 3339 in UTC
+    DateTime,
+}
+
+impl FieldType {
+    fn from_json_schema(property: &Value) -> Result<Self, String> {
+        let format = property.get("format").and_then(Value::as_str);
+        match (property.get("type").and_then(Value::as_str), format) {
+            (Some("string"), Some("date")) => Ok(FieldType::Date),
+            (Some("string"), Some("date-time")) => Ok(FieldType::DateTime),
+            (Some("string"), _) => Ok(FieldType::String),
+            (Some("number"), _) => Ok(FieldType::Number),
+            (Some("integer"), _) => Ok(FieldType::Integer),
+            (Some("boolean"), _) => Ok(FieldType::Boolean),
+            (Some(other), _) => Err(format!(
+                "unsupported type '{}', use string, number, integer or boolean",
+                other
+            )),
+            (None, _) => Err("missing type".to_string()),
+        }
+    }
+
+    fn to_json_schema(self) -> Value {
+        match self {
+            FieldType::String => json!({ "type": "string" }),
+            FieldType::Number => json!({ "type": "number" }),
+            FieldType::Integer => json!({ "type": "integer" }),
+            FieldType::Boolean => json!({ "type": "boolean" }),
+            FieldType::Date => json!({ "type": "string", "format": "date" }),
+            FieldType::DateTime => json!({ "type": "string", "format": "date-time" }),
+        }
+    }
+
+    /// Whether `gte` and `lte` filters make sense on this type.
+    pub fn is_ordered(self) -> bool {
+        matches!(
+            self,
+            FieldType::Number | FieldType::Integer | FieldType::Date | FieldType::DateTime
+        )
+    }
+
+    /// The value as stored, `None` if it doesn't have this type.
+    pub fn check(self, value: &Value) -> Option<Value> {
+        match (self, value) {
+            (FieldType::String, Value::String(_)) => Some(value.clone()),
+            (FieldType::Number, Value::Number(_)) => Some(value.clone()),
+            (FieldType::Integer, Value::Number(n)) if n.is_i64() || n.is_u64() => {
+                Some(value.clone())
+            }
+            (FieldType::Boolean, Value::Bool(_)) => Some(value.clone()),
+            (FieldType::Date, Value::String(s)) => NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                .ok()
+                .map(|d| Value::String(d.format("%Y-%m-%d").to_string())),
+            // Fixed precision so stored values compare as text
+            (FieldType::DateTime, Value::String(s)) => {
+                DateTime::parse_from_rfc3339(s).ok().map(|t| {
+                    Value::String(
+                        t.with_timezone(&Utc)
+                            .to_rfc3339_opts(SecondsFormat::Millis, true),
+                    )
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Like `check`, but also converts values of other types, for retyped fields and
+    /// query parameters.
+    pub fn coerce(self, value: &Value) -> Option<Value> {
+        if let Some(checked) = self.check(value) {
+            return Some(checked);
+        }
+        match (self, value) {
+            (FieldType::String, Value::Number(n)) => Some(Value::String(n.to_string())),
+            (FieldType::String, Value::Bool(b)) => Some(Value::String(b.to_string())),
+            (FieldType::Number, Value::String(s)) => s
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .and_then(|n| serde_json::Number::from_f64(n).map(Value::Number)),
+            (FieldType::Integer, Value::String(s)) => s
+                .trim()
+                .parse::<i64>()
+                .ok()
+                .map(|n| Value::Number(n.into())),
+            (FieldType::Integer, Value::Number(n)) => n
+                .as_f64()
+                .filter(|f| f.fract() == 0.0)
+                .map(|f| Value::Number((f as i64).into())),
+            (FieldType::Boolean, Value::String(s)) => match s.trim() {
+                "true" => Some(Value::Bool(true)),
+                "false" => Some(Value::Bool(false)),
+                _ => None,
+            },
+            (FieldType::Date, Value::String(s)) => DateTime::parse_from_rfc3339(s)
+                .ok()
+                .map(|t| Value::String(t.with_timezone(&Utc).format("%Y-%m-%d").to_string())),
+            (FieldType::DateTime, Value::String(s)) => {
+                NaiveDate::parse_from_str(s, "%Y-%m-%d").ok().map(|d| {
+                    Value::String(
+                        d.and_hms_opt(0, 0, 0)
+                            .unwrap()
+                            .and_utc()
+                            .to_rfc3339_opts(SecondsFormat::Millis, true),
+                    )
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// One field of a pipe content schema.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldSchema {
+    pub field_type: FieldType,
+    pub required: bool,
+    /// Added to the full text index of the record, strings only
+    pub indexable: bool,
+    /// Usable as a `fields.<name>` search filter
+    pub facetable: bool,
+    pub description: Option<String>,
+}
+
+/// Schema of a pipe content type. A subset of json schema: an object whose properties
+/// are strings, numbers, integers, booleans or dates, with the `indexable` and
+/// `facetable` extensions on each property.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "Value", into = "Value")]
+pub struct ContentSchema {
+    pub fields: BTreeMap<String, FieldSchema>,
+}
+
+impl TryFrom<Value> for ContentSchema {
+    type Error = String;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        Self::from_json_schema(&value)
+    }
+}
+
+impl From<ContentSchema> for Value {
+    fn from(schema: ContentSchema) -> Self {
+        schema.to_json_schema()
+    }
+}
+
+/// How one schema differs from the next version of it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SchemaChanges {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub retyped: Vec<String>,
+}
+
+impl SchemaChanges {
+    /// Whether existing records have to be rewritten.
+    pub fn is_breaking(&self) -> bool {
+        !self.removed.is_empty() || !self.retyped.is_empty()
+    }
+
+    /// Checks that `migration` names exactly the removed and retyped fields.
+    pub fn check_migration(&self, migration: &SchemaMigration) -> Result<(), String> {
+        let missing: Vec<&String> = self
+            .removed
+            .iter()
+            .filter(|f| !migration.remove_fields.contains(f))
+            .chain(
+                self.retyped
+                    .iter()
+                    .filter(|f| !migration.retype_fields.contains(f)),
+            )
+            .collect();
+        if !missing.is_empty() {
+            return Err(format!(
+                "the new schema removes or retypes {}, list them in migration.remove_fields or migration.retype_fields to confirm",
+                join(&missing)
+            ));
+        }
+        let unexpected: Vec<&String> = migration
+            .remove_fields
+            .iter()
+            .filter(|f| !self.removed.contains(f))
+            .chain(
+                migration
+                    .retype_fields
+                    .iter()
+                    .filter(|f| !self.retyped.contains(f)),
+            )
+            .collect();
+        if !unexpected.is_empty() {
+            return Err(format!(
+                "migration lists {} but the new schema doesn't remove or retype them",
+                join(&unexpected)
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Fields a pipe confirms it wants to drop or convert when registering a new
+/// version of a schema.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SchemaMigration {
+    pub remove_fields: Vec<String>,
+    pub retype_fields: Vec<String>,
+}
+
+/// Comparison in a field filter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterOp {
+    Eq,
+    /// Case insensitive equality, for string fields
+    EqText,
+    Gte,
+    Lte,
+}
+
+/// A `fields.<name>=<value>` search filter, checked against the record's json in sql.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldFilter {
+    /// json path of the field, `$.<name>`
+    pub path: String,
+    pub op: FilterOp,
+    pub value: Value,
+}
+
+impl ContentSchema {
+    pub fn from_json_schema(schema: &Value) -> Result<Self, String> {
+        let Some(object) = schema.as_object() else {
+            return Err("schema must be a json object".to_string());
+        };
+        match object.get("type").and_then(Value::as_str) {
+            Some("object") | None => {}
+            Some(other) => {
+                return Err(format!("schema type must be 'object', got '{}'", other));
+            }
+        }
+        let properties = match object.get("properties") {
+            Some(Value::Object(properties)) => properties.clone(),
+            Some(_) => return Err("properties must be an object".to_string()),
+            None => Map::new(),
+        };
+        if properties.len() > MAX_FIELDS {
+            return Err(format!("at most {} fields are allowed", MAX_FIELDS));
+        }
+        let required: Vec<&str> = match object.get("required") {
+            Some(Value::Array(names)) => names.iter().filter_map(Value::as_str).collect(),
+            Some(_) => return Err("required must be an array of field names".to_string()),
+            None => Vec::new(),
+        };
+        if let Some(unknown) = required
+            .iter()
+            .find(|name| !properties.contains_key(**name))
+        {
+            return Err(format!("required field '{}' is not in properties", unknown));
+        }
+
+        let mut fields = BTreeMap::new();
+        for (name, property) in properties {
+            if !is_valid_field_name(&name) {
+                return Err(format!(
+                    "invalid field name '{}', use letters, digits and underscores",
+                    name
+                ));
+            }
+            let field_type =
+                FieldType::from_json_schema(&property).map_err(|e| format!("{}: {}", name, e))?;
+            let flag = |key: &str| property.get(key).and_then(Value::as_bool).unwrap_or(false);
+            let indexable = flag("indexable");
+            if indexable && field_type != FieldType::String {
+                return Err(format!("{}: only string fields can be indexable", name));
+            }
+            fields.insert(
+                name.clone(),
+                FieldSchema {
+                    field_type,
+                    required: required.contains(&name.as_str()),
+                    indexable,
+                    facetable: flag("facetable"),
+                    description: property
+                        .get("description")
+                        .and_then(Value::as_str)
+                        .map(str::to_string),
+                },
+            );
+        }
+        Ok(Self { fields })
+    }
+
+    pub fn to_json_schema(&self) -> Value {
+        let mut properties = Map::new();
+        for (name, field) in &self.fields {
+            let mut property = field.field_type.to_json_schema();
+            if field.indexable {
+                property["indexable"] = Value::Bool(true);
+            }
+            if field.facetable {
+                property["facetable"] = Value::Bool(true);
+            }
+            if let Some(description) = &field.description {
+                property["description"] = Value::String(description.clone());
+            }
+            properties.insert(name.clone(), property);
+        }
+        let required: Vec<&String> = self
+            .fields
+            .iter()
+            .filter(|(_, field)| field.required)
+            .map(|(name, _)| name)
+            .collect();
+        json!({
+            "type": "object",
+            "properties": properties,
+            "required": required,
+        })
+    }
+
+    /// Checks a record's fields against the schema and returns them as stored.
+    /// Unknown fields are rejected, nulls count as missing.
+    pub fn validate(&self, fields: &Map<String, Value>) -> Result<Map<String, Value>, String> {
+        if let Some(unknown) = fields.keys().find(|name| !self.fields.contains_key(*name)) {
+            return Err(format!("unknown field '{}'", unknown));
+        }
+        let mut stored = Map::new();
+        for (name, field) in &self.fields {
+            match fields.get(name) {
+                None | Some(Value::Null) if field.required => {
+                    return Err(format!("missing required field '{}'", name));
+                }
+                None | Some(Value::Null) => {}
+                Some(value) => {
+                    let value = field.field_type.check(value).ok_or_else(|| {
+                        format!("field '{}' must be {}", name, type_name(field.field_type))
+                    })?;
+                    stored.insert(name.clone(), value);
+                }
+            }
+        }
+        Ok(stored)
+    }
+
+    /// Text the record is found by: its free text and its indexable fields.
+    pub fn search_text(&self, text: &str, fields: &Map<String, Value>) -> String {
+        let mut parts = vec![text.trim()];
+        for (name, field) in &self.fields {
+            if !field.indexable {
+                continue;
+            }
+            if let Some(Value::String(value)) = fields.get(name) {
+                parts.push(value.trim());
+            }
+        }
+        parts
+            .into_iter()
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Fields added, removed or retyped going from `self` to `next`.
+    pub fn changes(&self, next: &ContentSchema) -> SchemaChanges {
+        let mut changes = SchemaChanges::default();
+        for (name, field) in &next.fields {
+            match self.fields.get(name) {
+                None => changes.added.push(name.clone()),
+                Some(old) if old.field_type != field.field_type => {
+                    changes.retyped.push(name.clone())
+                }
+                Some(_) => {}
+            }
+        }
+        for name in self.fields.keys() {
+            if !next.fields.contains_key(name) {
+                changes.removed.push(name.clone());
+            }
+        }
+        changes
+    }
+
+    /// Rewrites a record stored under an older schema to this one. Removed fields are
+    /// dropped, retyped fields converted or dropped when they can't be.
+    pub fn migrate(&self, fields: &Map<String, Value>) -> Map<String, Value> {
+        let mut migrated = Map::new();
+        for (name, value) in fields {
+            let Some(field) = self.fields.get(name) else {
+                continue;
+            };
+            if let Some(value) = field.field_type.coerce(value) {
+                migrated.insert(name.clone(), value);
+            }
+        }
+        migrated
+    }
+
+    /// Parses a search parameter `fields.<key>=<raw>`, `key` being `<name>` or
+    /// `<name>.gte` / `<name>.lte` for ordered fields.
+    pub fn field_filter(&self, key: &str, raw: &str) -> Result<FieldFilter, String> {
+        let (name, op) = match key.rsplit_once('.') {
+            Some((name, "gte")) => (name, FilterOp::Gte),
+            Some((name, "lte")) => (name, FilterOp::Lte),
+            _ => (key, FilterOp::Eq),
+        };
+        let Some(field) = self.fields.get(name) else {
+            return Err(format!("unknown field '{}'", name));
+        };
+        if !field.facetable {
+            return Err(format!("field '{}' is not facetable", name));
+        }
+        if op != FilterOp::Eq && !field.field_type.is_ordered() {
+            return Err(format!(
+                "field '{}' is {}, .gte and .lte need a number or a date",
+                name,
+                type_name(field.field_type)
+            ));
+        }
+        let value = field
+            .field_type
+            .coerce(&Value::String(raw.to_string()))
+            .ok_or_else(|| {
+                format!(
+                    "'{}' is not a valid value for '{}', expected {}",
+                    raw,
+                    name,
+                    type_name(field.field_type)
+                )
+            })?;
+        let op = match (op, field.field_type) {
+            (FilterOp::Eq, FieldType::String) => FilterOp::EqText,
+            (op, _) => op,
+        };
+        Ok(FieldFilter {
+            path: format!("$.{}", name),
+            op,
+            value,
+        })
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PipeContentError {
+    #[error("{0}")]
+    Invalid(String),
+    #[error("content type '{0}' is not registered")]
+    UnknownType(String),
+    /// The type belongs to another pipe, or a schema change lacks migration intent
+    #[error("{0}")]
+    Conflict(String),
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+/// A registered content type, as returned by the api.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ContentTypeInfo {
+    pub name: String,
+    pub pipe_id: String,
+    pub version: i64,
+    pub schema: ContentSchema,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl TryFrom<PipeContentTypeRow> for ContentTypeInfo {
+    type Error = PipeContentError;
+
+    fn try_from(row: PipeContentTypeRow) -> Result<Self, Self::Error> {
+        let schema = serde_json::from_str(&row.schema).map_err(|e| {
+            PipeContentError::Invalid(format!("stored schema of '{}' is invalid: {}", row.name, e))
+        })?;
+        Ok(Self {
+            name: row.name,
+            pipe_id: row.pipe_id,
+            version: row.version,
+            schema,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+        })
+    }
+}
+
+/// Outcome of registering a schema.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Registration {
+    pub content_type: ContentTypeInfo,
+    pub changes: SchemaChanges,
+    /// Records rewritten for the new version
+    pub migrated: usize,
+}
+
+/// A record a pipe adds to the index.
+#[derive(Debug, Clone, Deserialize)]
+pub struct NewPipeContent {
+    #[serde(rename = "type")]
+    pub content_type: String,
+    pub pipe_id: String,
+    /// Defaults to now
+    #[serde(default)]
+    pub timestamp: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub text: String,
+    #[serde(default)]
+    pub fields: Map<String, Value>,
+    /// Returned by `GET /context/now` among the latest pipe results
+    #[serde(default)]
+    pub context: bool,
+}
+
+pub async fn get_content_type(
+    db: &DatabaseManager,
+    name: &str,
+) -> Result<ContentTypeInfo, PipeContentError> {
+    db.get_pipe_content_type(name)
+        .await?
+        .ok_or_else(|| PipeContentError::UnknownType(name.to_string()))?
+        .try_into()
+}
+
+pub async fn list_content_types(
+    db: &DatabaseManager,
+) -> Result<Vec<ContentTypeInfo>, PipeContentError> {
+    db.list_pipe_content_types()
+        .await?
+        .into_iter()
+        .map(ContentTypeInfo::try_from)
+        .collect()
+}
+
+/// Registers a content type or a new version of one. Adding fields is always
+/// allowed. Removing or retyping fields needs `migration` to list them, existing
+/// records are then rewritten and re-indexed.
+pub async fn register_content_type(
+    db: &DatabaseManager,
+    pipe_id: &str,
+    name: &str,
+    schema: ContentSchema,
+    migration: Option<SchemaMigration>,
+) -> Result<Registration, PipeContentError> {
+    validate_content_type_name(name).map_err(PipeContentError::Invalid)?;
+    if pipe_id.trim().is_empty() {
+        return Err(PipeContentError::Invalid("pipe_id is required".to_string()));
+    }
+    let schema_json = schema.to_json_schema().to_string();
+
+    let Some(current) = db.get_pipe_content_type(name).await? else {
+        db.save_pipe_content_type(name, pipe_id, &schema_json, 1, &[])
+            .await?;
+        return Ok(Registration {
+            content_type: get_content_type(db, name).await?,
+            changes: SchemaChanges {
+                added: schema.fields.keys().cloned().collect(),
+                ..Default::default()
+            },
+            migrated: 0,
+        });
+    };
+    let current = ContentTypeInfo::try_from(current)?;
+    if current.pipe_id != pipe_id {
+        return Err(PipeContentError::Conflict(format!(
+            "content type '{}' belongs to pipe '{}'",
+            name, current.pipe_id
+        )));
+    }
+    if current.schema == schema {
+        return Ok(Registration {
+            content_type: current,
+            changes: SchemaChanges::default(),
+            migrated: 0,
+        });
+    }
+
+    let changes = current.schema.changes(&schema);
+    let migration = migration.unwrap_or_default();
+    changes
+        .check_migration(&migration)
+        .map_err(PipeContentError::Conflict)?;
+
+    // Indexable flags may have changed too, so every record gets new search text
+    let mut rewrites = Vec::new();
+    for (id, text, fields) in db.get_pipe_content_records(name).await? {
+        let fields: Map<String, Value> = serde_json::from_str(&fields).unwrap_or_default();
+        let fields = schema.migrate(&fields);
+        let search_text = schema.search_text(&text, &fields);
+        rewrites.push((id, Value::Object(fields).to_string(), search_text));
+    }
+    db.save_pipe_content_type(name, pipe_id, &schema_json, current.version + 1, &rewrites)
+        .await?;
+
+    Ok(Registration {
+        content_type: get_content_type(db, name).await?,
+        changes,
+        migrated: rewrites.len(),
+    })
+}
+
+/// Validates a record against its content type and indexes it. Only the pipe that
+/// registered the type can add records to it.
+pub async fn insert_content(
+    db: &DatabaseManager,
+    record: &NewPipeContent,
+) -> Result<i64, PipeContentError> {
+    let content_type = get_content_type(db, &record.content_type).await?;
+    if content_type.pipe_id != record.pipe_id {
+        return Err(PipeContentError::Conflict(format!(
+            "content type '{}' belongs to pipe '{}'",
+            content_type.name, content_type.pipe_id
+        )));
+    }
+    let fields = content_type
+        .schema
+        .validate(&record.fields)
+        .map_err(PipeContentError::Invalid)?;
+    let search_text = content_type.schema.search_text(&record.text, &fields);
+    Ok(db
+        .insert_pipe_content(
+            &content_type.name,
+            &record.pipe_id,
+            record.timestamp.unwrap_or_else(Utc::now),
+            &record.text,
+            &Value::Object(fields).to_string(),
+            &search_text,
+            record.context,
+        )
+        .await?)
+}
+
+/// Field filters for a search from its `fields.<key>=<value>` params, checked
+/// against the schema of `content_type`.
+pub async fn field_filters(
+    db: &DatabaseManager,
+    content_type: &str,
+    params: &[(String, String)],
+) -> Result<Vec<FieldFilter>, PipeContentError> {
+    let schema = get_content_type(db, content_type).await?.schema;
+    params
+        .iter()
+        .filter_map(|(key, value)| key.strip_prefix("fields.").map(|key| (key, value)))
+        .map(|(key, value)| {
+            schema
+                .field_filter(key, value)
+                .map_err(PipeContentError::Invalid)
+        })
+        .collect()
+}
+
+/// Checks a content type name: lowercase letters, digits, `-` and `_`, and not one
+/// of the built in types.
+pub fn validate_content_type_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.len() > 64 {
+        return Err("content type name must be 1 to 64 characters".to_string());
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+    {
+        return Err(format!(
+            "invalid content type name '{}', use lowercase letters, digits, '-' and '_'",
+            name
+        ));
+    }
+    if RESERVED_NAMES.contains(&name) {
+        return Err(format!("'{}' is a built in content type", name));
+    }
+    Ok(())
+}
+
+fn is_valid_field_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && name.len() <= 64
+}
+
+fn type_name(field_type: FieldType) -> &'static str {
+    match field_type {
+        FieldType::String => "a string",
+        FieldType::Number => "a number",
+        FieldType::Integer => "an integer",
+        FieldType::Boolean => "a boolean",
+        FieldType::Date => "a date (YYYY-MM-DD)",
+        FieldType::DateTime => "an RFC 3339 date-time",
+    }
+}
+
+fn join(names: &[&String]) -> String {
+    names
+        .iter()
+        .map(|name| format!("'{}'", name))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -0,0 +1,480 @@
+//! `pipes.lock.json`, the exact pipes of a machine, and the sync making another
+//! machine match it: `screenpipe pipe lock` writes the file, `screenpipe pipe sync`
+//! applies it.
+//!
+//! A github source is locked to the commit its ref points to, and every pipe to a
+//! checksum of its files. Sync records both in `pipe.json` when it installs a pipe,
+//! so a pipe edited since is told apart from one that is only behind the lock: it is
+//! never replaced or removed without `force`. Pipes sync didn't install have no record
+//! and count as edited. Configs hold api keys, so the lock only has their hash, a
+//! config that differs is reported and left alone (`screenpipe pipe apply` sets them).
+
+use crate::pipe_batch::{discard_backup, OperationStatus};
+use crate::pipe_manager::PipeManager;
+use anyhow::Result;
+use screenpipe_core::{
+    pin_github_source, resolve_github_source, ConflictPolicy, DownloadOptions, PIPE_LOCK_FILE,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+pub const LOCK_VERSION: u32 = 1;
+
+/// Keys sync writes to `pipe.json`, left out of the config hash.
+const LOCK_KEYS: [&str; 3] = ["source", "resolved", "checksum"];
+
+/// Left out of the checksum along with hidden files: the config, the commit a download
+/// records, dependencies bun installs and build output.
+const UNHASHED: [&str; 9] = [
+    "pipe.json",
+    PIPE_LOCK_FILE,
+    "node_modules",
+    "dist",
+    "build",
+    "bun.lockb",
+    "bun.lock",
+    "package-lock.json",
+    "Thumbs.db",
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PipeLock {
+    pub version: u32,
+    pub pipes: Vec<LockedPipe>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LockedPipe {
+    pub id: String,
+    /// Github url pinned to `resolved`, or local path, the pipe is installed from
+    pub source: String,
+    /// Commit of a github source, `None` for local paths
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolved: Option<String>,
+    /// See [`pipe_checksum`]
+    pub checksum: String,
+    /// See [`config_hash`]
+    pub config_hash: String,
+}
+
+/// An installed pipe as sync compares it to the lock.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InstalledPipe {
+    pub id: String,
+    pub source: String,
+    pub resolved: Option<String>,
+    /// Checksum recorded when sync installed the pipe
+    pub recorded_checksum: Option<String>,
+    /// Checksum of its files now
+    pub checksum: String,
+    pub config_hash: String,
+}
+
+impl InstalledPipe {
+    fn edited(&self) -> bool {
+        self.recorded_checksum.as_deref() != Some(self.checksum.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncAction {
+    Install,
+    /// Reinstall a pipe behind the lock
+    Update,
+    /// Delete a pipe the lock doesn't list, with `prune`
+    Remove,
+    Unchanged,
+    /// Edited since it was installed, only replaced or removed with `force`
+    Conflict,
+    /// Not in the lock and kept, without `prune`
+    Unlisted,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyncStep {
+    pub pipe_id: String,
+    pub action: SyncAction,
+    /// The installed config hashes differently than the lock's, sync doesn't change it
+    pub config_differs: bool,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SyncRequest {
+    pub lock: PipeLock,
+    /// Remove installed pipes the lock doesn't list
+    #[serde(default)]
+    pub prune: bool,
+    /// Replace or remove pipes edited since they were installed
+    #[serde(default)]
+    pub force: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyncResult {
+    pub pipe_id: String,
+    pub action: SyncAction,
+    pub status: OperationStatus,
+    pub config_differs: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyncReport {
+    /// The installed pipes match the lock, configs aside
+    pub success: bool,
+    pub prune: bool,
+    pub force: bool,
+    pub results: Vec<SyncResult>,
+}
+
+/// Sha256 of the files of the pipe at `dir`, each hashed with its path relative to
+/// `dir` so renames count. Hidden files and [`UNHASHED`] names are skipped.
+pub fn pipe_checksum(dir: &Path) -> std::io::Result<String> {
+    let mut files = Vec::new();
+    collect_files(dir, dir, &mut files)?;
+    files.sort();
+
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 1 << 16];
+    for relative in files {
+        let mut file = fs::File::open(dir.join(&relative))?;
+        hasher.update(relative.as_bytes());
+        hasher.update([0]);
+        loop {
+            let read = file.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+        }
+        hasher.update([0]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Paths of the hashed files under `dir`, relative to `root` with `/` separators.
+fn collect_files(root: &Path, dir: &Path, files: &mut Vec<String>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name.starts_with('.') || UNHASHED.contains(&name.as_ref()) {
+            continue;
+        }
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            collect_files(root, &path, files)?;
+        } else {
+            let relative = path.strip_prefix(root).unwrap_or(&path);
+            let parts: Vec<_> = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect();
+            files.push(parts.join("/"));
+        }
+    }
+    Ok(())
+}
+
+/// Sha256 of `config` without the keys sync records, keys sorted.
+pub fn config_hash(config: &Value) -> String {
+    let mut config = sorted(config);
+    if let Value::Object(config) = &mut config {
+        for key in LOCK_KEYS {
+            config.remove(key);
+        }
+    }
+    format!("{:x}", Sha256::digest(config.to_string().as_bytes()))
+}
+
+fn sorted(value: &Value) -> Value {
+    match value {
+        Value::Object(object) => {
+            let mut keys: Vec<&String> = object.keys().collect();
+            keys.sort();
+            let mut sorted_object = Map::new();
+            for key in keys {
+                sorted_object.insert(key.clone(), sorted(&object[key]));
+            }
+            Value::Object(sorted_object)
+        }
+        Value::Array(items) => Value::Array(items.iter().map(sorted).collect()),
+        value => value.clone(),
+    }
+}
+
+/// Every installed pipe with the checksum of its files.
+pub async fn installed_pipes(manager: &PipeManager) -> Result<Vec<InstalledPipe>> {
+    let mut installed = Vec::new();
+    for info in manager.list_pipes().await {
+        let dir = manager.pipe_dir(&info.id);
+        let checksum = tokio::task::spawn_blocking(move || pipe_checksum(&dir)).await??;
+        let recorded = |key: &str| {
+            info.config
+                .get(key)
+                .and_then(Value::as_str)
+                .map(String::from)
+        };
+        installed.push(InstalledPipe {
+            resolved: recorded("resolved"),
+            recorded_checksum: recorded("checksum"),
+            checksum,
+            config_hash: config_hash(&info.config),
+            source: info.source,
+            id: info.id,
+        });
+    }
+    installed.sort_by(|a, b| a.id.cmp(&b.id));
+    Ok(installed)
+}
+
+/// The lock of the installed pipes. Github sources installed without sync are locked
+/// to the commit their ref points to now, so sync fails their checksum if the ref
+/// moved since they were downloaded. Pipes without a source are left out.
+pub async fn generate_lock(manager: &PipeManager) -> Result<PipeLock> {
+    let mut pipes = Vec::new();
+    for pipe in installed_pipes(manager).await? {
+        if pipe.source.is_empty() {
+            warn!("pipe {} has no source, leaving it out of the lock", pipe.id);
+            continue;
+        }
+        if pipe.recorded_checksum.is_some() && pipe.edited() {
+            warn!(
+                "pipe {} was edited since it was installed, other machines will fail its checksum",
+                pipe.id
+            );
+        }
+        let (source, resolved) = match pipe.resolved {
+            Some(resolved) => (pipe.source, Some(resolved)),
+            None => match resolve_github_source(&pipe.source).await? {
+                Some(commit) => (
+                    pin_github_source(&pipe.source, &commit.git_ref, &commit.sha)
+                        .unwrap_or(pipe.source),
+                    Some(commit.sha),
+                ),
+                None => (pipe.source, None),
+            },
+        };
+        pipes.push(LockedPipe {
+            id: pipe.id,
+            source,
+            resolved,
+            checksum: pipe.checksum,
+            config_hash: pipe.config_hash,
+        });
+    }
+    Ok(PipeLock {
+        version: LOCK_VERSION,
+        pipes,
+    })
+}
+
+/// What sync does to each pipe of `lock` and each installed pipe it doesn't list, in
+/// that order. Errors on a lock that can't be applied.
+pub fn plan_sync(
+    lock: &PipeLock,
+    installed: &[InstalledPipe],
+    prune: bool,
+    force: bool,
+) -> Result<Vec<SyncStep>, String> {
+    if lock.version != LOCK_VERSION {
+        return Err(format!(
+            "unsupported lock version {}, expected {}",
+            lock.version, LOCK_VERSION
+        ));
+    }
+    let mut steps: Vec<SyncStep> = Vec::new();
+    for locked in &lock.pipes {
+        if PipeManager::pipe_id_for_source(&locked.source).as_deref() != Some(&locked.id) {
+            return Err(format!(
+                "pipe '{}': source {} installs as another pipe",
+                locked.id, locked.source
+            ));
+        }
+        if steps.iter().any(|step| step.pipe_id == locked.id) {
+            return Err(format!("pipe '{}' is locked twice", locked.id));
+        }
+        let current = installed.iter().find(|pipe| pipe.id == locked.id);
+        let action = match current {
+            None => SyncAction::Install,
+            Some(pipe) if pipe.checksum == locked.checksum => SyncAction::Unchanged,
+            Some(pipe) if pipe.edited() && !force => SyncAction::Conflict,
+            Some(_) => SyncAction::Update,
+        };
+        steps.push(SyncStep {
+            pipe_id: locked.id.clone(),
+            action,
+            config_differs: current.is_some_and(|pipe| pipe.config_hash != locked.config_hash),
+        });
+    }
+
+    for pipe in installed {
+        if lock.pipes.iter().any(|locked| locked.id == pipe.id) {
+            continue;
+        }
+        let action = match (prune, pipe.edited() && !force) {
+            (false, _) => SyncAction::Unlisted,
+            (true, true) => SyncAction::Conflict,
+            (true, false) => SyncAction::Remove,
+        };
+        steps.push(SyncStep {
+            pipe_id: pipe.id.clone(),
+            action,
+            config_differs: false,
+        });
+    }
+    Ok(steps)
+}
+
+/// Brings the installed pipes to `lock`, see the module docs. Every pipe it may touch
+/// is locked for the whole sync.
+pub async fn sync_pipes(
+    manager: &PipeManager,
+    lock: &PipeLock,
+    prune: bool,
+    force: bool,
+) -> Result<SyncReport, String> {
+    let mut pipe_ids: Vec<String> = lock.pipes.iter().map(|pipe| pipe.id.clone()).collect();
+    pipe_ids.extend(manager.list_pipes().await.into_iter().map(|pipe| pipe.id));
+    let _guards = manager.lock_pipes(&pipe_ids).await;
+
+    let installed = installed_pipes(manager)
+        .await
+        .map_err(|e| format!("failed to read the installed pipes: {}", e))?;
+    let steps = plan_sync(lock, &installed, prune, force)?;
+
+    let mut results = Vec::with_capacity(steps.len());
+    for step in steps {
+        let locked = lock.pipes.iter().find(|pipe| pipe.id == step.pipe_id);
+        let outcome = match (step.action, locked) {
+            (SyncAction::Install | SyncAction::Update, Some(locked)) => {
+                install_locked(manager, locked).await
+            }
+            // Matching files without a record, e.g. downloaded before, are adopted
+            (SyncAction::Unchanged, Some(locked))
+                if installed
+                    .iter()
+                    .any(|pipe| pipe.id == locked.id && pipe.edited()) =>
+            {
+                record(manager, locked).await
+            }
+            (SyncAction::Remove, _) => manager
+                .delete_pipe_locked(&step.pipe_id)
+                .await
+                .map_err(|e| e.to_string()),
+            _ => Ok(()),
+        };
+        let (status, detail) = match outcome {
+            Err(e) => {
+                warn!("sync of pipe {} failed: {}", step.pipe_id, e);
+                (OperationStatus::Failed, Some(e))
+            }
+            Ok(()) if step.action == SyncAction::Conflict => (
+                OperationStatus::Skipped,
+                Some("edited since it was installed, use force to overwrite".to_string()),
+            ),
+            Ok(()) => (OperationStatus::Ok, None),
+        };
+        results.push(SyncResult {
+            pipe_id: step.pipe_id,
+            action: step.action,
+            status,
+            config_differs: step.config_differs,
+            detail,
+        });
+    }
+
+    let success = results.iter().all(|result| {
+        result.status == OperationStatus::Ok && result.action != SyncAction::Conflict
+    });
+    info!("pipe sync done, success: {}", success);
+    Ok(SyncReport {
+        success,
+        prune,
+        force,
+        results,
+    })
+}
+
+/// Installs the locked source over any installed copy, which comes back if the files
+/// don't match the lock's checksum. A running pipe is restarted on the new files.
+async fn install_locked(manager: &PipeManager, locked: &LockedPipe) -> Result<(), String> {
+    let was_running = manager.is_running(&locked.id).await;
+    manager
+        .stop_pipe(&locked.id)
+        .await
+        .map_err(|e| e.to_string())?;
+    let backup = manager
+        .backup_pipe_locked(&locked.id)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let installed = match manager
+        .download_pipe_locked(
+            &locked.source,
+            // The lock says which source the pipe is from
+            DownloadOptions {
+                on_conflict: ConflictPolicy::Replace,
+                ..Default::default()
+            },
+        )
+        .await
+    {
+        Ok(_) => checksum_of(manager.pipe_dir(&locked.id)).await,
+        Err(e) => Err(format!("failed to download pipe: {}", e)),
+    };
+    let installed = installed.and_then(|checksum| {
+        if checksum == locked.checksum {
+            Ok(())
+        } else {
+            Err(format!(
+                "checksum mismatch: expected {}, got {}",
+                locked.checksum, checksum
+            ))
+        }
+    });
+    if let Err(e) = installed {
+        if let Err(e) = manager
+            .restore_pipe_locked(&locked.id, backup.as_ref())
+            .await
+        {
+            warn!("failed to restore {} after a failed sync: {}", locked.id, e);
+        }
+        return Err(e);
+    }
+
+    discard_backup(backup).await;
+    record(manager, locked).await?;
+    if was_running {
+        manager
+            .start_pipe_locked(&locked.id)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+async fn checksum_of(dir: PathBuf) -> Result<String, String> {
+    tokio::task::spawn_blocking(move || pipe_checksum(&dir))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+}
+
+/// Notes in `pipe.json` what the pipe was installed as, for later syncs.
+async fn record(manager: &PipeManager, locked: &LockedPipe) -> Result<(), String> {
+    manager
+        .update_config_locked(
+            &locked.id,
+            json!({ "resolved": locked.resolved, "checksum": locked.checksum }),
+        )
+        .await
+        .map_err(|e| e.to_string())
+}
@@ -1,16 +1,33 @@
+use crate::events::{EventKind, EventRecorder};
+use crate::pipe_permissions::PermissionBroker;
+use crate::pipe_proxy::PipeProxy;
+use crate::pipe_tokens::pipe_tokens;
+use crate::DatabaseManager;
 use anyhow::Result;
-use screenpipe_core::download_pipe;
+use screenpipe_core::pipe_config::{load_config, ConfigError};
+use screenpipe_core::pipe_config_schema::get_pipe_config_schema;
+use screenpipe_core::pipe_diff::PipeDiff;
+use screenpipe_core::pipe_link::{is_linked_pipe, remove_pipe_dir};
+use screenpipe_core::pipe_manager::{PipeManager as CorePipeManager, RuntimeConfig};
+use screenpipe_core::pipe_manifest::{validate_manifest_file, ManifestIssue, Severity};
+use screenpipe_core::pipe_sandbox::SandboxPolicy;
+use screenpipe_core::pipe_stats::PipeRun;
+use screenpipe_core::{
+    pipe_id_from_source, DownloadOptions, OverwritePolicy, PipeLock, PipeReplSession,
+    PipeRunOptions, ShutdownToken, DEFAULT_PIPE_MEMORY_LIMIT,
+};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
 use std::future::Future;
-use std::path::PathBuf;
-use std::sync::Arc;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
 use tokio::fs::File;
 use tokio::io::AsyncWriteExt;
 use tokio::sync::mpsc::{self, Sender};
-use tokio::sync::RwLock;
-use tracing::{debug, info};
+use tokio::sync::{OwnedMutexGuard, RwLock};
+use tracing::{debug, info, warn};
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct PipeInfo {
@@ -21,37 +38,281 @@ pub struct PipeInfo {
     pub port: Option<u16>,
 }
 
+fn join_issues(issues: &[ManifestIssue]) -> String {
+    issues
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PipeError {
+    #[error("pipe '{0}' does not exist")]
+    NotFound(String),
+    #[error("{0}")]
+    InvalidConfig(String),
+    #[error("pipe.json of pipe '{pipe_id}' is invalid: {}", join_issues(.issues))]
+    InvalidManifest {
+        pipe_id: String,
+        issues: Vec<ManifestIssue>,
+    },
+    /// Killed once it ran for `elapsed`, its deadline
+    #[error("pipe '{pipe_id}' didn't exit within {}s, killed", elapsed.as_secs())]
+    Timeout { pipe_id: String, elapsed: Duration },
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+impl PipeError {
+    /// The error of [`screenpipe_core::wait_pipe`], its timeout as [`PipeError::Timeout`].
+    fn from_wait(e: anyhow::Error) -> Self {
+        match e.downcast::<screenpipe_core::PipeError>() {
+            Ok(screenpipe_core::PipeError::Timeout { pipe_name, elapsed }) => PipeError::Timeout {
+                pipe_id: pipe_name,
+                elapsed,
+            },
+            Ok(e) => PipeError::Other(e.into()),
+            Err(e) => PipeError::Other(e),
+        }
+    }
+}
+
 struct PipeHandle {
     pid: i32,
     kill_tx: Sender<()>,
 }
 
 pub struct PipeManager {
-    screenpipe_dir: PathBuf,
+    /// Installs, lists and starts the pipes of the screenpipe folder, which this one
+    /// supervises
+    pipes: CorePipeManager,
     running_pipes: Arc<RwLock<HashMap<String, PipeHandle>>>,
+    permissions: Arc<PermissionBroker>,
+    /// Serializes changes to the same pipe, from the ui, the cli and batches
+    pipe_locks: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+    /// Whether pipes go through their own network proxy
+    network_proxy: bool,
+    /// Where the proxies record traffic, set once the database is open
+    network_stats: OnceLock<Arc<DatabaseManager>>,
+    /// Where crashes are recorded, set once the database is open
+    events: OnceLock<Arc<EventRecorder>>,
+    /// Whether unknown keys in a pipe.json fail the pipe instead of being logged
+    strict_manifests: bool,
+    /// Stops the running pipes when screenpipe shuts down
+    shutdown: ShutdownToken,
+    /// Bytes of memory a pipe may use unless its pipe.json says otherwise
+    memory_limit: Option<u64>,
+    /// Syscalls deno pipes may make, unless their pipe.json asks for fewer
+    sandbox: SandboxPolicy,
 }
 
 impl PipeManager {
     pub fn new(screenpipe_dir: PathBuf) -> Self {
         PipeManager {
-            screenpipe_dir,
+            permissions: Arc::new(PermissionBroker::new(screenpipe_dir.clone(), false)),
+            pipes: CorePipeManager::new(screenpipe_dir, RuntimeConfig::default())
+                .expect("the default http client builds"),
             running_pipes: Arc::new(RwLock::new(HashMap::new())),
+            pipe_locks: Mutex::new(HashMap::new()),
+            network_proxy: false,
+            network_stats: OnceLock::new(),
+            events: OnceLock::new(),
+            strict_manifests: false,
+            shutdown: ShutdownToken::new(),
+            memory_limit: Some(DEFAULT_PIPE_MEMORY_LIMIT),
+            sandbox: SandboxPolicy::None,
+        }
+    }
+
+    /// Downloads pipes with the http client and github token of `config`. Fails when its
+    /// proxy isn't a valid url.
+    pub fn with_runtime_config(mut self, config: RuntimeConfig) -> Result<Self> {
+        self.pipes = CorePipeManager::new(self.screenpipe_dir().to_path_buf(), config)?;
+        Ok(self)
+    }
+
+    pub fn pipes(&self) -> &CorePipeManager {
+        &self.pipes
+    }
+
+    fn screenpipe_dir(&self) -> &Path {
+        self.pipes.screenpipe_dir()
+    }
+
+    /// Bytes of memory each pipe may use, `None` for no limit. A pipe's pipe.json
+    /// `memory_limit_mb` takes its place, and next.js pipes are only limited by theirs.
+    pub fn with_memory_limit(mut self, limit: Option<u64>) -> Self {
+        self.memory_limit = limit;
+        self
+    }
+
+    /// Syscalls each deno pipe may make, on linux. A stricter pipe.json `sandbox` takes
+    /// its place.
+    pub fn with_sandbox(mut self, policy: SandboxPolicy) -> Self {
+        self.sandbox = policy;
+        self
+    }
+
+    /// Time pipes get to exit on shutdown before they are killed.
+    pub fn with_shutdown_grace(mut self, grace: Duration) -> Self {
+        self.shutdown = ShutdownToken::with_grace(grace);
+        self
+    }
+
+    /// Asks the running pipes to exit and waits for them, killing those still running
+    /// after the grace period.
+    pub async fn shutdown(&self) {
+        self.shutdown.shutdown().await;
+    }
+
+    /// Sends each pipe's http traffic through a localhost proxy that counts it per host
+    /// and refuses hosts missing from the pipe's `hosts`. Off, pipes reach the network
+    /// directly.
+    pub fn with_network_proxy(mut self, enabled: bool) -> Self {
+        self.network_proxy = enabled;
+        self
+    }
+
+    pub fn network_proxy_enabled(&self) -> bool {
+        self.network_proxy
+    }
+
+    /// Fails pipes whose pipe.json has unknown keys, which are only logged otherwise.
+    pub fn with_strict_manifests(mut self, strict: bool) -> Self {
+        self.strict_manifests = strict;
+        self
+    }
+
+    pub fn strict_manifests(&self) -> bool {
+        self.strict_manifests
+    }
+
+    /// Checks the pipe's pipe.json against the manifest schema and logs its warnings.
+    /// Fails on errors, and in strict mode on warnings too. A pipe without a pipe.json
+    /// passes.
+    pub async fn check_manifest(&self, id: &str) -> Result<Vec<ManifestIssue>, PipeError> {
+        let config_path = self.pipe_dir(id).join("pipe.json");
+        let issues = match validate_manifest_file(&config_path, self.strict_manifests).await {
+            Ok(issues) => issues,
+            Err(e) if e.is_not_found() => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        if issues.iter().any(|issue| issue.severity == Severity::Error) {
+            return Err(PipeError::InvalidManifest {
+                pipe_id: id.to_string(),
+                issues,
+            });
+        }
+        for issue in &issues {
+            warn!("pipe.json of pipe {}: {}", id, issue);
+        }
+        Ok(issues)
+    }
+
+    /// Records the proxies' traffic in `db` from now on.
+    pub fn record_network_stats(&self, db: Arc<DatabaseManager>) {
+        let _ = self.network_stats.set(db);
+    }
+
+    /// Records pipe crashes in `events` from now on.
+    pub fn record_events(&self, events: Arc<EventRecorder>) {
+        let _ = self.events.set(events);
+    }
+
+    /// Grant every requested permission without prompting, for headless setups.
+    pub fn with_auto_approve_pipes(mut self, auto_approve: bool) -> Self {
+        self.permissions = Arc::new(PermissionBroker::new(
+            self.screenpipe_dir().to_path_buf(),
+            auto_approve,
+        ));
+        self
+    }
+
+    pub fn permissions(&self) -> &Arc<PermissionBroker> {
+        &self.permissions
+    }
+
+    /// Directory the pipe `id` is installed in.
+    pub fn pipe_dir(&self, id: &str) -> PathBuf {
+        self.screenpipe_dir().join("pipes").join(id)
+    }
+
+    /// Json schema of the settings of pipe `id`, see [`get_pipe_config_schema`].
+    pub async fn config_schema(&self, id: &str) -> Result<Value, PipeError> {
+        let pipe_dir = self.pipe_dir(id);
+        if id.starts_with('.') || !pipe_dir.is_dir() {
+            return Err(PipeError::NotFound(id.to_string()));
+        }
+        Ok(get_pipe_config_schema(&pipe_dir).await?)
+    }
+
+    /// What changed between pipe `id` and its source, see [`CorePipeManager::diff_pipe`].
+    pub async fn diff_pipe(&self, id: &str) -> Result<PipeDiff> {
+        self.pipes.diff_pipe(id).await
+    }
+
+    /// Id the pipe at `url` installs as, `None` if the url has no last segment.
+    pub fn pipe_id_for_source(url: &str) -> Option<String> {
+        pipe_id_from_source(&url.trim_matches('"').replace("\\", "/"))
+    }
+
+    /// Holds off other changes to the pipe until the guard is dropped.
+    pub async fn lock_pipe(&self, id: &str) -> OwnedMutexGuard<()> {
+        let lock = self
+            .pipe_locks
+            .lock()
+            .unwrap()
+            .entry(id.to_string())
+            .or_default()
+            .clone();
+        lock.lock_owned().await
+    }
+
+    /// Locks several pipes at once, always in the same order so two callers can't deadlock.
+    pub async fn lock_pipes(&self, ids: &[String]) -> Vec<OwnedMutexGuard<()>> {
+        let mut ids = ids.to_vec();
+        ids.sort();
+        ids.dedup();
+        let mut guards = Vec::with_capacity(ids.len());
+        for id in &ids {
+            guards.push(self.lock_pipe(id).await);
         }
+        guards
+    }
+
+    pub async fn is_running(&self, id: &str) -> bool {
+        self.running_pipes.read().await.contains_key(id)
     }
 
-    pub async fn update_config(&self, id: &str, new_config: Value) -> Result<()> {
+    pub async fn update_config(&self, id: &str, new_config: Value) -> Result<(), PipeError> {
+        let _guard = self.lock_pipe(id).await;
+        self.update_config_locked(id, new_config).await
+    }
+
+    /// [`Self::update_config`] for a caller already holding the pipe's lock.
+    pub(crate) async fn update_config_locked(
+        &self,
+        id: &str,
+        new_config: Value,
+    ) -> Result<(), PipeError> {
         debug!("Updating config for pipe: {}", id);
-        let pipe_dir = self.screenpipe_dir.join("pipes").join(id);
+        let pipe_dir = self.screenpipe_dir().join("pipes").join(id);
 
         if !pipe_dir.exists() {
-            return Err(anyhow::anyhow!("pipe '{}' does not exist", id));
+            return Err(PipeError::NotFound(id.to_string()));
         }
 
         let config_path = pipe_dir.join("pipe.json");
 
         let mut config: Value = if config_path.exists() {
-            let config_str = tokio::fs::read_to_string(&config_path).await?;
-            serde_json::from_str(&config_str)?
+            load_config(&config_path).await?
         } else {
             tokio::fs::create_dir_all(&pipe_dir).await?;
             serde_json::json!({
@@ -62,16 +323,11 @@ impl PipeManager {
 
         debug!("config: {}", config);
 
-        let was_enabled = if config_path.exists() {
-            let old_config: Value =
-                serde_json::from_str(&tokio::fs::read_to_string(&config_path).await?)?;
-            old_config
-                .get("enabled")
-                .and_then(Value::as_bool)
-                .unwrap_or(false)
-        } else {
-            false
-        };
+        let was_enabled = config
+            .get("enabled")
+            .and_then(Value::as_bool)
+            .unwrap_or(false)
+            && !screenpipe_core::is_pipe_disabled(&pipe_dir);
 
         let is_enabled = new_config.get("enabled").and_then(Value::as_bool);
 
@@ -83,16 +339,27 @@ impl PipeManager {
                     existing_config.insert(key, value);
                 }
             } else {
-                return Err(anyhow::anyhow!("new configuration must be an object"));
+                return Err(PipeError::InvalidConfig(
+                    "new configuration must be an object".to_string(),
+                ));
             }
         } else {
-            return Err(anyhow::anyhow!("existing configuration is not an object"));
+            return Err(PipeError::InvalidConfig(
+                "existing configuration is not an object".to_string(),
+            ));
         }
 
         let updated_config_str = serde_json::to_string_pretty(&config)?;
 
         let mut file = File::create(&config_path).await?;
         file.write_all(updated_config_str.as_bytes()).await?;
+        // The write finishes in the background otherwise, and the next change reads it
+        file.flush().await?;
+
+        if is_enabled == Some(true) {
+            // Enabling also lifts a `disable_pipe`, it would keep the pipe from starting
+            self.pipes.enable_pipe(id).await?;
+        }
 
         // Handle pipe state changes
         if let Some(enabled) = is_enabled {
@@ -123,23 +390,90 @@ impl PipeManager {
         Ok(())
     }
 
+    /// Applies `patch` to the pipe's config as a json merge patch (rfc 7396): objects
+    /// merge, `null` removes a key. A running pipe is restarted to pick it up. Expects
+    /// the pipe's lock to be held.
+    pub(crate) async fn patch_config_locked(
+        &self,
+        id: &str,
+        patch: Value,
+    ) -> Result<(), PipeError> {
+        let Value::Object(patch) = patch else {
+            return Err(PipeError::InvalidConfig(
+                "config patch must be an object".to_string(),
+            ));
+        };
+        if let Some(key) = ["enabled", "source"]
+            .iter()
+            .find(|k| patch.contains_key(**k))
+        {
+            return Err(PipeError::InvalidConfig(format!(
+                "'{}' can't be set through a config patch",
+                key
+            )));
+        }
+
+        let config_path = self
+            .screenpipe_dir()
+            .join("pipes")
+            .join(id)
+            .join("pipe.json");
+        if !config_path.parent().is_some_and(|dir| dir.exists()) {
+            return Err(PipeError::NotFound(id.to_string()));
+        }
+        let mut config: Value = if config_path.exists() {
+            load_config(&config_path).await?
+        } else {
+            serde_json::json!({ "id": id, "enabled": false })
+        };
+        merge_patch(&mut config, &Value::Object(patch));
+        tokio::fs::write(&config_path, serde_json::to_string_pretty(&config)?).await?;
+
+        if self.is_running(id).await {
+            self.stop_pipe(id).await?;
+            let future = self.start_pipe_task(id.to_string()).await?;
+            tokio::spawn(future);
+            info!("pipe {} restarted with the new config", id);
+        }
+        Ok(())
+    }
+
+    /// Runs the pipe without changing whether it is enabled, no-op if it already runs.
+    /// Expects the pipe's lock to be held.
+    pub(crate) async fn start_pipe_locked(&self, id: &str) -> Result<(), PipeError> {
+        if !self.screenpipe_dir().join("pipes").join(id).exists() {
+            return Err(PipeError::NotFound(id.to_string()));
+        }
+        if !self.is_running(id).await {
+            let future = self.start_pipe_task(id.to_string()).await?;
+            tokio::spawn(future);
+        }
+        Ok(())
+    }
+
     pub async fn get_pipe_info(&self, id: &str) -> Option<PipeInfo> {
         let pipes = self.list_pipes().await;
         pipes.iter().find(|pipe| pipe.id == id).cloned()
     }
 
     async fn load_pipe_info(pipe_id: String, config_path: PathBuf) -> PipeInfo {
-        let config = tokio::fs::read_to_string(&config_path)
-            .await
-            .and_then(|s| serde_json::from_str::<Value>(&s).map_err(Into::into))
-            .unwrap_or_else(|_| Value::Null);
+        let config = load_config(&config_path).await.unwrap_or_else(|e| {
+            if !e.is_not_found() {
+                warn!("{}", e);
+            }
+            Value::Null
+        });
 
+        let disabled = config_path
+            .parent()
+            .is_some_and(screenpipe_core::is_pipe_disabled);
         PipeInfo {
             id: pipe_id,
             enabled: config
                 .get("enabled")
                 .and_then(Value::as_bool)
-                .unwrap_or(false),
+                .unwrap_or(false)
+                && !disabled,
             source: config
                 .get("source")
                 .unwrap_or(&Value::Null)
@@ -155,45 +489,76 @@ impl PipeManager {
     }
 
     pub async fn list_pipes(&self) -> Vec<PipeInfo> {
-        let pipe_dir = self.screenpipe_dir.join("pipes");
-        let mut pipe_infos = Vec::new();
-
-        if let Ok(mut entries) = tokio::fs::read_dir(pipe_dir).await {
-            while let Ok(Some(entry)) = entries.next_entry().await {
-                let file_name = entry.file_name();
-                let pipe_id = file_name.to_string_lossy();
-
-                // ignore hidden directories and files
-                if !pipe_id.starts_with('.')
-                    && entry
-                        .file_type()
-                        .await
-                        .map(|ft| ft.is_dir())
-                        .unwrap_or(false)
-                {
-                    let config_path = entry.path().join("pipe.json");
-                    pipe_infos.push(Self::load_pipe_info(pipe_id.into_owned(), config_path).await);
-                }
+        let installed = match self.pipes.list_pipes().await {
+            Ok(installed) => installed,
+            Err(e) => {
+                warn!("failed to list pipes: {}", e);
+                return Vec::new();
             }
+        };
+        let mut pipe_infos = Vec::new();
+        for pipe in installed {
+            let config_path = self
+                .screenpipe_dir()
+                .join("pipes")
+                .join(&pipe.id)
+                .join("pipe.json");
+            pipe_infos.push(Self::load_pipe_info(pipe.id, config_path).await);
         }
 
         pipe_infos
     }
 
     pub async fn download_pipe(&self, url: &str) -> Result<String> {
+        self.download_pipe_with(url, DownloadOptions::default())
+            .await
+    }
+
+    /// [`Self::download_pipe`], with `options.locked` a github pipe already at the commit
+    /// its source points to is kept as installed.
+    pub async fn download_pipe_with(&self, url: &str, options: DownloadOptions) -> Result<String> {
+        let id = Self::pipe_id_for_source(url)
+            .ok_or_else(|| anyhow::anyhow!("invalid pipe source: {}", url))?;
+        let _guard = self.lock_pipe(&id).await;
+        self.download_pipe_locked(url, options).await
+    }
+
+    /// [`Self::download_pipe_with`] for a caller already holding the lock of the pipe's id.
+    pub(crate) async fn download_pipe_locked(
+        &self,
+        url: &str,
+        options: DownloadOptions,
+    ) -> Result<String> {
+        // A kept pipe keeps the source it was installed from
+        if options.overwrite == OverwritePolicy::Skip {
+            if let Some(id) = Self::pipe_id_for_source(url)
+                .filter(|id| self.screenpipe_dir().join("pipes").join(id).exists())
+            {
+                info!("pipe {} is installed already, keeping it", id);
+                return Ok(id);
+            }
+        }
+
         // Remove any surrounding quotes and normalize backslashes
         let normalized_url = url.trim_matches('"').replace("\\", "/");
 
-        let pipe_dir = download_pipe(&normalized_url, self.screenpipe_dir.clone()).await?;
+        let pipe_dir = self
+            .pipes
+            .download_pipe_with(&normalized_url, options)
+            .await?;
 
         // update the config with the source url
-        self.update_config(
+        self.update_config_locked(
             &pipe_dir.file_name().unwrap().to_string_lossy(),
             serde_json::json!({
                 "source": normalized_url,
             }),
         )
         .await?;
+        // Schema errors already failed the download. Warnings, errors in strict mode, leave
+        // it installed, it won't run until its pipe.json is fixed
+        self.check_manifest(&pipe_dir.file_name().unwrap().to_string_lossy())
+            .await?;
 
         info!(
             "pipe {} downloaded",
@@ -204,23 +569,72 @@ impl PipeManager {
     }
 
     pub async fn purge_pipes(&self) -> Result<()> {
-        let pipe_dir = self.screenpipe_dir.join("pipes");
+        let pipe_dir = self.screenpipe_dir().join("pipes");
         tokio::fs::remove_dir_all(pipe_dir).await?;
         Ok(())
     }
 
-    pub async fn delete_pipe(&self, id: &str) -> Result<()> {
+    /// Moves an installed pipe aside so a reinstall can be undone, keeping its
+    /// pipe.json in place for the download to merge, and its lock for the download to
+    /// tell where it is from. `None` if it isn't installed.
+    pub(crate) async fn backup_pipe_locked(&self, id: &str) -> Result<Option<PathBuf>> {
+        let pipe_dir = self.screenpipe_dir().join("pipes").join(id);
+        if !pipe_dir.exists() {
+            return Ok(None);
+        }
+        let backup_dir = self
+            .screenpipe_dir()
+            .join("pipes")
+            .join(format!(".{}.backup", id));
+        if backup_dir.exists() {
+            tokio::fs::remove_dir_all(&backup_dir).await?;
+        }
+        tokio::fs::rename(&pipe_dir, &backup_dir).await?;
+        tokio::fs::create_dir_all(&pipe_dir).await?;
+        let config_path = backup_dir.join("pipe.json");
+        if config_path.exists() {
+            tokio::fs::copy(&config_path, pipe_dir.join("pipe.json")).await?;
+        }
+        if let Some(lock) = PipeLock::load(&backup_dir)? {
+            lock.without_files().save(&pipe_dir).await?;
+        }
+        Ok(Some(backup_dir))
+    }
+
+    /// Undoes an install: the pipe goes back to `backup` or, without one, is removed.
+    pub(crate) async fn restore_pipe_locked(
+        &self,
+        id: &str,
+        backup: Option<&PathBuf>,
+    ) -> Result<()> {
+        self.stop_pipe(id).await?;
+        let pipe_dir = self.screenpipe_dir().join("pipes").join(id);
+        if pipe_dir.exists() {
+            remove_pipe_dir(&pipe_dir).await?;
+        }
+        if let Some(backup) = backup {
+            tokio::fs::rename(backup, &pipe_dir).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn delete_pipe(&self, id: &str) -> Result<(), PipeError> {
+        let _guard = self.lock_pipe(id).await;
+        self.delete_pipe_locked(id).await
+    }
+
+    pub(crate) async fn delete_pipe_locked(&self, id: &str) -> Result<(), PipeError> {
         // First stop the pipe if running
         self.stop_pipe(id).await?;
 
-        // Then delete the directory
-        let pipe_dir = self.screenpipe_dir.join("pipes").join(id);
-        if pipe_dir.exists() {
-            tokio::fs::remove_dir_all(pipe_dir).await?;
+        // Then delete the directory, only the link of a linked pipe
+        let pipe_dir = self.screenpipe_dir().join("pipes").join(id);
+        if pipe_dir.exists() || is_linked_pipe(&pipe_dir) {
+            remove_pipe_dir(&pipe_dir).await?;
             debug!("deleted pipe: {}", id);
             Ok(())
         } else {
-            Err(anyhow::anyhow!("pipe '{}' does not exist", id))
+            Err(PipeError::NotFound(id.to_string()))
         }
     }
 
@@ -261,13 +675,144 @@ impl PipeManager {
         Ok(())
     }
 
+    /// Runs the pipe once to handle `event` and waits for it to exit, killing it after
+    /// `timeout` or its own `timeout_secs` if shorter. Fails if it exits with a non-zero
+    /// status, with [`PipeError::Timeout`] if it was killed.
+    pub async fn run_pipe_once(&self, id: &str, event: &str, timeout: Duration) -> Result<()> {
+        if !self.screenpipe_dir().join("pipes").join(id).exists() {
+            return Err(PipeError::NotFound(id.to_string()).into());
+        }
+        self.check_manifest(id).await?;
+        let requested = screenpipe_core::requested_permissions(id, self.screenpipe_dir()).await;
+        let granted = if requested.is_empty() {
+            None
+        } else {
+            Some(self.permissions.resolve(id, &requested).await?)
+        };
+
+        let proxy = self.start_proxy(id).await?;
+        let mut extra_env = proxy.as_ref().map(PipeProxy::env).unwrap_or_default();
+        // Revoked once the pipe exits
+        let token = pipe_tokens().issue(id, &requested);
+        extra_env.extend(token.env());
+        let options = PipeRunOptions {
+            granted,
+            extra_env,
+            shutdown: Some(self.shutdown.clone()),
+            memory_limit: self.memory_limit,
+            sandbox: self.sandbox,
+            ..Default::default()
+        };
+        let run = PipeRun::start(id);
+        let mut child = self.pipes.run_pipe_once_with(id, event, options).await?;
+        let timeout = match screenpipe_core::pipe_timeout(id, self.screenpipe_dir()).await {
+            Some(own) => own.min(timeout),
+            None => timeout,
+        };
+        let status = screenpipe_core::wait_pipe(id, &mut child, Some(timeout))
+            .await
+            .map_err(PipeError::from_wait);
+        match &status {
+            Ok(status) => run.finish(Some(*status), self.screenpipe_dir()).await,
+            Err(PipeError::Timeout { .. }) => run.finish(None, self.screenpipe_dir()).await,
+            Err(_) => {}
+        }
+        let status = status?;
+        if !status.success() {
+            anyhow::bail!("pipe exited with non-zero status: {}", status)
+        }
+        Ok(())
+    }
+
+    /// Prepares a `pipe repl` session running as pipe `id`, or as a scratch pipe. The
+    /// session gets the scopes the pipe was already granted, it doesn't prompt: scopes
+    /// nobody answered yet are left out.
+    pub async fn repl_session(
+        &self,
+        id: Option<&str>,
+        server_url: &str,
+    ) -> Result<PipeReplSession> {
+        let granted = match id {
+            Some(id) => {
+                let requested =
+                    screenpipe_core::requested_permissions(id, self.screenpipe_dir()).await;
+                if requested.is_empty() {
+                    None
+                } else {
+                    let approved = self.permissions.granted(id).await;
+                    Some(
+                        requested
+                            .into_iter()
+                            .filter(|scope| approved.contains(scope))
+                            .collect(),
+                    )
+                }
+            }
+            None => None,
+        };
+        PipeReplSession::new(id, self.screenpipe_dir(), server_url, granted).await
+    }
+
+    /// The proxy pipe `id` goes through while it runs, `None` when the proxy is off.
+    async fn start_proxy(&self, id: &str) -> Result<Option<PipeProxy>> {
+        start_pipe_proxy(
+            id,
+            self.screenpipe_dir(),
+            self.network_proxy,
+            self.network_stats.get().cloned(),
+        )
+        .await
+    }
+
     pub async fn start_pipe_task(&self, id: String) -> Result<impl Future<Output = Result<()>>> {
-        let screenpipe_dir = self.screenpipe_dir.clone();
+        self.check_manifest(&id).await?;
+        let pipes = self.pipes.clone();
+        let screenpipe_dir = self.screenpipe_dir().to_path_buf();
         let running_pipes = self.running_pipes.clone();
+        let permissions = self.permissions.clone();
+        let network_proxy = self.network_proxy;
+        let network_stats = self.network_stats.get().cloned();
+        let events = self.events.get().cloned();
+        let shutdown = self.shutdown.clone();
+        let memory_limit = self.memory_limit;
+        let sandbox = self.sandbox;
         let id_for_map = id.clone();
+        let crashed = move |pipe_id: &str, error: String| {
+            if let Some(events) = &events {
+                events.emit(EventKind::PipeCrashed {
+                    pipe_id: pipe_id.to_string(),
+                    error,
+                });
+            }
+        };
 
         Ok(async move {
-            match screenpipe_core::run_pipe(&id, screenpipe_dir.clone()).await {
+            let requested = screenpipe_core::requested_permissions(&id, &screenpipe_dir).await;
+            let granted = if requested.is_empty() {
+                None
+            } else {
+                Some(permissions.resolve(&id, &requested).await?)
+            };
+
+            // Stopped with this task, once the pipe exits or is killed
+            let proxy =
+                start_pipe_proxy(&id, &screenpipe_dir, network_proxy, network_stats).await?;
+            let mut extra_env = proxy.as_ref().map(PipeProxy::env).unwrap_or_default();
+            // Held to the scopes its pipe.json declares, revoked with this task
+            let token = pipe_tokens().issue(&id, &requested);
+            extra_env.extend(token.env());
+
+            let timeout = screenpipe_core::pipe_timeout(&id, &screenpipe_dir).await;
+            let options = PipeRunOptions {
+                granted,
+                extra_env,
+                shutdown: Some(shutdown.clone()),
+                memory_limit,
+                sandbox,
+                ..Default::default()
+            };
+            let run = PipeRun::start(&id);
+            match pipes.run_pipe_with(&id, options).await {
                 Ok(mut child) => {
                     let pid = child.id().expect("Failed to get child pid") as i32;
                     let (kill_tx, mut kill_rx) = mpsc::channel::<()>(1);
@@ -283,16 +828,35 @@ impl PipeManager {
                     info!("started pipe: {} with pid {}", id, pid);
 
                     tokio::select! {
-                        status = child.wait() => {
+                        status = screenpipe_core::wait_pipe(&id, &mut child, timeout) => {
+                            let status = status.map_err(PipeError::from_wait);
+                            match &status {
+                                Ok(status) => run.finish(Some(*status), &screenpipe_dir).await,
+                                Err(PipeError::Timeout { .. }) => run.finish(None, &screenpipe_dir).await,
+                                Err(_) => {}
+                            }
                             match status {
+                                // Stopped with screenpipe
+                                Ok(_) if shutdown.is_cancelled() => {
+                                    running_pipes.write().await.remove(&id_for_map);
+                                    Ok(())
+                                }
+                                Err(e @ PipeError::Timeout { elapsed, .. }) => {
+                                    warn!("pipe {} ran for {}s, killed", id, elapsed.as_secs());
+                                    running_pipes.write().await.remove(&id_for_map);
+                                    crashed(&id, format!("killed after {}s", elapsed.as_secs()));
+                                    Err(e.into())
+                                }
                                 Ok(status) if !status.success() => {
                                     println!("pipe {} exited with status: {}", id, status);
                                     running_pipes.write().await.remove(&id_for_map);
+                                    crashed(&id, format!("exited with status: {}", status));
                                     anyhow::bail!("pipe exited with non-zero status: {}", status);
                                 }
                                 Err(e) => {
                                     println!("error waiting for pipe {}: {}", id, e);
                                     running_pipes.write().await.remove(&id_for_map);
+                                    crashed(&id, format!("error waiting for pipe: {}", e));
                                     anyhow::bail!("error waiting for pipe: {}", e);
                                 }
                                 Ok(_) => Ok(())
@@ -301,6 +865,7 @@ impl PipeManager {
                         _ = kill_rx.recv() => {
                             // Kill received through channel
                             let _ = child.kill().await;
+                            run.finish(None, &screenpipe_dir).await;
                             running_pipes.write().await.remove(&id_for_map);
                             Ok(())
                         }
@@ -314,3 +879,36 @@ impl PipeManager {
         })
     }
 }
+
+async fn start_pipe_proxy(
+    id: &str,
+    screenpipe_dir: &Path,
+    enabled: bool,
+    db: Option<Arc<DatabaseManager>>,
+) -> Result<Option<PipeProxy>> {
+    if !enabled {
+        return Ok(None);
+    }
+    let hosts = screenpipe_core::requested_hosts(id, screenpipe_dir).await;
+    Ok(Some(PipeProxy::start(id, hosts, db).await?))
+}
+
+/// Json merge patch (rfc 7396) of `patch` into `target`.
+pub fn merge_patch(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(Default::default());
+    }
+    if let Value::Object(target) = target {
+        for (key, value) in patch {
+            if value.is_null() {
+                target.remove(key);
+            } else {
+                merge_patch(target.entry(key.clone()).or_insert(Value::Null), value);
+            }
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::sync::{broadcast, Notify, RwLock};
+use tracing::{info, warn};
+
+/// How long a pipe start waits for the user to answer a permission prompt.
+pub const PERMISSION_PROMPT_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Events published on the pipe events stream (`GET /pipes/events`).
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PipeEvent {
+    PermissionRequest {
+        pipe_id: String,
+        scopes: Vec<String>,
+    },
+    PermissionResolved {
+        pipe_id: String,
+        granted: Vec<String>,
+    },
+}
+
+/// Per pipe, per scope decisions: `true` approved, `false` denied.
+type Decisions = BTreeMap<String, BTreeMap<String, bool>>;
+
+/// Asks the user (through the events stream) before a pipe gets a scope for the first time.
+///
+/// Decisions are persisted in `<screenpipe_dir>/pipe_permissions.json` so later starts
+/// don't prompt again until they are revoked.
+pub struct PermissionBroker {
+    store_path: PathBuf,
+    decisions: RwLock<Decisions>,
+    answered: RwLock<HashMap<String, std::sync::Arc<Notify>>>,
+    events: broadcast::Sender<PipeEvent>,
+    auto_approve: bool,
+    timeout: Duration,
+}
+
+impl PermissionBroker {
+    pub fn new(screenpipe_dir: PathBuf, auto_approve: bool) -> Self {
+        let store_path = screenpipe_dir.join("pipe_permissions.json");
+        let decisions = std::fs::read_to_string(&store_path)
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default();
+        let (events, _) = broadcast::channel(64);
+
+        Self {
+            store_path,
+            decisions: RwLock::new(decisions),
+            answered: RwLock::new(HashMap::new()),
+            events,
+            auto_approve,
+            timeout: PERMISSION_PROMPT_TIMEOUT,
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<PipeEvent> {
+        self.events.subscribe()
+    }
+
+    /// Scopes currently approved for a pipe.
+    pub async fn granted(&self, pipe_id: &str) -> Vec<String> {
+        self.decisions
+            .read()
+            .await
+            .get(pipe_id)
+            .map(|scopes| {
+                scopes
+                    .iter()
+                    .filter(|(_, approved)| **approved)
+                    .map(|(scope, _)| scope.clone())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Returns the subset of `requested` the pipe may use, prompting for undecided
+    /// scopes. Unanswered prompts are denied once the timeout expires.
+    pub async fn resolve(&self, pipe_id: &str, requested: &[String]) -> Result<Vec<String>> {
+        let undecided = self.undecided(pipe_id, requested).await;
+
+        if !undecided.is_empty() {
+            if self.auto_approve {
+                info!("auto approving {:?} for pipe {}", undecided, pipe_id);
+                self.decide(pipe_id, undecided, Vec::new()).await?;
+            } else {
+                let notify = self
+                    .answered
+                    .write()
+                    .await
+                    .entry(pipe_id.to_string())
+                    .or_default()
+                    .clone();
+                let answered = notify.notified();
+                tokio::pin!(answered);
+                answered.as_mut().enable();
+
+                let _ = self.events.send(PipeEvent::PermissionRequest {
+                    pipe_id: pipe_id.to_string(),
+                    scopes: undecided.clone(),
+                });
+
+                if tokio::time::timeout(self.timeout, answered).await.is_err() {
+                    warn!(
+                        "permission prompt for pipe {} timed out, denying {:?}",
+                        pipe_id, undecided
+                    );
+                    // Timeouts are not persisted, the next start prompts again
+                    let granted = self.granted_from(pipe_id, requested).await;
+                    self.publish_resolved(pipe_id, &granted);
+                    return Ok(granted);
+                }
+            }
+        }
+
+        let granted = self.granted_from(pipe_id, requested).await;
+        self.publish_resolved(pipe_id, &granted);
+        Ok(granted)
+    }
+
+    /// Records an answer to a permission prompt.
+    pub async fn decide(
+        &self,
+        pipe_id: &str,
+        approve: Vec<String>,
+        deny: Vec<String>,
+    ) -> Result<()> {
+        {
+            let mut decisions = self.decisions.write().await;
+            let scopes = decisions.entry(pipe_id.to_string()).or_default();
+            for scope in approve {
+                scopes.insert(scope, true);
+            }
+            // A scope listed in both is denied
+            for scope in deny {
+                scopes.insert(scope, false);
+            }
+            self.persist(&decisions).await?;
+        }
+
+        if let Some(notify) = self.answered.read().await.get(pipe_id) {
+            notify.notify_waiters();
+        }
+        Ok(())
+    }
+
+    /// Forgets every decision for a pipe so its next start prompts again.
+    pub async fn revoke(&self, pipe_id: &str) -> Result<()> {
+        let mut decisions = self.decisions.write().await;
+        if decisions.remove(pipe_id).is_some() {
+            self.persist(&decisions).await?;
+            info!("revoked permissions for pipe {}", pipe_id);
+        }
+        Ok(())
+    }
+
+    async fn undecided(&self, pipe_id: &str, requested: &[String]) -> Vec<String> {
+        let decisions = self.decisions.read().await;
+        let decided = decisions.get(pipe_id);
+        requested
+            .iter()
+            .filter(|scope| !decided.is_some_and(|d| d.contains_key(*scope)))
+            .cloned()
+            .collect()
+    }
+
+    async fn granted_from(&self, pipe_id: &str, requested: &[String]) -> Vec<String> {
+        let granted = self.granted(pipe_id).await;
+        requested
+            .iter()
+            .filter(|scope| granted.contains(scope))
+            .cloned()
+            .collect()
+    }
+
+    fn publish_resolved(&self, pipe_id: &str, granted: &[String]) {
+        let _ = self.events.send(PipeEvent::PermissionResolved {
+            pipe_id: pipe_id.to_string(),
+            granted: granted.to_vec(),
+        });
+    }
+
+    async fn persist(&self, decisions: &Decisions) -> Result<()> {
+        if let Some(parent) = self.store_path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(&self.store_path, serde_json::to_string_pretty(decisions)?).await?;
+        Ok(())
+    }
+}
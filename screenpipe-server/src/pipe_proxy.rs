@@ -0,0 +1,291 @@
+//! Forward proxy pipes can be made to go through, to count what each of them sends
+//! to which host and to hold them to the hosts they declared.
+//!
+//! Every pipe gets its own proxy on a free localhost port, passed in `HTTP_PROXY` and
+//! `HTTPS_PROXY`. Https goes through `CONNECT` tunnels, tls stays end to end and only
+//! bytes are counted. Pipes run on bun, which has no network permissions to pin them
+//! to the proxy: it accounts for the http clients that honor the proxy variables.
+
+use crate::DatabaseManager;
+use anyhow::{anyhow, Result};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+/// Longest request head the proxy reads before giving up on a connection.
+const MAX_HEAD_BYTES: usize = 16 * 1024;
+
+/// Headers meant for the proxy, not passed on.
+const HOP_HEADERS: [&str; 4] = [
+    "connection",
+    "proxy-connection",
+    "proxy-authorization",
+    "keep-alive",
+];
+
+/// Whether `host` is one of `allowed`, where `*.example.com` covers the subdomains of
+/// `example.com` but not `example.com` itself.
+pub fn host_allowed(allowed: &[String], host: &str) -> bool {
+    let host = host.trim_end_matches('.').to_lowercase();
+    allowed.iter().any(|entry| match entry.strip_prefix("*.") {
+        Some(domain) => host
+            .strip_suffix(domain)
+            .is_some_and(|sub| sub.ends_with('.') && sub.len() > 1),
+        None => *entry == host,
+    })
+}
+
+/// Host and port of a request target: `host:port` for `CONNECT`, an absolute
+/// `http://` url otherwise, with the origin-form path the upstream gets.
+pub fn parse_target(method: &str, target: &str) -> Option<(String, u16, String)> {
+    let (authority, path) = if method.eq_ignore_ascii_case("CONNECT") {
+        (target, String::new())
+    } else {
+        let rest = target
+            .get(..7)
+            .filter(|scheme| scheme.eq_ignore_ascii_case("http://"))
+            .map(|_| &target[7..])?;
+        match rest.find(['/', '?']) {
+            Some(i) if rest[i..].starts_with('/') => (&rest[..i], rest[i..].to_string()),
+            Some(i) => (&rest[..i], format!("/{}", &rest[i..])),
+            None => (rest, "/".to_string()),
+        }
+    };
+    // Credentials in the url are dropped
+    let authority = authority.rsplit('@').next()?;
+    let default_port = if path.is_empty() { 443 } else { 80 };
+    let (host, port) = match authority.strip_prefix('[') {
+        // [::1]:8080
+        Some(v6) => {
+            let (host, rest) = v6.split_once(']')?;
+            let port = match rest.strip_prefix(':') {
+                Some(port) => port.parse().ok()?,
+                None => default_port,
+            };
+            (host, port)
+        }
+        None => match authority.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().ok()?),
+            None => (authority, default_port),
+        },
+    };
+    if host.is_empty() {
+        return None;
+    }
+    Some((host.to_lowercase(), port, path))
+}
+
+/// A running proxy for one pipe, stopped when dropped.
+pub struct PipeProxy {
+    addr: SocketAddr,
+    task: JoinHandle<()>,
+}
+
+impl PipeProxy {
+    /// Starts a proxy for `pipe_id` on a free localhost port. `allowed` are the hosts the
+    /// pipe declared, `None` lets it reach any host. Traffic is recorded in `db` if set.
+    pub async fn start(
+        pipe_id: &str,
+        allowed: Option<Vec<String>>,
+        db: Option<Arc<DatabaseManager>>,
+    ) -> Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await?;
+        let addr = listener.local_addr()?;
+        let proxy = Arc::new(Connections {
+            pipe_id: pipe_id.to_string(),
+            allowed,
+            db,
+        });
+        let task = tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => {
+                        let proxy = proxy.clone();
+                        tokio::spawn(async move {
+                            if let Err(e) = proxy.handle(stream).await {
+                                debug!("pipe {} proxy connection: {}", proxy.pipe_id, e);
+                            }
+                        });
+                    }
+                    Err(e) => {
+                        warn!("pipe {} proxy stopped accepting: {}", proxy.pipe_id, e);
+                        break;
+                    }
+                }
+            }
+        });
+        debug!("network proxy for pipe {} listening on {}", pipe_id, addr);
+        Ok(Self { addr, task })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Environment sending the pipe's http clients through the proxy. Localhost, where
+    /// the screenpipe api is, is reached directly.
+    pub fn env(&self) -> Vec<(String, String)> {
+        let url = format!("http://{}", self.addr);
+        let mut env = Vec::new();
+        for name in ["HTTP_PROXY", "HTTPS_PROXY"] {
+            env.push((name.to_string(), url.clone()));
+            env.push((name.to_lowercase(), url.clone()));
+        }
+        for name in ["NO_PROXY", "no_proxy"] {
+            env.push((name.to_string(), "localhost,127.0.0.1,::1".to_string()));
+        }
+        env
+    }
+}
+
+impl Drop for PipeProxy {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+struct Connections {
+    pipe_id: String,
+    allowed: Option<Vec<String>>,
+    db: Option<Arc<DatabaseManager>>,
+}
+
+impl Connections {
+    async fn handle(&self, mut client: TcpStream) -> Result<()> {
+        let (head, rest) = read_head(&mut client).await?;
+        let mut lines = head.split("\r\n");
+        let request_line = lines.next().unwrap_or_default();
+        let mut parts = request_line.split_whitespace();
+        let (Some(method), Some(target), Some(version)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            return respond(&mut client, "400 Bad Request").await;
+        };
+        let Some((host, port, path)) = parse_target(method, target) else {
+            return respond(&mut client, "400 Bad Request").await;
+        };
+
+        if self
+            .allowed
+            .as_ref()
+            .is_some_and(|allowed| !host_allowed(allowed, &host))
+        {
+            warn!(
+                "pipe {} tried to reach undeclared host {}",
+                self.pipe_id, host
+            );
+            self.record(&host, 0, 0, true).await;
+            return respond(&mut client, "403 Forbidden").await;
+        }
+
+        let mut upstream = match TcpStream::connect((host.as_str(), port)).await {
+            Ok(upstream) => upstream,
+            Err(e) => {
+                debug!(
+                    "pipe {} proxy failed to reach {}: {}",
+                    self.pipe_id, host, e
+                );
+                return respond(&mut client, "502 Bad Gateway").await;
+            }
+        };
+
+        let mut sent = 0;
+        if method.eq_ignore_ascii_case("CONNECT") {
+            client
+                .write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n")
+                .await?;
+        } else {
+            // One request per connection, another host can't ride on this one
+            let mut forwarded = format!("{} {} {}\r\n", method, path, version);
+            for line in lines.filter(|line| !line.is_empty()) {
+                let name = line.split(':').next().unwrap_or_default().trim();
+                if !HOP_HEADERS.iter().any(|hop| name.eq_ignore_ascii_case(hop)) {
+                    forwarded.push_str(line);
+                    forwarded.push_str("\r\n");
+                }
+            }
+            forwarded.push_str("Connection: close\r\n\r\n");
+            upstream.write_all(forwarded.as_bytes()).await?;
+            sent += forwarded.len() as u64;
+        }
+        if !rest.is_empty() {
+            upstream.write_all(&rest).await?;
+            sent += rest.len() as u64;
+        }
+
+        let (client_read, client_write) = client.into_split();
+        let (upstream_read, upstream_write) = upstream.into_split();
+        let (up, down) = tokio::join!(
+            pump(client_read, upstream_write),
+            pump(upstream_read, client_write)
+        );
+        self.record(&host, sent + up, down, false).await;
+        Ok(())
+    }
+
+    async fn record(&self, host: &str, bytes_up: u64, bytes_down: u64, blocked: bool) {
+        let Some(db) = &self.db else {
+            return;
+        };
+        if let Err(e) = db
+            .record_pipe_network(&self.pipe_id, host, bytes_up, bytes_down, blocked)
+            .await
+        {
+            warn!(
+                "failed to record network stats of pipe {}: {}",
+                self.pipe_id, e
+            );
+        }
+    }
+}
+
+/// Reads up to the end of the request head, returning it and the bytes read past it.
+async fn read_head(client: &mut TcpStream) -> Result<(String, Vec<u8>)> {
+    let mut buf = Vec::with_capacity(1024);
+    let mut chunk = [0u8; 1024];
+    loop {
+        let n = client.read(&mut chunk).await?;
+        if n == 0 {
+            return Err(anyhow!("connection closed before the request head ended"));
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if let Some(end) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            let rest = buf.split_off(end + 4);
+            buf.truncate(end);
+            return Ok((String::from_utf8_lossy(&buf).into_owned(), rest));
+        }
+        if buf.len() > MAX_HEAD_BYTES {
+            return Err(anyhow!("request head over {} bytes", MAX_HEAD_BYTES));
+        }
+    }
+}
+
+async fn respond(client: &mut TcpStream, status: &str) -> Result<()> {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        status
+    );
+    client.write_all(response.as_bytes()).await?;
+    Ok(())
+}
+
+/// Copies `from` into `to` until either side closes, returning the bytes copied.
+async fn pump(mut from: impl AsyncRead + Unpin, mut to: impl AsyncWrite + Unpin) -> u64 {
+    let mut buf = vec![0u8; 16 * 1024];
+    let mut copied = 0;
+    loop {
+        let n = match from.read(&mut buf).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => n,
+        };
+        if to.write_all(&buf[..n]).await.is_err() {
+            break;
+        }
+        copied += n as u64;
+    }
+    let _ = to.shutdown().await;
+    copied
+}
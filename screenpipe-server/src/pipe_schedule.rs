@@ -0,0 +1,362 @@
+//! One-off runs pipes schedule for themselves, e.g. "run me again in 45 minutes with
+//! this payload". Cron covers recurring work, these fire once.
+//!
+//! Jobs are kept in the db so none are lost across restarts or sleep. The scheduler
+//! waits for the soonest due job, runs the pipe with the job as its `PIPE_EVENT` and
+//! marks the job done, or failed once its retries are used up. Due times are precise
+//! to a few seconds.
+
+use crate::db_types::{PipeJob, PipeJobStatus};
+use crate::{DatabaseManager, PipeManager};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use screenpipe_core::power::{power_state, PowerEvent, PowerState, SubsystemOutcome};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+use tracing::{error, info, warn};
+
+/// Jobs a pipe may have pending or running at once, so a pipe scheduling itself in a
+/// loop can't flood the queue.
+pub const MAX_ACTIVE_JOBS_PER_PIPE: i64 = 100;
+
+/// Largest payload a job carries, it is passed to the pipe in its environment.
+pub const MAX_PAYLOAD_BYTES: usize = 64 * 1024;
+
+/// Most runs a job may ask for through its retry policy.
+pub const MAX_ATTEMPTS: u32 = 10;
+
+pub const MAX_BACKOFF_SECONDS: u64 = 24 * 3600;
+
+/// How far ahead a job may be scheduled.
+pub const MAX_DAYS_AHEAD: i64 = 365;
+
+/// Longest the scheduler waits before looking at the db again. Timers may stand still
+/// while the machine sleeps, a wake it isn't told about delays jobs by at most this.
+pub const MAX_WAIT: Duration = Duration::from_secs(30);
+
+/// A run still going after this is killed and counts as failed.
+pub const JOB_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// How long finished and cancelled jobs stay listed.
+const FINISHED_JOB_RETENTION_DAYS: i64 = 7;
+
+const PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+
+const SUBSYSTEM: &str = "pipe scheduler";
+
+/// Body of `POST /pipes/:id/schedule`. Exactly one of `run_at` and `run_in_seconds`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ScheduleRequest {
+    #[serde(default)]
+    pub run_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub run_in_seconds: Option<u64>,
+    #[serde(default)]
+    pub payload: Value,
+    #[serde(default)]
+    pub retry: RetryPolicy,
+}
+
+/// How often a failed run is tried again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// Runs in total, 1 never retries
+    pub max_attempts: u32,
+    /// Wait before the first retry, doubled after every failed attempt
+    pub backoff_seconds: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 1,
+            backoff_seconds: 60,
+        }
+    }
+}
+
+/// Wait before retrying a job that failed its `attempts`th run.
+pub fn retry_delay(backoff_seconds: i64, attempts: i64) -> ChronoDuration {
+    let doublings = attempts.saturating_sub(1).clamp(0, 16) as u32;
+    ChronoDuration::seconds(backoff_seconds.max(0).saturating_mul(1 << doublings))
+}
+
+/// What the pipe receives in `PIPE_EVENT` and on stdin when a job fires.
+pub fn job_event(job: &PipeJob) -> Value {
+    json!({
+        "type": "scheduled_job",
+        "job_id": job.id,
+        "attempt": job.attempts,
+        "run_at": job.run_at,
+        "payload": job.payload,
+    })
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ScheduleError {
+    #[error("{0}")]
+    Invalid(String),
+    #[error("pipe '{pipe_id}' already has {max} jobs scheduled")]
+    TooManyJobs { pipe_id: String, max: i64 },
+    #[error("pipe '{pipe_id}' has no pending job {id}")]
+    NotFound { pipe_id: String, id: i64 },
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+/// Runs a due job. Swappable so the scheduler can be exercised without real pipes.
+pub type JobRunner =
+    Arc<dyn Fn(PipeJob) -> Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send>> + Send + Sync>;
+
+type SystemTime = Arc<dyn Fn() -> DateTime<Utc> + Send + Sync>;
+
+pub struct PipeScheduler {
+    db: Arc<DatabaseManager>,
+    runner: JobRunner,
+    now: SystemTime,
+    /// Wakes the scheduler when a job is added or retried, it may be due before the
+    /// next check
+    wakeup: Arc<Notify>,
+}
+
+impl PipeScheduler {
+    /// Fires jobs by running the pipe once through `pipe_manager`.
+    pub fn new(db: Arc<DatabaseManager>, pipe_manager: Arc<PipeManager>) -> Self {
+        Self::with_runner(
+            db,
+            Arc::new(move |job: PipeJob| {
+                let pipe_manager = pipe_manager.clone();
+                Box::pin(async move {
+                    let event = job_event(&job).to_string();
+                    pipe_manager
+                        .run_pipe_once(&job.pipe_id, &event, JOB_TIMEOUT)
+                        .await
+                })
+            }),
+        )
+    }
+
+    pub fn with_runner(db: Arc<DatabaseManager>, runner: JobRunner) -> Self {
+        Self {
+            db,
+            runner,
+            now: Arc::new(Utc::now),
+            wakeup: Arc::new(Notify::new()),
+        }
+    }
+
+    /// Reads the time from `now` instead of the system clock.
+    pub fn with_system_time(
+        mut self,
+        now: impl Fn() -> DateTime<Utc> + Send + Sync + 'static,
+    ) -> Self {
+        self.now = Arc::new(now);
+        self
+    }
+
+    pub async fn schedule(
+        &self,
+        pipe_id: &str,
+        request: ScheduleRequest,
+    ) -> Result<PipeJob, ScheduleError> {
+        let now = (self.now)();
+        let latest = now + ChronoDuration::days(MAX_DAYS_AHEAD);
+        let run_at = match (request.run_at, request.run_in_seconds) {
+            (Some(run_at), None) => run_at,
+            (None, Some(seconds)) => {
+                // Clamped past the limit so the check below rejects it without overflowing
+                let limit = (latest - now).num_seconds() as u64 + 1;
+                now + ChronoDuration::seconds(seconds.min(limit) as i64)
+            }
+            _ => {
+                return Err(ScheduleError::Invalid(
+                    "set either run_at or run_in_seconds".to_string(),
+                ))
+            }
+        };
+        if run_at > latest {
+            return Err(ScheduleError::Invalid(format!(
+                "jobs can be scheduled at most {} days ahead",
+                MAX_DAYS_AHEAD
+            )));
+        }
+        let payload_len = request.payload.to_string().len();
+        if payload_len > MAX_PAYLOAD_BYTES {
+            return Err(ScheduleError::Invalid(format!(
+                "payload is {} bytes, at most {} are allowed",
+                payload_len, MAX_PAYLOAD_BYTES
+            )));
+        }
+        let retry = request.retry;
+        if !(1..=MAX_ATTEMPTS).contains(&retry.max_attempts) {
+            return Err(ScheduleError::Invalid(format!(
+                "retry.max_attempts must be between 1 and {}",
+                MAX_ATTEMPTS
+            )));
+        }
+        if retry.backoff_seconds > MAX_BACKOFF_SECONDS {
+            return Err(ScheduleError::Invalid(format!(
+                "retry.backoff_seconds must be at most {}",
+                MAX_BACKOFF_SECONDS
+            )));
+        }
+
+        let id = self
+            .db
+            .insert_pipe_job(
+                pipe_id,
+                run_at,
+                &request.payload,
+                retry.max_attempts as i64,
+                retry.backoff_seconds as i64,
+                MAX_ACTIVE_JOBS_PER_PIPE,
+            )
+            .await?
+            .ok_or_else(|| ScheduleError::TooManyJobs {
+                pipe_id: pipe_id.to_string(),
+                max: MAX_ACTIVE_JOBS_PER_PIPE,
+            })?;
+        self.wakeup.notify_one();
+
+        let job = self
+            .db
+            .get_pipe_job(id)
+            .await?
+            .ok_or(sqlx::Error::RowNotFound)?;
+        info!("pipe {} scheduled job {} for {}", pipe_id, id, run_at);
+        Ok(job)
+    }
+
+    pub async fn list(
+        &self,
+        pipe_id: &str,
+        status: Option<PipeJobStatus>,
+    ) -> Result<Vec<PipeJob>, ScheduleError> {
+        Ok(self.db.get_pipe_jobs(pipe_id, status).await?)
+    }
+
+    /// Cancels a job that hasn't fired yet.
+    pub async fn cancel(&self, pipe_id: &str, id: i64) -> Result<PipeJob, ScheduleError> {
+        let not_found = || ScheduleError::NotFound {
+            pipe_id: pipe_id.to_string(),
+            id,
+        };
+        if !self.db.cancel_pipe_job(pipe_id, id).await? {
+            return Err(not_found());
+        }
+        self.db.get_pipe_job(id).await?.ok_or_else(not_found)
+    }
+
+    /// Runs the scheduler until the process exits.
+    pub fn spawn(self: &Arc<Self>) {
+        tokio::spawn(self.clone().run(power_state()));
+    }
+
+    /// Fires due jobs until the process exits. After a wake from `power` the next due
+    /// job is recomputed from the wall clock, the timer may not have advanced in sleep.
+    pub async fn run(self: Arc<Self>, power: &'static PowerState) {
+        power.register(SUBSYSTEM);
+        let mut power_events = power.subscribe();
+        match self.db.requeue_running_pipe_jobs().await {
+            Ok(0) => {}
+            Ok(requeued) => info!("requeued {} pipe jobs interrupted by a restart", requeued),
+            Err(e) => error!("failed to requeue interrupted pipe jobs: {}", e),
+        }
+
+        let mut woke = None;
+        let mut next_prune = Instant::now();
+        loop {
+            let fired = match self.fire_due_jobs().await {
+                Ok(fired) => fired,
+                Err(e) => {
+                    error!("failed to fire due pipe jobs: {}", e);
+                    0
+                }
+            };
+            if Instant::now() >= next_prune {
+                next_prune = Instant::now() + PRUNE_INTERVAL;
+                let before = (self.now)() - ChronoDuration::days(FINISHED_JOB_RETENTION_DAYS);
+                if let Err(e) = self.db.delete_finished_pipe_jobs(before).await {
+                    error!("failed to prune finished pipe jobs: {}", e);
+                }
+            }
+
+            let wait = self.next_wait().await;
+            if let Some(epoch) = woke.take() {
+                let outcome = format!("fired {} jobs, next check in {}s", fired, wait.as_secs());
+                power.report(SUBSYSTEM, epoch, SubsystemOutcome::Ok(outcome));
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep(wait) => {}
+                _ = self.wakeup.notified() => {}
+                event = power_events.recv() => {
+                    if let Ok(PowerEvent::Wake { epoch, .. }) = event {
+                        woke = Some(epoch);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Starts every job due now, returns how many.
+    pub async fn fire_due_jobs(&self) -> Result<usize, sqlx::Error> {
+        let jobs = self.db.claim_due_pipe_jobs((self.now)()).await?;
+        for job in &jobs {
+            let db = self.db.clone();
+            let runner = self.runner.clone();
+            let now = self.now.clone();
+            let wakeup = self.wakeup.clone();
+            let job = job.clone();
+            info!(
+                "firing job {} of pipe {}, attempt {}/{}",
+                job.id, job.pipe_id, job.attempts, job.max_attempts
+            );
+            tokio::spawn(async move {
+                let outcome = runner(job.clone()).await;
+                let saved = match outcome {
+                    Ok(()) => db.finish_pipe_job(job.id, PipeJobStatus::Done, None).await,
+                    Err(e) if job.attempts < job.max_attempts => {
+                        let run_at = now() + retry_delay(job.backoff_seconds, job.attempts);
+                        warn!(
+                            "job {} of pipe {} failed: {}, retrying at {}",
+                            job.id, job.pipe_id, e, run_at
+                        );
+                        let saved = db.retry_pipe_job(job.id, run_at, &e.to_string()).await;
+                        wakeup.notify_one();
+                        saved
+                    }
+                    Err(e) => {
+                        error!("job {} of pipe {} failed: {}", job.id, job.pipe_id, e);
+                        db.finish_pipe_job(job.id, PipeJobStatus::Failed, Some(&e.to_string()))
+                            .await
+                    }
+                };
+                if let Err(e) = saved {
+                    error!("failed to save the outcome of pipe job {}: {}", job.id, e);
+                }
+            });
+        }
+        Ok(jobs.len())
+    }
+
+    /// Until the soonest pending job is due, at most [`MAX_WAIT`].
+    async fn next_wait(&self) -> Duration {
+        match self.db.next_pipe_job_due().await {
+            Ok(Some(due)) => (due - (self.now)())
+                .to_std()
+                .unwrap_or(Duration::ZERO)
+                .min(MAX_WAIT),
+            Ok(None) => MAX_WAIT,
+            Err(e) => {
+                error!("failed to get the next pipe job: {}", e);
+                MAX_WAIT
+            }
+        }
+    }
+}
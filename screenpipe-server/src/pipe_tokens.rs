@@ -0,0 +1,169 @@
+//! Api tokens of running pipes and the scopes they hold the pipe to.
+//!
+//! Every pipe the server starts gets a token in [`PIPE_TOKEN_ENV`], sent back as
+//! `authorization: Bearer <token>`, and revoked when the pipe exits. A pipe whose
+//! pipe.json declares [`AGGREGATES_ONLY_SCOPE`] only reaches [`AGGREGATE_PATHS`] with its
+//! token: every other route, search, exports, frames and raw sql included, answers `403`.
+
+use crate::api_version::API_PREFIX;
+use crate::problem::{ApiError, ErrorCode};
+use axum::extract::{Request, State};
+use axum::http::{header, HeaderMap};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::Router;
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock, RwLock};
+
+/// Environment variable a pipe finds its api token in.
+pub const PIPE_TOKEN_ENV: &str = "SCREENPIPE_API_TOKEN";
+
+/// Scope restricting a pipe to the usage aggregates, no captured content.
+pub const AGGREGATES_ONLY_SCOPE: &str = "aggregates_only";
+
+/// Routes a pipe holding [`AGGREGATES_ONLY_SCOPE`] may call, relative to the version
+/// prefix. Matched exactly, anything else is refused.
+pub const AGGREGATE_PATHS: &[&str] = &["/usage/aggregate/apps", "/usage/aggregate/captures"];
+
+/// The pipe a token was issued to and the scopes its pipe.json declares.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PipeToken {
+    pub pipe_id: String,
+    pub scopes: Vec<String>,
+}
+
+impl PipeToken {
+    pub fn aggregates_only(&self) -> bool {
+        self.scopes
+            .iter()
+            .any(|scope| scope == AGGREGATES_ONLY_SCOPE)
+    }
+
+    /// Whether the token may call `path`, with or without the version prefix.
+    pub fn allows(&self, path: &str) -> bool {
+        if !self.aggregates_only() {
+            return true;
+        }
+        let path = match path.strip_prefix(API_PREFIX) {
+            Some(rest) if rest.starts_with('/') => rest,
+            _ => path,
+        };
+        AGGREGATE_PATHS.contains(&path)
+    }
+}
+
+/// Tokens of the pipes running now.
+#[derive(Debug, Clone, Default)]
+pub struct PipeTokens {
+    tokens: Arc<RwLock<HashMap<String, PipeToken>>>,
+}
+
+static PIPE_TOKENS: OnceLock<PipeTokens> = OnceLock::new();
+
+/// Process wide tokens, issued by the pipe manager and checked by the server's routes.
+pub fn pipe_tokens() -> PipeTokens {
+    PIPE_TOKENS.get_or_init(PipeTokens::default).clone()
+}
+
+impl PipeTokens {
+    /// A new token for `pipe_id`, held to `scopes`, revoked when the guard is dropped.
+    pub fn issue(&self, pipe_id: &str, scopes: &[String]) -> IssuedToken {
+        let token = uuid::Uuid::new_v4().simple().to_string();
+        let issued = PipeToken {
+            pipe_id: pipe_id.to_string(),
+            scopes: scopes.to_vec(),
+        };
+        self.tokens
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(token.clone(), issued);
+        IssuedToken {
+            tokens: self.clone(),
+            token,
+        }
+    }
+
+    pub fn get(&self, token: &str) -> Option<PipeToken> {
+        self.tokens
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(token)
+            .cloned()
+    }
+
+    fn revoke(&self, token: &str) {
+        self.tokens
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(token);
+    }
+}
+
+/// A token of a running pipe, revoked when dropped.
+#[derive(Debug)]
+pub struct IssuedToken {
+    tokens: PipeTokens,
+    token: String,
+}
+
+impl IssuedToken {
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    /// Environment passing the token to the pipe.
+    pub fn env(&self) -> Vec<(String, String)> {
+        vec![(PIPE_TOKEN_ENV.to_string(), self.token.clone())]
+    }
+}
+
+impl Drop for IssuedToken {
+    fn drop(&mut self) {
+        self.tokens.revoke(&self.token);
+    }
+}
+
+/// Holds requests carrying a pipe token to the scopes of its pipe. A token that isn't
+/// one of a running pipe is refused with `401`, a route its scopes don't reach with
+/// `403`. Requests without a token are left alone.
+pub fn with_pipe_scopes<S>(router: Router<S>, tokens: PipeTokens) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    router.layer(middleware::from_fn_with_state(tokens, pipe_scopes))
+}
+
+async fn pipe_scopes(State(tokens): State<PipeTokens>, request: Request, next: Next) -> Response {
+    let Some(token) = bearer_token(request.headers()) else {
+        return next.run(request).await;
+    };
+    let Some(pipe) = tokens.get(token) else {
+        return ApiError::new(
+            ErrorCode::Unauthorized,
+            "the api token isn't one of a running pipe",
+        )
+        .into_response();
+    };
+    let path = request.uri().path();
+    if !pipe.allows(path) {
+        return ApiError::new(
+            ErrorCode::Forbidden,
+            format!(
+                "pipe {} holds the {} scope, {} is out of it",
+                pipe.pipe_id, AGGREGATES_ONLY_SCOPE, path
+            ),
+        )
+        .with_extension("scopes", pipe.scopes.clone())
+        .into_response();
+    }
+    next.run(request).await
+}
+
+/// The token of an `authorization: Bearer <token>` header.
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.trim().split_once(' ')?;
+    scheme
+        .eq_ignore_ascii_case("bearer")
+        .then_some(token.trim())
+}
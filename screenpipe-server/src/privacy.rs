@@ -0,0 +1,320 @@
+//! What is kept about each app, for the privacy dashboard on `GET /privacy/summary`,
+//! and the actions taken from it.
+//!
+//! Counting frames by app on every request would scan the whole database, so
+//! [`run_rollups`] keeps per day and app counts up to date in the background and the
+//! summary only adds those up. Each run counts the rows captured since the previous
+//! one and rebuilds the days where counted rows were deleted. How far behind it is
+//! comes with the summary.
+//!
+//! From the dashboard an app can be ignored from now on, have everything captured of
+//! it deleted, or get its own retention policy through the retention settings.
+
+use crate::db_types::{AppRollup, RetentionKind, RollupProgress, RollupState};
+use crate::retention::{normalize_app_name, RetentionSettings};
+use crate::DatabaseManager;
+use anyhow::Result;
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::Duration;
+use tracing::{debug, error, warn};
+
+/// How often the rollups are brought up to date.
+pub const ROLLUP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Frames and transcriptions counted per transaction.
+pub const ROLLUP_BATCH: u32 = 5000;
+
+/// Rows captured more recently are counted by a later run, their text may still be
+/// on its way to the database.
+pub const ROLLUP_SETTLE_SECONDS: i64 = 120;
+
+/// Rows deleted per transaction when an app's data is deleted.
+const DELETE_BATCH: u32 = 1000;
+
+/// Apps whose text isn't stored from now on, read from
+/// `<screenpipe_dir>/ignored_apps.json`. Unlike `--ignored-windows` the list changes
+/// while recording, and it matches normalized app names instead of parts of names
+/// and titles.
+#[derive(Default)]
+pub struct IgnoredApps {
+    apps: RwLock<BTreeSet<String>>,
+}
+
+static IGNORED_APPS: OnceLock<IgnoredApps> = OnceLock::new();
+
+/// Process wide list checked by the vision pipeline for every window.
+pub fn ignored_apps() -> &'static IgnoredApps {
+    IGNORED_APPS.get_or_init(IgnoredApps::default)
+}
+
+impl IgnoredApps {
+    pub fn path(screenpipe_dir: &Path) -> PathBuf {
+        screenpipe_dir.join("ignored_apps.json")
+    }
+
+    /// Replaces the list with the one saved, an empty one if it is missing or invalid.
+    pub fn load(&self, screenpipe_dir: &Path) {
+        let path = Self::path(screenpipe_dir);
+        let apps = match std::fs::read_to_string(&path) {
+            Ok(content) => match serde_json::from_str::<Vec<String>>(&content) {
+                Ok(apps) => apps,
+                Err(e) => {
+                    warn!("invalid {}: {}, no app is ignored", path.display(), e);
+                    Vec::new()
+                }
+            },
+            Err(_) => Vec::new(),
+        };
+        *self.apps.write().unwrap() = apps
+            .iter()
+            .map(|app| normalize_app_name(app))
+            .filter(|app| !app.is_empty())
+            .collect();
+    }
+
+    pub fn contains(&self, app_name: &str) -> bool {
+        let apps = self.apps.read().unwrap();
+        !apps.is_empty() && apps.contains(&normalize_app_name(app_name))
+    }
+
+    pub fn list(&self) -> Vec<String> {
+        self.apps.read().unwrap().iter().cloned().collect()
+    }
+
+    /// Adds or removes an app and saves the list. Returns whether it changed.
+    pub fn set(&self, screenpipe_dir: &Path, app_name: &str, ignored: bool) -> Result<bool> {
+        let app_name = normalize_app_name(app_name);
+        let mut apps = self.apps.write().unwrap();
+        let changed = if ignored {
+            apps.insert(app_name)
+        } else {
+            apps.remove(&app_name)
+        };
+        if changed {
+            let list: Vec<&String> = apps.iter().collect();
+            std::fs::write(
+                Self::path(screenpipe_dir),
+                serde_json::to_string_pretty(&list)?,
+            )?;
+        }
+        Ok(changed)
+    }
+}
+
+static IGNORED_WINDOWS: OnceLock<Vec<String>> = OnceLock::new();
+
+/// Keeps the `--ignored-windows` of this run, for the checks made outside of capture.
+/// Only the first call has an effect.
+pub fn set_ignored_windows(patterns: &[String]) {
+    let patterns = patterns
+        .iter()
+        .map(|pattern| pattern.to_lowercase())
+        .filter(|pattern| !pattern.is_empty())
+        .collect();
+    let _ = IGNORED_WINDOWS.set(patterns);
+}
+
+/// The `--ignored-windows` of this run, lowercased. Like the vision pipeline they
+/// match any part of an app name or window title.
+pub fn ignored_windows() -> &'static [String] {
+    IGNORED_WINDOWS.get().map(Vec::as_slice).unwrap_or_default()
+}
+
+/// What is kept about one app.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AppPrivacy {
+    /// Normalized app name, see `normalize_app_name`
+    pub app_name: String,
+    /// Names the app was captured under
+    pub captured_as: Vec<String>,
+    /// A frame showing several apps counts for each of them
+    pub frames: i64,
+    pub ocr_characters: i64,
+    /// Transcribed audio heard while the app was focused
+    pub audio_minutes: f64,
+    pub oldest: DateTime<Utc>,
+    pub newest: DateTime<Utc>,
+    /// Days its content is kept, `None` keeps it forever
+    pub retention_days: Option<u32>,
+    /// Whether the app has its own retention policy instead of the global one
+    pub retention_overridden: bool,
+    pub ignored: bool,
+}
+
+/// How far behind the rollups the summary is read from are.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RollupFreshness {
+    /// End of the last rollup run, `None` before the first one
+    pub updated_at: Option<DateTime<Utc>>,
+    pub age_seconds: Option<i64>,
+    /// Captured since the last run, left out of the counts
+    pub pending_frames: i64,
+    pub pending_transcriptions: i64,
+    /// Days with deleted rows still counted
+    pub dirty_days: i64,
+}
+
+impl RollupFreshness {
+    pub fn new(state: &RollupState, now: DateTime<Utc>) -> Self {
+        Self {
+            updated_at: state.updated_at,
+            age_seconds: state.updated_at.map(|at| (now - at).num_seconds().max(0)),
+            pending_frames: state.pending_frames,
+            pending_transcriptions: state.pending_transcriptions,
+            dirty_days: state.dirty_days,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PrivacySummary {
+    pub generated_at: DateTime<Utc>,
+    /// Most frames first
+    pub apps: Vec<AppPrivacy>,
+    /// Everything on the ignore list, captured or not
+    pub ignored_apps: Vec<String>,
+    pub rollup: RollupFreshness,
+}
+
+/// Adds up the rollups of each app by normalized name.
+pub fn summarize(
+    rollups: &[AppRollup],
+    settings: &RetentionSettings,
+    ignored: &IgnoredApps,
+) -> Vec<AppPrivacy> {
+    let mut apps: BTreeMap<String, AppPrivacy> = BTreeMap::new();
+    for rollup in rollups {
+        let app_name = normalize_app_name(&rollup.app_name);
+        if app_name.is_empty() {
+            continue;
+        }
+        let app = apps.entry(app_name.clone()).or_insert_with(|| AppPrivacy {
+            retention_days: settings.policy_for(&app_name).max_age_days,
+            retention_overridden: settings.apps.contains_key(&app_name),
+            ignored: ignored.contains(&app_name),
+            app_name,
+            captured_as: Vec::new(),
+            frames: 0,
+            ocr_characters: 0,
+            audio_minutes: 0.0,
+            oldest: rollup.first_seen,
+            newest: rollup.last_seen,
+        });
+        app.captured_as.push(rollup.app_name.clone());
+        app.frames += rollup.frames;
+        app.ocr_characters += rollup.ocr_chars;
+        app.audio_minutes += rollup.audio_seconds / 60.0;
+        app.oldest = app.oldest.min(rollup.first_seen);
+        app.newest = app.newest.max(rollup.last_seen);
+    }
+
+    let mut apps: Vec<AppPrivacy> = apps.into_values().collect();
+    for app in &mut apps {
+        app.captured_as.sort();
+        app.audio_minutes = (app.audio_minutes * 100.0).round() / 100.0;
+    }
+    apps.sort_by(|a, b| {
+        b.frames
+            .cmp(&a.frames)
+            .then_with(|| a.app_name.cmp(&b.app_name))
+    });
+    apps
+}
+
+pub async fn summary(
+    db: &DatabaseManager,
+    settings: &RetentionSettings,
+    now: DateTime<Utc>,
+) -> Result<PrivacySummary> {
+    let rollups = db.get_app_rollups().await?;
+    let state = db.get_rollup_state().await?;
+    Ok(PrivacySummary {
+        generated_at: now,
+        apps: summarize(&rollups, settings, ignored_apps()),
+        ignored_apps: ignored_apps().list(),
+        rollup: RollupFreshness::new(&state, now),
+    })
+}
+
+/// Counts everything settled by `now` into the rollups, batch by batch.
+pub async fn update_rollups(db: &DatabaseManager, now: DateTime<Utc>) -> Result<RollupProgress> {
+    let settled_before = now - ChronoDuration::seconds(ROLLUP_SETTLE_SECONDS);
+    let mut total = RollupProgress::default();
+    loop {
+        let progress = db.update_app_rollups(settled_before, ROLLUP_BATCH).await?;
+        total.frames += progress.frames;
+        total.transcriptions += progress.transcriptions;
+        total.days_rebuilt += progress.days_rebuilt;
+        if progress.frames < ROLLUP_BATCH as i64 && progress.transcriptions < ROLLUP_BATCH as i64 {
+            return Ok(total);
+        }
+    }
+}
+
+pub async fn run_rollups(db: Arc<DatabaseManager>) {
+    let mut interval = tokio::time::interval(ROLLUP_INTERVAL);
+    loop {
+        interval.tick().await;
+        match update_rollups(&db, Utc::now()).await {
+            Ok(progress) if !progress.is_empty() => debug!("app rollups: {:?}", progress),
+            Ok(_) => {}
+            Err(e) => error!("app rollup run failed: {}", e),
+        }
+    }
+}
+
+/// What deleting an app's data removed.
+#[derive(Debug, Default, Serialize)]
+pub struct AppDeletion {
+    pub app_name: String,
+    pub frames: i64,
+    pub ui_rows: i64,
+    pub files_removed: usize,
+}
+
+/// Deletes every frame showing the app, with the text of the other apps on it since
+/// its image shows them together, and the app's ui text. Tagged content goes too.
+/// As with retention, a video chunk file is removed once none of its frames are left.
+/// Audio isn't captured from an app and is kept.
+pub async fn delete_app_data(db: &DatabaseManager, app_name: &str) -> Result<AppDeletion> {
+    let normalized = normalize_app_name(app_name);
+    let mut deletion = AppDeletion {
+        app_name: normalized.clone(),
+        ..Default::default()
+    };
+    let names: Vec<String> = db
+        .get_app_names()
+        .await?
+        .into_iter()
+        .filter(|name| normalize_app_name(name) == normalized)
+        .collect();
+    if names.is_empty() {
+        return Ok(deletion);
+    }
+
+    for kind in [RetentionKind::Frame, RetentionKind::Ui] {
+        loop {
+            // Deleted rows drop out of the next batch
+            let ids = db.get_app_row_ids(kind, &names, DELETE_BATCH).await?;
+            if ids.is_empty() {
+                break;
+            }
+            match kind {
+                RetentionKind::Frame => deletion.frames += ids.len() as i64,
+                _ => deletion.ui_rows += ids.len() as i64,
+            }
+            for file in db.delete_retention_rows(kind, &ids).await? {
+                match tokio::fs::remove_file(&file).await {
+                    Ok(()) => deletion.files_removed += 1,
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                    Err(e) => warn!("failed to remove {}: {}", file, e),
+                }
+            }
+        }
+    }
+    Ok(deletion)
+}
@@ -0,0 +1,428 @@
+use crate::pipe_content::PipeContentError;
+use crate::pipe_manager::PipeError;
+use crate::pipe_schedule::ScheduleError;
+use crate::search_query::QueryError;
+use crate::sessions::SessionError;
+use axum::body::to_bytes;
+use axum::extract::Request;
+use axum::http::{header, HeaderValue, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::Router;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::any::Any;
+use std::fmt;
+use tower_http::catch_panic::CatchPanicLayer;
+use tracing::{error, warn, Instrument};
+
+pub const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
+pub const TRACE_ID_HEADER: &str = "x-trace-id";
+
+/// Largest error body read back when wrapping a plain error response.
+const MAX_WRAPPED_BODY: usize = 64 * 1024;
+
+/// Machine readable error codes, documented in the api reference.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// Malformed body or query, or a value that failed validation
+    InvalidRequest,
+    /// The search query language couldn't parse `q`, see `span` and `hint`
+    InvalidQuery,
+    /// The request's api token isn't one of a running pipe
+    Unauthorized,
+    /// The pipe the api token belongs to holds a scope that doesn't reach this route
+    Forbidden,
+    /// No route or resource at this path
+    NotFound,
+    MethodNotAllowed,
+    Conflict,
+    PayloadTooLarge,
+    /// A per-pipe or per-client cap was hit, e.g. too many scheduled jobs
+    LimitExceeded,
+    PipeNotFound,
+    /// A pipe operation failed (bad config, failed start/stop/download)
+    PipeError,
+    DatabaseError,
+    /// A feature the request needs is turned off, e.g. the frame cache
+    FeatureDisabled,
+    Unavailable,
+    /// The media was moved to the archive directory and its volume isn't mounted
+    ArchiveUnavailable,
+    /// The media existed but was deleted, by retention or along with its text
+    NotRetained,
+    /// The content shows something kept private, e.g. an ignored app
+    Sensitive,
+    /// Unexpected failure, including handler panics
+    InternalError,
+}
+
+impl ErrorCode {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ErrorCode::InvalidRequest | ErrorCode::InvalidQuery => StatusCode::BAD_REQUEST,
+            ErrorCode::Unauthorized => StatusCode::UNAUTHORIZED,
+            ErrorCode::Forbidden => StatusCode::FORBIDDEN,
+            ErrorCode::NotFound | ErrorCode::PipeNotFound => StatusCode::NOT_FOUND,
+            ErrorCode::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+            ErrorCode::Conflict => StatusCode::CONFLICT,
+            ErrorCode::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            ErrorCode::LimitExceeded => StatusCode::TOO_MANY_REQUESTS,
+            ErrorCode::PipeError => StatusCode::BAD_REQUEST,
+            ErrorCode::FeatureDisabled | ErrorCode::Unavailable | ErrorCode::ArchiveUnavailable => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            ErrorCode::NotRetained => StatusCode::GONE,
+            ErrorCode::Sensitive => StatusCode::FORBIDDEN,
+            ErrorCode::DatabaseError | ErrorCode::InternalError => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            ErrorCode::InvalidRequest => "invalid request",
+            ErrorCode::InvalidQuery => "invalid search query",
+            ErrorCode::Unauthorized => "unauthorized",
+            ErrorCode::Forbidden => "forbidden",
+            ErrorCode::NotFound => "not found",
+            ErrorCode::MethodNotAllowed => "method not allowed",
+            ErrorCode::Conflict => "conflict",
+            ErrorCode::PayloadTooLarge => "payload too large",
+            ErrorCode::LimitExceeded => "limit exceeded",
+            ErrorCode::PipeNotFound => "pipe not found",
+            ErrorCode::PipeError => "pipe error",
+            ErrorCode::DatabaseError => "database error",
+            ErrorCode::FeatureDisabled => "feature disabled",
+            ErrorCode::Unavailable => "service unavailable",
+            ErrorCode::ArchiveUnavailable => "archive unavailable",
+            ErrorCode::NotRetained => "no longer retained",
+            ErrorCode::Sensitive => "sensitive content",
+            ErrorCode::InternalError => "internal error",
+        }
+    }
+
+    /// Code for an error response that didn't come with one.
+    fn from_status(status: StatusCode) -> Self {
+        match status {
+            StatusCode::NOT_FOUND => ErrorCode::NotFound,
+            StatusCode::METHOD_NOT_ALLOWED => ErrorCode::MethodNotAllowed,
+            StatusCode::CONFLICT => ErrorCode::Conflict,
+            StatusCode::PAYLOAD_TOO_LARGE => ErrorCode::PayloadTooLarge,
+            StatusCode::TOO_MANY_REQUESTS => ErrorCode::LimitExceeded,
+            StatusCode::SERVICE_UNAVAILABLE => ErrorCode::Unavailable,
+            s if s.is_client_error() => ErrorCode::InvalidRequest,
+            _ => ErrorCode::InternalError,
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let value = serde_json::to_value(self).map_err(|_| fmt::Error)?;
+        write!(f, "{}", value.as_str().unwrap_or_default())
+    }
+}
+
+/// RFC 7807 problem details. `error` and `success` mirror the older error
+/// shape so existing clients keep working.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Problem {
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: String,
+    pub status: u16,
+    pub detail: String,
+    pub code: ErrorCode,
+    pub trace_id: String,
+    pub error: String,
+    pub success: bool,
+    /// Extra members for some codes, e.g. `span` and `hint` for `invalid_query`
+    #[serde(flatten)]
+    pub extensions: Map<String, Value>,
+}
+
+/// Error returned by handlers, rendered as `application/problem+json`.
+#[derive(Debug, Clone)]
+pub struct ApiError {
+    pub code: ErrorCode,
+    pub status: StatusCode,
+    pub detail: String,
+    pub extensions: Map<String, Value>,
+}
+
+impl ApiError {
+    pub fn new(code: ErrorCode, detail: impl Into<String>) -> Self {
+        Self {
+            code,
+            status: code.status(),
+            detail: detail.into(),
+            extensions: Map::new(),
+        }
+    }
+
+    /// Adds a member to the problem body.
+    pub fn with_extension(mut self, key: &str, value: impl Into<Value>) -> Self {
+        self.extensions.insert(key.to_string(), value.into());
+        self
+    }
+
+    pub fn invalid_request(detail: impl Into<String>) -> Self {
+        Self::new(ErrorCode::InvalidRequest, detail)
+    }
+
+    pub fn not_found(detail: impl Into<String>) -> Self {
+        Self::new(ErrorCode::NotFound, detail)
+    }
+
+    pub fn internal(detail: impl Into<String>) -> Self {
+        Self::new(ErrorCode::InternalError, detail)
+    }
+
+    fn problem(&self, trace_id: &str) -> Problem {
+        Problem {
+            problem_type: format!("urn:screenpipe:error:{}", self.code),
+            title: self.code.title().to_string(),
+            status: self.status.as_u16(),
+            detail: self.detail.clone(),
+            code: self.code,
+            trace_id: trace_id.to_string(),
+            error: self.detail.clone(),
+            success: false,
+            extensions: self.extensions.clone(),
+        }
+    }
+
+    fn into_response_with_trace(self, trace_id: &str) -> Response {
+        let body = serde_json::to_vec(&self.problem(trace_id)).unwrap_or_default();
+        let mut response = (self.status, body).into_response();
+        response.headers_mut().insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static(PROBLEM_CONTENT_TYPE),
+        );
+        response
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.code, self.detail)
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        // `problem_details` renders it again once the trace id is known
+        let mut response = self.clone().into_response_with_trace("");
+        response.extensions_mut().insert(PendingProblem(self));
+        response
+    }
+}
+
+/// An `ApiError` waiting for the middleware to attach the trace id.
+#[derive(Debug, Clone)]
+struct PendingProblem(ApiError);
+
+impl From<sqlx::Error> for ApiError {
+    fn from(e: sqlx::Error) -> Self {
+        match e {
+            sqlx::Error::RowNotFound => ApiError::not_found("no matching record"),
+            e => ApiError::new(ErrorCode::DatabaseError, e.to_string()),
+        }
+    }
+}
+
+impl From<PipeError> for ApiError {
+    fn from(e: PipeError) -> Self {
+        match e {
+            PipeError::NotFound(_) => ApiError::new(ErrorCode::PipeNotFound, e.to_string()),
+            PipeError::InvalidConfig(_) => ApiError::invalid_request(e.to_string()),
+            PipeError::InvalidManifest { ref issues, .. } => {
+                let issues = json!(issues);
+                ApiError::new(ErrorCode::PipeError, e.to_string()).with_extension("issues", issues)
+            }
+            // Starting a pipe fails with a PipeError wrapped in anyhow
+            PipeError::Other(e) => match e.downcast::<PipeError>() {
+                Ok(e) => e.into(),
+                Err(e) => ApiError::new(ErrorCode::PipeError, e.to_string()),
+            },
+            e => ApiError::new(ErrorCode::PipeError, e.to_string()),
+        }
+    }
+}
+
+impl From<PipeContentError> for ApiError {
+    fn from(e: PipeContentError) -> Self {
+        match e {
+            PipeContentError::Invalid(_) | PipeContentError::UnknownType(_) => {
+                ApiError::invalid_request(e.to_string())
+            }
+            PipeContentError::Conflict(_) => ApiError::new(ErrorCode::Conflict, e.to_string()),
+            PipeContentError::Database(e) => e.into(),
+        }
+    }
+}
+
+impl From<ScheduleError> for ApiError {
+    fn from(e: ScheduleError) -> Self {
+        match e {
+            ScheduleError::Invalid(_) => ApiError::invalid_request(e.to_string()),
+            ScheduleError::TooManyJobs { .. } => {
+                ApiError::new(ErrorCode::LimitExceeded, e.to_string())
+            }
+            ScheduleError::NotFound { .. } => ApiError::not_found(e.to_string()),
+            ScheduleError::Database(e) => e.into(),
+        }
+    }
+}
+
+impl From<SessionError> for ApiError {
+    fn from(e: SessionError) -> Self {
+        match e {
+            SessionError::Invalid(_) => ApiError::invalid_request(e.to_string()),
+            SessionError::AlreadyOpen(ref open) => {
+                let open = serde_json::to_value(open).unwrap_or_default();
+                ApiError::new(ErrorCode::Conflict, e.to_string()).with_extension("session", open)
+            }
+            SessionError::NotOpen | SessionError::NotFound(_) => ApiError::not_found(e.to_string()),
+            SessionError::Database(e) => e.into(),
+        }
+    }
+}
+
+impl From<QueryError> for ApiError {
+    fn from(e: QueryError) -> Self {
+        ApiError::new(ErrorCode::InvalidQuery, e.message)
+            .with_extension("span", json!({"start": e.span.start, "end": e.span.end}))
+            .with_extension("hint", e.hint)
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(e: anyhow::Error) -> Self {
+        ApiError::internal(e.to_string())
+    }
+}
+
+/// Turns every error response into problem details and tags every request with a
+/// trace id, echoed in the `x-trace-id` header and recorded on the request span.
+/// Panics inside handlers come out as `internal_error` problems.
+pub fn with_problem_details<S>(router: Router<S>) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    router
+        .layer(CatchPanicLayer::custom(handle_panic))
+        .layer(middleware::from_fn(problem_details))
+}
+
+fn handle_panic(panic: Box<dyn Any + Send + 'static>) -> Response {
+    let message = panic
+        .downcast_ref::<String>()
+        .map(String::as_str)
+        .or_else(|| panic.downcast_ref::<&str>().copied())
+        .unwrap_or("unknown panic");
+    error!("handler panicked: {}", message);
+    ApiError::internal("the server hit an unexpected error while handling the request")
+        .into_response()
+}
+
+async fn problem_details(request: Request, next: Next) -> Response {
+    let trace_id = request
+        .headers()
+        .get(TRACE_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.is_empty() && v.len() <= 64)
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().simple().to_string());
+    let span = tracing::info_span!(
+        "request",
+        trace_id = %trace_id,
+        method = %request.method(),
+        path = %request.uri().path()
+    );
+
+    async move {
+        let mut response = next.run(request).await;
+        let failed = response.status().is_client_error() || response.status().is_server_error();
+
+        let mut response = if let Some(PendingProblem(api_error)) =
+            response.extensions_mut().remove::<PendingProblem>()
+        {
+            log_problem(&api_error);
+            api_error.into_response_with_trace(&trace_id)
+        } else if failed && !is_problem(&response) {
+            let api_error = wrap_error_response(response).await;
+            log_problem(&api_error);
+            api_error.into_response_with_trace(&trace_id)
+        } else {
+            response
+        };
+
+        if let Ok(value) = HeaderValue::from_str(&trace_id) {
+            response.headers_mut().insert(TRACE_ID_HEADER, value);
+        }
+        response
+    }
+    .instrument(span)
+    .await
+}
+
+pub(crate) fn is_problem(response: &Response) -> bool {
+    response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with(PROBLEM_CONTENT_TYPE))
+}
+
+/// Builds an `ApiError` out of a plain error response, e.g. an extractor rejection
+/// or a handler still returning `(StatusCode, Json({"error": ..}))`.
+async fn wrap_error_response(response: Response) -> ApiError {
+    let status = response.status();
+    let body = to_bytes(response.into_body(), MAX_WRAPPED_BODY)
+        .await
+        .unwrap_or_default();
+    let detail = match serde_json::from_slice::<Value>(&body) {
+        Ok(Value::Object(map)) => map
+            .get("error")
+            .or_else(|| map.get("message"))
+            .and_then(Value::as_str)
+            .map(str::to_string),
+        _ => None,
+    }
+    .unwrap_or_else(|| String::from_utf8_lossy(&body).trim().to_string());
+    let detail = if detail.is_empty() {
+        status
+            .canonical_reason()
+            .unwrap_or("request failed")
+            .to_lowercase()
+    } else {
+        detail
+    };
+
+    ApiError {
+        code: ErrorCode::from_status(status),
+        status,
+        detail,
+        extensions: Map::new(),
+    }
+}
+
+fn log_problem(api_error: &ApiError) {
+    if api_error.status.is_server_error() {
+        error!(
+            "request failed with {}: {}",
+            api_error.code, api_error.detail
+        );
+    } else {
+        warn!(
+            "request rejected with {}: {}",
+            api_error.code, api_error.detail
+        );
+    }
+}
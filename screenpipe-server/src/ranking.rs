@@ -0,0 +1,248 @@
+use crate::db_types::SearchResult;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// Rows fetched per content type before ranking. Pages are cut from this same pool,
+/// so pagination stays stable as long as `offset + limit` stays under it.
+pub const RANKING_CANDIDATE_POOL: u32 = 500;
+
+/// Tunables for relevance ranking, read from `<screenpipe_dir>/ranking.json`.
+/// Missing fields keep their default.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RankingWeights {
+    /// Share of the score coming from bm25 relevance
+    pub relevance: f64,
+    /// Share of the score coming from recency
+    pub recency: f64,
+    /// Age at which the recency part of the score has halved
+    pub recency_half_life_hours: f64,
+    /// How much low OCR confidence drags a hit down, 0 ignores confidence
+    pub ocr_confidence: f64,
+    pub ocr: f64,
+    pub audio: f64,
+    pub ui: f64,
+    pub document: f64,
+    pub pipe: f64,
+}
+
+impl Default for RankingWeights {
+    fn default() -> Self {
+        // Checked against the labeled queries in tests/ranking_test.rs
+        Self {
+            relevance: 0.7,
+            recency: 0.3,
+            recency_half_life_hours: 72.0,
+            ocr_confidence: 0.5,
+            ocr: 1.0,
+            audio: 0.9,
+            ui: 0.8,
+            document: 1.0,
+            pipe: 1.0,
+        }
+    }
+}
+
+impl RankingWeights {
+    pub fn path(screenpipe_dir: &Path) -> PathBuf {
+        screenpipe_dir.join("ranking.json")
+    }
+
+    /// Weights from the settings file, or the defaults if it is missing or invalid.
+    pub fn load(screenpipe_dir: &Path) -> Self {
+        let path = Self::path(screenpipe_dir);
+        let Ok(content) = std::fs::read_to_string(&path) else {
+            return Self::default();
+        };
+        match serde_json::from_str::<Self>(&content) {
+            Ok(weights) if weights.is_valid() => weights,
+            Ok(_) => {
+                warn!(
+                    "{} has negative or zero weights, using defaults",
+                    path.display()
+                );
+                Self::default()
+            }
+            Err(e) => {
+                warn!("failed to parse {}: {}, using defaults", path.display(), e);
+                Self::default()
+            }
+        }
+    }
+
+    fn is_valid(&self) -> bool {
+        let weights = [
+            self.relevance,
+            self.recency,
+            self.ocr_confidence,
+            self.ocr,
+            self.audio,
+            self.ui,
+            self.document,
+            self.pipe,
+        ];
+        weights.iter().all(|w| w.is_finite() && *w >= 0.0)
+            && self.relevance + self.recency > 0.0
+            && self.recency_half_life_hours > 0.0
+    }
+
+    fn kind_weight(&self, kind: HitKind) -> f64 {
+        match kind {
+            HitKind::Ocr => self.ocr,
+            HitKind::Audio => self.audio,
+            HitKind::Ui => self.ui,
+            HitKind::Document => self.document,
+            HitKind::Pipe => self.pipe,
+        }
+    }
+
+    /// Score in `[0, kind weight]`. `best_relevance` is the largest relevance among the
+    /// hits being ranked together, so the best match of a query always gets full relevance.
+    pub fn score(&self, hit: &Hit, best_relevance: f64, now: DateTime<Utc>) -> f64 {
+        let relevance = if best_relevance > 0.0 {
+            hit.relevance() / best_relevance
+        } else {
+            // No text query, or nothing bm25 could tell apart
+            1.0
+        };
+
+        let age_hours = (now - hit.timestamp).num_seconds().max(0) as f64 / 3600.0;
+        let recency = 0.5f64.powf(age_hours / self.recency_half_life_hours);
+
+        let confidence = match hit.confidence {
+            Some(c) => 1.0 - self.ocr_confidence.min(1.0) * (1.0 - c.clamp(0.0, 1.0)),
+            None => 1.0,
+        };
+
+        self.kind_weight(hit.kind)
+            * confidence
+            * (self.relevance * relevance + self.recency * recency)
+            / (self.relevance + self.recency)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum HitKind {
+    Ocr,
+    Audio,
+    Ui,
+    Document,
+    Pipe,
+}
+
+/// What the scorer needs to know about a search hit.
+#[derive(Debug, Clone)]
+pub struct Hit {
+    pub kind: HitKind,
+    /// Row id used to break ties, so equal scores always come out in the same order
+    pub row_id: (i64, i64),
+    /// FTS5 bm25 rank, lower is more relevant
+    pub bm25: f64,
+    pub timestamp: DateTime<Utc>,
+    /// OCR confidence in `[0, 1]`
+    pub confidence: Option<f64>,
+}
+
+impl Hit {
+    pub fn from_result(result: &SearchResult) -> Self {
+        match result {
+            SearchResult::OCR(ocr) => Hit {
+                kind: HitKind::Ocr,
+                row_id: (ocr.frame_id, 0),
+                bm25: ocr.rank,
+                timestamp: ocr.timestamp,
+                confidence: ocr_confidence(&ocr.text_json),
+            },
+            SearchResult::Audio(audio) => Hit {
+                kind: HitKind::Audio,
+                row_id: (audio.audio_chunk_id, audio.offset_index),
+                bm25: audio.rank,
+                timestamp: audio.timestamp,
+                confidence: None,
+            },
+            SearchResult::UI(ui) => Hit {
+                kind: HitKind::Ui,
+                row_id: (ui.id, 0),
+                bm25: ui.rank,
+                timestamp: ui.timestamp,
+                confidence: None,
+            },
+            SearchResult::Document(document) => Hit {
+                kind: HitKind::Document,
+                row_id: (document.id, 0),
+                bm25: document.rank,
+                timestamp: document.timestamp,
+                confidence: None,
+            },
+            SearchResult::Pipe(record) => Hit {
+                kind: HitKind::Pipe,
+                row_id: (record.id, 0),
+                bm25: record.rank,
+                timestamp: record.timestamp,
+                confidence: None,
+            },
+        }
+    }
+
+    fn relevance(&self) -> f64 {
+        // bm25 ranks are negative, the more negative the better
+        (-self.bm25).max(0.0)
+    }
+}
+
+/// Indices into `hits` from best to worst, with their scores. Ties are broken on
+/// content type then row id so pages never shuffle between requests.
+pub fn rank_hits(hits: &[Hit], weights: &RankingWeights, now: DateTime<Utc>) -> Vec<(usize, f64)> {
+    let best_relevance = hits.iter().map(Hit::relevance).fold(0.0, f64::max);
+    let mut ranked: Vec<(usize, f64)> = hits
+        .iter()
+        .enumerate()
+        .map(|(i, hit)| (i, weights.score(hit, best_relevance, now)))
+        .collect();
+
+    ranked.sort_by(|(a, score_a), (b, score_b)| {
+        score_b
+            .total_cmp(score_a)
+            .then_with(|| hits[*a].kind.cmp(&hits[*b].kind))
+            .then_with(|| hits[*b].row_id.cmp(&hits[*a].row_id))
+    });
+    ranked
+}
+
+/// Orders search results by score and returns each with its score.
+pub fn rank_results(
+    results: Vec<SearchResult>,
+    weights: &RankingWeights,
+    now: DateTime<Utc>,
+) -> Vec<(SearchResult, f64)> {
+    let hits: Vec<Hit> = results.iter().map(Hit::from_result).collect();
+    let order = rank_hits(&hits, weights, now);
+
+    let mut results: Vec<Option<SearchResult>> = results.into_iter().map(Some).collect();
+    order
+        .into_iter()
+        .filter_map(|(i, score)| results[i].take().map(|result| (result, score)))
+        .collect()
+}
+
+/// Average line confidence from an OCR `text_json`. Tesseract reports 0-100 under
+/// `confidence`, the native engines 0-1 under `conf`.
+pub fn ocr_confidence(text_json: &str) -> Option<f64> {
+    let lines: Vec<HashMap<String, String>> = serde_json::from_str(text_json).ok()?;
+    let values: Vec<f64> = lines
+        .iter()
+        .filter_map(|line| line.get("confidence").or_else(|| line.get("conf")))
+        .filter_map(|value| value.parse::<f64>().ok())
+        .filter(|value| value.is_finite() && *value >= 0.0)
+        .map(|value| if value > 1.0 { value / 100.0 } else { value })
+        .collect();
+
+    if values.is_empty() {
+        None
+    } else {
+        Some((values.iter().sum::<f64>() / values.len() as f64).min(1.0))
+    }
+}
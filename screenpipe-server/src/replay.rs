@@ -0,0 +1,682 @@
+//! Feeds previously captured frames and audio back through the capture pipeline.
+//!
+//! Replays write into a scratch database under `<data dir>/replay/<run>` so the
+//! real database is never touched. The source can either be a screenpipe
+//! database (`db.sqlite` or a data dir containing one) or an export directory:
+//!
+//! ```text
+//! <export>/frames/monitor_<id>/<unix ms>.png
+//! <export>/audio/<device name>/<unix ms>.<wav|mp3|mp4|...>
+//! ```
+//!
+//! Files whose stem is not a unix timestamp fall back to their modification time.
+//! Inputs are always ordered by (timestamp, path) so the same input set produces
+//! the same rows on every run.
+
+use crate::cli::{CliVadEngine, CliVadSensitivity};
+use crate::core::{drain_transcriptions, record_video};
+use crate::events::EventRecorder;
+use crate::sources::{AudioSource, FrameSource};
+use crate::video_utils::extract_frame;
+use crate::DatabaseManager;
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose, Engine as _};
+use chrono::{DateTime, TimeZone, Utc};
+use futures::future::{join_all, BoxFuture};
+use image::DynamicImage;
+use log::{debug, error, info, warn};
+use screenpipe_audio::vad_engine::{VadEngineEnum, VadSensitivity};
+use screenpipe_audio::{
+    create_whisper_channel, pcm_decode, AudioDevice, AudioInput, AudioTranscriptionEngine,
+};
+use screenpipe_core::latency::LatencyStamps;
+use screenpipe_core::Language;
+use screenpipe_vision::capture_screenshot_by_window::CapturedWindow;
+use screenpipe_vision::core::OcrTaskData;
+use screenpipe_vision::{process_ocr_task, CaptureResult, Frame, OcrEngine};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use sqlx::Row;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use tokio::sync::mpsc::Sender;
+
+/// How long the audio pipeline may stay silent after the last chunk was sent
+/// before the replay considers transcription finished.
+const AUDIO_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long frame inserts may stall before the replay gives up waiting for them.
+const FRAME_STALL_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Parses a playback speed such as `10x`, `0.5x` or `2`.
+pub fn parse_speed(s: &str) -> Result<f64, String> {
+    let value = s.trim().trim_end_matches(['x', 'X']);
+    match value.parse::<f64>() {
+        Ok(speed) if speed.is_finite() && speed > 0.0 => Ok(speed),
+        _ => Err(format!(
+            "invalid speed '{}', expected something like 10x",
+            s
+        )),
+    }
+}
+
+pub struct ReplayOptions {
+    pub from: PathBuf,
+    pub speed: f64,
+    pub preserve_timestamps: bool,
+    pub output_dir: PathBuf,
+    pub fps: f64,
+    pub video_chunk_duration: Duration,
+    pub ocr_engine: Arc<OcrEngine>,
+    pub audio_transcription_engine: Arc<AudioTranscriptionEngine>,
+    pub vad_engine: CliVadEngine,
+    pub vad_sensitivity: CliVadSensitivity,
+    pub deepgram_api_key: Option<String>,
+    pub languages: Vec<Language>,
+    pub use_pii_removal: bool,
+}
+
+#[derive(Debug, Clone)]
+enum FrameImage {
+    File(PathBuf),
+    Video {
+        file_path: String,
+        offset_index: i64,
+    },
+}
+
+#[derive(Debug, Clone)]
+struct ReplayWindow {
+    app_name: String,
+    window_name: String,
+    focused: bool,
+}
+
+#[derive(Debug, Clone)]
+struct ReplayFrame {
+    timestamp: DateTime<Utc>,
+    image: FrameImage,
+    windows: Vec<ReplayWindow>,
+}
+
+#[derive(Debug, Clone)]
+struct ReplayAudioChunk {
+    timestamp: DateTime<Utc>,
+    path: PathBuf,
+}
+
+#[derive(Default)]
+struct ReplaySet {
+    frames: BTreeMap<u32, Vec<ReplayFrame>>,
+    audio: BTreeMap<String, Vec<ReplayAudioChunk>>,
+}
+
+impl ReplaySet {
+    fn first_timestamp(&self) -> Option<DateTime<Utc>> {
+        let frames = self.frames.values().filter_map(|f| f.first());
+        let audio = self.audio.values().filter_map(|a| a.first());
+        frames
+            .map(|f| f.timestamp)
+            .chain(audio.map(|a| a.timestamp))
+            .min()
+    }
+}
+
+/// Maps replay wall-clock time back onto the original capture timeline.
+#[derive(Clone, Copy)]
+struct ReplayClock {
+    started: DateTime<Utc>,
+    origin: DateTime<Utc>,
+    speed: f64,
+}
+
+impl ReplayClock {
+    fn original_time(&self, replayed_at: DateTime<Utc>) -> DateTime<Utc> {
+        let elapsed_ms = (replayed_at - self.started).num_milliseconds() as f64;
+        self.origin + chrono::Duration::milliseconds((elapsed_ms * self.speed) as i64)
+    }
+}
+
+fn delay_between(previous: DateTime<Utc>, next: DateTime<Utc>, speed: f64) -> Duration {
+    let ms = (next - previous).num_milliseconds().max(0) as f64 / speed;
+    Duration::from_secs_f64(ms / 1000.0)
+}
+
+pub struct ReplayFrameSource {
+    frames: Vec<ReplayFrame>,
+    speed: f64,
+    preserve_timestamps: bool,
+    ocr_engine: Arc<OcrEngine>,
+    languages: Vec<Language>,
+    done: Arc<AtomicBool>,
+}
+
+impl ReplayFrameSource {
+    async fn load_image(image: &FrameImage) -> Result<DynamicImage> {
+        match image {
+            FrameImage::File(path) => Ok(image::open(path)?),
+            FrameImage::Video {
+                file_path,
+                offset_index,
+            } => {
+                let encoded = extract_frame(file_path, *offset_index).await?;
+                let bytes = general_purpose::STANDARD.decode(encoded)?;
+                Ok(image::load_from_memory(&bytes)?)
+            }
+        }
+    }
+
+    fn expected_rows(&self) -> usize {
+        self.frames.iter().map(|f| f.windows.len().max(1)).sum()
+    }
+}
+
+impl FrameSource for ReplayFrameSource {
+    fn start(self: Arc<Self>, result_tx: Sender<CaptureResult>) -> BoxFuture<'static, ()> {
+        Box::pin(async move {
+            let mut previous: Option<DateTime<Utc>> = None;
+            for (frame_number, frame) in self.frames.iter().enumerate() {
+                if let Some(previous) = previous {
+                    tokio::time::sleep(delay_between(previous, frame.timestamp, self.speed)).await;
+                }
+                previous = Some(frame.timestamp);
+
+                let image = Frame::new(match Self::load_image(&frame.image).await {
+                    Ok(image) => image,
+                    Err(e) => {
+                        // Keep the frame so row counts don't depend on decode flakiness
+                        error!("replay: failed to load frame {}: {}", frame_number, e);
+                        DynamicImage::new_rgb8(1, 1)
+                    }
+                });
+
+                let window_images = if frame.windows.is_empty() {
+                    vec![CapturedWindow {
+                        image: image.clone(),
+                        app_name: "replay".to_string(),
+                        window_name: format!("frame {}", frame_number),
+                        is_focused: true,
+                    }]
+                } else {
+                    frame
+                        .windows
+                        .iter()
+                        .map(|w| CapturedWindow {
+                            image: image.clone(),
+                            app_name: w.app_name.clone(),
+                            window_name: w.window_name.clone(),
+                            is_focused: w.focused,
+                        })
+                        .collect()
+                };
+
+                let timestamp = Instant::now();
+                let task = OcrTaskData {
+                    image,
+                    window_images,
+                    frame_number: frame_number as u64,
+                    timestamp,
+                    result_tx: result_tx.clone(),
+                    latency: LatencyStamps::captured(timestamp),
+                    window_layout: None,
+                };
+                if let Err(e) =
+                    process_ocr_task(task, &self.ocr_engine, self.languages.clone()).await
+                {
+                    error!("replay: ocr failed for frame {}: {}", frame_number, e);
+                }
+            }
+            self.done.store(true, Ordering::SeqCst);
+        })
+    }
+
+    fn lossless(&self) -> bool {
+        true
+    }
+
+    fn captured_at(&self, frame_number: u64) -> Option<DateTime<Utc>> {
+        if !self.preserve_timestamps {
+            return None;
+        }
+        self.frames.get(frame_number as usize).map(|f| f.timestamp)
+    }
+}
+
+pub struct ReplayAudioSource {
+    device: Arc<AudioDevice>,
+    chunks: Vec<ReplayAudioChunk>,
+    speed: f64,
+}
+
+impl AudioSource for ReplayAudioSource {
+    fn device(&self) -> Arc<AudioDevice> {
+        Arc::clone(&self.device)
+    }
+
+    fn start(
+        self: Arc<Self>,
+        whisper_sender: crossbeam::channel::Sender<AudioInput>,
+        is_running: Arc<AtomicBool>,
+    ) -> BoxFuture<'static, ()> {
+        Box::pin(async move {
+            let mut previous: Option<DateTime<Utc>> = None;
+            for chunk in &self.chunks {
+                if !is_running.load(Ordering::Relaxed) {
+                    break;
+                }
+                if let Some(previous) = previous {
+                    tokio::time::sleep(delay_between(previous, chunk.timestamp, self.speed)).await;
+                }
+                previous = Some(chunk.timestamp);
+
+                let (data, sample_rate) = match pcm_decode(&chunk.path) {
+                    Ok(decoded) => decoded,
+                    Err(e) => {
+                        error!("replay: failed to decode {:?}: {}", chunk.path, e);
+                        continue;
+                    }
+                };
+                let input = AudioInput {
+                    data: Arc::new(data),
+                    sample_rate,
+                    channels: 1,
+                    device: self.device(),
+                    latency: LatencyStamps::now(),
+                };
+                if whisper_sender.send(input).is_err() {
+                    warn!("replay: whisper channel closed, stopping {}", self.device);
+                    break;
+                }
+            }
+        })
+    }
+}
+
+/// Replays `options.from` and returns the path of the scratch database.
+pub async fn run_replay(options: ReplayOptions) -> Result<PathBuf> {
+    let set = load_replay_set(&options.from).await?;
+    let frame_count: usize = set.frames.values().map(Vec::len).sum();
+    let audio_count: usize = set.audio.values().map(Vec::len).sum();
+    if frame_count == 0 && audio_count == 0 {
+        return Err(anyhow!("nothing to replay in {}", options.from.display()));
+    }
+
+    let run_dir = options
+        .output_dir
+        .join(Utc::now().format("%Y-%m-%d_%H-%M-%S").to_string());
+    let media_dir = run_dir.join("data");
+    std::fs::create_dir_all(&media_dir)?;
+    let db_path = run_dir.join("db.sqlite");
+    let db = Arc::new(DatabaseManager::new(&db_path.to_string_lossy()).await?);
+    let output_path = Arc::new(media_dir.to_string_lossy().into_owned());
+
+    info!(
+        "replaying {} frames and {} audio chunks from {} at {}x into {}",
+        frame_count,
+        audio_count,
+        options.from.display(),
+        options.speed,
+        db_path.display()
+    );
+
+    let clock = ReplayClock {
+        started: Utc::now(),
+        origin: set.first_timestamp().unwrap_or_else(Utc::now),
+        speed: options.speed,
+    };
+
+    let video = replay_frames(&options, &set, &db, &output_path);
+    let audio = replay_audio(&options, &set, &db, &output_path);
+    let (video, audio) = tokio::join!(video, audio);
+    video?;
+    audio?;
+
+    if options.preserve_timestamps && audio_count > 0 {
+        remap_audio_timestamps(&db, clock).await?;
+    }
+
+    info!("replay finished, scratch database at {}", db_path.display());
+    Ok(db_path)
+}
+
+async fn replay_frames(
+    options: &ReplayOptions,
+    set: &ReplaySet,
+    db: &Arc<DatabaseManager>,
+    output_path: &Arc<String>,
+) -> Result<()> {
+    let mut tasks = Vec::new();
+    for (&monitor_id, frames) in &set.frames {
+        let done = Arc::new(AtomicBool::new(false));
+        let source = Arc::new(ReplayFrameSource {
+            frames: frames.clone(),
+            speed: options.speed,
+            preserve_timestamps: options.preserve_timestamps,
+            ocr_engine: Arc::clone(&options.ocr_engine),
+            languages: options.languages.clone(),
+            done: Arc::clone(&done),
+        });
+        let expected_rows = source.expected_rows();
+        let is_running = Arc::new(AtomicBool::new(true));
+
+        let recorder = tokio::spawn(record_video(
+            Arc::clone(db),
+            Arc::clone(output_path),
+            options.fps,
+            Arc::clone(&is_running),
+            Arc::clone(&options.ocr_engine),
+            monitor_id,
+            options.use_pii_removal,
+            options.video_chunk_duration,
+            source,
+            None,
+            None,
+            Arc::new(EventRecorder::new(Arc::clone(db))),
+        ));
+
+        let db = Arc::clone(db);
+        tasks.push(async move {
+            wait_for_frames(&db, monitor_id, expected_rows, &done).await;
+            is_running.store(false, Ordering::SeqCst);
+            match recorder.await {
+                Ok(Err(e)) => error!("replay: video recording failed: {}", e),
+                Err(e) => error!("replay: video task panicked: {}", e),
+                Ok(Ok(())) => {}
+            }
+        });
+    }
+    join_all(tasks).await;
+    Ok(())
+}
+
+async fn wait_for_frames(
+    db: &DatabaseManager,
+    monitor_id: u32,
+    expected_rows: usize,
+    done: &AtomicBool,
+) {
+    let device_name = format!("monitor_{}", monitor_id);
+    let mut last_count = 0;
+    let mut last_progress = Instant::now();
+    loop {
+        let count: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM frames f JOIN video_chunks vc ON f.video_chunk_id = vc.id WHERE vc.device_name = ?1",
+        )
+        .bind(&device_name)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap_or(0);
+        let count = count as usize;
+
+        if count >= expected_rows {
+            return;
+        }
+        if count != last_count || !done.load(Ordering::SeqCst) {
+            last_count = count;
+            last_progress = Instant::now();
+        } else if last_progress.elapsed() > FRAME_STALL_TIMEOUT {
+            warn!(
+                "replay: {} stalled at {}/{} frames, stopping",
+                device_name, count, expected_rows
+            );
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+}
+
+async fn replay_audio(
+    options: &ReplayOptions,
+    set: &ReplaySet,
+    db: &Arc<DatabaseManager>,
+    output_path: &Arc<String>,
+) -> Result<()> {
+    if set.audio.is_empty() {
+        return Ok(());
+    }
+
+    let (whisper_sender, whisper_receiver, whisper_shutdown_flag) = create_whisper_channel(
+        Arc::clone(&options.audio_transcription_engine),
+        VadEngineEnum::from(options.vad_engine.clone()),
+        options.deepgram_api_key.clone(),
+        &PathBuf::from(output_path.as_ref()),
+        VadSensitivity::from(options.vad_sensitivity.clone()),
+        options.languages.clone(),
+    )
+    .await?;
+
+    let is_running = Arc::new(AtomicBool::new(true));
+    let sources = set
+        .audio
+        .iter()
+        .map(|(device, chunks)| {
+            let device = AudioDevice::from_name(device)
+                .unwrap_or_else(|_| AudioDevice::from_name("replay (input)").unwrap());
+            let source = Arc::new(ReplayAudioSource {
+                device: Arc::new(device),
+                chunks: chunks.clone(),
+                speed: options.speed,
+            });
+            tokio::spawn(source.start(whisper_sender.clone(), Arc::clone(&is_running)))
+        })
+        .collect::<Vec<_>>();
+
+    let mut previous_transcript = String::new();
+    let mut previous_transcript_id = None;
+    let mut last_activity = Instant::now();
+    loop {
+        let processed = drain_transcriptions(
+            db,
+            &whisper_receiver,
+            &options.audio_transcription_engine,
+            &mut previous_transcript,
+            &mut previous_transcript_id,
+        )
+        .await;
+
+        let sending = sources.iter().any(|s| !s.is_finished()) || !whisper_sender.is_empty();
+        if processed > 0 || sending {
+            last_activity = Instant::now();
+        } else if last_activity.elapsed() > AUDIO_IDLE_TIMEOUT {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    whisper_shutdown_flag.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Audio rows are stamped when transcription finishes, so they are moved back
+/// onto the original timeline after the fact.
+async fn remap_audio_timestamps(db: &DatabaseManager, clock: ReplayClock) -> Result<()> {
+    for table in ["audio_chunks", "audio_transcriptions"] {
+        let rows = sqlx::query(&format!("SELECT id, timestamp FROM {}", table))
+            .fetch_all(&db.pool)
+            .await?;
+        for row in rows {
+            let id: i64 = row.try_get("id")?;
+            let timestamp: DateTime<Utc> = row.try_get("timestamp")?;
+            sqlx::query(&format!(
+                "UPDATE {} SET timestamp = ?1 WHERE id = ?2",
+                table
+            ))
+            .bind(clock.original_time(timestamp))
+            .bind(id)
+            .execute(&db.pool)
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+async fn load_replay_set(from: &Path) -> Result<ReplaySet> {
+    if from.is_file() {
+        return load_from_db(from).await;
+    }
+    let db_path = from.join("db.sqlite");
+    if db_path.is_file() {
+        return load_from_db(&db_path).await;
+    }
+    if from.is_dir() {
+        return load_from_export(from);
+    }
+    Err(anyhow!("replay source not found: {}", from.display()))
+}
+
+/// Reads frames and audio chunks from an existing database without modifying it.
+async fn load_from_db(path: &Path) -> Result<ReplaySet> {
+    let options =
+        SqliteConnectOptions::from_str(&format!("sqlite:{}", path.display()))?.read_only(true);
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect_with(options)
+        .await?;
+
+    let mut set = ReplaySet::default();
+
+    let frames = sqlx::query(
+        r#"
+        SELECT f.timestamp, f.offset_index, vc.file_path, vc.device_name,
+               o.app_name, o.window_name, o.focused
+        FROM frames f
+        JOIN video_chunks vc ON f.video_chunk_id = vc.id
+        LEFT JOIN ocr_text o ON o.frame_id = f.id
+        -- Frames captured without video have no image to replay
+        WHERE vc.file_path != ''
+        ORDER BY f.timestamp, f.id
+        "#,
+    )
+    .fetch_all(&pool)
+    .await?;
+    for row in frames {
+        let device_name: String = row.try_get("device_name")?;
+        let monitor_id = device_name
+            .trim_start_matches("monitor_")
+            .parse::<u32>()
+            .unwrap_or(0);
+        let window = row
+            .try_get::<Option<String>, _>("app_name")?
+            .map(|app_name| -> Result<ReplayWindow> {
+                Ok(ReplayWindow {
+                    app_name,
+                    window_name: row
+                        .try_get::<Option<String>, _>("window_name")?
+                        .unwrap_or_default(),
+                    focused: row.try_get::<Option<bool>, _>("focused")?.unwrap_or(false),
+                })
+            })
+            .transpose()?;
+        set.frames.entry(monitor_id).or_default().push(ReplayFrame {
+            timestamp: row.try_get("timestamp")?,
+            image: FrameImage::Video {
+                file_path: row.try_get("file_path")?,
+                offset_index: row.try_get("offset_index")?,
+            },
+            windows: window.into_iter().collect(),
+        });
+    }
+
+    let chunks = sqlx::query(
+        r#"
+        SELECT ac.file_path, ac.timestamp, MIN(at.device) AS device, MIN(at.is_input_device) AS is_input
+        FROM audio_chunks ac
+        LEFT JOIN audio_transcriptions at ON at.audio_chunk_id = ac.id
+        GROUP BY ac.id
+        ORDER BY ac.timestamp, ac.id
+        "#,
+    )
+    .fetch_all(&pool)
+    .await?;
+    for row in chunks {
+        let device = match row.try_get::<Option<String>, _>("device")? {
+            Some(name) => {
+                let is_input = row.try_get::<Option<bool>, _>("is_input")?.unwrap_or(true);
+                format!("{} ({})", name, if is_input { "input" } else { "output" })
+            }
+            None => "replay (input)".to_string(),
+        };
+        let file_path: String = row.try_get("file_path")?;
+        set.audio.entry(device).or_default().push(ReplayAudioChunk {
+            timestamp: row.try_get("timestamp")?,
+            path: PathBuf::from(file_path),
+        });
+    }
+
+    pool.close().await;
+    Ok(set)
+}
+
+fn load_from_export(dir: &Path) -> Result<ReplaySet> {
+    let mut set = ReplaySet::default();
+
+    for (group, path, timestamp) in list_timestamped_files(&dir.join("frames"))? {
+        let monitor_id = group
+            .as_deref()
+            .map(|g| g.trim_start_matches("monitor_"))
+            .and_then(|g| g.parse::<u32>().ok())
+            .unwrap_or(0);
+        set.frames.entry(monitor_id).or_default().push(ReplayFrame {
+            timestamp,
+            image: FrameImage::File(path),
+            windows: Vec::new(),
+        });
+    }
+
+    for (group, path, timestamp) in list_timestamped_files(&dir.join("audio"))? {
+        let device = group.unwrap_or_else(|| "replay (input)".to_string());
+        set.audio
+            .entry(device)
+            .or_default()
+            .push(ReplayAudioChunk { timestamp, path });
+    }
+
+    Ok(set)
+}
+
+/// Lists files directly in `root` (no group) and one level down (grouped by
+/// directory name), sorted by (timestamp, path).
+fn list_timestamped_files(root: &Path) -> Result<Vec<(Option<String>, PathBuf, DateTime<Utc>)>> {
+    let mut files = Vec::new();
+    if !root.is_dir() {
+        return Ok(files);
+    }
+    for entry in std::fs::read_dir(root)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            let group = path.file_name().map(|n| n.to_string_lossy().into_owned());
+            for inner in std::fs::read_dir(&path)? {
+                let inner = inner?.path();
+                if inner.is_file() {
+                    files.push((group.clone(), file_timestamp(&inner)?, inner));
+                }
+            }
+        } else if path.is_file() {
+            files.push((None, file_timestamp(&path)?, path));
+        }
+    }
+    files.sort_by(|a, b| (a.1, &a.2).cmp(&(b.1, &b.2)));
+    debug!("replay: found {} files in {}", files.len(), root.display());
+    Ok(files
+        .into_iter()
+        .map(|(group, timestamp, path)| (group, path, timestamp))
+        .collect())
+}
+
+fn file_timestamp(path: &Path) -> Result<DateTime<Utc>> {
+    let from_name = path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .and_then(|s| s.parse::<i64>().ok())
+        .and_then(|ms| Utc.timestamp_millis_opt(ms).single());
+    if let Some(timestamp) = from_name {
+        return Ok(timestamp);
+    }
+    let modified = std::fs::metadata(path)?.modified()?;
+    let ms = modified.duration_since(UNIX_EPOCH)?.as_millis() as i64;
+    Utc.timestamp_millis_opt(ms)
+        .single()
+        .ok_or_else(|| anyhow!("invalid modification time for {}", path.display()))
+}
@@ -0,0 +1,535 @@
+use crate::db_types::{RetentionKind, RetentionRow};
+use crate::storage::MediaVolume;
+use crate::DatabaseManager;
+use anyhow::Result;
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tracing::{error, info, warn};
+
+/// How often old content is pruned.
+pub const PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Rows read per query while pruning, deletes happen batch by batch.
+const PRUNE_BATCH: u32 = 1000;
+
+const BYTES_PER_GB: f64 = 1024.0 * 1024.0 * 1024.0;
+
+/// How long content is kept.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionPolicy {
+    /// Days content is kept, `None` keeps it forever
+    pub max_age_days: Option<u32>,
+}
+
+impl RetentionPolicy {
+    pub fn days(max_age_days: u32) -> Self {
+        Self {
+            max_age_days: Some(max_age_days),
+        }
+    }
+
+    /// Content captured before this is past the policy.
+    pub fn cutoff(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.max_age_days
+            .map(|days| now - ChronoDuration::days(days as i64))
+    }
+}
+
+/// What the pruning job does with one row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetentionDecision {
+    Keep,
+    /// Past its policy, kept because it is tagged
+    KeepTagged,
+    Delete,
+}
+
+/// Retention settings, read from `<screenpipe_dir>/retention.json`.
+///
+/// Rules, strongest first:
+/// 1. content tagged by hand is never pruned, whatever the policies say
+/// 2. a class a tag rule put the content in replaces the other policies, see
+///    [`crate::tag_rules`]. Classes missing here are ignored
+/// 3. an app override replaces the global policy for that app's frames and ui text
+/// 4. the global policy covers everything else, including audio which has no app
+///
+/// A frame showing several apps is kept as long as any of them keeps it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionSettings {
+    pub global: RetentionPolicy,
+    /// Overrides keyed by app name, see `normalize_app_name`
+    pub apps: BTreeMap<String, RetentionPolicy>,
+    /// Only used to warn when the policies are projected to outgrow it
+    pub disk_budget_gb: Option<f64>,
+    /// Policies tag rules refer to with `retention_class`, by name
+    pub classes: BTreeMap<String, RetentionPolicy>,
+}
+
+/// App names as overrides are matched: trimmed, lowercased and without `.exe` or `.app`.
+pub fn normalize_app_name(name: &str) -> String {
+    let name = name.trim().to_lowercase();
+    let name = name
+        .strip_suffix(".exe")
+        .or_else(|| name.strip_suffix(".app"))
+        .unwrap_or(&name);
+    name.trim().to_string()
+}
+
+impl RetentionSettings {
+    pub fn path(screenpipe_dir: &Path) -> PathBuf {
+        screenpipe_dir.join("retention.json")
+    }
+
+    /// Settings from the settings file, or keep-everything if it is missing or invalid.
+    pub fn load(screenpipe_dir: &Path) -> Self {
+        let path = Self::path(screenpipe_dir);
+        let Ok(content) = std::fs::read_to_string(&path) else {
+            return Self::default();
+        };
+        match serde_json::from_str::<Self>(&content)
+            .map_err(|e| e.to_string())
+            .and_then(Self::validated)
+        {
+            Ok(settings) => settings,
+            Err(e) => {
+                warn!("invalid {}: {}, keeping all content", path.display(), e);
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&self, screenpipe_dir: &Path) -> Result<()> {
+        std::fs::write(
+            Self::path(screenpipe_dir),
+            serde_json::to_string_pretty(self)?,
+        )?;
+        Ok(())
+    }
+
+    /// Normalizes the override names and checks the values.
+    pub fn validated(self) -> Result<Self, String> {
+        if self
+            .classes
+            .keys()
+            .any(|class| class.trim() != class || class.is_empty())
+        {
+            return Err("class names can't be empty or start or end with spaces".to_string());
+        }
+        let mut apps = BTreeMap::new();
+        for (name, policy) in self.apps {
+            let normalized = normalize_app_name(&name);
+            if normalized.is_empty() {
+                return Err(format!("'{}' is not an app name", name));
+            }
+            if apps.insert(normalized.clone(), policy).is_some() {
+                return Err(format!("more than one override for '{}'", normalized));
+            }
+        }
+        let settings = Self { apps, ..self };
+
+        let policies = std::iter::once(("global", &settings.global))
+            .chain(settings.apps.iter().map(|(name, p)| (name.as_str(), p)))
+            .chain(settings.classes.iter().map(|(name, p)| (name.as_str(), p)));
+        for (name, policy) in policies {
+            if policy.max_age_days == Some(0) {
+                return Err(format!(
+                    "{}: max_age_days must be at least 1, leave it out to keep forever",
+                    name
+                ));
+            }
+        }
+        if let Some(budget) = settings.disk_budget_gb {
+            if !budget.is_finite() || budget <= 0.0 {
+                return Err("disk_budget_gb must be a positive number".to_string());
+            }
+        }
+        Ok(settings)
+    }
+
+    pub fn policy_for(&self, app_name: &str) -> RetentionPolicy {
+        self.apps
+            .get(&normalize_app_name(app_name))
+            .copied()
+            .unwrap_or(self.global)
+    }
+
+    /// Rows newer than this are kept under every policy. `None` when nothing expires.
+    pub fn earliest_cutoff(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        std::iter::once(&self.global)
+            .chain(self.apps.values())
+            .chain(self.classes.values())
+            .filter_map(|policy| policy.cutoff(now))
+            .max()
+    }
+
+    pub fn decide(&self, row: &RetentionRow, now: DateTime<Utc>) -> RetentionDecision {
+        let class = row
+            .retention_class
+            .as_ref()
+            .and_then(|class| self.classes.get(class));
+        let cutoff = match class {
+            Some(policy) => policy.cutoff(now),
+            // The longest policy among the row's apps wins, keeping forever beats any age
+            None => row
+                .apps()
+                .map(|app| self.policy_for(app).cutoff(now))
+                .reduce(|a, b| a.zip(b).map(|(a, b)| a.min(b)))
+                .unwrap_or_else(|| self.global.cutoff(now)),
+        };
+        match cutoff {
+            Some(cutoff) if row.timestamp < cutoff && row.tagged => RetentionDecision::KeepTagged,
+            Some(cutoff) if row.timestamp < cutoff => RetentionDecision::Delete,
+            _ => RetentionDecision::Keep,
+        }
+    }
+
+    /// Warns when the policies are projected to outgrow the disk budget, assuming each
+    /// app keeps being captured at the rate seen so far.
+    pub fn budget_warnings(&self, usage: &[AppRetention], now: DateTime<Utc>) -> Vec<String> {
+        let Some(budget_gb) = self.disk_budget_gb else {
+            return Vec::new();
+        };
+
+        let mut projected = 0.0;
+        let mut forever = Vec::new();
+        for app in usage {
+            let Some(oldest) = app.oldest.filter(|_| app.bytes > 0) else {
+                continue;
+            };
+            let Some(days) = app.max_age_days else {
+                forever.push(app.label());
+                continue;
+            };
+            let observed_days = ((now - oldest).num_seconds() as f64 / 86400.0).max(1.0);
+            projected += app.bytes as f64 / observed_days * days as f64;
+        }
+
+        let mut warnings = Vec::new();
+        let projected_gb = projected / BYTES_PER_GB;
+        if projected_gb > budget_gb {
+            warnings.push(format!(
+                "the policies are projected to keep {:.1} GB, over the {:.1} GB disk budget",
+                projected_gb, budget_gb
+            ));
+        }
+        if !forever.is_empty() {
+            warnings.push(format!(
+                "{} kept forever, disk usage will keep growing past the {:.1} GB budget",
+                forever.join(", "),
+                budget_gb
+            ));
+        }
+        warnings
+    }
+}
+
+/// Disk usage and pending deletions of one app, or of audio.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct AppRetention {
+    /// Normalized app name, empty for frames without OCR text and for audio
+    pub app_name: String,
+    pub max_age_days: Option<u32>,
+    /// Whether the app has its own policy instead of the global one
+    pub overridden: bool,
+    /// Media bytes, a chunk shared by several apps is split by frame count
+    pub bytes: u64,
+    pub rows: i64,
+    pub oldest: Option<DateTime<Utc>>,
+    /// Rows past their policy that the next run deletes
+    pub scheduled_rows: i64,
+    pub scheduled_bytes: u64,
+    /// Rows past their policy kept because they are tagged
+    pub tagged_rows: i64,
+}
+
+impl AppRetention {
+    fn label(&self) -> String {
+        if self.app_name.is_empty() {
+            "content without an app".to_string()
+        } else {
+            self.app_name.clone()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RetentionReport {
+    pub generated_at: DateTime<Utc>,
+    pub settings: RetentionSettings,
+    /// Frames and ui text by app, largest first
+    pub apps: Vec<AppRetention>,
+    pub audio: AppRetention,
+    pub total_bytes: u64,
+    pub warnings: Vec<String>,
+}
+
+/// What one pruning run deleted.
+#[derive(Debug, Default, Serialize)]
+pub struct PruneSummary {
+    /// Frames and ui rows deleted by app
+    pub apps: BTreeMap<String, i64>,
+    pub audio: i64,
+    /// Rows past their policy kept because they are tagged
+    pub tagged_kept: i64,
+    pub files_removed: usize,
+}
+
+/// Holds the retention settings and prunes content that outlived them.
+pub struct RetentionManager {
+    db: Arc<DatabaseManager>,
+    screenpipe_dir: PathBuf,
+    media_volume: Option<Arc<MediaVolume>>,
+    settings: RwLock<RetentionSettings>,
+}
+
+impl RetentionManager {
+    pub fn new(
+        db: Arc<DatabaseManager>,
+        screenpipe_dir: PathBuf,
+        media_volume: Option<Arc<MediaVolume>>,
+    ) -> Self {
+        let settings = RetentionSettings::load(&screenpipe_dir);
+        Self {
+            db,
+            screenpipe_dir,
+            media_volume,
+            settings: RwLock::new(settings),
+        }
+    }
+
+    pub async fn settings(&self) -> RetentionSettings {
+        self.settings.read().await.clone()
+    }
+
+    /// Saves validated settings and returns the disk budget warnings for them. They
+    /// apply from the next pruning run.
+    pub async fn update(
+        &self,
+        settings: RetentionSettings,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<String>> {
+        let (apps, audio) = self.usage(&settings, now).await?;
+        let mut usage = apps;
+        usage.push(audio);
+        let warnings = settings.budget_warnings(&usage, now);
+
+        settings.save(&self.screenpipe_dir)?;
+        *self.settings.write().await = settings;
+        Ok(warnings)
+    }
+
+    pub async fn run(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+        loop {
+            interval.tick().await;
+            match self.prune(Utc::now()).await {
+                Ok(summary) => match serde_json::to_string(&summary) {
+                    Ok(json) => info!("retention: {}", json),
+                    Err(e) => error!("failed to serialize retention summary: {}", e),
+                },
+                Err(e) => error!("retention run failed: {}", e),
+            }
+        }
+    }
+
+    /// Deletes every row past its policy, and the chunk files left empty.
+    pub async fn prune(&self, now: DateTime<Utc>) -> Result<PruneSummary> {
+        let mut summary = PruneSummary::default();
+        if self
+            .media_volume
+            .as_ref()
+            .is_some_and(|v| !v.is_available())
+        {
+            // The chunk files can't be removed, rows would be dropped while their media stays
+            info!("media directory is unavailable, skipping retention run");
+            return Ok(summary);
+        }
+        let settings = self.settings().await;
+        let Some(before) = settings.earliest_cutoff(now) else {
+            return Ok(summary);
+        };
+
+        for kind in [
+            RetentionKind::Frame,
+            RetentionKind::Ui,
+            RetentionKind::Audio,
+        ] {
+            let mut after_id = 0;
+            loop {
+                let rows = self
+                    .db
+                    .get_retention_rows(kind, before, after_id, PRUNE_BATCH)
+                    .await?;
+                let Some(last) = rows.last() else {
+                    break;
+                };
+                after_id = last.id;
+
+                let mut ids = Vec::new();
+                for row in &rows {
+                    match settings.decide(row, now) {
+                        RetentionDecision::Delete => {
+                            ids.push(row.id);
+                            if kind == RetentionKind::Audio {
+                                summary.audio += 1;
+                            } else {
+                                *summary.apps.entry(owner(row)).or_default() += 1;
+                            }
+                        }
+                        RetentionDecision::KeepTagged => summary.tagged_kept += 1,
+                        RetentionDecision::Keep => {}
+                    }
+                }
+                if !ids.is_empty() {
+                    for file in self.db.delete_retention_rows(kind, &ids).await? {
+                        match tokio::fs::remove_file(&file).await {
+                            Ok(()) => summary.files_removed += 1,
+                            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                            Err(e) => warn!("failed to remove {}: {}", file, e),
+                        }
+                    }
+                }
+                if rows.len() < PRUNE_BATCH as usize {
+                    break;
+                }
+            }
+        }
+        Ok(summary)
+    }
+
+    /// Disk usage and scheduled deletions by app under the current settings.
+    pub async fn report(&self, now: DateTime<Utc>) -> Result<RetentionReport> {
+        let settings = self.settings().await;
+        let (apps, audio) = self.usage(&settings, now).await?;
+        let mut usage = apps.clone();
+        usage.push(audio.clone());
+        Ok(RetentionReport {
+            generated_at: now,
+            total_bytes: usage.iter().map(|app| app.bytes).sum(),
+            warnings: settings.budget_warnings(&usage, now),
+            settings,
+            apps,
+            audio,
+        })
+    }
+
+    async fn usage(
+        &self,
+        settings: &RetentionSettings,
+        now: DateTime<Utc>,
+    ) -> Result<(Vec<AppRetention>, AppRetention)> {
+        let mut apps: HashMap<String, AppRetention> = HashMap::new();
+        let mut audio = AppRetention {
+            max_age_days: settings.global.max_age_days,
+            ..Default::default()
+        };
+        let mut usage = Vec::new();
+        for kind in [
+            RetentionKind::Frame,
+            RetentionKind::Ui,
+            RetentionKind::Audio,
+        ] {
+            usage.push((kind, self.db.get_retention_usage(kind).await?));
+        }
+
+        // Size and row count of every chunk file, to split a file's bytes among its rows
+        let mut files: HashMap<String, (u64, i64)> = HashMap::new();
+        for row in usage.iter().flat_map(|(_, rows)| rows) {
+            if !row.file_path.is_empty() {
+                files.entry(row.file_path.clone()).or_default().1 += row.rows;
+            }
+        }
+        for (file_path, (size, _)) in files.iter_mut() {
+            *size = tokio::fs::metadata(file_path)
+                .await
+                .map(|m| m.len())
+                .unwrap_or(0);
+        }
+
+        for (kind, rows) in usage {
+            for row in rows {
+                let entry = if kind == RetentionKind::Audio {
+                    &mut audio
+                } else {
+                    let app_name = normalize_app_name(&row.app_name);
+                    apps.entry(app_name.clone())
+                        .or_insert_with(|| AppRetention {
+                            max_age_days: settings.policy_for(&app_name).max_age_days,
+                            overridden: settings.apps.contains_key(&app_name),
+                            app_name,
+                            ..Default::default()
+                        })
+                };
+                entry.rows += row.rows;
+                entry.bytes += share(&files, &row.file_path, row.rows);
+                entry.oldest = Some(entry.oldest.map_or(row.oldest, |o| o.min(row.oldest)));
+            }
+        }
+
+        if let Some(before) = settings.earliest_cutoff(now) {
+            for kind in [
+                RetentionKind::Frame,
+                RetentionKind::Ui,
+                RetentionKind::Audio,
+            ] {
+                let mut after_id = 0;
+                loop {
+                    let rows = self
+                        .db
+                        .get_retention_rows(kind, before, after_id, PRUNE_BATCH)
+                        .await?;
+                    let Some(last) = rows.last() else {
+                        break;
+                    };
+                    after_id = last.id;
+
+                    for row in &rows {
+                        let entry = if kind == RetentionKind::Audio {
+                            &mut audio
+                        } else {
+                            match apps.get_mut(&owner(row)) {
+                                Some(entry) => entry,
+                                None => continue,
+                            }
+                        };
+                        match settings.decide(row, now) {
+                            RetentionDecision::Delete => {
+                                entry.scheduled_rows += 1;
+                                entry.scheduled_bytes += share(&files, &row.file_path, 1);
+                            }
+                            RetentionDecision::KeepTagged => entry.tagged_rows += 1,
+                            RetentionDecision::Keep => {}
+                        }
+                    }
+                    if rows.len() < PRUNE_BATCH as usize {
+                        break;
+                    }
+                }
+            }
+        }
+
+        let mut apps: Vec<AppRetention> = apps.into_values().collect();
+        apps.sort_by(|a, b| b.bytes.cmp(&a.bytes).then(a.app_name.cmp(&b.app_name)));
+        Ok((apps, audio))
+    }
+}
+
+/// The app a row is counted under, the first of its apps by name.
+fn owner(row: &RetentionRow) -> String {
+    row.apps().min().map(normalize_app_name).unwrap_or_default()
+}
+
+/// Bytes of `rows` rows of a chunk file.
+fn share(files: &HashMap<String, (u64, i64)>, file_path: &str, rows: i64) -> u64 {
+    match files.get(file_path) {
+        Some((size, total)) if *total > 0 => size * rows as u64 / *total as u64,
+        _ => 0,
+    }
+}
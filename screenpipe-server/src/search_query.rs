@@ -0,0 +1,976 @@
+use crate::db_types::{ContentType, SearchFilters, TextFilter};
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Character range in the query or in a text, counted in unicode scalar values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    fn to(self, other: Span) -> Span {
+        Span::new(self.start, other.end)
+    }
+}
+
+/// A query that can't be run, with the part of it at fault and how to fix it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct QueryError {
+    pub message: String,
+    pub span: Span,
+    pub hint: String,
+}
+
+impl QueryError {
+    fn new(span: Span, message: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            span,
+            hint: hint.into(),
+        }
+    }
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} at {}..{}",
+            self.message, self.span.start, self.span.end
+        )
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+/// What a search query asks for, in the shapes the search params use. Everything in
+/// here is combined with the params using and.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ParsedQuery {
+    /// FTS5 match expression, empty when the query has no search terms
+    pub text: String,
+    pub start_time: Option<DateTime<Utc>>,
+    pub end_time: Option<DateTime<Utc>>,
+    pub speaker_ids: Option<Vec<i64>>,
+    pub filters: SearchFilters,
+}
+
+/// `name`, description, example
+const FIELDS: &[(&str, &str, &str)] = &[
+    (
+        "app",
+        "app name contains the value, case insensitive",
+        "app:slack",
+    ),
+    (
+        "window",
+        "window title contains the value, case insensitive",
+        "window:\"pull request\"",
+    ),
+    ("tag", "tagged with the value", "tag:important"),
+    (
+        "type",
+        "content type: ocr, audio, ui, document or pipe",
+        "type:audio",
+    ),
+    (
+        "speaker",
+        "audio from the speaker with this id",
+        "speaker:3",
+    ),
+    ("after", "captured at or after the date", "after:2024-06-01"),
+    (
+        "before",
+        "captured before the date",
+        "before:2024-06-01T18:00",
+    ),
+    ("on", "captured during the day", "on:yesterday"),
+];
+
+const DATE_HINT: &str = "use a date like 2024-06-01, a time like 2024-06-01T14:30 or \
+2024-06-01T14:30:00Z, today, yesterday, or an age like 30m, 12h, 7d or 2w";
+
+/// Parses a search query. `now` anchors relative dates such as `7d` or `today`, which
+/// are read in the local timezone like every date without an offset.
+pub fn parse_query(input: &str, now: DateTime<Utc>) -> Result<ParsedQuery, QueryError> {
+    let tokens = tokenize(input, now)?;
+    if tokens.is_empty() {
+        return Ok(ParsedQuery::default());
+    }
+    let mut parser = Parser { tokens, pos: 0 };
+    let node = parser.parse_and()?;
+    if let Some(token) = parser.peek() {
+        // Only a closing paren can stop the top level early
+        return Err(QueryError::new(
+            token.span,
+            "unmatched ')'",
+            "remove it, or add the '(' it closes",
+        ));
+    }
+    compile(node)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Filter {
+    App(String),
+    Window(String),
+    Tag(String),
+    Type(ContentType),
+    Speaker(i64),
+    After(DateTime<Utc>),
+    Before(DateTime<Utc>),
+    On(DateTime<Utc>, DateTime<Utc>),
+}
+
+impl Filter {
+    fn field(&self) -> &'static str {
+        match self {
+            Filter::App(_) => "app",
+            Filter::Window(_) => "window",
+            Filter::Tag(_) => "tag",
+            Filter::Type(_) => "type",
+            Filter::Speaker(_) => "speaker",
+            Filter::After(_) => "after",
+            Filter::Before(_) => "before",
+            Filter::On(..) => "on",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    LParen,
+    RParen,
+    And,
+    Or,
+    Not,
+    Term { text: String, prefix: bool },
+    Phrase(String),
+    Filter(Filter),
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    span: Span,
+}
+
+fn tokenize(input: &str, now: DateTime<Utc>) -> Result<Vec<Token>, QueryError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        let kind = match c {
+            '(' => {
+                i += 1;
+                TokenKind::LParen
+            }
+            ')' => {
+                i += 1;
+                TokenKind::RParen
+            }
+            '-' => {
+                i += 1;
+                if !matches!(chars.get(i), Some(c) if !c.is_whitespace() && *c != ')') {
+                    return Err(QueryError::new(
+                        Span::new(start, i),
+                        "'-' has nothing to exclude",
+                        "put it right before a word or filter, like -spotify or -app:spotify",
+                    ));
+                }
+                TokenKind::Not
+            }
+            '"' => {
+                let (text, end) = read_quoted(&chars, i)?;
+                i = end;
+                TokenKind::Phrase(text)
+            }
+            _ => {
+                while i < chars.len() && !is_word_boundary(chars[i]) {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                match word.as_str() {
+                    "AND" => TokenKind::And,
+                    "OR" => TokenKind::Or,
+                    "NOT" => TokenKind::Not,
+                    _ => match field_name(&word) {
+                        Some(name) => {
+                            let name_span = Span::new(start, start + name.chars().count());
+                            let value_start = name_span.end + 1;
+                            let value = if value_start == i && chars.get(i) == Some(&'"') {
+                                let (value, end) = read_quoted(&chars, i)?;
+                                i = end;
+                                value
+                            } else {
+                                chars[value_start..i].iter().collect()
+                            };
+                            let filter = parse_filter(
+                                name,
+                                name_span,
+                                &value,
+                                Span::new(value_start, i),
+                                now,
+                            )?;
+                            TokenKind::Filter(filter)
+                        }
+                        None => match word.strip_suffix('*') {
+                            Some(stem) if !stem.is_empty() => TokenKind::Term {
+                                text: stem.to_string(),
+                                prefix: true,
+                            },
+                            _ => TokenKind::Term {
+                                text: word,
+                                prefix: false,
+                            },
+                        },
+                    },
+                }
+            }
+        };
+        tokens.push(Token {
+            kind,
+            span: Span::new(start, i),
+        });
+    }
+
+    Ok(tokens)
+}
+
+fn is_word_boundary(c: char) -> bool {
+    c.is_whitespace() || c == '(' || c == ')' || c == '"'
+}
+
+/// Reads a quoted string starting at the quote at `start`. `\"` and `\\` escape.
+/// Returns the text and the index after the closing quote.
+fn read_quoted(chars: &[char], start: usize) -> Result<(String, usize), QueryError> {
+    let mut text = String::new();
+    let mut i = start + 1;
+    while i < chars.len() {
+        match chars[i] {
+            '"' => return Ok((text, i + 1)),
+            '\\' if matches!(chars.get(i + 1), Some('"') | Some('\\')) => {
+                text.push(chars[i + 1]);
+                i += 2;
+            }
+            c => {
+                text.push(c);
+                i += 1;
+            }
+        }
+    }
+    Err(QueryError::new(
+        Span::new(start, chars.len()),
+        "unterminated quote",
+        "close the phrase with another \", or write \\\" for a literal quote",
+    ))
+}
+
+/// The field of a `field:value` word. Words like `10:30` aren't fields.
+fn field_name(word: &str) -> Option<&str> {
+    let (name, _) = word.split_once(':')?;
+    if !name.is_empty() && name.chars().all(|c| c.is_ascii_alphabetic() || c == '_') {
+        Some(name)
+    } else {
+        None
+    }
+}
+
+fn parse_filter(
+    name: &str,
+    name_span: Span,
+    value: &str,
+    value_span: Span,
+    now: DateTime<Utc>,
+) -> Result<Filter, QueryError> {
+    let field = name.to_ascii_lowercase();
+    let Some((_, _, example)) = FIELDS.iter().find(|(f, _, _)| *f == field) else {
+        let fields: Vec<&str> = FIELDS.iter().map(|(f, _, _)| *f).collect();
+        return Err(QueryError::new(
+            name_span,
+            format!("unknown filter '{}:'", name),
+            format!(
+                "filters are {}. to search for text containing ':', put it in quotes",
+                fields.join(", ")
+            ),
+        ));
+    };
+    if value.trim().is_empty() {
+        return Err(QueryError::new(
+            name_span.to(value_span),
+            format!("'{}:' needs a value", field),
+            format!(
+                "write it without a space, like {}. to match any of several values use (a OR b)",
+                example
+            ),
+        ));
+    }
+
+    let invalid_date = || {
+        QueryError::new(
+            value_span,
+            format!("'{}' is not a date {}: understands", value, field),
+            DATE_HINT,
+        )
+    };
+    let filter = match field.as_str() {
+        "app" => Filter::App(value.to_string()),
+        "window" => Filter::Window(value.to_string()),
+        "tag" => Filter::Tag(value.to_string()),
+        "type" => Filter::Type(match value.to_ascii_lowercase().as_str() {
+            "ocr" => ContentType::OCR,
+            "audio" => ContentType::Audio,
+            "ui" => ContentType::UI,
+            "document" => ContentType::Document,
+            "pipe" => ContentType::Pipe,
+            _ => {
+                return Err(QueryError::new(
+                    value_span,
+                    format!("unknown content type '{}'", value),
+                    "use ocr, audio, ui, document or pipe",
+                ))
+            }
+        }),
+        "speaker" => Filter::Speaker(value.parse().map_err(|_| {
+            QueryError::new(
+                value_span,
+                format!("'{}' is not a speaker id", value),
+                "speakers are matched by their numeric id, like speaker:3",
+            )
+        })?),
+        "after" => match parse_date(value, now).ok_or_else(invalid_date)? {
+            DateValue::Day(day) => Filter::After(day_start(day, value_span)?),
+            DateValue::Instant(at) => Filter::After(at),
+        },
+        "before" => match parse_date(value, now).ok_or_else(invalid_date)? {
+            DateValue::Day(day) => Filter::Before(day_start(day, value_span)?),
+            DateValue::Instant(at) => Filter::Before(at),
+        },
+        _ => match parse_date(value, now).ok_or_else(invalid_date)? {
+            DateValue::Day(day) => {
+                let next = day.succ_opt().ok_or_else(invalid_date)?;
+                Filter::On(day_start(day, value_span)?, day_start(next, value_span)?)
+            }
+            DateValue::Instant(_) => return Err(QueryError::new(
+                value_span,
+                "'on:' takes a day, not a time",
+                "use a day like on:2024-06-01 or on:today, or after: and before: for a time range",
+            )),
+        },
+    };
+    Ok(filter)
+}
+
+enum DateValue {
+    Day(NaiveDate),
+    Instant(DateTime<Utc>),
+}
+
+fn parse_date(value: &str, now: DateTime<Utc>) -> Option<DateValue> {
+    let today = now.with_timezone(&Local).date_naive();
+    match value.to_ascii_lowercase().as_str() {
+        "today" => return Some(DateValue::Day(today)),
+        "yesterday" => return today.pred_opt().map(DateValue::Day),
+        _ => {}
+    }
+
+    if let Some(age) = parse_age(value) {
+        return now.checked_sub_signed(age).map(DateValue::Instant);
+    }
+    if let Ok(day) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Some(DateValue::Day(day));
+    }
+    if let Ok(at) = DateTime::parse_from_rfc3339(value) {
+        return Some(DateValue::Instant(at.with_timezone(&Utc)));
+    }
+    ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .and_then(|local| Local.from_local_datetime(&local).earliest())
+        .map(|at| DateValue::Instant(at.with_timezone(&Utc)))
+}
+
+/// `30m`, `12h`, `7d` or `2w` before now, up to a hundred years.
+fn parse_age(value: &str) -> Option<Duration> {
+    let unit = value.chars().last()?;
+    let amount: u32 = value[..value.len() - unit.len_utf8()].parse().ok()?;
+    let unit_secs = match unit {
+        'm' => 60,
+        'h' => 3600,
+        'd' => 86400,
+        'w' => 7 * 86400,
+        _ => return None,
+    };
+    let secs = i64::from(amount) * unit_secs;
+    (secs <= 100 * 365 * 86400).then(|| Duration::seconds(secs))
+}
+
+fn day_start(day: NaiveDate, span: Span) -> Result<DateTime<Utc>, QueryError> {
+    day.and_hms_opt(0, 0, 0)
+        .and_then(|midnight| Local.from_local_datetime(&midnight).earliest())
+        .map(|at| at.with_timezone(&Utc))
+        .ok_or_else(|| {
+            QueryError::new(
+                span,
+                format!("{} has no midnight in the local timezone", day),
+                "give a time instead, like 2024-06-01T01:00",
+            )
+        })
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Term { text: String, prefix: bool },
+    Phrase(String),
+    Filter(Filter),
+    Not(Box<Node>),
+    And(Vec<Node>),
+    Or(Vec<Node>),
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    expr: Expr,
+    span: Span,
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    /// Whether the next token can't start an operand.
+    fn at_operand_end(&self) -> bool {
+        matches!(
+            self.peek().map(|t| &t.kind),
+            None | Some(TokenKind::RParen) | Some(TokenKind::Or) | Some(TokenKind::And)
+        )
+    }
+
+    /// Terms next to each other, or joined with AND. The loosest level.
+    fn parse_and(&mut self) -> Result<Node, QueryError> {
+        let mut items = vec![self.parse_or()?];
+        loop {
+            match self.peek().map(|t| (&t.kind, t.span)) {
+                None | Some((TokenKind::RParen, _)) => break,
+                Some((TokenKind::And, span)) => {
+                    self.pos += 1;
+                    if self.at_operand_end() {
+                        return Err(QueryError::new(
+                            span,
+                            "AND needs something on both sides",
+                            "terms are joined with AND already, so it can be left out",
+                        ));
+                    }
+                }
+                Some(_) => {}
+            }
+            items.push(self.parse_or()?);
+        }
+        Ok(combine(items, Expr::And))
+    }
+
+    /// Alternatives joined with OR, which binds tighter than AND like in web search:
+    /// `slack deploy OR release` is slack and either of the other two.
+    fn parse_or(&mut self) -> Result<Node, QueryError> {
+        let mut items = vec![self.parse_unary()?];
+        while let Some(Token {
+            kind: TokenKind::Or,
+            span,
+        }) = self.peek().cloned()
+        {
+            self.pos += 1;
+            if self.at_operand_end() {
+                return Err(QueryError::new(
+                    span,
+                    "OR needs something on both sides",
+                    "write it between two alternatives, like deploy OR release",
+                ));
+            }
+            items.push(self.parse_unary()?);
+        }
+        Ok(combine(items, Expr::Or))
+    }
+
+    fn parse_unary(&mut self) -> Result<Node, QueryError> {
+        if let Some(Token {
+            kind: TokenKind::Not,
+            span,
+        }) = self.peek().cloned()
+        {
+            self.pos += 1;
+            if self.at_operand_end() {
+                return Err(QueryError::new(
+                    span,
+                    "NOT has nothing to exclude",
+                    "put a word, phrase, filter or group after it, like NOT spotify",
+                ));
+            }
+            let inner = self.parse_unary()?;
+            return Ok(Node {
+                span: span.to(inner.span),
+                expr: Expr::Not(Box::new(inner)),
+            });
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Node, QueryError> {
+        let token = self
+            .next()
+            .expect("parse_primary is only called before an operand");
+        let expr = match token.kind {
+            TokenKind::LParen => {
+                if let Some(Token {
+                    kind: TokenKind::RParen,
+                    span,
+                }) = self.peek().cloned()
+                {
+                    return Err(QueryError::new(
+                        token.span.to(span),
+                        "empty group",
+                        "put the alternatives inside, like (deploy OR release), or remove the parentheses",
+                    ));
+                }
+                let inner = self.parse_and()?;
+                return match self.next() {
+                    Some(Token {
+                        kind: TokenKind::RParen,
+                        span,
+                    }) => Ok(Node {
+                        expr: inner.expr,
+                        span: token.span.to(span),
+                    }),
+                    _ => Err(QueryError::new(
+                        token.span,
+                        "'(' is never closed",
+                        "add a ')' after the group",
+                    )),
+                };
+            }
+            TokenKind::RParen => {
+                return Err(QueryError::new(
+                    token.span,
+                    "unmatched ')'",
+                    "remove it, or add the '(' it closes",
+                ))
+            }
+            TokenKind::Or | TokenKind::And => {
+                let operator = if token.kind == TokenKind::Or {
+                    "OR"
+                } else {
+                    "AND"
+                };
+                return Err(QueryError::new(
+                    token.span,
+                    format!("{} needs something on both sides", operator),
+                    "to search for the word itself, write it in lowercase or in quotes",
+                ));
+            }
+            TokenKind::Not => unreachable!("handled by parse_unary"),
+            TokenKind::Term { text, prefix } => Expr::Term { text, prefix },
+            TokenKind::Phrase(text) => Expr::Phrase(text),
+            TokenKind::Filter(filter) => Expr::Filter(filter),
+        };
+        Ok(Node {
+            expr,
+            span: token.span,
+        })
+    }
+}
+
+fn combine(mut items: Vec<Node>, wrap: fn(Vec<Node>) -> Expr) -> Node {
+    if items.len() == 1 {
+        return items.remove(0);
+    }
+    let span = items[0].span.to(items[items.len() - 1].span);
+    Node {
+        expr: wrap(items),
+        span,
+    }
+}
+
+/// Whether the node only holds search terms, or only filters. `None` for a mix.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Text,
+    Filters,
+}
+
+fn kind(node: &Node) -> Option<Kind> {
+    match &node.expr {
+        Expr::Term { .. } | Expr::Phrase(_) => Some(Kind::Text),
+        Expr::Filter(_) => Some(Kind::Filters),
+        Expr::Not(inner) => kind(inner),
+        Expr::And(items) | Expr::Or(items) => {
+            let first = kind(&items[0])?;
+            items
+                .iter()
+                .skip(1)
+                .all(|item| kind(item) == Some(first))
+                .then_some(first)
+        }
+    }
+}
+
+fn compile(node: Node) -> Result<ParsedQuery, QueryError> {
+    let mut conjuncts = Vec::new();
+    flatten_and(node, &mut conjuncts);
+
+    let mut query = ParsedQuery::default();
+    let mut text = Vec::new();
+    for node in conjuncts {
+        match kind(&node) {
+            Some(Kind::Text) => text.push(node),
+            Some(Kind::Filters) => apply_filters(&node, false, &mut query)?,
+            None => {
+                return Err(QueryError::new(
+                    node.span,
+                    "can't mix search terms and filters inside OR or NOT",
+                    "OR can join search terms, like (deploy OR release), or values of one filter, \
+                     like (app:slack OR app:discord). put other filters next to them instead",
+                ))
+            }
+        }
+    }
+
+    if !text.is_empty() {
+        let span = text[0].span.to(text[text.len() - 1].span);
+        query.text = fts_and(&text, span)?;
+    }
+    Ok(query)
+}
+
+fn flatten_and(node: Node, out: &mut Vec<Node>) {
+    match node.expr {
+        Expr::And(items) => items.into_iter().for_each(|item| flatten_and(item, out)),
+        expr => out.push(Node {
+            expr,
+            span: node.span,
+        }),
+    }
+}
+
+/// Folds a filter-only node into the query, `negated` when it sits under a NOT.
+fn apply_filters(node: &Node, negated: bool, query: &mut ParsedQuery) -> Result<(), QueryError> {
+    match &node.expr {
+        Expr::Filter(filter) => apply_filter(filter, negated, node.span, query),
+        Expr::Not(inner) => apply_filters(inner, !negated, query),
+        Expr::And(items) if !negated => items
+            .iter()
+            .try_for_each(|item| apply_filters(item, false, query)),
+        // not (a or b) is not a and not b
+        Expr::Or(items) if negated => items
+            .iter()
+            .try_for_each(|item| apply_filters(item, true, query)),
+        Expr::Or(items) => apply_any_of(items, node.span, query),
+        _ => Err(QueryError::new(
+            node.span,
+            "can't exclude a group of different filters",
+            "exclude each one on its own, like -app:spotify -window:music",
+        )),
+    }
+}
+
+/// `(a OR b)` of filters on one field.
+fn apply_any_of(items: &[Node], span: Span, query: &mut ParsedQuery) -> Result<(), QueryError> {
+    let mut filters = Vec::new();
+    for item in items {
+        match &item.expr {
+            Expr::Filter(filter) => filters.push(filter),
+            _ => {
+                return Err(QueryError::new(
+                    item.span,
+                    "OR between filters only takes plain filters",
+                    "list the alternatives of one filter, like (app:slack OR app:discord)",
+                ))
+            }
+        }
+    }
+    let field = filters[0].field();
+    if filters.iter().any(|f| f.field() != field) {
+        return Err(QueryError::new(
+            span,
+            "OR can't join different filters",
+            "OR works between values of the same filter, like (app:slack OR app:discord)",
+        ));
+    }
+
+    let values = |filters: &[&Filter]| -> Vec<String> {
+        filters
+            .iter()
+            .filter_map(|f| match f {
+                Filter::App(v) | Filter::Window(v) | Filter::Tag(v) => Some(v.clone()),
+                _ => None,
+            })
+            .collect()
+    };
+    match filters[0] {
+        Filter::App(_) => query.filters.app_name.all_of.push(values(&filters)),
+        Filter::Window(_) => query.filters.window_name.all_of.push(values(&filters)),
+        Filter::Tag(_) => query.filters.tags.all_of.push(values(&filters)),
+        Filter::Type(_) => {
+            let types: Vec<ContentType> = filters
+                .iter()
+                .filter_map(|f| match f {
+                    Filter::Type(t) => Some(t.clone()),
+                    _ => None,
+                })
+                .collect();
+            limit_types(query, |t| types.contains(t));
+        }
+        Filter::Speaker(_) => {
+            let ids: Vec<i64> = filters
+                .iter()
+                .filter_map(|f| match f {
+                    Filter::Speaker(id) => Some(*id),
+                    _ => None,
+                })
+                .collect();
+            limit_speakers(query, ids);
+        }
+        Filter::After(_) | Filter::Before(_) | Filter::On(..) => {
+            return Err(QueryError::new(
+                span,
+                "OR can't join dates",
+                "use one range, like after:2024-06-01 before:2024-06-08",
+            ))
+        }
+    }
+    Ok(())
+}
+
+fn apply_filter(
+    filter: &Filter,
+    negated: bool,
+    span: Span,
+    query: &mut ParsedQuery,
+) -> Result<(), QueryError> {
+    let text_filter = |filters: &mut TextFilter, value: &String| {
+        if negated {
+            filters.none_of.push(value.clone());
+        } else {
+            filters.all_of.push(vec![value.clone()]);
+        }
+    };
+    match filter {
+        Filter::App(value) => text_filter(&mut query.filters.app_name, value),
+        Filter::Window(value) => text_filter(&mut query.filters.window_name, value),
+        Filter::Tag(value) => text_filter(&mut query.filters.tags, value),
+        Filter::Type(content_type) => limit_types(query, |t| (t == content_type) != negated),
+        Filter::Speaker(_) if negated => {
+            return Err(QueryError::new(
+                span,
+                "can't exclude a speaker",
+                "list the speakers to keep instead, like (speaker:1 OR speaker:2)",
+            ))
+        }
+        Filter::Speaker(id) => limit_speakers(query, vec![*id]),
+        Filter::After(at) | Filter::Before(at) => {
+            // not after x is before x and the other way around
+            if matches!(filter, Filter::After(_)) != negated {
+                query.start_time = Some(query.start_time.map_or(*at, |t| t.max(*at)));
+            } else {
+                query.end_time = Some(query.end_time.map_or(*at, |t| t.min(*at)));
+            }
+        }
+        Filter::On(..) if negated => {
+            return Err(QueryError::new(
+                span,
+                "can't exclude a day",
+                "use before: or after: to keep the days on one side of it",
+            ))
+        }
+        Filter::On(start, end) => {
+            query.start_time = Some(query.start_time.map_or(*start, |t| t.max(*start)));
+            query.end_time = Some(query.end_time.map_or(*end, |t| t.min(*end)));
+        }
+    }
+    Ok(())
+}
+
+fn limit_types(query: &mut ParsedQuery, keep: impl Fn(&ContentType) -> bool) {
+    let types = query
+        .filters
+        .content_types
+        .take()
+        .unwrap_or_else(|| ContentType::All.parts());
+    query.filters.content_types = Some(types.into_iter().filter(|t| keep(t)).collect());
+}
+
+fn limit_speakers(query: &mut ParsedQuery, ids: Vec<i64>) {
+    query.speaker_ids = Some(match query.speaker_ids.take() {
+        Some(current) => current.into_iter().filter(|id| ids.contains(id)).collect(),
+        None => ids,
+    });
+}
+
+/// FTS5 expression for search terms joined with and. FTS5 has no unary NOT, so at
+/// least one of them has to be something to look for.
+fn fts_and(items: &[Node], span: Span) -> Result<String, QueryError> {
+    let mut include = Vec::new();
+    let mut exclude = Vec::new();
+    for item in items {
+        match &item.expr {
+            Expr::Not(inner) => match &inner.expr {
+                Expr::Not(twice) => include.push(fts(twice)?),
+                _ => exclude.push(fts(inner)?),
+            },
+            _ => include.push(fts(item)?),
+        }
+    }
+    if include.is_empty() {
+        return Err(QueryError::new(
+            span,
+            "a search can't only leave words out",
+            "add a word to look for next to it, like deploy -staging, or use a filter such as -app:spotify",
+        ));
+    }
+
+    let mut expression = include.join(" AND ");
+    if include.len() > 1 && !exclude.is_empty() {
+        expression = format!("({})", expression);
+    }
+    for excluded in exclude {
+        expression = format!("{} NOT {}", expression, excluded);
+    }
+    Ok(expression)
+}
+
+fn fts(node: &Node) -> Result<String, QueryError> {
+    match &node.expr {
+        Expr::Term { text, prefix } => Ok(format!(
+            "{}{}",
+            fts_string(text),
+            if *prefix { "*" } else { "" }
+        )),
+        Expr::Phrase(text) => Ok(fts_string(text)),
+        Expr::And(items) => Ok(format!("({})", fts_and(items, node.span)?)),
+        Expr::Or(items) => {
+            let alternatives = items
+                .iter()
+                .map(|item| match item.expr {
+                    Expr::Not(_) => Err(QueryError::new(
+                        item.span,
+                        "can't use an excluded word as an alternative",
+                        "exclude it next to the group instead, like (deploy OR release) -staging",
+                    )),
+                    _ => fts(item),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(format!("({})", alternatives.join(" OR ")))
+        }
+        Expr::Not(_) => Err(QueryError::new(
+            node.span,
+            "a search can't only leave words out",
+            "add a word to look for next to it, like deploy -staging",
+        )),
+        Expr::Filter(_) => unreachable!("filters never reach the text expression"),
+    }
+}
+
+/// A string literal in FTS5 syntax, so punctuation and keywords are searched as text.
+fn fts_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('"', "\"\""))
+}
+
+#[derive(Debug, Serialize)]
+pub struct SyntaxEntry {
+    pub syntax: &'static str,
+    pub description: &'static str,
+}
+
+/// Reference for the query language, served at `GET /search/syntax` for the ui help.
+#[derive(Debug, Serialize)]
+pub struct SearchSyntax {
+    pub summary: &'static str,
+    pub operators: Vec<SyntaxEntry>,
+    pub filters: Vec<SyntaxEntry>,
+    pub date_formats: Vec<SyntaxEntry>,
+    pub examples: Vec<SyntaxEntry>,
+    pub notes: Vec<&'static str>,
+}
+
+pub fn search_syntax() -> SearchSyntax {
+    let entry = |syntax, description| SyntaxEntry {
+        syntax,
+        description,
+    };
+    SearchSyntax {
+        summary: "words are matched against the captured text and joined with AND. filters \
+                  narrow the results and combine with the other search parameters using AND",
+        operators: vec![
+            entry("deploy release", "both words"),
+            entry("\"release notes\"", "the exact phrase"),
+            entry("deplo*", "words starting with deplo"),
+            entry("deploy OR release", "either word. OR is uppercase, lowercase or is a word"),
+            entry("-staging, NOT staging", "leave out results with the word or filter"),
+            entry(
+                "slack deploy OR release",
+                "OR binds tighter than AND: slack, and deploy or release",
+            ),
+            entry("(a b) OR c", "parentheses group terms"),
+            entry("deploy AND release", "AND is optional, terms are joined with it anyway"),
+        ],
+        filters: FIELDS
+            .iter()
+            .map(|(_, description, example)| entry(example, description))
+            .collect(),
+        date_formats: vec![
+            entry("2024-06-01", "the start of the day, local time"),
+            entry("2024-06-01T14:30", "local time, seconds optional"),
+            entry("2024-06-01T14:30:00Z", "rfc 3339 with a timezone"),
+            entry("today, yesterday", "the start of the day, local time"),
+            entry("30m, 12h, 7d, 2w", "that long ago"),
+        ],
+        examples: vec![
+            entry(
+                "app:slack (deploy OR release) -app:spotify after:2024-06-01 tag:important",
+                "deploy or release in slack since june, tagged important",
+            ),
+            entry(
+                "\"quarterly report\" type:document",
+                "the phrase in watched folder documents",
+            ),
+            entry(
+                "(app:zoom OR app:meet) type:audio after:7d",
+                "audio from zoom or meet calls in the last week",
+            ),
+            entry("invoice on:yesterday -window:spam", "yesterday's invoices outside spam"),
+        ],
+        notes: vec![
+            "filter values with spaces go in quotes: window:\"pull request\"",
+            "a word followed by ':' reads as a filter, so quote text like \"http://example.com\". 10:30 works as is",
+            "OR joins search terms, or values of one filter. it can't join a term and a filter",
+            "a query needs at least one word to look for if it leaves words out",
+            "audio, documents and pipe content have no app or window, so app: and window: leave them out, and documents and pipe content have no tags",
+            "type:pipe covers every pipe content type, the type= and fields.*= params narrow it to one",
+            "invalid queries return 400 with the offending character span and a hint",
+        ],
+    }
+}
@@ -1,22 +1,70 @@
 use axum::{
     extract::{Json, Path, Query, State},
-    http::StatusCode,
-    response::{sse::Event, IntoResponse, Json as JsonResponse, Sse},
-    routing::{get, post},
+    http::{header, HeaderMap, StatusCode},
+    response::{sse::Event, IntoResponse, Json as JsonResponse, Response, Sse},
+    routing::{delete, get, post, put},
     serve, Router,
 };
 use crossbeam::queue::SegQueue;
 use futures::{
     future::{try_join, try_join_all},
-    Stream,
+    Stream, StreamExt,
 };
 use image::ImageFormat::{self};
 
 use crate::{
-    db_types::{ContentType, SearchResult, Speaker, TagContentType},
-    pipe_manager::PipeManager,
+    api_version::{
+        api_versioning, with_api_version, DeprecationReport, API_VERSION_HEADER,
+        DEPRECATION_HEADER, SUNSET_HEADER,
+    },
+    archive::Archiver,
+    audio_playback::{byte_range, load_audio, playback_url, Clip},
+    context::{self, ContextBundle},
+    copy::{
+        deep_link_document, frame_text, parse_deep_link, resolve_deep_link, sensitive, to_png,
+        CopyLink, CopyWhat, DeepLinkDocument, TimelinePosition,
+    },
+    db_retry::DbWriteMetricsSnapshot,
+    db_types::{
+        CaptureSession, ContentType, PipeJob, PipeJobStatus, SearchResult, Speaker, TagContentType,
+        UsageBucket, WindowGeometryFilter,
+    },
+    events::{
+        Event as RecordedEvent, EventFilter, EventKind, EventRecorder, Severity as EventSeverity,
+        EVENT_TYPES,
+    },
+    frame_find::{
+        fts_phrase, window_matches, FrameFind, DEFAULT_FIND_CONTEXT, MATCH_END, MATCH_START,
+        MAX_FIND_CONTEXT,
+    },
+    ocr_correction::CorrectionSource,
+    pipe_batch::{run_batch, BatchReport, BatchRequest},
+    pipe_content::{
+        self, ContentSchema, ContentTypeInfo, NewPipeContent, Registration, SchemaMigration,
+    },
+    pipe_lock::{sync_pipes, SyncReport, SyncRequest},
+    pipe_manager::{PipeError, PipeManager},
+    pipe_schedule::{PipeScheduler, ScheduleRequest},
+    pipe_tokens::{pipe_tokens, with_pipe_scopes},
+    privacy::{self, ignored_apps, AppDeletion, PrivacySummary},
+    problem::{with_problem_details, ApiError, ErrorCode},
+    ranking::{rank_results, RankingWeights, RANKING_CANDIDATE_POOL},
+    retention::{
+        normalize_app_name, RetentionManager, RetentionPolicy, RetentionReport, RetentionSettings,
+    },
+    search_query::{parse_query, search_syntax, SearchSyntax},
+    sessions::{SessionManager, StartSessionRequest},
+    settings_watch::{pipe_settings, SettingsWatch, WatchEvent, CLIENT_HEADER},
+    storage::MediaVolume,
+    storage_mode::{storage_mode, StorageMode},
+    tag_rules::{
+        rule_metrics, test_rule, RuleMetricsSnapshot, RuleSample, RuleTestReport, TagRule,
+        TagRules, TagRulesConfig, DEFAULT_TEST_ROWS, MAX_TEST_ROWS,
+    },
+    telemetry::{SentPing, Telemetry, TelemetryStatus, UsagePayload},
     video::{finish_ffmpeg_process, start_ffmpeg_process, write_frame_to_ffmpeg, MAX_FPS},
     video_cache::{FrameCache, TimeSeriesFrame},
+    video_guard::{encoder_guard, EncoderGuardSnapshot},
     video_utils::{
         merge_videos,
         validate_media,
@@ -35,11 +83,11 @@ use screenpipe_audio::{
     DeviceType,
 };
 use screenpipe_vision::monitor::list_monitors;
-use screenpipe_vision::OcrEngine;
+use screenpipe_vision::{OcrEngine, OcrScheduler};
 use serde::{Deserialize, Deserializer, Serialize};
 use serde_json::{json, Value};
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     convert::Infallible,
     net::SocketAddr,
     path::PathBuf,
@@ -59,6 +107,12 @@ use tower_http::{cors::CorsLayer, trace::DefaultMakeSpan};
 use enigo::{Enigo, Key, Settings};
 
 use screenpipe_audio::LAST_AUDIO_CAPTURE;
+use screenpipe_core::latency::{latency_tracker, LatencySnapshot};
+use screenpipe_core::models::{model_registry, ModelStatus};
+use screenpipe_core::pipe_manifest::manifest_schema;
+use screenpipe_core::power::power_state;
+use screenpipe_core::window_layout::WindowLayout;
+use screenpipe_core::{ConflictPolicy, DownloadOptions, OverwritePolicy};
 
 use std::str::FromStr;
 
@@ -74,6 +128,26 @@ pub struct AppState {
     pub audio_disabled: bool,
     pub ui_monitoring_enabled: bool,
     pub frame_cache: Option<Arc<FrameCache>>,
+    pub ocr_scheduler: Option<Arc<OcrScheduler>>,
+    pub media_volume: Option<Arc<MediaVolume>>,
+    pub archiver: Option<Arc<Archiver>>,
+    pub ranking: RankingWeights,
+    pub retention: Arc<RetentionManager>,
+    pub pipe_scheduler: Arc<PipeScheduler>,
+    pub sessions: Arc<SessionManager>,
+    pub telemetry: Arc<Telemetry>,
+    pub settings: Arc<SettingsWatch>,
+    pub events: Arc<EventRecorder>,
+}
+
+impl AppState {
+    /// Directory video and audio chunks are written to.
+    pub fn media_dir(&self) -> PathBuf {
+        self.media_volume
+            .as_ref()
+            .map(|v| v.dir().to_path_buf())
+            .unwrap_or_else(|| self.screenpipe_dir.join("data"))
+    }
 }
 
 // Update the SearchQuery struct
@@ -103,6 +177,42 @@ pub(crate) struct SearchQuery {
         default = "default_speaker_ids"
     )]
     speaker_ids: Option<Vec<i64>>,
+    #[serde(default)]
+    sort: Option<SearchSort>,
+    /// Pipe content type, needed by the `fields.<name>` filters
+    #[serde(default, rename = "type")]
+    pipe_type: Option<String>,
+    /// Bounds on the geometry of the window an ocr result was read from, in pixels
+    #[serde(default)]
+    window_width_gt: Option<i64>,
+    #[serde(default)]
+    window_width_lt: Option<i64>,
+    #[serde(default)]
+    window_height_gt: Option<i64>,
+    #[serde(default)]
+    window_height_lt: Option<i64>,
+    #[serde(default)]
+    window_x_gt: Option<i64>,
+    #[serde(default)]
+    window_x_lt: Option<i64>,
+    #[serde(default)]
+    window_y_gt: Option<i64>,
+    #[serde(default)]
+    window_y_lt: Option<i64>,
+    /// Include the window layout of each ocr result's frame
+    #[serde(default)]
+    layout: bool,
+    /// Only what was captured during the latest capture session with this name
+    #[serde(default)]
+    session: Option<String>,
+}
+
+/// `relevance` is the default when there is a text query, `recent` otherwise
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum SearchSort {
+    Relevance,
+    Recent,
 }
 
 #[derive(Deserialize)]
@@ -165,6 +275,8 @@ pub enum ContentItem {
     OCR(OCRContent),
     Audio(AudioContent),
     UI(UiContent),
+    Document(DocumentContent),
+    Pipe(PipeContent),
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -177,7 +289,28 @@ pub struct OCRContent {
     pub app_name: String,
     pub window_name: String,
     pub tags: Vec<String>,
+    /// `None` for frames stored without an image, see `storage_mode`
     pub frame: Option<String>,
+    #[serde(default)]
+    pub storage_mode: StorageMode,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thumbnail_path: Option<String>,
+    /// The video was moved to the archive directory, `frame` is null while its volume
+    /// isn't mounted
+    #[serde(default)]
+    pub archived: bool,
+    /// `text` was post-corrected, `raw_text` is what the OCR engine read
+    #[serde(default)]
+    pub corrected: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub corrected_by: Option<CorrectionSource>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_text: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub score: Option<f64>,
+    /// Every window on the monitor at this frame, with `layout=true`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub layout: Option<WindowLayout>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -193,6 +326,14 @@ pub struct AudioContent {
     pub speaker: Option<Speaker>,
     pub start_time: Option<f64>,
     pub end_time: Option<f64>,
+    /// Where the transcription starts in the chunk, `start_time` in milliseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offset_ms: Option<u64>,
+    /// `/audio/:chunk_id` playing the transcription, or the chunk without segment times
+    #[serde(default)]
+    pub playback_url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub score: Option<f64>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -205,6 +346,32 @@ pub struct UiContent {
     pub initial_traversal_at: Option<DateTime<Utc>>,
     pub file_path: String,
     pub offset_index: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub score: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocumentContent {
+    pub id: i64,
+    pub text: String,
+    pub timestamp: DateTime<Utc>,
+    pub file_path: String,
+    pub kind: String,
+    pub file_size: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub score: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PipeContent {
+    pub id: i64,
+    pub content_type: String,
+    pub pipe_id: String,
+    pub text: String,
+    pub timestamp: DateTime<Utc>,
+    pub fields: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub score: Option<f64>,
 }
 
 #[derive(Serialize)]
@@ -256,6 +423,21 @@ pub struct HealthCheckResponse {
     pub frame_status: String,
     pub audio_status: String,
     pub ui_status: String,
+    /// "ok", or "unavailable" while the media volume is gone and capture is paused
+    pub media_status: String,
+    /// p95 time from capture until searchable over the recent frames and audio chunks
+    pub searchable_within_ms: Option<u64>,
+    /// "ok", "over budget" or "no data"
+    pub latency_status: String,
+    pub storage_mode: StorageMode,
+    /// Disk a day of capture at the last hour's frame rate takes in the current mode
+    pub estimated_disk_mb_per_day: Option<f64>,
+    /// Transcription models, whether they are in memory and when they were last used
+    #[serde(default)]
+    pub models: Vec<ModelStatus>,
+    /// Critical events of the last day, newest first, see `GET /events`
+    #[serde(default)]
+    pub critical_events: Vec<RecordedEvent>,
     pub message: String,
     pub verbose_instructions: Option<String>,
 }
@@ -263,13 +445,11 @@ pub struct HealthCheckResponse {
 // Update the search function
 pub(crate) async fn search(
     Query(query): Query<SearchQuery>,
+    Query(params): Query<Vec<(String, String)>>,
     State(state): State<Arc<AppState>>,
-) -> Result<
-    JsonResponse<PaginatedResponse<ContentItem>>,
-    (StatusCode, JsonResponse<serde_json::Value>),
-> {
+) -> Result<JsonResponse<PaginatedResponse<ContentItem>>, ApiError> {
     info!(
-        "received search request: query='{}', content_type={:?}, limit={}, offset={}, start_time={:?}, end_time={:?}, app_name={:?}, window_name={:?}, min_length={:?}, max_length={:?}, speaker_ids={:?}",
+        "received search request: query='{}', content_type={:?}, limit={}, offset={}, start_time={:?}, end_time={:?}, app_name={:?}, window_name={:?}, min_length={:?}, max_length={:?}, speaker_ids={:?}, sort={:?}",
         query.q.as_deref().unwrap_or(""),
         query.content_type,
         query.pagination.limit,
@@ -280,51 +460,153 @@ pub(crate) async fn search(
         query.window_name,
         query.min_length,
         query.max_length,
-        query.speaker_ids
+        query.speaker_ids,
+        query.sort
     );
 
-    let query_str = query.q.as_deref().unwrap_or("");
+    // `q` is parsed with the query language, its filters and the params both apply
+    let parsed = parse_query(query.q.as_deref().unwrap_or(""), Utc::now())?;
+    let query_str = parsed.text.as_str();
+    let mut filters = parsed.filters;
+    let field_params: Vec<(String, String)> = params
+        .into_iter()
+        .filter(|(key, _)| key.starts_with("fields."))
+        .collect();
+    match &query.pipe_type {
+        Some(pipe_type) => {
+            filters.fields =
+                pipe_content::field_filters(&state.db, pipe_type, &field_params).await?;
+            filters.pipe_type = Some(pipe_type.clone());
+        }
+        None if !field_params.is_empty() => {
+            return Err(ApiError::invalid_request(
+                "fields.* filters need a type param naming a pipe content type",
+            ));
+        }
+        None => {}
+    }
+    filters.window = WindowGeometryFilter {
+        width_gt: query.window_width_gt,
+        width_lt: query.window_width_lt,
+        height_gt: query.window_height_gt,
+        height_lt: query.window_height_lt,
+        x_gt: query.window_x_gt,
+        x_lt: query.window_x_lt,
+        y_gt: query.window_y_gt,
+        y_lt: query.window_y_lt,
+    };
+    let start_time = match (query.start_time, parsed.start_time) {
+        (Some(a), Some(b)) => Some(a.max(b)),
+        (a, b) => a.or(b),
+    };
+    let end_time = match (query.end_time, parsed.end_time) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    };
+    let (start_time, end_time) = match &query.session {
+        Some(name) => {
+            let (started_at, ended_at) = state.sessions.time_range(name).await?;
+            (
+                Some(start_time.map_or(started_at, |t| t.max(started_at))),
+                Some(end_time.map_or(ended_at, |t| t.min(ended_at))),
+            )
+        }
+        None => (start_time, end_time),
+    };
+    let speaker_ids = match (query.speaker_ids.clone(), parsed.speaker_ids) {
+        (Some(a), Some(b)) => Some(a.into_iter().filter(|id| b.contains(id)).collect()),
+        (a, b) => a.or(b),
+    };
 
     let content_type = query.content_type.clone();
 
+    let sort = query.sort.unwrap_or(if query_str.is_empty() {
+        SearchSort::Recent
+    } else {
+        SearchSort::Relevance
+    });
+
+    let results = async {
+        let results: Vec<(SearchResult, Option<f64>)> = match sort {
+            // Plain reverse-chronological order, the scorer is not involved
+            SearchSort::Recent => state
+                .db
+                .search_with_filters(
+                    query_str,
+                    content_type.clone(),
+                    query.pagination.limit,
+                    query.pagination.offset,
+                    start_time,
+                    end_time,
+                    query.app_name.as_deref(),
+                    query.window_name.as_deref(),
+                    query.min_length,
+                    query.max_length,
+                    speaker_ids.clone(),
+                    &filters,
+                )
+                .await?
+                .into_iter()
+                .map(|result| (result, None))
+                .collect(),
+            SearchSort::Relevance => {
+                let pool = RANKING_CANDIDATE_POOL.max(
+                    query
+                        .pagination
+                        .offset
+                        .saturating_add(query.pagination.limit),
+                );
+                let candidates = state
+                    .db
+                    .search_candidates(
+                        query_str,
+                        content_type.clone(),
+                        pool,
+                        start_time,
+                        end_time,
+                        query.app_name.as_deref(),
+                        query.window_name.as_deref(),
+                        query.min_length,
+                        query.max_length,
+                        speaker_ids.clone(),
+                        &filters,
+                    )
+                    .await?;
+                rank_results(candidates, &state.ranking, Utc::now())
+                    .into_iter()
+                    .skip(query.pagination.offset as usize)
+                    .take(query.pagination.limit as usize)
+                    .map(|(result, score)| (result, Some(score)))
+                    .collect()
+            }
+        };
+        Ok::<_, sqlx::Error>(results)
+    };
+
     let (results, total) = try_join(
-        state.db.search(
+        results,
+        state.db.count_search_results_with_filters(
             query_str,
             content_type.clone(),
-            query.pagination.limit,
-            query.pagination.offset,
-            query.start_time,
-            query.end_time,
+            start_time,
+            end_time,
             query.app_name.as_deref(),
             query.window_name.as_deref(),
             query.min_length,
             query.max_length,
-            query.speaker_ids.clone(),
-        ),
-        state.db.count_search_results(
-            query_str,
-            content_type,
-            query.start_time,
-            query.end_time,
-            query.app_name.as_deref(),
-            query.window_name.as_deref(),
-            query.min_length,
-            query.max_length,
-            query.speaker_ids.clone(),
+            speaker_ids.clone(),
+            &filters,
         ),
     )
     .await
     .map_err(|e| {
         error!("failed to perform search operations: {}", e);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            JsonResponse(json!({"error": format!("failed to perform search operations: {}", e)})),
-        )
+        ApiError::internal(format!("failed to perform search operations: {}", e))
     })?;
 
     let mut content_items: Vec<ContentItem> = results
         .iter()
-        .map(|result| match result {
+        .map(|(result, score)| match result {
             SearchResult::OCR(ocr) => ContentItem::OCR(OCRContent {
                 frame_id: ocr.frame_id,
                 text: ocr.ocr_text.clone(),
@@ -335,6 +617,14 @@ pub(crate) async fn search(
                 window_name: ocr.window_name.clone(),
                 tags: ocr.tags.clone(),
                 frame: None,
+                storage_mode: ocr.storage_mode,
+                thumbnail_path: ocr.thumbnail_path.clone(),
+                archived: ocr.archived,
+                corrected: ocr.corrected_by.is_some(),
+                corrected_by: ocr.corrected_by,
+                raw_text: ocr.raw_text.clone(),
+                score: *score,
+                layout: None,
             }),
             SearchResult::Audio(audio) => ContentItem::Audio(AudioContent {
                 chunk_id: audio.audio_chunk_id,
@@ -348,6 +638,11 @@ pub(crate) async fn search(
                 speaker: audio.speaker.clone(),
                 start_time: audio.start_time,
                 end_time: audio.end_time,
+                offset_ms: audio
+                    .start_time
+                    .map(|start| (start * 1000.0).round() as u64),
+                playback_url: playback_url(audio.audio_chunk_id, audio.start_time, audio.end_time),
+                score: *score,
             }),
             SearchResult::UI(ui) => ContentItem::UI(UiContent {
                 id: ui.id,
@@ -358,31 +653,75 @@ pub(crate) async fn search(
                 initial_traversal_at: ui.initial_traversal_at,
                 file_path: ui.file_path.clone(),
                 offset_index: ui.offset_index,
+                score: *score,
+            }),
+            SearchResult::Document(document) => ContentItem::Document(DocumentContent {
+                id: document.id,
+                text: document.text.clone(),
+                timestamp: document.timestamp,
+                file_path: document.file_path.clone(),
+                kind: document.kind.clone(),
+                file_size: document.file_size,
+                score: *score,
+            }),
+            SearchResult::Pipe(record) => ContentItem::Pipe(PipeContent {
+                id: record.id,
+                content_type: record.content_type.clone(),
+                pipe_id: record.pipe_id.clone(),
+                text: record.text.clone(),
+                timestamp: record.timestamp,
+                fields: record.fields.clone(),
+                score: *score,
             }),
         })
         .collect();
 
     if query.include_frames {
         debug!("extracting frames for ocr content");
+        let archive_available = state
+            .archiver
+            .as_ref()
+            .is_some_and(|archiver| archiver.is_available());
         let frame_futures: Vec<_> = content_items
             .iter()
             .filter_map(|item| {
                 if let ContentItem::OCR(ocr_content) = item {
-                    Some(extract_frame(
-                        &ocr_content.file_path,
-                        ocr_content.offset_index,
-                    ))
+                    Some(ocr_frame(ocr_content, archive_available))
                 } else {
                     None
                 }
             })
             .collect();
 
-        let frames = try_join_all(frame_futures).await.unwrap(); // TODO: handle error
+        let frames = try_join_all(frame_futures).await.map_err(|e| {
+            error!("failed to extract frames: {}", e);
+            ApiError::internal(format!("failed to extract frames: {}", e))
+        })?;
+
+        let ocr_contents = content_items.iter_mut().filter_map(|item| match item {
+            ContentItem::OCR(ocr_content) => Some(ocr_content),
+            _ => None,
+        });
+        for (ocr_content, frame) in ocr_contents.zip(frames) {
+            ocr_content.frame = frame;
+        }
+    }
 
-        for (item, frame) in content_items.iter_mut().zip(frames.into_iter()) {
-            if let ContentItem::OCR(ref mut ocr_content) = item {
-                ocr_content.frame = Some(frame);
+    if query.layout {
+        let frame_ids: Vec<i64> = content_items
+            .iter()
+            .filter_map(|item| match item {
+                ContentItem::OCR(ocr_content) => Some(ocr_content.frame_id),
+                _ => None,
+            })
+            .collect();
+        let layouts = state.db.get_frame_layouts(&frame_ids).await.map_err(|e| {
+            error!("failed to get window layouts: {}", e);
+            ApiError::internal(format!("failed to get window layouts: {}", e))
+        })?;
+        for item in content_items.iter_mut() {
+            if let ContentItem::OCR(ocr_content) = item {
+                ocr_content.layout = layouts.get(&ocr_content.frame_id).cloned();
             }
         }
     }
@@ -398,6 +737,30 @@ pub(crate) async fn search(
     }))
 }
 
+/// The base64 image of a search result: extracted from its video, its thumbnail, or
+/// nothing for text only frames and archived frames whose volume isn't mounted.
+async fn ocr_frame(
+    ocr_content: &OCRContent,
+    archive_available: bool,
+) -> anyhow::Result<Option<String>> {
+    if ocr_content.archived && !archive_available {
+        return Ok(None);
+    }
+    match (ocr_content.storage_mode, &ocr_content.thumbnail_path) {
+        (StorageMode::Full, _) => extract_frame(&ocr_content.file_path, ocr_content.offset_index)
+            .await
+            .map(Some),
+        (_, Some(thumbnail_path)) => Ok(Some(
+            BASE64_STANDARD.encode(tokio::fs::read(thumbnail_path).await?),
+        )),
+        (_, None) => Ok(None),
+    }
+}
+
+pub(crate) async fn search_syntax_handler() -> JsonResponse<SearchSyntax> {
+    JsonResponse(search_syntax())
+}
+
 pub(crate) async fn api_list_audio_devices(
     State(_state): State<Arc<AppState>>,
 ) -> Result<JsonResponse<Vec<ListDeviceResponse>>, (StatusCode, JsonResponse<serde_json::Value>)> {
@@ -467,6 +830,485 @@ pub async fn api_list_monitors(
     }
 }
 
+pub(crate) async fn ocr_metrics_handler(
+    State(state): State<Arc<AppState>>,
+) -> Result<JsonResponse<Value>, (StatusCode, JsonResponse<Value>)> {
+    let scheduler = state.ocr_scheduler.as_ref().ok_or_else(|| {
+        (
+            StatusCode::NOT_FOUND,
+            JsonResponse(json!({"error": "ocr scheduler is not enabled (single monitor fifo)"})),
+        )
+    })?;
+    let metrics = scheduler.metrics();
+    let monitors: serde_json::Map<String, Value> = metrics
+        .monitors
+        .iter()
+        .map(|(id, m)| {
+            (
+                id.to_string(),
+                json!({
+                    "queue_depth": m.queue_depth,
+                    "processed": m.processed,
+                    "skipped": m.skipped,
+                    "dropped": m.dropped,
+                    "last_latency_ms": m.last_latency_ms,
+                    "max_latency_ms": m.max_latency_ms,
+                    "avg_latency_ms": m.avg_latency_ms,
+                }),
+            )
+        })
+        .collect();
+    Ok(JsonResponse(json!({
+        "queue_depth": metrics.queue_depth,
+        "throttled": metrics.throttled,
+        "monitors": monitors,
+    })))
+}
+
+/// Deprecated api surfaces and how often they are still used.
+pub(crate) async fn deprecations_handler() -> JsonResponse<DeprecationReport> {
+    JsonResponse(api_versioning().report())
+}
+
+/// Capture to searchable percentiles per pipeline and stage, with the budget state.
+pub(crate) async fn latency_metrics_handler() -> JsonResponse<LatencySnapshot> {
+    JsonResponse(latency_tracker().snapshot())
+}
+
+#[derive(Debug, Serialize)]
+pub struct ModelMetrics {
+    /// Models unused this long are unloaded, `None` keeps them in memory
+    pub idle_timeout_secs: Option<u64>,
+    /// With their load counts and times, what reloading after an unload costs
+    pub models: Vec<ModelStatus>,
+}
+
+pub(crate) async fn model_metrics_handler() -> JsonResponse<ModelMetrics> {
+    JsonResponse(ModelMetrics {
+        idle_timeout_secs: model_registry().idle_timeout().map(|idle| idle.as_secs()),
+        models: model_registry().statuses(),
+    })
+}
+
+/// Unloads every transcription model once its running inference is done. They are
+/// loaded again when next needed.
+pub(crate) async fn unload_models_handler() -> Result<JsonResponse<Value>, ApiError> {
+    let unloaded = tokio::task::spawn_blocking(|| model_registry().unload_all())
+        .await
+        .map_err(|e| ApiError::internal(format!("failed to unload models: {}", e)))?;
+    if !unloaded.is_empty() {
+        info!("unloaded models on request: {}", unloaded.join(", "));
+    }
+    Ok(JsonResponse(json!({
+        "success": true,
+        "unloaded": unloaded,
+        "models": model_registry().statuses(),
+    })))
+}
+
+/// Frames that didn't fit the video chunk they were for: converted, starting a new
+/// chunk or dropped, see [`crate::video_guard`].
+pub(crate) async fn video_metrics_handler() -> JsonResponse<EncoderGuardSnapshot> {
+    JsonResponse(encoder_guard().snapshot())
+}
+
+/// How often each tag rule ran, matched and ran past its budget, see
+/// [`crate::tag_rules`].
+pub(crate) async fn rule_metrics_handler() -> JsonResponse<RuleMetricsSnapshot> {
+    JsonResponse(rule_metrics().snapshot())
+}
+
+/// Writes that met a locked database: retried, spilled to the journal and replayed.
+pub(crate) async fn db_metrics_handler(
+    State(state): State<Arc<AppState>>,
+) -> JsonResponse<DbWriteMetricsSnapshot> {
+    JsonResponse(state.db.write_metrics().snapshot())
+}
+
+/// The image of a frame: a png extracted from its video chunk, or its jpeg thumbnail.
+/// Frames stored without an image are a 404 saying why, archived frames whose volume
+/// isn't mounted an `archive_unavailable` 503.
+pub(crate) async fn get_frame_handler(
+    State(state): State<Arc<AppState>>,
+    Path(frame_id): Path<i64>,
+) -> Result<Response, ApiError> {
+    let (image, content_type) = load_frame_image(&state, frame_id).await?;
+    Ok(([(header::CONTENT_TYPE, content_type)], image).into_response())
+}
+
+/// The image of a frame and its content type, see [`get_frame_handler`].
+async fn load_frame_image(
+    state: &AppState,
+    frame_id: i64,
+) -> Result<(Vec<u8>, &'static str), ApiError> {
+    let source = state
+        .db
+        .get_frame_image_source(frame_id)
+        .await?
+        .ok_or_else(|| ApiError::not_found(format!("frame {} not found", frame_id)))?;
+    if source.archived
+        && !state
+            .archiver
+            .as_ref()
+            .is_some_and(|archiver| archiver.is_available())
+    {
+        let archive_dir = state
+            .archiver
+            .as_ref()
+            .map(|archiver| archiver.archive_dir().display().to_string());
+        return Err(ApiError::new(
+            ErrorCode::ArchiveUnavailable,
+            format!(
+                "frame {} is archived and the archive volume is not available",
+                frame_id
+            ),
+        )
+        .with_extension("reason", "archived")
+        .with_extension("archive_dir", archive_dir));
+    }
+    let (image, content_type) = match (source.storage_mode, &source.thumbnail_path) {
+        (StorageMode::Full, _) => {
+            let encoded = extract_frame(&source.file_path, source.offset_index)
+                .await
+                .map_err(|e| {
+                    error!("failed to extract frame {}: {}", frame_id, e);
+                    ApiError::internal(format!("failed to extract frame {}: {}", frame_id, e))
+                })?;
+            let image = BASE64_STANDARD
+                .decode(encoded)
+                .map_err(|e| ApiError::internal(format!("invalid frame data: {}", e)))?;
+            (image, "image/png")
+        }
+        (_, Some(thumbnail_path)) => {
+            let image = tokio::fs::read(thumbnail_path).await.map_err(|e| {
+                ApiError::not_found(format!("thumbnail of frame {} is missing: {}", frame_id, e))
+                    .with_extension("reason", "thumbnail_missing")
+            })?;
+            (image, "image/jpeg")
+        }
+        (mode, None) => {
+            return Err(ApiError::not_found(
+                mode.missing_image_reason()
+                    .unwrap_or("no image available, the thumbnail of the frame wasn't written"),
+            )
+            .with_extension("reason", mode.as_str()))
+        }
+    };
+    Ok((image, content_type))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FindQuery {
+    /// Term to find, as a phrase
+    pub q: String,
+    /// Chars of text around each match in its snippet
+    #[serde(default)]
+    pub context: Option<usize>,
+    /// Filters of the view the frame is shown in, the frames before and after are
+    /// looked for within them. Same as the search params of the same names
+    #[serde(default)]
+    pub start_time: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub end_time: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub app_name: Option<String>,
+    #[serde(default)]
+    pub window_name: Option<String>,
+    /// Filters of the query language, like `app:slack -tag:private`, no search terms
+    #[serde(default)]
+    pub filter: Option<String>,
+}
+
+/// Where a term is in a frame's OCR text, and the frames before and after with it,
+/// see [`crate::frame_find`].
+pub(crate) async fn find_in_frame_handler(
+    State(state): State<Arc<AppState>>,
+    Path(frame_id): Path<i64>,
+    Query(query): Query<FindQuery>,
+) -> Result<JsonResponse<FrameFind>, ApiError> {
+    let term = query.q.trim();
+    if term.is_empty() {
+        return Err(ApiError::invalid_request("q is empty, give a term to find"));
+    }
+    let context = query.context.unwrap_or(DEFAULT_FIND_CONTEXT);
+    if context > MAX_FIND_CONTEXT {
+        return Err(ApiError::invalid_request(format!(
+            "context can be at most {} chars",
+            MAX_FIND_CONTEXT
+        )));
+    }
+    let parsed = parse_query(query.filter.as_deref().unwrap_or(""), Utc::now())?;
+    if !parsed.text.is_empty() {
+        return Err(ApiError::invalid_request(
+            "filter only takes filters like app:slack, the term to find goes in q",
+        ));
+    }
+    let start_time = match (query.start_time, parsed.start_time) {
+        (Some(a), Some(b)) => Some(a.max(b)),
+        (a, b) => a.or(b),
+    };
+    let end_time = match (query.end_time, parsed.end_time) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    };
+
+    let fts_query = fts_phrase(term);
+    let windows: Vec<_> = state
+        .db
+        .find_in_frame(frame_id, &fts_query, (MATCH_START, MATCH_END))
+        .await?
+        .ok_or_else(|| ApiError::not_found(format!("frame {} not found", frame_id)))?
+        .into_iter()
+        .map(|row| window_matches(row, context))
+        .collect();
+    // A view without ocr results has no frames to step through
+    let (previous_frame_id, next_frame_id) = if parsed.filters.allows(&ContentType::OCR) {
+        state
+            .db
+            .adjacent_matching_frames(
+                frame_id,
+                &fts_query,
+                start_time,
+                end_time,
+                query.app_name.as_deref(),
+                query.window_name.as_deref(),
+                &parsed.filters,
+            )
+            .await?
+    } else {
+        (None, None)
+    };
+
+    Ok(JsonResponse(FrameFind {
+        frame_id,
+        q: term.to_string(),
+        total: windows.iter().map(|window| window.matches.len()).sum(),
+        windows,
+        previous_frame_id,
+        next_frame_id,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CopyQuery {
+    #[serde(default)]
+    pub what: CopyWhat,
+}
+
+/// A frame's text, png or link to put on a clipboard, see [`crate::copy`]. Images of
+/// frames showing something sensitive are a `sensitive` 403 saying why.
+pub(crate) async fn copy_content_handler(
+    State(state): State<Arc<AppState>>,
+    Path(frame_id): Path<i64>,
+    Query(query): Query<CopyQuery>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let frame = state
+        .db
+        .get_frame_content(frame_id)
+        .await?
+        .ok_or_else(|| ApiError::not_found(format!("frame {} not found", frame_id)))?;
+    match query.what {
+        CopyWhat::Text => {
+            let text = frame_text(&frame).ok_or_else(|| {
+                ApiError::not_found(format!("frame {} has no text", frame_id))
+                    .with_extension("reason", "no_text")
+            })?;
+            Ok(([(header::CONTENT_TYPE, "text/plain; charset=utf-8")], text).into_response())
+        }
+        CopyWhat::Image => {
+            if let Some(why) = sensitive(&frame, ignored_apps(), privacy::ignored_windows()) {
+                return Err(ApiError::new(
+                    ErrorCode::Sensitive,
+                    format!("frame {} {}", frame_id, why),
+                )
+                .with_extension("reason", why.reason()));
+            }
+            let (image, _) = load_frame_image(&state, frame_id).await?;
+            let png = to_png(&image).map_err(|e| {
+                error!("failed to convert frame {} to png: {}", frame_id, e);
+                ApiError::internal(format!(
+                    "failed to convert frame {} to png: {}",
+                    frame_id, e
+                ))
+            })?;
+            Ok((
+                [
+                    (header::CONTENT_TYPE, "image/png".to_string()),
+                    (
+                        header::CONTENT_DISPOSITION,
+                        format!("inline; filename=\"screenpipe-frame-{}.png\"", frame_id),
+                    ),
+                    (header::CACHE_CONTROL, "no-store".to_string()),
+                ],
+                png,
+            )
+                .into_response())
+        }
+        CopyWhat::Link => Ok(JsonResponse(CopyLink::new(
+            frame_id,
+            frame.timestamp,
+            &base_url(&headers),
+        ))
+        .into_response()),
+    }
+}
+
+/// Where the client reached this server, for links back to it.
+fn base_url(headers: &HeaderMap) -> String {
+    let host = headers
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok())
+        .unwrap_or("localhost:3030");
+    format!("http://{}", host)
+}
+
+pub(crate) async fn deep_links_handler() -> JsonResponse<DeepLinkDocument> {
+    JsonResponse(deep_link_document())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResolveDeepLinkQuery {
+    pub url: String,
+}
+
+/// The timeline position a `screenpipe://` link opens.
+pub(crate) async fn resolve_deep_link_handler(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<ResolveDeepLinkQuery>,
+    headers: HeaderMap,
+) -> Result<JsonResponse<TimelinePosition>, ApiError> {
+    let target = parse_deep_link(&query.url).map_err(ApiError::invalid_request)?;
+    let frame = state.db.get_frame_content(target.frame_id).await?;
+    resolve_deep_link(&target, frame.as_ref(), &base_url(&headers))
+        .map(JsonResponse)
+        .ok_or_else(|| ApiError::not_found(format!("frame {} not found", target.frame_id)))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AudioQuery {
+    pub start_ms: Option<u64>,
+    pub end_ms: Option<u64>,
+}
+
+/// The audio of a chunk, or of `start_ms..end_ms` of it, with range support. Audio
+/// deleted by retention or along with its transcriptions is a `not_retained` 410.
+pub(crate) async fn get_audio_handler(
+    State(state): State<Arc<AppState>>,
+    Path(chunk_id): Path<i64>,
+    Query(query): Query<AudioQuery>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let clip = Clip::new(query.start_ms, query.end_ms).map_err(ApiError::invalid_request)?;
+    let not_retained = |reason: &str| {
+        ApiError::new(
+            ErrorCode::NotRetained,
+            format!("audio {} is no longer retained", chunk_id),
+        )
+        .with_extension("reason", reason)
+    };
+    let Some(source) = state.db.get_audio_chunk_source(chunk_id).await? else {
+        if state.db.audio_chunk_deleted(chunk_id).await? {
+            return Err(not_retained("deleted"));
+        }
+        return Err(ApiError::not_found(format!("audio {} not found", chunk_id)));
+    };
+    // Its text is gone, so is the audio
+    if source.segments.is_empty() {
+        return Err(not_retained("transcriptions_deleted"));
+    }
+    if source.archived
+        && !state
+            .archiver
+            .as_ref()
+            .is_some_and(|archiver| archiver.is_available())
+    {
+        let archive_dir = state
+            .archiver
+            .as_ref()
+            .map(|archiver| archiver.archive_dir().display().to_string());
+        return Err(ApiError::new(
+            ErrorCode::ArchiveUnavailable,
+            format!(
+                "audio {} is archived and the archive volume is not available",
+                chunk_id
+            ),
+        )
+        .with_extension("reason", "archived")
+        .with_extension("archive_dir", archive_dir));
+    }
+    if tokio::fs::metadata(&source.file_path).await.is_err() {
+        return Err(not_retained("file_missing"));
+    }
+
+    let (audio, content_type) = load_audio(&source, clip).await.map_err(|e| {
+        error!("failed to load audio {}: {}", chunk_id, e);
+        ApiError::internal(format!("failed to load audio {}: {}", chunk_id, e))
+    })?;
+    let total = audio.len() as u64;
+    let range = headers
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok());
+    match byte_range(range, total) {
+        Ok(None) => Ok((
+            [
+                (header::CONTENT_TYPE, content_type.to_string()),
+                (header::ACCEPT_RANGES, "bytes".to_string()),
+            ],
+            audio,
+        )
+            .into_response()),
+        Ok(Some(range)) => {
+            let part = audio[range.start as usize..=range.end as usize].to_vec();
+            Ok((
+                StatusCode::PARTIAL_CONTENT,
+                [
+                    (header::CONTENT_TYPE, content_type.to_string()),
+                    (header::ACCEPT_RANGES, "bytes".to_string()),
+                    (header::CONTENT_RANGE, range.content_range(total)),
+                ],
+                part,
+            )
+                .into_response())
+        }
+        Err(detail) => Ok((
+            StatusCode::RANGE_NOT_SATISFIABLE,
+            [(header::CONTENT_RANGE, format!("bytes */{}", total))],
+            detail,
+        )
+            .into_response()),
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StorageModeBody {
+    pub mode: StorageMode,
+}
+
+pub(crate) async fn get_storage_mode_handler() -> Json<StorageModeBody> {
+    Json(StorageModeBody {
+        mode: storage_mode().get(),
+    })
+}
+
+/// Switches the storage mode of new frames while recording.
+pub(crate) async fn update_storage_mode_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(request): Json<StorageModeBody>,
+) -> Json<Value> {
+    let previous = storage_mode().set(request.mode);
+    if previous != request.mode {
+        info!("storage mode changed from {} to {}", previous, request.mode);
+    }
+    publish_settings(&state, &headers).await;
+    Json(json!({
+        "success": true,
+        "mode": request.mode,
+        "previous": previous,
+    }))
+}
+
 pub(crate) async fn add_tags(
     State(state): State<Arc<AppState>>,
     Path((content_type, id)): Path<(String, i64)>,
@@ -529,11 +1371,19 @@ pub async fn health_check(State(state): State<Arc<AppState>>) -> JsonResponse<He
         .unwrap()
         .as_secs();
 
-    let app_uptime = (now as i64) - (state.app_start_time.timestamp());
+    // Measured from the last wake too, so after a night asleep nothing is reported
+    // stale before capture had a chance to resume
+    let baseline = power_state()
+        .last_wake()
+        .map_or(state.app_start_time, |woke_at| {
+            woke_at.max(state.app_start_time)
+        });
+    let app_uptime = (now as i64) - baseline.timestamp();
     let grace_period = 120; // 2 minutes in seconds
+    let in_grace_period = app_uptime < grace_period;
 
     let last_capture = LAST_AUDIO_CAPTURE.load(Ordering::Relaxed);
-    let audio_active = if app_uptime < grace_period {
+    let audio_active = if in_grace_period {
         true // Consider active during grace period
     } else {
         now - last_capture < 5 // Consider active if captured in last 5 seconds
@@ -560,6 +1410,7 @@ pub async fn health_check(State(state): State<Arc<AppState>>) -> JsonResponse<He
             {
                 "ok"
             }
+            Some(_) if in_grace_period => "ok",
             Some(_) => "stale",
             None => "no data",
         }
@@ -583,19 +1434,78 @@ pub async fn health_check(State(state): State<Arc<AppState>>) -> JsonResponse<He
             {
                 "ok"
             }
+            Some(_) if in_grace_period => "ok",
             Some(_) => "stale",
             None => "no data",
         }
     };
 
-    let (overall_status, message, verbose_instructions) = if (frame_status == "ok"
-        || frame_status == "disabled")
+    let media_unavailable_since = state
+        .media_volume
+        .as_ref()
+        .and_then(|v| v.unavailable_since());
+    let media_status = if media_unavailable_since.is_some() {
+        "unavailable"
+    } else {
+        "ok"
+    };
+
+    let mode = storage_mode().get();
+    let estimated_disk_mb_per_day = match state
+        .db
+        .count_frames_since(now - chrono::Duration::hours(1))
+        .await
+    {
+        Ok(counts) => {
+            let frames_per_hour: i64 = counts.iter().map(|(_, count)| count).sum();
+            (frames_per_hour > 0).then(|| {
+                (frames_per_hour as u64 * 24 * mode.bytes_per_frame()) as f64 / (1024.0 * 1024.0)
+            })
+        }
+        Err(e) => {
+            error!("failed to count recent frames: {}", e);
+            None
+        }
+    };
+
+    let latency = latency_tracker().snapshot();
+    let searchable_within = latency.searchable_within();
+    let latency_status = match searchable_within {
+        None => "no data",
+        Some(_) if latency.over_budget() => "over budget",
+        Some(_) => "ok",
+    };
+
+    let critical_events = state.events.recent_critical().await.unwrap_or_else(|e| {
+        error!("failed to read critical events: {}", e);
+        Vec::new()
+    });
+
+    let (overall_status, message, verbose_instructions) = if let Some(since) =
+        media_unavailable_since
+    {
+        (
+            "unhealthy",
+            format!(
+                "media directory {} is unavailable since {}, capture is paused until it returns.",
+                state.media_dir().display(),
+                since.to_rfc3339()
+            ),
+            Some("reconnect the drive holding your screenpipe media".to_string()),
+        )
+    } else if (frame_status == "ok" || frame_status == "disabled")
         && (audio_status == "ok" || audio_status == "disabled")
         && (ui_status == "ok" || ui_status == "disabled")
     {
         (
             "healthy",
-            "all systems are functioning normally.".to_string(),
+            match searchable_within {
+                Some(within) => format!(
+                    "all systems are functioning normally, searchable within {:.1}s.",
+                    within.as_secs_f64()
+                ),
+                None => "all systems are functioning normally.".to_string(),
+            },
             None,
         )
     } else {
@@ -626,6 +1536,13 @@ pub async fn health_check(State(state): State<Arc<AppState>>) -> JsonResponse<He
         frame_status: frame_status.to_string(),
         audio_status: audio_status.to_string(),
         ui_status: ui_status.to_string(),
+        media_status: media_status.to_string(),
+        searchable_within_ms: searchable_within.map(|within| within.as_millis() as u64),
+        latency_status: latency_status.to_string(),
+        storage_mode: mode,
+        estimated_disk_mb_per_day,
+        models: model_registry().statuses(),
+        critical_events,
         message,
         verbose_instructions,
     })
@@ -635,8 +1552,34 @@ pub async fn health_check(State(state): State<Arc<AppState>>) -> JsonResponse<He
 #[derive(Deserialize)]
 struct DownloadPipeRequest {
     url: String,
-}
-
+    /// Keep a github pipe already at the commit its source points to
+    #[serde(default)]
+    locked: bool,
+    /// Download every file, none is kept from the installed copy
+    #[serde(default)]
+    force: bool,
+    /// Branch, tag or commit sha to download the pipe at, in place of the ref of its url
+    #[serde(default, rename = "ref")]
+    git_ref: Option<String>,
+    /// Sha256 of each file by path below the pipe root, in place of the source's
+    /// `checksums.json`
+    #[serde(default)]
+    checksums: Option<HashMap<String, String>>,
+    /// Fail when a file changed since the installed copy without a version bump
+    #[serde(default)]
+    verify_integrity: bool,
+    /// `overwrite`, `skip` or `error_if_exists` when the pipe is installed already
+    #[serde(default)]
+    overwrite: OverwritePolicy,
+    /// `error`, `rename` or `replace` when the pipe installed under the same name is from
+    /// another source
+    #[serde(default)]
+    on_conflict: ConflictPolicy,
+    /// Install it even when it has nothing to start, a pipe.ts, pipe.js or next.js app
+    #[serde(default)]
+    skip_validation: bool,
+}
+
 #[derive(Deserialize)]
 struct RunPipeRequest {
     pipe_id: String,
@@ -651,37 +1594,57 @@ struct UpdatePipeConfigRequest {
 // Handler functions
 async fn download_pipe_handler(
     State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
     JsonResponse(payload): JsonResponse<DownloadPipeRequest>,
-) -> Result<JsonResponse<serde_json::Value>, (StatusCode, JsonResponse<Value>)> {
+) -> Result<JsonResponse<serde_json::Value>, ApiError> {
     debug!("Downloading pipe: {}", payload.url);
-    match state.pipe_manager.download_pipe(&payload.url).await {
-        Ok(pipe_dir) => Ok(JsonResponse(json!({
-            "data": {
-                "pipe_id": pipe_dir,
-                "message": "pipe downloaded successfully"
+    let pipe_dir = state
+        .pipe_manager
+        .download_pipe_with(
+            &payload.url,
+            DownloadOptions {
+                locked: payload.locked,
+                force: payload.force,
+                git_ref: payload.git_ref,
+                checksums: payload.checksums,
+                verify_integrity: payload.verify_integrity,
+                overwrite: payload.overwrite,
+                on_conflict: payload.on_conflict,
+                skip_validation: payload.skip_validation,
+                ..Default::default()
             },
-            "success": true
-        }))),
-        Err(e) => {
-            error!("Failed to download pipe: {}", e);
-            Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                JsonResponse(json!({
-                    "error": format!("failed to download pipe: {}", e),
-                    "success": false
-                })),
-            ))
-        }
-    }
+        )
+        .await
+        .map_err(|e| {
+            let error = ApiError::new(
+                ErrorCode::PipeError,
+                format!("failed to download pipe: {}", e),
+            );
+            match e.downcast_ref::<PipeError>() {
+                Some(PipeError::InvalidManifest { issues, .. }) => {
+                    error.with_extension("issues", json!(issues))
+                }
+                _ => error,
+            }
+        })?;
+    publish_settings(&state, &headers).await;
+    Ok(JsonResponse(json!({
+        "data": {
+            "pipe_id": pipe_dir,
+            "message": "pipe downloaded successfully"
+        },
+        "success": true
+    })))
 }
 
 async fn run_pipe_handler(
     State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
     JsonResponse(payload): JsonResponse<RunPipeRequest>,
-) -> Result<JsonResponse<Value>, (StatusCode, JsonResponse<Value>)> {
+) -> Result<JsonResponse<Value>, ApiError> {
     debug!("starting pipe: {}", payload.pipe_id);
 
-    match state
+    state
         .pipe_manager
         .update_config(
             &payload.pipe_id,
@@ -689,31 +1652,24 @@ async fn run_pipe_handler(
                 "enabled": true,
             }),
         )
-        .await
-    {
-        Ok(_) => Ok(JsonResponse(json!({
-            "data": {
-                "pipe_id": payload.pipe_id,
-                "message": "pipe started"
-            },
-            "success": true
-        }))),
-        Err(e) => Err((
-            StatusCode::BAD_REQUEST,
-            JsonResponse(json!({
-                "error": format!("failed to start pipe: {}", e),
-                "success": false
-            })),
-        )),
-    }
+        .await?;
+    publish_settings(&state, &headers).await;
+    Ok(JsonResponse(json!({
+        "data": {
+            "pipe_id": payload.pipe_id,
+            "message": "pipe started"
+        },
+        "success": true
+    })))
 }
 
 async fn stop_pipe_handler(
     State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
     JsonResponse(payload): JsonResponse<RunPipeRequest>,
-) -> Result<JsonResponse<Value>, (StatusCode, JsonResponse<Value>)> {
+) -> Result<JsonResponse<Value>, ApiError> {
     debug!("Stopping pipe: {}", payload.pipe_id);
-    match state
+    state
         .pipe_manager
         .update_config(
             &payload.pipe_id,
@@ -721,70 +1677,96 @@ async fn stop_pipe_handler(
                 "enabled": false,
             }),
         )
-        .await
-    {
-        Ok(_) => Ok(JsonResponse(json!({
-            "data": {
-                "pipe_id": payload.pipe_id,
-                "message": "pipe stopped"
-            },
-            "success": true
-        }))),
-        Err(e) => Err((
-            StatusCode::BAD_REQUEST,
-            JsonResponse(json!({
-                "error": format!("failed to stop pipe: {}", e),
-                "success": false
-            })),
-        )),
-    }
+        .await?;
+    publish_settings(&state, &headers).await;
+    Ok(JsonResponse(json!({
+        "data": {
+            "pipe_id": payload.pipe_id,
+            "message": "pipe stopped"
+        },
+        "success": true
+    })))
 }
 
 async fn update_pipe_config_handler(
     State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
     JsonResponse(payload): JsonResponse<UpdatePipeConfigRequest>,
-) -> Result<JsonResponse<Value>, (StatusCode, JsonResponse<Value>)> {
+) -> Result<JsonResponse<Value>, ApiError> {
     debug!("Updating pipe config for: {}", payload.pipe_id);
-    match state
+    state
         .pipe_manager
         .update_config(&payload.pipe_id, payload.config)
-        .await
-    {
-        Ok(_) => Ok(JsonResponse(json!({
-            "data": {
-                "pipe_id": payload.pipe_id,
-                "message": "pipe config updated"
-            },
-            "success": true
-        }))),
-        Err(e) => Err((
-            StatusCode::BAD_REQUEST,
-            JsonResponse(json!({
-                "error": format!("failed to update pipe config: {}", e),
-                "success": false
-            })),
-        )),
+        .await?;
+    publish_settings(&state, &headers).await;
+    Ok(JsonResponse(json!({
+        "data": {
+            "pipe_id": payload.pipe_id,
+            "message": "pipe config updated"
+        },
+        "success": true
+    })))
+}
+
+/// Runs the operations in order and reports each one, the status is 200 even when some
+/// failed so the client always gets the per-operation results.
+async fn pipe_batch_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    JsonResponse(payload): JsonResponse<BatchRequest>,
+) -> Result<JsonResponse<BatchReport>, ApiError> {
+    if payload.operations.is_empty() {
+        return Err(ApiError::invalid_request("the batch has no operations"));
     }
+    debug!(
+        "running pipe batch of {} operations, atomic: {}",
+        payload.operations.len(),
+        payload.atomic
+    );
+    let report = run_batch(&state.pipe_manager, payload.operations, payload.atomic).await;
+    publish_settings(&state, &headers).await;
+    Ok(JsonResponse(report))
+}
+
+/// Brings the installed pipes to the lock in the body, 200 with the per-pipe results
+/// even when some failed or conflict.
+async fn pipe_sync_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    JsonResponse(payload): JsonResponse<SyncRequest>,
+) -> Result<JsonResponse<SyncReport>, ApiError> {
+    debug!(
+        "syncing pipes to a lock of {} pipes, prune: {}, force: {}",
+        payload.lock.pipes.len(),
+        payload.prune,
+        payload.force
+    );
+    let report = sync_pipes(
+        &state.pipe_manager,
+        &payload.lock,
+        payload.prune,
+        payload.force,
+    )
+    .await
+    .map_err(ApiError::invalid_request)?;
+    publish_settings(&state, &headers).await;
+    Ok(JsonResponse(report))
 }
 
 async fn get_pipe_info_handler(
     State(state): State<Arc<AppState>>,
     Path(pipe_id): Path<String>,
-) -> Result<JsonResponse<Value>, (StatusCode, JsonResponse<Value>)> {
+) -> Result<JsonResponse<Value>, ApiError> {
     debug!("Getting pipe info for: {}", pipe_id);
-    match state.pipe_manager.get_pipe_info(&pipe_id).await {
-        Some(info) => Ok(JsonResponse(json!({
-            "data": info,
-            "success": true
-        }))),
-        None => Err((
-            StatusCode::NOT_FOUND,
-            JsonResponse(json!({
-                "error": "pipe not found",
-                "success": false
-            })),
-        )),
-    }
+    let info = state
+        .pipe_manager
+        .get_pipe_info(&pipe_id)
+        .await
+        .ok_or(PipeError::NotFound(pipe_id))?;
+    Ok(JsonResponse(json!({
+        "data": info,
+        "success": true
+    })))
 }
 
 async fn list_pipes_handler(State(state): State<Arc<AppState>>) -> JsonResponse<Value> {
@@ -796,6 +1778,121 @@ async fn list_pipes_handler(State(state): State<Arc<AppState>>) -> JsonResponse<
     }))
 }
 
+/// Json schema of pipe.json, for editors to validate and complete manifests with.
+async fn pipe_manifest_schema_handler() -> JsonResponse<Value> {
+    JsonResponse(manifest_schema().clone())
+}
+
+/// Json schema of a pipe's settings, for the app to render its settings form with.
+async fn get_pipe_config_schema_handler(
+    State(state): State<Arc<AppState>>,
+    Path(pipe_id): Path<String>,
+) -> Result<JsonResponse<Value>, ApiError> {
+    let schema = state.pipe_manager.config_schema(&pipe_id).await?;
+    Ok(JsonResponse(schema))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PipePermissionsRequest {
+    #[serde(default)]
+    approve: Vec<String>,
+    #[serde(default)]
+    deny: Vec<String>,
+}
+
+async fn get_pipe_permissions_handler(
+    State(state): State<Arc<AppState>>,
+    Path(pipe_id): Path<String>,
+) -> JsonResponse<Value> {
+    let granted = state.pipe_manager.permissions().granted(&pipe_id).await;
+    JsonResponse(json!({
+        "data": { "granted": granted },
+        "success": true
+    }))
+}
+
+async fn update_pipe_permissions_handler(
+    State(state): State<Arc<AppState>>,
+    Path(pipe_id): Path<String>,
+    JsonResponse(request): JsonResponse<PipePermissionsRequest>,
+) -> Result<JsonResponse<Value>, (StatusCode, JsonResponse<Value>)> {
+    debug!(
+        "permissions for pipe {}: approve {:?}, deny {:?}",
+        pipe_id, request.approve, request.deny
+    );
+    state
+        .pipe_manager
+        .permissions()
+        .decide(&pipe_id, request.approve, request.deny)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                JsonResponse(json!({
+                    "error": format!("failed to save permissions: {}", e),
+                    "success": false
+                })),
+            )
+        })?;
+    let granted = state.pipe_manager.permissions().granted(&pipe_id).await;
+    Ok(JsonResponse(json!({
+        "data": { "granted": granted },
+        "success": true
+    })))
+}
+
+async fn revoke_pipe_permissions_handler(
+    State(state): State<Arc<AppState>>,
+    Path(pipe_id): Path<String>,
+) -> Result<JsonResponse<Value>, (StatusCode, JsonResponse<Value>)> {
+    state
+        .pipe_manager
+        .permissions()
+        .revoke(&pipe_id)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                JsonResponse(json!({
+                    "error": format!("failed to revoke permissions: {}", e),
+                    "success": false
+                })),
+            )
+        })?;
+    let revoked = EventKind::PermissionRevoked {
+        pipe_id: pipe_id.clone(),
+    };
+    if let Err(e) = state.events.record(revoked).await {
+        error!("failed to record revoked permissions: {}", e);
+    }
+    Ok(JsonResponse(json!({
+        "message": "permissions revoked, the pipe will prompt again on next start",
+        "success": true
+    })))
+}
+
+async fn pipe_events_handler(
+    State(state): State<Arc<AppState>>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let mut events = state.pipe_manager.permissions().subscribe();
+    let stream = async_stream::stream! {
+        loop {
+            match events.recv().await {
+                Ok(event) => match serde_json::to_string(&event) {
+                    Ok(json) => yield Ok(Event::default().data(json)),
+                    Err(e) => error!("failed to serialize pipe event: {}", e),
+                },
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(skipped)) => {
+                    debug!("pipe events stream lagged, skipped {} events", skipped);
+                }
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    };
+
+    Sse::new(stream).keep_alive(axum::response::sse::KeepAlive::default())
+}
+
 pub struct Server {
     db: Arc<DatabaseManager>,
     addr: SocketAddr,
@@ -806,6 +1903,14 @@ pub struct Server {
     vision_disabled: bool,
     audio_disabled: bool,
     ui_monitoring_enabled: bool,
+    ocr_scheduler: Option<Arc<OcrScheduler>>,
+    media_volume: Option<Arc<MediaVolume>>,
+    archiver: Option<Arc<Archiver>>,
+    retention: Arc<RetentionManager>,
+    pipe_scheduler: Arc<PipeScheduler>,
+    sessions: Arc<SessionManager>,
+    telemetry: Arc<Telemetry>,
+    events: Arc<EventRecorder>,
 }
 
 impl Server {
@@ -820,6 +1925,14 @@ impl Server {
         vision_disabled: bool,
         audio_disabled: bool,
         ui_monitoring_enabled: bool,
+        ocr_scheduler: Option<Arc<OcrScheduler>>,
+        media_volume: Option<Arc<MediaVolume>>,
+        archiver: Option<Arc<Archiver>>,
+        retention: Arc<RetentionManager>,
+        pipe_scheduler: Arc<PipeScheduler>,
+        sessions: Arc<SessionManager>,
+        telemetry: Arc<Telemetry>,
+        events: Arc<EventRecorder>,
     ) -> Self {
         Server {
             db,
@@ -831,6 +1944,14 @@ impl Server {
             vision_disabled,
             audio_disabled,
             ui_monitoring_enabled,
+            ocr_scheduler,
+            media_volume,
+            archiver,
+            retention,
+            pipe_scheduler,
+            sessions,
+            telemetry,
+            events,
         }
     }
 
@@ -843,6 +1964,11 @@ impl Server {
     where
         F: Fn(&axum::http::Request<axum::body::Body>) + Clone + Send + Sync + 'static,
     {
+        let media_dir = self
+            .media_volume
+            .as_ref()
+            .map(|v| v.dir().to_path_buf())
+            .unwrap_or_else(|| self.screenpipe_dir.join("data"));
         let app_state = Arc::new(AppState {
             db: self.db.clone(),
             vision_control: self.vision_control,
@@ -856,13 +1982,21 @@ impl Server {
             ui_monitoring_enabled: self.ui_monitoring_enabled,
             frame_cache: if enable_frame_cache {
                 Some(Arc::new(
-                    FrameCache::new(self.screenpipe_dir.clone().join("data"), self.db.clone())
-                        .await
-                        .unwrap(),
+                    FrameCache::new(media_dir, self.db.clone()).await.unwrap(),
                 ))
             } else {
                 None
             },
+            ocr_scheduler: self.ocr_scheduler,
+            media_volume: self.media_volume,
+            archiver: self.archiver,
+            ranking: RankingWeights::load(&self.screenpipe_dir),
+            retention: self.retention,
+            pipe_scheduler: self.pipe_scheduler,
+            sessions: self.sessions,
+            telemetry: self.telemetry,
+            settings: Arc::new(SettingsWatch::new()),
+            events: self.events,
         });
 
         let app = create_router()
@@ -875,6 +2009,9 @@ impl Server {
                     .expose_headers([
                         axum::http::header::CONTENT_TYPE,
                         axum::http::header::CACHE_CONTROL,
+                        axum::http::HeaderName::from_static(API_VERSION_HEADER),
+                        axum::http::HeaderName::from_static(DEPRECATION_HEADER),
+                        axum::http::HeaderName::from_static(SUNSET_HEADER),
                     ]), // Important for SSE
             )
             .layer(
@@ -1106,7 +2243,7 @@ pub(crate) async fn add_to_database(
         "frames" => {
             if let ContentData::Frames(frames) = &payload.content.data {
                 if !frames.is_empty() {
-                    let output_dir = state.screenpipe_dir.join("data");
+                    let output_dir = state.media_dir();
                     let time = Utc::now();
                     let formatted_time = time.format("%Y-%m-%d_%H-%M-%S").to_string();
                     let video_file_path = PathBuf::from(output_dir)
@@ -1305,6 +2442,9 @@ pub struct DeviceFrameResponse {
     pub frame: String, // base64 encoded image
     pub metadata: DeviceMetadata,
     pub audio: Vec<AudioData>,
+    /// Set when `frame` is empty, e.g. for frames captured in text_only storage mode
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub no_image_reason: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -1356,6 +2496,7 @@ impl From<TimeSeriesFrame> for StreamTimeSeriesResponse {
                                 }
                             })
                             .collect(),
+                        no_image_reason: device_frame.no_image_reason,
                     }
                 })
                 .collect(),
@@ -1579,24 +2720,111 @@ pub fn create_router() -> Router<Arc<AppState>> {
         .expose_headers([
             axum::http::header::CONTENT_TYPE,
             axum::http::header::CACHE_CONTROL,
+            axum::http::HeaderName::from_static(API_VERSION_HEADER),
+            axum::http::HeaderName::from_static(DEPRECATION_HEADER),
+            axum::http::HeaderName::from_static(SUNSET_HEADER),
         ]); // Important for SSE
 
     let router = Router::new()
         .route("/search", get(search))
+        .route("/search/syntax", get(search_syntax_handler))
         .route("/audio/list", get(api_list_audio_devices))
         .route("/vision/list", post(api_list_monitors))
+        .route("/vision/metrics", get(ocr_metrics_handler))
+        .route("/latency/metrics", get(latency_metrics_handler))
+        .route("/db/metrics", get(db_metrics_handler))
+        .route("/video/metrics", get(video_metrics_handler))
+        .route("/models/metrics", get(model_metrics_handler))
+        .route("/rules/metrics", get(rule_metrics_handler))
+        .route("/models/unload", post(unload_models_handler))
         .route(
             "/tags/:content_type/:id",
             post(add_tags).delete(remove_tags),
         )
         .route("/pipes/info/:pipe_id", get(get_pipe_info_handler))
         .route("/pipes/list", get(list_pipes_handler))
+        .route("/pipes/manifest-schema", get(pipe_manifest_schema_handler))
         .route("/pipes/download", post(download_pipe_handler))
         .route("/pipes/enable", post(run_pipe_handler))
         .route("/pipes/disable", post(stop_pipe_handler))
         .route("/pipes/update", post(update_pipe_config_handler))
         .route("/pipes/delete", post(delete_pipe_handler))
+        .route("/pipes/batch", post(pipe_batch_handler))
+        .route("/pipes/sync", post(pipe_sync_handler))
+        .route("/pipes/events", get(pipe_events_handler))
+        .route(
+            "/pipes/content-types",
+            get(list_content_types_handler).post(register_content_type_handler),
+        )
+        .route("/pipes/content", post(insert_pipe_content_handler))
+        .route(
+            "/pipes/:pipe_id/schedule",
+            get(list_pipe_jobs_handler).post(schedule_pipe_job_handler),
+        )
+        .route(
+            "/pipes/:pipe_id/schedule/:job_id",
+            delete(cancel_pipe_job_handler),
+        )
+        .route(
+            "/pipes/:pipe_id/permissions",
+            get(get_pipe_permissions_handler)
+                .post(update_pipe_permissions_handler)
+                .delete(revoke_pipe_permissions_handler),
+        )
+        .route("/pipes/:pipe_id/stats", get(get_pipe_stats_handler))
+        .route(
+            "/pipes/:pipe_id/config-schema",
+            get(get_pipe_config_schema_handler),
+        )
+        .route("/sessions", get(list_sessions_handler))
+        .route("/sessions/current", get(current_session_handler))
+        .route("/sessions/start", post(start_session_handler))
+        .route("/sessions/stop", post(stop_session_handler))
+        .route("/sessions/events", get(session_events_handler))
+        .route("/events", get(list_events_handler))
+        .route("/events/stream", get(event_stream_handler))
+        .route("/usage/aggregate/apps", get(app_usage_handler))
+        .route("/usage/aggregate/captures", get(capture_counts_handler))
+        .route("/context/now", get(context_now_handler))
+        .route("/settings/watch", get(watch_settings_handler))
+        .route(
+            "/settings/rules",
+            get(get_tag_rules_handler).put(update_tag_rules_handler),
+        )
+        .route("/settings/rules/test", post(test_tag_rule_handler))
         .route("/health", get(health_check))
+        .route("/deprecations", get(deprecations_handler))
+        .route(
+            "/retention",
+            get(get_retention_handler).post(update_retention_handler),
+        )
+        .route("/retention/report", get(retention_report_handler))
+        .route("/privacy/summary", get(privacy_summary_handler))
+        .route("/privacy/apps/:app_name/ignore", post(ignore_app_handler))
+        .route(
+            "/privacy/apps/:app_name/data",
+            delete(delete_app_data_handler),
+        )
+        .route(
+            "/privacy/apps/:app_name/retention",
+            post(set_app_retention_handler).delete(clear_app_retention_handler),
+        )
+        .route(
+            "/storage/mode",
+            get(get_storage_mode_handler).post(update_storage_mode_handler),
+        )
+        .route(
+            "/telemetry",
+            get(get_telemetry_handler).post(update_telemetry_handler),
+        )
+        .route("/telemetry/preview", get(telemetry_preview_handler))
+        .route("/telemetry/log", get(telemetry_log_handler))
+        .route("/frames/:frame_id", get(get_frame_handler))
+        .route("/frames/:frame_id/find", get(find_in_frame_handler))
+        .route("/content/:id/copy", get(copy_content_handler))
+        .route("/deep-links", get(deep_links_handler))
+        .route("/deep-links/resolve", get(resolve_deep_link_handler))
+        .route("/audio/:chunk_id", get(get_audio_handler))
         .route("/raw_sql", post(execute_raw_sql))
         .route("/add", post(add_to_database))
         .route("/stream/frames", get(stream_frames_handler))
@@ -1615,11 +2843,13 @@ pub fn create_router() -> Router<Arc<AppState>> {
         .layer(cors);
 
     #[cfg(feature = "experimental")]
-    {
-        router = router.route("/experimental/input_control", post(input_control_handler));
-    }
+    let router = router.route("/experimental/input_control", post(input_control_handler));
 
-    router
+    // Pipes holding a scope only reach the routes it allows
+    let router = with_pipe_scopes(router, pipe_tokens());
+
+    // Mounted under /v1, the unversioned paths stay as aliases for older clients
+    with_api_version(with_problem_details(router), api_versioning())
 }
 
 // Add the new handler
@@ -1708,36 +2938,812 @@ async fn stream_frames_handler(
     )
 }
 
+pub async fn get_retention_handler(State(state): State<Arc<AppState>>) -> Json<RetentionSettings> {
+    Json(state.retention.settings().await)
+}
+
+/// Replaces the retention settings. Overrides projected to outgrow the disk budget
+/// are saved anyway and reported in `warnings`.
+pub async fn update_retention_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(settings): Json<RetentionSettings>,
+) -> Result<Json<Value>, ApiError> {
+    let settings = settings.validated().map_err(ApiError::invalid_request)?;
+    let warnings = state
+        .retention
+        .update(settings.clone(), Utc::now())
+        .await
+        .map_err(|e| {
+            error!("failed to update retention settings: {}", e);
+            ApiError::internal(format!("failed to update retention settings: {}", e))
+        })?;
+    publish_settings(&state, &headers).await;
+    Ok(Json(json!({
+        "success": true,
+        "settings": settings,
+        "warnings": warnings,
+    })))
+}
+
+pub async fn retention_report_handler(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<RetentionReport>, ApiError> {
+    let report = state.retention.report(Utc::now()).await.map_err(|e| {
+        error!("failed to build retention report: {}", e);
+        ApiError::internal(format!("failed to build retention report: {}", e))
+    })?;
+    Ok(Json(report))
+}
+
+pub async fn get_tag_rules_handler(State(state): State<Arc<AppState>>) -> Json<TagRulesConfig> {
+    Json(state.db.tag_rules().config().clone())
+}
+
+/// Replaces the tag rules. They apply to content captured from now on.
+pub async fn update_tag_rules_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(config): Json<TagRulesConfig>,
+) -> Result<Json<Value>, ApiError> {
+    let rules = TagRules::compile(config).map_err(ApiError::invalid_request)?;
+    rules.config().save(&state.screenpipe_dir).map_err(|e| {
+        error!("failed to save tag rules: {}", e);
+        ApiError::internal(format!("failed to save tag rules: {}", e))
+    })?;
+    let config = rules.config().clone();
+    state.db.set_tag_rules(rules);
+    publish_settings(&state, &headers).await;
+    Ok(Json(json!({
+        "success": true,
+        "rules": config,
+    })))
+}
+
+#[derive(Deserialize)]
+pub(crate) struct TestTagRuleRequest {
+    rule: TagRule,
+    /// Recent frames and audio chunks to run it on, of each
+    #[serde(default)]
+    limit: Option<u32>,
+}
+
+/// Runs a rule on recent content without saving it or changing the content.
+pub async fn test_tag_rule_handler(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<TestTagRuleRequest>,
+) -> Result<Json<RuleTestReport>, ApiError> {
+    let limit = request.limit.unwrap_or(DEFAULT_TEST_ROWS);
+    if limit == 0 || limit > MAX_TEST_ROWS {
+        return Err(ApiError::invalid_request(format!(
+            "limit must be between 1 and {}",
+            MAX_TEST_ROWS
+        )));
+    }
+    let content_types = match request.rule.when.content {
+        Some(content) => vec![content],
+        None => vec![TagContentType::Vision, TagContentType::Audio],
+    };
+    let mut samples = Vec::new();
+    for content in content_types {
+        let rows = state
+            .db
+            .get_rule_samples(content, limit)
+            .await
+            .map_err(|e| {
+                error!("failed to read content to test a rule on: {}", e);
+                ApiError::internal(format!("failed to read content: {}", e))
+            })?;
+        samples.extend(RuleSample::group(content, rows));
+    }
+    let budget_us = state.db.tag_rules().config().budget_us;
+    let report = test_rule(request.rule, budget_us, &samples).map_err(ApiError::invalid_request)?;
+    Ok(Json(report))
+}
+
+/// What is kept about each app, from the rollups the background job maintains.
+pub async fn privacy_summary_handler(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<PrivacySummary>, ApiError> {
+    let settings = state.retention.settings().await;
+    let summary = privacy::summary(&state.db, &settings, Utc::now())
+        .await
+        .map_err(|e| {
+            error!("failed to build privacy summary: {}", e);
+            ApiError::internal(format!("failed to build privacy summary: {}", e))
+        })?;
+    Ok(Json(summary))
+}
+
+/// An app name from the path, normalized as retention overrides and the ignore list
+/// match it.
+fn privacy_app_name(app_name: &str) -> Result<String, ApiError> {
+    let normalized = normalize_app_name(app_name);
+    if normalized.is_empty() {
+        return Err(ApiError::invalid_request(format!(
+            "'{}' is not an app name",
+            app_name
+        )));
+    }
+    Ok(normalized)
+}
+
+#[derive(Deserialize)]
+pub(crate) struct IgnoreAppRequest {
+    #[serde(default = "default_ignored")]
+    ignored: bool,
+}
+
+fn default_ignored() -> bool {
+    true
+}
+
+/// Adds the app to the ignore list, or takes it off with `"ignored": false`. Its text
+/// stops being stored from the next frame.
+pub async fn ignore_app_handler(
+    State(state): State<Arc<AppState>>,
+    Path(app_name): Path<String>,
+    headers: HeaderMap,
+    Json(request): Json<IgnoreAppRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let app_name = privacy_app_name(&app_name)?;
+    let changed = ignored_apps()
+        .set(&state.screenpipe_dir, &app_name, request.ignored)
+        .map_err(|e| {
+            error!("failed to save ignored apps: {}", e);
+            ApiError::internal(format!("failed to save ignored apps: {}", e))
+        })?;
+    if changed {
+        publish_settings(&state, &headers).await;
+    }
+    Ok(Json(json!({
+        "success": true,
+        "app_name": app_name,
+        "ignored": request.ignored,
+        "ignored_apps": ignored_apps().list(),
+    })))
+}
+
+/// Deletes every frame and ui text captured of the app, see
+/// [`privacy::delete_app_data`]. Refused while the media directory is unavailable,
+/// the files couldn't be removed.
+pub async fn delete_app_data_handler(
+    State(state): State<Arc<AppState>>,
+    Path(app_name): Path<String>,
+) -> Result<Json<AppDeletion>, ApiError> {
+    let app_name = privacy_app_name(&app_name)?;
+    if state
+        .media_volume
+        .as_ref()
+        .is_some_and(|v| !v.is_available())
+    {
+        return Err(ApiError::new(
+            ErrorCode::Unavailable,
+            "the media directory is unavailable, its files can't be deleted",
+        ));
+    }
+    let deletion = privacy::delete_app_data(&state.db, &app_name)
+        .await
+        .map_err(|e| {
+            error!("failed to delete data of {}: {}", app_name, e);
+            ApiError::internal(format!("failed to delete data of {}: {}", app_name, e))
+        })?;
+    info!(
+        "deleted data of {}: {} frames, {} ui rows, {} files",
+        app_name, deletion.frames, deletion.ui_rows, deletion.files_removed
+    );
+    // So the summary shows the deletion without waiting for the next run
+    if let Err(e) = privacy::update_rollups(&state.db, Utc::now()).await {
+        error!("failed to update app rollups: {}", e);
+    }
+    Ok(Json(deletion))
+}
+
+/// Sets the app's own retention policy, `{"max_age_days": null}` keeping it forever.
+pub async fn set_app_retention_handler(
+    State(state): State<Arc<AppState>>,
+    Path(app_name): Path<String>,
+    headers: HeaderMap,
+    Json(policy): Json<RetentionPolicy>,
+) -> Result<Json<Value>, ApiError> {
+    let app_name = privacy_app_name(&app_name)?;
+    let mut settings = state.retention.settings().await;
+    settings.apps.insert(app_name, policy);
+    update_retention_handler(State(state), headers, Json(settings)).await
+}
+
+/// Removes the app's own retention policy, the global one applies again.
+pub async fn clear_app_retention_handler(
+    State(state): State<Arc<AppState>>,
+    Path(app_name): Path<String>,
+    headers: HeaderMap,
+) -> Result<Json<Value>, ApiError> {
+    let app_name = privacy_app_name(&app_name)?;
+    let mut settings = state.retention.settings().await;
+    settings.apps.remove(&app_name);
+    update_retention_handler(State(state), headers, Json(settings)).await
+}
+
+pub async fn get_telemetry_handler(State(state): State<Arc<AppState>>) -> Json<TelemetryStatus> {
+    Json(state.telemetry.status())
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct TelemetryRequest {
+    enabled: bool,
+}
+
+/// Opts in or out of the daily usage pings.
+pub async fn update_telemetry_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(request): Json<TelemetryRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let previous = state.telemetry.set_enabled(request.enabled).map_err(|e| {
+        error!("failed to save telemetry settings: {}", e);
+        ApiError::internal(format!("failed to save telemetry settings: {}", e))
+    })?;
+    if previous != request.enabled {
+        info!(
+            "usage pings {}",
+            if request.enabled {
+                "enabled"
+            } else {
+                "disabled"
+            }
+        );
+        publish_settings(&state, &headers).await;
+    }
+    Ok(Json(json!({
+        "success": true,
+        "previous": previous,
+        "status": state.telemetry.status(),
+    })))
+}
+
+/// The payload the next usage ping sends, whether or not pings are on.
+pub async fn telemetry_preview_handler(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<UsagePayload>, ApiError> {
+    let payload = state.telemetry.preview(Utc::now()).await.map_err(|e| {
+        error!("failed to build usage ping: {}", e);
+        ApiError::internal(format!("failed to build usage ping: {}", e))
+    })?;
+    Ok(Json(payload))
+}
+
+/// Every usage ping sent, oldest first.
+pub async fn telemetry_log_handler(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<SentPing>>, ApiError> {
+    let sent = state.telemetry.sent().map_err(|e| {
+        error!("failed to read the telemetry log: {}", e);
+        ApiError::internal(format!("failed to read the telemetry log: {}", e))
+    })?;
+    Ok(Json(sent))
+}
+
 // Add this new handler function
+/// Deletes a pipe along with the content types and records it added to the index,
+/// unless `keep_content` is set.
 pub async fn delete_pipe_handler(
     State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
     Json(request): Json<DeletePipeRequest>,
-) -> impl IntoResponse {
-    match state.pipe_manager.delete_pipe(&request.pipe_id).await {
-        Ok(_) => (
-            StatusCode::OK,
-            Json(json!({
-                "success": true,
-                "message": "pipe deleted successfully"
-            })),
-        ),
-        Err(e) => {
-            error!("failed to delete pipe: {}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({
-                "success": false,
-                "error": format!("failed to delete pipe: {}", e)
-                })),
+) -> Result<Json<Value>, ApiError> {
+    state.pipe_manager.delete_pipe(&request.pipe_id).await?;
+    publish_settings(&state, &headers).await;
+    let (content_types, records) = if request.keep_content {
+        (0, 0)
+    } else {
+        state.db.delete_pipe_content(&request.pipe_id).await?
+    };
+    Ok(Json(json!({
+        "success": true,
+        "message": "pipe deleted successfully",
+        "removed_content_types": content_types,
+        "removed_records": records,
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PipeJobsQuery {
+    #[serde(default)]
+    status: Option<PipeJobStatus>,
+}
+
+pub async fn schedule_pipe_job_handler(
+    State(state): State<Arc<AppState>>,
+    Path(pipe_id): Path<String>,
+    Json(request): Json<ScheduleRequest>,
+) -> Result<Json<PipeJob>, ApiError> {
+    if state.pipe_manager.get_pipe_info(&pipe_id).await.is_none() {
+        return Err(PipeError::NotFound(pipe_id).into());
+    }
+    Ok(Json(
+        state.pipe_scheduler.schedule(&pipe_id, request).await?,
+    ))
+}
+
+pub async fn list_pipe_jobs_handler(
+    State(state): State<Arc<AppState>>,
+    Path(pipe_id): Path<String>,
+    Query(query): Query<PipeJobsQuery>,
+) -> Result<Json<Vec<PipeJob>>, ApiError> {
+    Ok(Json(
+        state.pipe_scheduler.list(&pipe_id, query.status).await?,
+    ))
+}
+
+pub async fn cancel_pipe_job_handler(
+    State(state): State<Arc<AppState>>,
+    Path((pipe_id, job_id)): Path<(String, i64)>,
+) -> Result<Json<PipeJob>, ApiError> {
+    Ok(Json(state.pipe_scheduler.cancel(&pipe_id, job_id).await?))
+}
+
+/// What a pipe sent and received through its network proxy, per host.
+pub async fn get_pipe_stats_handler(
+    State(state): State<Arc<AppState>>,
+    Path(pipe_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let hosts = state.db.get_pipe_network_stats(&pipe_id).await?;
+    let bytes_up: i64 = hosts.iter().map(|host| host.bytes_up).sum();
+    let bytes_down: i64 = hosts.iter().map(|host| host.bytes_down).sum();
+    let blocked: i64 = hosts.iter().map(|host| host.blocked).sum();
+    Ok(Json(json!({
+        "pipe_id": pipe_id,
+        "network": {
+            "proxied": state.pipe_manager.network_proxy_enabled(),
+            "bytes_up": bytes_up,
+            "bytes_down": bytes_down,
+            "blocked": blocked,
+            "hosts": hosts,
+        },
+    })))
+}
+
+#[derive(Deserialize)]
+pub(crate) struct SessionsQuery {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(flatten)]
+    pagination: PaginationQuery,
+}
+
+/// Starts a named capture session, refused while another one is open.
+pub async fn start_session_handler(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<StartSessionRequest>,
+) -> Result<Json<CaptureSession>, ApiError> {
+    Ok(Json(state.sessions.start(request).await?))
+}
+
+/// Ends the open capture session, after exporting it if it was started with
+/// `auto_export`.
+pub async fn stop_session_handler(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<CaptureSession>, ApiError> {
+    Ok(Json(state.sessions.stop().await?))
+}
+
+pub async fn current_session_handler(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Option<CaptureSession>>, ApiError> {
+    Ok(Json(state.sessions.current().await?))
+}
+
+pub(crate) async fn list_sessions_handler(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<SessionsQuery>,
+) -> Result<Json<Vec<CaptureSession>>, ApiError> {
+    Ok(Json(
+        state
+            .sessions
+            .list(
+                query.name.as_deref(),
+                query.pagination.limit,
+                query.pagination.offset,
             )
+            .await?,
+    ))
+}
+
+async fn session_events_handler(
+    State(state): State<Arc<AppState>>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let mut events = state.sessions.subscribe();
+    let stream = async_stream::stream! {
+        loop {
+            match events.recv().await {
+                Ok(event) => match serde_json::to_string(&event) {
+                    Ok(json) => yield Ok(Event::default().data(json)),
+                    Err(e) => error!("failed to serialize session event: {}", e),
+                },
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(skipped)) => {
+                    debug!("session events stream lagged, skipped {} events", skipped);
+                }
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+            }
         }
+    };
+
+    Sse::new(stream).keep_alive(axum::response::sse::KeepAlive::default())
+}
+
+/// Events `GET /events` returns when the request doesn't say.
+const DEFAULT_EVENTS_LIMIT: u32 = 100;
+
+const MAX_EVENTS_LIMIT: u32 = 1000;
+
+#[derive(Deserialize)]
+pub(crate) struct EventsQuery {
+    /// Comma separated, any type when missing
+    #[serde(default, rename = "type")]
+    event_type: Option<String>,
+    #[serde(default)]
+    since: Option<DateTime<Utc>>,
+    #[serde(default)]
+    until: Option<DateTime<Utc>>,
+    /// This severity and above
+    #[serde(default)]
+    severity: Option<String>,
+    #[serde(default)]
+    limit: Option<u32>,
+}
+
+impl EventsQuery {
+    fn filter(&self) -> Result<EventFilter, ApiError> {
+        let types: Vec<String> = self
+            .event_type
+            .iter()
+            .flat_map(|types| types.split(','))
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+            .map(String::from)
+            .collect();
+        if let Some(unknown) = types.iter().find(|t| !EVENT_TYPES.contains(&t.as_str())) {
+            return Err(ApiError::invalid_request(format!(
+                "unknown event type '{}', expected one of: {}",
+                unknown,
+                EVENT_TYPES.join(", ")
+            )));
+        }
+        let severity = self
+            .severity
+            .as_deref()
+            .map(str::parse::<EventSeverity>)
+            .transpose()
+            .map_err(ApiError::invalid_request)?;
+        Ok(EventFilter {
+            types,
+            since: self.since,
+            until: self.until,
+            severity,
+            after_id: None,
+            limit: self
+                .limit
+                .unwrap_or(DEFAULT_EVENTS_LIMIT)
+                .clamp(1, MAX_EVENTS_LIMIT),
+        })
+    }
+}
+
+/// Recorded events, newest first.
+pub(crate) async fn list_events_handler(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<EventsQuery>,
+) -> Result<Json<Vec<RecordedEvent>>, ApiError> {
+    let events = state.events.query(&query.filter()?).await.map_err(|e| {
+        error!("failed to read events: {}", e);
+        ApiError::internal(format!("failed to read events: {}", e))
+    })?;
+    Ok(Json(events))
+}
+
+/// Events as they are recorded, as sse named by their type. Takes the filters of
+/// `GET /events` but `limit`. A client reconnecting with `Last-Event-ID` first gets the
+/// events it missed, from the database.
+async fn event_stream_handler(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<EventsQuery>,
+    headers: HeaderMap,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    let filter = query.filter()?;
+    let last_event_id = headers
+        .get("last-event-id")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<i64>().ok());
+    // Before reading the missed events, so none falls between the two
+    let mut live = state.events.subscribe();
+    let missed = match last_event_id {
+        Some(after_id) => {
+            let missed = EventFilter {
+                after_id: Some(after_id),
+                limit: MAX_EVENTS_LIMIT,
+                ..filter.clone()
+            };
+            state.events.query(&missed).await.map_err(|e| {
+                error!("failed to read missed events: {}", e);
+                ApiError::internal(format!("failed to read missed events: {}", e))
+            })?
+        }
+        None => Vec::new(),
+    };
+
+    let stream = async_stream::stream! {
+        let mut last_id = last_event_id.unwrap_or(0);
+        for event in missed {
+            last_id = event.id;
+            if let Some(sse) = to_sse(&event) {
+                yield Ok(sse);
+            }
+        }
+        loop {
+            match live.recv().await {
+                Ok(event) if event.id > last_id && filter.matches(&event) => {
+                    last_id = event.id;
+                    if let Some(sse) = to_sse(&event) {
+                        yield Ok(sse);
+                    }
+                }
+                Ok(_) => {}
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(skipped)) => {
+                    debug!("event stream lagged, skipped {} events", skipped);
+                }
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    };
+
+    Ok(Sse::new(stream).keep_alive(axum::response::sse::KeepAlive::default()))
+}
+
+fn to_sse(event: &RecordedEvent) -> Option<Event> {
+    Event::default()
+        .id(event.id.to_string())
+        .event(event.kind.event_type())
+        .json_data(event)
+        .map_err(|e| error!("failed to serialize event {}: {}", event.id, e))
+        .ok()
+}
+
+/// Every setting `GET /settings/watch` covers, by key.
+async fn current_settings(state: &AppState) -> BTreeMap<String, Value> {
+    let mut values = pipe_settings(&state.pipe_manager.list_pipes().await);
+    values.insert(
+        "retention".to_string(),
+        serde_json::to_value(state.retention.settings().await).unwrap_or_default(),
+    );
+    values.insert(
+        "rules".to_string(),
+        serde_json::to_value(state.db.tag_rules().config()).unwrap_or_default(),
+    );
+    values.insert("storage.mode".to_string(), json!(storage_mode().get()));
+    values.insert(
+        "privacy.ignored_apps".to_string(),
+        json!(ignored_apps().list()),
+    );
+    values.insert(
+        "telemetry.enabled".to_string(),
+        json!(state.telemetry.status().enabled),
+    );
+    values
+}
+
+/// Hands the settings to the watch after a change, from the client the request names.
+async fn publish_settings(state: &AppState, headers: &HeaderMap) {
+    let source = headers.get(CLIENT_HEADER).and_then(|v| v.to_str().ok());
+    let changes = state.settings.update(current_settings(state).await, source);
+    if !changes.is_empty() {
+        debug!("{} settings changed", changes.len());
+    }
+}
+
+#[derive(Deserialize)]
+pub(crate) struct WatchSettingsQuery {
+    /// Comma separated keys or patterns, every setting when left out
+    #[serde(default)]
+    keys: Option<String>,
+}
+
+/// The watched settings as sse: a `snapshot` event with their values, then `change`
+/// events. A client reconnecting with `Last-Event-ID` gets the changes it missed.
+async fn watch_settings_handler(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<WatchSettingsQuery>,
+    headers: HeaderMap,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let patterns: Vec<String> = match &query.keys {
+        Some(keys) => keys
+            .split(',')
+            .map(str::trim)
+            .filter(|key| !key.is_empty())
+            .map(String::from)
+            .collect(),
+        None => vec!["*".to_string()],
+    };
+    let last_event_id = headers
+        .get("last-event-id")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u64>().ok());
+
+    // Picks up what changed without going through the api, e.g. a pipe's own pipe.json
+    state.settings.update(current_settings(&state).await, None);
+    let events = state.settings.watch(patterns, last_event_id);
+    let stream = async_stream::stream! {
+        futures::pin_mut!(events);
+        while let Some(event) = events.next().await {
+            let name = match &event {
+                WatchEvent::Snapshot { .. } => "snapshot",
+                WatchEvent::Change { .. } => "change",
+            };
+            match Event::default().id(event.id().to_string()).event(name).json_data(&event) {
+                Ok(event) => yield Ok(event),
+                Err(e) => error!("failed to serialize settings event: {}", e),
+            }
+        }
+    };
+
+    Sse::new(stream).keep_alive(axum::response::sse::KeepAlive::default())
+}
+
+/// Span usage aggregates cover when the request doesn't say.
+const DEFAULT_USAGE_DAYS: i64 = 7;
+
+#[derive(Deserialize)]
+pub(crate) struct UsageQuery {
+    #[serde(default)]
+    start_time: Option<DateTime<Utc>>,
+    #[serde(default)]
+    end_time: Option<DateTime<Utc>>,
+    #[serde(default)]
+    bucket: UsageBucket,
+}
+
+impl UsageQuery {
+    /// The requested span, the last week up to now by default.
+    fn span(&self) -> Result<(DateTime<Utc>, DateTime<Utc>), ApiError> {
+        let end = self.end_time.unwrap_or_else(Utc::now);
+        let start = self
+            .start_time
+            .unwrap_or_else(|| end - chrono::Duration::days(DEFAULT_USAGE_DAYS));
+        if start >= end {
+            return Err(ApiError::invalid_request(
+                "start_time must be before end_time",
+            ));
+        }
+        Ok((start, end))
+    }
+}
+
+/// Time per focused app per bucket, no captured content.
+pub(crate) async fn app_usage_handler(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<UsageQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let (start, end) = query.span()?;
+    let apps = state.db.app_usage(start, end, query.bucket).await?;
+    Ok(Json(json!({
+        "start_time": start,
+        "end_time": end,
+        "bucket": query.bucket,
+        "apps": apps,
+    })))
+}
+
+/// Frames and transcriptions captured per bucket.
+pub(crate) async fn capture_counts_handler(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<UsageQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let (start, end) = query.span()?;
+    let counts = state.db.capture_counts(start, end, query.bucket).await?;
+    Ok(Json(json!({
+        "start_time": start,
+        "end_time": end,
+        "bucket": query.bucket,
+        "captures": counts,
+    })))
+}
+
+#[derive(Deserialize)]
+pub(crate) struct ContextQuery {
+    #[serde(default)]
+    lookback_seconds: Option<u64>,
+    #[serde(default)]
+    max_chars: Option<usize>,
+}
+
+/// What's on screen and being said now, with the open session and the latest pipe
+/// results flagged as context, in one call.
+pub(crate) async fn context_now_handler(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<ContextQuery>,
+) -> Result<Json<ContextBundle>, ApiError> {
+    let lookback = query
+        .lookback_seconds
+        .map_or(context::DEFAULT_LOOKBACK, Duration::from_secs);
+    if lookback.is_zero() || lookback > context::MAX_LOOKBACK {
+        return Err(ApiError::invalid_request(format!(
+            "lookback_seconds must be between 1 and {}",
+            context::MAX_LOOKBACK.as_secs()
+        )));
+    }
+    let max_chars = query.max_chars.unwrap_or(context::DEFAULT_MAX_CHARS);
+    if max_chars == 0 || max_chars > context::MAX_CHARS {
+        return Err(ApiError::invalid_request(format!(
+            "max_chars must be between 1 and {}",
+            context::MAX_CHARS
+        )));
     }
+    let bundle = context::assemble(&state.db, Utc::now(), lookback, max_chars).await?;
+    Ok(Json(bundle))
 }
 
 // Add this struct for the request payload
 #[derive(Debug, Deserialize)]
 pub struct DeletePipeRequest {
     pipe_id: String,
+    /// Keep the pipe's records searchable after it is gone
+    #[serde(default)]
+    keep_content: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RegisterContentTypeRequest {
+    pipe_id: String,
+    name: String,
+    schema: Value,
+    /// Needed when the new schema removes or retypes fields
+    #[serde(default)]
+    migration: Option<SchemaMigration>,
+}
+
+pub async fn list_content_types_handler(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<ContentTypeInfo>>, ApiError> {
+    Ok(Json(pipe_content::list_content_types(&state.db).await?))
+}
+
+pub async fn register_content_type_handler(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<RegisterContentTypeRequest>,
+) -> Result<Json<Registration>, ApiError> {
+    if state
+        .pipe_manager
+        .get_pipe_info(&request.pipe_id)
+        .await
+        .is_none()
+    {
+        return Err(PipeError::NotFound(request.pipe_id).into());
+    }
+    let schema =
+        ContentSchema::from_json_schema(&request.schema).map_err(ApiError::invalid_request)?;
+    let registration = pipe_content::register_content_type(
+        &state.db,
+        &request.pipe_id,
+        &request.name,
+        schema,
+        request.migration,
+    )
+    .await?;
+    info!(
+        "pipe {} registered content type {} v{}",
+        request.pipe_id, registration.content_type.name, registration.content_type.version
+    );
+    Ok(Json(registration))
+}
+
+pub async fn insert_pipe_content_handler(
+    State(state): State<Arc<AppState>>,
+    Json(record): Json<NewPipeContent>,
+) -> Result<Json<Value>, ApiError> {
+    let id = pipe_content::insert_content(&state.db, &record).await?;
+    Ok(Json(json!({
+        "success": true,
+        "id": id,
+    })))
 }
 
 #[derive(Deserialize, Debug)]
@@ -0,0 +1,584 @@
+//! Named capture sessions, e.g. "ux interview #4", started and stopped by hand so
+//! a span of recording can be found, exported and summarized as one piece.
+//!
+//! Captures belong to a session by time: everything recorded between its start and
+//! its end, there is nothing to tag on the write path. One session is open at a time.
+//! A session left open past the longest allowed duration is closed with a warning.
+//! When a session ends it is exported to markdown or json if asked at start, then the
+//! enabled pipes that subscribed to session events are run once with the event.
+
+use crate::db_types::{CaptureSession, ContentType, ExportFormat, SearchResult, SessionEndReason};
+use crate::pipe_manager::PipeInfo;
+use crate::pipe_schedule::JOB_TIMEOUT;
+use crate::{DatabaseManager, PipeManager};
+use anyhow::Result;
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{broadcast, Notify};
+use tracing::{error, info, warn};
+
+/// Longest a session stays open unless configured otherwise.
+pub const DEFAULT_MAX_DURATION: Duration = Duration::from_secs(4 * 3600);
+
+pub const MAX_NAME_CHARS: usize = 200;
+
+/// Longest the auto-close task waits before looking at the open session again.
+const MAX_WAIT: Duration = Duration::from_secs(30);
+
+/// Rows read per search while exporting.
+const EXPORT_PAGE: u32 = 1000;
+
+/// Most screen and audio rows an export reads, each.
+const MAX_EXPORT_ROWS: usize = 200_000;
+
+/// Body of `POST /sessions/start`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct StartSessionRequest {
+    pub name: String,
+    #[serde(default)]
+    pub auto_export: Option<ExportFormat>,
+}
+
+/// Published on `GET /sessions/events`. Pipes get `session_started` and
+/// `session_ended` in `PIPE_EVENT`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SessionEvent {
+    SessionStarted {
+        session: CaptureSession,
+    },
+    SessionEnded {
+        session: CaptureSession,
+    },
+    /// Warning that a session ran past the longest allowed and was closed, it is
+    /// followed by its `session_ended`
+    SessionTimedOut {
+        session: CaptureSession,
+        max_duration_seconds: u64,
+    },
+}
+
+impl SessionEvent {
+    pub fn event_type(&self) -> &'static str {
+        match self {
+            SessionEvent::SessionStarted { .. } => "session_started",
+            SessionEvent::SessionEnded { .. } => "session_ended",
+            SessionEvent::SessionTimedOut { .. } => "session_timed_out",
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SessionError {
+    #[error("{0}")]
+    Invalid(String),
+    #[error("session '{}' is still open, stop it before starting another", .0.name)]
+    AlreadyOpen(Box<CaptureSession>),
+    #[error("no session is open")]
+    NotOpen,
+    #[error("no session named '{0}'")]
+    NotFound(String),
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+/// Pipes to run for `event_type`: the enabled ones listing it in the `events` of their
+/// `pipe.json`, e.g. `"events": ["session_ended"]`.
+pub fn subscribed_pipes(pipes: &[PipeInfo], event_type: &str) -> Vec<String> {
+    pipes
+        .iter()
+        .filter(|pipe| pipe.enabled)
+        .filter(|pipe| {
+            pipe.config
+                .get("events")
+                .and_then(|events| events.as_array())
+                .is_some_and(|events| events.iter().any(|e| e.as_str() == Some(event_type)))
+        })
+        .map(|pipe| pipe.id.clone())
+        .collect()
+}
+
+type SystemTime = Arc<dyn Fn() -> DateTime<Utc> + Send + Sync>;
+
+pub struct SessionManager {
+    db: Arc<DatabaseManager>,
+    export_dir: PathBuf,
+    max_duration: Duration,
+    pipe_manager: Option<Arc<PipeManager>>,
+    now: SystemTime,
+    events: broadcast::Sender<SessionEvent>,
+    /// Wakes the auto-close task when a session starts
+    wakeup: Notify,
+}
+
+impl SessionManager {
+    /// Sessions exported on end are written to `export_dir`.
+    pub fn new(db: Arc<DatabaseManager>, export_dir: PathBuf) -> Self {
+        let (events, _) = broadcast::channel(16);
+        Self {
+            db,
+            export_dir,
+            max_duration: DEFAULT_MAX_DURATION,
+            pipe_manager: None,
+            now: Arc::new(Utc::now),
+            events,
+            wakeup: Notify::new(),
+        }
+    }
+
+    pub fn with_max_duration(mut self, max_duration: Duration) -> Self {
+        self.max_duration = max_duration;
+        self
+    }
+
+    /// Runs the subscribed pipes of `pipe_manager` on session events.
+    pub fn with_pipe_manager(mut self, pipe_manager: Arc<PipeManager>) -> Self {
+        self.pipe_manager = Some(pipe_manager);
+        self
+    }
+
+    /// Reads the time from `now` instead of the system clock.
+    pub fn with_system_time(
+        mut self,
+        now: impl Fn() -> DateTime<Utc> + Send + Sync + 'static,
+    ) -> Self {
+        self.now = Arc::new(now);
+        self
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<SessionEvent> {
+        self.events.subscribe()
+    }
+
+    pub async fn start(
+        &self,
+        request: StartSessionRequest,
+    ) -> Result<CaptureSession, SessionError> {
+        let name = request.name.trim();
+        if name.is_empty() {
+            return Err(SessionError::Invalid("name must not be empty".to_string()));
+        }
+        if name.chars().count() > MAX_NAME_CHARS {
+            return Err(SessionError::Invalid(format!(
+                "name must be at most {} characters",
+                MAX_NAME_CHARS
+            )));
+        }
+
+        let started = self
+            .db
+            .start_capture_session(name, (self.now)(), request.auto_export)
+            .await?;
+        let Some(session) = started else {
+            return Err(match self.db.get_open_capture_session().await? {
+                Some(open) => SessionError::AlreadyOpen(Box::new(open)),
+                // Closed between the insert and this read
+                None => SessionError::Invalid("another session was closing, try again".into()),
+            });
+        };
+        info!("capture session '{}' started", session.name);
+        self.wakeup.notify_one();
+        self.publish(SessionEvent::SessionStarted {
+            session: session.clone(),
+        })
+        .await;
+        Ok(session)
+    }
+
+    /// Ends the open session.
+    pub async fn stop(&self) -> Result<CaptureSession, SessionError> {
+        let open = self
+            .db
+            .get_open_capture_session()
+            .await?
+            .ok_or(SessionError::NotOpen)?;
+        self.end(open.id, SessionEndReason::Stopped)
+            .await?
+            .ok_or(SessionError::NotOpen)
+    }
+
+    pub async fn current(&self) -> Result<Option<CaptureSession>, SessionError> {
+        Ok(self.db.get_open_capture_session().await?)
+    }
+
+    pub async fn list(
+        &self,
+        name: Option<&str>,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<CaptureSession>, SessionError> {
+        Ok(self.db.get_capture_sessions(name, limit, offset).await?)
+    }
+
+    /// Time span of the latest session named `name`, open ones end now.
+    pub async fn time_range(
+        &self,
+        name: &str,
+    ) -> Result<(DateTime<Utc>, DateTime<Utc>), SessionError> {
+        let session = self
+            .db
+            .get_capture_sessions(Some(name), 1, 0)
+            .await?
+            .pop()
+            .ok_or_else(|| SessionError::NotFound(name.to_string()))?;
+        Ok((
+            session.started_at,
+            session.ended_at.unwrap_or_else(|| (self.now)()),
+        ))
+    }
+
+    /// Closes the open session if it ran past the longest allowed, returns it.
+    pub async fn close_expired(&self) -> Result<Option<CaptureSession>, SessionError> {
+        let Some(open) = self.db.get_open_capture_session().await? else {
+            return Ok(None);
+        };
+        if (self.now)() < self.deadline(&open) {
+            return Ok(None);
+        }
+        warn!(
+            "capture session '{}' still open after {}s, closing it",
+            open.name,
+            self.max_duration.as_secs()
+        );
+        self.publish(SessionEvent::SessionTimedOut {
+            session: open.clone(),
+            max_duration_seconds: self.max_duration.as_secs(),
+        })
+        .await;
+        Ok(self.end(open.id, SessionEndReason::TimedOut).await?)
+    }
+
+    /// Runs the auto-close task until the process exits.
+    pub fn spawn(self: &Arc<Self>) {
+        tokio::spawn(self.clone().run());
+    }
+
+    /// Closes sessions left open too long, including one left open across a restart.
+    pub async fn run(self: Arc<Self>) {
+        loop {
+            if let Err(e) = self.close_expired().await {
+                error!("failed to close an expired capture session: {}", e);
+            }
+            let wait = match self.db.get_open_capture_session().await {
+                Ok(Some(open)) => (self.deadline(&open) - (self.now)())
+                    .to_std()
+                    .unwrap_or(Duration::ZERO)
+                    .min(MAX_WAIT),
+                Ok(None) => MAX_WAIT,
+                Err(e) => {
+                    error!("failed to get the open capture session: {}", e);
+                    MAX_WAIT
+                }
+            };
+            tokio::select! {
+                _ = tokio::time::sleep(wait) => {}
+                _ = self.wakeup.notified() => {}
+            }
+        }
+    }
+
+    fn deadline(&self, session: &CaptureSession) -> DateTime<Utc> {
+        session.started_at + ChronoDuration::seconds(self.max_duration.as_secs() as i64)
+    }
+
+    /// Closes session `id`, exports it and tells the pipes. `None` if it was already
+    /// closed.
+    async fn end(
+        &self,
+        id: i64,
+        reason: SessionEndReason,
+    ) -> Result<Option<CaptureSession>, sqlx::Error> {
+        let Some(mut session) = self
+            .db
+            .end_capture_session(id, (self.now)(), reason)
+            .await?
+        else {
+            return Ok(None);
+        };
+        info!(
+            "capture session '{}' ended ({})",
+            session.name,
+            reason.as_str()
+        );
+
+        if let Some(format) = session.auto_export {
+            match export_session(&self.db, &session, format, &self.export_dir).await {
+                Ok(path) => {
+                    let path = path.to_string_lossy().into_owned();
+                    self.db
+                        .set_capture_session_export(session.id, &path)
+                        .await?;
+                    info!("capture session '{}' exported to {}", session.name, path);
+                    session.export_path = Some(path);
+                }
+                Err(e) => error!("failed to export capture session '{}': {}", session.name, e),
+            }
+        }
+
+        self.publish(SessionEvent::SessionEnded {
+            session: session.clone(),
+        })
+        .await;
+        Ok(Some(session))
+    }
+
+    async fn publish(&self, event: SessionEvent) {
+        let _ = self.events.send(event.clone());
+        let Some(pipe_manager) = &self.pipe_manager else {
+            return;
+        };
+        let pipes = subscribed_pipes(&pipe_manager.list_pipes().await, event.event_type());
+        let payload = match serde_json::to_string(&event) {
+            Ok(payload) => payload,
+            Err(e) => {
+                error!("failed to serialize session event: {}", e);
+                return;
+            }
+        };
+        for pipe_id in pipes {
+            let pipe_manager = pipe_manager.clone();
+            let payload = payload.clone();
+            let event_type = event.event_type();
+            tokio::spawn(async move {
+                info!("running pipe {} on {}", pipe_id, event_type);
+                if let Err(e) = pipe_manager
+                    .run_pipe_once(&pipe_id, &payload, JOB_TIMEOUT)
+                    .await
+                {
+                    error!("pipe {} failed on {}: {}", pipe_id, event_type, e);
+                }
+            });
+        }
+    }
+}
+
+/// One entry of a session export, in capture order.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ExportItem {
+    Screen {
+        timestamp: DateTime<Utc>,
+        frame_id: i64,
+        app_name: String,
+        window_name: String,
+        text: String,
+    },
+    Audio {
+        timestamp: DateTime<Utc>,
+        audio_chunk_id: i64,
+        device_name: String,
+        speaker: Option<String>,
+        text: String,
+    },
+}
+
+impl ExportItem {
+    fn timestamp(&self) -> DateTime<Utc> {
+        match self {
+            ExportItem::Screen { timestamp, .. } | ExportItem::Audio { timestamp, .. } => {
+                *timestamp
+            }
+        }
+    }
+}
+
+/// Screen text and transcripts captured during `session`, oldest first. Screen text a
+/// window still showed unchanged is left out after its first capture.
+pub async fn session_items(
+    db: &DatabaseManager,
+    session: &CaptureSession,
+) -> Result<Vec<ExportItem>, sqlx::Error> {
+    let end = session.ended_at.unwrap_or_else(Utc::now);
+    let mut items = Vec::new();
+    for content_type in [ContentType::OCR, ContentType::Audio] {
+        let mut offset = 0;
+        while (offset as usize) < MAX_EXPORT_ROWS {
+            let page = db
+                .search(
+                    "",
+                    content_type.clone(),
+                    EXPORT_PAGE,
+                    offset,
+                    Some(session.started_at),
+                    Some(end),
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                )
+                .await?;
+            let read = page.len() as u32;
+            items.extend(page.into_iter().filter_map(|result| match result {
+                SearchResult::OCR(ocr) => Some(ExportItem::Screen {
+                    timestamp: ocr.timestamp,
+                    frame_id: ocr.frame_id,
+                    app_name: ocr.app_name,
+                    window_name: ocr.window_name,
+                    text: ocr.ocr_text,
+                }),
+                SearchResult::Audio(audio) => Some(ExportItem::Audio {
+                    timestamp: audio.timestamp,
+                    audio_chunk_id: audio.audio_chunk_id,
+                    device_name: audio.device_name,
+                    speaker: audio.speaker.map(|s| s.name).filter(|n| !n.is_empty()),
+                    text: audio.transcription,
+                }),
+                _ => None,
+            }));
+            if read < EXPORT_PAGE {
+                break;
+            }
+            offset += read;
+        }
+    }
+    items.sort_by_key(ExportItem::timestamp);
+
+    let mut shown: HashMap<(String, String), String> = HashMap::new();
+    items.retain(|item| match item {
+        ExportItem::Screen {
+            app_name,
+            window_name,
+            text,
+            ..
+        } => {
+            let text = text.trim();
+            let window = (app_name.clone(), window_name.clone());
+            if text.is_empty() || shown.get(&window).is_some_and(|last| last == text) {
+                return false;
+            }
+            shown.insert(window, text.to_string());
+            true
+        }
+        ExportItem::Audio { text, .. } => !text.trim().is_empty(),
+    });
+    Ok(items)
+}
+
+/// The export of `session` in `format`.
+pub fn render_export(
+    session: &CaptureSession,
+    items: &[ExportItem],
+    format: ExportFormat,
+) -> Result<String> {
+    Ok(match format {
+        ExportFormat::Json => serde_json::to_string_pretty(&serde_json::json!({
+            "session": session,
+            "items": items,
+        }))?,
+        ExportFormat::Markdown => render_markdown(session, items),
+    })
+}
+
+fn render_markdown(session: &CaptureSession, items: &[ExportItem]) -> String {
+    let time = |t: DateTime<Utc>| t.format("%Y-%m-%d %H:%M:%S UTC").to_string();
+    let mut out = format!("# {}\n\n", session.name);
+    out.push_str(&format!("- started: {}\n", time(session.started_at)));
+    if let Some(ended_at) = session.ended_at {
+        let minutes = (ended_at - session.started_at).num_minutes();
+        let reason = match session.end_reason {
+            Some(SessionEndReason::TimedOut) => ", closed after running too long",
+            _ => "",
+        };
+        out.push_str(&format!("- ended: {}{}\n", time(ended_at), reason));
+        out.push_str(&format!(
+            "- duration: {}h {:02}m\n",
+            minutes / 60,
+            minutes % 60
+        ));
+    }
+    out.push('\n');
+
+    if items.is_empty() {
+        out.push_str("nothing was captured during this session\n");
+        return out;
+    }
+    for item in items {
+        let (heading, text) = match item {
+            ExportItem::Screen {
+                timestamp,
+                app_name,
+                window_name,
+                text,
+                ..
+            } => (
+                format!(
+                    "{} · screen · {} · {}",
+                    timestamp.format("%H:%M:%S"),
+                    app_name,
+                    window_name
+                ),
+                text,
+            ),
+            ExportItem::Audio {
+                timestamp,
+                device_name,
+                speaker,
+                text,
+                ..
+            } => (
+                match speaker {
+                    Some(speaker) => format!(
+                        "{} · audio · {} · {}",
+                        timestamp.format("%H:%M:%S"),
+                        device_name,
+                        speaker
+                    ),
+                    None => format!("{} · audio · {}", timestamp.format("%H:%M:%S"), device_name),
+                },
+                text,
+            ),
+        };
+        out.push_str(&format!("## {}\n\n{}\n\n", heading, text.trim()));
+    }
+    out
+}
+
+/// Writes the export of `session` to `dir` and returns its path, named after the start
+/// time and the session name.
+pub async fn export_session(
+    db: &DatabaseManager,
+    session: &CaptureSession,
+    format: ExportFormat,
+    dir: &Path,
+) -> Result<PathBuf> {
+    let items = session_items(db, session).await?;
+    let content = render_export(session, &items, format)?;
+    tokio::fs::create_dir_all(dir).await?;
+    let path = dir.join(format!(
+        "{}_{}.{}",
+        session.started_at.format("%Y-%m-%d_%H-%M-%S"),
+        file_stem(&session.name),
+        format.extension()
+    ));
+    tokio::fs::write(&path, content).await?;
+    Ok(path)
+}
+
+/// `name` with everything but letters, digits, `-` and `_` replaced, safe as a file name.
+fn file_stem(name: &str) -> String {
+    let stem: String = name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    let stem = stem
+        .split('-')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+    if stem.is_empty() {
+        "session".to_string()
+    } else {
+        stem.chars().take(80).collect()
+    }
+}
@@ -0,0 +1,276 @@
+//! Settings clients watch on `GET /settings/watch` instead of polling, by dotted key:
+//! `retention`, `rules`, `storage.mode`, `pipes.<id>.enabled` and `pipes.<id>.config`.
+//!
+//! Handlers changing a setting hand every current value to [`SettingsWatch::update`]
+//! along with the client that asked, and each key whose value changed gets a change
+//! with an increasing id. A watch sends the current values, then the changes to its
+//! keys, those close together in one event. The last [`HISTORY`] changes are kept so
+//! a client reconnecting with `Last-Event-ID` gets only what it missed, or the current
+//! values again when that is too far back.
+
+use async_stream::stream;
+use chrono::{DateTime, Utc};
+use futures::Stream;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::time::Instant;
+
+use crate::pipe_manager::PipeInfo;
+
+/// Header a client names itself with on writes, returned as the `source` of the
+/// changes they made so it can skip its own.
+pub const CLIENT_HEADER: &str = "x-screenpipe-client";
+
+/// Changes kept for clients resuming with `Last-Event-ID`.
+pub const HISTORY: usize = 256;
+
+/// How long a watch waits for more changes before sending the ones it has.
+pub const COALESCE_WINDOW: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SettingChange {
+    pub id: u64,
+    pub key: String,
+    /// `null` once the setting is gone, e.g. its pipe deleted
+    pub value: Value,
+    /// [`CLIENT_HEADER`] of the request that made the change
+    pub source: Option<String>,
+    pub changed_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WatchEvent {
+    /// Current values of the watched keys, on connect and when changes were missed
+    Snapshot {
+        id: u64,
+        values: BTreeMap<String, Value>,
+    },
+    /// Latest change of each watched key that changed, oldest first
+    Change {
+        id: u64,
+        changes: Vec<SettingChange>,
+    },
+}
+
+impl WatchEvent {
+    /// Id of the latest change the event covers, the sse event id.
+    pub fn id(&self) -> u64 {
+        match self {
+            WatchEvent::Snapshot { id, .. } | WatchEvent::Change { id, .. } => *id,
+        }
+    }
+}
+
+#[derive(Default)]
+struct WatchState {
+    values: BTreeMap<String, Value>,
+    last_id: u64,
+    history: VecDeque<SettingChange>,
+}
+
+pub struct SettingsWatch {
+    state: Arc<Mutex<WatchState>>,
+    changes: broadcast::Sender<SettingChange>,
+}
+
+impl Default for SettingsWatch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SettingsWatch {
+    pub fn new() -> Self {
+        let (changes, _) = broadcast::channel(HISTORY);
+        Self {
+            state: Arc::new(Mutex::new(WatchState::default())),
+            changes,
+        }
+    }
+
+    /// Takes `values` as every setting there is now: keys with a new value and keys
+    /// left out, which are removed, become changes from `source`.
+    pub fn update(
+        &self,
+        values: BTreeMap<String, Value>,
+        source: Option<&str>,
+    ) -> Vec<SettingChange> {
+        let mut state = self.state.lock().unwrap();
+        let removed: Vec<String> = state
+            .values
+            .keys()
+            .filter(|key| !values.contains_key(*key))
+            .cloned()
+            .collect();
+        let updated = values
+            .into_iter()
+            .filter(|(key, value)| state.values.get(key) != Some(value));
+        let edits: Vec<(String, Value)> = removed
+            .into_iter()
+            .map(|key| (key, Value::Null))
+            .chain(updated)
+            .collect();
+
+        let now = Utc::now();
+        let mut changes = Vec::with_capacity(edits.len());
+        for (key, value) in edits {
+            if value.is_null() {
+                state.values.remove(&key);
+            } else {
+                state.values.insert(key.clone(), value.clone());
+            }
+            state.last_id += 1;
+            let change = SettingChange {
+                id: state.last_id,
+                key,
+                value,
+                source: source.map(String::from),
+                changed_at: now,
+            };
+            if state.history.len() == HISTORY {
+                state.history.pop_front();
+            }
+            state.history.push_back(change.clone());
+            // Sent under the lock so a watch subscribing meanwhile sees it once
+            let _ = self.changes.send(change.clone());
+            changes.push(change);
+        }
+        changes
+    }
+
+    /// Events for the keys matching `patterns`, see [`key_matches`]. Starts with the
+    /// current values, or with the changes after `last_event_id` when all of them are
+    /// still kept, nothing if there are none.
+    pub fn watch(
+        &self,
+        patterns: Vec<String>,
+        last_event_id: Option<u64>,
+    ) -> impl Stream<Item = WatchEvent> + Send + 'static {
+        let watched = move |key: &str| patterns.iter().any(|p| key_matches(p, key));
+        let (first, mut changes) = {
+            let state = self.state.lock().unwrap();
+            let resumable = last_event_id.filter(|id| {
+                *id <= state.last_id
+                    && state
+                        .history
+                        .front()
+                        .map_or(*id == state.last_id, |oldest| *id + 1 >= oldest.id)
+            });
+            let first = match resumable {
+                Some(id) => {
+                    let missed = state
+                        .history
+                        .iter()
+                        .filter(|change| change.id > id && watched(&change.key))
+                        .cloned()
+                        .collect();
+                    coalesce(state.last_id, missed)
+                }
+                None => Some(snapshot(&state, &watched)),
+            };
+            (first, self.changes.subscribe())
+        };
+        let state = self.state.clone();
+
+        stream! {
+            if let Some(first) = first {
+                yield first;
+            }
+            loop {
+                let mut batch = Vec::new();
+                let mut deadline = None;
+                let mut last_id = 0;
+                loop {
+                    let received = match deadline {
+                        None => changes.recv().await,
+                        Some(deadline) => match tokio::time::timeout_at(deadline, changes.recv()).await {
+                            Ok(received) => received,
+                            Err(_) => break,
+                        },
+                    };
+                    match received {
+                        Ok(change) => {
+                            last_id = change.id;
+                            if watched(&change.key) {
+                                batch.push(change);
+                                deadline.get_or_insert(Instant::now() + COALESCE_WINDOW);
+                            }
+                        }
+                        // Too slow to keep up, start over from the current values
+                        Err(RecvError::Lagged(_)) => {
+                            batch.clear();
+                            deadline = None;
+                            let current = snapshot(&state.lock().unwrap(), &watched);
+                            yield current;
+                        }
+                        Err(RecvError::Closed) => return,
+                    }
+                }
+                if let Some(event) = coalesce(last_id, batch) {
+                    yield event;
+                }
+            }
+        }
+    }
+}
+
+fn snapshot(state: &WatchState, watched: impl Fn(&str) -> bool) -> WatchEvent {
+    WatchEvent::Snapshot {
+        id: state.last_id,
+        values: state
+            .values
+            .iter()
+            .filter(|(key, _)| watched(key))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect(),
+    }
+}
+
+/// One change event keeping the latest change of each key, `None` without changes.
+fn coalesce(id: u64, changes: Vec<SettingChange>) -> Option<WatchEvent> {
+    if changes.is_empty() {
+        return None;
+    }
+    let mut latest: Vec<SettingChange> = Vec::with_capacity(changes.len());
+    for change in changes {
+        latest.retain(|kept| kept.key != change.key);
+        latest.push(change);
+    }
+    Some(WatchEvent::Change {
+        id,
+        changes: latest,
+    })
+}
+
+/// Whether `key` matches `pattern`, both dotted. A `*` segment matches any one
+/// segment, and any number of them at the end: `pipes.*` matches every pipe setting,
+/// `pipes.*.enabled` whether each pipe is enabled.
+pub fn key_matches(pattern: &str, key: &str) -> bool {
+    let pattern: Vec<&str> = pattern.split('.').collect();
+    let key: Vec<&str> = key.split('.').collect();
+    for (i, segment) in pattern.iter().enumerate() {
+        if *segment == "*" && i == pattern.len() - 1 {
+            return key.len() > i;
+        }
+        match key.get(i) {
+            Some(part) if *segment == "*" || segment == part => {}
+            _ => return false,
+        }
+    }
+    key.len() == pattern.len()
+}
+
+/// The settings of the installed pipes, by key.
+pub fn pipe_settings(pipes: &[PipeInfo]) -> BTreeMap<String, Value> {
+    let mut values = BTreeMap::new();
+    for pipe in pipes {
+        values.insert(format!("pipes.{}.enabled", pipe.id), json!(pipe.enabled));
+        values.insert(format!("pipes.{}.config", pipe.id), pipe.config.clone());
+    }
+    values
+}
@@ -0,0 +1,261 @@
+use crate::storage::MediaVolume;
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use log::{debug, error, info, warn};
+use screenpipe_audio::{record_and_transcribe, AudioDevice, AudioInput, AudioStream};
+use screenpipe_core::power::{power_state, SubsystemOutcome};
+use screenpipe_core::Language;
+use screenpipe_vision::{
+    capture_screenshot_by_window::WindowFilters, continuous_capture_with_scheduler, CaptureResult,
+    OcrEngine, OcrScheduler,
+};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::Sender;
+
+/// Produces OCR'd frames for a single monitor.
+///
+/// The live implementation grabs the screen; other implementations (replay, tests)
+/// plug in at the same spot so everything downstream of capture stays identical.
+pub trait FrameSource: Send + Sync {
+    /// Pushes frames into `result_tx` until the source is exhausted or the receiver is dropped.
+    fn start(self: Arc<Self>, result_tx: Sender<CaptureResult>) -> BoxFuture<'static, ()>;
+
+    /// Frames from a lossless source are never dropped when downstream queues are full,
+    /// the producer waits instead. Needed for reproducible row counts.
+    fn lossless(&self) -> bool {
+        false
+    }
+
+    /// Timestamp to store for a frame, `None` means "now".
+    fn captured_at(&self, _frame_number: u64) -> Option<DateTime<Utc>> {
+        None
+    }
+}
+
+/// Produces raw audio chunks for a single device.
+pub trait AudioSource: Send + Sync {
+    fn device(&self) -> Arc<AudioDevice>;
+
+    /// Sends audio chunks to the transcription channel while `is_running` is set.
+    fn start(
+        self: Arc<Self>,
+        whisper_sender: crossbeam::channel::Sender<AudioInput>,
+        is_running: Arc<AtomicBool>,
+    ) -> BoxFuture<'static, ()>;
+}
+
+pub struct LiveFrameSource {
+    interval: Duration,
+    ocr_engine: Arc<OcrEngine>,
+    monitor_id: u32,
+    window_filters: Arc<WindowFilters>,
+    languages: Vec<Language>,
+    capture_unfocused_windows: bool,
+    ocr_scheduler: Option<Arc<OcrScheduler>>,
+}
+
+impl LiveFrameSource {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        fps: f64,
+        ocr_engine: Arc<OcrEngine>,
+        monitor_id: u32,
+        ignore_list: &[String],
+        include_list: &[String],
+        languages: Vec<Language>,
+        capture_unfocused_windows: bool,
+        ocr_scheduler: Option<Arc<OcrScheduler>>,
+    ) -> Self {
+        let fps = if fps.is_finite() && fps > 0.0 {
+            fps
+        } else {
+            1.0
+        };
+        Self {
+            interval: Duration::from_secs_f64(1.0 / fps),
+            ocr_engine,
+            monitor_id,
+            window_filters: Arc::new(WindowFilters::new(ignore_list, include_list)),
+            languages,
+            capture_unfocused_windows,
+            ocr_scheduler,
+        }
+    }
+}
+
+impl FrameSource for LiveFrameSource {
+    fn start(self: Arc<Self>, result_tx: Sender<CaptureResult>) -> BoxFuture<'static, ()> {
+        Box::pin(async move {
+            continuous_capture_with_scheduler(
+                result_tx,
+                self.interval,
+                *self.ocr_engine,
+                self.monitor_id,
+                Arc::clone(&self.window_filters),
+                self.languages.clone(),
+                self.capture_unfocused_windows,
+                self.ocr_scheduler.clone(),
+            )
+            .await;
+        })
+    }
+}
+
+pub struct LiveAudioSource {
+    device: Arc<AudioDevice>,
+    chunk_duration: Duration,
+    media_volume: Option<Arc<MediaVolume>>,
+}
+
+impl LiveAudioSource {
+    pub fn new(
+        device: Arc<AudioDevice>,
+        chunk_duration: Duration,
+        media_volume: Option<Arc<MediaVolume>>,
+    ) -> Self {
+        Self {
+            device,
+            chunk_duration,
+            media_volume,
+        }
+    }
+
+    /// Flag the stream runs on: follows `is_running`, and also drops while the
+    /// media volume is unavailable so no chunks are recorded that can't be saved,
+    /// and on sleep or wake (power `epoch` changing) so the device is reopened after.
+    fn stream_running(&self, is_running: &Arc<AtomicBool>, epoch: u64) -> Arc<AtomicBool> {
+        let media_volume = self.media_volume.clone();
+        let stream_running = Arc::new(AtomicBool::new(true));
+        let flag = Arc::clone(&stream_running);
+        let is_running = Arc::clone(is_running);
+        tokio::spawn(async move {
+            let power = power_state();
+            // Ends with the stream, once the watcher holds the last reference
+            while flag.load(Ordering::Relaxed) && Arc::strong_count(&flag) > 1 {
+                if !is_running.load(Ordering::Relaxed)
+                    || media_volume.as_ref().is_some_and(|v| !v.is_available())
+                    || power.epoch() != epoch
+                {
+                    flag.store(false, Ordering::Relaxed);
+                }
+                tokio::time::sleep(Duration::from_millis(500)).await;
+            }
+        });
+        stream_running
+    }
+}
+
+impl AudioSource for LiveAudioSource {
+    fn device(&self) -> Arc<AudioDevice> {
+        Arc::clone(&self.device)
+    }
+
+    fn start(
+        self: Arc<Self>,
+        whisper_sender: crossbeam::channel::Sender<AudioInput>,
+        is_running: Arc<AtomicBool>,
+    ) -> BoxFuture<'static, ()> {
+        Box::pin(async move {
+            debug!("Starting audio capture thread for device: {}", &self.device);
+            let mut did_warn = false;
+            let power = power_state();
+            let subsystem = format!("audio {}", self.device);
+            power.register(&subsystem);
+            let mut reported_epoch = power.epoch();
+
+            while is_running.load(Ordering::Relaxed) {
+                if power.is_asleep() {
+                    // The stream was closed for the sleep, the device is reopened on wake
+                    tokio::time::sleep(Duration::from_millis(500)).await;
+                    continue;
+                }
+                if let Some(media_volume) = &self.media_volume {
+                    if !media_volume.is_available() {
+                        tokio::time::sleep(Duration::from_millis(500)).await;
+                        continue;
+                    }
+                }
+                let epoch = power.epoch();
+                let stream_running = self.stream_running(&is_running, epoch);
+                let audio_stream = match AudioStream::from_device(
+                    self.device(),
+                    Arc::clone(&stream_running),
+                )
+                .await
+                {
+                    Ok(stream) => {
+                        if reported_epoch != epoch {
+                            power.report(
+                                &subsystem,
+                                epoch,
+                                SubsystemOutcome::Ok("device reinitialized".to_string()),
+                            );
+                            reported_epoch = epoch;
+                        }
+                        stream
+                    }
+                    Err(e) => {
+                        if e.to_string().contains("Audio device not found") {
+                            if !did_warn {
+                                warn!("Audio device not found: {}", self.device.name);
+                                did_warn = true;
+                            }
+                            if reported_epoch != epoch {
+                                power.report(
+                                    &subsystem,
+                                    epoch,
+                                    SubsystemOutcome::Failed(
+                                        "device not found, retrying".to_string(),
+                                    ),
+                                );
+                                reported_epoch = epoch;
+                            }
+                            tokio::time::sleep(Duration::from_secs(1)).await;
+                            continue;
+                        } else {
+                            error!("Failed to create audio stream: {}", e);
+                            power.report(
+                                &subsystem,
+                                epoch,
+                                SubsystemOutcome::Failed(e.to_string()),
+                            );
+                            power.unregister(&subsystem);
+                            return;
+                        }
+                    }
+                };
+
+                let audio_stream = Arc::new(audio_stream);
+                let whisper_sender = whisper_sender.clone();
+                let is_running_loop = Arc::clone(&stream_running);
+                let chunk_duration = self.chunk_duration;
+                let record_handle = tokio::spawn(async move {
+                    let _ = record_and_transcribe(
+                        audio_stream,
+                        chunk_duration,
+                        whisper_sender,
+                        is_running_loop,
+                    )
+                    .await;
+                });
+
+                record_handle.await.unwrap();
+
+                // The last segment was flushed to whisper when the stream stopped
+                if power.is_asleep() && reported_epoch != power.epoch() {
+                    reported_epoch = power.epoch();
+                    power.report(
+                        &subsystem,
+                        reported_epoch,
+                        SubsystemOutcome::Ok("stream closed".to_string()),
+                    );
+                }
+            }
+
+            power.unregister(&subsystem);
+            info!("exiting audio capture thread for device: {}", &self.device);
+        })
+    }
+}
@@ -0,0 +1,717 @@
+use crate::events::{EventKind, EventRecorder};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+const STORAGE_CONFIG_FILE: &str = "storage.json";
+/// Marker written in every configured storage directory. Its content identifies the
+/// directory so it can be found again when the drive is mounted at another path.
+const VOLUME_MARKER_FILE: &str = ".screenpipe-volume";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StorageKind {
+    Db,
+    Media,
+    Models,
+    /// Cold storage old media is moved to, see `archive`
+    Archive,
+}
+
+impl fmt::Display for StorageKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StorageKind::Db => write!(f, "db"),
+            StorageKind::Media => write!(f, "media"),
+            StorageKind::Models => write!(f, "models"),
+            StorageKind::Archive => write!(f, "archive"),
+        }
+    }
+}
+
+impl StorageKind {
+    /// Location used when nothing is configured, matching the historical layout.
+    pub fn default_dir(&self, base_dir: &Path) -> PathBuf {
+        match self {
+            StorageKind::Db => base_dir.to_path_buf(),
+            StorageKind::Media => base_dir.join("data"),
+            StorageKind::Models => dirs::cache_dir()
+                .unwrap_or_else(|| base_dir.to_path_buf())
+                .join("screenpipe")
+                .join("models"),
+            StorageKind::Archive => base_dir.join("archive"),
+        }
+    }
+
+    /// Whether a directory entry belongs to this kind. The db shares its default
+    /// directory with pipes and logs, so only the database files move.
+    fn owns(&self, file_name: &str) -> bool {
+        match self {
+            StorageKind::Db => file_name.starts_with("db.sqlite"),
+            StorageKind::Media | StorageKind::Models | StorageKind::Archive => {
+                file_name != VOLUME_MARKER_FILE
+            }
+        }
+    }
+}
+
+/// A configured directory, remembered both as an absolute path and as a path
+/// relative to the root of the volume holding it.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct StorageLocation {
+    pub path: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub volume_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relative_path: Option<PathBuf>,
+}
+
+/// `<screenpipe_dir>/storage.json`, unset entries use the default locations.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct StorageConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub db_dir: Option<StorageLocation>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub media_dir: Option<StorageLocation>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub models_dir: Option<StorageLocation>,
+    /// Unset until `screenpipe storage archive --to` picks a directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archive_dir: Option<StorageLocation>,
+    /// Media older than this is archived by the server, `None` archives nothing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archive_after_days: Option<u32>,
+}
+
+impl StorageConfig {
+    pub fn load(base_dir: &Path) -> Result<Self> {
+        let path = base_dir.join(STORAGE_CONFIG_FILE);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(&path)?;
+        serde_json::from_str(&content)
+            .map_err(|e| anyhow!("invalid storage config {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, base_dir: &Path) -> Result<()> {
+        fs::create_dir_all(base_dir)?;
+        // Write then rename so a crash never leaves a truncated config behind
+        let path = base_dir.join(STORAGE_CONFIG_FILE);
+        let tmp = base_dir.join(format!("{}.tmp", STORAGE_CONFIG_FILE));
+        fs::write(&tmp, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+
+    pub fn location(&self, kind: StorageKind) -> Option<&StorageLocation> {
+        match kind {
+            StorageKind::Db => self.db_dir.as_ref(),
+            StorageKind::Media => self.media_dir.as_ref(),
+            StorageKind::Models => self.models_dir.as_ref(),
+            StorageKind::Archive => self.archive_dir.as_ref(),
+        }
+    }
+
+    pub fn set_location(&mut self, kind: StorageKind, location: StorageLocation) {
+        let slot = match kind {
+            StorageKind::Db => &mut self.db_dir,
+            StorageKind::Media => &mut self.media_dir,
+            StorageKind::Models => &mut self.models_dir,
+            StorageKind::Archive => &mut self.archive_dir,
+        };
+        *slot = Some(location);
+    }
+
+    /// Finds where a location currently lives, following its volume marker if the
+    /// drive was remounted at a different path.
+    pub fn resolve(&self, base_dir: &Path, kind: StorageKind) -> ResolvedLocation {
+        let Some(location) = self.location(kind) else {
+            return ResolvedLocation {
+                path: kind.default_dir(base_dir),
+                volume_id: None,
+                available: true,
+                relocated_from: None,
+            };
+        };
+
+        let volume_id = location.volume_id.clone();
+        if marker_matches(&location.path, volume_id.as_deref()) {
+            return ResolvedLocation {
+                path: location.path.clone(),
+                volume_id,
+                available: true,
+                relocated_from: None,
+            };
+        }
+
+        if let (Some(id), Some(relative)) = (&volume_id, &location.relative_path) {
+            for root in candidate_mount_roots() {
+                let candidate = root.join(relative);
+                if candidate != location.path && marker_matches(&candidate, Some(id)) {
+                    info!(
+                        "{} storage moved from {} to {}",
+                        kind,
+                        location.path.display(),
+                        candidate.display()
+                    );
+                    return ResolvedLocation {
+                        path: candidate,
+                        volume_id,
+                        available: true,
+                        relocated_from: Some(location.path.clone()),
+                    };
+                }
+            }
+        }
+
+        ResolvedLocation {
+            path: location.path.clone(),
+            volume_id,
+            available: false,
+            relocated_from: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResolvedLocation {
+    pub path: PathBuf,
+    pub volume_id: Option<String>,
+    pub available: bool,
+    /// Set when the volume was found at a new mount point
+    pub relocated_from: Option<PathBuf>,
+}
+
+impl ResolvedLocation {
+    /// Config entry pointing at the resolved path.
+    pub fn to_location(&self) -> StorageLocation {
+        StorageLocation {
+            path: self.path.clone(),
+            volume_id: self.volume_id.clone(),
+            relative_path: relative_to_mount(&self.path),
+        }
+    }
+}
+
+fn marker_matches(dir: &Path, volume_id: Option<&str>) -> bool {
+    match volume_id {
+        Some(id) => fs::read_to_string(dir.join(VOLUME_MARKER_FILE))
+            .map(|content| content.trim() == id)
+            .unwrap_or(false),
+        None => dir.is_dir(),
+    }
+}
+
+fn write_marker(dir: &Path) -> Result<String> {
+    let id = uuid::Uuid::new_v4().to_string();
+    fs::write(dir.join(VOLUME_MARKER_FILE), &id)?;
+    Ok(id)
+}
+
+/// Directories removable drives get mounted under.
+fn candidate_mount_roots() -> Vec<PathBuf> {
+    let mut roots = Vec::new();
+    let list = |dir: &Path| -> Vec<PathBuf> {
+        fs::read_dir(dir)
+            .map(|entries| {
+                entries
+                    .filter_map(|e| e.ok())
+                    .map(|e| e.path())
+                    .filter(|p| p.is_dir())
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+
+    #[cfg(target_os = "macos")]
+    roots.extend(list(Path::new("/Volumes")));
+
+    #[cfg(target_os = "linux")]
+    {
+        for user_dir in list(Path::new("/media"))
+            .into_iter()
+            .chain(list(Path::new("/run/media")))
+        {
+            roots.extend(list(&user_dir));
+            roots.push(user_dir);
+        }
+        roots.extend(list(Path::new("/mnt")));
+    }
+
+    #[cfg(target_os = "windows")]
+    roots.extend(
+        (b'A'..=b'Z')
+            .map(|letter| PathBuf::from(format!("{}:\\", letter as char)))
+            .filter(|p| p.exists()),
+    );
+
+    #[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
+    let _ = list;
+
+    roots
+}
+
+/// Path of `path` relative to the root of the filesystem it lives on.
+fn relative_to_mount(path: &Path) -> Option<PathBuf> {
+    let path = fs::canonicalize(path).ok()?;
+
+    #[cfg(unix)]
+    let root = {
+        use std::os::unix::fs::MetadataExt;
+        let dev = fs::metadata(&path).ok()?.dev();
+        path.ancestors()
+            .take_while(|a| fs::metadata(a).map(|m| m.dev() == dev).unwrap_or(false))
+            .last()?
+            .to_path_buf()
+    };
+
+    #[cfg(not(unix))]
+    let root = path.ancestors().last()?.to_path_buf();
+
+    path.strip_prefix(root).ok().map(Path::to_path_buf)
+}
+
+/// Storage locations resolved at startup.
+pub struct StorageDirs {
+    pub db: ResolvedLocation,
+    pub media: ResolvedLocation,
+    pub models_dir: PathBuf,
+    /// `None` until an archive directory is configured
+    pub archive: Option<ResolvedLocation>,
+    config: StorageConfig,
+}
+
+impl StorageDirs {
+    pub fn resolve(base_dir: &Path) -> Result<Self> {
+        let config = StorageConfig::load(base_dir)?;
+
+        let db = config.resolve(base_dir, StorageKind::Db);
+        let media = config.resolve(base_dir, StorageKind::Media);
+        if !media.available {
+            warn!(
+                "media directory {} is not available, media writes are paused until it returns",
+                media.path.display()
+            );
+        }
+        let models = config.resolve(base_dir, StorageKind::Models);
+        if !models.available {
+            warn!(
+                "models directory {} is not available, using the default location",
+                models.path.display()
+            );
+        }
+
+        let archive = config
+            .archive_dir
+            .is_some()
+            .then(|| config.resolve(base_dir, StorageKind::Archive));
+        if let Some(archive) = archive.as_ref().filter(|archive| !archive.available) {
+            warn!(
+                "archive directory {} is not available, archived media can't be served",
+                archive.path.display()
+            );
+        }
+
+        let mut dirs = Self {
+            db: db.clone(),
+            media,
+            models_dir: if models.available {
+                models.path.clone()
+            } else {
+                StorageKind::Models.default_dir(base_dir)
+            },
+            archive,
+            config,
+        };
+        // Media and archive paths are also stored in the database, so a relocated
+        // media or archive directory is only persisted by `commit_relocations`
+        for (kind, resolved) in [(StorageKind::Db, db), (StorageKind::Models, models)] {
+            if resolved.relocated_from.is_some() {
+                dirs.config.set_location(kind, resolved.to_location());
+                dirs.config.save(base_dir)?;
+            }
+        }
+        Ok(dirs)
+    }
+
+    /// Path of the sqlite database, failing if its volume is gone.
+    pub fn db_path(&self) -> Result<PathBuf> {
+        if !self.db.available {
+            return Err(anyhow!(
+                "database directory {} is not available, reconnect the drive or run `screenpipe storage move db <path>`",
+                self.db.path.display()
+            ));
+        }
+        Ok(self.db.path.join("db.sqlite"))
+    }
+
+    /// Whether a models directory was configured, as opposed to the default cache.
+    pub fn custom_models_dir(&self) -> bool {
+        self.config.models_dir.is_some()
+    }
+
+    pub fn archive_after_days(&self) -> Option<u32> {
+        self.config.archive_after_days
+    }
+
+    /// Persists a media or archive directory found at a new mount point. Call once
+    /// the database paths have been rebased onto it.
+    pub fn commit_relocations(&mut self, base_dir: &Path) -> Result<()> {
+        if self.media.relocated_from.take().is_some() {
+            self.config
+                .set_location(StorageKind::Media, self.media.to_location());
+            self.config.save(base_dir)?;
+        }
+        if let Some(archive) = &mut self.archive {
+            if archive.relocated_from.take().is_some() {
+                self.config
+                    .set_location(StorageKind::Archive, archive.to_location());
+                self.config.save(base_dir)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A verified copy of a storage location, waiting to be switched to.
+#[derive(Debug)]
+pub struct PendingMove {
+    pub kind: StorageKind,
+    pub from: PathBuf,
+    pub to: PathBuf,
+    /// Files copied, relative to `from`
+    pub files: Vec<PathBuf>,
+    pub bytes: u64,
+    location: StorageLocation,
+}
+
+/// Copies a storage location under `destination` and verifies every copied file by
+/// size and sha256. Nothing is switched or deleted yet.
+pub fn copy_storage(base_dir: &Path, kind: StorageKind, destination: &Path) -> Result<PendingMove> {
+    let config = StorageConfig::load(base_dir)?;
+    let source = config.resolve(base_dir, kind);
+    if !source.available {
+        return Err(anyhow!(
+            "{} directory {} is not available",
+            kind,
+            source.path.display()
+        ));
+    }
+    let from = source.path;
+    let to = destination.join("screenpipe").join(kind.to_string());
+    if to == from {
+        return Err(anyhow!("{} storage is already at {}", kind, to.display()));
+    }
+    if to.starts_with(&from) {
+        return Err(anyhow!(
+            "cannot move {} storage into itself ({})",
+            kind,
+            to.display()
+        ));
+    }
+
+    let files = list_files(&from, kind)?;
+    for file in &files {
+        if to.join(file).exists() {
+            return Err(anyhow!(
+                "{} already exists, refusing to overwrite",
+                to.join(file).display()
+            ));
+        }
+    }
+
+    fs::create_dir_all(&to)?;
+    let volume_id = write_marker(&to)?;
+
+    let mut bytes = 0;
+    for (i, file) in files.iter().enumerate() {
+        let src = from.join(file);
+        let dst = to.join(file);
+        if let Some(parent) = dst.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        bytes += fs::copy(&src, &dst)?;
+        verify_copy(&src, &dst)?;
+        debug!("copied {}/{}: {}", i + 1, files.len(), file.display());
+    }
+
+    let location = StorageLocation {
+        path: to.clone(),
+        volume_id: Some(volume_id),
+        relative_path: relative_to_mount(&to),
+    };
+    Ok(PendingMove {
+        kind,
+        from,
+        to,
+        files,
+        bytes,
+        location,
+    })
+}
+
+impl PendingMove {
+    /// Points the config at the new location.
+    pub fn switch_config(&self, base_dir: &Path) -> Result<()> {
+        let mut config = StorageConfig::load(base_dir)?;
+        config.set_location(self.kind, self.location.clone());
+        config.save(base_dir)
+    }
+
+    /// Deletes the original files. Only call after `switch_config`.
+    pub fn remove_originals(&self) -> Result<()> {
+        for file in &self.files {
+            let original = self.from.join(file);
+            if let Err(e) = fs::remove_file(&original) {
+                warn!("failed to remove {}: {}", original.display(), e);
+            }
+        }
+        if self.kind != StorageKind::Db {
+            remove_empty_dirs(&self.from);
+        }
+        Ok(())
+    }
+}
+
+fn list_files(root: &Path, kind: StorageKind) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut stack = vec![root.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            let file_type = entry.file_type()?;
+            if dir == root && !kind.owns(&entry.file_name().to_string_lossy()) {
+                continue;
+            }
+            if file_type.is_dir() {
+                stack.push(path);
+            } else if file_type.is_file() {
+                files.push(path.strip_prefix(root)?.to_path_buf());
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Sets the archive directory to `<destination>/screenpipe/archive`, and the age media
+/// is archived at when `after_days` is set. An archive directory that holds media, or
+/// can't be checked because its volume is gone, is only changed by
+/// `screenpipe storage move archive`.
+pub fn configure_archive(
+    base_dir: &Path,
+    destination: Option<&Path>,
+    after_days: Option<u32>,
+) -> Result<StorageConfig> {
+    let mut config = StorageConfig::load(base_dir)?;
+    if let Some(destination) = destination {
+        let to = destination.join("screenpipe").join("archive");
+        let current = config
+            .archive_dir
+            .is_some()
+            .then(|| config.resolve(base_dir, StorageKind::Archive));
+        match current {
+            Some(current) if current.path == to => {}
+            Some(current)
+                if !current.available
+                    || !list_files(&current.path, StorageKind::Archive)?.is_empty() =>
+            {
+                return Err(anyhow!(
+                    "archive directory {} holds archived media or is not available, run `screenpipe storage move archive {}` to move it",
+                    current.path.display(),
+                    destination.display()
+                ));
+            }
+            _ => {
+                fs::create_dir_all(&to)?;
+                let volume_id = match fs::read_to_string(to.join(VOLUME_MARKER_FILE)) {
+                    Ok(id) => id.trim().to_string(),
+                    Err(_) => write_marker(&to)?,
+                };
+                config.set_location(
+                    StorageKind::Archive,
+                    StorageLocation {
+                        path: to.clone(),
+                        volume_id: Some(volume_id),
+                        relative_path: relative_to_mount(&to),
+                    },
+                );
+            }
+        }
+    }
+    if let Some(days) = after_days {
+        if days == 0 {
+            return Err(anyhow!("the archive age must be at least one day"));
+        }
+        config.archive_after_days = Some(days);
+    }
+    config.save(base_dir)?;
+    Ok(config)
+}
+
+/// Whether `dir` is reachable and, with a volume id, still the configured directory.
+pub fn volume_available(dir: &Path, volume_id: Option<&str>) -> bool {
+    marker_matches(dir, volume_id)
+}
+
+pub(crate) fn verify_copy(src: &Path, dst: &Path) -> Result<()> {
+    let (src_len, dst_len) = (fs::metadata(src)?.len(), fs::metadata(dst)?.len());
+    if src_len != dst_len {
+        return Err(anyhow!(
+            "size mismatch for {}: {} != {} bytes",
+            dst.display(),
+            src_len,
+            dst_len
+        ));
+    }
+    if hash_file(src)? != hash_file(dst)? {
+        return Err(anyhow!("checksum mismatch for {}", dst.display()));
+    }
+    Ok(())
+}
+
+fn hash_file(path: &Path) -> Result<Vec<u8>> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 1 << 16];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher.finalize().to_vec())
+}
+
+fn remove_empty_dirs(dir: &Path) {
+    if let Ok(entries) = fs::read_dir(dir) {
+        for entry in entries.filter_map(|e| e.ok()) {
+            if entry.file_type().map(|t| t.is_dir()).unwrap_or(false) {
+                remove_empty_dirs(&entry.path());
+            }
+        }
+    }
+    let _ = fs::remove_dir(dir);
+}
+
+/// Tracks whether the media directory is reachable so capture can pause media
+/// writes while a drive is unplugged instead of failing on every frame.
+///
+/// Only a return at the same path is picked up at runtime, a volume mounted
+/// elsewhere is found on the next start.
+pub struct MediaVolume {
+    dir: PathBuf,
+    volume_id: Option<String>,
+    available: AtomicBool,
+    unavailable_since: Mutex<Option<DateTime<Utc>>>,
+    /// Where pauses and resumes are recorded, set once the database is open
+    events: OnceLock<Arc<EventRecorder>>,
+}
+
+impl MediaVolume {
+    pub fn new(dir: PathBuf, volume_id: Option<String>) -> Self {
+        let volume = Self {
+            dir,
+            volume_id,
+            available: AtomicBool::new(true),
+            unavailable_since: Mutex::new(None),
+            events: OnceLock::new(),
+        };
+        volume.check();
+        volume
+    }
+
+    /// Records capture pausing and resuming from now on, and the pause the volume is
+    /// already in. Must be called from within a tokio runtime.
+    pub fn record_events(&self, events: Arc<EventRecorder>) {
+        if self.events.set(events).is_ok() && !self.is_available() {
+            self.emit_paused();
+        }
+    }
+
+    fn emit_paused(&self) {
+        if let Some(events) = self.events.get() {
+            events.emit(EventKind::CapturePaused {
+                reason: "media_unavailable".to_string(),
+                media_dir: self.dir.display().to_string(),
+            });
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn is_available(&self) -> bool {
+        self.available.load(Ordering::Relaxed)
+    }
+
+    pub fn unavailable_since(&self) -> Option<DateTime<Utc>> {
+        *self.unavailable_since.lock().unwrap()
+    }
+
+    /// Re-checks the directory and returns whether it is available.
+    pub fn check(&self) -> bool {
+        let available = marker_matches(&self.dir, self.volume_id.as_deref());
+        let was_available = self.available.swap(available, Ordering::Relaxed);
+        let mut since = self.unavailable_since.lock().unwrap();
+        if !available && (was_available || since.is_none()) {
+            warn!(
+                "media directory {} is unavailable, pausing media writes",
+                self.dir.display()
+            );
+            *since = Some(Utc::now());
+            self.emit_paused();
+        } else if available && !was_available {
+            info!(
+                "media directory {} is back, resuming media writes",
+                self.dir.display()
+            );
+            if let (Some(events), Some(paused_at)) = (self.events.get(), *since) {
+                events.emit(EventKind::CaptureResumed {
+                    reason: "media_available".to_string(),
+                    media_dir: self.dir.display().to_string(),
+                    paused_secs: (Utc::now() - paused_at).num_seconds(),
+                });
+            }
+            *since = None;
+        }
+        available
+    }
+
+    pub fn spawn_watcher(self: &Arc<Self>, interval: Duration) {
+        let volume = Arc::clone(self);
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                volume.check();
+            }
+        });
+    }
+
+    pub async fn wait_available(&self) {
+        while !self.is_available() {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+    }
+}
+
+/// `dir` as a string ending with a separator, for prefix matching stored file paths.
+pub fn path_prefix(dir: &Path) -> String {
+    let mut prefix = dir.to_string_lossy().into_owned();
+    if !prefix.ends_with(std::path::MAIN_SEPARATOR) {
+        prefix.push(std::path::MAIN_SEPARATOR);
+    }
+    prefix
+}
@@ -0,0 +1,146 @@
+//! How much of a captured screen is kept on disk.
+//!
+//! In `text_only` mode no pixel ever reaches the disk: frames are OCR'd in memory and
+//! only their text and metadata are stored, the video encoder is idle.
+//! `text_plus_thumbnails` keeps a small JPEG per frame instead of the video. The mode
+//! can change while recording, every frame row records the one it was captured in.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::OnceLock;
+
+/// Longest side of a stored thumbnail.
+pub const THUMBNAIL_MAX_SIDE: u32 = 320;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageMode {
+    /// Video chunks, text and metadata
+    #[default]
+    Full,
+    /// Text and metadata only
+    TextOnly,
+    /// Text, metadata and a thumbnail per frame
+    TextPlusThumbnails,
+}
+
+impl StorageMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StorageMode::Full => "full",
+            StorageMode::TextOnly => "text_only",
+            StorageMode::TextPlusThumbnails => "text_plus_thumbnails",
+        }
+    }
+
+    /// Rows written before the mode was recorded are full captures.
+    pub fn from_db(value: &str) -> Self {
+        match value {
+            "text_only" => StorageMode::TextOnly,
+            "text_plus_thumbnails" => StorageMode::TextPlusThumbnails,
+            _ => StorageMode::Full,
+        }
+    }
+
+    pub fn keeps_video(&self) -> bool {
+        *self == StorageMode::Full
+    }
+
+    pub fn keeps_thumbnails(&self) -> bool {
+        *self == StorageMode::TextPlusThumbnails
+    }
+
+    /// Rough disk use of one frame row: its text in the database, plus its share of a
+    /// video chunk or its thumbnail.
+    pub fn bytes_per_frame(&self) -> u64 {
+        const TEXT: u64 = 2 * 1024;
+        match self {
+            StorageMode::Full => TEXT + 40 * 1024,
+            StorageMode::TextOnly => TEXT,
+            StorageMode::TextPlusThumbnails => TEXT + 12 * 1024,
+        }
+    }
+
+    /// Why frames captured in this mode have no image to show, if they don't.
+    pub fn missing_image_reason(&self) -> Option<&'static str> {
+        match self {
+            StorageMode::TextOnly => {
+                Some("no image available, the frame was captured in text_only storage mode")
+            }
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for StorageMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The mode new frames are captured in, changed by the server while recording.
+pub struct StorageModeSetting {
+    mode: AtomicU8,
+}
+
+static STORAGE_MODE: OnceLock<StorageModeSetting> = OnceLock::new();
+
+/// Process wide setting read by the vision pipeline for every frame.
+pub fn storage_mode() -> &'static StorageModeSetting {
+    STORAGE_MODE.get_or_init(|| StorageModeSetting::new(StorageMode::Full))
+}
+
+impl StorageModeSetting {
+    pub fn new(mode: StorageMode) -> Self {
+        Self {
+            mode: AtomicU8::new(mode as u8),
+        }
+    }
+
+    pub fn get(&self) -> StorageMode {
+        match self.mode.load(Ordering::SeqCst) {
+            1 => StorageMode::TextOnly,
+            2 => StorageMode::TextPlusThumbnails,
+            _ => StorageMode::Full,
+        }
+    }
+
+    /// Switches the mode, returning the previous one. The video encoder finishes the
+    /// chunk it has open before it goes idle, and starts a new one when video is
+    /// kept again.
+    pub fn set(&self, mode: StorageMode) -> StorageMode {
+        match self.mode.swap(mode as u8, Ordering::SeqCst) {
+            1 => StorageMode::TextOnly,
+            2 => StorageMode::TextPlusThumbnails,
+            _ => StorageMode::Full,
+        }
+    }
+}
+
+/// Where the thumbnail of a frame captured at `captured_at` goes.
+pub fn thumbnail_path(media_dir: &Path, monitor_id: u32, captured_at: DateTime<Utc>) -> PathBuf {
+    media_dir
+        .join("thumbnails")
+        .join(format!("monitor_{}", monitor_id))
+        .join(format!(
+            "{}.jpg",
+            captured_at.format("%Y-%m-%d_%H-%M-%S%.3f")
+        ))
+}
+
+/// Writes a JPEG of `image` scaled down to fit [`THUMBNAIL_MAX_SIDE`].
+pub fn write_thumbnail(image: &DynamicImage, path: &Path) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    // JPEG has no alpha channel
+    let thumbnail = image
+        .thumbnail(THUMBNAIL_MAX_SIDE, THUMBNAIL_MAX_SIDE)
+        .to_rgb8();
+    thumbnail.save_with_format(path, image::ImageFormat::Jpeg)?;
+    Ok(())
+}
@@ -0,0 +1,706 @@
+//! Rules that tag content as it is captured, read from `<screenpipe_dir>/tag_rules.json`
+//! and edited with `GET/PUT /settings/rules`.
+//!
+//! A rule has conditions, all of which must hold, and actions:
+//!
+//! ```json
+//! {
+//!   "mode": "all",
+//!   "budget_us": 2000,
+//!   "rules": [{
+//!     "name": "bank",
+//!     "when": { "apps": ["firefox"], "window": "online banking", "keywords": ["iban"] },
+//!     "then": { "add_tags": ["finance"], "sensitive": true, "retention_class": "short" }
+//!   }]
+//! }
+//! ```
+//!
+//! Conditions: the content type, the app, a regex on the window title, keywords or a regex
+//! on the OCR text or transcript, and a window of local time. Matching ignores case. The
+//! conditions on a frame hold when one of its windows meets all of them at once. Audio has
+//! no app or window, rules with those conditions never match it.
+//!
+//! Rules run in the order they are listed, synchronously as the row is inserted, in its
+//! transaction. In `all` mode every rule runs: tags add up, `sensitive` is set when any
+//! matching rule sets it, and the first matching rule with a `retention_class` decides it.
+//! In `first_match` mode the first matching rule is the only one applied.
+//!
+//! Regexes are compiled once when the rules are loaded, with a size limit. Each rule has
+//! `budget_us` to run on a row: a rule still running past it is abandoned for that row, as
+//! if it hadn't matched, and counted as over budget in `GET /rules/metrics`.
+//!
+//! Rule tags are kept apart from tags set by hand: they don't keep content from being
+//! pruned or archived, see [`crate::retention`], until the tag is set by hand too.
+
+use crate::db_types::{RuleSampleRow, TagContentType};
+use crate::retention::normalize_app_name;
+use anyhow::Result;
+use chrono::{DateTime, Datelike, Local, NaiveTime, Utc, Weekday};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+use tracing::warn;
+
+pub const DEFAULT_BUDGET_US: u64 = 2000;
+pub const MAX_BUDGET_US: u64 = 1_000_000;
+pub const MAX_RULES: usize = 256;
+
+/// Compiled size a regex may take, patterns past it are rejected when loaded.
+const MAX_REGEX_SIZE: usize = 1 << 20;
+
+/// Rows of each content type `POST /settings/rules/test` runs a rule on.
+pub const DEFAULT_TEST_ROWS: u32 = 200;
+pub const MAX_TEST_ROWS: u32 = 5000;
+
+/// Chars of text shown with each match of a rule test.
+const EXCERPT_CHARS: usize = 160;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleMode {
+    /// Every matching rule applies
+    #[default]
+    All,
+    /// Only the first matching rule applies
+    FirstMatch,
+}
+
+/// The rules as saved, see the module docs.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TagRulesConfig {
+    pub mode: RuleMode,
+    /// Microseconds each rule may take on a row
+    pub budget_us: u64,
+    pub rules: Vec<TagRule>,
+}
+
+impl Default for TagRulesConfig {
+    fn default() -> Self {
+        Self {
+            mode: RuleMode::default(),
+            budget_us: DEFAULT_BUDGET_US,
+            rules: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TagRule {
+    pub name: String,
+    #[serde(default)]
+    pub when: RuleConditions,
+    pub then: RuleActions,
+}
+
+/// What a row must be for a rule to match, every condition set must hold.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RuleConditions {
+    pub content: Option<TagContentType>,
+    /// Any of these apps, see `normalize_app_name`
+    pub apps: Vec<String>,
+    /// Regex on the window title
+    pub window: Option<String>,
+    /// Any of these words in the text
+    pub keywords: Vec<String>,
+    /// Regex on the text
+    pub text: Option<String>,
+    pub time: Option<TimeWindow>,
+}
+
+/// Local time content is captured in. `from` is inclusive and `to` exclusive, a window
+/// with `from` after `to` wraps past midnight. `days` are those of the content's local
+/// date, any day when empty.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TimeWindow {
+    pub days: Vec<Weekday>,
+    pub from: Option<NaiveTime>,
+    pub to: Option<NaiveTime>,
+}
+
+impl TimeWindow {
+    pub fn contains(&self, timestamp: DateTime<Utc>) -> bool {
+        let local = timestamp.with_timezone(&Local);
+        if !self.days.is_empty() && !self.days.contains(&local.weekday()) {
+            return false;
+        }
+        let time = local.time();
+        match (self.from, self.to) {
+            (Some(from), Some(to)) if from <= to => from <= time && time < to,
+            (Some(from), Some(to)) => from <= time || time < to,
+            (Some(from), None) => from <= time,
+            (None, Some(to)) => time < to,
+            (None, None) => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RuleActions {
+    pub add_tags: Vec<String>,
+    /// The content's image isn't copied, see [`crate::copy`]
+    pub sensitive: bool,
+    /// Retention policy the content is kept under, a class of the retention settings
+    pub retention_class: Option<String>,
+}
+
+impl TagRulesConfig {
+    pub fn path(screenpipe_dir: &Path) -> PathBuf {
+        screenpipe_dir.join("tag_rules.json")
+    }
+
+    pub fn save(&self, screenpipe_dir: &Path) -> Result<()> {
+        std::fs::write(
+            Self::path(screenpipe_dir),
+            serde_json::to_string_pretty(self)?,
+        )?;
+        Ok(())
+    }
+}
+
+/// A row as the rules see it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuleInput<'a> {
+    pub content: TagContentType,
+    pub timestamp: DateTime<Utc>,
+    /// The windows of a frame, a transcription is one window without app
+    pub windows: Vec<RuleWindow<'a>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RuleWindow<'a> {
+    pub app_name: Option<&'a str>,
+    pub window_name: Option<&'a str>,
+    pub text: &'a str,
+}
+
+/// A tag a rule adds, with the rule.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RuleTag {
+    pub tag: String,
+    pub rule: String,
+}
+
+/// What the rules do to one row.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct RuleOutcome {
+    /// Rules that matched, in order
+    pub matched: Vec<String>,
+    /// Each tag once, with the first rule adding it
+    pub tags: Vec<RuleTag>,
+    pub sensitive: bool,
+    pub retention_class: Option<String>,
+    /// Rules abandoned for running past the budget
+    pub over_budget: Vec<String>,
+}
+
+impl RuleOutcome {
+    /// Nothing to write.
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty() && !self.sensitive && self.retention_class.is_none()
+    }
+
+    fn apply(&mut self, rule: &CompiledRule) {
+        self.matched.push(rule.name.clone());
+        for tag in &rule.then.add_tags {
+            if !self.tags.iter().any(|added| &added.tag == tag) {
+                self.tags.push(RuleTag {
+                    tag: tag.clone(),
+                    rule: rule.name.clone(),
+                });
+            }
+        }
+        self.sensitive |= rule.then.sensitive;
+        if self.retention_class.is_none() {
+            self.retention_class = rule.then.retention_class.clone();
+        }
+    }
+}
+
+struct CompiledRule {
+    name: String,
+    content: Option<TagContentType>,
+    apps: Vec<String>,
+    window: Option<Regex>,
+    /// The keywords as one regex
+    keywords: Option<Regex>,
+    text: Option<Regex>,
+    time: Option<TimeWindow>,
+    then: RuleActions,
+}
+
+const NO_WINDOW: [RuleWindow<'static>; 1] = [RuleWindow {
+    app_name: None,
+    window_name: None,
+    text: "",
+}];
+
+/// How a rule ran on a row.
+enum RuleRun {
+    /// The index of the window it matched
+    Matched(usize),
+    NoMatch,
+    OverBudget,
+}
+
+impl CompiledRule {
+    fn run(&self, input: &RuleInput, apps: &[Option<String>], budget_us: u64) -> RuleRun {
+        if self.content.is_some_and(|content| content != input.content) {
+            return RuleRun::NoMatch;
+        }
+        if self
+            .time
+            .as_ref()
+            .is_some_and(|time| !time.contains(input.timestamp))
+        {
+            return RuleRun::NoMatch;
+        }
+        // A frame without text is still there to match on time
+        let windows = if input.windows.is_empty() {
+            &NO_WINDOW[..]
+        } else {
+            &input.windows[..]
+        };
+        let started = Instant::now();
+        for (i, window) in windows.iter().enumerate() {
+            let app = apps.get(i).and_then(|app| app.as_deref());
+            if self.matches_window(window, app) {
+                return RuleRun::Matched(i);
+            }
+            if started.elapsed().as_micros() as u64 > budget_us {
+                return RuleRun::OverBudget;
+            }
+        }
+        RuleRun::NoMatch
+    }
+
+    fn matches_window(&self, window: &RuleWindow, app: Option<&str>) -> bool {
+        if !self.apps.is_empty() && !app.is_some_and(|app| self.apps.iter().any(|a| a == app)) {
+            return false;
+        }
+        if let Some(pattern) = &self.window {
+            if !window
+                .window_name
+                .is_some_and(|title| pattern.is_match(title))
+            {
+                return false;
+            }
+        }
+        [&self.keywords, &self.text]
+            .into_iter()
+            .flatten()
+            .all(|pattern| pattern.is_match(window.text))
+    }
+}
+
+/// Rules ready to run, their regexes compiled.
+#[derive(Default)]
+pub struct TagRules {
+    config: TagRulesConfig,
+    rules: Vec<CompiledRule>,
+}
+
+impl TagRules {
+    /// Rules from the rules file, or none if it is missing or invalid.
+    pub fn load(screenpipe_dir: &Path) -> Self {
+        let path = TagRulesConfig::path(screenpipe_dir);
+        let Ok(content) = std::fs::read_to_string(&path) else {
+            return Self::default();
+        };
+        match serde_json::from_str::<TagRulesConfig>(&content)
+            .map_err(|e| e.to_string())
+            .and_then(Self::compile)
+        {
+            Ok(rules) => rules,
+            Err(e) => {
+                warn!("invalid {}: {}, no content is tagged", path.display(), e);
+                Self::default()
+            }
+        }
+    }
+
+    /// Checks the rules and compiles their regexes. The config kept has its app names
+    /// normalized, names, keywords and tags trimmed, blank keywords and repeats dropped.
+    pub fn compile(config: TagRulesConfig) -> Result<Self, String> {
+        if config.budget_us == 0 || config.budget_us > MAX_BUDGET_US {
+            return Err(format!("budget_us must be between 1 and {}", MAX_BUDGET_US));
+        }
+        if config.rules.len() > MAX_RULES {
+            return Err(format!("at most {} rules", MAX_RULES));
+        }
+        let mut normalized = Vec::with_capacity(config.rules.len());
+        let mut rules = Vec::with_capacity(config.rules.len());
+        for rule in config.rules {
+            let rule = normalized_rule(rule)?;
+            if normalized
+                .iter()
+                .any(|other: &TagRule| other.name == rule.name)
+            {
+                return Err(format!("more than one rule named '{}'", rule.name));
+            }
+            rules.push(compiled_rule(&rule)?);
+            normalized.push(rule);
+        }
+        Ok(Self {
+            config: TagRulesConfig {
+                rules: normalized,
+                ..config
+            },
+            rules,
+        })
+    }
+
+    pub fn config(&self) -> &TagRulesConfig {
+        &self.config
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Runs the rules on a row, counting every run in `metrics`.
+    pub fn evaluate(&self, input: &RuleInput, metrics: &RuleMetrics) -> RuleOutcome {
+        let mut outcome = RuleOutcome::default();
+        if self.rules.is_empty() {
+            return outcome;
+        }
+        let apps: Vec<Option<String>> = input
+            .windows
+            .iter()
+            .map(|window| window.app_name.map(normalize_app_name))
+            .collect();
+        let mut runs = Vec::with_capacity(self.rules.len());
+        for rule in &self.rules {
+            let started = Instant::now();
+            let run = rule.run(input, &apps, self.config.budget_us);
+            let elapsed_us = started.elapsed().as_micros() as u64;
+            // A match found just past the budget is abandoned too
+            let run = match run {
+                RuleRun::Matched(_) if elapsed_us > self.config.budget_us => RuleRun::OverBudget,
+                run => run,
+            };
+            runs.push(RunStats {
+                rule: &rule.name,
+                elapsed_us,
+                hit: matches!(run, RuleRun::Matched(_)),
+                over_budget: matches!(run, RuleRun::OverBudget),
+            });
+            match run {
+                RuleRun::Matched(_) => outcome.apply(rule),
+                RuleRun::OverBudget => outcome.over_budget.push(rule.name.clone()),
+                RuleRun::NoMatch => {}
+            }
+            if self.config.mode == RuleMode::FirstMatch && !outcome.matched.is_empty() {
+                break;
+            }
+        }
+        metrics.record(&runs, &outcome);
+        outcome
+    }
+
+    /// The window of `input` the `index`th rule matches, without budget.
+    fn matching_window(&self, index: usize, input: &RuleInput) -> Option<usize> {
+        let apps: Vec<Option<String>> = input
+            .windows
+            .iter()
+            .map(|window| window.app_name.map(normalize_app_name))
+            .collect();
+        match self.rules.get(index)?.run(input, &apps, u64::MAX) {
+            RuleRun::Matched(window) => Some(window),
+            _ => None,
+        }
+    }
+}
+
+fn normalized_rule(rule: TagRule) -> Result<TagRule, String> {
+    let name = rule.name.trim().to_string();
+    if name.is_empty() {
+        return Err("every rule needs a name".to_string());
+    }
+    let fail = |message: &str| Err(format!("rule '{}': {}", name, message));
+
+    let mut apps = Vec::new();
+    for app in &rule.when.apps {
+        let app = normalize_app_name(app);
+        if app.is_empty() {
+            return fail("an app name is empty");
+        }
+        if !apps.contains(&app) {
+            apps.push(app);
+        }
+    }
+    let keywords: Vec<String> = rule
+        .when
+        .keywords
+        .iter()
+        .map(|keyword| keyword.trim().to_string())
+        .filter(|keyword| !keyword.is_empty())
+        .collect();
+    if !rule.when.keywords.is_empty() && keywords.is_empty() {
+        return fail("every keyword is empty");
+    }
+    if let Some(time) = &rule.when.time {
+        if time.from.is_some() && time.from == time.to {
+            return fail("time window starts where it ends");
+        }
+    }
+
+    let mut add_tags: Vec<String> = Vec::new();
+    for tag in &rule.then.add_tags {
+        let tag = tag.trim();
+        if tag.is_empty() {
+            return fail("a tag is empty");
+        }
+        if !add_tags.iter().any(|added| added == tag) {
+            add_tags.push(tag.to_string());
+        }
+    }
+    let retention_class = match rule.then.retention_class.as_deref().map(str::trim) {
+        Some("") => return fail("retention_class is empty"),
+        class => class.map(str::to_string),
+    };
+    if add_tags.is_empty() && !rule.then.sensitive && retention_class.is_none() {
+        return fail("does nothing, set add_tags, sensitive or retention_class");
+    }
+
+    Ok(TagRule {
+        when: RuleConditions {
+            apps,
+            keywords,
+            ..rule.when
+        },
+        then: RuleActions {
+            add_tags,
+            retention_class,
+            ..rule.then
+        },
+        name,
+    })
+}
+
+fn compiled_rule(rule: &TagRule) -> Result<CompiledRule, String> {
+    let compile = |field: &str, pattern: &str| {
+        RegexBuilder::new(pattern)
+            .case_insensitive(true)
+            .size_limit(MAX_REGEX_SIZE)
+            .build()
+            .map_err(|e| format!("rule '{}': invalid {} regex: {}", rule.name, field, e))
+    };
+    let keywords = if rule.when.keywords.is_empty() {
+        None
+    } else {
+        let alternatives: Vec<String> = rule
+            .when
+            .keywords
+            .iter()
+            .map(|k| regex::escape(k))
+            .collect();
+        Some(compile("keywords", &alternatives.join("|"))?)
+    };
+    Ok(CompiledRule {
+        name: rule.name.clone(),
+        content: rule.when.content,
+        apps: rule.when.apps.clone(),
+        window: rule
+            .when
+            .window
+            .as_deref()
+            .map(|pattern| compile("window", pattern))
+            .transpose()?,
+        keywords,
+        text: rule
+            .when
+            .text
+            .as_deref()
+            .map(|pattern| compile("text", pattern))
+            .transpose()?,
+        time: rule.when.time.clone(),
+        then: rule.then.clone(),
+    })
+}
+
+/// Runs of one rule since the process started.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct RuleStats {
+    pub evaluations: u64,
+    pub hits: u64,
+    pub over_budget: u64,
+    pub total_us: u64,
+    pub max_us: u64,
+}
+
+struct RunStats<'a> {
+    rule: &'a str,
+    elapsed_us: u64,
+    hit: bool,
+    over_budget: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct RuleMetricsSnapshot {
+    /// Rows the rules ran on
+    pub rows: u64,
+    /// Rows at least one rule matched
+    pub matched_rows: u64,
+    /// By rule name, rules renamed or removed since the start stay listed
+    pub rules: BTreeMap<String, RuleStats>,
+}
+
+#[derive(Debug, Default)]
+pub struct RuleMetrics {
+    inner: Mutex<RuleMetricsSnapshot>,
+}
+
+impl RuleMetrics {
+    fn record(&self, runs: &[RunStats], outcome: &RuleOutcome) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.rows += 1;
+        if !outcome.matched.is_empty() {
+            inner.matched_rows += 1;
+        }
+        for run in runs {
+            let stats = match inner.rules.get_mut(run.rule) {
+                Some(stats) => stats,
+                None => inner.rules.entry(run.rule.to_string()).or_default(),
+            };
+            stats.evaluations += 1;
+            stats.hits += run.hit as u64;
+            stats.over_budget += run.over_budget as u64;
+            stats.total_us += run.elapsed_us;
+            stats.max_us = stats.max_us.max(run.elapsed_us);
+        }
+    }
+
+    pub fn snapshot(&self) -> RuleMetricsSnapshot {
+        self.inner.lock().unwrap().clone()
+    }
+}
+
+static RULE_METRICS: OnceLock<RuleMetrics> = OnceLock::new();
+
+/// Runs of the rules at ingest, shown by `GET /rules/metrics`.
+pub fn rule_metrics() -> &'static RuleMetrics {
+    RULE_METRICS.get_or_init(RuleMetrics::default)
+}
+
+/// A frame or an audio chunk a rule is tested on, from the rows of
+/// [`crate::DatabaseManager::get_rule_samples`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuleSample {
+    pub content_type: TagContentType,
+    pub id: i64,
+    pub timestamp: DateTime<Utc>,
+    pub windows: Vec<RuleSampleRow>,
+}
+
+impl RuleSample {
+    /// Groups rows by id, in their order.
+    pub fn group(content_type: TagContentType, rows: Vec<RuleSampleRow>) -> Vec<Self> {
+        let mut samples: Vec<Self> = Vec::new();
+        for row in rows {
+            match samples.last_mut() {
+                Some(sample) if sample.id == row.id => sample.windows.push(row),
+                _ => samples.push(Self {
+                    content_type,
+                    id: row.id,
+                    timestamp: row.timestamp,
+                    windows: vec![row],
+                }),
+            }
+        }
+        samples
+    }
+
+    pub fn input(&self) -> RuleInput<'_> {
+        RuleInput {
+            content: self.content_type,
+            timestamp: self.timestamp,
+            windows: self
+                .windows
+                .iter()
+                .map(|row| RuleWindow {
+                    app_name: row.app_name.as_deref(),
+                    window_name: row.window_name.as_deref(),
+                    text: row.text.as_deref().unwrap_or_default(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// A row a tested rule matches.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RuleTestMatch {
+    pub content_type: TagContentType,
+    pub id: i64,
+    pub timestamp: DateTime<Utc>,
+    /// Of the window matched, none for audio
+    pub app_name: Option<String>,
+    pub window_name: Option<String>,
+    /// Start of the text of the window matched
+    pub excerpt: String,
+}
+
+/// Response of `POST /settings/rules/test`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RuleTestReport {
+    pub rule: TagRule,
+    pub rows: usize,
+    pub matched: Vec<RuleTestMatch>,
+    pub stats: RuleStats,
+}
+
+/// Runs a rule alone on `samples`, most recent first, without touching them.
+pub fn test_rule(
+    rule: TagRule,
+    budget_us: u64,
+    samples: &[RuleSample],
+) -> Result<RuleTestReport, String> {
+    let rules = TagRules::compile(TagRulesConfig {
+        mode: RuleMode::All,
+        budget_us,
+        rules: vec![rule],
+    })?;
+    let metrics = RuleMetrics::default();
+    let mut matched = Vec::new();
+    for sample in samples {
+        let input = sample.input();
+        if rules.evaluate(&input, &metrics).matched.is_empty() {
+            continue;
+        }
+        let window = rules
+            .matching_window(0, &input)
+            .and_then(|i| sample.windows.get(i));
+        matched.push(RuleTestMatch {
+            content_type: sample.content_type,
+            id: sample.id,
+            timestamp: sample.timestamp,
+            app_name: window.and_then(|w| w.app_name.clone()),
+            window_name: window.and_then(|w| w.window_name.clone()),
+            excerpt: window
+                .and_then(|w| w.text.as_deref())
+                .unwrap_or_default()
+                .chars()
+                .take(EXCERPT_CHARS)
+                .collect(),
+        });
+    }
+    let TagRules { mut config, .. } = rules;
+    Ok(RuleTestReport {
+        rule: config.rules.remove(0),
+        rows: samples.len(),
+        matched,
+        stats: metrics
+            .snapshot()
+            .rules
+            .into_values()
+            .next()
+            .unwrap_or_default(),
+    })
+}
@@ -0,0 +1,343 @@
+//! Anonymous usage pings, off unless the user opts in.
+//!
+//! Once a day the counts of the previous utc day are sent as a small [`UsagePayload`]:
+//! version, os, the features turned on, frames and transcriptions as orders of
+//! magnitude and how many pipes are installed. Never a name, text or url. What the
+//! next ping holds is on `GET /telemetry/preview`, and every ping sent is appended to
+//! `<screenpipe_dir>/telemetry_log.jsonl`.
+//!
+//! `DO_NOT_TRACK` and `--disable-telemetry` win over the opt-in. Pings go out from
+//! their own task with a short timeout, a failed one is retried later with a growing
+//! delay. The http client is only built for the first ping, with telemetry off none
+//! exists.
+
+use crate::db_types::UsageBucket;
+use crate::{DatabaseManager, PipeManager};
+use anyhow::Result;
+use chrono::{DateTime, Duration as ChronoDuration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tracing::{debug, warn};
+
+pub const TELEMETRY_URL: &str = "https://telemetry.screenpi.pe/v1/usage";
+
+/// Bumped when fields are added to or removed from [`UsagePayload`].
+pub const PAYLOAD_VERSION: u32 = 1;
+
+/// How often the sender checks whether a ping is due.
+pub const TICK_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Nothing is sent this soon after start.
+const STARTUP_DELAY: Duration = Duration::from_secs(300);
+
+/// A ping still unanswered after this is a failure.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Delay after the first failure, doubling with each one after.
+const RETRY_BASE_SECONDS: i64 = 15 * 60;
+const RETRY_MAX_SECONDS: i64 = 24 * 3600;
+
+/// Whether `DO_NOT_TRACK` is set to anything but `0` or `false`.
+pub fn do_not_track() -> bool {
+    std::env::var("DO_NOT_TRACK")
+        .map(|value| !matches!(value.trim(), "" | "0" | "false"))
+        .unwrap_or(false)
+}
+
+/// `0`, or the power of ten range `n` falls in, e.g. `100-999`.
+pub fn magnitude_bucket(n: i64) -> String {
+    if n <= 0 {
+        return "0".to_string();
+    }
+    let low = 10_i64.pow(n.ilog10());
+    format!("{}-{}", low, low.saturating_mul(10) - 1)
+}
+
+/// Everything a ping sends.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsagePayload {
+    pub payload_version: u32,
+    /// Utc day the counts are from
+    pub day: NaiveDate,
+    pub version: String,
+    pub os: String,
+    pub arch: String,
+    /// Optional features turned on, by flag name
+    pub features: Vec<String>,
+    /// See [`magnitude_bucket`]
+    pub frames: String,
+    pub transcriptions: String,
+    pub pipes: usize,
+    pub pipes_enabled: usize,
+}
+
+/// A ping as kept in the local log.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SentPing {
+    pub sent_at: DateTime<Utc>,
+    pub payload: UsagePayload,
+}
+
+/// Saved in `<screenpipe_dir>/telemetry.json`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+struct TelemetrySettings {
+    enabled: bool,
+    /// Day of the counts last sent
+    last_sent_day: Option<NaiveDate>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TelemetryStatus {
+    /// Whether the user opted in
+    pub enabled: bool,
+    pub do_not_track: bool,
+    /// Opted in and not overridden
+    pub sending: bool,
+    pub endpoint: String,
+    pub last_sent_day: Option<NaiveDate>,
+    /// Set while failed pings are being retried
+    pub retry_at: Option<DateTime<Utc>>,
+}
+
+/// What a check of the sender did.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PingOutcome {
+    /// Telemetry is off
+    Off,
+    /// The last complete day was sent already
+    UpToDate,
+    /// Waiting out the delay after a failure
+    Backoff,
+    Sent(UsagePayload),
+}
+
+#[derive(Debug, Default)]
+struct Backoff {
+    failures: u32,
+    retry_at: Option<DateTime<Utc>>,
+}
+
+pub struct Telemetry {
+    db: Arc<DatabaseManager>,
+    pipe_manager: Arc<PipeManager>,
+    screenpipe_dir: PathBuf,
+    endpoint: String,
+    features: Vec<String>,
+    do_not_track: bool,
+    settings: Mutex<TelemetrySettings>,
+    backoff: Mutex<Backoff>,
+    client: OnceLock<reqwest::Client>,
+}
+
+impl Telemetry {
+    pub fn new(
+        db: Arc<DatabaseManager>,
+        pipe_manager: Arc<PipeManager>,
+        screenpipe_dir: PathBuf,
+    ) -> Self {
+        let settings = std::fs::read_to_string(Self::settings_path(&screenpipe_dir))
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        Self {
+            db,
+            pipe_manager,
+            screenpipe_dir,
+            endpoint: TELEMETRY_URL.to_string(),
+            features: Vec::new(),
+            do_not_track: false,
+            settings: Mutex::new(settings),
+            backoff: Mutex::new(Backoff::default()),
+            client: OnceLock::new(),
+        }
+    }
+
+    pub fn with_endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.endpoint = endpoint.into();
+        self
+    }
+
+    /// Optional features turned on, sent by name.
+    pub fn with_features(mut self, mut features: Vec<String>) -> Self {
+        features.sort();
+        self.features = features;
+        self
+    }
+
+    /// Nothing is sent whatever the user opted into, for `DO_NOT_TRACK` and
+    /// `--disable-telemetry`.
+    pub fn with_do_not_track(mut self, do_not_track: bool) -> Self {
+        self.do_not_track = do_not_track;
+        self
+    }
+
+    fn settings_path(screenpipe_dir: &Path) -> PathBuf {
+        screenpipe_dir.join("telemetry.json")
+    }
+
+    pub fn log_path(&self) -> PathBuf {
+        self.screenpipe_dir.join("telemetry_log.jsonl")
+    }
+
+    pub fn status(&self) -> TelemetryStatus {
+        let settings = self.settings.lock().unwrap().clone();
+        TelemetryStatus {
+            enabled: settings.enabled,
+            do_not_track: self.do_not_track,
+            sending: settings.enabled && !self.do_not_track,
+            endpoint: self.endpoint.clone(),
+            last_sent_day: settings.last_sent_day,
+            retry_at: self.backoff.lock().unwrap().retry_at,
+        }
+    }
+
+    /// Opts in or out and saves the choice. Returns the previous one.
+    pub fn set_enabled(&self, enabled: bool) -> Result<bool> {
+        let mut settings = self.settings.lock().unwrap();
+        let previous = settings.enabled;
+        if previous != enabled {
+            let mut updated = settings.clone();
+            updated.enabled = enabled;
+            self.save(&updated)?;
+            *settings = updated;
+            *self.backoff.lock().unwrap() = Backoff::default();
+        }
+        Ok(previous)
+    }
+
+    fn save(&self, settings: &TelemetrySettings) -> Result<()> {
+        std::fs::write(
+            Self::settings_path(&self.screenpipe_dir),
+            serde_json::to_string_pretty(settings)?,
+        )?;
+        Ok(())
+    }
+
+    /// The payload the next ping sends: the counts of the day before `now`.
+    pub async fn preview(&self, now: DateTime<Utc>) -> Result<UsagePayload> {
+        let day = (now - ChronoDuration::days(1)).date_naive();
+        let start = day.and_hms_opt(0, 0, 0).unwrap().and_utc();
+        let counts = self
+            .db
+            .capture_counts(start, start + ChronoDuration::days(1), UsageBucket::Day)
+            .await?;
+        let pipes = self.pipe_manager.list_pipes().await;
+        Ok(UsagePayload {
+            payload_version: PAYLOAD_VERSION,
+            day,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            features: self.features.clone(),
+            frames: magnitude_bucket(counts.iter().map(|c| c.frames).sum()),
+            transcriptions: magnitude_bucket(counts.iter().map(|c| c.transcriptions).sum()),
+            pipes: pipes.len(),
+            pipes_enabled: pipes.iter().filter(|pipe| pipe.enabled).count(),
+        })
+    }
+
+    /// Every ping sent from this data directory, oldest first.
+    pub fn sent(&self) -> Result<Vec<SentPing>> {
+        let content = match std::fs::read_to_string(self.log_path()) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(content
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect())
+    }
+
+    /// Whether the http client for pings was built, which only happens to send one.
+    pub fn client_built(&self) -> bool {
+        self.client.get().is_some()
+    }
+
+    /// Sends the ping of the last complete day if it is due.
+    pub async fn ping(&self, now: DateTime<Utc>) -> Result<PingOutcome> {
+        let settings = self.settings.lock().unwrap().clone();
+        if !settings.enabled || self.do_not_track {
+            return Ok(PingOutcome::Off);
+        }
+        let day = (now - ChronoDuration::days(1)).date_naive();
+        if settings.last_sent_day.is_some_and(|sent| sent >= day) {
+            return Ok(PingOutcome::UpToDate);
+        }
+        if self
+            .backoff
+            .lock()
+            .unwrap()
+            .retry_at
+            .is_some_and(|retry_at| retry_at > now)
+        {
+            return Ok(PingOutcome::Backoff);
+        }
+
+        let payload = self.preview(now).await?;
+        if let Err(e) = self.send(&payload).await {
+            let mut backoff = self.backoff.lock().unwrap();
+            backoff.failures += 1;
+            let delay = RETRY_BASE_SECONDS
+                .saturating_mul(1 << (backoff.failures - 1).min(16))
+                .min(RETRY_MAX_SECONDS);
+            backoff.retry_at = Some(now + ChronoDuration::seconds(delay));
+            return Err(e);
+        }
+        *self.backoff.lock().unwrap() = Backoff::default();
+
+        self.log(&SentPing {
+            sent_at: now,
+            payload: payload.clone(),
+        })?;
+        let mut settings = self.settings.lock().unwrap();
+        settings.last_sent_day = Some(day);
+        self.save(&settings)?;
+        Ok(PingOutcome::Sent(payload))
+    }
+
+    async fn send(&self, payload: &UsagePayload) -> Result<()> {
+        let client = match self.client.get() {
+            Some(client) => client,
+            None => {
+                let client = reqwest::Client::builder()
+                    .timeout(REQUEST_TIMEOUT)
+                    .build()?;
+                self.client.get_or_init(|| client)
+            }
+        };
+        client
+            .post(&self.endpoint)
+            .json(payload)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    fn log(&self, ping: &SentPing) -> Result<()> {
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.log_path())?;
+        writeln!(file, "{}", serde_json::to_string(ping)?)?;
+        Ok(())
+    }
+
+    pub async fn run(self: Arc<Self>) {
+        let start = tokio::time::Instant::now() + STARTUP_DELAY;
+        let mut interval = tokio::time::interval_at(start, TICK_INTERVAL);
+        loop {
+            interval.tick().await;
+            match self.ping(Utc::now()).await {
+                Ok(PingOutcome::Sent(payload)) => debug!("usage ping sent for {}", payload.day),
+                Ok(_) => {}
+                Err(e) => warn!("usage ping failed, retrying later: {}", e),
+            }
+        }
+    }
+}
@@ -1,16 +1,22 @@
+use crate::sources::FrameSource;
+use crate::storage::MediaVolume;
+use crate::storage_mode::{storage_mode, StorageMode};
+use crate::video_guard::{
+    admit, encoder_guard, unencodable, Admission, ChunkFormat, DroppedFrames, VideoGap,
+};
 use chrono::Utc;
 use crossbeam::queue::ArrayQueue;
+use image::DynamicImage;
 use image::ImageFormat::{self};
 use log::{debug, error};
 use log::{info, warn};
-use screenpipe_core::{find_ffmpeg_path, Language};
-use screenpipe_vision::{
-    capture_screenshot_by_window::WindowFilters, continuous_capture, CaptureResult, OcrEngine,
-};
+use screenpipe_core::find_ffmpeg_path;
+use screenpipe_core::power::{power_state, SubsystemOutcome};
+use screenpipe_vision::CaptureResult;
 use std::path::PathBuf;
 use std::process::Stdio;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::io::AsyncBufReadExt;
 use tokio::io::AsyncWriteExt;
 use tokio::io::BufReader;
@@ -20,25 +26,36 @@ use tokio::time::sleep;
 
 pub(crate) const MAX_FPS: f64 = 30.0; // Adjust based on your needs
 const MAX_QUEUE_SIZE: usize = 10;
+/// How long a new resolution must hold before the chunk is split for it. Docking
+/// stations flip between configurations for a few seconds when plugged in.
+pub(crate) const DISPLAY_SETTLE_TIME: Duration = Duration::from_secs(3);
+
+/// Width and height of the frames a chunk is encoded at
+type FrameSize = (u32, u32);
 
 pub struct VideoCapture {
     #[allow(unused)]
     video_frame_queue: Arc<ArrayQueue<Arc<CaptureResult>>>,
-    pub ocr_frame_queue: Arc<ArrayQueue<Arc<CaptureResult>>>,
+    /// Frames with the storage mode they were captured in, only frames captured in
+    /// [`StorageMode::Full`] reach the video encoder
+    pub ocr_frame_queue: Arc<ArrayQueue<(Arc<CaptureResult>, StorageMode)>>,
 }
 
 impl VideoCapture {
+    /// `display_changed` is called with the previous and new frame size when a chunk
+    /// is split because the monitor's resolution changed, `video_gap` with the frames
+    /// that couldn't be written to any chunk.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         output_path: &str,
         fps: f64,
         video_chunk_duration: Duration,
         new_chunk_callback: impl Fn(&str) + Send + Sync + 'static,
-        ocr_engine: Arc<OcrEngine>,
+        frame_source: Arc<dyn FrameSource>,
         monitor_id: u32,
-        ignore_list: &[String],
-        include_list: &[String],
-        languages: Vec<Language>,
-        capture_unfocused_windows: bool,
+        media_volume: Option<Arc<MediaVolume>>,
+        display_changed: impl Fn(FrameSize, FrameSize) + Send + Sync + 'static,
+        video_gap: impl Fn(VideoGap) + Send + Sync + 'static,
     ) -> Self {
         let fps = if fps.is_finite() && fps > 0.0 {
             fps
@@ -46,44 +63,33 @@ impl VideoCapture {
             warn!("Invalid FPS value: {}. Using default of 1.0", fps);
             1.0
         };
-        let interval = Duration::from_secs_f64(1.0 / fps);
         let video_frame_queue = Arc::new(ArrayQueue::new(MAX_QUEUE_SIZE));
         let ocr_frame_queue = Arc::new(ArrayQueue::new(MAX_QUEUE_SIZE));
         let new_chunk_callback = Arc::new(new_chunk_callback);
         let new_chunk_callback_clone = Arc::clone(&new_chunk_callback);
+        let display_changed = Arc::new(display_changed);
+        let video_gap = Arc::new(video_gap);
 
         let capture_video_frame_queue = video_frame_queue.clone();
         let capture_ocr_frame_queue = ocr_frame_queue.clone();
         let (result_sender, mut result_receiver) = channel(512);
-        let window_filters = Arc::new(WindowFilters::new(ignore_list, include_list));
-        let window_filters_clone = Arc::clone(&window_filters);
-        let _capture_thread = tokio::spawn(async move {
-            continuous_capture(
-                result_sender,
-                interval,
-                *ocr_engine,
-                monitor_id,
-                window_filters_clone,
-                languages.clone(),
-                capture_unfocused_windows,
-            )
-            .await;
-        });
+        let lossless = frame_source.lossless();
+        let _capture_thread = tokio::spawn(frame_source.start(result_sender));
 
         // In the _queue_thread
         let _queue_thread = tokio::spawn(async move {
             // Helper function to push to queue and handle errors
-            fn push_to_queue(
-                queue: &ArrayQueue<Arc<CaptureResult>>,
-                result: &Arc<CaptureResult>,
+            fn push_to_queue<T: Clone>(
+                queue: &ArrayQueue<T>,
+                result: &T,
                 queue_name: &str,
             ) -> bool {
-                if queue.push(Arc::clone(result)).is_err() {
+                if queue.push(result.clone()).is_err() {
                     if queue.pop().is_none() {
                         error!("{} queue is in an inconsistent state", queue_name);
                         return false;
                     }
-                    if queue.push(Arc::clone(result)).is_err() {
+                    if queue.push(result.clone()).is_err() {
                         error!(
                             "Failed to push to {} queue after removing oldest frame",
                             queue_name
@@ -99,9 +105,22 @@ impl VideoCapture {
                 debug!("Received frame {} for queueing", frame_number);
 
                 let result = Arc::new(result);
+                // Decided once per frame, so its row and the encoder agree on it
+                let mode = storage_mode().get();
+
+                if lossless {
+                    // Wait for room instead of dropping, so replays stay reproducible
+                    while (mode.keeps_video() && capture_video_frame_queue.is_full())
+                        || capture_ocr_frame_queue.is_full()
+                    {
+                        sleep(Duration::from_millis(10)).await;
+                    }
+                }
 
-                let video_pushed = push_to_queue(&capture_video_frame_queue, &result, "Video");
-                let ocr_pushed = push_to_queue(&capture_ocr_frame_queue, &result, "OCR");
+                let video_pushed = !mode.keeps_video()
+                    || push_to_queue(&capture_video_frame_queue, &result, "Video");
+                let ocr_pushed =
+                    push_to_queue(&capture_ocr_frame_queue, &(result.clone(), mode), "OCR");
 
                 if !video_pushed || !ocr_pushed {
                     error!(
@@ -129,8 +148,11 @@ impl VideoCapture {
                 &output_path,
                 fps,
                 new_chunk_callback_clone,
+                display_changed,
+                video_gap,
                 monitor_id,
                 video_chunk_duration,
+                media_volume,
             )
             .await;
         });
@@ -210,29 +232,105 @@ async fn log_ffmpeg_output(stream: impl AsyncBufReadExt + Unpin, stream_name: &s
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn save_frames_as_video(
     frame_queue: &Arc<ArrayQueue<Arc<CaptureResult>>>,
     output_path: &str,
     fps: f64,
     new_chunk_callback: Arc<dyn Fn(&str) + Send + Sync>,
+    display_changed: Arc<dyn Fn(FrameSize, FrameSize) + Send + Sync>,
+    video_gap: Arc<dyn Fn(VideoGap) + Send + Sync>,
     monitor_id: u32,
     video_chunk_duration: Duration,
+    media_volume: Option<Arc<MediaVolume>>,
 ) {
     debug!("Starting save_frames_as_video function");
     let frames_per_video = (fps * video_chunk_duration.as_secs_f64()).ceil() as usize;
     let mut frame_count = 0;
     let mut current_ffmpeg: Option<Child> = None;
     let mut current_stdin: Option<ChildStdin> = None;
+    // ffmpeg can't change the size or pixel format of a chunk midway, a frame that
+    // doesn't fit starts the next one, see `video_guard`
+    let mut chunk_format: Option<ChunkFormat> = None;
+    let mut next_chunk_frame: Option<Arc<CaptureResult>> = None;
+    let power = power_state();
+    let subsystem = format!("video monitor {}", monitor_id);
+    power.register(&subsystem);
+    let mut chunk_epoch = power.epoch();
 
     loop {
+        if power.is_asleep() || power.epoch() != chunk_epoch {
+            // A chunk must not span the time the machine was asleep: close it before
+            // sleeping (or right after waking when the sleep wasn't announced)
+            let closed = current_ffmpeg.is_some();
+            if let Some(child) = current_ffmpeg.take() {
+                finish_ffmpeg_process(child, current_stdin.take()).await;
+            }
+            chunk_epoch = power.epoch();
+            let outcome = match (power.is_asleep(), closed) {
+                (true, true) => "chunk finalized",
+                (true, false) => "no chunk open",
+                (false, _) => "new chunk on next frame",
+            };
+            power.report(
+                &subsystem,
+                chunk_epoch,
+                SubsystemOutcome::Ok(outcome.to_string()),
+            );
+            while power.is_asleep() {
+                while frame_queue.pop().is_some() {}
+                sleep(Duration::from_millis(500)).await;
+            }
+            frame_count = 0;
+            continue;
+        }
+
+        if let Some(media_volume) = media_volume.as_ref().filter(|v| !v.is_available()) {
+            // Close the chunk being written and drop frames until the volume returns,
+            // then start a fresh chunk
+            if let Some(child) = current_ffmpeg.take() {
+                finish_ffmpeg_process(child, current_stdin.take()).await;
+            }
+            while !media_volume.is_available() {
+                while frame_queue.pop().is_some() {}
+                sleep(Duration::from_millis(500)).await;
+            }
+            frame_count = 0;
+            continue;
+        }
+
+        if frame_queue.is_empty() && !storage_mode().get().keeps_video() {
+            // Frames captured without video never reach the queue: once the queued ones
+            // are encoded the chunk is complete, a new one starts when video is kept again
+            if let Some(child) = current_ffmpeg.take() {
+                finish_ffmpeg_process(child, current_stdin.take()).await;
+            }
+            frame_count = 0;
+            sleep(Duration::from_millis(100)).await;
+            continue;
+        }
+
         if frame_count >= frames_per_video || current_ffmpeg.is_none() {
             if let Some(child) = current_ffmpeg.take() {
                 finish_ffmpeg_process(child, current_stdin.take()).await;
             }
 
             frame_count = 0;
-            let first_frame = wait_for_first_frame(frame_queue).await;
-            let buffer = encode_frame(&first_frame);
+            let first_frame = match next_chunk_frame.take() {
+                Some(frame) => frame,
+                None => wait_for_first_frame(frame_queue).await,
+            };
+            // A frame that can't be encoded can't start a chunk either
+            let buffer = match encode_image(first_frame.image.image()) {
+                Ok(buffer) => buffer,
+                Err(reason) => {
+                    let image = first_frame.image.image();
+                    encoder_guard().record(monitor_id, None, image, &Admission::Drop(reason));
+                    drop_frame(monitor_id, &first_frame, reason, video_gap.as_ref());
+                    continue;
+                }
+            };
+            chunk_format = Some(ChunkFormat::of(first_frame.image.image()));
 
             let output_file = create_output_file(output_path, monitor_id);
             new_chunk_callback(&output_file);
@@ -244,6 +342,7 @@ async fn save_frames_as_video(
 
                     if let Err(e) = write_frame_to_ffmpeg(&mut stdin, &buffer).await {
                         error!("Failed to write first frame to ffmpeg: {}", e);
+                        report_gap(&first_frame, "write_failed", video_gap.as_ref());
                         continue;
                     }
                     frame_count += 1;
@@ -254,20 +353,70 @@ async fn save_frames_as_video(
                 }
                 Err(e) => {
                     error!("Failed to start FFmpeg process: {}", e);
+                    report_gap(&first_frame, "encoder_failed", video_gap.as_ref());
                     continue;
                 }
             }
         }
 
-        process_frames(
+        let chunk_break = process_frames(
             frame_queue,
             &mut current_stdin,
             &mut frame_count,
             frames_per_video,
             fps,
+            media_volume.as_deref(),
+            chunk_epoch,
+            chunk_format,
+            monitor_id,
+            video_gap.as_ref(),
         )
         .await;
 
+        match chunk_break {
+            Some(ChunkBreak::Resized(resized)) => {
+                let mut dropped = DroppedFrames::default();
+                let settled =
+                    wait_for_stable_size(frame_queue, resized, DISPLAY_SETTLE_TIME, &mut dropped)
+                        .await;
+                let size = frame_size(&settled);
+                match chunk_format.map(|chunk| (chunk.width, chunk.height)) {
+                    Some(previous) if previous != size => {
+                        info!(
+                            "monitor {} changed from {}x{} to {}x{}, starting a new chunk",
+                            monitor_id, previous.0, previous.1, size.0, size.1
+                        );
+                        if let Some(child) = current_ffmpeg.take() {
+                            finish_ffmpeg_process(child, current_stdin.take()).await;
+                        }
+                        display_changed(previous, size);
+                        next_chunk_frame = Some(settled);
+                    }
+                    // Flipped back before settling, the chunk goes on
+                    _ => {
+                        debug!("monitor {} is back to its previous size", monitor_id);
+                        dropped.add(settled.timestamp);
+                    }
+                }
+                if let Some(gap) = dropped.take_gap("display_settling") {
+                    video_gap(gap);
+                }
+            }
+            Some(ChunkBreak::Reformatted(frame)) => {
+                info!(
+                    "monitor {} frames changed from {} to {}, starting a new chunk",
+                    monitor_id,
+                    chunk_format.map_or_else(String::new, |chunk| chunk.to_string()),
+                    ChunkFormat::of(frame.image.image())
+                );
+                if let Some(child) = current_ffmpeg.take() {
+                    finish_ffmpeg_process(child, current_stdin.take()).await;
+                }
+                next_chunk_frame = Some(frame);
+            }
+            None => {}
+        }
+
         tokio::task::yield_now().await;
     }
 }
@@ -284,21 +433,91 @@ async fn wait_for_first_frame(
     }
 }
 
-fn encode_frame(frame: &CaptureResult) -> Vec<u8> {
+/// Drops frames until their size held for `settle`, then returns the latest one.
+/// The frames dropped are added to `dropped`.
+async fn wait_for_stable_size(
+    frame_queue: &Arc<ArrayQueue<Arc<CaptureResult>>>,
+    first: Arc<CaptureResult>,
+    settle: Duration,
+    dropped: &mut DroppedFrames,
+) -> Arc<CaptureResult> {
+    let mut latest = first;
+    let mut since = Instant::now();
+    while since.elapsed() < settle {
+        match frame_queue.pop() {
+            Some(frame) => {
+                if frame_size(&frame) != frame_size(&latest) {
+                    since = Instant::now();
+                }
+                dropped.add(latest.timestamp);
+                latest = frame;
+            }
+            None => sleep(Duration::from_millis(50)).await,
+        }
+    }
+    latest
+}
+
+fn frame_size(frame: &CaptureResult) -> FrameSize {
+    (frame.image.width(), frame.image.height())
+}
+
+/// The png piped to ffmpeg for an image, or why it can't be encoded.
+fn encode_image(image: &DynamicImage) -> Result<Vec<u8>, &'static str> {
+    if let Some(reason) = unencodable(image) {
+        return Err(reason);
+    }
     let mut buffer = Vec::new();
-    frame
-        .image
+    image
         .write_to(&mut std::io::Cursor::new(&mut buffer), ImageFormat::Png)
-        .expect("Failed to encode frame");
-    buffer
+        .map_err(|e| {
+            warn!("Failed to encode frame: {}", e);
+            "encode_failed"
+        })?;
+    Ok(buffer)
+}
+
+/// Leaves a frame the encoder can't use out of the video, reported as a gap.
+fn drop_frame(
+    monitor_id: u32,
+    frame: &CaptureResult,
+    reason: &str,
+    video_gap: &(dyn Fn(VideoGap) + Send + Sync),
+) {
+    warn!(
+        "monitor {}: frame {} ({}) left out of the video: {}",
+        monitor_id,
+        frame.frame_number,
+        ChunkFormat::of(frame.image.image()),
+        reason
+    );
+    report_gap(frame, reason, video_gap);
+}
+
+/// A frame missing from the video, as a gap of its own.
+fn report_gap(frame: &CaptureResult, reason: &str, video_gap: &(dyn Fn(VideoGap) + Send + Sync)) {
+    let mut dropped = DroppedFrames::default();
+    dropped.add(frame.timestamp);
+    if let Some(gap) = dropped.take_gap(reason) {
+        video_gap(gap);
+    }
 }
 
 fn create_output_file(output_path: &str, monitor_id: u32) -> String {
     let time = Utc::now();
     let formatted_time = time.format("%Y-%m-%d_%H-%M-%S").to_string();
-    PathBuf::from(output_path)
-        .join(format!("monitor_{}_{}.mp4", monitor_id, formatted_time))
-        .to_str()
+    let mut path =
+        PathBuf::from(output_path).join(format!("monitor_{}_{}.mp4", monitor_id, formatted_time));
+    // Chunks split for a frame that didn't fit can start within the same second
+    let mut suffix = 1;
+    while path.exists() {
+        path = PathBuf::from(output_path).join(format!(
+            "monitor_{}_{}_{}.mp4",
+            monitor_id, formatted_time, suffix
+        ));
+        suffix += 1;
+    }
+    path.to_str()
         .expect("Failed to create valid path")
         .to_string()
 }
@@ -312,20 +531,79 @@ fn spawn_ffmpeg_loggers(stderr: Option<ChildStderr>, stdout: Option<ChildStdout>
     }
 }
 
+/// Why the open chunk can't take a frame, with the frame.
+enum ChunkBreak {
+    /// Another size, the display changed
+    Resized(Arc<CaptureResult>),
+    /// Another pixel format that isn't converted
+    Reformatted(Arc<CaptureResult>),
+}
+
+/// Writes queued frames to the open chunk until it is full, returning the first frame
+/// that doesn't fit `chunk_format`.
+#[allow(clippy::too_many_arguments)]
 async fn process_frames(
     frame_queue: &Arc<ArrayQueue<Arc<CaptureResult>>>,
     current_stdin: &mut Option<ChildStdin>,
     frame_count: &mut usize,
     frames_per_video: usize,
     fps: f64,
-) {
+    media_volume: Option<&MediaVolume>,
+    chunk_epoch: u64,
+    chunk_format: Option<ChunkFormat>,
+    monitor_id: u32,
+    video_gap: &(dyn Fn(VideoGap) + Send + Sync),
+) -> Option<ChunkBreak> {
     let write_timeout = Duration::from_secs_f64(1.0 / fps);
+    let power = power_state();
     while *frame_count < frames_per_video {
+        if media_volume.is_some_and(|v| !v.is_available()) {
+            break;
+        }
+        if power.is_asleep() || power.epoch() != chunk_epoch {
+            break;
+        }
+        if frame_queue.is_empty() && !storage_mode().get().keeps_video() {
+            break;
+        }
         if let Some(frame) = frame_queue.pop() {
-            let buffer = encode_frame(&frame);
+            let admission = match chunk_format.as_ref() {
+                Some(chunk) => admit(frame.image.image(), chunk),
+                None => Admission::Write,
+            };
+            encoder_guard().record(
+                monitor_id,
+                chunk_format.as_ref(),
+                frame.image.image(),
+                &admission,
+            );
+            let converted;
+            let image = match admission {
+                Admission::Write => frame.image.image(),
+                Admission::Convert(image) => {
+                    converted = image;
+                    &converted
+                }
+                Admission::Resized => return Some(ChunkBreak::Resized(frame)),
+                Admission::NewChunk => return Some(ChunkBreak::Reformatted(frame)),
+                Admission::Drop(reason) => {
+                    drop_frame(monitor_id, &frame, reason, video_gap);
+                    continue;
+                }
+            };
+            let buffer = match encode_image(image) {
+                Ok(buffer) => buffer,
+                Err(reason) => {
+                    let admission = Admission::Drop(reason);
+                    encoder_guard().record(monitor_id, chunk_format.as_ref(), image, &admission);
+                    drop_frame(monitor_id, &frame, reason, video_gap);
+                    continue;
+                }
+            };
             if let Some(stdin) = current_stdin.as_mut() {
                 if let Err(e) = write_frame_with_retry(stdin, &buffer).await {
                     error!("Failed to write frame to ffmpeg after max retries: {}", e);
+                    report_gap(&frame, "write_failed", video_gap);
                     break;
                 }
                 *frame_count += 1;
@@ -337,6 +615,7 @@ async fn process_frames(
             tokio::time::sleep(write_timeout).await;
         }
     }
+    None
 }
 
 async fn write_frame_with_retry(
@@ -33,6 +33,8 @@ pub struct DeviceFrame {
     pub image_data: Vec<u8>,
     pub metadata: FrameMetadata,
     pub audio_entries: Vec<AudioEntry>,
+    /// Set when `image_data` is empty because the frame was stored without an image
+    pub no_image_reason: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -482,6 +484,52 @@ impl FrameCache {
             };
 
             for device_data in &chunk.ocr_entries {
+                if !device_data.storage_mode.keeps_video() {
+                    // Nothing to extract, the frame has a thumbnail or no image at all
+                    let image_data = match &device_data.thumbnail_path {
+                        Some(path) => fs::read(path).await.unwrap_or_else(|e| {
+                            debug!("failed to read thumbnail {}: {}", path, e);
+                            Vec::new()
+                        }),
+                        None => Vec::new(),
+                    };
+                    let no_image_reason = if image_data.is_empty() {
+                        Some(
+                            device_data
+                                .storage_mode
+                                .missing_image_reason()
+                                .unwrap_or("no image available, the thumbnail is missing")
+                                .to_string(),
+                        )
+                    } else {
+                        None
+                    };
+                    timeseries_frame.frame_data.push(DeviceFrame {
+                        device_id: device_data.device_name.clone(),
+                        image_data,
+                        metadata: FrameMetadata {
+                            file_path: device_data.thumbnail_path.clone().unwrap_or_default(),
+                            app_name: device_data.app_name.clone(),
+                            window_name: device_data.window_name.clone(),
+                            transcription: chunk
+                                .audio_entries
+                                .iter()
+                                .map(|a| a.transcription.clone())
+                                .collect::<Vec<_>>()
+                                .join(" "),
+                            ocr_text: device_data.text.clone(),
+                        },
+                        audio_entries: chunk
+                            .audio_entries
+                            .iter()
+                            .cloned()
+                            .map(Into::into)
+                            .collect(),
+                        no_image_reason,
+                    });
+                    continue;
+                }
+
                 let cache_key = format!("{}||{}", chunk.timestamp, device_data.device_name);
                 debug!("checking cache for key: {}", cache_key);
 
@@ -511,6 +559,7 @@ impl FrameCache {
                                     duration_secs: a.duration_secs,
                                 })
                                 .collect(),
+                            no_image_reason: None,
                         });
                     }
                     _ => {
@@ -785,6 +834,7 @@ async fn extract_frame(
                             duration_secs: a.duration_secs,
                         })
                         .collect(),
+                    no_image_reason: None,
                 }],
             })
             .await?;
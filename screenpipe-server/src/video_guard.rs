@@ -0,0 +1,253 @@
+//! Checks every frame against the video chunk it is about to be written to.
+//!
+//! A chunk is encoded in the size and pixel format of its first frame. Once in a while
+//! capture hands over a frame in another layout, a gpu driver switching formats or a
+//! dpi change mid capture, and piping it into the running ffmpeg crashes it or writes a
+//! chunk that doesn't play. So before a frame is written:
+//!
+//! - a frame that can't be encoded at all, without pixels or failing to encode, is
+//!   dropped and reported as a `video_gap` event
+//! - a frame of another size is left to the encoder, which starts a new chunk once the
+//!   new size holds, see [`crate::video`]
+//! - an 8 bit frame in another 8 bit layout is converted to the chunk's
+//! - any other format change closes the chunk, a new one starts with the frame
+//!
+//! Rows of captured frames are tightly packed, a change of stride is a change of size
+//! or format.
+//!
+//! Every mismatch is counted in [`encoder_guard`], with the formats involved, and shown
+//! by `GET /video/metrics`.
+
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use image::{ColorType, DynamicImage};
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+
+/// Mismatches kept in [`EncoderGuardSnapshot::recent`].
+pub const RECENT_MISMATCHES: usize = 32;
+
+/// Size and pixel format a chunk is encoded in, those of its first frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkFormat {
+    pub width: u32,
+    pub height: u32,
+    pub color: ColorType,
+}
+
+impl ChunkFormat {
+    pub fn of(image: &DynamicImage) -> Self {
+        Self {
+            width: image.width(),
+            height: image.height(),
+            color: image.color(),
+        }
+    }
+}
+
+impl fmt::Display for ChunkFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{} {:?}", self.width, self.height, self.color)
+    }
+}
+
+/// What to do with a frame before writing it to the open chunk.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Admission {
+    /// Fits the chunk as is
+    Write,
+    /// Fits once converted to the chunk's pixel format
+    Convert(DynamicImage),
+    /// Another size, a display change
+    Resized,
+    /// Another pixel format that isn't converted, the chunk is closed and the frame
+    /// starts the next one
+    NewChunk,
+    /// Can't be encoded, with why
+    Drop(&'static str),
+}
+
+/// Why a frame can't be encoded in any chunk, if it can't.
+pub fn unencodable(image: &DynamicImage) -> Option<&'static str> {
+    if image.width() == 0 || image.height() == 0 {
+        return Some("empty_frame");
+    }
+    None
+}
+
+/// Whether `image` can be written to a chunk in `chunk`, and how.
+pub fn admit(image: &DynamicImage, chunk: &ChunkFormat) -> Admission {
+    if let Some(reason) = unencodable(image) {
+        return Admission::Drop(reason);
+    }
+    let format = ChunkFormat::of(image);
+    if (format.width, format.height) != (chunk.width, chunk.height) {
+        return Admission::Resized;
+    }
+    if format.color == chunk.color {
+        return Admission::Write;
+    }
+    if !is_8_bit(format.color) || !is_8_bit(chunk.color) {
+        // Converting would lose depth or make it up
+        return Admission::NewChunk;
+    }
+    Admission::Convert(match chunk.color {
+        ColorType::Rgb8 => DynamicImage::ImageRgb8(image.to_rgb8()),
+        ColorType::Rgba8 => DynamicImage::ImageRgba8(image.to_rgba8()),
+        ColorType::L8 => DynamicImage::ImageLuma8(image.to_luma8()),
+        _ => DynamicImage::ImageLumaA8(image.to_luma_alpha8()),
+    })
+}
+
+fn is_8_bit(color: ColorType) -> bool {
+    matches!(
+        color,
+        ColorType::Rgb8 | ColorType::Rgba8 | ColorType::L8 | ColorType::La8
+    )
+}
+
+/// Frames captured between `start_time` and `end_time` that are missing from the
+/// video, `frames` of them. Their text is still indexed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VideoGap {
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    pub frames: u64,
+    pub reason: String,
+}
+
+/// Frames dropped in a row, reported as one [`VideoGap`].
+#[derive(Debug, Default)]
+pub struct DroppedFrames {
+    range: Option<(Instant, Instant)>,
+    count: u64,
+}
+
+impl DroppedFrames {
+    pub fn add(&mut self, captured_at: Instant) {
+        self.range = Some(match self.range {
+            Some((first, last)) => (first.min(captured_at), last.max(captured_at)),
+            None => (captured_at, captured_at),
+        });
+        self.count += 1;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// The gap of the frames added so far, starting over.
+    pub fn take_gap(&mut self, reason: &str) -> Option<VideoGap> {
+        let (first, last) = self.range.take()?;
+        let frames = std::mem::take(&mut self.count);
+        Some(VideoGap {
+            start_time: wall_time(first),
+            end_time: wall_time(last),
+            frames,
+            reason: reason.to_string(),
+        })
+    }
+}
+
+/// Wall clock time of an instant of this process.
+fn wall_time(instant: Instant) -> DateTime<Utc> {
+    let ago = Instant::now().saturating_duration_since(instant);
+    Utc::now() - ChronoDuration::from_std(ago).unwrap_or_default()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MismatchAction {
+    Converted,
+    NewChunk,
+    Dropped,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FormatMismatch {
+    pub timestamp: DateTime<Utc>,
+    pub monitor_id: u32,
+    /// Format of the open chunk, none when the frame was to start one
+    pub chunk: Option<String>,
+    /// Format of the frame, e.g. `1920x1080 Rgba16`
+    pub frame: String,
+    pub action: MismatchAction,
+    /// Why a dropped frame couldn't be encoded
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct EncoderGuardSnapshot {
+    /// Frames converted to the pixel format of their chunk
+    pub converted: u64,
+    /// Chunks closed early for a frame in another pixel format
+    pub new_chunks: u64,
+    /// Frames that couldn't be encoded
+    pub dropped: u64,
+    /// Mismatches by the pixel format of the frame that caused them
+    pub by_format: BTreeMap<String, u64>,
+    /// The last [`RECENT_MISMATCHES`], oldest first
+    pub recent: Vec<FormatMismatch>,
+}
+
+/// Frames that didn't fit their chunk, across the monitors of the process.
+#[derive(Debug, Default)]
+pub struct EncoderGuardMetrics {
+    inner: Mutex<(EncoderGuardSnapshot, VecDeque<FormatMismatch>)>,
+}
+
+impl EncoderGuardMetrics {
+    pub fn record(
+        &self,
+        monitor_id: u32,
+        chunk: Option<&ChunkFormat>,
+        frame: &DynamicImage,
+        admission: &Admission,
+    ) {
+        let (action, reason) = match admission {
+            Admission::Convert(_) => (MismatchAction::Converted, None),
+            Admission::NewChunk => (MismatchAction::NewChunk, None),
+            Admission::Drop(reason) => (MismatchAction::Dropped, Some(reason.to_string())),
+            Admission::Write | Admission::Resized => return,
+        };
+        let mut inner = self.inner.lock().unwrap();
+        let (counts, recent) = &mut *inner;
+        match action {
+            MismatchAction::Converted => counts.converted += 1,
+            MismatchAction::NewChunk => counts.new_chunks += 1,
+            MismatchAction::Dropped => counts.dropped += 1,
+        }
+        *counts
+            .by_format
+            .entry(format!("{:?}", frame.color()))
+            .or_default() += 1;
+        if recent.len() == RECENT_MISMATCHES {
+            recent.pop_front();
+        }
+        recent.push_back(FormatMismatch {
+            timestamp: Utc::now(),
+            monitor_id,
+            chunk: chunk.map(ToString::to_string),
+            frame: ChunkFormat::of(frame).to_string(),
+            action,
+            reason,
+        });
+    }
+
+    pub fn snapshot(&self) -> EncoderGuardSnapshot {
+        let inner = self.inner.lock().unwrap();
+        let (counts, recent) = &*inner;
+        EncoderGuardSnapshot {
+            recent: recent.iter().cloned().collect(),
+            ..counts.clone()
+        }
+    }
+}
+
+static ENCODER_GUARD: OnceLock<EncoderGuardMetrics> = OnceLock::new();
+
+pub fn encoder_guard() -> &'static EncoderGuardMetrics {
+    ENCODER_GUARD.get_or_init(EncoderGuardMetrics::default)
+}
@@ -0,0 +1,157 @@
+use crate::events::{EventKind, EventRecorder};
+use chrono::{DateTime, Utc};
+use log::{error, info, warn};
+use screenpipe_core::clock::Clock;
+use screenpipe_core::models::model_registry;
+use screenpipe_core::power::{PowerEvent, PowerSource, PowerState, SubsystemOutcome};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+
+/// How long the wake sequence waits for subsystems to come back before logging.
+pub const WAKE_REPORT_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Everything that happened when the machine woke up, recorded as one `wake` event.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WakeSummary {
+    pub source: PowerSource,
+    pub slept_at: DateTime<Utc>,
+    pub woke_at: DateTime<Utc>,
+    pub slept_secs: i64,
+    pub subsystems: BTreeMap<String, SubsystemOutcome>,
+}
+
+/// Records every sleep and runs the wake sequence for every wake reported by `power`
+/// until the process exits.
+pub async fn handle_power_events(events: Arc<EventRecorder>, power: &'static PowerState) {
+    let mut power_events = power.subscribe();
+    loop {
+        match power_events.recv().await {
+            Ok(PowerEvent::Sleep { at, .. }) => {
+                if let Err(e) = events.record_at(EventKind::Sleep {}, at).await {
+                    error!("failed to record sleep: {}", e);
+                }
+            }
+            Ok(PowerEvent::Wake {
+                epoch,
+                slept_at,
+                woke_at,
+                source,
+            }) => {
+                let summary = run_wake_sequence(
+                    &events,
+                    power,
+                    epoch,
+                    slept_at,
+                    woke_at,
+                    source,
+                    WAKE_REPORT_TIMEOUT,
+                )
+                .await;
+                match serde_json::to_string(&summary) {
+                    Ok(json) => info!("wake: {}", json),
+                    Err(e) => error!("failed to serialize wake summary: {}", e),
+                }
+                if let Err(e) = events.record_at(EventKind::Wake(summary), woke_at).await {
+                    error!("failed to record wake: {}", e);
+                }
+            }
+            Err(RecvError::Lagged(skipped)) => {
+                warn!("missed {} power events", skipped);
+            }
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
+
+/// Unloads the transcription models whenever the machine goes to sleep, they are
+/// loaded again for the first audio after waking. For `--unload-models-on-sleep`.
+pub async fn unload_models_on_sleep(power: &'static PowerState) {
+    let mut events = power.subscribe();
+    loop {
+        match events.recv().await {
+            Ok(PowerEvent::Sleep { .. }) => {
+                match tokio::task::spawn_blocking(|| model_registry().unload_all()).await {
+                    Ok(unloaded) if !unloaded.is_empty() => {
+                        info!("unloaded models for sleep: {}", unloaded.join(", "))
+                    }
+                    Ok(_) => {}
+                    Err(e) => error!("failed to unload models for sleep: {}", e),
+                }
+            }
+            Ok(PowerEvent::Wake { .. }) => {}
+            Err(RecvError::Lagged(skipped)) => {
+                warn!("missed {} power events", skipped);
+            }
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
+
+/// Records every wall clock jump noticed by `clock` until the process exits, so
+/// stamps around the jump can be told apart from the system time.
+pub async fn record_clock_adjustments(events: Arc<EventRecorder>, clock: Arc<Clock>) {
+    let mut adjustments = clock.subscribe();
+    loop {
+        match adjustments.recv().await {
+            Ok(adjustment) => {
+                let kind = EventKind::ClockAdjusted {
+                    system_time: adjustment.system_time,
+                    monotonic_ns: adjustment.monotonic_ns as i64,
+                    jump_ms: adjustment.jump_ms,
+                    timestamp_source: adjustment.source.as_str().to_string(),
+                };
+                if let Err(e) = events.record_at(kind, adjustment.detected_at).await {
+                    error!("failed to record clock adjustment: {}", e);
+                }
+            }
+            Err(RecvError::Lagged(skipped)) => {
+                warn!("missed {} clock adjustments", skipped);
+            }
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
+
+/// Records the sleep as a capture gap and collects what each subsystem did on wake.
+/// Video, audio and pipes react to the wake on their own; this only waits for them.
+pub async fn run_wake_sequence(
+    events: &EventRecorder,
+    power: &'static PowerState,
+    epoch: u64,
+    slept_at: DateTime<Utc>,
+    woke_at: DateTime<Utc>,
+    source: PowerSource,
+    timeout: Duration,
+) -> WakeSummary {
+    let gap = EventKind::CaptureGap {
+        start_time: slept_at,
+        end_time: woke_at,
+        reason: "sleep".to_string(),
+    };
+    let gap = match events.record_at(gap, slept_at).await {
+        Ok(event) => SubsystemOutcome::Ok(format!("gap {} recorded", event.id)),
+        Err(e) => SubsystemOutcome::Failed(e.to_string()),
+    };
+
+    let mut subsystems =
+        tokio::task::spawn_blocking(move || power.wait_for_reports(epoch, timeout))
+            .await
+            .unwrap_or_default();
+    subsystems.insert("database".to_string(), gap);
+    // The health check measures staleness from the last wake, see `health_check`
+    subsystems.insert(
+        "watchdog".to_string(),
+        SubsystemOutcome::Ok("baseline reset".to_string()),
+    );
+
+    WakeSummary {
+        source,
+        slept_at,
+        woke_at,
+        slept_secs: (woke_at - slept_at).num_seconds(),
+        subsystems,
+    }
+}
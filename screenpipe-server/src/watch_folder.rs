@@ -0,0 +1,443 @@
+use crate::storage::path_prefix;
+use crate::DatabaseManager;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use screenpipe_core::Language;
+use screenpipe_vision::{ocr_image, OcrEngine};
+use std::collections::HashSet;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+pub const DEFAULT_MAX_FILE_SIZE: u64 = 50 * 1024 * 1024;
+pub const DEFAULT_EXTRACTION_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Events arriving within this window are handled as one batch, so a file written
+/// in several chunks is only extracted once.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Bytes read from a file with an unknown extension to decide whether it is text.
+const SNIFF_LEN: usize = 8 * 1024;
+
+const TEXT_EXTENSIONS: &[&str] = &[
+    "txt", "md", "markdown", "rst", "org", "csv", "tsv", "json", "yaml", "yml", "log", "html",
+    "htm", "xml",
+];
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "webp", "bmp", "tif", "tiff", "gif"];
+/// Files still being written by browsers and editors.
+const PARTIAL_EXTENSIONS: &[&str] = &["part", "crdownload", "download", "tmp", "swp"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocumentKind {
+    Text,
+    Pdf,
+    Image,
+}
+
+impl DocumentKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DocumentKind::Text => "text",
+            DocumentKind::Pdf => "pdf",
+            DocumentKind::Image => "image",
+        }
+    }
+
+    /// Kind implied by the extension. Unknown extensions return `None` and are sniffed.
+    pub fn from_extension(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        if extension == "pdf" {
+            Some(DocumentKind::Pdf)
+        } else if TEXT_EXTENSIONS.contains(&extension.as_str()) {
+            Some(DocumentKind::Text)
+        } else if IMAGE_EXTENSIONS.contains(&extension.as_str()) {
+            Some(DocumentKind::Image)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipReason {
+    Unsupported,
+    TooLarge,
+    TimedOut,
+    Failed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexOutcome {
+    Indexed,
+    Unchanged,
+    Removed,
+    /// Hidden files, partial downloads and directories with nothing to do
+    Ignored,
+    Skipped(SkipReason),
+}
+
+#[derive(Debug, Default)]
+pub struct WatchFolderStats {
+    pub indexed: AtomicU64,
+    pub removed: AtomicU64,
+    pub skipped_unsupported: AtomicU64,
+    pub skipped_too_large: AtomicU64,
+    pub timed_out: AtomicU64,
+    pub failed: AtomicU64,
+}
+
+impl WatchFolderStats {
+    pub fn skipped(&self) -> u64 {
+        self.skipped_unsupported.load(Ordering::Relaxed)
+            + self.skipped_too_large.load(Ordering::Relaxed)
+            + self.timed_out.load(Ordering::Relaxed)
+            + self.failed.load(Ordering::Relaxed)
+    }
+
+    fn record(&self, outcome: IndexOutcome) {
+        let counter = match outcome {
+            IndexOutcome::Indexed => &self.indexed,
+            IndexOutcome::Removed => &self.removed,
+            IndexOutcome::Skipped(SkipReason::Unsupported) => &self.skipped_unsupported,
+            IndexOutcome::Skipped(SkipReason::TooLarge) => &self.skipped_too_large,
+            IndexOutcome::Skipped(SkipReason::TimedOut) => &self.timed_out,
+            IndexOutcome::Skipped(SkipReason::Failed) => &self.failed,
+            IndexOutcome::Unchanged | IndexOutcome::Ignored => return,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WatchFolderConfig {
+    pub dirs: Vec<PathBuf>,
+    pub max_file_size: u64,
+    pub extraction_timeout: Duration,
+    /// Engine used for images. Without one, images are skipped as unsupported.
+    pub ocr_engine: Option<Arc<OcrEngine>>,
+    pub languages: Vec<Language>,
+}
+
+impl WatchFolderConfig {
+    pub fn new(dirs: Vec<PathBuf>) -> Self {
+        Self {
+            dirs,
+            max_file_size: DEFAULT_MAX_FILE_SIZE,
+            extraction_timeout: DEFAULT_EXTRACTION_TIMEOUT,
+            ocr_engine: None,
+            languages: Vec::new(),
+        }
+    }
+}
+
+/// Indexes the files in user chosen directories as `document` content.
+///
+/// Files are re-extracted when their mtime or size changes, and deleting a file
+/// tombstones its document so it drops out of search.
+pub struct WatchFolder {
+    db: Arc<DatabaseManager>,
+    config: WatchFolderConfig,
+    stats: WatchFolderStats,
+    reported_skips: AtomicU64,
+}
+
+impl WatchFolder {
+    pub fn new(db: Arc<DatabaseManager>, config: WatchFolderConfig) -> Self {
+        Self {
+            db,
+            config,
+            stats: WatchFolderStats::default(),
+            reported_skips: AtomicU64::new(0),
+        }
+    }
+
+    pub fn stats(&self) -> &WatchFolderStats {
+        &self.stats
+    }
+
+    /// Watches the configured directories until the watcher fails.
+    pub async fn run(self: Arc<Self>) -> Result<()> {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let mut watcher = RecommendedWatcher::new(
+            move |event: notify::Result<notify::Event>| {
+                let _ = tx.send(event);
+            },
+            notify::Config::default(),
+        )?;
+
+        for dir in &self.config.dirs {
+            match watcher.watch(dir, RecursiveMode::Recursive) {
+                Ok(_) => info!("watching folder {}", dir.display()),
+                Err(e) => warn!("failed to watch folder {}: {}", dir.display(), e),
+            }
+        }
+
+        // Catch up on whatever changed while screenpipe wasn't running
+        self.scan().await?;
+
+        while let Some(event) = rx.recv().await {
+            let mut paths = HashSet::new();
+            collect_paths(event, &mut paths);
+            while let Ok(Some(event)) = tokio::time::timeout(DEBOUNCE, rx.recv()).await {
+                collect_paths(event, &mut paths);
+            }
+
+            for path in paths {
+                if let Err(e) = self.handle_path(&path).await {
+                    warn!("failed to index {}: {}", path.display(), e);
+                }
+            }
+            self.report_skips();
+        }
+
+        Err(anyhow!("watch folder event stream closed"))
+    }
+
+    /// Indexes every file under the configured directories and tombstones documents
+    /// whose file is gone.
+    pub async fn scan(&self) -> Result<()> {
+        for dir in &self.config.dirs {
+            if !dir.is_dir() {
+                warn!("watch folder {} is not a directory", dir.display());
+                continue;
+            }
+
+            let root = dir.clone();
+            let files = tokio::task::spawn_blocking(move || walk_files(&root)).await?;
+            for file in &files {
+                if let Err(e) = self.handle_path(file).await {
+                    warn!("failed to index {}: {}", file.display(), e);
+                }
+            }
+
+            for indexed in self.db.list_document_paths(&path_prefix(dir)).await? {
+                if !Path::new(&indexed).exists() {
+                    self.handle_path(Path::new(&indexed)).await?;
+                }
+            }
+        }
+
+        info!(
+            "watch folders scanned: {} indexed, {} removed",
+            self.stats.indexed.load(Ordering::Relaxed),
+            self.stats.removed.load(Ordering::Relaxed)
+        );
+        self.report_skips();
+        Ok(())
+    }
+
+    /// Brings the index in line with `path`, which may have been created, changed or deleted.
+    pub async fn handle_path(&self, path: &Path) -> Result<IndexOutcome> {
+        if is_ignored(path) {
+            return Ok(IndexOutcome::Ignored);
+        }
+
+        let outcome = match tokio::fs::metadata(path).await {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => self.remove_path(path).await?,
+            Err(e) => return Err(e.into()),
+            Ok(metadata) if metadata.is_dir() => {
+                // A directory moved into the folder only produces one event
+                let root = path.to_path_buf();
+                let files = tokio::task::spawn_blocking(move || walk_files(&root)).await?;
+                for file in files {
+                    if let Err(e) = Box::pin(self.handle_path(&file)).await {
+                        warn!("failed to index {}: {}", file.display(), e);
+                    }
+                }
+                IndexOutcome::Ignored
+            }
+            Ok(metadata) => self.index_file(path, &metadata).await?,
+        };
+        self.stats.record(outcome);
+        Ok(outcome)
+    }
+
+    async fn index_file(&self, path: &Path, metadata: &std::fs::Metadata) -> Result<IndexOutcome> {
+        let file_path = path.to_string_lossy();
+        let modified: DateTime<Utc> = metadata.modified()?.into();
+        let size = metadata.len();
+
+        if let Some(state) = self.db.get_document_state(&file_path).await? {
+            if state.deleted_at.is_none()
+                && state.file_size == size as i64
+                && state.timestamp.timestamp_millis() == modified.timestamp_millis()
+            {
+                return Ok(IndexOutcome::Unchanged);
+            }
+        }
+
+        if size > self.config.max_file_size {
+            debug!(
+                "skipping {}: {} bytes is over the size cap",
+                file_path, size
+            );
+            return Ok(IndexOutcome::Skipped(SkipReason::TooLarge));
+        }
+
+        let kind = match DocumentKind::from_extension(path) {
+            Some(kind) => kind,
+            None => {
+                let sniff_path = path.to_path_buf();
+                if tokio::task::spawn_blocking(move || looks_like_text(&sniff_path)).await?? {
+                    DocumentKind::Text
+                } else {
+                    debug!("skipping {}: unsupported format", file_path);
+                    return Ok(IndexOutcome::Skipped(SkipReason::Unsupported));
+                }
+            }
+        };
+
+        // Blocking extraction keeps running after a timeout, its result is just dropped
+        let text =
+            match tokio::time::timeout(self.config.extraction_timeout, self.extract(path, kind))
+                .await
+            {
+                Err(_) => {
+                    debug!("skipping {}: extraction timed out", file_path);
+                    return Ok(IndexOutcome::Skipped(SkipReason::TimedOut));
+                }
+                Ok(Err(e)) => {
+                    debug!("skipping {}: {}", file_path, e);
+                    return Ok(IndexOutcome::Skipped(SkipReason::Failed));
+                }
+                Ok(Ok(None)) => {
+                    debug!("skipping {}: unsupported format", file_path);
+                    return Ok(IndexOutcome::Skipped(SkipReason::Unsupported));
+                }
+                Ok(Ok(Some(text))) => text,
+            };
+
+        self.db
+            .upsert_document(
+                &file_path,
+                kind.as_str(),
+                text.trim(),
+                modified,
+                size as i64,
+            )
+            .await?;
+        debug!("indexed {} ({})", file_path, kind.as_str());
+        Ok(IndexOutcome::Indexed)
+    }
+
+    /// Tombstones the document at `path`, or every document under it for a directory.
+    async fn remove_path(&self, path: &Path) -> Result<IndexOutcome> {
+        let mut removed = self.db.tombstone_document(&path.to_string_lossy()).await?;
+        for child in self.db.list_document_paths(&path_prefix(path)).await? {
+            removed |= self.db.tombstone_document(&child).await?;
+        }
+
+        Ok(if removed {
+            debug!("tombstoned documents at {}", path.display());
+            IndexOutcome::Removed
+        } else {
+            IndexOutcome::Ignored
+        })
+    }
+
+    /// Extracted text, or `None` for a file that turned out not to be readable as `kind`.
+    async fn extract(&self, path: &Path, kind: DocumentKind) -> Result<Option<String>> {
+        let path = path.to_path_buf();
+        match kind {
+            DocumentKind::Text => Ok(tokio::task::spawn_blocking(move || {
+                let bytes = std::fs::read(path)?;
+                if bytes.iter().take(SNIFF_LEN).any(|b| *b == 0) {
+                    return Ok::<_, anyhow::Error>(None);
+                }
+                Ok(Some(String::from_utf8_lossy(&bytes).into_owned()))
+            })
+            .await??),
+            DocumentKind::Pdf => Ok(tokio::task::spawn_blocking(move || {
+                let document = lopdf::Document::load(path)?;
+                let pages: Vec<u32> = document.get_pages().keys().copied().collect();
+                Ok::<_, anyhow::Error>(Some(document.extract_text(&pages)?))
+            })
+            .await??),
+            DocumentKind::Image => {
+                let Some(ocr_engine) = self.config.ocr_engine.as_ref() else {
+                    return Ok(None);
+                };
+                let image = tokio::task::spawn_blocking(move || image::open(path)).await??;
+                let text = ocr_image(image, ocr_engine, self.config.languages.clone()).await?;
+                Ok(Some(text))
+            }
+        }
+    }
+
+    /// One warning for all the files skipped since the last report, instead of one per file.
+    fn report_skips(&self) {
+        let skipped = self.stats.skipped();
+        let reported = self.reported_skips.swap(skipped, Ordering::Relaxed);
+        if skipped > reported {
+            warn!(
+                "skipped {} files in watch folders ({} unsupported, {} too large, {} timed out, {} failed)",
+                skipped - reported,
+                self.stats.skipped_unsupported.load(Ordering::Relaxed),
+                self.stats.skipped_too_large.load(Ordering::Relaxed),
+                self.stats.timed_out.load(Ordering::Relaxed),
+                self.stats.failed.load(Ordering::Relaxed)
+            );
+        }
+    }
+}
+
+fn collect_paths(event: notify::Result<notify::Event>, paths: &mut HashSet<PathBuf>) {
+    match event {
+        Ok(event) if !event.kind.is_access() => paths.extend(event.paths),
+        Ok(_) => {}
+        Err(e) => warn!("watch folder error: {}", e),
+    }
+}
+
+fn is_ignored(path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+        return true;
+    };
+    if name.starts_with('.') || name.starts_with("~$") {
+        return true;
+    }
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| PARTIAL_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+}
+
+fn walk_files(root: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if is_ignored(&path) {
+                continue;
+            }
+            match entry.file_type() {
+                Ok(file_type) if file_type.is_dir() => pending.push(path),
+                Ok(file_type) if file_type.is_file() => files.push(path),
+                _ => {}
+            }
+        }
+    }
+    files
+}
+
+fn looks_like_text(path: &Path) -> Result<bool> {
+    let mut buffer = vec![0; SNIFF_LEN];
+    let read = std::fs::File::open(path)?.read(&mut buffer)?;
+    let sample = &buffer[..read];
+    if sample.is_empty() || sample.contains(&0) {
+        return Ok(false);
+    }
+    // A multi-byte character may be cut off at the end of the sample
+    Ok(match std::str::from_utf8(sample) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none(),
+    })
+}
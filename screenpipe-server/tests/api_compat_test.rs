@@ -0,0 +1,211 @@
+#[cfg(test)]
+mod tests {
+    // Replays the v1 requests recorded in tests/api_v1 and diffs the responses against
+    // the recording. A recorded member must still be there with the same value, new
+    // members are fine. "$string", "$number" and "$any" match any value of that kind.
+    use axum::body::{to_bytes, Body};
+    use axum::http::Request;
+    use axum::Router;
+    use chrono::Utc;
+    use crossbeam::queue::SegQueue;
+    use screenpipe_server::events::EventRecorder;
+    use screenpipe_server::pipe_schedule::PipeScheduler;
+    use screenpipe_server::ranking::RankingWeights;
+    use screenpipe_server::retention::RetentionManager;
+    use screenpipe_server::sessions::SessionManager;
+    use screenpipe_server::settings_watch::SettingsWatch;
+    use screenpipe_server::telemetry::Telemetry;
+    use screenpipe_server::video_cache::FrameCache;
+    use screenpipe_server::PipeManager;
+    use screenpipe_server::{create_router, AppState, DatabaseManager};
+    use serde::Deserialize;
+    use serde_json::{Map, Value};
+    use std::collections::HashMap;
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    #[derive(Deserialize)]
+    struct Recording {
+        request: RecordedRequest,
+        response: RecordedResponse,
+    }
+
+    #[derive(Deserialize)]
+    struct RecordedRequest {
+        method: String,
+        path: String,
+        body: Option<Value>,
+    }
+
+    #[derive(Deserialize)]
+    struct RecordedResponse {
+        status: u16,
+        #[serde(default)]
+        headers: Map<String, Value>,
+        body: Value,
+    }
+
+    async fn setup_test_app() -> Router {
+        let db = Arc::new(DatabaseManager::new("sqlite::memory:").await.unwrap());
+
+        let app_state = Arc::new(AppState {
+            db: db.clone(),
+            vision_control: Arc::new(AtomicBool::new(false)),
+            audio_devices_control: Arc::new(SegQueue::new()),
+            devices_status: HashMap::new(),
+            app_start_time: Utc::now(),
+            screenpipe_dir: PathBuf::from(""),
+            pipe_manager: Arc::new(PipeManager::new(PathBuf::from(""))),
+            retention: Arc::new(RetentionManager::new(db.clone(), PathBuf::from(""), None)),
+            pipe_scheduler: Arc::new(PipeScheduler::new(
+                db.clone(),
+                Arc::new(PipeManager::new(PathBuf::from(""))),
+            )),
+            sessions: Arc::new(SessionManager::new(db.clone(), PathBuf::from(""))),
+            telemetry: Arc::new(Telemetry::new(
+                db.clone(),
+                Arc::new(PipeManager::new(PathBuf::from(""))),
+                PathBuf::from(""),
+            )),
+            settings: Arc::new(SettingsWatch::new()),
+            events: Arc::new(EventRecorder::new(db.clone())),
+            vision_disabled: false,
+            audio_disabled: false,
+            frame_cache: Some(Arc::new(
+                FrameCache::new(PathBuf::from(""), db).await.unwrap(),
+            )),
+            ui_monitoring_enabled: false,
+            ocr_scheduler: None,
+            media_volume: None,
+            archiver: None,
+            ranking: RankingWeights::default(),
+        });
+
+        create_router().with_state(app_state)
+    }
+
+    fn recordings() -> Vec<(String, Recording)> {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/api_v1");
+        let mut paths: Vec<PathBuf> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect();
+        paths.sort();
+        paths
+            .into_iter()
+            .map(|path| {
+                let name = path.file_stem().unwrap().to_string_lossy().to_string();
+                let recording = serde_json::from_str(&std::fs::read_to_string(&path).unwrap())
+                    .unwrap_or_else(|e| panic!("bad recording {}: {}", name, e));
+                (name, recording)
+            })
+            .collect()
+    }
+
+    fn diff(expected: &Value, actual: Option<&Value>, at: &str, out: &mut Vec<String>) {
+        let Some(actual) = actual else {
+            out.push(format!("{}: missing", at));
+            return;
+        };
+        let matches = match expected {
+            Value::String(s) if s == "$any" => true,
+            Value::String(s) if s == "$string" => actual.is_string(),
+            Value::String(s) if s == "$number" => actual.is_number(),
+            Value::Object(expected) => {
+                if actual.is_object() {
+                    for (key, value) in expected {
+                        diff(value, actual.get(key), &format!("{}.{}", at, key), out);
+                    }
+                    true
+                } else {
+                    false
+                }
+            }
+            Value::Array(expected) => match actual.as_array() {
+                Some(actual) if actual.len() == expected.len() => {
+                    for (i, value) in expected.iter().enumerate() {
+                        diff(value, actual.get(i), &format!("{}[{}]", at, i), out);
+                    }
+                    true
+                }
+                _ => false,
+            },
+            _ => expected == actual,
+        };
+        if !matches {
+            out.push(format!("{}: expected {}, got {}", at, expected, actual));
+        }
+    }
+
+    async fn replay(app: &Router, request: &RecordedRequest, path: &str) -> (u16, Value, Value) {
+        let mut builder = Request::builder().method(request.method.as_str()).uri(path);
+        let body = match &request.body {
+            Some(body) => {
+                builder = builder.header("content-type", "application/json");
+                Body::from(body.to_string())
+            }
+            None => Body::empty(),
+        };
+        let response = app
+            .clone()
+            .oneshot(builder.body(body).unwrap())
+            .await
+            .unwrap();
+        let status = response.status().as_u16();
+        let headers: Map<String, Value> = response
+            .headers()
+            .iter()
+            .map(|(name, value)| {
+                let value = value.to_str().unwrap_or_default().to_string();
+                (name.to_string(), Value::String(value))
+            })
+            .collect();
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+        (status, Value::Object(headers), body)
+    }
+
+    #[tokio::test]
+    async fn test_recorded_v1_requests_still_match() {
+        let app = setup_test_app().await;
+        let recordings = recordings();
+        assert!(!recordings.is_empty());
+
+        let mut failures = Vec::new();
+        for (name, recording) in &recordings {
+            let (status, headers, body) =
+                replay(&app, &recording.request, &recording.request.path).await;
+            if status != recording.response.status {
+                failures.push(format!(
+                    "{}: status: expected {}, got {}",
+                    name, recording.response.status, status
+                ));
+            }
+            let expected_headers = Value::Object(recording.response.headers.clone());
+            diff(&expected_headers, Some(&headers), name, &mut failures);
+            diff(&recording.response.body, Some(&body), name, &mut failures);
+        }
+        assert!(
+            failures.is_empty(),
+            "v1 responses changed:\n{}",
+            failures.join("\n")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unversioned_aliases_match_v1() {
+        let app = setup_test_app().await;
+        for (name, recording) in recordings() {
+            let alias = recording.request.path.strip_prefix("/v1").unwrap();
+            let (status, _, body) = replay(&app, &recording.request, alias).await;
+            assert_eq!(status, recording.response.status, "{}", name);
+
+            let mut failures = Vec::new();
+            diff(&recording.response.body, Some(&body), &name, &mut failures);
+            assert!(failures.is_empty(), "{}", failures.join("\n"));
+        }
+    }
+}
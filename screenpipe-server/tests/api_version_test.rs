@@ -0,0 +1,129 @@
+#[cfg(test)]
+mod tests {
+    use axum::body::{to_bytes, Body};
+    use axum::http::{Request, StatusCode};
+    use axum::response::{Json, Response};
+    use axum::routing::get;
+    use axum::Router;
+    use screenpipe_server::api_version::{
+        with_api_version, ApiVersioning, Deprecation, Surface, API_VERSION, API_VERSION_HEADER,
+        DEPRECATIONS, DEPRECATION_HEADER, SUNSET_HEADER,
+    };
+    use screenpipe_server::problem::with_problem_details;
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    const OLD_SEARCH: Deprecation = Deprecation {
+        surface: Surface::Endpoint {
+            method: "GET",
+            path: "/old/:id",
+        },
+        since: "1",
+        sunset: Some("Sat, 01 Mar 2025 00:00:00 GMT"),
+        replacement: "`/search`",
+    };
+    const LIMIT_PARAM: Deprecation = Deprecation {
+        surface: Surface::Param {
+            method: "GET",
+            path: "/search",
+            name: "max",
+        },
+        since: "1",
+        sunset: None,
+        replacement: "`limit`",
+    };
+
+    fn test_app() -> (Router, Arc<ApiVersioning>) {
+        let router = Router::new()
+            .route("/search", get(|| async { Json(json!({"data": []})) }))
+            .route("/old/:id", get(|| async { Json(json!({"data": [1]})) }));
+        let mut deprecations = DEPRECATIONS.to_vec();
+        deprecations.extend([OLD_SEARCH, LIMIT_PARAM]);
+        let versioning = Arc::new(ApiVersioning::new(deprecations));
+        (
+            with_api_version(with_problem_details(router), versioning.clone()),
+            versioning,
+        )
+    }
+
+    async fn send(app: &Router, uri: &str) -> Response {
+        let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        app.clone().oneshot(request).await.unwrap()
+    }
+
+    async fn json_body(response: Response) -> Value {
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_versioned_and_unversioned_paths_match() {
+        let (app, versioning) = test_app();
+        let versioned = send(&app, "/v1/search").await;
+        let alias = send(&app, "/search").await;
+        assert_eq!(versioned.status(), StatusCode::OK);
+        assert_eq!(alias.status(), StatusCode::OK);
+        assert_eq!(versioned.headers()[API_VERSION_HEADER], API_VERSION);
+        assert_eq!(alias.headers()[API_VERSION_HEADER], API_VERSION);
+        assert!(!versioned.headers().contains_key(DEPRECATION_HEADER));
+        assert_eq!(json_body(versioned).await, json_body(alias).await);
+
+        let report = versioning.report();
+        assert_eq!(report.versioned_requests, 1);
+        assert_eq!(report.unversioned_requests, 1);
+    }
+
+    #[tokio::test]
+    async fn test_deprecated_endpoint_and_param_warn() {
+        let (app, versioning) = test_app();
+        let response = send(&app, "/v1/old/42").await;
+        assert_eq!(response.headers()[DEPRECATION_HEADER], "true");
+        assert_eq!(
+            response.headers()[SUNSET_HEADER],
+            "Sat, 01 Mar 2025 00:00:00 GMT"
+        );
+        let body = json_body(response).await;
+        assert_eq!(body["data"], json!([1]));
+        assert_eq!(body["warnings"][0]["code"], "deprecated");
+        assert_eq!(body["warnings"][0]["kind"], "endpoint");
+        assert_eq!(body["warnings"][0]["path"], "/old/:id");
+
+        let response = send(&app, "/search?q=x&max=5").await;
+        assert_eq!(response.headers()[DEPRECATION_HEADER], "true");
+        assert!(!response.headers().contains_key(SUNSET_HEADER));
+        let body = json_body(response).await;
+        assert_eq!(body["warnings"][0]["name"], "max");
+        assert!(body["warnings"][0]["message"]
+            .as_str()
+            .unwrap()
+            .contains("use `limit` instead"));
+
+        let report = versioning.report();
+        let usage: Vec<u64> = report.deprecations.iter().map(|d| d.requests).collect();
+        assert_eq!(usage, vec![0, 0, 1, 1]);
+        assert!(report.deprecations[2].last_used.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_problem_bodies_warn_about_legacy_fields() {
+        let (app, versioning) = test_app();
+        let response = send(&app, "/v1/nope").await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(response.headers()[API_VERSION_HEADER], API_VERSION);
+        assert_eq!(response.headers()[DEPRECATION_HEADER], "true");
+        let body = json_body(response).await;
+        assert_eq!(body["code"], "not_found");
+        let fields: Vec<&str> = body["warnings"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|w| w["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(fields, vec!["error", "success"]);
+
+        let report = versioning.report();
+        assert_eq!(report.deprecations[0].requests, 1);
+        assert_eq!(report.deprecations[1].requests, 1);
+    }
+}
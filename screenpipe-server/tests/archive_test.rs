@@ -0,0 +1,281 @@
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+
+    use chrono::{Duration, Utc};
+    use screenpipe_server::{
+        archive::Archiver,
+        db_types::{ContentType, MediaChunkKind, SearchResult, TagContentType},
+        storage::{configure_archive, StorageConfig, StorageKind},
+        DatabaseManager,
+    };
+    use screenpipe_vision::OcrEngine;
+
+    struct Setup {
+        _dir: tempfile::TempDir,
+        db: Arc<DatabaseManager>,
+        media_dir: PathBuf,
+        archive_dir: PathBuf,
+    }
+
+    async fn setup() -> Setup {
+        let dir = tempfile::tempdir().unwrap();
+        let media_dir = dir.path().join("data");
+        let archive_dir = dir.path().join("nas").join("screenpipe").join("archive");
+        std::fs::create_dir_all(&media_dir).unwrap();
+        std::fs::create_dir_all(&archive_dir).unwrap();
+        Setup {
+            db: Arc::new(DatabaseManager::new("sqlite::memory:").await.unwrap()),
+            media_dir,
+            archive_dir,
+            _dir: dir,
+        }
+    }
+
+    fn archiver(setup: &Setup) -> Archiver {
+        Archiver::new(
+            setup.db.clone(),
+            setup.media_dir.clone(),
+            setup.archive_dir.clone(),
+            None,
+            Some(30),
+        )
+    }
+
+    /// A video chunk file with one frame captured `age_days` ago, returns the frame id.
+    async fn video_chunk(setup: &Setup, name: &str, age_days: i64, size: usize) -> i64 {
+        let file = setup.media_dir.join(name);
+        std::fs::write(&file, vec![7u8; size]).unwrap();
+        setup
+            .db
+            .insert_video_chunk(file.to_str().unwrap(), "monitor_1")
+            .await
+            .unwrap();
+        let frame_id = setup
+            .db
+            .insert_frame("monitor_1", Some(Utc::now() - Duration::days(age_days)))
+            .await
+            .unwrap();
+        setup
+            .db
+            .insert_ocr_text(
+                frame_id,
+                &format!("text of {}", name),
+                "",
+                "app",
+                "window",
+                Arc::new(OcrEngine::Tesseract),
+                false,
+            )
+            .await
+            .unwrap();
+        frame_id
+    }
+
+    fn chunk_path(setup: &Setup, name: &str) -> String {
+        setup.media_dir.join(name).to_string_lossy().into_owned()
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_plan_lists_old_untagged_media() {
+        let setup = setup().await;
+        video_chunk(&setup, "old.mp4", 40, 1000).await;
+        let tagged = video_chunk(&setup, "tagged.mp4", 40, 3000).await;
+        video_chunk(&setup, "recent.mp4", 2, 5000).await;
+        setup
+            .db
+            .add_tags(tagged, TagContentType::Vision, vec!["keep".to_string()])
+            .await
+            .unwrap();
+        let audio = setup
+            .db
+            .insert_audio_chunk(&chunk_path(&setup, "old.wav"))
+            .await
+            .unwrap();
+        std::fs::write(setup.media_dir.join("old.wav"), vec![1u8; 500]).unwrap();
+        sqlx::query("UPDATE audio_chunks SET timestamp = ?1 WHERE id = ?2")
+            .bind(Utc::now() - Duration::days(60))
+            .bind(audio)
+            .execute(&setup.db.pool)
+            .await
+            .unwrap();
+        // Old enough, but its file is already gone
+        setup
+            .db
+            .insert_audio_chunk(&chunk_path(&setup, "gone.wav"))
+            .await
+            .unwrap();
+        sqlx::query("UPDATE audio_chunks SET timestamp = ?1 WHERE file_path LIKE '%gone.wav'")
+            .bind(Utc::now() - Duration::days(60))
+            .execute(&setup.db.pool)
+            .await
+            .unwrap();
+
+        let archiver = archiver(&setup);
+        let plan = archiver.plan(Utc::now()).await.unwrap();
+        let planned: Vec<(MediaChunkKind, String)> = plan
+            .chunks
+            .iter()
+            .map(|chunk| (chunk.kind, chunk.file_path.clone()))
+            .collect();
+        assert_eq!(
+            planned,
+            vec![
+                (MediaChunkKind::Video, chunk_path(&setup, "old.mp4")),
+                (MediaChunkKind::Audio, chunk_path(&setup, "old.wav")),
+            ]
+        );
+        assert_eq!(plan.bytes, 1500);
+        assert_eq!(plan.missing, 1);
+        assert_eq!(
+            archiver.archive_path(&plan.chunks[0]),
+            setup.archive_dir.join("old.mp4")
+        );
+        // Nothing moved
+        assert!(setup.media_dir.join("old.mp4").exists());
+    }
+
+    #[tokio::test]
+    async fn test_archive_moves_verified_files_and_keeps_text() {
+        let setup = setup().await;
+        let frame_id = video_chunk(&setup, "old.mp4", 40, 4096).await;
+        let recent = video_chunk(&setup, "recent.mp4", 2, 10).await;
+
+        let summary = archiver(&setup).archive(Utc::now()).await.unwrap();
+        assert_eq!(summary.moved, 1);
+        assert_eq!(summary.bytes, 4096);
+        assert_eq!(summary.failed, 0);
+
+        assert!(!setup.media_dir.join("old.mp4").exists());
+        let archived = setup.archive_dir.join("old.mp4");
+        assert_eq!(std::fs::read(&archived).unwrap(), vec![7u8; 4096]);
+
+        let source = setup
+            .db
+            .get_frame_image_source(frame_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(source.archived);
+        assert_eq!(source.file_path, archived.to_string_lossy());
+        let recent = setup
+            .db
+            .get_frame_image_source(recent)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!recent.archived);
+
+        let results = setup
+            .db
+            .search(
+                "old",
+                ContentType::OCR,
+                10,
+                0,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        match &results[0] {
+            SearchResult::OCR(ocr) => assert!(ocr.archived),
+            _ => panic!("expected an ocr result"),
+        }
+
+        // A second run has nothing left to do
+        let summary = archiver(&setup).archive(Utc::now()).await.unwrap();
+        assert_eq!(summary.moved, 0);
+    }
+
+    #[tokio::test]
+    async fn test_interrupted_run_resumes() {
+        let setup = setup().await;
+        let frame_id = video_chunk(&setup, "old.mp4", 40, 100).await;
+        let original = chunk_path(&setup, "old.mp4");
+
+        // A run that crashed after switching the row, before deleting the original
+        let copy = setup.archive_dir.join("old.mp4");
+        std::fs::copy(&original, &copy).unwrap();
+        let chunk_id: i64 = sqlx::query_scalar("SELECT video_chunk_id FROM frames WHERE id = ?1")
+            .bind(frame_id)
+            .fetch_one(&setup.db.pool)
+            .await
+            .unwrap();
+        setup
+            .db
+            .mark_chunk_archived(
+                MediaChunkKind::Video,
+                chunk_id,
+                &original,
+                &copy.to_string_lossy(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(setup.db.get_pending_archive_moves().await.unwrap().len(), 1);
+        // Switching it again does nothing
+        assert!(setup
+            .db
+            .mark_chunk_archived(MediaChunkKind::Video, chunk_id, &original, "elsewhere")
+            .await
+            .unwrap()
+            .is_none());
+
+        let summary = archiver(&setup).archive(Utc::now()).await.unwrap();
+        assert_eq!(summary.resumed, 1);
+        assert_eq!(summary.moved, 0);
+        assert!(!Path::new(&original).exists());
+        assert!(copy.exists());
+        assert!(setup
+            .db
+            .get_pending_archive_moves()
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_unavailable_archive_moves_nothing() {
+        let setup = setup().await;
+        video_chunk(&setup, "old.mp4", 40, 100).await;
+        std::fs::remove_dir_all(&setup.archive_dir).unwrap();
+
+        let archiver = archiver(&setup);
+        assert!(!archiver.is_available());
+        assert!(archiver.archive(Utc::now()).await.is_err());
+        assert!(setup.media_dir.join("old.mp4").exists());
+    }
+
+    #[test]
+    fn test_configure_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().join("screenpipe");
+        let nas = dir.path().join("nas");
+
+        let config = configure_archive(&base, Some(&nas), Some(90)).unwrap();
+        assert_eq!(config.archive_after_days, Some(90));
+        assert_eq!(config, StorageConfig::load(&base).unwrap());
+        let archive = config.resolve(&base, StorageKind::Archive);
+        assert!(archive.available);
+        assert_eq!(archive.path, nas.join("screenpipe").join("archive"));
+
+        // The age alone can change, the directory stays
+        let config = configure_archive(&base, None, Some(30)).unwrap();
+        assert_eq!(config.archive_after_days, Some(30));
+        assert_eq!(config.archive_dir.as_ref().unwrap().path, archive.path);
+        assert!(configure_archive(&base, None, Some(0)).is_err());
+
+        // Once it holds media only `storage move archive` switches it
+        std::fs::write(archive.path.join("old.mp4"), b"video").unwrap();
+        assert!(configure_archive(&base, Some(&dir.path().join("other")), None).is_err());
+        assert!(configure_archive(&base, Some(&nas), None).is_ok());
+    }
+}
@@ -0,0 +1,154 @@
+#[cfg(test)]
+mod tests {
+    use screenpipe_audio::{AudioDevice, DeviceType};
+    use screenpipe_server::audio_playback::{
+        audible_ranges, browser_content_type, byte_range, playback_url, transcode_args, ByteRange,
+        Clip, SEGMENT_PADDING_SECS,
+    };
+    use screenpipe_server::db_types::RetentionKind;
+    use screenpipe_server::DatabaseManager;
+
+    async fn transcribe(db: &DatabaseManager, chunk_id: i64, speaker_id: Option<i64>, at: f64) {
+        db.insert_audio_transcription(
+            chunk_id,
+            "hello",
+            0,
+            "",
+            &AudioDevice::new("microphone".to_string(), DeviceType::Input),
+            speaker_id,
+            Some(at),
+            Some(at + 2.0),
+        )
+        .await
+        .unwrap();
+    }
+
+    #[test]
+    fn test_byte_ranges() {
+        let range = |header| byte_range(Some(header), 1000);
+        assert_eq!(byte_range(None, 1000), Ok(None));
+        assert_eq!(
+            range("bytes=0-99"),
+            Ok(Some(ByteRange { start: 0, end: 99 }))
+        );
+        assert_eq!(
+            range("bytes=900-"),
+            Ok(Some(ByteRange {
+                start: 900,
+                end: 999
+            }))
+        );
+        assert_eq!(
+            range("bytes=-100"),
+            Ok(Some(ByteRange {
+                start: 900,
+                end: 999
+            }))
+        );
+        // Past the end is cut to the end
+        assert_eq!(
+            range("bytes=500-5000"),
+            Ok(Some(ByteRange {
+                start: 500,
+                end: 999
+            }))
+        );
+        assert_eq!(
+            ByteRange { start: 0, end: 99 }.content_range(1000),
+            "bytes 0-99/1000"
+        );
+
+        // Several ranges or another unit get the whole body
+        assert_eq!(range("bytes=0-1,5-9"), Ok(None));
+        assert_eq!(range("items=0-1"), Ok(None));
+
+        assert!(range("bytes=1000-").is_err());
+        assert!(range("bytes=20-10").is_err());
+        assert!(range("bytes=-0").is_err());
+        assert!(range("bytes=a-b").is_err());
+    }
+
+    #[test]
+    fn test_only_kept_transcriptions_are_audible() {
+        let pad = SEGMENT_PADDING_SECS;
+        // Overlapping once padded, in any order
+        let ranges = audible_ranges(&[
+            (Some(10.0), Some(12.0)),
+            (Some(0.2), Some(3.0)),
+            (Some(3.5), Some(5.0)),
+        ])
+        .unwrap();
+        assert_eq!(ranges, vec![(0.0, 5.0 + pad), (10.0 - pad, 12.0 + pad)]);
+        assert_eq!(audible_ranges(&[]), Some(vec![]));
+        // Transcriptions without segment times cover the whole chunk
+        assert_eq!(
+            audible_ranges(&[(Some(1.0), Some(2.0)), (None, None)]),
+            None
+        );
+
+        let clip = Clip::new(Some(1500), Some(4000)).unwrap();
+        assert!(!clip.is_whole());
+        assert!(Clip::new(None, None).unwrap().is_whole());
+        assert!(Clip::new(Some(0), None).unwrap().is_whole());
+        assert!(Clip::new(Some(4000), Some(4000)).is_err());
+
+        let args = transcode_args("/data/mic.mp4", clip, Some(&ranges)).join(" ");
+        assert!(args.contains("-i /data/mic.mp4"));
+        assert!(args.contains(
+            "-af volume=enable='not(between(t,0.000,5.500)+between(t,9.500,12.500))':volume=0"
+        ));
+        // The clip is cut after the filter, which sees the chunk's times
+        assert!(args.contains("volume=0 -ss 1.500 -t 2.500"));
+        assert!(args.ends_with("-c:a aac -b:a 128k -f adts pipe:1"));
+        let whole = transcode_args("/data/mic.flac", Clip::default(), None).join(" ");
+        assert!(!whole.contains("-af") && !whole.contains("-ss") && !whole.contains("-t "));
+
+        assert_eq!(browser_content_type("/data/mic.mp4"), Some("audio/mp4"));
+        assert_eq!(browser_content_type("/data/mic.MP3"), Some("audio/mpeg"));
+        assert_eq!(browser_content_type("/data/mic.flac"), None);
+
+        assert_eq!(
+            playback_url(7, Some(1.25), Some(3.5)),
+            "/audio/7?start_ms=1250&end_ms=3500"
+        );
+        assert_eq!(playback_url(7, Some(1.25), None), "/audio/7?start_ms=1250");
+        assert_eq!(playback_url(7, None, None), "/audio/7");
+    }
+
+    #[tokio::test]
+    async fn test_deleted_audio_is_told_apart_from_unknown_audio() {
+        let db = DatabaseManager::new("sqlite::memory:").await.unwrap();
+        let kept = db.insert_audio_chunk("kept.mp4").await.unwrap();
+        let pruned = db.insert_audio_chunk("pruned.mp4").await.unwrap();
+        let forgotten = db.insert_audio_chunk("forgotten.mp4").await.unwrap();
+        transcribe(&db, kept, None, 4.0).await;
+        transcribe(&db, kept, None, 1.0).await;
+        transcribe(&db, pruned, None, 0.0).await;
+        let speaker = db.insert_speaker(&[0.0; 512]).await.unwrap();
+        transcribe(&db, forgotten, Some(speaker.id), 0.0).await;
+
+        let source = db.get_audio_chunk_source(kept).await.unwrap().unwrap();
+        assert_eq!(source.file_path, "kept.mp4");
+        assert!(!source.archived);
+        assert_eq!(
+            source.segments,
+            vec![(Some(1.0), Some(3.0)), (Some(4.0), Some(6.0))]
+        );
+
+        // Retention deletes the chunk, deleting a speaker only its transcriptions
+        db.delete_retention_rows(RetentionKind::Audio, &[pruned])
+            .await
+            .unwrap();
+        db.delete_speaker(speaker.id).await.unwrap();
+        assert_eq!(db.get_audio_chunk_source(pruned).await.unwrap(), None);
+        assert!(db.audio_chunk_deleted(pruned).await.unwrap());
+        let forgotten = db.get_audio_chunk_source(forgotten).await.unwrap().unwrap();
+        assert!(forgotten.segments.is_empty());
+
+        assert!(!db.audio_chunk_deleted(kept).await.unwrap());
+        assert!(!db
+            .audio_chunk_deleted(forgotten.chunk_id + 1)
+            .await
+            .unwrap());
+    }
+}
@@ -0,0 +1,205 @@
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Duration as ChronoDuration, Utc};
+    use screenpipe_audio::{AudioDevice, DeviceType};
+    use screenpipe_server::context::assemble;
+    use screenpipe_server::db_types::{CaptureWrite, FrameWrite, WindowOcrWrite};
+    use screenpipe_server::pipe_content::{self, ContentSchema, NewPipeContent};
+    use screenpipe_server::storage_mode::StorageMode;
+    use screenpipe_server::DatabaseManager;
+    use serde_json::{json, Map};
+    use std::time::Duration;
+
+    async fn setup() -> DatabaseManager {
+        let db = DatabaseManager::new("sqlite::memory:").await.unwrap();
+        db.insert_video_chunk("video.mp4", "monitor_1")
+            .await
+            .unwrap();
+        let schema = ContentSchema::from_json_schema(&json!({"type": "object"})).unwrap();
+        pipe_content::register_content_type(&db, "notes", "summary", schema, None)
+            .await
+            .unwrap();
+        db
+    }
+
+    fn window(app_name: &str, text: &str, focused: bool) -> WindowOcrWrite {
+        WindowOcrWrite {
+            text: text.to_string(),
+            text_json: "[]".to_string(),
+            app_name: app_name.to_string(),
+            window_name: format!("{} window", app_name),
+            ocr_engine: "Tesseract".to_string(),
+            focused,
+            raw_text: None,
+            corrected_by: None,
+        }
+    }
+
+    async fn frame(db: &DatabaseManager, timestamp: DateTime<Utc>, windows: Vec<WindowOcrWrite>) {
+        db.write_capture(CaptureWrite::Frame(FrameWrite {
+            device_name: "monitor_1".to_string(),
+            video_chunk_id: None,
+            timestamp: Some(timestamp),
+            windows,
+            storage_mode: StorageMode::Full,
+            thumbnail_path: None,
+            window_layout: None,
+        }))
+        .await
+        .unwrap();
+    }
+
+    async fn transcript(db: &DatabaseManager, text: &str) {
+        let audio_chunk_id = db.insert_audio_chunk("audio.mp4").await.unwrap();
+        db.insert_audio_transcription(
+            audio_chunk_id,
+            text,
+            0,
+            "",
+            &AudioDevice::new("microphone".to_string(), DeviceType::Input),
+            None,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    }
+
+    async fn pipe_result(db: &DatabaseManager, text: &str, age_minutes: i64, context: bool) {
+        pipe_content::insert_content(
+            db,
+            &NewPipeContent {
+                content_type: "summary".to_string(),
+                pipe_id: "notes".to_string(),
+                timestamp: Some(Utc::now() - ChronoDuration::minutes(age_minutes)),
+                text: text.to_string(),
+                fields: Map::new(),
+                context,
+            },
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_context_bundle_reads_the_latest_of_everything() {
+        let db = setup().await;
+        let now = Utc::now();
+        frame(
+            &db,
+            now - ChronoDuration::minutes(10),
+            vec![window("Mail", "inbox", true)],
+        )
+        .await;
+        frame(
+            &db,
+            now - ChronoDuration::seconds(60),
+            vec![
+                window("Firefox", "pricing page", true),
+                window("Slack", "#general", false),
+            ],
+        )
+        .await;
+        frame(
+            &db,
+            now - ChronoDuration::seconds(30),
+            vec![
+                window("Slack", "#general", false),
+                window("Code", "fn main() {}", true),
+            ],
+        )
+        .await;
+        transcript(&db, "let's ship on friday").await;
+        transcript(&db, "sounds good").await;
+        transcript(&db, "").await;
+        db.start_capture_session("planning", now - ChronoDuration::minutes(5), None)
+            .await
+            .unwrap();
+        pipe_result(&db, "standup: release friday", 30, true).await;
+        pipe_result(&db, "not for prompts", 2, false).await;
+        pipe_result(&db, "user is reviewing pricing", 1, true).await;
+
+        let now = Utc::now() + ChronoDuration::seconds(1);
+        let bundle = assemble(&db, now, Duration::from_secs(120), 4000)
+            .await
+            .unwrap();
+        let focused = bundle.focused.unwrap();
+        assert_eq!(focused.app_name, "Code");
+        assert_eq!(focused.window_name, "Code window");
+        assert_eq!(focused.text, "fn main() {}");
+        assert_eq!(
+            bundle
+                .transcripts
+                .iter()
+                .map(|t| t.text.as_str())
+                .collect::<Vec<_>>(),
+            vec!["let's ship on friday", "sounds good"]
+        );
+        assert_eq!(bundle.transcripts[0].device_name, "microphone");
+        assert_eq!(bundle.session.unwrap().name, "planning");
+        assert_eq!(
+            bundle
+                .pipe_results
+                .iter()
+                .map(|r| r.text.as_str())
+                .collect::<Vec<_>>(),
+            vec!["user is reviewing pricing", "standup: release friday"]
+        );
+        assert!(!bundle.truncated);
+        assert_eq!(bundle.chars, 12 + 20 + 11 + 25 + 23);
+        assert!(bundle.assembly_ms >= 0.0);
+
+        // Ten minutes later nothing was captured in the lookback, the session is open still
+        let later = assemble(
+            &db,
+            now + ChronoDuration::minutes(10),
+            Duration::from_secs(120),
+            4000,
+        )
+        .await
+        .unwrap();
+        assert_eq!(later.focused, None);
+        assert!(later.transcripts.is_empty());
+        assert!(later.session.is_some());
+        assert_eq!(later.pipe_results.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_context_bundle_fits_the_budget() {
+        let db = setup().await;
+        frame(
+            &db,
+            Utc::now() - ChronoDuration::seconds(5),
+            vec![window("Word", &"é".repeat(300), true)],
+        )
+        .await;
+        for word in ["first", "second", "third"] {
+            transcript(&db, &format!("{:<50}", word)).await;
+        }
+        pipe_result(&db, &"b".repeat(40), 2, true).await;
+        pipe_result(&db, &"a".repeat(40), 1, true).await;
+
+        let bundle = assemble(
+            &db,
+            Utc::now() + ChronoDuration::seconds(1),
+            Duration::from_secs(120),
+            200,
+        )
+        .await
+        .unwrap();
+        // Half for the screen, a quarter for pipe results, the newest transcript in the rest
+        assert_eq!(bundle.focused.unwrap().text, "é".repeat(100));
+        assert_eq!(
+            bundle
+                .pipe_results
+                .iter()
+                .map(|r| r.text.clone())
+                .collect::<Vec<_>>(),
+            vec!["a".repeat(40), "b".repeat(10)]
+        );
+        assert_eq!(bundle.transcripts.len(), 1);
+        assert!(bundle.transcripts[0].text.starts_with("third"));
+        assert_eq!(bundle.chars, 200);
+        assert!(bundle.truncated);
+    }
+}
@@ -0,0 +1,414 @@
+#[cfg(test)]
+mod tests {
+    use axum::body::{to_bytes, Body};
+    use axum::http::{header, Request, StatusCode};
+    use axum::Router;
+    use chrono::{TimeZone, Utc};
+    use crossbeam::queue::SegQueue;
+    use screenpipe_core::window_layout::{LayoutWindow, WindowLayout, WindowRect};
+    use screenpipe_server::copy::{
+        deep_link, deep_link_document, frame_text, parse_deep_link, resolve_deep_link, sensitive,
+        to_png, CopyLink, Sensitive, TimelinePosition,
+    };
+    use screenpipe_server::db_types::{CaptureOutcome, CaptureWrite, FrameWrite, WindowOcrWrite};
+    use screenpipe_server::events::EventRecorder;
+    use screenpipe_server::pipe_schedule::PipeScheduler;
+    use screenpipe_server::privacy::IgnoredApps;
+    use screenpipe_server::ranking::RankingWeights;
+    use screenpipe_server::retention::RetentionManager;
+    use screenpipe_server::sessions::SessionManager;
+    use screenpipe_server::settings_watch::SettingsWatch;
+    use screenpipe_server::storage_mode::StorageMode;
+    use screenpipe_server::telemetry::Telemetry;
+    use screenpipe_server::{create_router, AppState, DatabaseManager, PipeManager};
+    use serde_json::Value;
+    use std::collections::HashMap;
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+    use tempfile::tempdir;
+    use tower::ServiceExt;
+
+    fn window(app_name: &str, window_name: &str, text: &str, focused: bool) -> WindowOcrWrite {
+        WindowOcrWrite {
+            text: text.to_string(),
+            text_json: "[]".to_string(),
+            app_name: app_name.to_string(),
+            window_name: window_name.to_string(),
+            ocr_engine: "Tesseract".to_string(),
+            focused,
+            raw_text: None,
+            corrected_by: None,
+        }
+    }
+
+    fn layout_window(app_name: &str, window_name: &str, z: u32) -> LayoutWindow {
+        LayoutWindow {
+            app_name: app_name.to_string(),
+            window_name: window_name.to_string(),
+            rect: WindowRect::new(0, 0, 800, 600),
+            z,
+            focused: z == 0,
+        }
+    }
+
+    async fn write_frame(
+        db: &DatabaseManager,
+        windows: Vec<WindowOcrWrite>,
+        thumbnail_path: Option<&Path>,
+        window_layout: Option<WindowLayout>,
+    ) -> i64 {
+        let write = CaptureWrite::Frame(FrameWrite {
+            device_name: "monitor_1".to_string(),
+            video_chunk_id: None,
+            timestamp: Some(Utc.with_ymd_and_hms(2024, 10, 16, 9, 30, 0).unwrap()),
+            windows,
+            storage_mode: StorageMode::TextPlusThumbnails,
+            thumbnail_path: thumbnail_path.map(|path| path.to_string_lossy().to_string()),
+            window_layout,
+        });
+        match db.write_capture(write).await.unwrap() {
+            CaptureOutcome::Written(id) => id,
+            CaptureOutcome::Spilled => panic!("write was spilled"),
+        }
+    }
+
+    /// A 4x2 jpeg, like the thumbnails of frames.
+    fn write_jpeg(dir: &Path) -> PathBuf {
+        let path = dir.join("thumb.jpg");
+        image::RgbImage::from_pixel(4, 2, image::Rgb([200, 40, 40]))
+            .save_with_format(&path, image::ImageFormat::Jpeg)
+            .unwrap();
+        path
+    }
+
+    async fn setup_test_app(db: Arc<DatabaseManager>) -> Router {
+        let app_state = Arc::new(AppState {
+            db: db.clone(),
+            vision_control: Arc::new(AtomicBool::new(false)),
+            audio_devices_control: Arc::new(SegQueue::new()),
+            devices_status: HashMap::new(),
+            app_start_time: Utc::now(),
+            screenpipe_dir: PathBuf::from(""),
+            pipe_manager: Arc::new(PipeManager::new(PathBuf::from(""))),
+            retention: Arc::new(RetentionManager::new(db.clone(), PathBuf::from(""), None)),
+            pipe_scheduler: Arc::new(PipeScheduler::new(
+                db.clone(),
+                Arc::new(PipeManager::new(PathBuf::from(""))),
+            )),
+            sessions: Arc::new(SessionManager::new(db.clone(), PathBuf::from(""))),
+            telemetry: Arc::new(Telemetry::new(
+                db.clone(),
+                Arc::new(PipeManager::new(PathBuf::from(""))),
+                PathBuf::from(""),
+            )),
+            settings: Arc::new(SettingsWatch::new()),
+            events: Arc::new(EventRecorder::new(db.clone())),
+            vision_disabled: false,
+            audio_disabled: false,
+            frame_cache: None,
+            ui_monitoring_enabled: false,
+            ocr_scheduler: None,
+            media_volume: None,
+            archiver: None,
+            ranking: RankingWeights::default(),
+        });
+        create_router().with_state(app_state)
+    }
+
+    async fn get(app: &Router, uri: &str) -> (StatusCode, header::HeaderMap, Vec<u8>) {
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri(uri)
+                    .header(header::HOST, "localhost:3030")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, headers, body.to_vec())
+    }
+
+    #[tokio::test]
+    async fn test_frame_text_puts_the_focused_window_first() {
+        let db = DatabaseManager::new("sqlite::memory:").await.unwrap();
+        let id = write_frame(
+            &db,
+            vec![
+                window("slack", "general", "behind", false),
+                window("code", "main.rs", "  in front\n", true),
+                window("finder", "", "", false),
+            ],
+            None,
+            None,
+        )
+        .await;
+
+        let frame = db.get_frame_content(id).await.unwrap().unwrap();
+        assert_eq!(frame.windows[0].app_name, "code");
+        assert_eq!(frame_text(&frame).unwrap(), "in front\n\nbehind");
+        assert!(db.get_frame_content(id + 1).await.unwrap().is_none());
+
+        let empty = write_frame(&db, vec![window("finder", "", " ", true)], None, None).await;
+        let frame = db.get_frame_content(empty).await.unwrap().unwrap();
+        assert_eq!(frame_text(&frame), None);
+    }
+
+    #[tokio::test]
+    async fn test_images_of_sensitive_frames_are_not_copied() {
+        let db = DatabaseManager::new("sqlite::memory:").await.unwrap();
+        let dir = tempdir().unwrap();
+        let ignored = IgnoredApps::default();
+        ignored.set(dir.path(), "1Password", true).unwrap();
+        let ignored_windows = vec!["private browsing".to_string()];
+
+        let plain = write_frame(
+            &db,
+            vec![window("code", "main.rs", "fn main", true)],
+            None,
+            None,
+        )
+        .await;
+        // Ignored apps leave no text, their windows are still in the layout
+        let behind = write_frame(
+            &db,
+            vec![window("code", "main.rs", "fn main", true)],
+            None,
+            Some(WindowLayout {
+                monitor_width: 800,
+                monitor_height: 600,
+                windows: vec![
+                    layout_window("code", "main.rs", 0),
+                    layout_window("1Password.app", "vault", 1),
+                ],
+            }),
+        )
+        .await;
+        let private = write_frame(
+            &db,
+            vec![window(
+                "Firefox",
+                "Private Browsing - Mozilla",
+                "news",
+                true,
+            )],
+            None,
+            None,
+        )
+        .await;
+        let redacted = write_frame(
+            &db,
+            vec![window("mail", "inbox", "from [EMAIL] about the card", true)],
+            None,
+            None,
+        )
+        .await;
+        let raw = write_frame(
+            &db,
+            vec![window("mail", "inbox", "write to test@example.com", true)],
+            None,
+            None,
+        )
+        .await;
+
+        let mut reasons = Vec::new();
+        for id in [plain, behind, private, redacted, raw] {
+            let frame = db.get_frame_content(id).await.unwrap().unwrap();
+            reasons.push(sensitive(&frame, &ignored, &ignored_windows));
+        }
+        assert_eq!(
+            reasons,
+            vec![
+                None,
+                Some(Sensitive::IgnoredApp("1Password.app".to_string())),
+                Some(Sensitive::IgnoredWindow("private browsing".to_string())),
+                Some(Sensitive::Pii),
+                Some(Sensitive::Pii),
+            ]
+        );
+        assert_eq!(reasons[1].as_ref().unwrap().reason(), "ignored_app");
+    }
+
+    #[test]
+    fn test_deep_links_round_trip_to_a_timeline_position() {
+        let timestamp = Utc.with_ymd_and_hms(2024, 10, 16, 9, 30, 0).unwrap();
+        let link = CopyLink::new(42, timestamp, "http://localhost:3030/");
+        assert_eq!(
+            link.deep_link,
+            "screenpipe://frame/42?timestamp=2024-10-16T09:30:00.000Z"
+        );
+        assert_eq!(
+            link.http_url,
+            "http://localhost:3030/v1/content/42/copy?what=image"
+        );
+
+        let target = parse_deep_link(&deep_link(42, timestamp)).unwrap();
+        assert_eq!((target.frame_id, target.timestamp), (42, Some(timestamp)));
+        assert_eq!(
+            parse_deep_link("screenpipe://frame/7").unwrap().timestamp,
+            None
+        );
+        assert!(parse_deep_link("https://frame/7").is_err());
+        assert!(parse_deep_link("screenpipe://search/7").is_err());
+        assert!(parse_deep_link("screenpipe://frame/7?timestamp=yesterday").is_err());
+
+        // A deleted frame still opens at the time of the link, without one it can't
+        let gone = resolve_deep_link(&target, None, "http://localhost:3030").unwrap();
+        assert_eq!(
+            gone,
+            TimelinePosition {
+                frame_id: 42,
+                timestamp,
+                found: false,
+                app_name: None,
+                window_name: None,
+                http_url: None,
+            }
+        );
+        let untimed = parse_deep_link("screenpipe://frame/42").unwrap();
+        assert_eq!(
+            resolve_deep_link(&untimed, None, "http://localhost:3030"),
+            None
+        );
+
+        let document = deep_link_document();
+        assert_eq!(document.scheme, "screenpipe");
+        assert_eq!(document.handlers[0].opens, "timeline");
+    }
+
+    #[test]
+    fn test_thumbnails_are_converted_to_png() {
+        let dir = tempdir().unwrap();
+        let jpeg = std::fs::read(write_jpeg(dir.path())).unwrap();
+        let png = to_png(&jpeg).unwrap();
+        assert_eq!(image::guess_format(&png).unwrap(), image::ImageFormat::Png);
+        assert_eq!(image::load_from_memory(&png).unwrap().width(), 4);
+        // Already a png, kept as is
+        assert_eq!(to_png(&png).unwrap(), png);
+    }
+
+    #[tokio::test]
+    async fn test_copy_endpoint_variants() {
+        let db = Arc::new(DatabaseManager::new("sqlite::memory:").await.unwrap());
+        let dir = tempdir().unwrap();
+        let thumbnail = write_jpeg(dir.path());
+        let id = write_frame(
+            &db,
+            vec![window("code", "main.rs", "fn main() {}", true)],
+            Some(&thumbnail),
+            None,
+        )
+        .await;
+        let pii = write_frame(
+            &db,
+            vec![window("mail", "inbox", "ssn [SSN]", true)],
+            Some(&thumbnail),
+            None,
+        )
+        .await;
+        let app = setup_test_app(db).await;
+
+        // Text is the default
+        for uri in [
+            format!("/v1/content/{}/copy", id),
+            format!("/v1/content/{}/copy?what=text", id),
+        ] {
+            let (status, headers, body) = get(&app, &uri).await;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(headers[header::CONTENT_TYPE], "text/plain; charset=utf-8");
+            assert_eq!(body, b"fn main() {}");
+        }
+
+        let (status, headers, body) =
+            get(&app, &format!("/v1/content/{}/copy?what=image", id)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers[header::CONTENT_TYPE], "image/png");
+        assert_eq!(headers[header::CACHE_CONTROL], "no-store");
+        assert_eq!(
+            headers[header::CONTENT_DISPOSITION],
+            format!("inline; filename=\"screenpipe-frame-{}.png\"", id).as_str()
+        );
+        assert_eq!(image::guess_format(&body).unwrap(), image::ImageFormat::Png);
+
+        let (status, _, body) = get(&app, &format!("/v1/content/{}/copy?what=image", pii)).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let problem: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(problem["code"], "sensitive");
+        assert_eq!(problem["reason"], "pii");
+        // The redacted text itself can be copied
+        let (status, _, body) = get(&app, &format!("/v1/content/{}/copy", pii)).await;
+        assert_eq!(
+            (status, body.as_slice()),
+            (StatusCode::OK, &b"ssn [SSN]"[..])
+        );
+
+        let (status, _, body) = get(&app, &format!("/v1/content/{}/copy?what=link", id)).await;
+        assert_eq!(status, StatusCode::OK);
+        let link: CopyLink = serde_json::from_slice(&body).unwrap();
+        assert_eq!(link.frame_id, id);
+        assert!(link
+            .deep_link
+            .starts_with(&format!("screenpipe://frame/{}?", id)));
+        assert_eq!(
+            link.http_url,
+            format!("http://localhost:3030/v1/content/{}/copy?what=image", id)
+        );
+
+        let (status, _, body) = get(
+            &app,
+            &format!(
+                "/v1/deep-links/resolve?url={}",
+                link.deep_link
+                    .replace(':', "%3A")
+                    .replace('?', "%3F")
+                    .replace('=', "%3D")
+            ),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let position: TimelinePosition = serde_json::from_slice(&body).unwrap();
+        assert!(position.found);
+        assert_eq!(position.timestamp, link.timestamp);
+        assert_eq!(position.app_name.as_deref(), Some("code"));
+
+        let (status, _, body) = get(&app, "/v1/deep-links").await;
+        assert_eq!(status, StatusCode::OK);
+        let document: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(document["scheme"], "screenpipe");
+
+        let (status, _, _) = get(&app, &format!("/v1/content/{}/copy?what=html", id)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _, _) = get(&app, &format!("/v1/content/{}/copy", pii + 1)).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    /// Needs a desktop session, skipped on linux without a display.
+    #[cfg(any(target_os = "macos", target_os = "windows", target_os = "linux"))]
+    #[test]
+    fn test_clipboard_smoke() {
+        use screenpipe_server::copy::{copy_to_clipboard, ClipboardContent};
+        use std::time::Duration;
+
+        if cfg!(target_os = "linux")
+            && std::env::var_os("DISPLAY").is_none()
+            && std::env::var_os("WAYLAND_DISPLAY").is_none()
+        {
+            return;
+        }
+        let text = format!("screenpipe clipboard test {}", std::process::id());
+        copy_to_clipboard(
+            &ClipboardContent::Text(text.clone()),
+            Duration::from_millis(100),
+        )
+        .unwrap();
+        if cfg!(not(target_os = "linux")) {
+            // Elsewhere the clipboard outlives the process that set it
+            assert_eq!(arboard::Clipboard::new().unwrap().get_text().unwrap(), text);
+        }
+    }
+}
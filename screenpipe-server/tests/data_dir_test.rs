@@ -0,0 +1,196 @@
+#[cfg(test)]
+mod tests {
+    use screenpipe_server::data_dir::{init_data_dir, InitError, BACKUPS_DIR};
+    use std::fs;
+    use std::path::Path;
+    use tempfile::tempdir;
+
+    fn write(base: &Path, path: &str, content: &str) {
+        let path = base.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    /// Every file and directory under `dir`, relative and sorted, directories ending
+    /// with `/`. Backups are left out.
+    fn tree(dir: &Path) -> Vec<String> {
+        fn walk(root: &Path, dir: &Path, entries: &mut Vec<String>) {
+            for entry in fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                let relative = path
+                    .strip_prefix(root)
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string();
+                if relative == BACKUPS_DIR {
+                    continue;
+                }
+                if path.is_dir() {
+                    entries.push(format!("{}/", relative));
+                    walk(root, &path, entries);
+                } else {
+                    entries.push(relative);
+                }
+            }
+        }
+        let mut entries = Vec::new();
+        walk(dir, dir, &mut entries);
+        entries.sort();
+        entries
+    }
+
+    fn read(base: &Path, path: &str) -> String {
+        fs::read_to_string(base.join(path)).unwrap()
+    }
+
+    #[test]
+    fn test_fresh_install_gets_the_tree() {
+        let root = tempdir().unwrap();
+        let base = root.path().join("nested").join(".screenpipe");
+
+        let report = init_data_dir(&base).unwrap();
+        assert!(report.changed());
+        assert_eq!(report.created.len(), 4);
+        assert!(report.migrated.is_empty());
+        assert_eq!(tree(&base), vec!["data/", "logs/", "pipes/"]);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(base.join("data"))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o700);
+        }
+
+        let again = init_data_dir(&base).unwrap();
+        assert!(!again.changed());
+        assert!(again.skipped.is_empty());
+        assert_eq!(tree(&base), vec!["data/", "logs/", "pipes/"]);
+    }
+
+    #[test]
+    fn test_legacy_layouts_are_moved_into_place() {
+        let root = tempdir().unwrap();
+        let base = root.path();
+        // Database under its old name, logs and a pipe folder in the root
+        write(base, "screenpipe.db", "db");
+        write(base, "screenpipe.db-wal", "wal");
+        write(base, "screenpipe.2024-12-01.log", "log");
+        write(base, "screenpipe-app.2024-12-01.log", "app log");
+        write(base, "digest/pipe.json", r#"{"enabled": true}"#);
+        write(base, "digest/pipe.ts", "digest");
+        write(base, "digest/node_modules/dep/index.js", "dep");
+        // A single file pipe and a pipe with an index entry point
+        write(base, "pipes/notes.ts", "notes");
+        write(base, "pipes/notes.json", r#"{"interval": 60}"#);
+        write(base, "pipes/clock/pipe.json", "{}");
+        write(base, "pipes/clock/index.js", "clock");
+        // Already current
+        write(base, "pipes/search/pipe.json", "{}");
+        write(base, "pipes/search/pipe.ts", "search");
+        write(base, "pipes/search/index.ts", "helpers");
+        write(base, "data/monitor_1.mp4", "video");
+
+        let report = init_data_dir(base).unwrap();
+        assert_eq!(report.migrated.len(), 7);
+        assert!(report.skipped.is_empty());
+        assert_eq!(
+            tree(base),
+            vec![
+                "data/",
+                "data/monitor_1.mp4",
+                "db.sqlite",
+                "db.sqlite-wal",
+                "logs/",
+                "logs/screenpipe.2024-12-01.log",
+                "pipes/",
+                "pipes/clock/",
+                "pipes/clock/pipe.js",
+                "pipes/clock/pipe.json",
+                "pipes/digest/",
+                "pipes/digest/node_modules/",
+                "pipes/digest/node_modules/dep/",
+                "pipes/digest/node_modules/dep/index.js",
+                "pipes/digest/pipe.json",
+                "pipes/digest/pipe.ts",
+                "pipes/notes/",
+                "pipes/notes/pipe.json",
+                "pipes/notes/pipe.ts",
+                "pipes/search/",
+                "pipes/search/index.ts",
+                "pipes/search/pipe.json",
+                "pipes/search/pipe.ts",
+                "screenpipe-app.2024-12-01.log",
+            ]
+        );
+        assert_eq!(read(base, "db.sqlite"), "db");
+        assert_eq!(read(base, "pipes/notes/pipe.json"), r#"{"interval": 60}"#);
+
+        // Everything moved was copied first, dependencies aside
+        let backup = report.backup_dir.unwrap();
+        assert!(backup.starts_with(base.join(BACKUPS_DIR)));
+        assert_eq!(read(&backup, "screenpipe.db"), "db");
+        assert_eq!(read(&backup, "pipes/clock/index.js"), "clock");
+        assert_eq!(read(&backup, "digest/pipe.ts"), "digest");
+        assert!(!backup.join("digest/node_modules").exists());
+
+        let again = init_data_dir(base).unwrap();
+        assert!(!again.changed());
+        assert_eq!(again.backup_dir, None);
+    }
+
+    #[test]
+    fn test_taken_places_are_left_alone() {
+        let root = tempdir().unwrap();
+        let base = root.path();
+        write(base, "db.sqlite", "current");
+        write(base, "screenpipe.sqlite", "legacy");
+        write(base, "screenpipe.sqlite-wal", "legacy wal");
+        write(base, "pipes/notes/pipe.ts", "current");
+        write(base, "pipes/notes.ts", "legacy");
+        write(base, "notes/pipe.json", "{}");
+
+        let report = init_data_dir(base).unwrap();
+        assert!(report.migrated.is_empty());
+        assert_eq!(report.skipped.len(), 4);
+        assert!(report.skipped[0].contains("screenpipe.sqlite was left in place"));
+        assert_eq!(read(base, "db.sqlite"), "current");
+        assert!(!base.join("db.sqlite-wal").exists());
+        assert_eq!(read(base, "pipes/notes/pipe.ts"), "current");
+        assert_eq!(read(base, "pipes/notes.ts"), "legacy");
+        assert!(base.join("notes/pipe.json").exists());
+    }
+
+    #[test]
+    fn test_unusable_locations_fail_with_what_to_do() {
+        let root = tempdir().unwrap();
+        let file = root.path().join("screenpipe");
+        fs::write(&file, "").unwrap();
+        let error = init_data_dir(&file).unwrap_err();
+        assert!(matches!(error, InitError::NotADirectory(_)));
+        assert!(error.to_string().contains("--data-dir"));
+
+        let base = root.path().join("base");
+        write(&base, "pipes", "not a directory");
+        assert!(matches!(
+            init_data_dir(&base).unwrap_err(),
+            InitError::NotADirectory(path) if path == base.join("pipes")
+        ));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let locked = root.path().join("locked");
+            fs::create_dir(&locked).unwrap();
+            fs::set_permissions(&locked, fs::Permissions::from_mode(0o500)).unwrap();
+            // Root writes anyway
+            if fs::write(locked.join("probe"), "").is_err() {
+                let error = init_data_dir(&locked).unwrap_err();
+                assert!(matches!(error, InitError::PermissionDenied(_)), "{}", error);
+                assert!(error.to_string().contains("chown"));
+            }
+            fs::set_permissions(&locked, fs::Permissions::from_mode(0o700)).unwrap();
+        }
+    }
+}
@@ -5,7 +5,7 @@ mod tests {
     use chrono::Utc;
     use screenpipe_audio::{AudioDevice, DeviceType};
     use screenpipe_server::{
-        db_types::{ContentType, SearchResult},
+        db_types::{ContentType, SearchFilters, SearchOrder, SearchResult},
         DatabaseManager,
     };
     use screenpipe_vision::OcrEngine;
@@ -360,14 +360,36 @@ mod tests {
 
         // After inserting both audio transcriptions, let's check all audio entries
         let all_audio = db
-            .search_audio("", 100, 0, None, None, None, None, None)
+            .search_audio(
+                "",
+                100,
+                0,
+                None,
+                None,
+                None,
+                None,
+                None,
+                &SearchFilters::default(),
+                SearchOrder::Recent,
+            )
             .await
             .unwrap();
         println!("All audio entries: {:?}", all_audio);
 
         // Then try specific search
         let audio_results = db
-            .search_audio("2", 100, 0, None, None, None, None, None)
+            .search_audio(
+                "2",
+                100,
+                0,
+                None,
+                None,
+                None,
+                None,
+                None,
+                &SearchFilters::default(),
+                SearchOrder::Recent,
+            )
             .await
             .unwrap();
         println!("Audio results for '2': {:?}", audio_results);
@@ -0,0 +1,207 @@
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Utc};
+    use screenpipe_server::db_retry::{drain_spill_journal, BusyRetry, SPILL_JOURNAL_FILE};
+    use screenpipe_server::db_types::{
+        CaptureOutcome, CaptureWrite, FrameWrite, TranscriptionWrite, WindowOcrWrite,
+    };
+    use screenpipe_server::storage_mode::StorageMode;
+    use screenpipe_server::DatabaseManager;
+    use sqlx::{Connection, SqliteConnection};
+    use std::path::Path;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    async fn setup(dir: &Path) -> DatabaseManager {
+        let db_path = dir.join("db.sqlite");
+        DatabaseManager::new_with_busy_timeout(
+            &db_path.to_string_lossy(),
+            Duration::from_millis(20),
+        )
+        .await
+        .unwrap()
+        .with_write_retry(BusyRetry {
+            max_retries: 3,
+            base_delay: Duration::from_millis(10),
+            max_delay: Duration::from_millis(50),
+            deadline: Duration::from_millis(300),
+        })
+        .with_spill_journal(dir.join(SPILL_JOURNAL_FILE))
+    }
+
+    /// Another process (a backup, a sqlite shell) holding the write lock.
+    async fn lock(dir: &Path) -> SqliteConnection {
+        let db_path = dir.join("db.sqlite");
+        let mut conn = SqliteConnection::connect(&format!("sqlite:{}", db_path.display()))
+            .await
+            .unwrap();
+        sqlx::query("BEGIN EXCLUSIVE")
+            .execute(&mut conn)
+            .await
+            .unwrap();
+        conn
+    }
+
+    fn frame(i: usize) -> CaptureWrite {
+        CaptureWrite::Frame(FrameWrite {
+            device_name: "monitor 1".to_string(),
+            video_chunk_id: None,
+            timestamp: None,
+            windows: vec![WindowOcrWrite {
+                text: format!("frame {}", i),
+                text_json: "[]".to_string(),
+                app_name: "app".to_string(),
+                window_name: "window".to_string(),
+                ocr_engine: "Tesseract".to_string(),
+                focused: true,
+                raw_text: None,
+                corrected_by: None,
+            }],
+            storage_mode: StorageMode::Full,
+            thumbnail_path: None,
+            window_layout: None,
+        })
+    }
+
+    fn transcription(i: usize) -> CaptureWrite {
+        CaptureWrite::Transcription(TranscriptionWrite {
+            file_path: format!("audio_{}.mp4", i),
+            transcription: format!("chunk {}", i),
+            offset_index: 0,
+            transcription_engine: "WhisperLargeV3Turbo".to_string(),
+            device_name: "mic".to_string(),
+            is_input_device: true,
+            speaker_id: None,
+            start_time: Some(0.0),
+            end_time: Some(1.0),
+        })
+    }
+
+    async fn wait_for_drain(db: &DatabaseManager) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while db.write_metrics().pending.load(Ordering::Relaxed) > 0 && Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_no_capture_is_lost_while_another_connection_holds_the_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Arc::new(setup(dir.path()).await);
+        db.insert_video_chunk("video.mp4", "monitor 1")
+            .await
+            .unwrap();
+        tokio::spawn(drain_spill_journal(db.clone(), Duration::from_millis(50)));
+
+        let writer = tokio::spawn({
+            let db = db.clone();
+            async move {
+                let mut outcomes = Vec::new();
+                for i in 0..40 {
+                    outcomes.push(db.write_capture(frame(i)).await.unwrap());
+                    outcomes.push(db.write_capture(transcription(i)).await.unwrap());
+                    tokio::time::sleep(Duration::from_millis(25)).await;
+                }
+                outcomes
+            }
+        });
+
+        // The lock storm starts while capture is running, and ends before it stops
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let mut locker = lock(dir.path()).await;
+        tokio::time::sleep(Duration::from_millis(700)).await;
+        sqlx::query("COMMIT").execute(&mut locker).await.unwrap();
+
+        let outcomes = writer.await.unwrap();
+        assert!(outcomes.contains(&CaptureOutcome::Spilled));
+        wait_for_drain(&db).await;
+
+        // Every item made it, in capture order
+        let frames: Vec<(i64, String)> = sqlx::query_as(
+            "SELECT f.offset_index, o.text FROM frames f JOIN ocr_text o ON o.frame_id = f.id ORDER BY f.id",
+        )
+        .fetch_all(&db.pool)
+        .await
+        .unwrap();
+        let expected: Vec<(i64, String)> = (0..40)
+            .map(|i| (i as i64, format!("frame {}", i)))
+            .collect();
+        assert_eq!(frames, expected);
+
+        let transcriptions: Vec<String> =
+            sqlx::query_scalar("SELECT transcription FROM audio_transcriptions ORDER BY id")
+                .fetch_all(&db.pool)
+                .await
+                .unwrap();
+        let expected: Vec<String> = (0..40).map(|i| format!("chunk {}", i)).collect();
+        assert_eq!(transcriptions, expected);
+
+        let metrics = db.write_metrics().snapshot();
+        assert!(metrics.retried > 0);
+        assert!(metrics.spilled > 0);
+        assert_eq!(metrics.replayed, metrics.spilled);
+        assert_eq!(metrics.dropped, 0);
+        assert_eq!(metrics.pending, 0);
+        assert!(!dir.path().join(SPILL_JOURNAL_FILE).exists());
+    }
+
+    #[tokio::test]
+    async fn test_spilled_writes_are_replayed_by_the_next_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let spilled_at = {
+            let db = setup(dir.path()).await;
+            db.insert_video_chunk("video.mp4", "monitor 1")
+                .await
+                .unwrap();
+            let mut locker = lock(dir.path()).await;
+            for i in 0..3 {
+                assert_eq!(
+                    db.write_capture(frame(i)).await.unwrap(),
+                    CaptureOutcome::Spilled
+                );
+            }
+            assert_eq!(db.write_metrics().pending.load(Ordering::Relaxed), 3);
+            let spilled_at = Utc::now();
+            // The process exits with the database still locked
+            db.pool.close().await;
+            sqlx::query("ROLLBACK").execute(&mut locker).await.unwrap();
+            spilled_at
+        };
+
+        let db = setup(dir.path()).await;
+        assert_eq!(db.write_metrics().pending.load(Ordering::Relaxed), 3);
+        // The chunk spilled frames were encoded into is kept, not the latest one
+        db.insert_video_chunk("video_2.mp4", "monitor 1")
+            .await
+            .unwrap();
+        assert_eq!(db.replay_spill_journal().await.unwrap(), 3);
+
+        let frames: Vec<(i64, DateTime<Utc>, Option<i64>)> = sqlx::query_as(
+            "SELECT video_chunk_id, timestamp, monotonic_ns FROM frames ORDER BY id",
+        )
+        .fetch_all(&db.pool)
+        .await
+        .unwrap();
+        assert_eq!(frames.len(), 3);
+        for (video_chunk_id, timestamp, _) in frames {
+            assert_eq!(video_chunk_id, 1);
+            assert!(timestamp <= spilled_at);
+        }
+        assert_eq!(db.write_metrics().pending.load(Ordering::Relaxed), 0);
+        assert_eq!(db.replay_spill_journal().await.unwrap(), 0);
+    }
+
+    #[test]
+    fn test_retry_delays_are_jittered_and_capped() {
+        let policy = BusyRetry::default();
+        for retry in 1..=20 {
+            let backoff = policy
+                .base_delay
+                .saturating_mul(1 << (retry - 1).min(16))
+                .min(policy.max_delay);
+            let delay = policy.delay(retry);
+            assert!(delay >= backoff / 2 && delay <= backoff, "{:?}", delay);
+        }
+    }
+}
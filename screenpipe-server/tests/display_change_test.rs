@@ -0,0 +1,216 @@
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration as ChronoDuration, Utc};
+    use futures::future::BoxFuture;
+    use image::{DynamicImage, Rgb, RgbImage};
+    use screenpipe_core::find_ffmpeg_path;
+    use screenpipe_core::latency::LatencyStamps;
+    use screenpipe_server::events::{EventKind, EventRecorder};
+    use screenpipe_server::sources::FrameSource;
+    use screenpipe_server::video_guard::VideoGap;
+    use screenpipe_server::{DatabaseManager, VideoCapture};
+    use screenpipe_vision::{CaptureResult, Frame};
+    use std::process::Command;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+    use tokio::sync::mpsc::Sender;
+
+    /// Sends frames of each size in turn, like a monitor a projector gets plugged into.
+    struct ResizingSource {
+        sizes: Vec<((u32, u32), usize)>,
+        interval: Duration,
+    }
+
+    impl FrameSource for ResizingSource {
+        fn start(self: Arc<Self>, result_tx: Sender<CaptureResult>) -> BoxFuture<'static, ()> {
+            Box::pin(async move {
+                let mut frame_number = 0;
+                for &((width, height), count) in &self.sizes {
+                    for _ in 0..count {
+                        let shade = (frame_number * 7 % 200) as u8;
+                        let image = RgbImage::from_pixel(width, height, Rgb([shade, 80, 160]));
+                        let timestamp = Instant::now();
+                        let frame = CaptureResult {
+                            image: Frame::new(DynamicImage::ImageRgb8(image)),
+                            frame_number,
+                            timestamp,
+                            window_ocr_results: vec![],
+                            duplicate_of: None,
+                            latency: LatencyStamps::captured(timestamp),
+                            window_layout: None,
+                        };
+                        if result_tx.send(frame).await.is_err() {
+                            return;
+                        }
+                        frame_number += 1;
+                        tokio::time::sleep(self.interval).await;
+                    }
+                }
+            })
+        }
+    }
+
+    /// Size of the first frame of a chunk, decoding all of it on the way.
+    fn first_frame_size(path: &str) -> (u32, u32) {
+        let ffmpeg = find_ffmpeg_path().expect("ffmpeg not found");
+        let decoded = Command::new(&ffmpeg)
+            .args(["-v", "error", "-i", path, "-f", "null", "-"])
+            .output()
+            .unwrap();
+        assert!(decoded.status.success(), "{} is not playable", path);
+        assert!(
+            decoded.stderr.is_empty(),
+            "{}",
+            String::from_utf8_lossy(&decoded.stderr)
+        );
+
+        let first = Command::new(&ffmpeg)
+            .args([
+                "-v",
+                "error",
+                "-i",
+                path,
+                "-vframes",
+                "1",
+                "-f",
+                "image2pipe",
+                "-vcodec",
+                "png",
+                "-",
+            ])
+            .output()
+            .unwrap();
+        let image = image::load_from_memory(&first.stdout).unwrap();
+        (image.width(), image.height())
+    }
+
+    #[tokio::test]
+    async fn test_resolution_change_mid_chunk_starts_a_new_chunk() {
+        let output = tempfile::tempdir().unwrap();
+        let db = Arc::new(DatabaseManager::new("sqlite::memory:").await.unwrap());
+        let events = Arc::new(EventRecorder::new(db.clone()));
+        let chunks = Arc::new(Mutex::new(Vec::<String>::new()));
+        let changes = Arc::new(Mutex::new(Vec::new()));
+        let gaps = Arc::new(Mutex::new(Vec::<VideoGap>::new()));
+        let started = Utc::now();
+
+        // Half a second at the laptop's size, then the projector's until the end
+        let source = Arc::new(ResizingSource {
+            sizes: vec![((320, 240), 5), ((480, 270), 90)],
+            interval: Duration::from_millis(100),
+        });
+        let _capture = VideoCapture::new(
+            output.path().to_str().unwrap(),
+            10.0,
+            Duration::from_secs(2),
+            {
+                let chunks = chunks.clone();
+                let db = db.clone();
+                move |path: &str| {
+                    chunks.lock().unwrap().push(path.to_string());
+                    let (db, path) = (db.clone(), path.to_string());
+                    tokio::spawn(async move {
+                        db.insert_video_chunk(&path, "monitor_1").await.unwrap();
+                    });
+                }
+            },
+            source,
+            1,
+            None,
+            {
+                let changes = changes.clone();
+                move |previous: (u32, u32), current: (u32, u32)| {
+                    changes.lock().unwrap().push((previous, current));
+                    events.emit(EventKind::DisplayChanged {
+                        device_name: "monitor_1".to_string(),
+                        previous_width: previous.0,
+                        previous_height: previous.1,
+                        width: current.0,
+                        height: current.1,
+                    });
+                }
+            },
+            {
+                let gaps = gaps.clone();
+                move |gap| gaps.lock().unwrap().push(gap)
+            },
+        );
+
+        // The second chunk is finalized once the third starts
+        let deadline = Instant::now() + Duration::from_secs(30);
+        while chunks.lock().unwrap().len() < 3 {
+            assert!(Instant::now() < deadline, "chunks: {:?}", chunks.lock());
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let chunks = chunks.lock().unwrap().clone();
+        assert_eq!(first_frame_size(&chunks[0]), (320, 240));
+        assert_eq!(first_frame_size(&chunks[1]), (480, 270));
+        assert_eq!(*changes.lock().unwrap(), vec![((320, 240), (480, 270))]);
+        // The frames shown while the new size settled are missing from the video
+        let gaps = gaps.lock().unwrap().clone();
+        assert_eq!(gaps.len(), 1);
+        assert_eq!(gaps[0].reason, "display_settling");
+        assert!(gaps[0].frames > 0);
+
+        let indexed: Vec<String> =
+            sqlx::query_scalar("SELECT file_path FROM video_chunks ORDER BY id")
+                .fetch_all(&db.pool)
+                .await
+                .unwrap();
+        assert_eq!(indexed[..2], chunks[..2]);
+
+        let recorded = db
+            .get_display_changes(started, Utc::now() + ChronoDuration::seconds(1))
+            .await
+            .unwrap();
+        assert_eq!(recorded.len(), 1);
+        assert_eq!(recorded[0].device_name, "monitor_1");
+        assert_eq!(
+            (recorded[0].previous_width, recorded[0].previous_height),
+            (320, 240)
+        );
+        assert_eq!((recorded[0].width, recorded[0].height), (480, 270));
+    }
+
+    #[tokio::test]
+    async fn test_flapping_resolution_keeps_the_chunk() {
+        let output = tempfile::tempdir().unwrap();
+        let chunks = Arc::new(Mutex::new(Vec::<String>::new()));
+        let changes = Arc::new(Mutex::new(Vec::new()));
+
+        // A dock flipping the size every few frames, and back before it settles
+        let mut sizes = Vec::new();
+        for _ in 0..4 {
+            sizes.push(((320, 240), 3));
+            sizes.push(((480, 270), 3));
+        }
+        sizes.push(((320, 240), 50));
+        let source = Arc::new(ResizingSource {
+            sizes,
+            interval: Duration::from_millis(100),
+        });
+        let _capture = VideoCapture::new(
+            output.path().to_str().unwrap(),
+            10.0,
+            Duration::from_secs(60),
+            {
+                let chunks = chunks.clone();
+                move |path: &str| chunks.lock().unwrap().push(path.to_string())
+            },
+            source,
+            1,
+            None,
+            {
+                let changes = changes.clone();
+                move |previous, current| changes.lock().unwrap().push((previous, current))
+            },
+            |_| {},
+        );
+
+        tokio::time::sleep(Duration::from_secs(8)).await;
+        assert_eq!(chunks.lock().unwrap().len(), 1);
+        assert!(changes.lock().unwrap().is_empty());
+    }
+}
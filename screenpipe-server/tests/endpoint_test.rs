@@ -10,6 +10,13 @@ mod tests {
     use screenpipe_audio::{AudioDevice, DeviceType};
     use screenpipe_server::db_types::ContentType;
     use screenpipe_server::db_types::SearchResult;
+    use screenpipe_server::events::EventRecorder;
+    use screenpipe_server::pipe_schedule::PipeScheduler;
+    use screenpipe_server::ranking::RankingWeights;
+    use screenpipe_server::retention::RetentionManager;
+    use screenpipe_server::sessions::SessionManager;
+    use screenpipe_server::settings_watch::SettingsWatch;
+    use screenpipe_server::telemetry::Telemetry;
     use screenpipe_server::video_cache::FrameCache;
     use screenpipe_server::PipeManager;
     use screenpipe_server::{
@@ -39,12 +46,29 @@ mod tests {
             app_start_time: Utc::now(),
             screenpipe_dir: PathBuf::from(""),
             pipe_manager: Arc::new(PipeManager::new(PathBuf::from(""))),
+            retention: Arc::new(RetentionManager::new(db.clone(), PathBuf::from(""), None)),
+            pipe_scheduler: Arc::new(PipeScheduler::new(
+                db.clone(),
+                Arc::new(PipeManager::new(PathBuf::from(""))),
+            )),
+            sessions: Arc::new(SessionManager::new(db.clone(), PathBuf::from(""))),
+            telemetry: Arc::new(Telemetry::new(
+                db.clone(),
+                Arc::new(PipeManager::new(PathBuf::from(""))),
+                PathBuf::from(""),
+            )),
+            settings: Arc::new(SettingsWatch::new()),
+            events: Arc::new(EventRecorder::new(db.clone())),
             vision_disabled: false,
             audio_disabled: false,
             frame_cache: Some(Arc::new(
                 FrameCache::new(PathBuf::from(""), db).await.unwrap(),
             )),
             ui_monitoring_enabled: false,
+            ocr_scheduler: None,
+            media_volume: None,
+            archiver: None,
+            ranking: RankingWeights::default(),
         });
 
         let router = create_router();
@@ -0,0 +1,297 @@
+#[cfg(test)]
+mod tests {
+    use axum::body::{to_bytes, Body};
+    use axum::http::{Request, StatusCode};
+    use axum::Router;
+    use chrono::{Duration as ChronoDuration, TimeZone, Utc};
+    use crossbeam::queue::SegQueue;
+    use screenpipe_core::power::{PowerSource, SubsystemOutcome};
+    use screenpipe_server::events::{
+        Event, EventFilter, EventKind, EventRecorder, Severity, EVENT_TYPES,
+    };
+    use screenpipe_server::pipe_schedule::PipeScheduler;
+    use screenpipe_server::ranking::RankingWeights;
+    use screenpipe_server::retention::RetentionManager;
+    use screenpipe_server::sessions::SessionManager;
+    use screenpipe_server::settings_watch::SettingsWatch;
+    use screenpipe_server::telemetry::Telemetry;
+    use screenpipe_server::wake::WakeSummary;
+    use screenpipe_server::{
+        create_router, AppState, DatabaseManager, HealthCheckResponse, PipeManager,
+    };
+    use serde_json::Value;
+    use std::collections::{BTreeMap, HashMap};
+    use std::path::PathBuf;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    async fn setup_test_app() -> (Router, Arc<EventRecorder>) {
+        let db = Arc::new(DatabaseManager::new("sqlite::memory:").await.unwrap());
+        let events = Arc::new(EventRecorder::new(db.clone()));
+        let app_state = Arc::new(AppState {
+            db: db.clone(),
+            vision_control: Arc::new(AtomicBool::new(false)),
+            audio_devices_control: Arc::new(SegQueue::new()),
+            devices_status: HashMap::new(),
+            app_start_time: Utc::now(),
+            screenpipe_dir: PathBuf::from(""),
+            pipe_manager: Arc::new(PipeManager::new(PathBuf::from(""))),
+            retention: Arc::new(RetentionManager::new(db.clone(), PathBuf::from(""), None)),
+            pipe_scheduler: Arc::new(PipeScheduler::new(
+                db.clone(),
+                Arc::new(PipeManager::new(PathBuf::from(""))),
+            )),
+            sessions: Arc::new(SessionManager::new(db.clone(), PathBuf::from(""))),
+            telemetry: Arc::new(Telemetry::new(
+                db.clone(),
+                Arc::new(PipeManager::new(PathBuf::from(""))),
+                PathBuf::from(""),
+            )),
+            settings: Arc::new(SettingsWatch::new()),
+            events: events.clone(),
+            vision_disabled: true,
+            audio_disabled: true,
+            frame_cache: None,
+            ui_monitoring_enabled: false,
+            ocr_scheduler: None,
+            media_volume: None,
+            archiver: None,
+            ranking: RankingWeights::default(),
+        });
+        (create_router().with_state(app_state), events)
+    }
+
+    async fn get(app: &Router, uri: &str) -> (StatusCode, Vec<u8>) {
+        let response = app
+            .clone()
+            .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, body.to_vec())
+    }
+
+    async fn get_events(app: &Router, uri: &str) -> Vec<Event> {
+        let (status, body) = get(app, uri).await;
+        assert_eq!(status, StatusCode::OK, "{}", String::from_utf8_lossy(&body));
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    /// One event of every documented type.
+    fn every_kind() -> Vec<EventKind> {
+        let slept_at = Utc.with_ymd_and_hms(2024, 12, 30, 1, 0, 0).unwrap();
+        let woke_at = Utc.with_ymd_and_hms(2024, 12, 30, 8, 30, 0).unwrap();
+        vec![
+            EventKind::Sleep {},
+            EventKind::Wake(WakeSummary {
+                source: PowerSource::ClockJump,
+                slept_at,
+                woke_at,
+                slept_secs: (woke_at - slept_at).num_seconds(),
+                subsystems: BTreeMap::from([
+                    (
+                        "video monitor 1".to_string(),
+                        SubsystemOutcome::Ok("chunk finalized".to_string()),
+                    ),
+                    ("audio mic".to_string(), SubsystemOutcome::TimedOut),
+                ]),
+            }),
+            EventKind::CaptureGap {
+                start_time: slept_at,
+                end_time: woke_at,
+                reason: "sleep".to_string(),
+            },
+            EventKind::CapturePaused {
+                reason: "media_unavailable".to_string(),
+                media_dir: "/Volumes/capture/data".to_string(),
+            },
+            EventKind::CaptureResumed {
+                reason: "media_available".to_string(),
+                media_dir: "/Volumes/capture/data".to_string(),
+                paused_secs: 95,
+            },
+            EventKind::DisplayChanged {
+                device_name: "monitor_1".to_string(),
+                previous_width: 2560,
+                previous_height: 1600,
+                width: 1920,
+                height: 1080,
+            },
+            EventKind::VideoGap {
+                device_name: "monitor_1".to_string(),
+                start_time: slept_at,
+                end_time: slept_at + ChronoDuration::seconds(3),
+                frames: 28,
+                reason: "display_settling".to_string(),
+            },
+            EventKind::ClockAdjusted {
+                system_time: Utc.with_ymd_and_hms(2024, 12, 30, 9, 0, 0).unwrap(),
+                monotonic_ns: 3_600_000_000_123,
+                jump_ms: -3_600_000,
+                timestamp_source: "monotonic".to_string(),
+            },
+            EventKind::PipeCrashed {
+                pipe_id: "daily-digest".to_string(),
+                error: "exited with status: exit status: 1".to_string(),
+            },
+            EventKind::PermissionRevoked {
+                pipe_id: "daily-digest".to_string(),
+            },
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_every_event_type_round_trips_through_the_api() {
+        let kinds = every_kind();
+        let types: Vec<&str> = kinds.iter().map(|kind| kind.event_type()).collect();
+        assert_eq!(types, EVENT_TYPES, "every documented type is covered");
+
+        let (app, events) = setup_test_app().await;
+        let mut recorded = Vec::new();
+        for kind in &kinds {
+            recorded.push(events.record(kind.clone()).await.unwrap());
+        }
+
+        let mut listed = get_events(&app, "/v1/events?limit=1000").await;
+        listed.reverse();
+        assert_eq!(listed, recorded);
+        for (event, kind) in listed.iter().zip(&kinds) {
+            assert_eq!(&event.kind, kind);
+            assert_eq!(event.subsystem, kind.subsystem());
+            assert_eq!(event.severity, kind.severity());
+        }
+
+        // One type at a time, through the documented json shape
+        for kind in &kinds {
+            let (status, body) = get(&app, &format!("/v1/events?type={}", kind.event_type())).await;
+            assert_eq!(status, StatusCode::OK);
+            let json: Value = serde_json::from_slice(&body).unwrap();
+            let json = &json.as_array().unwrap()[0];
+            assert_eq!(json["type"], kind.event_type());
+            let expected = serde_json::to_value(kind).unwrap();
+            assert_eq!(json["payload"], expected["payload"]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_events_filters() {
+        let (app, events) = setup_test_app().await;
+        let now = Utc::now();
+        for (kind, hours_ago) in every_kind().into_iter().zip(0..) {
+            events
+                .record_at(kind, now - ChronoDuration::hours(hours_ago))
+                .await
+                .unwrap();
+        }
+
+        let listed = get_events(&app, "/v1/events?type=sleep,pipe_crashed").await;
+        let types: Vec<&str> = listed.iter().map(|e| e.kind.event_type()).collect();
+        assert_eq!(types, vec!["sleep", "pipe_crashed"]);
+
+        let listed = get_events(&app, "/v1/events?severity=warning").await;
+        assert!(listed.iter().all(|e| e.severity >= Severity::Warning));
+        let types: Vec<&str> = listed.iter().map(|e| e.kind.event_type()).collect();
+        assert_eq!(
+            types,
+            vec![
+                "wake",
+                "capture_paused",
+                "video_gap",
+                "clock_adjusted",
+                "pipe_crashed"
+            ]
+        );
+
+        let since = (now - ChronoDuration::minutes(90)).to_rfc3339();
+        let listed = get_events(
+            &app,
+            &format!("/v1/events?since={}", since.replace('+', "%2B")),
+        )
+        .await;
+        let types: Vec<&str> = listed.iter().map(|e| e.kind.event_type()).collect();
+        assert_eq!(types, vec!["sleep", "wake"]);
+
+        let listed = get_events(&app, "/v1/events?limit=2").await;
+        assert_eq!(listed.len(), 2);
+
+        for uri in ["/v1/events?type=reboot", "/v1/events?severity=fatal"] {
+            let (status, _) = get(&app, uri).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{}", uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_live_and_stored_events_agree() {
+        let (app, events) = setup_test_app().await;
+        let mut live = events.subscribe();
+        for kind in every_kind() {
+            events.record(kind).await.unwrap();
+        }
+
+        let mut streamed = Vec::new();
+        while let Ok(event) = live.try_recv() {
+            streamed.push(event);
+        }
+        let mut listed = get_events(&app, "/v1/events").await;
+        listed.reverse();
+        assert_eq!(streamed, listed);
+
+        // Resuming after an id reads the rest oldest first
+        let missed = events
+            .query(&EventFilter {
+                after_id: Some(listed[6].id),
+                limit: 100,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(missed, listed[7..]);
+    }
+
+    #[tokio::test]
+    async fn test_health_reports_recent_critical_events() {
+        let (app, events) = setup_test_app().await;
+        let paused = EventKind::CapturePaused {
+            reason: "media_unavailable".to_string(),
+            media_dir: "/Volumes/capture/data".to_string(),
+        };
+        // Past the window health looks at
+        events
+            .record_at(paused.clone(), Utc::now() - ChronoDuration::days(2))
+            .await
+            .unwrap();
+        let recent = events.record(paused).await.unwrap();
+        events
+            .record(EventKind::PermissionRevoked {
+                pipe_id: "daily-digest".to_string(),
+            })
+            .await
+            .unwrap();
+
+        let (status, body) = get(&app, "/health").await;
+        assert_eq!(status, StatusCode::OK);
+        let health: HealthCheckResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(health.critical_events, vec![recent]);
+    }
+
+    #[tokio::test]
+    async fn test_events_are_kept_for_a_year() {
+        let (app, events) = setup_test_app().await;
+        let now = Utc::now();
+        for days_ago in [400, 366, 300, 0] {
+            events
+                .record_at(EventKind::Sleep {}, now - ChronoDuration::days(days_ago))
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(events.prune(now).await.unwrap(), 2);
+        let listed = get_events(&app, "/v1/events").await;
+        assert_eq!(listed.len(), 2);
+        assert!(listed
+            .iter()
+            .all(|e| now - e.timestamp < ChronoDuration::days(365)));
+    }
+}
@@ -0,0 +1,364 @@
+#[cfg(test)]
+mod tests {
+    use axum::body::{to_bytes, Body};
+    use axum::http::{Request, StatusCode};
+    use axum::Router;
+    use chrono::{DateTime, Duration as ChronoDuration, TimeZone, Utc};
+    use crossbeam::queue::SegQueue;
+    use screenpipe_server::db_types::{
+        CaptureOutcome, CaptureWrite, FrameWrite, SearchFilters, WindowOcrWrite,
+    };
+    use screenpipe_server::events::EventRecorder;
+    use screenpipe_server::frame_find::{
+        fts_phrase, window_matches, BoundingBox, FrameFind, WindowMatches, MATCH_END, MATCH_START,
+    };
+    use screenpipe_server::pipe_schedule::PipeScheduler;
+    use screenpipe_server::ranking::RankingWeights;
+    use screenpipe_server::retention::RetentionManager;
+    use screenpipe_server::search_query::{parse_query, Span};
+    use screenpipe_server::sessions::SessionManager;
+    use screenpipe_server::settings_watch::SettingsWatch;
+    use screenpipe_server::storage_mode::StorageMode;
+    use screenpipe_server::telemetry::Telemetry;
+    use screenpipe_server::{create_router, AppState, DatabaseManager, PipeManager};
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    fn window(app_name: &str, text: &str, text_json: &str, focused: bool) -> WindowOcrWrite {
+        WindowOcrWrite {
+            text: text.to_string(),
+            text_json: text_json.to_string(),
+            app_name: app_name.to_string(),
+            window_name: format!("{} window", app_name),
+            ocr_engine: "Tesseract".to_string(),
+            focused,
+            raw_text: None,
+            corrected_by: None,
+        }
+    }
+
+    async fn write_frame(
+        db: &DatabaseManager,
+        timestamp: DateTime<Utc>,
+        windows: Vec<WindowOcrWrite>,
+    ) -> i64 {
+        let write = CaptureWrite::Frame(FrameWrite {
+            device_name: "monitor_1".to_string(),
+            video_chunk_id: None,
+            timestamp: Some(timestamp),
+            windows,
+            storage_mode: StorageMode::TextPlusThumbnails,
+            thumbnail_path: None,
+            window_layout: None,
+        });
+        match db.write_capture(write).await.unwrap() {
+            CaptureOutcome::Written(id) => id,
+            CaptureOutcome::Spilled => panic!("write was spilled"),
+        }
+    }
+
+    async fn find(db: &DatabaseManager, frame_id: i64, term: &str) -> Vec<WindowMatches> {
+        db.find_in_frame(frame_id, &fts_phrase(term), (MATCH_START, MATCH_END))
+            .await
+            .unwrap()
+            .unwrap()
+            .into_iter()
+            .map(|row| window_matches(row, 6))
+            .collect()
+    }
+
+    fn at(minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 10, 16, 9, minute, 0).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_offsets_are_in_characters_not_bytes() {
+        let db = DatabaseManager::new("sqlite::memory:").await.unwrap();
+        let text = "🎉 Réunion à Zürich — CAFÉ 𝄞 crème, café";
+        let id = write_frame(&db, at(0), vec![window("notes", text, "[]", true)]).await;
+
+        let windows = find(&db, id, "cafe").await;
+        assert_eq!(windows.len(), 1);
+        let matches = &windows[0].matches;
+        // Case and diacritics don't count, like in search
+        let found: Vec<&str> = matches.iter().map(|m| m.text.as_str()).collect();
+        assert_eq!(found, vec!["CAFÉ", "café"]);
+
+        let chars: Vec<char> = text.chars().collect();
+        let utf16: Vec<u16> = text.encode_utf16().collect();
+        for m in matches {
+            let by_chars: String = chars[m.range.start..m.range.end].iter().collect();
+            assert_eq!(by_chars, m.text);
+            let by_utf16 = String::from_utf16(&utf16[m.utf16_range.start..m.utf16_range.end]);
+            assert_eq!(by_utf16.unwrap(), m.text);
+            let snippet: Vec<char> = m.snippet.chars().collect();
+            let in_snippet: String = snippet[m.snippet_range.start..m.snippet_range.end]
+                .iter()
+                .collect();
+            assert_eq!(in_snippet, m.text);
+        }
+        // 🎉 and 𝄞 are two utf-16 code units each, and several bytes
+        assert_eq!(matches[0].range, Span { start: 21, end: 25 });
+        assert_eq!(matches[0].utf16_range, Span { start: 22, end: 26 });
+        assert_eq!(matches[1].range, Span { start: 35, end: 39 });
+        assert_eq!(matches[1].utf16_range, Span { start: 37, end: 41 });
+        assert_eq!(matches[0].snippet, "ich — CAFÉ 𝄞 crè");
+        assert_eq!(matches[1].snippet, "rème, café");
+
+        let phrase = find(&db, id, "reunion a zurich").await;
+        assert_eq!(phrase[0].matches[0].text, "Réunion à Zürich");
+        assert!(find(&db, id, "zurichs").await.is_empty());
+        assert!(db
+            .find_in_frame(id + 1, &fts_phrase("cafe"), (MATCH_START, MATCH_END))
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_matches_carry_the_boxes_of_their_words() {
+        let db = DatabaseManager::new("sqlite::memory:").await.unwrap();
+        let text_json = r#"[
+            {"text": "quarterly", "left": "10", "top": "20", "width": "90", "height": "12"},
+            {"text": "", "left": "0", "top": "0", "width": "0", "height": "0"},
+            {"text": "Überblick", "left": "105", "top": "20", "width": "80", "height": "12"},
+            {"text": "report", "left": 190, "top": 20, "width": 50, "height": 12}
+        ]"#;
+        let id = write_frame(
+            &db,
+            at(0),
+            vec![
+                window("slack", "nothing to see", "[]", false),
+                window("docs", "quarterly Überblick report", text_json, true),
+            ],
+        )
+        .await;
+
+        let windows = find(&db, id, "uberblick report").await;
+        assert_eq!(windows.len(), 1);
+        assert!(windows[0].focused);
+        assert_eq!(
+            windows[0].matches[0].boxes,
+            vec![
+                BoundingBox {
+                    left: 105.0,
+                    top: 20.0,
+                    width: 80.0,
+                    height: 12.0
+                },
+                BoundingBox {
+                    left: 190.0,
+                    top: 20.0,
+                    width: 50.0,
+                    height: 12.0
+                },
+            ]
+        );
+
+        // Without boxes from the engine the match is still found
+        let id = write_frame(
+            &db,
+            at(1),
+            vec![window("docs", "quarterly report", "not json", true)],
+        )
+        .await;
+        let windows = find(&db, id, "report").await;
+        assert!(windows[0].matches[0].boxes.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_previous_and_next_frames_respect_the_view() {
+        let db = DatabaseManager::new("sqlite::memory:").await.unwrap();
+        let mut ids = Vec::new();
+        for (minute, app, text) in [
+            (0, "slack", "deploy at noon"),
+            (1, "code", "deploy script"),
+            (2, "slack", "nothing"),
+            (3, "slack", "Déploy done"),
+            (4, "code", "deploy again"),
+            (5, "slack", "the deploy failed"),
+        ] {
+            ids.push(write_frame(&db, at(minute), vec![window(app, text, "[]", true)]).await);
+        }
+        let current = ids[3];
+        let adjacent = |app: Option<&'static str>, filter: &str| {
+            let db = &db;
+            let filters = parse_query(filter, Utc::now()).unwrap().filters;
+            async move {
+                db.adjacent_matching_frames(
+                    current,
+                    &fts_phrase("deploy"),
+                    None,
+                    None,
+                    app,
+                    None,
+                    &filters,
+                )
+                .await
+                .unwrap()
+            }
+        };
+
+        assert_eq!(adjacent(None, "").await, (Some(ids[1]), Some(ids[4])));
+        assert_eq!(
+            adjacent(Some("slack"), "").await,
+            (Some(ids[0]), Some(ids[5]))
+        );
+        assert_eq!(
+            adjacent(None, "-app:code").await,
+            (Some(ids[0]), Some(ids[5]))
+        );
+        let range = db
+            .adjacent_matching_frames(
+                current,
+                &fts_phrase("deploy"),
+                Some(at(1)),
+                Some(at(3)),
+                None,
+                None,
+                &SearchFilters::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(range, (Some(ids[1]), None));
+        // App names aren't searched, only the text
+        assert_eq!(
+            db.adjacent_matching_frames(
+                current,
+                &fts_phrase("slack"),
+                None,
+                None,
+                None,
+                None,
+                &SearchFilters::default(),
+            )
+            .await
+            .unwrap(),
+            (None, None)
+        );
+        // Frames captured at the same time are stepped through by id
+        let same_time = write_frame(&db, at(3), vec![window("code", "deploy", "[]", true)]).await;
+        assert_eq!(adjacent(None, "").await.1, Some(same_time));
+    }
+
+    async fn setup_test_app(db: Arc<DatabaseManager>) -> Router {
+        let app_state = Arc::new(AppState {
+            db: db.clone(),
+            vision_control: Arc::new(AtomicBool::new(false)),
+            audio_devices_control: Arc::new(SegQueue::new()),
+            devices_status: HashMap::new(),
+            app_start_time: Utc::now(),
+            screenpipe_dir: PathBuf::from(""),
+            pipe_manager: Arc::new(PipeManager::new(PathBuf::from(""))),
+            retention: Arc::new(RetentionManager::new(db.clone(), PathBuf::from(""), None)),
+            pipe_scheduler: Arc::new(PipeScheduler::new(
+                db.clone(),
+                Arc::new(PipeManager::new(PathBuf::from(""))),
+            )),
+            sessions: Arc::new(SessionManager::new(db.clone(), PathBuf::from(""))),
+            telemetry: Arc::new(Telemetry::new(
+                db.clone(),
+                Arc::new(PipeManager::new(PathBuf::from(""))),
+                PathBuf::from(""),
+            )),
+            settings: Arc::new(SettingsWatch::new()),
+            events: Arc::new(EventRecorder::new(db.clone())),
+            vision_disabled: false,
+            audio_disabled: false,
+            frame_cache: None,
+            ui_monitoring_enabled: false,
+            ocr_scheduler: None,
+            media_volume: None,
+            archiver: None,
+            ranking: RankingWeights::default(),
+        });
+        create_router().with_state(app_state)
+    }
+
+    async fn get(app: &Router, uri: &str) -> (StatusCode, Vec<u8>) {
+        let response = app
+            .clone()
+            .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, body.to_vec())
+    }
+
+    #[tokio::test]
+    async fn test_find_endpoint() {
+        let db = Arc::new(DatabaseManager::new("sqlite::memory:").await.unwrap());
+        let before = write_frame(&db, at(0), vec![window("slack", "café", "[]", true)]).await;
+        let id = write_frame(
+            &db,
+            at(1),
+            vec![
+                window("code", "let café = \"CAFÉ\";", "[]", true),
+                window("slack", "a café here", "[]", false),
+            ],
+        )
+        .await;
+        let after = write_frame(
+            &db,
+            at(1) + ChronoDuration::seconds(30),
+            vec![window("code", "cafe", "[]", true)],
+        )
+        .await;
+        let app = setup_test_app(db).await;
+
+        let (status, body) = get(&app, &format!("/v1/frames/{}/find?q=Cafe&context=3", id)).await;
+        assert_eq!(status, StatusCode::OK, "{}", String::from_utf8_lossy(&body));
+        let found: FrameFind = serde_json::from_slice(&body).unwrap();
+        assert_eq!(found.total, 3);
+        assert_eq!(found.windows[0].app_name, "code");
+        assert_eq!(found.windows[0].matches[1].snippet, "= \"CAFÉ\";");
+        assert_eq!(
+            (found.previous_frame_id, found.next_frame_id),
+            (Some(before), Some(after))
+        );
+
+        // The view's filters, as params and in the query language
+        let (_, body) = get(
+            &app,
+            &format!("/v1/frames/{}/find?q=cafe&filter=app%3Aslack", id),
+        )
+        .await;
+        let found: FrameFind = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            (found.previous_frame_id, found.next_frame_id),
+            (Some(before), None)
+        );
+        let (_, body) = get(&app, &format!("/frames/{}/find?q=cafe&app_name=code", id)).await;
+        let found: FrameFind = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            (found.previous_frame_id, found.next_frame_id),
+            (None, Some(after))
+        );
+
+        for (uri, expected) in [
+            (
+                format!("/v1/frames/{}/find?q=%20", id),
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                format!("/v1/frames/{}/find?q=cafe&context=5000", id),
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                format!("/v1/frames/{}/find?q=cafe&filter=deploy", id),
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                format!("/v1/frames/{}/find?q=cafe", after + 1),
+                StatusCode::NOT_FOUND,
+            ),
+        ] {
+            let (status, _) = get(&app, &uri).await;
+            assert_eq!(status, expected, "{}", uri);
+        }
+    }
+}
@@ -0,0 +1,148 @@
+#[cfg(test)]
+mod tests {
+    // The server as a headless container runs it: capture never started, the api,
+    // database and pipes fed by what other machines push.
+    use axum::body::{to_bytes, Body};
+    use axum::http::{Request, StatusCode};
+    use axum::Router;
+    use chrono::Utc;
+    use clap::Parser;
+    use crossbeam::queue::SegQueue;
+    use screenpipe_server::events::EventRecorder;
+    use screenpipe_server::pipe_schedule::PipeScheduler;
+    use screenpipe_server::ranking::RankingWeights;
+    use screenpipe_server::retention::RetentionManager;
+    use screenpipe_server::sessions::SessionManager;
+    use screenpipe_server::settings_watch::SettingsWatch;
+    use screenpipe_server::telemetry::Telemetry;
+    use screenpipe_server::{create_router, AppState, Cli, DatabaseManager, PipeManager};
+    use serde_json::{json, Value};
+    use std::collections::HashMap;
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    async fn setup_headless_app() -> Router {
+        let db = Arc::new(DatabaseManager::new("sqlite::memory:").await.unwrap());
+        let pipe_manager = Arc::new(PipeManager::new(PathBuf::from("")));
+
+        let app_state = Arc::new(AppState {
+            db: db.clone(),
+            vision_control: Arc::new(AtomicBool::new(false)),
+            audio_devices_control: Arc::new(SegQueue::new()),
+            devices_status: HashMap::new(),
+            app_start_time: Utc::now(),
+            screenpipe_dir: PathBuf::from(""),
+            pipe_manager: pipe_manager.clone(),
+            retention: Arc::new(RetentionManager::new(db.clone(), PathBuf::from(""), None)),
+            pipe_scheduler: Arc::new(PipeScheduler::new(db.clone(), pipe_manager.clone())),
+            sessions: Arc::new(SessionManager::new(db.clone(), PathBuf::from(""))),
+            telemetry: Arc::new(Telemetry::new(db.clone(), pipe_manager, PathBuf::from(""))),
+            settings: Arc::new(SettingsWatch::new()),
+            events: Arc::new(EventRecorder::new(db.clone())),
+            vision_disabled: true,
+            audio_disabled: true,
+            frame_cache: None,
+            ui_monitoring_enabled: false,
+            ocr_scheduler: None,
+            media_volume: None,
+            archiver: None,
+            ranking: RankingWeights::default(),
+        });
+
+        create_router().with_state(app_state)
+    }
+
+    async fn send(app: &Router, method: &str, path: &str, body: Option<Value>) -> (u16, Value) {
+        let mut builder = Request::builder().method(method).uri(path);
+        let body = match body {
+            Some(body) => {
+                builder = builder.header("content-type", "application/json");
+                Body::from(body.to_string())
+            }
+            None => Body::empty(),
+        };
+        let response = app
+            .clone()
+            .oneshot(builder.body(body).unwrap())
+            .await
+            .unwrap();
+        let status = response.status().as_u16();
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (
+            status,
+            serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+        )
+    }
+
+    #[test]
+    fn test_headless_flag() {
+        let cli = Cli::try_parse_from(["screenpipe", "--headless"]).unwrap();
+        assert!(cli.headless);
+        assert!(cli.is_headless());
+    }
+
+    #[tokio::test]
+    async fn test_health_reports_capture_disabled() {
+        let app = setup_headless_app().await;
+        let (status, body) = send(&app, "GET", "/health", None).await;
+        assert_eq!(status, 200);
+        assert_eq!(body["frame_status"], "disabled");
+        assert_eq!(body["audio_status"], "disabled");
+        assert_eq!(body["ui_status"], "disabled");
+        assert_eq!(body["status"], "healthy");
+    }
+
+    #[tokio::test]
+    async fn test_recorded_requests_answer_the_same() {
+        // The v1 recordings cover no capture endpoint, all of them must behave alike
+        let app = setup_headless_app().await;
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/api_v1");
+        let mut failures = Vec::new();
+        for entry in std::fs::read_dir(&dir).unwrap() {
+            let path = entry.unwrap().path();
+            let recording: Value =
+                serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+            let request = &recording["request"];
+            let (status, _) = send(
+                &app,
+                request["method"].as_str().unwrap(),
+                request["path"].as_str().unwrap(),
+                request.get("body").cloned(),
+            )
+            .await;
+            if Some(status as u64) != recording["response"]["status"].as_u64() {
+                failures.push(format!("{}: got {}", path.display(), status));
+            }
+        }
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+
+    #[tokio::test]
+    async fn test_pushed_content_is_searchable() {
+        let app = setup_headless_app().await;
+        let (status, _) = send(
+            &app,
+            "POST",
+            "/add",
+            Some(json!({
+                "device_name": "wearable",
+                "content": {
+                    "content_type": "transcription",
+                    "data": {
+                        "transcription": "standup moved to thursday",
+                        "transcription_engine": "whisper-large"
+                    }
+                }
+            })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK.as_u16());
+
+        let (status, body) = send(&app, "GET", "/search?q=thursday&content_type=audio", None).await;
+        assert_eq!(status, 200);
+        assert_eq!(body["data"].as_array().unwrap().len(), 1);
+        assert_eq!(body["data"][0]["content"]["device_name"], "wearable");
+    }
+}
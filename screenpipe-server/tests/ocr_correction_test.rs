@@ -0,0 +1,348 @@
+#[cfg(test)]
+mod tests {
+    use screenpipe_server::db_types::{
+        CaptureOutcome, CaptureWrite, ContentType, FrameWrite, SearchResult, WindowOcrWrite,
+    };
+    use screenpipe_server::ocr_correction::{
+        CorrectionModel, CorrectionPrompt, CorrectionSource, Dictionary, OcrCorrectionConfig,
+        OcrCorrector,
+    };
+    use screenpipe_server::storage_mode::StorageMode;
+    use screenpipe_server::DatabaseManager;
+    use serde::Deserialize;
+    use std::collections::BTreeSet;
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Misreads OCR engines make on degraded screenshots, one sample per kind of
+    /// degradation, with the text that was on screen.
+    #[derive(Deserialize)]
+    struct Sample {
+        name: String,
+        truth: String,
+        ocr: String,
+    }
+
+    fn fixtures() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/ocr_correction")
+    }
+
+    fn dictionary() -> Dictionary {
+        Dictionary::load(&[fixtures().join("words.txt")]).unwrap()
+    }
+
+    fn samples() -> Vec<Sample> {
+        serde_json::from_str(&std::fs::read_to_string(fixtures().join("degraded.json")).unwrap())
+            .unwrap()
+    }
+
+    fn window(
+        text: &str,
+        raw_text: Option<String>,
+        corrected_by: Option<CorrectionSource>,
+    ) -> CaptureWrite {
+        CaptureWrite::Frame(FrameWrite {
+            device_name: "monitor_1".to_string(),
+            video_chunk_id: None,
+            timestamp: None,
+            windows: vec![WindowOcrWrite {
+                text: text.to_string(),
+                text_json: "[]".to_string(),
+                app_name: "app".to_string(),
+                window_name: "window".to_string(),
+                ocr_engine: "Tesseract".to_string(),
+                focused: true,
+                raw_text,
+                corrected_by,
+            }],
+            storage_mode: StorageMode::Full,
+            thumbnail_path: None,
+            window_layout: None,
+        })
+    }
+
+    async fn setup_test_db() -> DatabaseManager {
+        let db = DatabaseManager::new("sqlite::memory:").await.unwrap();
+        db.insert_video_chunk("video.mp4", "monitor_1")
+            .await
+            .unwrap();
+        db
+    }
+
+    async fn write(db: &DatabaseManager, write: CaptureWrite) -> i64 {
+        match db.write_capture(write).await.unwrap() {
+            CaptureOutcome::Written(id) => id,
+            CaptureOutcome::Spilled => panic!("write was spilled"),
+        }
+    }
+
+    async fn search(db: &DatabaseManager, query: &str) -> Vec<SearchResult> {
+        db.search(
+            query,
+            ContentType::OCR,
+            100,
+            0,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .await
+        .unwrap()
+    }
+
+    fn ocr_ids(results: &[SearchResult]) -> BTreeSet<i64> {
+        results
+            .iter()
+            .filter_map(|result| match result {
+                SearchResult::OCR(ocr) => Some(ocr.frame_id),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Share of (word, sample) pairs found by searching the word, over every known word
+    /// of four letters or more on screen, and the frames returned that don't have it.
+    async fn recall(db: &DatabaseManager, frames: &[(i64, &Sample)]) -> (f64, usize) {
+        let dictionary = dictionary();
+        let words_of = |text: &str| -> BTreeSet<String> {
+            text.split(|c: char| !c.is_alphanumeric())
+                .filter(|w| w.chars().count() >= 4 && dictionary.contains(w))
+                .map(str::to_lowercase)
+                .collect()
+        };
+        let queries: BTreeSet<String> = frames
+            .iter()
+            .flat_map(|(_, s)| words_of(&s.truth))
+            .collect();
+
+        let (mut expected, mut found, mut wrong) = (0, 0, 0);
+        for query in &queries {
+            let hits = ocr_ids(&search(db, query).await);
+            for (frame_id, sample) in frames {
+                let on_screen = words_of(&sample.truth).contains(query);
+                match (on_screen, hits.contains(frame_id)) {
+                    (true, true) => {
+                        expected += 1;
+                        found += 1;
+                    }
+                    (true, false) => expected += 1,
+                    (false, true) => wrong += 1,
+                    (false, false) => {}
+                }
+            }
+        }
+        (found as f64 / expected as f64, wrong)
+    }
+
+    #[test]
+    fn test_dictionary_fixes_typical_misreads() {
+        let dictionary = dictionary();
+        assert_eq!(dictionary.suggest("rnove").as_deref(), Some("move"));
+        assert_eq!(dictionary.suggest("Payrnent").as_deref(), Some("Payment"));
+        assert_eq!(dictionary.suggest("ERR0R").as_deref(), Some("ERROR"));
+        assert_eq!(dictionary.suggest("cleleted").as_deref(), Some("deleted"));
+        assert_eq!(dictionary.suggest("custmer").as_deref(), Some("customer"));
+        // Known, too short, or not a word to begin with
+        assert_eq!(dictionary.suggest("move"), None);
+        assert_eq!(dictionary.suggest("rn"), None);
+        assert_eq!(dictionary.suggest("2024"), None);
+        assert_eq!(dictionary.suggest("getUserProfile"), None);
+        // A single edit is not enough to rename a capitalized word
+        assert_eq!(dictionary.suggest("Custmer"), None);
+
+        assert_eq!(
+            dictionary.correct("the ﬁnal recog-\nnition of\nthe rneeting, 5ales: 100"),
+            "the final recognition of\nthe meeting, sales: 100"
+        );
+    }
+
+    #[test]
+    fn test_ambiguous_words_are_left_alone() {
+        let dictionary = Dictionary::parse("cart 10\ncard 10\ncare 100\n# comment\ndon't\n");
+        assert_eq!(dictionary.len(), 3);
+        assert_eq!(dictionary.suggest("carx"), Some("care".to_string()));
+        let dictionary = Dictionary::parse("cart\ncard\n");
+        assert_eq!(dictionary.suggest("carx"), None);
+
+        // Both halves are words, the hyphen stays
+        let dictionary = Dictionary::parse("check\nout\ncheckout\n");
+        assert_eq!(dictionary.correct("check-\nout"), "check-\nout");
+    }
+
+    #[tokio::test]
+    async fn test_recall_on_degraded_fixtures() {
+        let samples = samples();
+        let corrector = OcrCorrector::new(OcrCorrectionConfig::default(), Some(dictionary()));
+
+        let raw_db = setup_test_db().await;
+        let corrected_db = setup_test_db().await;
+        let mut raw_frames = Vec::new();
+        let mut corrected_frames = Vec::new();
+        for sample in &samples {
+            raw_frames.push((
+                write(&raw_db, window(&sample.ocr, None, None)).await,
+                sample,
+            ));
+
+            let correction = corrector.correct(&sample.ocr, "[]").await;
+            let written = match &correction {
+                Some(correction) => window(
+                    &correction.text,
+                    Some(sample.ocr.clone()),
+                    Some(correction.source),
+                ),
+                None => window(&sample.ocr, None, None),
+            };
+            corrected_frames.push((write(&corrected_db, written).await, sample));
+            if sample.ocr == sample.truth {
+                assert_eq!(correction, None, "{} was changed", sample.name);
+            }
+        }
+
+        let (raw_recall, raw_wrong) = recall(&raw_db, &raw_frames).await;
+        let (corrected_recall, corrected_wrong) = recall(&corrected_db, &corrected_frames).await;
+        println!(
+            "recall on {} degraded samples: {:.1}% raw, {:.1}% corrected",
+            samples.len(),
+            raw_recall * 100.0,
+            corrected_recall * 100.0
+        );
+        assert!(raw_recall < 0.75, "raw recall {}", raw_recall);
+        assert!(
+            corrected_recall > 0.95,
+            "corrected recall {}",
+            corrected_recall
+        );
+        assert_eq!(raw_wrong, 0);
+        assert_eq!(corrected_wrong, 0);
+    }
+
+    #[tokio::test]
+    async fn test_search_returns_raw_text_of_corrected_windows() {
+        let db = setup_test_db().await;
+        let frame_id = write(
+            &db,
+            window(
+                "the meeting moved",
+                Some("the rneeting rnoved".to_string()),
+                Some(CorrectionSource::Dictionary),
+            ),
+        )
+        .await;
+        write(&db, window("meeting notes", None, None)).await;
+
+        let results = search(&db, "meeting").await;
+        assert_eq!(results.len(), 2);
+        for result in results {
+            let SearchResult::OCR(ocr) = result else {
+                panic!("expected an ocr result");
+            };
+            if ocr.frame_id == frame_id {
+                assert_eq!(ocr.ocr_text, "the meeting moved");
+                assert_eq!(ocr.raw_text.as_deref(), Some("the rneeting rnoved"));
+                assert_eq!(ocr.corrected_by, Some(CorrectionSource::Dictionary));
+            } else {
+                assert_eq!(ocr.raw_text, None);
+                assert_eq!(ocr.corrected_by, None);
+            }
+        }
+        // Only the corrected text is indexed
+        assert!(search(&db, "rneeting").await.is_empty());
+    }
+
+    fn model(reply: &'static str, calls: Arc<AtomicUsize>) -> CorrectionModel {
+        Arc::new(move |prompt: CorrectionPrompt| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            assert!(prompt.max_tokens >= prompt.text.len() / 3);
+            Box::pin(async move { Ok(reply.to_string()) })
+        })
+    }
+
+    #[tokio::test]
+    async fn test_llm_pass_only_for_low_confidence_windows() {
+        let unsure = r#"[{"text":"x","confidence":"31.5"}]"#;
+        let sure = r#"[{"text":"x","conf":"0.97"}]"#;
+        let calls = Arc::new(AtomicUsize::new(0));
+        let corrector = OcrCorrector::new(
+            OcrCorrectionConfig {
+                llm_calls_per_minute: 600,
+                ..Default::default()
+            },
+            Some(dictionary()),
+        )
+        .with_model(model("Ask Priya to review the handoff.", calls.clone()));
+
+        let corrected = corrector
+            .correct("Ask Priya to rcvicw the handoff.", unsure)
+            .await
+            .unwrap();
+        assert_eq!(corrected.text, "Ask Priya to review the handoff.");
+        assert_eq!(corrected.source, CorrectionSource::Llm);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // Confident, or without any confidence, only the dictionary pass runs
+        assert_eq!(
+            corrector
+                .correct("Ask Priya to rcvicw the handoff.", sure)
+                .await,
+            None
+        );
+        assert_eq!(
+            corrector
+                .correct("Ask Priya to rcvicw the handoff.", "[]")
+                .await,
+            None
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_llm_rewrites_and_calls_over_budget_are_dropped() {
+        let unsure = r#"[{"text":"x","confidence":"20"}]"#;
+        let calls = Arc::new(AtomicUsize::new(0));
+        let corrector = OcrCorrector::new(
+            OcrCorrectionConfig {
+                llm_calls_per_minute: 600,
+                ..Default::default()
+            },
+            None,
+        )
+        .with_model(model(
+            "Sure! Here is a summary: the user is reviewing a design.",
+            calls.clone(),
+        ));
+        assert_eq!(
+            corrector
+                .correct("Ask Priya to rcvicw the handoff.", unsure)
+                .await,
+            None
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let corrector = OcrCorrector::new(
+            OcrCorrectionConfig {
+                llm_calls_per_minute: 1,
+                ..Default::default()
+            },
+            None,
+        )
+        .with_model(model("Ask Priya to review the handoff.", calls.clone()));
+        assert!(corrector
+            .correct("Ask Priya to rcvicw the handoff.", unsure)
+            .await
+            .is_some());
+        assert_eq!(
+            corrector
+                .correct("Ask Priya to rcvicw the handoff.", unsure)
+                .await,
+            None
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
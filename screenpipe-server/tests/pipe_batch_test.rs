@@ -0,0 +1,311 @@
+#[cfg(test)]
+mod tests {
+    use screenpipe_server::pipe_batch::{
+        plan_manifest, run_batch, ManifestPipe, OperationStatus, PipeManifest, PipeOperation,
+    };
+    use screenpipe_server::pipe_manager::{merge_patch, PipeInfo};
+    use screenpipe_server::problem::ErrorCode;
+    use screenpipe_server::PipeManager;
+    use serde_json::{json, Value};
+    use std::path::Path;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tempfile::{tempdir, TempDir};
+
+    /// A pipe source folder, installed by copying it
+    fn source(root: &TempDir, name: &str, marker: &str) -> String {
+        let dir = root.path().join("sources").join(name);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("pipe.ts"), marker).unwrap();
+        std::fs::write(
+            dir.join("pipe.json"),
+            format!(r#"{{"name": "{}", "interval": 60}}"#, name),
+        )
+        .unwrap();
+        dir.to_string_lossy().into_owned()
+    }
+
+    fn config(manager_dir: &Path, id: &str) -> Value {
+        let path = manager_dir.join("pipes").join(id).join("pipe.json");
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    fn pipe_info(id: &str, source: &str, enabled: bool, config: Value) -> PipeInfo {
+        PipeInfo {
+            id: id.to_string(),
+            enabled,
+            config,
+            source: source.to_string(),
+            port: None,
+        }
+    }
+
+    fn manifest_pipe(source: &str, enabled: bool, config: Value) -> ManifestPipe {
+        ManifestPipe {
+            source: source.to_string(),
+            enabled,
+            config: config.as_object().cloned().unwrap_or_default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_batch_runs_in_order_and_reports_each_operation() {
+        let root = tempdir().unwrap();
+        let manager = PipeManager::new(root.path().to_path_buf());
+        let notes = source(&root, "notes", "v1");
+
+        let report = run_batch(
+            &manager,
+            vec![
+                PipeOperation::Install { url: notes.clone() },
+                PipeOperation::SetConfig {
+                    pipe_id: "notes".to_string(),
+                    patch: json!({ "interval": 30, "llm": { "model": "llama3" } }),
+                },
+                PipeOperation::Stop {
+                    pipe_id: "missing".to_string(),
+                },
+                PipeOperation::Update {
+                    pipe_id: "notes".to_string(),
+                    config: json!({ "title": "notes" }),
+                },
+            ],
+            false,
+        )
+        .await;
+
+        assert!(!report.success);
+        assert!(!report.rolled_back);
+        let statuses: Vec<OperationStatus> = report.results.iter().map(|r| r.status).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                OperationStatus::Ok,
+                OperationStatus::Ok,
+                OperationStatus::Failed,
+                OperationStatus::Ok
+            ]
+        );
+        assert_eq!(report.results[0].pipe_id.as_deref(), Some("notes"));
+        assert_eq!(report.results[2].code, Some(ErrorCode::PipeNotFound));
+
+        let config = config(root.path(), "notes");
+        assert_eq!(config["interval"], 30);
+        assert_eq!(config["llm"]["model"], "llama3");
+        assert_eq!(config["title"], "notes");
+        assert_eq!(config["source"], notes.as_str());
+    }
+
+    #[tokio::test]
+    async fn test_atomic_batch_rolls_back_installs() {
+        let root = tempdir().unwrap();
+        let manager = PipeManager::new(root.path().to_path_buf());
+        let notes_v1 = source(&root, "notes", "v1");
+        let todo = source(&root, "todo", "v1");
+        assert!(
+            run_batch(
+                &manager,
+                vec![PipeOperation::Install { url: notes_v1 }],
+                true
+            )
+            .await
+            .success
+        );
+        manager
+            .update_config("notes", json!({ "interval": 5 }))
+            .await
+            .unwrap();
+
+        // The new version of notes comes from another folder with the same name
+        let root_v2 = tempdir().unwrap();
+        let notes_v2 = source(&root_v2, "notes", "v2");
+        let report = run_batch(
+            &manager,
+            vec![
+                PipeOperation::Install { url: notes_v2 },
+                PipeOperation::Install { url: todo },
+                PipeOperation::SetConfig {
+                    pipe_id: "notes".to_string(),
+                    patch: json!({ "enabled": true }),
+                },
+                PipeOperation::Start {
+                    pipe_id: "todo".to_string(),
+                },
+            ],
+            true,
+        )
+        .await;
+
+        assert!(!report.success);
+        assert!(report.rolled_back);
+        let statuses: Vec<OperationStatus> = report.results.iter().map(|r| r.status).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                OperationStatus::RolledBack,
+                OperationStatus::RolledBack,
+                OperationStatus::Failed,
+                OperationStatus::Skipped
+            ]
+        );
+        assert_eq!(report.results[2].code, Some(ErrorCode::InvalidRequest));
+
+        // notes is back to v1 with its config, todo is gone, no backup is left over
+        let pipes = root.path().join("pipes");
+        assert_eq!(
+            std::fs::read_to_string(pipes.join("notes").join("pipe.ts")).unwrap(),
+            "v1"
+        );
+        assert_eq!(config(root.path(), "notes")["interval"], 5);
+        assert!(!pipes.join("todo").exists());
+        let ids: Vec<String> = manager
+            .list_pipes()
+            .await
+            .into_iter()
+            .map(|p| p.id)
+            .collect();
+        assert_eq!(ids, vec!["notes"]);
+        assert_eq!(std::fs::read_dir(&pipes).unwrap().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_failed_install_keeps_the_installed_copy() {
+        let root = tempdir().unwrap();
+        let manager = PipeManager::new(root.path().to_path_buf());
+        let notes = source(&root, "notes", "v1");
+        manager.download_pipe(&notes).await.unwrap();
+
+        let gone = root.path().join("elsewhere").join("notes");
+        let report = run_batch(
+            &manager,
+            vec![PipeOperation::Install {
+                url: gone.to_string_lossy().into_owned(),
+            }],
+            false,
+        )
+        .await;
+        assert_eq!(report.results[0].status, OperationStatus::Failed);
+        assert_eq!(report.results[0].code, Some(ErrorCode::PipeError));
+        let pipe_ts = root.path().join("pipes").join("notes").join("pipe.ts");
+        assert_eq!(std::fs::read_to_string(pipe_ts).unwrap(), "v1");
+    }
+
+    #[tokio::test]
+    async fn test_batch_holds_the_pipe_locks() {
+        let root = tempdir().unwrap();
+        let manager = Arc::new(PipeManager::new(root.path().to_path_buf()));
+        let notes = source(&root, "notes", "v1");
+        manager.download_pipe(&notes).await.unwrap();
+
+        let guards = manager.lock_pipes(&["notes".to_string()]).await;
+        let ui = tokio::spawn({
+            let manager = manager.clone();
+            async move { manager.update_config("notes", json!({ "x": 1 })).await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!ui.is_finished());
+        assert!(config(root.path(), "notes").get("x").is_none());
+
+        drop(guards);
+        ui.await.unwrap().unwrap();
+        assert_eq!(config(root.path(), "notes")["x"], 1);
+    }
+
+    #[test]
+    fn test_merge_patch() {
+        let mut config = json!({ "a": 1, "llm": { "model": "x", "temp": 0.5 }, "tags": [1] });
+        merge_patch(
+            &mut config,
+            &json!({ "a": null, "llm": { "temp": 0.1 }, "tags": [2], "b": { "c": null } }),
+        );
+        assert_eq!(
+            config,
+            json!({ "llm": { "model": "x", "temp": 0.1 }, "tags": [2], "b": {} })
+        );
+    }
+
+    #[test]
+    fn test_manifest_plans_the_minimal_batch() {
+        let installed = vec![
+            pipe_info(
+                "notes",
+                "https://github.com/a/b/tree/main/pipes/notes",
+                true,
+                json!({ "interval": 60, "llm": { "model": "x" } }),
+            ),
+            pipe_info("todo", "/old/todo", true, json!({ "interval": 60 })),
+            pipe_info("unlisted", "/x/unlisted", true, Value::Null),
+        ];
+        let manifest = PipeManifest {
+            pipes: vec![
+                // Matches, nothing to do
+                manifest_pipe(
+                    "https://github.com/a/b/tree/main/pipes/notes/",
+                    true,
+                    json!({ "llm": { "model": "x" } }),
+                ),
+                // New source, disabled with a config change
+                manifest_pipe("/new/todo", false, json!({ "interval": 30 })),
+                // Not installed yet
+                manifest_pipe("/new/digest", true, json!({ "to": "me", "cc": null })),
+            ],
+        };
+
+        let operations = plan_manifest(&manifest, &installed).unwrap();
+        assert_eq!(
+            operations,
+            vec![
+                PipeOperation::Install {
+                    url: "/new/todo".to_string()
+                },
+                PipeOperation::Disable {
+                    pipe_id: "todo".to_string()
+                },
+                PipeOperation::SetConfig {
+                    pipe_id: "todo".to_string(),
+                    patch: json!({ "interval": 30 }),
+                },
+                PipeOperation::Install {
+                    url: "/new/digest".to_string()
+                },
+                PipeOperation::SetConfig {
+                    pipe_id: "digest".to_string(),
+                    patch: json!({ "to": "me" }),
+                },
+                PipeOperation::Enable {
+                    pipe_id: "digest".to_string()
+                },
+            ]
+        );
+
+        // Once applied there is nothing left to do
+        let applied = vec![
+            installed[0].clone(),
+            pipe_info("todo", "/new/todo", false, json!({ "interval": 30 })),
+            pipe_info("digest", "/new/digest", true, json!({ "to": "me" })),
+        ];
+        assert!(plan_manifest(&manifest, &applied).unwrap().is_empty());
+
+        let mut twice = manifest.clone();
+        twice
+            .pipes
+            .push(manifest_pipe("/other/todo", true, json!({})));
+        assert!(plan_manifest(&twice, &installed).is_err());
+        let reserved = PipeManifest {
+            pipes: vec![manifest_pipe(
+                "/new/todo",
+                true,
+                json!({ "enabled": false }),
+            )],
+        };
+        assert!(plan_manifest(&reserved, &installed).is_err());
+
+        let yaml = "pipes:\n  - source: /new/digest\n    config:\n      to: me\n";
+        let parsed: PipeManifest = serde_yaml::from_str(yaml).unwrap();
+        assert!(parsed.pipes[0].enabled);
+        assert_eq!(
+            Value::Object(parsed.pipes[0].config.clone()),
+            json!({ "to": "me" })
+        );
+    }
+}
@@ -0,0 +1,336 @@
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+    use screenpipe_server::{
+        db_types::{ContentType, SearchFilters, SearchResult},
+        pipe_content::{
+            self, ContentSchema, FieldType, FilterOp, NewPipeContent, PipeContentError,
+            SchemaMigration,
+        },
+        DatabaseManager,
+    };
+    use serde_json::{json, Map, Value};
+
+    fn invoice_schema() -> ContentSchema {
+        ContentSchema::from_json_schema(&json!({
+            "type": "object",
+            "properties": {
+                "vendor": { "type": "string", "indexable": true, "facetable": true },
+                "amount": { "type": "number", "facetable": true },
+                "due": { "type": "string", "format": "date", "facetable": true },
+                "paid": { "type": "boolean", "facetable": true },
+                "notes": { "type": "string" }
+            },
+            "required": ["vendor", "amount"]
+        }))
+        .unwrap()
+    }
+
+    fn fields(value: Value) -> Map<String, Value> {
+        value.as_object().unwrap().clone()
+    }
+
+    fn invoice(vendor: &str, amount: f64, text: &str) -> NewPipeContent {
+        NewPipeContent {
+            content_type: "invoice".to_string(),
+            pipe_id: "invoices".to_string(),
+            timestamp: Some(Utc::now() - Duration::minutes(amount as i64)),
+            text: text.to_string(),
+            fields: fields(json!({ "vendor": vendor, "amount": amount, "paid": false })),
+            context: false,
+        }
+    }
+
+    async fn setup() -> DatabaseManager {
+        let db = DatabaseManager::new("sqlite::memory:").await.unwrap();
+        pipe_content::register_content_type(&db, "invoices", "invoice", invoice_schema(), None)
+            .await
+            .unwrap();
+        db
+    }
+
+    async fn search(db: &DatabaseManager, query: &str, filters: &SearchFilters) -> Vec<i64> {
+        db.search_with_filters(
+            query,
+            ContentType::All,
+            100,
+            0,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            filters,
+        )
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|result| match result {
+            SearchResult::Pipe(record) => record.id,
+            other => panic!("unexpected result {:?}", other),
+        })
+        .collect()
+    }
+
+    #[test]
+    fn test_schema_round_trip_and_rejects() {
+        let schema = invoice_schema();
+        assert_eq!(schema.fields["due"].field_type, FieldType::Date);
+        assert!(schema.fields["vendor"].required);
+        assert!(!schema.fields["notes"].facetable);
+        assert_eq!(
+            ContentSchema::from_json_schema(&schema.to_json_schema()).unwrap(),
+            schema
+        );
+
+        for bad in [
+            json!({ "type": "array" }),
+            json!({ "properties": { "tags": { "type": "array" } } }),
+            json!({ "properties": { "amount": { "type": "number", "indexable": true } } }),
+            json!({ "properties": { "bad name": { "type": "string" } } }),
+            json!({ "properties": {}, "required": ["vendor"] }),
+        ] {
+            assert!(ContentSchema::from_json_schema(&bad).is_err(), "{}", bad);
+        }
+
+        assert!(pipe_content::validate_content_type_name("invoice").is_ok());
+        assert!(pipe_content::validate_content_type_name("ocr").is_err());
+        assert!(pipe_content::validate_content_type_name("Invoice").is_err());
+    }
+
+    #[test]
+    fn test_validate_checks_types_and_normalizes_dates() {
+        let schema = invoice_schema();
+
+        let stored = schema
+            .validate(&fields(json!({
+                "vendor": "Acme",
+                "amount": 12.5,
+                "due": "2024-12-01",
+                "notes": null
+            })))
+            .unwrap();
+        assert_eq!(stored.get("due"), Some(&json!("2024-12-01")));
+        assert!(!stored.contains_key("notes"));
+
+        assert!(schema
+            .validate(&fields(json!({ "vendor": "Acme" })))
+            .unwrap_err()
+            .contains("missing required field 'amount'"));
+        assert!(schema
+            .validate(&fields(json!({ "vendor": "Acme", "amount": "12" })))
+            .unwrap_err()
+            .contains("must be a number"));
+        assert!(schema
+            .validate(&fields(
+                json!({ "vendor": "Acme", "amount": 1, "total": 1 })
+            ))
+            .unwrap_err()
+            .contains("unknown field 'total'"));
+        assert!(schema
+            .validate(&fields(
+                json!({ "vendor": "Acme", "amount": 1, "due": "tomorrow" })
+            ))
+            .is_err());
+
+        let at = FieldType::DateTime
+            .check(&json!("2024-12-01T10:00:00+02:00"))
+            .unwrap();
+        assert_eq!(at, json!("2024-12-01T08:00:00.000Z"));
+    }
+
+    #[test]
+    fn test_field_filters_follow_the_schema() {
+        let schema = invoice_schema();
+
+        let vendor = schema.field_filter("vendor", "acme").unwrap();
+        assert_eq!(vendor.path, "$.vendor");
+        assert_eq!(vendor.op, FilterOp::EqText);
+
+        let amount = schema.field_filter("amount.gte", "100").unwrap();
+        assert_eq!(amount.op, FilterOp::Gte);
+        assert_eq!(amount.value, json!(100.0));
+
+        let paid = schema.field_filter("paid", "true").unwrap();
+        assert_eq!(paid.value, json!(true));
+
+        assert!(schema
+            .field_filter("notes", "x")
+            .unwrap_err()
+            .contains("not facetable"));
+        assert!(schema.field_filter("vendor.gte", "a").is_err());
+        assert!(schema.field_filter("amount", "lots").is_err());
+        assert!(schema.field_filter("missing", "x").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_records_are_searchable_by_text_and_fields() {
+        let db = setup().await;
+        let acme = pipe_content::insert_content(&db, &invoice("Acme", 120.0, "hosting"))
+            .await
+            .unwrap();
+        let globex = pipe_content::insert_content(&db, &invoice("Globex", 80.0, "paper"))
+            .await
+            .unwrap();
+
+        // Indexable fields are in the full text index, not just the text
+        let filters = SearchFilters {
+            content_types: Some(vec![ContentType::Pipe]),
+            ..Default::default()
+        };
+        assert_eq!(search(&db, "acme", &filters).await, vec![acme]);
+        assert_eq!(search(&db, "paper", &filters).await, vec![globex]);
+
+        let schema = invoice_schema();
+        let filters = SearchFilters {
+            pipe_type: Some("invoice".to_string()),
+            fields: vec![schema.field_filter("vendor", "ACME").unwrap()],
+            ..Default::default()
+        };
+        assert_eq!(search(&db, "", &filters).await, vec![acme]);
+
+        let filters = SearchFilters {
+            pipe_type: Some("invoice".to_string()),
+            fields: vec![
+                schema.field_filter("amount.lte", "100").unwrap(),
+                schema.field_filter("paid", "false").unwrap(),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(search(&db, "", &filters).await, vec![globex]);
+        let count = db
+            .count_search_results_with_filters(
+                "",
+                ContentType::All,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                &filters,
+            )
+            .await
+            .unwrap();
+        assert_eq!(count, 1);
+
+        // A field the record doesn't have never matches
+        let filters = SearchFilters {
+            pipe_type: Some("invoice".to_string()),
+            fields: vec![schema.field_filter("due.gte", "2024-01-01").unwrap()],
+            ..Default::default()
+        };
+        assert!(search(&db, "", &filters).await.is_empty());
+
+        let mut bad = invoice("Acme", 1.0, "");
+        bad.fields.insert("amount".to_string(), json!("one"));
+        assert!(matches!(
+            pipe_content::insert_content(&db, &bad).await,
+            Err(PipeContentError::Invalid(_))
+        ));
+        let mut other_pipe = invoice("Acme", 1.0, "");
+        other_pipe.pipe_id = "other".to_string();
+        assert!(matches!(
+            pipe_content::insert_content(&db, &other_pipe).await,
+            Err(PipeContentError::Conflict(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_schema_changes_need_migration_intent() {
+        let db = setup().await;
+        let id = pipe_content::insert_content(&db, &invoice("Acme", 120.0, "hosting"))
+            .await
+            .unwrap();
+
+        // Same schema again is a no-op, another pipe can't take the name
+        let same =
+            pipe_content::register_content_type(&db, "invoices", "invoice", invoice_schema(), None)
+                .await
+                .unwrap();
+        assert_eq!(same.content_type.version, 1);
+        assert!(matches!(
+            pipe_content::register_content_type(&db, "other", "invoice", invoice_schema(), None)
+                .await,
+            Err(PipeContentError::Conflict(_))
+        ));
+
+        // Adding a field is fine
+        let mut added = invoice_schema();
+        added
+            .fields
+            .insert("currency".to_string(), added.fields["notes"].clone());
+        let registration =
+            pipe_content::register_content_type(&db, "invoices", "invoice", added.clone(), None)
+                .await
+                .unwrap();
+        assert_eq!(registration.content_type.version, 2);
+        assert_eq!(registration.changes.added, vec!["currency"]);
+
+        // Retyping amount and dropping vendor needs both listed
+        let mut breaking = added.clone();
+        breaking.fields.remove("vendor");
+        breaking.fields.get_mut("amount").unwrap().field_type = FieldType::String;
+        let err = pipe_content::register_content_type(
+            &db,
+            "invoices",
+            "invoice",
+            breaking.clone(),
+            Some(SchemaMigration {
+                remove_fields: vec!["vendor".to_string()],
+                retype_fields: vec![],
+            }),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(err, PipeContentError::Conflict(_)));
+        assert!(err.to_string().contains("'amount'"));
+
+        let registration = pipe_content::register_content_type(
+            &db,
+            "invoices",
+            "invoice",
+            breaking,
+            Some(SchemaMigration {
+                remove_fields: vec!["vendor".to_string()],
+                retype_fields: vec!["amount".to_string()],
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(registration.content_type.version, 3);
+        assert_eq!(registration.migrated, 1);
+
+        let records = db.get_pipe_content_records("invoice").await.unwrap();
+        let stored: Value = serde_json::from_str(&records[0].2).unwrap();
+        assert_eq!(records[0].0, id);
+        assert_eq!(stored, json!({ "amount": "120.0", "paid": false }));
+
+        // vendor is gone from the index with the field
+        let filters = SearchFilters {
+            content_types: Some(vec![ContentType::Pipe]),
+            ..Default::default()
+        };
+        assert!(search(&db, "acme", &filters).await.is_empty());
+        assert_eq!(search(&db, "hosting", &filters).await, vec![id]);
+    }
+
+    #[tokio::test]
+    async fn test_delete_pipe_content_removes_types_and_records() {
+        let db = setup().await;
+        pipe_content::insert_content(&db, &invoice("Acme", 120.0, "hosting"))
+            .await
+            .unwrap();
+
+        assert_eq!(db.delete_pipe_content("invoices").await.unwrap(), (1, 1));
+        assert!(pipe_content::list_content_types(&db)
+            .await
+            .unwrap()
+            .is_empty());
+        let filters = SearchFilters::default();
+        assert!(search(&db, "hosting", &filters).await.is_empty());
+    }
+}
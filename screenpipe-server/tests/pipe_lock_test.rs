@@ -0,0 +1,283 @@
+#[cfg(test)]
+mod tests {
+    use screenpipe_server::pipe_batch::OperationStatus;
+    use screenpipe_server::pipe_lock::{
+        config_hash, generate_lock, pipe_checksum, plan_sync, sync_pipes, InstalledPipe,
+        LockedPipe, PipeLock, SyncAction, SyncStep, LOCK_VERSION,
+    };
+    use screenpipe_server::PipeManager;
+    use serde_json::{json, Value};
+    use std::fs;
+    use std::path::Path;
+    use tempfile::{tempdir, TempDir};
+
+    /// A pipe source folder, installed by copying it
+    fn source(root: &TempDir, name: &str, marker: &str) -> String {
+        let dir = root.path().join("sources").join(name);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("pipe.ts"), marker).unwrap();
+        fs::write(
+            dir.join("pipe.json"),
+            format!(r#"{{"name": "{}", "interval": 60}}"#, name),
+        )
+        .unwrap();
+        dir.to_string_lossy().into_owned()
+    }
+
+    fn config(manager_dir: &Path, id: &str) -> Value {
+        let path = manager_dir.join("pipes").join(id).join("pipe.json");
+        serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    fn installed(id: &str, recorded: Option<&str>, checksum: &str) -> InstalledPipe {
+        InstalledPipe {
+            id: id.to_string(),
+            source: format!("/pipes/{}", id),
+            resolved: None,
+            recorded_checksum: recorded.map(String::from),
+            checksum: checksum.to_string(),
+            config_hash: "config".to_string(),
+        }
+    }
+
+    fn locked(id: &str, checksum: &str) -> LockedPipe {
+        LockedPipe {
+            id: id.to_string(),
+            source: format!("/pipes/{}", id),
+            resolved: None,
+            checksum: checksum.to_string(),
+            config_hash: "config".to_string(),
+        }
+    }
+
+    fn step(pipe_id: &str, action: SyncAction) -> SyncStep {
+        SyncStep {
+            pipe_id: pipe_id.to_string(),
+            action,
+            config_differs: false,
+        }
+    }
+
+    #[test]
+    fn test_checksum_covers_the_code_not_the_config() {
+        let dir = tempdir().unwrap();
+        let pipe = dir.path();
+        fs::create_dir_all(pipe.join("lib")).unwrap();
+        fs::create_dir_all(pipe.join("node_modules/left-pad")).unwrap();
+        fs::write(pipe.join("pipe.ts"), "export default 1").unwrap();
+        fs::write(pipe.join("lib/util.ts"), "export const a = 1").unwrap();
+        fs::write(pipe.join("pipe.json"), r#"{"interval": 60}"#).unwrap();
+        let checksum = pipe_checksum(pipe).unwrap();
+        assert_eq!(checksum.len(), 64);
+
+        // Config, dependencies and hidden files don't count
+        fs::write(pipe.join("pipe.json"), r#"{"interval": 30}"#).unwrap();
+        fs::write(pipe.join("node_modules/left-pad/index.js"), "1").unwrap();
+        fs::write(pipe.join(".env"), "KEY=1").unwrap();
+        assert_eq!(pipe_checksum(pipe).unwrap(), checksum);
+
+        fs::write(pipe.join("lib/util.ts"), "export const a = 2").unwrap();
+        let edited = pipe_checksum(pipe).unwrap();
+        assert_ne!(edited, checksum);
+        fs::rename(pipe.join("lib/util.ts"), pipe.join("lib/helpers.ts")).unwrap();
+        assert_ne!(pipe_checksum(pipe).unwrap(), edited);
+
+        // What sync records and key order don't change the config hash
+        assert_eq!(
+            config_hash(&json!({ "interval": 60, "llm": { "model": "a", "url": "b" } })),
+            config_hash(&json!({
+                "llm": { "url": "b", "model": "a" },
+                "source": "/pipes/notes",
+                "checksum": checksum,
+                "interval": 60,
+            }))
+        );
+        assert_ne!(
+            config_hash(&json!({ "interval": 60 })),
+            config_hash(&json!({ "interval": 30 }))
+        );
+    }
+
+    #[test]
+    fn test_plan_follows_the_lock_and_protects_edits() {
+        let lock = PipeLock {
+            version: LOCK_VERSION,
+            pipes: vec![
+                locked("missing", "a"),
+                locked("same", "b"),
+                locked("behind", "c"),
+                locked("edited", "d"),
+            ],
+        };
+        let mut same = installed("same", None, "b");
+        same.config_hash = "changed".to_string();
+        let pipes = vec![
+            same,
+            installed("behind", Some("old"), "old"),
+            installed("edited", Some("old"), "mine"),
+            installed("extra", Some("e"), "e"),
+            installed("downloaded", None, "f"),
+        ];
+
+        let steps = plan_sync(&lock, &pipes, false, false).unwrap();
+        assert_eq!(
+            steps,
+            vec![
+                step("missing", SyncAction::Install),
+                SyncStep {
+                    config_differs: true,
+                    ..step("same", SyncAction::Unchanged)
+                },
+                step("behind", SyncAction::Update),
+                step("edited", SyncAction::Conflict),
+                step("extra", SyncAction::Unlisted),
+                step("downloaded", SyncAction::Unlisted),
+            ]
+        );
+
+        // Pipes without a record may have been edited, pruning them needs force too
+        let actions = |prune, force| -> Vec<SyncAction> {
+            plan_sync(&lock, &pipes, prune, force)
+                .unwrap()
+                .into_iter()
+                .skip(3)
+                .map(|step| step.action)
+                .collect()
+        };
+        assert_eq!(
+            actions(true, false),
+            vec![
+                SyncAction::Conflict,
+                SyncAction::Remove,
+                SyncAction::Conflict
+            ]
+        );
+        assert_eq!(
+            actions(true, true),
+            vec![SyncAction::Update, SyncAction::Remove, SyncAction::Remove]
+        );
+
+        let invalid = |lock: PipeLock| plan_sync(&lock, &pipes, false, false).unwrap_err();
+        assert!(invalid(PipeLock {
+            version: 2,
+            pipes: vec![]
+        })
+        .contains("unsupported lock version 2"));
+        assert!(invalid(PipeLock {
+            version: LOCK_VERSION,
+            pipes: vec![locked("same", "b"), locked("same", "b")],
+        })
+        .contains("locked twice"));
+        assert!(invalid(PipeLock {
+            version: LOCK_VERSION,
+            pipes: vec![LockedPipe {
+                id: "notes".to_string(),
+                ..locked("other", "a")
+            }],
+        })
+        .contains("installs as another pipe"));
+    }
+
+    #[tokio::test]
+    async fn test_a_lock_reproduces_the_pipes_on_another_machine() {
+        let root = tempdir().unwrap();
+        let notes = source(&root, "notes", "v1");
+        let digest = source(&root, "digest", "v1");
+        let old = source(&root, "old", "v1");
+
+        let laptop = tempdir().unwrap();
+        let laptop_pipes = PipeManager::new(laptop.path().to_path_buf());
+        laptop_pipes.download_pipe(&notes).await.unwrap();
+        laptop_pipes.download_pipe(&digest).await.unwrap();
+        let lock = generate_lock(&laptop_pipes).await.unwrap();
+        assert_eq!(lock.version, LOCK_VERSION);
+        assert_eq!(
+            lock.pipes.iter().map(|p| p.id.as_str()).collect::<Vec<_>>(),
+            vec!["digest", "notes"]
+        );
+        assert_eq!(lock.pipes[1].source, notes);
+        assert_eq!(lock.pipes[1].resolved, None);
+
+        let desktop = tempdir().unwrap();
+        let desktop_pipes = PipeManager::new(desktop.path().to_path_buf());
+        desktop_pipes.download_pipe(&old).await.unwrap();
+        let report = sync_pipes(&desktop_pipes, &lock, false, false)
+            .await
+            .unwrap();
+        assert!(report.success, "{:?}", report);
+        let actions: Vec<(&str, SyncAction, OperationStatus)> = report
+            .results
+            .iter()
+            .map(|r| (r.pipe_id.as_str(), r.action, r.status))
+            .collect();
+        assert_eq!(
+            actions,
+            vec![
+                ("digest", SyncAction::Install, OperationStatus::Ok),
+                ("notes", SyncAction::Install, OperationStatus::Ok),
+                ("old", SyncAction::Unlisted, OperationStatus::Ok),
+            ]
+        );
+        assert_eq!(
+            config(desktop.path(), "notes")["checksum"],
+            json!(lock.pipes[1].checksum)
+        );
+        assert_eq!(generate_lock(&desktop_pipes).await.unwrap().pipes.len(), 3);
+
+        // A local edit is never overwritten without force
+        let edited = desktop.path().join("pipes/notes/pipe.ts");
+        fs::write(&edited, "my fix").unwrap();
+        let report = sync_pipes(&desktop_pipes, &lock, true, false)
+            .await
+            .unwrap();
+        assert!(!report.success);
+        assert_eq!(report.results[1].action, SyncAction::Conflict);
+        assert_eq!(report.results[1].status, OperationStatus::Skipped);
+        // Downloaded without sync, so it may have been edited too
+        assert_eq!(report.results[2].action, SyncAction::Conflict);
+        assert_eq!(fs::read_to_string(&edited).unwrap(), "my fix");
+        assert!(desktop.path().join("pipes/old").exists());
+
+        let report = sync_pipes(&desktop_pipes, &lock, true, true).await.unwrap();
+        assert!(report.success, "{:?}", report);
+        assert_eq!(report.results[1].action, SyncAction::Update);
+        assert_eq!(report.results[2].action, SyncAction::Remove);
+        assert_eq!(fs::read_to_string(&edited).unwrap(), "v1");
+        assert!(!desktop.path().join("pipes/old").exists());
+        // The config the pipe had is kept
+        assert_eq!(config(desktop.path(), "notes")["interval"], json!(60));
+    }
+
+    #[tokio::test]
+    async fn test_a_checksum_mismatch_keeps_the_installed_pipe() {
+        let root = tempdir().unwrap();
+        let notes = source(&root, "notes", "v1");
+        let dir = tempdir().unwrap();
+        let manager = PipeManager::new(dir.path().to_path_buf());
+        manager.download_pipe(&notes).await.unwrap();
+        let lock = generate_lock(&manager).await.unwrap();
+        sync_pipes(&manager, &lock, false, false).await.unwrap();
+
+        // The source changed since the lock was written
+        fs::write(Path::new(&notes).join("pipe.ts"), "v2").unwrap();
+        let mut stale = lock.clone();
+        stale.pipes[0].checksum = "0".repeat(64);
+        let report = sync_pipes(&manager, &stale, false, false).await.unwrap();
+        assert!(!report.success);
+        assert_eq!(report.results[0].action, SyncAction::Update);
+        assert_eq!(report.results[0].status, OperationStatus::Failed);
+        assert!(report.results[0]
+            .detail
+            .as_ref()
+            .unwrap()
+            .starts_with("checksum mismatch"));
+        assert_eq!(
+            fs::read_to_string(dir.path().join("pipes/notes/pipe.ts")).unwrap(),
+            "v1"
+        );
+        assert_eq!(
+            config(dir.path(), "notes")["checksum"],
+            json!(lock.pipes[0].checksum)
+        );
+    }
+}
@@ -0,0 +1,256 @@
+#[cfg(test)]
+mod tests {
+    use screenpipe_core::pipe_manifest::{
+        manifest_schema, validate_manifest, validate_manifest_str, ManifestIssue, Severity,
+        MANIFEST_SCHEMA_VERSION,
+    };
+    use screenpipe_server::pipe_manager::PipeError;
+    use screenpipe_server::problem::{ApiError, ErrorCode};
+    use screenpipe_server::PipeManager;
+    use serde_json::{json, Value};
+    use std::path::{Path, PathBuf};
+    use tempfile::tempdir;
+
+    /// The manifests of the pipes in this repo and the ones in tests/pipe_manifests,
+    /// written the way screenpipe leaves them once installed, synced or configured.
+    fn corpus() -> Vec<PathBuf> {
+        fn walk(dir: &Path, manifests: &mut Vec<PathBuf>) {
+            for entry in std::fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                let name = path.file_name().unwrap().to_string_lossy();
+                if path.is_dir() && name != "node_modules" {
+                    walk(&path, manifests);
+                } else if name == "pipe.json" {
+                    manifests.push(path);
+                }
+            }
+        }
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let mut manifests = Vec::new();
+        walk(&root.join("../pipes"), &mut manifests);
+        manifests.extend(
+            std::fs::read_dir(root.join("tests/pipe_manifests"))
+                .unwrap()
+                .map(|entry| entry.unwrap().path()),
+        );
+        manifests.sort();
+        manifests
+    }
+
+    fn messages(issues: &[ManifestIssue]) -> Vec<String> {
+        issues.iter().map(ToString::to_string).collect()
+    }
+
+    fn write_manifest(dir: &Path, id: &str, manifest: &Value) {
+        let pipe_dir = dir.join("pipes").join(id);
+        std::fs::create_dir_all(&pipe_dir).unwrap();
+        std::fs::write(pipe_dir.join("pipe.json"), manifest.to_string()).unwrap();
+    }
+
+    #[test]
+    fn test_real_world_manifests_keep_validating() {
+        let manifests = corpus();
+        assert!(
+            manifests.len() >= 15,
+            "corpus went missing: {:?}",
+            manifests
+        );
+        for path in manifests {
+            let issues = validate_manifest_str(&std::fs::read_to_string(&path).unwrap(), true);
+            assert!(
+                issues.is_empty(),
+                "{}: {:?}",
+                path.display(),
+                messages(&issues)
+            );
+        }
+
+        let id = manifest_schema()["$id"].as_str().unwrap();
+        assert!(id.ends_with(&format!("/v{}.json", MANIFEST_SCHEMA_VERSION)));
+    }
+
+    #[test]
+    fn test_errors_cite_the_path_and_expected_type() {
+        let issues = validate_manifest(
+            &json!({
+                "is_nextjs": true,
+                "port": "3000",
+                "crons": [
+                    { "path": "/api/log", "schedule": 5 },
+                    { "path": "/api/digest" },
+                    { "path": "", "schedule": "*/5 * * * *" }
+                ],
+                "events": ["session_ended", "sessio_ended"],
+                "hosts": "api.openai.com",
+                "fields": [{ "name": "interval", "optional": "yes" }]
+            }),
+            false,
+        );
+        assert!(issues.iter().all(|issue| issue.severity == Severity::Error));
+        // Keys in alphabetical order
+        let messages = messages(&issues);
+        assert_eq!(
+            messages[0],
+            "$.crons[0].schedule: expected string, found integer"
+        );
+        assert_eq!(
+            messages[1],
+            "$.crons[1]: missing required key 'schedule', expected string"
+        );
+        assert_eq!(messages[2], "$.crons[2].path: expected a non-empty string");
+        // Five fields as for vercel, the scheduler wants seconds too
+        assert!(messages[3].starts_with("$.crons[2].schedule: expected a cron expression"));
+        assert!(messages[4].starts_with("$.events[1]: expected one of \"session_started\""));
+        assert_eq!(
+            messages[5],
+            "$.fields[0].optional: expected boolean, found string"
+        );
+        assert_eq!(messages[6], "$.hosts: expected array, found string");
+        assert_eq!(messages[7], "$.port: expected integer, found string");
+        assert_eq!(messages.len(), 8);
+
+        assert_eq!(
+            messages_of(json!({ "port": 70000 })),
+            vec!["$.port: expected a number from 0 to 65535, found 70000"]
+        );
+        assert_eq!(
+            messages_of(json!([])),
+            vec!["$: expected object, found array"]
+        );
+        assert_eq!(
+            validate_manifest_str("{\"enabled\": tru}", false)[0].to_string(),
+            "$: pipe.json: invalid json at line 1, column 16: expected ident"
+        );
+    }
+
+    fn messages_of(manifest: Value) -> Vec<String> {
+        messages(&validate_manifest(&manifest, false))
+    }
+
+    #[test]
+    fn test_unknown_keys_are_warnings_with_the_likely_key() {
+        let manifest = json!({
+            "enabled": true,
+            "permisions": ["network"],
+            "Hosts": ["api.openai.com"],
+            "crons": [{ "path": "/api/log", "schedule": "0 0 * * * *", "timezone": "UTC" }],
+            "fields": [{ "name": "interval", "defualt": 60 }],
+            "my setting": 1
+        });
+        let issues = validate_manifest(&manifest, false);
+        assert!(issues
+            .iter()
+            .all(|issue| issue.severity == Severity::Warning));
+        let suggestions: Vec<(&str, Option<&str>)> = issues
+            .iter()
+            .map(|issue| (issue.path.as_str(), issue.suggestion.as_deref()))
+            .collect();
+        assert_eq!(
+            suggestions,
+            vec![
+                ("$.Hosts", Some("hosts")),
+                ("$.crons[0].timezone", None),
+                ("$.fields[0].defualt", Some("default")),
+                ("$[\"my setting\"]", None),
+                ("$.permisions", Some("permissions")),
+                // Crons are only scheduled for next.js pipes
+                ("$.crons", None),
+            ]
+        );
+        assert_eq!(
+            issues[4].to_string(),
+            "$.permisions: unknown key, did you mean 'permissions'?"
+        );
+
+        let strict = validate_manifest(&manifest, true);
+        assert_eq!(strict.len(), issues.len());
+        assert!(strict.iter().all(|issue| issue.severity == Severity::Error));
+    }
+
+    #[tokio::test]
+    async fn test_pipes_are_checked_before_they_run() {
+        let dir = tempdir().unwrap();
+        let manager = PipeManager::new(dir.path().to_path_buf());
+        let strict = PipeManager::new(dir.path().to_path_buf()).with_strict_manifests(true);
+
+        write_manifest(
+            dir.path(),
+            "typo",
+            &json!({ "enabled": true, "evnets": [] }),
+        );
+        let warnings = manager.check_manifest("typo").await.unwrap();
+        assert_eq!(warnings[0].suggestion.as_deref(), Some("events"));
+        assert!(matches!(
+            strict.check_manifest("typo").await,
+            Err(PipeError::InvalidManifest { issues, .. }) if issues.len() == 1
+        ));
+
+        // Not started, whatever the mode
+        write_manifest(
+            dir.path(),
+            "broken",
+            &json!({ "enabled": true, "hosts": "*" }),
+        );
+        let error = manager
+            .start_pipe_task("broken".to_string())
+            .await
+            .err()
+            .unwrap();
+        assert!(error
+            .to_string()
+            .contains("$.hosts: expected array, found string"));
+        let error = manager
+            .update_config("broken", json!({ "enabled": true }))
+            .await
+            .unwrap_err();
+        let api_error = ApiError::from(error);
+        assert_eq!(api_error.code, ErrorCode::PipeError);
+        assert_eq!(api_error.extensions["issues"][0]["path"], "$.hosts");
+
+        // A pipe without a manifest has nothing to check
+        std::fs::create_dir_all(dir.path().join("pipes").join("bare")).unwrap();
+        assert!(strict.check_manifest("bare").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_hand_edited_manifests_are_loaded_defensively() {
+        let dir = tempdir().unwrap();
+        let manager = PipeManager::new(dir.path().to_path_buf());
+        let write = |id: &str, content: &[u8]| {
+            let pipe_dir = dir.path().join("pipes").join(id);
+            std::fs::create_dir_all(&pipe_dir).unwrap();
+            std::fs::write(pipe_dir.join("pipe.json"), content).unwrap();
+            pipe_dir.join("pipe.json")
+        };
+
+        write("bom", b"\xEF\xBB\xBF{\"enabled\": false}");
+        assert!(manager.check_manifest("bom").await.unwrap().is_empty());
+        manager
+            .update_config("bom", json!({ "port": 3100 }))
+            .await
+            .unwrap();
+
+        let path = write("comma", b"{\n  \"enabled\": true,\n}\n");
+        let expected = format!(
+            "$: {}: invalid json at line 3, column 1: trailing comma",
+            path.display()
+        );
+        match manager.check_manifest("comma").await {
+            Err(PipeError::InvalidManifest { issues, .. }) => {
+                assert_eq!(issues[0].to_string(), expected)
+            }
+            other => panic!("expected an invalid manifest, got {:?}", other),
+        }
+        // The config endpoints go through the same loader
+        let error = manager
+            .update_config("comma", json!({ "port": 3100 }))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, PipeError::Config(_)));
+        assert!(error.to_string().contains("line 3, column 1"));
+
+        write("huge", &vec![b' '; 2 * 1024 * 1024]);
+        let error = manager.check_manifest("huge").await.unwrap_err();
+        assert!(error.to_string().contains("config files are limited to"));
+    }
+}
@@ -0,0 +1,103 @@
+#[cfg(test)]
+mod tests {
+    use screenpipe_server::pipe_permissions::{PermissionBroker, PipeEvent};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tempfile::tempdir;
+
+    fn scopes(s: &[&str]) -> Vec<String> {
+        s.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_auto_approve_grants_everything() {
+        let dir = tempdir().unwrap();
+        let broker = PermissionBroker::new(dir.path().to_path_buf(), true);
+
+        let granted = broker
+            .resolve("pipe", &scopes(&["fs:read", "network"]))
+            .await
+            .unwrap();
+        assert_eq!(granted, scopes(&["fs:read", "network"]));
+    }
+
+    #[tokio::test]
+    async fn test_prompt_is_answered_and_persisted() {
+        let dir = tempdir().unwrap();
+        let broker = Arc::new(PermissionBroker::new(dir.path().to_path_buf(), false));
+        let mut events = broker.subscribe();
+
+        let resolving = {
+            let broker = Arc::clone(&broker);
+            tokio::spawn(async move {
+                broker
+                    .resolve("pipe", &scopes(&["fs:read", "network"]))
+                    .await
+                    .unwrap()
+            })
+        };
+
+        match events.recv().await.unwrap() {
+            PipeEvent::PermissionRequest { pipe_id, scopes: s } => {
+                assert_eq!(pipe_id, "pipe");
+                assert_eq!(s, scopes(&["fs:read", "network"]));
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+
+        broker
+            .decide("pipe", scopes(&["network"]), scopes(&["fs:read"]))
+            .await
+            .unwrap();
+        assert_eq!(resolving.await.unwrap(), scopes(&["network"]));
+
+        // A new broker reads the persisted decisions and doesn't prompt again
+        let reloaded = PermissionBroker::new(dir.path().to_path_buf(), false)
+            .with_timeout(Duration::from_millis(10));
+        let mut events = reloaded.subscribe();
+        let granted = reloaded
+            .resolve("pipe", &scopes(&["fs:read", "network"]))
+            .await
+            .unwrap();
+        assert_eq!(granted, scopes(&["network"]));
+        assert!(matches!(
+            events.try_recv(),
+            Ok(PipeEvent::PermissionResolved { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_unanswered_prompt_is_denied() {
+        let dir = tempdir().unwrap();
+        let broker = PermissionBroker::new(dir.path().to_path_buf(), false)
+            .with_timeout(Duration::from_millis(50));
+
+        let granted = broker.resolve("pipe", &scopes(&["network"])).await.unwrap();
+        assert!(granted.is_empty());
+        assert!(broker.granted("pipe").await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_revoke_forces_new_prompt() {
+        let dir = tempdir().unwrap();
+        let broker = PermissionBroker::new(dir.path().to_path_buf(), false)
+            .with_timeout(Duration::from_millis(50));
+        broker
+            .decide("pipe", scopes(&["network"]), vec![])
+            .await
+            .unwrap();
+        assert_eq!(
+            broker.resolve("pipe", &scopes(&["network"])).await.unwrap(),
+            scopes(&["network"])
+        );
+
+        broker.revoke("pipe").await.unwrap();
+        let mut events = broker.subscribe();
+        let granted = broker.resolve("pipe", &scopes(&["network"])).await.unwrap();
+        assert!(granted.is_empty());
+        assert!(matches!(
+            events.try_recv(),
+            Ok(PipeEvent::PermissionRequest { .. })
+        ));
+    }
+}
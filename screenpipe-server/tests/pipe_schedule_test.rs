@@ -0,0 +1,286 @@
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration as ChronoDuration, Utc};
+    use screenpipe_core::power::{PowerState, SubsystemOutcome};
+    use screenpipe_server::db_types::{PipeJob, PipeJobStatus};
+    use screenpipe_server::pipe_schedule::{
+        job_event, JobRunner, PipeScheduler, RetryPolicy, ScheduleError, ScheduleRequest,
+        MAX_ACTIVE_JOBS_PER_PIPE,
+    };
+    use screenpipe_server::DatabaseManager;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicI64, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    /// Records the events of the jobs it runs, failing the first `failures` runs.
+    fn recording_runner(failures: usize) -> (JobRunner, Arc<Mutex<Vec<serde_json::Value>>>) {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let runner: JobRunner = Arc::new({
+            let events = events.clone();
+            move |job: PipeJob| {
+                let events = events.clone();
+                Box::pin(async move {
+                    let mut events = events.lock().unwrap();
+                    events.push(job_event(&job));
+                    if events.len() <= failures {
+                        anyhow::bail!("run {} failed", events.len());
+                    }
+                    Ok(())
+                })
+            }
+        });
+        (runner, events)
+    }
+
+    fn in_seconds(seconds: u64) -> ScheduleRequest {
+        ScheduleRequest {
+            run_in_seconds: Some(seconds),
+            ..Default::default()
+        }
+    }
+
+    fn leaked_power_state() -> &'static PowerState {
+        Box::leak(Box::new(PowerState::new()))
+    }
+
+    async fn wait_for_status(db: &DatabaseManager, id: i64, status: PipeJobStatus) -> PipeJob {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let job = db.get_pipe_job(id).await.unwrap().unwrap();
+            if job.status == status || Instant::now() > deadline {
+                return job;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_schedule_list_and_cancel() {
+        let db = Arc::new(DatabaseManager::new("sqlite::memory:").await.unwrap());
+        let (runner, _) = recording_runner(0);
+        let scheduler = PipeScheduler::with_runner(db.clone(), runner);
+
+        let later = scheduler
+            .schedule(
+                "follow-up",
+                ScheduleRequest {
+                    run_in_seconds: Some(45 * 60),
+                    payload: json!({"thread": 7}),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        let run_at = Utc::now() + ChronoDuration::minutes(10);
+        let sooner = scheduler
+            .schedule(
+                "follow-up",
+                ScheduleRequest {
+                    run_at: Some(run_at),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(later.status, PipeJobStatus::Pending);
+        assert_eq!(later.payload, json!({"thread": 7}));
+        assert_eq!(sooner.run_at, run_at);
+
+        let jobs = scheduler.list("follow-up", None).await.unwrap();
+        assert_eq!(
+            jobs.iter().map(|j| j.id).collect::<Vec<_>>(),
+            vec![sooner.id, later.id]
+        );
+        assert!(scheduler.list("other", None).await.unwrap().is_empty());
+
+        let cancelled = scheduler.cancel("follow-up", later.id).await.unwrap();
+        assert_eq!(cancelled.status, PipeJobStatus::Cancelled);
+        assert!(matches!(
+            scheduler.cancel("follow-up", later.id).await,
+            Err(ScheduleError::NotFound { .. })
+        ));
+        assert!(matches!(
+            scheduler.cancel("other", sooner.id).await,
+            Err(ScheduleError::NotFound { .. })
+        ));
+        let pending = scheduler
+            .list("follow-up", Some(PipeJobStatus::Pending))
+            .await
+            .unwrap();
+        assert_eq!(pending.len(), 1);
+
+        // Neither or both times, and policies out of range, are rejected
+        for request in [
+            ScheduleRequest::default(),
+            ScheduleRequest {
+                run_at: Some(run_at),
+                run_in_seconds: Some(1),
+                ..Default::default()
+            },
+            in_seconds(400 * 24 * 3600),
+            ScheduleRequest {
+                retry: RetryPolicy {
+                    max_attempts: 0,
+                    ..Default::default()
+                },
+                ..in_seconds(1)
+            },
+            ScheduleRequest {
+                payload: json!("x".repeat(100 * 1024)),
+                ..in_seconds(1)
+            },
+        ] {
+            assert!(matches!(
+                scheduler.schedule("follow-up", request).await,
+                Err(ScheduleError::Invalid(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_jobs_per_pipe_are_capped() {
+        let db = Arc::new(DatabaseManager::new("sqlite::memory:").await.unwrap());
+        let (runner, _) = recording_runner(0);
+        let scheduler = PipeScheduler::with_runner(db.clone(), runner);
+
+        for _ in 0..MAX_ACTIVE_JOBS_PER_PIPE {
+            scheduler.schedule("loop", in_seconds(60)).await.unwrap();
+        }
+        assert!(matches!(
+            scheduler.schedule("loop", in_seconds(60)).await,
+            Err(ScheduleError::TooManyJobs { .. })
+        ));
+        // Other pipes have their own cap, and a cancelled job frees a slot
+        scheduler.schedule("other", in_seconds(60)).await.unwrap();
+        let first = scheduler.list("loop", None).await.unwrap()[0].id;
+        scheduler.cancel("loop", first).await.unwrap();
+        scheduler.schedule("loop", in_seconds(60)).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_due_jobs_fire_with_their_payload_and_retry() {
+        let db = Arc::new(DatabaseManager::new("sqlite::memory:").await.unwrap());
+        let (runner, events) = recording_runner(1);
+        let scheduler = Arc::new(PipeScheduler::with_runner(db.clone(), runner));
+        tokio::spawn(scheduler.clone().run(leaked_power_state()));
+
+        let job = scheduler
+            .schedule(
+                "follow-up",
+                ScheduleRequest {
+                    run_in_seconds: Some(0),
+                    payload: json!({"thread": 7}),
+                    retry: RetryPolicy {
+                        max_attempts: 2,
+                        backoff_seconds: 0,
+                    },
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+
+        let done = wait_for_status(&db, job.id, PipeJobStatus::Done).await;
+        assert_eq!(done.status, PipeJobStatus::Done);
+        assert_eq!(done.attempts, 2);
+        assert_eq!(done.last_error.as_deref(), Some("run 1 failed"));
+        let events = events.lock().unwrap().clone();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[1]["type"], "scheduled_job");
+        assert_eq!(events[1]["job_id"], job.id);
+        assert_eq!(events[1]["attempt"], 2);
+        assert_eq!(events[1]["payload"], json!({"thread": 7}));
+
+        // Without retries the first failure is final
+        let (runner, _) = recording_runner(usize::MAX);
+        let failing = Arc::new(PipeScheduler::with_runner(db.clone(), runner));
+        let job = failing.schedule("broken", in_seconds(0)).await.unwrap();
+        failing.fire_due_jobs().await.unwrap();
+        let failed = wait_for_status(&db, job.id, PipeJobStatus::Failed).await;
+        assert_eq!(failed.status, PipeJobStatus::Failed);
+        assert!(failed.finished_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_jobs_survive_a_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("db.sqlite");
+        let db_path = db_path.to_string_lossy();
+
+        let (interrupted, due) = {
+            let db = Arc::new(DatabaseManager::new(&db_path).await.unwrap());
+            let (runner, _) = recording_runner(0);
+            let scheduler = PipeScheduler::with_runner(db.clone(), runner);
+            let interrupted = scheduler.schedule("p", in_seconds(0)).await.unwrap();
+            // The process exits while the first job runs and before the second is due
+            db.claim_due_pipe_jobs(Utc::now()).await.unwrap();
+            let due = scheduler.schedule("p", in_seconds(1)).await.unwrap();
+            db.pool.close().await;
+            (interrupted, due)
+        };
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+
+        let db = Arc::new(DatabaseManager::new(&db_path).await.unwrap());
+        let (runner, events) = recording_runner(0);
+        let scheduler = Arc::new(PipeScheduler::with_runner(db.clone(), runner));
+        tokio::spawn(scheduler.run(leaked_power_state()));
+
+        for job in [interrupted, due] {
+            let job = wait_for_status(&db, job.id, PipeJobStatus::Done).await;
+            assert_eq!(job.status, PipeJobStatus::Done);
+            assert_eq!(job.attempts, 1);
+        }
+        assert_eq!(events.lock().unwrap().len(), 2);
+    }
+
+    /// The machine sleeps past a job's due time. The timer the scheduler waits on
+    /// doesn't know, the wake has it recompute from the wall clock.
+    #[tokio::test]
+    async fn test_wake_recomputes_the_next_due_job() {
+        let db = Arc::new(DatabaseManager::new("sqlite::memory:").await.unwrap());
+        let offset_secs = Arc::new(AtomicI64::new(0));
+        let (runner, events) = recording_runner(0);
+        let scheduler = Arc::new(
+            PipeScheduler::with_runner(db.clone(), runner).with_system_time({
+                let offset_secs = offset_secs.clone();
+                move || Utc::now() + ChronoDuration::seconds(offset_secs.load(Ordering::SeqCst))
+            }),
+        );
+        let power = leaked_power_state();
+        tokio::spawn(scheduler.clone().run(power));
+
+        let job = scheduler.schedule("p", in_seconds(2 * 3600)).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(events.lock().unwrap().is_empty());
+
+        // Three hours pass while asleep, the wall clock jumps ahead at once
+        power.sleep(Utc::now());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(events.lock().unwrap().is_empty());
+        offset_secs.store(3 * 3600, Ordering::SeqCst);
+        let epoch = match power.wake_from_sleep(Utc::now()) {
+            Some(screenpipe_core::power::PowerEvent::Wake { epoch, .. }) => epoch,
+            event => panic!("expected a wake, got {:?}", event),
+        };
+
+        let fired_at = Instant::now();
+        let job = wait_for_status(&db, job.id, PipeJobStatus::Done).await;
+        assert_eq!(job.status, PipeJobStatus::Done);
+        assert!(fired_at.elapsed() < Duration::from_secs(2));
+
+        let reports = tokio::task::spawn_blocking(move || {
+            power.wait_for_reports(epoch, Duration::from_secs(2))
+        })
+        .await
+        .unwrap();
+        assert!(
+            matches!(
+                &reports["pipe scheduler"],
+                SubsystemOutcome::Ok(outcome) if outcome.starts_with("fired 1 jobs")
+            ),
+            "{:?}",
+            reports
+        );
+    }
+}
@@ -0,0 +1,140 @@
+#[cfg(test)]
+mod tests {
+    use axum::body::{to_bytes, Body};
+    use axum::http::{header, Method, Request, StatusCode};
+    use axum::response::{Json, Response};
+    use axum::routing::{get, post};
+    use axum::Router;
+    use screenpipe_server::api_version::{with_api_version, ApiVersioning, DEPRECATIONS};
+    use screenpipe_server::pipe_tokens::{with_pipe_scopes, PipeTokens, AGGREGATES_ONLY_SCOPE};
+    use screenpipe_server::problem::with_problem_details;
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    /// The raw content routes a scope could be bypassed through, and the aggregates.
+    fn test_app(tokens: &PipeTokens) -> Router {
+        let content = || async { Json(json!({"data": ["captured text"]})) };
+        let router = Router::new()
+            .route("/search", get(content))
+            .route("/sessions/start", post(content))
+            .route("/sessions/stop", post(content))
+            .route("/raw_sql", post(content))
+            .route("/frames/:frame_id", get(content))
+            .route(
+                "/usage/aggregate/apps",
+                get(|| async { Json(json!({"apps": []})) }),
+            )
+            .route(
+                "/usage/aggregate/captures",
+                get(|| async { Json(json!({"captures": []})) }),
+            );
+        let router = with_pipe_scopes(router, tokens.clone());
+        let versioning = Arc::new(ApiVersioning::new(DEPRECATIONS.to_vec()));
+        with_api_version(with_problem_details(router), versioning)
+    }
+
+    async fn send(app: &Router, method: Method, uri: &str, token: Option<&str>) -> Response {
+        let mut request = Request::builder().method(method).uri(uri);
+        if let Some(token) = token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let request = request
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"name": "export", "auto_export": "md"}"#))
+            .unwrap();
+        app.clone().oneshot(request).await.unwrap()
+    }
+
+    async fn json_body(response: Response) -> Value {
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_an_aggregates_only_pipe_only_reaches_the_aggregates() {
+        let tokens = PipeTokens::default();
+        let app = test_app(&tokens);
+        let issued = tokens.issue("time-tracker", &[AGGREGATES_ONLY_SCOPE.to_string()]);
+        let token = Some(issued.token());
+
+        for uri in [
+            "/usage/aggregate/apps",
+            "/v1/usage/aggregate/captures?bucket=day",
+        ] {
+            let response = send(&app, Method::GET, uri, token).await;
+            assert_eq!(response.status(), StatusCode::OK, "{}", uri);
+        }
+
+        let refused = [
+            (Method::GET, "/search?q=password"),
+            (Method::GET, "/v1/search?content_type=all"),
+            (Method::POST, "/sessions/start"),
+            (Method::POST, "/v1/sessions/stop"),
+            (Method::POST, "/raw_sql"),
+            (Method::GET, "/frames/1"),
+            // Paths that only look like an aggregate
+            (Method::GET, "/usage/aggregate/apps/../../search"),
+            (Method::GET, "/usage/aggregate/../search"),
+            (Method::GET, "//search"),
+            (Method::GET, "/v1/v1/search"),
+            (Method::GET, "/usage/aggregate"),
+        ];
+        for (method, uri) in refused {
+            let response = send(&app, method.clone(), uri, token).await;
+            assert_eq!(
+                response.status(),
+                StatusCode::FORBIDDEN,
+                "{} {}",
+                method,
+                uri
+            );
+            let body = json_body(response).await;
+            assert_eq!(body["code"], "forbidden", "{} {}", method, uri);
+            assert_eq!(body["scopes"], json!([AGGREGATES_ONLY_SCOPE]));
+            assert!(body.get("data").is_none(), "{} {}", method, uri);
+        }
+
+        // The scheme is case insensitive, a lowercase one doesn't slip through
+        let request = Request::builder()
+            .uri("/search")
+            .header(header::AUTHORIZATION, format!("bearer {}", issued.token()))
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_other_pipes_and_the_user_reach_every_route() {
+        let tokens = PipeTokens::default();
+        let app = test_app(&tokens);
+        let issued = tokens.issue("notes", &["read:ocr".to_string()]);
+
+        for token in [Some(issued.token()), None] {
+            let response = send(&app, Method::GET, "/search?q=x", token).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let response = send(&app, Method::POST, "/sessions/start", token).await;
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_the_token_of_a_stopped_pipe_is_refused() {
+        let tokens = PipeTokens::default();
+        let app = test_app(&tokens);
+        let issued = tokens.issue("time-tracker", &[AGGREGATES_ONLY_SCOPE.to_string()]);
+        let token = issued.token().to_string();
+        assert!(tokens.get(&token).is_some());
+        drop(issued);
+        assert!(tokens.get(&token).is_none());
+
+        for uri in ["/usage/aggregate/apps", "/search"] {
+            let response = send(&app, Method::GET, uri, Some(&token)).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "{}", uri);
+            assert_eq!(json_body(response).await["code"], "unauthorized");
+        }
+        let response = send(&app, Method::GET, "/search", Some("made-up")).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,235 @@
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, TimeZone, Utc};
+    use screenpipe_audio::{AudioDevice, DeviceType};
+    use screenpipe_server::db_types::{
+        AppRollup, CaptureWrite, FrameWrite, RetentionKind, WindowOcrWrite,
+    };
+    use screenpipe_server::privacy::{
+        delete_app_data, summarize, summary, update_rollups, IgnoredApps,
+    };
+    use screenpipe_server::retention::{RetentionPolicy, RetentionSettings};
+    use screenpipe_server::storage_mode::StorageMode;
+    use screenpipe_server::DatabaseManager;
+    use tempfile::tempdir;
+
+    async fn setup_test_db() -> DatabaseManager {
+        let db = DatabaseManager::new("sqlite::memory:").await.unwrap();
+        db.insert_video_chunk("video.mp4", "monitor_1")
+            .await
+            .unwrap();
+        db
+    }
+
+    fn window(app_name: &str, focused: bool) -> WindowOcrWrite {
+        WindowOcrWrite {
+            text: format!("{} text", app_name),
+            text_json: "[]".to_string(),
+            app_name: app_name.to_string(),
+            window_name: "window".to_string(),
+            ocr_engine: "Tesseract".to_string(),
+            focused,
+            raw_text: None,
+            corrected_by: None,
+        }
+    }
+
+    /// A frame at `timestamp` with `focused` in front and the other apps behind it.
+    async fn frame(db: &DatabaseManager, timestamp: DateTime<Utc>, focused: &str, behind: &[&str]) {
+        let mut windows = vec![window(focused, true)];
+        windows.extend(behind.iter().map(|app| window(app, false)));
+        db.write_capture(CaptureWrite::Frame(FrameWrite {
+            device_name: "monitor_1".to_string(),
+            video_chunk_id: None,
+            timestamp: Some(timestamp),
+            windows,
+            storage_mode: StorageMode::Full,
+            thumbnail_path: None,
+            window_layout: None,
+        }))
+        .await
+        .unwrap();
+    }
+
+    /// A transcription of `seconds` of audio heard at `timestamp`.
+    async fn transcription(db: &DatabaseManager, timestamp: DateTime<Utc>, seconds: f64) {
+        let chunk_id = db.insert_audio_chunk("audio.mp4").await.unwrap();
+        let id = db
+            .insert_audio_transcription(
+                chunk_id,
+                "hello",
+                0,
+                "",
+                &AudioDevice::new("microphone".to_string(), DeviceType::Input),
+                None,
+                Some(0.0),
+                Some(seconds),
+            )
+            .await
+            .unwrap();
+        sqlx::query("UPDATE audio_transcriptions SET timestamp = ?1 WHERE id = ?2")
+            .bind(timestamp)
+            .bind(id)
+            .execute(&db.pool)
+            .await
+            .unwrap();
+    }
+
+    fn at(hour: u32, minute: u32, second: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 12, 27, hour, minute, second)
+            .unwrap()
+    }
+
+    fn settings() -> RetentionSettings {
+        RetentionSettings {
+            global: RetentionPolicy::days(30),
+            apps: [("slack".to_string(), RetentionPolicy::days(7))].into(),
+            disk_budget_gb: None,
+            classes: Default::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rollups_count_new_rows_and_rebuild_deleted_days() {
+        let db = setup_test_db().await;
+        frame(&db, at(10, 0, 0), "Code", &["Slack"]).await;
+        frame(&db, at(10, 0, 30), "code.exe", &["Slack"]).await;
+        frame(&db, at(10, 4, 30), "Firefox", &["Slack"]).await;
+        transcription(&db, at(10, 0, 10), 30.0).await;
+        transcription(&db, at(10, 0, 40), 12.0).await;
+        // Nothing on screen for two hours, not credited to any app
+        transcription(&db, at(12, 0, 0), 60.0).await;
+
+        // Rows captured within the settle time are left for later
+        let now = at(10, 5, 0);
+        let progress = update_rollups(&db, now).await.unwrap();
+        assert_eq!((progress.frames, progress.transcriptions), (2, 2));
+        let first = summary(&db, &settings(), now).await.unwrap();
+        assert_eq!(first.rollup.pending_frames, 1);
+        assert_eq!(first.rollup.pending_transcriptions, 1);
+        assert!(first.rollup.updated_at.is_some());
+
+        let apps: Vec<_> = first
+            .apps
+            .iter()
+            .map(|app| {
+                (
+                    app.app_name.as_str(),
+                    app.frames,
+                    app.ocr_characters,
+                    app.audio_minutes,
+                )
+            })
+            .collect();
+        assert_eq!(apps, vec![("code", 2, 22, 0.7), ("slack", 2, 20, 0.0)]);
+        let code = &first.apps[0];
+        assert_eq!(code.captured_as, vec!["Code", "code.exe"]);
+        // Audio heard over the app counts as its data too
+        assert_eq!((code.oldest, code.newest), (at(10, 0, 0), at(10, 0, 40)));
+        assert_eq!(code.retention_days, Some(30));
+        assert!(!code.retention_overridden);
+        assert_eq!(first.apps[1].retention_days, Some(7));
+        assert!(first.apps[1].retention_overridden);
+
+        // Later runs only count what came since
+        let later = at(13, 0, 0);
+        let progress = update_rollups(&db, later).await.unwrap();
+        assert_eq!((progress.frames, progress.transcriptions), (1, 1));
+        assert!(update_rollups(&db, later).await.unwrap().is_empty());
+        let apps = summary(&db, &settings(), later).await.unwrap().apps;
+        assert_eq!(apps[0].app_name, "slack");
+        assert_eq!(apps[0].frames, 3);
+        assert_eq!(apps[2].app_name, "firefox");
+
+        // Deleting the first frame takes it and the audio heard over it out of the counts
+        let first_frame: i64 = sqlx::query_scalar("SELECT MIN(id) FROM frames")
+            .fetch_one(&db.pool)
+            .await
+            .unwrap();
+        db.delete_retention_rows(RetentionKind::Frame, &[first_frame])
+            .await
+            .unwrap();
+        assert_eq!(db.get_rollup_state().await.unwrap().dirty_days, 1);
+        let progress = update_rollups(&db, later).await.unwrap();
+        assert_eq!(progress.days_rebuilt, 1);
+        let rebuilt = summary(&db, &settings(), later).await.unwrap();
+        let code = rebuilt
+            .apps
+            .iter()
+            .find(|app| app.app_name == "code")
+            .unwrap();
+        assert_eq!((code.frames, code.audio_minutes), (1, 0.2));
+        assert_eq!(code.captured_as, vec!["code.exe"]);
+        assert_eq!(rebuilt.rollup.dirty_days, 0);
+        assert_eq!(rebuilt.rollup.pending_frames, 0);
+    }
+
+    #[test]
+    fn test_ignore_list_matches_normalized_names() {
+        let dir = tempdir().unwrap();
+        let ignored = IgnoredApps::default();
+        assert!(!ignored.contains("Slack"));
+        assert!(ignored.set(dir.path(), " Slack.app", true).unwrap());
+        assert!(!ignored.set(dir.path(), "slack", true).unwrap());
+        assert!(ignored.contains("SLACK.exe"));
+        assert!(!ignored.contains("Slack Huddle"));
+
+        // Saved for the next start
+        let reloaded = IgnoredApps::default();
+        reloaded.load(dir.path());
+        assert_eq!(reloaded.list(), vec!["slack"]);
+
+        let rollup = |app_name: &str, frames: i64| AppRollup {
+            app_name: app_name.to_string(),
+            frames,
+            ocr_chars: frames * 10,
+            audio_seconds: 0.0,
+            first_seen: at(9, 0, 0),
+            last_seen: at(11, 0, 0),
+        };
+        let apps = summarize(
+            &[
+                rollup("Slack", 4),
+                rollup("Code", 1),
+                rollup("slack.exe", 2),
+                rollup(" ", 9),
+            ],
+            &RetentionSettings::default(),
+            &reloaded,
+        );
+        assert_eq!(apps.len(), 2);
+        assert_eq!(apps[0].app_name, "slack");
+        assert_eq!(apps[0].frames, 6);
+        assert!(apps[0].ignored);
+        assert!(!apps[1].ignored);
+        assert_eq!(apps[1].retention_days, None);
+
+        assert!(reloaded.set(dir.path(), "Slack", false).unwrap());
+        assert!(!reloaded.contains("slack"));
+        std::fs::write(IgnoredApps::path(dir.path()), "not json").unwrap();
+        ignored.load(dir.path());
+        assert!(ignored.list().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_deleting_an_app_deletes_every_frame_showing_it() {
+        let db = setup_test_db().await;
+        frame(&db, at(10, 0, 0), "Code", &["Slack.app"]).await;
+        frame(&db, at(10, 0, 30), "Firefox", &[]).await;
+        frame(&db, at(10, 1, 0), "slack", &[]).await;
+        update_rollups(&db, at(11, 0, 0)).await.unwrap();
+
+        assert_eq!(delete_app_data(&db, "Nothing").await.unwrap().frames, 0);
+        let deletion = delete_app_data(&db, "SLACK").await.unwrap();
+        assert_eq!(deletion.app_name, "slack");
+        assert_eq!(deletion.frames, 2);
+        // The chunk still holds the Firefox frame
+        assert_eq!(deletion.files_removed, 0);
+        assert_eq!(db.get_app_names().await.unwrap(), vec!["Firefox"]);
+
+        update_rollups(&db, at(11, 0, 0)).await.unwrap();
+        let apps = summary(&db, &settings(), at(11, 0, 0)).await.unwrap().apps;
+        let names: Vec<_> = apps.iter().map(|app| app.app_name.as_str()).collect();
+        assert_eq!(names, vec!["firefox"]);
+    }
+}
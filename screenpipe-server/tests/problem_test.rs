@@ -0,0 +1,158 @@
+#[cfg(test)]
+mod tests {
+    use axum::body::{to_bytes, Body};
+    use axum::http::{Request, StatusCode};
+    use axum::response::Json;
+    use axum::routing::{get, post};
+    use axum::Router;
+    use chrono::Utc;
+    use screenpipe_server::pipe_manager::PipeError;
+    use screenpipe_server::problem::{
+        with_problem_details, ApiError, ErrorCode, Problem, PROBLEM_CONTENT_TYPE, TRACE_ID_HEADER,
+    };
+    use screenpipe_server::search_query::parse_query;
+    use serde_json::{json, Value};
+    use tower::ServiceExt;
+
+    fn test_app() -> Router {
+        let router = Router::new()
+            .route(
+                "/pipes/info/:id",
+                get(|| async {
+                    Err::<Json<Value>, ApiError>(PipeError::NotFound("missing".into()).into())
+                }),
+            )
+            .route(
+                "/pipes/enable",
+                post(|Json(body): Json<Value>| async move { Json(body) }),
+            )
+            .route(
+                "/legacy",
+                get(|| async {
+                    (
+                        StatusCode::BAD_REQUEST,
+                        Json(json!({"error": "bad limit", "success": false})),
+                    )
+                }),
+            )
+            .route(
+                "/panic",
+                get(|| async {
+                    panic!("boom");
+                    #[allow(unreachable_code)]
+                    "unreachable"
+                }),
+            )
+            .route(
+                "/search",
+                get(|| async {
+                    parse_query("slack OR", Utc::now())?;
+                    Ok::<_, ApiError>(Json(json!({"success": true})))
+                }),
+            )
+            .route("/ok", get(|| async { Json(json!({"success": true})) }));
+        with_problem_details(router)
+    }
+
+    async fn send(request: Request<Body>) -> (StatusCode, String, String, Problem) {
+        let response = test_app().oneshot(request).await.unwrap();
+        let status = response.status();
+        let content_type = response.headers()["content-type"]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let trace_id = response.headers()[TRACE_ID_HEADER]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (
+            status,
+            content_type,
+            trace_id,
+            serde_json::from_slice(&body).unwrap(),
+        )
+    }
+
+    fn get_request(uri: &str) -> Request<Body> {
+        Request::builder().uri(uri).body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_unknown_route_is_not_found_problem() {
+        let (status, content_type, trace_id, problem) = send(get_request("/nope")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(content_type, PROBLEM_CONTENT_TYPE);
+        assert_eq!(problem.code, ErrorCode::NotFound);
+        assert_eq!(problem.status, 404);
+        assert_eq!(problem.problem_type, "urn:screenpipe:error:not_found");
+        assert_eq!(problem.trace_id, trace_id);
+        assert!(!problem.success);
+    }
+
+    #[tokio::test]
+    async fn test_bad_json_is_invalid_request() {
+        let request = Request::builder()
+            .method("POST")
+            .uri("/pipes/enable")
+            .header("content-type", "application/json")
+            .body(Body::from("{not json"))
+            .unwrap();
+        let (status, _, _, problem) = send(request).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(problem.code, ErrorCode::InvalidRequest);
+        assert!(!problem.detail.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_pipe_not_found_keeps_error_field() {
+        let (status, _, _, problem) = send(get_request("/pipes/info/missing")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(problem.code, ErrorCode::PipeNotFound);
+        assert_eq!(problem.detail, "pipe 'missing' does not exist");
+        assert_eq!(problem.error, problem.detail);
+    }
+
+    #[tokio::test]
+    async fn test_query_error_carries_span_and_hint() {
+        let (status, _, _, problem) = send(get_request("/search")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(problem.code, ErrorCode::InvalidQuery);
+        assert_eq!(problem.title, "invalid search query");
+        assert_eq!(problem.extensions["span"], json!({"start": 6, "end": 8}));
+        assert!(problem.extensions["hint"].as_str().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_legacy_error_body_is_wrapped() {
+        let (status, content_type, _, problem) = send(get_request("/legacy")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(content_type, PROBLEM_CONTENT_TYPE);
+        assert_eq!(problem.code, ErrorCode::InvalidRequest);
+        assert_eq!(problem.detail, "bad limit");
+    }
+
+    #[tokio::test]
+    async fn test_panic_is_internal_error() {
+        let (status, _, _, problem) = send(get_request("/panic")).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(problem.code, ErrorCode::InternalError);
+        assert!(!problem.detail.contains("boom"));
+    }
+
+    #[tokio::test]
+    async fn test_trace_id_is_propagated() {
+        let request = Request::builder()
+            .uri("/nope")
+            .header(TRACE_ID_HEADER, "abc123")
+            .body(Body::empty())
+            .unwrap();
+        let (_, _, trace_id, problem) = send(request).await;
+        assert_eq!(trace_id, "abc123");
+        assert_eq!(problem.trace_id, "abc123");
+
+        let response = test_app().oneshot(get_request("/ok")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().contains_key(TRACE_ID_HEADER));
+    }
+}
@@ -0,0 +1,188 @@
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Duration, Utc};
+    use screenpipe_server::ranking::{ocr_confidence, rank_hits, Hit, HitKind, RankingWeights};
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn hit(
+        kind: HitKind,
+        row_id: i64,
+        bm25: f64,
+        age: Duration,
+        confidence: Option<f64>,
+        now: DateTime<Utc>,
+    ) -> Hit {
+        Hit {
+            kind,
+            row_id: (row_id, 0),
+            bm25,
+            timestamp: now - age,
+            confidence,
+        }
+    }
+
+    /// Small labeled set: for each query, the candidates a search would return and the
+    /// row that a person judged the best answer.
+    struct LabeledQuery {
+        query: &'static str,
+        hits: Vec<Hit>,
+        expected_top: (HitKind, i64),
+    }
+
+    fn labeled_queries(now: DateTime<Utc>) -> Vec<LabeledQuery> {
+        vec![
+            LabeledQuery {
+                // Exact match on screen yesterday beats a passing mention a minute ago
+                query: "invoice 4471",
+                hits: vec![
+                    hit(HitKind::Ocr, 1, -8.0, Duration::hours(20), Some(0.95), now),
+                    hit(HitKind::Audio, 2, -1.5, Duration::minutes(1), None, now),
+                    hit(HitKind::Ui, 3, -1.0, Duration::minutes(5), None, now),
+                ],
+                expected_top: (HitKind::Ocr, 1),
+            },
+            LabeledQuery {
+                // Equally relevant, the recent meeting wins over last month's
+                query: "standup notes",
+                hits: vec![
+                    hit(HitKind::Audio, 10, -4.0, Duration::days(30), None, now),
+                    hit(HitKind::Audio, 11, -4.0, Duration::hours(2), None, now),
+                ],
+                expected_top: (HitKind::Audio, 11),
+            },
+            LabeledQuery {
+                // Garbled low confidence OCR loses to a clean read of the same text
+                query: "quarterly revenue",
+                hits: vec![
+                    hit(HitKind::Ocr, 20, -5.0, Duration::hours(1), Some(0.2), now),
+                    hit(HitKind::Ocr, 21, -4.6, Duration::hours(3), Some(0.97), now),
+                ],
+                expected_top: (HitKind::Ocr, 21),
+            },
+            LabeledQuery {
+                // Strong document match beats weak recent ui text
+                query: "lease agreement",
+                hits: vec![
+                    hit(HitKind::Document, 30, -9.0, Duration::days(4), None, now),
+                    hit(HitKind::Ui, 31, -2.0, Duration::minutes(10), None, now),
+                    hit(HitKind::Ocr, 32, -2.5, Duration::hours(6), Some(0.9), now),
+                ],
+                expected_top: (HitKind::Document, 30),
+            },
+            LabeledQuery {
+                // Same relevance and age, transcript is weighted below the screen
+                query: "deploy failed",
+                hits: vec![
+                    hit(HitKind::Audio, 40, -3.0, Duration::hours(1), None, now),
+                    hit(HitKind::Ocr, 41, -3.0, Duration::hours(1), Some(1.0), now),
+                ],
+                expected_top: (HitKind::Ocr, 41),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_default_weights_match_labeled_queries() {
+        let now = Utc::now();
+        let weights = RankingWeights::default();
+        for labeled in labeled_queries(now) {
+            let ranked = rank_hits(&labeled.hits, &weights, now);
+            let top = &labeled.hits[ranked[0].0];
+            assert_eq!(
+                (top.kind, top.row_id.0),
+                labeled.expected_top,
+                "wrong top hit for '{}'",
+                labeled.query
+            );
+        }
+    }
+
+    #[test]
+    fn test_scores_are_returned_in_descending_order() {
+        let now = Utc::now();
+        for labeled in labeled_queries(now) {
+            let ranked = rank_hits(&labeled.hits, &RankingWeights::default(), now);
+            assert_eq!(ranked.len(), labeled.hits.len());
+            assert!(ranked.windows(2).all(|w| w[0].1 >= w[1].1));
+            assert!(ranked
+                .iter()
+                .all(|(_, score)| *score > 0.0 && *score <= 1.0));
+        }
+    }
+
+    #[test]
+    fn test_ties_break_on_row_id_so_pages_are_stable() {
+        let now = Utc::now();
+        let hits: Vec<Hit> = (1..=50)
+            .map(|id| hit(HitKind::Ocr, id, -2.0, Duration::hours(1), None, now))
+            .collect();
+        let ranked = rank_hits(&hits, &RankingWeights::default(), now);
+        let ids: Vec<i64> = ranked.iter().map(|(i, _)| hits[*i].row_id.0).collect();
+        assert_eq!(ids, (1..=50).rev().collect::<Vec<_>>());
+
+        // Same candidates in a different order give the same ranking
+        let reversed: Vec<Hit> = hits.iter().rev().cloned().collect();
+        let ranked = rank_hits(&reversed, &RankingWeights::default(), now);
+        let reversed_ids: Vec<i64> = ranked.iter().map(|(i, _)| reversed[*i].row_id.0).collect();
+        assert_eq!(ids, reversed_ids);
+    }
+
+    #[test]
+    fn test_weights_are_tunable() {
+        let now = Utc::now();
+        let hits = vec![
+            hit(HitKind::Ocr, 1, -8.0, Duration::days(10), None, now),
+            hit(HitKind::Ocr, 2, -1.0, Duration::minutes(1), None, now),
+        ];
+        let recency_only = RankingWeights {
+            relevance: 0.0,
+            recency: 1.0,
+            ..Default::default()
+        };
+        let ranked = rank_hits(&hits, &recency_only, now);
+        assert_eq!(hits[ranked[0].0].row_id.0, 2);
+
+        let relevance_only = RankingWeights {
+            relevance: 1.0,
+            recency: 0.0,
+            ..Default::default()
+        };
+        let ranked = rank_hits(&hits, &relevance_only, now);
+        assert_eq!(hits[ranked[0].0].row_id.0, 1);
+    }
+
+    #[test]
+    fn test_load_weights_from_settings() {
+        let dir = tempdir().unwrap();
+        assert_eq!(RankingWeights::load(dir.path()), RankingWeights::default());
+
+        fs::write(
+            RankingWeights::path(dir.path()),
+            r#"{"recency_half_life_hours": 24, "audio": 1.2}"#,
+        )
+        .unwrap();
+        let weights = RankingWeights::load(dir.path());
+        assert_eq!(weights.recency_half_life_hours, 24.0);
+        assert_eq!(weights.audio, 1.2);
+        assert_eq!(weights.ocr, RankingWeights::default().ocr);
+
+        fs::write(RankingWeights::path(dir.path()), r#"{"recency": -1}"#).unwrap();
+        assert_eq!(RankingWeights::load(dir.path()), RankingWeights::default());
+
+        fs::write(RankingWeights::path(dir.path()), "not json").unwrap();
+        assert_eq!(RankingWeights::load(dir.path()), RankingWeights::default());
+    }
+
+    #[test]
+    fn test_ocr_confidence_from_text_json() {
+        let tesseract = r#"[{"text":"a","confidence":"90"},{"text":"b","confidence":"70"}]"#;
+        assert!((ocr_confidence(tesseract).unwrap() - 0.8).abs() < 1e-9);
+
+        let apple = r#"[{"text":"a","conf":"0.5"}]"#;
+        assert_eq!(ocr_confidence(apple), Some(0.5));
+
+        assert_eq!(ocr_confidence("[]"), None);
+        assert_eq!(ocr_confidence("not json"), None);
+    }
+}
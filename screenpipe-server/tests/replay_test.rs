@@ -0,0 +1,67 @@
+#[cfg(test)]
+mod tests {
+    use screenpipe_audio::AudioTranscriptionEngine;
+    use screenpipe_server::cli::{CliVadEngine, CliVadSensitivity};
+    use screenpipe_server::replay::{parse_speed, run_replay, ReplayOptions};
+    use screenpipe_vision::OcrEngine;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tempfile::tempdir;
+
+    fn options(from: PathBuf, output_dir: PathBuf) -> ReplayOptions {
+        ReplayOptions {
+            from,
+            speed: 10.0,
+            preserve_timestamps: false,
+            output_dir,
+            fps: 1.0,
+            video_chunk_duration: Duration::from_secs(60),
+            ocr_engine: Arc::new(OcrEngine::Tesseract),
+            audio_transcription_engine: Arc::new(AudioTranscriptionEngine::WhisperTiny),
+            vad_engine: CliVadEngine::Silero,
+            vad_sensitivity: CliVadSensitivity::High,
+            deepgram_api_key: None,
+            languages: vec![],
+            use_pii_removal: false,
+        }
+    }
+
+    #[test]
+    fn test_parse_speed() {
+        assert_eq!(parse_speed("10x"), Ok(10.0));
+        assert_eq!(parse_speed("0.5X"), Ok(0.5));
+        assert_eq!(parse_speed("2"), Ok(2.0));
+        assert!(parse_speed("0x").is_err());
+        assert!(parse_speed("-1x").is_err());
+        assert!(parse_speed("fast").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_replay_empty_export_does_not_create_scratch_db() {
+        let source = tempdir().unwrap();
+        std::fs::create_dir_all(source.path().join("frames")).unwrap();
+        std::fs::create_dir_all(source.path().join("audio")).unwrap();
+        let output = tempdir().unwrap();
+
+        let result = run_replay(options(
+            source.path().to_path_buf(),
+            output.path().join("replay"),
+        ))
+        .await;
+
+        assert!(result.is_err());
+        assert!(!output.path().join("replay").exists());
+    }
+
+    #[tokio::test]
+    async fn test_replay_missing_source() {
+        let output = tempdir().unwrap();
+        let result = run_replay(options(
+            PathBuf::from("/definitely/not/here"),
+            output.path().to_path_buf(),
+        ))
+        .await;
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,297 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+
+    use chrono::{DateTime, Duration, Utc};
+    use screenpipe_server::{
+        db_types::{RetentionRow, TagContentType},
+        retention::{
+            normalize_app_name, AppRetention, RetentionDecision, RetentionManager, RetentionPolicy,
+            RetentionSettings,
+        },
+        DatabaseManager,
+    };
+    use screenpipe_vision::OcrEngine;
+
+    fn settings() -> RetentionSettings {
+        RetentionSettings {
+            global: RetentionPolicy::days(30),
+            apps: BTreeMap::from([
+                ("code".to_string(), RetentionPolicy::days(365)),
+                ("slack".to_string(), RetentionPolicy::days(7)),
+                ("notes".to_string(), RetentionPolicy::default()),
+            ]),
+            disk_budget_gb: None,
+            classes: BTreeMap::new(),
+        }
+    }
+
+    fn row(now: DateTime<Utc>, apps: &str, age_days: i64, tagged: bool) -> RetentionRow {
+        RetentionRow {
+            id: 1,
+            timestamp: now - Duration::days(age_days),
+            app_names: apps.to_string(),
+            file_path: String::new(),
+            tagged,
+            retention_class: None,
+        }
+    }
+
+    #[test]
+    fn test_app_override_replaces_global_policy() {
+        let settings = settings();
+        let now = Utc::now();
+
+        assert_eq!(
+            settings.decide(&row(now, "Code", 100, false), now),
+            RetentionDecision::Keep
+        );
+        assert_eq!(
+            settings.decide(&row(now, "Code.exe", 400, false), now),
+            RetentionDecision::Delete
+        );
+        assert_eq!(
+            settings.decide(&row(now, "Slack", 10, false), now),
+            RetentionDecision::Delete
+        );
+        assert_eq!(
+            settings.decide(&row(now, "Arc", 10, false), now),
+            RetentionDecision::Keep
+        );
+        assert_eq!(
+            settings.decide(&row(now, "Arc", 40, false), now),
+            RetentionDecision::Delete
+        );
+        // Audio and frames without OCR text have no app and follow the global policy
+        assert_eq!(
+            settings.decide(&row(now, "", 40, false), now),
+            RetentionDecision::Delete
+        );
+        assert_eq!(
+            settings.decide(&row(now, "", 20, false), now),
+            RetentionDecision::Keep
+        );
+    }
+
+    #[test]
+    fn test_tags_beat_every_policy() {
+        let settings = settings();
+        let now = Utc::now();
+
+        assert_eq!(
+            settings.decide(&row(now, "Slack", 10, true), now),
+            RetentionDecision::KeepTagged
+        );
+        assert_eq!(
+            settings.decide(&row(now, "", 4000, true), now),
+            RetentionDecision::KeepTagged
+        );
+        // Not past its policy yet, the tag doesn't matter
+        assert_eq!(
+            settings.decide(&row(now, "Slack", 1, true), now),
+            RetentionDecision::Keep
+        );
+    }
+
+    #[test]
+    fn test_row_with_several_apps_keeps_the_longest_policy() {
+        let settings = settings();
+        let now = Utc::now();
+
+        assert_eq!(
+            settings.decide(&row(now, "Slack\nCode", 100, false), now),
+            RetentionDecision::Keep
+        );
+        assert_eq!(
+            settings.decide(&row(now, "Slack\nArc", 20, false), now),
+            RetentionDecision::Keep
+        );
+        assert_eq!(
+            settings.decide(&row(now, "Slack\nArc", 40, false), now),
+            RetentionDecision::Delete
+        );
+        assert_eq!(
+            settings.decide(&row(now, "Slack\nNotes", 4000, false), now),
+            RetentionDecision::Keep
+        );
+    }
+
+    #[test]
+    fn test_default_settings_keep_everything() {
+        let settings = RetentionSettings::default();
+        let now = Utc::now();
+
+        assert_eq!(settings.earliest_cutoff(now), None);
+        assert_eq!(
+            settings.decide(&row(now, "Slack", 4000, false), now),
+            RetentionDecision::Keep
+        );
+        assert_eq!(
+            self::settings().earliest_cutoff(now),
+            Some(now - Duration::days(7))
+        );
+    }
+
+    #[test]
+    fn test_validated_normalizes_and_rejects() {
+        assert_eq!(normalize_app_name("  Google Chrome.APP "), "google chrome");
+        assert_eq!(normalize_app_name("Code.exe"), "code");
+
+        let settings = RetentionSettings {
+            apps: BTreeMap::from([("Slack.app".to_string(), RetentionPolicy::days(7))]),
+            ..Default::default()
+        }
+        .validated()
+        .unwrap();
+        assert_eq!(settings.apps.keys().collect::<Vec<_>>(), vec!["slack"]);
+
+        let duplicate = RetentionSettings {
+            apps: BTreeMap::from([
+                ("Slack".to_string(), RetentionPolicy::days(7)),
+                ("slack.exe".to_string(), RetentionPolicy::days(30)),
+            ]),
+            ..Default::default()
+        };
+        assert!(duplicate.validated().is_err());
+
+        let zero = RetentionSettings {
+            global: RetentionPolicy::days(0),
+            ..Default::default()
+        };
+        assert!(zero.validated().is_err());
+
+        let budget = RetentionSettings {
+            disk_budget_gb: Some(-1.0),
+            ..Default::default()
+        };
+        assert!(budget.validated().is_err());
+    }
+
+    #[test]
+    fn test_budget_warnings() {
+        let now = Utc::now();
+        let gb = 1024 * 1024 * 1024;
+        let usage = vec![
+            AppRetention {
+                app_name: "code".to_string(),
+                max_age_days: Some(365),
+                bytes: 10 * gb,
+                oldest: Some(now - Duration::days(10)),
+                ..Default::default()
+            },
+            AppRetention {
+                app_name: "slack".to_string(),
+                max_age_days: Some(7),
+                bytes: gb,
+                oldest: Some(now - Duration::days(10)),
+                ..Default::default()
+            },
+        ];
+
+        let mut settings = settings();
+        assert!(settings.budget_warnings(&usage, now).is_empty());
+
+        // 1 GB a day of code kept for a year is far over 100 GB
+        settings.disk_budget_gb = Some(100.0);
+        let warnings = settings.budget_warnings(&usage, now);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("100.0 GB"));
+
+        settings.disk_budget_gb = Some(1000.0);
+        assert!(settings.budget_warnings(&usage, now).is_empty());
+
+        let mut forever = usage.clone();
+        forever[0].max_age_days = None;
+        let warnings = settings.budget_warnings(&forever, now);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("code kept forever"));
+    }
+
+    #[tokio::test]
+    async fn test_prune_applies_overrides_per_row() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Arc::new(DatabaseManager::new("sqlite::memory:").await.unwrap());
+        let now = Utc::now();
+        let old = Some(now - Duration::days(40));
+
+        let slack_file = dir.path().join("slack.mp4");
+        let code_file = dir.path().join("code.mp4");
+        std::fs::write(&slack_file, vec![0u8; 2000]).unwrap();
+        std::fs::write(&code_file, vec![0u8; 1000]).unwrap();
+
+        let mut frames = Vec::new();
+        for (file, apps) in [
+            (&slack_file, vec!["Slack", "Slack"]),
+            (&code_file, vec!["Code", "Slack"]),
+        ] {
+            db.insert_video_chunk(file.to_str().unwrap(), "test_device")
+                .await
+                .unwrap();
+            for app in apps {
+                let frame_id = db.insert_frame("test_device", old).await.unwrap();
+                db.insert_ocr_text(
+                    frame_id,
+                    "text",
+                    "",
+                    app,
+                    "",
+                    Arc::new(OcrEngine::Tesseract),
+                    false,
+                )
+                .await
+                .unwrap();
+                frames.push(frame_id);
+            }
+        }
+        db.add_tags(frames[3], TagContentType::Vision, vec!["keep".to_string()])
+            .await
+            .unwrap();
+        db.insert_audio_chunk("audio.mp4").await.unwrap();
+
+        let retention = RetentionManager::new(db.clone(), dir.path().to_path_buf(), None);
+        let warnings = retention.update(settings(), now).await.unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(
+            RetentionSettings::load(dir.path()),
+            retention.settings().await
+        );
+
+        let report = retention.report(now).await.unwrap();
+        let slack = report.apps.iter().find(|a| a.app_name == "slack").unwrap();
+        assert_eq!(slack.rows, 3);
+        assert_eq!(slack.bytes, 2500);
+        assert_eq!(slack.scheduled_rows, 2);
+        assert_eq!(slack.scheduled_bytes, 2000);
+        assert_eq!(slack.tagged_rows, 1);
+        assert_eq!(slack.max_age_days, Some(7));
+        assert!(slack.overridden);
+        let code = report.apps.iter().find(|a| a.app_name == "code").unwrap();
+        assert_eq!(code.scheduled_rows, 0);
+        assert_eq!(report.total_bytes, 3000);
+
+        let summary = retention.prune(now).await.unwrap();
+        assert_eq!(summary.apps.get("slack"), Some(&2));
+        assert_eq!(summary.tagged_kept, 1);
+        assert_eq!(summary.audio, 0);
+        assert_eq!(summary.files_removed, 1);
+        assert!(!slack_file.exists());
+        assert!(code_file.exists());
+
+        let remaining: Vec<i64> = sqlx::query_scalar("SELECT id FROM frames ORDER BY id")
+            .fetch_all(&db.pool)
+            .await
+            .unwrap();
+        assert_eq!(remaining, vec![frames[2], frames[3]]);
+        let chunks: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM video_chunks")
+            .fetch_one(&db.pool)
+            .await
+            .unwrap();
+        assert_eq!(chunks, 1);
+
+        // Nothing left past its policy, a second run is a no-op
+        let summary = retention.prune(now).await.unwrap();
+        assert!(summary.apps.is_empty());
+        assert_eq!(summary.tagged_kept, 1);
+    }
+}
@@ -0,0 +1,366 @@
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Duration, Local, NaiveDate, TimeZone, Utc};
+    use screenpipe_server::db_types::{ContentType, TextFilter};
+    use screenpipe_server::search_query::{parse_query, ParsedQuery, QueryError, Span};
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 6, 15, 12, 0, 0).unwrap()
+    }
+
+    fn parse(input: &str) -> ParsedQuery {
+        parse_query(input, now()).unwrap_or_else(|e| panic!("'{}' failed: {:?}", input, e))
+    }
+
+    fn error(input: &str) -> QueryError {
+        match parse_query(input, now()) {
+            Ok(parsed) => panic!("'{}' parsed as {:?}", input, parsed),
+            Err(e) => {
+                assert!(!e.hint.is_empty(), "no hint for '{}'", input);
+                e
+            }
+        }
+    }
+
+    fn local_midnight(year: i32, month: u32, day: u32) -> DateTime<Utc> {
+        let day = NaiveDate::from_ymd_opt(year, month, day).unwrap();
+        Local
+            .from_local_datetime(&day.and_hms_opt(0, 0, 0).unwrap())
+            .earliest()
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn all_of(groups: &[&[&str]]) -> TextFilter {
+        TextFilter {
+            all_of: groups
+                .iter()
+                .map(|g| g.iter().map(|v| v.to_string()).collect())
+                .collect(),
+            none_of: vec![],
+        }
+    }
+
+    #[test]
+    fn test_empty_query() {
+        assert_eq!(parse(""), ParsedQuery::default());
+        assert_eq!(parse("   \t "), ParsedQuery::default());
+    }
+
+    #[test]
+    fn test_terms_are_joined_with_and() {
+        assert_eq!(parse("deploy").text, "\"deploy\"");
+        assert_eq!(parse("deploy release").text, "\"deploy\" AND \"release\"");
+        assert_eq!(
+            parse("deploy AND release").text,
+            "\"deploy\" AND \"release\""
+        );
+        assert_eq!(parse("deplo*").text, "\"deplo\"*");
+    }
+
+    #[test]
+    fn test_or_not_and_nesting() {
+        assert_eq!(
+            parse("deploy OR release").text,
+            "(\"deploy\" OR \"release\")"
+        );
+        assert_eq!(
+            parse("(deploy OR release) notes").text,
+            "(\"deploy\" OR \"release\") AND \"notes\""
+        );
+        assert_eq!(parse("deploy -staging").text, "\"deploy\" NOT \"staging\"");
+        assert_eq!(
+            parse("deploy NOT staging").text,
+            "\"deploy\" NOT \"staging\""
+        );
+        assert_eq!(
+            parse("a b -c -d").text,
+            "(\"a\" AND \"b\") NOT \"c\" NOT \"d\""
+        );
+        assert_eq!(
+            parse("(a OR (b c -d)) -(e OR f)").text,
+            "(\"a\" OR ((\"b\" AND \"c\") NOT \"d\")) NOT (\"e\" OR \"f\")"
+        );
+        // OR binds tighter than AND
+        assert_eq!(
+            parse("a b OR c d").text,
+            "\"a\" AND (\"b\" OR \"c\") AND \"d\""
+        );
+        assert_eq!(parse("(a b) OR c").text, "((\"a\" AND \"b\") OR \"c\")");
+        // Double negation cancels out
+        assert_eq!(parse("a --b").text, "\"a\" AND \"b\"");
+    }
+
+    #[test]
+    fn test_lowercase_operators_are_words() {
+        assert_eq!(
+            parse("rock or roll").text,
+            "\"rock\" AND \"or\" AND \"roll\""
+        );
+        assert_eq!(parse("\"OR\"").text, "\"OR\"");
+    }
+
+    #[test]
+    fn test_quoting() {
+        assert_eq!(parse("\"release notes\"").text, "\"release notes\"");
+        assert_eq!(parse(r#""say \"hi\" now""#).text, "\"say \"\"hi\"\" now\"");
+        assert_eq!(parse(r#""back\\slash""#).text, "\"back\\slash\"");
+        // Punctuation and fts5 syntax are searched as text
+        assert_eq!(parse("NEAR(a b)").text, "\"NEAR\" AND \"a\" AND \"b\"");
+        assert_eq!(parse("c++ 10:30").text, "\"c++\" AND \"10:30\"");
+        assert_eq!(
+            parse("\"http://example.com\"").text,
+            "\"http://example.com\""
+        );
+        assert_eq!(parse("well-known").text, "\"well-known\"");
+    }
+
+    #[test]
+    fn test_unicode() {
+        assert_eq!(parse("café crème").text, "\"café\" AND \"crème\"");
+        assert_eq!(
+            parse("\"会議 メモ\" -über").text,
+            "\"会議 メモ\" NOT \"über\""
+        );
+        assert_eq!(parse("🚀*").text, "\"🚀\"*");
+        let parsed = parse("app:微信 window:\"日本語 タイトル\"");
+        assert_eq!(parsed.filters.app_name, all_of(&[&["微信"]]));
+        assert_eq!(parsed.filters.window_name, all_of(&[&["日本語 タイトル"]]));
+
+        // Spans count characters, not bytes
+        let e = error("日本語 foo:bar");
+        assert_eq!(e.span, Span { start: 4, end: 7 });
+    }
+
+    #[test]
+    fn test_request_example() {
+        let parsed =
+            parse("app:slack (deploy OR release) -app:spotify after:2024-06-01 tag:important");
+        assert_eq!(parsed.text, "(\"deploy\" OR \"release\")");
+        assert_eq!(
+            parsed.filters.app_name,
+            TextFilter {
+                all_of: vec![vec!["slack".to_string()]],
+                none_of: vec!["spotify".to_string()],
+            }
+        );
+        assert_eq!(parsed.filters.tags, all_of(&[&["important"]]));
+        assert_eq!(parsed.start_time, Some(local_midnight(2024, 6, 1)));
+        assert_eq!(parsed.end_time, None);
+    }
+
+    #[test]
+    fn test_filter_groups() {
+        let parsed = parse("(app:slack OR app:discord) app:web -(window:music OR window:video)");
+        assert_eq!(
+            parsed.filters.app_name,
+            all_of(&[&["slack", "discord"], &["web"]])
+        );
+        assert_eq!(
+            parsed.filters.window_name,
+            TextFilter {
+                all_of: vec![],
+                none_of: vec!["music".to_string(), "video".to_string()],
+            }
+        );
+        assert_eq!(parsed.text, "");
+
+        // Filters grouped with and are the same as listing them
+        assert_eq!(
+            parse("(app:slack tag:x) deploy"),
+            parse("app:slack tag:x deploy")
+        );
+        // Field names are case insensitive
+        assert_eq!(parse("APP:Slack").filters.app_name, all_of(&[&["Slack"]]));
+    }
+
+    #[test]
+    fn test_type_and_speaker() {
+        assert_eq!(
+            parse("type:audio").filters.content_types,
+            Some(vec![ContentType::Audio])
+        );
+        assert_eq!(
+            parse("(type:ocr OR type:ui) meeting").filters.content_types,
+            Some(vec![ContentType::OCR, ContentType::UI])
+        );
+        assert_eq!(
+            parse("-type:document").filters.content_types,
+            Some(vec![
+                ContentType::OCR,
+                ContentType::Audio,
+                ContentType::UI,
+                ContentType::Pipe
+            ])
+        );
+        assert_eq!(
+            parse("type:pipe").filters.content_types,
+            Some(vec![ContentType::Pipe])
+        );
+        // Contradicting types leave nothing to search
+        assert_eq!(
+            parse("type:ocr type:audio").filters.content_types,
+            Some(vec![])
+        );
+
+        assert_eq!(parse("speaker:3").speaker_ids, Some(vec![3]));
+        assert_eq!(
+            parse("(speaker:1 OR speaker:2) speaker:2").speaker_ids,
+            Some(vec![2])
+        );
+    }
+
+    #[test]
+    fn test_date_formats() {
+        let n = now();
+        assert_eq!(
+            parse("after:2024-06-01").start_time,
+            Some(local_midnight(2024, 6, 1))
+        );
+        assert_eq!(
+            parse("before:2024-06-01T14:30:00Z").end_time,
+            Some(Utc.with_ymd_and_hms(2024, 6, 1, 14, 30, 0).unwrap())
+        );
+        assert_eq!(
+            parse("after:2024-06-01T09:15:00+02:00").start_time,
+            Some(Utc.with_ymd_and_hms(2024, 6, 1, 7, 15, 0).unwrap())
+        );
+        let local = |h, m, s| {
+            Local
+                .with_ymd_and_hms(2024, 6, 1, h, m, s)
+                .earliest()
+                .unwrap()
+                .with_timezone(&Utc)
+        };
+        assert_eq!(
+            parse("after:2024-06-01T14:30").start_time,
+            Some(local(14, 30, 0))
+        );
+        assert_eq!(
+            parse("after:2024-06-01T14:30:15").start_time,
+            Some(local(14, 30, 15))
+        );
+        assert_eq!(
+            parse("after:30m").start_time,
+            Some(n - Duration::minutes(30))
+        );
+        assert_eq!(parse("after:12h").start_time, Some(n - Duration::hours(12)));
+        assert_eq!(parse("after:7d").start_time, Some(n - Duration::days(7)));
+        assert_eq!(parse("after:2w").start_time, Some(n - Duration::weeks(2)));
+
+        let today = n.with_timezone(&Local).date_naive();
+        let midnight = |day: NaiveDate| {
+            Local
+                .from_local_datetime(&day.and_hms_opt(0, 0, 0).unwrap())
+                .earliest()
+                .unwrap()
+                .with_timezone(&Utc)
+        };
+        assert_eq!(parse("after:today").start_time, Some(midnight(today)));
+        let on = parse("on:yesterday");
+        assert_eq!(on.start_time, Some(midnight(today.pred_opt().unwrap())));
+        assert_eq!(on.end_time, Some(midnight(today)));
+        assert_eq!(
+            parse("on:2024-02-29").end_time,
+            Some(local_midnight(2024, 3, 1))
+        );
+    }
+
+    #[test]
+    fn test_date_ranges_combine() {
+        let parsed = parse("after:2024-06-01 after:2024-06-03 before:2024-06-10 before:2024-06-08");
+        assert_eq!(parsed.start_time, Some(local_midnight(2024, 6, 3)));
+        assert_eq!(parsed.end_time, Some(local_midnight(2024, 6, 8)));
+
+        // Not after a date is before it
+        let parsed = parse("-after:2024-06-01 -before:2024-05-01");
+        assert_eq!(parsed.end_time, Some(local_midnight(2024, 6, 1)));
+        assert_eq!(parsed.start_time, Some(local_midnight(2024, 5, 1)));
+
+        let parsed = parse("on:2024-06-05 after:2024-06-05T12:00:00Z");
+        assert_eq!(
+            parsed.start_time,
+            Some(Utc.with_ymd_and_hms(2024, 6, 5, 12, 0, 0).unwrap())
+                .max(Some(local_midnight(2024, 6, 5)))
+        );
+    }
+
+    #[test]
+    fn test_syntax_errors_point_at_the_problem() {
+        let e = error("deploy \"release notes");
+        assert_eq!(e.span, Span { start: 7, end: 21 });
+        assert!(e.message.contains("unterminated"));
+
+        let e = error("(deploy OR release");
+        assert_eq!(e.span, Span { start: 0, end: 1 });
+
+        let e = error("deploy) release");
+        assert_eq!(e.span, Span { start: 6, end: 7 });
+
+        let e = error("deploy ()");
+        assert_eq!(e.span, Span { start: 7, end: 9 });
+
+        let e = error("deploy OR");
+        assert_eq!(e.span, Span { start: 7, end: 9 });
+        let e = error("OR deploy");
+        assert_eq!(e.span, Span { start: 0, end: 2 });
+        let e = error("deploy AND");
+        assert_eq!(e.span, Span { start: 7, end: 10 });
+        let e = error("deploy NOT");
+        assert_eq!(e.span, Span { start: 7, end: 10 });
+        let e = error("deploy - release");
+        assert_eq!(e.span, Span { start: 7, end: 8 });
+        let e = error("(deploy -)");
+        assert_eq!(e.span, Span { start: 8, end: 9 });
+    }
+
+    #[test]
+    fn test_filter_errors_point_at_the_problem() {
+        let e = error("deploy url:github.com");
+        assert_eq!(e.span, Span { start: 7, end: 10 });
+        assert!(e.hint.contains("app"));
+
+        let e = error("https://example.com");
+        assert_eq!(e.span, Span { start: 0, end: 5 });
+        assert!(e.hint.contains("quotes"));
+
+        let e = error("app: slack");
+        assert_eq!(e.span, Span { start: 0, end: 4 });
+
+        let e = error("x after:last-week");
+        assert_eq!(e.span, Span { start: 8, end: 17 });
+        assert!(e.hint.contains("2024-06-01"));
+        error("after:2024-13-01");
+        error("after:2024-06-01T25:00");
+        error("after:-7d");
+        error("after:99999999w");
+        error("on:2024-06-01T10:00");
+
+        let e = error("type:video");
+        assert_eq!(e.span, Span { start: 5, end: 10 });
+        let e = error("speaker:bob");
+        assert_eq!(e.span, Span { start: 8, end: 11 });
+    }
+
+    #[test]
+    fn test_unsupported_combinations() {
+        // A term or a filter, can't be expressed as one filter plus a text search
+        let e = error("x (app:slack OR deploy)");
+        assert_eq!(e.span, Span { start: 2, end: 23 });
+        assert!(e.hint.contains("app:slack OR app:discord"));
+
+        let e = error("(app:slack OR window:deploy)");
+        assert_eq!(e.span, Span { start: 0, end: 28 });
+        let e = error("-(app:slack tag:x)");
+        assert_eq!(e.span, Span { start: 1, end: 18 });
+        error("(after:2024-06-01 OR after:2024-07-01)");
+        error("-speaker:2");
+        error("-on:today");
+
+        // Only leaving words out
+        let e = error("-staging");
+        assert_eq!(e.span, Span { start: 0, end: 8 });
+        error("app:slack -staging");
+        error("deploy (a OR -b)");
+    }
+}
@@ -0,0 +1,323 @@
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Duration as ChronoDuration, Utc};
+    use screenpipe_audio::{AudioDevice, DeviceType};
+    use screenpipe_server::db_types::{
+        CaptureSession, CaptureWrite, ExportFormat, FrameWrite, SessionEndReason, WindowOcrWrite,
+    };
+    use screenpipe_server::pipe_manager::PipeInfo;
+    use screenpipe_server::sessions::{
+        session_items, subscribed_pipes, ExportItem, SessionError, SessionEvent, SessionManager,
+        StartSessionRequest,
+    };
+    use screenpipe_server::storage_mode::StorageMode;
+    use screenpipe_server::DatabaseManager;
+    use serde_json::json;
+    use std::path::PathBuf;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tokio::sync::broadcast::Receiver;
+
+    /// A clock the test moves by hand.
+    #[derive(Clone)]
+    struct Clock(Arc<Mutex<DateTime<Utc>>>);
+
+    impl Clock {
+        fn new(start: DateTime<Utc>) -> Self {
+            Self(Arc::new(Mutex::new(start)))
+        }
+
+        fn now(&self) -> DateTime<Utc> {
+            *self.0.lock().unwrap()
+        }
+
+        fn set(&self, now: DateTime<Utc>) {
+            *self.0.lock().unwrap() = now;
+        }
+    }
+
+    async fn setup(clock: &Clock, export_dir: PathBuf) -> (Arc<DatabaseManager>, SessionManager) {
+        let db = Arc::new(DatabaseManager::new("sqlite::memory:").await.unwrap());
+        db.insert_video_chunk("video.mp4", "monitor_1")
+            .await
+            .unwrap();
+        let clock = clock.clone();
+        let sessions = SessionManager::new(db.clone(), export_dir)
+            .with_max_duration(Duration::from_secs(3600))
+            .with_system_time(move || clock.now());
+        (db, sessions)
+    }
+
+    fn start(name: &str, auto_export: Option<ExportFormat>) -> StartSessionRequest {
+        StartSessionRequest {
+            name: name.to_string(),
+            auto_export,
+        }
+    }
+
+    fn frame(timestamp: DateTime<Utc>, app_name: &str, text: &str) -> CaptureWrite {
+        CaptureWrite::Frame(FrameWrite {
+            device_name: "monitor_1".to_string(),
+            video_chunk_id: None,
+            timestamp: Some(timestamp),
+            windows: vec![WindowOcrWrite {
+                text: text.to_string(),
+                text_json: "[]".to_string(),
+                app_name: app_name.to_string(),
+                window_name: "window".to_string(),
+                ocr_engine: "Tesseract".to_string(),
+                focused: true,
+                raw_text: None,
+                corrected_by: None,
+            }],
+            storage_mode: StorageMode::Full,
+            thumbnail_path: None,
+            window_layout: None,
+        })
+    }
+
+    fn drain(events: &mut Receiver<SessionEvent>) -> Vec<&'static str> {
+        let mut types = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            types.push(event.event_type());
+        }
+        types
+    }
+
+    #[tokio::test]
+    async fn test_overlapping_sessions_are_rejected() {
+        let clock = Clock::new(Utc::now());
+        let (_db, sessions) = setup(&clock, PathBuf::from("")).await;
+        let mut events = sessions.subscribe();
+
+        let interview = sessions
+            .start(start("ux interview #4", None))
+            .await
+            .unwrap();
+        assert_eq!(interview.name, "ux interview #4");
+        assert_eq!(interview.ended_at, None);
+
+        match sessions.start(start("standup", None)).await {
+            Err(SessionError::AlreadyOpen(open)) => assert_eq!(open.id, interview.id),
+            other => panic!("expected the overlap to be rejected, got {:?}", other),
+        }
+        assert!(matches!(
+            sessions.start(start("  ", None)).await,
+            Err(SessionError::Invalid(_))
+        ));
+        assert_eq!(sessions.current().await.unwrap(), Some(interview.clone()));
+
+        clock.set(clock.now() + ChronoDuration::minutes(30));
+        let stopped = sessions.stop().await.unwrap();
+        assert_eq!(stopped.id, interview.id);
+        assert_eq!(stopped.ended_at, Some(clock.now()));
+        assert_eq!(stopped.end_reason, Some(SessionEndReason::Stopped));
+        assert!(matches!(sessions.stop().await, Err(SessionError::NotOpen)));
+        assert_eq!(sessions.current().await.unwrap(), None);
+
+        // The next one may start once the first stopped
+        sessions.start(start("standup", None)).await.unwrap();
+        assert_eq!(
+            drain(&mut events),
+            vec!["session_started", "session_ended", "session_started"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_forgotten_session_is_closed_with_a_warning() {
+        let started = Utc::now();
+        let clock = Clock::new(started);
+        let (_db, sessions) = setup(&clock, PathBuf::from("")).await;
+        let mut events = sessions.subscribe();
+        sessions.start(start("focus block", None)).await.unwrap();
+
+        clock.set(started + ChronoDuration::minutes(59));
+        assert_eq!(sessions.close_expired().await.unwrap(), None);
+
+        clock.set(started + ChronoDuration::minutes(61));
+        let closed = sessions.close_expired().await.unwrap().unwrap();
+        assert_eq!(closed.end_reason, Some(SessionEndReason::TimedOut));
+        assert_eq!(closed.ended_at, Some(clock.now()));
+        assert_eq!(sessions.current().await.unwrap(), None);
+        assert_eq!(sessions.close_expired().await.unwrap(), None);
+
+        let _started = events.try_recv().unwrap();
+        match events.try_recv().unwrap() {
+            SessionEvent::SessionTimedOut {
+                session,
+                max_duration_seconds,
+            } => {
+                assert_eq!(session.name, "focus block");
+                assert_eq!(max_duration_seconds, 3600);
+            }
+            other => panic!("expected a timeout warning, got {:?}", other),
+        }
+        // Ended exactly once
+        assert_eq!(drain(&mut events), vec!["session_ended"]);
+    }
+
+    #[tokio::test]
+    async fn test_sessions_are_found_by_name() {
+        let clock = Clock::new(Utc::now() - ChronoDuration::hours(3));
+        let (_db, sessions) = setup(&clock, PathBuf::from("")).await;
+
+        let first = sessions.start(start("standup", None)).await.unwrap();
+        clock.set(first.started_at + ChronoDuration::minutes(15));
+        sessions.stop().await.unwrap();
+        clock.set(first.started_at + ChronoDuration::hours(1));
+        sessions.start(start("review", None)).await.unwrap();
+        clock.set(first.started_at + ChronoDuration::hours(2));
+        sessions.stop().await.unwrap();
+        let second = sessions.start(start("standup", None)).await.unwrap();
+
+        let standups = sessions.list(Some("standup"), 10, 0).await.unwrap();
+        assert_eq!(
+            standups.iter().map(|s| s.id).collect::<Vec<_>>(),
+            vec![second.id, first.id]
+        );
+        assert_eq!(sessions.list(None, 10, 0).await.unwrap().len(), 3);
+
+        // The latest one, still open, runs until now
+        clock.set(second.started_at + ChronoDuration::minutes(5));
+        assert_eq!(
+            sessions.time_range("standup").await.unwrap(),
+            (second.started_at, clock.now())
+        );
+        assert!(matches!(
+            sessions.time_range("retro").await,
+            Err(SessionError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_stopped_session_is_exported() {
+        let export_dir = tempfile::tempdir().unwrap();
+        let started = Utc::now() - ChronoDuration::minutes(10);
+        let clock = Clock::new(started);
+        let (db, sessions) = setup(&clock, export_dir.path().to_path_buf()).await;
+
+        db.write_capture(frame(
+            started - ChronoDuration::minutes(1),
+            "Slack",
+            "before the session",
+        ))
+        .await
+        .unwrap();
+        let session = sessions
+            .start(start("ux interview #4", Some(ExportFormat::Markdown)))
+            .await
+            .unwrap();
+        for (minute, text) in [
+            (1, "onboarding flow v2"),
+            (2, "onboarding flow v2"),
+            (3, "checkout step one"),
+        ] {
+            db.write_capture(frame(
+                started + ChronoDuration::minutes(minute),
+                "Figma",
+                text,
+            ))
+            .await
+            .unwrap();
+        }
+        let audio_chunk_id = db.insert_audio_chunk("audio.mp4").await.unwrap();
+        db.insert_audio_transcription(
+            audio_chunk_id,
+            "where would you click to pay",
+            0,
+            "",
+            &AudioDevice::new("microphone".to_string(), DeviceType::Input),
+            None,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+
+        clock.set(Utc::now() + ChronoDuration::minutes(1));
+        let stopped = sessions.stop().await.unwrap();
+        let path = PathBuf::from(stopped.export_path.clone().unwrap());
+        assert!(path.starts_with(export_dir.path()));
+        assert!(path
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .ends_with("_ux-interview-4.md"));
+        assert_eq!(
+            sessions.list(None, 1, 0).await.unwrap()[0].export_path,
+            stopped.export_path
+        );
+
+        let markdown = std::fs::read_to_string(&path).unwrap();
+        assert!(markdown.starts_with("# ux interview #4\n"));
+        assert_eq!(markdown.matches("onboarding flow v2").count(), 1);
+        assert!(markdown.contains("checkout step one"));
+        assert!(markdown.contains("audio · microphone"));
+        assert!(markdown.contains("where would you click to pay"));
+        assert!(!markdown.contains("before the session"));
+
+        let items = session_items(&db, &stopped).await.unwrap();
+        assert_eq!(items.len(), 3);
+        assert!(matches!(items.last(), Some(ExportItem::Audio { .. })));
+        assert!(session.export_path.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_json_export_lists_session_and_items() {
+        let export_dir = tempfile::tempdir().unwrap();
+        let started = Utc::now() - ChronoDuration::minutes(5);
+        let clock = Clock::new(started);
+        let (db, sessions) = setup(&clock, export_dir.path().to_path_buf()).await;
+
+        sessions
+            .start(start("../../etc", Some(ExportFormat::Json)))
+            .await
+            .unwrap();
+        db.write_capture(frame(
+            started + ChronoDuration::minutes(1),
+            "Terminal",
+            "cargo test",
+        ))
+        .await
+        .unwrap();
+        clock.set(started + ChronoDuration::minutes(2));
+        let stopped = sessions.stop().await.unwrap();
+
+        // The name can't take the export out of its directory
+        let path = PathBuf::from(stopped.export_path.clone().unwrap());
+        assert_eq!(path.parent().unwrap(), export_dir.path());
+        let export: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        let exported: CaptureSession = serde_json::from_value(export["session"].clone()).unwrap();
+        assert_eq!(exported.name, "../../etc");
+        assert_eq!(export["items"][0]["type"], "screen");
+        assert_eq!(export["items"][0]["text"], "cargo test");
+    }
+
+    #[test]
+    fn test_pipes_run_for_the_events_they_subscribe_to() {
+        let pipe = |id: &str, enabled: bool, config: serde_json::Value| PipeInfo {
+            id: id.to_string(),
+            enabled,
+            config,
+            source: String::new(),
+            port: None,
+        };
+        let pipes = vec![
+            pipe("summarizer", true, json!({"events": ["session_ended"]})),
+            pipe(
+                "tracker",
+                true,
+                json!({"events": ["session_started", "session_ended"]}),
+            ),
+            pipe("off", false, json!({"events": ["session_ended"]})),
+            pipe("cron", true, json!({"crons": []})),
+        ];
+        assert_eq!(
+            subscribed_pipes(&pipes, "session_ended"),
+            vec!["summarizer", "tracker"]
+        );
+        assert_eq!(subscribed_pipes(&pipes, "session_started"), vec!["tracker"]);
+        assert!(subscribed_pipes(&pipes, "session_timed_out").is_empty());
+    }
+}
@@ -0,0 +1,207 @@
+#[cfg(test)]
+mod tests {
+    use futures::{Stream, StreamExt};
+    use screenpipe_server::pipe_manager::PipeInfo;
+    use screenpipe_server::settings_watch::{
+        key_matches, pipe_settings, SettingsWatch, WatchEvent, COALESCE_WINDOW, HISTORY,
+    };
+    use serde_json::{json, Value};
+    use std::collections::BTreeMap;
+    use std::time::Duration;
+
+    fn settings(pairs: &[(&str, Value)]) -> BTreeMap<String, Value> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.clone()))
+            .collect()
+    }
+
+    fn patterns(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(|p| p.to_string()).collect()
+    }
+
+    async fn next(events: &mut (impl Stream<Item = WatchEvent> + Unpin)) -> WatchEvent {
+        tokio::time::timeout(Duration::from_secs(2), events.next())
+            .await
+            .expect("no event")
+            .unwrap()
+    }
+
+    async fn nothing_within(
+        events: &mut (impl Stream<Item = WatchEvent> + Unpin),
+        wait: Duration,
+    ) -> bool {
+        tokio::time::timeout(wait, events.next()).await.is_err()
+    }
+
+    /// Changed keys of a change event with their values.
+    fn changed(event: &WatchEvent) -> Vec<(String, Value)> {
+        match event {
+            WatchEvent::Change { changes, .. } => changes
+                .iter()
+                .map(|c| (c.key.clone(), c.value.clone()))
+                .collect(),
+            other => panic!("expected a change, got {:?}", other),
+        }
+    }
+
+    fn pipe(id: &str, enabled: bool, config: Value) -> PipeInfo {
+        PipeInfo {
+            id: id.to_string(),
+            enabled,
+            config,
+            source: String::new(),
+            port: None,
+        }
+    }
+
+    #[test]
+    fn test_key_patterns() {
+        assert!(key_matches("storage.mode", "storage.mode"));
+        assert!(!key_matches("storage", "storage.mode"));
+        assert!(!key_matches("storage.mode.x", "storage.mode"));
+        assert!(key_matches("pipes.*", "pipes.notes.config"));
+        assert!(key_matches("pipes.*", "pipes.notes.enabled"));
+        assert!(!key_matches("pipes.*", "pipes"));
+        assert!(key_matches("pipes.*.enabled", "pipes.notes.enabled"));
+        assert!(!key_matches("pipes.*.enabled", "pipes.notes.config"));
+        assert!(key_matches("*", "retention"));
+
+        let values = pipe_settings(&[pipe("notes", true, json!({ "interval": 60 }))]);
+        assert_eq!(
+            values,
+            settings(&[
+                ("pipes.notes.config", json!({ "interval": 60 })),
+                ("pipes.notes.enabled", json!(true)),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_watch_sends_current_values_then_coalesced_changes() {
+        let watch = SettingsWatch::new();
+        watch.update(
+            settings(&[
+                ("storage.mode", json!("full")),
+                ("pipes.notes.enabled", json!(false)),
+                ("pipes.notes.config", json!({ "interval": 60 })),
+            ]),
+            None,
+        );
+
+        let events = watch.watch(patterns(&["pipes.notes.*"]), None);
+        futures::pin_mut!(events);
+        assert_eq!(
+            next(&mut events).await,
+            WatchEvent::Snapshot {
+                id: 3,
+                values: settings(&[
+                    ("pipes.notes.config", json!({ "interval": 60 })),
+                    ("pipes.notes.enabled", json!(false)),
+                ]),
+            }
+        );
+
+        // Unwatched keys and values that didn't change send nothing
+        watch.update(
+            settings(&[
+                ("storage.mode", json!("text_only")),
+                ("pipes.notes.enabled", json!(false)),
+                ("pipes.notes.config", json!({ "interval": 60 })),
+            ]),
+            None,
+        );
+        assert!(nothing_within(&mut events, COALESCE_WINDOW * 2).await);
+
+        // Rapid changes are one event with the latest value of each key
+        for interval in [30, 20, 10] {
+            watch.update(
+                settings(&[
+                    ("storage.mode", json!("text_only")),
+                    ("pipes.notes.enabled", json!(true)),
+                    ("pipes.notes.config", json!({ "interval": interval })),
+                ]),
+                Some("desktop-app"),
+            );
+        }
+        let event = next(&mut events).await;
+        assert_eq!(event.id(), 8);
+        assert_eq!(
+            changed(&event),
+            vec![
+                ("pipes.notes.enabled".to_string(), json!(true)),
+                ("pipes.notes.config".to_string(), json!({ "interval": 10 })),
+            ]
+        );
+        let WatchEvent::Change { changes, .. } = &event else {
+            unreachable!()
+        };
+        assert!(changes
+            .iter()
+            .all(|c| c.source.as_deref() == Some("desktop-app")));
+
+        // A deleted pipe's settings become null
+        watch.update(settings(&[("storage.mode", json!("text_only"))]), None);
+        assert_eq!(
+            changed(&next(&mut events).await),
+            vec![
+                ("pipes.notes.config".to_string(), Value::Null),
+                ("pipes.notes.enabled".to_string(), Value::Null),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_resyncs_from_the_last_event_id() {
+        let watch = SettingsWatch::new();
+        let mode = |mode: &str| settings(&[("storage.mode", json!(mode))]);
+        watch.update(mode("full"), None);
+
+        let events = watch.watch(patterns(&["storage.mode"]), None);
+        futures::pin_mut!(events);
+        let last_seen = next(&mut events).await.id();
+        assert_eq!(last_seen, 1);
+        drop(events);
+
+        // Changes while disconnected are replayed as one event, nothing else is sent
+        watch.update(mode("text_only"), Some("cli"));
+        watch.update(mode("thumbnails"), Some("cli"));
+        let events = watch.watch(patterns(&["storage.mode"]), Some(last_seen));
+        futures::pin_mut!(events);
+        let missed = next(&mut events).await;
+        assert_eq!(missed.id(), 3);
+        assert_eq!(
+            changed(&missed),
+            vec![("storage.mode".to_string(), json!("thumbnails"))]
+        );
+        assert!(nothing_within(&mut events, COALESCE_WINDOW * 2).await);
+        drop(events);
+
+        // Up to date, nothing to send until the next change
+        let events = watch.watch(patterns(&["storage.mode"]), Some(3));
+        futures::pin_mut!(events);
+        assert!(nothing_within(&mut events, COALESCE_WINDOW * 2).await);
+        watch.update(mode("full"), None);
+        assert_eq!(next(&mut events).await.id(), 4);
+        drop(events);
+
+        // Too far back, or an id from before a restart, gets the current values again
+        for i in 0..HISTORY {
+            watch.update(
+                settings(&[("storage.mode", json!("full")), ("retention", json!(i))]),
+                None,
+            );
+        }
+        for last_event_id in [2, 10_000] {
+            let events = watch.watch(patterns(&["storage.mode"]), Some(last_event_id));
+            futures::pin_mut!(events);
+            assert_eq!(
+                next(&mut events).await,
+                WatchEvent::Snapshot {
+                    id: 4 + HISTORY as u64,
+                    values: mode("full"),
+                }
+            );
+        }
+    }
+}
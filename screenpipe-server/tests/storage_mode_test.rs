@@ -0,0 +1,217 @@
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration as ChronoDuration, Utc};
+    use screenpipe_server::db_types::{
+        CaptureOutcome, CaptureWrite, ContentType, FrameWrite, RetentionKind, SearchResult,
+        WindowOcrWrite,
+    };
+    use screenpipe_server::storage_mode::{
+        thumbnail_path, write_thumbnail, StorageMode, StorageModeSetting,
+    };
+    use screenpipe_server::DatabaseManager;
+
+    async fn setup_test_db() -> DatabaseManager {
+        DatabaseManager::new("sqlite::memory:").await.unwrap()
+    }
+
+    fn frame(text: &str, storage_mode: StorageMode, thumbnail_path: Option<&str>) -> CaptureWrite {
+        CaptureWrite::Frame(FrameWrite {
+            device_name: "monitor_1".to_string(),
+            video_chunk_id: None,
+            timestamp: None,
+            windows: vec![WindowOcrWrite {
+                text: text.to_string(),
+                text_json: "[]".to_string(),
+                app_name: "app".to_string(),
+                window_name: "window".to_string(),
+                ocr_engine: "Tesseract".to_string(),
+                focused: true,
+                raw_text: None,
+                corrected_by: None,
+            }],
+            storage_mode,
+            thumbnail_path: thumbnail_path.map(str::to_string),
+            window_layout: None,
+        })
+    }
+
+    async fn write(db: &DatabaseManager, write: CaptureWrite) -> i64 {
+        match db.write_capture(write).await.unwrap() {
+            CaptureOutcome::Written(id) => id,
+            CaptureOutcome::Spilled => panic!("write was spilled"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_frames_record_their_storage_mode() {
+        let db = setup_test_db().await;
+        db.insert_video_chunk("video.mp4", "monitor_1")
+            .await
+            .unwrap();
+
+        let full = write(&db, frame("full one", StorageMode::Full, None)).await;
+        let text_only = write(&db, frame("text only", StorageMode::TextOnly, None)).await;
+        let thumbnail = write(
+            &db,
+            frame(
+                "with thumbnail",
+                StorageMode::TextPlusThumbnails,
+                Some("thumb.jpg"),
+            ),
+        )
+        .await;
+        // Back to full, the frame goes to the video chunk and not the text only one
+        let full_again = write(&db, frame("full two", StorageMode::Full, None)).await;
+
+        let sources = [full, text_only, thumbnail, full_again].map(|id| {
+            let db = &db;
+            async move { db.get_frame_image_source(id).await.unwrap().unwrap() }
+        });
+        let [full, text_only, thumbnail, full_again] =
+            futures::future::join_all(sources).await.try_into().unwrap();
+        assert_eq!(full.storage_mode, StorageMode::Full);
+        assert_eq!(full.file_path, "video.mp4");
+        assert_eq!(full.offset_index, 0);
+        assert_eq!(text_only.storage_mode, StorageMode::TextOnly);
+        assert_eq!(text_only.file_path, "");
+        assert_eq!(text_only.thumbnail_path, None);
+        assert_eq!(thumbnail.storage_mode, StorageMode::TextPlusThumbnails);
+        assert_eq!(thumbnail.file_path, "");
+        assert_eq!(thumbnail.thumbnail_path.as_deref(), Some("thumb.jpg"));
+        assert_eq!(full_again.file_path, "video.mp4");
+        assert_eq!(full_again.offset_index, 1);
+        assert!(db
+            .get_frame_image_source(full_again.frame_id + 1)
+            .await
+            .unwrap()
+            .is_none());
+
+        // One text only chunk per device, reused by every frame without video
+        let chunks: Vec<String> =
+            sqlx::query_scalar("SELECT file_path FROM video_chunks ORDER BY id")
+                .fetch_all(&db.pool)
+                .await
+                .unwrap();
+        assert_eq!(chunks, vec!["video.mp4".to_string(), String::new()]);
+
+        let results = db
+            .search(
+                "",
+                ContentType::OCR,
+                10,
+                0,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        let mut modes: Vec<(String, StorageMode)> = results
+            .into_iter()
+            .map(|result| match result {
+                SearchResult::OCR(ocr) => (ocr.ocr_text, ocr.storage_mode),
+                _ => panic!("expected ocr results"),
+            })
+            .collect();
+        modes.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            modes,
+            vec![
+                ("full one".to_string(), StorageMode::Full),
+                ("full two".to_string(), StorageMode::Full),
+                ("text only".to_string(), StorageMode::TextOnly),
+                (
+                    "with thumbnail".to_string(),
+                    StorageMode::TextPlusThumbnails
+                ),
+            ]
+        );
+
+        let counts = db
+            .count_frames_since(Utc::now() - ChronoDuration::hours(1))
+            .await
+            .unwrap();
+        assert_eq!(counts.iter().map(|(_, count)| count).sum::<i64>(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_text_only_frames_need_no_video_chunk() {
+        let db = setup_test_db().await;
+        // Without a video chunk full frames can't be stored, text only frames can
+        assert_eq!(
+            db.write_capture(frame("full", StorageMode::Full, None))
+                .await
+                .unwrap(),
+            CaptureOutcome::Written(0)
+        );
+        let id = write(&db, frame("private", StorageMode::TextOnly, None)).await;
+        assert!(id > 0);
+
+        // Video kept again: a new chunk starts, the text only chunk is never picked
+        db.insert_video_chunk("video.mp4", "monitor_1")
+            .await
+            .unwrap();
+        let id = write(&db, frame("full", StorageMode::Full, None)).await;
+        let source = db.get_frame_image_source(id).await.unwrap().unwrap();
+        assert_eq!(source.file_path, "video.mp4");
+        assert_eq!(source.offset_index, 0);
+    }
+
+    #[tokio::test]
+    async fn test_retention_removes_thumbnails_not_the_text_only_chunk() {
+        let db = setup_test_db().await;
+        write(
+            &db,
+            frame("a", StorageMode::TextPlusThumbnails, Some("a.jpg")),
+        )
+        .await;
+        write(&db, frame("b", StorageMode::TextOnly, None)).await;
+
+        let rows = db
+            .get_retention_rows(
+                RetentionKind::Frame,
+                Utc::now() + ChronoDuration::hours(1),
+                0,
+                10,
+            )
+            .await
+            .unwrap();
+        let ids: Vec<i64> = rows.iter().map(|row| row.id).collect();
+        let files = db
+            .delete_retention_rows(RetentionKind::Frame, &ids)
+            .await
+            .unwrap();
+        assert_eq!(files, vec!["a.jpg".to_string()]);
+    }
+
+    #[test]
+    fn test_mode_setting_and_thumbnails() {
+        let setting = StorageModeSetting::new(StorageMode::Full);
+        assert_eq!(setting.set(StorageMode::TextOnly), StorageMode::Full);
+        assert_eq!(setting.get(), StorageMode::TextOnly);
+        assert!(StorageMode::TextOnly.missing_image_reason().is_some());
+        assert!(StorageMode::TextOnly.bytes_per_frame() < StorageMode::Full.bytes_per_frame());
+        for mode in [
+            StorageMode::Full,
+            StorageMode::TextOnly,
+            StorageMode::TextPlusThumbnails,
+        ] {
+            assert_eq!(StorageMode::from_db(mode.as_str()), mode);
+            assert_eq!(
+                serde_json::to_value(mode).unwrap(),
+                serde_json::json!(mode.as_str())
+            );
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = thumbnail_path(dir.path(), 1, Utc::now());
+        let image = image::DynamicImage::new_rgba8(1920, 1080);
+        write_thumbnail(&image, &path).unwrap();
+        let thumbnail = image::open(&path).unwrap();
+        assert_eq!((thumbnail.width(), thumbnail.height()), (320, 180));
+    }
+}
@@ -0,0 +1,132 @@
+#[cfg(test)]
+mod tests {
+    use screenpipe_server::storage::{
+        copy_storage, path_prefix, MediaVolume, StorageConfig, StorageDirs, StorageKind,
+    };
+    use std::fs;
+    use std::path::Path;
+    use tempfile::tempdir;
+
+    fn write(path: &Path, content: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn test_defaults_match_historical_layout() {
+        let base = tempdir().unwrap();
+        let dirs = StorageDirs::resolve(base.path()).unwrap();
+        assert_eq!(dirs.db.path, base.path());
+        assert_eq!(dirs.media.path, base.path().join("data"));
+        assert_eq!(dirs.db_path().unwrap(), base.path().join("db.sqlite"));
+        assert!(!dirs.custom_models_dir());
+    }
+
+    #[test]
+    fn test_move_media_copies_verifies_and_switches() {
+        let base = tempdir().unwrap();
+        let drive = tempdir().unwrap();
+        write(&base.path().join("data/monitor_0_a.mp4"), "video");
+        write(&base.path().join("data/nested/audio.mp4"), "audio");
+
+        let pending = copy_storage(base.path(), StorageKind::Media, drive.path()).unwrap();
+        let target = drive.path().join("screenpipe/media");
+        assert_eq!(pending.to, target);
+        assert_eq!(pending.files.len(), 2);
+        assert_eq!(pending.bytes, 10);
+        assert_eq!(
+            fs::read_to_string(target.join("nested/audio.mp4")).unwrap(),
+            "audio"
+        );
+        // Nothing is switched or deleted before the caller commits
+        assert!(base.path().join("data/monitor_0_a.mp4").exists());
+        assert_eq!(
+            StorageConfig::load(base.path()).unwrap(),
+            StorageConfig::default()
+        );
+
+        pending.switch_config(base.path()).unwrap();
+        pending.remove_originals().unwrap();
+        assert!(!base.path().join("data").exists());
+
+        let dirs = StorageDirs::resolve(base.path()).unwrap();
+        assert_eq!(dirs.media.path, target);
+        assert!(dirs.media.available);
+        assert!(dirs.media.volume_id.is_some());
+    }
+
+    #[test]
+    fn test_move_db_only_takes_database_files() {
+        let base = tempdir().unwrap();
+        let drive = tempdir().unwrap();
+        write(&base.path().join("db.sqlite"), "db");
+        write(&base.path().join("db.sqlite-wal"), "wal");
+        write(&base.path().join("pipes/foo/pipe.json"), "{}");
+
+        let pending = copy_storage(base.path(), StorageKind::Db, drive.path()).unwrap();
+        pending.switch_config(base.path()).unwrap();
+        pending.remove_originals().unwrap();
+
+        assert!(!base.path().join("db.sqlite").exists());
+        assert!(base.path().join("pipes/foo/pipe.json").exists());
+        let dirs = StorageDirs::resolve(base.path()).unwrap();
+        assert_eq!(
+            dirs.db_path().unwrap(),
+            drive.path().join("screenpipe/db/db.sqlite")
+        );
+    }
+
+    #[test]
+    fn test_move_refuses_to_overwrite() {
+        let base = tempdir().unwrap();
+        let drive = tempdir().unwrap();
+        write(&base.path().join("data/a.mp4"), "new");
+        write(&drive.path().join("screenpipe/media/a.mp4"), "old");
+
+        assert!(copy_storage(base.path(), StorageKind::Media, drive.path()).is_err());
+        assert_eq!(
+            fs::read_to_string(drive.path().join("screenpipe/media/a.mp4")).unwrap(),
+            "old"
+        );
+    }
+
+    #[test]
+    fn test_unplugged_drive_is_unavailable() {
+        let base = tempdir().unwrap();
+        let drive = tempdir().unwrap();
+        write(&base.path().join("db.sqlite"), "db");
+        let pending = copy_storage(base.path(), StorageKind::Db, drive.path()).unwrap();
+        pending.switch_config(base.path()).unwrap();
+
+        fs::remove_dir_all(drive.path().join("screenpipe")).unwrap();
+        let dirs = StorageDirs::resolve(base.path()).unwrap();
+        assert!(!dirs.db.available);
+        assert!(dirs.db_path().is_err());
+    }
+
+    #[test]
+    fn test_media_volume_pauses_and_resumes() {
+        let drive = tempdir().unwrap();
+        let media = drive.path().join("media");
+        fs::create_dir_all(&media).unwrap();
+
+        let volume = MediaVolume::new(media.clone(), None);
+        assert!(volume.is_available());
+        assert!(volume.unavailable_since().is_none());
+
+        fs::remove_dir_all(&media).unwrap();
+        assert!(!volume.check());
+        assert!(volume.unavailable_since().is_some());
+
+        fs::create_dir_all(&media).unwrap();
+        assert!(volume.check());
+        assert!(volume.unavailable_since().is_none());
+    }
+
+    #[test]
+    fn test_path_prefix_ends_with_separator() {
+        let prefix = path_prefix(Path::new("/tmp/media"));
+        assert!(prefix.ends_with(std::path::MAIN_SEPARATOR));
+        assert_eq!(path_prefix(Path::new(&prefix)), prefix);
+    }
+}
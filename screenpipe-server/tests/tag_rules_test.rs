@@ -0,0 +1,756 @@
+#[cfg(test)]
+mod tests {
+    use axum::body::{to_bytes, Body};
+    use axum::http::{Request, StatusCode};
+    use axum::Router;
+    use chrono::{DateTime, Duration, Local, NaiveTime, TimeZone, Utc, Weekday};
+    use crossbeam::queue::SegQueue;
+    use screenpipe_server::copy::{sensitive, Sensitive};
+    use screenpipe_server::db_types::{
+        CaptureOutcome, CaptureWrite, FrameWrite, RetentionKind, TagContentType,
+        TranscriptionWrite, WindowOcrWrite,
+    };
+    use screenpipe_server::events::EventRecorder;
+    use screenpipe_server::pipe_schedule::PipeScheduler;
+    use screenpipe_server::privacy::IgnoredApps;
+    use screenpipe_server::ranking::RankingWeights;
+    use screenpipe_server::retention::{
+        RetentionDecision, RetentionManager, RetentionPolicy, RetentionSettings,
+    };
+    use screenpipe_server::sessions::SessionManager;
+    use screenpipe_server::settings_watch::SettingsWatch;
+    use screenpipe_server::storage_mode::StorageMode;
+    use screenpipe_server::tag_rules::{
+        test_rule, RuleActions, RuleConditions, RuleInput, RuleMetrics, RuleMode, RuleSample,
+        RuleWindow, TagRule, TagRules, TagRulesConfig, TimeWindow,
+    };
+    use screenpipe_server::telemetry::Telemetry;
+    use screenpipe_server::{create_router, AppState, DatabaseManager, PipeManager};
+    use serde_json::{json, Value};
+    use std::collections::{BTreeMap, HashMap};
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+    use tempfile::tempdir;
+    use tower::ServiceExt;
+
+    fn rule(name: &str, when: RuleConditions, then: RuleActions) -> TagRule {
+        TagRule {
+            name: name.to_string(),
+            when,
+            then,
+        }
+    }
+
+    fn tags(tags: &[&str]) -> RuleActions {
+        RuleActions {
+            add_tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn keywords(keywords: &[&str]) -> RuleConditions {
+        RuleConditions {
+            keywords: keywords.iter().map(|k| k.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn compile(mode: RuleMode, rules: Vec<TagRule>) -> TagRules {
+        TagRules::compile(TagRulesConfig {
+            mode,
+            rules,
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    fn frame<'a>(
+        timestamp: DateTime<Utc>,
+        windows: &[(&'a str, &'a str, &'a str)],
+    ) -> RuleInput<'a> {
+        RuleInput {
+            content: TagContentType::Vision,
+            timestamp,
+            windows: windows
+                .iter()
+                .map(|&(app_name, window_name, text)| RuleWindow {
+                    app_name: Some(app_name),
+                    window_name: Some(window_name),
+                    text,
+                })
+                .collect(),
+        }
+    }
+
+    fn audio(text: &str) -> RuleInput<'_> {
+        RuleInput {
+            content: TagContentType::Audio,
+            timestamp: Utc::now(),
+            windows: vec![RuleWindow {
+                app_name: None,
+                window_name: None,
+                text,
+            }],
+        }
+    }
+
+    /// Three rules in order: the first two match a frame with an invoice in it.
+    fn ordered_rules() -> Vec<TagRule> {
+        vec![
+            rule(
+                "invoices",
+                keywords(&["invoice"]),
+                RuleActions {
+                    retention_class: Some("finance".to_string()),
+                    ..tags(&["finance", "billing"])
+                },
+            ),
+            rule(
+                "money",
+                RuleConditions {
+                    text: Some(r"\$\d+".to_string()),
+                    ..Default::default()
+                },
+                RuleActions {
+                    sensitive: true,
+                    retention_class: Some("short".to_string()),
+                    ..tags(&["billing", "money"])
+                },
+            ),
+            rule("never", keywords(&["nothing like this"]), tags(&["never"])),
+        ]
+    }
+
+    #[test]
+    fn test_all_mode_accumulates_in_rule_order() {
+        let rules = compile(RuleMode::All, ordered_rules());
+        let metrics = RuleMetrics::default();
+        let input = frame(Utc::now(), &[("Mail", "Inbox", "INVOICE 42, total $120")]);
+        let outcome = rules.evaluate(&input, &metrics);
+
+        assert_eq!(outcome.matched, ["invoices", "money"]);
+        let added: Vec<(&str, &str)> = outcome
+            .tags
+            .iter()
+            .map(|tag| (tag.tag.as_str(), tag.rule.as_str()))
+            .collect();
+        assert_eq!(
+            added,
+            [
+                ("finance", "invoices"),
+                ("billing", "invoices"),
+                ("money", "money")
+            ]
+        );
+        assert!(outcome.sensitive);
+        // The first matching rule with a class decides it
+        assert_eq!(outcome.retention_class.as_deref(), Some("finance"));
+
+        let snapshot = metrics.snapshot();
+        assert_eq!((snapshot.rows, snapshot.matched_rows), (1, 1));
+        assert_eq!(snapshot.rules["invoices"].hits, 1);
+        assert_eq!(snapshot.rules["never"].evaluations, 1);
+        assert_eq!(snapshot.rules["never"].hits, 0);
+    }
+
+    #[test]
+    fn test_first_match_mode_stops_at_the_first_hit() {
+        let rules = compile(RuleMode::FirstMatch, ordered_rules());
+        let metrics = RuleMetrics::default();
+        let outcome = rules.evaluate(
+            &frame(Utc::now(), &[("Mail", "Inbox", "invoice, total $120")]),
+            &metrics,
+        );
+        assert_eq!(outcome.matched, ["invoices"]);
+        assert!(!outcome.sensitive);
+        assert_eq!(outcome.tags.len(), 2);
+        // Rules after the hit don't run
+        assert!(!metrics.snapshot().rules.contains_key("money"));
+
+        // Without a hit every rule runs, in order
+        let outcome = rules.evaluate(&frame(Utc::now(), &[("Mail", "Inbox", "$5")]), &metrics);
+        assert_eq!(outcome.matched, ["money"]);
+        assert_eq!(outcome.retention_class.as_deref(), Some("short"));
+    }
+
+    #[test]
+    fn test_conditions_hold_on_one_window() {
+        let rules = compile(
+            RuleMode::All,
+            vec![rule(
+                "bank",
+                RuleConditions {
+                    apps: vec!["Firefox.app".to_string()],
+                    window: Some("online bank(ing)?".to_string()),
+                    ..keywords(&["iban", "sort code"])
+                },
+                tags(&["bank"]),
+            )],
+        );
+        let metrics = RuleMetrics::default();
+        let matched = |windows: &[(&str, &str, &str)]| {
+            !rules
+                .evaluate(&frame(Utc::now(), windows), &metrics)
+                .matched
+                .is_empty()
+        };
+
+        assert!(matched(&[(
+            "firefox",
+            "My Online Banking",
+            "your IBAN: GB00"
+        )]));
+        assert!(matched(&[
+            ("Slack", "general", "iban please"),
+            ("Firefox.exe", "Online bank", "Sort Code 00-00-00"),
+        ]));
+        // The app, the title and the text are each on another window
+        assert!(!matched(&[
+            ("firefox", "news", "iban"),
+            ("Slack", "online banking", "iban"),
+            ("firefox", "online banking", "nothing"),
+        ]));
+
+        // Audio has no app or window
+        assert!(rules
+            .evaluate(&audio("my iban is"), &metrics)
+            .matched
+            .is_empty());
+        let audio_rule = compile(
+            RuleMode::All,
+            vec![rule(
+                "calls",
+                RuleConditions {
+                    content: Some(TagContentType::Audio),
+                    ..keywords(&["iban"])
+                },
+                tags(&["call"]),
+            )],
+        );
+        assert_eq!(
+            audio_rule.evaluate(&audio("My IBAN is"), &metrics).matched,
+            ["calls"]
+        );
+        let text = frame(Utc::now(), &[("firefox", "bank", "iban")]);
+        assert!(audio_rule.evaluate(&text, &metrics).matched.is_empty());
+    }
+
+    #[test]
+    fn test_time_windows_in_local_time() {
+        // A monday
+        let at = |hour, minute| {
+            Local
+                .with_ymd_and_hms(2024, 12, 30, hour, minute, 0)
+                .unwrap()
+                .with_timezone(&Utc)
+        };
+        let time = |hour, minute| NaiveTime::from_hms_opt(hour, minute, 0);
+
+        let office = TimeWindow {
+            days: vec![Weekday::Mon, Weekday::Fri],
+            from: time(9, 0),
+            to: time(17, 30),
+        };
+        assert!(office.contains(at(9, 0)));
+        assert!(office.contains(at(17, 29)));
+        assert!(!office.contains(at(17, 30)));
+        assert!(!office.contains(at(8, 59)));
+        assert!(!office.contains(at(12, 0) + Duration::days(1)));
+
+        // Past midnight
+        let night = TimeWindow {
+            from: time(22, 0),
+            to: time(6, 0),
+            ..Default::default()
+        };
+        assert!(night.contains(at(23, 0)));
+        assert!(night.contains(at(5, 59)));
+        assert!(!night.contains(at(6, 0)));
+        assert!(!night.contains(at(12, 0)));
+
+        // Given in config files as strings
+        let config: TagRulesConfig = serde_json::from_value(json!({
+            "rules": [{
+                "name": "night",
+                "when": { "time": { "days": ["mon"], "from": "22:00", "to": "06:00" } },
+                "then": { "sensitive": true }
+            }]
+        }))
+        .unwrap();
+        let rules = TagRules::compile(config).unwrap();
+        let metrics = RuleMetrics::default();
+        let empty = frame(at(23, 0), &[]);
+        assert_eq!(rules.evaluate(&empty, &metrics).matched, ["night"]);
+        let tuesday = frame(at(23, 0) + Duration::days(1), &[]);
+        assert!(rules.evaluate(&tuesday, &metrics).matched.is_empty());
+    }
+
+    #[test]
+    fn test_rules_are_validated_and_normalized() {
+        let config = |rules: Vec<TagRule>| TagRulesConfig {
+            rules,
+            ..Default::default()
+        };
+        let rules = TagRules::compile(config(vec![rule(
+            " chat ",
+            RuleConditions {
+                apps: vec!["Slack.exe".to_string(), "slack".to_string()],
+                ..keywords(&[" standup ", ""])
+            },
+            tags(&["meeting", " meeting"]),
+        )]))
+        .unwrap();
+        let saved = &rules.config().rules[0];
+        assert_eq!(saved.name, "chat");
+        assert_eq!(saved.when.apps, ["slack"]);
+        assert_eq!(saved.when.keywords, ["standup"]);
+        assert_eq!(saved.then.add_tags, ["meeting"]);
+
+        let invalid = [
+            (
+                config(vec![
+                    rule("a", keywords(&["x"]), tags(&["x"])),
+                    rule("a", keywords(&["y"]), tags(&["y"])),
+                ]),
+                "more than one rule named 'a'",
+            ),
+            (
+                config(vec![rule(
+                    "regex",
+                    RuleConditions {
+                        window: Some("(unclosed".to_string()),
+                        ..Default::default()
+                    },
+                    tags(&["x"]),
+                )]),
+                "rule 'regex': invalid window regex",
+            ),
+            (
+                config(vec![rule("idle", keywords(&["x"]), RuleActions::default())]),
+                "rule 'idle': does nothing",
+            ),
+            (
+                config(vec![rule("", keywords(&["x"]), tags(&["x"]))]),
+                "every rule needs a name",
+            ),
+            (
+                config(vec![rule(
+                    "class",
+                    keywords(&["x"]),
+                    RuleActions {
+                        retention_class: Some(" ".to_string()),
+                        ..Default::default()
+                    },
+                )]),
+                "rule 'class': retention_class is empty",
+            ),
+            (
+                TagRulesConfig {
+                    budget_us: 0,
+                    ..Default::default()
+                },
+                "budget_us must be between",
+            ),
+        ];
+        for (config, expected) in invalid {
+            match TagRules::compile(config) {
+                Err(e) => assert!(e.starts_with(expected), "{}", e),
+                Ok(_) => panic!("{} was accepted", expected),
+            }
+        }
+    }
+
+    #[test]
+    fn test_rules_past_their_budget_are_abandoned() {
+        let rules = TagRules::compile(TagRulesConfig {
+            budget_us: 1,
+            rules: vec![
+                rule(
+                    "slow",
+                    RuleConditions {
+                        text: Some(r"(\w+\s+){3}never".to_string()),
+                        ..Default::default()
+                    },
+                    tags(&["slow"]),
+                ),
+                rule("empty", RuleConditions::default(), tags(&["any"])),
+            ],
+            ..Default::default()
+        })
+        .unwrap();
+        let metrics = RuleMetrics::default();
+        let text = "lorem ipsum dolor sit amet ".repeat(20_000) + "never";
+        let windows: Vec<(&str, &str, &str)> = (0..4).map(|_| ("app", "", text.as_str())).collect();
+        let outcome = rules.evaluate(&frame(Utc::now(), &windows), &metrics);
+
+        assert!(outcome.over_budget.contains(&"slow".to_string()));
+        assert!(!outcome.matched.contains(&"slow".to_string()));
+        assert!(outcome.tags.iter().all(|tag| tag.tag != "slow"));
+        let stats = metrics.snapshot().rules["slow"];
+        assert_eq!(
+            (stats.evaluations, stats.hits, stats.over_budget),
+            (1, 0, 1)
+        );
+        assert!(stats.max_us > 1);
+        // The rules after it still run
+        assert_eq!(metrics.snapshot().rules["empty"].evaluations, 1);
+    }
+
+    fn window(app_name: &str, window_name: &str, text: &str) -> WindowOcrWrite {
+        WindowOcrWrite {
+            text: text.to_string(),
+            text_json: "[]".to_string(),
+            app_name: app_name.to_string(),
+            window_name: window_name.to_string(),
+            ocr_engine: "Tesseract".to_string(),
+            focused: true,
+            raw_text: None,
+            corrected_by: None,
+        }
+    }
+
+    async fn write(db: &DatabaseManager, write: CaptureWrite) -> i64 {
+        match db.write_capture(write).await.unwrap() {
+            CaptureOutcome::Written(id) => id,
+            CaptureOutcome::Spilled => panic!("write was spilled"),
+        }
+    }
+
+    async fn write_frame(db: &DatabaseManager, age_days: i64, text: &str) -> i64 {
+        let frame = CaptureWrite::Frame(FrameWrite {
+            device_name: "monitor_1".to_string(),
+            video_chunk_id: None,
+            timestamp: Some(Utc::now() - Duration::days(age_days)),
+            windows: vec![window("Mail", "Inbox", text)],
+            storage_mode: StorageMode::TextOnly,
+            thumbnail_path: None,
+            window_layout: None,
+        });
+        write(db, frame).await
+    }
+
+    async fn write_transcription(db: &DatabaseManager, file_path: &str, text: &str) -> i64 {
+        let transcription = CaptureWrite::Transcription(TranscriptionWrite {
+            file_path: file_path.to_string(),
+            transcription: text.to_string(),
+            offset_index: 0,
+            transcription_engine: "WhisperLargeV3Turbo".to_string(),
+            device_name: "mic".to_string(),
+            is_input_device: true,
+            speaker_id: None,
+            start_time: Some(0.0),
+            end_time: Some(1.0),
+        });
+        write(db, transcription).await
+    }
+
+    async fn rule_tags(db: &DatabaseManager, frame_id: i64) -> Vec<(String, Option<String>)> {
+        sqlx::query_as(
+            "SELECT t.name, vt.rule FROM vision_tags vt JOIN tags t ON t.id = vt.tag_id WHERE vt.vision_id = ?1 ORDER BY t.name",
+        )
+        .bind(frame_id)
+        .fetch_all(&db.pool)
+        .await
+        .unwrap()
+    }
+
+    async fn setup_test_db() -> DatabaseManager {
+        DatabaseManager::new("sqlite::memory:")
+            .await
+            .unwrap()
+            .with_tag_rules(compile(RuleMode::All, ordered_rules()))
+    }
+
+    #[tokio::test]
+    async fn test_rules_apply_when_captures_are_written() {
+        let db = setup_test_db().await;
+        let invoice = write_frame(&db, 0, "invoice: $120").await;
+        let plain = write_frame(&db, 0, "nothing to see").await;
+
+        assert_eq!(
+            rule_tags(&db, invoice).await,
+            [
+                ("billing".to_string(), Some("invoices".to_string())),
+                ("finance".to_string(), Some("invoices".to_string())),
+                ("money".to_string(), Some("money".to_string())),
+            ]
+        );
+        assert!(rule_tags(&db, plain).await.is_empty());
+        let (marked, class): (bool, Option<String>) =
+            sqlx::query_as("SELECT sensitive, retention_class FROM frames WHERE id = ?1")
+                .bind(invoice)
+                .fetch_one(&db.pool)
+                .await
+                .unwrap();
+        assert!(marked);
+        assert_eq!(class.as_deref(), Some("finance"));
+
+        // The image of a frame a rule marked sensitive isn't copied
+        let content = db.get_frame_content(invoice).await.unwrap().unwrap();
+        assert!(content.sensitive);
+        assert_eq!(
+            sensitive(&content, &IgnoredApps::default(), &[]),
+            Some(Sensitive::Rule)
+        );
+        let content = db.get_frame_content(plain).await.unwrap().unwrap();
+        assert_eq!(sensitive(&content, &IgnoredApps::default(), &[]), None);
+
+        // Transcriptions of one chunk add up, the first class stays
+        let chunk = write_transcription(&db, "audio_1.mp4", "the invoice").await;
+        assert_eq!(
+            write_transcription(&db, "audio_1.mp4", "costs $5").await,
+            chunk
+        );
+        let (marked, class): (bool, Option<String>) =
+            sqlx::query_as("SELECT sensitive, retention_class FROM audio_chunks WHERE id = ?1")
+                .bind(chunk)
+                .fetch_one(&db.pool)
+                .await
+                .unwrap();
+        assert!(marked);
+        assert_eq!(class.as_deref(), Some("finance"));
+        let audio_tags: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM audio_tags WHERE audio_chunk_id = ?1 AND rule IS NOT NULL",
+        )
+        .bind(chunk)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+        assert_eq!(audio_tags, 3);
+
+        // Rules changed later only apply to what comes next
+        db.set_tag_rules(TagRules::default());
+        let after = write_frame(&db, 0, "invoice").await;
+        assert!(rule_tags(&db, after).await.is_empty());
+        assert_eq!(rule_tags(&db, invoice).await.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_retention_classes_and_rule_tags() {
+        let db = Arc::new(setup_test_db().await);
+        let invoice = write_frame(&db, 20, "invoice").await;
+        let money = write_frame(&db, 20, "paid $3").await;
+        let plain = write_frame(&db, 20, "plain").await;
+        let tagged_by_hand = write_frame(&db, 20, "$4").await;
+        db.add_tags(
+            tagged_by_hand,
+            TagContentType::Vision,
+            vec!["money".to_string()],
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            rule_tags(&db, tagged_by_hand).await,
+            [
+                ("billing".to_string(), Some("money".to_string())),
+                ("money".to_string(), None),
+            ]
+        );
+
+        let settings = RetentionSettings {
+            global: RetentionPolicy::days(30),
+            classes: BTreeMap::from([
+                ("finance".to_string(), RetentionPolicy::days(365)),
+                ("short".to_string(), RetentionPolicy::days(7)),
+            ]),
+            ..Default::default()
+        }
+        .validated()
+        .unwrap();
+        let now = Utc::now();
+        let rows = db
+            .get_retention_rows(RetentionKind::Frame, now, 0, 100)
+            .await
+            .unwrap();
+        let decisions: HashMap<i64, RetentionDecision> = rows
+            .iter()
+            .map(|row| (row.id, settings.decide(row, now)))
+            .collect();
+        assert_eq!(decisions[&invoice], RetentionDecision::Keep);
+        // Rule tags don't keep content, tags set by hand do
+        assert_eq!(decisions[&money], RetentionDecision::Delete);
+        assert_eq!(decisions[&plain], RetentionDecision::Keep);
+        assert_eq!(decisions[&tagged_by_hand], RetentionDecision::KeepTagged);
+
+        // A class the settings don't have falls back to the other policies
+        let without_classes = RetentionSettings {
+            global: RetentionPolicy::days(10),
+            ..Default::default()
+        };
+        let row = rows.iter().find(|row| row.id == invoice).unwrap();
+        assert_eq!(row.retention_class.as_deref(), Some("finance"));
+        assert_eq!(without_classes.decide(row, now), RetentionDecision::Delete);
+        // Classes count for the earliest cutoff
+        assert_eq!(
+            settings.earliest_cutoff(now),
+            RetentionPolicy::days(7).cutoff(now)
+        );
+
+        let zero_class = RetentionSettings {
+            classes: BTreeMap::from([("short".to_string(), RetentionPolicy::days(0))]),
+            ..Default::default()
+        };
+        assert!(zero_class.validated().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_a_rule_is_tested_on_recent_content() {
+        let db = DatabaseManager::new("sqlite::memory:").await.unwrap();
+        for text in ["old invoice", "lunch", "invoice 7"] {
+            write_frame(&db, 0, text).await;
+        }
+        write_transcription(&db, "audio_1.mp4", "send the invoice").await;
+        write_frame(&db, 0, "nothing").await;
+
+        let candidate = rule("invoices", keywords(&["INVOICE"]), tags(&["finance"]));
+        let mut samples = RuleSample::group(
+            TagContentType::Vision,
+            db.get_rule_samples(TagContentType::Vision, 3)
+                .await
+                .unwrap(),
+        );
+        samples.extend(RuleSample::group(
+            TagContentType::Audio,
+            db.get_rule_samples(TagContentType::Audio, 3).await.unwrap(),
+        ));
+        let report = test_rule(candidate, 2000, &samples).unwrap();
+
+        assert_eq!(report.rows, 4);
+        let matched: Vec<(TagContentType, &str)> = report
+            .matched
+            .iter()
+            .map(|m| (m.content_type, m.excerpt.as_str()))
+            .collect();
+        // The oldest frame is past the limit
+        assert_eq!(
+            matched,
+            [
+                (TagContentType::Vision, "invoice 7"),
+                (TagContentType::Audio, "send the invoice"),
+            ]
+        );
+        assert_eq!(report.matched[0].app_name.as_deref(), Some("Mail"));
+        assert_eq!(report.stats.evaluations, 4);
+        assert_eq!(report.stats.hits, 2);
+        // Nothing was tagged
+        let tags: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM vision_tags")
+            .fetch_one(&db.pool)
+            .await
+            .unwrap();
+        assert_eq!(tags, 0);
+
+        let invalid = rule("invalid", keywords(&["x"]), RuleActions::default());
+        assert!(test_rule(invalid, 2000, &samples).is_err());
+    }
+
+    async fn setup_test_app(db: Arc<DatabaseManager>, screenpipe_dir: &Path) -> Router {
+        let app_state = Arc::new(AppState {
+            db: db.clone(),
+            vision_control: Arc::new(AtomicBool::new(false)),
+            audio_devices_control: Arc::new(SegQueue::new()),
+            devices_status: HashMap::new(),
+            app_start_time: Utc::now(),
+            screenpipe_dir: screenpipe_dir.to_path_buf(),
+            pipe_manager: Arc::new(PipeManager::new(PathBuf::from(""))),
+            retention: Arc::new(RetentionManager::new(db.clone(), PathBuf::from(""), None)),
+            pipe_scheduler: Arc::new(PipeScheduler::new(
+                db.clone(),
+                Arc::new(PipeManager::new(PathBuf::from(""))),
+            )),
+            sessions: Arc::new(SessionManager::new(db.clone(), PathBuf::from(""))),
+            telemetry: Arc::new(Telemetry::new(
+                db.clone(),
+                Arc::new(PipeManager::new(PathBuf::from(""))),
+                PathBuf::from(""),
+            )),
+            settings: Arc::new(SettingsWatch::new()),
+            events: Arc::new(EventRecorder::new(db.clone())),
+            vision_disabled: false,
+            audio_disabled: false,
+            frame_cache: None,
+            ui_monitoring_enabled: false,
+            ocr_scheduler: None,
+            media_volume: None,
+            archiver: None,
+            ranking: RankingWeights::default(),
+        });
+        create_router().with_state(app_state)
+    }
+
+    async fn send(app: &Router, method: &str, uri: &str, body: Value) -> (StatusCode, Value) {
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method(method)
+                    .uri(uri)
+                    .header("content-type", "application/json")
+                    .body(Body::from(body.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    #[tokio::test]
+    async fn test_rules_endpoints() {
+        let dir = tempdir().unwrap();
+        let db = Arc::new(DatabaseManager::new("sqlite::memory:").await.unwrap());
+        let app = setup_test_app(db.clone(), dir.path()).await;
+        write_frame(&db, 0, "invoice 7").await;
+
+        let (status, body) = send(&app, "GET", "/settings/rules", Value::Null).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["rules"], json!([]));
+        assert_eq!(body["mode"], "all");
+
+        let rules = json!({
+            "mode": "first_match",
+            "rules": [{
+                "name": "invoices",
+                "when": { "apps": ["Mail.app"], "keywords": ["invoice"] },
+                "then": { "add_tags": ["finance"] }
+            }]
+        });
+        let (status, body) = send(&app, "PUT", "/v1/settings/rules", rules).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["rules"]["rules"][0]["when"]["apps"], json!(["mail"]));
+        assert_eq!(db.tag_rules().config().mode, RuleMode::FirstMatch);
+        let saved = TagRulesConfig::path(dir.path());
+        let saved: TagRulesConfig =
+            serde_json::from_str(&std::fs::read_to_string(saved).unwrap()).unwrap();
+        assert_eq!(&saved, db.tag_rules().config());
+        assert_eq!(TagRules::load(dir.path()).config(), &saved);
+
+        let invalid = json!({ "rules": [{ "name": "x", "when": { "text": "(" }, "then": { "sensitive": true } }] });
+        let (status, body) = send(&app, "PUT", "/settings/rules", invalid).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body["detail"]
+            .as_str()
+            .unwrap()
+            .contains("invalid text regex"));
+        assert_eq!(db.tag_rules().config().rules.len(), 1);
+
+        let candidate = json!({
+            "rule": { "name": "try", "when": { "keywords": ["invoice"] }, "then": { "add_tags": ["t"] } },
+            "limit": 10
+        });
+        let (status, body) = send(&app, "POST", "/settings/rules/test", candidate).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["rows"], 1);
+        assert_eq!(body["matched"][0]["excerpt"], "invoice 7");
+        let too_many =
+            json!({ "rule": { "name": "try", "then": { "sensitive": true } }, "limit": 0 });
+        let (status, _) = send(&app, "POST", "/settings/rules/test", too_many).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        write_frame(&db, 0, "another invoice").await;
+        let (status, body) = send(&app, "GET", "/rules/metrics", Value::Null).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body["rules"]["invoices"]["hits"].as_u64().unwrap() >= 1);
+    }
+}
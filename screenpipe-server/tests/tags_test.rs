@@ -13,6 +13,13 @@ use std::sync::Arc;
 use std::{collections::HashMap, path::PathBuf};
 use tower::ServiceExt;
 
+use screenpipe_server::events::EventRecorder;
+use screenpipe_server::pipe_schedule::PipeScheduler;
+use screenpipe_server::ranking::RankingWeights;
+use screenpipe_server::retention::RetentionManager;
+use screenpipe_server::sessions::SessionManager;
+use screenpipe_server::settings_watch::SettingsWatch;
+use screenpipe_server::telemetry::Telemetry;
 use screenpipe_server::{
     create_router, video_cache::FrameCache, AppState, ContentItem, DatabaseManager,
     PaginatedResponse, PipeManager,
@@ -36,10 +43,27 @@ async fn setup_test_app() -> (Router, Arc<AppState>) {
         app_start_time: Utc::now(),
         screenpipe_dir: PathBuf::from(""),
         pipe_manager: Arc::new(PipeManager::new(PathBuf::from(""))),
+        retention: Arc::new(RetentionManager::new(db.clone(), PathBuf::from(""), None)),
+        pipe_scheduler: Arc::new(PipeScheduler::new(
+            db.clone(),
+            Arc::new(PipeManager::new(PathBuf::from(""))),
+        )),
+        sessions: Arc::new(SessionManager::new(db.clone(), PathBuf::from(""))),
+        telemetry: Arc::new(Telemetry::new(
+            db.clone(),
+            Arc::new(PipeManager::new(PathBuf::from(""))),
+            PathBuf::from(""),
+        )),
+        settings: Arc::new(SettingsWatch::new()),
+        events: Arc::new(EventRecorder::new(db.clone())),
         frame_cache: Some(Arc::new(
             FrameCache::new(PathBuf::from(""), db).await.unwrap(),
         )),
         ui_monitoring_enabled: false,
+        ocr_scheduler: None,
+        media_volume: None,
+        archiver: None,
+        ranking: RankingWeights::default(),
     });
 
     let app = create_router().with_state(app_state.clone());
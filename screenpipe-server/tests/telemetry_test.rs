@@ -0,0 +1,159 @@
+#[cfg(test)]
+mod tests {
+    use axum::{routing::post, Json, Router};
+    use chrono::{Duration, TimeZone, Utc};
+    use screenpipe_server::telemetry::{magnitude_bucket, PingOutcome, Telemetry, UsagePayload};
+    use screenpipe_server::{DatabaseManager, PipeManager};
+    use serde_json::{json, Value};
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+    use tempfile::tempdir;
+
+    async fn setup(dir: &Path) -> Telemetry {
+        let db = Arc::new(DatabaseManager::new("sqlite::memory:").await.unwrap());
+        let pipe_manager = Arc::new(PipeManager::new(dir.to_path_buf()));
+        Telemetry::new(db, pipe_manager, dir.to_path_buf())
+    }
+
+    fn write_pipe(dir: &Path, id: &str, manifest: Value) {
+        let pipe_dir = dir.join("pipes").join(id);
+        std::fs::create_dir_all(&pipe_dir).unwrap();
+        std::fs::write(pipe_dir.join("pipe.json"), manifest.to_string()).unwrap();
+    }
+
+    /// A local endpoint keeping what it was sent.
+    async fn collector() -> (String, Arc<Mutex<Vec<Value>>>) {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let kept = received.clone();
+        let app = Router::new().route(
+            "/usage",
+            post(move |Json(body): Json<Value>| async move {
+                kept.lock().unwrap().push(body);
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (format!("http://{}/usage", addr), received)
+    }
+
+    #[test]
+    fn test_counts_are_sent_as_orders_of_magnitude() {
+        assert_eq!(magnitude_bucket(-3), "0");
+        assert_eq!(magnitude_bucket(0), "0");
+        assert_eq!(magnitude_bucket(1), "1-9");
+        assert_eq!(magnitude_bucket(9), "1-9");
+        assert_eq!(magnitude_bucket(10), "10-99");
+        assert_eq!(magnitude_bucket(4321), "1000-9999");
+    }
+
+    #[tokio::test]
+    async fn test_nothing_is_sent_or_built_when_off() {
+        let dir = tempdir().unwrap();
+        let (endpoint, received) = collector().await;
+        let telemetry = setup(dir.path()).await.with_endpoint(&endpoint);
+
+        assert!(!telemetry.status().enabled);
+        assert_eq!(telemetry.ping(Utc::now()).await.unwrap(), PingOutcome::Off);
+        // The preview is available either way and doesn't need a client
+        telemetry.preview(Utc::now()).await.unwrap();
+        assert!(!telemetry.client_built());
+
+        // DO_NOT_TRACK wins over the opt-in
+        let telemetry = setup(dir.path())
+            .await
+            .with_endpoint(&endpoint)
+            .with_do_not_track(true);
+        telemetry.set_enabled(true).unwrap();
+        let status = telemetry.status();
+        assert!(status.enabled && status.do_not_track && !status.sending);
+        assert_eq!(telemetry.ping(Utc::now()).await.unwrap(), PingOutcome::Off);
+        assert!(!telemetry.client_built());
+        assert!(received.lock().unwrap().is_empty());
+        assert!(telemetry.sent().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_sends_the_previewed_payload_once_a_day() {
+        let dir = tempdir().unwrap();
+        let (endpoint, received) = collector().await;
+        write_pipe(dir.path(), "my-secret-project", json!({ "enabled": true }));
+        write_pipe(dir.path(), "journal", json!({ "enabled": false }));
+        let telemetry = setup(dir.path())
+            .await
+            .with_endpoint(&endpoint)
+            .with_features(vec!["enable-llm".to_string(), "disable-audio".to_string()]);
+        telemetry.set_enabled(true).unwrap();
+
+        let now = Utc.with_ymd_and_hms(2024, 10, 16, 9, 0, 0).unwrap();
+        let preview = telemetry.preview(now).await.unwrap();
+        assert_eq!(preview.day.to_string(), "2024-10-15");
+        assert_eq!(preview.features, vec!["disable-audio", "enable-llm"]);
+        assert_eq!((preview.pipes, preview.pipes_enabled), (2, 1));
+        assert_eq!(preview.frames, "0");
+        let preview_json = serde_json::to_string(&preview).unwrap();
+        assert!(!preview_json.contains("secret") && !preview_json.contains("journal"));
+
+        assert_eq!(
+            telemetry.ping(now).await.unwrap(),
+            PingOutcome::Sent(preview.clone())
+        );
+        let sent: Vec<UsagePayload> = received
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|body| serde_json::from_value(body.clone()).unwrap())
+            .collect();
+        assert_eq!(sent, vec![preview.clone()]);
+        let log = telemetry.sent().unwrap();
+        assert_eq!(log.len(), 1);
+        assert_eq!((log[0].sent_at, &log[0].payload), (now, &preview));
+
+        // Once for the day, also after a restart
+        assert_eq!(
+            telemetry.ping(now + Duration::hours(5)).await.unwrap(),
+            PingOutcome::UpToDate
+        );
+        let restarted = setup(dir.path()).await.with_endpoint(&endpoint);
+        assert_eq!(
+            restarted.ping(now + Duration::hours(5)).await.unwrap(),
+            PingOutcome::UpToDate
+        );
+        assert!(matches!(
+            restarted.ping(now + Duration::days(1)).await.unwrap(),
+            PingOutcome::Sent(_)
+        ));
+        assert_eq!(restarted.sent().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_backs_off_when_unreachable() {
+        let dir = tempdir().unwrap();
+        // Nothing listens on port 1
+        let telemetry = setup(dir.path())
+            .await
+            .with_endpoint("http://127.0.0.1:1/usage");
+        telemetry.set_enabled(true).unwrap();
+
+        let now = Utc.with_ymd_and_hms(2024, 10, 16, 9, 0, 0).unwrap();
+        assert!(telemetry.ping(now).await.is_err());
+        assert_eq!(
+            telemetry.status().retry_at,
+            Some(now + Duration::minutes(15))
+        );
+        assert_eq!(
+            telemetry.ping(now + Duration::minutes(10)).await.unwrap(),
+            PingOutcome::Backoff
+        );
+
+        // The delay doubles with each failure
+        let retry = now + Duration::minutes(15);
+        assert!(telemetry.ping(retry).await.is_err());
+        assert_eq!(
+            telemetry.status().retry_at,
+            Some(retry + Duration::minutes(30))
+        );
+        assert!(telemetry.sent().unwrap().is_empty());
+        assert_eq!(telemetry.status().last_sent_day, None);
+    }
+}
@@ -0,0 +1,120 @@
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Duration as ChronoDuration, Utc};
+    use screenpipe_audio::{AudioDevice, DeviceType};
+    use screenpipe_core::clock::{Clock, ClockConfig, TimestampSource};
+    use screenpipe_server::events::EventRecorder;
+    use screenpipe_server::wake::record_clock_adjustments;
+    use screenpipe_server::DatabaseManager;
+    use std::sync::atomic::{AtomicI64, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    /// A db stamping from a system clock that is `offset` seconds off.
+    async fn setup(source: TimestampSource) -> (Arc<DatabaseManager>, Arc<AtomicI64>) {
+        let offset = Arc::new(AtomicI64::new(0));
+        let clock = Arc::new(Clock::with_system_time(
+            ClockConfig {
+                source,
+                ..ClockConfig::default()
+            },
+            {
+                let offset = offset.clone();
+                move || Utc::now() + ChronoDuration::seconds(offset.load(Ordering::SeqCst))
+            },
+        ));
+        let db = Arc::new(
+            DatabaseManager::new("sqlite::memory:")
+                .await
+                .unwrap()
+                .with_clock(clock.clone()),
+        );
+        let events = Arc::new(EventRecorder::new(db.clone()));
+        tokio::spawn(record_clock_adjustments(events, clock));
+        (db, offset)
+    }
+
+    /// Captures frames and audio while the system clock is set back and forth by an hour.
+    async fn capture_through_jumps(db: &DatabaseManager, offset: &AtomicI64) {
+        let device = AudioDevice::new("mic".to_string(), DeviceType::Input);
+        db.insert_video_chunk("video.mp4", "monitor 1")
+            .await
+            .unwrap();
+        for jump in [0, -3600, 0, 3600, -3600] {
+            offset.fetch_add(jump, Ordering::SeqCst);
+            for _ in 0..3 {
+                db.insert_frame("monitor 1", None).await.unwrap();
+                let chunk = db.insert_audio_chunk("audio.mp4").await.unwrap();
+                db.insert_audio_transcription(chunk, "hello", 0, "", &device, None, None, None)
+                    .await
+                    .unwrap();
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        }
+    }
+
+    async fn stamps(db: &DatabaseManager, table: &str) -> Vec<(DateTime<Utc>, Option<i64>)> {
+        sqlx::query_as(&format!(
+            "SELECT timestamp, monotonic_ns FROM {} ORDER BY id",
+            table
+        ))
+        .fetch_all(&db.pool)
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_stamps_never_go_backwards_across_clock_jumps() {
+        let (db, offset) = setup(TimestampSource::Monotonic).await;
+        let start = Utc::now();
+        capture_through_jumps(&db, &offset).await;
+
+        for table in ["frames", "audio_chunks", "audio_transcriptions"] {
+            let stamps = stamps(&db, table).await;
+            assert_eq!(stamps.len(), 15, "{}", table);
+            for pair in stamps.windows(2) {
+                assert!(pair[1].0 >= pair[0].0, "{} went back: {:?}", table, pair);
+                assert!(pair[1].1.unwrap() > pair[0].1.unwrap());
+            }
+            // The hour jumps were not followed, only slewed towards
+            let last = stamps.last().unwrap().0;
+            assert!(last - start < ChronoDuration::minutes(1), "{}", table);
+        }
+
+        // Every jump is recorded once, by the first item stamped after it
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let adjustments = db
+            .get_clock_adjustments(start, Utc::now() + ChronoDuration::hours(1))
+            .await
+            .unwrap();
+        let jumps: Vec<i64> = adjustments
+            .iter()
+            .map(|a| (a.jump_ms as f64 / 60_000.0).round() as i64)
+            .collect();
+        assert_eq!(jumps, vec![-60, 60, -60]);
+        assert!(adjustments
+            .iter()
+            .all(|a| a.timestamp_source == "monotonic"));
+    }
+
+    #[tokio::test]
+    async fn test_system_source_and_explicit_timestamps_are_kept() {
+        let (db, offset) = setup(TimestampSource::System).await;
+        capture_through_jumps(&db, &offset).await;
+
+        let stamps = stamps(&db, "frames").await;
+        assert!(stamps.windows(2).any(|pair| pair[1].0 < pair[0].0));
+
+        // Replayed and imported frames keep their own timestamp
+        let imported = Utc::now() - ChronoDuration::days(3);
+        let id = db.insert_frame("monitor 1", Some(imported)).await.unwrap();
+        let (timestamp, monotonic_ns): (DateTime<Utc>, Option<i64>) =
+            sqlx::query_as("SELECT timestamp, monotonic_ns FROM frames WHERE id = ?1")
+                .bind(id)
+                .fetch_one(&db.pool)
+                .await
+                .unwrap();
+        assert_eq!(timestamp, imported);
+        assert_eq!(monotonic_ns, None);
+    }
+}
@@ -0,0 +1,155 @@
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Duration, TimeZone, Utc};
+    use screenpipe_audio::{AudioDevice, DeviceType};
+    use screenpipe_server::db::MAX_FOCUS_GAP_SECONDS;
+    use screenpipe_server::db_types::{
+        AppUsage, CaptureWrite, FrameWrite, UsageBucket, WindowOcrWrite,
+    };
+    use screenpipe_server::storage_mode::StorageMode;
+    use screenpipe_server::DatabaseManager;
+
+    async fn setup_test_db() -> DatabaseManager {
+        let db = DatabaseManager::new("sqlite::memory:").await.unwrap();
+        db.insert_video_chunk("video.mp4", "monitor_1")
+            .await
+            .unwrap();
+        db
+    }
+
+    fn window(app_name: &str, focused: bool) -> WindowOcrWrite {
+        WindowOcrWrite {
+            text: format!("{} text", app_name),
+            text_json: "[]".to_string(),
+            app_name: app_name.to_string(),
+            window_name: "window".to_string(),
+            ocr_engine: "Tesseract".to_string(),
+            focused,
+            raw_text: None,
+            corrected_by: None,
+        }
+    }
+
+    /// A frame at `timestamp` with `focused` in front and a window of Slack behind it.
+    async fn frame(db: &DatabaseManager, timestamp: DateTime<Utc>, focused: &str) {
+        db.write_capture(CaptureWrite::Frame(FrameWrite {
+            device_name: "monitor_1".to_string(),
+            video_chunk_id: None,
+            timestamp: Some(timestamp),
+            windows: vec![window(focused, true), window("Slack", false)],
+            storage_mode: StorageMode::Full,
+            thumbnail_path: None,
+            window_layout: None,
+        }))
+        .await
+        .unwrap();
+    }
+
+    fn at(hour: u32, minute: u32, second: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 12, 27, hour, minute, second)
+            .unwrap()
+    }
+
+    fn usage(bucket: DateTime<Utc>, app_name: &str, seconds: f64) -> AppUsage {
+        AppUsage {
+            bucket,
+            app_name: app_name.to_string(),
+            seconds,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_app_usage_per_hour() {
+        let db = setup_test_db().await;
+        // Code from 9:59:30 to 10:00:30, a browser until 10:01, then nothing for an hour
+        frame(&db, at(9, 59, 30), "Code").await;
+        frame(&db, at(10, 0, 0), "Code").await;
+        frame(&db, at(10, 0, 30), "Firefox").await;
+        frame(&db, at(10, 1, 0), "Firefox").await;
+        frame(&db, at(11, 1, 0), "Code").await;
+        frame(&db, at(11, 1, 5), "Code").await;
+
+        let hourly = db
+            .app_usage(at(0, 0, 0), at(23, 0, 0), UsageBucket::Hour)
+            .await
+            .unwrap();
+        assert_eq!(
+            hourly,
+            vec![
+                usage(at(9, 0, 0), "Code", 30.0),
+                // The idle hour counts for at most the gap cap
+                usage(at(10, 0, 0), "Firefox", 30.0 + MAX_FOCUS_GAP_SECONDS),
+                usage(at(10, 0, 0), "Code", 30.0),
+                usage(at(11, 0, 0), "Code", 5.0),
+            ]
+        );
+
+        let daily = db
+            .app_usage(at(0, 0, 0), at(23, 0, 0), UsageBucket::Day)
+            .await
+            .unwrap();
+        assert_eq!(
+            daily,
+            vec![
+                usage(at(0, 0, 0), "Firefox", 90.0),
+                usage(at(0, 0, 0), "Code", 65.0),
+            ]
+        );
+
+        // Only frames inside the span count, the last one has no next frame to end it
+        let morning = db
+            .app_usage(at(10, 0, 0), at(10, 1, 0), UsageBucket::Hour)
+            .await
+            .unwrap();
+        assert_eq!(morning, vec![usage(at(10, 0, 0), "Code", 30.0)]);
+    }
+
+    #[tokio::test]
+    async fn test_capture_counts_per_day() {
+        let db = setup_test_db().await;
+        let now = Utc::now();
+        frame(&db, now - Duration::days(1), "Code").await;
+        frame(&db, now - Duration::seconds(1), "Code").await;
+        frame(&db, now - Duration::seconds(2), "Code").await;
+        let audio_chunk_id = db.insert_audio_chunk("audio.mp4").await.unwrap();
+        db.insert_audio_transcription(
+            audio_chunk_id,
+            "standup",
+            0,
+            "",
+            &AudioDevice::new("microphone".to_string(), DeviceType::Input),
+            None,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+
+        let counts = db
+            .capture_counts(
+                now - Duration::days(2),
+                now + Duration::minutes(1),
+                UsageBucket::Day,
+            )
+            .await
+            .unwrap();
+        let frames: i64 = counts.iter().map(|c| c.frames).sum();
+        let transcriptions: i64 = counts.iter().map(|c| c.transcriptions).sum();
+        assert_eq!(frames, 3);
+        assert_eq!(transcriptions, 1);
+        assert!(counts.windows(2).all(|w| w[0].bucket < w[1].bucket));
+        assert!(counts
+            .iter()
+            .all(|c| c.bucket.format("%H:%M:%S").to_string() == "00:00:00"));
+
+        assert!(db
+            .capture_counts(
+                now - Duration::days(9),
+                now - Duration::days(8),
+                UsageBucket::Hour
+            )
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,297 @@
+#[cfg(test)]
+mod tests {
+    use futures::future::BoxFuture;
+    use image::{
+        DynamicImage, GrayAlphaImage, GrayImage, ImageBuffer, LumaA, Rgb, RgbImage, Rgba, RgbaImage,
+    };
+    use screenpipe_core::find_ffmpeg_path;
+    use screenpipe_core::latency::LatencyStamps;
+    use screenpipe_server::sources::FrameSource;
+    use screenpipe_server::video_guard::{
+        admit, encoder_guard, Admission, ChunkFormat, DroppedFrames, EncoderGuardMetrics,
+        MismatchAction, VideoGap, RECENT_MISMATCHES,
+    };
+    use screenpipe_server::VideoCapture;
+    use screenpipe_vision::{CaptureResult, Frame};
+    use std::process::Command;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+    use tokio::sync::mpsc::Sender;
+
+    const WIDTH: u32 = 160;
+    const HEIGHT: u32 = 120;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum Kind {
+        Rgb8,
+        Rgba8,
+        Luma8,
+        LumaA8,
+        Rgb16,
+        Rgba16,
+        Empty,
+    }
+
+    const KINDS: [Kind; 7] = [
+        Kind::Rgb8,
+        Kind::Rgba8,
+        Kind::Luma8,
+        Kind::LumaA8,
+        Kind::Rgb16,
+        Kind::Rgba16,
+        Kind::Empty,
+    ];
+
+    fn image(kind: Kind, shade: u8) -> DynamicImage {
+        let (w, h) = (WIDTH, HEIGHT);
+        match kind {
+            Kind::Rgb8 => RgbImage::from_pixel(w, h, Rgb([shade, 80, 160])).into(),
+            Kind::Rgba8 => RgbaImage::from_pixel(w, h, Rgba([shade, 80, 160, 255])).into(),
+            Kind::Luma8 => GrayImage::from_pixel(w, h, image::Luma([shade])).into(),
+            Kind::LumaA8 => GrayAlphaImage::from_pixel(w, h, LumaA([shade, 255])).into(),
+            Kind::Rgb16 => {
+                let pixel = Rgb([shade as u16 * 257, 20560, 41120]);
+                DynamicImage::ImageRgb16(ImageBuffer::from_pixel(w, h, pixel))
+            }
+            Kind::Rgba16 => {
+                let pixel = Rgba([shade as u16 * 257, 20560, 41120, u16::MAX]);
+                DynamicImage::ImageRgba16(ImageBuffer::from_pixel(w, h, pixel))
+            }
+            Kind::Empty => RgbImage::new(0, 0).into(),
+        }
+    }
+
+    fn chunk(kind: Kind) -> ChunkFormat {
+        ChunkFormat::of(&image(kind, 0))
+    }
+
+    #[test]
+    fn test_frames_are_admitted_converted_or_refused() {
+        let rgb = chunk(Kind::Rgb8);
+        assert_eq!(admit(&image(Kind::Rgb8, 10), &rgb), Admission::Write);
+
+        // 8 bit layouts are converted to the chunk's
+        match admit(&image(Kind::Rgba8, 10), &rgb) {
+            Admission::Convert(converted) => {
+                assert_eq!(converted, image(Kind::Rgb8, 10));
+            }
+            other => panic!("{:?}", other),
+        }
+        for (frame, chunk_kind) in [
+            (Kind::Luma8, Kind::Rgba8),
+            (Kind::Rgb8, Kind::LumaA8),
+            (Kind::LumaA8, Kind::Luma8),
+        ] {
+            let chunk = chunk(chunk_kind);
+            match admit(&image(frame, 10), &chunk) {
+                Admission::Convert(converted) => {
+                    assert_eq!(ChunkFormat::of(&converted), chunk, "{:?}", frame)
+                }
+                other => panic!("{:?} in {:?}: {:?}", frame, chunk_kind, other),
+            }
+        }
+
+        // Other depths start a new chunk, in both directions
+        assert_eq!(admit(&image(Kind::Rgb16, 10), &rgb), Admission::NewChunk);
+        assert_eq!(
+            admit(&image(Kind::Rgb8, 10), &chunk(Kind::Rgb16)),
+            Admission::NewChunk
+        );
+        assert_eq!(
+            admit(&image(Kind::Rgba16, 10), &chunk(Kind::Rgb16)),
+            Admission::NewChunk
+        );
+
+        // Size changes are the encoder's, empty frames are dropped whatever the chunk
+        let small: DynamicImage = RgbImage::new(WIDTH / 2, HEIGHT).into();
+        assert_eq!(admit(&small, &rgb), Admission::Resized);
+        assert_eq!(
+            admit(&image(Kind::Empty, 0), &rgb),
+            Admission::Drop("empty_frame")
+        );
+        let tall: DynamicImage = RgbImage::new(WIDTH, 0).into();
+        assert_eq!(admit(&tall, &rgb), Admission::Drop("empty_frame"));
+    }
+
+    #[test]
+    fn test_mismatches_are_counted_with_their_formats() {
+        let metrics = EncoderGuardMetrics::default();
+        let rgb = chunk(Kind::Rgb8);
+        for kind in [Kind::Rgba8, Kind::Rgb8, Kind::Rgb16, Kind::Empty] {
+            let frame = image(kind, 0);
+            metrics.record(1, Some(&rgb), &frame, &admit(&frame, &rgb));
+        }
+        let snapshot = metrics.snapshot();
+        assert_eq!(
+            (snapshot.converted, snapshot.new_chunks, snapshot.dropped),
+            (1, 1, 1)
+        );
+        assert_eq!(
+            snapshot.by_format.keys().collect::<Vec<_>>(),
+            ["Rgb16", "Rgb8", "Rgba8"]
+        );
+        assert_eq!(snapshot.recent[1].chunk.as_deref(), Some("160x120 Rgb8"));
+        assert_eq!(snapshot.recent[1].frame, "160x120 Rgb16");
+        assert_eq!(snapshot.recent[2].action, MismatchAction::Dropped);
+        assert_eq!(snapshot.recent[2].reason.as_deref(), Some("empty_frame"));
+
+        let frame = image(Kind::Luma8, 0);
+        for _ in 0..RECENT_MISMATCHES {
+            metrics.record(2, Some(&rgb), &frame, &admit(&frame, &rgb));
+        }
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.recent.len(), RECENT_MISMATCHES);
+        assert!(snapshot.recent.iter().all(|m| m.monitor_id == 2));
+        assert_eq!(snapshot.by_format["L8"], RECENT_MISMATCHES as u64);
+    }
+
+    #[test]
+    fn test_dropped_frames_make_one_gap() {
+        let mut dropped = DroppedFrames::default();
+        assert!(dropped.take_gap("display_settling").is_none());
+
+        let first = Instant::now() - Duration::from_secs(3);
+        for offset in [1, 0, 2] {
+            dropped.add(first + Duration::from_secs(offset));
+        }
+        let gap = dropped.take_gap("display_settling").unwrap();
+        assert_eq!(gap.frames, 3);
+        assert_eq!(gap.reason, "display_settling");
+        let span = (gap.end_time - gap.start_time).num_milliseconds();
+        assert!((span - 2000).abs() < 50, "{}", span);
+        assert!(dropped.is_empty());
+        assert!(dropped.take_gap("display_settling").is_none());
+    }
+
+    /// Sends frames of the given kinds, same size, in order.
+    struct MixedSource {
+        kinds: Vec<Kind>,
+    }
+
+    impl FrameSource for MixedSource {
+        fn start(self: Arc<Self>, result_tx: Sender<CaptureResult>) -> BoxFuture<'static, ()> {
+            Box::pin(async move {
+                for (frame_number, &kind) in self.kinds.iter().enumerate() {
+                    let timestamp = Instant::now();
+                    let frame = CaptureResult {
+                        image: Frame::new(image(kind, (frame_number * 7 % 200) as u8)),
+                        frame_number: frame_number as u64,
+                        timestamp,
+                        window_ocr_results: vec![],
+                        duplicate_of: None,
+                        latency: LatencyStamps::captured(timestamp),
+                        window_layout: None,
+                    };
+                    if result_tx.send(frame).await.is_err() {
+                        return;
+                    }
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                }
+            })
+        }
+
+        fn lossless(&self) -> bool {
+            true
+        }
+    }
+
+    /// Frames in a chunk, decoding all of it.
+    fn decoded_frames(path: &str) -> usize {
+        let ffmpeg = find_ffmpeg_path().expect("ffmpeg not found");
+        let decoded = Command::new(&ffmpeg)
+            .args(["-v", "error", "-i", path, "-f", "framecrc", "-"])
+            .output()
+            .unwrap();
+        assert!(decoded.status.success(), "{} is not playable", path);
+        assert!(
+            decoded.stderr.is_empty(),
+            "{}: {}",
+            path,
+            String::from_utf8_lossy(&decoded.stderr)
+        );
+        String::from_utf8_lossy(&decoded.stdout)
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .count()
+    }
+
+    #[tokio::test]
+    async fn test_inconsistent_frames_never_corrupt_a_chunk() {
+        // Same sequence every run, every kind of frame next to every other
+        let mut seed: u64 = 0x5eed;
+        let mut kinds: Vec<Kind> = (0..80)
+            .map(|_| {
+                seed = seed
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                KINDS[(seed >> 33) as usize % KINDS.len()]
+            })
+            .collect();
+        let empty = kinds.iter().filter(|&&kind| kind == Kind::Empty).count() as u64;
+        assert!(empty > 0 && kinds.contains(&Kind::Rgb16));
+        // Whatever the last chunk is in, these close it and start one of their own
+        kinds.extend([Kind::Rgb8, Kind::Rgba16]);
+        let sent = kinds.len();
+
+        let output = tempfile::tempdir().unwrap();
+        let chunks = Arc::new(Mutex::new(Vec::<String>::new()));
+        let gaps = Arc::new(Mutex::new(Vec::<VideoGap>::new()));
+        let capture = VideoCapture::new(
+            output.path().to_str().unwrap(),
+            10.0,
+            Duration::from_secs(1),
+            {
+                let chunks = chunks.clone();
+                move |path: &str| chunks.lock().unwrap().push(path.to_string())
+            },
+            Arc::new(MixedSource { kinds }),
+            1,
+            None,
+            |previous, current| panic!("no display change: {:?} {:?}", previous, current),
+            {
+                let gaps = gaps.clone();
+                move |gap| gaps.lock().unwrap().push(gap)
+            },
+        );
+        // Nothing runs OCR here, the frames waiting for it would hold capture back
+        let ocr_queue = capture.ocr_frame_queue.clone();
+        let drain = tokio::spawn(async move {
+            loop {
+                while ocr_queue.pop().is_some() {}
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        });
+
+        // Every frame but the last is in a finalized chunk or in a gap
+        let deadline = Instant::now() + Duration::from_secs(60);
+        loop {
+            let finalized = {
+                let chunks = chunks.lock().unwrap();
+                chunks[..chunks.len().saturating_sub(1)].to_vec()
+            };
+            let encoded: usize = finalized.iter().map(|path| decoded_frames(path)).sum();
+            let dropped: u64 = gaps.lock().unwrap().iter().map(|gap| gap.frames).sum();
+            if encoded + dropped as usize == sent - 1 {
+                break;
+            }
+            assert!(encoded + dropped as usize <= sent - 1);
+            assert!(
+                Instant::now() < deadline,
+                "{} frames encoded and {} dropped of {}",
+                encoded,
+                dropped,
+                sent
+            );
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+        drain.abort();
+
+        let gaps = gaps.lock().unwrap();
+        assert!(gaps.iter().all(|gap| gap.reason == "empty_frame"));
+        assert_eq!(gaps.iter().map(|gap| gap.frames).sum::<u64>(), empty);
+        let snapshot = encoder_guard().snapshot();
+        assert!(snapshot.dropped >= empty);
+        assert!(snapshot.new_chunks > 0 && snapshot.converted > 0);
+        assert!(snapshot.by_format.contains_key("Rgb16"));
+    }
+}
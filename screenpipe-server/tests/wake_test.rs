@@ -0,0 +1,75 @@
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration as ChronoDuration, Utc};
+    use screenpipe_core::power::{PowerEvent, PowerSource, PowerState, SubsystemOutcome};
+    use screenpipe_server::events::EventRecorder;
+    use screenpipe_server::wake::run_wake_sequence;
+    use screenpipe_server::DatabaseManager;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_wake_sequence_records_gap_and_outcomes() {
+        let db = Arc::new(DatabaseManager::new("sqlite::memory:").await.unwrap());
+        let events = EventRecorder::new(db.clone());
+        let power: &'static PowerState = Box::leak(Box::new(PowerState::new()));
+        power.register("video monitor 1");
+        power.register("audio mic");
+
+        let slept_at = Utc::now() - ChronoDuration::hours(8);
+        power.sleep(slept_at).unwrap();
+        let woke_at = Utc::now();
+        let epoch = match power.wake_from_sleep(woke_at).unwrap() {
+            PowerEvent::Wake { epoch, .. } => epoch,
+            event => panic!("expected wake, got {:?}", event),
+        };
+        power.report(
+            "video monitor 1",
+            epoch,
+            SubsystemOutcome::Ok("chunk finalized".to_string()),
+        );
+
+        let summary = run_wake_sequence(
+            &events,
+            power,
+            epoch,
+            slept_at,
+            woke_at,
+            PowerSource::Native,
+            Duration::from_millis(100),
+        )
+        .await;
+
+        assert_eq!(summary.slept_secs, 8 * 3600);
+        assert_eq!(
+            summary.subsystems["video monitor 1"],
+            SubsystemOutcome::Ok("chunk finalized".to_string())
+        );
+        assert_eq!(summary.subsystems["audio mic"], SubsystemOutcome::TimedOut);
+        assert!(matches!(
+            summary.subsystems["database"],
+            SubsystemOutcome::Ok(_)
+        ));
+
+        let gaps = db
+            .get_capture_gaps(slept_at - ChronoDuration::minutes(1), woke_at)
+            .await
+            .unwrap();
+        assert_eq!(gaps.len(), 1);
+        assert_eq!(gaps[0].reason, "sleep");
+        assert_eq!(
+            gaps[0].start_time.timestamp_millis(),
+            slept_at.timestamp_millis()
+        );
+
+        // Gaps outside the requested range are left out
+        let gaps = db
+            .get_capture_gaps(
+                woke_at + ChronoDuration::minutes(1),
+                Utc::now() + ChronoDuration::hours(1),
+            )
+            .await
+            .unwrap();
+        assert!(gaps.is_empty());
+    }
+}
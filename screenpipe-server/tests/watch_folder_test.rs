@@ -0,0 +1,176 @@
+#[cfg(test)]
+mod tests {
+    use screenpipe_server::db_types::SearchOrder;
+    use screenpipe_server::watch_folder::{
+        IndexOutcome, SkipReason, WatchFolder, WatchFolderConfig,
+    };
+    use screenpipe_server::DatabaseManager;
+    use std::fs;
+    use std::path::Path;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+    use tempfile::{tempdir, TempDir};
+
+    async fn setup(dir: &TempDir) -> (Arc<DatabaseManager>, WatchFolder) {
+        let db = Arc::new(DatabaseManager::new("sqlite::memory:").await.unwrap());
+        let watch_folder = WatchFolder::new(
+            db.clone(),
+            WatchFolderConfig::new(vec![dir.path().to_path_buf()]),
+        );
+        (db, watch_folder)
+    }
+
+    async fn search(db: &DatabaseManager, query: &str) -> Vec<String> {
+        db.search_documents(query, 10, 0, None, None, None, None, SearchOrder::Recent)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|d| d.file_path)
+            .collect()
+    }
+
+    fn touch(path: &Path, seconds_later: u64) {
+        let file = fs::File::options().write(true).open(path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(seconds_later))
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_indexes_text_files_with_mtime() {
+        let dir = tempdir().unwrap();
+        let (db, watch_folder) = setup(&dir).await;
+        let note = dir.path().join("notes/meeting.md");
+        fs::create_dir_all(note.parent().unwrap()).unwrap();
+        fs::write(&note, "# quarterly roadmap review").unwrap();
+        fs::write(dir.path().join("todo"), "buy groceries").unwrap();
+
+        watch_folder.scan().await.unwrap();
+
+        assert_eq!(
+            search(&db, "roadmap").await,
+            vec![note.to_string_lossy().to_string()]
+        );
+        assert_eq!(search(&db, "groceries").await.len(), 1);
+        let document = &db
+            .search_documents(
+                "roadmap",
+                10,
+                0,
+                None,
+                None,
+                None,
+                None,
+                SearchOrder::Recent,
+            )
+            .await
+            .unwrap()[0];
+        assert_eq!(document.kind, "text");
+        let mtime: chrono::DateTime<chrono::Utc> =
+            fs::metadata(&note).unwrap().modified().unwrap().into();
+        assert_eq!(
+            document.timestamp.timestamp_millis(),
+            mtime.timestamp_millis()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reindexes_only_changed_files() {
+        let dir = tempdir().unwrap();
+        let (db, watch_folder) = setup(&dir).await;
+        let file = dir.path().join("draft.txt");
+        fs::write(&file, "first version").unwrap();
+
+        assert_eq!(
+            watch_folder.handle_path(&file).await.unwrap(),
+            IndexOutcome::Indexed
+        );
+        assert_eq!(
+            watch_folder.handle_path(&file).await.unwrap(),
+            IndexOutcome::Unchanged
+        );
+
+        fs::write(&file, "second revision").unwrap();
+        touch(&file, 5);
+        assert_eq!(
+            watch_folder.handle_path(&file).await.unwrap(),
+            IndexOutcome::Indexed
+        );
+        assert!(search(&db, "first").await.is_empty());
+        assert_eq!(search(&db, "revision").await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_deleted_files_are_tombstoned() {
+        let dir = tempdir().unwrap();
+        let (db, watch_folder) = setup(&dir).await;
+        let live = dir.path().join("receipt.txt");
+        let gone = dir.path().join("invoice.txt");
+        fs::write(&live, "receipt for lunch").unwrap();
+        fs::write(&gone, "invoice for hosting").unwrap();
+        watch_folder.scan().await.unwrap();
+
+        fs::remove_file(&live).unwrap();
+        assert_eq!(
+            watch_folder.handle_path(&live).await.unwrap(),
+            IndexOutcome::Removed
+        );
+        let state = db
+            .get_document_state(&live.to_string_lossy())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(state.deleted_at.is_some());
+        assert!(search(&db, "receipt").await.is_empty());
+
+        // Deleted while screenpipe wasn't running
+        fs::remove_file(&gone).unwrap();
+        watch_folder.scan().await.unwrap();
+        assert!(search(&db, "invoice").await.is_empty());
+        assert_eq!(watch_folder.stats().removed.load(Ordering::Relaxed), 2);
+
+        // Coming back clears the tombstone
+        fs::write(&live, "receipt for dinner").unwrap();
+        watch_folder.handle_path(&live).await.unwrap();
+        assert_eq!(search(&db, "dinner").await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_skips_are_counted_not_errors() {
+        let dir = tempdir().unwrap();
+        let db = Arc::new(DatabaseManager::new("sqlite::memory:").await.unwrap());
+        let mut config = WatchFolderConfig::new(vec![dir.path().to_path_buf()]);
+        config.max_file_size = 16;
+        let watch_folder = WatchFolder::new(db.clone(), config);
+
+        let binary = dir.path().join("archive.bin");
+        fs::write(&binary, [0u8, 159, 146, 150, 0, 1]).unwrap();
+        let large = dir.path().join("large.txt");
+        fs::write(&large, "x".repeat(64)).unwrap();
+        let photo = dir.path().join("photo.png");
+        fs::write(&photo, "not really a png").unwrap();
+        let hidden = dir.path().join(".DS_Store");
+        fs::write(&hidden, "hidden").unwrap();
+
+        assert_eq!(
+            watch_folder.handle_path(&binary).await.unwrap(),
+            IndexOutcome::Skipped(SkipReason::Unsupported)
+        );
+        assert_eq!(
+            watch_folder.handle_path(&large).await.unwrap(),
+            IndexOutcome::Skipped(SkipReason::TooLarge)
+        );
+        // No ocr engine configured
+        assert_eq!(
+            watch_folder.handle_path(&photo).await.unwrap(),
+            IndexOutcome::Skipped(SkipReason::Unsupported)
+        );
+        assert_eq!(
+            watch_folder.handle_path(&hidden).await.unwrap(),
+            IndexOutcome::Ignored
+        );
+
+        assert_eq!(watch_folder.stats().skipped(), 3);
+        assert!(search(&db, "").await.is_empty());
+    }
+}
@@ -0,0 +1,220 @@
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration as ChronoDuration, Utc};
+    use screenpipe_core::window_layout::{LayoutWindow, WindowLayout, WindowRect};
+    use screenpipe_server::db_types::{
+        CaptureOutcome, CaptureWrite, ContentType, FrameWrite, RetentionKind, SearchFilters,
+        SearchResult, WindowGeometryFilter, WindowOcrWrite,
+    };
+    use screenpipe_server::storage_mode::StorageMode;
+    use screenpipe_server::DatabaseManager;
+
+    async fn setup_test_db() -> DatabaseManager {
+        let db = DatabaseManager::new("sqlite::memory:").await.unwrap();
+        db.insert_video_chunk("video.mp4", "monitor_1")
+            .await
+            .unwrap();
+        db
+    }
+
+    fn window(app_name: &str, rect: WindowRect, z: u32) -> LayoutWindow {
+        LayoutWindow {
+            app_name: app_name.to_string(),
+            window_name: format!("{} window", app_name),
+            rect,
+            z,
+            focused: z == 0,
+        }
+    }
+
+    /// An editor filling the left half in front of a browser filling the screen.
+    fn layout() -> WindowLayout {
+        WindowLayout {
+            monitor_width: 1920,
+            monitor_height: 1080,
+            windows: vec![
+                window("editor", WindowRect::new(0, 0, 960, 1080), 0),
+                window("browser", WindowRect::new(0, 0, 1920, 1080), 1),
+            ],
+        }
+    }
+
+    async fn write_frame(
+        db: &DatabaseManager,
+        app_name: &str,
+        window_layout: Option<WindowLayout>,
+    ) -> i64 {
+        let write = CaptureWrite::Frame(FrameWrite {
+            device_name: "monitor_1".to_string(),
+            video_chunk_id: None,
+            timestamp: None,
+            windows: vec![WindowOcrWrite {
+                text: format!("{} text", app_name),
+                text_json: "[]".to_string(),
+                app_name: app_name.to_string(),
+                window_name: format!("{} window", app_name),
+                ocr_engine: "Tesseract".to_string(),
+                focused: true,
+                raw_text: None,
+                corrected_by: None,
+            }],
+            storage_mode: StorageMode::Full,
+            thumbnail_path: None,
+            window_layout,
+        });
+        match db.write_capture(write).await.unwrap() {
+            CaptureOutcome::Written(id) => id,
+            CaptureOutcome::Spilled => panic!("write was spilled"),
+        }
+    }
+
+    async fn search_apps(db: &DatabaseManager, window: WindowGeometryFilter) -> Vec<String> {
+        let filters = SearchFilters {
+            window,
+            ..Default::default()
+        };
+        let results = db
+            .search_with_filters(
+                "",
+                ContentType::All,
+                10,
+                0,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                &filters,
+            )
+            .await
+            .unwrap();
+        let count = db
+            .count_search_results_with_filters(
+                "",
+                ContentType::All,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                &filters,
+            )
+            .await
+            .unwrap();
+        assert_eq!(count, results.len());
+        let mut apps: Vec<String> = results
+            .into_iter()
+            .map(|result| match result {
+                SearchResult::OCR(ocr) => ocr.app_name,
+                _ => panic!("expected ocr results"),
+            })
+            .collect();
+        apps.sort();
+        apps
+    }
+
+    #[tokio::test]
+    async fn test_layout_is_stored_with_the_frame() {
+        let db = setup_test_db().await;
+        let editor = write_frame(&db, "editor", Some(layout())).await;
+        let without = write_frame(&db, "terminal", None).await;
+
+        let layouts = db.get_frame_layouts(&[editor, without]).await.unwrap();
+        assert_eq!(layouts.len(), 1);
+        assert_eq!(layouts[&editor], layout());
+        assert_eq!(layouts[&editor].focused().unwrap().app_name, "editor");
+        assert!(db.get_frame_layouts(&[]).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_search_filters_on_the_row_window_geometry() {
+        let db = setup_test_db().await;
+        // One row per window read from the same screen, as the capture loop writes them
+        write_frame(&db, "editor", Some(layout())).await;
+        write_frame(&db, "browser", Some(layout())).await;
+        // Not in the layout, the focused window's bounds are kept
+        write_frame(&db, "palette", Some(layout())).await;
+        write_frame(&db, "terminal", None).await;
+
+        let everything = search_apps(&db, WindowGeometryFilter::default()).await;
+        assert_eq!(everything, vec!["browser", "editor", "palette", "terminal"]);
+
+        let wide = search_apps(
+            &db,
+            WindowGeometryFilter {
+                width_gt: Some(1000),
+                ..Default::default()
+            },
+        )
+        .await;
+        assert_eq!(wide, vec!["browser"]);
+
+        let half = search_apps(
+            &db,
+            WindowGeometryFilter {
+                width_lt: Some(1000),
+                height_gt: Some(1000),
+                x_lt: Some(10),
+                ..Default::default()
+            },
+        )
+        .await;
+        assert_eq!(half, vec!["editor", "palette"]);
+
+        let offscreen = search_apps(
+            &db,
+            WindowGeometryFilter {
+                y_gt: Some(0),
+                ..Default::default()
+            },
+        )
+        .await;
+        assert!(offscreen.is_empty());
+    }
+
+    #[test]
+    fn test_geometry_filter_only_matches_frames() {
+        let filters = SearchFilters {
+            window: WindowGeometryFilter {
+                width_gt: Some(100),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert!(filters.allows(&ContentType::OCR));
+        assert!(!filters.allows(&ContentType::Audio));
+        assert!(filters.to_json().unwrap().contains("width_gt"));
+        assert_eq!(SearchFilters::default().to_json(), None);
+    }
+
+    #[tokio::test]
+    async fn test_retention_removes_layouts() {
+        let db = setup_test_db().await;
+        let id = write_frame(&db, "editor", Some(layout())).await;
+
+        let rows = db
+            .get_retention_rows(
+                RetentionKind::Frame,
+                Utc::now() + ChronoDuration::hours(1),
+                0,
+                10,
+            )
+            .await
+            .unwrap();
+        let ids: Vec<i64> = rows.iter().map(|row| row.id).collect();
+        db.delete_retention_rows(RetentionKind::Frame, &ids)
+            .await
+            .unwrap();
+
+        assert!(db.get_frame_layouts(&[id]).await.unwrap().is_empty());
+        let left: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM frame_layouts")
+            .fetch_one(&db.pool)
+            .await
+            .unwrap();
+        assert_eq!(left, 0);
+    }
+}
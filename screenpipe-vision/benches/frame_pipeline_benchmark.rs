@@ -0,0 +1,148 @@
+// cargo bench --bench frame_pipeline_benchmark
+//
+// What every captured 4K frame costs between capture and OCR: the dedup hash, the
+// comparison with the previous frame and handing the frame to the OCR and video
+// stages. "copying" is the DynamicImage pipeline, "shared" the Frame one. Allocated
+// bytes per frame are printed before the timings.
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use image::{DynamicImage, Rgba, RgbaImage};
+use screenpipe_vision::utils::{
+    calculate_hash, compare_images_histogram, compare_images_ssim, frame_difference,
+};
+use screenpipe_vision::Frame;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+struct CountingAllocator;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATED.fetch_add(new_size.saturating_sub(layout.size()), Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+const WIDTH: u32 = 3840;
+const HEIGHT: u32 = 2160;
+const STREAM_LEN: u32 = 8;
+
+/// A screen with a block of text scrolling down a little every frame
+fn synthetic_capture(index: u32) -> RgbaImage {
+    let offset = index * 12;
+    RgbaImage::from_fn(WIDTH, HEIGHT, |x, y| {
+        let line = (y + offset) / 24;
+        let ink = (x / 9 + line * 7) % 5 == 2 && (y + offset) % 24 < 16;
+        if ink {
+            Rgba([20, 20, 30, 255])
+        } else {
+            Rgba([240, 240, 235, 255])
+        }
+    })
+}
+
+/// Hash, compare with the previous frame, keep it as the best frame and hand the
+/// screen and its window to OCR, as each captured frame went before.
+fn copying_pipeline(previous: &DynamicImage, current: DynamicImage) -> (u64, f64) {
+    let hash = calculate_hash(&current);
+    let histogram_diff = compare_images_histogram(previous, &current).unwrap();
+    let ssim_diff = 1.0 - compare_images_ssim(previous, &current);
+    let max_average = current.clone();
+    let window = current.clone();
+    black_box((&max_average, &window, &current));
+    (hash, (histogram_diff + ssim_diff) / 2.0)
+}
+
+/// The same steps on shared frames
+fn shared_pipeline(previous: &Frame, current: Frame) -> (u64, f64) {
+    let hash = current.hash();
+    let difference = frame_difference(previous, &current).unwrap();
+    let max_average = current.clone();
+    let window = current.clone();
+    black_box((&max_average, &window, &current));
+    (hash, difference)
+}
+
+fn allocated_during(f: impl FnOnce()) -> usize {
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    f();
+    ALLOCATED.load(Ordering::Relaxed) - before
+}
+
+fn frame_pipeline_benchmark(c: &mut Criterion) {
+    let stream: Vec<RgbaImage> = (0..STREAM_LEN).map(synthetic_capture).collect();
+    let capture = |i: usize| stream[i % stream.len()].clone();
+
+    // The capture itself allocates the same in both pipelines, it is left out
+    let mut copying_bytes = 0;
+    let mut shared_bytes = 0;
+    let copying_previous = DynamicImage::ImageRgba8(capture(0));
+    let shared_previous = Frame::from(capture(0));
+    shared_previous.hash();
+    for i in 1..stream.len() {
+        let current = DynamicImage::ImageRgba8(capture(i));
+        copying_bytes += allocated_during(|| {
+            black_box(copying_pipeline(&copying_previous, current));
+        });
+        let current = Frame::from(capture(i));
+        shared_bytes += allocated_during(|| {
+            black_box(shared_pipeline(&shared_previous, current));
+        });
+    }
+    let frames = (stream.len() - 1) as f64;
+    println!(
+        "allocated per {}x{} frame: copying {:.1} MB, shared {:.1} MB",
+        WIDTH,
+        HEIGHT,
+        copying_bytes as f64 / frames / (1024.0 * 1024.0),
+        shared_bytes as f64 / frames / (1024.0 * 1024.0)
+    );
+
+    let mut group = c.benchmark_group("frame_pipeline_4k");
+    group.sample_size(10);
+    group.measurement_time(Duration::from_secs(20));
+
+    let mut i = 0;
+    group.bench_function("copying", |b| {
+        b.iter_batched(
+            || {
+                i += 1;
+                DynamicImage::ImageRgba8(capture(i))
+            },
+            |current| copying_pipeline(&copying_previous, current),
+            BatchSize::PerIteration,
+        )
+    });
+
+    let mut i = 0;
+    group.bench_function("shared", |b| {
+        b.iter_batched(
+            || {
+                i += 1;
+                Frame::from(capture(i))
+            },
+            |current| shared_pipeline(&shared_previous, current),
+            BatchSize::PerIteration,
+        )
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, frame_pipeline_benchmark);
+criterion_main!(benches);
@@ -4,8 +4,7 @@ use cidre::{
     ns,
     vn::{self, ImageRequestHandler, RecognizeTextRequest},
 };
-use image::DynamicImage;
-use image::GenericImageView;
+use image::{DynamicImage, GrayImage};
 use log::error;
 use serde::{Deserialize, Serialize};
 use std::{ffi::c_void, ptr::null_mut};
@@ -42,10 +41,19 @@ extern "C" fn release_callback(_refcon: *mut c_void, _data_ptr: *const *const c_
 pub fn perform_ocr_apple(
     image: &DynamicImage,
     languages: &ns::ArrayMut<ns::String>,
+) -> (String, String, Option<f64>) {
+    perform_ocr_apple_luma(&image.to_luma8(), languages)
+}
+
+/// Same as [`perform_ocr_apple`] on an image that is already grayscale, e.g. the
+/// cached [`crate::frame::Frame::luma`] of a captured window.
+#[cfg(target_os = "macos")]
+pub fn perform_ocr_apple_luma(
+    image: &GrayImage,
+    languages: &ns::ArrayMut<ns::String>,
 ) -> (String, String, Option<f64>) {
     let (width, height) = image.dimensions();
-    let rgb = image.grayscale().to_luma8();
-    let raw_data = rgb.as_raw();
+    let raw_data = image.as_raw();
     // let pixels = image.pixels();
 
     let mut overall_confidence = 0.0;
@@ -1,6 +1,10 @@
+use crate::frame::Frame;
 use image::DynamicImage;
 use log::error;
 use once_cell::sync::Lazy;
+use screenpipe_core::window_layout::{
+    build_window_layout, window_layout_supported, ListedWindow, WindowLayout, WindowRect,
+};
 use std::collections::HashSet;
 use std::error::Error;
 use std::fmt;
@@ -140,7 +144,7 @@ static SKIP_TITLES: Lazy<HashSet<&'static str>> = Lazy::new(|| {
 
 #[derive(Debug, Clone)]
 pub struct CapturedWindow {
-    pub image: DynamicImage,
+    pub image: Frame,
     pub app_name: String,
     pub window_name: String,
     pub is_focused: bool,
@@ -190,13 +194,24 @@ impl WindowFilters {
 
         false
     }
+
+    /// Whether the window matches the ignore list, whatever the include list says.
+    pub fn is_ignored(&self, app_name: &str, title: &str) -> bool {
+        let app_name_lower = app_name.to_lowercase();
+        let title_lower = title.to_lowercase();
+        self.ignore_set
+            .iter()
+            .any(|ignore| app_name_lower.contains(ignore) || title_lower.contains(ignore))
+    }
 }
 
+/// Captures the windows to OCR, and the layout of every window on the monitor from
+/// the same listing. The layout is `None` where windows can't be listed.
 pub async fn capture_all_visible_windows(
     monitor: &Monitor,
     window_filters: &WindowFilters,
     capture_unfocused_windows: bool,
-) -> Result<Vec<CapturedWindow>, Box<dyn Error>> {
+) -> Result<(Vec<CapturedWindow>, Option<WindowLayout>), Box<dyn Error>> {
     let mut all_captured_images = Vec::new();
 
     let windows = retry_with_backoff(
@@ -213,6 +228,9 @@ pub async fn capture_all_visible_windows(
     )
     .await?;
 
+    let layout =
+        window_layout_supported().then(|| window_layout(monitor, &windows, window_filters));
+
     for window in &windows {
         let is_valid = is_valid_window(window, monitor, window_filters, capture_unfocused_windows);
 
@@ -225,14 +243,14 @@ pub async fn capture_all_visible_windows(
 
         match window.capture_image() {
             Ok(buffer) => {
-                let image = DynamicImage::ImageRgba8(
+                let image = Frame::new(DynamicImage::ImageRgba8(
                     image::ImageBuffer::from_raw(
                         buffer.width() as u32,
                         buffer.height() as u32,
                         buffer.into_raw(),
                     )
                     .unwrap(),
-                );
+                ));
 
                 all_captured_images.push(CapturedWindow {
                     image,
@@ -250,7 +268,30 @@ pub async fn capture_all_visible_windows(
         }
     }
 
-    Ok(all_captured_images)
+    Ok((all_captured_images, layout))
+}
+
+/// Windows listed front to back, the order every platform's window list uses.
+fn window_layout(monitor: &Monitor, windows: &[Window], filters: &WindowFilters) -> WindowLayout {
+    let monitor_rect = WindowRect::new(monitor.x(), monitor.y(), monitor.width(), monitor.height());
+    let listed = windows
+        .iter()
+        .filter(|window| {
+            !SKIP_APPS.contains(window.app_name()) && !SKIP_TITLES.contains(window.title())
+        })
+        .map(|window| ListedWindow {
+            app_name: window.app_name(),
+            window_name: window.title(),
+            rect: WindowRect::new(window.x(), window.y(), window.width(), window.height()),
+            minimized: window.is_minimized(),
+            #[cfg(target_os = "macos")]
+            focused: window.is_focused(),
+            #[cfg(not(target_os = "macos"))]
+            focused: false,
+        });
+    build_window_layout(monitor_rect, listed, |app_name, title| {
+        filters.is_ignored(app_name, title)
+    })
 }
 
 pub fn is_valid_window(
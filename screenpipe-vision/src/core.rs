@@ -1,18 +1,22 @@
 #[cfg(target_os = "macos")]
-use crate::apple::perform_ocr_apple;
+use crate::apple::perform_ocr_apple_luma;
 use crate::capture_screenshot_by_window::CapturedWindow;
 use crate::capture_screenshot_by_window::WindowFilters;
+use crate::frame::Frame;
 #[cfg(target_os = "windows")]
 use crate::microsoft::perform_ocr_windows;
-use crate::monitor::get_monitor_by_id;
+use crate::monitor::{list_monitors, remap_display, DisplayConfig};
+use crate::ocr_scheduler::OcrScheduler;
 use crate::tesseract::perform_ocr_tesseract;
 use crate::utils::OcrEngine;
-use crate::utils::{capture_screenshot, compare_with_previous_image};
+use crate::utils::{capture_screenshot, compare_with_previous_image, CapturedScreen};
 use anyhow::{anyhow, Result};
 #[cfg(target_os = "macos")]
 use cidre::ns;
 use image::DynamicImage;
-use log::{debug, error};
+use log::{debug, error, info};
+use screenpipe_core::latency::{latency_tracker, LatencyStamps};
+use screenpipe_core::window_layout::WindowLayout;
 use screenpipe_core::Language;
 use screenpipe_integrations::unstructured_ocr::perform_ocr_cloud;
 use serde_json;
@@ -35,14 +39,21 @@ use xcap::Monitor;
 static APPLE_LANGUAGE_MAP: OnceLock<HashMap<Language, &'static str>> = OnceLock::new();
 
 pub struct CaptureResult {
-    pub image: DynamicImage,
+    pub image: Frame,
     pub frame_number: u64,
     pub timestamp: Instant,
     pub window_ocr_results: Vec<WindowOcrResult>,
+    /// Set when the OCR scheduler skipped this frame and reused the OCR of a newer frame
+    pub duplicate_of: Option<u64>,
+    /// Captured at `timestamp`, processed once OCR is done, committed by the recorder
+    pub latency: LatencyStamps,
+    /// Every window on the monitor, `None` where they can't be listed
+    pub window_layout: Option<WindowLayout>,
 }
 
+#[derive(Clone)]
 pub struct WindowOcrResult {
-    pub image: DynamicImage,
+    pub image: Frame,
     pub window_name: String,
     pub app_name: String,
     pub text: String,
@@ -52,11 +63,13 @@ pub struct WindowOcrResult {
 }
 
 pub struct OcrTaskData {
-    pub image: DynamicImage,
+    pub image: Frame,
     pub window_images: Vec<CapturedWindow>,
     pub frame_number: u64,
     pub timestamp: Instant,
     pub result_tx: Sender<CaptureResult>,
+    pub latency: LatencyStamps,
+    pub window_layout: Option<WindowLayout>,
 }
 
 pub async fn continuous_capture(
@@ -67,11 +80,39 @@ pub async fn continuous_capture(
     window_filters: Arc<WindowFilters>,
     languages: Vec<Language>,
     capture_unfocused_windows: bool,
+) {
+    continuous_capture_with_scheduler(
+        result_tx,
+        interval,
+        ocr_engine,
+        monitor_id,
+        window_filters,
+        languages,
+        capture_unfocused_windows,
+        None,
+    )
+    .await
+}
+
+/// Same as [`continuous_capture`], but hands frames to a shared [`OcrScheduler`]
+/// instead of running OCR inline when one is given.
+#[allow(clippy::too_many_arguments)]
+pub async fn continuous_capture_with_scheduler(
+    result_tx: Sender<CaptureResult>,
+    interval: Duration,
+    ocr_engine: OcrEngine,
+    monitor_id: u32,
+    window_filters: Arc<WindowFilters>,
+    languages: Vec<Language>,
+    capture_unfocused_windows: bool,
+    scheduler: Option<Arc<OcrScheduler>>,
 ) {
     let mut frame_counter: u64 = 0;
-    let mut previous_image: Option<DynamicImage> = None;
+    let mut previous_image: Option<Frame> = None;
     let mut max_average: Option<MaxAverageFrame> = None;
     let mut max_avg_value = 0.0;
+    // What the monitor was last seen as, to find it again once displays are renumbered
+    let mut display: Option<DisplayConfig> = None;
 
     debug!(
         "continuous_capture: Starting using monitor: {:?}",
@@ -79,21 +120,37 @@ pub async fn continuous_capture(
     );
 
     loop {
-        let monitor = match get_monitor_by_id(monitor_id).await {
-            Some(m) => m,
-            None => {
-                sleep(Duration::from_secs(1)).await;
-                continue;
-            }
+        let mut monitors = list_monitors().await;
+        let configs: Vec<DisplayConfig> = monitors.iter().map(DisplayConfig::of).collect();
+        let index = match &display {
+            Some(target) => remap_display(target, &configs),
+            None => configs.iter().position(|d| d.id == monitor_id),
+        };
+        let Some(index) = index else {
+            sleep(Duration::from_secs(1)).await;
+            continue;
         };
+        let found = configs[index].clone();
+        if let Some(previous) = display.as_ref().filter(|d| **d != found) {
+            info!(
+                "monitor {} display configuration changed: {} -> {}",
+                monitor_id, previous, found
+            );
+            // Frames from before the change are not compared with or sent after it
+            previous_image = None;
+            max_average = None;
+            max_avg_value = 0.0;
+        }
+        let monitor = monitors.swap_remove(index);
+        display = Some(found);
         let capture_result =
             match capture_screenshot(&monitor, &window_filters, capture_unfocused_windows).await {
-                Ok((image, window_images, image_hash, _capture_duration)) => {
+                Ok(screen) => {
                     debug!(
                         "Captured screenshot on monitor {} with hash: {}",
-                        monitor_id, image_hash
+                        monitor_id, screen.image_hash
                     );
-                    Some((image, window_images, image_hash))
+                    Some(screen)
                 }
                 Err(e) => {
                     error!("Failed to capture screenshot: {}", e);
@@ -101,7 +158,14 @@ pub async fn continuous_capture(
                 }
             };
 
-        if let Some((image, window_images, image_hash)) = capture_result {
+        if let Some(CapturedScreen {
+            image,
+            window_images,
+            window_layout,
+            image_hash,
+            ..
+        }) = capture_result
+        {
             let current_average = match compare_with_previous_image(
                 previous_image.as_ref(),
                 &image,
@@ -130,14 +194,16 @@ pub async fn continuous_capture(
                     frame_counter, current_average
                 );
                 frame_counter += 1;
-                tokio::time::sleep(interval).await;
+                tokio::time::sleep(latency_tracker().capture_interval(interval)).await;
                 continue;
             }
 
+            // Frames share their pixels, keeping one around for later doesn't copy it
             if current_average > max_avg_value {
                 max_average = Some(MaxAverageFrame {
                     image: image.clone(),
                     window_images: window_images.clone(),
+                    window_layout: window_layout.clone(),
                     image_hash,
                     frame_number: frame_counter,
                     timestamp: Instant::now(),
@@ -156,9 +222,13 @@ pub async fn continuous_capture(
                     frame_number: max_avg_frame.frame_number,
                     timestamp: max_avg_frame.timestamp,
                     result_tx: max_avg_frame.result_tx,
+                    latency: LatencyStamps::captured(max_avg_frame.timestamp),
+                    window_layout: max_avg_frame.window_layout,
                 };
 
-                if let Err(e) =
+                if let Some(scheduler) = &scheduler {
+                    scheduler.submit(monitor_id, ocr_task_data);
+                } else if let Err(e) =
                     process_ocr_task(ocr_task_data, &ocr_engine, languages.clone()).await
                 {
                     error!("Error processing OCR task: {}", e);
@@ -172,13 +242,15 @@ pub async fn continuous_capture(
         }
 
         frame_counter += 1;
-        tokio::time::sleep(interval).await;
+        // Stretched while over the latency budget with auto degrade on
+        tokio::time::sleep(latency_tracker().capture_interval(interval)).await;
     }
 }
 
 pub struct MaxAverageFrame {
-    pub image: DynamicImage,
+    pub image: Frame,
     pub window_images: Vec<CapturedWindow>,
+    pub window_layout: Option<WindowLayout>,
     pub image_hash: u64,
     pub frame_number: u64,
     pub timestamp: Instant,
@@ -197,6 +269,8 @@ pub async fn process_ocr_task(
         frame_number,
         timestamp,
         result_tx,
+        mut latency,
+        window_layout,
     } = ocr_task_data;
 
     let start_time = Instant::now();
@@ -205,6 +279,42 @@ pub async fn process_ocr_task(
         frame_number
     );
 
+    let window_ocr_results = ocr_windows(window_images, ocr_engine, languages).await?;
+    let window_count = window_ocr_results.len();
+    latency.mark_processed();
+
+    let capture_result = CaptureResult {
+        image,
+        frame_number,
+        timestamp,
+        window_ocr_results,
+        duplicate_of: None,
+        latency,
+        window_layout,
+    };
+
+    if let Err(e) = result_tx.send(capture_result).await {
+        error!("Failed to send OCR result: {}", e);
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            "Failed to send OCR result",
+        ));
+    }
+
+    let duration = start_time.elapsed();
+    debug!(
+        "OCR task processed frame {} with {} windows in {:?}",
+        frame_number, window_count, duration
+    );
+    Ok(())
+}
+
+/// Runs OCR on every captured window of a frame.
+pub async fn ocr_windows(
+    window_images: Vec<CapturedWindow>,
+    ocr_engine: &OcrEngine,
+    languages: Vec<Language>,
+) -> Result<Vec<WindowOcrResult>, std::io::Error> {
     let mut window_ocr_results = Vec::new();
     let mut total_confidence = 0.0;
     let mut window_count = 0;
@@ -233,7 +343,9 @@ pub async fn process_ocr_task(
                 .await
                 .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?,
             #[cfg(target_os = "macos")]
-            OcrEngine::AppleNative => perform_ocr_apple(&captured_window.image, &languages_slice),
+            OcrEngine::AppleNative => {
+                perform_ocr_apple_luma(captured_window.image.luma(), &languages_slice)
+            }
             _ => {
                 return Err(std::io::Error::new(
                     std::io::ErrorKind::Other,
@@ -258,32 +370,37 @@ pub async fn process_ocr_task(
         });
     }
 
-    let capture_result = CaptureResult {
-        image,
-        frame_number,
-        timestamp,
-        window_ocr_results,
-    };
-
-    if let Err(e) = result_tx.send(capture_result).await {
-        error!("Failed to send OCR result: {}", e);
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            "Failed to send OCR result",
-        ));
-    }
-
-    let duration = start_time.elapsed();
     let avg_confidence = if window_count > 0 {
         total_confidence / window_count as f64
     } else {
         0.0
     };
     debug!(
-        "OCR task processed frame {} with {} windows in {:?}, average confidence: {:.2}",
-        frame_number, window_count, duration, avg_confidence
+        "OCR'd {} windows, average confidence: {:.2}",
+        window_ocr_results.len(),
+        avg_confidence
     );
-    Ok(())
+    Ok(window_ocr_results)
+}
+
+/// Runs OCR on a single image that didn't come from a screen capture, e.g. a file on disk.
+pub async fn ocr_image(
+    image: DynamicImage,
+    ocr_engine: &OcrEngine,
+    languages: Vec<Language>,
+) -> Result<String, std::io::Error> {
+    let window = CapturedWindow {
+        image: Frame::new(image),
+        app_name: String::new(),
+        window_name: String::new(),
+        is_focused: false,
+    };
+    let results = ocr_windows(vec![window], ocr_engine, languages).await?;
+    Ok(results
+        .into_iter()
+        .next()
+        .map(|result| result.text)
+        .unwrap_or_default())
 }
 
 fn parse_json_output(json_output: &str) -> Vec<HashMap<String, String>> {
@@ -0,0 +1,221 @@
+//! Captured frames shared between dedup, OCR and the video encoder.
+//!
+//! A [`Frame`] owns its pixels once, behind an `Arc`: handing it to another stage is a
+//! refcount bump. Other layouts (grayscale for OCR, a downscaled preview for dedup)
+//! are derived on first use and cached on the frame, so each is computed at most once
+//! however many stages ask for it.
+
+use image::{DynamicImage, GrayImage, RgbaImage};
+use std::fmt;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::ops::Deref;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+
+/// Longest side of the preview used for the dedup hash and comparisons.
+pub const PREVIEW_MAX_SIDE: u32 = 480;
+
+/// Pixel bytes held by frames that are still alive, across the process.
+static LIVE_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    Rgba8,
+    Rgb8,
+    Luma8,
+    LumaA8,
+    /// 16 bit and float layouts, only seen for images loaded from disk
+    Other,
+}
+
+impl PixelFormat {
+    fn of(image: &DynamicImage) -> Self {
+        match image {
+            DynamicImage::ImageRgba8(_) => PixelFormat::Rgba8,
+            DynamicImage::ImageRgb8(_) => PixelFormat::Rgb8,
+            DynamicImage::ImageLuma8(_) => PixelFormat::Luma8,
+            DynamicImage::ImageLumaA8(_) => PixelFormat::LumaA8,
+            _ => PixelFormat::Other,
+        }
+    }
+
+    /// Bytes per pixel for the 8 bit layouts
+    fn channels(self) -> Option<usize> {
+        match self {
+            PixelFormat::Rgba8 => Some(4),
+            PixelFormat::Rgb8 => Some(3),
+            PixelFormat::Luma8 => Some(1),
+            PixelFormat::LumaA8 => Some(2),
+            PixelFormat::Other => None,
+        }
+    }
+}
+
+struct FrameData {
+    image: DynamicImage,
+    format: PixelFormat,
+    luma: OnceLock<GrayImage>,
+    preview: OnceLock<GrayImage>,
+    hash: OnceLock<u64>,
+}
+
+impl Drop for FrameData {
+    fn drop(&mut self) {
+        LIVE_BYTES.fetch_sub(self.image.as_bytes().len(), Ordering::Relaxed);
+    }
+}
+
+/// An immutable captured image. Cloning shares the pixels, it never copies them.
+///
+/// Derefs to the underlying [`DynamicImage`] for code that only reads it, e.g. the
+/// video encoder.
+#[derive(Clone)]
+pub struct Frame(Arc<FrameData>);
+
+impl Frame {
+    pub fn new(image: DynamicImage) -> Self {
+        LIVE_BYTES.fetch_add(image.as_bytes().len(), Ordering::Relaxed);
+        Self(Arc::new(FrameData {
+            format: PixelFormat::of(&image),
+            image,
+            luma: OnceLock::new(),
+            preview: OnceLock::new(),
+            hash: OnceLock::new(),
+        }))
+    }
+
+    pub fn image(&self) -> &DynamicImage {
+        &self.0.image
+    }
+
+    pub fn format(&self) -> PixelFormat {
+        self.0.format
+    }
+
+    /// Bytes between the start of two rows
+    pub fn stride(&self) -> usize {
+        let bytes_per_pixel = self.0.image.color().bytes_per_pixel() as usize;
+        self.0.image.width() as usize * bytes_per_pixel
+    }
+
+    /// Full resolution grayscale, converted on first use. Free for luma frames.
+    pub fn luma(&self) -> &GrayImage {
+        match &self.0.image {
+            DynamicImage::ImageLuma8(luma) => luma,
+            image => self.0.luma.get_or_init(|| image.to_luma8()),
+        }
+    }
+
+    /// Grayscale downscaled to at most [`PREVIEW_MAX_SIDE`], computed on first use
+    /// straight from the captured pixels. Frames that already fit are not scaled.
+    pub fn preview(&self) -> &GrayImage {
+        let (width, height) = (self.0.image.width(), self.0.image.height());
+        let factor = width.max(height).div_ceil(PREVIEW_MAX_SIDE).max(1);
+        if factor == 1 {
+            return self.luma();
+        }
+        self.0
+            .preview
+            .get_or_init(|| match self.0.format.channels() {
+                Some(channels) => {
+                    downscale_luma(self.0.image.as_bytes(), width, height, channels, factor)
+                }
+                None => downscale_luma(self.luma().as_raw(), width, height, 1, factor),
+            })
+    }
+
+    /// Hash of the preview, so frames that look the same hash the same without
+    /// hashing every captured byte.
+    pub fn hash(&self) -> u64 {
+        *self.0.hash.get_or_init(|| {
+            let preview = self.preview();
+            let mut hasher = DefaultHasher::new();
+            preview.dimensions().hash(&mut hasher);
+            preview.as_raw().hash(&mut hasher);
+            hasher.finish()
+        })
+    }
+
+    /// Whether both frames share the same pixels
+    pub fn ptr_eq(&self, other: &Frame) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+
+    /// Pixel bytes held by all frames still alive in the process, shared pixels
+    /// counted once.
+    pub fn live_bytes() -> usize {
+        LIVE_BYTES.load(Ordering::Relaxed)
+    }
+}
+
+impl Deref for Frame {
+    type Target = DynamicImage;
+
+    fn deref(&self) -> &DynamicImage {
+        &self.0.image
+    }
+}
+
+impl From<DynamicImage> for Frame {
+    fn from(image: DynamicImage) -> Self {
+        Frame::new(image)
+    }
+}
+
+impl From<RgbaImage> for Frame {
+    fn from(image: RgbaImage) -> Self {
+        Frame::new(DynamicImage::ImageRgba8(image))
+    }
+}
+
+impl fmt::Debug for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Frame")
+            .field("width", &self.0.image.width())
+            .field("height", &self.0.image.height())
+            .field("format", &self.0.format)
+            .finish()
+    }
+}
+
+/// Box filtered grayscale of `factor` x `factor` blocks of 8 bit pixels, in one pass
+/// over the source rows. Partial blocks at the right and bottom edges are dropped.
+fn downscale_luma(
+    bytes: &[u8],
+    width: u32,
+    height: u32,
+    channels: usize,
+    factor: u32,
+) -> GrayImage {
+    let out_width = (width / factor).max(1);
+    let out_height = (height / factor).max(1);
+    let stride = width as usize * channels;
+    let columns = (out_width * factor).min(width) as usize;
+
+    let mut sums = vec![0u32; out_width as usize];
+    let mut pixels = Vec::with_capacity((out_width * out_height) as usize);
+    for out_y in 0..out_height {
+        sums.fill(0);
+        let rows = out_y * factor..((out_y + 1) * factor).min(height);
+        let row_count = rows.len() as u32;
+        for y in rows {
+            let row = &bytes[y as usize * stride..][..stride];
+            for x in 0..columns {
+                let pixel = &row[x * channels..][..channels];
+                let luma = if channels >= 3 {
+                    // Rec. 709, same weights as `DynamicImage::to_luma8`
+                    let (r, g, b) = (pixel[0] as u32, pixel[1] as u32, pixel[2] as u32);
+                    (2126 * r + 7152 * g + 722 * b + 5000) / 10000
+                } else {
+                    pixel[0] as u32
+                };
+                sums[x / factor as usize] += luma;
+            }
+        }
+        for (out_x, sum) in sums.iter().enumerate() {
+            let column_count = (width - out_x as u32 * factor).min(factor);
+            pixels.push((sum / (row_count * column_count)) as u8);
+        }
+    }
+    GrayImage::from_raw(out_width, out_height, pixels).expect("preview size matches its pixels")
+}
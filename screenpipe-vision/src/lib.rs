@@ -1,15 +1,22 @@
 #[cfg(target_os = "macos")]
 pub mod apple;
 pub mod core;
+pub mod frame;
 pub mod run_ui_monitoring_macos;
 #[cfg(target_os = "windows")]
 pub mod microsoft;
 pub mod monitor;
+pub mod ocr_scheduler;
 pub mod tesseract;
 pub mod utils;
 #[cfg(target_os = "macos")]
 pub use apple::perform_ocr_apple;
-pub use core::{continuous_capture, process_ocr_task, CaptureResult};
+pub use core::{
+    continuous_capture, continuous_capture_with_scheduler, ocr_image, process_ocr_task,
+    CaptureResult,
+};
+pub use frame::Frame;
+pub use ocr_scheduler::{OcrScheduler, OcrSchedulerConfig, OcrSchedulerMetrics};
 pub use utils::OcrEngine;
 pub mod capture_screenshot_by_window;
 pub use run_ui_monitoring_macos::run_ui;
@@ -1,3 +1,6 @@
+use log::error;
+use std::fmt;
+
 #[cfg(target_os = "macos")]
 use xcap_macos::Monitor;
 
@@ -5,8 +8,14 @@ use xcap_macos::Monitor;
 use xcap::Monitor;
 
 pub async fn list_monitors() -> Vec<Monitor> {
-    let monitors = Monitor::all().unwrap();
-    return monitors.iter().map(|m| m.clone()).collect();
+    // Listing fails for a moment while displays are being reconfigured
+    match Monitor::all() {
+        Ok(monitors) => monitors,
+        Err(e) => {
+            error!("Failed to list monitors: {}", e);
+            Vec::new()
+        }
+    }
 }
 
 pub async fn get_default_monitor() -> Monitor {
@@ -18,3 +27,56 @@ pub async fn get_monitor_by_id(id: u32) -> Option<Monitor> {
     let monitors = list_monitors().await;
     monitors.iter().find(|m| m.id() == id).cloned()
 }
+
+/// Where a display sits and how big it is, as last seen by the capture loop.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisplayConfig {
+    pub id: u32,
+    pub name: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl DisplayConfig {
+    pub fn of(monitor: &Monitor) -> Self {
+        Self {
+            id: monitor.id(),
+            name: monitor.name().to_string(),
+            x: monitor.x(),
+            y: monitor.y(),
+            width: monitor.width(),
+            height: monitor.height(),
+        }
+    }
+}
+
+impl fmt::Display for DisplayConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}) {}x{} at {},{}",
+            self.name, self.id, self.width, self.height, self.x, self.y
+        )
+    }
+}
+
+/// Index of the display `target` became after a configuration change: the one with
+/// the same id, else the only one with its name, else the one at its position.
+/// Plugging in a projector can renumber every display.
+pub fn remap_display(target: &DisplayConfig, displays: &[DisplayConfig]) -> Option<usize> {
+    if let Some(index) = displays.iter().position(|d| d.id == target.id) {
+        return Some(index);
+    }
+    let mut named = displays
+        .iter()
+        .enumerate()
+        .filter(|(_, d)| d.name == target.name);
+    if let (Some((index, _)), None) = (named.next(), named.next()) {
+        return Some(index);
+    }
+    displays
+        .iter()
+        .position(|d| d.x == target.x && d.y == target.y)
+}
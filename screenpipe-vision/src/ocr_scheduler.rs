@@ -0,0 +1,338 @@
+use crate::capture_screenshot_by_window::CapturedWindow;
+use crate::core::{ocr_windows, CaptureResult, OcrTaskData, WindowOcrResult};
+use crate::utils::OcrEngine;
+use log::{debug, error, warn};
+use screenpipe_core::Language;
+use std::collections::{BTreeMap, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+/// Runs OCR for the windows of one frame. Swappable so the scheduler can be
+/// exercised without a real OCR engine.
+pub type OcrProcessor = Arc<
+    dyn Fn(
+            Vec<CapturedWindow>,
+        )
+            -> Pin<Box<dyn Future<Output = Result<Vec<WindowOcrResult>, std::io::Error>> + Send>>
+        + Send
+        + Sync,
+>;
+
+#[derive(Debug, Clone)]
+pub struct OcrSchedulerConfig {
+    /// Upper bound on OCR'd frames per minute across all monitors, `None` for unlimited
+    pub max_frames_per_minute: Option<u32>,
+    /// Pending frames kept per window before the oldest is dropped
+    pub max_pending_per_window: usize,
+    /// Number of frames OCR'd concurrently
+    pub workers: usize,
+}
+
+impl Default for OcrSchedulerConfig {
+    fn default() -> Self {
+        Self {
+            max_frames_per_minute: None,
+            max_pending_per_window: 8,
+            workers: 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MonitorOcrMetrics {
+    pub queue_depth: usize,
+    pub processed: u64,
+    /// Frames that reused the OCR of a newer frame from the same window
+    pub skipped: u64,
+    /// Frames dropped because the window's pending queue was full
+    pub dropped: u64,
+    pub last_latency_ms: u64,
+    pub max_latency_ms: u64,
+    pub avg_latency_ms: f64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct OcrSchedulerMetrics {
+    pub queue_depth: usize,
+    /// Times a worker waited because of `max_frames_per_minute`
+    pub throttled: u64,
+    pub monitors: BTreeMap<u32, MonitorOcrMetrics>,
+}
+
+/// Frames are batched per monitor and focused window: only the newest pending
+/// frame of a batch is OCR'd.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct BatchKey {
+    monitor_id: u32,
+    window: String,
+}
+
+struct PendingFrame {
+    task: OcrTaskData,
+    seq: u64,
+}
+
+#[derive(Default)]
+struct SchedulerState {
+    pending: BTreeMap<BatchKey, VecDeque<PendingFrame>>,
+    focused_monitor: Option<u32>,
+    next_seq: u64,
+    next_slot: Option<Instant>,
+    metrics: OcrSchedulerMetrics,
+}
+
+/// Shared OCR queue for multi-monitor setups.
+///
+/// Focused monitor first, newest frame per window, optional global frames/minute cap.
+/// Within a priority class batches are served oldest first.
+pub struct OcrScheduler {
+    config: OcrSchedulerConfig,
+    processor: OcrProcessor,
+    state: Mutex<SchedulerState>,
+    notify: Notify,
+}
+
+impl OcrScheduler {
+    pub fn new(
+        config: OcrSchedulerConfig,
+        ocr_engine: OcrEngine,
+        languages: Vec<Language>,
+    ) -> Self {
+        let processor: OcrProcessor = Arc::new(move |windows| {
+            let languages = languages.clone();
+            Box::pin(async move { ocr_windows(windows, &ocr_engine, languages).await })
+        });
+        Self::with_processor(config, processor)
+    }
+
+    pub fn with_processor(config: OcrSchedulerConfig, processor: OcrProcessor) -> Self {
+        Self {
+            config,
+            processor,
+            state: Mutex::new(SchedulerState::default()),
+            notify: Notify::new(),
+        }
+    }
+
+    /// Spawns the OCR workers.
+    pub fn spawn(self: &Arc<Self>) {
+        for worker in 0..self.config.workers.max(1) {
+            let scheduler = Arc::clone(self);
+            tokio::spawn(async move {
+                debug!("ocr scheduler worker {} started", worker);
+                scheduler.run().await;
+            });
+        }
+    }
+
+    pub fn submit(&self, monitor_id: u32, task: OcrTaskData) {
+        let focused_window = task.window_images.iter().find(|w| w.is_focused);
+        let key = BatchKey {
+            monitor_id,
+            window: focused_window
+                .map(|w| format!("{}/{}", w.app_name, w.window_name))
+                .unwrap_or_default(),
+        };
+        let has_focus = focused_window.is_some();
+
+        let mut state = self.state.lock().unwrap();
+        if has_focus {
+            state.focused_monitor = Some(monitor_id);
+        }
+        let seq = state.next_seq;
+        state.next_seq += 1;
+
+        let queue = state.pending.entry(key).or_default();
+        queue.push_back(PendingFrame { task, seq });
+        let dropped = if queue.len() > self.config.max_pending_per_window.max(1) {
+            queue.pop_front().is_some()
+        } else {
+            false
+        };
+
+        let monitor = state.metrics.monitors.entry(monitor_id).or_default();
+        monitor.queue_depth += 1;
+        state.metrics.queue_depth += 1;
+        if dropped {
+            let monitor = state.metrics.monitors.entry(monitor_id).or_default();
+            monitor.queue_depth -= 1;
+            monitor.dropped += 1;
+            state.metrics.queue_depth -= 1;
+        }
+        drop(state);
+
+        self.notify.notify_one();
+    }
+
+    pub fn metrics(&self) -> OcrSchedulerMetrics {
+        self.state.lock().unwrap().metrics.clone()
+    }
+
+    async fn run(&self) {
+        loop {
+            let batch = match self.next_batch() {
+                Some(batch) => batch,
+                None => {
+                    self.notify.notified().await;
+                    continue;
+                }
+            };
+            self.process_batch(batch).await;
+        }
+    }
+
+    /// Picks the batch to OCR next: focused monitor first, then the batch
+    /// holding the oldest pending frame.
+    fn next_batch(&self) -> Option<(u32, Vec<PendingFrame>)> {
+        let mut state = self.state.lock().unwrap();
+        let focused = state.focused_monitor;
+        let key = state
+            .pending
+            .iter()
+            .filter(|(_, frames)| !frames.is_empty())
+            .min_by_key(|(key, frames)| {
+                let not_focused = Some(key.monitor_id) != focused;
+                (
+                    not_focused,
+                    frames.front().map(|f| f.seq).unwrap_or(u64::MAX),
+                )
+            })
+            .map(|(key, _)| key.clone())?;
+
+        let frames: Vec<PendingFrame> = state.pending.remove(&key)?.into_iter().collect();
+        let monitor = state.metrics.monitors.entry(key.monitor_id).or_default();
+        monitor.queue_depth -= frames.len();
+        state.metrics.queue_depth -= frames.len();
+        Some((key.monitor_id, frames))
+    }
+
+    /// Waits for the next slot allowed by `max_frames_per_minute`.
+    async fn throttle(&self) {
+        let Some(per_minute) = self.config.max_frames_per_minute.filter(|n| *n > 0) else {
+            return;
+        };
+        let spacing = Duration::from_secs_f64(60.0 / per_minute as f64);
+        let wait = {
+            let mut state = self.state.lock().unwrap();
+            let now = Instant::now();
+            let slot = state.next_slot.map_or(now, |slot| slot.max(now));
+            state.next_slot = Some(slot + spacing);
+            if slot > now {
+                state.metrics.throttled += 1;
+            }
+            slot - now
+        };
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    async fn process_batch(&self, (monitor_id, mut frames): (u32, Vec<PendingFrame>)) {
+        self.throttle().await;
+
+        // Frames that piled up while throttled fold into this batch as well
+        {
+            let mut state = self.state.lock().unwrap();
+            let keys: Vec<BatchKey> = state
+                .pending
+                .keys()
+                .filter(|k| k.monitor_id == monitor_id)
+                .cloned()
+                .collect();
+            let window = frames
+                .last()
+                .and_then(|f| f.task.window_images.iter().find(|w| w.is_focused))
+                .map(|w| format!("{}/{}", w.app_name, w.window_name))
+                .unwrap_or_default();
+            for key in keys.into_iter().filter(|k| k.window == window) {
+                if let Some(more) = state.pending.remove(&key) {
+                    let monitor = state.metrics.monitors.entry(monitor_id).or_default();
+                    monitor.queue_depth -= more.len();
+                    state.metrics.queue_depth -= more.len();
+                    frames.extend(more);
+                }
+            }
+        }
+
+        let Some(newest) = frames.pop() else {
+            return;
+        };
+        let skipped = frames;
+        let OcrTaskData {
+            image,
+            window_images,
+            frame_number,
+            timestamp,
+            result_tx,
+            latency: stamps,
+            window_layout,
+        } = newest.task;
+
+        let window_ocr_results = match (self.processor)(window_images).await {
+            Ok(results) => results,
+            Err(e) => {
+                error!(
+                    "ocr failed for frame {} on monitor {}: {}",
+                    frame_number, monitor_id, e
+                );
+                return;
+            }
+        };
+
+        let stamps = stamps.processed();
+        let latency = timestamp.elapsed();
+        {
+            let mut state = self.state.lock().unwrap();
+            let monitor = state.metrics.monitors.entry(monitor_id).or_default();
+            let latency_ms = latency.as_millis() as u64;
+            monitor.processed += 1;
+            monitor.skipped += skipped.len() as u64;
+            monitor.last_latency_ms = latency_ms;
+            monitor.max_latency_ms = monitor.max_latency_ms.max(latency_ms);
+            monitor.avg_latency_ms +=
+                (latency_ms as f64 - monitor.avg_latency_ms) / monitor.processed as f64;
+        }
+
+        if !skipped.is_empty() {
+            debug!(
+                "monitor {}: frame {} stands in for {} skipped frames",
+                monitor_id,
+                frame_number,
+                skipped.len()
+            );
+        }
+
+        // Oldest first so the video encoder sees frames in capture order
+        for frame in skipped {
+            let result = CaptureResult {
+                image: frame.task.image,
+                frame_number: frame.task.frame_number,
+                timestamp: frame.task.timestamp,
+                window_ocr_results: window_ocr_results.clone(),
+                duplicate_of: Some(frame_number),
+                latency: frame.task.latency.processed(),
+                // Each skipped frame keeps the layout it was captured with
+                window_layout: frame.task.window_layout,
+            };
+            if frame.task.result_tx.send(result).await.is_err() {
+                warn!("ocr result receiver for monitor {} dropped", monitor_id);
+            }
+        }
+
+        let result = CaptureResult {
+            image,
+            frame_number,
+            timestamp,
+            window_ocr_results,
+            duplicate_of: None,
+            latency: stamps,
+            window_layout,
+        };
+        if result_tx.send(result).await.is_err() {
+            warn!("ocr result receiver for monitor {} dropped", monitor_id);
+        }
+    }
+}
@@ -2,9 +2,11 @@ use crate::capture_screenshot_by_window::{
     capture_all_visible_windows, CapturedWindow, WindowFilters,
 };
 use crate::core::MaxAverageFrame;
-use image::DynamicImage;
+use crate::frame::Frame;
+use image::{DynamicImage, GrayImage};
 use image_compare::{Algorithm, Metric, Similarity};
 use log::{debug, error, warn};
+use screenpipe_core::window_layout::WindowLayout;
 use std::hash::{DefaultHasher, Hash, Hasher};
 use std::time::{Duration, Instant};
 
@@ -37,68 +39,99 @@ pub fn compare_images_histogram(
     image1: &DynamicImage,
     image2: &DynamicImage,
 ) -> anyhow::Result<f64> {
-    let image_one = image1.to_luma8();
-    let image_two = image2.to_luma8();
-    image_compare::gray_similarity_histogram(Metric::Hellinger, &image_one, &image_two)
-        .map_err(|e| anyhow::anyhow!("Failed to compare images: {}", e))
+    compare_gray_histogram(&image1.to_luma8(), &image2.to_luma8())
 }
 
 pub fn compare_images_ssim(image1: &DynamicImage, image2: &DynamicImage) -> f64 {
-    let image_one = image1.to_luma8();
-    let image_two = image2.to_luma8();
+    compare_gray_ssim(&image1.to_luma8(), &image2.to_luma8())
+}
+
+fn compare_gray_histogram(image_one: &GrayImage, image_two: &GrayImage) -> anyhow::Result<f64> {
+    image_compare::gray_similarity_histogram(Metric::Hellinger, image_one, image_two)
+        .map_err(|e| anyhow::anyhow!("Failed to compare images: {}", e))
+}
+
+fn compare_gray_ssim(image_one: &GrayImage, image_two: &GrayImage) -> f64 {
     let result: Similarity =
-        image_compare::gray_similarity_structure(&Algorithm::MSSIMSimple, &image_one, &image_two)
+        image_compare::gray_similarity_structure(&Algorithm::MSSIMSimple, image_one, image_two)
             .expect("Images had different dimensions");
     result.score
 }
 
+/// Difference between two frames, from 0 (same) to 1, averaged over the histogram and
+/// SSIM distances of their previews. Frames of different sizes (e.g. after a
+/// resolution change) count as completely different.
+pub fn frame_difference(previous: &Frame, current: &Frame) -> anyhow::Result<f64> {
+    let (previous, current) = (previous.preview(), current.preview());
+    if previous.dimensions() != current.dimensions() {
+        return Ok(1.0);
+    }
+    let histogram_diff = compare_gray_histogram(previous, current)?;
+    let ssim_diff = 1.0 - compare_gray_ssim(previous, current);
+    Ok((histogram_diff + ssim_diff) / 2.0)
+}
+
+/// A monitor screenshot with the windows captured on it.
+pub struct CapturedScreen {
+    pub image: Frame,
+    pub window_images: Vec<CapturedWindow>,
+    /// `None` where the platform doesn't list window positions
+    pub window_layout: Option<WindowLayout>,
+    pub image_hash: u64,
+    pub capture_duration: Duration,
+}
+
 pub async fn capture_screenshot(
     monitor: &Monitor,
     window_filters: &WindowFilters,
     capture_unfocused_windows: bool,
-) -> Result<(DynamicImage, Vec<CapturedWindow>, u64, Duration), anyhow::Error> {
+) -> Result<CapturedScreen, anyhow::Error> {
     // info!("Starting screenshot capture for monitor: {:?}", monitor);
     let capture_start = Instant::now();
     let buffer = monitor.capture_image().map_err(|e| {
         error!("Failed to capture monitor image: {}", e);
         anyhow::anyhow!("Monitor capture failed")
     })?;
-    let image = DynamicImage::ImageRgba8(buffer);
-    let image_hash = calculate_hash(&image);
+    let image = Frame::from(buffer);
+    let image_hash = image.hash();
     let capture_duration = capture_start.elapsed();
 
-    let window_images =
+    let (window_images, window_layout) =
         match capture_all_visible_windows(monitor, window_filters, capture_unfocused_windows).await
         {
-            Ok(images) => images,
+            Ok(windows) => windows,
             Err(e) => {
                 warn!(
                     "Failed to capture window images: {}. Continuing with empty result.",
                     e
                 );
-                Vec::new()
+                (Vec::new(), None)
             }
         };
 
-    Ok((image, window_images, image_hash, capture_duration))
+    Ok(CapturedScreen {
+        image,
+        window_images,
+        window_layout,
+        image_hash,
+        capture_duration,
+    })
 }
 
 pub async fn compare_with_previous_image(
-    previous_image: Option<&DynamicImage>,
-    current_image: &DynamicImage,
+    previous_image: Option<&Frame>,
+    current_image: &Frame,
     max_average: &mut Option<MaxAverageFrame>,
     frame_number: u64,
     max_avg_value: &mut f64,
 ) -> anyhow::Result<f64> {
     let mut current_average = 0.0;
     if let Some(prev_image) = previous_image {
-        let histogram_diff = compare_images_histogram(prev_image, current_image)?;
-        let ssim_diff = 1.0 - compare_images_ssim(prev_image, current_image);
-        current_average = (histogram_diff + ssim_diff) / 2.0;
+        current_average = frame_difference(prev_image, current_image)?;
         let max_avg_frame_number = max_average.as_ref().map_or(0, |frame| frame.frame_number);
         debug!(
-            "Frame {}: Histogram diff: {:.3}, SSIM diff: {:.3}, Current Average: {:.3}, Max_avr: {:.3} Fr: {}",
-            frame_number, histogram_diff, ssim_diff, current_average, *max_avg_value, max_avg_frame_number
+            "Frame {}: Current Average: {:.3}, Max_avr: {:.3} Fr: {}",
+            frame_number, current_average, *max_avg_value, max_avg_frame_number
         );
     } else {
         debug!("No previous image to compare for frame {}", frame_number);
@@ -0,0 +1,128 @@
+#[cfg(test)]
+mod tests {
+    // Alone in its test binary: Frame::live_bytes counts every frame in the process.
+    use image::RgbaImage;
+    use screenpipe_core::latency::LatencyStamps;
+    use screenpipe_vision::capture_screenshot_by_window::CapturedWindow;
+    use screenpipe_vision::core::{OcrTaskData, WindowOcrResult};
+    use screenpipe_vision::ocr_scheduler::OcrProcessor;
+    use screenpipe_vision::{CaptureResult, Frame, OcrScheduler, OcrSchedulerConfig};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+    use tokio::sync::mpsc;
+
+    const WIDTH: u32 = 1920;
+    const HEIGHT: u32 = 1080;
+    const FRAME_BYTES: usize = (WIDTH * HEIGHT * 4) as usize;
+    const MAX_PENDING: usize = 4;
+    const RESULT_CAPACITY: usize = 2;
+
+    fn record_peak(peak: &AtomicUsize) {
+        peak.fetch_max(Frame::live_bytes(), Ordering::Relaxed);
+    }
+
+    fn processor(peak: Arc<AtomicUsize>) -> OcrProcessor {
+        Arc::new(move |windows: Vec<CapturedWindow>| {
+            let peak = peak.clone();
+            Box::pin(async move {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                record_peak(&peak);
+                Ok(windows
+                    .into_iter()
+                    .map(|w| WindowOcrResult {
+                        image: w.image,
+                        window_name: w.window_name,
+                        app_name: w.app_name,
+                        text: String::new(),
+                        text_json: vec![],
+                        focused: w.is_focused,
+                        confidence: 1.0,
+                    })
+                    .collect())
+            })
+        })
+    }
+
+    /// Capture outpaces both OCR and a slow consumer standing in for the video
+    /// encoder. Pixels held stay bounded by the queues, not by the frames captured.
+    #[tokio::test]
+    async fn test_memory_is_bounded_under_backpressure() {
+        let peak = Arc::new(AtomicUsize::new(0));
+        let scheduler = Arc::new(OcrScheduler::with_processor(
+            OcrSchedulerConfig {
+                max_frames_per_minute: None,
+                max_pending_per_window: MAX_PENDING,
+                workers: 1,
+            },
+            processor(peak.clone()),
+        ));
+        scheduler.spawn();
+
+        let (tx, mut rx) = mpsc::channel::<CaptureResult>(RESULT_CAPACITY);
+        let consumer = tokio::spawn({
+            let peak = peak.clone();
+            async move {
+                let mut received = 0;
+                while let Some(result) = rx.recv().await {
+                    record_peak(&peak);
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    drop(result);
+                    received += 1;
+                }
+                received
+            }
+        });
+
+        let start = Instant::now();
+        let mut captured = 0;
+        while start.elapsed() < Duration::from_secs(1) {
+            let image = Frame::from(RgbaImage::new(WIDTH, HEIGHT));
+            let timestamp = Instant::now();
+            scheduler.submit(
+                0,
+                OcrTaskData {
+                    window_images: vec![CapturedWindow {
+                        image: image.clone(),
+                        app_name: "app".to_string(),
+                        window_name: "window".to_string(),
+                        is_focused: true,
+                    }],
+                    image,
+                    frame_number: captured,
+                    timestamp,
+                    result_tx: tx.clone(),
+                    latency: LatencyStamps::captured(timestamp),
+                    window_layout: None,
+                },
+            );
+            record_peak(&peak);
+            captured += 1;
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        drop(tx);
+
+        // Pending frames, a batch with frames folded in while it waited, the result
+        // channel, the frame being encoded and the one being captured
+        let ceiling = (2 * MAX_PENDING + RESULT_CAPACITY + 2) * FRAME_BYTES;
+        let peak = peak.load(Ordering::Relaxed);
+        assert!(
+            peak <= ceiling,
+            "held {} MB for {} captured frames, ceiling {} MB",
+            peak / (1024 * 1024),
+            captured,
+            ceiling / (1024 * 1024)
+        );
+        assert!(scheduler.metrics().monitors[&0].dropped > 0);
+
+        // Once everything is consumed no pixels are left behind
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while scheduler.metrics().queue_depth > 0 && Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        drop(scheduler);
+        let received = consumer.await.unwrap();
+        assert!(received > 0 && received < captured);
+        assert_eq!(Frame::live_bytes(), 0);
+    }
+}
@@ -0,0 +1,83 @@
+#[cfg(test)]
+mod tests {
+    use image::{DynamicImage, GrayImage, Luma, Rgba, RgbaImage};
+    use screenpipe_vision::frame::{Frame, PixelFormat, PREVIEW_MAX_SIDE};
+    use screenpipe_vision::utils::frame_difference;
+
+    fn screen(width: u32, height: u32, shift: u32) -> Frame {
+        Frame::from(RgbaImage::from_fn(width, height, |x, y| {
+            let ink = ((x + shift) / 7 + y / 13) % 4 == 1;
+            if ink {
+                Rgba([30, 30, 40, 255])
+            } else {
+                Rgba([230, 235, 240, 255])
+            }
+        }))
+    }
+
+    #[test]
+    fn test_clones_share_pixels() {
+        let frame = screen(64, 32, 0);
+        let window = frame.clone();
+        assert!(window.ptr_eq(&frame));
+        assert_eq!(window.as_bytes().as_ptr(), frame.as_bytes().as_ptr());
+        assert_eq!(frame.format(), PixelFormat::Rgba8);
+        assert_eq!(frame.stride(), 64 * 4);
+
+        // Derived layouts are computed once and shared by every clone
+        assert_eq!(window.luma().as_ptr(), frame.luma().as_ptr());
+        assert_eq!(window.preview().as_ptr(), frame.preview().as_ptr());
+    }
+
+    #[test]
+    fn test_luma_frames_are_not_converted() {
+        let frame = Frame::from(DynamicImage::ImageLuma8(GrayImage::from_pixel(
+            8,
+            8,
+            Luma([7]),
+        )));
+        assert_eq!(frame.luma().as_ptr(), frame.as_bytes().as_ptr());
+        assert_eq!(frame.preview().as_ptr(), frame.as_bytes().as_ptr());
+    }
+
+    #[test]
+    fn test_preview_is_a_downscaled_luma() {
+        let frame = screen(3840, 2160, 0);
+        let preview = frame.preview();
+        assert_eq!(preview.dimensions(), (PREVIEW_MAX_SIDE, 270));
+
+        let reference = image::imageops::resize(
+            frame.luma(),
+            preview.width(),
+            preview.height(),
+            image::imageops::FilterType::Triangle,
+        );
+        let mean_error = preview
+            .pixels()
+            .zip(reference.pixels())
+            .map(|(a, b)| (a[0] as f64 - b[0] as f64).abs())
+            .sum::<f64>()
+            / preview.len() as f64;
+        assert!(mean_error < 16.0, "mean error {}", mean_error);
+
+        // Other layouts go through the full resolution luma first
+        let deep = Frame::from(DynamicImage::new_rgb16(1920, 10));
+        assert_eq!(deep.format(), PixelFormat::Other);
+        assert_eq!(deep.preview().dimensions(), (480, 2));
+    }
+
+    #[test]
+    fn test_hash_and_difference_follow_the_content() {
+        let frame = screen(1920, 1080, 0);
+        let same = screen(1920, 1080, 0);
+        let scrolled = screen(1920, 1080, 40);
+        assert_eq!(frame.hash(), same.hash());
+        assert_ne!(frame.hash(), scrolled.hash());
+
+        assert!(frame_difference(&frame, &same).unwrap() < 0.006);
+        assert!(frame_difference(&frame, &scrolled).unwrap() > 0.006);
+        // A resolution change is a new screen, not a comparison error
+        let resized = screen(1280, 720, 0);
+        assert_eq!(frame_difference(&frame, &resized).unwrap(), 1.0);
+    }
+}
@@ -0,0 +1,43 @@
+#[cfg(test)]
+mod tests {
+    use screenpipe_vision::monitor::{remap_display, DisplayConfig};
+
+    fn display(id: u32, name: &str, x: i32, width: u32) -> DisplayConfig {
+        DisplayConfig {
+            id,
+            name: name.to_string(),
+            x,
+            y: 0,
+            width,
+            height: 1080,
+        }
+    }
+
+    #[test]
+    fn test_remap_display_after_reconfiguration() {
+        let laptop = display(1, "Built-in Retina Display", 0, 1920);
+
+        // Same id at another resolution
+        let resized = vec![display(1, "Built-in Retina Display", 0, 1440)];
+        assert_eq!(remap_display(&laptop, &resized), Some(0));
+
+        // A projector was plugged in and every display renumbered
+        let renumbered = vec![
+            display(7, "Epson Projector", 1920, 1280),
+            display(8, "Built-in Retina Display", 0, 1920),
+        ];
+        assert_eq!(remap_display(&laptop, &renumbered), Some(1));
+
+        // Two displays of the same model, the position tells them apart
+        let left = display(2, "DELL U2720Q", 0, 2560);
+        let twins = vec![
+            display(5, "DELL U2720Q", 2560, 2560),
+            display(6, "DELL U2720Q", 0, 2560),
+        ];
+        assert_eq!(remap_display(&left, &twins), Some(1));
+
+        // Unplugged
+        let projector = display(7, "Epson Projector", 1920, 1280);
+        assert_eq!(remap_display(&projector, &resized), None);
+    }
+}
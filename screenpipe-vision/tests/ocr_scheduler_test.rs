@@ -0,0 +1,195 @@
+#[cfg(test)]
+mod tests {
+    use image::DynamicImage;
+    use screenpipe_core::latency::{LatencyStage, LatencyStamps};
+    use screenpipe_vision::capture_screenshot_by_window::CapturedWindow;
+    use screenpipe_vision::core::{OcrTaskData, WindowOcrResult};
+    use screenpipe_vision::ocr_scheduler::OcrProcessor;
+    use screenpipe_vision::{CaptureResult, OcrScheduler, OcrSchedulerConfig};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+    use tokio::sync::mpsc::{self, Receiver, Sender};
+
+    const OCR_COST: Duration = Duration::from_millis(20);
+    const FRAME_INTERVAL: Duration = Duration::from_millis(10);
+    const LOAD_DURATION: Duration = Duration::from_millis(1500);
+
+    fn slow_processor() -> OcrProcessor {
+        Arc::new(|windows: Vec<CapturedWindow>| {
+            Box::pin(async move {
+                tokio::time::sleep(OCR_COST).await;
+                Ok(windows
+                    .into_iter()
+                    .map(|w| WindowOcrResult {
+                        image: w.image,
+                        window_name: w.window_name,
+                        app_name: w.app_name,
+                        text: "text".to_string(),
+                        text_json: vec![],
+                        focused: w.is_focused,
+                        confidence: 1.0,
+                    })
+                    .collect())
+            })
+        })
+    }
+
+    fn task(frame_number: u64, focused: bool, tx: &Sender<CaptureResult>) -> OcrTaskData {
+        let timestamp = Instant::now();
+        OcrTaskData {
+            image: DynamicImage::new_rgb8(4, 4).into(),
+            window_images: vec![CapturedWindow {
+                image: DynamicImage::new_rgb8(4, 4).into(),
+                app_name: "app".to_string(),
+                window_name: "window".to_string(),
+                is_focused: focused,
+            }],
+            frame_number,
+            timestamp,
+            result_tx: tx.clone(),
+            latency: LatencyStamps::captured(timestamp),
+            window_layout: None,
+        }
+    }
+
+    fn drain(mut rx: Receiver<CaptureResult>) -> tokio::task::JoinHandle<Vec<CaptureResult>> {
+        tokio::spawn(async move {
+            let mut results = Vec::new();
+            while let Some(result) = rx.recv().await {
+                results.push(result);
+            }
+            results
+        })
+    }
+
+    /// Three monitors producing frames faster than OCR can keep up.
+    #[tokio::test]
+    async fn test_scheduler_latency_is_bounded_under_load() {
+        let scheduler = Arc::new(OcrScheduler::with_processor(
+            OcrSchedulerConfig::default(),
+            slow_processor(),
+        ));
+        scheduler.spawn();
+
+        let (tx, rx) = mpsc::channel(100_000);
+        let collector = drain(rx);
+
+        let start = Instant::now();
+        let mut frame_number = 0;
+        while start.elapsed() < LOAD_DURATION {
+            for monitor_id in 0..3 {
+                scheduler.submit(monitor_id, task(frame_number, monitor_id == 0, &tx));
+            }
+            frame_number += 1;
+            tokio::time::sleep(FRAME_INTERVAL).await;
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let metrics = scheduler.metrics();
+        for (monitor_id, monitor) in &metrics.monitors {
+            assert!(
+                monitor.max_latency_ms < 500,
+                "monitor {} latency grew to {}ms",
+                monitor_id,
+                monitor.max_latency_ms
+            );
+            assert!(monitor.skipped > 0);
+        }
+        assert_eq!(metrics.queue_depth, 0);
+
+        drop(tx);
+        drop(scheduler);
+        let results = collector.await.unwrap();
+        let duplicates = results.iter().filter(|r| r.duplicate_of.is_some()).count();
+        assert!(duplicates > 0);
+        // Skipped frames are processed once the frame standing in for them is
+        for result in &results {
+            assert!(result.latency.is_monotonic());
+            let processing = result.latency.stage(LatencyStage::Processing).unwrap();
+            assert!(processing >= OCR_COST, "processed in {:?}", processing);
+        }
+    }
+
+    /// Baseline: the same load through a plain fifo queue keeps falling behind.
+    #[tokio::test]
+    async fn test_fifo_latency_grows_under_same_load() {
+        let (queue_tx, mut queue_rx) = mpsc::unbounded_channel::<Instant>();
+        let processor = slow_processor();
+        let latencies = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let worker_latencies = Arc::clone(&latencies);
+        let worker = tokio::spawn(async move {
+            while let Some(captured_at) = queue_rx.recv().await {
+                let _ = (processor)(vec![]).await;
+                worker_latencies.lock().unwrap().push(captured_at.elapsed());
+            }
+        });
+
+        let start = Instant::now();
+        while start.elapsed() < LOAD_DURATION {
+            for _ in 0..3 {
+                queue_tx.send(Instant::now()).unwrap();
+            }
+            tokio::time::sleep(FRAME_INTERVAL).await;
+        }
+        worker.abort();
+
+        let latencies = latencies.lock().unwrap();
+        let last = *latencies.last().unwrap();
+        assert!(last > Duration::from_millis(500), "fifo latency {:?}", last);
+        assert!(last > latencies[0] * 10);
+    }
+
+    #[tokio::test]
+    async fn test_focused_monitor_goes_first() {
+        let scheduler = Arc::new(OcrScheduler::with_processor(
+            OcrSchedulerConfig::default(),
+            slow_processor(),
+        ));
+        let (tx, rx) = mpsc::channel(100);
+        let collector = drain(rx);
+
+        // Queue before starting the worker so ordering is decided by priority alone
+        scheduler.submit(1, task(10, false, &tx));
+        scheduler.submit(2, task(20, true, &tx));
+        scheduler.spawn();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        drop(tx);
+        drop(scheduler);
+        let results = collector.await.unwrap();
+        let order: Vec<u64> = results.iter().map(|r| r.frame_number).collect();
+        assert_eq!(order, vec![20, 10]);
+    }
+
+    #[tokio::test]
+    async fn test_frames_per_minute_cap() {
+        let scheduler = Arc::new(OcrScheduler::with_processor(
+            OcrSchedulerConfig {
+                max_frames_per_minute: Some(600),
+                ..Default::default()
+            },
+            slow_processor(),
+        ));
+        scheduler.spawn();
+        let (tx, rx) = mpsc::channel(100_000);
+        let _collector = drain(rx);
+
+        let start = Instant::now();
+        let mut frame_number = 0;
+        while start.elapsed() < Duration::from_secs(1) {
+            scheduler.submit(frame_number as u32 % 4, task(frame_number, false, &tx));
+            frame_number += 1;
+            tokio::time::sleep(FRAME_INTERVAL).await;
+        }
+
+        let processed: u64 = scheduler
+            .metrics()
+            .monitors
+            .values()
+            .map(|m| m.processed)
+            .sum();
+        // 600/min is one frame every 100ms
+        assert!(processed <= 12, "processed {} frames", processed);
+        assert!(scheduler.metrics().throttled > 0);
+    }
+}
@@ -1,6 +1,7 @@
 #[cfg(target_os = "windows")]
 #[cfg(test)]
 mod tests {
+    use screenpipe_core::latency::LatencyStamps;
     use screenpipe_vision::core::OcrTaskData;
     use screenpipe_vision::monitor::get_default_monitor;
     use screenpipe_vision::{process_ocr_task, OcrEngine};
@@ -41,6 +42,8 @@ mod tests {
                 frame_number,
                 timestamp,
                 result_tx: tx,
+                latency: LatencyStamps::captured(timestamp),
+                window_layout: None,
             },
             false,
             &ocr_engine,